@@ -11,7 +11,7 @@ mod wire {
     use smoltcp::wire::{Ipv4Address, Ipv4Repr, Ipv4Packet};
     use smoltcp::phy::{ChecksumCapabilities};
     use smoltcp::wire::{IpAddress, IpProtocol};
-    use smoltcp::wire::{TcpRepr, TcpPacket, TcpSeqNumber, TcpControl};
+    use smoltcp::wire::{TcpRepr, TcpPacket, TcpSeqNumber, TcpControl, TCP_SACK_RANGE_COUNT};
     use smoltcp::wire::{UdpRepr, UdpPacket};
 
     #[cfg(feature = "proto-ipv6")]
@@ -39,6 +39,13 @@ mod wire {
             window_len:   0x0123,
             control:      TcpControl::Syn,
             max_seg_size: None,
+            sack_permitted: false,
+            sack_ranges:  [None; TCP_SACK_RANGE_COUNT],
+            timestamp:    None,
+            md5_signature: None,
+            ece:          false,
+            cwr:          false,
+            urgent_at:    None,
             payload:      &PAYLOAD_BYTES
         };
         let mut bytes = vec![0xa5; repr.buffer_len()];
@@ -75,7 +82,9 @@ mod wire {
             dst_addr:    Ipv4Address([192, 168, 1, 2]),
             protocol:    IpProtocol::Tcp,
             payload_len: 100,
-            hop_limit:   64
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0
         };
         let mut bytes = vec![0xa5; repr.buffer_len()];
 
@@ -95,7 +104,10 @@ mod wire {
                                       0, 0, 0, 0, 0, 0, 0, 2]),
             next_header: IpProtocol::Tcp,
             payload_len: 100,
-            hop_limit:   64
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0,
+            flow_label:  0
         };
         let mut bytes = vec![0xa5; repr.buffer_len()];
 
@@ -0,0 +1,83 @@
+#[macro_use]
+extern crate log;
+extern crate env_logger;
+extern crate getopts;
+extern crate smoltcp;
+
+mod utils;
+
+use std::collections::BTreeMap;
+use std::time::Instant;
+use std::os::unix::io::AsRawFd;
+use smoltcp::phy::wait as phy_wait;
+use smoltcp::wire::{EthernetAddress, Ipv4Address, IpCidr, Ipv4Cidr};
+use smoltcp::iface::{NeighborCache, AddressLifetimes, EthernetInterfaceBuilder};
+use smoltcp::socket::{SocketSet, RawSocketBuffer, RawPacketBuffer};
+use smoltcp::dhcp::Dhcpv4Client;
+
+fn main() {
+    utils::setup_logging("");
+
+    let (mut opts, mut free) = utils::create_options();
+    utils::add_tap_options(&mut opts, &mut free);
+    utils::add_middleware_options(&mut opts, &mut free);
+
+    let mut matches = utils::parse_options(&opts, free);
+    let device = utils::parse_tap_options(&mut matches);
+    let fd = device.as_raw_fd();
+    let device = utils::parse_middleware_options(&mut matches, device, /*loopback=*/false);
+
+    let neighbor_cache = NeighborCache::new(BTreeMap::new());
+    let ethernet_addr = EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+    let ip_addrs = [IpCidr::new(Ipv4Address::UNSPECIFIED.into(), 0)];
+    let mut iface = EthernetInterfaceBuilder::new(device)
+            .ethernet_addr(ethernet_addr)
+            .neighbor_cache(neighbor_cache)
+            .ip_addrs(ip_addrs)
+            .address_lifetimes(AddressLifetimes::new(vec![]))
+            .finalize();
+
+    let mut sockets = SocketSet::new(vec![]);
+    let dhcp_rx_buffer = RawSocketBuffer::new(vec![RawPacketBuffer::new(vec![0; 900])]);
+    let dhcp_tx_buffer = RawSocketBuffer::new(vec![RawPacketBuffer::new(vec![0; 600])]);
+    let startup_time = Instant::now();
+    let mut dhcp = Dhcpv4Client::new(&mut sockets, dhcp_rx_buffer, dhcp_tx_buffer,
+                                     utils::millis_since(startup_time));
+
+    loop {
+        let timestamp = utils::millis_since(startup_time);
+        iface.poll(&mut sockets, timestamp)
+            .map(|_| ())
+            .unwrap_or_else(|e| println!("Poll: {:?}", e));
+
+        let config = dhcp.poll(&iface, &mut sockets, timestamp)
+            .unwrap_or_else(|e| {
+                println!("DHCP: {:?}", e);
+                None
+            });
+        config.map(|config| {
+            println!("DHCP config: {:?}", config);
+            let address = config.address
+                .unwrap_or(Ipv4Cidr::new(Ipv4Address::UNSPECIFIED, 0));
+            iface.update_ip_addrs(|addrs| {
+                addrs.iter_mut().next().map(|addr| {
+                    *addr = IpCidr::Ipv4(address);
+                });
+            });
+            if config.address.is_some() {
+                iface.set_ip_addr_lifetime(address.address(), None, config.expires_at)
+                    .unwrap_or_else(|e| println!("Lifetime: {:?}", e));
+            }
+            iface.set_ipv4_gateway(config.router);
+            for dns_server in config.dns_servers.iter().filter_map(|s| *s) {
+                info!("DNS server: {}", dns_server);
+            }
+        });
+
+        let timestamp = utils::millis_since(startup_time);
+        let mut timeout = dhcp.next_poll(timestamp) - timestamp;
+        iface.poll_delay(&sockets, timestamp)
+            .map(|delay| timeout = timeout.min(delay));
+        phy_wait(fd, Some(timeout)).expect("wait error");
+    }
+}
@@ -117,8 +117,8 @@ fn main() {
 
     let mut socket_set_entries: [_; 2] = Default::default();
     let mut socket_set = SocketSet::new(&mut socket_set_entries[..]);
-    let server_handle = socket_set.add(server_socket);
-    let client_handle = socket_set.add(client_socket);
+    let server_handle = socket_set.add_typed(server_socket);
+    let client_handle = socket_set.add_typed(client_socket);
 
     let mut did_listen  = false;
     let mut did_connect = false;
@@ -127,7 +127,7 @@ fn main() {
         iface.poll(&mut socket_set, clock.elapsed()).expect("poll error");
 
         {
-            let mut socket = socket_set.get::<TcpSocket>(server_handle);
+            let mut socket = socket_set.get_typed(server_handle);
             if !socket.is_active() && !socket.is_listening() {
                 if !did_listen {
                     debug!("listening");
@@ -146,7 +146,7 @@ fn main() {
         }
 
         {
-            let mut socket = socket_set.get::<TcpSocket>(client_handle);
+            let mut socket = socket_set.get_typed(client_handle);
             if !socket.is_open() {
                 if !did_connect {
                     debug!("connecting");
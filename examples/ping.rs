@@ -3,7 +3,6 @@ extern crate log;
 extern crate env_logger;
 extern crate getopts;
 extern crate smoltcp;
-extern crate byteorder;
 
 mod utils;
 
@@ -11,14 +10,14 @@ use std::str::FromStr;
 use std::collections::BTreeMap;
 use std::time::Instant;
 use std::os::unix::io::AsRawFd;
+use smoltcp::Error;
 use smoltcp::phy::Device;
 use smoltcp::phy::wait as phy_wait;
-use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr,
-                    Ipv4Address, Icmpv4Repr, Icmpv4Packet};
-use smoltcp::iface::{NeighborCache, EthernetInterfaceBuilder};
-use smoltcp::socket::{SocketSet, IcmpSocket, IcmpSocketBuffer, IcmpPacketBuffer, IcmpEndpoint};
-use std::collections::HashMap;
-use byteorder::{ByteOrder, NetworkEndian};
+use smoltcp::time::Duration;
+use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr, Ipv4Address};
+use smoltcp::iface::{NeighborCache, EthernetInterfaceBuilder, PendingBuffer, PendingQueue};
+use smoltcp::socket::{SocketSet, IcmpSocket, IcmpSocketBuffer, IcmpPacketBuffer,
+                      Pinger, PingEvent};
 
 fn main() {
     utils::setup_logging("warn");
@@ -63,93 +62,52 @@ fn main() {
             .ip_addrs([ip_addr])
             .ipv4_gateway(default_v4_gw)
             .neighbor_cache(neighbor_cache)
+            .pending_queue(PendingQueue::new(vec![PendingBuffer::new(vec![0; 1500])]))
             .finalize();
 
     let mut sockets = SocketSet::new(vec![]);
     let icmp_handle = sockets.add(icmp_socket);
 
     let mut send_at = 0;
-    let mut seq_no = 0;
-    let mut received = 0;
-    let mut echo_payload = [0xffu8; 40];
-    let mut waiting_queue = HashMap::new();
-    let ident = 0x22b;
-    let endpoint = IpAddress::Ipv4(remote_addr);
+    let echo_payload = [0xffu8; 40];
+    let mut pinger = Pinger::new(icmp_handle, remote_addr, 0x22b, vec![]);
+    pinger.set_timeout(Duration::from_millis(timeout * 1000));
 
     loop {
         let timestamp = utils::millis_since(startup_time);
         iface.poll(&mut sockets, timestamp).expect("poll error");
 
-        {
-            let mut socket = sockets.get::<IcmpSocket>(icmp_handle);
-            if !socket.is_open() {
-                socket.bind(IcmpEndpoint::Ident(ident)).unwrap()
-            }
-
-            let timestamp = Instant::now().duration_since(startup_time);
-            let timestamp_us = (timestamp.as_secs() * 1000000) +
-                (timestamp.subsec_nanos() / 1000) as u64;
-
-            if socket.can_send() && seq_no < count as u16 &&
-                    send_at <= utils::millis_since(startup_time) {
-                NetworkEndian::write_u64(&mut echo_payload, timestamp_us);
-                let icmp_repr = Icmpv4Repr::EchoRequest {
-                    ident: ident,
-                    seq_no,
-                    data: &echo_payload,
-                };
-
-                let icmp_payload = socket
-                    .send(icmp_repr.buffer_len(), endpoint)
-                    .unwrap();
-
-                let mut icmp_packet = Icmpv4Packet::new(icmp_payload);
-                icmp_repr.emit(&mut icmp_packet, &device_caps.checksum);
-
-                waiting_queue.insert(seq_no, timestamp);
-                seq_no += 1;
-                send_at += interval * 1000;
+        while let Some(event) = pinger.poll(&mut sockets, timestamp, &device_caps.checksum) {
+            match event {
+                PingEvent::Reply { seq_no, len, rtt } =>
+                    println!("{} bytes from {}: icmp_seq={}, time={}ms",
+                             len, remote_addr, seq_no, rtt.total_millis()),
+                PingEvent::Timeout { seq_no } =>
+                    println!("From {} icmp_seq={} timeout", remote_addr, seq_no)
             }
+        }
 
-            if socket.can_recv() {
-                let (payload, _) = socket.recv().unwrap();
-                let icmp_packet = Icmpv4Packet::new(&payload);
-                let icmp_repr = Icmpv4Repr::parse(&icmp_packet, &device_caps.checksum).unwrap();
-
-                if let Icmpv4Repr::EchoReply { seq_no, data, .. } = icmp_repr {
-                    if let Some(_) = waiting_queue.get(&seq_no) {
-                        let packet_timestamp_us = NetworkEndian::read_u64(data);
-                        println!("{} bytes from {}: icmp_seq={}, time={:.3}ms",
-                                 data.len(), remote_addr, seq_no,
-                                 (timestamp_us - packet_timestamp_us) as f64 / 1000.0);
-                        waiting_queue.remove(&seq_no);
-                        received += 1;
-                    }
-                }
+        if pinger.sent() < count as u64 && send_at <= timestamp {
+            match pinger.send(&mut sockets, timestamp, &device_caps.checksum, &echo_payload) {
+                Ok(_) => send_at += interval * 1000,
+                Err(Error::Exhausted) => (),
+                Err(e) => panic!("send error: {}", e)
             }
+        }
 
-            waiting_queue.retain(|seq, from| {
-                if (timestamp - *from).as_secs() < timeout {
-                    true
-                } else {
-                    println!("From {} icmp_seq={} timeout", remote_addr, seq);
-                    false
-                }
-            });
-
-            if seq_no == count as u16 && waiting_queue.is_empty() {
-                break
-            }
+        if pinger.sent() == count as u64 && pinger.waiting() == 0 {
+            break
         }
 
         let timestamp = utils::millis_since(startup_time);
 
         let poll_at = iface.poll_at(&sockets, timestamp);
-        let resume_at = [poll_at, Some(send_at)].iter().flat_map(|x| *x).min();
+        let resume_at = [poll_at, pinger.poll_at(), Some(send_at)].iter().flat_map(|x| *x).min();
         phy_wait(fd, resume_at.map(|at| at.saturating_sub(timestamp))).expect("wait error");
     }
 
     println!("--- {} ping statistics ---", remote_addr);
     println!("{} packets transmitted, {} received, {:.0}% packet loss",
-             seq_no, received, 100.0 * (seq_no - received) as f64 / seq_no as f64);
+             pinger.sent(), pinger.received(),
+             100.0 * (pinger.sent() - pinger.received()) as f64 / pinger.sent() as f64);
 }
@@ -15,9 +15,12 @@ use env_logger::LogBuilder;
 use getopts::{Options, Matches};
 
 use smoltcp::phy::{Device, EthernetTracer, FaultInjector};
-#[cfg(feature = "phy-tap_interface")]
+#[cfg(all(feature = "phy-tap_interface",
+          any(target_os = "linux", target_os = "freebsd", target_os = "openbsd")))]
 use smoltcp::phy::TapInterface;
-use smoltcp::phy::{PcapWriter, PcapSink, PcapMode, PcapLinkType};
+#[cfg(all(feature = "phy-tap_interface", target_os = "macos"))]
+use smoltcp::phy::UtunInterface;
+use smoltcp::phy::{PcapWriter, PcapSink, PcapMode, PcapLinkType, Medium};
 
 #[cfg(feature = "log")]
 pub fn setup_logging_with_clock<F>(filter: &str, since_startup: F)
@@ -82,16 +85,26 @@ pub fn add_tap_options(_opts: &mut Options, free: &mut Vec<&str>) {
     free.push("INTERFACE");
 }
 
-#[cfg(feature = "phy-tap_interface")]
+#[cfg(all(feature = "phy-tap_interface",
+          any(target_os = "linux", target_os = "freebsd", target_os = "openbsd")))]
 pub fn parse_tap_options(matches: &mut Matches) -> TapInterface {
     let interface = matches.free.remove(0);
     TapInterface::new(&interface).unwrap()
 }
 
+#[cfg(all(feature = "phy-tap_interface", target_os = "macos"))]
+pub fn parse_tap_options(matches: &mut Matches) -> UtunInterface {
+    let interface = matches.free.remove(0);
+    UtunInterface::new(&interface).unwrap()
+}
+
 pub fn add_middleware_options(opts: &mut Options, _free: &mut Vec<&str>) {
     opts.optopt("", "pcap", "Write a packet capture file", "FILE");
     opts.optopt("", "drop-chance", "Chance of dropping a packet (%)", "CHANCE");
     opts.optopt("", "corrupt-chance", "Chance of corrupting a packet (%)", "CHANCE");
+    opts.optopt("", "reorder-chance", "Chance of reordering a received packet (%)", "CHANCE");
+    opts.optopt("", "duplicate-chance", "Chance of duplicating a received packet (%)", "CHANCE");
+    opts.optopt("", "seed", "Seed of the fault injector (random by default)", "SEED");
     opts.optopt("", "size-limit", "Drop packets larger than given size (octets)", "SIZE");
     opts.optopt("", "tx-rate-limit", "Drop packets after transmit rate exceeds given limit \
                                       (packets per interval)", "RATE");
@@ -108,6 +121,10 @@ pub fn parse_middleware_options<D>(matches: &mut Matches, device: D, loopback: b
                                   .unwrap_or(0);
     let corrupt_chance   = matches.opt_str("corrupt-chance").map(|s| u8::from_str(&s).unwrap())
                                   .unwrap_or(0);
+    let reorder_chance   = matches.opt_str("reorder-chance").map(|s| u8::from_str(&s).unwrap())
+                                  .unwrap_or(0);
+    let duplicate_chance = matches.opt_str("duplicate-chance").map(|s| u8::from_str(&s).unwrap())
+                                  .unwrap_or(0);
     let size_limit       = matches.opt_str("size-limit").map(|s| usize::from_str(&s).unwrap())
                                   .unwrap_or(0);
     let tx_rate_limit    = matches.opt_str("tx-rate-limit").map(|s| u64::from_str(&s).unwrap())
@@ -124,11 +141,20 @@ pub fn parse_middleware_options<D>(matches: &mut Matches, device: D, loopback: b
         pcap_writer = Box::new(io::sink())
     }
 
-    let seed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().subsec_nanos();
+    let seed = matches.opt_str("seed").map(|s| u32::from_str(&s).unwrap()).unwrap_or_else(||
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().subsec_nanos());
+    #[cfg(feature = "log")]
+    debug!("fault injector seed: {}", seed);
 
+    let link_type = match device.capabilities().medium {
+        Medium::Ethernet => PcapLinkType::Ethernet,
+        Medium::Ip => PcapLinkType::Ip,
+        #[cfg(feature = "proto-sixlowpan")]
+        Medium::Ieee802154 => PcapLinkType::Ieee802154
+    };
     let device = PcapWriter::new(device, Rc::new(RefCell::new(pcap_writer)) as Rc<PcapSink>,
                                  if loopback { PcapMode::TxOnly } else { PcapMode::Both },
-                                 PcapLinkType::Ethernet);
+                                 link_type);
     let device = EthernetTracer::new(device, |_timestamp, _printer| {
         #[cfg(feature = "log")]
         trace!("{}", _printer);
@@ -136,6 +162,8 @@ pub fn parse_middleware_options<D>(matches: &mut Matches, device: D, loopback: b
     let mut device = FaultInjector::new(device, seed);
     device.set_drop_chance(drop_chance);
     device.set_corrupt_chance(corrupt_chance);
+    device.set_reorder_chance(reorder_chance);
+    device.set_duplicate_chance(duplicate_chance);
     device.set_max_packet_size(size_limit);
     device.set_max_tx_rate(tx_rate_limit);
     device.set_max_rx_rate(rx_rate_limit);
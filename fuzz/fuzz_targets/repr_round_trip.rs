@@ -0,0 +1,69 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+#[macro_use] extern crate arbitrary;
+extern crate smoltcp;
+
+use smoltcp::phy::ChecksumCapabilities;
+use smoltcp::wire::*;
+
+#[derive(Debug, Arbitrary)]
+enum Input<'a> {
+    Arp(ArpRepr),
+    Ipv4(Ipv4Repr),
+    Ipv6(Ipv6Repr),
+    Ipv6Option(Ipv6OptionRepr<'a>),
+    Icmpv4(Icmpv4Repr<'a>),
+    Icmpv6(Icmpv6Repr<'a>),
+    Tcp(TcpRepr<'a>, Ipv6Address, Ipv6Address),
+    Udp(UdpRepr<'a>, Ipv6Address, Ipv6Address),
+}
+
+fuzz_target!(|input: Input| {
+    let caps = ChecksumCapabilities::default();
+    match input {
+        Input::Arp(repr) => {
+            let mut bytes = vec![0; repr.buffer_len()];
+            repr.emit(&mut ArpPacket::new(&mut bytes));
+            assert_eq!(ArpRepr::parse(&ArpPacket::new(&bytes)), Ok(repr));
+        }
+        Input::Ipv4(repr) => {
+            let mut bytes = vec![0; repr.buffer_len() + repr.payload_len];
+            repr.emit(&mut Ipv4Packet::new(&mut bytes), &caps);
+            assert_eq!(Ipv4Repr::parse(&Ipv4Packet::new(&bytes), &caps), Ok(repr));
+        }
+        Input::Ipv6(repr) => {
+            let mut bytes = vec![0; repr.buffer_len() + repr.payload_len];
+            repr.emit(&mut Ipv6Packet::new(&mut bytes));
+            assert_eq!(Ipv6Repr::parse(&Ipv6Packet::new(&bytes)), Ok(repr));
+        }
+        Input::Ipv6Option(repr) => {
+            let mut bytes = vec![0; repr.buffer_len()];
+            repr.emit(&mut Ipv6Option::new(&mut bytes[..]));
+            assert_eq!(Ipv6OptionRepr::parse(&Ipv6Option::new(&bytes[..])), Ok(repr));
+        }
+        Input::Icmpv4(repr) => {
+            let mut bytes = vec![0; repr.buffer_len()];
+            repr.emit(&mut Icmpv4Packet::new(&mut bytes), &caps);
+            assert_eq!(Icmpv4Repr::parse(&Icmpv4Packet::new(&bytes), &caps), Ok(repr));
+        }
+        Input::Icmpv6(repr) => {
+            let mut bytes = vec![0; repr.buffer_len()];
+            repr.emit(&mut Icmpv6Packet::new(&mut bytes), &caps);
+            assert_eq!(Icmpv6Repr::parse(&Icmpv6Packet::new(&bytes), &caps), Ok(repr));
+        }
+        Input::Tcp(repr, src_addr, dst_addr) => {
+            let (src_addr, dst_addr) = (IpAddress::Ipv6(src_addr), IpAddress::Ipv6(dst_addr));
+            let mut bytes = vec![0; repr.buffer_len()];
+            repr.emit(&mut TcpPacket::new(&mut bytes), &src_addr, &dst_addr, &caps);
+            assert_eq!(TcpRepr::parse(&TcpPacket::new(&bytes), &src_addr, &dst_addr, &caps),
+                       Ok(repr));
+        }
+        Input::Udp(repr, src_addr, dst_addr) => {
+            let (src_addr, dst_addr) = (IpAddress::Ipv6(src_addr), IpAddress::Ipv6(dst_addr));
+            let mut bytes = vec![0; repr.buffer_len()];
+            repr.emit(&mut UdpPacket::new(&mut bytes), &src_addr, &dst_addr, &caps);
+            assert_eq!(UdpRepr::parse(&UdpPacket::new(&bytes), &src_addr, &dst_addr, &caps),
+                       Ok(repr));
+        }
+    }
+});
@@ -0,0 +1,88 @@
+/* C bindings for smoltcp; see the documentation of the `smoltcp::capi` module.
+ * Build the library with e.g.
+ *   cargo rustc --release --features capi --crate-type staticlib
+ */
+
+#ifndef SMOLTCP_H
+#define SMOLTCP_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define SMOLTCP_ERR_EXHAUSTED     (-1)
+#define SMOLTCP_ERR_ILLEGAL       (-2)
+#define SMOLTCP_ERR_UNADDRESSABLE (-3)
+#define SMOLTCP_ERR_TRUNCATED     (-4)
+#define SMOLTCP_ERR_CHECKSUM      (-5)
+#define SMOLTCP_ERR_UNRECOGNIZED  (-6)
+#define SMOLTCP_ERR_FRAGMENTED    (-7)
+#define SMOLTCP_ERR_MALFORMED     (-8)
+#define SMOLTCP_ERR_DROPPED       (-9)
+#define SMOLTCP_ERR_INVALID       (-10)
+
+#define SMOLTCP_TCP_CLOSED       0
+#define SMOLTCP_TCP_LISTEN       1
+#define SMOLTCP_TCP_SYN_SENT     2
+#define SMOLTCP_TCP_SYN_RECEIVED 3
+#define SMOLTCP_TCP_ESTABLISHED  4
+#define SMOLTCP_TCP_FIN_WAIT_1   5
+#define SMOLTCP_TCP_FIN_WAIT_2   6
+#define SMOLTCP_TCP_CLOSE_WAIT   7
+#define SMOLTCP_TCP_CLOSING      8
+#define SMOLTCP_TCP_LAST_ACK     9
+#define SMOLTCP_TCP_TIME_WAIT    10
+
+typedef struct smoltcp_stack smoltcp_stack;
+
+typedef struct {
+    void *ctx;
+    /* Returns the frame length before truncation, 0 if none, negative on failure. */
+    intptr_t (*receive_frame)(void *ctx, uint8_t *buffer, size_t len);
+    /* Returns 0, or nonzero on failure. */
+    int32_t (*send_frame)(void *ctx, const uint8_t *frame, size_t len);
+} smoltcp_driver;
+
+typedef struct {
+    uint8_t ethernet_addr[6];
+    uint8_t ipv4_addr[4];
+    uint8_t ipv4_prefix_len;
+    uint8_t ipv4_gateway[4];
+    size_t  max_frame_size;
+} smoltcp_config;
+
+typedef struct {
+    uint8_t  addr[4];
+    uint16_t port;
+} smoltcp_endpoint;
+
+smoltcp_stack *smoltcp_stack_new(smoltcp_driver driver, const smoltcp_config *config);
+void smoltcp_stack_free(smoltcp_stack *stack);
+int32_t smoltcp_poll(smoltcp_stack *stack, uint64_t timestamp);
+int64_t smoltcp_poll_delay(const smoltcp_stack *stack, uint64_t timestamp);
+int32_t smoltcp_socket_free(smoltcp_stack *stack, int32_t socket);
+
+int32_t smoltcp_tcp_new(smoltcp_stack *stack, size_t rx_size, size_t tx_size);
+int32_t smoltcp_tcp_listen(smoltcp_stack *stack, int32_t socket, uint16_t port);
+int32_t smoltcp_tcp_connect(smoltcp_stack *stack, int32_t socket,
+                            smoltcp_endpoint remote, uint16_t local_port);
+int32_t smoltcp_tcp_send(smoltcp_stack *stack, int32_t socket, const uint8_t *data, size_t len);
+int32_t smoltcp_tcp_recv(smoltcp_stack *stack, int32_t socket, uint8_t *data, size_t len);
+int32_t smoltcp_tcp_close(smoltcp_stack *stack, int32_t socket);
+int32_t smoltcp_tcp_state(smoltcp_stack *stack, int32_t socket);
+
+int32_t smoltcp_udp_new(smoltcp_stack *stack, size_t packets, size_t packet_size);
+int32_t smoltcp_udp_bind(smoltcp_stack *stack, int32_t socket, uint16_t port);
+int32_t smoltcp_udp_send(smoltcp_stack *stack, int32_t socket, const uint8_t *data, size_t len,
+                         smoltcp_endpoint remote);
+int32_t smoltcp_udp_recv(smoltcp_stack *stack, int32_t socket, uint8_t *data, size_t len,
+                         smoltcp_endpoint *remote);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SMOLTCP_H */
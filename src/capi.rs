@@ -0,0 +1,576 @@
+//! C bindings for the interface and the TCP and UDP sockets.
+//!
+//! The functions of this module are exported unmangled, with a `smoltcp_` prefix, and take
+//! and return only `#[repr(C)]` types, so that firmware written in C can use _smoltcp_
+//! through the header `include/smoltcp.h` and move to Rust one part at a time.
+//! To build a library that C code links against, use e.g.
+//! `cargo rustc --release --features capi --crate-type staticlib`.
+//!
+//! A [Stack](struct.Stack.html) owns an Ethernet interface over a [driver](struct.Driver.html)
+//! made of C callbacks, and a socket set. Sockets are identified by small non-negative
+//! integers, and the functions return a negative `SMOLTCP_ERR_*` code on failure.
+//!
+//! # Safety
+//! The pointers passed to the functions must be null or valid for the given lengths,
+//! and a stack must not be used after it is freed, nor from several threads at once.
+
+#![allow(unsafe_code)]
+
+use core::{ptr, slice};
+use core::ffi::c_void;
+#[cfg(feature = "std")]
+use std::boxed::Box;
+#[cfg(feature = "std")]
+use std::vec::Vec;
+#[cfg(feature = "std")]
+use std::collections::BTreeMap;
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::Vec;
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::boxed::Box;
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::btree_map::BTreeMap;
+
+use {Error, Result};
+use phy::{SpiEthDevice, SpiEthDriver};
+use iface::{EthernetInterface, EthernetInterfaceBuilder, NeighborCache};
+use wire::{EthernetAddress, IpAddress, IpCidr, IpEndpoint, Ipv4Address};
+use socket::{AnySocket, Socket, SocketSet, SocketHandle};
+use socket::{TcpSocket, TcpSocketBuffer, TcpState};
+use socket::{UdpSocket, UdpSocketBuffer, UdpPacketBuffer};
+
+/// A buffer space was exhausted, or there is no data to receive.
+pub const SMOLTCP_ERR_EXHAUSTED: i32 = -1;
+/// The operation is not permitted in the current state.
+pub const SMOLTCP_ERR_ILLEGAL: i32 = -2;
+/// The destination is unaddressable.
+pub const SMOLTCP_ERR_UNADDRESSABLE: i32 = -3;
+/// An incoming packet was truncated.
+pub const SMOLTCP_ERR_TRUNCATED: i32 = -4;
+/// An incoming packet had an incorrect checksum.
+pub const SMOLTCP_ERR_CHECKSUM: i32 = -5;
+/// An incoming packet was not recognized.
+pub const SMOLTCP_ERR_UNRECOGNIZED: i32 = -6;
+/// An incoming packet was fragmented and could not be reassembled.
+pub const SMOLTCP_ERR_FRAGMENTED: i32 = -7;
+/// An incoming packet was malformed.
+pub const SMOLTCP_ERR_MALFORMED: i32 = -8;
+/// An incoming packet was dropped.
+pub const SMOLTCP_ERR_DROPPED: i32 = -9;
+/// An argument was invalid, e.g. a null pointer, or a socket that does not exist
+/// or is of another type.
+pub const SMOLTCP_ERR_INVALID: i32 = -10;
+
+fn error_code(err: Error) -> i32 {
+    match err {
+        Error::Exhausted     => SMOLTCP_ERR_EXHAUSTED,
+        Error::Illegal       => SMOLTCP_ERR_ILLEGAL,
+        Error::Unaddressable => SMOLTCP_ERR_UNADDRESSABLE,
+        Error::Truncated     => SMOLTCP_ERR_TRUNCATED,
+        Error::Checksum      => SMOLTCP_ERR_CHECKSUM,
+        Error::Unrecognized  => SMOLTCP_ERR_UNRECOGNIZED,
+        Error::Fragmented    => SMOLTCP_ERR_FRAGMENTED,
+        Error::Malformed     => SMOLTCP_ERR_MALFORMED,
+        Error::Dropped       => SMOLTCP_ERR_DROPPED,
+        Error::__Nonexhaustive => unreachable!()
+    }
+}
+
+/// An Ethernet controller driver, made of C callbacks.
+///
+/// `receive_frame` copies the next received frame into the buffer, and returns its length
+/// before truncation, zero if there is no frame, or a negative value on failure.
+/// `send_frame` sends a frame, and returns zero, or a nonzero value on failure.
+/// Both are called with `ctx` as their first argument.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Driver {
+    pub ctx:           *mut c_void,
+    pub receive_frame: extern "C" fn(ctx: *mut c_void, buffer: *mut u8, len: usize) -> isize,
+    pub send_frame:    extern "C" fn(ctx: *mut c_void, frame: *const u8, len: usize) -> i32,
+}
+
+impl SpiEthDriver for Driver {
+    type Error = isize;
+
+    fn receive_frame(&mut self, buffer: &mut [u8])
+                    -> ::core::result::Result<Option<usize>, isize> {
+        match (self.receive_frame)(self.ctx, buffer.as_mut_ptr(), buffer.len()) {
+            0 => Ok(None),
+            len if len > 0 => Ok(Some(len as usize)),
+            err => Err(err)
+        }
+    }
+
+    fn send_frame(&mut self, frame: &[u8]) -> ::core::result::Result<(), isize> {
+        match (self.send_frame)(self.ctx, frame.as_ptr(), frame.len()) {
+            0 => Ok(()),
+            err => Err(err as isize)
+        }
+    }
+}
+
+/// The configuration of a stack.
+///
+/// `max_frame_size` is the size of the largest Ethernet frame, without the frame check
+/// sequence, that the stack sends and receives, e.g. 1514. A gateway of `0.0.0.0`
+/// means that there is none.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    pub ethernet_addr:   [u8; 6],
+    pub ipv4_addr:       [u8; 4],
+    pub ipv4_prefix_len: u8,
+    pub ipv4_gateway:    [u8; 4],
+    pub max_frame_size:  usize,
+}
+
+/// An IPv4 endpoint.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Endpoint {
+    pub addr: [u8; 4],
+    pub port: u16,
+}
+
+impl From<Endpoint> for IpEndpoint {
+    fn from(endpoint: Endpoint) -> IpEndpoint {
+        IpEndpoint::new(Ipv4Address(endpoint.addr).into(), endpoint.port)
+    }
+}
+
+impl From<IpEndpoint> for Endpoint {
+    fn from(endpoint: IpEndpoint) -> Endpoint {
+        let addr = match endpoint.addr {
+            IpAddress::Ipv4(addr) => addr.0,
+            _ => [0; 4]
+        };
+        Endpoint { addr, port: endpoint.port }
+    }
+}
+
+/// A network stack, owning an interface and a socket set.
+///
+/// The stack is opaque to C code, which only holds pointers to it.
+pub struct Stack {
+    iface:   EthernetInterface<'static, 'static, SpiEthDevice<'static, Driver>>,
+    sockets: SocketSet<'static, 'static, 'static>,
+    handles: Vec<Option<SocketHandle>>,
+}
+
+impl Stack {
+    fn add<T: Into<Socket<'static, 'static>>>(&mut self, socket: T) -> i32 {
+        let handle = self.sockets.add(socket);
+        let index = match self.handles.iter().position(|slot| slot.is_none()) {
+            Some(index) => index,
+            None => {
+                self.handles.push(None);
+                self.handles.len() - 1
+            }
+        };
+        self.handles[index] = Some(handle);
+        index as i32
+    }
+
+    fn handle(&self, socket: i32) -> Option<SocketHandle> {
+        if socket < 0 { return None }
+        self.handles.get(socket as usize).and_then(|slot| *slot)
+    }
+}
+
+/// Call `f` with the socket of type `T` identified by `socket`, and return
+/// the value it returns or the code of its error.
+unsafe fn with_socket<T, F>(stack: *mut Stack, socket: i32, f: F) -> i32
+        where T: AnySocket<'static, 'static>, F: FnOnce(&mut T) -> Result<i32> {
+    let stack = match stack.as_mut() {
+        Some(stack) => stack,
+        None => return SMOLTCP_ERR_INVALID
+    };
+    let handle = match stack.handle(socket) {
+        Some(handle) => handle,
+        None => return SMOLTCP_ERR_INVALID
+    };
+    match T::downcast(stack.sockets.get_any(handle)) {
+        Some(mut socket) => f(&mut socket).unwrap_or_else(error_code),
+        None => SMOLTCP_ERR_INVALID
+    }
+}
+
+unsafe fn slice_from<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    if data.is_null() { if len == 0 { Some(&[]) } else { None } }
+    else { Some(slice::from_raw_parts(data, len)) }
+}
+
+unsafe fn slice_from_mut<'a>(data: *mut u8, len: usize) -> Option<&'a mut [u8]> {
+    if data.is_null() { if len == 0 { Some(&mut []) } else { None } }
+    else { Some(slice::from_raw_parts_mut(data, len)) }
+}
+
+/// Create a stack over the given driver, or return null if the configuration is invalid.
+#[no_mangle]
+pub unsafe extern "C" fn smoltcp_stack_new(driver: Driver, config: *const Config) -> *mut Stack {
+    let config = match config.as_ref() {
+        Some(config) => config,
+        None => return ptr::null_mut()
+    };
+    let ethernet_addr = EthernetAddress(config.ethernet_addr);
+    let ipv4_addr = Ipv4Address(config.ipv4_addr);
+    let ipv4_gateway = Ipv4Address(config.ipv4_gateway);
+    if !ethernet_addr.is_unicast() || !ipv4_addr.is_unicast() ||
+            config.ipv4_prefix_len > 32 || config.max_frame_size == 0 ||
+            !(ipv4_gateway.is_unspecified() || ipv4_gateway.is_unicast()) {
+        return ptr::null_mut()
+    }
+
+    let device = SpiEthDevice::new(driver, vec![0; config.max_frame_size],
+                                   vec![0; config.max_frame_size]);
+    let mut builder = EthernetInterfaceBuilder::new(device)
+            .ethernet_addr(ethernet_addr)
+            .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+            .ip_addrs(vec![IpCidr::new(ipv4_addr.into(), config.ipv4_prefix_len)]);
+    if !ipv4_gateway.is_unspecified() {
+        builder = builder.ipv4_gateway(ipv4_gateway);
+    }
+
+    Box::into_raw(Box::new(Stack {
+        iface:   builder.finalize(),
+        sockets: SocketSet::new(vec![]),
+        handles: vec![],
+    }))
+}
+
+/// Free a stack and all of its sockets.
+#[no_mangle]
+pub unsafe extern "C" fn smoltcp_stack_free(stack: *mut Stack) {
+    if !stack.is_null() {
+        drop(Box::from_raw(stack))
+    }
+}
+
+/// Poll the interface at the given timestamp, in milliseconds, and return 1 if
+/// the state of any socket changed, 0 if not, or an error code.
+///
+/// The errors concern individual packets; polling should continue regardless.
+#[no_mangle]
+pub unsafe extern "C" fn smoltcp_poll(stack: *mut Stack, timestamp: u64) -> i32 {
+    match stack.as_mut() {
+        Some(stack) => match stack.iface.poll(&mut stack.sockets, timestamp) {
+            Ok(changed) => changed as i32,
+            Err(err) => error_code(err)
+        },
+        None => SMOLTCP_ERR_INVALID
+    }
+}
+
+/// Return the number of milliseconds after which the interface should be polled
+/// again at the latest, or -1 if it only has to be polled once a frame is received.
+#[no_mangle]
+pub unsafe extern "C" fn smoltcp_poll_delay(stack: *const Stack, timestamp: u64) -> i64 {
+    match stack.as_ref() {
+        Some(stack) => match stack.iface.poll_delay(&stack.sockets, timestamp) {
+            Some(delay) => delay as i64,
+            None => -1
+        },
+        None => -1
+    }
+}
+
+/// Remove a socket of any type from the stack. Its number may be reused.
+#[no_mangle]
+pub unsafe extern "C" fn smoltcp_socket_free(stack: *mut Stack, socket: i32) -> i32 {
+    let stack = match stack.as_mut() {
+        Some(stack) => stack,
+        None => return SMOLTCP_ERR_INVALID
+    };
+    match stack.handle(socket) {
+        Some(handle) => {
+            stack.sockets.remove(handle);
+            stack.handles[socket as usize] = None;
+            0
+        }
+        None => SMOLTCP_ERR_INVALID
+    }
+}
+
+/// Add a TCP socket with buffers of the given sizes to the stack, and return its number.
+#[no_mangle]
+pub unsafe extern "C" fn smoltcp_tcp_new(stack: *mut Stack, rx_size: usize,
+                                         tx_size: usize) -> i32 {
+    match stack.as_mut() {
+        Some(stack) => {
+            let socket = TcpSocket::new(TcpSocketBuffer::new(vec![0; rx_size]),
+                                        TcpSocketBuffer::new(vec![0; tx_size]));
+            stack.add(socket)
+        }
+        None => SMOLTCP_ERR_INVALID
+    }
+}
+
+/// Listen for a TCP connection on the given local port.
+#[no_mangle]
+pub unsafe extern "C" fn smoltcp_tcp_listen(stack: *mut Stack, socket: i32, port: u16) -> i32 {
+    with_socket(stack, socket, |socket: &mut TcpSocket| {
+        socket.listen(port).map(|()| 0)
+    })
+}
+
+/// Connect to the given remote endpoint from the given local port.
+#[no_mangle]
+pub unsafe extern "C" fn smoltcp_tcp_connect(stack: *mut Stack, socket: i32,
+                                             remote: Endpoint, local_port: u16) -> i32 {
+    with_socket(stack, socket, |socket: &mut TcpSocket| {
+        socket.connect(IpEndpoint::from(remote), local_port).map(|()| 0)
+    })
+}
+
+/// Enqueue up to `len` octets to be sent, and return the number of octets enqueued.
+#[no_mangle]
+pub unsafe extern "C" fn smoltcp_tcp_send(stack: *mut Stack, socket: i32,
+                                          data: *const u8, len: usize) -> i32 {
+    let data = match slice_from(data, len) {
+        Some(data) => data,
+        None => return SMOLTCP_ERR_INVALID
+    };
+    with_socket(stack, socket, |socket: &mut TcpSocket| {
+        socket.send_slice(data).map(|size| size as i32)
+    })
+}
+
+/// Dequeue up to `len` received octets, and return the number of octets dequeued.
+#[no_mangle]
+pub unsafe extern "C" fn smoltcp_tcp_recv(stack: *mut Stack, socket: i32,
+                                          data: *mut u8, len: usize) -> i32 {
+    let data = match slice_from_mut(data, len) {
+        Some(data) => data,
+        None => return SMOLTCP_ERR_INVALID
+    };
+    with_socket(stack, socket, |socket: &mut TcpSocket| {
+        socket.recv_slice(data).map(|size| size as i32)
+    })
+}
+
+/// Close the transmit half of the connection.
+#[no_mangle]
+pub unsafe extern "C" fn smoltcp_tcp_close(stack: *mut Stack, socket: i32) -> i32 {
+    with_socket(stack, socket, |socket: &mut TcpSocket| {
+        socket.close();
+        Ok(0)
+    })
+}
+
+/// Return the state of the connection, as a `SMOLTCP_TCP_*` value.
+#[no_mangle]
+pub unsafe extern "C" fn smoltcp_tcp_state(stack: *mut Stack, socket: i32) -> i32 {
+    with_socket(stack, socket, |socket: &mut TcpSocket| {
+        Ok(match socket.state() {
+            TcpState::Closed      => 0,
+            TcpState::Listen      => 1,
+            TcpState::SynSent     => 2,
+            TcpState::SynReceived => 3,
+            TcpState::Established => 4,
+            TcpState::FinWait1    => 5,
+            TcpState::FinWait2    => 6,
+            TcpState::CloseWait   => 7,
+            TcpState::Closing     => 8,
+            TcpState::LastAck     => 9,
+            TcpState::TimeWait    => 10,
+        })
+    })
+}
+
+/// Add a UDP socket with buffers of `packets` packets of up to `packet_size` octets each
+/// to the stack, and return its number.
+#[no_mangle]
+pub unsafe extern "C" fn smoltcp_udp_new(stack: *mut Stack, packets: usize,
+                                         packet_size: usize) -> i32 {
+    fn buffer(packets: usize, packet_size: usize) -> UdpSocketBuffer<'static, 'static> {
+        let packets: Vec<_> = (0..packets).map(|_| UdpPacketBuffer::new(vec![0; packet_size]))
+                                          .collect();
+        UdpSocketBuffer::new(packets)
+    }
+
+    match stack.as_mut() {
+        Some(stack) => {
+            let socket = UdpSocket::new(buffer(packets, packet_size),
+                                        buffer(packets, packet_size));
+            stack.add(socket)
+        }
+        None => SMOLTCP_ERR_INVALID
+    }
+}
+
+/// Bind the socket to the given local port.
+#[no_mangle]
+pub unsafe extern "C" fn smoltcp_udp_bind(stack: *mut Stack, socket: i32, port: u16) -> i32 {
+    with_socket(stack, socket, |socket: &mut UdpSocket| {
+        socket.bind(port).map(|()| 0)
+    })
+}
+
+/// Enqueue a datagram of `len` octets to be sent to the given remote endpoint.
+#[no_mangle]
+pub unsafe extern "C" fn smoltcp_udp_send(stack: *mut Stack, socket: i32,
+                                          data: *const u8, len: usize, remote: Endpoint) -> i32 {
+    let data = match slice_from(data, len) {
+        Some(data) => data,
+        None => return SMOLTCP_ERR_INVALID
+    };
+    with_socket(stack, socket, |socket: &mut UdpSocket| {
+        socket.send_slice(data, remote.into()).map(|()| 0)
+    })
+}
+
+/// Dequeue a received datagram into a buffer of `len` octets, storing its source
+/// in `remote` unless it is null, and return its length.
+///
+/// A datagram larger than the buffer is truncated.
+#[no_mangle]
+pub unsafe extern "C" fn smoltcp_udp_recv(stack: *mut Stack, socket: i32,
+                                          data: *mut u8, len: usize,
+                                          remote: *mut Endpoint) -> i32 {
+    let data = match slice_from_mut(data, len) {
+        Some(data) => data,
+        None => return SMOLTCP_ERR_INVALID
+    };
+    with_socket(stack, socket, |socket: &mut UdpSocket| {
+        let (size, endpoint) = socket.recv_slice(data)?;
+        if let Some(remote) = remote.as_mut() {
+            *remote = endpoint.into();
+        }
+        Ok(size as i32)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+    use core::cmp;
+    use super::*;
+
+    const ADDR: [u8; 4] = [192, 168, 1, 1];
+
+    extern "C" fn receive_frame(ctx: *mut c_void, buffer: *mut u8, len: usize) -> isize {
+        let queue = unsafe { &mut *(ctx as *mut VecDeque<Vec<u8>>) };
+        match queue.pop_front() {
+            Some(frame) => {
+                let size = cmp::min(frame.len(), len);
+                unsafe { ptr::copy_nonoverlapping(frame.as_ptr(), buffer, size) };
+                frame.len() as isize
+            }
+            None => 0
+        }
+    }
+
+    extern "C" fn send_frame(ctx: *mut c_void, frame: *const u8, len: usize) -> i32 {
+        let queue = unsafe { &mut *(ctx as *mut VecDeque<Vec<u8>>) };
+        queue.push_back(unsafe { slice::from_raw_parts(frame, len) }.to_vec());
+        0
+    }
+
+    fn config() -> Config {
+        Config {
+            ethernet_addr:   [0x02, 0, 0, 0, 0, 1],
+            ipv4_addr:       ADDR,
+            ipv4_prefix_len: 24,
+            ipv4_gateway:    [0; 4],
+            max_frame_size:  1514,
+        }
+    }
+
+    fn poll(stack: *mut Stack, timestamp: &mut u64) {
+        for _ in 0..20 {
+            *timestamp += 1;
+            unsafe { smoltcp_poll(stack, *timestamp) };
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut queue = VecDeque::<Vec<u8>>::new();
+        let driver = Driver {
+            ctx: &mut queue as *mut _ as *mut c_void,
+            receive_frame, send_frame
+        };
+        unsafe {
+            assert!(smoltcp_stack_new(driver, ptr::null()).is_null());
+            let mut invalid = config();
+            invalid.ipv4_prefix_len = 33;
+            assert!(smoltcp_stack_new(driver, &invalid).is_null());
+
+            let stack = smoltcp_stack_new(driver, &config());
+            assert!(!stack.is_null());
+            assert_eq!(smoltcp_tcp_state(stack, 0), SMOLTCP_ERR_INVALID);
+            let socket = smoltcp_udp_new(stack, 1, 64);
+            assert_eq!(socket, 0);
+            assert_eq!(smoltcp_tcp_state(stack, socket), SMOLTCP_ERR_INVALID);
+            assert_eq!(smoltcp_udp_recv(stack, socket, ptr::null_mut(), 0, ptr::null_mut()),
+                       SMOLTCP_ERR_EXHAUSTED);
+            assert_eq!(smoltcp_socket_free(stack, socket), 0);
+            assert_eq!(smoltcp_socket_free(stack, socket), SMOLTCP_ERR_INVALID);
+            smoltcp_stack_free(stack);
+        }
+    }
+
+    #[test]
+    fn test_tcp() {
+        let mut queue = VecDeque::<Vec<u8>>::new();
+        let driver = Driver {
+            ctx: &mut queue as *mut _ as *mut c_void,
+            receive_frame, send_frame
+        };
+        let mut timestamp = 0;
+        unsafe {
+            let stack = smoltcp_stack_new(driver, &config());
+            let server = smoltcp_tcp_new(stack, 64, 64);
+            let client = smoltcp_tcp_new(stack, 64, 64);
+            assert_eq!(smoltcp_tcp_listen(stack, server, 1234), 0);
+            assert_eq!(smoltcp_tcp_state(stack, server), 1);
+            let remote = Endpoint { addr: ADDR, port: 1234 };
+            assert_eq!(smoltcp_tcp_connect(stack, client, remote, 49152), 0);
+            poll(stack, &mut timestamp);
+            assert_eq!(smoltcp_tcp_state(stack, client), 4);
+            assert_eq!(smoltcp_tcp_state(stack, server), 4);
+
+            assert_eq!(smoltcp_tcp_send(stack, client, b"hello".as_ptr(), 5), 5);
+            poll(stack, &mut timestamp);
+            let mut data = [0; 16];
+            assert_eq!(smoltcp_tcp_recv(stack, server, data.as_mut_ptr(), data.len()), 5);
+            assert_eq!(&data[..5], b"hello");
+
+            assert_eq!(smoltcp_tcp_close(stack, client), 0);
+            poll(stack, &mut timestamp);
+            assert_eq!(smoltcp_tcp_state(stack, server), 7);
+            smoltcp_stack_free(stack);
+        }
+    }
+
+    #[test]
+    fn test_udp() {
+        let mut queue = VecDeque::<Vec<u8>>::new();
+        let driver = Driver {
+            ctx: &mut queue as *mut _ as *mut c_void,
+            receive_frame, send_frame
+        };
+        let mut timestamp = 0;
+        unsafe {
+            let stack = smoltcp_stack_new(driver, &config());
+            let server = smoltcp_udp_new(stack, 2, 64);
+            let client = smoltcp_udp_new(stack, 2, 64);
+            assert_eq!(smoltcp_udp_bind(stack, server, 1234), 0);
+            assert_eq!(smoltcp_udp_bind(stack, client, 5678), 0);
+
+            let remote = Endpoint { addr: ADDR, port: 1234 };
+            assert_eq!(smoltcp_udp_send(stack, client, b"hello".as_ptr(), 5, remote), 0);
+            poll(stack, &mut timestamp);
+            let mut data = [0; 16];
+            let mut source = Endpoint::default();
+            assert_eq!(smoltcp_udp_recv(stack, server, data.as_mut_ptr(), data.len(),
+                                        &mut source), 5);
+            assert_eq!(&data[..5], b"hello");
+            assert_eq!(source.addr, ADDR);
+            assert_eq!(source.port, 5678);
+            smoltcp_stack_free(stack);
+        }
+    }
+}
@@ -0,0 +1,774 @@
+// Heads up! Before working on this file you should read RFC 2131,
+// which describes the client state machine, and RFC 2132, which
+// describes the options.
+
+use core::cmp;
+
+use {Error, Result};
+use phy::{Device, ChecksumCapabilities};
+use wire::{IpVersion, IpProtocol, EthernetAddress,
+           Ipv4Address, Ipv4Cidr, Ipv4Packet, Ipv4Repr,
+           UdpPacket, UdpRepr,
+           DhcpPacket, DhcpRepr, DhcpMessageType, DhcpOption,
+           DHCP_CLIENT_PORT, DHCP_SERVER_PORT};
+use wire::dhcpv4::{field as dhcpv4_field, MAX_DNS_SERVER_COUNT};
+use socket::{SocketSet, SocketHandle, RawSocket, RawSocketBuffer};
+use iface::{EthernetInterface, Identity};
+
+/// The initial retransmission timeout of DHCPDISCOVER and DHCPREQUEST messages.
+const INITIAL_RETRY_TIMEOUT: u64 = 4_000;
+/// The retransmission timeout is doubled until it reaches this value.
+const MAX_RETRY_TIMEOUT: u64 = 64_000;
+/// The number of DHCPREQUEST messages sent for an offer before starting over.
+const MAX_REQUEST_RETRIES: u8 = 5;
+/// The minimum interval between DHCPREQUEST messages while renewing or rebinding a lease.
+const MIN_RENEW_TIMEOUT: u64 = 60_000;
+/// The lease duration assumed if the server does not specify one, in seconds.
+const DEFAULT_LEASE_DURATION: u32 = 120;
+
+const PARAMETER_REQUEST_LIST: &[u8] = &[
+    dhcpv4_field::OPT_SUBNET_MASK,
+    dhcpv4_field::OPT_ROUTER,
+    dhcpv4_field::OPT_DOMAIN_NAME_SERVER,
+];
+
+/// IPv4 configuration data provided by the DHCP server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Config {
+    /// The interface address and prefix length, or `None` if the lease was lost
+    /// and the address must not be used anymore.
+    pub address: Option<Ipv4Cidr>,
+    /// The moment the lease expires, as a timestamp, unless it is renewed before;
+    /// it should be passed to [set_ip_addr_lifetime] with the address.
+    ///
+    /// [set_ip_addr_lifetime]: ../iface/struct.EthernetInterface.html#method.set_ip_addr_lifetime
+    pub expires_at: Option<u64>,
+    /// The default gateway.
+    pub router: Option<Ipv4Address>,
+    /// DNS servers, in order of preference.
+    pub dns_servers: [Option<Ipv4Address>; MAX_DNS_SERVER_COUNT],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RequestState {
+    retry: u8,
+    server_identifier: Ipv4Address,
+    requested_ip: Ipv4Address,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RenewState {
+    server_identifier: Ipv4Address,
+    address: Ipv4Address,
+    renew_at: u64,
+    rebind_at: u64,
+    expires_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Looking for a server by broadcasting DHCPDISCOVER.
+    Discovering,
+    /// Requesting an offered address by broadcasting DHCPREQUEST.
+    Requesting(RequestState),
+    /// Holding a lease, and renewing (or rebinding) it once it is due.
+    Renewing(RenewState),
+}
+
+/// A DHCPv4 client.
+///
+/// The client talks to DHCP servers through a raw UDP socket that it adds to the socket set,
+/// and reports every change of the IPv4 configuration from [poll], where it is up to
+/// the application to apply it to the interface.
+///
+/// Until a lease is obtained, the interface should have the unspecified address
+/// `0.0.0.0/0` as its only IPv4 address, so that the client can send packets from it.
+///
+/// The hostname, vendor class identifier, client identifier and list of requested options
+/// sent in DHCP messages are taken from the [identity] of the interface.
+///
+/// [poll]: #method.poll
+/// [identity]: ../iface/struct.Identity.html
+#[derive(Debug)]
+pub struct Client {
+    state: State,
+    raw_handle: SocketHandle,
+    /// When to send the next DHCP message.
+    next_egress: u64,
+    /// The current retransmission timeout, for discovering and requesting.
+    retry_timeout: u64,
+    transaction_id: u32,
+    rand_state: u32,
+    /// The configuration last reported to the application.
+    config: Config,
+    /// The configuration change to report from the next call to `poll`.
+    pending_config: Option<Config>,
+}
+
+impl Client {
+    /// Create a DHCPv4 client, and add its raw socket, created with the given buffers,
+    /// to the socket set.
+    ///
+    /// The receive buffer must be able to hold DHCP messages of at least 576 octets.
+    pub fn new<'a, 'b, 'c>(sockets: &mut SocketSet<'a, 'b, 'c>,
+                           rx_buffer: RawSocketBuffer<'b, 'c>,
+                           tx_buffer: RawSocketBuffer<'b, 'c>,
+                           now: u64) -> Client {
+        let raw_socket = RawSocket::new(IpVersion::Ipv4, IpProtocol::Udp, rx_buffer, tx_buffer);
+        let raw_handle = sockets.add(raw_socket);
+
+        Client {
+            state: State::Discovering,
+            raw_handle: raw_handle,
+            next_egress: now,
+            retry_timeout: INITIAL_RETRY_TIMEOUT,
+            transaction_id: 0,
+            rand_state: (now as u32) ^ ((now >> 32) as u32) ^ 0x2545f491,
+            config: Config::default(),
+            pending_config: None,
+        }
+    }
+
+    /// Return the handle of the raw socket used by the client.
+    pub fn raw_handle(&self) -> SocketHandle {
+        self.raw_handle
+    }
+
+    /// Return the time, in milliseconds, at which the client should be polled next,
+    /// assuming no packets arrive in the meantime.
+    pub fn next_poll(&self, now: u64) -> u64 {
+        cmp::max(self.next_egress, now)
+    }
+
+    /// Restart the client, e.g. after the link was down.
+    ///
+    /// If a lease was held, the next call to [poll] reports that it was lost.
+    ///
+    /// [poll]: #method.poll
+    pub fn reset(&mut self, now: u64) {
+        net_trace!("DHCP reset");
+        self.configure(Config::default());
+        self.start_discovering(now);
+    }
+
+    /// Process DHCP messages received by the raw socket, and send any messages that are due.
+    ///
+    /// Returns `Ok(Some(config))` if the IPv4 configuration of the interface has to change,
+    /// including when the lease is renewed, and `Ok(None)` otherwise.
+    pub fn poll<'b, 'c, DeviceT>(&mut self, iface: &EthernetInterface<'b, 'c, DeviceT>,
+                                 sockets: &mut SocketSet, now: u64) -> Result<Option<Config>>
+            where DeviceT: for<'d> Device<'d> {
+        let ethernet_addr = iface.ethernet_addr();
+        let identity = iface.identity();
+
+        {
+            let mut raw_socket = sockets.get::<RawSocket>(self.raw_handle);
+            while let Ok(packet) = raw_socket.recv() {
+                match parse_reply(packet) {
+                    Ok(dhcp_repr) => self.ingress(&dhcp_repr, ethernet_addr, now),
+                    Err(_) => {
+                        net_trace!("DHCP ignoring malformed or unrelated packet");
+                    }
+                }
+            }
+        }
+
+        if now >= self.next_egress {
+            self.egress(sockets, ethernet_addr, identity, now)?;
+        }
+
+        Ok(self.pending_config.take())
+    }
+
+    fn ingress(&mut self, dhcp_repr: &DhcpRepr, ethernet_addr: EthernetAddress, now: u64) {
+        if dhcp_repr.transaction_id != self.transaction_id ||
+                dhcp_repr.client_hardware_address != ethernet_addr {
+            return
+        }
+
+        match (self.state, dhcp_repr.message_type) {
+            (State::Discovering, DhcpMessageType::Offer) => {
+                let server_identifier = match dhcp_repr.server_identifier {
+                    Some(addr) if addr.is_unicast() => addr,
+                    _ => return
+                };
+                if !dhcp_repr.your_ip.is_unicast() { return }
+
+                net_debug!("DHCP offer of {} from {}", dhcp_repr.your_ip, server_identifier);
+                self.state = State::Requesting(RequestState {
+                    retry: 0,
+                    server_identifier: server_identifier,
+                    requested_ip: dhcp_repr.your_ip,
+                });
+                self.retry_timeout = INITIAL_RETRY_TIMEOUT;
+                self.next_egress = now;
+            }
+            (State::Requesting(_), DhcpMessageType::Ack) |
+            (State::Renewing(_), DhcpMessageType::Ack) => {
+                let server_identifier = match (dhcp_repr.server_identifier, self.state) {
+                    (Some(addr), _) => addr,
+                    (None, State::Requesting(state)) => state.server_identifier,
+                    (None, State::Renewing(state)) => state.server_identifier,
+                    (None, State::Discovering) => return
+                };
+                let prefix_len = match dhcp_repr.subnet_mask.and_then(netmask_prefix_len) {
+                    Some(prefix_len) => prefix_len,
+                    None => {
+                        net_debug!("DHCP ignoring ack without a valid subnet mask");
+                        return
+                    }
+                };
+                if !dhcp_repr.your_ip.is_unicast() { return }
+
+                let lease_duration = dhcp_repr.lease_duration
+                    .unwrap_or(DEFAULT_LEASE_DURATION) as u64 * 1000;
+                let renew_duration = dhcp_repr.renew_duration
+                    .map(|duration| duration as u64 * 1000)
+                    .unwrap_or(lease_duration / 2);
+                let rebind_duration = dhcp_repr.rebind_duration
+                    .map(|duration| duration as u64 * 1000)
+                    .unwrap_or(lease_duration / 8 * 7);
+
+                let renew_state = RenewState {
+                    server_identifier: server_identifier,
+                    address: dhcp_repr.your_ip,
+                    renew_at: now + renew_duration,
+                    rebind_at: now + rebind_duration,
+                    expires_at: now + lease_duration,
+                };
+                net_debug!("DHCP ack of {}/{} for {} ms",
+                           dhcp_repr.your_ip, prefix_len, lease_duration);
+
+                let config = Config {
+                    address: Some(Ipv4Cidr::new(dhcp_repr.your_ip, prefix_len)),
+                    expires_at: Some(renew_state.expires_at),
+                    router: dhcp_repr.router,
+                    dns_servers: dhcp_repr.dns_servers.unwrap_or([None; MAX_DNS_SERVER_COUNT]),
+                };
+                self.configure(config);
+                self.state = State::Renewing(renew_state);
+                self.next_egress = renew_state.renew_at;
+            }
+            (State::Requesting(_), DhcpMessageType::Nak) |
+            (State::Renewing(_), DhcpMessageType::Nak) => {
+                net_debug!("DHCP nak");
+                self.configure(Config::default());
+                self.start_discovering(now);
+            }
+            _ => ()
+        }
+    }
+
+    fn egress(&mut self, sockets: &mut SocketSet, ethernet_addr: EthernetAddress,
+              identity: &Identity, now: u64) -> Result<()> {
+        let mut options = [DhcpOption::Pad; 3];
+        let mut option_count = 0;
+        if let Some(hostname) = identity.hostname() {
+            options[option_count] =
+                DhcpOption::Other { kind: dhcpv4_field::OPT_HOST_NAME, data: hostname.as_bytes() };
+            option_count += 1;
+        }
+        if let Some(vendor_class) = identity.vendor_class() {
+            options[option_count] =
+                DhcpOption::Other { kind: dhcpv4_field::OPT_VENDOR_CLASS_ID, data: vendor_class };
+            option_count += 1;
+        }
+        if let Some(client_id) = identity.client_id() {
+            options[option_count] =
+                DhcpOption::Other { kind: dhcpv4_field::OPT_CLIENT_ID, data: client_id };
+            option_count += 1;
+        }
+
+        let mut dhcp_repr = DhcpRepr {
+            message_type: DhcpMessageType::Discover,
+            transaction_id: self.transaction_id,
+            client_hardware_address: ethernet_addr,
+            client_ip: Ipv4Address::UNSPECIFIED,
+            your_ip: Ipv4Address::UNSPECIFIED,
+            server_ip: Ipv4Address::UNSPECIFIED,
+            router: None,
+            subnet_mask: None,
+            relay_agent_ip: Ipv4Address::UNSPECIFIED,
+            broadcast: true,
+            requested_ip: None,
+            client_identifier: match identity.client_id() {
+                Some(_) => None,
+                None => Some(ethernet_addr)
+            },
+            server_identifier: None,
+            parameter_request_list: Some(identity.requested_options()
+                                                 .unwrap_or(PARAMETER_REQUEST_LIST)),
+            dns_servers: None,
+            max_size: Some(1500),
+            lease_duration: None,
+            renew_duration: None,
+            rebind_duration: None,
+            additional_options: &options[..option_count],
+        };
+
+        match self.state {
+            State::Discovering => {
+                self.transaction_id = self.random_u32();
+                dhcp_repr.transaction_id = self.transaction_id;
+                net_trace!("DHCP send discover");
+                send(sockets, self.raw_handle, Ipv4Address::UNSPECIFIED,
+                     Ipv4Address::BROADCAST, &dhcp_repr)?;
+
+                self.next_egress = now + self.retry_timeout;
+                self.retry_timeout = cmp::min(self.retry_timeout * 2, MAX_RETRY_TIMEOUT);
+            }
+            State::Requesting(mut state) => {
+                if state.retry >= MAX_REQUEST_RETRIES {
+                    net_debug!("DHCP no ack to request, starting over");
+                    self.start_discovering(now);
+                    return self.egress(sockets, ethernet_addr, identity, now)
+                }
+
+                dhcp_repr.message_type = DhcpMessageType::Request;
+                dhcp_repr.requested_ip = Some(state.requested_ip);
+                dhcp_repr.server_identifier = Some(state.server_identifier);
+                net_trace!("DHCP send request for {}", state.requested_ip);
+                send(sockets, self.raw_handle, Ipv4Address::UNSPECIFIED,
+                     Ipv4Address::BROADCAST, &dhcp_repr)?;
+
+                state.retry += 1;
+                self.state = State::Requesting(state);
+                self.next_egress = now + self.retry_timeout;
+                self.retry_timeout = cmp::min(self.retry_timeout * 2, MAX_RETRY_TIMEOUT);
+            }
+            State::Renewing(state) => {
+                if now >= state.expires_at {
+                    net_debug!("DHCP lease of {} expired", state.address);
+                    self.configure(Config::default());
+                    self.start_discovering(now);
+                    return self.egress(sockets, ethernet_addr, identity, now)
+                }
+
+                // Renew with the server that granted the lease until T2, then rebind
+                // with any server.
+                let (dst_addr, deadline) = if now >= state.rebind_at {
+                    (Ipv4Address::BROADCAST, state.expires_at)
+                } else {
+                    (state.server_identifier, state.rebind_at)
+                };
+
+                dhcp_repr.message_type = DhcpMessageType::Request;
+                dhcp_repr.client_ip = state.address;
+                dhcp_repr.broadcast = false;
+                net_trace!("DHCP send renew of {} to {}", state.address, dst_addr);
+                send(sockets, self.raw_handle, state.address, dst_addr, &dhcp_repr)?;
+
+                // See RFC 2131 § 4.4.5.
+                self.next_egress = cmp::min(now + cmp::max((deadline - now) / 2, MIN_RENEW_TIMEOUT),
+                                            deadline);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn configure(&mut self, config: Config) {
+        if config != self.config {
+            self.config = config;
+            self.pending_config = Some(config);
+        }
+    }
+
+    fn start_discovering(&mut self, now: u64) {
+        self.state = State::Discovering;
+        self.retry_timeout = INITIAL_RETRY_TIMEOUT;
+        self.next_egress = now;
+    }
+
+    fn random_u32(&mut self) -> u32 {
+        // xorshift32; transaction IDs only need to be distinct, not unpredictable.
+        let mut x = self.rand_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rand_state = x;
+        x
+    }
+}
+
+/// Parse a DHCP reply out of an IPv4 packet received by the raw socket.
+fn parse_reply<'a>(packet: &'a [u8]) -> Result<DhcpRepr<'a>> {
+    let checksum_caps = ChecksumCapabilities::default();
+    let ipv4_packet = Ipv4Packet::new_checked(packet)?;
+    let ipv4_repr = Ipv4Repr::parse(&ipv4_packet, &checksum_caps)?;
+    let udp_packet = UdpPacket::new_checked(ipv4_packet.payload())?;
+    let udp_repr = UdpRepr::parse(&udp_packet, &ipv4_repr.src_addr.into(),
+                                  &ipv4_repr.dst_addr.into(), &checksum_caps)?;
+    if udp_repr.src_port != DHCP_SERVER_PORT || udp_repr.dst_port != DHCP_CLIENT_PORT {
+        return Err(Error::Unrecognized)
+    }
+    let dhcp_packet = DhcpPacket::new_checked(udp_repr.payload)?;
+    DhcpRepr::parse(&dhcp_packet)
+}
+
+/// Enqueue a DHCP message in a UDP datagram on the raw socket.
+fn send(sockets: &mut SocketSet, raw_handle: SocketHandle,
+        src_addr: Ipv4Address, dst_addr: Ipv4Address, dhcp_repr: &DhcpRepr) -> Result<()> {
+    let udp_len = 8 + dhcp_repr.buffer_len();
+    let ipv4_repr = Ipv4Repr {
+        src_addr: src_addr,
+        dst_addr: dst_addr,
+        protocol: IpProtocol::Udp,
+        payload_len: udp_len,
+        hop_limit: 64,
+        ecn: 0,
+        dscp: 0,
+    };
+
+    let mut raw_socket = sockets.get::<RawSocket>(raw_handle);
+    let buffer = raw_socket.send(ipv4_repr.buffer_len() + udp_len)?;
+    let mut ipv4_packet = Ipv4Packet::new(buffer);
+    ipv4_repr.emit(&mut ipv4_packet, &ChecksumCapabilities::default());
+
+    let mut udp_packet = UdpPacket::new(ipv4_packet.payload_mut());
+    udp_packet.set_src_port(DHCP_CLIENT_PORT);
+    udp_packet.set_dst_port(DHCP_SERVER_PORT);
+    udp_packet.set_len(udp_len as u16);
+    dhcp_repr.emit(&mut DhcpPacket::new(udp_packet.payload_mut()));
+    udp_packet.fill_checksum(&src_addr.into(), &dst_addr.into());
+    Ok(())
+}
+
+/// Convert a contiguous netmask into a prefix length.
+fn netmask_prefix_len(netmask: Ipv4Address) -> Option<u8> {
+    let bits = (netmask.0[0] as u32) << 24 | (netmask.0[1] as u32) << 16 |
+               (netmask.0[2] as u32) << 8  |  netmask.0[3] as u32;
+    let host_bits = !bits;
+    if host_bits & host_bits.wrapping_add(1) == 0 {
+        Some(bits.count_ones() as u8)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use std::collections::BTreeMap;
+    use phy::Loopback;
+    use wire::IpRepr;
+    use iface::{EthernetInterfaceBuilder, NeighborCache};
+    use socket::RawPacketBuffer;
+    use super::*;
+
+    const CLIENT_MAC: EthernetAddress = EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+    const SERVER_IP:  Ipv4Address = Ipv4Address([192, 168, 1, 1]);
+    const OFFERED_IP: Ipv4Address = Ipv4Address([192, 168, 1, 23]);
+
+    fn buffer() -> RawSocketBuffer<'static, 'static> {
+        RawSocketBuffer::new(vec![RawPacketBuffer::new(vec![0; 1500]),
+                                  RawPacketBuffer::new(vec![0; 1500])])
+    }
+
+    fn setup() -> (EthernetInterface<'static, 'static, Loopback>,
+                   SocketSet<'static, 'static, 'static>, Client) {
+        let iface = EthernetInterfaceBuilder::new(Loopback::new())
+                .ethernet_addr(CLIENT_MAC)
+                .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+                .finalize();
+        let mut sockets = SocketSet::new(vec![]);
+        let client = Client::new(&mut sockets, buffer(), buffer(), 0);
+        (iface, sockets, client)
+    }
+
+    fn sent(sockets: &mut SocketSet, client: &Client) -> Option<(Ipv4Repr, Vec<u8>)> {
+        let mut result = None;
+        let _ = sockets.get::<RawSocket>(client.raw_handle())
+            .dispatch(&ChecksumCapabilities::default(), |(ip_repr, payload)| {
+                match ip_repr {
+                    IpRepr::Ipv4(ipv4_repr) => result = Some((ipv4_repr, payload.to_vec())),
+                    _ => unreachable!()
+                }
+                Ok(())
+            });
+        result
+    }
+
+    fn parse_sent<'a>(ipv4_repr: &Ipv4Repr, payload: &'a [u8]) -> DhcpRepr<'a> {
+        let udp_packet = UdpPacket::new_checked(payload).unwrap();
+        let udp_repr = UdpRepr::parse(&udp_packet, &ipv4_repr.src_addr.into(),
+                                      &ipv4_repr.dst_addr.into(),
+                                      &ChecksumCapabilities::default()).unwrap();
+        assert_eq!(udp_repr.src_port, DHCP_CLIENT_PORT);
+        assert_eq!(udp_repr.dst_port, DHCP_SERVER_PORT);
+        DhcpRepr::parse(&DhcpPacket::new_checked(udp_repr.payload).unwrap()).unwrap()
+    }
+
+    fn server_repr(message_type: DhcpMessageType, transaction_id: u32) -> DhcpRepr<'static> {
+        DhcpRepr {
+            message_type: message_type,
+            transaction_id: transaction_id,
+            client_hardware_address: CLIENT_MAC,
+            client_ip: Ipv4Address::UNSPECIFIED,
+            your_ip: OFFERED_IP,
+            server_ip: Ipv4Address::UNSPECIFIED,
+            router: Some(SERVER_IP),
+            subnet_mask: Some(Ipv4Address([255, 255, 255, 0])),
+            relay_agent_ip: Ipv4Address::UNSPECIFIED,
+            broadcast: false,
+            requested_ip: None,
+            client_identifier: None,
+            server_identifier: Some(SERVER_IP),
+            parameter_request_list: None,
+            dns_servers: Some([Some(SERVER_IP), None, None]),
+            max_size: None,
+            lease_duration: Some(1000),
+            renew_duration: None,
+            rebind_duration: None,
+            additional_options: &[],
+        }
+    }
+
+    fn reply(sockets: &mut SocketSet, client: &Client, dhcp_repr: &DhcpRepr) {
+        let mut bytes = vec![0; 8 + dhcp_repr.buffer_len()];
+        {
+            let mut udp_packet = UdpPacket::new(&mut bytes[..]);
+            udp_packet.set_src_port(DHCP_SERVER_PORT);
+            udp_packet.set_dst_port(DHCP_CLIENT_PORT);
+            udp_packet.set_len(8 + dhcp_repr.buffer_len() as u16);
+            dhcp_repr.emit(&mut DhcpPacket::new(udp_packet.payload_mut()));
+            udp_packet.fill_checksum(&SERVER_IP.into(), &Ipv4Address::BROADCAST.into());
+        }
+        let ip_repr = IpRepr::Ipv4(Ipv4Repr {
+            src_addr: SERVER_IP,
+            dst_addr: Ipv4Address::BROADCAST,
+            protocol: IpProtocol::Udp,
+            payload_len: bytes.len(),
+            hop_limit: 64,
+            ecn: 0,
+            dscp: 0
+        });
+        sockets.get::<RawSocket>(client.raw_handle())
+            .process(&ip_repr, &bytes, &ChecksumCapabilities::default()).unwrap();
+    }
+
+    fn expected_config() -> Config {
+        Config {
+            address: Some(Ipv4Cidr::new(OFFERED_IP, 24)),
+            expires_at: Some(20 + 1_000_000),
+            router: Some(SERVER_IP),
+            dns_servers: [Some(SERVER_IP), None, None],
+        }
+    }
+
+    /// Run through discovery until the client holds a lease, at `now == 20`.
+    fn bind(iface: &EthernetInterface<Loopback>, sockets: &mut SocketSet, client: &mut Client) {
+        assert_eq!(client.poll(iface, sockets, 0), Ok(None));
+        let (ipv4_repr, payload) = sent(sockets, client).unwrap();
+        let xid = parse_sent(&ipv4_repr, &payload).transaction_id;
+
+        reply(sockets, client, &server_repr(DhcpMessageType::Offer, xid));
+        assert_eq!(client.poll(iface, sockets, 10), Ok(None));
+        assert!(sent(sockets, client).is_some());
+
+        reply(sockets, client, &server_repr(DhcpMessageType::Ack, xid));
+        assert_eq!(client.poll(iface, sockets, 20), Ok(Some(expected_config())));
+    }
+
+    #[test]
+    fn test_discover() {
+        let (iface, mut sockets, mut client) = setup();
+        assert_eq!(client.poll(&iface, &mut sockets, 0), Ok(None));
+
+        let (ipv4_repr, payload) = sent(&mut sockets, &client).unwrap();
+        assert_eq!(ipv4_repr.src_addr, Ipv4Address::UNSPECIFIED);
+        assert_eq!(ipv4_repr.dst_addr, Ipv4Address::BROADCAST);
+        let dhcp_repr = parse_sent(&ipv4_repr, &payload);
+        assert_eq!(dhcp_repr.message_type, DhcpMessageType::Discover);
+        assert_eq!(dhcp_repr.client_hardware_address, CLIENT_MAC);
+        assert_eq!(dhcp_repr.client_identifier, Some(CLIENT_MAC));
+        assert_eq!(dhcp_repr.parameter_request_list, Some(PARAMETER_REQUEST_LIST));
+        assert_eq!(client.next_poll(0), INITIAL_RETRY_TIMEOUT);
+    }
+
+    #[test]
+    fn test_discover_identity() {
+        let (mut iface, mut sockets, mut client) = setup();
+        iface.identity_mut().set_hostname(Some("sensor")).unwrap();
+        iface.identity_mut().set_vendor_class(Some(b"smoltcp")).unwrap();
+        iface.identity_mut().set_client_id(Some(&[0xff, 0x01, 0x02])).unwrap();
+        iface.identity_mut().set_requested_options(Some(&[1, 3])).unwrap();
+        assert_eq!(client.poll(&iface, &mut sockets, 0), Ok(None));
+
+        let (ipv4_repr, payload) = sent(&mut sockets, &client).unwrap();
+        let dhcp_repr = parse_sent(&ipv4_repr, &payload);
+        assert_eq!(dhcp_repr.client_identifier, None);
+        assert_eq!(dhcp_repr.parameter_request_list, Some(&[1, 3][..]));
+
+        let udp_packet = UdpPacket::new_checked(&payload[..]).unwrap();
+        let dhcp_packet = DhcpPacket::new_checked(udp_packet.payload()).unwrap();
+        let mut options = dhcp_packet.options();
+        let mut found = Vec::new();
+        while let Ok((next_options, option)) = DhcpOption::parse(options) {
+            match option {
+                DhcpOption::EndOfList => break,
+                DhcpOption::Other { kind, data }
+                        if kind != dhcpv4_field::OPT_PARAMETER_REQUEST_LIST =>
+                    found.push((kind, data.to_vec())),
+                _ => ()
+            }
+            options = next_options;
+        }
+        assert_eq!(found, vec![
+            (dhcpv4_field::OPT_HOST_NAME, b"sensor".to_vec()),
+            (dhcpv4_field::OPT_VENDOR_CLASS_ID, b"smoltcp".to_vec()),
+            (dhcpv4_field::OPT_CLIENT_ID, vec![0xff, 0x01, 0x02]),
+        ]);
+    }
+
+    #[test]
+    fn test_discover_retransmit() {
+        let (iface, mut sockets, mut client) = setup();
+        assert_eq!(client.poll(&iface, &mut sockets, 0), Ok(None));
+        assert!(sent(&mut sockets, &client).is_some());
+
+        assert_eq!(client.poll(&iface, &mut sockets, 1000), Ok(None));
+        assert!(sent(&mut sockets, &client).is_none());
+
+        assert_eq!(client.poll(&iface, &mut sockets, 4000), Ok(None));
+        assert!(sent(&mut sockets, &client).is_some());
+        assert_eq!(client.next_poll(4000), 4000 + INITIAL_RETRY_TIMEOUT * 2);
+    }
+
+    #[test]
+    fn test_request() {
+        let (iface, mut sockets, mut client) = setup();
+        assert_eq!(client.poll(&iface, &mut sockets, 0), Ok(None));
+        let (ipv4_repr, payload) = sent(&mut sockets, &client).unwrap();
+        let xid = parse_sent(&ipv4_repr, &payload).transaction_id;
+
+        reply(&mut sockets, &client, &server_repr(DhcpMessageType::Offer, xid));
+        assert_eq!(client.poll(&iface, &mut sockets, 10), Ok(None));
+
+        let (ipv4_repr, payload) = sent(&mut sockets, &client).unwrap();
+        assert_eq!(ipv4_repr.dst_addr, Ipv4Address::BROADCAST);
+        let dhcp_repr = parse_sent(&ipv4_repr, &payload);
+        assert_eq!(dhcp_repr.message_type, DhcpMessageType::Request);
+        assert_eq!(dhcp_repr.transaction_id, xid);
+        assert_eq!(dhcp_repr.requested_ip, Some(OFFERED_IP));
+        assert_eq!(dhcp_repr.server_identifier, Some(SERVER_IP));
+    }
+
+    #[test]
+    fn test_ignore_other_transaction() {
+        let (iface, mut sockets, mut client) = setup();
+        assert_eq!(client.poll(&iface, &mut sockets, 0), Ok(None));
+        let (ipv4_repr, payload) = sent(&mut sockets, &client).unwrap();
+        let xid = parse_sent(&ipv4_repr, &payload).transaction_id;
+
+        reply(&mut sockets, &client, &server_repr(DhcpMessageType::Offer, xid ^ 1));
+        assert_eq!(client.poll(&iface, &mut sockets, 10), Ok(None));
+        assert!(sent(&mut sockets, &client).is_none());
+    }
+
+    #[test]
+    fn test_request_timeout() {
+        let (iface, mut sockets, mut client) = setup();
+        assert_eq!(client.poll(&iface, &mut sockets, 0), Ok(None));
+        let (ipv4_repr, payload) = sent(&mut sockets, &client).unwrap();
+        let xid = parse_sent(&ipv4_repr, &payload).transaction_id;
+        reply(&mut sockets, &client, &server_repr(DhcpMessageType::Offer, xid));
+
+        let mut now = 0;
+        for _ in 0..MAX_REQUEST_RETRIES {
+            assert_eq!(client.poll(&iface, &mut sockets, now), Ok(None));
+            let (ipv4_repr, payload) = sent(&mut sockets, &client).unwrap();
+            assert_eq!(parse_sent(&ipv4_repr, &payload).message_type, DhcpMessageType::Request);
+            now = client.next_poll(now);
+        }
+
+        assert_eq!(client.poll(&iface, &mut sockets, now), Ok(None));
+        let (ipv4_repr, payload) = sent(&mut sockets, &client).unwrap();
+        assert_eq!(parse_sent(&ipv4_repr, &payload).message_type, DhcpMessageType::Discover);
+    }
+
+    #[test]
+    fn test_bind() {
+        let (iface, mut sockets, mut client) = setup();
+        bind(&iface, &mut sockets, &mut client);
+        assert_eq!(client.next_poll(20), 20 + 500_000);
+    }
+
+    #[test]
+    fn test_nak() {
+        let (iface, mut sockets, mut client) = setup();
+        assert_eq!(client.poll(&iface, &mut sockets, 0), Ok(None));
+        let (ipv4_repr, payload) = sent(&mut sockets, &client).unwrap();
+        let xid = parse_sent(&ipv4_repr, &payload).transaction_id;
+        reply(&mut sockets, &client, &server_repr(DhcpMessageType::Offer, xid));
+        assert_eq!(client.poll(&iface, &mut sockets, 10), Ok(None));
+        assert!(sent(&mut sockets, &client).is_some());
+
+        reply(&mut sockets, &client, &server_repr(DhcpMessageType::Nak, xid));
+        assert_eq!(client.poll(&iface, &mut sockets, 20), Ok(None));
+        let (ipv4_repr, payload) = sent(&mut sockets, &client).unwrap();
+        assert_eq!(parse_sent(&ipv4_repr, &payload).message_type, DhcpMessageType::Discover);
+    }
+
+    #[test]
+    fn test_renew() {
+        let (iface, mut sockets, mut client) = setup();
+        bind(&iface, &mut sockets, &mut client);
+
+        assert_eq!(client.poll(&iface, &mut sockets, 20 + 500_000), Ok(None));
+        let (ipv4_repr, payload) = sent(&mut sockets, &client).unwrap();
+        assert_eq!(ipv4_repr.src_addr, OFFERED_IP);
+        assert_eq!(ipv4_repr.dst_addr, SERVER_IP);
+        let dhcp_repr = parse_sent(&ipv4_repr, &payload);
+        assert_eq!(dhcp_repr.message_type, DhcpMessageType::Request);
+        assert_eq!(dhcp_repr.client_ip, OFFERED_IP);
+        // Half of the time remaining until T2.
+        assert_eq!(client.next_poll(20 + 500_000), 20 + 687_500);
+
+        // An ack with the same configuration is reported again with the new expiry.
+        reply(&mut sockets, &client, &server_repr(DhcpMessageType::Ack,
+                                                  dhcp_repr.transaction_id));
+        assert_eq!(client.poll(&iface, &mut sockets, 20 + 500_100),
+                   Ok(Some(Config { expires_at: Some(20 + 1_500_100), ..expected_config() })));
+        assert_eq!(client.poll(&iface, &mut sockets, 20 + 500_200), Ok(None));
+        assert_eq!(client.next_poll(20 + 500_100), 20 + 500_100 + 500_000);
+    }
+
+    #[test]
+    fn test_rebind_and_expire() {
+        let (iface, mut sockets, mut client) = setup();
+        bind(&iface, &mut sockets, &mut client);
+
+        assert_eq!(client.poll(&iface, &mut sockets, 20 + 875_000), Ok(None));
+        let (ipv4_repr, _) = sent(&mut sockets, &client).unwrap();
+        assert_eq!(ipv4_repr.src_addr, OFFERED_IP);
+        assert_eq!(ipv4_repr.dst_addr, Ipv4Address::BROADCAST);
+        // Half of the time remaining until the lease expires.
+        assert_eq!(client.next_poll(20 + 875_000), 20 + 937_500);
+
+        assert_eq!(client.poll(&iface, &mut sockets, 20 + 1_000_000),
+                   Ok(Some(Config::default())));
+        let (ipv4_repr, payload) = sent(&mut sockets, &client).unwrap();
+        assert_eq!(parse_sent(&ipv4_repr, &payload).message_type, DhcpMessageType::Discover);
+    }
+
+    #[test]
+    fn test_reset() {
+        let (iface, mut sockets, mut client) = setup();
+        bind(&iface, &mut sockets, &mut client);
+
+        client.reset(100);
+        assert_eq!(client.poll(&iface, &mut sockets, 100), Ok(Some(Config::default())));
+        let (ipv4_repr, payload) = sent(&mut sockets, &client).unwrap();
+        assert_eq!(parse_sent(&ipv4_repr, &payload).message_type, DhcpMessageType::Discover);
+    }
+
+    #[test]
+    fn test_netmask_prefix_len() {
+        assert_eq!(netmask_prefix_len(Ipv4Address([255, 255, 255, 0])), Some(24));
+        assert_eq!(netmask_prefix_len(Ipv4Address([255, 255, 255, 255])), Some(32));
+        assert_eq!(netmask_prefix_len(Ipv4Address([255, 128, 0, 0])), Some(9));
+        assert_eq!(netmask_prefix_len(Ipv4Address([0, 0, 0, 0])), Some(0));
+        assert_eq!(netmask_prefix_len(Ipv4Address([255, 0, 255, 0])), None);
+    }
+}
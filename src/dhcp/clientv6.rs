@@ -0,0 +1,921 @@
+// Heads up! Before working on this file you should read RFC 8415,
+// which describes the client state machine (§ 18) and the retransmission
+// algorithm (§ 15), and RFC 3646, which describes the DNS servers option.
+
+use core::{cmp, fmt};
+
+use {Error, Result};
+use phy::Device;
+use wire::{EthernetAddress, IpAddress, IpEndpoint,
+           Ipv6Address, Ipv6Cidr,
+           Dhcpv6Packet, Dhcpv6Repr, Dhcpv6MessageType, Dhcpv6StatusCode,
+           Dhcpv6IaNa, Dhcpv6IaAddress,
+           DHCPV6_CLIENT_PORT, DHCPV6_SERVER_PORT, DHCPV6_ALL_RELAY_AGENTS_AND_SERVERS};
+use wire::dhcpv6::{field as dhcpv6_field, MAX_DNS_SERVER_COUNT};
+use socket::{SocketSet, SocketHandle, UdpSocket, UdpSocketBuffer};
+use iface::EthernetInterface;
+
+/// The initial and maximum retransmission timeouts of Solicit messages.
+const SOL_TIMEOUT: u64 = 1_000;
+const SOL_MAX_RT: u64 = 3_600_000;
+/// The initial and maximum retransmission timeouts of Request messages, and the number
+/// of Request messages sent for an advertised address before starting over.
+const REQ_TIMEOUT: u64 = 1_000;
+const REQ_MAX_RT: u64 = 30_000;
+const REQ_MAX_RC: u8 = 10;
+/// The initial and maximum retransmission timeouts of Renew and Rebind messages.
+const REN_TIMEOUT: u64 = 10_000;
+const REN_MAX_RT: u64 = 600_000;
+const REB_TIMEOUT: u64 = 10_000;
+const REB_MAX_RT: u64 = 600_000;
+/// The maximum length of a DUID, including its type.
+const MAX_DUID_LEN: usize = 130;
+/// The prefix length given to acquired addresses.
+const PREFIX_LEN: u8 = 64;
+
+const OPTION_REQUEST_LIST: &[u8] = &[
+    (dhcpv6_field::OPT_DNS_SERVERS >> 8) as u8, dhcpv6_field::OPT_DNS_SERVERS as u8,
+];
+
+/// IPv6 configuration data provided by the DHCPv6 server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Config {
+    /// The acquired interface address, or `None` if the binding was lost
+    /// and the address must not be used anymore.
+    ///
+    /// DHCPv6 does not tell which prefixes are on-link; the address is given a prefix
+    /// length of 64, which is what SLAAC would use on the same network.
+    pub address: Option<Ipv6Cidr>,
+    /// The moment after which the address is deprecated, as a timestamp, unless
+    /// its lifetimes are extended before.
+    pub preferred_until: Option<u64>,
+    /// The moment the address expires, as a timestamp, unless its lifetimes are
+    /// extended before; it should be passed to [set_ip_addr_lifetime] with the address
+    /// and `preferred_until`.
+    ///
+    /// [set_ip_addr_lifetime]: ../iface/struct.EthernetInterface.html#method.set_ip_addr_lifetime
+    pub expires_at: Option<u64>,
+    /// DNS servers, in order of preference.
+    pub dns_servers: [Option<Ipv6Address>; MAX_DNS_SERVER_COUNT],
+}
+
+/// A DHCP unique identifier of a server.
+#[derive(Clone, Copy)]
+struct ServerId {
+    len: usize,
+    bytes: [u8; MAX_DUID_LEN],
+}
+
+impl ServerId {
+    fn new(duid: &[u8]) -> Option<ServerId> {
+        if duid.len() == 0 || duid.len() > MAX_DUID_LEN { return None }
+        let mut server_id = ServerId { len: duid.len(), bytes: [0; MAX_DUID_LEN] };
+        server_id.bytes[..duid.len()].copy_from_slice(duid);
+        Some(server_id)
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl PartialEq for ServerId {
+    fn eq(&self, other: &ServerId) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl Eq for ServerId {}
+
+impl fmt::Debug for ServerId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, octet) in self.as_bytes().iter().enumerate() {
+            if i != 0 { write!(f, ":")? }
+            write!(f, "{:02x}", octet)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RequestState {
+    retry: u8,
+    server_id: ServerId,
+    address: Ipv6Address,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BindingState {
+    server_id: ServerId,
+    address: Ipv6Address,
+    renew_at: u64,
+    rebind_at: u64,
+    expires_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Looking for a server by sending Solicit.
+    Soliciting,
+    /// Requesting an advertised address by sending Request.
+    Requesting(RequestState),
+    /// Holding an address until T1.
+    Bound(BindingState),
+    /// Extending the lifetimes of the address with the server that assigned it,
+    /// by sending Renew until T2.
+    Renewing(BindingState),
+    /// Extending the lifetimes of the address with any server, by sending Rebind
+    /// until the address expires.
+    Rebinding(BindingState),
+}
+
+/// A DHCPv6 client, acquiring a single non-temporary address (IA_NA).
+///
+/// The client talks to DHCPv6 servers through a UDP socket that it adds to the socket set,
+/// and reports every change of the IPv6 configuration from [poll], where it is up to
+/// the application to add the address to the interface or to remove it.
+///
+/// The interface must have a link-local address, which is used to talk to the servers.
+/// The interface does not answer neighbor solicitations, so servers will only be able
+/// to reach the client if they already know its hardware address.
+///
+/// [poll]: #method.poll
+#[derive(Debug)]
+pub struct Client {
+    state: State,
+    udp_handle: SocketHandle,
+    /// When to send the next DHCPv6 message.
+    next_egress: u64,
+    /// The current retransmission timeout.
+    retry_timeout: u64,
+    /// When the first message of the current exchange was sent.
+    exchange_start: u64,
+    transaction_id: u32,
+    rand_state: u32,
+    /// The configuration last reported to the application.
+    config: Config,
+    /// The configuration change to report from the next call to `poll`.
+    pending_config: Option<Config>,
+}
+
+impl Client {
+    /// Create a DHCPv6 client, and add its UDP socket, created with the given buffers,
+    /// to the socket set.
+    pub fn new<'a, 'b, 'c>(sockets: &mut SocketSet<'a, 'b, 'c>,
+                           rx_buffer: UdpSocketBuffer<'b, 'c>,
+                           tx_buffer: UdpSocketBuffer<'b, 'c>,
+                           now: u64) -> Client {
+        let udp_socket = UdpSocket::new(rx_buffer, tx_buffer);
+        let udp_handle = sockets.add(udp_socket);
+
+        let mut client = Client {
+            state: State::Soliciting,
+            udp_handle: udp_handle,
+            next_egress: now,
+            retry_timeout: SOL_TIMEOUT,
+            exchange_start: now,
+            transaction_id: 0,
+            rand_state: (now as u32) ^ ((now >> 32) as u32) ^ 0x3c6ef372,
+            config: Config::default(),
+            pending_config: None,
+        };
+        client.start_exchange(now, SOL_TIMEOUT);
+        client
+    }
+
+    /// Return the handle of the UDP socket used by the client.
+    pub fn udp_handle(&self) -> SocketHandle {
+        self.udp_handle
+    }
+
+    /// Return the time, in milliseconds, at which the client should be polled next,
+    /// assuming no packets arrive in the meantime.
+    pub fn next_poll(&self, now: u64) -> u64 {
+        cmp::max(self.next_egress, now)
+    }
+
+    /// Restart the client, e.g. after the link was down.
+    ///
+    /// If an address was held, the next call to [poll] reports that it was lost.
+    ///
+    /// [poll]: #method.poll
+    pub fn reset(&mut self, now: u64) {
+        net_trace!("DHCPv6 reset");
+        self.configure(Config::default());
+        self.start_soliciting(now);
+    }
+
+    /// Process DHCPv6 messages received by the UDP socket, and send any messages that are due.
+    ///
+    /// Returns `Ok(Some(config))` if the IPv6 configuration of the interface has to change,
+    /// including when the lifetimes of the address are extended, and `Ok(None)` otherwise. Returns `Err(Error::Unaddressable)` if the interface
+    /// has no link-local address.
+    pub fn poll<'b, 'c, DeviceT>(&mut self, iface: &EthernetInterface<'b, 'c, DeviceT>,
+                                 sockets: &mut SocketSet, now: u64) -> Result<Option<Config>>
+            where DeviceT: for<'d> Device<'d> {
+        let client_id = client_duid(iface.ethernet_addr());
+        let iaid = client_iaid(iface.ethernet_addr());
+
+        {
+            let mut udp_socket = sockets.get::<UdpSocket>(self.udp_handle);
+            if !udp_socket.is_open() {
+                let link_local = iface.ip_addrs().iter()
+                    .filter_map(|cidr| match cidr.address() {
+                        IpAddress::Ipv6(addr) if addr.is_link_local() => Some(addr),
+                        _ => None
+                    })
+                    .next()
+                    .ok_or(Error::Unaddressable)?;
+                udp_socket.bind(IpEndpoint::new(link_local.into(), DHCPV6_CLIENT_PORT))?;
+            }
+
+            while let Ok((payload, endpoint)) = udp_socket.recv() {
+                if endpoint.port != DHCPV6_SERVER_PORT { continue }
+                match Dhcpv6Packet::new_checked(payload)
+                        .and_then(|packet| Dhcpv6Repr::parse(&packet)) {
+                    Ok(dhcp_repr) => self.ingress(&dhcp_repr, &client_id, iaid, now),
+                    Err(_) => {
+                        net_trace!("DHCPv6 ignoring malformed packet");
+                    }
+                }
+            }
+        }
+
+        if now >= self.next_egress {
+            self.egress(sockets, &client_id, iaid, now)?;
+        }
+
+        Ok(self.pending_config.take())
+    }
+
+    fn ingress(&mut self, dhcp_repr: &Dhcpv6Repr, client_id: &[u8], iaid: u32, now: u64) {
+        if dhcp_repr.transaction_id != self.transaction_id ||
+                dhcp_repr.client_id != Some(client_id) {
+            return
+        }
+        let server_id = match dhcp_repr.server_id.and_then(ServerId::new) {
+            Some(server_id) => server_id,
+            None => return
+        };
+
+        match (self.state, dhcp_repr.message_type) {
+            (State::Soliciting, Dhcpv6MessageType::Advertise) => {
+                let (_, ia_address) = match assigned_address(dhcp_repr, iaid) {
+                    Some(assigned) => assigned,
+                    None => return
+                };
+
+                net_debug!("DHCPv6 advertise of {} from {:?}", ia_address.address, server_id);
+                self.state = State::Requesting(RequestState {
+                    retry: 0,
+                    server_id: server_id,
+                    address: ia_address.address,
+                });
+                self.start_exchange(now, REQ_TIMEOUT);
+            }
+            (State::Requesting(_), Dhcpv6MessageType::Reply) |
+            (State::Renewing(_), Dhcpv6MessageType::Reply) |
+            (State::Rebinding(_), Dhcpv6MessageType::Reply) => {
+                let (ia_na, ia_address) = match assigned_address(dhcp_repr, iaid) {
+                    Some(assigned) => assigned,
+                    None => return self.ingress_failure(dhcp_repr, server_id, now)
+                };
+
+                let preferred_lifetime = ia_address.preferred_lifetime as u64 * 1000;
+                let valid_lifetime = ia_address.valid_lifetime as u64 * 1000;
+                let t1 = match ia_na.t1 {
+                    0 => preferred_lifetime / 2,
+                    t1 => t1 as u64 * 1000
+                };
+                let t2 = match ia_na.t2 {
+                    0 => preferred_lifetime / 5 * 4,
+                    t2 => t2 as u64 * 1000
+                };
+
+                let expires_at = now + valid_lifetime;
+                let rebind_at = cmp::min(now + cmp::max(t1, t2), expires_at);
+                let renew_at = cmp::min(now + t1, rebind_at);
+                let binding_state = BindingState {
+                    server_id: server_id,
+                    address: ia_address.address,
+                    renew_at: renew_at,
+                    rebind_at: rebind_at,
+                    expires_at: expires_at,
+                };
+                net_debug!("DHCPv6 reply with {} for {} ms", ia_address.address, valid_lifetime);
+
+                let config = Config {
+                    address: Some(Ipv6Cidr::new(ia_address.address, PREFIX_LEN)),
+                    preferred_until: Some(now + preferred_lifetime),
+                    expires_at: Some(expires_at),
+                    dns_servers: dhcp_repr.dns_servers.unwrap_or([None; MAX_DNS_SERVER_COUNT]),
+                };
+                self.configure(config);
+                self.state = State::Bound(binding_state);
+                self.next_egress = renew_at;
+            }
+            _ => ()
+        }
+    }
+
+    /// Handle a reply that did not assign an address.
+    fn ingress_failure(&mut self, dhcp_repr: &Dhcpv6Repr, server_id: ServerId, now: u64) {
+        let ia_status_code = dhcp_repr.ia_na.and_then(|ia_na| ia_na.status_code);
+        match (self.state, ia_status_code) {
+            (State::Requesting(_), _) => {
+                net_debug!("DHCPv6 request failed with {:?}, starting over",
+                           dhcp_repr.status_code.or(ia_status_code));
+                self.configure(Config::default());
+                self.start_soliciting(now);
+            }
+            // The server lost the binding; ask for the same address again (RFC 8415 § 18.2.10.1).
+            (State::Renewing(state), Some(Dhcpv6StatusCode::NoBinding)) |
+            (State::Rebinding(state), Some(Dhcpv6StatusCode::NoBinding)) => {
+                net_debug!("DHCPv6 server has no binding for {}", state.address);
+                self.state = State::Requesting(RequestState {
+                    retry: 0,
+                    server_id: server_id,
+                    address: state.address,
+                });
+                self.start_exchange(now, REQ_TIMEOUT);
+            }
+            // Other failures to extend the lifetimes are retried until the address expires.
+            _ => ()
+        }
+    }
+
+    fn egress(&mut self, sockets: &mut SocketSet, client_id: &[u8], iaid: u32,
+              now: u64) -> Result<()> {
+        let mut dhcp_repr = Dhcpv6Repr {
+            message_type: Dhcpv6MessageType::Solicit,
+            transaction_id: self.transaction_id,
+            client_id: Some(client_id),
+            server_id: None,
+            ia_na: Some(Dhcpv6IaNa {
+                iaid: iaid,
+                t1: 0,
+                t2: 0,
+                address: None,
+                status_code: None,
+            }),
+            option_request: Some(OPTION_REQUEST_LIST),
+            elapsed_time: Some(cmp::min((now - self.exchange_start) / 10, 0xffff) as u16),
+            preference: None,
+            status_code: None,
+            dns_servers: None,
+        };
+
+        // Ask to keep an address we know about; the lifetimes are only hints.
+        let hint = |address: Ipv6Address| Some(Dhcpv6IaNa {
+            iaid: iaid,
+            t1: 0,
+            t2: 0,
+            address: Some(Dhcpv6IaAddress {
+                address: address,
+                preferred_lifetime: 0,
+                valid_lifetime: 0,
+            }),
+            status_code: None,
+        });
+
+        let (max_timeout, deadline) = match self.state {
+            State::Soliciting => {
+                net_trace!("DHCPv6 send solicit");
+                send(sockets, self.udp_handle, &dhcp_repr)?;
+                self.retransmit(now, SOL_MAX_RT, None);
+                return Ok(())
+            }
+            State::Requesting(mut state) => {
+                if state.retry >= REQ_MAX_RC {
+                    net_debug!("DHCPv6 no reply to request, starting over");
+                    self.configure(Config::default());
+                    self.start_soliciting(now);
+                    return self.egress(sockets, client_id, iaid, now)
+                }
+
+                dhcp_repr.message_type = Dhcpv6MessageType::Request;
+                dhcp_repr.server_id = Some(state.server_id.as_bytes());
+                dhcp_repr.ia_na = hint(state.address);
+                net_trace!("DHCPv6 send request for {}", state.address);
+                send(sockets, self.udp_handle, &dhcp_repr)?;
+
+                state.retry += 1;
+                self.state = State::Requesting(state);
+                self.retransmit(now, REQ_MAX_RT, None);
+                return Ok(())
+            }
+            State::Bound(state) => {
+                self.state = State::Renewing(state);
+                self.start_exchange(now, REN_TIMEOUT);
+                return self.egress(sockets, client_id, iaid, now)
+            }
+            State::Renewing(state) => {
+                if now >= state.rebind_at {
+                    self.state = State::Rebinding(state);
+                    self.start_exchange(now, REB_TIMEOUT);
+                    return self.egress(sockets, client_id, iaid, now)
+                }
+
+                dhcp_repr.message_type = Dhcpv6MessageType::Renew;
+                dhcp_repr.server_id = Some(state.server_id.as_bytes());
+                dhcp_repr.ia_na = hint(state.address);
+                net_trace!("DHCPv6 send renew of {}", state.address);
+                send(sockets, self.udp_handle, &dhcp_repr)?;
+                (REN_MAX_RT, state.rebind_at)
+            }
+            State::Rebinding(state) => {
+                if now >= state.expires_at {
+                    net_debug!("DHCPv6 address {} expired", state.address);
+                    self.configure(Config::default());
+                    self.start_soliciting(now);
+                    return self.egress(sockets, client_id, iaid, now)
+                }
+
+                dhcp_repr.message_type = Dhcpv6MessageType::Rebind;
+                dhcp_repr.ia_na = hint(state.address);
+                net_trace!("DHCPv6 send rebind of {}", state.address);
+                send(sockets, self.udp_handle, &dhcp_repr)?;
+                (REB_MAX_RT, state.expires_at)
+            }
+        };
+
+        self.retransmit(now, max_timeout, Some(deadline));
+        Ok(())
+    }
+
+    fn configure(&mut self, config: Config) {
+        if config != self.config {
+            self.config = config;
+            self.pending_config = Some(config);
+        }
+    }
+
+    fn start_soliciting(&mut self, now: u64) {
+        self.state = State::Soliciting;
+        self.start_exchange(now, SOL_TIMEOUT);
+    }
+
+    /// Begin a new message exchange, with a new transaction ID, sending the first message
+    /// immediately.
+    fn start_exchange(&mut self, now: u64, initial_timeout: u64) {
+        self.transaction_id = self.random_u32() & 0xffffff;
+        self.exchange_start = now;
+        self.retry_timeout = self.jitter(initial_timeout);
+        self.next_egress = now;
+    }
+
+    /// Schedule the retransmission of the message just sent, and back off the retransmission
+    /// timeout as described in RFC 8415 § 15.
+    fn retransmit(&mut self, now: u64, max_timeout: u64, deadline: Option<u64>) {
+        self.next_egress = now + self.retry_timeout;
+        if let Some(deadline) = deadline {
+            self.next_egress = cmp::min(self.next_egress, deadline);
+        }
+        let retry_timeout = cmp::min(self.retry_timeout * 2, max_timeout);
+        self.retry_timeout = self.jitter(retry_timeout);
+    }
+
+    /// Randomize a timeout by ±10%.
+    fn jitter(&mut self, timeout: u64) -> u64 {
+        let spread = timeout / 5;
+        timeout - timeout / 10 + self.random_u32() as u64 % (spread + 1)
+    }
+
+    fn random_u32(&mut self) -> u32 {
+        // xorshift32; transaction IDs only need to be distinct, not unpredictable.
+        let mut x = self.rand_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rand_state = x;
+        x
+    }
+}
+
+/// Build a link-layer DUID (DUID-LL, RFC 8415 § 11.4) from the hardware address.
+fn client_duid(ethernet_addr: EthernetAddress) -> [u8; 10] {
+    let mut duid = [0x00, 0x03, 0x00, 0x01, 0, 0, 0, 0, 0, 0];
+    duid[4..].copy_from_slice(ethernet_addr.as_bytes());
+    duid
+}
+
+/// Derive an IAID that stays the same across restarts from the hardware address.
+fn client_iaid(ethernet_addr: EthernetAddress) -> u32 {
+    let bytes = ethernet_addr.as_bytes();
+    (bytes[2] as u32) << 24 | (bytes[3] as u32) << 16 | (bytes[4] as u32) << 8 | bytes[5] as u32
+}
+
+/// Return the identity association and the address assigned by a server,
+/// if it assigned a usable one.
+fn assigned_address(dhcp_repr: &Dhcpv6Repr, iaid: u32) -> Option<(Dhcpv6IaNa, Dhcpv6IaAddress)> {
+    let is_success = |status_code: Option<Dhcpv6StatusCode>|
+        status_code.map_or(true, |code| code == Dhcpv6StatusCode::Success);
+
+    if !is_success(dhcp_repr.status_code) { return None }
+    let ia_na = match dhcp_repr.ia_na {
+        Some(ia_na) if ia_na.iaid == iaid && is_success(ia_na.status_code) => ia_na,
+        _ => return None
+    };
+    match ia_na.address {
+        Some(ia_address) if ia_address.address.is_unicast() &&
+                            ia_address.valid_lifetime != 0 &&
+                            ia_address.preferred_lifetime <= ia_address.valid_lifetime =>
+            Some((ia_na, ia_address)),
+        _ => None
+    }
+}
+
+/// Enqueue a DHCPv6 message to all relay agents and servers on the UDP socket.
+fn send(sockets: &mut SocketSet, udp_handle: SocketHandle, dhcp_repr: &Dhcpv6Repr) -> Result<()> {
+    let endpoint = IpEndpoint::new(DHCPV6_ALL_RELAY_AGENTS_AND_SERVERS.into(), DHCPV6_SERVER_PORT);
+    let mut udp_socket = sockets.get::<UdpSocket>(udp_handle);
+    let buffer = udp_socket.send(dhcp_repr.buffer_len(), endpoint)?;
+    dhcp_repr.emit(&mut Dhcpv6Packet::new(buffer));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use std::collections::BTreeMap;
+    use phy::Loopback;
+    use wire::{IpCidr, IpProtocol, IpRepr, UdpRepr};
+    use iface::{EthernetInterfaceBuilder, NeighborCache};
+    use socket::UdpPacketBuffer;
+    use super::*;
+
+    const CLIENT_MAC: EthernetAddress = EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+    const CLIENT_LL: Ipv6Address =
+        Ipv6Address([0xfe, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01]);
+    const SERVER_LL: Ipv6Address =
+        Ipv6Address([0xfe, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02]);
+    const OFFERED_IP: Ipv6Address =
+        Ipv6Address([0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00,
+                     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x23]);
+    const DNS_SERVER: Ipv6Address =
+        Ipv6Address([0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00,
+                     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x35]);
+    static SERVER_DUID: [u8; 10] = [0x00, 0x03, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x02];
+
+    fn buffer() -> UdpSocketBuffer<'static, 'static> {
+        UdpSocketBuffer::new(vec![UdpPacketBuffer::new(vec![0; 600]),
+                                  UdpPacketBuffer::new(vec![0; 600])])
+    }
+
+    fn setup() -> (EthernetInterface<'static, 'static, Loopback>,
+                   SocketSet<'static, 'static, 'static>, Client) {
+        let iface = EthernetInterfaceBuilder::new(Loopback::new())
+                .ethernet_addr(CLIENT_MAC)
+                .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+                .ip_addrs([IpCidr::new(CLIENT_LL.into(), 64)])
+                .finalize();
+        let mut sockets = SocketSet::new(vec![]);
+        let client = Client::new(&mut sockets, buffer(), buffer(), 0);
+        (iface, sockets, client)
+    }
+
+    fn sent(sockets: &mut SocketSet, client: &Client) -> Option<(IpRepr, Vec<u8>)> {
+        let mut result = None;
+        let _ = sockets.get::<UdpSocket>(client.udp_handle())
+            .dispatch(0, |(ip_repr, udp_repr)| {
+                assert_eq!(udp_repr.src_port, DHCPV6_CLIENT_PORT);
+                assert_eq!(udp_repr.dst_port, DHCPV6_SERVER_PORT);
+                result = Some((ip_repr, udp_repr.payload.to_vec()));
+                Ok(())
+            });
+        result
+    }
+
+    fn parse_sent(payload: &[u8]) -> Dhcpv6Repr {
+        Dhcpv6Repr::parse(&Dhcpv6Packet::new_checked(payload).unwrap()).unwrap()
+    }
+
+    fn server_repr<'a>(message_type: Dhcpv6MessageType, transaction_id: u32,
+                       client_id: &'a [u8]) -> Dhcpv6Repr<'a> {
+        Dhcpv6Repr {
+            message_type: message_type,
+            transaction_id: transaction_id,
+            client_id: Some(client_id),
+            server_id: Some(&SERVER_DUID),
+            ia_na: Some(Dhcpv6IaNa {
+                iaid: client_iaid(CLIENT_MAC),
+                t1: 1000,
+                t2: 1600,
+                address: Some(Dhcpv6IaAddress {
+                    address: OFFERED_IP,
+                    preferred_lifetime: 2000,
+                    valid_lifetime: 4000,
+                }),
+                status_code: None,
+            }),
+            option_request: None,
+            elapsed_time: None,
+            preference: Some(0),
+            status_code: None,
+            dns_servers: Some([Some(DNS_SERVER), None, None]),
+        }
+    }
+
+    fn reply(sockets: &mut SocketSet, client: &Client, dhcp_repr: &Dhcpv6Repr) {
+        let mut bytes = vec![0; dhcp_repr.buffer_len()];
+        dhcp_repr.emit(&mut Dhcpv6Packet::new(&mut bytes[..]));
+        let udp_repr = UdpRepr {
+            src_port: DHCPV6_SERVER_PORT,
+            dst_port: DHCPV6_CLIENT_PORT,
+            payload:  &bytes
+        };
+        let ip_repr = IpRepr::Unspecified {
+            src_addr: SERVER_LL.into(),
+            dst_addr: CLIENT_LL.into(),
+            protocol: IpProtocol::Udp,
+            payload_len: udp_repr.buffer_len(),
+            hop_limit: 64,
+            ecn: 0,
+            dscp: 0,
+            flow_label: 0
+        };
+        sockets.get::<UdpSocket>(client.udp_handle())
+            .process_lent(0, None, &ip_repr, &udp_repr, None).unwrap();
+    }
+
+    fn expected_config() -> Config {
+        Config {
+            address: Some(Ipv6Cidr::new(OFFERED_IP, 64)),
+            preferred_until: Some(20 + 2_000_000),
+            expires_at: Some(20 + 4_000_000),
+            dns_servers: [Some(DNS_SERVER), None, None],
+        }
+    }
+
+    fn client_id() -> [u8; 10] {
+        client_duid(CLIENT_MAC)
+    }
+
+    /// Run through solicitation until the client holds an address, at `now == 20`.
+    fn bind(iface: &EthernetInterface<Loopback>, sockets: &mut SocketSet, client: &mut Client) {
+        let client_id = client_id();
+        assert_eq!(client.poll(iface, sockets, 0), Ok(None));
+        let (_, payload) = sent(sockets, client).unwrap();
+        let xid = parse_sent(&payload).transaction_id;
+
+        reply(sockets, client, &server_repr(Dhcpv6MessageType::Advertise, xid, &client_id));
+        assert_eq!(client.poll(iface, sockets, 10), Ok(None));
+        let (_, payload) = sent(sockets, client).unwrap();
+        let xid = parse_sent(&payload).transaction_id;
+
+        reply(sockets, client, &server_repr(Dhcpv6MessageType::Reply, xid, &client_id));
+        assert_eq!(client.poll(iface, sockets, 20), Ok(Some(expected_config())));
+    }
+
+    #[test]
+    fn test_solicit() {
+        let (iface, mut sockets, mut client) = setup();
+        assert_eq!(client.poll(&iface, &mut sockets, 0), Ok(None));
+
+        let (ip_repr, payload) = sent(&mut sockets, &client).unwrap();
+        assert_eq!(ip_repr.src_addr(), IpAddress::Ipv6(CLIENT_LL));
+        assert_eq!(ip_repr.dst_addr(), IpAddress::Ipv6(DHCPV6_ALL_RELAY_AGENTS_AND_SERVERS));
+        let dhcp_repr = parse_sent(&payload);
+        assert_eq!(dhcp_repr.message_type, Dhcpv6MessageType::Solicit);
+        assert_eq!(dhcp_repr.client_id, Some(&client_id()[..]));
+        assert_eq!(dhcp_repr.server_id, None);
+        assert_eq!(dhcp_repr.ia_na.unwrap().iaid, client_iaid(CLIENT_MAC));
+        assert_eq!(dhcp_repr.option_request, Some(OPTION_REQUEST_LIST));
+        assert_eq!(dhcp_repr.elapsed_time, Some(0));
+        let next_poll = client.next_poll(0);
+        assert!(next_poll >= 900 && next_poll <= 1100);
+    }
+
+    #[test]
+    fn test_no_link_local() {
+        let (mut iface, mut sockets, mut client) = setup();
+        iface.update_ip_addrs(|addrs| {
+            addrs.iter_mut().next().map(|addr| {
+                *addr = IpCidr::new(OFFERED_IP.into(), 64);
+            });
+        });
+        assert_eq!(client.poll(&iface, &mut sockets, 0), Err(Error::Unaddressable));
+        assert!(sent(&mut sockets, &client).is_none());
+    }
+
+    #[test]
+    fn test_solicit_retransmit() {
+        let (iface, mut sockets, mut client) = setup();
+        assert_eq!(client.poll(&iface, &mut sockets, 0), Ok(None));
+        let (_, payload) = sent(&mut sockets, &client).unwrap();
+        let xid = parse_sent(&payload).transaction_id;
+
+        assert_eq!(client.poll(&iface, &mut sockets, 500), Ok(None));
+        assert!(sent(&mut sockets, &client).is_none());
+
+        let now = client.next_poll(500);
+        assert_eq!(client.poll(&iface, &mut sockets, now), Ok(None));
+        let (_, payload) = sent(&mut sockets, &client).unwrap();
+        let dhcp_repr = parse_sent(&payload);
+        assert_eq!(dhcp_repr.transaction_id, xid);
+        assert_eq!(dhcp_repr.elapsed_time, Some((now / 10) as u16));
+        // The timeout roughly doubles.
+        let timeout = client.next_poll(now) - now;
+        assert!(timeout >= 1600 && timeout <= 2500);
+    }
+
+    #[test]
+    fn test_request() {
+        let (iface, mut sockets, mut client) = setup();
+        let client_id = client_id();
+        assert_eq!(client.poll(&iface, &mut sockets, 0), Ok(None));
+        let (_, payload) = sent(&mut sockets, &client).unwrap();
+        let xid = parse_sent(&payload).transaction_id;
+
+        reply(&mut sockets, &client,
+              &server_repr(Dhcpv6MessageType::Advertise, xid, &client_id));
+        assert_eq!(client.poll(&iface, &mut sockets, 10), Ok(None));
+
+        let (ip_repr, payload) = sent(&mut sockets, &client).unwrap();
+        assert_eq!(ip_repr.dst_addr(), IpAddress::Ipv6(DHCPV6_ALL_RELAY_AGENTS_AND_SERVERS));
+        let dhcp_repr = parse_sent(&payload);
+        assert_eq!(dhcp_repr.message_type, Dhcpv6MessageType::Request);
+        assert!(dhcp_repr.transaction_id != xid);
+        assert_eq!(dhcp_repr.server_id, Some(&SERVER_DUID[..]));
+        assert_eq!(dhcp_repr.ia_na.unwrap().address.unwrap().address, OFFERED_IP);
+        assert_eq!(dhcp_repr.elapsed_time, Some(0));
+    }
+
+    #[test]
+    fn test_ignore_other_transaction() {
+        let (iface, mut sockets, mut client) = setup();
+        let client_id = client_id();
+        assert_eq!(client.poll(&iface, &mut sockets, 0), Ok(None));
+        let (_, payload) = sent(&mut sockets, &client).unwrap();
+        let xid = parse_sent(&payload).transaction_id;
+
+        reply(&mut sockets, &client,
+              &server_repr(Dhcpv6MessageType::Advertise, xid ^ 1, &client_id));
+        assert_eq!(client.poll(&iface, &mut sockets, 10), Ok(None));
+        assert!(sent(&mut sockets, &client).is_none());
+    }
+
+    #[test]
+    fn test_ignore_advertise_without_address() {
+        let (iface, mut sockets, mut client) = setup();
+        let client_id = client_id();
+        assert_eq!(client.poll(&iface, &mut sockets, 0), Ok(None));
+        let (_, payload) = sent(&mut sockets, &client).unwrap();
+        let xid = parse_sent(&payload).transaction_id;
+
+        let mut dhcp_repr = server_repr(Dhcpv6MessageType::Advertise, xid, &client_id);
+        dhcp_repr.ia_na = None;
+        dhcp_repr.status_code = Some(Dhcpv6StatusCode::NoAddrsAvail);
+        reply(&mut sockets, &client, &dhcp_repr);
+        assert_eq!(client.poll(&iface, &mut sockets, 10), Ok(None));
+        assert!(sent(&mut sockets, &client).is_none());
+    }
+
+    #[test]
+    fn test_request_timeout() {
+        let (iface, mut sockets, mut client) = setup();
+        let client_id = client_id();
+        assert_eq!(client.poll(&iface, &mut sockets, 0), Ok(None));
+        let (_, payload) = sent(&mut sockets, &client).unwrap();
+        let xid = parse_sent(&payload).transaction_id;
+        reply(&mut sockets, &client,
+              &server_repr(Dhcpv6MessageType::Advertise, xid, &client_id));
+
+        let mut now = 0;
+        for _ in 0..REQ_MAX_RC {
+            assert_eq!(client.poll(&iface, &mut sockets, now), Ok(None));
+            let (_, payload) = sent(&mut sockets, &client).unwrap();
+            assert_eq!(parse_sent(&payload).message_type, Dhcpv6MessageType::Request);
+            now = client.next_poll(now);
+        }
+
+        assert_eq!(client.poll(&iface, &mut sockets, now), Ok(None));
+        let (_, payload) = sent(&mut sockets, &client).unwrap();
+        assert_eq!(parse_sent(&payload).message_type, Dhcpv6MessageType::Solicit);
+    }
+
+    #[test]
+    fn test_bind() {
+        let (iface, mut sockets, mut client) = setup();
+        bind(&iface, &mut sockets, &mut client);
+        assert_eq!(client.next_poll(20), 20 + 1_000_000);
+    }
+
+    #[test]
+    fn test_request_failed() {
+        let (iface, mut sockets, mut client) = setup();
+        let client_id = client_id();
+        assert_eq!(client.poll(&iface, &mut sockets, 0), Ok(None));
+        let (_, payload) = sent(&mut sockets, &client).unwrap();
+        let xid = parse_sent(&payload).transaction_id;
+        reply(&mut sockets, &client,
+              &server_repr(Dhcpv6MessageType::Advertise, xid, &client_id));
+        assert_eq!(client.poll(&iface, &mut sockets, 10), Ok(None));
+        let (_, payload) = sent(&mut sockets, &client).unwrap();
+        let xid = parse_sent(&payload).transaction_id;
+
+        let mut dhcp_repr = server_repr(Dhcpv6MessageType::Reply, xid, &client_id);
+        dhcp_repr.ia_na.as_mut().unwrap().address = None;
+        dhcp_repr.ia_na.as_mut().unwrap().status_code = Some(Dhcpv6StatusCode::NoAddrsAvail);
+        reply(&mut sockets, &client, &dhcp_repr);
+        assert_eq!(client.poll(&iface, &mut sockets, 20), Ok(None));
+        let (_, payload) = sent(&mut sockets, &client).unwrap();
+        assert_eq!(parse_sent(&payload).message_type, Dhcpv6MessageType::Solicit);
+    }
+
+    #[test]
+    fn test_renew() {
+        let (iface, mut sockets, mut client) = setup();
+        let client_id = client_id();
+        bind(&iface, &mut sockets, &mut client);
+
+        assert_eq!(client.poll(&iface, &mut sockets, 20 + 1_000_000), Ok(None));
+        let (_, payload) = sent(&mut sockets, &client).unwrap();
+        let dhcp_repr = parse_sent(&payload);
+        assert_eq!(dhcp_repr.message_type, Dhcpv6MessageType::Renew);
+        assert_eq!(dhcp_repr.server_id, Some(&SERVER_DUID[..]));
+        assert_eq!(dhcp_repr.ia_na.unwrap().address.unwrap().address, OFFERED_IP);
+        let timeout = client.next_poll(20 + 1_000_000) - (20 + 1_000_000);
+        assert!(timeout >= 9_000 && timeout <= 11_000);
+
+        // A reply with the same configuration is reported again with the new lifetimes.
+        let xid = dhcp_repr.transaction_id;
+        reply(&mut sockets, &client, &server_repr(Dhcpv6MessageType::Reply, xid, &client_id));
+        assert_eq!(client.poll(&iface, &mut sockets, 20 + 1_000_100),
+                   Ok(Some(Config {
+                       preferred_until: Some(20 + 3_000_100),
+                       expires_at: Some(20 + 5_000_100),
+                       ..expected_config()
+                   })));
+        assert_eq!(client.poll(&iface, &mut sockets, 20 + 1_000_200), Ok(None));
+        assert_eq!(client.next_poll(20 + 1_000_100), 20 + 1_000_100 + 1_000_000);
+    }
+
+    #[test]
+    fn test_renew_no_binding() {
+        let (iface, mut sockets, mut client) = setup();
+        let client_id = client_id();
+        bind(&iface, &mut sockets, &mut client);
+
+        assert_eq!(client.poll(&iface, &mut sockets, 20 + 1_000_000), Ok(None));
+        let (_, payload) = sent(&mut sockets, &client).unwrap();
+        let xid = parse_sent(&payload).transaction_id;
+
+        let mut dhcp_repr = server_repr(Dhcpv6MessageType::Reply, xid, &client_id);
+        dhcp_repr.ia_na.as_mut().unwrap().address = None;
+        dhcp_repr.ia_na.as_mut().unwrap().status_code = Some(Dhcpv6StatusCode::NoBinding);
+        reply(&mut sockets, &client, &dhcp_repr);
+        assert_eq!(client.poll(&iface, &mut sockets, 20 + 1_000_100), Ok(None));
+        let (_, payload) = sent(&mut sockets, &client).unwrap();
+        let dhcp_repr = parse_sent(&payload);
+        assert_eq!(dhcp_repr.message_type, Dhcpv6MessageType::Request);
+        assert_eq!(dhcp_repr.ia_na.unwrap().address.unwrap().address, OFFERED_IP);
+    }
+
+    #[test]
+    fn test_rebind_and_expire() {
+        let (iface, mut sockets, mut client) = setup();
+        bind(&iface, &mut sockets, &mut client);
+
+        assert_eq!(client.poll(&iface, &mut sockets, 20 + 1_600_000), Ok(None));
+        let (_, payload) = sent(&mut sockets, &client).unwrap();
+        let dhcp_repr = parse_sent(&payload);
+        assert_eq!(dhcp_repr.message_type, Dhcpv6MessageType::Rebind);
+        assert_eq!(dhcp_repr.server_id, None);
+        assert_eq!(dhcp_repr.elapsed_time, Some(0));
+
+        assert_eq!(client.poll(&iface, &mut sockets, 20 + 4_000_000),
+                   Ok(Some(Config::default())));
+        let (_, payload) = sent(&mut sockets, &client).unwrap();
+        assert_eq!(parse_sent(&payload).message_type, Dhcpv6MessageType::Solicit);
+    }
+
+    #[test]
+    fn test_rebind_deadline() {
+        let (iface, mut sockets, mut client) = setup();
+        bind(&iface, &mut sockets, &mut client);
+
+        // Retransmissions of Rebind never go past the expiry of the address.
+        assert_eq!(client.poll(&iface, &mut sockets, 20 + 3_999_000), Ok(None));
+        assert!(sent(&mut sockets, &client).is_some());
+        assert_eq!(client.next_poll(20 + 3_999_000), 20 + 4_000_000);
+    }
+
+    #[test]
+    fn test_reset() {
+        let (iface, mut sockets, mut client) = setup();
+        bind(&iface, &mut sockets, &mut client);
+
+        client.reset(100);
+        assert_eq!(client.poll(&iface, &mut sockets, 100), Ok(Some(Config::default())));
+        let (_, payload) = sent(&mut sockets, &client).unwrap();
+        assert_eq!(parse_sent(&payload).message_type, Dhcpv6MessageType::Solicit);
+    }
+
+    #[test]
+    fn test_client_duid() {
+        assert_eq!(client_id(), [0x00, 0x03, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+    }
+}
@@ -0,0 +1,22 @@
+/*! Dynamic host configuration.
+
+The `dhcp` module implements a DHCPv4 client that obtains and maintains a lease for an
+interface address, on top of a raw socket, a minimal DHCPv4 server that hands out
+addresses from a single pool, on top of a UDP socket, and a DHCPv6 client that acquires
+a non-temporary address, on top of a UDP socket.
+*/
+
+#[cfg(feature = "proto-dhcpv4")]
+mod clientv4;
+#[cfg(all(feature = "proto-dhcpv4", feature = "socket-udp"))]
+mod serverv4;
+#[cfg(feature = "proto-dhcpv6")]
+mod clientv6;
+
+#[cfg(feature = "proto-dhcpv4")]
+pub use self::clientv4::{Client as Dhcpv4Client, Config as Dhcpv4Config};
+#[cfg(all(feature = "proto-dhcpv4", feature = "socket-udp"))]
+pub use self::serverv4::{Server as Dhcpv4Server, Config as Dhcpv4ServerConfig,
+                         Lease as Dhcpv4Lease, LeaseState as Dhcpv4LeaseState};
+#[cfg(feature = "proto-dhcpv6")]
+pub use self::clientv6::{Client as Dhcpv6Client, Config as Dhcpv6Config};
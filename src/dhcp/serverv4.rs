@@ -0,0 +1,682 @@
+// Heads up! Before working on this file you should read RFC 2131,
+// in particular § 4.3, which describes the server behavior.
+
+use byteorder::{ByteOrder, NetworkEndian};
+use managed::ManagedSlice;
+
+use Result;
+use wire::{IpEndpoint, EthernetAddress, Ipv4Address, Ipv4Cidr,
+           DhcpPacket, DhcpRepr, DhcpMessageType,
+           DHCP_CLIENT_PORT, DHCP_SERVER_PORT};
+use wire::dhcpv4::MAX_DNS_SERVER_COUNT;
+use socket::{SocketSet, SocketHandle, UdpSocket, UdpSocketBuffer};
+
+/// How long an offered address is reserved for the client it was offered to.
+const OFFER_TIMEOUT: u64 = 60_000;
+
+/// Configuration of a DHCPv4 server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// The address of the server on the served network, and the network prefix
+    /// handed out to clients. The address is also used as the server identifier.
+    pub server_address: Ipv4Cidr,
+    /// The first address of the pool. The pool contains as many consecutive addresses
+    /// as there are lease slots.
+    pub pool_start: Ipv4Address,
+    /// The default gateway handed out to clients.
+    pub router: Option<Ipv4Address>,
+    /// DNS servers handed out to clients.
+    pub dns_servers: [Option<Ipv4Address>; MAX_DNS_SERVER_COUNT],
+    /// The lease duration, in seconds.
+    pub lease_duration: u32,
+}
+
+/// The state of a lease.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaseState {
+    /// The address was offered to the client, which has not requested it yet.
+    Offered,
+    /// The address was acknowledged to the client.
+    Bound,
+    /// The client reported that the address is already in use by another host.
+    Declined,
+}
+
+/// A lease of a pool address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lease {
+    /// The hardware address of the client the address is leased to.
+    pub hardware_addr: EthernetAddress,
+    /// The leased address.
+    pub address: Ipv4Address,
+    /// The state of the lease.
+    pub state: LeaseState,
+    /// The time, in milliseconds, at which the address returns to the pool.
+    pub expires_at: u64,
+}
+
+/// A DHCPv4 server.
+///
+/// The server answers DHCP clients through a UDP socket bound to port 67 that it adds to
+/// the socket set, and hands out addresses from a single pool of consecutive addresses.
+/// Each address of the pool has a lease slot in the storage provided to the server.
+///
+/// The interface must have the server address assigned.
+#[derive(Debug)]
+pub struct Server<'a> {
+    udp_handle: SocketHandle,
+    config: Config,
+    leases: ManagedSlice<'a, Option<Lease>>,
+}
+
+impl<'a> Server<'a> {
+    /// Create a DHCPv4 server, and add its UDP socket, created with the given buffers,
+    /// to the socket set.
+    ///
+    /// # Panics
+    /// This function panics if the pool does not fit into the served network.
+    pub fn new<'b, 'c, 'e, LeasesT>(sockets: &mut SocketSet<'b, 'c, 'e>,
+                                    rx_buffer: UdpSocketBuffer<'c, 'e>,
+                                    tx_buffer: UdpSocketBuffer<'c, 'e>,
+                                    config: Config, leases: LeasesT) -> Server<'a>
+            where LeasesT: Into<ManagedSlice<'a, Option<Lease>>> {
+        let mut udp_socket = UdpSocket::new(rx_buffer, tx_buffer);
+        udp_socket.bind(DHCP_SERVER_PORT).expect("cannot bind DHCP server socket");
+        // Clients without an address broadcast their requests, and may ask for broadcast replies.
+        udp_socket.set_broadcast(true);
+        let udp_handle = sockets.add(udp_socket);
+
+        let server = Server {
+            udp_handle: udp_handle,
+            config: config,
+            leases: leases.into(),
+        };
+        server.check_config();
+        server
+    }
+
+    /// Return the handle of the UDP socket used by the server.
+    pub fn udp_handle(&self) -> SocketHandle {
+        self.udp_handle
+    }
+
+    /// Return the server configuration.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Change the server configuration.
+    ///
+    /// If the pool or the served network changes, all leases are forgotten.
+    ///
+    /// # Panics
+    /// This function panics if the pool does not fit into the served network.
+    pub fn set_config(&mut self, config: Config) {
+        if config.server_address != self.config.server_address ||
+                config.pool_start != self.config.pool_start {
+            for lease in self.leases.iter_mut() {
+                *lease = None;
+            }
+        }
+        self.config = config;
+        self.check_config();
+    }
+
+    /// Return the lease slots, one per pool address.
+    ///
+    /// Expired leases are only cleared when their slot is reused, so check
+    /// the `expires_at` field.
+    pub fn leases(&self) -> &[Option<Lease>] {
+        &self.leases
+    }
+
+    /// Answer the DHCP messages received by the UDP socket.
+    pub fn poll(&mut self, sockets: &mut SocketSet, now: u64) -> Result<()> {
+        let mut udp_socket = sockets.get::<UdpSocket>(self.udp_handle);
+        while udp_socket.can_recv() {
+            let reply = {
+                let (payload, _) = udp_socket.recv()?;
+                match DhcpPacket::new_checked(payload).and_then(|packet| DhcpRepr::parse(&packet)) {
+                    Ok(dhcp_repr) => self.process(&dhcp_repr, now),
+                    Err(_) => {
+                        net_debug!("DHCP server ignoring malformed packet");
+                        None
+                    }
+                }
+            };
+
+            if let Some((dhcp_repr, endpoint)) = reply {
+                let buffer = udp_socket.send(dhcp_repr.buffer_len(), endpoint)?;
+                dhcp_repr.emit(&mut DhcpPacket::new(buffer));
+            }
+        }
+        Ok(())
+    }
+
+    fn process(&mut self, dhcp_repr: &DhcpRepr, now: u64) ->
+              Option<(DhcpRepr<'static>, IpEndpoint)> {
+        let server_identifier = self.config.server_address.address();
+        let hardware_addr = dhcp_repr.client_hardware_address;
+
+        match dhcp_repr.message_type {
+            DhcpMessageType::Discover => {
+                let index = self.find_lease(hardware_addr, dhcp_repr.requested_ip, now)?;
+                let address = self.pool_address(index);
+                match self.leases[index] {
+                    // Keep the lease of a bound client that has lost track of it.
+                    Some(ref lease) if lease.state == LeaseState::Bound &&
+                                       lease.hardware_addr == hardware_addr &&
+                                       lease.expires_at > now => (),
+                    _ => {
+                        self.leases[index] = Some(Lease {
+                            hardware_addr: hardware_addr,
+                            address: address,
+                            state: LeaseState::Offered,
+                            expires_at: now + OFFER_TIMEOUT,
+                        })
+                    }
+                }
+                net_debug!("DHCP server offering {} to {}", address, hardware_addr);
+                Some(self.reply(dhcp_repr, DhcpMessageType::Offer, address))
+            }
+            DhcpMessageType::Request => {
+                match dhcp_repr.server_identifier {
+                    Some(addr) if addr != server_identifier => {
+                        // The client accepted an offer from another server.
+                        self.release(hardware_addr, None);
+                        return None
+                    }
+                    _ => ()
+                }
+
+                let address = match dhcp_repr.requested_ip {
+                    Some(addr) => addr,
+                    None if !dhcp_repr.client_ip.is_unspecified() => dhcp_repr.client_ip,
+                    None => return None
+                };
+                let index = match self.pool_index(address) {
+                    Some(index) if self.is_available(index, hardware_addr, now) => index,
+                    _ => {
+                        net_debug!("DHCP server refusing {} to {}", address, hardware_addr);
+                        return Some(self.reply(dhcp_repr, DhcpMessageType::Nak,
+                                               Ipv4Address::UNSPECIFIED))
+                    }
+                };
+
+                self.leases[index] = Some(Lease {
+                    hardware_addr: hardware_addr,
+                    address: address,
+                    state: LeaseState::Bound,
+                    expires_at: now + self.config.lease_duration as u64 * 1000,
+                });
+                net_debug!("DHCP server leasing {} to {}", address, hardware_addr);
+                Some(self.reply(dhcp_repr, DhcpMessageType::Ack, address))
+            }
+            DhcpMessageType::Decline => {
+                if dhcp_repr.server_identifier != Some(server_identifier) { return None }
+
+                let index = self.pool_index(dhcp_repr.requested_ip?)?;
+                match self.leases[index] {
+                    Some(ref mut lease) if lease.hardware_addr == hardware_addr => {
+                        net_debug!("DHCP server: {} is in use", lease.address);
+                        lease.state = LeaseState::Declined;
+                        lease.expires_at = now + self.config.lease_duration as u64 * 1000;
+                    }
+                    _ => ()
+                }
+                None
+            }
+            DhcpMessageType::Release => {
+                if dhcp_repr.server_identifier != Some(server_identifier) { return None }
+
+                self.release(hardware_addr, Some(dhcp_repr.client_ip));
+                None
+            }
+            _ => None
+        }
+    }
+
+    fn reply(&self, request: &DhcpRepr, message_type: DhcpMessageType,
+             address: Ipv4Address) -> (DhcpRepr<'static>, IpEndpoint) {
+        let (router, subnet_mask, dns_servers, lease_duration) =
+            if message_type == DhcpMessageType::Nak {
+                (None, None, None, None)
+            } else {
+                (self.config.router,
+                 Some(self.config.server_address.netmask()),
+                 Some(self.config.dns_servers),
+                 Some(self.config.lease_duration))
+            };
+
+        let dhcp_repr = DhcpRepr {
+            message_type: message_type,
+            transaction_id: request.transaction_id,
+            client_hardware_address: request.client_hardware_address,
+            client_ip: request.client_ip,
+            your_ip: address,
+            server_ip: Ipv4Address::UNSPECIFIED,
+            router: router,
+            subnet_mask: subnet_mask,
+            relay_agent_ip: request.relay_agent_ip,
+            broadcast: request.broadcast,
+            requested_ip: None,
+            client_identifier: None,
+            server_identifier: Some(self.config.server_address.address()),
+            parameter_request_list: None,
+            dns_servers: dns_servers,
+            max_size: None,
+            lease_duration: lease_duration,
+            renew_duration: None,
+            rebind_duration: None,
+            additional_options: &[],
+        };
+
+        // See RFC 2131 § 4.1. Unicasting to a client without an address would require
+        // filling the neighbor cache using the client hardware address, so broadcast
+        // to such clients instead.
+        let endpoint = if !request.relay_agent_ip.is_unspecified() {
+            IpEndpoint::new(request.relay_agent_ip.into(), DHCP_SERVER_PORT)
+        } else if message_type != DhcpMessageType::Nak && !request.client_ip.is_unspecified() {
+            IpEndpoint::new(request.client_ip.into(), DHCP_CLIENT_PORT)
+        } else {
+            IpEndpoint::new(Ipv4Address::BROADCAST.into(), DHCP_CLIENT_PORT)
+        };
+
+        (dhcp_repr, endpoint)
+    }
+
+    /// Find the lease slot to offer to a client: the one it already holds, the one
+    /// it asks for if that is available, or any available one.
+    fn find_lease(&self, hardware_addr: EthernetAddress, requested_ip: Option<Ipv4Address>,
+                  now: u64) -> Option<usize> {
+        let held = self.leases.iter().position(|lease| {
+            match *lease {
+                Some(ref lease) => lease.hardware_addr == hardware_addr &&
+                                   lease.state != LeaseState::Declined &&
+                                   lease.expires_at > now,
+                None => false
+            }
+        });
+        if held.is_some() { return held }
+
+        if let Some(index) = requested_ip.and_then(|addr| self.pool_index(addr)) {
+            if self.is_available(index, hardware_addr, now) { return Some(index) }
+        }
+
+        (0..self.leases.len()).find(|&index| self.is_available(index, hardware_addr, now))
+    }
+
+    fn is_available(&self, index: usize, hardware_addr: EthernetAddress, now: u64) -> bool {
+        match self.leases[index] {
+            None => true,
+            Some(ref lease) if lease.expires_at <= now => true,
+            Some(ref lease) => lease.hardware_addr == hardware_addr &&
+                               lease.state != LeaseState::Declined
+        }
+    }
+
+    fn release(&mut self, hardware_addr: EthernetAddress, address: Option<Ipv4Address>) {
+        for slot in self.leases.iter_mut() {
+            let matches = match *slot {
+                Some(ref lease) =>
+                    lease.hardware_addr == hardware_addr &&
+                    lease.state != LeaseState::Declined &&
+                    address.map(|addr| addr == lease.address)
+                           .unwrap_or(lease.state == LeaseState::Offered),
+                None => false
+            };
+            if matches { *slot = None }
+        }
+    }
+
+    fn pool_index(&self, address: Ipv4Address) -> Option<usize> {
+        let start = NetworkEndian::read_u32(self.config.pool_start.as_bytes());
+        let offset = NetworkEndian::read_u32(address.as_bytes()).wrapping_sub(start) as usize;
+        if offset < self.leases.len() { Some(offset) } else { None }
+    }
+
+    fn pool_address(&self, index: usize) -> Ipv4Address {
+        let start = NetworkEndian::read_u32(self.config.pool_start.as_bytes());
+        let mut bytes = [0; 4];
+        NetworkEndian::write_u32(&mut bytes, start + index as u32);
+        Ipv4Address(bytes)
+    }
+
+    fn check_config(&self) {
+        let network = self.config.server_address;
+        if self.leases.len() == 0 { return }
+
+        let last = self.pool_address(self.leases.len() - 1);
+        if !network.contains_addr(&self.config.pool_start) || !network.contains_addr(&last) {
+            panic!("DHCP pool {}..={} is not within {}", self.config.pool_start, last, network)
+        }
+        if self.pool_index(network.address()).is_some() {
+            panic!("DHCP pool {}..={} contains the server address", self.config.pool_start, last)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use wire::{IpRepr, IpProtocol, Ipv4Repr, UdpRepr};
+    use socket::UdpPacketBuffer;
+    use super::*;
+
+    const SERVER_IP: Ipv4Address = Ipv4Address([192, 168, 1, 1]);
+    const POOL_START: Ipv4Address = Ipv4Address([192, 168, 1, 100]);
+    const CLIENT_MAC_1: EthernetAddress = EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+    const CLIENT_MAC_2: EthernetAddress = EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x02]);
+
+    fn buffer() -> UdpSocketBuffer<'static, 'static> {
+        UdpSocketBuffer::new(vec![UdpPacketBuffer::new(vec![0; 600]),
+                                  UdpPacketBuffer::new(vec![0; 600])])
+    }
+
+    fn config() -> Config {
+        Config {
+            server_address: Ipv4Cidr::new(SERVER_IP, 24),
+            pool_start: POOL_START,
+            router: Some(SERVER_IP),
+            dns_servers: [Some(SERVER_IP), None, None],
+            lease_duration: 3600,
+        }
+    }
+
+    fn setup(pool_size: usize) -> (SocketSet<'static, 'static, 'static>, Server<'static>) {
+        let mut sockets = SocketSet::new(vec![]);
+        let server = Server::new(&mut sockets, buffer(), buffer(), config(),
+                                 vec![None; pool_size]);
+        (sockets, server)
+    }
+
+    fn client_repr(message_type: DhcpMessageType,
+                   hardware_addr: EthernetAddress) -> DhcpRepr<'static> {
+        DhcpRepr {
+            message_type: message_type,
+            transaction_id: 0x12345678,
+            client_hardware_address: hardware_addr,
+            client_ip: Ipv4Address::UNSPECIFIED,
+            your_ip: Ipv4Address::UNSPECIFIED,
+            server_ip: Ipv4Address::UNSPECIFIED,
+            router: None,
+            subnet_mask: None,
+            relay_agent_ip: Ipv4Address::UNSPECIFIED,
+            broadcast: false,
+            requested_ip: None,
+            client_identifier: Some(hardware_addr),
+            server_identifier: None,
+            parameter_request_list: None,
+            dns_servers: None,
+            max_size: None,
+            lease_duration: None,
+            renew_duration: None,
+            rebind_duration: None,
+            additional_options: &[],
+        }
+    }
+
+    fn request(hardware_addr: EthernetAddress, address: Ipv4Address) -> DhcpRepr<'static> {
+        DhcpRepr {
+            requested_ip: Some(address),
+            server_identifier: Some(SERVER_IP),
+            ..client_repr(DhcpMessageType::Request, hardware_addr)
+        }
+    }
+
+    fn send(sockets: &mut SocketSet, server: &Server, dhcp_repr: &DhcpRepr) {
+        let mut bytes = vec![0; dhcp_repr.buffer_len()];
+        dhcp_repr.emit(&mut DhcpPacket::new(&mut bytes[..]));
+        let udp_repr = UdpRepr {
+            src_port: DHCP_CLIENT_PORT,
+            dst_port: DHCP_SERVER_PORT,
+            payload:  &bytes
+        };
+        let ip_repr = IpRepr::Ipv4(Ipv4Repr {
+            src_addr: dhcp_repr.client_ip,
+            dst_addr: Ipv4Address::BROADCAST,
+            protocol: IpProtocol::Udp,
+            payload_len: udp_repr.buffer_len(),
+            hop_limit: 64,
+            ecn: 0,
+            dscp: 0
+        });
+        sockets.get::<UdpSocket>(server.udp_handle())
+            .process_lent(0, None, &ip_repr, &udp_repr, None).unwrap();
+    }
+
+    fn recv(sockets: &mut SocketSet, server: &Server) -> Option<(IpEndpoint, Vec<u8>)> {
+        let mut result = None;
+        let _ = sockets.get::<UdpSocket>(server.udp_handle()).dispatch(0, |(ip_repr, udp_repr)| {
+            assert_eq!(udp_repr.src_port, DHCP_SERVER_PORT);
+            result = Some((IpEndpoint::new(ip_repr.dst_addr(), udp_repr.dst_port),
+                           udp_repr.payload.to_vec()));
+            Ok(())
+        });
+        result
+    }
+
+    fn parse(bytes: &[u8]) -> DhcpRepr {
+        DhcpRepr::parse(&DhcpPacket::new_checked(bytes).unwrap()).unwrap()
+    }
+
+    const BROADCAST_ENDPOINT: IpEndpoint = IpEndpoint {
+        addr: ::wire::IpAddress::Ipv4(Ipv4Address::BROADCAST),
+        port: DHCP_CLIENT_PORT
+    };
+
+    #[test]
+    fn test_offer() {
+        let (mut sockets, mut server) = setup(4);
+        send(&mut sockets, &server, &client_repr(DhcpMessageType::Discover, CLIENT_MAC_1));
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+
+        let (endpoint, bytes) = recv(&mut sockets, &server).unwrap();
+        assert_eq!(endpoint, BROADCAST_ENDPOINT);
+        let dhcp_repr = parse(&bytes);
+        assert_eq!(dhcp_repr.message_type, DhcpMessageType::Offer);
+        assert_eq!(dhcp_repr.transaction_id, 0x12345678);
+        assert_eq!(dhcp_repr.client_hardware_address, CLIENT_MAC_1);
+        assert_eq!(dhcp_repr.your_ip, POOL_START);
+        assert_eq!(dhcp_repr.server_identifier, Some(SERVER_IP));
+        assert_eq!(dhcp_repr.subnet_mask, Some(Ipv4Address([255, 255, 255, 0])));
+        assert_eq!(dhcp_repr.router, Some(SERVER_IP));
+        assert_eq!(dhcp_repr.lease_duration, Some(3600));
+
+        assert_eq!(server.leases()[0], Some(Lease {
+            hardware_addr: CLIENT_MAC_1,
+            address: POOL_START,
+            state: LeaseState::Offered,
+            expires_at: OFFER_TIMEOUT
+        }));
+    }
+
+    #[test]
+    fn test_offer_requested_ip() {
+        let (mut sockets, mut server) = setup(4);
+        send(&mut sockets, &server, &DhcpRepr {
+            requested_ip: Some(Ipv4Address([192, 168, 1, 102])),
+            ..client_repr(DhcpMessageType::Discover, CLIENT_MAC_1)
+        });
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+
+        let (_, bytes) = recv(&mut sockets, &server).unwrap();
+        assert_eq!(parse(&bytes).your_ip, Ipv4Address([192, 168, 1, 102]));
+    }
+
+    #[test]
+    fn test_ack() {
+        let (mut sockets, mut server) = setup(4);
+        send(&mut sockets, &server, &client_repr(DhcpMessageType::Discover, CLIENT_MAC_1));
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+        assert!(recv(&mut sockets, &server).is_some());
+
+        send(&mut sockets, &server, &request(CLIENT_MAC_1, POOL_START));
+        assert_eq!(server.poll(&mut sockets, 10), Ok(()));
+        let (endpoint, bytes) = recv(&mut sockets, &server).unwrap();
+        assert_eq!(endpoint, BROADCAST_ENDPOINT);
+        let dhcp_repr = parse(&bytes);
+        assert_eq!(dhcp_repr.message_type, DhcpMessageType::Ack);
+        assert_eq!(dhcp_repr.your_ip, POOL_START);
+
+        assert_eq!(server.leases()[0], Some(Lease {
+            hardware_addr: CLIENT_MAC_1,
+            address: POOL_START,
+            state: LeaseState::Bound,
+            expires_at: 10 + 3_600_000
+        }));
+    }
+
+    #[test]
+    fn test_renew() {
+        let (mut sockets, mut server) = setup(4);
+        send(&mut sockets, &server, &request(CLIENT_MAC_1, POOL_START));
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+        assert!(recv(&mut sockets, &server).is_some());
+
+        send(&mut sockets, &server, &DhcpRepr {
+            client_ip: POOL_START,
+            ..client_repr(DhcpMessageType::Request, CLIENT_MAC_1)
+        });
+        assert_eq!(server.poll(&mut sockets, 1000), Ok(()));
+        let (endpoint, bytes) = recv(&mut sockets, &server).unwrap();
+        assert_eq!(endpoint, IpEndpoint::new(POOL_START.into(), DHCP_CLIENT_PORT));
+        assert_eq!(parse(&bytes).message_type, DhcpMessageType::Ack);
+        assert_eq!(server.leases()[0].unwrap().expires_at, 1000 + 3_600_000);
+    }
+
+    #[test]
+    fn test_nak_taken() {
+        let (mut sockets, mut server) = setup(4);
+        send(&mut sockets, &server, &request(CLIENT_MAC_1, POOL_START));
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+        assert!(recv(&mut sockets, &server).is_some());
+
+        send(&mut sockets, &server, &request(CLIENT_MAC_2, POOL_START));
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+        let (endpoint, bytes) = recv(&mut sockets, &server).unwrap();
+        assert_eq!(endpoint, BROADCAST_ENDPOINT);
+        let dhcp_repr = parse(&bytes);
+        assert_eq!(dhcp_repr.message_type, DhcpMessageType::Nak);
+        assert_eq!(dhcp_repr.your_ip, Ipv4Address::UNSPECIFIED);
+    }
+
+    #[test]
+    fn test_nak_outside_pool() {
+        let (mut sockets, mut server) = setup(4);
+        send(&mut sockets, &server, &request(CLIENT_MAC_1, Ipv4Address([10, 0, 0, 2])));
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+        let (_, bytes) = recv(&mut sockets, &server).unwrap();
+        assert_eq!(parse(&bytes).message_type, DhcpMessageType::Nak);
+    }
+
+    #[test]
+    fn test_other_server_chosen() {
+        let (mut sockets, mut server) = setup(4);
+        send(&mut sockets, &server, &client_repr(DhcpMessageType::Discover, CLIENT_MAC_1));
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+        assert!(recv(&mut sockets, &server).is_some());
+
+        send(&mut sockets, &server, &DhcpRepr {
+            server_identifier: Some(Ipv4Address([192, 168, 1, 2])),
+            ..request(CLIENT_MAC_1, POOL_START)
+        });
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+        assert!(recv(&mut sockets, &server).is_none());
+        assert_eq!(server.leases()[0], None);
+    }
+
+    #[test]
+    fn test_pool_exhausted() {
+        let (mut sockets, mut server) = setup(1);
+        send(&mut sockets, &server, &client_repr(DhcpMessageType::Discover, CLIENT_MAC_1));
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+        assert!(recv(&mut sockets, &server).is_some());
+
+        send(&mut sockets, &server, &client_repr(DhcpMessageType::Discover, CLIENT_MAC_2));
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+        assert!(recv(&mut sockets, &server).is_none());
+
+        // The offer times out, and the address can be offered to another client.
+        assert_eq!(server.poll(&mut sockets, OFFER_TIMEOUT), Ok(()));
+        send(&mut sockets, &server, &client_repr(DhcpMessageType::Discover, CLIENT_MAC_2));
+        assert_eq!(server.poll(&mut sockets, OFFER_TIMEOUT), Ok(()));
+        let (_, bytes) = recv(&mut sockets, &server).unwrap();
+        assert_eq!(parse(&bytes).client_hardware_address, CLIENT_MAC_2);
+    }
+
+    #[test]
+    fn test_release() {
+        let (mut sockets, mut server) = setup(4);
+        send(&mut sockets, &server, &request(CLIENT_MAC_1, POOL_START));
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+        assert!(recv(&mut sockets, &server).is_some());
+
+        send(&mut sockets, &server, &DhcpRepr {
+            client_ip: POOL_START,
+            server_identifier: Some(SERVER_IP),
+            ..client_repr(DhcpMessageType::Release, CLIENT_MAC_1)
+        });
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+        assert!(recv(&mut sockets, &server).is_none());
+        assert_eq!(server.leases()[0], None);
+    }
+
+    #[test]
+    fn test_decline() {
+        let (mut sockets, mut server) = setup(4);
+        send(&mut sockets, &server, &request(CLIENT_MAC_1, POOL_START));
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+        assert!(recv(&mut sockets, &server).is_some());
+
+        send(&mut sockets, &server, &DhcpRepr {
+            requested_ip: Some(POOL_START),
+            server_identifier: Some(SERVER_IP),
+            ..client_repr(DhcpMessageType::Decline, CLIENT_MAC_1)
+        });
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+        assert_eq!(server.leases()[0].unwrap().state, LeaseState::Declined);
+
+        send(&mut sockets, &server, &client_repr(DhcpMessageType::Discover, CLIENT_MAC_1));
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+        let (_, bytes) = recv(&mut sockets, &server).unwrap();
+        assert_eq!(parse(&bytes).your_ip, Ipv4Address([192, 168, 1, 101]));
+    }
+
+    #[test]
+    fn test_relay() {
+        let (mut sockets, mut server) = setup(4);
+        send(&mut sockets, &server, &DhcpRepr {
+            relay_agent_ip: Ipv4Address([192, 168, 1, 254]),
+            ..client_repr(DhcpMessageType::Discover, CLIENT_MAC_1)
+        });
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+        let (endpoint, bytes) = recv(&mut sockets, &server).unwrap();
+        assert_eq!(endpoint, IpEndpoint::new(Ipv4Address([192, 168, 1, 254]).into(),
+                                             DHCP_SERVER_PORT));
+        assert_eq!(parse(&bytes).relay_agent_ip, Ipv4Address([192, 168, 1, 254]));
+    }
+
+    #[test]
+    fn test_set_config() {
+        let (mut sockets, mut server) = setup(4);
+        send(&mut sockets, &server, &request(CLIENT_MAC_1, POOL_START));
+        assert_eq!(server.poll(&mut sockets, 0), Ok(()));
+        assert!(server.leases()[0].is_some());
+
+        server.set_config(Config { lease_duration: 60, ..config() });
+        assert!(server.leases()[0].is_some());
+
+        server.set_config(Config { pool_start: Ipv4Address([192, 168, 1, 200]), ..config() });
+        assert_eq!(server.leases()[0], None);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not within")]
+    fn test_pool_outside_network() {
+        let mut sockets = SocketSet::new(vec![]);
+        Server::new(&mut sockets, buffer(), buffer(),
+                    Config { pool_start: Ipv4Address([192, 168, 1, 254]), ..config() },
+                    vec![None; 4]);
+    }
+}
@@ -0,0 +1,22 @@
+/*! Hypertext Transfer Protocol server.
+
+The `httpd` module implements a minimal HTTP/1.0 server, on top of TCP sockets, meant
+for the configuration pages of a device: it parses the request line, the headers
+and the body of each request, hands the request to a handler provided by the application,
+which writes the response, and closes the connection once the response is sent.
+*/
+
+mod request;
+mod server;
+
+pub use self::request::{Request, Method, Version, Headers, url_decode};
+pub use self::server::{Server, Response, Status};
+
+/// The maximum length of a request, including its body.
+pub const MAX_REQUEST_LEN: usize = 1024;
+
+/// The maximum number of headers in a request.
+pub const MAX_HEADER_COUNT: usize = 16;
+
+/// The maximum number of connections a server can serve at once.
+pub const MAX_CONNECTION_COUNT: usize = 4;
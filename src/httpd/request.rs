@@ -0,0 +1,313 @@
+// Heads up! Before working on this file you should read RFC 1945, which describes
+// HTTP/1.0, and RFC 7230 § 3, which describes the message syntax HTTP/1.1 clients use.
+
+use core::str;
+
+use {Error, Result};
+use super::MAX_HEADER_COUNT;
+
+/// A request method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method<'a> {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Options,
+    Other(&'a str),
+}
+
+impl<'a> Method<'a> {
+    fn parse(token: &'a str) -> Method<'a> {
+        match token {
+            "GET"     => Method::Get,
+            "HEAD"    => Method::Head,
+            "POST"    => Method::Post,
+            "PUT"     => Method::Put,
+            "DELETE"  => Method::Delete,
+            "OPTIONS" => Method::Options,
+            other     => Method::Other(other),
+        }
+    }
+}
+
+/// An HTTP protocol version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+/// A request, parsed in place from the octets received.
+///
+/// The path and the query are left as they are in the request line, with percent-encoded
+/// octets; see [url_decode](fn.url_decode.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Request<'a> {
+    /// The request method.
+    pub method:  Method<'a>,
+    /// The path of the requested resource, up to the query.
+    pub path:    &'a str,
+    /// The query of the requested resource, without the question mark, if any.
+    pub query:   Option<&'a str>,
+    /// The version of the protocol the client speaks.
+    pub version: Version,
+    /// The body of the request, whose length is given by the `Content-Length` header.
+    pub body:    &'a [u8],
+    headers:      [(&'a str, &'a str); MAX_HEADER_COUNT],
+    header_count: usize,
+}
+
+impl<'a> Request<'a> {
+    /// Parse a request from the octets received, and return it along with the length
+    /// of the request, including its body.
+    ///
+    /// This function returns `Err(Error::Truncated)` if the request is incomplete,
+    /// `Err(Error::Exhausted)` if it has more than [MAX_HEADER_COUNT] headers,
+    /// `Err(Error::Unrecognized)` if it uses a protocol version or a transfer coding
+    /// that is not supported, and `Err(Error::Malformed)` if it is not a valid request.
+    ///
+    /// [MAX_HEADER_COUNT]: constant.MAX_HEADER_COUNT.html
+    pub fn parse(data: &'a [u8]) -> Result<(Request<'a>, usize)> {
+        // Find the empty line that ends the head. Lines ending with a bare LF
+        // are accepted, as recommended by RFC 7230 § 3.5.
+        let mut head_len = None;
+        let mut line_start = 0;
+        for (index, &octet) in data.iter().enumerate() {
+            if octet != b'\n' { continue }
+            let line = &data[line_start..index];
+            if line.is_empty() || line == b"\r" {
+                head_len = Some(index + 1);
+                break
+            }
+            line_start = index + 1;
+        }
+        let head_len = match head_len {
+            Some(head_len) => head_len,
+            None => return Err(Error::Truncated)
+        };
+        let head = str::from_utf8(&data[..head_len]).map_err(|_| Error::Malformed)?;
+
+        let mut lines = head.split('\n').map(|line| line.trim_end_matches('\r'));
+        let request_line = lines.next().ok_or(Error::Malformed)?;
+        let mut parts = request_line.split(' ');
+        let (method, target, version) = match (parts.next(), parts.next(), parts.next(),
+                                                parts.next()) {
+            (Some(method), Some(target), Some(version), None) => (method, target, version),
+            _ => return Err(Error::Malformed)
+        };
+        if method.is_empty() || !method.bytes().all(is_token) {
+            return Err(Error::Malformed)
+        }
+        if !target.starts_with('/') { return Err(Error::Malformed) }
+        let (path, query) = match target.find('?') {
+            Some(index) => (&target[..index], Some(&target[index + 1..])),
+            None => (target, None)
+        };
+        let version = match version {
+            "HTTP/1.0" => Version::Http10,
+            "HTTP/1.1" => Version::Http11,
+            _ if version.starts_with("HTTP/") => return Err(Error::Unrecognized),
+            _ => return Err(Error::Malformed)
+        };
+
+        let mut headers = [("", ""); MAX_HEADER_COUNT];
+        let mut header_count = 0;
+        for line in lines {
+            if line.is_empty() { break }
+            let colon = line.find(':').ok_or(Error::Malformed)?;
+            let name = &line[..colon];
+            // This also rejects obsolete line folding.
+            if name.is_empty() || !name.bytes().all(is_token) { return Err(Error::Malformed) }
+            if header_count == MAX_HEADER_COUNT { return Err(Error::Exhausted) }
+            headers[header_count] = (name, line[colon + 1..].trim());
+            header_count += 1;
+        }
+
+        let mut request = Request {
+            method: Method::parse(method),
+            path, query, version,
+            body: &[],
+            headers, header_count
+        };
+        if request.header("Transfer-Encoding").is_some() { return Err(Error::Unrecognized) }
+        let body_len = match request.header("Content-Length") {
+            Some(value) => value.parse::<usize>().map_err(|_| Error::Malformed)?,
+            None => 0
+        };
+        if data.len() - head_len < body_len { return Err(Error::Truncated) }
+        request.body = &data[head_len..head_len + body_len];
+        Ok((request, head_len + body_len))
+    }
+
+    /// Return the value of the first header with the given name, compared without regard
+    /// to case, if any.
+    pub fn header(&self, name: &str) -> Option<&'a str> {
+        self.headers().find(|&(header, _)| header.eq_ignore_ascii_case(name))
+                      .map(|(_, value)| value)
+    }
+
+    /// Return the headers of the request, as names and values, in order.
+    pub fn headers(&self) -> Headers<'a> {
+        Headers { inner: self.headers, index: 0, count: self.header_count }
+    }
+
+    /// Return the value of the first parameter with the given name in the query or,
+    /// if the body is an URL-encoded form, e.g. submitted by a browser, in the body.
+    ///
+    /// The value is left percent-encoded; see [url_decode](fn.url_decode.html).
+    pub fn param(&self, name: &str) -> Option<&'a str> {
+        if let Some(value) = self.query.and_then(|query| find_param(query, name)) {
+            return Some(value)
+        }
+        match self.header("Content-Type") {
+            Some(content_type) if content_type.split(';').next().unwrap().trim()
+                    .eq_ignore_ascii_case("application/x-www-form-urlencoded") =>
+                str::from_utf8(self.body).ok().and_then(|form| find_param(form, name)),
+            _ => None
+        }
+    }
+}
+
+/// An iterator over the headers of a request.
+#[derive(Debug, Clone)]
+pub struct Headers<'a> {
+    inner: [(&'a str, &'a str); MAX_HEADER_COUNT],
+    index: usize,
+    count: usize,
+}
+
+impl<'a> Iterator for Headers<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<(&'a str, &'a str)> {
+        if self.index == self.count { return None }
+        self.index += 1;
+        Some(self.inner[self.index - 1])
+    }
+}
+
+fn is_token(octet: u8) -> bool {
+    match octet {
+        b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'*' | b'+' | b'-' | b'.' |
+        b'^' | b'_' | b'`' | b'|' | b'~' => true,
+        _ => octet.is_ascii_alphanumeric()
+    }
+}
+
+fn find_param<'a>(params: &'a str, name: &str) -> Option<&'a str> {
+    params.split('&').filter_map(|param| {
+        let mut parts = param.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(key), value) if key == name => Some(value.unwrap_or("")),
+            _ => None
+        }
+    }).next()
+}
+
+/// Decode a percent-encoded path, query or form value into the given buffer, replacing
+/// plus signs with spaces as in forms, and return it.
+///
+/// This function returns `Err(Error::Exhausted)` if the buffer is too short,
+/// and `Err(Error::Malformed)` if an escape is invalid or the result is not UTF-8.
+pub fn url_decode<'b>(value: &str, buffer: &'b mut [u8]) -> Result<&'b str> {
+    let value = value.as_bytes();
+    let (mut index, mut length) = (0, 0);
+    while index < value.len() {
+        let octet = match value[index] {
+            b'+' => b' ',
+            b'%' => {
+                if index + 2 >= value.len() { return Err(Error::Malformed) }
+                let hex = str::from_utf8(&value[index + 1..index + 3])
+                    .map_err(|_| Error::Malformed)?;
+                index += 2;
+                u8::from_str_radix(hex, 16).map_err(|_| Error::Malformed)?
+            }
+            octet => octet
+        };
+        *buffer.get_mut(length).ok_or(Error::Exhausted)? = octet;
+        index += 1;
+        length += 1;
+    }
+    str::from_utf8(&buffer[..length]).map_err(|_| Error::Malformed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static GET_BYTES: &'static [u8] =
+        b"GET /config?ssid=home+net&channel=6 HTTP/1.1\r\n\
+          Host: 192.168.1.1\r\n\
+          Accept:  text/html \r\n\
+          \r\n";
+
+    static POST_BYTES: &'static [u8] =
+        b"POST /config HTTP/1.0\n\
+          Content-Type: application/x-www-form-urlencoded; charset=utf-8\n\
+          Content-Length: 25\n\
+          \n\
+          ssid=caf%C3%A9&channel=11";
+
+    #[test]
+    fn test_parse_get() {
+        let (request, length) = Request::parse(GET_BYTES).unwrap();
+        assert_eq!(length, GET_BYTES.len());
+        assert_eq!(request.method, Method::Get);
+        assert_eq!(request.path, "/config");
+        assert_eq!(request.query, Some("ssid=home+net&channel=6"));
+        assert_eq!(request.version, Version::Http11);
+        assert_eq!(request.header("host"), Some("192.168.1.1"));
+        assert_eq!(request.header("Accept"), Some("text/html"));
+        assert_eq!(request.headers().count(), 2);
+        assert_eq!(request.body, b"");
+        assert_eq!(request.param("channel"), Some("6"));
+        assert_eq!(request.param("mode"), None);
+    }
+
+    #[test]
+    fn test_parse_post() {
+        let (request, length) = Request::parse(POST_BYTES).unwrap();
+        assert_eq!(length, POST_BYTES.len());
+        assert_eq!(request.method, Method::Post);
+        assert_eq!(request.version, Version::Http10);
+        assert_eq!(request.body, b"ssid=caf%C3%A9&channel=11");
+        assert_eq!(request.param("channel"), Some("11"));
+
+        let mut buffer = [0; 16];
+        assert_eq!(url_decode(request.param("ssid").unwrap(), &mut buffer), Ok("café"));
+        assert_eq!(url_decode("home+net", &mut buffer), Ok("home net"));
+        assert_eq!(url_decode("%4", &mut buffer), Err(Error::Malformed));
+        assert_eq!(url_decode("%zz", &mut buffer), Err(Error::Malformed));
+        assert_eq!(url_decode("%ff", &mut buffer), Err(Error::Malformed));
+        assert_eq!(url_decode("0123456789abcdefg", &mut buffer), Err(Error::Exhausted));
+    }
+
+    #[test]
+    fn test_parse_incomplete() {
+        assert_eq!(Request::parse(&GET_BYTES[..GET_BYTES.len() - 2]), Err(Error::Truncated));
+        assert_eq!(Request::parse(&POST_BYTES[..POST_BYTES.len() - 2]), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        assert_eq!(Request::parse(b"GET /\r\n\r\n"), Err(Error::Malformed));
+        assert_eq!(Request::parse(b"GET http://host/ HTTP/1.0\r\n\r\n"), Err(Error::Malformed));
+        assert_eq!(Request::parse(b"GET / HTTP/2.0\r\n\r\n"), Err(Error::Unrecognized));
+        assert_eq!(Request::parse(b"GET / HTTP/1.0\r\nHost\r\n\r\n"), Err(Error::Malformed));
+        assert_eq!(Request::parse(b"GET / HTTP/1.0\r\nA: b\r\n c\r\n\r\n"),
+                   Err(Error::Malformed));
+        assert_eq!(Request::parse(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n"),
+                   Err(Error::Unrecognized));
+        assert_eq!(Request::parse(b"POST / HTTP/1.1\r\nContent-Length: x\r\n\r\n"),
+                   Err(Error::Malformed));
+
+        let mut bytes = b"GET / HTTP/1.0\r\n".to_vec();
+        for _ in 0..MAX_HEADER_COUNT + 1 {
+            bytes.extend_from_slice(b"A: b\r\n");
+        }
+        bytes.extend_from_slice(b"\r\n");
+        assert_eq!(Request::parse(&bytes), Err(Error::Exhausted));
+    }
+}
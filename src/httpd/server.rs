@@ -0,0 +1,454 @@
+use core::fmt;
+
+use {Error, Result};
+use socket::{SocketSet, SocketHandle, TcpSocket, TcpSocketBuffer, TcpState};
+use super::{Request, Method, MAX_REQUEST_LEN, MAX_CONNECTION_COUNT};
+
+/// A response status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Created,
+    NoContent,
+    MovedPermanently,
+    Found,
+    SeeOther,
+    NotModified,
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    PayloadTooLarge,
+    InternalServerError,
+    NotImplemented,
+    ServiceUnavailable,
+}
+
+impl Status {
+    /// Return the status code.
+    pub fn code(&self) -> u16 {
+        match *self {
+            Status::Ok                  => 200,
+            Status::Created             => 201,
+            Status::NoContent           => 204,
+            Status::MovedPermanently    => 301,
+            Status::Found               => 302,
+            Status::SeeOther            => 303,
+            Status::NotModified         => 304,
+            Status::BadRequest          => 400,
+            Status::Unauthorized        => 401,
+            Status::Forbidden           => 403,
+            Status::NotFound            => 404,
+            Status::MethodNotAllowed    => 405,
+            Status::PayloadTooLarge     => 413,
+            Status::InternalServerError => 500,
+            Status::NotImplemented      => 501,
+            Status::ServiceUnavailable  => 503,
+        }
+    }
+
+    /// Return the reason phrase sent with the status code.
+    pub fn reason(&self) -> &'static str {
+        match *self {
+            Status::Ok                  => "OK",
+            Status::Created             => "Created",
+            Status::NoContent           => "No Content",
+            Status::MovedPermanently    => "Moved Permanently",
+            Status::Found               => "Found",
+            Status::SeeOther            => "See Other",
+            Status::NotModified         => "Not Modified",
+            Status::BadRequest          => "Bad Request",
+            Status::Unauthorized        => "Unauthorized",
+            Status::Forbidden           => "Forbidden",
+            Status::NotFound            => "Not Found",
+            Status::MethodNotAllowed    => "Method Not Allowed",
+            Status::PayloadTooLarge     => "Payload Too Large",
+            Status::InternalServerError => "Internal Server Error",
+            Status::NotImplemented      => "Not Implemented",
+            Status::ServiceUnavailable  => "Service Unavailable",
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.reason())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Part {
+    Status,
+    Headers,
+    Body,
+}
+
+/// A response being written into the transmit buffer of a TCP socket.
+///
+/// The status line is sent along with the first header, or the first part of the body;
+/// the status cannot be changed after that. The response is not buffered elsewhere,
+/// so the transmit buffer of the socket has to be large enough to hold it whole:
+/// the writes that do not fit return `Err(Error::Exhausted)`, and the response
+/// is truncated.
+pub struct Response<'s, 'a: 's> {
+    socket:    &'s mut TcpSocket<'a>,
+    status:    Status,
+    part:      Part,
+    head_only: bool,
+    truncated: bool,
+}
+
+impl<'s, 'a> Response<'s, 'a> {
+    fn new(socket: &'s mut TcpSocket<'a>, head_only: bool) -> Response<'s, 'a> {
+        Response {
+            socket,
+            status:    Status::Ok,
+            part:      Part::Status,
+            head_only,
+            truncated: false,
+        }
+    }
+
+    /// Return the status of the response, which is `Status::Ok` unless it is changed.
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /// Set the status of the response.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the status line was already sent.
+    pub fn set_status(&mut self, status: Status) -> Result<()> {
+        if self.part != Part::Status { return Err(Error::Illegal) }
+        self.status = status;
+        Ok(())
+    }
+
+    /// Send a header, e.g. `Content-Type` or `Location`.
+    ///
+    /// This function returns `Err(Error::Illegal)` if a part of the body was already sent.
+    pub fn header(&mut self, name: &str, value: &str) -> Result<()> {
+        if self.part == Part::Body { return Err(Error::Illegal) }
+        self.start()?;
+        self.send(name.as_bytes())?;
+        self.send(b": ")?;
+        self.send(value.as_bytes())?;
+        self.send(b"\r\n")
+    }
+
+    /// Send a part of the body. The body of a response to a `HEAD` request is discarded.
+    pub fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.end_head()?;
+        if self.head_only { return Ok(()) }
+        self.send(data)
+    }
+
+    /// Query whether the response did not fit in the transmit buffer of the socket.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        if self.truncated { return Err(Error::Exhausted) }
+        if self.socket.send_slice(data)? < data.len() {
+            net_debug!("HTTP response truncated");
+            self.truncated = true;
+            return Err(Error::Exhausted)
+        }
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<()> {
+        if self.part != Part::Status { return Ok(()) }
+        self.part = Part::Headers;
+        let code = self.status.code();
+        let code = [b'0' + (code / 100) as u8, b'0' + (code / 10 % 10) as u8,
+                    b'0' + (code % 10) as u8];
+        self.send(b"HTTP/1.0 ")?;
+        self.send(&code)?;
+        self.send(b" ")?;
+        self.send(self.status.reason().as_bytes())?;
+        self.send(b"\r\nServer: smoltcp\r\nConnection: close\r\n")
+    }
+
+    fn end_head(&mut self) -> Result<()> {
+        if self.part == Part::Body { return Ok(()) }
+        self.start()?;
+        self.part = Part::Body;
+        self.send(b"\r\n")
+    }
+}
+
+impl<'s, 'a> fmt::Write for Response<'s, 'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+impl<'s, 'a> fmt::Debug for Response<'s, 'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Response")
+            .field("status", &self.status)
+            .field("part", &self.part)
+            .field("head_only", &self.head_only)
+            .field("truncated", &self.truncated)
+            .finish()
+    }
+}
+
+struct Connection {
+    handle:    SocketHandle,
+    request:   [u8; MAX_REQUEST_LEN],
+    length:    usize,
+    responded: bool,
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Connection")
+            .field("handle", &self.handle)
+            .field("length", &self.length)
+            .field("responded", &self.responded)
+            .finish()
+    }
+}
+
+/// An HTTP server.
+///
+/// The server listens on a port with each of the TCP sockets added with [add_socket],
+/// so that it serves as many connections at once as it has sockets. Once a request
+/// is received in full, [poll] calls back into the application with it to write the response,
+/// and closes the connection; persistent connections are not supported. Requests that
+/// cannot be parsed, or are longer than [MAX_REQUEST_LEN], are answered with an error
+/// status without calling back into the application.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let mut server = Server::new(80);
+/// server.add_socket(&mut sockets, rx_buffer, tx_buffer)?;
+/// loop {
+///     iface.poll(&mut sockets, timestamp)?;
+///     server.poll(&mut sockets, |request, response| {
+///         match (request.method, request.path) {
+///             (Method::Get, "/") => {
+///                 let _ = response.header("Content-Type", "text/html");
+///                 let _ = write!(response, "<p>Uptime: {} s</p>", uptime());
+///             }
+///             _ => { let _ = response.set_status(Status::NotFound); }
+///         }
+///     })?;
+/// }
+/// ```
+///
+/// [add_socket]: #method.add_socket
+/// [poll]: #method.poll
+/// [MAX_REQUEST_LEN]: constant.MAX_REQUEST_LEN.html
+#[derive(Debug)]
+pub struct Server {
+    port:        u16,
+    connections: [Option<Connection>; MAX_CONNECTION_COUNT],
+}
+
+impl Server {
+    /// Create an HTTP server listening on the given port, without any sockets.
+    pub fn new(port: u16) -> Server {
+        Server {
+            port,
+            connections: [None, None, None, None],
+        }
+    }
+
+    /// Return the port the server listens on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Add a TCP socket, created with the given buffers, to the socket set, and serve
+    /// a connection at a time with it. The transmit buffer should be large enough
+    /// to hold a whole response.
+    ///
+    /// This function returns `Err(Error::Exhausted)` if the server already has
+    /// [MAX_CONNECTION_COUNT](constant.MAX_CONNECTION_COUNT.html) sockets.
+    pub fn add_socket<'a, 'b>(&mut self, sockets: &mut SocketSet<'a, 'b, 'static>,
+                              rx_buffer: TcpSocketBuffer<'b>,
+                              tx_buffer: TcpSocketBuffer<'b>) -> Result<SocketHandle> {
+        let slot = self.connections.iter_mut().find(|slot| slot.is_none())
+                       .ok_or(Error::Exhausted)?;
+        let handle = sockets.add(TcpSocket::new(rx_buffer, tx_buffer));
+        *slot = Some(Connection {
+            handle,
+            request:   [0; MAX_REQUEST_LEN],
+            length:    0,
+            responded: false,
+        });
+        Ok(handle)
+    }
+
+    /// Listen with the sockets that are closed, receive the requests sent through
+    /// the others, and call `handler` with every complete request, to write the response.
+    /// Return the number of requests the handler was called with.
+    pub fn poll<H>(&mut self, sockets: &mut SocketSet, mut handler: H) -> Result<usize>
+            where H: FnMut(&Request, &mut Response) {
+        let mut handled = 0;
+        for connection in self.connections.iter_mut().filter_map(|slot| slot.as_mut()) {
+            let mut socket = sockets.get::<TcpSocket>(connection.handle);
+            if !socket.is_open() {
+                socket.listen(self.port)?;
+                connection.length = 0;
+                connection.responded = false;
+                continue
+            }
+            match socket.state() {
+                TcpState::Listen | TcpState::SynSent | TcpState::SynReceived => continue,
+                _ if connection.responded => continue,
+                _ => ()
+            }
+
+            if socket.can_recv() {
+                let length = connection.length;
+                connection.length += socket.recv_slice(&mut connection.request[length..])?;
+            }
+            let status = match Request::parse(&connection.request[..connection.length]) {
+                Ok((request, _)) => {
+                    net_debug!("HTTP {:?} {} from {}",
+                               request.method, request.path, socket.remote_endpoint());
+                    let mut response = Response::new(&mut socket, request.method == Method::Head);
+                    handler(&request, &mut response);
+                    let _ = response.end_head();
+                    handled += 1;
+                    None
+                }
+                Err(Error::Truncated) if connection.length < MAX_REQUEST_LEN => {
+                    if socket.may_recv() { continue }
+                    // The client closed the connection before sending the whole request.
+                    None
+                }
+                Err(Error::Truncated) | Err(Error::Exhausted) => Some(Status::PayloadTooLarge),
+                Err(Error::Unrecognized) => Some(Status::NotImplemented),
+                Err(_) => Some(Status::BadRequest)
+            };
+            if let Some(status) = status {
+                net_debug!("HTTP refusing request from {}: {}", socket.remote_endpoint(), status);
+                let mut response = Response::new(&mut socket, false);
+                let _ = response.set_status(status);
+                let _ = response.header("Content-Type", "text/plain");
+                let _ = response.write(status.reason().as_bytes());
+            }
+            socket.close();
+            connection.responded = true;
+        }
+        Ok(handled)
+    }
+}
+
+#[cfg(all(test, feature = "proto-ipv4"))]
+mod test {
+    use core::fmt::Write;
+    use std::collections::BTreeMap;
+    use std::vec::Vec;
+    use phy::Loopback;
+    use iface::{EthernetInterface, EthernetInterfaceBuilder, NeighborCache};
+    use wire::{EthernetAddress, IpAddress, IpCidr, Ipv4Address};
+    use super::*;
+
+    const LOCAL_ADDR: IpAddress = IpAddress::Ipv4(Ipv4Address([127, 0, 0, 1]));
+
+    fn setup() -> (EthernetInterface<'static, 'static, Loopback>,
+                   SocketSet<'static, 'static, 'static>, Server) {
+        let iface = EthernetInterfaceBuilder::new(Loopback::new())
+                .ethernet_addr(EthernetAddress::default())
+                .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+                .ip_addrs(vec![IpCidr::new(LOCAL_ADDR, 8)])
+                .finalize();
+        let mut sockets = SocketSet::new(vec![]);
+        let mut server = Server::new(80);
+        server.add_socket(&mut sockets, TcpSocketBuffer::new(vec![0; 256]),
+                          TcpSocketBuffer::new(vec![0; 256])).unwrap();
+        (iface, sockets, server)
+    }
+
+    fn handler(request: &Request, response: &mut Response) {
+        match (request.method, request.path) {
+            (Method::Get, "/") | (Method::Head, "/") => {
+                response.header("Content-Type", "text/plain").unwrap();
+                write!(response, "Hello, {}!", request.param("name").unwrap_or("world")).unwrap();
+            }
+            (Method::Post, "/config") => {
+                response.set_status(Status::SeeOther).unwrap();
+                response.header("Location", "/").unwrap();
+                assert_eq!(response.set_status(Status::Ok), Err(Error::Illegal));
+            }
+            _ => response.set_status(Status::NotFound).unwrap()
+        }
+    }
+
+    /// Send the request from a new client socket, and return the response.
+    fn exchange(iface: &mut EthernetInterface<'static, 'static, Loopback>,
+                sockets: &mut SocketSet<'static, 'static, 'static>, server: &mut Server,
+                request: &[u8]) -> Vec<u8> {
+        let client = sockets.add(TcpSocket::new(TcpSocketBuffer::new(vec![0; 256]),
+                                                TcpSocketBuffer::new(vec![0; 256])));
+        sockets.get::<TcpSocket>(client).connect((LOCAL_ADDR, 80), 49152).unwrap();
+        let mut response = Vec::new();
+        let mut sent = false;
+        for timestamp in 0..100 {
+            server.poll(sockets, handler).unwrap();
+            iface.poll(sockets, timestamp).unwrap();
+            let mut socket = sockets.get::<TcpSocket>(client);
+            if socket.can_send() && !sent {
+                assert_eq!(socket.send_slice(request), Ok(request.len()));
+                sent = true;
+            }
+            if socket.can_recv() {
+                socket.recv(|data| {
+                    response.extend_from_slice(data);
+                    (data.len(), ())
+                }).unwrap();
+            }
+            // Close the connection once the server has.
+            if sent && !socket.may_recv() { socket.close() }
+            if sent && !socket.is_open() { break }
+        }
+        sockets.remove(client);
+        response
+    }
+
+    #[test]
+    fn test_get() {
+        let (mut iface, mut sockets, mut server) = setup();
+        assert_eq!(exchange(&mut iface, &mut sockets, &mut server,
+                            b"GET /?name=smoltcp HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+                   &b"HTTP/1.0 200 OK\r\nServer: smoltcp\r\nConnection: close\r\n\
+                      Content-Type: text/plain\r\n\r\nHello, smoltcp!"[..]);
+    }
+
+    #[test]
+    fn test_head() {
+        let (mut iface, mut sockets, mut server) = setup();
+        assert_eq!(exchange(&mut iface, &mut sockets, &mut server, b"HEAD / HTTP/1.0\r\n\r\n"),
+                   &b"HTTP/1.0 200 OK\r\nServer: smoltcp\r\nConnection: close\r\n\
+                      Content-Type: text/plain\r\n\r\n"[..]);
+    }
+
+    #[test]
+    fn test_post_redirect() {
+        let (mut iface, mut sockets, mut server) = setup();
+        assert_eq!(exchange(&mut iface, &mut sockets, &mut server,
+                            b"POST /config HTTP/1.0\r\nContent-Length: 6\r\n\r\nssid=a"),
+                   &b"HTTP/1.0 303 See Other\r\nServer: smoltcp\r\nConnection: close\r\n\
+                      Location: /\r\n\r\n"[..]);
+    }
+
+    #[test]
+    fn test_bad_request() {
+        let (mut iface, mut sockets, mut server) = setup();
+        assert_eq!(exchange(&mut iface, &mut sockets, &mut server, b"GET /\r\n\r\n"),
+                   &b"HTTP/1.0 400 Bad Request\r\nServer: smoltcp\r\nConnection: close\r\n\
+                      Content-Type: text/plain\r\n\r\nBad Request"[..]);
+
+        // The socket serves another connection once the previous one is closed.
+        assert_eq!(exchange(&mut iface, &mut sockets, &mut server, b"GET /none HTTP/1.0\r\n\r\n"),
+                   &b"HTTP/1.0 404 Not Found\r\nServer: smoltcp\r\nConnection: close\r\n\r\n"[..]);
+    }
+}
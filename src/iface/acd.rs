@@ -0,0 +1,538 @@
+// Heads up! Before working on this file you should read RFC 5227,
+// which describes how IPv4 address conflicts are detected and handled.
+
+use managed::ManagedSlice;
+
+use {Error, Result};
+use wire::{EthernetAddress, Ipv4Address, Ipv4Cidr};
+
+// The protocol constants of RFC 5227 § 1.1, in milliseconds.
+const PROBE_WAIT:          u64 = 1_000;
+const PROBE_NUM:           u8  = 3;
+const PROBE_MIN:           u64 = 1_000;
+const PROBE_MAX:           u64 = 2_000;
+const ANNOUNCE_WAIT:       u64 = 2_000;
+const ANNOUNCE_NUM:        u8  = 2;
+const ANNOUNCE_INTERVAL:   u64 = 2_000;
+const MAX_CONFLICTS:       u8  = 10;
+const RATE_LIMIT_INTERVAL: u64 = 60_000;
+const DEFEND_INTERVAL:     u64 = 10_000;
+
+/// The state of an IPv4 address of an interface, with regard to
+/// Address Conflict Detection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcdState {
+    /// The address is being probed to verify that no other host uses it;
+    /// it cannot be used yet.
+    Probing,
+    /// The address is used, and is being announced so that the other hosts
+    /// update their ARP caches.
+    Announcing,
+    /// The address is used, and defended against the hosts that claim it.
+    Defending,
+    /// Another host uses the address, so it cannot be used.
+    Conflict,
+}
+
+/// An IPv4 address of an interface, with its Address Conflict Detection state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AcdAddress {
+    pub cidr:    Ipv4Cidr,
+    state:       AcdState,
+    /// The number of probes or announcements sent in the current state.
+    sent:        u8,
+    /// The moment at which the next probe or announcement has to be sent,
+    /// or `None` if it is scheduled when the interface is polled next.
+    send_at:     Option<u64>,
+    defended_at: Option<u64>,
+}
+
+impl AcdAddress {
+    fn new(cidr: Ipv4Cidr) -> AcdAddress {
+        AcdAddress {
+            cidr,
+            state:       AcdState::Probing,
+            sent:        0,
+            send_at:     None,
+            defended_at: None,
+        }
+    }
+
+    /// Return the state of the address.
+    pub fn state(&self) -> AcdState {
+        self.state
+    }
+
+    fn poll(&mut self, timestamp: u64, rate_limited: bool,
+            rand_state: &mut u32) -> Option<Action> {
+        let addr = self.cidr.address();
+        match self.state {
+            AcdState::Probing => {
+                let send_at = match self.send_at {
+                    Some(send_at) => send_at,
+                    None => {
+                        let mut delay = random_delay(rand_state, PROBE_WAIT);
+                        if rate_limited { delay += RATE_LIMIT_INTERVAL }
+                        self.send_at = Some(timestamp + delay);
+                        timestamp + delay
+                    }
+                };
+                if timestamp < send_at { return None }
+
+                if self.sent < PROBE_NUM {
+                    self.sent += 1;
+                    let delay = if self.sent < PROBE_NUM {
+                        PROBE_MIN + random_delay(rand_state, PROBE_MAX - PROBE_MIN)
+                    } else {
+                        ANNOUNCE_WAIT
+                    };
+                    self.send_at = Some(timestamp + delay);
+                    Some(Action::Probe(addr))
+                } else {
+                    // The address can be used as soon as the first announcement is sent.
+                    self.state   = AcdState::Announcing;
+                    self.sent    = 0;
+                    self.send_at = Some(timestamp);
+                    Some(Action::Configure(self.cidr))
+                }
+            }
+            AcdState::Announcing => {
+                if timestamp < self.send_at.unwrap_or(timestamp) { return None }
+
+                self.sent += 1;
+                if self.sent < ANNOUNCE_NUM {
+                    self.send_at = Some(timestamp + ANNOUNCE_INTERVAL);
+                } else {
+                    self.state   = AcdState::Defending;
+                    self.send_at = None;
+                }
+                Some(Action::Announce(addr))
+            }
+            AcdState::Defending | AcdState::Conflict => None
+        }
+    }
+}
+
+/// A conflict between an IPv4 address of an interface and another host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressConflict {
+    /// The address.
+    pub addr:          Ipv4Address,
+    /// The hardware address of the other host.
+    pub hardware_addr: EthernetAddress,
+    /// The moment at which the conflict was detected.
+    pub timestamp:     u64,
+    /// Whether the interface defended the address and kept using it. Otherwise,
+    /// the interface gave up the address, or never used it.
+    pub defended:      bool,
+}
+
+/// Something the interface has to do for Address Conflict Detection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Action {
+    /// Send an ARP probe for the address.
+    Probe(Ipv4Address),
+    /// Start using the address, which was verified to be unique.
+    Configure(Ipv4Cidr),
+    /// Send an ARP announcement for the address.
+    Announce(Ipv4Address),
+}
+
+/// The response of the interface to a conflicting ARP packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Reaction {
+    /// Do nothing, because the address is not used yet, or the packet does not conflict.
+    None,
+    /// Send an ARP announcement for the address.
+    Defend(Ipv4Address),
+    /// Stop using the address.
+    Abandon(Ipv4Cidr),
+}
+
+/// The state of IPv4 Address Conflict Detection on an interface.
+///
+/// Every unicast IPv4 address assigned to an interface with a conflict detector,
+/// either when it is created or later, is probed with ARP before it is used, and
+/// announced afterwards. An address that another host uses, or starts to use,
+/// is reported as an [AddressConflict](struct.AddressConflict.html).
+///
+/// # Examples
+///
+/// On systems with heap, this detector can be created with:
+///
+/// ```rust
+/// use smoltcp::iface::ConflictDetector;
+/// let mut conflict_detector = ConflictDetector::new(vec![]);
+/// ```
+///
+/// On systems without heap, use:
+///
+/// ```rust
+/// use smoltcp::iface::ConflictDetector;
+/// let mut conflict_detector_storage = [None; 2];
+/// let mut conflict_detector = ConflictDetector::new(&mut conflict_detector_storage[..]);
+/// ```
+#[derive(Debug)]
+pub struct ConflictDetector<'a> {
+    storage:    ManagedSlice<'a, Option<AcdAddress>>,
+    /// The number of conflicts detected while probing since an address was last
+    /// verified to be unique.
+    conflicts:  u8,
+    conflict:   Option<AddressConflict>,
+    rand_state: u32,
+}
+
+impl<'a> ConflictDetector<'a> {
+    /// Create a conflict detector. The backing storage is cleared upon creation.
+    pub fn new<T>(storage: T) -> ConflictDetector<'a>
+            where T: Into<ManagedSlice<'a, Option<AcdAddress>>> {
+        let mut storage = storage.into();
+        for slot in storage.iter_mut() {
+            *slot = None;
+        }
+
+        ConflictDetector {
+            storage,
+            conflicts:  0,
+            conflict:   None,
+            rand_state: 0x85ebca6b
+        }
+    }
+
+    /// Return an iterator over the addresses in the detector.
+    pub fn iter(&self) -> Iter {
+        Iter { slots: self.storage.iter() }
+    }
+
+    /// Return the state of the given address, if it is in the detector.
+    pub fn state(&self, addr: &Ipv4Address) -> Option<AcdState> {
+        self.iter()
+            .find(|acd_addr| acd_addr.cidr.address() == *addr)
+            .map(|acd_addr| acd_addr.state())
+    }
+
+    /// Seed the generator of the random delays of the probes, so that the hosts
+    /// that start at the same time do not probe at the same time.
+    pub(crate) fn seed(&mut self, seed: u32) {
+        if seed != 0 { self.rand_state = seed }
+    }
+
+    /// Return the last conflict that was detected, if it was not returned before.
+    pub(crate) fn take_conflict(&mut self) -> Option<AddressConflict> {
+        self.conflict.take()
+    }
+
+    /// Start probing an address, unless it is already being probed.
+    ///
+    /// An address that was found to be in conflict is probed anew; after many
+    /// conflicts, the probes only start after a while. When the detector is full,
+    /// another address that was found to be in conflict is forgotten.
+    pub(crate) fn probe(&mut self, cidr: Ipv4Cidr) -> Result<()> {
+        let index = self.storage.iter()
+            .position(|slot| slot.map_or(false, |acd_addr| acd_addr.cidr.address() ==
+                                                               cidr.address()))
+            .or_else(|| self.storage.iter().position(|slot| slot.is_none()))
+            .or_else(|| self.storage.iter().position(|slot| {
+                slot.map_or(false, |acd_addr| acd_addr.state == AcdState::Conflict)
+            }));
+        match index {
+            Some(index) => {
+                match self.storage[index] {
+                    Some(ref mut acd_addr) if acd_addr.state == AcdState::Probing =>
+                        acd_addr.cidr = cidr,
+                    ref mut slot => *slot = Some(AcdAddress::new(cidr))
+                }
+                Ok(())
+            }
+            None => match self.storage {
+                ManagedSlice::Borrowed(_) => Err(Error::Exhausted),
+                #[cfg(any(feature = "std", feature = "alloc"))]
+                ManagedSlice::Owned(ref mut addrs) => {
+                    addrs.push(Some(AcdAddress::new(cidr)));
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Announce every address that is used anew, e.g. because the hardware address
+    /// of the interface changed.
+    pub(crate) fn announce(&mut self) {
+        for acd_addr in self.storage.iter_mut().filter_map(|slot| slot.as_mut()) {
+            match acd_addr.state {
+                AcdState::Announcing | AcdState::Defending => {
+                    acd_addr.state   = AcdState::Announcing;
+                    acd_addr.sent    = 0;
+                    acd_addr.send_at = None;
+                }
+                AcdState::Probing | AcdState::Conflict => ()
+            }
+        }
+    }
+
+    /// Forget the addresses that are not being probed, and for which the given
+    /// predicate returns `false`, i.e. that are no longer assigned.
+    pub(crate) fn retain<F>(&mut self, mut f: F)
+            where F: FnMut(&Ipv4Address) -> bool {
+        for slot in self.storage.iter_mut() {
+            let assigned = match *slot {
+                Some(ref acd_addr) =>
+                    acd_addr.state == AcdState::Probing || f(&acd_addr.cidr.address()),
+                None => continue
+            };
+            if !assigned { *slot = None }
+        }
+    }
+
+    /// Forget an address, whatever its state.
+    pub(crate) fn forget(&mut self, addr: &Ipv4Address) {
+        for slot in self.storage.iter_mut() {
+            if slot.map_or(false, |acd_addr| acd_addr.cidr.address() == *addr) {
+                *slot = None
+            }
+        }
+    }
+
+    /// Return what has to be done for the addresses at the given moment, if anything.
+    pub(crate) fn poll(&mut self, timestamp: u64) -> Option<Action> {
+        let rate_limited = self.conflicts >= MAX_CONFLICTS;
+        let &mut ConflictDetector { ref mut storage, ref mut rand_state, .. } = self;
+        let action = storage.iter_mut()
+            .filter_map(|slot| slot.as_mut())
+            .filter_map(|acd_addr| acd_addr.poll(timestamp, rate_limited, rand_state))
+            .next();
+        if let Some(Action::Configure(cidr)) = action {
+            net_debug!("address {} is not in conflict", cidr);
+            self.conflicts = 0;
+        }
+        action
+    }
+
+    /// Return the moment at which the next probe or announcement has to be sent.
+    pub(crate) fn poll_at(&self, timestamp: u64) -> Option<u64> {
+        self.iter()
+            .filter_map(|acd_addr| match acd_addr.state {
+                AcdState::Probing | AcdState::Announcing =>
+                    Some(acd_addr.send_at.unwrap_or(timestamp)),
+                AcdState::Defending | AcdState::Conflict => None
+            })
+            .min()
+    }
+
+    /// Look for a conflict in an ARP packet that was sent by another host.
+    ///
+    /// While an address is probed, a packet from it, or a probe for it, means that
+    /// another host uses it or is about to. Afterwards, a packet from it means that
+    /// another host uses it too; the address is defended once, and given up if
+    /// the conflict persists. See RFC 5227 § 2.1.1 and § 2.4.
+    pub(crate) fn process(&mut self, hardware_addr: EthernetAddress,
+                          source_addr: Ipv4Address, target_addr: Ipv4Address,
+                          timestamp: u64) -> Reaction {
+        let acd_addr = self.storage.iter_mut()
+            .filter_map(|slot| slot.as_mut())
+            .find(|acd_addr| match acd_addr.state {
+                AcdState::Probing if source_addr.is_unspecified() =>
+                    acd_addr.cidr.address() == target_addr,
+                AcdState::Conflict => false,
+                _ => acd_addr.cidr.address() == source_addr
+            });
+        let acd_addr = match acd_addr {
+            Some(acd_addr) => acd_addr,
+            None => return Reaction::None
+        };
+        let addr = acd_addr.cidr.address();
+
+        let reaction = match acd_addr.state {
+            AcdState::Probing => {
+                acd_addr.state = AcdState::Conflict;
+                self.conflicts = self.conflicts.saturating_add(1);
+                Reaction::None
+            }
+            _ => match acd_addr.defended_at {
+                Some(defended_at) if timestamp < defended_at + DEFEND_INTERVAL => {
+                    acd_addr.state = AcdState::Conflict;
+                    Reaction::Abandon(acd_addr.cidr)
+                }
+                _ => {
+                    acd_addr.defended_at = Some(timestamp);
+                    Reaction::Defend(addr)
+                }
+            }
+        };
+
+        let defended = match reaction {
+            Reaction::Defend(_) => true,
+            _ => false
+        };
+        net_debug!("address {} is in conflict with {}{}", addr, hardware_addr,
+                   if defended { ", defending it" } else { "" });
+        self.conflict = Some(AddressConflict { addr, hardware_addr, timestamp, defended });
+        reaction
+    }
+}
+
+fn random_delay(rand_state: &mut u32, max_delay: u64) -> u64 {
+    // xorshift32; the delays only need to differ between hosts.
+    let mut x = *rand_state;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    *rand_state = x;
+    if max_delay == 0 { 0 } else { x as u64 % max_delay }
+}
+
+/// An iterator over the addresses in a [ConflictDetector](struct.ConflictDetector.html).
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    slots: ::core::slice::Iter<'a, Option<AcdAddress>>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a AcdAddress;
+
+    fn next(&mut self) -> Option<&'a AcdAddress> {
+        while let Some(slot) = self.slots.next() {
+            if let Some(ref acd_addr) = *slot {
+                return Some(acd_addr)
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ADDR: Ipv4Address = Ipv4Address([192, 168, 1, 1]);
+    const OTHER_ADDR: Ipv4Address = Ipv4Address([192, 168, 1, 2]);
+    const OTHER_HW_ADDR: EthernetAddress = EthernetAddress([0x52, 0x54, 0, 0, 0, 1]);
+
+    fn cidr() -> Ipv4Cidr {
+        Ipv4Cidr::new(ADDR, 24)
+    }
+
+    /// Poll the detector every 10 ms until it has something to do, and return
+    /// the moment and the action.
+    fn next_action(acd: &mut ConflictDetector, from: u64) -> (u64, Action) {
+        let mut timestamp = from;
+        loop {
+            if let Some(action) = acd.poll(timestamp) {
+                return (timestamp, action)
+            }
+            assert!(timestamp < from + RATE_LIMIT_INTERVAL + PROBE_WAIT, "no action");
+            timestamp += 10;
+        }
+    }
+
+    #[test]
+    fn test_probe_and_announce() {
+        let mut acd = ConflictDetector::new(vec![]);
+        acd.probe(cidr()).unwrap();
+        assert_eq!(acd.state(&ADDR), Some(AcdState::Probing));
+        assert_eq!(acd.poll_at(100), Some(100));
+
+        let (mut last, action) = next_action(&mut acd, 0);
+        assert!(last < PROBE_WAIT);
+        assert_eq!(action, Action::Probe(ADDR));
+        for _ in 1..PROBE_NUM {
+            let (timestamp, action) = next_action(&mut acd, last);
+            assert_eq!(action, Action::Probe(ADDR));
+            assert!(timestamp - last >= PROBE_MIN && timestamp - last <= PROBE_MAX);
+            last = timestamp;
+        }
+
+        let (timestamp, action) = next_action(&mut acd, last);
+        assert_eq!(timestamp, last + ANNOUNCE_WAIT);
+        assert_eq!(action, Action::Configure(cidr()));
+        assert_eq!(acd.poll(timestamp), Some(Action::Announce(ADDR)));
+        assert_eq!(acd.state(&ADDR), Some(AcdState::Announcing));
+        assert_eq!(acd.poll_at(timestamp), Some(timestamp + ANNOUNCE_INTERVAL));
+        assert_eq!(acd.poll(timestamp + ANNOUNCE_INTERVAL), Some(Action::Announce(ADDR)));
+        assert_eq!(acd.state(&ADDR), Some(AcdState::Defending));
+        assert_eq!(acd.poll_at(timestamp), None);
+
+        // The address is announced anew on request.
+        acd.announce();
+        assert_eq!(acd.poll(timestamp + 5_000), Some(Action::Announce(ADDR)));
+    }
+
+    #[test]
+    fn test_conflict_while_probing() {
+        let mut acd = ConflictDetector::new(vec![]);
+        acd.probe(cidr()).unwrap();
+        let (timestamp, _) = next_action(&mut acd, 0);
+
+        // Requests from other addresses do not conflict...
+        assert_eq!(acd.process(OTHER_HW_ADDR, OTHER_ADDR, ADDR, timestamp), Reaction::None);
+        assert_eq!(acd.take_conflict(), None);
+        // ... but probes for the address do.
+        assert_eq!(acd.process(OTHER_HW_ADDR, Ipv4Address::UNSPECIFIED, ADDR, timestamp),
+                   Reaction::None);
+        assert_eq!(acd.state(&ADDR), Some(AcdState::Conflict));
+        assert_eq!(acd.take_conflict(), Some(AddressConflict {
+            addr: ADDR, hardware_addr: OTHER_HW_ADDR, timestamp, defended: false
+        }));
+        assert_eq!(acd.take_conflict(), None);
+        assert_eq!(acd.poll(timestamp + 10_000), None);
+
+        // The address may be probed again.
+        acd.probe(cidr()).unwrap();
+        assert_eq!(acd.state(&ADDR), Some(AcdState::Probing));
+        assert_eq!(acd.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut acd = ConflictDetector::new(vec![]);
+        for _ in 0..MAX_CONFLICTS {
+            acd.probe(cidr()).unwrap();
+            acd.process(OTHER_HW_ADDR, ADDR, ADDR, 0);
+        }
+        acd.probe(cidr()).unwrap();
+        let (timestamp, action) = next_action(&mut acd, 0);
+        assert_eq!(action, Action::Probe(ADDR));
+        assert!(timestamp >= RATE_LIMIT_INTERVAL);
+    }
+
+    #[test]
+    fn test_defend() {
+        let mut acd = ConflictDetector::new(vec![]);
+        acd.probe(cidr()).unwrap();
+        let mut timestamp = 0;
+        while acd.state(&ADDR) != Some(AcdState::Defending) {
+            timestamp = next_action(&mut acd, timestamp).0;
+        }
+
+        // Probes for a used address are answered, not defended against.
+        assert_eq!(acd.process(OTHER_HW_ADDR, Ipv4Address::UNSPECIFIED, ADDR, timestamp),
+                   Reaction::None);
+        // The address is defended once...
+        assert_eq!(acd.process(OTHER_HW_ADDR, ADDR, ADDR, timestamp), Reaction::Defend(ADDR));
+        assert_eq!(acd.take_conflict().map(|conflict| conflict.defended), Some(true));
+        // ... and given up if the conflict persists.
+        assert_eq!(acd.process(OTHER_HW_ADDR, ADDR, OTHER_ADDR, timestamp + 5_000),
+                   Reaction::Abandon(cidr()));
+        assert_eq!(acd.take_conflict().map(|conflict| conflict.defended), Some(false));
+        assert_eq!(acd.state(&ADDR), Some(AcdState::Conflict));
+
+        // Addresses that are no longer assigned are forgotten.
+        acd.retain(|_| false);
+        assert_eq!(acd.state(&ADDR), None);
+    }
+
+    #[test]
+    fn test_exhausted() {
+        let mut storage = [None; 1];
+        let mut acd = ConflictDetector::new(&mut storage[..]);
+        acd.probe(cidr()).unwrap();
+        assert_eq!(acd.probe(Ipv4Cidr::new(OTHER_ADDR, 24)), Err(Error::Exhausted));
+
+        // Addresses in conflict make room for others.
+        acd.process(OTHER_HW_ADDR, ADDR, ADDR, 0);
+        assert_eq!(acd.probe(Ipv4Cidr::new(OTHER_ADDR, 24)), Ok(()));
+        assert_eq!(acd.state(&ADDR), None);
+        acd.forget(&OTHER_ADDR);
+        assert_eq!(acd.iter().count(), 0);
+    }
+}
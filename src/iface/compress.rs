@@ -0,0 +1,219 @@
+use core::mem;
+use managed::ManagedSlice;
+
+use {Error, Result};
+
+/// A hook an interface calls to compress the headers of the IP packets it sends, and
+/// to decompress those of the packets it receives, e.g. to implement Robust Header
+/// Compression (RFC 5795) on a cellular link.
+///
+/// Packets are transformed in place: the packet is in the first `packet_len` octets of
+/// `buffer`, and the methods return the length of the transformed packet, which may take
+/// up the whole buffer. An error drops the packet.
+pub trait HeaderCompressor {
+    /// Compress the headers of an IP packet the interface is about to send.
+    /// The packet may be left as it is, e.g. if it belongs to no compressed flow.
+    fn compress(&mut self, buffer: &mut [u8], packet_len: usize,
+                timestamp: u64) -> Result<usize>;
+
+    /// Decompress the headers of a packet received by the interface, restoring the IP
+    /// packet. A length of zero means that the packet was meant for the compressor
+    /// itself, e.g. ROHC feedback, and there is nothing left to process.
+    fn decompress(&mut self, buffer: &mut [u8], packet_len: usize,
+                  timestamp: u64) -> Result<usize>;
+}
+
+/// Counters of the packets transformed by a [HeaderCompression].
+///
+/// [HeaderCompression]: struct.HeaderCompression.html
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// Number of packets received and decompressed, including those meant
+    /// for the compressor.
+    pub in_packets:             u64,
+    /// Number of octets of the packets received, as they were compressed.
+    pub in_compressed_octets:   u64,
+    /// Number of octets of the packets received, once they were decompressed.
+    pub in_uncompressed_octets: u64,
+    /// Number of packets received that could not be decompressed.
+    pub in_errors:              u64,
+    /// Number of packets compressed and sent.
+    pub out_packets:            u64,
+    /// Number of octets of the packets sent, before they were compressed.
+    pub out_uncompressed_octets: u64,
+    /// Number of octets of the packets sent, as they were compressed.
+    pub out_compressed_octets:  u64,
+    /// Number of packets that could not be compressed.
+    pub out_errors:             u64,
+}
+
+/// The header compression of an interface.
+///
+/// It passes the packets exchanged with a device whose medium is IP through
+/// a [HeaderCompressor], using a buffer for each direction, since a packet cannot
+/// be transformed in the buffer of the device. Each buffer has to be large enough for
+/// the packets of the largest size the device transfers, and for any octets
+/// the compressor adds to them, such as those of ROHC initialization packets.
+///
+/// As the device cannot split compressed packets, TCP segmentation offload is
+/// disabled by the compression.
+///
+/// [HeaderCompressor]: trait.HeaderCompressor.html
+pub struct HeaderCompression<'a> {
+    compressor: &'a mut HeaderCompressor,
+    rx_buffer:  ManagedSlice<'a, u8>,
+    tx_buffer:  ManagedSlice<'a, u8>,
+    stats:      CompressionStats,
+}
+
+impl<'a> HeaderCompression<'a> {
+    /// Create a header compression with the given compressor and buffers.
+    pub fn new<T, U>(compressor: &'a mut HeaderCompressor,
+                     rx_buffer: T, tx_buffer: U) -> HeaderCompression<'a>
+            where T: Into<ManagedSlice<'a, u8>>, U: Into<ManagedSlice<'a, u8>> {
+        HeaderCompression {
+            compressor,
+            rx_buffer: rx_buffer.into(),
+            tx_buffer: tx_buffer.into(),
+            stats:     CompressionStats::default(),
+        }
+    }
+
+    /// Return the compressor.
+    pub fn compressor(&mut self) -> &mut HeaderCompressor {
+        &mut *self.compressor
+    }
+
+    /// Return the counters of the packets transformed so far.
+    pub fn stats(&self) -> CompressionStats {
+        self.stats
+    }
+
+    /// Take the receive buffer out, so that the packet decompressed into it can be
+    /// processed while the compression is used to send the response.
+    pub(crate) fn take_rx_buffer(&mut self) -> ManagedSlice<'a, u8> {
+        mem::replace(&mut self.rx_buffer, ManagedSlice::Borrowed(&mut []))
+    }
+
+    /// Put the receive buffer taken out by `take_rx_buffer` back.
+    pub(crate) fn restore_rx_buffer(&mut self, rx_buffer: ManagedSlice<'a, u8>) {
+        self.rx_buffer = rx_buffer
+    }
+
+    /// Decompress a received packet into the receive buffer, and return the IP packet,
+    /// if there is any.
+    pub(crate) fn decompress<'b>(&mut self, rx_buffer: &'b mut [u8], packet: &[u8],
+                                 timestamp: u64) -> Result<Option<&'b [u8]>> {
+        self.stats.in_packets += 1;
+        self.stats.in_compressed_octets += packet.len() as u64;
+        if packet.len() > rx_buffer.len() {
+            net_debug!("compressed packet of {} octets too large for the buffer", packet.len());
+            self.stats.in_errors += 1;
+            return Err(Error::Exhausted)
+        }
+
+        rx_buffer[..packet.len()].copy_from_slice(packet);
+        match self.compressor.decompress(rx_buffer, packet.len(), timestamp) {
+            Ok(0) => Ok(None),
+            Ok(packet_len) => {
+                self.stats.in_uncompressed_octets += packet_len as u64;
+                Ok(Some(&rx_buffer[..packet_len]))
+            }
+            Err(err) => {
+                self.stats.in_errors += 1;
+                Err(err)
+            }
+        }
+    }
+
+    /// Compress an IP packet of the given length, emitted into the transmit buffer
+    /// by `f`, and return the compressed packet.
+    pub(crate) fn compress<F>(&mut self, packet_len: usize, timestamp: u64,
+                              f: F) -> Result<&[u8]>
+        where F: FnOnce(&mut [u8])
+    {
+        if packet_len > self.tx_buffer.len() {
+            net_debug!("packet of {} octets too large for the compression buffer", packet_len);
+            self.stats.out_errors += 1;
+            return Err(Error::Exhausted)
+        }
+
+        f(&mut self.tx_buffer[..packet_len]);
+        match self.compressor.compress(&mut self.tx_buffer, packet_len, timestamp) {
+            Ok(compressed_len) => {
+                self.stats.out_packets += 1;
+                self.stats.out_uncompressed_octets += packet_len as u64;
+                self.stats.out_compressed_octets += compressed_len as u64;
+                Ok(&self.tx_buffer[..compressed_len])
+            }
+            Err(err) => {
+                self.stats.out_errors += 1;
+                Err(err)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Removes the second octet of a packet, which is assumed to be zero, and restores it.
+    /// A packet of one octet is feedback.
+    struct Squash;
+
+    impl HeaderCompressor for Squash {
+        fn compress(&mut self, buffer: &mut [u8], packet_len: usize,
+                    _timestamp: u64) -> Result<usize> {
+            if packet_len < 2 { return Err(Error::Truncated) }
+            for index in 1..packet_len - 1 {
+                buffer[index] = buffer[index + 1]
+            }
+            Ok(packet_len - 1)
+        }
+
+        fn decompress(&mut self, buffer: &mut [u8], packet_len: usize,
+                      _timestamp: u64) -> Result<usize> {
+            if packet_len == 1 { return Ok(0) }
+            for index in (1..packet_len).rev() {
+                buffer[index + 1] = buffer[index]
+            }
+            buffer[1] = 0;
+            Ok(packet_len + 1)
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut compressor = Squash;
+        let (mut rx_storage, mut tx_storage) = ([0; 8], [0; 8]);
+        let mut compression = HeaderCompression::new(&mut compressor, &mut rx_storage[..],
+                                                     &mut tx_storage[..]);
+
+        let compressed = compression.compress(4, 0, |buffer| {
+            buffer.copy_from_slice(&[0x45, 0x00, 0x01, 0x02])
+        }).unwrap().to_vec();
+        assert_eq!(compressed, [0x45, 0x01, 0x02]);
+
+        let mut rx_buffer = compression.take_rx_buffer();
+        assert_eq!(compression.decompress(&mut rx_buffer, &compressed, 0),
+                   Ok(Some(&[0x45, 0x00, 0x01, 0x02][..])));
+        assert_eq!(compression.decompress(&mut rx_buffer, &[0x00], 0), Ok(None));
+        assert_eq!(compression.decompress(&mut rx_buffer, &[0; 9], 0), Err(Error::Exhausted));
+        compression.restore_rx_buffer(rx_buffer);
+
+        assert_eq!(compression.compress(1, 0, |_| ()), Err(Error::Truncated));
+        assert_eq!(compression.compress(9, 0, |_| ()), Err(Error::Exhausted));
+
+        assert_eq!(compression.stats(), CompressionStats {
+            in_packets:              3,
+            in_compressed_octets:    13,
+            in_uncompressed_octets:  4,
+            in_errors:               1,
+            out_packets:             1,
+            out_uncompressed_octets: 4,
+            out_compressed_octets:   3,
+            out_errors:              2,
+        });
+    }
+}
@@ -0,0 +1,129 @@
+// Heads up! Before working on this file you should read the parts
+// of RFC 4862 that discuss Duplicate Address Detection (§ 5.4).
+
+use wire::Ipv6Cidr;
+
+/// The time between retransmissions of Neighbor Solicitations, in milliseconds.
+/// See RFC 4861 § 10.
+const RETRANS_TIMER: u64 = 1_000;
+
+/// The state of an IPv6 address of an interface, with regard to
+/// Duplicate Address Detection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressState {
+    /// The uniqueness of the address on the link is being verified;
+    /// it cannot be used yet.
+    Tentative,
+    /// The address is unique on the link, or was not verified, and can be used.
+    Valid,
+    /// Another node on the link uses the address, so it cannot be used.
+    Duplicate,
+}
+
+/// An IPv6 address that is undergoing Duplicate Address Detection, or that
+/// was found to be a duplicate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TentativeAddress {
+    pub cidr:    Ipv6Cidr,
+    dad_counter: u8,
+    probes_left: u8,
+    probe_at:    u64,
+    duplicate:   bool,
+}
+
+impl TentativeAddress {
+    /// Create a tentative address that is verified by sending the given number
+    /// of probes, starting at the given moment.
+    ///
+    /// The DAD counter is the number of times a duplicate was detected for
+    /// the prefix before; see [SlaacMode::address](enum.SlaacMode.html#method.address).
+    pub(crate) fn new(cidr: Ipv6Cidr, dad_counter: u8, probes: u8,
+                      timestamp: u64) -> TentativeAddress {
+        TentativeAddress {
+            cidr, dad_counter,
+            probes_left: probes,
+            probe_at:    timestamp,
+            duplicate:   false,
+        }
+    }
+
+    /// Return the state of the address; either `Tentative` or `Duplicate`.
+    pub fn state(&self) -> AddressState {
+        if self.duplicate {
+            AddressState::Duplicate
+        } else {
+            AddressState::Tentative
+        }
+    }
+
+    pub(crate) fn dad_counter(&self) -> u8 {
+        self.dad_counter
+    }
+
+    /// Mark the address as a duplicate, stopping the verification.
+    pub(crate) fn set_duplicate(&mut self) {
+        self.duplicate = true
+    }
+
+    /// Query whether a probe has to be sent at the given moment, and if it does,
+    /// schedule the next one.
+    pub(crate) fn probe(&mut self, timestamp: u64) -> bool {
+        if self.duplicate || self.probes_left == 0 || timestamp < self.probe_at {
+            return false
+        }
+        self.probes_left -= 1;
+        self.probe_at = timestamp + RETRANS_TIMER;
+        true
+    }
+
+    /// Query whether the address was verified to be unique at the given moment,
+    /// i.e. every probe was sent, and no duplicate was reported in time.
+    pub(crate) fn is_unique(&self, timestamp: u64) -> bool {
+        !self.duplicate && self.probes_left == 0 && timestamp >= self.probe_at
+    }
+
+    /// Return the moment at which the next probe has to be sent, or the verification
+    /// completes, unless the address is a duplicate.
+    pub(crate) fn poll_at(&self) -> Option<u64> {
+        if self.duplicate {
+            None
+        } else {
+            Some(self.probe_at)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wire::Ipv6Address;
+
+    fn cidr() -> Ipv6Cidr {
+        Ipv6Cidr::new(Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1), 64)
+    }
+
+    #[test]
+    fn test_probe() {
+        let mut addr = TentativeAddress::new(cidr(), 0, 2, 100);
+        assert_eq!(addr.state(), AddressState::Tentative);
+        assert!(!addr.probe(99));
+        assert!(addr.probe(100));
+        assert!(!addr.probe(100));
+        assert_eq!(addr.poll_at(), Some(1_100));
+        assert!(addr.probe(1_100));
+        assert!(!addr.is_unique(2_099));
+        assert!(!addr.probe(2_100));
+        assert!(addr.is_unique(2_100));
+    }
+
+    #[test]
+    fn test_duplicate() {
+        let mut addr = TentativeAddress::new(cidr(), 1, 1, 0);
+        assert!(addr.probe(0));
+        addr.set_duplicate();
+        assert_eq!(addr.state(), AddressState::Duplicate);
+        assert_eq!(addr.dad_counter(), 1);
+        assert_eq!(addr.poll_at(), None);
+        assert!(!addr.is_unique(1_000));
+    }
+}
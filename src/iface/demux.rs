@@ -0,0 +1,376 @@
+use managed::ManagedSlice;
+
+#[cfg(feature = "socket-raw")]
+use wire::{IpVersion, IpProtocol};
+#[cfg(all(feature = "socket-icmp", any(feature = "proto-ipv4", feature = "proto-ipv6")))]
+use wire::{IcmpRepr, UdpPacket};
+#[cfg(all(feature = "socket-icmp", feature = "proto-ipv4"))]
+use wire::Icmpv4Repr;
+#[cfg(all(feature = "socket-icmp", feature = "proto-ipv6"))]
+use wire::Icmpv6Repr;
+use socket::{Socket, SocketSet, SocketHandle};
+#[cfg(all(feature = "socket-icmp", any(feature = "proto-ipv4", feature = "proto-ipv6")))]
+use socket::IcmpEndpoint;
+use super::fnv::Fnv1a;
+
+/// A key of a socket demultiplexing table, describing the packets a socket may receive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DemuxKey {
+    /// UDP datagrams to a local port.
+    #[cfg(feature = "socket-udp")]
+    Udp(u16),
+    /// ICMP echo requests and replies with an identifier.
+    #[cfg(all(feature = "socket-icmp", any(feature = "proto-ipv4", feature = "proto-ipv6")))]
+    IcmpIdent(u16),
+    /// ICMP destination unreachable messages for UDP datagrams from a local port.
+    #[cfg(all(feature = "socket-icmp", any(feature = "proto-ipv4", feature = "proto-ipv6")))]
+    IcmpUdp(u16),
+    /// ICMP destination unreachable and time exceeded messages.
+    #[cfg(all(feature = "socket-icmp", any(feature = "proto-ipv4", feature = "proto-ipv6")))]
+    IcmpErrors,
+    /// IP packets of a version and a protocol.
+    #[cfg(feature = "socket-raw")]
+    Raw(IpVersion, IpProtocol),
+}
+
+impl DemuxKey {
+    /// Return the key of the packets the given socket may receive, if it is a socket
+    /// that receives them from the interface and is bound.
+    fn of(socket: &Socket) -> Option<DemuxKey> {
+        match *socket {
+            #[cfg(feature = "socket-raw")]
+            Socket::Raw(ref socket) =>
+                Some(DemuxKey::Raw(socket.ip_version(), socket.ip_protocol())),
+            #[cfg(all(feature = "socket-icmp",
+                      any(feature = "proto-ipv4", feature = "proto-ipv6")))]
+            Socket::Icmp(ref socket) => match socket.endpoint() {
+                IcmpEndpoint::Ident(ident) => Some(DemuxKey::IcmpIdent(ident)),
+                IcmpEndpoint::Udp(endpoint) => Some(DemuxKey::IcmpUdp(endpoint.port)),
+                IcmpEndpoint::Errors => Some(DemuxKey::IcmpErrors),
+                IcmpEndpoint::Unspecified => None
+            },
+            #[cfg(feature = "socket-udp")]
+            Socket::Udp(ref socket) =>
+                Some(DemuxKey::Udp(socket.endpoint().port)),
+            _ => None
+        }
+    }
+
+    /// Return the keys of the ICMP sockets that may receive the given message.
+    #[cfg(all(feature = "socket-icmp", any(feature = "proto-ipv4", feature = "proto-ipv6")))]
+    pub(crate) fn icmp(icmp_repr: &IcmpRepr) -> [Option<DemuxKey>; 2] {
+        // The sockets bound to the errors for a UDP port match them against the source port
+        // of the datagram returned in the message.
+        fn udp_port(data: &[u8]) -> Option<DemuxKey> {
+            UdpPacket::new_checked(data).ok().map(|packet| DemuxKey::IcmpUdp(packet.src_port()))
+        }
+
+        match *icmp_repr {
+            #[cfg(feature = "proto-ipv4")]
+            IcmpRepr::Ipv4(Icmpv4Repr::EchoRequest { ident, .. }) |
+            IcmpRepr::Ipv4(Icmpv4Repr::EchoReply { ident, .. }) =>
+                [Some(DemuxKey::IcmpIdent(ident)), None],
+            #[cfg(feature = "proto-ipv4")]
+            IcmpRepr::Ipv4(Icmpv4Repr::DstUnreachable { data, .. }) =>
+                [udp_port(data), Some(DemuxKey::IcmpErrors)],
+            #[cfg(feature = "proto-ipv4")]
+            IcmpRepr::Ipv4(Icmpv4Repr::TimeExceeded { .. }) =>
+                [Some(DemuxKey::IcmpErrors), None],
+            #[cfg(feature = "proto-ipv6")]
+            IcmpRepr::Ipv6(Icmpv6Repr::EchoRequest { ident, .. }) |
+            IcmpRepr::Ipv6(Icmpv6Repr::EchoReply { ident, .. }) =>
+                [Some(DemuxKey::IcmpIdent(ident)), None],
+            #[cfg(feature = "proto-ipv6")]
+            IcmpRepr::Ipv6(Icmpv6Repr::DstUnreachable { data, .. }) =>
+                [udp_port(data), Some(DemuxKey::IcmpErrors)],
+            #[cfg(feature = "proto-ipv6")]
+            IcmpRepr::Ipv6(Icmpv6Repr::TimeExceeded { .. }) =>
+                [Some(DemuxKey::IcmpErrors), None],
+            _ => [None, None]
+        }
+    }
+
+    /// Compute the 32-bit FNV-1a hash of the key.
+    fn hash(&self) -> usize {
+        let (tag, value): (u8, u16) = match *self {
+            #[cfg(feature = "socket-udp")]
+            DemuxKey::Udp(port) => (1, port),
+            #[cfg(all(feature = "socket-icmp",
+                      any(feature = "proto-ipv4", feature = "proto-ipv6")))]
+            DemuxKey::IcmpIdent(ident) => (2, ident),
+            #[cfg(all(feature = "socket-icmp",
+                      any(feature = "proto-ipv4", feature = "proto-ipv6")))]
+            DemuxKey::IcmpUdp(port) => (3, port),
+            #[cfg(all(feature = "socket-icmp",
+                      any(feature = "proto-ipv4", feature = "proto-ipv6")))]
+            DemuxKey::IcmpErrors => (4, 0),
+            #[cfg(feature = "socket-raw")]
+            DemuxKey::Raw(version, protocol) => {
+                let version = match version {
+                    #[cfg(feature = "proto-ipv4")]
+                    IpVersion::Ipv4 => 4,
+                    #[cfg(feature = "proto-ipv6")]
+                    IpVersion::Ipv6 => 6,
+                    _ => 0
+                };
+                (5, version << 8 | u8::from(protocol) as u16)
+            }
+        };
+
+        let mut hash = Fnv1a::new();
+        hash.write(&[tag, (value >> 8) as u8, value as u8]);
+        hash.finish() as usize
+    }
+}
+
+/// A socket demultiplexing table.
+///
+/// The table maps the ports, ICMP identifiers, and IP protocols of incoming packets
+/// to the UDP, ICMP and raw sockets that may receive them, through a hash table,
+/// so that a packet is not offered to every socket in the set in turn.
+///
+/// The interface rebuilds the table when it starts receiving packets, if sockets
+/// were added to or removed from the set, or the application accessed a socket that
+/// it may have bound to another endpoint since. Until then, or if the table has
+/// no room for every socket, packets are offered to every socket, as without a table.
+///
+/// If the storage is a `Vec`, the table grows as sockets are added to the set, and can be
+/// created empty.
+///
+/// # Examples
+///
+/// On systems with heap, this table can be created with:
+///
+/// ```rust
+/// use smoltcp::iface::DemuxTable;
+/// let mut demux_table = DemuxTable::new(vec![]);
+/// ```
+///
+/// On systems without heap, use:
+///
+/// ```rust
+/// use smoltcp::iface::DemuxTable;
+/// let mut demux_table_storage = [None; 32];
+/// let mut demux_table = DemuxTable::new(&mut demux_table_storage[..]);
+/// ```
+#[derive(Debug)]
+pub struct DemuxTable<'a> {
+    storage:    ManagedSlice<'a, Option<(DemuxKey, SocketHandle)>>,
+    len:        usize,
+    generation: Option<u32>,
+    complete:   bool,
+}
+
+impl<'a> DemuxTable<'a> {
+    /// Create a socket demultiplexing table. The backing storage is cleared upon creation.
+    pub fn new<T>(storage: T) -> DemuxTable<'a>
+            where T: Into<ManagedSlice<'a, Option<(DemuxKey, SocketHandle)>>> {
+        let mut storage = storage.into();
+        for slot in storage.iter_mut() {
+            *slot = None;
+        }
+
+        DemuxTable { storage, len: 0, generation: None, complete: false }
+    }
+
+    /// Return the number of sockets in the table.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return whether the table holds every socket of the set that receives packets
+    /// from the interface, bound as it is now.
+    pub fn is_current(&self, sockets: &SocketSet) -> bool {
+        self.complete && self.generation == Some(sockets.generation())
+    }
+
+    /// Return an iterator over the handles of the sockets with the given key,
+    /// in the order of the socket set.
+    pub fn handles(&self, key: DemuxKey) -> Handles {
+        let capacity = self.storage.len();
+        Handles {
+            slots:     &self.storage,
+            key:       key,
+            index:     if capacity == 0 { 0 } else { key.hash() % capacity },
+            remaining: capacity
+        }
+    }
+
+    /// Rebuild the table from the sockets of the set, if they changed since it was built.
+    pub(crate) fn refresh(&mut self, sockets: &SocketSet) {
+        if self.generation == Some(sockets.generation()) { return }
+        self.generation = Some(sockets.generation());
+
+        // Keep the load factor of the table below 3/4, so that lookups stay short;
+        // a fixed-size table needs at least one empty slot to end every lookup.
+        let count = sockets.iter().filter_map(DemuxKey::of).count();
+        match self.storage {
+            ManagedSlice::Borrowed(_) => (),
+            #[cfg(any(feature = "std", feature = "alloc"))]
+            ManagedSlice::Owned(ref mut storage) => {
+                if count * 4 > storage.len() * 3 {
+                    let capacity = ::core::cmp::max(count * 2, 16);
+                    storage.resize(capacity, None);
+                }
+            }
+        }
+
+        for slot in self.storage.iter_mut() {
+            *slot = None;
+        }
+        self.len = 0;
+        self.complete = count < self.storage.len();
+        if !self.complete {
+            if self.storage.len() > 0 {
+                net_debug!("socket demultiplexing table too small for {} sockets", count);
+            }
+            return
+        }
+
+        let capacity = self.storage.len();
+        for socket in sockets.iter() {
+            let key = match DemuxKey::of(socket) {
+                Some(key) => key,
+                None => continue
+            };
+            let mut index = key.hash() % capacity;
+            while self.storage[index].is_some() {
+                index = (index + 1) % capacity;
+            }
+            self.storage[index] = Some((key, socket.handle()));
+            self.len += 1;
+        }
+    }
+
+    /// Return the handles of the sockets that may receive a packet with any of the given
+    /// keys, or of every socket in the set if the table is not current.
+    pub(crate) fn candidates(&self, sockets: &SocketSet,
+                             keys: [Option<DemuxKey>; 2]) -> Candidates {
+        if self.is_current(sockets) {
+            let mut handles = keys.iter().filter_map(|key| key.map(|key| self.handles(key)));
+            Candidates::Table(handles.next(), handles.next())
+        } else {
+            Candidates::Scan { after: None, done: false }
+        }
+    }
+}
+
+/// An iterator over the handles of the sockets with a key in a
+/// [DemuxTable](struct.DemuxTable.html).
+#[derive(Debug, Clone)]
+pub struct Handles<'a> {
+    slots:     &'a [Option<(DemuxKey, SocketHandle)>],
+    key:       DemuxKey,
+    index:     usize,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Handles<'a> {
+    type Item = SocketHandle;
+
+    fn next(&mut self) -> Option<SocketHandle> {
+        while self.remaining > 0 {
+            self.remaining -= 1;
+            match self.slots[self.index] {
+                Some((key, handle)) => {
+                    self.index = (self.index + 1) % self.slots.len();
+                    if key == self.key { return Some(handle) }
+                }
+                None => self.remaining = 0
+            }
+        }
+        None
+    }
+}
+
+/// The handles of the sockets a packet is offered to.
+#[derive(Debug)]
+pub(crate) enum Candidates<'a> {
+    Table(Option<Handles<'a>>, Option<Handles<'a>>),
+    Scan { after: Option<SocketHandle>, done: bool }
+}
+
+impl<'a> Candidates<'a> {
+    pub(crate) fn next(&mut self, sockets: &SocketSet) -> Option<SocketHandle> {
+        match *self {
+            Candidates::Table(ref mut first, ref mut second) =>
+                first.as_mut().and_then(Iterator::next)
+                     .or_else(|| second.as_mut().and_then(Iterator::next)),
+            Candidates::Scan { ref mut after, ref mut done } => {
+                if *done { return None }
+                *after = sockets.next_handle(*after);
+                *done = after.is_none();
+                *after
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "socket-udp", feature = "socket-tcp"))]
+mod test {
+    use std::vec::Vec;
+    use socket::{TcpSocket, TcpSocketBuffer, UdpSocket, UdpSocketBuffer};
+    use super::*;
+
+    fn udp_socket() -> UdpSocket<'static, 'static> {
+        UdpSocket::new(UdpSocketBuffer::new(vec![]), UdpSocketBuffer::new(vec![]))
+    }
+
+    #[test]
+    fn test_refresh() {
+        let mut sockets = SocketSet::new(vec![]);
+        let mut table = DemuxTable::new(vec![]);
+        let mut handles = [None; 40];
+        for (index, handle) in handles.iter_mut().enumerate() {
+            let mut socket = udp_socket();
+            socket.bind(1000 + (index % 20) as u16).unwrap();
+            *handle = Some(sockets.add(socket));
+        }
+        sockets.add(TcpSocket::new(TcpSocketBuffer::new(vec![]), TcpSocketBuffer::new(vec![])));
+        assert!(!table.is_current(&sockets));
+
+        table.refresh(&sockets);
+        assert!(table.is_current(&sockets));
+        assert_eq!(table.len(), 40);
+        for port in 1000..1020 {
+            let index = (port - 1000) as usize;
+            assert_eq!(table.handles(DemuxKey::Udp(port)).collect::<Vec<_>>(),
+                       vec![handles[index].unwrap(), handles[index + 20].unwrap()]);
+        }
+        assert_eq!(table.handles(DemuxKey::Udp(1020)).next(), None);
+
+        // Binding a socket requires accessing it, which makes the table stale.
+        let handle = handles[0].unwrap();
+        sockets.get::<UdpSocket>(handle).close();
+        assert!(!table.is_current(&sockets));
+        table.refresh(&sockets);
+        assert_eq!(table.handles(DemuxKey::Udp(1000)).collect::<Vec<_>>(),
+                   vec![handles[20].unwrap()]);
+        assert_eq!(table.handles(DemuxKey::Udp(0)).collect::<Vec<_>>(), vec![handle]);
+
+        let mut candidates = table.candidates(&sockets, [Some(DemuxKey::Udp(1000)),
+                                                         Some(DemuxKey::Udp(1001))]);
+        assert_eq!(candidates.next(&sockets), handles[20]);
+        assert_eq!(candidates.next(&sockets), handles[1]);
+        assert_eq!(candidates.next(&sockets), handles[21]);
+        assert_eq!(candidates.next(&sockets), None);
+    }
+
+    #[test]
+    fn test_too_small() {
+        let mut sockets = SocketSet::new(vec![]);
+        let mut storage = [None; 2];
+        let mut table = DemuxTable::new(&mut storage[..]);
+        sockets.add(udp_socket());
+        let handle = sockets.add(udp_socket());
+        table.refresh(&sockets);
+        assert!(!table.is_current(&sockets));
+
+        // Every socket is a candidate.
+        let mut candidates = table.candidates(&sockets, [Some(DemuxKey::Udp(1000)), None]);
+        assert!(candidates.next(&sockets).is_some());
+        assert_eq!(candidates.next(&sockets), Some(handle));
+        assert_eq!(candidates.next(&sockets), None);
+        assert_eq!(candidates.next(&sockets), None);
+    }
+}
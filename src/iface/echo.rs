@@ -0,0 +1,131 @@
+use super::icmp_error::TokenBucket;
+
+/// A policy for replying to ICMPv4 and ICMPv6 echo requests.
+///
+/// The interface replies to echo requests on its own, whether or not an ICMP socket
+/// also receives them. Some deployments must not answer pings at all, and since
+/// an echo reply is as large as the request, replies to large requests sent from
+/// a spoofed address may be used to flood a third party; the policy allows turning
+/// the replies off for each family, ignoring requests with large payloads, and
+/// limiting the rate of replies with a token bucket, which holds up to `burst` replies
+/// and is refilled at `rate_limit` replies per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EchoPolicy {
+    /// Whether ICMPv4 echo requests are replied to.
+    pub ipv4:            bool,
+    /// Whether ICMPv6 echo requests are replied to.
+    pub ipv6:            bool,
+    /// The largest payload of an echo request that is replied to, in octets, or `None`
+    /// for no limit. Larger requests are ignored rather than replied to in part.
+    pub max_payload_len: Option<usize>,
+    /// The sustained rate of echo replies, in replies per second, or `None` for no limit.
+    pub rate_limit:      Option<u32>,
+    /// The number of echo replies that may be sent at once before the rate limit applies.
+    pub burst:           u32
+}
+
+impl Default for EchoPolicy {
+    /// Reply to every echo request, without limits.
+    fn default() -> EchoPolicy {
+        EchoPolicy {
+            ipv4:            true,
+            ipv6:            true,
+            max_payload_len: None,
+            rate_limit:      None,
+            burst:           10
+        }
+    }
+}
+
+/// The family of an echo request, as far as the policy is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EchoFamily {
+    #[cfg(feature = "proto-ipv4")]
+    Ipv4,
+    #[cfg(feature = "proto-ipv6")]
+    Ipv6
+}
+
+/// The state of the echo responder of an interface.
+#[derive(Debug)]
+pub(crate) struct EchoResponder {
+    policy: EchoPolicy,
+    bucket: TokenBucket
+}
+
+impl EchoResponder {
+    /// Create a responder with the given policy and a full bucket.
+    pub(crate) fn new(policy: EchoPolicy) -> EchoResponder {
+        EchoResponder {
+            policy: policy,
+            bucket: TokenBucket::new(policy.burst)
+        }
+    }
+
+    /// Return the policy.
+    pub(crate) fn policy(&self) -> EchoPolicy {
+        self.policy
+    }
+
+    /// Replace the policy, keeping at most a full bucket of the new size.
+    pub(crate) fn set_policy(&mut self, policy: EchoPolicy) {
+        self.policy = policy;
+        self.bucket.clamp(policy.burst);
+    }
+
+    /// Decide whether an echo request of the given family, with a payload of the given
+    /// length, may be replied to at the given moment, and if so, take a token from
+    /// the bucket.
+    pub(crate) fn allow(&mut self, family: EchoFamily, payload_len: usize,
+                        timestamp: u64) -> bool {
+        let enabled = match family {
+            #[cfg(feature = "proto-ipv4")]
+            EchoFamily::Ipv4 => self.policy.ipv4,
+            #[cfg(feature = "proto-ipv6")]
+            EchoFamily::Ipv6 => self.policy.ipv6
+        };
+        if !enabled { return false }
+        match self.policy.max_payload_len {
+            Some(max_payload_len) if payload_len > max_payload_len => return false,
+            _ => ()
+        }
+
+        self.bucket.take(self.policy.rate_limit, self.policy.burst, timestamp)
+    }
+}
+
+#[cfg(all(test, feature = "proto-ipv4"))]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "proto-ipv6")]
+    fn test_families() {
+        let mut responder = EchoResponder::new(EchoPolicy {
+            ipv6: false,
+            ..EchoPolicy::default()
+        });
+        assert!(responder.allow(EchoFamily::Ipv4, 56, 0));
+        assert!(!responder.allow(EchoFamily::Ipv6, 56, 0));
+        responder.set_policy(EchoPolicy { ipv4: false, ipv6: true, ..responder.policy() });
+        assert!(!responder.allow(EchoFamily::Ipv4, 56, 0));
+        assert!(responder.allow(EchoFamily::Ipv6, 56, 0));
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut responder = EchoResponder::new(EchoPolicy {
+            max_payload_len: Some(64),
+            rate_limit:      Some(1),
+            burst:           2,
+            ..EchoPolicy::default()
+        });
+        // Large requests are ignored, and do not take tokens.
+        assert!(!responder.allow(EchoFamily::Ipv4, 65, 1_000));
+        assert!(responder.allow(EchoFamily::Ipv4, 64, 1_000));
+        assert!(responder.allow(EchoFamily::Ipv4, 0, 1_000));
+        assert!(!responder.allow(EchoFamily::Ipv4, 0, 1_000));
+        assert!(!responder.allow(EchoFamily::Ipv4, 0, 1_999));
+        assert!(responder.allow(EchoFamily::Ipv4, 0, 2_000));
+    }
+}
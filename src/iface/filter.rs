@@ -0,0 +1,35 @@
+use wire::IpRepr;
+
+/// The decision of a [PacketFilter](trait.PacketFilter.html) on a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Let the packet through.
+    Accept,
+    /// Discard the packet silently.
+    Drop,
+}
+
+/// A hook an interface calls with the IP packets it receives and sends, e.g. to
+/// implement a simple firewall or a packet tap.
+///
+/// Every method accepts the packet by default, so an implementation only has to
+/// provide the ones it needs.
+pub trait PacketFilter {
+    /// Inspect an IP packet received by the interface, before it is processed or
+    /// forwarded; the payload is that of the packet, so the fragments of a datagram
+    /// are inspected one by one, before they are reassembled.
+    fn ingress(&mut self, _ip_repr: &IpRepr, _payload: &[u8], _timestamp: u64) -> Verdict {
+        Verdict::Accept
+    }
+
+    /// Inspect an IP packet the interface is about to send, before it is emitted.
+    /// Packets forwarded from other interfaces are not inspected.
+    fn egress(&mut self, _ip_repr: &IpRepr, _timestamp: u64) -> Verdict {
+        Verdict::Accept
+    }
+
+    /// Modify the payload of an IP packet the interface sends, after it was accepted
+    /// by [egress](#method.egress) and emitted, and before it is fragmented, if it has
+    /// to be. The checksums of the payload, if any, must be updated accordingly.
+    fn mangle(&mut self, _ip_repr: &IpRepr, _payload: &mut [u8], _timestamp: u64) {}
+}
@@ -0,0 +1,98 @@
+// Heads up! Before working on this file you should read RFC 6437,
+// which describes how flow labels should be assigned by a source.
+
+use wire::{IpAddress, IpProtocol};
+use super::fnv::Fnv1a;
+
+/// A policy for assigning IPv6 flow labels to outgoing packets.
+///
+/// Routers performing equal-cost multipath routing may use the flow label to keep
+/// all packets of a single flow (e.g. a TCP connection) on the same path, without
+/// having to look at transport layer headers. For this to work, the flow label
+/// has to be constant for every packet of a flow, and uniformly distributed across
+/// distinct flows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowLabelPolicy {
+    /// Do not label any packets; the flow label field is always zero.
+    Unlabeled,
+    /// Label packets with a stable hash of the source address, destination address,
+    /// protocol, source port and destination port of the flow.
+    ///
+    /// The secret should be chosen randomly at startup, so that the flow labels
+    /// cannot be predicted by an off-path observer.
+    Hashed {
+        secret: u32
+    }
+}
+
+impl Default for FlowLabelPolicy {
+    fn default() -> FlowLabelPolicy {
+        FlowLabelPolicy::Unlabeled
+    }
+}
+
+impl FlowLabelPolicy {
+    /// Return the flow label for the given 5-tuple.
+    ///
+    /// For protocols without ports, such as ICMPv6, both ports should be zero.
+    /// The returned value is always a valid 20-bit flow label, and is only zero
+    /// for the `Unlabeled` policy.
+    pub fn flow_label(&self, src_addr: &IpAddress, dst_addr: &IpAddress,
+                      protocol: IpProtocol, src_port: u16, dst_port: u16) -> u32 {
+        match *self {
+            FlowLabelPolicy::Unlabeled => 0,
+            FlowLabelPolicy::Hashed { secret } => {
+                let mut hash = Fnv1a::new();
+                hash.write(&[(secret >> 24) as u8, (secret >> 16) as u8,
+                             (secret >>  8) as u8,  secret        as u8]);
+                hash.write_addr(src_addr);
+                hash.write_addr(dst_addr);
+                hash.write(&[protocol.into()]);
+                hash.write(&[(src_port >> 8) as u8, src_port as u8,
+                             (dst_port >> 8) as u8, dst_port as u8]);
+
+                // Fold the 32-bit hash into 20 bits. A zero flow label means "unlabeled",
+                // so avoid emitting it for a labeled flow.
+                let label = (hash.finish() ^ (hash.finish() >> 20)) & 0xfffff;
+                if label == 0 { 1 } else { label }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wire::ip::test::{MOCK_IP_ADDR_1, MOCK_IP_ADDR_2};
+    use super::*;
+
+    const POLICY: FlowLabelPolicy = FlowLabelPolicy::Hashed { secret: 0x12345678 };
+
+    #[test]
+    fn test_unlabeled() {
+        assert_eq!(FlowLabelPolicy::Unlabeled.flow_label(&MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2,
+                                                         IpProtocol::Tcp, 49500, 80), 0);
+    }
+
+    #[test]
+    fn test_hashed_stable() {
+        let label = POLICY.flow_label(&MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2,
+                                      IpProtocol::Tcp, 49500, 80);
+        assert!(label != 0);
+        assert!(label <= 0xfffff);
+        assert_eq!(POLICY.flow_label(&MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2,
+                                     IpProtocol::Tcp, 49500, 80), label);
+    }
+
+    #[test]
+    fn test_hashed_distinct() {
+        let label = POLICY.flow_label(&MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2,
+                                      IpProtocol::Tcp, 49500, 80);
+        assert!(POLICY.flow_label(&MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2,
+                                  IpProtocol::Tcp, 49501, 80) != label);
+        assert!(POLICY.flow_label(&MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2,
+                                  IpProtocol::Udp, 49500, 80) != label);
+        assert!(FlowLabelPolicy::Hashed { secret: 1 }
+                    .flow_label(&MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2,
+                                IpProtocol::Tcp, 49500, 80) != label);
+    }
+}
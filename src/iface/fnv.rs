@@ -0,0 +1,84 @@
+#[cfg(any(feature = "proto-ipv6", feature = "socket-tcp"))]
+use wire::IpAddress;
+
+/// A 32-bit FNV-1a hasher. It is not cryptographically strong, but it is fast,
+/// has good dispersion, and does not need any storage.
+pub(crate) struct Fnv1a(u32);
+
+impl Fnv1a {
+    pub(crate) fn new() -> Fnv1a {
+        Fnv1a(0x811c9dc5)
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u32;
+            self.0 = self.0.wrapping_mul(0x01000193);
+        }
+    }
+
+    #[cfg(any(feature = "proto-ipv6", feature = "socket-tcp"))]
+    pub(crate) fn write_addr(&mut self, addr: &IpAddress) {
+        match addr {
+            #[cfg(feature = "proto-ipv4")]
+            &IpAddress::Ipv4(ref addr) => self.write(addr.as_bytes()),
+            #[cfg(feature = "proto-ipv6")]
+            &IpAddress::Ipv6(ref addr) => self.write(addr.as_bytes()),
+            _ => ()
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u32 {
+        self.0
+    }
+}
+
+/// A 64-bit FNV-1a hasher. Like the 32-bit one, it is not cryptographically strong,
+/// but keeping the secret unknown is enough to make the interface identifiers
+/// hashed with it opaque to an observer.
+#[cfg(feature = "proto-ipv6")]
+pub(crate) struct Fnv1a64(u64);
+
+#[cfg(feature = "proto-ipv6")]
+impl Fnv1a64 {
+    pub(crate) fn new() -> Fnv1a64 {
+        Fnv1a64(0xcbf29ce484222325)
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fnv1a() {
+        let mut hash = Fnv1a::new();
+        assert_eq!(hash.finish(), 0x811c9dc5);
+        hash.write(b"a");
+        assert_eq!(hash.finish(), 0xe40c292c);
+        hash.write(b"bc");
+        assert_eq!(hash.finish(), 0x1a47e90b);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv6")]
+    fn test_fnv1a64() {
+        let mut hash = Fnv1a64::new();
+        assert_eq!(hash.finish(), 0xcbf29ce484222325);
+        hash.write(b"a");
+        assert_eq!(hash.finish(), 0xaf63dc4c8601ec8c);
+        hash.write(b"bc");
+        assert_eq!(hash.finish(), 0xe71fa2190541574b);
+    }
+}
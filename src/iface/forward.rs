@@ -0,0 +1,252 @@
+// Heads up! Before working on this file you should read the parts
+// of RFC 1812 that discuss forwarding (§ 5.2 and § 5.3), and of RFC 8200
+// that discuss the hop limit (§ 3).
+
+use managed::ManagedSlice;
+
+use {Error, Result};
+use wire::IpAddress;
+#[cfg(feature = "proto-ipv4")]
+use wire::Ipv4Packet;
+#[cfg(feature = "proto-ipv6")]
+use wire::Ipv6Packet;
+
+/// The time after which a packet that could not be sent, e.g. because the link layer
+/// address of the next hop is not known, is discarded, in milliseconds.
+const FORWARD_TIMEOUT: u64 = 3_000;
+
+/// The reasons an IP packet cannot be forwarded, which are reported to its source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ForwardError {
+    /// The hop limit of the packet has run out.
+    HopLimit,
+    /// No interface has a route to the destination.
+    NoRoute,
+    /// The link layer address of the next hop could not be resolved.
+    NoNeighbor,
+    /// The packet exceeds the MTU of the outgoing interface, and cannot be fragmented.
+    TooBig(usize),
+    /// The source address of the packet is link-local, but the destination is not
+    /// on the link; only reported for IPv6.
+    BeyondScope,
+}
+
+/// A buffer holding an IP packet that is waiting to be forwarded.
+///
+/// The size of the storage limits the packets that can be forwarded.
+#[derive(Debug)]
+pub struct ForwardBuffer<'a> {
+    storage:   ManagedSlice<'a, u8>,
+    len:       usize,
+    routable:  bool,
+    queued_at: u64,
+}
+
+impl<'a> ForwardBuffer<'a> {
+    /// Create a forwarding buffer using the given storage.
+    pub fn new<T>(storage: T) -> ForwardBuffer<'a>
+            where T: Into<ManagedSlice<'a, u8>> {
+        ForwardBuffer { storage: storage.into(), len: 0, routable: false, queued_at: 0 }
+    }
+
+    /// Return the largest packet that can be held in the buffer.
+    pub fn capacity(&self) -> usize {
+        self.storage.len()
+    }
+}
+
+/// A queue of IP packets received on an interface, that are waiting to be forwarded
+/// through another interface.
+///
+/// # Examples
+///
+/// On systems with heap, this queue can be created with:
+///
+/// ```rust
+/// use smoltcp::iface::{ForwardBuffer, ForwardQueue};
+/// let mut queue = ForwardQueue::new(vec![ForwardBuffer::new(vec![0; 1500]),
+///                                        ForwardBuffer::new(vec![0; 1500])]);
+/// ```
+///
+/// On systems without heap, use:
+///
+/// ```rust
+/// use smoltcp::iface::{ForwardBuffer, ForwardQueue};
+/// let mut storage = [0; 1500];
+/// let mut buffers = [ForwardBuffer::new(&mut storage[..])];
+/// let mut queue = ForwardQueue::new(&mut buffers[..]);
+/// ```
+#[derive(Debug)]
+pub struct ForwardQueue<'a, 'b: 'a> {
+    buffers: ManagedSlice<'a, ForwardBuffer<'b>>,
+}
+
+impl<'a, 'b: 'a> ForwardQueue<'a, 'b> {
+    /// Create a forwarding queue using the given buffers. The buffers are cleared
+    /// upon creation.
+    pub fn new<T>(buffers: T) -> ForwardQueue<'a, 'b>
+            where T: Into<ManagedSlice<'a, ForwardBuffer<'b>>> {
+        let mut buffers = buffers.into();
+        for buffer in buffers.iter_mut() {
+            buffer.len = 0;
+        }
+
+        ForwardQueue { buffers }
+    }
+
+    /// Return the number of packets waiting to be forwarded.
+    pub fn len(&self) -> usize {
+        self.buffers.iter().filter(|buffer| buffer.len != 0).count()
+    }
+
+    /// Query whether there are no packets waiting to be forwarded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return the number of buffers, i.e. the bound of the indices of the packets.
+    pub(crate) fn slots(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Copy a packet into a free buffer, and return the copy.
+    ///
+    /// Returns `Err(Error::Exhausted)` if there is no free buffer the packet fits into.
+    pub(crate) fn enqueue(&mut self, packet: &[u8], timestamp: u64) -> Result<&mut [u8]> {
+        let buffer = self.buffers.iter_mut()
+            .find(|buffer| buffer.len == 0 && buffer.capacity() >= packet.len())
+            .ok_or(Error::Exhausted)?;
+        buffer.len = packet.len();
+        buffer.routable = false;
+        buffer.queued_at = timestamp;
+
+        let copy = &mut buffer.storage[..packet.len()];
+        copy.copy_from_slice(packet);
+        Ok(copy)
+    }
+
+    /// Return the packet with the given index, if any.
+    pub(crate) fn get(&self, index: usize) -> Option<&[u8]> {
+        let buffer = &self.buffers[index];
+        if buffer.len == 0 {
+            None
+        } else {
+            Some(&buffer.storage[..buffer.len])
+        }
+    }
+
+    /// Record that an interface has a route for the packet with the given index,
+    /// even though it could not be sent yet.
+    pub(crate) fn mark_routable(&mut self, index: usize) {
+        self.buffers[index].routable = true
+    }
+
+    /// Remove the packet with the given index.
+    pub(crate) fn remove(&mut self, index: usize) {
+        self.buffers[index].len = 0
+    }
+
+    /// Return the index of a packet that was not forwarded, and the reason, if any.
+    /// Packets that no interface had a route for since the last call to
+    /// [clear_routable](#method.clear_routable) are not forwarded, nor are those
+    /// that could not be sent in time.
+    pub(crate) fn expired(&self, timestamp: u64) -> Option<(usize, ForwardError)> {
+        self.buffers.iter().enumerate()
+            .filter(|&(_, buffer)| buffer.len != 0)
+            .filter_map(|(index, buffer)| {
+                if !buffer.routable {
+                    Some((index, ForwardError::NoRoute))
+                } else if timestamp >= buffer.queued_at + FORWARD_TIMEOUT {
+                    Some((index, ForwardError::NoNeighbor))
+                } else {
+                    None
+                }
+            })
+            .next()
+    }
+
+    /// Forget which packets the interfaces had a route for, so that they have
+    /// to be routed again before the next call to [expired](#method.expired).
+    pub(crate) fn clear_routable(&mut self) {
+        for buffer in self.buffers.iter_mut() {
+            buffer.routable = false;
+        }
+    }
+}
+
+/// Return the source address of a valid IP packet.
+pub(crate) fn src_addr(packet: &[u8]) -> IpAddress {
+    match packet[0] >> 4 {
+        #[cfg(feature = "proto-ipv4")]
+        4 => Ipv4Packet::new(packet).src_addr().into(),
+        #[cfg(feature = "proto-ipv6")]
+        6 => Ipv6Packet::new(packet).src_addr().into(),
+        _ => unreachable!()
+    }
+}
+
+/// Return the destination address of a valid IP packet.
+pub(crate) fn dst_addr(packet: &[u8]) -> IpAddress {
+    match packet[0] >> 4 {
+        #[cfg(feature = "proto-ipv4")]
+        4 => Ipv4Packet::new(packet).dst_addr().into(),
+        #[cfg(feature = "proto-ipv6")]
+        6 => Ipv6Packet::new(packet).dst_addr().into(),
+        _ => unreachable!()
+    }
+}
+
+/// Query whether a valid IP packet that exceeds the MTU can be fragmented when it is
+/// forwarded, i.e. whether it is an IPv4 datagram that is not a fragment already,
+/// and is not marked otherwise. Routers never fragment IPv6 packets.
+pub(crate) fn can_fragment(packet: &[u8]) -> bool {
+    match packet[0] >> 4 {
+        #[cfg(feature = "proto-ipv4")]
+        4 => {
+            let packet = Ipv4Packet::new(packet);
+            !packet.dont_frag() && !packet.more_frags() && packet.frag_offset() == 0
+        }
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_enqueue() {
+        let mut queue = ForwardQueue::new(vec![ForwardBuffer::new(vec![0; 4]),
+                                               ForwardBuffer::new(vec![0; 8])]);
+        assert_eq!(queue.enqueue(&[1; 6], 0).map(|copy| copy.len()), Ok(6));
+        assert_eq!(queue.enqueue(&[2; 6], 0), Err(Error::Exhausted));
+        assert_eq!(queue.enqueue(&[3; 2], 0).map(|copy| copy.len()), Ok(2));
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.get(0), Some(&[3; 2][..]));
+        assert_eq!(queue.get(1), Some(&[1; 6][..]));
+
+        queue.remove(1);
+        assert_eq!(queue.get(1), None);
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn test_expired() {
+        let mut queue = ForwardQueue::new(vec![ForwardBuffer::new(vec![0; 8]),
+                                               ForwardBuffer::new(vec![0; 8])]);
+        queue.enqueue(&[1; 8], 0).unwrap();
+        queue.enqueue(&[2; 8], 0).unwrap();
+        queue.mark_routable(1);
+        assert_eq!(queue.expired(100), Some((0, ForwardError::NoRoute)));
+        queue.remove(0);
+        assert_eq!(queue.expired(100), None);
+
+        // A packet has to be routed again before every check.
+        queue.clear_routable();
+        assert_eq!(queue.expired(100), Some((1, ForwardError::NoRoute)));
+        queue.mark_routable(1);
+        assert_eq!(queue.expired(FORWARD_TIMEOUT), Some((1, ForwardError::NoNeighbor)));
+        queue.remove(1);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,959 @@
+// Heads up! Before working on this file you should read the parts
+// of RFC 791 that discuss fragmentation and reassembly (§ 2.3 and § 3.2),
+// of RFC 1122 that discuss reassembly (§ 3.3.2), and of RFC 8200
+// that discuss the IPv6 Fragment header (§ 4.5) and RFC 5722.
+
+#[cfg(feature = "std")]
+use std::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::Vec;
+use core::{cmp, mem};
+use managed::ManagedSlice;
+
+use {Error, Result};
+use phy::ChecksumCapabilities;
+use storage::{Assembler, PoolQuota, ASSEMBLER_MAX_HOLES};
+use wire::{EthernetAddress, EthernetProtocol, IpAddress, IpProtocol, IpRepr};
+#[cfg(feature = "proto-ipv4")]
+use wire::{Ipv4Address, Ipv4Packet, Ipv4Repr};
+#[cfg(feature = "proto-ipv6")]
+use wire::{Ipv6Address, Ipv6Packet, Ipv6Repr, Ipv6FragmentHeader, Ipv6FragmentRepr};
+
+/// The time after which an incomplete datagram is discarded, in milliseconds.
+/// See RFC 1122 § 3.3.2, which recommends a fixed value between 60 and 120 seconds,
+/// and RFC 8200 § 4.5, which requires 60 seconds.
+const REASSEMBLY_TIMEOUT: u64 = 60_000;
+
+/// The fields that identify the fragments of a datagram. See RFC 791 § 3.2
+/// and RFC 8200 § 4.5.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    #[cfg(feature = "proto-ipv4")]
+    Ipv4 {
+        src_addr: Ipv4Address,
+        dst_addr: Ipv4Address,
+        protocol: IpProtocol,
+        ident:    u16,
+    },
+    #[cfg(feature = "proto-ipv6")]
+    Ipv6 {
+        src_addr: Ipv6Address,
+        dst_addr: Ipv6Address,
+        ident:    u32,
+    },
+}
+
+impl Key {
+    fn src_addr(&self) -> IpAddress {
+        match self {
+            #[cfg(feature = "proto-ipv4")]
+            &Key::Ipv4 { src_addr, .. } => src_addr.into(),
+            #[cfg(feature = "proto-ipv6")]
+            &Key::Ipv6 { src_addr, .. } => src_addr.into(),
+        }
+    }
+
+    /// Return the largest payload of a datagram. The total length of an IPv4 datagram
+    /// is limited to 65535 octets, and its header is at least 20 octets long;
+    /// the payload length of an IPv6 packet is limited to 65535 octets.
+    fn max_payload_len(&self) -> usize {
+        match self {
+            #[cfg(feature = "proto-ipv4")]
+            &Key::Ipv4 { .. } => 65535 - 20,
+            #[cfg(feature = "proto-ipv6")]
+            &Key::Ipv6 { .. } => 65535,
+        }
+    }
+
+    /// Query whether fragments that overlap discard the datagram, as RFC 5722
+    /// requires for IPv6, instead of overwriting each other.
+    fn rejects_overlap(&self) -> bool {
+        match self {
+            #[cfg(feature = "proto-ipv4")]
+            &Key::Ipv4 { .. } => false,
+            #[cfg(feature = "proto-ipv6")]
+            &Key::Ipv6 { .. } => true,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Reassembly {
+    key:        Key,
+    header:     IpRepr,
+    assembler:  Assembler,
+    total_len:  Option<usize>,
+    expires_at: u64,
+}
+
+impl Reassembly {
+    fn is_complete(&self) -> bool {
+        match self.total_len {
+            Some(total_len) => self.assembler.iter_data().next() == Some((0, total_len)),
+            None => false
+        }
+    }
+}
+
+/// Take a chunk of a packet pool through the given quota, if there is one and
+/// the storage has no chunk yet.
+fn acquire<'a>(quota: Option<&'a PoolQuota<'a>>, storage: &mut ManagedSlice<'a, u8>) ->
+              Result<()> {
+    match quota {
+        Some(quota) if storage.len() == 0 => {
+            let chunk = quota.allocate().ok_or(Error::Exhausted)?;
+            *storage = ManagedSlice::Borrowed(chunk);
+            Ok(())
+        }
+        _ => Ok(())
+    }
+}
+
+/// Return the chunk of a packet pool the storage holds, if any, through the given quota.
+fn release<'a>(quota: Option<&'a PoolQuota<'a>>, storage: &mut ManagedSlice<'a, u8>) {
+    if let Some(quota) = quota {
+        if let ManagedSlice::Borrowed(chunk) = mem::replace(storage,
+                                                            ManagedSlice::Borrowed(&mut [])) {
+            if chunk.len() > 0 { quota.release(chunk) }
+        }
+    }
+}
+
+/// A buffer for reassembling a single IPv4 datagram or IPv6 packet.
+///
+/// The size of the storage limits the payload of the datagrams that can be reassembled.
+#[derive(Debug)]
+pub struct FragmentBuffer<'a> {
+    storage:    ManagedSlice<'a, u8>,
+    reassembly: Option<Reassembly>,
+    /// The size heap-allocated storage may grow to.
+    #[cfg(any(feature = "std", feature = "alloc"))]
+    max_size:   usize,
+    /// The quota the storage is drawn from, if it is pooled.
+    quota:      Option<&'a PoolQuota<'a>>,
+}
+
+impl<'a> FragmentBuffer<'a> {
+    /// Create a reassembly buffer using the given storage.
+    pub fn new<T>(storage: T) -> FragmentBuffer<'a>
+            where T: Into<ManagedSlice<'a, u8>> {
+        FragmentBuffer {
+            storage:    storage.into(),
+            reassembly: None,
+            #[cfg(any(feature = "std", feature = "alloc"))]
+            max_size:   0,
+            quota:      None,
+        }
+    }
+
+    /// Create a reassembly buffer with heap-allocated storage, which grows
+    /// as fragments arrive to hold datagram payloads of up to `max_size` octets.
+    #[cfg(any(feature = "std", feature = "alloc"))]
+    pub fn growable(max_size: usize) -> FragmentBuffer<'a> {
+        let mut buffer = FragmentBuffer::new(ManagedSlice::Owned(Vec::new()));
+        buffer.max_size = max_size;
+        buffer
+    }
+
+    /// Create a reassembly buffer whose storage is a chunk of a [packet pool], taken
+    /// through the given quota while a datagram is reassembled in the buffer.
+    ///
+    /// [packet pool]: ../storage/struct.PacketPool.html
+    pub fn pooled(quota: &'a PoolQuota<'a>) -> FragmentBuffer<'a> {
+        let mut buffer = FragmentBuffer::new(&mut [][..]);
+        buffer.quota = Some(quota);
+        buffer
+    }
+
+    /// Return the largest payload of a datagram that can be reassembled in the buffer.
+    pub fn capacity(&self) -> usize {
+        match self.quota {
+            Some(quota) => quota.chunk_size(),
+            None => cmp::max(self.storage.len(), self.max_size())
+        }
+    }
+
+    /// Discard the datagram in reassembly, if any, and return the storage
+    /// to its pool, if it is pooled.
+    fn clear(&mut self) {
+        self.reassembly = None;
+        release(self.quota, &mut self.storage)
+    }
+
+    #[cfg(any(feature = "std", feature = "alloc"))]
+    fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    #[cfg(not(any(feature = "std", feature = "alloc")))]
+    fn max_size(&self) -> usize {
+        0
+    }
+
+    /// Make room for a fragment ending at `end` in growable storage.
+    #[cfg(any(feature = "std", feature = "alloc"))]
+    fn grow(&mut self, end: usize) {
+        if end > self.storage.len() && end <= self.max_size {
+            if let ManagedSlice::Owned(ref mut storage) = self.storage {
+                storage.resize(end, 0)
+            }
+        }
+    }
+
+    #[cfg(not(any(feature = "std", feature = "alloc")))]
+    fn grow(&mut self, _end: usize) {}
+}
+
+impl<'a> Drop for FragmentBuffer<'a> {
+    fn drop(&mut self) {
+        release(self.quota, &mut self.storage)
+    }
+}
+
+/// A set of buffers for reassembling fragmented IPv4 datagrams and IPv6 packets.
+///
+/// The number of datagrams a single source can have in reassembly at once is limited,
+/// so that it cannot occupy every buffer; by default, the limit is half of the buffers,
+/// rounded up. Datagrams that are not complete after 60 seconds are discarded.
+///
+/// # Examples
+///
+/// On systems with heap, this set can be created with:
+///
+/// ```rust
+/// use smoltcp::iface::{FragmentBuffer, FragmentSet};
+/// let mut fragments = FragmentSet::new(vec![FragmentBuffer::new(vec![0; 1500]),
+///                                           FragmentBuffer::new(vec![0; 1500])]);
+/// ```
+///
+/// On systems without heap, use:
+///
+/// ```rust
+/// use smoltcp::iface::{FragmentBuffer, FragmentSet};
+/// let mut storage = [0; 1500];
+/// let mut buffers = [FragmentBuffer::new(&mut storage[..])];
+/// let mut fragments = FragmentSet::new(&mut buffers[..]);
+/// ```
+#[derive(Debug)]
+pub struct FragmentSet<'a, 'b: 'a> {
+    buffers:      ManagedSlice<'a, FragmentBuffer<'b>>,
+    source_limit: usize,
+}
+
+impl<'a, 'b: 'a> FragmentSet<'a, 'b> {
+    /// Create a reassembly set using the given buffers. The buffers are cleared
+    /// upon creation.
+    pub fn new<T>(buffers: T) -> FragmentSet<'a, 'b>
+            where T: Into<ManagedSlice<'a, FragmentBuffer<'b>>> {
+        let mut buffers = buffers.into();
+        for buffer in buffers.iter_mut() {
+            buffer.clear();
+        }
+
+        let source_limit = (buffers.len() + 1) / 2;
+        FragmentSet { buffers, source_limit }
+    }
+
+    /// Return the number of datagrams a single source can have in reassembly at once.
+    pub fn source_limit(&self) -> usize {
+        self.source_limit
+    }
+
+    /// Set the number of datagrams a single source can have in reassembly at once.
+    pub fn set_source_limit(&mut self, limit: usize) {
+        self.source_limit = limit
+    }
+
+    /// Add an IPv4 fragment to the datagram it belongs to, and return whether
+    /// that datagram is now complete. See [add](#method.add).
+    #[cfg(feature = "proto-ipv4")]
+    pub(crate) fn add_ipv4<T>(&mut self, packet: &Ipv4Packet<&T>, ipv4_repr: &Ipv4Repr,
+                              timestamp: u64) -> Result<bool>
+            where T: AsRef<[u8]> + ?Sized {
+        let key = Key::Ipv4 {
+            src_addr: ipv4_repr.src_addr,
+            dst_addr: ipv4_repr.dst_addr,
+            protocol: ipv4_repr.protocol,
+            ident:    packet.ident(),
+        };
+        self.add(key, IpRepr::Ipv4(*ipv4_repr), packet.frag_offset() as usize,
+                 packet.more_frags(), packet.payload(), timestamp)
+    }
+
+    /// Add the data following an IPv6 Fragment header to the packet it belongs to,
+    /// and return whether that packet is now complete. See [add](#method.add).
+    #[cfg(feature = "proto-ipv6")]
+    pub(crate) fn add_ipv6(&mut self, ipv6_repr: &Ipv6Repr, frag_repr: &Ipv6FragmentRepr,
+                           data: &[u8], timestamp: u64) -> Result<bool> {
+        let key = Key::Ipv6 {
+            src_addr: ipv6_repr.src_addr,
+            dst_addr: ipv6_repr.dst_addr,
+            ident:    frag_repr.ident,
+        };
+        // The reassembled packet has no Fragment header; the header following it
+        // in the first fragment follows the IPv6 header instead.
+        let header = Ipv6Repr {
+            next_header: frag_repr.next_header,
+            payload_len: data.len(),
+            ..*ipv6_repr
+        };
+        self.add(key, IpRepr::Ipv6(header), frag_repr.frag_offset as usize,
+                 frag_repr.more_frags, data, timestamp)
+    }
+
+    /// Add a fragment to the datagram it belongs to, and return whether that datagram
+    /// is now complete.
+    ///
+    /// Returns `Err(Error::Fragmented)` if there are no reassembly buffers at all,
+    /// and `Err(Error::Exhausted)` if there is no buffer available for the datagram,
+    /// or it is too large for the buffer. A datagram whose fragments contradict
+    /// each other, or overlap in case of IPv6, is discarded, and `Err(Error::Malformed)`
+    /// is returned.
+    fn add(&mut self, key: Key, header: IpRepr, offset: usize, more_frags: bool,
+           data: &[u8], timestamp: u64) -> Result<bool> {
+        if self.buffers.is_empty() { return Err(Error::Fragmented) }
+
+        let end = offset + data.len();
+
+        // Every fragment but the last one carries a multiple of eight octets.
+        if more_frags && data.len() % 8 != 0 { return Err(Error::Malformed) }
+        if end > key.max_payload_len() { return Err(Error::Malformed) }
+
+        let index = match self.find(&key) {
+            Some(index) => index,
+            None => self.allocate(key, &header, timestamp)?
+        };
+
+        let result = {
+            let buffer = &mut self.buffers[index];
+            buffer.grow(end);
+            let storage = &mut buffer.storage;
+            let reassembly = buffer.reassembly.as_mut().unwrap();
+            if reassembly.is_complete() {
+                // A duplicate of a fragment of a datagram that is waiting to be processed.
+                return Ok(false)
+            }
+
+            let last_end = reassembly.assembler.iter_data().last().map_or(0, |(_, end)| end);
+            if end > storage.len() {
+                net_debug!("datagram from {} does not fit into a reassembly buffer",
+                           key.src_addr());
+                Err(Error::Exhausted)
+            } else if reassembly.total_len.map_or(false, |total_len|
+                          end > total_len || (!more_frags && end != total_len)) ||
+                      (!more_frags && last_end > end) {
+                net_debug!("fragments of a datagram from {} overlap inconsistently",
+                           key.src_addr());
+                Err(Error::Malformed)
+            } else if key.rejects_overlap() &&
+                      reassembly.assembler.iter_data().any(|(start, stop)|
+                          start < end && offset < stop) {
+                net_debug!("fragments of a packet from {} overlap", key.src_addr());
+                Err(Error::Malformed)
+            } else if reassembly.assembler.add(offset, data.len()).is_err() {
+                net_debug!("too many holes in a datagram from {}", key.src_addr());
+                Err(Error::Exhausted)
+            } else {
+                storage[offset..end].copy_from_slice(data);
+                // The header of the reassembled datagram is that of its first fragment.
+                if offset == 0 {
+                    reassembly.header = header;
+                }
+                if !more_frags {
+                    reassembly.total_len = Some(end);
+                }
+                Ok(reassembly.is_complete())
+            }
+        };
+
+        if result.is_err() {
+            self.buffers[index].clear();
+        }
+        result
+    }
+
+    fn find(&self, key: &Key) -> Option<usize> {
+        self.buffers.iter().position(|buffer|
+            buffer.reassembly.as_ref().map_or(false, |reassembly| reassembly.key == *key))
+    }
+
+    fn allocate(&mut self, key: Key, header: &IpRepr, timestamp: u64) -> Result<usize> {
+        let from_source = self.buffers.iter()
+            .filter_map(|buffer| buffer.reassembly.as_ref())
+            .filter(|reassembly| reassembly.key.src_addr() == key.src_addr())
+            .count();
+        if from_source >= self.source_limit {
+            net_debug!("too many datagrams from {} in reassembly", key.src_addr());
+            return Err(Error::Exhausted)
+        }
+
+        let index = self.buffers.iter().position(|buffer| buffer.reassembly.is_none())
+                                .ok_or(Error::Exhausted)?;
+        let buffer = &mut self.buffers[index];
+        acquire(buffer.quota, &mut buffer.storage).map_err(|err| {
+            net_debug!("no memory in the packet pool to reassemble a datagram from {}",
+                       key.src_addr());
+            err
+        })?;
+        buffer.reassembly = Some(Reassembly {
+            key,
+            header:     header.clone(),
+            assembler:  Assembler::with_holes(buffer.capacity(), ASSEMBLER_MAX_HOLES),
+            total_len:  None,
+            expires_at: timestamp + REASSEMBLY_TIMEOUT,
+        });
+        Ok(index)
+    }
+
+    /// Return the header and the payload of a datagram that was reassembled completely,
+    /// if any.
+    pub(crate) fn reassembled(&self) -> Option<(IpRepr, &[u8])> {
+        self.buffers.iter().filter_map(|buffer| {
+            let reassembly = buffer.reassembly.as_ref()?;
+            let total_len = reassembly.total_len?;
+            if !reassembly.is_complete() { return None }
+            let mut header = reassembly.header.clone();
+            header.set_payload_len(total_len);
+            Some((header, &buffer.storage[..total_len]))
+        }).next()
+    }
+
+    /// Free the buffer of the datagram returned by [reassembled](#method.reassembled).
+    pub(crate) fn remove_reassembled(&mut self) {
+        for buffer in self.buffers.iter_mut() {
+            let complete = buffer.reassembly.as_ref()
+                                 .map_or(false, |reassembly| reassembly.is_complete());
+            if complete {
+                buffer.clear();
+                return
+            }
+        }
+    }
+
+    /// Discard the datagrams whose reassembly timed out.
+    pub(crate) fn expire(&mut self, timestamp: u64) {
+        for buffer in self.buffers.iter_mut() {
+            let expired = buffer.reassembly.as_ref()
+                                .map_or(false, |reassembly| timestamp >= reassembly.expires_at);
+            if expired {
+                net_debug!("reassembly of a datagram from {} timed out",
+                           buffer.reassembly.as_ref().unwrap().key.src_addr());
+                buffer.clear();
+            }
+        }
+    }
+
+    /// Return the moment at which the reassembly of a datagram times out, if any.
+    pub(crate) fn poll_at(&self) -> Option<u64> {
+        self.buffers.iter()
+            .filter_map(|buffer| buffer.reassembly.as_ref())
+            .map(|reassembly| reassembly.expires_at)
+            .min()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Fragmentation {
+    header:            IpRepr,
+    dst_hardware_addr: EthernetAddress,
+    ident:             u32,
+    fragment_len:      usize,
+    offset:            usize,
+}
+
+impl Fragmentation {
+    /// Return the length of the headers of every fragment; an IPv6 fragment
+    /// carries a Fragment header after the IPv6 header.
+    fn header_len(&self) -> usize {
+        match self.header {
+            #[cfg(feature = "proto-ipv6")]
+            IpRepr::Ipv6(_) => self.header.buffer_len() + 8,
+            _ => self.header.buffer_len()
+        }
+    }
+}
+
+/// A buffer for fragmenting outgoing IPv4 datagrams and IPv6 packets that exceed the MTU.
+///
+/// The size of the storage limits the payload of the datagrams that can be fragmented;
+/// larger datagrams are dropped.
+#[derive(Debug)]
+pub struct Fragmenter<'a> {
+    storage:       ManagedSlice<'a, u8>,
+    next_ident:    u32,
+    fragmentation: Option<Fragmentation>,
+    /// The quota the storage is drawn from, if it is pooled.
+    quota:         Option<&'a PoolQuota<'a>>,
+}
+
+impl<'a> Fragmenter<'a> {
+    /// Create a fragmentation buffer using the given storage.
+    pub fn new<T>(storage: T) -> Fragmenter<'a>
+            where T: Into<ManagedSlice<'a, u8>> {
+        Fragmenter { storage: storage.into(), next_ident: 0, fragmentation: None, quota: None }
+    }
+
+    /// Create a fragmentation buffer whose storage is a chunk of a [packet pool], taken
+    /// through the given quota while the fragments of a datagram are sent.
+    ///
+    /// [packet pool]: ../storage/struct.PacketPool.html
+    pub fn pooled(quota: &'a PoolQuota<'a>) -> Fragmenter<'a> {
+        let mut fragmenter = Fragmenter::new(&mut [][..]);
+        fragmenter.quota = Some(quota);
+        fragmenter
+    }
+
+    /// Return the largest payload of a datagram that can be fragmented.
+    pub fn capacity(&self) -> usize {
+        match self.quota {
+            Some(quota) => quota.chunk_size(),
+            None => self.storage.len()
+        }
+    }
+
+    /// Query whether the fragments of a datagram are still being sent.
+    pub(crate) fn is_pending(&self) -> bool {
+        self.fragmentation.is_some()
+    }
+
+    /// Start fragmenting a datagram with the given header into packets no larger than
+    /// the given MTU, and return the buffer its payload has to be emitted into,
+    /// or `None` if the datagram does not fit into the buffer.
+    pub(crate) fn start(&mut self, header: IpRepr, dst_hardware_addr: EthernetAddress,
+                        mtu: usize) -> Option<&mut [u8]> {
+        let payload_len = header.payload_len();
+        if self.is_pending() || payload_len > self.capacity() { return None }
+
+        let mut fragmentation = Fragmentation {
+            header, dst_hardware_addr,
+            ident:        self.next_ident,
+            fragment_len: 0,
+            offset:       0
+        };
+        // Every fragment but the last one carries a multiple of eight octets.
+        fragmentation.fragment_len = mtu.saturating_sub(fragmentation.header_len()) & !7;
+        if fragmentation.fragment_len == 0 { return None }
+        if acquire(self.quota, &mut self.storage).is_err() {
+            net_debug!("no memory in the packet pool to fragment a datagram");
+            return None
+        }
+
+        self.next_ident = self.next_ident.wrapping_add(1);
+        self.fragmentation = Some(fragmentation);
+        Some(&mut self.storage[..payload_len])
+    }
+
+    /// Return the hardware address the next fragment is sent to, the protocol
+    /// of its frame, and its total length, if there is one.
+    pub(crate) fn next_fragment(&self) -> Option<(EthernetAddress, EthernetProtocol, usize)> {
+        self.fragmentation.as_ref().map(|fragmentation| {
+            let remaining = fragmentation.header.payload_len() - fragmentation.offset;
+            let payload_len = if remaining > fragmentation.fragment_len {
+                fragmentation.fragment_len
+            } else {
+                remaining
+            };
+            let protocol = match fragmentation.header {
+                #[cfg(feature = "proto-ipv6")]
+                IpRepr::Ipv6(_) => EthernetProtocol::Ipv6,
+                _ => EthernetProtocol::Ipv4
+            };
+            (fragmentation.dst_hardware_addr, protocol,
+             fragmentation.header_len() + payload_len)
+        })
+    }
+
+    /// Emit the next fragment into the given buffer, whose length has to be the one
+    /// returned by [next_fragment](#method.next_fragment).
+    pub(crate) fn emit_fragment(&mut self, buffer: &mut [u8],
+                                _checksum_caps: &ChecksumCapabilities) {
+        let mut fragmentation = self.fragmentation.take().expect("no pending fragments");
+        let start = fragmentation.offset;
+        let end = start + buffer.len() - fragmentation.header_len();
+        let more_frags = end < fragmentation.header.payload_len();
+
+        match fragmentation.header {
+            #[cfg(feature = "proto-ipv4")]
+            IpRepr::Ipv4(ipv4_repr) => {
+                let header = Ipv4Repr { payload_len: end - start, ..ipv4_repr };
+                let mut packet = Ipv4Packet::new(buffer);
+                header.emit(&mut packet, &ChecksumCapabilities::ignored());
+                packet.set_ident(fragmentation.ident as u16);
+                packet.clear_flags();
+                packet.set_more_frags(more_frags);
+                packet.set_frag_offset(start as u16);
+                if _checksum_caps.ipv4.tx() {
+                    packet.fill_checksum();
+                } else {
+                    packet.set_checksum(0);
+                }
+                packet.payload_mut().copy_from_slice(&self.storage[start..end]);
+            }
+            #[cfg(feature = "proto-ipv6")]
+            IpRepr::Ipv6(ipv6_repr) => {
+                let frag_repr = Ipv6FragmentRepr {
+                    next_header: ipv6_repr.next_header,
+                    frag_offset: start as u16,
+                    more_frags:  more_frags,
+                    ident:       fragmentation.ident
+                };
+                let header = Ipv6Repr {
+                    next_header: IpProtocol::Ipv6Frag,
+                    payload_len: frag_repr.buffer_len() + end - start,
+                    ..ipv6_repr
+                };
+                let mut packet = Ipv6Packet::new(buffer);
+                header.emit(&mut packet);
+                let payload = packet.payload_mut();
+                let (frag_header, data) = payload.split_at_mut(frag_repr.buffer_len());
+                frag_repr.emit(&mut Ipv6FragmentHeader::new(frag_header));
+                data.copy_from_slice(&self.storage[start..end]);
+            }
+            _ => unreachable!()
+        }
+
+        if more_frags {
+            fragmentation.offset = end;
+            self.fragmentation = Some(fragmentation);
+        } else {
+            release(self.quota, &mut self.storage)
+        }
+    }
+}
+
+impl<'a> Drop for Fragmenter<'a> {
+    fn drop(&mut self) {
+        release(self.quota, &mut self.storage)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use super::*;
+
+    #[cfg(feature = "proto-ipv4")]
+    const SRC_ADDR_1: Ipv4Address = Ipv4Address([10, 0, 0, 1]);
+    #[cfg(feature = "proto-ipv4")]
+    const SRC_ADDR_2: Ipv4Address = Ipv4Address([10, 0, 0, 2]);
+    #[cfg(feature = "proto-ipv4")]
+    const DST_ADDR:   Ipv4Address = Ipv4Address([10, 0, 0, 3]);
+
+    #[cfg(feature = "proto-ipv4")]
+    fn header(src_addr: Ipv4Address, payload_len: usize) -> Ipv4Repr {
+        Ipv4Repr {
+            src_addr, payload_len,
+            dst_addr:  DST_ADDR,
+            protocol:  IpProtocol::Udp,
+            hop_limit: 64,
+            ecn:       0,
+            dscp:      0
+        }
+    }
+
+    #[cfg(feature = "proto-ipv4")]
+    fn fragment(src_addr: Ipv4Address, ident: u16, offset: usize,
+                more_frags: bool, data: &[u8]) -> Vec<u8> {
+        let repr = header(src_addr, data.len());
+        let mut bytes = vec![0; repr.buffer_len() + data.len()];
+        {
+            let mut packet = Ipv4Packet::new(&mut bytes);
+            repr.emit(&mut packet, &ChecksumCapabilities::default());
+            packet.set_ident(ident);
+            packet.clear_flags();
+            packet.set_more_frags(more_frags);
+            packet.set_frag_offset(offset as u16);
+            packet.payload_mut().copy_from_slice(data);
+        }
+        bytes
+    }
+
+    #[cfg(feature = "proto-ipv4")]
+    fn add(set: &mut FragmentSet, bytes: &[u8], timestamp: u64) -> Result<bool> {
+        let packet = Ipv4Packet::new(bytes);
+        let repr = Ipv4Repr::parse_fragment(&packet, &ChecksumCapabilities::ignored()).unwrap();
+        set.add_ipv4(&packet, &repr, timestamp)
+    }
+
+    fn buffers(count: usize, size: usize) -> Vec<FragmentBuffer<'static>> {
+        (0..count).map(|_| FragmentBuffer::new(vec![0; size])).collect()
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_reassemble() {
+        let mut set = FragmentSet::new(buffers(1, 64));
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 1, 16, false, &[3; 4]), 0), Ok(false));
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 1, 0, true, &[1; 8]), 0), Ok(false));
+        assert_eq!(set.reassembled(), None);
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 1, 8, true, &[2; 8]), 0), Ok(true));
+
+        let mut payload = vec![1; 8];
+        payload.extend(&[2; 8]);
+        payload.extend(&[3; 4]);
+        assert_eq!(set.reassembled(), Some((IpRepr::Ipv4(header(SRC_ADDR_1, 20)), &payload[..])));
+        set.remove_reassembled();
+        assert_eq!(set.reassembled(), None);
+        assert_eq!(set.poll_at(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_reassemble_growable() {
+        let mut set = FragmentSet::new(vec![FragmentBuffer::growable(24)]);
+        assert_eq!(set.buffers[0].capacity(), 24);
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 1, 8, true, &[2; 8]), 0), Ok(false));
+        assert_eq!(set.buffers[0].storage.len(), 16);
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 1, 0, true, &[1; 8]), 0), Ok(false));
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 1, 16, false, &[3; 4]), 0), Ok(true));
+
+        let mut payload = vec![1; 8];
+        payload.extend(&[2; 8]);
+        payload.extend(&[3; 4]);
+        assert_eq!(set.reassembled(), Some((IpRepr::Ipv4(header(SRC_ADDR_1, 20)), &payload[..])));
+        set.remove_reassembled();
+
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 2, 24, false, &[1; 4]), 0),
+                   Err(Error::Exhausted));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_reassemble_pooled() {
+        use storage::PacketPool;
+
+        let mut storage = [0; 64];
+        let mut chunks = [None];
+        let pool = PacketPool::new(&mut storage[..], 64, &mut chunks[..]);
+        let quota = pool.quota(1);
+        let mut buffers = [FragmentBuffer::pooled(&quota), FragmentBuffer::pooled(&quota)];
+        {
+            let mut set = FragmentSet::new(&mut buffers[..]);
+            assert_eq!(set.buffers[0].capacity(), 64);
+            assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 1, 0, true, &[1; 8]), 0),
+                       Ok(false));
+            assert_eq!(pool.available(), 0);
+            assert_eq!(add(&mut set, &fragment(SRC_ADDR_2, 1, 0, true, &[1; 8]), 0),
+                       Err(Error::Exhausted));
+            assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 1, 8, false, &[2; 4]), 0),
+                       Ok(true));
+            set.remove_reassembled();
+            assert_eq!(pool.available(), 1);
+
+            assert_eq!(add(&mut set, &fragment(SRC_ADDR_2, 1, 0, true, &[1; 8]), 0),
+                       Ok(false));
+            assert_eq!(quota.used(), 1);
+            set.expire(60_000);
+            assert_eq!(quota.used(), 0);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_no_buffers() {
+        let mut set = FragmentSet::new(vec![]);
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 1, 0, true, &[1; 8]), 0),
+                   Err(Error::Fragmented));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_malformed() {
+        let mut set = FragmentSet::new(buffers(1, 64));
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 1, 0, true, &[1; 7]), 0),
+                   Err(Error::Malformed));
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 1, 8, false, &[1; 8]), 0), Ok(false));
+        // A second last fragment that ends elsewhere discards the datagram.
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 1, 0, false, &[1; 8]), 0),
+                   Err(Error::Malformed));
+        assert_eq!(set.poll_at(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_too_large() {
+        let mut set = FragmentSet::new(buffers(1, 16));
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 1, 16, false, &[1; 8]), 0),
+                   Err(Error::Exhausted));
+        assert_eq!(set.poll_at(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_source_limit() {
+        let mut set = FragmentSet::new(buffers(3, 64));
+        assert_eq!(set.source_limit(), 2);
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 1, 0, true, &[1; 8]), 0), Ok(false));
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 2, 0, true, &[1; 8]), 0), Ok(false));
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 3, 0, true, &[1; 8]), 0),
+                   Err(Error::Exhausted));
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_2, 1, 0, true, &[1; 8]), 0), Ok(false));
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_2, 2, 0, true, &[1; 8]), 0),
+                   Err(Error::Exhausted));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_expire() {
+        let mut set = FragmentSet::new(buffers(1, 64));
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 1, 0, true, &[1; 8]), 1_000), Ok(false));
+        assert_eq!(set.poll_at(), Some(61_000));
+        set.expire(60_999);
+        assert_eq!(set.poll_at(), Some(61_000));
+        set.expire(61_000);
+        assert_eq!(set.poll_at(), None);
+        // The rest of the datagram cannot complete it anymore.
+        assert_eq!(add(&mut set, &fragment(SRC_ADDR_1, 1, 8, false, &[2; 4]), 61_000),
+                   Ok(false));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_fragment() {
+        let mut fragmenter = Fragmenter::new(vec![0; 64]);
+        let dst_hardware_addr = EthernetAddress([0x02, 0, 0, 0, 0, 1]);
+        let ip_repr = IpRepr::Ipv4(header(SRC_ADDR_1, 65));
+        assert_eq!(fragmenter.start(ip_repr, dst_hardware_addr, 40), None);
+        let ip_repr = IpRepr::Ipv4(header(SRC_ADDR_1, 40));
+        {
+            let payload = fragmenter.start(ip_repr.clone(), dst_hardware_addr, 40).unwrap();
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte = i as u8;
+            }
+        }
+        assert!(fragmenter.start(ip_repr.clone(), dst_hardware_addr, 40).is_none());
+
+        let mut set = FragmentSet::new(buffers(1, 64));
+        let mut lens = vec![];
+        while let Some((addr, protocol, len)) = fragmenter.next_fragment() {
+            assert_eq!(addr, dst_hardware_addr);
+            assert_eq!(protocol, EthernetProtocol::Ipv4);
+            let mut bytes = vec![0; len];
+            fragmenter.emit_fragment(&mut bytes, &ChecksumCapabilities::default());
+            assert!(Ipv4Packet::new(&bytes).verify_checksum());
+            assert!(!Ipv4Packet::new(&bytes).dont_frag());
+            lens.push(len);
+            add(&mut set, &bytes, 0).unwrap();
+        }
+        assert_eq!(lens, vec![36, 36, 28]);
+        assert!(!fragmenter.is_pending());
+
+        let payload = (0..40).collect::<Vec<u8>>();
+        assert_eq!(set.reassembled(), Some((ip_repr, &payload[..])));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_fragment_pooled() {
+        use storage::PacketPool;
+
+        let mut storage = [0; 64];
+        let mut chunks = [None];
+        let pool = PacketPool::new(&mut storage[..], 64, &mut chunks[..]);
+        let quota = pool.quota(1);
+        let mut fragmenter = Fragmenter::pooled(&quota);
+        assert_eq!(fragmenter.capacity(), 64);
+
+        let dst_hardware_addr = EthernetAddress([0x02, 0, 0, 0, 0, 1]);
+        let ip_repr = IpRepr::Ipv4(header(SRC_ADDR_1, 40));
+        assert!(fragmenter.start(ip_repr, dst_hardware_addr, 40).is_some());
+        assert_eq!(pool.available(), 0);
+        while let Some((_, _, len)) = fragmenter.next_fragment() {
+            fragmenter.emit_fragment(&mut vec![0; len], &ChecksumCapabilities::default());
+        }
+        assert_eq!(pool.available(), 1);
+    }
+
+    #[cfg(feature = "proto-ipv6")]
+    const IPV6_SRC_ADDR: Ipv6Address = Ipv6Address([0xfe, 0x80, 0, 0, 0, 0, 0, 0,
+                                                    0, 0, 0, 0, 0, 0, 0, 1]);
+    #[cfg(feature = "proto-ipv6")]
+    const IPV6_DST_ADDR: Ipv6Address = Ipv6Address([0xfe, 0x80, 0, 0, 0, 0, 0, 0,
+                                                    0, 0, 0, 0, 0, 0, 0, 2]);
+
+    #[cfg(feature = "proto-ipv6")]
+    fn ipv6_header(next_header: IpProtocol, payload_len: usize) -> Ipv6Repr {
+        Ipv6Repr {
+            next_header, payload_len,
+            src_addr:    IPV6_SRC_ADDR,
+            dst_addr:    IPV6_DST_ADDR,
+            hop_limit:   64,
+            flow_label:  0,
+            ecn:         0,
+            dscp:        0
+        }
+    }
+
+    #[cfg(feature = "proto-ipv6")]
+    fn add_ipv6(set: &mut FragmentSet, offset: u16, more_frags: bool,
+                data: &[u8]) -> Result<bool> {
+        let frag_repr = Ipv6FragmentRepr {
+            next_header: IpProtocol::Udp,
+            frag_offset: offset,
+            more_frags:  more_frags,
+            ident:       1
+        };
+        let ipv6_repr = ipv6_header(IpProtocol::Ipv6Frag, frag_repr.buffer_len() + data.len());
+        set.add_ipv6(&ipv6_repr, &frag_repr, data, 0)
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv6")]
+    fn test_reassemble_ipv6() {
+        let mut set = FragmentSet::new(buffers(1, 64));
+        assert_eq!(add_ipv6(&mut set, 8, false, &[2; 4]), Ok(false));
+        assert_eq!(add_ipv6(&mut set, 0, true, &[1; 8]), Ok(true));
+
+        let mut payload = vec![1; 8];
+        payload.extend(&[2; 4]);
+        let ip_repr = IpRepr::Ipv6(ipv6_header(IpProtocol::Udp, 12));
+        assert_eq!(set.reassembled(), Some((ip_repr, &payload[..])));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv6")]
+    fn test_overlap_ipv6() {
+        let mut set = FragmentSet::new(buffers(1, 64));
+        assert_eq!(add_ipv6(&mut set, 0, true, &[1; 16]), Ok(false));
+        assert_eq!(add_ipv6(&mut set, 8, false, &[2; 8]), Err(Error::Malformed));
+        assert_eq!(set.poll_at(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv6")]
+    fn test_fragment_ipv6() {
+        let mut fragmenter = Fragmenter::new(vec![0; 64]);
+        let dst_hardware_addr = EthernetAddress([0x02, 0, 0, 0, 0, 1]);
+        {
+            let ip_repr = IpRepr::Ipv6(ipv6_header(IpProtocol::Udp, 40));
+            let payload = fragmenter.start(ip_repr, dst_hardware_addr, 64).unwrap();
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte = i as u8;
+            }
+        }
+
+        let mut set = FragmentSet::new(buffers(1, 64));
+        let mut lens = vec![];
+        while let Some((addr, protocol, len)) = fragmenter.next_fragment() {
+            assert_eq!(addr, dst_hardware_addr);
+            assert_eq!(protocol, EthernetProtocol::Ipv6);
+            let mut bytes = vec![0; len];
+            fragmenter.emit_fragment(&mut bytes, &ChecksumCapabilities::default());
+            lens.push(len);
+
+            let packet = Ipv6Packet::new(&bytes[..]);
+            let ipv6_repr = Ipv6Repr::parse(&packet).unwrap();
+            assert_eq!(ipv6_repr.next_header, IpProtocol::Ipv6Frag);
+            let header = Ipv6FragmentHeader::new(packet.payload());
+            let frag_repr = Ipv6FragmentRepr::parse(&header).unwrap();
+            set.add_ipv6(&ipv6_repr, &frag_repr, header.payload(), 0).unwrap();
+        }
+        // Every fragment carries up to 16 octets of the payload after 48 octets of headers.
+        assert_eq!(lens, vec![64, 64, 56]);
+        assert!(!fragmenter.is_pending());
+
+        let payload = (0..40).collect::<Vec<u8>>();
+        let ip_repr = IpRepr::Ipv6(ipv6_header(IpProtocol::Udp, 40));
+        assert_eq!(set.reassembled(), Some((ip_repr, &payload[..])));
+    }
+}
@@ -0,0 +1,187 @@
+// Heads up! Before working on this file you should read RFC 1812 § 4.3.2.8 and
+// RFC 4443 § 2.4, which describe why and how ICMP error messages are rate limited.
+
+/// A policy for generating ICMP error messages.
+///
+/// Every error message is sent in response to a packet received from the network, so
+/// a host scanning the ports of the interface, or a stream of packets sent with a low
+/// hop limit, could otherwise make the interface spend its whole transmit capacity
+/// on error messages. The rate of error messages is limited with a token bucket,
+/// which holds up to `burst` messages and is refilled at `rate_limit` messages
+/// per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IcmpErrorPolicy {
+    /// Whether destination unreachable messages are sent for packets to a port,
+    /// or of a protocol, that no socket listens to.
+    pub port_unreachable: bool,
+    /// Whether time exceeded messages are sent for packets whose hop limit runs out
+    /// while they are forwarded.
+    pub time_exceeded:    bool,
+    /// Whether parameter problem messages are sent for packets with erroneous
+    /// headers, unrecognized next headers, or unrecognized options that require one.
+    pub param_problem:    bool,
+    /// The sustained rate of error messages, in messages per second, or `None`
+    /// for no limit.
+    pub rate_limit:       Option<u32>,
+    /// The number of error messages that may be sent at once before the rate limit
+    /// applies.
+    pub burst:            u32
+}
+
+impl Default for IcmpErrorPolicy {
+    /// Send every kind of error message, at most 100 per second in bursts of 10.
+    fn default() -> IcmpErrorPolicy {
+        IcmpErrorPolicy {
+            port_unreachable: true,
+            time_exceeded:    true,
+            param_problem:    true,
+            rate_limit:       Some(100),
+            burst:            10
+        }
+    }
+}
+
+/// The kind of an ICMP error message, as far as the policy is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IcmpError {
+    PortUnreachable,
+    TimeExceeded,
+    #[cfg(feature = "proto-ipv6")]
+    ParamProblem,
+    /// Any other error, e.g. an unreachable network or a packet too big;
+    /// these are only rate limited.
+    Other
+}
+
+/// A token bucket limiting the rate of ICMP messages.
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    /// The tokens in the bucket, in thousandths of a message.
+    credit:     u64,
+    updated_at: u64
+}
+
+impl TokenBucket {
+    /// Create a bucket holding the given number of messages.
+    pub(crate) fn new(burst: u32) -> TokenBucket {
+        TokenBucket {
+            credit:     burst as u64 * 1000,
+            updated_at: 0
+        }
+    }
+
+    /// Keep at most the given number of messages in the bucket.
+    pub(crate) fn clamp(&mut self, burst: u32) {
+        self.credit = self.credit.min(burst as u64 * 1000);
+    }
+
+    /// Refill the bucket at `rate_limit` messages per second up to `burst` messages,
+    /// and take a token from it if there is one.
+    pub(crate) fn take(&mut self, rate_limit: Option<u32>, burst: u32, timestamp: u64) -> bool {
+        let rate_limit = match rate_limit {
+            Some(rate_limit) => rate_limit as u64,
+            None => return true
+        };
+        // One message per second is one thousandth of a message per millisecond.
+        let elapsed = timestamp.saturating_sub(self.updated_at);
+        self.credit = self.credit.saturating_add(elapsed.saturating_mul(rate_limit))
+                                 .min(burst as u64 * 1000);
+        self.updated_at = self.updated_at.max(timestamp);
+
+        if self.credit >= 1000 {
+            self.credit -= 1000;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// The state of the token bucket limiting the rate of ICMP error messages.
+#[derive(Debug)]
+pub(crate) struct IcmpErrorLimiter {
+    policy: IcmpErrorPolicy,
+    bucket: TokenBucket
+}
+
+impl IcmpErrorLimiter {
+    /// Create a limiter with the given policy and a full bucket.
+    pub(crate) fn new(policy: IcmpErrorPolicy) -> IcmpErrorLimiter {
+        IcmpErrorLimiter {
+            policy: policy,
+            bucket: TokenBucket::new(policy.burst)
+        }
+    }
+
+    /// Return the policy.
+    pub(crate) fn policy(&self) -> IcmpErrorPolicy {
+        self.policy
+    }
+
+    /// Replace the policy, keeping at most a full bucket of the new size.
+    pub(crate) fn set_policy(&mut self, policy: IcmpErrorPolicy) {
+        self.policy = policy;
+        self.bucket.clamp(policy.burst);
+    }
+
+    /// Decide whether an error message of the given kind may be sent at the given moment,
+    /// and if so, take a token from the bucket.
+    pub(crate) fn allow(&mut self, error: IcmpError, timestamp: u64) -> bool {
+        let enabled = match error {
+            IcmpError::PortUnreachable => self.policy.port_unreachable,
+            IcmpError::TimeExceeded    => self.policy.time_exceeded,
+            #[cfg(feature = "proto-ipv6")]
+            IcmpError::ParamProblem    => self.policy.param_problem,
+            IcmpError::Other           => true
+        };
+        if !enabled { return false }
+
+        self.bucket.take(self.policy.rate_limit, self.policy.burst, timestamp)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_kinds() {
+        let mut limiter = IcmpErrorLimiter::new(IcmpErrorPolicy {
+            time_exceeded: false,
+            rate_limit:    None,
+            ..IcmpErrorPolicy::default()
+        });
+        assert!(limiter.allow(IcmpError::PortUnreachable, 0));
+        assert!(!limiter.allow(IcmpError::TimeExceeded, 0));
+        #[cfg(feature = "proto-ipv6")]
+        assert!(limiter.allow(IcmpError::ParamProblem, 0));
+        assert!(limiter.allow(IcmpError::Other, 0));
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut limiter = IcmpErrorLimiter::new(IcmpErrorPolicy {
+            rate_limit: Some(10),
+            burst:      3,
+            ..IcmpErrorPolicy::default()
+        });
+        // A full bucket allows a burst...
+        for _ in 0..3 {
+            assert!(limiter.allow(IcmpError::Other, 1_000));
+        }
+        assert!(!limiter.allow(IcmpError::Other, 1_000));
+        // ... and is then refilled with one message every 100 ms.
+        assert!(!limiter.allow(IcmpError::Other, 1_099));
+        assert!(limiter.allow(IcmpError::Other, 1_100));
+        assert!(!limiter.allow(IcmpError::Other, 1_100));
+        // Disabled kinds do not take tokens.
+        limiter.set_policy(IcmpErrorPolicy { port_unreachable: false, ..limiter.policy() });
+        assert!(!limiter.allow(IcmpError::PortUnreachable, 1_200));
+        assert!(limiter.allow(IcmpError::Other, 1_200));
+        // The bucket never holds more than a burst.
+        for _ in 0..3 {
+            assert!(limiter.allow(IcmpError::Other, 60_000));
+        }
+        assert!(!limiter.allow(IcmpError::Other, 60_000));
+    }
+}
@@ -0,0 +1,185 @@
+use core::{fmt, str};
+
+use {Error, Result};
+
+/// The maximum length of a hostname.
+pub const MAX_HOSTNAME_LEN: usize = 63;
+/// The maximum length of a vendor class identifier.
+pub const MAX_VENDOR_CLASS_LEN: usize = 64;
+/// The maximum length of a client identifier.
+pub const MAX_CLIENT_ID_LEN: usize = 32;
+/// The maximum number of options in the list of requested options.
+pub const MAX_REQUESTED_OPTION_COUNT: usize = 16;
+
+/// The identity of a host, as presented by the protocols that configure the interface
+/// and advertise it on the link.
+///
+/// The identity is kept by the interface, so that it is set in one place: the DHCPv4
+/// client sends the hostname, the vendor class identifier, the client identifier and
+/// the list of requested options in its messages, and the mDNS and LLMNR responders
+/// answer queries for the hostname. Every setting is unset by default, in which case
+/// the client and the responders use their own defaults.
+#[derive(Clone, Copy)]
+pub struct Identity {
+    hostname:               [u8; MAX_HOSTNAME_LEN],
+    hostname_len:           usize,
+    vendor_class:           [u8; MAX_VENDOR_CLASS_LEN],
+    vendor_class_len:       usize,
+    client_id:              [u8; MAX_CLIENT_ID_LEN],
+    client_id_len:          usize,
+    requested_options:      [u8; MAX_REQUESTED_OPTION_COUNT],
+    requested_option_count: usize,
+}
+
+impl Default for Identity {
+    fn default() -> Identity {
+        Identity::new()
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Identity")
+         .field("hostname", &self.hostname())
+         .field("vendor_class", &self.vendor_class())
+         .field("client_id", &self.client_id())
+         .field("requested_options", &self.requested_options())
+         .finish()
+    }
+}
+
+/// Check that `hostname` is a single label, i.e. that it is not empty, is at most
+/// `MAX_HOSTNAME_LEN` octets long, and does not contain a dot, or return
+/// `Err(Error::Illegal)`.
+pub(crate) fn check_hostname(hostname: &str) -> Result<()> {
+    if hostname.is_empty() || hostname.len() > MAX_HOSTNAME_LEN || hostname.contains('.') {
+        return Err(Error::Illegal)
+    }
+    Ok(())
+}
+
+/// Copy `value` into `buffer`, returning its length, or `Err(Error::Illegal)` if it
+/// does not fit.
+fn store(buffer: &mut [u8], value: Option<&[u8]>) -> Result<usize> {
+    match value {
+        Some(value) if value.len() > buffer.len() => Err(Error::Illegal),
+        Some(value) => {
+            buffer[..value.len()].copy_from_slice(value);
+            Ok(value.len())
+        }
+        None => Ok(0)
+    }
+}
+
+fn load(buffer: &[u8], len: usize) -> Option<&[u8]> {
+    if len == 0 { None } else { Some(&buffer[..len]) }
+}
+
+impl Identity {
+    /// Create an identity with every setting unset.
+    pub fn new() -> Identity {
+        Identity {
+            hostname:               [0; MAX_HOSTNAME_LEN],
+            hostname_len:           0,
+            vendor_class:           [0; MAX_VENDOR_CLASS_LEN],
+            vendor_class_len:       0,
+            client_id:              [0; MAX_CLIENT_ID_LEN],
+            client_id_len:          0,
+            requested_options:      [0; MAX_REQUESTED_OPTION_COUNT],
+            requested_option_count: 0,
+        }
+    }
+
+    /// Return the hostname.
+    pub fn hostname(&self) -> Option<&str> {
+        load(&self.hostname, self.hostname_len)
+            .map(|hostname| str::from_utf8(hostname).unwrap())
+    }
+
+    /// Set the hostname, which must be a single label.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the hostname is empty, longer than
+    /// `MAX_HOSTNAME_LEN` octets, or contains a dot.
+    pub fn set_hostname(&mut self, hostname: Option<&str>) -> Result<()> {
+        if let Some(hostname) = hostname { check_hostname(hostname)? }
+        self.hostname_len = store(&mut self.hostname, hostname.map(str::as_bytes))?;
+        Ok(())
+    }
+
+    /// Return the vendor class identifier.
+    pub fn vendor_class(&self) -> Option<&[u8]> {
+        load(&self.vendor_class, self.vendor_class_len)
+    }
+
+    /// Set the vendor class identifier, e.g. the name and version of the firmware.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the identifier is empty or longer
+    /// than `MAX_VENDOR_CLASS_LEN` octets.
+    pub fn set_vendor_class(&mut self, vendor_class: Option<&[u8]>) -> Result<()> {
+        if vendor_class.map_or(false, |value| value.is_empty()) { return Err(Error::Illegal) }
+        self.vendor_class_len = store(&mut self.vendor_class, vendor_class)?;
+        Ok(())
+    }
+
+    /// Return the client identifier.
+    pub fn client_id(&self) -> Option<&[u8]> {
+        load(&self.client_id, self.client_id_len)
+    }
+
+    /// Set the client identifier, as sent in the DHCPv4 Client-identifier option:
+    /// a type octet followed by the identifier, e.g. `255` followed by an IAID and
+    /// a DUID as described in RFC 4361. By default, the hardware address of the interface
+    /// is used.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the identifier is shorter than
+    /// two octets or longer than `MAX_CLIENT_ID_LEN` octets.
+    pub fn set_client_id(&mut self, client_id: Option<&[u8]>) -> Result<()> {
+        if client_id.map_or(false, |value| value.len() < 2) { return Err(Error::Illegal) }
+        self.client_id_len = store(&mut self.client_id, client_id)?;
+        Ok(())
+    }
+
+    /// Return the list of the DHCPv4 options requested from servers.
+    pub fn requested_options(&self) -> Option<&[u8]> {
+        load(&self.requested_options, self.requested_option_count)
+    }
+
+    /// Set the list of the DHCPv4 options requested from servers, which replaces
+    /// the list of the options the client understands.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the list is empty or has more than
+    /// `MAX_REQUESTED_OPTION_COUNT` options.
+    pub fn set_requested_options(&mut self, options: Option<&[u8]>) -> Result<()> {
+        if options.map_or(false, |value| value.is_empty()) { return Err(Error::Illegal) }
+        self.requested_option_count = store(&mut self.requested_options, options)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_settings() {
+        let mut identity = Identity::new();
+        assert_eq!(identity.hostname(), None);
+        assert_eq!(identity.set_hostname(Some("sensor-1")), Ok(()));
+        assert_eq!(identity.hostname(), Some("sensor-1"));
+        assert_eq!(identity.set_hostname(Some("sensor.local")), Err(Error::Illegal));
+        assert_eq!(identity.set_hostname(Some("")), Err(Error::Illegal));
+        assert_eq!(identity.set_hostname(Some(str::from_utf8(&[b'x'; 64]).unwrap())), Err(Error::Illegal));
+        assert_eq!(identity.hostname(), Some("sensor-1"));
+        assert_eq!(identity.set_hostname(None), Ok(()));
+        assert_eq!(identity.hostname(), None);
+
+        assert_eq!(identity.set_vendor_class(Some(b"smoltcp 0.4")), Ok(()));
+        assert_eq!(identity.vendor_class(), Some(&b"smoltcp 0.4"[..]));
+        assert_eq!(identity.set_client_id(Some(&[0x01])), Err(Error::Illegal));
+        assert_eq!(identity.set_client_id(Some(&[0x00, 0x01])), Ok(()));
+        assert_eq!(identity.client_id(), Some(&[0x00, 0x01][..]));
+        assert_eq!(identity.set_requested_options(Some(&[0; 17])), Err(Error::Illegal));
+        assert_eq!(identity.set_requested_options(Some(&[1, 3])), Ok(()));
+        assert_eq!(identity.requested_options(), Some(&[1, 3][..]));
+    }
+}
@@ -0,0 +1,256 @@
+// Heads up! Before working on this file you should read the parts of RFC 4862
+// that discuss the lifetimes of addresses (§ 2 and § 5.5.4), and RFC 6724 § 5,
+// which describes how deprecated addresses are avoided as source addresses.
+
+use managed::ManagedSlice;
+
+use {Error, Result};
+use wire::IpAddress;
+
+/// The lifetimes of an IP address of an interface, e.g. those of a DHCP lease,
+/// or of an address configured by the application.
+///
+/// The deadlines are timestamps, in milliseconds, with `None` meaning infinity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressLifetime {
+    pub addr:            IpAddress,
+    /// The moment after which the address is deprecated, i.e. not used for new
+    /// communication if any other address is available.
+    pub preferred_until: Option<u64>,
+    /// The moment after which the address is removed from the interface.
+    pub valid_until:     Option<u64>,
+    expired:             bool,
+}
+
+impl AddressLifetime {
+    /// Create the lifetimes of an address that did not expire.
+    pub(crate) fn new(addr: IpAddress, preferred_until: Option<u64>,
+                      valid_until: Option<u64>) -> AddressLifetime {
+        AddressLifetime { addr, preferred_until, valid_until, expired: false }
+    }
+
+    /// Query whether the address is deprecated at the given moment.
+    pub fn is_deprecated(&self, timestamp: u64) -> bool {
+        self.preferred_until.map_or(false, |deadline| timestamp >= deadline)
+    }
+
+    /// Query whether the address has to be removed at the given moment.
+    pub fn is_expired(&self, timestamp: u64) -> bool {
+        self.valid_until.map_or(false, |deadline| timestamp >= deadline)
+    }
+
+    /// Return the moment at which the state of the address changes next, if any.
+    pub(crate) fn poll_at(&self, timestamp: u64) -> Option<u64> {
+        match self.preferred_until {
+            Some(deadline) if deadline > timestamp => Some(deadline),
+            _ => self.valid_until
+        }
+    }
+}
+
+/// A table of the lifetimes of the IP addresses of an interface, which also holds
+/// the addresses that expired until the application is told about them.
+///
+/// Addresses without an entry in the table never expire. When the table is full,
+/// the entry of an address that expired is replaced.
+///
+/// # Examples
+///
+/// On systems with heap, this table can be created with:
+///
+/// ```rust
+/// use smoltcp::iface::AddressLifetimes;
+/// let mut address_lifetimes = AddressLifetimes::new(vec![]);
+/// ```
+///
+/// On systems without heap, use:
+///
+/// ```rust
+/// use smoltcp::iface::AddressLifetimes;
+/// let mut address_lifetimes_storage = [None; 4];
+/// let mut address_lifetimes = AddressLifetimes::new(&mut address_lifetimes_storage[..]);
+/// ```
+#[derive(Debug)]
+pub struct AddressLifetimes<'a> {
+    storage: ManagedSlice<'a, Option<AddressLifetime>>,
+}
+
+impl<'a> AddressLifetimes<'a> {
+    /// Create an address lifetime table. The backing storage is cleared upon creation.
+    pub fn new<T>(storage: T) -> AddressLifetimes<'a>
+            where T: Into<ManagedSlice<'a, Option<AddressLifetime>>> {
+        let mut storage = storage.into();
+        for slot in storage.iter_mut() {
+            *slot = None;
+        }
+
+        AddressLifetimes { storage }
+    }
+
+    /// Return an iterator over the lifetimes of the addresses that did not expire.
+    pub fn iter(&self) -> Iter {
+        Iter { slots: self.storage.iter() }
+    }
+
+    /// Return the lifetimes of the given address, unless it expired.
+    pub fn lookup(&self, addr: &IpAddress) -> Option<AddressLifetime> {
+        self.iter().find(|lifetime| lifetime.addr == *addr).cloned()
+    }
+
+    /// Query whether the given address is deprecated at the given moment.
+    pub fn is_deprecated(&self, addr: &IpAddress, timestamp: u64) -> bool {
+        self.lookup(addr).map_or(false, |lifetime| lifetime.is_deprecated(timestamp))
+    }
+
+    /// Set the lifetimes of an address, replacing its previous ones.
+    ///
+    /// Returns `Err(Error::Exhausted)` if there is no space for another address.
+    pub(crate) fn set(&mut self, addr: IpAddress, preferred_until: Option<u64>,
+                      valid_until: Option<u64>) -> Result<()> {
+        self.insert(AddressLifetime::new(addr, preferred_until, valid_until))
+    }
+
+    /// Record that an address expired, so that the application is told about it.
+    pub(crate) fn expired(&mut self, addr: IpAddress, timestamp: u64) {
+        let lifetime = AddressLifetime {
+            addr,
+            preferred_until: Some(timestamp),
+            valid_until:     Some(timestamp),
+            expired:         true
+        };
+        if self.insert(lifetime).is_err() {
+            net_debug!("no space to record the expiry of address {}", addr);
+        }
+    }
+
+    fn insert(&mut self, lifetime: AddressLifetime) -> Result<()> {
+        let index = self.storage.iter()
+            .position(|slot| slot.map_or(false, |probe| probe.addr == lifetime.addr))
+            .or_else(|| self.storage.iter().position(|slot| slot.is_none()))
+            .or_else(|| self.storage.iter()
+                             .position(|slot| slot.map_or(false, |probe| probe.expired)));
+        match index {
+            Some(index) => {
+                self.storage[index] = Some(lifetime);
+                Ok(())
+            }
+            None => match self.storage {
+                ManagedSlice::Borrowed(_) => Err(Error::Exhausted),
+                #[cfg(any(feature = "std", feature = "alloc"))]
+                ManagedSlice::Owned(ref mut lifetimes) => {
+                    lifetimes.push(Some(lifetime));
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Forget the lifetimes of an address, and whether it expired.
+    pub(crate) fn remove(&mut self, addr: &IpAddress) {
+        for slot in self.storage.iter_mut() {
+            if slot.map_or(false, |probe| probe.addr == *addr) {
+                *slot = None
+            }
+        }
+    }
+
+    /// Mark an address whose valid lifetime ran out at the given moment as expired,
+    /// and return it, if there is any.
+    pub(crate) fn expire(&mut self, timestamp: u64) -> Option<IpAddress> {
+        for slot in self.storage.iter_mut() {
+            if let Some(ref mut lifetime) = *slot {
+                if !lifetime.expired && lifetime.is_expired(timestamp) {
+                    lifetime.expired = true;
+                    return Some(lifetime.addr)
+                }
+            }
+        }
+        None
+    }
+
+    /// Return an address that expired and forget it, if there is any.
+    pub(crate) fn take_expired(&mut self) -> Option<IpAddress> {
+        for slot in self.storage.iter_mut() {
+            if slot.map_or(false, |lifetime| lifetime.expired) {
+                return slot.take().map(|lifetime| lifetime.addr)
+            }
+        }
+        None
+    }
+
+    /// Return the moment at which an address is deprecated or expires next, if any.
+    pub(crate) fn poll_at(&self, timestamp: u64) -> Option<u64> {
+        self.iter().filter_map(|lifetime| lifetime.poll_at(timestamp)).min()
+    }
+}
+
+/// An iterator over the lifetimes in an [AddressLifetimes](struct.AddressLifetimes.html) table.
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    slots: ::core::slice::Iter<'a, Option<AddressLifetime>>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a AddressLifetime;
+
+    fn next(&mut self) -> Option<&'a AddressLifetime> {
+        while let Some(slot) = self.slots.next() {
+            match *slot {
+                Some(ref lifetime) if !lifetime.expired => return Some(lifetime),
+                _ => ()
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "proto-ipv6")]
+    const ADDR_1: IpAddress = IpAddress::Ipv6(::wire::Ipv6Address([
+        0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]));
+    #[cfg(feature = "proto-ipv6")]
+    const ADDR_2: IpAddress = IpAddress::Ipv6(::wire::Ipv6Address([
+        0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]));
+
+    #[test]
+    #[cfg(feature = "proto-ipv6")]
+    fn test_deprecate_expire() {
+        let mut lifetimes = AddressLifetimes::new(vec![]);
+        assert_eq!(lifetimes.set(ADDR_1, Some(1_000), Some(2_000)), Ok(()));
+        assert_eq!(lifetimes.set(ADDR_2, None, None), Ok(()));
+        assert_eq!(lifetimes.poll_at(0), Some(1_000));
+
+        assert!(!lifetimes.is_deprecated(&ADDR_1, 999));
+        assert!(lifetimes.is_deprecated(&ADDR_1, 1_000));
+        assert!(!lifetimes.is_deprecated(&ADDR_2, u64::max_value()));
+        assert_eq!(lifetimes.poll_at(1_000), Some(2_000));
+
+        assert_eq!(lifetimes.expire(1_999), None);
+        assert_eq!(lifetimes.expire(2_000), Some(ADDR_1));
+        assert_eq!(lifetimes.expire(2_000), None);
+        assert_eq!(lifetimes.lookup(&ADDR_1), None);
+        assert_eq!(lifetimes.poll_at(2_000), None);
+
+        assert_eq!(lifetimes.take_expired(), Some(ADDR_1));
+        assert_eq!(lifetimes.take_expired(), None);
+        assert_eq!(lifetimes.iter().count(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv6")]
+    fn test_replace_expired() {
+        let mut storage = [None; 1];
+        let mut lifetimes = AddressLifetimes::new(&mut storage[..]);
+        lifetimes.expired(ADDR_1, 0);
+        assert_eq!(lifetimes.set(ADDR_2, None, Some(1_000)), Ok(()));
+        assert_eq!(lifetimes.take_expired(), None);
+        assert_eq!(lifetimes.set(ADDR_1, None, None), Err(Error::Exhausted));
+
+        // Setting the lifetimes of an address again replaces them.
+        assert_eq!(lifetimes.set(ADDR_2, None, None), Ok(()));
+        assert_eq!(lifetimes.lookup(&ADDR_2).and_then(|lifetime| lifetime.valid_until), None);
+    }
+}
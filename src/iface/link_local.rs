@@ -0,0 +1,77 @@
+// Heads up! Before working on this file you should read RFC 3927,
+// which describes how IPv4 link-local addresses are configured.
+
+use wire::{EthernetAddress, Ipv4Address, Ipv4Cidr};
+
+/// The number of addresses that may be selected; the first and last 256 addresses
+/// of 169.254/16 are reserved. See RFC 3927 § 2.1.
+const ADDRESS_COUNT: u32 = 254 * 256;
+
+/// The state of IPv4 link-local address configuration on an interface.
+#[derive(Debug)]
+pub(crate) struct LinkLocal {
+    cidr:       Option<Ipv4Cidr>,
+    rand_state: u32,
+}
+
+impl LinkLocal {
+    /// Create the state of an interface with the given hardware address, which seeds
+    /// the selection of the addresses, so that the interface likely selects the same
+    /// address every time it starts.
+    pub(crate) fn new(hardware_addr: EthernetAddress) -> LinkLocal {
+        let b = hardware_addr.as_bytes();
+        let seed = ((b[2] as u32) << 24) | ((b[3] as u32) << 16) |
+                   ((b[4] as u32) << 8) | b[5] as u32;
+        LinkLocal {
+            cidr:       None,
+            rand_state: if seed != 0 { seed } else { 0xcc9e2d51 }
+        }
+    }
+
+    /// Return the address that was selected last, if any.
+    pub(crate) fn cidr(&self) -> Option<Ipv4Cidr> {
+        self.cidr
+    }
+
+    /// Select a new address, e.g. because the last one is used by another host.
+    pub(crate) fn select(&mut self) -> Ipv4Cidr {
+        // xorshift32; the addresses only need to differ between hosts.
+        let mut x = self.rand_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rand_state = x;
+
+        let index = x % ADDRESS_COUNT;
+        let addr = Ipv4Address([169, 254, (1 + index / 256) as u8, (index % 256) as u8]);
+        let cidr = Ipv4Cidr::new(addr, 16);
+        self.cidr = Some(cidr);
+        cidr
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let hardware_addr = EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        let mut link_local = LinkLocal::new(hardware_addr);
+        assert_eq!(link_local.cidr(), None);
+
+        let first = link_local.select();
+        assert_eq!(link_local.cidr(), Some(first));
+        // The same address is selected first every time.
+        assert_eq!(LinkLocal::new(hardware_addr).select(), first);
+
+        for _ in 0..1000 {
+            let cidr = link_local.select();
+            let bytes = cidr.address().0;
+            assert_eq!(cidr.prefix_len(), 16);
+            assert_eq!(&bytes[..2], &[169, 254]);
+            assert!(bytes[2] >= 1 && bytes[2] <= 254);
+        }
+        assert!(link_local.select() != link_local.select());
+    }
+}
@@ -0,0 +1,174 @@
+// Heads up! Before working on this file you should read the parts
+// of RFC 1122 that discuss the loopback addresses (§ 3.2.1.3) and
+// the processing of the packets a host sends to itself (§ 3.3.1.1).
+
+use core::mem;
+use managed::ManagedSlice;
+
+use {Error, Result};
+
+/// A buffer holding a frame that an interface sent to itself.
+///
+/// The size of the storage limits the packets that can be looped back.
+#[derive(Debug)]
+pub struct LoopbackBuffer<'a> {
+    storage: ManagedSlice<'a, u8>,
+    len:     usize,
+}
+
+impl<'a> LoopbackBuffer<'a> {
+    /// Create a loopback buffer using the given storage.
+    pub fn new<T>(storage: T) -> LoopbackBuffer<'a>
+            where T: Into<ManagedSlice<'a, u8>> {
+        LoopbackBuffer { storage: storage.into(), len: 0 }
+    }
+
+    /// Return the largest frame that can be held in the buffer.
+    pub fn capacity(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// Return the frame held in the buffer.
+    pub(crate) fn frame(&self) -> &[u8] {
+        &self.storage[..self.len]
+    }
+}
+
+/// A queue of the frames an interface sent to a loopback address or to one of its own
+/// addresses, that are waiting to be received by the interface, in the order they
+/// were sent.
+///
+/// # Examples
+///
+/// On systems with heap, this queue can be created with:
+///
+/// ```rust
+/// use smoltcp::iface::{LoopbackBuffer, LoopbackQueue};
+/// let mut queue = LoopbackQueue::new(vec![LoopbackBuffer::new(vec![0; 1514]),
+///                                         LoopbackBuffer::new(vec![0; 1514])]);
+/// ```
+///
+/// On systems without heap, use:
+///
+/// ```rust
+/// use smoltcp::iface::{LoopbackBuffer, LoopbackQueue};
+/// let mut storage = [0; 1514];
+/// let mut buffers = [LoopbackBuffer::new(&mut storage[..])];
+/// let mut queue = LoopbackQueue::new(&mut buffers[..]);
+/// ```
+#[derive(Debug)]
+pub struct LoopbackQueue<'a, 'b: 'a> {
+    buffers: ManagedSlice<'a, LoopbackBuffer<'b>>,
+    read_at: usize,
+    len:     usize,
+}
+
+impl<'a, 'b: 'a> LoopbackQueue<'a, 'b> {
+    /// Create a loopback queue using the given buffers. The buffers are cleared
+    /// upon creation.
+    pub fn new<T>(buffers: T) -> LoopbackQueue<'a, 'b>
+            where T: Into<ManagedSlice<'a, LoopbackBuffer<'b>>> {
+        let mut buffers = buffers.into();
+        for buffer in buffers.iter_mut() {
+            buffer.len = 0;
+        }
+
+        LoopbackQueue { buffers, read_at: 0, len: 0 }
+    }
+
+    /// Return the number of frames waiting to be received.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Query whether there are no frames waiting to be received.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Allocate a frame of the given size at the back of the queue, and return it.
+    ///
+    /// Returns `Err(Error::Exhausted)` if the queue is full, or if the frame does not fit
+    /// into the next buffer.
+    pub(crate) fn enqueue(&mut self, size: usize) -> Result<&mut [u8]> {
+        if self.len == self.buffers.len() { return Err(Error::Exhausted) }
+
+        let index = (self.read_at + self.len) % self.buffers.len();
+        let buffer = &mut self.buffers[index];
+        if buffer.capacity() < size { return Err(Error::Exhausted) }
+
+        self.len += 1;
+        buffer.len = size;
+        Ok(&mut buffer.storage[..size])
+    }
+
+    /// Remove the buffer at the front of the queue, and return its index and the buffer,
+    /// if any. The buffer has to be put back with [restore](#method.restore) once its
+    /// frame is processed; until then, no frame is enqueued in its place.
+    pub(crate) fn dequeue(&mut self) -> Option<(usize, LoopbackBuffer<'b>)> {
+        if self.len == 0 { return None }
+
+        let index = self.read_at;
+        let buffer = mem::replace(&mut self.buffers[index],
+                                  LoopbackBuffer::new(ManagedSlice::Borrowed(&mut [])));
+        self.read_at = (self.read_at + 1) % self.buffers.len();
+        self.len -= 1;
+        Some((index, buffer))
+    }
+
+    /// Put back a buffer returned by [dequeue](#method.dequeue).
+    pub(crate) fn restore(&mut self, index: usize, mut buffer: LoopbackBuffer<'b>) {
+        buffer.len = 0;
+        self.buffers[index] = buffer;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn queue() -> LoopbackQueue<'static, 'static> {
+        LoopbackQueue::new(vec![LoopbackBuffer::new(vec![0; 8]),
+                                LoopbackBuffer::new(vec![0; 4])])
+    }
+
+    #[test]
+    fn test_enqueue_dequeue() {
+        let mut queue = queue();
+        assert!(queue.is_empty());
+        queue.enqueue(6).unwrap().copy_from_slice(&[1; 6]);
+        queue.enqueue(2).unwrap().copy_from_slice(&[2; 2]);
+        assert_eq!(queue.enqueue(1), Err(Error::Exhausted));
+        assert_eq!(queue.len(), 2);
+
+        let (index, buffer) = queue.dequeue().unwrap();
+        assert_eq!(buffer.frame(), &[1; 6]);
+        // The buffer of the frame being processed is not used meanwhile.
+        assert_eq!(queue.enqueue(1), Err(Error::Exhausted));
+        queue.restore(index, buffer);
+
+        queue.enqueue(3).unwrap().copy_from_slice(&[3; 3]);
+        for expected in [&[2; 2][..], &[3; 3][..]].iter() {
+            let (index, buffer) = queue.dequeue().unwrap();
+            assert_eq!(buffer.frame(), *expected);
+            queue.restore(index, buffer);
+        }
+        assert!(queue.is_empty());
+        assert!(queue.dequeue().is_none());
+    }
+
+    #[test]
+    fn test_too_large() {
+        let mut queue = queue();
+        queue.enqueue(8).unwrap();
+        assert_eq!(queue.enqueue(5), Err(Error::Exhausted));
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut queue = LoopbackQueue::new(vec![]);
+        assert_eq!(queue.enqueue(0), Err(Error::Exhausted));
+        assert!(queue.dequeue().is_none());
+    }
+}
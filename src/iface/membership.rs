@@ -0,0 +1,454 @@
+// Heads up! Before working on this file you should read the parts
+// of RFC 2236 and RFC 3810 that discuss the host state machine
+// (§ 3 and § 6 of the former, § 6 and § 8 of the latter).
+
+use managed::ManagedSlice;
+
+use {Error, Result};
+use wire::{IpAddress, IpVersion};
+
+/// The number of unsolicited reports sent when a group is joined, and the interval
+/// between them, in milliseconds. See RFC 2236 § 8.4 and § 8.10, and RFC 3810 § 9.11.
+const UNSOLICITED_REPORT_COUNT: u8 = 2;
+const UNSOLICITED_REPORT_INTERVAL: u64 = 10_000;
+#[cfg(feature = "proto-ipv6")]
+const MLDV2_UNSOLICITED_REPORT_INTERVAL: u64 = 1_000;
+/// The time after hearing a query from an IGMPv1 router until the host stops
+/// acting as an IGMPv1 host, in milliseconds. See RFC 2236 § 8.11.
+#[cfg(feature = "proto-igmp")]
+const V1_ROUTER_PRESENT_TIMEOUT: u64 = 400_000;
+/// The time after hearing a query from an MLDv1 router until the node stops
+/// acting as an MLDv1 node, in milliseconds. See RFC 3810 § 9.12.
+#[cfg(feature = "proto-ipv6")]
+const OLDER_VERSION_QUERIER_PRESENT_TIMEOUT: u64 = 260_000;
+
+/// The membership of a multicast group, which is reported to the multicast routers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Membership {
+    group_addr:    IpAddress,
+    report_at:     Option<u64>,
+    reports_left:  u8,
+    queried:       bool,
+    last_reporter: bool,
+    leaving:       bool,
+}
+
+impl Membership {
+    /// Return the address of the group.
+    pub fn group_addr(&self) -> IpAddress {
+        self.group_addr
+    }
+
+    /// Query whether the group was left, and the routers are about to be told so.
+    pub fn is_leaving(&self) -> bool {
+        self.leaving
+    }
+}
+
+/// The kind of a report that is due.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Report {
+    /// The group was joined.
+    Join,
+    /// The group was queried.
+    Current,
+    /// The group was left.
+    Leave,
+}
+
+/// The memberships of the multicast groups joined by an interface or its sockets.
+///
+/// # Examples
+///
+/// On systems with heap, this table can be created with:
+///
+/// ```rust
+/// use smoltcp::iface::Memberships;
+/// let mut memberships = Memberships::new(vec![]);
+/// ```
+///
+/// On systems without heap, use:
+///
+/// ```rust
+/// use smoltcp::iface::Memberships;
+/// let mut memberships_storage = [None; 8];
+/// let mut memberships = Memberships::new(&mut memberships_storage[..]);
+/// ```
+#[derive(Debug)]
+pub struct Memberships<'a> {
+    storage:             ManagedSlice<'a, Option<Membership>>,
+    #[cfg(feature = "proto-igmp")]
+    igmp_v1_until:       u64,
+    #[cfg(feature = "proto-ipv6")]
+    mldv1_until:         u64,
+    rand_state:          u32,
+}
+
+impl<'a> Memberships<'a> {
+    /// Create a membership table. The backing storage is cleared upon creation.
+    pub fn new<T>(storage: T) -> Memberships<'a>
+            where T: Into<ManagedSlice<'a, Option<Membership>>> {
+        let mut storage = storage.into();
+        for slot in storage.iter_mut() {
+            *slot = None;
+        }
+
+        Memberships {
+            storage,
+            #[cfg(feature = "proto-igmp")]
+            igmp_v1_until: 0,
+            #[cfg(feature = "proto-ipv6")]
+            mldv1_until:   0,
+            rand_state:    0x1b873593
+        }
+    }
+
+    /// Return an iterator over the memberships in the table.
+    pub fn iter(&self) -> Iter {
+        Iter { slots: self.storage.iter() }
+    }
+
+    /// Seed the generator of the random delays of the reports, so that the hosts
+    /// on a link do not all report at the same time.
+    pub(crate) fn seed(&mut self, seed: u32) {
+        if seed != 0 { self.rand_state = seed }
+    }
+
+    /// Return the number of slots, i.e. the bound of the indices of the memberships.
+    pub(crate) fn slots(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// Return the group of the membership with the given index, unless it was left.
+    pub(crate) fn joined_group(&self, index: usize) -> Option<IpAddress> {
+        match self.storage[index] {
+            Some(ref membership) if !membership.leaving => Some(membership.group_addr),
+            _ => None
+        }
+    }
+
+    /// Join a group, if it was not joined yet, and return whether it was.
+    ///
+    /// Returns `Err(Error::Exhausted)` if there is no space for another membership.
+    pub(crate) fn join(&mut self, group_addr: IpAddress, timestamp: u64) -> Result<bool> {
+        let membership = Membership {
+            group_addr,
+            report_at:     Some(timestamp),
+            reports_left:  UNSOLICITED_REPORT_COUNT,
+            queried:       false,
+            last_reporter: false,
+            leaving:       false,
+        };
+
+        for slot in self.storage.iter_mut() {
+            match *slot {
+                Some(ref mut probe) if probe.group_addr == group_addr => {
+                    if !probe.leaving { return Ok(false) }
+                    // The group was joined again before the routers were told that it was left.
+                    *probe = membership;
+                    return Ok(true)
+                }
+                _ => ()
+            }
+        }
+
+        for slot in self.storage.iter_mut() {
+            if slot.is_none() {
+                *slot = Some(membership);
+                return Ok(true)
+            }
+        }
+
+        match self.storage {
+            ManagedSlice::Borrowed(_) => Err(Error::Exhausted),
+            #[cfg(any(feature = "std", feature = "alloc"))]
+            ManagedSlice::Owned(ref mut memberships) => {
+                memberships.push(Some(membership));
+                Ok(true)
+            }
+        }
+    }
+
+    /// Leave the group with the given index. The routers are told so if we were the last
+    /// host to report the membership, unless there is an IGMPv1 router on the link.
+    ///
+    /// MLDv2 nodes do not suppress their reports, so they are always the last to report.
+    pub(crate) fn leave(&mut self, index: usize, _timestamp: u64) {
+        let igmp_v1 = match self.storage[index] {
+            Some(ref membership) => match membership.group_addr {
+                #[cfg(feature = "proto-igmp")]
+                IpAddress::Ipv4(_) => self.older_querier(IpVersion::Ipv4, _timestamp),
+                _ => false
+            },
+            None => return
+        };
+        let slot = &mut self.storage[index];
+        match *slot {
+            Some(ref mut membership) if membership.last_reporter && !igmp_v1 => {
+                membership.leaving = true;
+                return
+            }
+            _ => ()
+        }
+        *slot = None
+    }
+
+    /// Return the group that a report, or a leave message, is due for, if any.
+    pub(crate) fn pending(&self, timestamp: u64) -> Option<(IpAddress, Report)> {
+        self.iter()
+            .find(|membership| membership.leaving ||
+                               membership.report_at.map_or(false, |at| at <= timestamp))
+            .map(|membership| {
+                let report = if membership.leaving {
+                    Report::Leave
+                } else if membership.queried {
+                    Report::Current
+                } else {
+                    Report::Join
+                };
+                (membership.group_addr, report)
+            })
+    }
+
+    /// Record that a report for the given group was sent.
+    pub(crate) fn reported(&mut self, group_addr: IpAddress, timestamp: u64) {
+        let interval = match group_addr {
+            #[cfg(feature = "proto-ipv6")]
+            IpAddress::Ipv6(_) if !self.older_querier(IpVersion::Ipv6, timestamp) =>
+                MLDV2_UNSOLICITED_REPORT_INTERVAL,
+            _ => UNSOLICITED_REPORT_INTERVAL
+        };
+        let delay = self.random_delay(interval);
+        if let Some(membership) = self.find_mut(group_addr) {
+            membership.last_reporter = true;
+            membership.queried = false;
+            membership.reports_left = membership.reports_left.saturating_sub(1);
+            membership.report_at = if membership.reports_left > 0 {
+                Some(timestamp + delay)
+            } else {
+                None
+            };
+        }
+    }
+
+    /// Record that the routers were told that the given group was left.
+    pub(crate) fn left(&mut self, group_addr: IpAddress) {
+        for slot in self.storage.iter_mut() {
+            let matches = slot.map_or(false, |probe|
+                probe.leaving && probe.group_addr == group_addr);
+            if matches { *slot = None }
+        }
+    }
+
+    /// Schedule a report, after a random delay of at most the maximum response time,
+    /// for the given group, or every group of the same IP version if the given address
+    /// is unspecified.
+    pub(crate) fn query(&mut self, group_addr: IpAddress, max_resp_time: u64,
+                        timestamp: u64) {
+        for index in 0..self.storage.len() {
+            let delay = self.random_delay(max_resp_time);
+            if let Some(ref mut membership) = self.storage[index] {
+                if membership.leaving { continue }
+                if group_addr.is_unspecified() {
+                    if version_of(&group_addr) != version_of(&membership.group_addr) { continue }
+                } else if group_addr != membership.group_addr {
+                    continue
+                }
+
+                let report_at = timestamp + delay;
+                if membership.report_at.map_or(true, |at| at > report_at) {
+                    membership.report_at = Some(report_at);
+                    membership.queried = true;
+                }
+                membership.reports_left = membership.reports_left.max(1);
+            }
+        }
+    }
+
+    /// Cancel the pending report for the given group, since another host reported it.
+    pub(crate) fn report_heard(&mut self, group_addr: IpAddress) {
+        if let Some(membership) = self.find_mut(group_addr) {
+            if membership.report_at.is_some() {
+                membership.report_at = None;
+                membership.reports_left = 0;
+                membership.last_reporter = false;
+            }
+        }
+    }
+
+    /// Record that a query was heard from a router running the older version of
+    /// the protocol for the given IP version, i.e. IGMPv1 or MLDv1.
+    pub(crate) fn set_older_querier(&mut self, version: IpVersion, timestamp: u64) {
+        match version {
+            #[cfg(feature = "proto-igmp")]
+            IpVersion::Ipv4 => self.igmp_v1_until = timestamp + V1_ROUTER_PRESENT_TIMEOUT,
+            #[cfg(feature = "proto-ipv6")]
+            IpVersion::Ipv6 => self.mldv1_until = timestamp + OLDER_VERSION_QUERIER_PRESENT_TIMEOUT,
+            _ => ()
+        }
+    }
+
+    /// Query whether a router running the older version of the protocol for the given
+    /// IP version was heard recently, so that IGMPv1 or MLDv1 reports are sent.
+    pub(crate) fn older_querier(&self, version: IpVersion, timestamp: u64) -> bool {
+        match version {
+            #[cfg(feature = "proto-igmp")]
+            IpVersion::Ipv4 => timestamp < self.igmp_v1_until,
+            #[cfg(feature = "proto-ipv6")]
+            IpVersion::Ipv6 => timestamp < self.mldv1_until,
+            _ => false
+        }
+    }
+
+    /// Return the earliest time a report or a leave message is due at.
+    pub(crate) fn poll_at(&self, timestamp: u64) -> Option<u64> {
+        self.iter()
+            .filter_map(|membership| if membership.leaving {
+                Some(timestamp)
+            } else {
+                membership.report_at
+            })
+            .min()
+    }
+
+    fn find_mut(&mut self, group_addr: IpAddress) -> Option<&mut Membership> {
+        self.storage.iter_mut()
+            .filter_map(|slot| slot.as_mut())
+            .find(|membership| !membership.leaving && membership.group_addr == group_addr)
+    }
+
+    fn random_delay(&mut self, max_delay: u64) -> u64 {
+        // xorshift32; the delays only need to differ between hosts.
+        let mut x = self.rand_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rand_state = x;
+        if max_delay == 0 { 0 } else { x as u64 % max_delay }
+    }
+}
+
+fn version_of(addr: &IpAddress) -> IpVersion {
+    match addr {
+        #[cfg(feature = "proto-ipv4")]
+        &IpAddress::Ipv4(_) => IpVersion::Ipv4,
+        #[cfg(feature = "proto-ipv6")]
+        &IpAddress::Ipv6(_) => IpVersion::Ipv6,
+        _ => IpVersion::Unspecified
+    }
+}
+
+/// An iterator over the memberships in a [Memberships](struct.Memberships.html) table.
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    slots: ::core::slice::Iter<'a, Option<Membership>>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Membership;
+
+    fn next(&mut self) -> Option<&'a Membership> {
+        while let Some(slot) = self.slots.next() {
+            if let Some(ref membership) = *slot {
+                return Some(membership)
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "proto-igmp")]
+    const GROUP_1: IpAddress = IpAddress::Ipv4(::wire::Ipv4Address([224, 0, 0, 251]));
+    #[cfg(feature = "proto-igmp")]
+    const GROUP_2: IpAddress = IpAddress::Ipv4(::wire::Ipv4Address([239, 1, 2, 3]));
+
+    #[test]
+    #[cfg(feature = "proto-igmp")]
+    fn test_unsolicited_reports() {
+        let mut memberships = Memberships::new(vec![]);
+        assert_eq!(memberships.join(GROUP_1, 0), Ok(true));
+        assert_eq!(memberships.join(GROUP_1, 0), Ok(false));
+        assert_eq!(memberships.pending(0), Some((GROUP_1, Report::Join)));
+
+        memberships.reported(GROUP_1, 0);
+        let repeat_at = memberships.poll_at(0).unwrap();
+        assert!(repeat_at < UNSOLICITED_REPORT_INTERVAL);
+        assert_eq!(memberships.pending(repeat_at), Some((GROUP_1, Report::Join)));
+        memberships.reported(GROUP_1, repeat_at);
+        assert_eq!(memberships.poll_at(repeat_at), None);
+
+        // The routers are told that we left the group, since we reported it last.
+        memberships.leave(0, repeat_at);
+        assert_eq!(memberships.pending(repeat_at), Some((GROUP_1, Report::Leave)));
+        memberships.left(GROUP_1);
+        assert_eq!(memberships.iter().count(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-igmp")]
+    fn test_query() {
+        let mut memberships = Memberships::new(vec![]);
+        assert_eq!(memberships.join(GROUP_1, 0), Ok(true));
+        assert_eq!(memberships.join(GROUP_2, 0), Ok(true));
+        for _ in 0..UNSOLICITED_REPORT_COUNT {
+            memberships.reported(GROUP_1, 0);
+            memberships.reported(GROUP_2, 0);
+        }
+        assert_eq!(memberships.poll_at(0), None);
+
+        memberships.query(GROUP_2, 1_000, 100);
+        let report_at = memberships.poll_at(100).unwrap();
+        assert!(report_at >= 100 && report_at < 1_100);
+        assert_eq!(memberships.pending(report_at), Some((GROUP_2, Report::Current)));
+
+        // Another host reports the group first, so we do not.
+        memberships.report_heard(GROUP_2);
+        assert_eq!(memberships.poll_at(100), None);
+
+        // Nor do we tell the routers that we left it.
+        memberships.leave(1, 200);
+        assert_eq!(memberships.pending(200), None);
+        assert_eq!(memberships.iter().count(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-igmp")]
+    fn test_v1_querier() {
+        let mut storage = [None; 1];
+        let mut memberships = Memberships::new(&mut storage[..]);
+        assert_eq!(memberships.join(GROUP_1, 0), Ok(true));
+        assert_eq!(memberships.join(GROUP_2, 0), Err(Error::Exhausted));
+        memberships.reported(GROUP_1, 0);
+
+        memberships.set_older_querier(IpVersion::Ipv4, 0);
+        assert!(memberships.older_querier(IpVersion::Ipv4, V1_ROUTER_PRESENT_TIMEOUT - 1));
+        memberships.leave(0, 1_000);
+        assert_eq!(memberships.pending(1_000), None);
+        assert!(!memberships.older_querier(IpVersion::Ipv4, V1_ROUTER_PRESENT_TIMEOUT));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv6")]
+    fn test_mld() {
+        let group = IpAddress::v6(0xff02, 0, 0, 0, 0, 1, 0xff12, 0x3456);
+        let mut memberships = Memberships::new(vec![]);
+        assert_eq!(memberships.join(group, 0), Ok(true));
+        memberships.reported(group, 0);
+        assert!(memberships.poll_at(0).unwrap() < MLDV2_UNSOLICITED_REPORT_INTERVAL);
+
+        memberships.reported(group, 0);
+        assert_eq!(memberships.poll_at(0), None);
+
+        memberships.set_older_querier(IpVersion::Ipv6, 0);
+        assert!(memberships.older_querier(IpVersion::Ipv6, 0));
+        assert!(!memberships.older_querier(IpVersion::Ipv6, OLDER_VERSION_QUERIER_PRESENT_TIMEOUT));
+
+        // MLDv1 routers are told that the group was left, unlike IGMPv1 routers.
+        memberships.leave(0, 0);
+        assert_eq!(memberships.pending(0), Some((group, Report::Leave)));
+    }
+}
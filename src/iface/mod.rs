@@ -5,10 +5,127 @@ provides lookup and caching of hardware addresses, and handles management packet
 */
 
 mod neighbor;
+mod route;
+mod stats;
+mod identity;
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+mod fragment;
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+mod forward;
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+mod pending;
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+mod loopback;
+#[cfg(feature = "proto-ipv4")]
+mod nat;
+#[cfg(feature = "proto-ipv4")]
+mod acd;
+#[cfg(feature = "proto-ipv4")]
+mod link_local;
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+mod icmp_error;
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+mod echo;
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+mod path_mtu;
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+mod filter;
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+mod compress;
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+mod lifetime;
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+mod virtual_addr;
+#[cfg(any(feature = "proto-igmp", feature = "proto-ipv6"))]
+mod membership;
+#[cfg(any(feature = "proto-ipv6", feature = "socket-raw", feature = "socket-udp",
+          feature = "socket-tcp",
+          all(feature = "socket-icmp", any(feature = "proto-ipv4", feature = "proto-ipv6"))))]
+mod fnv;
+#[cfg(feature = "proto-ipv6")]
+mod flow_label;
+#[cfg(feature = "proto-ipv6")]
+mod slaac;
+#[cfg(feature = "proto-ipv6")]
+mod temp_addr;
+#[cfg(feature = "proto-ipv6")]
+mod router;
+#[cfg(feature = "proto-ipv6")]
+mod source_addr;
+#[cfg(feature = "proto-ipv6")]
+mod dad;
+#[cfg(feature = "proto-sixlowpan")]
+mod sixlowpan;
+#[cfg(feature = "socket-tcp")]
+mod port;
+#[cfg(feature = "socket-tcp")]
+mod tcp_table;
+#[cfg(any(feature = "socket-raw", feature = "socket-udp",
+          all(feature = "socket-icmp", any(feature = "proto-ipv4", feature = "proto-ipv6"))))]
+mod demux;
 mod ethernet;
 
 pub use self::neighbor::Neighbor as Neighbor;
 pub(crate) use self::neighbor::Answer as NeighborAnswer;
 pub use self::neighbor::Cache as NeighborCache;
+pub use self::neighbor::{EvictionPolicy as NeighborEvictionPolicy,
+                         Observer as NeighborObserver, Removal as NeighborRemoval,
+                         Entry as NeighborEntry, Reachability as NeighborReachability,
+                         Iter as NeighborCacheIter};
+pub use self::route::{Route, Routes, Iter as RoutesIter, IterMut as RoutesIterMut};
+pub use self::stats::{Stats as InterfaceStats, Counters as InterfaceCounters};
+pub use self::identity::{Identity, MAX_HOSTNAME_LEN, MAX_VENDOR_CLASS_LEN, MAX_CLIENT_ID_LEN,
+                         MAX_REQUESTED_OPTION_COUNT};
+pub(crate) use self::identity::check_hostname;
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+pub use self::fragment::{FragmentBuffer, FragmentSet, Fragmenter};
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+pub use self::forward::{ForwardBuffer, ForwardQueue};
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+pub use self::pending::{PendingBuffer, PendingQueue, DEFAULT_NEIGHBOR_LIMIT};
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+pub use self::loopback::{LoopbackBuffer, LoopbackQueue};
+#[cfg(feature = "proto-ipv4")]
+pub use self::nat::{Nat, Mapping as NatMapping, Iter as NatIter};
+#[cfg(feature = "proto-ipv4")]
+pub use self::acd::{AcdAddress, AcdState, AddressConflict, ConflictDetector,
+                    Iter as ConflictDetectorIter};
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+pub use self::icmp_error::IcmpErrorPolicy;
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+pub use self::echo::EchoPolicy;
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+pub use self::path_mtu::{PathMtu, PathMtuCache, Iter as PathMtuCacheIter};
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+pub use self::filter::{PacketFilter, Verdict};
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+pub use self::compress::{HeaderCompressor, HeaderCompression, CompressionStats};
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+pub use self::lifetime::{AddressLifetime, AddressLifetimes, Iter as AddressLifetimesIter};
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+pub use self::virtual_addr::{VirtualAddress, VirtualAddressState, VirtualAddresses,
+                             Iter as VirtualAddressesIter};
+#[cfg(any(feature = "proto-igmp", feature = "proto-ipv6"))]
+pub use self::membership::{Membership, Memberships, Iter as MembershipsIter};
+#[cfg(feature = "proto-ipv6")]
+pub use self::flow_label::FlowLabelPolicy;
+#[cfg(feature = "proto-ipv6")]
+pub use self::slaac::{SlaacMode, SlaacAddress};
+#[cfg(feature = "proto-ipv6")]
+pub use self::temp_addr::{TempAddrPolicy, TempAddress};
+#[cfg(feature = "proto-ipv6")]
+pub use self::router::{DefaultRouter, DefaultRouters, Iter as DefaultRoutersIter};
+#[cfg(feature = "proto-ipv6")]
+pub use self::dad::{AddressState, TentativeAddress};
+#[cfg(feature = "proto-sixlowpan")]
+pub use self::sixlowpan::Sixlowpan;
+#[cfg(feature = "socket-tcp")]
+pub use self::port::{PortAllocator, EPHEMERAL_PORT_FIRST, EPHEMERAL_PORT_LAST};
+#[cfg(feature = "socket-tcp")]
+pub use self::tcp_table::{TcpTable, TcpConnection, Iter as TcpTableIter};
+#[cfg(any(feature = "socket-raw", feature = "socket-udp",
+          all(feature = "socket-icmp", any(feature = "proto-ipv4", feature = "proto-ipv6"))))]
+pub use self::demux::{DemuxTable, DemuxKey, Handles as DemuxTableHandles};
 pub use self::ethernet::{Interface as EthernetInterface,
-                         InterfaceBuilder as EthernetInterfaceBuilder};
+                         InterfaceBuilder as EthernetInterfaceBuilder,
+                         PollWork};
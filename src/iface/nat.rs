@@ -0,0 +1,510 @@
+// Heads up! Before working on this file you should read RFC 3022, and the parts
+// of RFC 4787, RFC 5382 and RFC 5508 that discuss the mapping of UDP, TCP and ICMP
+// sessions, and the timeouts of the mappings.
+
+use managed::ManagedSlice;
+
+use {Error, Result};
+use wire::{IpProtocol, Ipv4Address, Ipv4Packet};
+use wire::{TcpPacket, UdpPacket, Icmpv4Packet, Icmpv4Message};
+use wire::ip::checksum;
+
+/// The time after which a mapping of an established TCP connection is removed,
+/// in milliseconds. See RFC 5382 § 5.
+const TCP_ESTABLISHED_TIMEOUT: u64 = 7_440_000;
+/// The time after which a mapping of a TCP connection that is being opened or
+/// closed is removed, in milliseconds. See RFC 5382 § 5.
+const TCP_TRANSITORY_TIMEOUT: u64 = 240_000;
+/// The time after which a mapping of UDP packets is removed, in milliseconds.
+/// See RFC 4787 § 4.3.
+const UDP_TIMEOUT: u64 = 300_000;
+/// The time after which a mapping of ICMP queries is removed, in milliseconds.
+/// See RFC 5508 § 3.2.
+const ICMP_TIMEOUT: u64 = 60_000;
+
+/// An address and a port, or an ICMP query identifier.
+pub(crate) type Endpoint = (Ipv4Address, u16);
+
+/// A mapping of the packets of a connection between a host behind the NAT
+/// and a remote host to an external port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapping {
+    protocol:      IpProtocol,
+    internal:      Endpoint,
+    remote:        Endpoint,
+    external_port: u16,
+    expires_at:    u64,
+}
+
+impl Mapping {
+    /// Return the protocol of the mapped packets.
+    pub fn protocol(&self) -> IpProtocol {
+        self.protocol
+    }
+
+    /// Return the address and port, or ICMP query identifier, of the host behind the NAT.
+    pub fn internal(&self) -> (Ipv4Address, u16) {
+        self.internal
+    }
+
+    /// Return the address and port of the remote host.
+    pub fn remote(&self) -> (Ipv4Address, u16) {
+        self.remote
+    }
+
+    /// Return the port, or ICMP query identifier, the packets are sent from.
+    pub fn external_port(&self) -> u16 {
+        self.external_port
+    }
+}
+
+/// A network address and port translator.
+///
+/// The translator is applied to an interface that packets from other interfaces
+/// are forwarded through, and rewrites the source of the outgoing packets to
+/// the external address and a port that is allocated for the connection. Only
+/// the replies to the mapped connections are forwarded back.
+///
+/// TCP and UDP packets, and ICMP Echo queries are translated. Fragments are not.
+///
+/// # Examples
+///
+/// On systems with heap, this translator can be created with:
+///
+/// ```rust
+/// use smoltcp::iface::Nat;
+/// use smoltcp::wire::Ipv4Address;
+/// let mut nat = Nat::new(Ipv4Address::new(192, 0, 2, 1), vec![]);
+/// ```
+///
+/// On systems without heap, use:
+///
+/// ```rust
+/// use smoltcp::iface::Nat;
+/// use smoltcp::wire::Ipv4Address;
+/// let mut mappings_storage = [None; 64];
+/// let mut nat = Nat::new(Ipv4Address::new(192, 0, 2, 1), &mut mappings_storage[..]);
+/// ```
+#[derive(Debug)]
+pub struct Nat<'a> {
+    external_addr: Ipv4Address,
+    first_port:    u16,
+    last_port:     u16,
+    next_port:     u16,
+    storage:       ManagedSlice<'a, Option<Mapping>>,
+}
+
+impl<'a> Nat<'a> {
+    /// Create a translator to the given external address. The backing storage
+    /// is cleared upon creation.
+    ///
+    /// The ports are allocated from the dynamic range, 49152 to 65535, by default.
+    ///
+    /// # Panics
+    /// This function panics if the external address is not unicast.
+    pub fn new<T>(external_addr: Ipv4Address, storage: T) -> Nat<'a>
+            where T: Into<ManagedSlice<'a, Option<Mapping>>> {
+        assert!(external_addr.is_unicast());
+
+        let mut storage = storage.into();
+        for slot in storage.iter_mut() {
+            *slot = None;
+        }
+
+        Nat { external_addr, first_port: 49152, last_port: 65535, next_port: 49152, storage }
+    }
+
+    /// Return the address the packets are sent from.
+    pub fn external_addr(&self) -> Ipv4Address {
+        self.external_addr
+    }
+
+    /// Set the range of ports, and ICMP query identifiers, that are allocated
+    /// to the connections.
+    ///
+    /// # Panics
+    /// This function panics if the range is empty.
+    pub fn set_ports(&mut self, first_port: u16, last_port: u16) {
+        assert!(first_port <= last_port);
+
+        self.first_port = first_port;
+        self.last_port = last_port;
+        self.next_port = first_port;
+    }
+
+    /// Return an iterator over the mappings that did not expire at the given time.
+    pub fn mappings(&self, timestamp: u64) -> Iter {
+        Iter { slots: self.storage.iter(), timestamp }
+    }
+
+    /// Remove every mapping.
+    pub fn clear(&mut self) {
+        for slot in self.storage.iter_mut() {
+            *slot = None;
+        }
+    }
+
+    /// Map the connection of a valid IPv4 packet that is forwarded through the
+    /// external interface, if it is not mapped yet, and return the source and
+    /// destination the packet is rewritten to.
+    ///
+    /// Returns `Err(Error::Unrecognized)` if the packet cannot be translated, and
+    /// `Err(Error::Exhausted)` if there is no space or port for another mapping.
+    pub(crate) fn map_outbound(&mut self, packet: &[u8],
+                               timestamp: u64) -> Result<(Endpoint, Endpoint)> {
+        let packet = Ipv4Packet::new(packet);
+        if packet.more_frags() || packet.frag_offset() != 0 { return Err(Error::Unrecognized) }
+
+        let (src_port, dst_port, timeout) = ports(&packet, true)?;
+        let protocol = packet.protocol();
+        let internal = (packet.src_addr(), src_port);
+        let remote = (packet.dst_addr(), dst_port);
+
+        let index = match self.find(timestamp, |mapping| mapping.protocol == protocol &&
+                                                         mapping.internal == internal &&
+                                                         mapping.remote == remote) {
+            Some(index) => index,
+            None => {
+                let index = self.map(protocol, internal, remote, timestamp)?;
+                net_trace!("nat: mapped {}:{} to port {}",
+                           internal.0, internal.1, self.storage[index].unwrap().external_port);
+                index
+            }
+        };
+        let mapping = self.storage[index].as_mut().unwrap();
+        mapping.expires_at = timestamp + timeout;
+        Ok(((self.external_addr, mapping.external_port), remote))
+    }
+
+    /// Rewrite the destination of a valid IPv4 packet that is received on the external
+    /// interface, if it belongs to a mapped connection, and return whether it does.
+    pub(crate) fn translate_inbound(&mut self, packet: &mut [u8], timestamp: u64) -> bool {
+        let (index, timeout) = match self.lookup_inbound(&*packet, timestamp) {
+            Some(found) => found,
+            None => return false
+        };
+        let mapping = self.storage[index].as_mut().unwrap();
+        mapping.expires_at = timestamp + timeout;
+
+        rewrite(packet, mapping.remote, mapping.internal);
+        true
+    }
+
+    /// Query whether a valid IPv4 packet that is received on the external interface
+    /// belongs to a mapped connection.
+    pub(crate) fn has_inbound_mapping(&self, packet: &[u8], timestamp: u64) -> bool {
+        self.lookup_inbound(packet, timestamp).is_some()
+    }
+
+    fn lookup_inbound(&self, packet: &[u8], timestamp: u64) -> Option<(usize, u64)> {
+        let packet = Ipv4Packet::new(packet);
+        if packet.dst_addr() != self.external_addr { return None }
+        if packet.more_frags() || packet.frag_offset() != 0 { return None }
+
+        let (src_port, dst_port, timeout) = match ports(&packet, false) {
+            Ok(ports) => ports,
+            Err(_) => return None
+        };
+        let (protocol, remote) = (packet.protocol(), (packet.src_addr(), src_port));
+        self.find(timestamp, |mapping| mapping.protocol == protocol &&
+                                       mapping.external_port == dst_port &&
+                                       mapping.remote == remote)
+            .map(|index| (index, timeout))
+    }
+
+    fn find<F>(&self, timestamp: u64, f: F) -> Option<usize>
+            where F: Fn(&Mapping) -> bool {
+        self.storage.iter().position(|slot| match *slot {
+            Some(ref mapping) => mapping.expires_at > timestamp && f(mapping),
+            None => false
+        })
+    }
+
+    /// Allocate a port to a connection, preserving the internal port if possible,
+    /// and return the index of the new mapping.
+    fn map(&mut self, protocol: IpProtocol, internal: Endpoint, remote: Endpoint,
+           timestamp: u64) -> Result<usize> {
+        let in_use = |nat: &Nat, port: u16| nat.find(timestamp, |mapping|
+            mapping.protocol == protocol && mapping.external_port == port).is_some();
+
+        let external_port =
+            if internal.1 >= self.first_port && internal.1 <= self.last_port &&
+                    !in_use(self, internal.1) {
+                internal.1
+            } else {
+                let count = (self.last_port - self.first_port) as u32 + 1;
+                let mut found = None;
+                for _ in 0..count {
+                    let port = self.next_port;
+                    self.next_port = if port == self.last_port { self.first_port }
+                                     else { port + 1 };
+                    if !in_use(self, port) {
+                        found = Some(port);
+                        break
+                    }
+                }
+                found.ok_or(Error::Exhausted)?
+            };
+
+        let mapping = Mapping { protocol, internal, remote, external_port, expires_at: 0 };
+        let free = self.storage.iter().position(|slot| match *slot {
+            Some(ref mapping) => mapping.expires_at <= timestamp,
+            None => true
+        });
+        match free {
+            Some(index) => {
+                self.storage[index] = Some(mapping);
+                Ok(index)
+            }
+            None => match self.storage {
+                ManagedSlice::Borrowed(_) => Err(Error::Exhausted),
+                #[cfg(any(feature = "std", feature = "alloc"))]
+                ManagedSlice::Owned(ref mut mappings) => {
+                    mappings.push(Some(mapping));
+                    Ok(mappings.len() - 1)
+                }
+            }
+        }
+    }
+}
+
+/// An iterator over the mappings of a [Nat](struct.Nat.html).
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    slots:     ::core::slice::Iter<'a, Option<Mapping>>,
+    timestamp: u64,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Mapping;
+
+    fn next(&mut self) -> Option<&'a Mapping> {
+        while let Some(slot) = self.slots.next() {
+            match *slot {
+                Some(ref mapping) if mapping.expires_at > self.timestamp =>
+                    return Some(mapping),
+                _ => ()
+            }
+        }
+        None
+    }
+}
+
+/// Return the source and destination ports of an outbound or inbound packet that can
+/// be translated, and the timeout of its mapping. ICMP Echo requests are sent from
+/// their identifier, and the replies are sent to it.
+fn ports(packet: &Ipv4Packet<&[u8]>, outbound: bool) -> Result<(u16, u16, u64)> {
+    match packet.protocol() {
+        IpProtocol::Tcp => {
+            let tcp_packet = TcpPacket::new_checked(packet.payload())?;
+            let timeout = if tcp_packet.syn() || tcp_packet.fin() || tcp_packet.rst() {
+                TCP_TRANSITORY_TIMEOUT
+            } else {
+                TCP_ESTABLISHED_TIMEOUT
+            };
+            Ok((tcp_packet.src_port(), tcp_packet.dst_port(), timeout))
+        }
+        IpProtocol::Udp => {
+            let udp_packet = UdpPacket::new_checked(packet.payload())?;
+            Ok((udp_packet.src_port(), udp_packet.dst_port(), UDP_TIMEOUT))
+        }
+        IpProtocol::Icmp => {
+            let icmp_packet = Icmpv4Packet::new_checked(packet.payload())?;
+            match (icmp_packet.msg_type(), outbound) {
+                (Icmpv4Message::EchoRequest, true) =>
+                    Ok((icmp_packet.echo_ident(), 0, ICMP_TIMEOUT)),
+                (Icmpv4Message::EchoReply, false) =>
+                    Ok((0, icmp_packet.echo_ident(), ICMP_TIMEOUT)),
+                _ => Err(Error::Unrecognized)
+            }
+        }
+        _ => Err(Error::Unrecognized)
+    }
+}
+
+/// Rewrite the source and destination of a packet that can be translated, and update
+/// its checksums incrementally. The IPv4 header checksum is filled when the packet
+/// is sent.
+pub(crate) fn rewrite(packet: &mut [u8], src: Endpoint, dst: Endpoint) {
+    let mut packet = Ipv4Packet::new(packet);
+    let old_addrs = (packet.src_addr(), packet.dst_addr());
+    packet.set_src_addr(src.0);
+    packet.set_dst_addr(dst.0);
+
+    let protocol = packet.protocol();
+    rewrite_payload(protocol, packet.payload_mut(), old_addrs, src, dst)
+}
+
+/// Rewrite the ports of the payload of a datagram that can be translated, and update
+/// its checksum incrementally, given the addresses the datagram was sent between.
+pub(crate) fn rewrite_payload(protocol: IpProtocol, payload: &mut [u8],
+                              old_addrs: (Ipv4Address, Ipv4Address),
+                              src: Endpoint, dst: Endpoint) {
+    let mut old_bytes = [0; 8];
+    old_bytes[..4].copy_from_slice(old_addrs.0.as_bytes());
+    old_bytes[4..].copy_from_slice(old_addrs.1.as_bytes());
+    let mut new_bytes = [0; 8];
+    new_bytes[..4].copy_from_slice(src.0.as_bytes());
+    new_bytes[4..].copy_from_slice(dst.0.as_bytes());
+    let (old_addrs, new_addrs) = (&old_bytes[..], &new_bytes[..]);
+
+    match protocol {
+        IpProtocol::Tcp => {
+            let mut tcp_packet = TcpPacket::new(payload);
+            let mut sum = checksum::update_data(tcp_packet.checksum(), old_addrs, new_addrs);
+            sum = checksum::update(sum, tcp_packet.src_port(), src.1);
+            sum = checksum::update(sum, tcp_packet.dst_port(), dst.1);
+            tcp_packet.set_src_port(src.1);
+            tcp_packet.set_dst_port(dst.1);
+            tcp_packet.set_checksum(sum);
+        }
+        IpProtocol::Udp => {
+            let mut udp_packet = UdpPacket::new(payload);
+            // A zero checksum means that the checksum was not computed by the sender.
+            if udp_packet.checksum() != 0 {
+                let mut sum = checksum::update_data(udp_packet.checksum(),
+                                                    old_addrs, new_addrs);
+                sum = checksum::update(sum, udp_packet.src_port(), src.1);
+                sum = checksum::update(sum, udp_packet.dst_port(), dst.1);
+                udp_packet.set_checksum(if sum == 0 { 0xffff } else { sum });
+            }
+            udp_packet.set_src_port(src.1);
+            udp_packet.set_dst_port(dst.1);
+        }
+        IpProtocol::Icmp => {
+            // ICMP checksums do not cover a pseudo header.
+            let mut icmp_packet = Icmpv4Packet::new(payload);
+            let ident = if icmp_packet.msg_type() == Icmpv4Message::EchoRequest {
+                src.1
+            } else {
+                dst.1
+            };
+            let sum = checksum::update(icmp_packet.checksum(), icmp_packet.echo_ident(), ident);
+            icmp_packet.set_echo_ident(ident);
+            icmp_packet.set_checksum(sum);
+        }
+        _ => unreachable!()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use super::*;
+    use phy::ChecksumCapabilities;
+    use wire::{IpAddress, Ipv4Repr, UdpRepr, Icmpv4Repr};
+
+    const INTERNAL_ADDR: Ipv4Address = Ipv4Address([10, 0, 0, 2]);
+    const EXTERNAL_ADDR: Ipv4Address = Ipv4Address([192, 0, 2, 1]);
+    const REMOTE_ADDR:   Ipv4Address = Ipv4Address([198, 51, 100, 7]);
+
+    fn udp_packet(src: Endpoint, dst: Endpoint) -> Vec<u8> {
+        let udp_repr = UdpRepr { src_port: src.1, dst_port: dst.1, payload: &[0xa5; 5] };
+        let ip_repr = Ipv4Repr {
+            src_addr:    src.0,
+            dst_addr:    dst.0,
+            protocol:    IpProtocol::Udp,
+            payload_len: udp_repr.buffer_len(),
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0
+        };
+        let mut bytes = vec![0; ip_repr.buffer_len() + udp_repr.buffer_len()];
+        let mut packet = Ipv4Packet::new(&mut bytes);
+        ip_repr.emit(&mut packet, &ChecksumCapabilities::default());
+        udp_repr.emit(&mut UdpPacket::new(packet.payload_mut()),
+                      &src.0.into(), &dst.0.into(), &ChecksumCapabilities::default());
+        bytes
+    }
+
+    fn translate_outbound(nat: &mut Nat, packet: &mut [u8], timestamp: u64) -> Result<()> {
+        let (src, dst) = nat.map_outbound(packet, timestamp)?;
+        rewrite(packet, src, dst);
+        Ok(())
+    }
+
+    fn assert_udp(bytes: &[u8], src: Endpoint, dst: Endpoint) {
+        let packet = Ipv4Packet::new(bytes);
+        assert_eq!((packet.src_addr(), packet.dst_addr()), (src.0, dst.0));
+        let udp_packet = UdpPacket::new(packet.payload());
+        assert_eq!((udp_packet.src_port(), udp_packet.dst_port()), (src.1, dst.1));
+        assert!(udp_packet.verify_checksum(&IpAddress::Ipv4(src.0), &IpAddress::Ipv4(dst.0)));
+    }
+
+    #[test]
+    fn test_udp() {
+        let mut nat = Nat::new(EXTERNAL_ADDR, vec![]);
+        let mut outbound = udp_packet((INTERNAL_ADDR, 1234), (REMOTE_ADDR, 53));
+        assert_eq!(translate_outbound(&mut nat, &mut outbound, 0), Ok(()));
+        assert_udp(&outbound, (EXTERNAL_ADDR, 49152), (REMOTE_ADDR, 53));
+
+        // The packets of a connection share the mapping.
+        let mut outbound = udp_packet((INTERNAL_ADDR, 1234), (REMOTE_ADDR, 53));
+        assert_eq!(translate_outbound(&mut nat, &mut outbound, 0), Ok(()));
+        assert_udp(&outbound, (EXTERNAL_ADDR, 49152), (REMOTE_ADDR, 53));
+        assert_eq!(nat.mappings(0).count(), 1);
+
+        let mut inbound = udp_packet((REMOTE_ADDR, 53), (EXTERNAL_ADDR, 49152));
+        assert!(nat.translate_inbound(&mut inbound, 1000));
+        assert_udp(&inbound, (REMOTE_ADDR, 53), (INTERNAL_ADDR, 1234));
+
+        // Only the replies from the remote endpoint are translated.
+        let inbound = udp_packet((REMOTE_ADDR, 54), (EXTERNAL_ADDR, 49152));
+        assert!(!nat.has_inbound_mapping(&inbound, 1000));
+
+        let inbound = udp_packet((REMOTE_ADDR, 53), (EXTERNAL_ADDR, 49152));
+        assert!(!nat.has_inbound_mapping(&inbound, 1000 + UDP_TIMEOUT));
+        assert_eq!(nat.mappings(1000 + UDP_TIMEOUT).count(), 0);
+    }
+
+    #[test]
+    fn test_port_allocation() {
+        let mut nat = Nat::new(EXTERNAL_ADDR, vec![]);
+        nat.set_ports(50000, 50001);
+
+        // The internal port is preserved if possible.
+        let mut outbound = udp_packet((INTERNAL_ADDR, 50001), (REMOTE_ADDR, 53));
+        assert_eq!(translate_outbound(&mut nat, &mut outbound, 0), Ok(()));
+        assert_udp(&outbound, (EXTERNAL_ADDR, 50001), (REMOTE_ADDR, 53));
+
+        let mut outbound = udp_packet((Ipv4Address::new(10, 0, 0, 3), 50001), (REMOTE_ADDR, 53));
+        assert_eq!(translate_outbound(&mut nat, &mut outbound, 0), Ok(()));
+        assert_udp(&outbound, (EXTERNAL_ADDR, 50000), (REMOTE_ADDR, 53));
+
+        let mut outbound = udp_packet((Ipv4Address::new(10, 0, 0, 4), 50001), (REMOTE_ADDR, 53));
+        assert_eq!(translate_outbound(&mut nat, &mut outbound, 0), Err(Error::Exhausted));
+
+        // The ports of expired mappings are reused.
+        assert_eq!(translate_outbound(&mut nat, &mut outbound, UDP_TIMEOUT), Ok(()));
+        assert_eq!(nat.mappings(UDP_TIMEOUT).count(), 1);
+    }
+
+    #[test]
+    fn test_icmp_echo() {
+        let echo_repr = Icmpv4Repr::EchoRequest { ident: 0x1234, seq_no: 1, data: &[0xa5; 4] };
+        let ip_repr = Ipv4Repr {
+            src_addr:    INTERNAL_ADDR,
+            dst_addr:    REMOTE_ADDR,
+            protocol:    IpProtocol::Icmp,
+            payload_len: echo_repr.buffer_len(),
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0
+        };
+        let caps = ChecksumCapabilities::default();
+        let mut bytes = vec![0; ip_repr.buffer_len() + echo_repr.buffer_len()];
+        {
+            let mut packet = Ipv4Packet::new(&mut bytes);
+            ip_repr.emit(&mut packet, &caps);
+            echo_repr.emit(&mut Icmpv4Packet::new(packet.payload_mut()), &caps);
+        }
+
+        let mut nat = Nat::new(EXTERNAL_ADDR, vec![]);
+        assert_eq!(translate_outbound(&mut nat, &mut bytes, 0), Ok(()));
+        let packet = Ipv4Packet::new(&bytes);
+        assert_eq!(packet.src_addr(), EXTERNAL_ADDR);
+        let icmp_packet = Icmpv4Packet::new(packet.payload());
+        assert_eq!(icmp_packet.echo_ident(), 49152);
+        assert!(icmp_packet.verify_checksum());
+    }
+}
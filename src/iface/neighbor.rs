@@ -1,18 +1,161 @@
 // Heads up! Before working on this file you should read, at least,
-// the parts of RFC 1122 that discuss ARP.
+// the parts of RFC 1122 that discuss ARP, and the parts of RFC 4861 that
+// discuss Neighbor Unreachability Detection (§ 7.3).
 
+use core::{fmt, slice};
 use managed::ManagedMap;
+#[cfg(feature = "std")]
+use std::collections::btree_map;
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::btree_map;
 
+use {Error, Result};
 use wire::{EthernetAddress, IpAddress};
 
+/// The reachability state of a cached neighbor. See RFC 4861 § 7.3.2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// The hardware address is being resolved; the next request may be sent
+    /// at the given moment.
+    Incomplete { retry_at: u64 },
+    /// The neighbor was recently confirmed to be reachable, until the given moment.
+    Reachable { until: u64 },
+    /// The neighbor is not known to be reachable, but nothing was sent to it since.
+    Stale,
+    /// Something was sent to the neighbor; unless its reachability is confirmed
+    /// by an upper layer, it is probed starting at the given moment.
+    Delay { probe_at: u64 },
+    /// The neighbor is being probed with unicast requests; the next one is sent
+    /// at the given moment.
+    Probe { probe_at: u64, probes_sent: u8 },
+    /// The neighbor was added by the application; it is never probed, replaced
+    /// or evicted.
+    Permanent,
+}
+
 /// A cached neighbor.
 ///
 /// A neighbor mapping translates from a protocol address to a hardware address,
-/// and contains the timestamp past which the mapping should be discarded.
+/// and tracks whether the neighbor is still reachable at that hardware address.
 #[derive(Debug, Clone, Copy)]
 pub struct Neighbor {
     hardware_addr: EthernetAddress,
-    expires_at:    u64,
+    state:         State,
+    updated_at:    u64,
+    used_at:       u64,
+}
+
+impl Neighbor {
+    /// Return the hardware address, unless it is not resolved yet, or every probe
+    /// of the neighbor went unanswered.
+    fn hardware_addr(&self, timestamp: u64) -> Option<EthernetAddress> {
+        match self.state {
+            State::Incomplete { .. } => None,
+            State::Probe { probe_at, probes_sent }
+                if probes_sent >= Cache::MAX_UNICAST_SOLICIT && timestamp >= probe_at => None,
+            _ => Some(self.hardware_addr)
+        }
+    }
+
+    /// Query whether the hardware address of the neighbor was ever resolved.
+    fn is_resolved(&self) -> bool {
+        match self.state {
+            State::Incomplete { .. } => false,
+            _ => true
+        }
+    }
+
+    /// Return the key by which the neighbors are ordered for eviction according to
+    /// the given policy, the lowest first, or `None` if the neighbor is never evicted.
+    /// Pending resolutions are always evicted first.
+    fn eviction_key(&self, policy: EvictionPolicy) -> Option<(bool, u64, u64)> {
+        let resolved = self.is_resolved();
+        match (policy, self.state) {
+            (_, State::Permanent) => None,
+            (EvictionPolicy::LeastRecentlyUsed, _) =>
+                Some((resolved, self.used_at, 0)),
+            (EvictionPolicy::EarliestExpiry, State::Reachable { until }) =>
+                Some((resolved, until, self.updated_at)),
+            (EvictionPolicy::EarliestExpiry, _) =>
+                Some((resolved, 0, self.updated_at)),
+        }
+    }
+}
+
+/// The reachability of a cached neighbor, as reported by
+/// [Cache::iter](struct.Cache.html#method.iter).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reachability {
+    /// The hardware address is being resolved.
+    Incomplete,
+    /// The neighbor was recently confirmed to be reachable.
+    Reachable,
+    /// The neighbor is not known to be reachable, and will be probed once something
+    /// is sent to it.
+    Stale,
+    /// The neighbor will be probed soon, unless its reachability is confirmed.
+    Delay,
+    /// The neighbor is being probed with unicast requests.
+    Probe,
+    /// The neighbor did not answer any probe, and will be removed.
+    Unreachable,
+    /// The neighbor was added by the application.
+    Permanent,
+}
+
+impl fmt::Display for Reachability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Reachability::Incomplete  => write!(f, "INCOMPLETE"),
+            Reachability::Reachable   => write!(f, "REACHABLE"),
+            Reachability::Stale       => write!(f, "STALE"),
+            Reachability::Delay       => write!(f, "DELAY"),
+            Reachability::Probe       => write!(f, "PROBE"),
+            Reachability::Unreachable => write!(f, "FAILED"),
+            Reachability::Permanent   => write!(f, "PERMANENT"),
+        }
+    }
+}
+
+/// A cached neighbor, as reported by [Cache::iter](struct.Cache.html#method.iter),
+/// e.g. to show it in the same way as `ip neigh` or `arp -a` do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    /// The protocol address of the neighbor.
+    pub protocol_addr: IpAddress,
+    /// The hardware address of the neighbor, or `None` if it is not resolved yet.
+    pub hardware_addr: Option<EthernetAddress>,
+    /// The reachability of the neighbor.
+    pub state:         Reachability,
+    /// The time since the neighbor was added or last updated, in milliseconds, or `None`
+    /// if it was added by the application.
+    pub age:           Option<u64>,
+}
+
+impl Entry {
+    fn new(protocol_addr: IpAddress, neighbor: &Neighbor, timestamp: u64) -> Entry {
+        let state = match neighbor.state {
+            State::Incomplete { .. } => Reachability::Incomplete,
+            State::Reachable { until } if timestamp < until => Reachability::Reachable,
+            State::Reachable { .. } | State::Stale => Reachability::Stale,
+            State::Delay { .. } => Reachability::Delay,
+            State::Probe { .. } if neighbor.hardware_addr(timestamp).is_none() =>
+                Reachability::Unreachable,
+            State::Probe { .. } => Reachability::Probe,
+            State::Permanent => Reachability::Permanent,
+        };
+        let age = match neighbor.state {
+            State::Permanent => None,
+            _ => Some(timestamp.saturating_sub(neighbor.updated_at))
+        };
+        Entry {
+            protocol_addr,
+            hardware_addr: if neighbor.is_resolved() { Some(neighbor.hardware_addr) }
+                           else { None },
+            state,
+            age
+        }
+    }
 }
 
 /// An answer to a neighbor cache lookup.
@@ -27,8 +170,53 @@ pub(crate) enum Answer {
     RateLimited
 }
 
+/// The choice of the neighbor that is evicted when a new one does not fit into
+/// a full cache. Pending resolutions are evicted first with either policy, and
+/// the neighbors added by the application are never evicted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Evict the neighbor that was least recently sent something to, or updated.
+    LeastRecentlyUsed,
+    /// Evict a neighbor that is not known to be reachable, the least recently updated
+    /// one first; otherwise, the neighbor whose reachability expires first.
+    EarliestExpiry,
+}
+
+/// The reason a neighbor was removed from a cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Removal {
+    /// The neighbor was evicted to make room for another one.
+    Evicted,
+    /// The neighbor did not answer any probe.
+    Unreachable,
+    /// The neighbor was removed by the application.
+    Removed,
+}
+
+/// A hook a neighbor cache calls when neighbors are added and removed, e.g. to log
+/// them, or to mirror them elsewhere.
+///
+/// Only neighbors whose hardware address is resolved are reported. Every method does
+/// nothing by default, so an implementation only has to provide the ones it needs.
+pub trait Observer {
+    /// Called when a neighbor is added to the cache, or its hardware address changes.
+    fn added(&mut self, _protocol_addr: IpAddress, _hardware_addr: EthernetAddress) {}
+
+    /// Called when a neighbor is removed from the cache for the given reason.
+    fn removed(&mut self, _protocol_addr: IpAddress, _hardware_addr: EthernetAddress,
+               _reason: Removal) {}
+}
+
 /// A neighbor cache backed by a map.
 ///
+/// The reachability of the neighbors is tracked as described in RFC 4861 § 7.3,
+/// for IPv4 neighbors as well as IPv6 ones. A neighbor that has not been confirmed
+/// to be reachable recently is still used, but it is probed, and removed from
+/// the cache if it does not answer.
+///
+/// Neighbors can also be added by the application, e.g. peers known in advance;
+/// they are used as long as they are not removed.
+///
 /// # Examples
 ///
 /// On systems with heap, this cache can be created with:
@@ -46,18 +234,40 @@ pub(crate) enum Answer {
 /// let mut neighbor_cache_storage = [None; 8];
 /// let mut neighbor_cache = NeighborCache::new(&mut neighbor_cache_storage[..]);
 /// ```
-#[derive(Debug)]
 pub struct Cache<'a> {
-    storage:      ManagedMap<'a, IpAddress, Neighbor>,
-    silent_until: u64,
+    storage:         ManagedMap<'a, IpAddress, Neighbor>,
+    eviction_policy: EvictionPolicy,
+    max_len:         Option<usize>,
+    observer:        Option<&'a mut Observer>,
+}
+
+impl<'a> fmt::Debug for Cache<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cache")
+         .field("storage", &self.storage)
+         .field("eviction_policy", &self.eviction_policy)
+         .field("max_len", &self.max_len)
+         .finish()
+    }
 }
 
 impl<'a> Cache<'a> {
     /// Minimum delay between discovery requests, in milliseconds.
     pub(crate) const SILENT_TIME: u64 = 1_000;
 
-    /// Neighbor entry lifetime, in milliseconds.
-    pub(crate) const ENTRY_LIFETIME: u64 = 60_000;
+    /// Time for which a neighbor is considered reachable after a confirmation,
+    /// in milliseconds.
+    pub(crate) const REACHABLE_TIME: u64 = 30_000;
+
+    /// Delay between sending something to a neighbor that is not known to be reachable
+    /// and probing it, in milliseconds.
+    pub(crate) const DELAY_FIRST_PROBE_TIME: u64 = 5_000;
+
+    /// Delay between unicast probes, in milliseconds.
+    pub(crate) const RETRANS_TIMER: u64 = 1_000;
+
+    /// Number of unanswered unicast probes after which a neighbor is removed.
+    pub(crate) const MAX_UNICAST_SOLICIT: u8 = 3;
 
     /// Create a cache. The backing storage is cleared upon creation.
     ///
@@ -68,59 +278,263 @@ impl<'a> Cache<'a> {
         let mut storage = storage.into();
         storage.clear();
 
-        Cache { storage, silent_until: 0 }
+        Cache {
+            storage,
+            eviction_policy: EvictionPolicy::LeastRecentlyUsed,
+            max_len:         None,
+            observer:        None
+        }
+    }
+
+    /// Return an iterator over the neighbors in the cache at the given moment, including
+    /// those being resolved, with their reachability state and age.
+    ///
+    /// The iterator does not change the cache; in particular, the neighbors that did not
+    /// answer any probe are reported until the interface is polled and removes them.
+    pub fn iter(&self, timestamp: u64) -> Iter {
+        let lower = match self.storage {
+            ManagedMap::Borrowed(ref pairs) =>
+                IterLower::Borrowed(pairs.iter()),
+            #[cfg(any(feature = "std", feature = "alloc"))]
+            ManagedMap::Owned(ref map) =>
+                IterLower::Owned(map.iter())
+        };
+        Iter { lower, timestamp }
+    }
+
+    /// Return the number of neighbors in the cache, including those being resolved.
+    pub fn len(&self) -> usize {
+        match self.storage {
+            ManagedMap::Borrowed(ref pairs) =>
+                pairs.iter().filter(|pair| pair.is_some()).count(),
+            #[cfg(any(feature = "std", feature = "alloc"))]
+            ManagedMap::Owned(ref map) =>
+                map.len()
+        }
+    }
+
+    /// Query whether there are no neighbors in the cache.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
+    /// Return the eviction policy.
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        self.eviction_policy
+    }
+
+    /// Set the eviction policy.
+    ///
+    /// By default, the least recently used neighbor is evicted.
+    pub fn set_eviction_policy(&mut self, policy: EvictionPolicy) {
+        self.eviction_policy = policy
+    }
+
+    /// Return the maximum number of neighbors in a cache backed by an owned map, if any.
+    pub fn max_len(&self) -> Option<usize> {
+        self.max_len
+    }
+
+    /// Set the maximum number of neighbors in a cache backed by an owned map, evicting
+    /// the neighbors that do not fit anymore according to the eviction policy.
+    ///
+    /// By default, such a cache grows as needed.
+    #[cfg(any(feature = "std", feature = "alloc"))]
+    pub fn set_max_len(&mut self, max_len: Option<usize>) {
+        self.max_len = max_len;
+        if let Some(max_len) = max_len {
+            while self.len() > max_len {
+                match self.victim() {
+                    Some(protocol_addr) => self.remove_neighbor(&protocol_addr, Removal::Evicted),
+                    None => break
+                }
+            }
+        }
+    }
+
+    /// Set the hook that is told about added and removed neighbors, and return
+    /// the previous one.
+    pub fn set_observer(&mut self, observer: Option<&'a mut Observer>) ->
+                       Option<&'a mut Observer> {
+        ::core::mem::replace(&mut self.observer, observer)
+    }
+
+    /// Add a neighbor that is always used, replacing any neighbor with the same
+    /// protocol address; it is never probed, replaced or evicted until it is removed.
+    ///
+    /// Returns `Err(Error::Unaddressable)` if either address is not a unicast address,
+    /// and `Err(Error::Exhausted)` if the cache is full of such neighbors.
+    pub fn add_static(&mut self, protocol_addr: IpAddress,
+                      hardware_addr: EthernetAddress) -> Result<()> {
+        if !protocol_addr.is_unicast() || !hardware_addr.is_unicast() {
+            return Err(Error::Unaddressable)
+        }
+
+        let inserted = self.insert(protocol_addr, Neighbor {
+            hardware_addr, state: State::Permanent, updated_at: 0, used_at: 0
+        });
+        if inserted { Ok(()) } else { Err(Error::Exhausted) }
+    }
+
+    /// Query whether the neighbor with the given protocol address was added
+    /// with [add_static](#method.add_static).
+    pub fn is_static(&self, protocol_addr: &IpAddress) -> bool {
+        self.storage.get(protocol_addr)
+            .map_or(false, |neighbor| neighbor.state == State::Permanent)
+    }
+
+    /// Remove the neighbor with the given protocol address, whether it was added
+    /// by the application or not, and return whether there was one.
+    pub fn remove(&mut self, protocol_addr: &IpAddress) -> bool {
+        if self.storage.get(protocol_addr).is_none() { return false }
+
+        self.remove_neighbor(protocol_addr, Removal::Removed);
+        true
+    }
+
+    /// Fill the cache from a packet that does not confirm the reachability of
+    /// the neighbor, e.g. any packet it has sent.
+    ///
+    /// A new or changed hardware address is not trusted until it is used and
+    /// confirmed, or probed.
     pub(crate) fn fill(&mut self, protocol_addr: IpAddress, hardware_addr: EthernetAddress,
                        timestamp: u64) {
+        if let Some(neighbor) = self.storage.get(&protocol_addr) {
+            if neighbor.state == State::Permanent ||
+                    neighbor.hardware_addr(timestamp) == Some(hardware_addr) {
+                return
+            }
+        }
+
+        self.insert(protocol_addr, Neighbor {
+            hardware_addr, state: State::Stale, updated_at: timestamp, used_at: timestamp
+        });
+    }
+
+    /// Fill the cache from an answer to a request, which confirms the reachability
+    /// of the neighbor.
+    pub(crate) fn reach(&mut self, protocol_addr: IpAddress, hardware_addr: EthernetAddress,
+                        timestamp: u64) {
+        if self.is_static(&protocol_addr) { return }
+
+        self.insert(protocol_addr, Neighbor {
+            hardware_addr,
+            state:      State::Reachable { until: timestamp + Self::REACHABLE_TIME },
+            updated_at: timestamp,
+            used_at:    timestamp
+        });
+    }
+
+    /// Confirm the reachability of a neighbor through the progress of an upper layer
+    /// protocol, e.g. the acknowledgement of new TCP data.
+    pub(crate) fn confirm(&mut self, protocol_addr: &IpAddress, timestamp: u64) {
+        if let Some(neighbor) = self.storage.get_mut(protocol_addr) {
+            match neighbor.state {
+                State::Incomplete { .. } | State::Permanent => return,
+                _ => ()
+            }
+
+            neighbor.state = State::Reachable { until: timestamp + Self::REACHABLE_TIME };
+            neighbor.updated_at = timestamp;
+        }
+    }
+
+    /// Insert a neighbor, evicting another one if the cache is full, and return
+    /// whether there was space for it.
+    fn insert(&mut self, protocol_addr: IpAddress, neighbor: Neighbor) -> bool {
         debug_assert!(protocol_addr.is_unicast());
-        debug_assert!(hardware_addr.is_unicast());
+        debug_assert!(neighbor.hardware_addr.is_unicast());
+
+        let hardware_addr = neighbor.hardware_addr;
+        let old_neighbor = self.storage.get(&protocol_addr).cloned();
+        if old_neighbor.is_none() && self.is_full() {
+            match self.victim() {
+                Some(old_protocol_addr) => {
+                    net_trace!("evicting {} to make room for {}",
+                               old_protocol_addr, protocol_addr);
+                    self.remove_neighbor(&old_protocol_addr, Removal::Evicted)
+                }
+                None => {
+                    net_debug!("no space for neighbor {}", protocol_addr);
+                    return false
+                }
+            }
+        }
 
-        let neighbor = Neighbor {
-            expires_at: timestamp + Self::ENTRY_LIFETIME, hardware_addr
-        };
         match self.storage.insert(protocol_addr, neighbor) {
-            Ok(Some(old_neighbor)) => {
-                if old_neighbor.hardware_addr != hardware_addr {
-                    net_trace!("replaced {} => {} (was {})",
-                               protocol_addr, hardware_addr, old_neighbor.hardware_addr);
-                }
+            Ok(_) => (),
+            // There is space for the neighbor, as ensured above.
+            Err(_) => unreachable!()
+        }
+
+        match old_neighbor {
+            Some(ref old_neighbor) if old_neighbor.hardware_addr != hardware_addr => {
+                net_trace!("replaced {} => {} (was {})",
+                           protocol_addr, hardware_addr, old_neighbor.hardware_addr);
             }
-            Ok(None) => {
+            None => {
                 net_trace!("filled {} => {} (was empty)", protocol_addr, hardware_addr);
             }
-            Err((protocol_addr, neighbor)) => {
-                // If we're going down this branch, it means that a fixed-size cache storage
-                // is full, and we need to evict an entry.
-                let old_protocol_addr = match self.storage {
-                    ManagedMap::Borrowed(ref mut pairs) => {
-                        pairs
-                            .iter()
-                            .min_by_key(|pair_opt| {
-                                let (_protocol_addr, neighbor) = pair_opt.unwrap();
-                                neighbor.expires_at
-                            })
-                            .expect("empty neighbor cache storage") // unwraps min_by_key
-                            .unwrap() // unwraps pair
-                            .0
-                    }
-                    // Owned maps can extend themselves.
-                    #[cfg(any(feature = "std", feature = "alloc"))]
-                    ManagedMap::Owned(_) => unreachable!()
-                };
+            _ => ()
+        }
 
-                let _old_neighbor =
-                    self.storage.remove(&old_protocol_addr).unwrap();
-                match self.storage.insert(protocol_addr, neighbor) {
-                    Ok(None) => {
-                        net_trace!("filled {} => {} (evicted {} => {})",
-                                   protocol_addr, hardware_addr,
-                                   old_protocol_addr, _old_neighbor.hardware_addr);
-                    }
-                    // We've covered everything else above.
-                    _ => unreachable!()
+        if let Some(ref mut observer) = self.observer {
+            let was_resolved = old_neighbor.map_or(false, |old| old.is_resolved());
+            if neighbor.is_resolved() {
+                let changed = old_neighbor.map_or(true, |old| old.hardware_addr != hardware_addr);
+                if !was_resolved || changed {
+                    observer.added(protocol_addr, hardware_addr)
                 }
+            } else if was_resolved {
+                let old_hardware_addr = old_neighbor.unwrap().hardware_addr;
+                observer.removed(protocol_addr, old_hardware_addr, Removal::Unreachable)
+            }
+        }
+        true
+    }
 
+    /// Query whether a new neighbor does not fit into the cache without evicting another.
+    fn is_full(&self) -> bool {
+        match self.storage {
+            ManagedMap::Borrowed(ref pairs) =>
+                pairs.iter().all(|pair| pair.is_some()),
+            #[cfg(any(feature = "std", feature = "alloc"))]
+            ManagedMap::Owned(ref map) =>
+                self.max_len.map_or(false, |max_len| map.len() >= max_len)
+        }
+    }
+
+    /// Return the protocol address of the neighbor that has to be evicted first
+    /// according to the eviction policy, if any may be.
+    fn victim(&self) -> Option<IpAddress> {
+        let policy = self.eviction_policy;
+        match self.storage {
+            ManagedMap::Borrowed(ref pairs) => {
+                pairs.iter()
+                     .filter_map(|pair| pair.as_ref())
+                     .filter_map(|&(protocol_addr, ref neighbor)|
+                         neighbor.eviction_key(policy).map(|key| (key, protocol_addr)))
+                     .min_by_key(|&(key, _)| key)
+                     .map(|(_, protocol_addr)| protocol_addr)
+            }
+            #[cfg(any(feature = "std", feature = "alloc"))]
+            ManagedMap::Owned(ref map) => {
+                map.iter()
+                   .filter_map(|(&protocol_addr, neighbor)|
+                       neighbor.eviction_key(policy).map(|key| (key, protocol_addr)))
+                   .min_by_key(|&(key, _)| key)
+                   .map(|(_, protocol_addr)| protocol_addr)
+            }
+        }
+    }
+
+    /// Remove a neighbor, and tell the observer about it if it was resolved.
+    fn remove_neighbor(&mut self, protocol_addr: &IpAddress, reason: Removal) {
+        if let Some(neighbor) = self.storage.remove(protocol_addr) {
+            if let Some(ref mut observer) = self.observer {
+                if neighbor.is_resolved() {
+                    observer.removed(*protocol_addr, neighbor.hardware_addr, reason)
+                }
             }
         }
     }
@@ -131,34 +545,167 @@ impl<'a> Cache<'a> {
             return Some(EthernetAddress::BROADCAST)
         }
 
-        match self.storage.get(protocol_addr) {
-            Some(&Neighbor { expires_at, hardware_addr }) => {
-                if timestamp < expires_at {
-                    return Some(hardware_addr)
+        self.storage.get(protocol_addr)
+            .and_then(|neighbor| neighbor.hardware_addr(timestamp))
+    }
+
+    /// Look up the hardware address of a neighbor that something is about to be sent to.
+    ///
+    /// If the neighbor has not been confirmed to be reachable recently, it will be
+    /// probed soon, unless it is confirmed in the meantime.
+    pub(crate) fn lookup(&mut self, protocol_addr: &IpAddress, timestamp: u64) -> Answer {
+        if protocol_addr.is_broadcast() {
+            return Answer::Found(EthernetAddress::BROADCAST)
+        }
+
+        if let Some(neighbor) = self.storage.get_mut(protocol_addr) {
+            if neighbor.hardware_addr(timestamp).is_some() {
+                neighbor.used_at = timestamp;
+                match neighbor.state {
+                    State::Reachable { until } if timestamp >= until => {
+                        net_trace!("{} is stale, delaying probe", protocol_addr);
+                        neighbor.state = State::Delay {
+                            probe_at: timestamp + Self::DELAY_FIRST_PROBE_TIME
+                        };
+                    }
+                    State::Stale => {
+                        net_trace!("{} is stale, delaying probe", protocol_addr);
+                        neighbor.state = State::Delay {
+                            probe_at: timestamp + Self::DELAY_FIRST_PROBE_TIME
+                        };
+                    }
+                    _ => ()
+                }
+                return Answer::Found(neighbor.hardware_addr)
+            }
+
+            if let State::Incomplete { retry_at } = neighbor.state {
+                if timestamp < retry_at {
+                    return Answer::RateLimited
                 }
             }
-            None => ()
         }
 
-        None
+        self.insert(*protocol_addr, Neighbor {
+            hardware_addr: EthernetAddress::default(),
+            state:         State::Incomplete { retry_at: timestamp + Self::SILENT_TIME },
+            updated_at:    timestamp,
+            used_at:       timestamp
+        });
+        Answer::NotFound
     }
 
-    pub(crate) fn lookup(&mut self, protocol_addr: &IpAddress, timestamp: u64) -> Answer {
-        match self.lookup_pure(protocol_addr, timestamp) {
-            Some(hardware_addr) =>
-                Answer::Found(hardware_addr),
-            None if timestamp < self.silent_until =>
-                Answer::RateLimited,
-            None => {
-                self.silent_until = timestamp + Self::SILENT_TIME;
-                Answer::NotFound
+    /// Return the address of a neighbor that has to be probed at the given moment, and
+    /// the hardware address the probe has to be sent to, if any; and schedule the next
+    /// probe of that neighbor.
+    ///
+    /// The neighbors that did not answer any probe are removed.
+    pub(crate) fn probe(&mut self, timestamp: u64) -> Option<(IpAddress, EthernetAddress)> {
+        let mut result = None;
+        let mut unreachable = None;
+        {
+            let mut visit = |protocol_addr: &IpAddress, neighbor: &mut Neighbor| {
+                let probes_sent = match neighbor.state {
+                    State::Delay { probe_at } if timestamp >= probe_at => 0,
+                    State::Probe { probe_at, probes_sent } if timestamp >= probe_at =>
+                        probes_sent,
+                    _ => return false
+                };
+                if probes_sent >= Self::MAX_UNICAST_SOLICIT {
+                    unreachable = Some(*protocol_addr);
+                } else {
+                    neighbor.state = State::Probe {
+                        probe_at:    timestamp + Self::RETRANS_TIMER,
+                        probes_sent: probes_sent + 1
+                    };
+                    result = Some((*protocol_addr, neighbor.hardware_addr));
+                }
+                true
+            };
+
+            match self.storage {
+                ManagedMap::Borrowed(ref mut pairs) => {
+                    for &mut (ref protocol_addr, ref mut neighbor) in
+                            pairs.iter_mut().filter_map(|pair| pair.as_mut()) {
+                        if visit(protocol_addr, neighbor) { break }
+                    }
+                }
+                #[cfg(any(feature = "std", feature = "alloc"))]
+                ManagedMap::Owned(ref mut map) => {
+                    for (protocol_addr, neighbor) in map.iter_mut() {
+                        if visit(protocol_addr, neighbor) { break }
+                    }
+                }
             }
         }
+
+        match unreachable {
+            Some(protocol_addr) => {
+                net_debug!("neighbor {} is unreachable", protocol_addr);
+                self.remove_neighbor(&protocol_addr, Removal::Unreachable);
+                self.probe(timestamp)
+            }
+            None => result
+        }
+    }
+
+    /// Return the moment at which the next neighbor has to be probed, if any.
+    pub(crate) fn poll_at(&self) -> Option<u64> {
+        fn probe_at(neighbor: &Neighbor) -> Option<u64> {
+            match neighbor.state {
+                State::Delay { probe_at } | State::Probe { probe_at, .. } => Some(probe_at),
+                _ => None
+            }
+        }
+
+        match self.storage {
+            ManagedMap::Borrowed(ref pairs) => {
+                pairs.iter()
+                     .filter_map(|pair| pair.as_ref())
+                     .filter_map(|&(_, ref neighbor)| probe_at(neighbor))
+                     .min()
+            }
+            #[cfg(any(feature = "std", feature = "alloc"))]
+            ManagedMap::Owned(ref map) => {
+                map.values().filter_map(probe_at).min()
+            }
+        }
+    }
+}
+
+enum IterLower<'a> {
+    Borrowed(slice::Iter<'a, Option<(IpAddress, Neighbor)>>),
+    #[cfg(any(feature = "std", feature = "alloc"))]
+    Owned(btree_map::Iter<'a, IpAddress, Neighbor>),
+}
+
+/// An iterator over the neighbors in a [Cache](struct.Cache.html).
+pub struct Iter<'a> {
+    lower:     IterLower<'a>,
+    timestamp: u64,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        let pair = match self.lower {
+            IterLower::Borrowed(ref mut pairs) =>
+                pairs.filter_map(|pair| pair.as_ref())
+                     .map(|&(ref protocol_addr, ref neighbor)| (protocol_addr, neighbor))
+                     .next(),
+            #[cfg(any(feature = "std", feature = "alloc"))]
+            IterLower::Owned(ref mut map) =>
+                map.next()
+        };
+        pair.map(|(protocol_addr, neighbor)| Entry::new(*protocol_addr, neighbor, self.timestamp))
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::vec::Vec;
+    use std::collections::BTreeMap;
     use super::*;
     use wire::ip::test::{MOCK_IP_ADDR_1, MOCK_IP_ADDR_2, MOCK_IP_ADDR_3, MOCK_IP_ADDR_4};
 
@@ -178,7 +725,8 @@ mod test {
         cache.fill(MOCK_IP_ADDR_1, HADDR_A, 0);
         assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_1, 0), Some(HADDR_A));
         assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_2, 0), None);
-        assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_1, 2 * Cache::ENTRY_LIFETIME), None);
+        // A neighbor that was not probed is never forgotten.
+        assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_1, 2 * Cache::REACHABLE_TIME), Some(HADDR_A));
 
         cache.fill(MOCK_IP_ADDR_1, HADDR_A, 0);
         assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_2, 0), None);
@@ -190,8 +738,60 @@ mod test {
         let mut cache = Cache::new(&mut cache_storage[..]);
 
         cache.fill(MOCK_IP_ADDR_1, HADDR_A, 0);
-        assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_1, 0), Some(HADDR_A));
-        assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_1, 2 * Cache::ENTRY_LIFETIME), None);
+        assert_eq!(cache.poll_at(), None);
+        assert_eq!(cache.lookup(&MOCK_IP_ADDR_1, 0), Answer::Found(HADDR_A));
+        assert_eq!(cache.poll_at(), Some(Cache::DELAY_FIRST_PROBE_TIME));
+        assert_eq!(cache.probe(4_999), None);
+
+        // The neighbor is still used while it is probed.
+        assert_eq!(cache.probe(5_000), Some((MOCK_IP_ADDR_1, HADDR_A)));
+        assert_eq!(cache.probe(5_000), None);
+        assert_eq!(cache.lookup(&MOCK_IP_ADDR_1, 5_500), Answer::Found(HADDR_A));
+        assert_eq!(cache.probe(6_000), Some((MOCK_IP_ADDR_1, HADDR_A)));
+        assert_eq!(cache.probe(7_000), Some((MOCK_IP_ADDR_1, HADDR_A)));
+        assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_1, 7_999), Some(HADDR_A));
+        assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_1, 8_000), None);
+        assert_eq!(cache.probe(8_000), None);
+        assert_eq!(cache.poll_at(), None);
+        assert_eq!(cache.lookup(&MOCK_IP_ADDR_1, 8_000), Answer::NotFound);
+    }
+
+    #[test]
+    fn test_reachable() {
+        let mut cache_storage = [Default::default(); 3];
+        let mut cache = Cache::new(&mut cache_storage[..]);
+
+        cache.reach(MOCK_IP_ADDR_1, HADDR_A, 0);
+        assert_eq!(cache.lookup(&MOCK_IP_ADDR_1, 1_000), Answer::Found(HADDR_A));
+        assert_eq!(cache.poll_at(), None);
+
+        // Once the reachability is not recent anymore, using the neighbor
+        // schedules a probe, unless it is confirmed again.
+        assert_eq!(cache.lookup(&MOCK_IP_ADDR_1, Cache::REACHABLE_TIME), Answer::Found(HADDR_A));
+        assert_eq!(cache.poll_at(), Some(Cache::REACHABLE_TIME + Cache::DELAY_FIRST_PROBE_TIME));
+        cache.confirm(&MOCK_IP_ADDR_1, Cache::REACHABLE_TIME + 1_000);
+        assert_eq!(cache.poll_at(), None);
+
+        // A confirmation does not resolve a neighbor.
+        cache.confirm(&MOCK_IP_ADDR_2, 0);
+        assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_2, 0), None);
+    }
+
+    #[test]
+    fn test_fill_changed() {
+        let mut cache_storage = [Default::default(); 3];
+        let mut cache = Cache::new(&mut cache_storage[..]);
+
+        // Unconfirmed information does not change the state of a neighbor,
+        // unless its hardware address changes.
+        cache.reach(MOCK_IP_ADDR_1, HADDR_A, 0);
+        cache.fill(MOCK_IP_ADDR_1, HADDR_A, 0);
+        assert_eq!(cache.lookup(&MOCK_IP_ADDR_1, 1_000), Answer::Found(HADDR_A));
+        assert_eq!(cache.poll_at(), None);
+
+        cache.fill(MOCK_IP_ADDR_1, HADDR_B, 1_000);
+        assert_eq!(cache.lookup(&MOCK_IP_ADDR_1, 1_000), Answer::Found(HADDR_B));
+        assert_eq!(cache.poll_at(), Some(1_000 + Cache::DELAY_FIRST_PROBE_TIME));
     }
 
     #[test]
@@ -230,4 +830,151 @@ mod test {
         assert_eq!(cache.lookup(&MOCK_IP_ADDR_1, 100), Answer::RateLimited);
         assert_eq!(cache.lookup(&MOCK_IP_ADDR_1, 2000), Answer::NotFound);
     }
+
+    #[test]
+    fn test_static() {
+        let mut cache_storage = [Default::default(); 2];
+        let mut cache = Cache::new(&mut cache_storage[..]);
+
+        assert_eq!(cache.add_static(MOCK_IP_ADDR_1, EthernetAddress::BROADCAST),
+                   Err(Error::Unaddressable));
+        assert_eq!(cache.add_static(MOCK_IP_ADDR_1, HADDR_A), Ok(()));
+        assert!(cache.is_static(&MOCK_IP_ADDR_1));
+
+        // A static neighbor is neither replaced nor probed.
+        cache.fill(MOCK_IP_ADDR_1, HADDR_B, 0);
+        cache.reach(MOCK_IP_ADDR_1, HADDR_B, 0);
+        assert_eq!(cache.lookup(&MOCK_IP_ADDR_1, 0), Answer::Found(HADDR_A));
+        assert_eq!(cache.lookup(&MOCK_IP_ADDR_1, 2 * Cache::REACHABLE_TIME),
+                   Answer::Found(HADDR_A));
+        assert_eq!(cache.poll_at(), None);
+
+        // Nor is it evicted.
+        assert_eq!(cache.add_static(MOCK_IP_ADDR_2, HADDR_B), Ok(()));
+        cache.fill(MOCK_IP_ADDR_3, HADDR_C, 0);
+        assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_3, 0), None);
+        assert_eq!(cache.add_static(MOCK_IP_ADDR_3, HADDR_C), Err(Error::Exhausted));
+
+        assert!(cache.remove(&MOCK_IP_ADDR_1));
+        assert!(!cache.remove(&MOCK_IP_ADDR_1));
+        cache.fill(MOCK_IP_ADDR_3, HADDR_C, 0);
+        assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_3, 0), Some(HADDR_C));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut cache_storage = [Default::default(); 3];
+        let mut cache = Cache::new(&mut cache_storage[..]);
+
+        cache.reach(MOCK_IP_ADDR_1, HADDR_A, 0);
+        cache.add_static(MOCK_IP_ADDR_2, HADDR_B).unwrap();
+        assert_eq!(cache.lookup(&MOCK_IP_ADDR_3, 1_000), Answer::NotFound);
+        let mut entries = cache.iter(2_000).collect::<Vec<_>>();
+        entries.sort_by_key(|entry| entry.protocol_addr);
+        assert_eq!(entries, vec![
+            Entry { protocol_addr: MOCK_IP_ADDR_1, hardware_addr: Some(HADDR_A),
+                    state: Reachability::Reachable, age: Some(2_000) },
+            Entry { protocol_addr: MOCK_IP_ADDR_2, hardware_addr: Some(HADDR_B),
+                    state: Reachability::Permanent, age: None },
+            Entry { protocol_addr: MOCK_IP_ADDR_3, hardware_addr: None,
+                    state: Reachability::Incomplete, age: Some(1_000) },
+        ]);
+
+        let entry = cache.iter(Cache::REACHABLE_TIME)
+                         .find(|entry| entry.protocol_addr == MOCK_IP_ADDR_1).unwrap();
+        assert_eq!(entry.state, Reachability::Stale);
+        assert_eq!(format!("{}", entry.state), "STALE");
+    }
+
+    #[test]
+    fn test_evict_lru() {
+        let mut cache_storage = [Default::default(); 2];
+        let mut cache = Cache::new(&mut cache_storage[..]);
+
+        cache.fill(MOCK_IP_ADDR_1, HADDR_A, 0);
+        cache.fill(MOCK_IP_ADDR_2, HADDR_B, 100);
+        assert_eq!(cache.lookup(&MOCK_IP_ADDR_1, 200), Answer::Found(HADDR_A));
+
+        cache.fill(MOCK_IP_ADDR_3, HADDR_C, 300);
+        assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_1, 300), Some(HADDR_A));
+        assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_2, 300), None);
+    }
+
+    #[test]
+    fn test_evict_earliest_expiry() {
+        let mut cache_storage = [Default::default(); 2];
+        let mut cache = Cache::new(&mut cache_storage[..]);
+        cache.set_eviction_policy(EvictionPolicy::EarliestExpiry);
+
+        cache.reach(MOCK_IP_ADDR_1, HADDR_A, 0);
+        cache.fill(MOCK_IP_ADDR_2, HADDR_B, 100);
+        assert_eq!(cache.lookup(&MOCK_IP_ADDR_1, 200), Answer::Found(HADDR_A));
+
+        // Neighbors that are not known to be reachable go first...
+        cache.reach(MOCK_IP_ADDR_3, HADDR_C, 300);
+        assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_2, 300), None);
+
+        // ...and then those whose reachability expires first.
+        cache.reach(MOCK_IP_ADDR_4, HADDR_D, 400);
+        assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_1, 400), None);
+        assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_3, 400), Some(HADDR_C));
+    }
+
+    #[test]
+    fn test_max_len() {
+        let mut cache = Cache::new(BTreeMap::new());
+        cache.fill(MOCK_IP_ADDR_1, HADDR_A, 0);
+        cache.fill(MOCK_IP_ADDR_2, HADDR_B, 100);
+        cache.fill(MOCK_IP_ADDR_3, HADDR_C, 200);
+
+        cache.set_max_len(Some(2));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_1, 200), None);
+
+        cache.fill(MOCK_IP_ADDR_4, HADDR_D, 300);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_2, 300), None);
+        assert_eq!(cache.lookup_pure(&MOCK_IP_ADDR_4, 300), Some(HADDR_D));
+    }
+
+    #[derive(Default)]
+    struct MockObserver {
+        added:   Vec<(IpAddress, EthernetAddress)>,
+        removed: Vec<(IpAddress, EthernetAddress, Removal)>,
+    }
+
+    impl Observer for MockObserver {
+        fn added(&mut self, protocol_addr: IpAddress, hardware_addr: EthernetAddress) {
+            self.added.push((protocol_addr, hardware_addr))
+        }
+
+        fn removed(&mut self, protocol_addr: IpAddress, hardware_addr: EthernetAddress,
+                   reason: Removal) {
+            self.removed.push((protocol_addr, hardware_addr, reason))
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let mut observer = MockObserver::default();
+        {
+            let mut cache_storage = [Default::default(); 1];
+            let mut cache = Cache::new(&mut cache_storage[..]);
+            cache.set_observer(Some(&mut observer));
+
+            // Pending resolutions are not reported.
+            assert_eq!(cache.lookup(&MOCK_IP_ADDR_1, 0), Answer::NotFound);
+            cache.reach(MOCK_IP_ADDR_1, HADDR_A, 0);
+            cache.fill(MOCK_IP_ADDR_1, HADDR_A, 0);
+            cache.fill(MOCK_IP_ADDR_1, HADDR_B, 0);
+            cache.fill(MOCK_IP_ADDR_2, HADDR_C, 0);
+            assert!(cache.remove(&MOCK_IP_ADDR_2));
+        }
+        assert_eq!(observer.added, vec![(MOCK_IP_ADDR_1, HADDR_A),
+                                        (MOCK_IP_ADDR_1, HADDR_B),
+                                        (MOCK_IP_ADDR_2, HADDR_C)]);
+        assert_eq!(observer.removed, vec![(MOCK_IP_ADDR_1, HADDR_B, Removal::Evicted),
+                                          (MOCK_IP_ADDR_2, HADDR_C, Removal::Removed)]);
+    }
 }
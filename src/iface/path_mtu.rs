@@ -0,0 +1,221 @@
+// Heads up! Before working on this file you should read RFC 1191 and RFC 8201,
+// which describe Path MTU Discovery for IPv4 and IPv6.
+
+use managed::ManagedSlice;
+
+use wire::IpAddress;
+#[cfg(feature = "proto-ipv4")]
+use wire::IPV4_MIN_MTU;
+#[cfg(feature = "proto-ipv6")]
+use wire::IPV6_MIN_MTU;
+
+/// The time after which a path MTU estimate is discarded, so that an increase of
+/// the path MTU is noticed, in milliseconds. See RFC 1191 § 6.3 and RFC 8201 § 4.
+const PATH_MTU_TIMEOUT: u64 = 600_000;
+
+/// The common MTUs of the links on the Internet. See RFC 1191 § 7.
+#[cfg(feature = "proto-ipv4")]
+const MTU_PLATEAUS: [usize; 11] = [65535, 32000, 17914, 8166, 4352, 2002, 1492, 1006, 508, 296, 68];
+
+/// Guess the MTU of the path on which a datagram of the given length was too big,
+/// for routers that predate RFC 1191 and report no next-hop MTU.
+#[cfg(feature = "proto-ipv4")]
+pub(crate) fn plateau_below(total_len: usize) -> usize {
+    MTU_PLATEAUS.iter()
+        .cloned()
+        .find(|&plateau| plateau < total_len)
+        .unwrap_or(IPV4_MIN_MTU)
+}
+
+/// The MTU of the path to a destination, learned from an ICMPv4 Fragmentation Needed
+/// or an ICMPv6 Packet Too Big message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathMtu {
+    /// The destination address.
+    pub dst_addr:   IpAddress,
+    /// The largest IP packet that can be sent to the destination.
+    pub mtu:        usize,
+    /// The timestamp past which the estimate is discarded.
+    pub expires_at: u64,
+}
+
+/// A cache of the MTUs of the paths to the destinations of the packets sent
+/// through an interface.
+///
+/// The cache only ever holds estimates that are lower than the MTU of the interface.
+/// When it is full, the estimate that expires first is replaced.
+///
+/// # Examples
+///
+/// On systems with heap, this cache can be created with:
+///
+/// ```rust
+/// use smoltcp::iface::PathMtuCache;
+/// let mut path_mtu_cache = PathMtuCache::new(vec![]);
+/// ```
+///
+/// On systems without heap, use:
+///
+/// ```rust
+/// use smoltcp::iface::PathMtuCache;
+/// let mut path_mtu_cache_storage = [None; 8];
+/// let mut path_mtu_cache = PathMtuCache::new(&mut path_mtu_cache_storage[..]);
+/// ```
+#[derive(Debug)]
+pub struct PathMtuCache<'a> {
+    storage: ManagedSlice<'a, Option<PathMtu>>,
+}
+
+impl<'a> PathMtuCache<'a> {
+    /// Create a path MTU cache. The backing storage is cleared upon creation.
+    pub fn new<T>(storage: T) -> PathMtuCache<'a>
+            where T: Into<ManagedSlice<'a, Option<PathMtu>>> {
+        let mut storage = storage.into();
+        for slot in storage.iter_mut() {
+            *slot = None;
+        }
+
+        PathMtuCache { storage }
+    }
+
+    /// Return an iterator over the estimates in the cache, including the ones that
+    /// have expired.
+    pub fn iter(&self) -> Iter {
+        Iter { slots: self.storage.iter() }
+    }
+
+    /// Return the MTU of the path to the given destination, if it is known to be
+    /// lower than the MTU of the interface.
+    pub fn lookup(&self, dst_addr: &IpAddress, timestamp: u64) -> Option<usize> {
+        self.iter()
+            .find(|path| path.dst_addr == *dst_addr && timestamp < path.expires_at)
+            .map(|path| path.mtu)
+    }
+
+    /// Lower the MTU of the path to the given destination, as reported by a router
+    /// on the path.
+    ///
+    /// The estimate is never raised by a report, nor lowered below the minimum MTU
+    /// that every link must support, so that forged reports cannot make the interface
+    /// send tiny packets.
+    pub(crate) fn update(&mut self, dst_addr: IpAddress, mtu: usize, timestamp: u64) {
+        let min_mtu = match dst_addr {
+            #[cfg(feature = "proto-ipv4")]
+            IpAddress::Ipv4(_) => IPV4_MIN_MTU,
+            #[cfg(feature = "proto-ipv6")]
+            IpAddress::Ipv6(_) => IPV6_MIN_MTU,
+            _ => return
+        };
+        let mtu = if mtu < min_mtu { min_mtu } else { mtu };
+        if self.lookup(&dst_addr, timestamp).map_or(false, |known| known <= mtu) {
+            return
+        }
+
+        net_debug!("path MTU to {} is {}", dst_addr, mtu);
+        let path = Some(PathMtu { dst_addr, mtu, expires_at: timestamp + PATH_MTU_TIMEOUT });
+        let index = self.storage.iter()
+            .position(|slot| slot.map_or(false, |probe| probe.dst_addr == dst_addr))
+            .or_else(|| self.storage.iter().position(|slot| {
+                slot.map_or(true, |probe| timestamp >= probe.expires_at)
+            }));
+        match index {
+            Some(index) => self.storage[index] = path,
+            None => match self.storage {
+                ManagedSlice::Borrowed(_) => {
+                    let oldest = self.storage.iter()
+                        .enumerate()
+                        .min_by_key(|&(_, slot)| slot.map(|probe| probe.expires_at))
+                        .map(|(index, _)| index);
+                    if let Some(index) = oldest {
+                        self.storage[index] = path
+                    }
+                }
+                #[cfg(any(feature = "std", feature = "alloc"))]
+                ManagedSlice::Owned(ref mut paths) => paths.push(path)
+            }
+        }
+    }
+}
+
+/// An iterator over the estimates in a [PathMtuCache](struct.PathMtuCache.html).
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    slots: ::core::slice::Iter<'a, Option<PathMtu>>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a PathMtu;
+
+    fn next(&mut self) -> Option<&'a PathMtu> {
+        while let Some(slot) = self.slots.next() {
+            if let Some(ref path) = *slot {
+                return Some(path)
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "proto-ipv4")]
+    const ADDR_1: IpAddress = IpAddress::Ipv4(::wire::Ipv4Address([192, 168, 1, 1]));
+    #[cfg(feature = "proto-ipv4")]
+    const ADDR_2: IpAddress = IpAddress::Ipv4(::wire::Ipv4Address([192, 168, 1, 2]));
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_update() {
+        let mut cache = PathMtuCache::new(vec![]);
+        assert_eq!(cache.lookup(&ADDR_1, 0), None);
+
+        cache.update(ADDR_1, 1400, 0);
+        assert_eq!(cache.lookup(&ADDR_1, 0), Some(1400));
+        assert_eq!(cache.lookup(&ADDR_2, 0), None);
+
+        // The estimate is only ever lowered, and never below the minimum MTU.
+        cache.update(ADDR_1, 1450, 1_000);
+        assert_eq!(cache.lookup(&ADDR_1, 1_000), Some(1400));
+        cache.update(ADDR_1, 68, 1_000);
+        assert_eq!(cache.lookup(&ADDR_1, 1_000), Some(IPV4_MIN_MTU));
+        assert_eq!(cache.iter().count(), 1);
+
+        // The estimate expires.
+        assert_eq!(cache.lookup(&ADDR_1, 600_999), Some(IPV4_MIN_MTU));
+        assert_eq!(cache.lookup(&ADDR_1, 601_000), None);
+        cache.update(ADDR_1, 1450, 601_000);
+        assert_eq!(cache.lookup(&ADDR_1, 601_000), Some(1450));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_plateau_below() {
+        assert_eq!(plateau_below(1500), 1492);
+        assert_eq!(plateau_below(1492), 1006);
+        assert_eq!(plateau_below(9000), 8166);
+        assert_eq!(plateau_below(68), IPV4_MIN_MTU);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_replace() {
+        let mut storage = [None; 1];
+        let mut cache = PathMtuCache::new(&mut storage[..]);
+
+        cache.update(ADDR_1, 1400, 0);
+        cache.update(ADDR_2, 1300, 1_000);
+        assert_eq!(cache.lookup(&ADDR_1, 1_000), None);
+        assert_eq!(cache.lookup(&ADDR_2, 1_000), Some(1300));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv6")]
+    fn test_ipv6_min_mtu() {
+        let addr = IpAddress::v6(0xfdbe, 0, 0, 0, 0, 0, 0, 1);
+        let mut cache = PathMtuCache::new(vec![]);
+        cache.update(addr, 1000, 0);
+        assert_eq!(cache.lookup(&addr, 0), Some(IPV6_MIN_MTU));
+    }
+}
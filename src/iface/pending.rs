@@ -0,0 +1,255 @@
+// Heads up! Before working on this file you should read RFC 1122 § 2.3.2.2,
+// which discusses queueing packets while the link layer address is resolved,
+// and RFC 4861 § 7.2.2.
+
+use managed::ManagedSlice;
+
+use {Error, Result};
+use wire::IpAddress;
+
+/// The time after which a packet waiting for the link layer address of its next hop
+/// is discarded, in milliseconds.
+const PENDING_TIMEOUT: u64 = 3_000;
+
+/// The number of packets kept for each next hop by default.
+pub const DEFAULT_NEIGHBOR_LIMIT: usize = 3;
+
+/// A buffer holding an IP packet that is waiting for the link layer address
+/// of its next hop to be resolved.
+///
+/// The size of the storage limits the packets that can be queued.
+#[derive(Debug)]
+pub struct PendingBuffer<'a> {
+    storage:   ManagedSlice<'a, u8>,
+    len:       usize,
+    next_hop:  Option<IpAddress>,
+    queued_at: u64,
+}
+
+impl<'a> PendingBuffer<'a> {
+    /// Create a pending packet buffer using the given storage.
+    pub fn new<T>(storage: T) -> PendingBuffer<'a>
+            where T: Into<ManagedSlice<'a, u8>> {
+        PendingBuffer { storage: storage.into(), len: 0, next_hop: None, queued_at: 0 }
+    }
+
+    /// Return the largest packet that can be held in the buffer.
+    pub fn capacity(&self) -> usize {
+        self.storage.len()
+    }
+}
+
+/// A queue of IP packets sent through an interface, that are waiting for the link layer
+/// address of their next hop to be resolved.
+///
+/// At most a few packets are kept for each next hop; when another one is queued,
+/// the oldest one is discarded, as recommended by RFC 1122.
+///
+/// # Examples
+///
+/// On systems with heap, this queue can be created with:
+///
+/// ```rust
+/// use smoltcp::iface::{PendingBuffer, PendingQueue};
+/// let mut queue = PendingQueue::new(vec![PendingBuffer::new(vec![0; 1500]),
+///                                        PendingBuffer::new(vec![0; 1500])]);
+/// ```
+///
+/// On systems without heap, use:
+///
+/// ```rust
+/// use smoltcp::iface::{PendingBuffer, PendingQueue};
+/// let mut storage = [0; 1500];
+/// let mut buffers = [PendingBuffer::new(&mut storage[..])];
+/// let mut queue = PendingQueue::new(&mut buffers[..]);
+/// ```
+#[derive(Debug)]
+pub struct PendingQueue<'a, 'b: 'a> {
+    buffers:        ManagedSlice<'a, PendingBuffer<'b>>,
+    neighbor_limit: usize,
+}
+
+impl<'a, 'b: 'a> PendingQueue<'a, 'b> {
+    /// Create a pending packet queue using the given buffers. The buffers are cleared
+    /// upon creation.
+    pub fn new<T>(buffers: T) -> PendingQueue<'a, 'b>
+            where T: Into<ManagedSlice<'a, PendingBuffer<'b>>> {
+        let mut buffers = buffers.into();
+        for buffer in buffers.iter_mut() {
+            buffer.len = 0;
+        }
+
+        PendingQueue { buffers, neighbor_limit: DEFAULT_NEIGHBOR_LIMIT }
+    }
+
+    /// Return the number of packets kept for each next hop.
+    pub fn neighbor_limit(&self) -> usize {
+        self.neighbor_limit
+    }
+
+    /// Set the number of packets kept for each next hop.
+    ///
+    /// The default is [DEFAULT_NEIGHBOR_LIMIT](constant.DEFAULT_NEIGHBOR_LIMIT.html).
+    ///
+    /// # Panics
+    /// This function panics if the limit is zero.
+    pub fn set_neighbor_limit(&mut self, limit: usize) {
+        assert!(limit > 0);
+        self.neighbor_limit = limit
+    }
+
+    /// Return the number of packets waiting for their next hop to be resolved.
+    pub fn len(&self) -> usize {
+        self.buffers.iter().filter(|buffer| buffer.len != 0).count()
+    }
+
+    /// Query whether there are no packets waiting for their next hop to be resolved.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Query whether the queue has any buffers at all.
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.buffers.is_empty()
+    }
+
+    /// Allocate a buffer for a packet of the given size to the given next hop, discarding
+    /// the oldest packet to the same next hop if it already has as many as allowed,
+    /// and return the buffer.
+    ///
+    /// Returns `Err(Error::Exhausted)` if there is no free buffer the packet fits into.
+    pub(crate) fn enqueue(&mut self, next_hop: IpAddress, size: usize, timestamp: u64) ->
+                         Result<&mut [u8]> {
+        let queued = self.buffers.iter()
+            .filter(|buffer| buffer.len != 0 && buffer.next_hop == Some(next_hop))
+            .count();
+        if queued >= self.neighbor_limit {
+            let oldest = self.buffers.iter_mut()
+                .filter(|buffer| buffer.len != 0 && buffer.next_hop == Some(next_hop))
+                .min_by_key(|buffer| buffer.queued_at)
+                .unwrap();
+            net_debug!("dropping a packet to {} waiting for resolution", next_hop);
+            oldest.len = 0;
+        }
+
+        let buffer = self.buffers.iter_mut()
+            .filter(|buffer| buffer.len == 0 && buffer.capacity() >= size)
+            .min_by_key(|buffer| buffer.capacity())
+            .ok_or(Error::Exhausted)?;
+        buffer.len = size;
+        buffer.next_hop = Some(next_hop);
+        buffer.queued_at = timestamp;
+        Ok(&mut buffer.storage[..size])
+    }
+
+    /// Return the index of the packet queued first after the packet with the given index,
+    /// or of the packet queued first of all if there is none, so that the packets
+    /// are sent in the order they were queued in.
+    pub(crate) fn next_after(&self, index: Option<usize>) -> Option<usize> {
+        let after = index.map(|index| (self.buffers[index].queued_at, index));
+        self.buffers.iter().enumerate()
+            .filter(|&(_, buffer)| buffer.len != 0)
+            .map(|(index, buffer)| (buffer.queued_at, index))
+            .filter(|&key| after.map_or(true, |after| key > after))
+            .min()
+            .map(|(_, index)| index)
+    }
+
+    /// Return the packet with the given index, if any.
+    pub(crate) fn get(&self, index: usize) -> Option<&[u8]> {
+        let buffer = &self.buffers[index];
+        if buffer.len == 0 {
+            None
+        } else {
+            Some(&buffer.storage[..buffer.len])
+        }
+    }
+
+    /// Remove the packet with the given index.
+    pub(crate) fn remove(&mut self, index: usize) {
+        self.buffers[index].len = 0
+    }
+
+    /// Discard the packets that have been waiting for their next hop for too long,
+    /// and return how many there were.
+    pub(crate) fn expire(&mut self, timestamp: u64) -> usize {
+        let mut expired = 0;
+        for buffer in self.buffers.iter_mut().filter(|buffer| buffer.len != 0) {
+            if timestamp >= buffer.queued_at + PENDING_TIMEOUT {
+                net_debug!("dropping a packet to {} that was not resolved",
+                           buffer.next_hop.unwrap());
+                buffer.len = 0;
+                expired += 1;
+            }
+        }
+        expired
+    }
+
+    /// Return the earliest time a packet is discarded at, if any are queued.
+    pub(crate) fn poll_at(&self) -> Option<u64> {
+        self.buffers.iter()
+            .filter(|buffer| buffer.len != 0)
+            .map(|buffer| buffer.queued_at + PENDING_TIMEOUT)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use super::*;
+
+    fn queue(count: usize) -> PendingQueue<'static, 'static> {
+        let buffers = (0..count).map(|_| PendingBuffer::new(vec![0; 64])).collect::<Vec<_>>();
+        PendingQueue::new(buffers)
+    }
+
+    #[cfg(feature = "proto-ipv4")]
+    #[test]
+    fn test_enqueue_expire() {
+        let next_hop = IpAddress::v4(192, 168, 1, 1);
+        let mut queue = queue(2);
+        assert!(queue.is_empty());
+        assert_eq!(queue.poll_at(), None);
+
+        queue.enqueue(next_hop, 20, 100).unwrap().copy_from_slice(&[1; 20]);
+        assert_eq!(queue.enqueue(next_hop, 65, 200), Err(Error::Exhausted));
+        queue.enqueue(next_hop, 40, 200).unwrap();
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.get(0), Some(&[1; 20][..]));
+        assert_eq!(queue.enqueue(next_hop, 20, 300), Err(Error::Exhausted));
+        assert_eq!(queue.poll_at(), Some(100 + PENDING_TIMEOUT));
+
+        assert_eq!(queue.expire(100 + PENDING_TIMEOUT), 1);
+        assert_eq!(queue.get(0), None);
+        assert_eq!(queue.len(), 1);
+        queue.remove(1);
+        assert!(queue.is_empty());
+    }
+
+    #[cfg(feature = "proto-ipv4")]
+    #[test]
+    fn test_neighbor_limit() {
+        let (first, second) = (IpAddress::v4(192, 168, 1, 1), IpAddress::v4(192, 168, 1, 2));
+        let mut queue = queue(3);
+        queue.set_neighbor_limit(2);
+
+        queue.enqueue(first, 10, 0).unwrap();
+        queue.enqueue(first, 20, 1).unwrap();
+        queue.enqueue(second, 30, 2).unwrap();
+        assert_eq!(queue.len(), 3);
+
+        // The oldest packet to the first neighbor makes room for the newest one.
+        queue.enqueue(first, 40, 3).unwrap();
+        assert_eq!(queue.len(), 3);
+        let lens = (0..queue.buffers.len())
+            .filter_map(|index| queue.get(index).map(|packet| packet.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(lens, vec![40, 20, 30]);
+
+        assert_eq!(queue.next_after(None), Some(1));
+        assert_eq!(queue.next_after(Some(1)), Some(2));
+        assert_eq!(queue.next_after(Some(2)), Some(0));
+        assert_eq!(queue.next_after(Some(0)), None);
+    }
+}
@@ -0,0 +1,107 @@
+// Heads up! Before working on this file you should read RFC 6056,
+// which describes how ephemeral ports should be selected, and RFC 6335 § 6,
+// which defines the dynamic port range.
+
+/// The first port of the dynamic port range.
+pub const EPHEMERAL_PORT_FIRST: u16 = 49152;
+
+/// The last port of the dynamic port range.
+pub const EPHEMERAL_PORT_LAST: u16 = 65535;
+
+/// An ephemeral port allocator.
+///
+/// The allocator selects the local port of a socket that connects without one,
+/// from a configurable range. A port is probed at a random offset within the range,
+/// and the following ports are probed in turn until one that is not in use is found,
+/// which is the first algorithm of RFC 6056 § 3.3.1; it makes the ports of new
+/// connections hard to guess for an off-path attacker.
+#[derive(Debug)]
+pub struct PortAllocator {
+    first:      u16,
+    last:       u16,
+    rand_state: u32,
+}
+
+impl PortAllocator {
+    /// Create an allocator for the dynamic port range.
+    pub fn new() -> PortAllocator {
+        PortAllocator {
+            first:      EPHEMERAL_PORT_FIRST,
+            last:       EPHEMERAL_PORT_LAST,
+            rand_state: 0x68e31da4
+        }
+    }
+
+    /// Seed the selection of the ports, e.g. with the hardware address of the interface,
+    /// so that distinct hosts select distinct ports.
+    pub(crate) fn seed(&mut self, seed: u32) {
+        if seed != 0 { self.rand_state = seed }
+    }
+
+    /// Return the first and the last port of the range.
+    pub fn range(&self) -> (u16, u16) {
+        (self.first, self.last)
+    }
+
+    /// Set the first and the last port of the range.
+    ///
+    /// # Panics
+    /// This function panics if the first port is zero, or greater than the last one.
+    pub fn set_range(&mut self, first: u16, last: u16) {
+        assert!(first != 0 && first <= last, "invalid ephemeral port range");
+        self.first = first;
+        self.last  = last;
+    }
+
+    /// Select a port that is not in use, according to the given function, if any.
+    pub fn allocate<F>(&mut self, timestamp: u64, in_use: F) -> Option<u16>
+            where F: Fn(u16) -> bool {
+        // xorshift32, perturbed by the current time.
+        let mut x = self.rand_state ^ (timestamp as u32);
+        if x == 0 { x = 0x68e31da4 }
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rand_state = x;
+
+        let count = (self.last - self.first) as u32 + 1;
+        let offset = x % count;
+        (0..count)
+            .map(|index| self.first + ((offset + index) % count) as u16)
+            .find(|&port| !in_use(port))
+    }
+}
+
+impl Default for PortAllocator {
+    fn default() -> PortAllocator {
+        PortAllocator::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_allocate() {
+        let mut ports = PortAllocator::new();
+        let first = ports.allocate(0, |_| false).unwrap();
+        assert!(first >= EPHEMERAL_PORT_FIRST);
+        // The ports are randomized.
+        assert!((0..10).any(|timestamp| ports.allocate(timestamp, |_| false) != Some(first)));
+
+        ports.set_range(1024, 1027);
+        for timestamp in 0..100 {
+            let port = ports.allocate(timestamp, |port| port != 1026).unwrap();
+            assert_eq!(port, 1026);
+        }
+        assert_eq!(ports.allocate(0, |_| true), None);
+        assert_eq!(ports.range(), (1024, 1027));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid ephemeral port range")]
+    fn test_set_range_invalid() {
+        PortAllocator::new().set_range(2000, 1999);
+    }
+}
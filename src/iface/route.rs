@@ -0,0 +1,336 @@
+use managed::ManagedSlice;
+
+use {Error, Result};
+use wire::{IpAddress, IpCidr};
+
+/// A route to a range of destinations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Route {
+    /// The destinations the route applies to.
+    pub cidr:       IpCidr,
+    /// The router packets to the destinations are sent through, or `None` if
+    /// the destinations are on-link.
+    pub via_router: Option<IpAddress>,
+    /// The metric of the route; of the routes with the longest matching prefix,
+    /// the one with the lowest metric is used.
+    pub metric:     u32,
+    /// The timestamp past which the route is removed from the table, or `None`
+    /// if it never expires.
+    pub expires_at: Option<u64>,
+}
+
+impl Route {
+    /// Create a route to destinations that are on-link.
+    pub fn new_on_link(cidr: IpCidr, metric: u32) -> Route {
+        Route { cidr, via_router: None, metric, expires_at: None }
+    }
+
+    /// Create a route to destinations that are reachable through the given router.
+    pub fn new_via_router<T>(cidr: IpCidr, router: T, metric: u32) -> Route
+            where T: Into<IpAddress> {
+        Route { cidr, via_router: Some(router.into()), metric, expires_at: None }
+    }
+
+    /// Return the address packets to the given destination are sent to using this route.
+    pub fn next_hop(&self, addr: &IpAddress) -> IpAddress {
+        self.via_router.unwrap_or(*addr)
+    }
+}
+
+/// A table of routes to ranges of destinations.
+///
+/// Packets are sent using the matching route with the longest prefix. The subnets
+/// of the addresses of the interface are on-link without a route, and the routes
+/// to them are only used if they are more specific.
+///
+/// # Examples
+///
+/// On systems with heap, this table can be created with:
+///
+/// ```rust
+/// use smoltcp::iface::Routes;
+/// let mut routes = Routes::new(vec![]);
+/// ```
+///
+/// On systems without heap, use:
+///
+/// ```rust
+/// use smoltcp::iface::Routes;
+/// let mut routes_storage = [None; 4];
+/// let mut routes = Routes::new(&mut routes_storage[..]);
+/// ```
+#[derive(Debug)]
+pub struct Routes<'a> {
+    storage: ManagedSlice<'a, Option<Route>>,
+}
+
+impl<'a> Routes<'a> {
+    /// Create a routing table. The backing storage is cleared upon creation.
+    pub fn new<T>(storage: T) -> Routes<'a>
+            where T: Into<ManagedSlice<'a, Option<Route>>> {
+        let mut storage = storage.into();
+        for slot in storage.iter_mut() {
+            *slot = None;
+        }
+
+        Routes { storage }
+    }
+
+    /// Return an iterator over the routes in the table, including the ones that have
+    /// expired but were not yet removed by polling the interface.
+    pub fn iter(&self) -> Iter {
+        Iter { slots: self.storage.iter() }
+    }
+
+    /// Return an iterator over the routes in the table that allows changing them,
+    /// e.g. to update their metric or lifetime.
+    pub fn iter_mut(&mut self) -> IterMut {
+        IterMut { slots: self.storage.iter_mut() }
+    }
+
+    /// Add a route to the table, or update the metric and the lifetime of the route
+    /// to the same destinations through the same router.
+    ///
+    /// Returns `Err(Error::Unaddressable)` if the router is not a unicast address
+    /// of the same family as the destinations, and `Err(Error::Exhausted)` if there
+    /// is no space for another route.
+    pub fn add(&mut self, route: Route) -> Result<()> {
+        if let Some(router) = route.via_router {
+            let same_family = match (route.cidr, router) {
+                #[cfg(feature = "proto-ipv4")]
+                (IpCidr::Ipv4(_), IpAddress::Ipv4(_)) => true,
+                #[cfg(feature = "proto-ipv6")]
+                (IpCidr::Ipv6(_), IpAddress::Ipv6(_)) => true,
+                _ => false
+            };
+            if !same_family || !router.is_unicast() { return Err(Error::Unaddressable) }
+        }
+
+        for slot in self.storage.iter_mut() {
+            let matches = slot.map_or(false, |probe|
+                probe.cidr == route.cidr && probe.via_router == route.via_router);
+            if matches {
+                *slot = Some(route);
+                return Ok(())
+            }
+        }
+
+        for slot in self.storage.iter_mut() {
+            if slot.is_none() {
+                *slot = Some(route);
+                return Ok(())
+            }
+        }
+
+        match self.storage {
+            ManagedSlice::Borrowed(_) => Err(Error::Exhausted),
+            #[cfg(any(feature = "std", feature = "alloc"))]
+            ManagedSlice::Owned(ref mut routes) => {
+                routes.push(Some(route));
+                Ok(())
+            }
+        }
+    }
+
+    /// Remove the route to the given destinations through the given router,
+    /// or the on-link one if it is `None`, and return it.
+    pub fn remove(&mut self, cidr: IpCidr, via_router: Option<IpAddress>) -> Option<Route> {
+        for slot in self.storage.iter_mut() {
+            let matches = slot.map_or(false, |probe|
+                probe.cidr == cidr && probe.via_router == via_router);
+            if matches {
+                return slot.take()
+            }
+        }
+        None
+    }
+
+    /// Remove the routes for which the given predicate returns `false`.
+    pub fn retain<F>(&mut self, mut f: F)
+            where F: FnMut(&Route) -> bool {
+        for slot in self.storage.iter_mut() {
+            if slot.map_or(false, |route| !f(&route)) {
+                *slot = None
+            }
+        }
+    }
+
+    /// Remove every route from the table.
+    pub fn clear(&mut self) {
+        for slot in self.storage.iter_mut() {
+            *slot = None;
+        }
+    }
+
+    /// Remove the routes whose lifetime has ended.
+    pub(crate) fn expire(&mut self, timestamp: u64) {
+        for slot in self.storage.iter_mut() {
+            let expired = slot.map_or(false, |route|
+                route.expires_at.map_or(false, |expires_at| timestamp >= expires_at));
+            if expired {
+                net_debug!("route to {} expired", slot.unwrap().cidr);
+                *slot = None
+            }
+        }
+    }
+
+    /// Return the moment at which the next route expires, if any.
+    pub(crate) fn poll_at(&self) -> Option<u64> {
+        self.iter().filter_map(|route| route.expires_at).min()
+    }
+
+    /// Return the route packets to the given destination are sent with, if any.
+    pub fn lookup(&self, addr: &IpAddress) -> Option<&Route> {
+        self.iter()
+            .filter(|route| route.cidr.contains_addr(addr))
+            .fold(None, |best: Option<&Route>, route| match best {
+                Some(best) if (best.cidr.prefix_len(), route.metric) >=
+                              (route.cidr.prefix_len(), best.metric) => Some(best),
+                _ => Some(route)
+            })
+    }
+}
+
+/// An iterator over the routes in a [Routes](struct.Routes.html) table.
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    slots: ::core::slice::Iter<'a, Option<Route>>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Route;
+
+    fn next(&mut self) -> Option<&'a Route> {
+        while let Some(slot) = self.slots.next() {
+            if let Some(ref route) = *slot {
+                return Some(route)
+            }
+        }
+        None
+    }
+}
+
+/// An iterator over the routes in a [Routes](struct.Routes.html) table that allows
+/// changing them.
+#[derive(Debug)]
+pub struct IterMut<'a> {
+    slots: ::core::slice::IterMut<'a, Option<Route>>,
+}
+
+impl<'a> Iterator for IterMut<'a> {
+    type Item = &'a mut Route;
+
+    fn next(&mut self) -> Option<&'a mut Route> {
+        while let Some(slot) = self.slots.next() {
+            if let Some(ref mut route) = *slot {
+                return Some(route)
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "proto-ipv4")]
+    use wire::{Ipv4Address, Ipv4Cidr};
+    #[cfg(feature = "proto-ipv6")]
+    use wire::{Ipv6Address, Ipv6Cidr};
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_longest_prefix() {
+        let mut routes = Routes::new(vec![]);
+        let default = Route::new_via_router(IpCidr::new(IpAddress::v4(0, 0, 0, 0), 0),
+                                            IpAddress::v4(192, 168, 1, 1), 0);
+        let subnet = Route::new_via_router(IpCidr::new(IpAddress::v4(10, 0, 0, 0), 8),
+                                           IpAddress::v4(192, 168, 1, 2), 10);
+        let on_link = Route::new_on_link(IpCidr::new(IpAddress::v4(10, 1, 0, 0), 16), 10);
+        assert_eq!(routes.add(default), Ok(()));
+        assert_eq!(routes.add(subnet), Ok(()));
+        assert_eq!(routes.add(on_link), Ok(()));
+
+        let addr = IpAddress::v4(8, 8, 8, 8);
+        assert_eq!(routes.lookup(&addr), Some(&default));
+        assert_eq!(routes.lookup(&addr).unwrap().next_hop(&addr), IpAddress::v4(192, 168, 1, 1));
+        assert_eq!(routes.lookup(&IpAddress::v4(10, 2, 0, 1)), Some(&subnet));
+        let addr = IpAddress::v4(10, 1, 0, 1);
+        assert_eq!(routes.lookup(&addr), Some(&on_link));
+        assert_eq!(routes.lookup(&addr).unwrap().next_hop(&addr), addr);
+
+        assert_eq!(routes.remove(on_link.cidr, None), Some(on_link));
+        assert_eq!(routes.lookup(&addr), Some(&subnet));
+        assert_eq!(routes.iter().count(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_metric() {
+        let mut routes = Routes::new(vec![]);
+        let cidr = IpCidr::Ipv4(Ipv4Cidr::new(Ipv4Address::new(10, 0, 0, 0), 8));
+        let first = Route::new_via_router(cidr, IpAddress::v4(192, 168, 1, 1), 20);
+        let second = Route::new_via_router(cidr, IpAddress::v4(192, 168, 1, 2), 10);
+        assert_eq!(routes.add(first), Ok(()));
+        assert_eq!(routes.add(second), Ok(()));
+        assert_eq!(routes.lookup(&IpAddress::v4(10, 0, 0, 1)), Some(&second));
+
+        // Adding a route again updates its metric.
+        let first = Route { metric: 5, ..first };
+        assert_eq!(routes.add(first), Ok(()));
+        assert_eq!(routes.iter().count(), 2);
+        assert_eq!(routes.lookup(&IpAddress::v4(10, 0, 0, 1)), Some(&first));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_invalid_router() {
+        let mut routes = Routes::new(vec![]);
+        let cidr = IpCidr::new(IpAddress::v4(10, 0, 0, 0), 8);
+        assert_eq!(routes.add(Route::new_via_router(cidr, IpAddress::v4(255, 255, 255, 255), 0)),
+                   Err(Error::Unaddressable));
+        #[cfg(feature = "proto-ipv6")]
+        assert_eq!(routes.add(Route::new_via_router(cidr, Ipv6Address::LOOPBACK, 0)),
+                   Err(Error::Unaddressable));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv6")]
+    fn test_exhausted() {
+        let mut storage = [None; 1];
+        let mut routes = Routes::new(&mut storage[..]);
+        let cidr = IpCidr::Ipv6(Ipv6Cidr::new(Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0),
+                                              32));
+        let router = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        assert_eq!(routes.add(Route::new_via_router(cidr, router, 0)), Ok(()));
+        assert_eq!(routes.add(Route::new_on_link(cidr, 0)), Err(Error::Exhausted));
+        routes.clear();
+        assert_eq!(routes.add(Route::new_on_link(cidr, 0)), Ok(()));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv6")]
+    fn test_expire() {
+        let mut routes = Routes::new(vec![]);
+        let cidr = IpCidr::Ipv6(Ipv6Cidr::new(Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0),
+                                              32));
+        let router = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        let route = Route { expires_at: Some(2_000), ..Route::new_via_router(cidr, router, 0) };
+        assert_eq!(routes.add(route), Ok(()));
+        assert_eq!(routes.add(Route::new_on_link(cidr, 10)), Ok(()));
+        assert_eq!(routes.poll_at(), Some(2_000));
+
+        // Changing a route in place extends its lifetime.
+        for route in routes.iter_mut().filter(|route| route.via_router.is_some()) {
+            route.expires_at = Some(3_000);
+        }
+        routes.expire(2_000);
+        assert_eq!(routes.iter().count(), 2);
+        routes.expire(3_000);
+        assert_eq!(routes.iter().count(), 1);
+        assert_eq!(routes.poll_at(), None);
+
+        routes.retain(|route| route.via_router.is_some());
+        assert_eq!(routes.iter().count(), 0);
+    }
+}
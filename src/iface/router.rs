@@ -0,0 +1,208 @@
+// Heads up! Before working on this file you should read the parts
+// of RFC 4861 that discuss the Default Router List (§ 5.1 and § 6.3.4)
+// and default router selection (§ 6.3.6).
+
+use managed::ManagedSlice;
+
+use {Error, Result};
+use wire::Ipv6Address;
+
+/// A router that advertised itself as a default router.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DefaultRouter {
+    /// The link-local address of the router.
+    pub addr:       Ipv6Address,
+    /// The timestamp past which the router is no longer a default router.
+    pub expires_at: u64,
+}
+
+/// A list of default routers, learned from Router Advertisements.
+///
+/// # Examples
+///
+/// On systems with heap, this list can be created with:
+///
+/// ```rust
+/// use smoltcp::iface::DefaultRouters;
+/// let mut default_routers = DefaultRouters::new(vec![]);
+/// ```
+///
+/// On systems without heap, use:
+///
+/// ```rust
+/// use smoltcp::iface::DefaultRouters;
+/// let mut default_routers_storage = [None; 2];
+/// let mut default_routers = DefaultRouters::new(&mut default_routers_storage[..]);
+/// ```
+#[derive(Debug)]
+pub struct DefaultRouters<'a> {
+    storage: ManagedSlice<'a, Option<DefaultRouter>>,
+}
+
+impl<'a> DefaultRouters<'a> {
+    /// Create a default router list. The backing storage is cleared upon creation.
+    pub fn new<T>(storage: T) -> DefaultRouters<'a>
+            where T: Into<ManagedSlice<'a, Option<DefaultRouter>>> {
+        let mut storage = storage.into();
+        for slot in storage.iter_mut() {
+            *slot = None;
+        }
+
+        DefaultRouters { storage }
+    }
+
+    /// Return an iterator over the routers in the list, including the ones that have
+    /// expired but were not yet removed by polling the interface.
+    pub fn iter(&self) -> Iter {
+        Iter { slots: self.storage.iter() }
+    }
+
+    /// Add, refresh or remove a router, according to the router lifetime, in seconds,
+    /// it has advertised.
+    ///
+    /// Returns `Err(Error::Exhausted)` if there is no space for a new router.
+    pub(crate) fn update(&mut self, addr: Ipv6Address, lifetime: u16,
+                         timestamp: u64) -> Result<()> {
+        let expires_at = timestamp + lifetime as u64 * 1000;
+
+        for slot in self.storage.iter_mut() {
+            let matches = slot.map_or(false, |router| router.addr == addr);
+            if matches {
+                if lifetime == 0 {
+                    net_debug!("default router {} is leaving", addr);
+                    *slot = None
+                } else {
+                    *slot = Some(DefaultRouter { addr, expires_at })
+                }
+                return Ok(())
+            }
+        }
+        if lifetime == 0 { return Ok(()) }
+
+        net_debug!("adding default router {}", addr);
+        let router = Some(DefaultRouter { addr, expires_at });
+        for slot in self.storage.iter_mut() {
+            if slot.is_none() {
+                *slot = router;
+                return Ok(())
+            }
+        }
+
+        match self.storage {
+            ManagedSlice::Borrowed(_) => Err(Error::Exhausted),
+            #[cfg(any(feature = "std", feature = "alloc"))]
+            ManagedSlice::Owned(ref mut routers) => {
+                routers.push(router);
+                Ok(())
+            }
+        }
+    }
+
+    /// Remove the routers whose lifetime has ended.
+    pub(crate) fn expire(&mut self, timestamp: u64) {
+        for slot in self.storage.iter_mut() {
+            let expired = slot.map_or(false, |router| timestamp >= router.expires_at);
+            if expired {
+                net_debug!("default router {} expired", slot.unwrap().addr);
+                *slot = None
+            }
+        }
+    }
+
+    /// Return the moment at which the next router expires, if any.
+    pub(crate) fn poll_at(&self) -> Option<u64> {
+        self.iter().map(|router| router.expires_at).min()
+    }
+
+    /// Select a default router for an off-link destination.
+    ///
+    /// Routers whose link-layer address is known, according to the given predicate,
+    /// are preferred over the ones that may not be reachable; among those, the router
+    /// learned first is selected. See RFC 4861 § 6.3.6.
+    pub(crate) fn select<F>(&self, timestamp: u64, is_reachable: F) -> Option<Ipv6Address>
+            where F: Fn(&Ipv6Address) -> bool {
+        let mut routers = self.iter().filter(|router| timestamp < router.expires_at);
+        let fallback = routers.clone().next().map(|router| router.addr);
+        routers.find(|router| is_reachable(&router.addr))
+               .map(|router| router.addr)
+               .or(fallback)
+    }
+}
+
+/// An iterator over the routers in a [DefaultRouters](struct.DefaultRouters.html) list.
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    slots: ::core::slice::Iter<'a, Option<DefaultRouter>>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a DefaultRouter;
+
+    fn next(&mut self) -> Option<&'a DefaultRouter> {
+        while let Some(slot) = self.slots.next() {
+            if let Some(ref router) = *slot {
+                return Some(router)
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use super::*;
+
+    const ROUTER_1: Ipv6Address = Ipv6Address([0xfe, 0x80, 0, 0, 0, 0, 0, 0,
+                                               0, 0, 0, 0, 0, 0, 0, 1]);
+    const ROUTER_2: Ipv6Address = Ipv6Address([0xfe, 0x80, 0, 0, 0, 0, 0, 0,
+                                               0, 0, 0, 0, 0, 0, 0, 2]);
+
+    #[test]
+    fn test_update() {
+        let mut storage = [None; 1];
+        let mut routers = DefaultRouters::new(&mut storage[..]);
+
+        assert_eq!(routers.update(ROUTER_1, 1800, 1_000), Ok(()));
+        assert_eq!(routers.iter().collect::<Vec<_>>(),
+                   vec![&DefaultRouter { addr: ROUTER_1, expires_at: 1_801_000 }]);
+        assert_eq!(routers.update(ROUTER_1, 600, 2_000), Ok(()));
+        assert_eq!(routers.poll_at(), Some(602_000));
+        assert_eq!(routers.update(ROUTER_2, 1800, 2_000), Err(Error::Exhausted));
+
+        // A zero lifetime removes the router.
+        assert_eq!(routers.update(ROUTER_1, 0, 3_000), Ok(()));
+        assert_eq!(routers.iter().next(), None);
+        assert_eq!(routers.update(ROUTER_2, 0, 3_000), Ok(()));
+        assert_eq!(routers.iter().next(), None);
+    }
+
+    #[test]
+    fn test_expire() {
+        let mut routers = DefaultRouters::new(vec![]);
+        assert_eq!(routers.update(ROUTER_1, 10, 0), Ok(()));
+        assert_eq!(routers.update(ROUTER_2, 20, 0), Ok(()));
+
+        routers.expire(9_999);
+        assert_eq!(routers.iter().count(), 2);
+        routers.expire(10_000);
+        assert_eq!(routers.iter().map(|router| router.addr).collect::<Vec<_>>(),
+                   vec![ROUTER_2]);
+        assert_eq!(routers.poll_at(), Some(20_000));
+    }
+
+    #[test]
+    fn test_select() {
+        let mut routers = DefaultRouters::new(vec![]);
+        assert_eq!(routers.select(0, |_| true), None);
+
+        assert_eq!(routers.update(ROUTER_1, 10, 0), Ok(()));
+        assert_eq!(routers.update(ROUTER_2, 20, 0), Ok(()));
+        assert_eq!(routers.select(0, |_| false), Some(ROUTER_1));
+        assert_eq!(routers.select(0, |addr| *addr == ROUTER_2), Some(ROUTER_2));
+
+        // An expired router is never selected, even before it is removed.
+        assert_eq!(routers.select(10_000, |addr| *addr == ROUTER_1), Some(ROUTER_2));
+        assert_eq!(routers.select(20_000, |_| true), None);
+    }
+}
@@ -0,0 +1,486 @@
+// Heads up! Before working on this file you should read RFC 4944, which describes
+// the transmission of IPv6 packets over IEEE 802.15.4 networks, and RFC 6282, which
+// describes the compression of their headers.
+
+use core::mem;
+use managed::ManagedSlice;
+
+use {Error, Result};
+use phy::TxToken;
+use wire::{IpProtocol, Ipv6Address, Ipv6Packet, Ipv6Repr, UdpPacket};
+use wire::{Ieee802154Address, Ieee802154Frame, Ieee802154FrameType, Ieee802154Pan,
+           Ieee802154Repr};
+use wire::{SixlowpanDispatch, SixlowpanFragmentHeader, SixlowpanFragmentRepr,
+           SixlowpanIphcPacket, SixlowpanIphcRepr, SixlowpanUdpNhcPacket,
+           SixlowpanUdpNhcRepr, sixlowpan_is_udp_nhc,
+           SIXLOWPAN_FIRST_FRAGMENT_HEADER_LEN, SIXLOWPAN_FRAGMENT_HEADER_LEN};
+
+/// The time after which a datagram that is not reassembled yet is discarded,
+/// in milliseconds. See RFC 4944 § 5.3.
+const REASSEMBLY_TIMEOUT: u64 = 60_000;
+
+/// The length of an uncompressed IPv6 header.
+const IPV6_HEADER_LEN: usize = 40;
+
+/// The length of an uncompressed UDP header.
+const UDP_HEADER_LEN: usize = 8;
+
+/// The largest datagram size a fragmentation header can carry.
+const MAX_DATAGRAM_SIZE: usize = 0x7ff;
+
+/// The datagram being reassembled.
+#[derive(Debug, Clone, Copy)]
+struct Reassembly {
+    src_addr:   Ieee802154Address,
+    dst_addr:   Ieee802154Address,
+    tag:        u16,
+    size:       usize,
+    /// A bit for each 8-octet unit of the datagram, set once it was received.
+    received:   [u64; 4],
+    expires_at: u64,
+}
+
+impl Reassembly {
+    fn matches(&self, src_addr: Ieee802154Address, dst_addr: Ieee802154Address,
+               repr: &SixlowpanFragmentRepr) -> bool {
+        self.src_addr == src_addr && self.dst_addr == dst_addr &&
+            self.tag == repr.datagram_tag && self.size == repr.datagram_size as usize
+    }
+
+    /// Mark the octets from `offset` to `offset + len` as received, and return whether
+    /// the whole datagram was.
+    fn add(&mut self, offset: usize, len: usize) -> bool {
+        for unit in offset / 8..(offset + len + 7) / 8 {
+            self.received[unit / 64] |= 1 << (unit % 64);
+        }
+        (0..(self.size + 7) / 8).all(|unit| self.received[unit / 64] & (1 << (unit % 64)) != 0)
+    }
+}
+
+/// The datagram whose fragments are being sent.
+#[derive(Debug, Clone, Copy)]
+struct Outgoing {
+    dst_addr: Ieee802154Address,
+    tag:      u16,
+    size:     usize,
+    /// The offset of the next fragment in the uncompressed datagram.
+    offset:   usize,
+}
+
+/// The 6LoWPAN adaptation layer of an interface.
+///
+/// It carries the IPv6 packets of an interface whose device medium is IEEE 802.15.4
+/// in data frames, compressing their headers as described in RFC 6282, and fragmenting
+/// the packets that do not fit in a single frame as described in RFC 4944.
+///
+/// This implementation has the following limitations:
+///
+/// - Headers are compressed without contexts, so only the addresses derived from
+///   the link-local prefix are elided; packets compressed with a context are dropped.
+/// - Only one datagram is reassembled at a time, from a receive buffer that has to be
+///   large enough for it, and for the packets received unfragmented meanwhile, which
+///   are decompressed after it. A buffer of 1280 octets, the IPv6 minimum MTU, plus
+///   twice the MTU of the device is enough for every packet the interface sends.
+/// - Only one datagram is fragmented at a time, from a transmit buffer that has to be
+///   large enough for the packets of the IPv6 minimum MTU.
+/// - The link-layer address of a neighbor is derived from the interface identifier of
+///   its IPv6 address, as 6LoWPAN Neighbor Discovery (RFC 6775) is not implemented;
+///   multicast packets are broadcast.
+pub struct Sixlowpan<'a> {
+    addr:            Ieee802154Address,
+    pan_id:          Ieee802154Pan,
+    rx_buffer:       ManagedSlice<'a, u8>,
+    tx_buffer:       ManagedSlice<'a, u8>,
+    reassembly:      Option<Reassembly>,
+    outgoing:        Option<Outgoing>,
+    next_tag:        u16,
+    sequence_number: u8,
+}
+
+impl<'a> Sixlowpan<'a> {
+    /// Create a 6LoWPAN adaptation layer for the device with the given address,
+    /// in the given PAN, with the given buffers.
+    pub fn new<T, U>(addr: Ieee802154Address, pan_id: Ieee802154Pan,
+                     rx_buffer: T, tx_buffer: U) -> Sixlowpan<'a>
+            where T: Into<ManagedSlice<'a, u8>>, U: Into<ManagedSlice<'a, u8>> {
+        Sixlowpan {
+            addr, pan_id,
+            rx_buffer:       rx_buffer.into(),
+            tx_buffer:       tx_buffer.into(),
+            reassembly:      None,
+            outgoing:        None,
+            next_tag:        0,
+            sequence_number: 0,
+        }
+    }
+
+    /// Return the IEEE 802.15.4 address of the device.
+    pub fn addr(&self) -> Ieee802154Address {
+        self.addr
+    }
+
+    /// Return the identifier of the PAN of the device.
+    pub fn pan_id(&self) -> Ieee802154Pan {
+        self.pan_id
+    }
+
+    /// Return the link-local address derived from the address of the device.
+    pub fn link_local_addr(&self) -> Ipv6Address {
+        let mut bytes = [0; 16];
+        bytes[..2].copy_from_slice(&[0xfe, 0x80]);
+        bytes[8..].copy_from_slice(&self.addr.as_interface_id());
+        Ipv6Address(bytes)
+    }
+
+    /// Take the receive buffer out, so that the packet decompressed into it can be
+    /// processed while the adaptation layer is used to send the response.
+    pub(crate) fn take_rx_buffer(&mut self) -> ManagedSlice<'a, u8> {
+        mem::replace(&mut self.rx_buffer, ManagedSlice::Borrowed(&mut []))
+    }
+
+    /// Put the receive buffer taken out by `take_rx_buffer` back.
+    pub(crate) fn restore_rx_buffer(&mut self, rx_buffer: ManagedSlice<'a, u8>) {
+        self.rx_buffer = rx_buffer
+    }
+
+    /// Query whether a received frame is addressed to the device.
+    pub(crate) fn accepts(&self, repr: &Ieee802154Repr) -> bool {
+        let pan_id_matches = repr.dst_pan_id.map_or(true, |pan_id|
+            pan_id == self.pan_id || pan_id == Ieee802154Pan::BROADCAST);
+        let addr_matches = repr.dst_addr.map_or(false, |addr|
+            addr == self.addr || addr.is_broadcast());
+        repr.frame_type == Ieee802154FrameType::Data && pan_id_matches && addr_matches
+    }
+
+    /// Decompress the payload of a data frame received from `src_addr` to `dst_addr`
+    /// into the receive buffer, or add it to the datagram being reassembled there,
+    /// and return the IPv6 packet once it is complete.
+    pub(crate) fn receive<'b>(&mut self, rx_buffer: &'b mut [u8], payload: &[u8],
+                              src_addr: Ieee802154Address, dst_addr: Ieee802154Address,
+                              timestamp: u64) -> Result<Option<&'b [u8]>> {
+        if self.reassembly.map_or(false, |reassembly| timestamp >= reassembly.expires_at) {
+            net_debug!("6LoWPAN reassembly timed out");
+            self.reassembly = None
+        }
+
+        match SixlowpanDispatch::of_packet(payload)? {
+            SixlowpanDispatch::Ipv6 | SixlowpanDispatch::Iphc => {
+                // A packet received unfragmented is decompressed after the datagram
+                // being reassembled, if any.
+                let start = self.reassembly.map_or(0, |reassembly| reassembly.size);
+                let buffer = &mut rx_buffer[start..];
+                let (header_len, compressed_len) =
+                    decompress(buffer, payload, src_addr, dst_addr, None)?;
+                let rest = &payload[compressed_len..];
+                if header_len + rest.len() > buffer.len() {
+                    net_debug!("6LoWPAN packet too large for the buffer");
+                    return Err(Error::Exhausted)
+                }
+                buffer[header_len..header_len + rest.len()].copy_from_slice(rest);
+                Ok(Some(&buffer[..header_len + rest.len()]))
+            }
+            SixlowpanDispatch::FirstFragment | SixlowpanDispatch::Fragment => {
+                let header = SixlowpanFragmentHeader::new_checked(payload)?;
+                let repr = SixlowpanFragmentRepr::parse(&header)?;
+                let result = self.reassemble(rx_buffer, header.payload(), &repr,
+                                             src_addr, dst_addr, timestamp);
+                match result {
+                    Ok(true) => {
+                        self.reassembly = None;
+                        Ok(Some(&rx_buffer[..repr.datagram_size as usize]))
+                    }
+                    Ok(false) => Ok(None),
+                    Err(Error::Fragmented) => Err(Error::Fragmented),
+                    Err(err) => {
+                        net_debug!("dropping 6LoWPAN datagram with tag {}", repr.datagram_tag);
+                        self.reassembly = None;
+                        Err(err)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Add a fragment to the datagram being reassembled, and return whether
+    /// the datagram is complete.
+    fn reassemble(&mut self, rx_buffer: &mut [u8], data: &[u8], repr: &SixlowpanFragmentRepr,
+                  src_addr: Ieee802154Address, dst_addr: Ieee802154Address,
+                  timestamp: u64) -> Result<bool> {
+        let size = repr.datagram_size as usize;
+        match self.reassembly {
+            Some(ref reassembly) if reassembly.matches(src_addr, dst_addr, repr) => (),
+            Some(_) => {
+                net_debug!("dropping 6LoWPAN fragment while another datagram is reassembled");
+                return Err(Error::Fragmented)
+            }
+            None if size > rx_buffer.len() => {
+                net_debug!("6LoWPAN datagram of {} octets too large for the buffer", size);
+                return Err(Error::Fragmented)
+            }
+            None => {
+                self.reassembly = Some(Reassembly {
+                    src_addr, dst_addr, size,
+                    tag:        repr.datagram_tag,
+                    received:   [0; 4],
+                    expires_at: timestamp + REASSEMBLY_TIMEOUT,
+                })
+            }
+        }
+
+        let buffer = &mut rx_buffer[..size];
+        let (offset, header_len, rest) = if repr.datagram_offset == 0 {
+            let (header_len, compressed_len) =
+                decompress(buffer, data, src_addr, dst_addr, Some(size))?;
+            (0, header_len, &data[compressed_len..])
+        } else {
+            (repr.datagram_offset as usize, 0, data)
+        };
+        let start = offset + header_len;
+        if start + rest.len() > size {
+            return Err(Error::Malformed)
+        }
+        buffer[start..start + rest.len()].copy_from_slice(rest);
+        Ok(self.reassembly.as_mut().unwrap().add(offset, header_len + rest.len()))
+    }
+
+    /// Emit an IPv6 packet of the given length into the transmit buffer with `f`,
+    /// and return its destination address.
+    pub(crate) fn stage<F>(&mut self, packet_len: usize, f: F) -> Result<Ipv6Address>
+        where F: FnOnce(&mut [u8])
+    {
+        if self.outgoing.is_some() {
+            return Err(Error::Exhausted)
+        }
+        if packet_len > self.tx_buffer.len() || packet_len > MAX_DATAGRAM_SIZE {
+            net_debug!("packet of {} octets too large for the 6LoWPAN buffer", packet_len);
+            return Err(Error::Exhausted)
+        }
+
+        f(&mut self.tx_buffer[..packet_len]);
+        Ok(Ipv6Packet::new_checked(&self.tx_buffer[..packet_len])?.dst_addr())
+    }
+
+    /// Send the packet emitted by `stage` to the given link-layer address in a frame
+    /// no longer than `mtu`, or in its first fragment if it does not fit.
+    pub(crate) fn dispatch<Tx>(&mut self, tx_token: Tx, timestamp: u64, packet_len: usize,
+                               dst_addr: Ieee802154Address, mtu: usize) -> Result<()>
+        where Tx: TxToken
+    {
+        let (iphc_repr, udp_repr, header_len) = self.compression(packet_len)?;
+        let mac_repr = self.mac_repr(dst_addr);
+        let mac_len = mac_repr.buffer_len();
+        let src_addr = self.addr;
+        let iphc_len = iphc_repr.buffer_len(src_addr, dst_addr);
+        let compressed_len = iphc_len + udp_repr.map_or(0, |repr| repr.buffer_len());
+        let emit_compressed = |buffer: &mut [u8]| {
+            iphc_repr.emit(&mut SixlowpanIphcPacket::new(&mut buffer[..iphc_len]),
+                           src_addr, dst_addr);
+            if let Some(udp_repr) = udp_repr {
+                udp_repr.emit(&mut SixlowpanUdpNhcPacket::new(&mut buffer[iphc_len..]));
+            }
+        };
+
+        let rest = &self.tx_buffer[header_len..packet_len];
+        if mac_len + compressed_len + rest.len() <= mtu {
+            let frame_len = mac_len + compressed_len + rest.len();
+            return tx_token.consume(timestamp, frame_len, |tx_buffer| {
+                mac_repr.emit(&mut Ieee802154Frame::new(&mut *tx_buffer));
+                let (compressed, payload) = tx_buffer[mac_len..].split_at_mut(compressed_len);
+                emit_compressed(compressed);
+                payload.copy_from_slice(rest);
+                Ok(())
+            })
+        }
+
+        // Every fragment but the last carries a multiple of 8 octets of the datagram.
+        let room = mtu.checked_sub(mac_len + SIXLOWPAN_FIRST_FRAGMENT_HEADER_LEN +
+                                   compressed_len).unwrap_or(0);
+        let first_len = (header_len + room) / 8 * 8;
+        if first_len <= header_len {
+            net_debug!("MTU {} too small for 6LoWPAN fragments", mtu);
+            return Err(Error::Exhausted)
+        }
+        let data = &rest[..first_len - header_len];
+        let frag_repr = SixlowpanFragmentRepr {
+            datagram_size:   packet_len as u16,
+            datagram_tag:    self.next_tag,
+            datagram_offset: 0
+        };
+        let frame_len = mac_len + frag_repr.buffer_len() + compressed_len + data.len();
+        tx_token.consume(timestamp, frame_len, |tx_buffer| {
+            mac_repr.emit(&mut Ieee802154Frame::new(&mut *tx_buffer));
+            let tx_buffer = &mut tx_buffer[mac_len..];
+            frag_repr.emit(&mut SixlowpanFragmentHeader::new(&mut *tx_buffer));
+            let tx_buffer = &mut tx_buffer[frag_repr.buffer_len()..];
+            let (compressed, payload) = tx_buffer.split_at_mut(compressed_len);
+            emit_compressed(compressed);
+            payload.copy_from_slice(data);
+            Ok(())
+        })?;
+
+        net_trace!("6LoWPAN: fragmenting datagram of {} octets with tag {}",
+                   packet_len, self.next_tag);
+        self.outgoing = Some(Outgoing {
+            dst_addr,
+            tag:    self.next_tag,
+            size:   packet_len,
+            offset: first_len
+        });
+        self.next_tag = self.next_tag.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Query whether there are fragments of a datagram left to send.
+    pub(crate) fn is_pending(&self) -> bool {
+        self.outgoing.is_some()
+    }
+
+    /// Send the next fragment of the datagram being fragmented, in a frame
+    /// no longer than `mtu`.
+    pub(crate) fn dispatch_fragment<Tx>(&mut self, tx_token: Tx, timestamp: u64,
+                                        mtu: usize) -> Result<()>
+        where Tx: TxToken
+    {
+        let outgoing = match self.outgoing {
+            Some(outgoing) => outgoing,
+            None => return Ok(())
+        };
+        let mac_repr = self.mac_repr(outgoing.dst_addr);
+        let mac_len = mac_repr.buffer_len();
+        let room = mtu - mac_len - SIXLOWPAN_FRAGMENT_HEADER_LEN;
+        let remaining = outgoing.size - outgoing.offset;
+        let data_len = if remaining <= room { remaining } else { room / 8 * 8 };
+        let data = &self.tx_buffer[outgoing.offset..outgoing.offset + data_len];
+        let frag_repr = SixlowpanFragmentRepr {
+            datagram_size:   outgoing.size as u16,
+            datagram_tag:    outgoing.tag,
+            datagram_offset: outgoing.offset as u16
+        };
+        let frame_len = mac_len + frag_repr.buffer_len() + data_len;
+        let result = tx_token.consume(timestamp, frame_len, |tx_buffer| {
+            mac_repr.emit(&mut Ieee802154Frame::new(&mut *tx_buffer));
+            let tx_buffer = &mut tx_buffer[mac_len..];
+            frag_repr.emit(&mut SixlowpanFragmentHeader::new(&mut *tx_buffer));
+            tx_buffer[frag_repr.buffer_len()..].copy_from_slice(data);
+            Ok(())
+        });
+
+        // A datagram with a fragment missing cannot be reassembled, so the rest
+        // of it is not sent either.
+        if result.is_err() || outgoing.offset + data_len == outgoing.size {
+            self.outgoing = None
+        } else {
+            self.outgoing = Some(Outgoing { offset: outgoing.offset + data_len, ..outgoing })
+        }
+        result
+    }
+
+    /// Return the representations of the compressed headers of the packet emitted
+    /// by `stage`, and the length of the headers they compress.
+    fn compression(&self, packet_len: usize) ->
+                  Result<(SixlowpanIphcRepr, Option<SixlowpanUdpNhcRepr>, usize)> {
+        let packet = Ipv6Packet::new_checked(&self.tx_buffer[..packet_len])?;
+        let ipv6_repr = Ipv6Repr::parse(&packet)?;
+        let udp_repr = if ipv6_repr.next_header == IpProtocol::Udp &&
+                              packet.payload().len() >= UDP_HEADER_LEN {
+            let udp_packet = UdpPacket::new(packet.payload());
+            Some(SixlowpanUdpNhcRepr {
+                src_port: udp_packet.src_port(),
+                dst_port: udp_packet.dst_port(),
+                checksum: udp_packet.checksum()
+            })
+        } else {
+            None
+        };
+        let iphc_repr = SixlowpanIphcRepr {
+            src_addr:    ipv6_repr.src_addr,
+            dst_addr:    ipv6_repr.dst_addr,
+            next_header: if udp_repr.is_some() { None } else { Some(ipv6_repr.next_header) },
+            hop_limit:   ipv6_repr.hop_limit,
+            ecn:         ipv6_repr.ecn,
+            dscp:        ipv6_repr.dscp,
+            flow_label:  ipv6_repr.flow_label
+        };
+        let header_len = IPV6_HEADER_LEN + udp_repr.map_or(0, |_| UDP_HEADER_LEN);
+        Ok((iphc_repr, udp_repr, header_len))
+    }
+
+    /// Return the MAC header of the next data frame to the given address.
+    fn mac_repr(&mut self, dst_addr: Ieee802154Address) -> Ieee802154Repr {
+        let sequence_number = self.sequence_number;
+        self.sequence_number = sequence_number.wrapping_add(1);
+        Ieee802154Repr {
+            frame_type:         Ieee802154FrameType::Data,
+            frame_pending:      false,
+            ack_request:        !dst_addr.is_broadcast(),
+            sequence_number,
+            pan_id_compression: true,
+            dst_pan_id:         Some(self.pan_id),
+            dst_addr:           Some(dst_addr),
+            src_pan_id:         Some(self.pan_id),
+            src_addr:           Some(self.addr),
+        }
+    }
+}
+
+/// Decompress the 6LoWPAN header at the start of `data` into an IPv6 header, followed by
+/// a UDP header if it is compressed too, at the start of `buffer`.
+///
+/// `datagram_size` is the length of the whole IPv6 packet if `data` is its first fragment;
+/// otherwise, the packet ends with `data`. Returns the length of the uncompressed headers,
+/// and the one of their compressed form.
+fn decompress(buffer: &mut [u8], data: &[u8], src_addr: Ieee802154Address,
+              dst_addr: Ieee802154Address, datagram_size: Option<usize>) -> Result<(usize, usize)> {
+    match SixlowpanDispatch::of_packet(data)? {
+        // The IPv6 header follows the dispatch value as it is.
+        SixlowpanDispatch::Ipv6 => return Ok((0, 1)),
+        SixlowpanDispatch::Iphc => (),
+        _ => return Err(Error::Malformed)
+    }
+
+    let packet = SixlowpanIphcPacket::new_checked(data)?;
+    let iphc_repr = SixlowpanIphcRepr::parse(&packet, src_addr, dst_addr)?;
+    let mut compressed_len = packet.header_len();
+    let (next_header, udp_repr) = match iphc_repr.next_header {
+        Some(next_header) => (next_header, None),
+        None if sixlowpan_is_udp_nhc(packet.payload()) => {
+            let udp_packet = SixlowpanUdpNhcPacket::new_checked(packet.payload())?;
+            let udp_repr = SixlowpanUdpNhcRepr::parse(&udp_packet)?;
+            compressed_len += udp_packet.header_len();
+            (IpProtocol::Udp, Some(udp_repr))
+        }
+        // Extension headers are not compressed by this implementation, and not
+        // decompressed either.
+        None => return Err(Error::Unrecognized)
+    };
+
+    let header_len = IPV6_HEADER_LEN + udp_repr.map_or(0, |_| UDP_HEADER_LEN);
+    let packet_len = datagram_size.unwrap_or(header_len + data.len() - compressed_len);
+    if packet_len < header_len {
+        return Err(Error::Malformed)
+    }
+    if packet_len > buffer.len() {
+        net_debug!("6LoWPAN packet of {} octets too large for the buffer", packet_len);
+        return Err(Error::Exhausted)
+    }
+
+    let ipv6_repr = Ipv6Repr {
+        src_addr:    iphc_repr.src_addr,
+        dst_addr:    iphc_repr.dst_addr,
+        next_header: next_header,
+        payload_len: packet_len - IPV6_HEADER_LEN,
+        hop_limit:   iphc_repr.hop_limit,
+        flow_label:  iphc_repr.flow_label,
+        ecn:         iphc_repr.ecn,
+        dscp:        iphc_repr.dscp
+    };
+    ipv6_repr.emit(&mut Ipv6Packet::new(&mut buffer[..IPV6_HEADER_LEN]));
+    if let Some(udp_repr) = udp_repr {
+        let mut udp_packet = UdpPacket::new(&mut buffer[IPV6_HEADER_LEN..header_len]);
+        udp_packet.set_src_port(udp_repr.src_port);
+        udp_packet.set_dst_port(udp_repr.dst_port);
+        udp_packet.set_len((packet_len - IPV6_HEADER_LEN) as u16);
+        udp_packet.set_checksum(udp_repr.checksum);
+    }
+    Ok((header_len, compressed_len))
+}
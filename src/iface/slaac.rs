@@ -0,0 +1,220 @@
+// Heads up! Before working on this file you should read RFC 4862, which describes
+// stateless address autoconfiguration, and RFC 7217, which describes how stable
+// opaque interface identifiers are generated.
+
+use wire::{EthernetAddress, Ipv6Address, Ipv6Cidr, NdiscPrefixInfo};
+use super::AddressLifetime;
+use super::fnv::Fnv1a64;
+
+/// The lifetime value that represents infinity.
+pub(crate) const INFINITE_LIFETIME: u32 = 0xffffffff;
+/// A received valid lifetime never shortens the remaining valid lifetime of an address
+/// below two hours, so that an unauthenticated advertisement cannot take it away.
+/// See RFC 4862 § 5.5.3 (e).
+const TWO_HOURS: u64 = 2 * 60 * 60 * 1000;
+
+/// A mode of generating the interface identifiers of autoconfigured IPv6 addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlaacMode {
+    /// Do not autoconfigure any addresses.
+    Disabled,
+    /// Derive the interface identifier from the Ethernet address, in the modified
+    /// EUI-64 format described in RFC 4291 § 2.5.1.
+    ///
+    /// This is simple, but makes the host trackable across networks.
+    Eui64,
+    /// Derive a stable, opaque interface identifier from the prefix, the Ethernet address
+    /// and a secret, as described in RFC 7217.
+    ///
+    /// The secret should be chosen randomly once, and then persisted, so that the
+    /// addresses are stable within a network, but unrelated across networks.
+    StablePrivacy {
+        secret: [u8; 16]
+    }
+}
+
+impl Default for SlaacMode {
+    fn default() -> SlaacMode {
+        SlaacMode::Disabled
+    }
+}
+
+impl SlaacMode {
+    /// Query whether addresses are autoconfigured at all.
+    pub fn is_enabled(&self) -> bool {
+        *self != SlaacMode::Disabled
+    }
+
+    /// Return the address formed from the upper 64 bits of the given prefix, and an
+    /// interface identifier for the given Ethernet address.
+    ///
+    /// The DAD counter is the number of times an address was found to be a duplicate
+    /// for this prefix; it only changes the result in the `StablePrivacy` mode.
+    /// Returns `None` if autoconfiguration is disabled.
+    pub fn address(&self, prefix: &Ipv6Address, ethernet_addr: EthernetAddress,
+                   dad_counter: u8) -> Option<Ipv6Address> {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&prefix.as_bytes()[..8]);
+
+        let mac = ethernet_addr.as_bytes();
+        match *self {
+            SlaacMode::Disabled => return None,
+            SlaacMode::Eui64 => {
+                bytes[8..].copy_from_slice(&[mac[0] ^ 0x02, mac[1], mac[2], 0xff,
+                                             0xfe, mac[3], mac[4], mac[5]]);
+            }
+            SlaacMode::StablePrivacy { secret } => {
+                let mut hash = Fnv1a64::new();
+                hash.write(&prefix.as_bytes()[..8]);
+                hash.write(mac);
+                hash.write(&[dad_counter]);
+                hash.write(&secret);
+
+                let iid = hash.finish();
+                for (i, byte) in bytes[8..].iter_mut().enumerate() {
+                    *byte = (iid >> (56 - i * 8)) as u8;
+                }
+                // The all-zeros interface identifier is the subnet-router anycast
+                // address; see RFC 5453.
+                if bytes[8..] == [0; 8] { bytes[15] = 1 }
+            }
+        }
+        Some(Ipv6Address(bytes))
+    }
+}
+
+/// An IPv6 address autoconfigured from a Router Advertisement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlaacAddress {
+    pub cidr:     Ipv6Cidr,
+    /// The lifetimes of the address, taken from those of its prefix.
+    pub lifetime: AddressLifetime,
+}
+
+impl SlaacAddress {
+    /// Create an address with the lifetimes of the given prefix information.
+    pub(crate) fn new(cidr: Ipv6Cidr, info: &NdiscPrefixInfo, timestamp: u64) -> SlaacAddress {
+        SlaacAddress {
+            cidr,
+            lifetime: AddressLifetime::new(cidr.address().into(),
+                                           Self::deadline(info.preferred_lifetime, timestamp),
+                                           Self::deadline(info.valid_lifetime, timestamp)),
+        }
+    }
+
+    fn deadline(lifetime: u32, timestamp: u64) -> Option<u64> {
+        if lifetime == INFINITE_LIFETIME {
+            None
+        } else {
+            Some(timestamp + lifetime as u64 * 1000)
+        }
+    }
+
+    /// Query whether the address was formed from the given prefix.
+    pub(crate) fn matches(&self, info: &NdiscPrefixInfo) -> bool {
+        self.cidr.prefix_len() == info.prefix_len &&
+            Ipv6Cidr::new(info.prefix, info.prefix_len).contains_addr(&self.cidr.address())
+    }
+
+    /// Update the lifetimes from a later advertisement of the same prefix,
+    /// as described in RFC 4862 § 5.5.3 (e).
+    pub(crate) fn update(&mut self, info: &NdiscPrefixInfo, timestamp: u64) {
+        let lifetime = &mut self.lifetime;
+        lifetime.preferred_until = Self::deadline(info.preferred_lifetime, timestamp);
+
+        match Self::deadline(info.valid_lifetime, timestamp) {
+            None => lifetime.valid_until = None,
+            Some(received) => {
+                let remaining = lifetime.valid_until.unwrap_or(u64::max_value());
+                let two_hours = timestamp + TWO_HOURS;
+                if received > two_hours || received > remaining {
+                    lifetime.valid_until = Some(received)
+                } else if remaining > two_hours {
+                    lifetime.valid_until = Some(two_hours)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MAC: EthernetAddress = EthernetAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+    const PREFIX: Ipv6Address = Ipv6Address([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 1,
+                                             0, 0, 0, 0, 0, 0, 0, 0]);
+
+    fn prefix_info(valid_lifetime: u32, preferred_lifetime: u32) -> NdiscPrefixInfo {
+        NdiscPrefixInfo {
+            prefix_len: 64,
+            on_link:    true,
+            autonomous: true,
+            prefix:     PREFIX,
+            valid_lifetime, preferred_lifetime
+        }
+    }
+
+    #[test]
+    fn test_eui64() {
+        assert_eq!(SlaacMode::Eui64.address(&PREFIX, MAC, 0),
+                   Some(Ipv6Address::new(0x2001, 0xdb8, 0, 1, 0x5054, 0x00ff, 0xfe12, 0x3456)));
+        assert_eq!(SlaacMode::Disabled.address(&PREFIX, MAC, 0), None);
+    }
+
+    #[test]
+    fn test_stable_privacy() {
+        let mode = SlaacMode::StablePrivacy { secret: [0x5a; 16] };
+        let addr = mode.address(&PREFIX, MAC, 0).unwrap();
+        assert_eq!(&addr.as_bytes()[..8], &PREFIX.as_bytes()[..8]);
+        assert_eq!(mode.address(&PREFIX, MAC, 0), Some(addr));
+        assert!(mode.address(&PREFIX, MAC, 1) != Some(addr));
+        assert!(mode.address(&Ipv6Address::new(0x2001, 0xdb8, 0, 2, 0, 0, 0, 0), MAC, 0)
+                    .map(|other| other.as_bytes()[8..] != addr.as_bytes()[8..]).unwrap());
+        let other_mode = SlaacMode::StablePrivacy { secret: [0xa5; 16] };
+        assert!(other_mode.address(&PREFIX, MAC, 0) != Some(addr));
+    }
+
+    #[test]
+    fn test_lifetimes() {
+        let cidr = Ipv6Cidr::new(SlaacMode::Eui64.address(&PREFIX, MAC, 0).unwrap(), 64);
+        let addr = SlaacAddress::new(cidr, &prefix_info(600, 300), 1_000);
+        assert!(addr.matches(&prefix_info(0, 0)));
+        assert_eq!(addr.lifetime.addr, cidr.address().into());
+        assert!(!addr.lifetime.is_deprecated(300_999));
+        assert!(addr.lifetime.is_deprecated(301_000));
+        assert!(!addr.lifetime.is_expired(600_999));
+        assert!(addr.lifetime.is_expired(601_000));
+        assert_eq!(addr.lifetime.poll_at(1_000), Some(301_000));
+        assert_eq!(addr.lifetime.poll_at(301_000), Some(601_000));
+
+        let addr = SlaacAddress::new(cidr, &prefix_info(INFINITE_LIFETIME, INFINITE_LIFETIME), 0);
+        assert!(!addr.lifetime.is_deprecated(u64::max_value()));
+        assert!(!addr.lifetime.is_expired(u64::max_value()));
+        assert_eq!(addr.lifetime.poll_at(0), None);
+    }
+
+    #[test]
+    fn test_update_two_hour_rule() {
+        let cidr = Ipv6Cidr::new(SlaacMode::Eui64.address(&PREFIX, MAC, 0).unwrap(), 64);
+        let mut addr = SlaacAddress::new(cidr, &prefix_info(86400, 14400), 0);
+
+        // A longer lifetime is always accepted.
+        addr.update(&prefix_info(172800, 86400), 0);
+        assert_eq!(addr.lifetime.valid_until, Some(172_800_000));
+        assert_eq!(addr.lifetime.preferred_until, Some(86_400_000));
+
+        // A short lifetime only shortens the remaining lifetime to two hours.
+        addr.update(&prefix_info(0, 0), 0);
+        assert_eq!(addr.lifetime.valid_until, Some(TWO_HOURS));
+        assert!(addr.lifetime.is_deprecated(0));
+
+        // Once less than two hours remain, a shorter lifetime is ignored.
+        addr.update(&prefix_info(60, 0), 1_000);
+        assert_eq!(addr.lifetime.valid_until, Some(TWO_HOURS));
+
+        // A lifetime longer than two hours is accepted even if it is shorter.
+        addr.update(&prefix_info(10800, 0), 0);
+        assert_eq!(addr.lifetime.valid_until, Some(10_800_000));
+    }
+}
@@ -0,0 +1,214 @@
+// Heads up! Before working on this file you should read RFC 6724 § 5,
+// which describes how the source address of an IPv6 packet is selected.
+
+use core::cmp::{self, Ordering};
+
+use wire::{Ipv6Address, Ipv6Cidr};
+
+/// The default policy table, as prefixes and their labels. See RFC 6724 § 2.1.
+const POLICY_LABELS: [([u8; 16], u8, u8); 9] = [
+    ([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],                   128, 0),
+    ([0; 16],                                                             0, 1),
+    ([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 0, 0],            96, 4),
+    ([0x20, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],            16, 2),
+    ([0x20, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],            32, 5),
+    ([0xfc, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],                7, 13),
+    ([0; 16],                                                            96, 3),
+    ([0xfe, 0xc0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],            10, 11),
+    ([0x3f, 0xfe, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],            16, 12),
+];
+
+/// An address of the interface that may be used as the source of a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Candidate {
+    pub cidr:       Ipv6Cidr,
+    pub deprecated: bool,
+    pub temporary:  bool
+}
+
+/// Return the scope of an address. See RFC 6724 § 3.1.
+fn scope(addr: &Ipv6Address) -> u8 {
+    if addr.is_multicast() {
+        addr.as_bytes()[1] & 0x0f
+    } else if addr.is_link_local() || addr.is_loopback() {
+        0x2
+    } else if addr.as_bytes()[0] == 0xfe && addr.as_bytes()[1] & 0xc0 == 0xc0 {
+        // Deprecated site-local addresses.
+        0x5
+    } else {
+        0xe
+    }
+}
+
+/// Return the label of the longest prefix of the policy table that matches an address.
+fn label(addr: &Ipv6Address) -> u8 {
+    POLICY_LABELS.iter()
+        .map(|&(prefix, prefix_len, label)| (Ipv6Cidr::new(Ipv6Address(prefix), prefix_len), label))
+        .filter(|&(cidr, _)| cidr.contains_addr(addr))
+        .max_by_key(|&(cidr, _)| cidr.prefix_len())
+        .map(|(_, label)| label)
+        .unwrap_or(1)
+}
+
+/// Return the length of the longest prefix the candidate has in common with an address,
+/// up to the length of the prefix of the candidate. See RFC 6724 § 2.2.
+fn common_prefix_len(candidate: &Candidate, addr: &Ipv6Address) -> u8 {
+    let mut len = 0;
+    for (a, b) in candidate.cidr.address().as_bytes().iter().zip(addr.as_bytes()) {
+        let diff = a ^ b;
+        len += diff.leading_zeros() as u8;
+        if diff != 0 { break }
+    }
+    cmp::min(len, candidate.cidr.prefix_len())
+}
+
+/// Order two candidates, the preferred one first, as the source of a packet
+/// to the given destination. Temporary addresses are preferred if `prefer_temporary`
+/// is set, and avoided otherwise.
+///
+/// Rules 4 and 5 are not applied, since the interface has no home addresses,
+/// and no other interfaces.
+fn compare(a: &Candidate, b: &Candidate, dst_addr: &Ipv6Address,
+           prefer_temporary: bool) -> Ordering {
+    let (addr_a, addr_b) = (a.cidr.address(), b.cidr.address());
+
+    // Rule 1: Prefer same address.
+    (addr_b == *dst_addr).cmp(&(addr_a == *dst_addr))
+        // Rule 2: Prefer appropriate scope.
+        .then_with(|| {
+            let (scope_a, scope_b, scope_dst) = (scope(&addr_a), scope(&addr_b), scope(dst_addr));
+            if scope_a < scope_b {
+                if scope_a < scope_dst { Ordering::Greater } else { Ordering::Less }
+            } else if scope_b < scope_a {
+                if scope_b < scope_dst { Ordering::Less } else { Ordering::Greater }
+            } else {
+                Ordering::Equal
+            }
+        })
+        // Rule 3: Avoid deprecated addresses.
+        .then_with(|| a.deprecated.cmp(&b.deprecated))
+        // Rule 6: Prefer matching label.
+        .then_with(|| {
+            let label_dst = label(dst_addr);
+            (label(&addr_b) == label_dst).cmp(&(label(&addr_a) == label_dst))
+        })
+        // Rule 7: Prefer temporary addresses.
+        .then_with(|| (b.temporary == prefer_temporary).cmp(&(a.temporary == prefer_temporary)))
+        // Rule 8: Use longest matching prefix.
+        .then_with(|| common_prefix_len(b, dst_addr).cmp(&common_prefix_len(a, dst_addr)))
+}
+
+/// Select the source address of a packet to the given destination among the candidates.
+/// If several are equally preferable, the first of them is selected.
+pub(crate) fn select<I>(candidates: I, dst_addr: &Ipv6Address,
+                        prefer_temporary: bool) -> Option<Ipv6Address>
+        where I: IntoIterator<Item = Candidate> {
+    candidates.into_iter()
+        .min_by(|a, b| compare(a, b, dst_addr, prefer_temporary))
+        .map(|candidate| candidate.cidr.address())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LINK_LOCAL: Ipv6Address = Ipv6Address([0xfe, 0x80, 0, 0, 0, 0, 0, 0,
+                                                 0, 0, 0, 0, 0, 0, 0, 1]);
+    const ULA: Ipv6Address = Ipv6Address([0xfd, 0xbe, 0, 0, 0, 0, 0, 0,
+                                          0, 0, 0, 0, 0, 0, 0, 1]);
+    const GLOBAL: Ipv6Address = Ipv6Address([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0,
+                                             0, 0, 0, 0, 0, 0, 0, 1]);
+    const OTHER_GLOBAL: Ipv6Address = Ipv6Address([0x2a, 0x00, 0x14, 0x50, 0, 0, 0, 0,
+                                                   0, 0, 0, 0, 0, 0, 0, 1]);
+
+    fn candidate(addr: Ipv6Address, prefix_len: u8) -> Candidate {
+        Candidate { cidr: Ipv6Cidr::new(addr, prefix_len), deprecated: false, temporary: false }
+    }
+
+    fn candidates() -> [Candidate; 3] {
+        [candidate(LINK_LOCAL, 64), candidate(ULA, 64), candidate(GLOBAL, 64)]
+    }
+
+    #[test]
+    fn test_scope() {
+        assert_eq!(scope(&LINK_LOCAL), 0x2);
+        assert_eq!(scope(&Ipv6Address::LOOPBACK), 0x2);
+        assert_eq!(scope(&ULA), 0xe);
+        assert_eq!(scope(&Ipv6Address::LINK_LOCAL_ALL_NODES), 0x2);
+        assert_eq!(scope(&Ipv6Address::new(0xff05, 0, 0, 0, 0, 0, 0, 2)), 0x5);
+    }
+
+    #[test]
+    fn test_label() {
+        assert_eq!(label(&Ipv6Address::LOOPBACK), 0);
+        assert_eq!(label(&ULA), 13);
+        assert_eq!(label(&GLOBAL), 1);
+        assert_eq!(label(&Ipv6Address::new(0x2001, 0, 0, 0, 0, 0, 0, 1)), 5);
+        assert_eq!(label(&Ipv6Address::new(0, 0, 0, 0, 0, 0xffff, 0x0a00, 1)), 4);
+    }
+
+    #[test]
+    fn test_common_prefix_len() {
+        let dst_addr = Ipv6Address::new(0x2001, 0x0db8, 0x8000, 0, 0, 0, 0, 1);
+        assert_eq!(common_prefix_len(&candidate(GLOBAL, 64), &dst_addr), 32);
+        assert_eq!(common_prefix_len(&candidate(GLOBAL, 16), &dst_addr), 16);
+        assert_eq!(common_prefix_len(&candidate(GLOBAL, 128), &GLOBAL), 128);
+    }
+
+    #[test]
+    fn test_same_address() {
+        assert_eq!(select(candidates().iter().cloned(), &ULA, false), Some(ULA));
+    }
+
+    #[test]
+    fn test_appropriate_scope() {
+        let dst_addr = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 2);
+        assert_eq!(select(candidates().iter().cloned(), &dst_addr, false), Some(LINK_LOCAL));
+        // A larger scope is used when there is no address of the scope of the destination.
+        assert_eq!(select(candidates()[1..].iter().cloned(), &dst_addr, false), Some(GLOBAL));
+        assert_eq!(select(candidates()[..1].iter().cloned(), &OTHER_GLOBAL, false),
+                   Some(LINK_LOCAL));
+    }
+
+    #[test]
+    fn test_deprecated() {
+        let mut candidates = candidates();
+        candidates[2].deprecated = true;
+        assert_eq!(select(candidates.iter().cloned(), &OTHER_GLOBAL, false), Some(ULA));
+    }
+
+    #[test]
+    fn test_matching_label() {
+        // Unique local destinations are reached from unique local addresses,
+        // and global ones from global addresses.
+        let ula_dst_addr = Ipv6Address::new(0xfd00, 0, 0, 0, 0, 0, 0, 1);
+        assert_eq!(select(candidates().iter().cloned(), &ula_dst_addr, false), Some(ULA));
+        assert_eq!(select(candidates().iter().cloned(), &OTHER_GLOBAL, false), Some(GLOBAL));
+    }
+
+    #[test]
+    fn test_longest_matching_prefix() {
+        let first  = candidate(Ipv6Address::new(0x2a00, 0x1450, 0, 0, 0, 0, 0, 1), 64);
+        let second = candidate(Ipv6Address::new(0x2001, 0x0db8, 0, 0, 0, 0, 0, 1), 64);
+        let dst_addr = Ipv6Address::new(0x2001, 0x0db8, 0, 1, 0, 0, 0, 1);
+        assert_eq!(select(vec![first, second], &dst_addr, false), Some(second.cidr.address()));
+        assert_eq!(select(vec![second, first], &dst_addr, false), Some(second.cidr.address()));
+    }
+
+    #[test]
+    fn test_temporary() {
+        let public    = candidate(Ipv6Address::new(0x2001, 0x0db8, 0, 1, 0, 0, 0, 1), 64);
+        let temporary = Candidate {
+            temporary: true,
+            ..candidate(Ipv6Address::new(0x2001, 0x0db8, 0, 1, 0x5a, 0, 0, 1), 64)
+        };
+        assert_eq!(select(vec![public, temporary], &OTHER_GLOBAL, true),
+                   Some(temporary.cidr.address()));
+        assert_eq!(select(vec![temporary, public], &OTHER_GLOBAL, false),
+                   Some(public.cidr.address()));
+        // A deprecated temporary address is avoided anyway.
+        let deprecated = Candidate { deprecated: true, ..temporary };
+        assert_eq!(select(vec![public, deprecated], &OTHER_GLOBAL, true),
+                   Some(public.cidr.address()));
+    }
+}
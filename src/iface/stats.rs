@@ -0,0 +1,182 @@
+use {Error, Result};
+use wire::{EthernetFrame, EthernetProtocol};
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+use wire::IpVersion;
+
+/// Counters of the packets of a protocol received and sent by an interface, modeled after
+/// the `ifTable` of MIB-II (RFC 1213) and the `ipSystemStatsTable` of RFC 4293.
+///
+/// The counters start from zero when the interface is created.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Counters {
+    /// Number of packets received, including those in error or discarded.
+    pub in_packets:        u64,
+    /// Number of octets received, including headers.
+    pub in_octets:         u64,
+    /// Number of packets received that were discarded because they were truncated,
+    /// malformed or had an incorrect checksum.
+    pub in_errors:         u64,
+    /// Number of packets received that were valid, but discarded nonetheless,
+    /// e.g. for lack of buffer space or by a packet filter.
+    pub in_discards:       u64,
+    /// Number of packets received that were discarded because their protocol
+    /// is unknown or unsupported.
+    pub in_unknown_protos: u64,
+    /// Number of packets sent, including forwarded packets and fragments.
+    pub out_packets:       u64,
+    /// Number of octets sent, including headers.
+    pub out_octets:        u64,
+    /// Number of packets that could not be sent because of an error of the device.
+    pub out_errors:        u64,
+    /// Number of packets that were not sent, e.g. for lack of buffer space
+    /// or by a packet filter.
+    pub out_discards:      u64,
+}
+
+impl Counters {
+    /// Count a received packet of the given length.
+    pub(crate) fn received(&mut self, octets: usize) {
+        self.in_packets += 1;
+        self.in_octets += octets as u64;
+    }
+
+    /// Count the received packet whose processing failed with the given result,
+    /// if it did, as an error or a discard.
+    pub(crate) fn receive_result<T>(&mut self, result: Result<T>) -> Result<T> {
+        match result {
+            Err(Error::Exhausted) | Err(Error::Fragmented) | Err(Error::Dropped) =>
+                self.in_discards += 1,
+            Err(_) =>
+                self.in_errors += 1,
+            Ok(_) => ()
+        }
+        result
+    }
+
+    /// Count a sent packet of the given length.
+    pub(crate) fn sent(&mut self, octets: usize) {
+        self.out_packets += 1;
+        self.out_octets += octets as u64;
+    }
+
+    /// Count a packet that could not be sent because of the given error.
+    pub(crate) fn send_failed(&mut self, error: &Error) {
+        match *error {
+            Error::Exhausted =>
+                self.out_discards += 1,
+            _ =>
+                self.out_errors += 1
+        }
+    }
+}
+
+/// Statistics about the traffic of an interface, per protocol family.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// The frames received and sent through the device, of any EtherType,
+    /// and those looped back to the interface, if the medium of the device is Ethernet.
+    pub ethernet: Counters,
+    /// The IPv4 packets received and sent, including those that were forwarded.
+    #[cfg(feature = "proto-ipv4")]
+    pub ipv4:     Counters,
+    /// The IPv6 packets received and sent, including those that were forwarded.
+    #[cfg(feature = "proto-ipv6")]
+    pub ipv6:     Counters,
+}
+
+impl Stats {
+    /// Return the counters of the given IP version.
+    ///
+    /// # Panics
+    /// This function panics if the version is not supported.
+    #[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+    pub(crate) fn ip_mut(&mut self, version: IpVersion) -> &mut Counters {
+        match version {
+            #[cfg(feature = "proto-ipv4")]
+            IpVersion::Ipv4 => &mut self.ipv4,
+            #[cfg(feature = "proto-ipv6")]
+            IpVersion::Ipv6 => &mut self.ipv6,
+            _ => unreachable!()
+        }
+    }
+
+    /// Count a frame of the given length sent through the device with the given result.
+    /// The frame is also counted as a packet of its EtherType, if it is known.
+    pub(crate) fn frame_sent(&mut self, ethertype: Option<EthernetProtocol>, frame_len: usize,
+                             result: Result<()>) -> Result<()> {
+        match result {
+            Ok(()) => self.ethernet.sent(frame_len),
+            Err(ref err) => self.ethernet.send_failed(err)
+        }
+        match ethertype {
+            Some(ethertype) => {
+                let packet_len = frame_len - EthernetFrame::<&[u8]>::header_len();
+                self.packet_sent(ethertype, packet_len, result)
+            }
+            None => result
+        }
+    }
+
+    /// Count a packet of the given EtherType and length sent with the given result,
+    /// if it is a packet of a known protocol.
+    pub(crate) fn packet_sent(&mut self, ethertype: EthernetProtocol, packet_len: usize,
+                              result: Result<()>) -> Result<()> {
+        let counters: Option<&mut Counters> = match ethertype {
+            #[cfg(feature = "proto-ipv4")]
+            EthernetProtocol::Ipv4 => Some(&mut self.ipv4),
+            #[cfg(feature = "proto-ipv6")]
+            EthernetProtocol::Ipv6 => Some(&mut self.ipv6),
+            _ => None
+        };
+        if let Some(counters) = counters {
+            match result {
+                Ok(()) => counters.sent(packet_len),
+                Err(ref err) => counters.send_failed(err)
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_receive_result() {
+        let mut counters = Counters::default();
+        counters.received(60);
+        counters.received(40);
+        assert_eq!(counters.receive_result(Ok(())), Ok(()));
+        assert_eq!(counters.receive_result::<()>(Err(Error::Checksum)), Err(Error::Checksum));
+        assert_eq!(counters.receive_result::<()>(Err(Error::Exhausted)), Err(Error::Exhausted));
+        assert_eq!(counters, Counters {
+            in_packets:  2,
+            in_octets:   100,
+            in_errors:   1,
+            in_discards: 1,
+            ..Counters::default()
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_frame_sent() {
+        let mut stats = Stats::default();
+        assert_eq!(stats.frame_sent(Some(EthernetProtocol::Ipv4), 74, Ok(())), Ok(()));
+        assert_eq!(stats.frame_sent(Some(EthernetProtocol::Arp), 42, Ok(())), Ok(()));
+        assert_eq!(stats.frame_sent(None, 42, Err(Error::Exhausted)),
+                   Err(Error::Exhausted));
+        assert_eq!(stats.ethernet, Counters {
+            out_packets:  2,
+            out_octets:   116,
+            out_discards: 1,
+            ..Counters::default()
+        });
+        assert_eq!(stats.ipv4, Counters {
+            out_packets: 1,
+            out_octets:  60,
+            ..Counters::default()
+        });
+    }
+}
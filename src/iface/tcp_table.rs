@@ -0,0 +1,304 @@
+use managed::ManagedSlice;
+
+use wire::IpEndpoint;
+use socket::SocketHandle;
+use super::fnv::Fnv1a;
+
+/// An entry of a TCP connection table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpConnection {
+    /// The local endpoint. A listener that accepts connections to any address
+    /// of the interface has an unspecified address.
+    pub local_endpoint:  IpEndpoint,
+    /// The remote endpoint. A listener has an unspecified endpoint.
+    pub remote_endpoint: IpEndpoint,
+    /// The handle of the socket that handles the segments of the connection.
+    pub handle:          SocketHandle,
+}
+
+impl TcpConnection {
+    /// Return whether the entry is a listener.
+    pub fn is_listener(&self) -> bool {
+        self.remote_endpoint == IpEndpoint::default()
+    }
+}
+
+/// A TCP connection table.
+///
+/// The table maps the local and remote endpoints of incoming TCP segments to the sockets
+/// that handle them, through a hash table, so that a segment is not offered to every
+/// socket in the set in turn. Since sockets are opened and closed by the application
+/// without the interface knowing, the entries are only hints: the interface checks
+/// that the socket it finds accepts the segment, and otherwise offers the segment to
+/// every socket, and adds the socket that handles it to the table.
+///
+/// If the storage is a `Vec`, the table grows as connections are added to it, and can be
+/// created empty. If it is fixed-size and full, a new entry replaces the one at its
+/// position, which should be avoided by making room for every connection.
+///
+/// # Examples
+///
+/// On systems with heap, this table can be created with:
+///
+/// ```rust
+/// use smoltcp::iface::TcpTable;
+/// let mut tcp_table = TcpTable::new(vec![]);
+/// ```
+///
+/// On systems without heap, use:
+///
+/// ```rust
+/// use smoltcp::iface::TcpTable;
+/// let mut tcp_table_storage = [None; 32];
+/// let mut tcp_table = TcpTable::new(&mut tcp_table_storage[..]);
+/// ```
+#[derive(Debug)]
+pub struct TcpTable<'a> {
+    storage: ManagedSlice<'a, Option<TcpConnection>>,
+    len:     usize,
+}
+
+impl<'a> TcpTable<'a> {
+    /// Create a TCP connection table. The backing storage is cleared upon creation.
+    pub fn new<T>(storage: T) -> TcpTable<'a>
+            where T: Into<ManagedSlice<'a, Option<TcpConnection>>> {
+        let mut storage = storage.into();
+        for slot in storage.iter_mut() {
+            *slot = None;
+        }
+
+        TcpTable { storage, len: 0 }
+    }
+
+    /// Return the number of entries in the table.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return an iterator over the entries in the table.
+    pub fn iter(&self) -> Iter {
+        Iter { slots: self.storage.iter() }
+    }
+
+    /// Return the handle of the socket of the connection, or the first listener,
+    /// with the given endpoints.
+    pub fn lookup(&self, local_endpoint: &IpEndpoint,
+                  remote_endpoint: &IpEndpoint) -> Option<SocketHandle> {
+        self.find(local_endpoint, remote_endpoint, |_| true).map(|conn| conn.handle)
+    }
+
+    /// Return the first entry with the given endpoints that satisfies the predicate.
+    pub(crate) fn find<F>(&self, local_endpoint: &IpEndpoint, remote_endpoint: &IpEndpoint,
+                          mut f: F) -> Option<TcpConnection>
+            where F: FnMut(&TcpConnection) -> bool {
+        if self.storage.len() == 0 { return None }
+
+        let mut index = self.home(local_endpoint, remote_endpoint);
+        for _ in 0..self.storage.len() {
+            match self.storage[index] {
+                Some(ref conn) if conn.local_endpoint == *local_endpoint &&
+                                  conn.remote_endpoint == *remote_endpoint && f(conn) =>
+                    return Some(*conn),
+                Some(_) => (),
+                None => return None
+            }
+            index = (index + 1) % self.storage.len();
+        }
+        None
+    }
+
+    /// Add an entry to the table, unless it is there already.
+    pub(crate) fn insert(&mut self, conn: TcpConnection) {
+        if self.find(&conn.local_endpoint, &conn.remote_endpoint,
+                     |probe| probe.handle == conn.handle).is_some() {
+            return
+        }
+
+        // Keep the load factor of the table below 3/4, so that lookups stay short.
+        if (self.len + 1) * 4 > self.storage.len() * 3 {
+            match self.storage {
+                ManagedSlice::Borrowed(_) => (),
+                #[cfg(any(feature = "std", feature = "alloc"))]
+                ManagedSlice::Owned(ref mut storage) => {
+                    let capacity = ::core::cmp::max(storage.len() * 2, 16);
+                    let entries = storage.split_off(0);
+                    storage.resize(capacity, None);
+                    self.len = 0;
+                    for conn in entries.into_iter().filter_map(|slot| slot) {
+                        Self::put(storage, &mut self.len, conn)
+                    }
+                }
+            }
+        }
+        if self.storage.len() == 0 { return }
+
+        if self.len == self.storage.len() {
+            let index = self.home(&conn.local_endpoint, &conn.remote_endpoint);
+            net_debug!("TCP connection table full, replacing {:?}", self.storage[index]);
+            self.storage[index] = Some(conn);
+        } else {
+            Self::put(&mut self.storage, &mut self.len, conn)
+        }
+    }
+
+    /// Remove an entry from the table, if it is there.
+    pub(crate) fn remove(&mut self, conn: &TcpConnection) {
+        if self.storage.len() == 0 { return }
+
+        let capacity = self.storage.len();
+        let mut index = self.home(&conn.local_endpoint, &conn.remote_endpoint);
+        for _ in 0..capacity {
+            match self.storage[index] {
+                Some(ref probe) if probe == conn => break,
+                Some(_) => index = (index + 1) % capacity,
+                None => return
+            }
+        }
+        if self.storage[index] != Some(*conn) { return }
+        self.storage[index] = None;
+        self.len -= 1;
+
+        // Move the following entries of the cluster back, so that no entry is separated
+        // from its position by an empty slot.
+        let mut hole = index;
+        let mut next = (index + 1) % capacity;
+        while let Some(probe) = self.storage[next] {
+            let home = self.home(&probe.local_endpoint, &probe.remote_endpoint);
+            let stays = if hole <= next { hole < home && home <= next }
+                        else { hole < home || home <= next };
+            if !stays {
+                self.storage[hole] = Some(probe);
+                self.storage[next] = None;
+                hole = next;
+            }
+            next = (next + 1) % capacity;
+        }
+    }
+
+    fn put(storage: &mut [Option<TcpConnection>], len: &mut usize, conn: TcpConnection) {
+        let mut index = hash(&conn.local_endpoint, &conn.remote_endpoint) % storage.len();
+        while storage[index].is_some() {
+            index = (index + 1) % storage.len();
+        }
+        storage[index] = Some(conn);
+        *len += 1;
+    }
+
+    fn home(&self, local_endpoint: &IpEndpoint, remote_endpoint: &IpEndpoint) -> usize {
+        hash(local_endpoint, remote_endpoint) % self.storage.len()
+    }
+}
+
+/// Compute the 32-bit FNV-1a hash of a pair of endpoints.
+fn hash(local_endpoint: &IpEndpoint, remote_endpoint: &IpEndpoint) -> usize {
+    let mut hash = Fnv1a::new();
+    for endpoint in &[local_endpoint, remote_endpoint] {
+        hash.write_addr(&endpoint.addr);
+        hash.write(&[(endpoint.port >> 8) as u8, endpoint.port as u8]);
+    }
+    hash.finish() as usize
+}
+
+/// An iterator over the entries in a [TcpTable](struct.TcpTable.html).
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    slots: ::core::slice::Iter<'a, Option<TcpConnection>>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a TcpConnection;
+
+    fn next(&mut self) -> Option<&'a TcpConnection> {
+        while let Some(slot) = self.slots.next() {
+            if let Some(ref conn) = *slot {
+                return Some(conn)
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use wire::ip::test::{MOCK_IP_ADDR_1, MOCK_IP_ADDR_2};
+    use socket::{SocketSet, TcpSocket, TcpSocketBuffer};
+    use super::*;
+
+    const LOCAL_END: IpEndpoint = IpEndpoint { addr: MOCK_IP_ADDR_1, port: 80 };
+
+    fn handles(count: usize) -> Vec<SocketHandle> {
+        let mut sockets = SocketSet::new(vec![]);
+        (0..count).map(|_| {
+            let rx_buffer = TcpSocketBuffer::new(vec![]);
+            let tx_buffer = TcpSocketBuffer::new(vec![]);
+            sockets.add(TcpSocket::new(rx_buffer, tx_buffer))
+        }).collect()
+    }
+
+    fn conn(remote_port: u16, handle: SocketHandle) -> TcpConnection {
+        TcpConnection {
+            local_endpoint:  LOCAL_END,
+            remote_endpoint: IpEndpoint::new(MOCK_IP_ADDR_2, remote_port),
+            handle:          handle
+        }
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let handles = handles(100);
+        let mut table = TcpTable::new(vec![]);
+        assert_eq!(table.lookup(&LOCAL_END, &conn(49500, handles[0]).remote_endpoint), None);
+
+        for (index, &handle) in handles.iter().enumerate() {
+            table.insert(conn(49500 + index as u16, handle));
+            table.insert(conn(49500 + index as u16, handle));
+        }
+        assert_eq!(table.len(), 100);
+        for (index, &handle) in handles.iter().enumerate() {
+            let remote_endpoint = conn(49500 + index as u16, handle).remote_endpoint;
+            assert_eq!(table.lookup(&LOCAL_END, &remote_endpoint), Some(handle));
+        }
+
+        // Every other entry is removed, and the others can still be found.
+        for (index, &handle) in handles.iter().enumerate().filter(|&(index, _)| index % 2 == 0) {
+            table.remove(&conn(49500 + index as u16, handle));
+        }
+        assert_eq!(table.len(), 50);
+        for (index, &handle) in handles.iter().enumerate() {
+            let remote_endpoint = conn(49500 + index as u16, handle).remote_endpoint;
+            let expected = if index % 2 == 0 { None } else { Some(handle) };
+            assert_eq!(table.lookup(&LOCAL_END, &remote_endpoint), expected);
+        }
+    }
+
+    #[test]
+    fn test_listeners() {
+        let handles = handles(2);
+        let mut table = TcpTable::new(vec![]);
+        let listener = |handle| TcpConnection {
+            local_endpoint:  LOCAL_END,
+            remote_endpoint: IpEndpoint::default(),
+            handle:          handle
+        };
+        table.insert(listener(handles[0]));
+        table.insert(listener(handles[1]));
+        assert!(table.iter().all(TcpConnection::is_listener));
+        assert_eq!(table.find(&LOCAL_END, &IpEndpoint::default(),
+                              |conn| conn.handle == handles[1]),
+                   Some(listener(handles[1])));
+    }
+
+    #[test]
+    fn test_full() {
+        let handles = handles(3);
+        let mut storage = [None; 2];
+        let mut table = TcpTable::new(&mut storage[..]);
+        for (index, &handle) in handles.iter().enumerate() {
+            table.insert(conn(49500 + index as u16, handle));
+        }
+        assert_eq!(table.len(), 2);
+        let remote_endpoint = conn(49502, handles[2]).remote_endpoint;
+        assert_eq!(table.lookup(&LOCAL_END, &remote_endpoint), Some(handles[2]));
+    }
+}
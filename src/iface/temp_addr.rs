@@ -0,0 +1,235 @@
+// Heads up! Before working on this file you should read RFC 8981, which describes
+// temporary addresses for stateless address autoconfiguration in IPv6.
+
+use core::cmp;
+
+use wire::{Ipv6Address, Ipv6Cidr};
+use super::{AddressLifetime, SlaacAddress};
+use super::fnv::Fnv1a64;
+
+/// The longest valid lifetime of a temporary address. See RFC 8981 § 3.8.
+pub(crate) const TEMP_VALID_LIFETIME: u64 = 2 * 24 * 60 * 60 * 1000;
+/// The longest preferred lifetime of a temporary address. See RFC 8981 § 3.8.
+pub(crate) const TEMP_PREFERRED_LIFETIME: u64 = 24 * 60 * 60 * 1000;
+/// How long before a temporary address is deprecated a new one is generated, so that
+/// Duplicate Address Detection completes in time: two seconds, and one second for each
+/// of three attempts with one probe.
+pub(crate) const REGEN_ADVANCE: u64 = 5 * 1000;
+/// The longest time by which the preferred lifetime of a temporary address is shortened,
+/// so that the hosts that started together do not all regenerate their addresses together.
+const MAX_DESYNC_FACTOR: u64 = TEMP_PREFERRED_LIFETIME / 10 * 4;
+
+/// A policy on the temporary IPv6 addresses of an interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TempAddrPolicy {
+    /// Do not form any temporary addresses.
+    Disabled,
+    /// Form a temporary address along with every autoconfigured address, and renew
+    /// it regularly, but select the autoconfigured addresses as the source of outgoing
+    /// packets; temporary addresses are only used by the sockets bound to them.
+    PreferPublic {
+        secret: [u8; 16]
+    },
+    /// Form a temporary address along with every autoconfigured address, renew it
+    /// regularly, and select it as the source of outgoing packets.
+    PreferTemporary {
+        secret: [u8; 16]
+    }
+}
+
+impl Default for TempAddrPolicy {
+    fn default() -> TempAddrPolicy {
+        TempAddrPolicy::Disabled
+    }
+}
+
+impl TempAddrPolicy {
+    /// Query whether temporary addresses are formed at all.
+    pub fn is_enabled(&self) -> bool {
+        *self != TempAddrPolicy::Disabled
+    }
+
+    /// Query whether temporary addresses are preferred as source addresses.
+    pub fn prefers_temporary(&self) -> bool {
+        match *self {
+            TempAddrPolicy::PreferTemporary { .. } => true,
+            _ => false
+        }
+    }
+
+    /// Return a temporary address formed from the upper 64 bits of the given prefix,
+    /// and the time by which its preferred lifetime is shortened.
+    ///
+    /// The interface identifier is derived from the prefix, a secret, the number of
+    /// the address and the time it is generated at, and is thus opaque to an observer,
+    /// as described in RFC 8981 § 3.3.2. The secret should be chosen randomly every time
+    /// the interface is created, so that the sequence of addresses differs after a restart.
+    pub(crate) fn address(&self, prefix: &Ipv6Address, count: u32,
+                          timestamp: u64) -> Option<(Ipv6Address, u64)> {
+        let secret = match *self {
+            TempAddrPolicy::Disabled => return None,
+            TempAddrPolicy::PreferPublic { secret } |
+            TempAddrPolicy::PreferTemporary { secret } => secret
+        };
+
+        let mut hash = Fnv1a64::new();
+        hash.write(&prefix.as_bytes()[..8]);
+        hash.write(&secret);
+        for shift in (0..4).rev() {
+            hash.write(&[(count >> (shift * 8)) as u8]);
+        }
+        for shift in (0..8).rev() {
+            hash.write(&[(timestamp >> (shift * 8)) as u8]);
+        }
+
+        let mut iid = hash.finish();
+        // The all-zeros interface identifier is the subnet-router anycast address,
+        // and the highest 128 are the reserved subnet anycast addresses; see RFC 5453.
+        if iid == 0 { iid = 1 }
+        if iid | 0x7f == 0xfdff_ffff_ffff_ffff { iid ^= 1 << 63 }
+
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&prefix.as_bytes()[..8]);
+        for (i, byte) in bytes[8..].iter_mut().enumerate() {
+            *byte = (iid >> (56 - i * 8)) as u8;
+        }
+
+        hash.write(&[0xff]);
+        let desync = hash.finish() % (MAX_DESYNC_FACTOR + 1);
+        Some((Ipv6Address(bytes), desync))
+    }
+}
+
+/// A temporary IPv6 address, formed along with an autoconfigured one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TempAddress {
+    pub cidr:            Ipv6Cidr,
+    /// The lifetimes of the address, which are always finite.
+    pub lifetime:        AddressLifetime,
+    /// The latest moments the address may stay preferred and valid until,
+    /// however long the lifetimes of its prefix are.
+    max_preferred_until: u64,
+    max_valid_until:     u64,
+    /// Whether another temporary address was generated to replace this one.
+    regenerated:         bool,
+}
+
+impl TempAddress {
+    /// Create a temporary address with the lifetimes of the given autoconfigured address
+    /// and the desync factor, unless it would be deprecated too soon to be any use.
+    pub(crate) fn new(cidr: Ipv6Cidr, public: &SlaacAddress, desync: u64,
+                      timestamp: u64) -> Option<TempAddress> {
+        let mut temp_addr = TempAddress {
+            cidr,
+            lifetime:            AddressLifetime::new(cidr.address().into(), None, None),
+            max_preferred_until: timestamp + TEMP_PREFERRED_LIFETIME - desync,
+            max_valid_until:     timestamp + TEMP_VALID_LIFETIME,
+            regenerated:         false,
+        };
+        temp_addr.update(public);
+        if temp_addr.preferred_until() <= timestamp + REGEN_ADVANCE {
+            return None
+        }
+        Some(temp_addr)
+    }
+
+    /// Query whether the address was formed along with the given autoconfigured address.
+    pub(crate) fn matches(&self, public: &SlaacAddress) -> bool {
+        self.cidr.prefix_len() == public.cidr.prefix_len() &&
+            Ipv6Cidr::new(public.cidr.address(), public.cidr.prefix_len())
+                .contains_addr(&self.cidr.address())
+    }
+
+    /// Update the lifetimes from those of the autoconfigured address, which were updated
+    /// by an advertisement of their prefix, as described in RFC 8981 § 3.4.
+    pub(crate) fn update(&mut self, public: &SlaacAddress) {
+        let bound = |deadline: Option<u64>, max: u64|
+            Some(cmp::min(deadline.unwrap_or(u64::max_value()), max));
+        self.lifetime.preferred_until = bound(public.lifetime.preferred_until,
+                                              self.max_preferred_until);
+        self.lifetime.valid_until = bound(public.lifetime.valid_until, self.max_valid_until);
+    }
+
+    /// Return the moment after which the address is deprecated.
+    fn preferred_until(&self) -> u64 {
+        self.lifetime.preferred_until.unwrap_or(u64::max_value())
+    }
+
+    /// Query whether another temporary address has to be generated to replace this one
+    /// at the given moment, and remember that it was if so.
+    pub(crate) fn regenerate(&mut self, timestamp: u64) -> bool {
+        if self.regenerated || self.lifetime.is_deprecated(timestamp) ||
+                timestamp + REGEN_ADVANCE < self.preferred_until() {
+            return false
+        }
+        self.regenerated = true;
+        true
+    }
+
+    /// Return the moment at which the state of the address changes next, if any.
+    pub(crate) fn poll_at(&self, timestamp: u64) -> Option<u64> {
+        let regenerate_at = self.preferred_until().saturating_sub(REGEN_ADVANCE);
+        if !self.regenerated && regenerate_at > timestamp {
+            Some(regenerate_at)
+        } else {
+            self.lifetime.poll_at(timestamp)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PREFIX: Ipv6Address = Ipv6Address([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 1,
+                                             0, 0, 0, 0, 0, 0, 0, 0]);
+    const POLICY: TempAddrPolicy = TempAddrPolicy::PreferTemporary { secret: [0x5a; 16] };
+
+    fn public(valid_until: Option<u64>, preferred_until: Option<u64>) -> SlaacAddress {
+        let cidr = Ipv6Cidr::new(Ipv6Address::new(0x2001, 0xdb8, 0, 1, 0, 0, 0, 1), 64);
+        SlaacAddress {
+            cidr,
+            lifetime: AddressLifetime::new(cidr.address().into(), preferred_until, valid_until)
+        }
+    }
+
+    #[test]
+    fn test_address() {
+        let (addr, desync) = POLICY.address(&PREFIX, 1, 0).unwrap();
+        assert_eq!(&addr.as_bytes()[..8], &PREFIX.as_bytes()[..8]);
+        assert!(desync <= MAX_DESYNC_FACTOR);
+        assert_eq!(POLICY.address(&PREFIX, 1, 0), Some((addr, desync)));
+        assert!(POLICY.address(&PREFIX, 2, 0).unwrap().0 != addr);
+        assert!(POLICY.address(&PREFIX, 1, 1).unwrap().0 != addr);
+        assert_eq!(TempAddrPolicy::Disabled.address(&PREFIX, 1, 0), None);
+    }
+
+    #[test]
+    fn test_lifetimes() {
+        let cidr = Ipv6Cidr::new(POLICY.address(&PREFIX, 1, 0).unwrap().0, 64);
+        let public = public(None, None);
+        let mut temp_addr = TempAddress::new(cidr, &public, 1_000, 0).unwrap();
+        assert!(temp_addr.matches(&public));
+        assert_eq!(temp_addr.lifetime.preferred_until, Some(TEMP_PREFERRED_LIFETIME - 1_000));
+        assert_eq!(temp_addr.lifetime.valid_until, Some(TEMP_VALID_LIFETIME));
+
+        // The lifetimes of the prefix bound those of the address.
+        temp_addr.update(&self::public(Some(600_000), Some(300_000)));
+        assert_eq!((temp_addr.lifetime.preferred_until, temp_addr.lifetime.valid_until),
+                   (Some(300_000), Some(600_000)));
+        assert_eq!(temp_addr.poll_at(0), Some(300_000 - REGEN_ADVANCE));
+
+        // Another address is generated shortly before the address is deprecated, once.
+        assert!(!temp_addr.regenerate(300_000 - REGEN_ADVANCE - 1));
+        assert!(temp_addr.regenerate(300_000 - REGEN_ADVANCE));
+        assert!(!temp_addr.regenerate(300_000 - REGEN_ADVANCE));
+        assert_eq!(temp_addr.poll_at(300_000 - REGEN_ADVANCE), Some(300_000));
+        assert!(temp_addr.lifetime.is_deprecated(300_000));
+        assert_eq!(temp_addr.poll_at(300_000), Some(600_000));
+        assert!(temp_addr.lifetime.is_expired(600_000));
+
+        // An address that would be deprecated right away is not created.
+        assert_eq!(TempAddress::new(cidr, &self::public(None, Some(REGEN_ADVANCE)), 0, 0),
+                   None);
+    }
+}
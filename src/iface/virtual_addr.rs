@@ -0,0 +1,283 @@
+// Heads up! Before working on this file you should read the parts of RFC 5798
+// that discuss what a Master router does with the addresses it owns (§ 6.4.3),
+// and what a router does when it becomes the Master (§ 6.4.1).
+
+use managed::ManagedSlice;
+
+use {Error, Result};
+use wire::IpAddress;
+#[cfg(feature = "proto-ipv6")]
+use wire::Ipv6Address;
+
+/// The ownership of a virtual address, as decided by the election logic of
+/// the application, e.g. an implementation of VRRP (RFC 5798).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VirtualAddressState {
+    /// Another host owns the address; it is ignored by the interface.
+    Backup,
+    /// The interface owns the address; it is used as an address of the interface.
+    Master,
+}
+
+/// A virtual address, shared by several hosts of which only one owns it at a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VirtualAddress {
+    pub addr:       IpAddress,
+    pub state:      VirtualAddressState,
+    announce_count: u8,
+    announce_at:    u64,
+}
+
+impl VirtualAddress {
+    /// Return the address if it is an IPv6 address the interface owns.
+    #[cfg(feature = "proto-ipv6")]
+    pub(crate) fn ipv6_master(&self) -> Option<Ipv6Address> {
+        match (self.state, self.addr) {
+            (VirtualAddressState::Master, IpAddress::Ipv6(addr)) => Some(addr),
+            _ => None
+        }
+    }
+}
+
+/// A table of the virtual addresses of an interface.
+///
+/// The interface answers ARP requests and Neighbor Solicitations for the virtual
+/// addresses it owns, receives the packets sent to them, and lets sockets send
+/// packets from them, exactly as for its own addresses. When it claims an address,
+/// the address is announced with gratuitous ARP or unsolicited Neighbor Advertisements,
+/// so that the neighbors send their packets to the new owner; an address that is
+/// released is ignored, and not announced in any way.
+///
+/// # Examples
+///
+/// On systems with heap, this table can be created with:
+///
+/// ```rust
+/// use smoltcp::iface::VirtualAddresses;
+/// let mut virtual_addrs = VirtualAddresses::new(vec![]);
+/// ```
+///
+/// On systems without heap, use:
+///
+/// ```rust
+/// use smoltcp::iface::VirtualAddresses;
+/// let mut virtual_addrs_storage = [None; 2];
+/// let mut virtual_addrs = VirtualAddresses::new(&mut virtual_addrs_storage[..]);
+/// ```
+#[derive(Debug)]
+pub struct VirtualAddresses<'a> {
+    storage: ManagedSlice<'a, Option<VirtualAddress>>,
+}
+
+impl<'a> VirtualAddresses<'a> {
+    /// Number of announcements of a claimed address.
+    const ANNOUNCE_COUNT: u8 = 3;
+
+    /// Delay between the announcements of a claimed address, in milliseconds.
+    const ANNOUNCE_INTERVAL: u64 = 1_000;
+
+    /// Create a virtual address table. The backing storage is cleared upon creation.
+    pub fn new<T>(storage: T) -> VirtualAddresses<'a>
+            where T: Into<ManagedSlice<'a, Option<VirtualAddress>>> {
+        let mut storage = storage.into();
+        for slot in storage.iter_mut() {
+            *slot = None;
+        }
+
+        VirtualAddresses { storage }
+    }
+
+    /// Return an iterator over the virtual addresses, whether they are owned or not.
+    pub fn iter(&self) -> Iter {
+        Iter { slots: self.storage.iter() }
+    }
+
+    /// Return the given virtual address, if there is one.
+    pub fn lookup(&self, addr: &IpAddress) -> Option<&VirtualAddress> {
+        self.iter().find(|virtual_addr| virtual_addr.addr == *addr)
+    }
+
+    /// Query whether the given address is a virtual address the interface owns.
+    pub fn is_master(&self, addr: &IpAddress) -> bool {
+        self.lookup(addr)
+            .map_or(false, |virtual_addr| virtual_addr.state == VirtualAddressState::Master)
+    }
+
+    /// Add a virtual address, owned by another host until it is claimed.
+    /// Adding an address again does nothing.
+    ///
+    /// Returns `Err(Error::Unaddressable)` if the address is not a unicast address,
+    /// and `Err(Error::Exhausted)` if there is no space for another address.
+    pub fn add(&mut self, addr: IpAddress) -> Result<()> {
+        if !addr.is_unicast() { return Err(Error::Unaddressable) }
+        if self.lookup(&addr).is_some() { return Ok(()) }
+
+        let virtual_addr = VirtualAddress {
+            addr,
+            state:          VirtualAddressState::Backup,
+            announce_count: 0,
+            announce_at:    0
+        };
+        match self.storage.iter().position(|slot| slot.is_none()) {
+            Some(index) => {
+                self.storage[index] = Some(virtual_addr);
+                Ok(())
+            }
+            None => match self.storage {
+                ManagedSlice::Borrowed(_) => Err(Error::Exhausted),
+                #[cfg(any(feature = "std", feature = "alloc"))]
+                ManagedSlice::Owned(ref mut virtual_addrs) => {
+                    virtual_addrs.push(Some(virtual_addr));
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Remove a virtual address, whether it is owned or not, and return whether
+    /// there was one.
+    pub fn remove(&mut self, addr: &IpAddress) -> bool {
+        let mut removed = false;
+        for slot in self.storage.iter_mut() {
+            if slot.map_or(false, |virtual_addr| virtual_addr.addr == *addr) {
+                *slot = None;
+                removed = true;
+            }
+        }
+        removed
+    }
+
+    /// Claim a virtual address, i.e. become its owner, and announce it.
+    /// Claiming an address that is already owned does nothing.
+    ///
+    /// Returns `Err(Error::Unaddressable)` if there is no such virtual address.
+    pub fn claim(&mut self, addr: &IpAddress) -> Result<()> {
+        let virtual_addr = self.lookup_mut(addr).ok_or(Error::Unaddressable)?;
+        if virtual_addr.state == VirtualAddressState::Master { return Ok(()) }
+
+        net_debug!("claimed virtual address {}", addr);
+        virtual_addr.state = VirtualAddressState::Master;
+        virtual_addr.announce_count = Self::ANNOUNCE_COUNT;
+        virtual_addr.announce_at = 0;
+        Ok(())
+    }
+
+    /// Release a virtual address, i.e. leave it to another host.
+    ///
+    /// Returns `Err(Error::Unaddressable)` if there is no such virtual address.
+    pub fn release(&mut self, addr: &IpAddress) -> Result<()> {
+        let virtual_addr = self.lookup_mut(addr).ok_or(Error::Unaddressable)?;
+        if virtual_addr.state == VirtualAddressState::Backup { return Ok(()) }
+
+        net_debug!("released virtual address {}", addr);
+        virtual_addr.state = VirtualAddressState::Backup;
+        virtual_addr.announce_count = 0;
+        Ok(())
+    }
+
+    fn lookup_mut(&mut self, addr: &IpAddress) -> Option<&mut VirtualAddress> {
+        self.storage.iter_mut()
+            .filter_map(|slot| slot.as_mut())
+            .find(|virtual_addr| virtual_addr.addr == *addr)
+    }
+
+    /// Announce the owned addresses again, e.g. after the Ethernet address changed.
+    pub(crate) fn reannounce(&mut self) {
+        for virtual_addr in self.storage.iter_mut().filter_map(|slot| slot.as_mut()) {
+            if virtual_addr.state == VirtualAddressState::Master {
+                virtual_addr.announce_count = Self::ANNOUNCE_COUNT;
+                virtual_addr.announce_at = 0;
+            }
+        }
+    }
+
+    /// Return a claimed address that has to be announced at the given moment,
+    /// if there is any, and schedule its next announcement.
+    pub(crate) fn announce(&mut self, timestamp: u64) -> Option<IpAddress> {
+        for virtual_addr in self.storage.iter_mut().filter_map(|slot| slot.as_mut()) {
+            if virtual_addr.announce_count > 0 && virtual_addr.announce_at <= timestamp {
+                virtual_addr.announce_count -= 1;
+                virtual_addr.announce_at = timestamp + Self::ANNOUNCE_INTERVAL;
+                return Some(virtual_addr.addr)
+            }
+        }
+        None
+    }
+
+    /// Return the moment at which a claimed address has to be announced next, if any.
+    pub(crate) fn poll_at(&self) -> Option<u64> {
+        self.iter()
+            .filter(|virtual_addr| virtual_addr.announce_count > 0)
+            .map(|virtual_addr| virtual_addr.announce_at)
+            .min()
+    }
+}
+
+/// An iterator over the addresses in a [VirtualAddresses](struct.VirtualAddresses.html) table.
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    slots: ::core::slice::Iter<'a, Option<VirtualAddress>>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a VirtualAddress;
+
+    fn next(&mut self) -> Option<&'a VirtualAddress> {
+        while let Some(slot) = self.slots.next() {
+            if let Some(ref virtual_addr) = *slot {
+                return Some(virtual_addr)
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wire::ip::test::{MOCK_IP_ADDR_1, MOCK_IP_ADDR_2};
+
+    #[test]
+    fn test_claim_release() {
+        let mut virtual_addrs = VirtualAddresses::new(vec![]);
+        assert_eq!(virtual_addrs.claim(&MOCK_IP_ADDR_1), Err(Error::Unaddressable));
+        assert_eq!(virtual_addrs.add(MOCK_IP_ADDR_1), Ok(()));
+        assert_eq!(virtual_addrs.add(MOCK_IP_ADDR_2), Ok(()));
+        assert!(!virtual_addrs.is_master(&MOCK_IP_ADDR_1));
+        assert_eq!(virtual_addrs.announce(0), None);
+
+        // A claimed address is announced three times, one second apart.
+        assert_eq!(virtual_addrs.claim(&MOCK_IP_ADDR_1), Ok(()));
+        assert!(virtual_addrs.is_master(&MOCK_IP_ADDR_1));
+        assert_eq!(virtual_addrs.poll_at(), Some(0));
+        assert_eq!(virtual_addrs.announce(100), Some(MOCK_IP_ADDR_1));
+        assert_eq!(virtual_addrs.announce(100), None);
+        assert_eq!(virtual_addrs.poll_at(), Some(1_100));
+        assert_eq!(virtual_addrs.announce(1_100), Some(MOCK_IP_ADDR_1));
+        assert_eq!(virtual_addrs.announce(2_100), Some(MOCK_IP_ADDR_1));
+        assert_eq!(virtual_addrs.poll_at(), None);
+
+        // Claiming it again announces nothing.
+        assert_eq!(virtual_addrs.claim(&MOCK_IP_ADDR_1), Ok(()));
+        assert_eq!(virtual_addrs.announce(5_000), None);
+
+        // Pending announcements are cancelled when an address is released.
+        assert_eq!(virtual_addrs.claim(&MOCK_IP_ADDR_2), Ok(()));
+        assert_eq!(virtual_addrs.release(&MOCK_IP_ADDR_2), Ok(()));
+        assert!(!virtual_addrs.is_master(&MOCK_IP_ADDR_2));
+        assert_eq!(virtual_addrs.poll_at(), None);
+
+        assert!(virtual_addrs.remove(&MOCK_IP_ADDR_1));
+        assert!(!virtual_addrs.is_master(&MOCK_IP_ADDR_1));
+        assert_eq!(virtual_addrs.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_exhausted() {
+        let mut storage = [None; 1];
+        let mut virtual_addrs = VirtualAddresses::new(&mut storage[..]);
+        assert_eq!(virtual_addrs.add(MOCK_IP_ADDR_1), Ok(()));
+        assert_eq!(virtual_addrs.add(MOCK_IP_ADDR_1), Ok(()));
+        assert_eq!(virtual_addrs.add(MOCK_IP_ADDR_2), Err(Error::Exhausted));
+    }
+}
@@ -81,13 +81,19 @@ extern crate managed;
 #[cfg(any(test, feature = "std"))]
 #[macro_use]
 extern crate std;
-#[cfg(any(feature = "phy-raw_socket", feature = "phy-tap_interface"))]
+#[cfg(any(feature = "phy-raw_socket", feature = "phy-tap_interface", feature = "phy-xdp"))]
 extern crate libc;
 #[cfg(feature = "alloc")]
 extern crate alloc;
 #[cfg(feature = "log")]
 #[macro_use(log, trace, debug)]
 extern crate log;
+#[cfg(feature = "arbitrary")]
+extern crate arbitrary;
+#[cfg(all(feature = "async", feature = "std"))]
+extern crate futures_io;
+#[cfg(all(feature = "embedded-nal", any(feature = "socket-tcp", feature = "socket-udp")))]
+extern crate embedded_nal;
 
 use core::fmt;
 
@@ -101,6 +107,26 @@ pub mod wire;
 pub mod iface;
 pub mod socket;
 pub mod time;
+#[cfg(feature = "std")]
+pub mod sim;
+#[cfg(any(feature = "proto-dhcpv4", feature = "proto-dhcpv6"))]
+pub mod dhcp;
+#[cfg(any(feature = "proto-mdns", feature = "proto-llmnr"))]
+pub mod mdns;
+#[cfg(feature = "proto-snmp")]
+pub mod snmp;
+#[cfg(feature = "proto-sntp")]
+pub mod sntp;
+#[cfg(feature = "proto-tftp")]
+pub mod tftp;
+#[cfg(feature = "proto-syslog")]
+pub mod syslog;
+#[cfg(feature = "proto-httpd")]
+pub mod httpd;
+#[cfg(all(feature = "embedded-nal", any(feature = "socket-tcp", feature = "socket-udp")))]
+pub mod nal;
+#[cfg(all(feature = "capi", any(feature = "std", feature = "alloc")))]
+pub mod capi;
 
 /// The error type for the networking stack.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -123,7 +149,7 @@ pub enum Error {
     /// E.g. an Ethernet packet with an unknown EtherType.
     Unrecognized,
     /// An incoming IP packet has been split into several IP fragments and was dropped,
-    /// since IP reassembly is not supported.
+    /// since there are no buffers to reassemble it in.
     Fragmented,
     /// An incoming packet was recognized but was self-contradictory.
     /// E.g. a TCP packet with both SYN and FIN flags set.
@@ -73,5 +73,14 @@ macro_rules! enum_with_unknown {
                 }
             }
         }
+
+        #[cfg(feature = "arbitrary")]
+        impl<'a> ::arbitrary::Arbitrary<'a> for $name {
+            fn arbitrary(u: &mut ::arbitrary::Unstructured<'a>) -> ::arbitrary::Result<Self> {
+                // Going through the raw value makes known variants and `Unknown`
+                // values with the same probability as on the wire.
+                Ok($name::from(<$ty as ::arbitrary::Arbitrary>::arbitrary(u)?))
+            }
+        }
     }
 }
@@ -0,0 +1,198 @@
+use Result;
+use iface::check_hostname;
+use wire::{IpAddress, IpCidr, DnsPacket, DnsQuestion, DnsRecord, DnsRecordData, DnsName, DnsType};
+use wire::dns::MAX_NAME_LEN;
+
+/// The maximum number of addresses returned for a query, or sent in a response.
+pub const MAX_ADDRESS_COUNT: usize = 4;
+
+/// The query type asking for records of every type.
+const TYPE_ANY: u16 = 255;
+
+/// The name a responder answers address queries for.
+#[derive(Clone, Copy)]
+pub struct Hostname {
+    bytes: [u8; MAX_NAME_LEN],
+    len: usize,
+}
+
+impl Hostname {
+    /// Encode `label`, which must be a valid hostname as checked by `check_hostname`,
+    /// followed by the encoded name `domain`.
+    pub fn new(label: &str, domain: &[u8]) -> Result<Hostname> {
+        check_hostname(label)?;
+        let label = label.as_bytes();
+
+        let mut hostname = Hostname { bytes: [0; MAX_NAME_LEN], len: 1 + label.len() };
+        hostname.bytes[0] = label.len() as u8;
+        hostname.bytes[1..hostname.len].copy_from_slice(label);
+        hostname.bytes[hostname.len..hostname.len + domain.len()].copy_from_slice(domain);
+        hostname.len += domain.len();
+        Ok(hostname)
+    }
+
+    /// Return the encoded name.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// Return the first label of the name.
+    pub fn label(&self) -> &[u8] {
+        &self.bytes[1..1 + self.bytes[0] as usize]
+    }
+
+    /// Return the name.
+    pub fn name(&self) -> DnsName {
+        DnsName::new(self.as_bytes(), self.as_bytes())
+    }
+
+    /// Find the questions about this hostname in a query, and return which address
+    /// records they ask for, or `None` if they do not ask for any.
+    pub fn address_query(&self, packet: &DnsPacket<&[u8]>) -> Result<Option<AddressQuery>> {
+        let message = packet.message();
+        let hostname = self.name();
+
+        let mut query: Option<AddressQuery> = None;
+        let mut questions = packet.payload();
+        for _ in 0..packet.question_count() {
+            let (next_questions, question) = DnsQuestion::parse(questions)?;
+            questions = next_questions;
+            if !DnsName::new(message, question.name).eq_ignore_case(&hostname) { continue }
+
+            let type_ = question.type_;
+            match type_ {
+                DnsType::A | DnsType::Aaaa | DnsType::Unknown(TYPE_ANY) => (),
+                _ => continue
+            }
+            let query = query.get_or_insert(AddressQuery {
+                question_type: type_,
+                #[cfg(feature = "proto-ipv4")]
+                want_a: false,
+                #[cfg(feature = "proto-ipv6")]
+                want_aaaa: false,
+            });
+            #[cfg(feature = "proto-ipv4")]
+            { query.want_a |= type_ == DnsType::A || type_ == DnsType::Unknown(TYPE_ANY); }
+            #[cfg(feature = "proto-ipv6")]
+            { query.want_aaaa |= type_ == DnsType::Aaaa || type_ == DnsType::Unknown(TYPE_ANY); }
+        }
+        Ok(query)
+    }
+}
+
+/// The address records asked for about a hostname by the questions of a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressQuery {
+    /// The type of the first question about the hostname.
+    pub question_type: DnsType,
+    #[cfg(feature = "proto-ipv4")]
+    want_a: bool,
+    #[cfg(feature = "proto-ipv6")]
+    want_aaaa: bool,
+}
+
+impl AddressQuery {
+    /// Emit a record with the given class and TTL for each of the addresses of the interface
+    /// that were asked for, up to `MAX_ADDRESS_COUNT`, and return the length of the records
+    /// and their count.
+    pub fn emit_answers(&self, hostname: &Hostname, ip_addrs: &[IpCidr], class: u16, ttl: u32,
+                        buffer: &mut [u8]) -> (usize, usize) {
+        let mut length = 0;
+        let mut count = 0;
+        for cidr in ip_addrs {
+            if count == MAX_ADDRESS_COUNT { break }
+            let data = match cidr.address() {
+                #[cfg(feature = "proto-ipv4")]
+                IpAddress::Ipv4(addr) if self.want_a && !addr.is_unspecified() =>
+                    DnsRecordData::A(addr),
+                #[cfg(feature = "proto-ipv6")]
+                IpAddress::Ipv6(addr) if self.want_aaaa && !addr.is_unspecified() =>
+                    DnsRecordData::Aaaa(addr),
+                _ => continue
+            };
+            let record = DnsRecord { name: hostname.as_bytes(), class: class, ttl: ttl, data: data };
+            record.emit(&mut buffer[length..]);
+            length += record.buffer_len();
+            count += 1;
+        }
+        (length, count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    #[cfg(all(feature = "proto-ipv4", feature = "proto-ipv6"))]
+    use wire::{Ipv4Address, Ipv6Address};
+    use wire::dns::encode_name;
+    #[cfg(all(feature = "proto-ipv4", feature = "proto-ipv6"))]
+    use wire::dns::CLASS_IN;
+    use super::*;
+
+    fn query(questions: &[(&str, DnsType)]) -> Vec<u8> {
+        let mut bytes = vec![0; 12];
+        for &(name, type_) in questions {
+            let mut name_bytes = [0; MAX_NAME_LEN];
+            let name_len = encode_name(name, &mut name_bytes).unwrap();
+            let question = DnsQuestion { name: &name_bytes[..name_len], type_: type_ };
+            let start = bytes.len();
+            bytes.resize(start + question.buffer_len(), 0);
+            question.emit(&mut bytes[start..]);
+        }
+        DnsPacket::new(&mut bytes[..]).set_question_count(questions.len() as u16);
+        bytes
+    }
+
+    #[test]
+    fn test_hostname() {
+        let hostname = Hostname::new("device", b"\x05local\x00").unwrap();
+        assert_eq!(hostname.as_bytes(), b"\x06device\x05local\x00");
+        assert_eq!(Hostname::new("device", b"\x00").unwrap().as_bytes(), b"\x06device\x00");
+
+        assert!(Hostname::new("", b"\x00").is_err());
+        assert!(Hostname::new("device.local", b"\x00").is_err());
+        assert!(Hostname::new(&"x".repeat(64), b"\x00").is_err());
+    }
+
+    #[test]
+    fn test_address_query() {
+        let hostname = Hostname::new("device", b"\x00").unwrap();
+
+        let bytes = query(&[("other", DnsType::A), ("DEVICE", DnsType::Txt)]);
+        let packet = DnsPacket::new_checked(&bytes[..]).unwrap();
+        assert_eq!(hostname.address_query(&packet), Ok(None));
+
+        let bytes = query(&[("other", DnsType::A), ("DEVICE", DnsType::Unknown(TYPE_ANY))]);
+        let packet = DnsPacket::new_checked(&bytes[..]).unwrap();
+        let address_query = hostname.address_query(&packet).unwrap().unwrap();
+        assert_eq!(address_query.question_type, DnsType::Unknown(TYPE_ANY));
+    }
+
+    #[test]
+    #[cfg(all(feature = "proto-ipv4", feature = "proto-ipv6"))]
+    fn test_emit_answers() {
+        let hostname = Hostname::new("device", b"\x00").unwrap();
+        let ipv4_addr = Ipv4Address([192, 168, 1, 10]);
+        let ipv6_addr = Ipv6Address([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        let ip_addrs = [IpCidr::new(Ipv4Address::UNSPECIFIED.into(), 0),
+                        IpCidr::new(ipv4_addr.into(), 24),
+                        IpCidr::new(ipv6_addr.into(), 64)];
+
+        let bytes = query(&[("device", DnsType::Aaaa)]);
+        let packet = DnsPacket::new_checked(&bytes[..]).unwrap();
+        let address_query = hostname.address_query(&packet).unwrap().unwrap();
+
+        let mut buffer = [0; 64];
+        let (length, count) = address_query.emit_answers(&hostname, &ip_addrs, CLASS_IN, 30,
+                                                         &mut buffer);
+        assert_eq!(count, 1);
+        let (rest, record) = DnsRecord::parse(&buffer[..length]).unwrap();
+        assert_eq!(rest, &[]);
+        assert_eq!(record, DnsRecord {
+            name: hostname.as_bytes(),
+            class: CLASS_IN,
+            ttl: 30,
+            data: DnsRecordData::Aaaa(ipv6_addr),
+        });
+    }
+}
@@ -0,0 +1,400 @@
+// Heads up! Before working on this file you should read RFC 4795,
+// which describes how LLMNR queries are answered (§ 2.1.1, § 2.4),
+// and how queries from other links are rejected (§ 2.5).
+
+use core::fmt;
+
+use Result;
+use phy::Device;
+use wire::{IpAddress, IpCidr, IpEndpoint,
+           DnsPacket, DnsQuestion, DnsName, DnsOpcode, DnsRcode, LLMNR_PORT};
+#[cfg(feature = "proto-ipv4")]
+use wire::LLMNR_IPV4_GROUP;
+#[cfg(feature = "proto-ipv6")]
+use wire::LLMNR_IPV6_GROUP;
+use wire::dns::CLASS_IN;
+use socket::{SocketSet, SocketHandle, UdpSocket, UdpSocketBuffer};
+use iface::EthernetInterface;
+use super::Hostname;
+
+/// The time the address records of the hostname may be cached for, in seconds.
+const RECORD_TTL: u32 = 30;
+/// The maximum length of a message sent by the responder.
+const MAX_MESSAGE_LEN: usize = 512;
+/// The encoded domain of the hostname; LLMNR names are usually a single label.
+const ROOT_DOMAIN: &[u8] = b"\x00";
+
+/// An LLMNR responder.
+///
+/// The responder answers A and AAAA queries for the hostname with the addresses
+/// of the interface. It receives queries through a UDP socket that it adds to
+/// the socket set, and joins the LLMNR groups on the interface while polled.
+/// Queries from hosts that are not on any of the networks of the interface are ignored.
+///
+/// The responder does not verify that the hostname is unique on the link.
+pub struct Responder {
+    udp_handle: SocketHandle,
+    hostname: Hostname,
+}
+
+impl fmt::Debug for Responder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Responder")
+         .field("udp_handle", &self.udp_handle)
+         .field("hostname", &self.hostname())
+         .finish()
+    }
+}
+
+impl Responder {
+    /// Create an LLMNR responder for the given hostname, which must be a single label,
+    /// and add its UDP socket, created with the given buffers, to the socket set.
+    ///
+    /// # Panics
+    /// This function panics if the hostname is not a valid label.
+    pub fn new<'a, 'b, 'c>(sockets: &mut SocketSet<'a, 'b, 'c>,
+                           rx_buffer: UdpSocketBuffer<'b, 'c>,
+                           tx_buffer: UdpSocketBuffer<'b, 'c>,
+                           hostname: &str) -> Responder {
+        let mut udp_socket = UdpSocket::new(rx_buffer, tx_buffer);
+        // See RFC 4795 § 2.5.
+        udp_socket.set_hop_limit(Some(1));
+        let udp_handle = sockets.add(udp_socket);
+
+        let hostname = match Hostname::new(hostname, ROOT_DOMAIN) {
+            Ok(hostname) => hostname,
+            Err(_) => panic!("hostname {:?} is not a valid label", hostname)
+        };
+        Responder {
+            udp_handle: udp_handle,
+            hostname: hostname,
+        }
+    }
+
+    /// Return the handle of the UDP socket used by the responder.
+    pub fn udp_handle(&self) -> SocketHandle {
+        self.udp_handle
+    }
+
+    /// Return the name the responder answers queries for.
+    pub fn hostname(&self) -> DnsName {
+        self.hostname.name()
+    }
+
+    /// Set the hostname, which is validated like [Identity::set_hostname] does.
+    ///
+    /// If the [identity] of the interface has a hostname, it replaces this one
+    /// the next time the responder is polled.
+    ///
+    /// [Identity::set_hostname]: ../iface/struct.Identity.html#method.set_hostname
+    /// [identity]: ../iface/struct.Identity.html
+    pub fn set_hostname(&mut self, hostname: &str) -> Result<()> {
+        self.hostname = Hostname::new(hostname, ROOT_DOMAIN)?;
+        Ok(())
+    }
+
+    /// Adopt the hostname of the interface, if it has one, join the LLMNR groups
+    /// on the interface, and answer the queries received by the UDP socket.
+    ///
+    /// Returns `Err(Error::Exhausted)` if the interface has no space for the multicast
+    /// groups.
+    pub fn poll<'b, 'c, DeviceT>(&mut self, iface: &mut EthernetInterface<'b, 'c, DeviceT>,
+                                 sockets: &mut SocketSet) -> Result<()>
+            where DeviceT: for<'d> Device<'d> {
+        if let Some(hostname) = iface.identity().hostname() {
+            if self.hostname.label() != hostname.as_bytes() {
+                net_debug!("LLMNR adopting hostname {} of the interface", hostname);
+                self.set_hostname(hostname)?;
+            }
+        }
+
+        #[cfg(feature = "proto-ipv4")]
+        iface.join_multicast_group(LLMNR_IPV4_GROUP)?;
+        #[cfg(feature = "proto-ipv6")]
+        iface.join_multicast_group(LLMNR_IPV6_GROUP)?;
+
+        let mut udp_socket = sockets.get::<UdpSocket>(self.udp_handle);
+        if !udp_socket.is_open() {
+            udp_socket.bind(LLMNR_PORT)?;
+        }
+
+        loop {
+            let mut buffer = [0; MAX_MESSAGE_LEN];
+            let response = match udp_socket.recv() {
+                Ok((payload, endpoint)) =>
+                    self.process(iface.ip_addrs(), payload, endpoint, &mut buffer)
+                        .map(|length| (&buffer[..length], endpoint)),
+                Err(_) => break
+            };
+            if let Some((response, endpoint)) = response {
+                match udp_socket.send_slice(response, endpoint) {
+                    Ok(()) => {
+                        net_trace!("LLMNR sent response to {}", endpoint);
+                    }
+                    Err(_) => {
+                        net_debug!("LLMNR dropped response to {}", endpoint);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Process a received message, and build a response into `response` if one
+    /// has to be sent back, returning its length.
+    fn process(&self, ip_addrs: &[IpCidr], payload: &[u8], endpoint: IpEndpoint,
+               response: &mut [u8]) -> Option<usize> {
+        let packet = match DnsPacket::new_checked(payload) {
+            Ok(packet) => packet,
+            Err(_) => {
+                net_trace!("LLMNR ignoring malformed message");
+                return None
+            }
+        };
+        if packet.is_response() || packet.opcode() != DnsOpcode::Query ||
+                packet.question_count() != 1 || packet.answer_record_count() != 0 ||
+                packet.authority_record_count() != 0 {
+            return None
+        }
+        if !on_link(ip_addrs, &endpoint.addr) {
+            net_debug!("LLMNR ignoring query from off-link host {}", endpoint);
+            return None
+        }
+
+        let address_query = match self.hostname.address_query(&packet) {
+            Ok(Some(address_query)) => address_query,
+            Ok(None) => return None,
+            Err(_) => {
+                net_trace!("LLMNR ignoring malformed message");
+                return None
+            }
+        };
+
+        // The response repeats the question, and has no answers if the interface has no
+        // addresses of the type asked for.
+        let question = DnsQuestion {
+            name: self.hostname.as_bytes(),
+            type_: address_query.question_type
+        };
+        question.emit(&mut response[12..]);
+        let mut length = 12 + question.buffer_len();
+        let (answers_len, answer_count) =
+            address_query.emit_answers(&self.hostname, ip_addrs, CLASS_IN, RECORD_TTL,
+                                       &mut response[length..]);
+        length += answers_len;
+
+        {
+            let mut packet_out = DnsPacket::new(&mut response[..length]);
+            packet_out.set_transaction_id(packet.transaction_id());
+            packet_out.clear_flags();
+            packet_out.set_response(true);
+            packet_out.set_opcode(DnsOpcode::Query);
+            packet_out.set_rcode(DnsRcode::NoError);
+            packet_out.set_question_count(1);
+            packet_out.set_answer_record_count(answer_count as u16);
+            packet_out.set_authority_record_count(0);
+            packet_out.set_additional_record_count(0);
+        }
+        net_trace!("LLMNR answering query for {} from {}", self.hostname(), endpoint);
+        Some(length)
+    }
+}
+
+/// Check whether the address is link-local, or on one of the networks of the interface.
+fn on_link(ip_addrs: &[IpCidr], addr: &IpAddress) -> bool {
+    let link_local = match *addr {
+        #[cfg(feature = "proto-ipv4")]
+        IpAddress::Ipv4(addr) => addr.is_link_local(),
+        #[cfg(feature = "proto-ipv6")]
+        IpAddress::Ipv6(addr) => addr.is_link_local(),
+        _ => false
+    };
+    link_local || ip_addrs.iter().any(|cidr| cidr.contains_addr(addr))
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use std::collections::BTreeMap;
+    use phy::Loopback;
+    use wire::EthernetAddress;
+    #[cfg(feature = "proto-ipv4")]
+    use wire::{Ipv4Address, IpProtocol, IpRepr, UdpRepr, DnsRecord, DnsRecordData, DnsType};
+    #[cfg(feature = "proto-ipv4")]
+    use wire::dns::{encode_name, MAX_NAME_LEN};
+    use iface::{EthernetInterfaceBuilder, NeighborCache};
+    use socket::UdpPacketBuffer;
+    use super::*;
+
+    #[cfg(feature = "proto-ipv4")]
+    const LOCAL_IP: Ipv4Address = Ipv4Address([192, 168, 1, 10]);
+    #[cfg(feature = "proto-ipv4")]
+    const REMOTE_IP: Ipv4Address = Ipv4Address([192, 168, 1, 20]);
+
+    fn buffer() -> UdpSocketBuffer<'static, 'static> {
+        UdpSocketBuffer::new((0..4).map(|_| UdpPacketBuffer::new(vec![0; 600]))
+                                   .collect::<Vec<_>>())
+    }
+
+    fn setup() -> (EthernetInterface<'static, 'static, Loopback>,
+                   SocketSet<'static, 'static, 'static>, Responder) {
+        let iface = EthernetInterfaceBuilder::new(Loopback::new())
+                .ethernet_addr(EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]))
+                .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+                .ip_addrs(vec![
+                    #[cfg(feature = "proto-ipv4")]
+                    IpCidr::new(LOCAL_IP.into(), 24)
+                ])
+                .multicast_groups(vec![])
+                .finalize();
+        let mut sockets = SocketSet::new(vec![]);
+        let responder = Responder::new(&mut sockets, buffer(), buffer(), "device");
+        (iface, sockets, responder)
+    }
+
+    fn sent(sockets: &mut SocketSet, responder: &Responder) -> Option<(IpEndpoint, Vec<u8>)> {
+        let mut result = None;
+        let _ = sockets.get::<UdpSocket>(responder.udp_handle())
+            .dispatch(0, |(ip_repr, udp_repr)| {
+                assert_eq!(udp_repr.src_port, LLMNR_PORT);
+                result = Some((IpEndpoint::new(ip_repr.dst_addr(), udp_repr.dst_port),
+                               udp_repr.payload.to_vec()));
+                Ok(())
+            });
+        result
+    }
+
+    #[cfg(feature = "proto-ipv4")]
+    fn recv(sockets: &mut SocketSet, responder: &Responder,
+            src: IpEndpoint, dst_addr: IpAddress, bytes: &[u8]) {
+        let udp_repr = UdpRepr {
+            src_port: src.port,
+            dst_port: LLMNR_PORT,
+            payload:  bytes
+        };
+        let ip_repr = IpRepr::Unspecified {
+            src_addr: src.addr,
+            dst_addr: dst_addr,
+            protocol: IpProtocol::Udp,
+            payload_len: udp_repr.buffer_len(),
+            hop_limit: 1,
+            ecn: 0,
+            dscp: 0,
+            flow_label: 0
+        };
+        sockets.get::<UdpSocket>(responder.udp_handle())
+            .process_lent(0, None, &ip_repr, &udp_repr, None).unwrap();
+    }
+
+    #[cfg(feature = "proto-ipv4")]
+    fn name(name: &str) -> Vec<u8> {
+        let mut bytes = vec![0; MAX_NAME_LEN];
+        let length = encode_name(name, &mut bytes).unwrap();
+        bytes.truncate(length);
+        bytes
+    }
+
+    #[cfg(feature = "proto-ipv4")]
+    fn query(transaction_id: u16, name: &[u8], type_: DnsType) -> Vec<u8> {
+        let question = DnsQuestion { name: name, type_: type_ };
+        let mut bytes = vec![0; 12 + question.buffer_len()];
+        question.emit(&mut bytes[12..]);
+        {
+            let mut packet = DnsPacket::new(&mut bytes[..]);
+            packet.set_transaction_id(transaction_id);
+            packet.set_question_count(1);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_join_groups() {
+        let (mut iface, mut sockets, mut responder) = setup();
+        assert_eq!(format!("{}", responder.hostname()), "device.");
+        assert_eq!(responder.poll(&mut iface, &mut sockets), Ok(()));
+        #[cfg(feature = "proto-ipv4")]
+        assert!(iface.has_multicast_group(LLMNR_IPV4_GROUP));
+        #[cfg(feature = "proto-ipv6")]
+        assert!(iface.has_multicast_group(LLMNR_IPV6_GROUP));
+        assert!(sent(&mut sockets, &responder).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_answer_query() {
+        let (mut iface, mut sockets, mut responder) = setup();
+        assert_eq!(responder.poll(&mut iface, &mut sockets), Ok(()));
+
+        let src = IpEndpoint::new(REMOTE_IP.into(), 50000);
+        recv(&mut sockets, &responder, src, LLMNR_IPV4_GROUP.into(),
+             &query(0xabcd, &name("DEVICE"), DnsType::A));
+        assert_eq!(responder.poll(&mut iface, &mut sockets), Ok(()));
+
+        let (endpoint, payload) = sent(&mut sockets, &responder).unwrap();
+        assert_eq!(endpoint, src);
+        let packet = DnsPacket::new_checked(&payload[..]).unwrap();
+        assert_eq!(packet.transaction_id(), 0xabcd);
+        assert!(packet.is_response());
+        assert_eq!(packet.question_count(), 1);
+        assert_eq!(packet.answer_record_count(), 1);
+        let (rest, question) = DnsQuestion::parse(packet.payload()).unwrap();
+        assert_eq!(question, DnsQuestion { name: &name("device"), type_: DnsType::A });
+        let (rest, record) = DnsRecord::parse(rest).unwrap();
+        assert_eq!(rest, &[]);
+        assert_eq!(record, DnsRecord {
+            name: &name("device"),
+            class: CLASS_IN,
+            ttl: RECORD_TTL,
+            data: DnsRecordData::A(LOCAL_IP),
+        });
+    }
+
+    #[test]
+    #[cfg(all(feature = "proto-ipv4", feature = "proto-ipv6"))]
+    fn test_answer_query_without_addresses() {
+        let (mut iface, mut sockets, mut responder) = setup();
+        assert_eq!(responder.poll(&mut iface, &mut sockets), Ok(()));
+
+        let src = IpEndpoint::new(REMOTE_IP.into(), 50000);
+        recv(&mut sockets, &responder, src, LLMNR_IPV4_GROUP.into(),
+             &query(0xabcd, &name("device"), DnsType::Aaaa));
+        assert_eq!(responder.poll(&mut iface, &mut sockets), Ok(()));
+
+        let (_, payload) = sent(&mut sockets, &responder).unwrap();
+        let packet = DnsPacket::new_checked(&payload[..]).unwrap();
+        assert_eq!(packet.rcode(), DnsRcode::NoError);
+        assert_eq!(packet.question_count(), 1);
+        assert_eq!(packet.answer_record_count(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_ignore_other_queries() {
+        let (mut iface, mut sockets, mut responder) = setup();
+        assert_eq!(responder.poll(&mut iface, &mut sockets), Ok(()));
+
+        let src = IpEndpoint::new(REMOTE_IP.into(), 50000);
+        recv(&mut sockets, &responder, src, LLMNR_IPV4_GROUP.into(),
+             &query(1, &name("other"), DnsType::A));
+        recv(&mut sockets, &responder, src, LLMNR_IPV4_GROUP.into(),
+             &query(2, &name("device"), DnsType::Txt));
+        let mut response = query(3, &name("device"), DnsType::A);
+        DnsPacket::new(&mut response[..]).set_response(true);
+        recv(&mut sockets, &responder, src, LLMNR_IPV4_GROUP.into(), &response);
+        assert_eq!(responder.poll(&mut iface, &mut sockets), Ok(()));
+        assert!(sent(&mut sockets, &responder).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_ignore_off_link_query() {
+        let (mut iface, mut sockets, mut responder) = setup();
+        assert_eq!(responder.poll(&mut iface, &mut sockets), Ok(()));
+
+        let src = IpEndpoint::new(Ipv4Address([10, 0, 0, 1]).into(), 50000);
+        recv(&mut sockets, &responder, src, LOCAL_IP.into(),
+             &query(1, &name("device"), DnsType::A));
+        assert_eq!(responder.poll(&mut iface, &mut sockets), Ok(()));
+        assert!(sent(&mut sockets, &responder).is_none());
+    }
+}
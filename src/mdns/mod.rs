@@ -0,0 +1,28 @@
+/*! Multicast DNS and LLMNR.
+
+The `mdns` module implements a multicast DNS responder, on top of a UDP socket,
+that answers queries for the addresses of the hostname of an interface, advertises
+services with DNS-SD, and resolves the addresses of other hosts on the link with one-shot
+queries, as well as an LLMNR responder, on top of a UDP socket, that answers queries
+for the addresses of the hostname of an interface.
+*/
+
+mod answer;
+#[cfg(feature = "proto-mdns")]
+mod responder;
+#[cfg(feature = "proto-mdns")]
+mod service;
+#[cfg(feature = "proto-llmnr")]
+mod llmnr;
+
+use self::answer::Hostname;
+
+pub use self::answer::MAX_ADDRESS_COUNT;
+#[cfg(feature = "proto-mdns")]
+pub use self::responder::{Responder as MdnsResponder, QueryHandle as MdnsQueryHandle,
+                          MAX_QUERY_COUNT};
+#[cfg(feature = "proto-mdns")]
+pub use self::service::{ServiceHandle as MdnsServiceHandle, ServiceState as MdnsServiceState,
+                        MAX_SERVICE_COUNT, MAX_TXT_LEN};
+#[cfg(feature = "proto-llmnr")]
+pub use self::llmnr::Responder as LlmnrResponder;
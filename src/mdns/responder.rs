@@ -0,0 +1,1166 @@
+// Heads up! Before working on this file you should read RFC 6762,
+// which describes how multicast DNS queries are sent (§ 5) and answered (§ 6),
+// including the legacy unicast responses (§ 6.7), and how unique records are
+// probed for and announced (§ 8).
+
+use core::{cmp, fmt};
+
+use {Error, Result};
+use phy::Device;
+use wire::{IpAddress, IpCidr, IpEndpoint,
+           DnsPacket, DnsQuestion, DnsRecord, DnsRecordData, DnsName,
+           DnsType, DnsOpcode, DnsRcode, MDNS_PORT};
+#[cfg(feature = "proto-ipv4")]
+use wire::MDNS_IPV4_GROUP;
+#[cfg(feature = "proto-ipv6")]
+use wire::MDNS_IPV6_GROUP;
+use wire::dns::{encode_name, MAX_NAME_LEN, CLASS_IN};
+use socket::{SocketSet, SocketHandle, UdpSocket, UdpSocketBuffer, DnsCache};
+use iface::EthernetInterface;
+use super::{Hostname, MAX_ADDRESS_COUNT};
+use super::service::{Service, Status, ServiceHandle, ServiceState,
+                     MAX_SERVICE_COUNT, ALL_RECORDS, SRV_RECORD, TXT_RECORD, ENUM_RECORD};
+
+/// The maximum number of queries that can be pending at once.
+pub const MAX_QUERY_COUNT: usize = 4;
+
+/// The interval between the transmissions of a query, in milliseconds, and the number
+/// of times it is sent before failing.
+const QUERY_INTERVAL: u64 = 1_000;
+const QUERY_SEND_COUNT: u8 = 3;
+/// The time the address records of the hostname may be cached for, in seconds.
+const RECORD_TTL: u32 = 120;
+/// The same, in responses to legacy unicast queries.
+const LEGACY_RECORD_TTL: u32 = 10;
+/// The time the records of the services may be cached for, in seconds.
+const SERVICE_RECORD_TTL: u32 = 4500;
+/// The interval between the probes for a service, in milliseconds, and their number.
+const PROBE_INTERVAL: u64 = 250;
+const PROBE_SEND_COUNT: u8 = 3;
+/// The interval between the announcements of a service, in milliseconds, and their number.
+const ANNOUNCE_INTERVAL: u64 = 1_000;
+const ANNOUNCE_SEND_COUNT: u8 = 2;
+/// The query type asking for records of every type.
+const TYPE_ANY: u16 = 255;
+/// The flag in the class of a record telling caches to flush any other records
+/// with the same name and type.
+const CLASS_CACHE_FLUSH: u16 = 0x8000;
+/// The maximum length of a message sent by the responder.
+const MAX_MESSAGE_LEN: usize = 512;
+/// The encoded domain of the hostname.
+const LOCAL_DOMAIN: &[u8] = b"\x05local\x00";
+
+/// A handle, identifying a query in an mDNS responder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct QueryHandle(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Sent `send_count` times, and to be sent again at `next_send`.
+    Pending { send_count: u8, next_send: u64 },
+    /// Answered with at least one address.
+    Completed([Option<IpAddress>; MAX_ADDRESS_COUNT]),
+    /// Not answered in time.
+    Failed,
+}
+
+#[derive(Clone, Copy)]
+struct Query {
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    query_type: DnsType,
+    state: State,
+}
+
+impl Query {
+    fn name(&self) -> DnsName {
+        let name = &self.name[..self.name_len];
+        DnsName::new(name, name)
+    }
+}
+
+impl fmt::Debug for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Query")
+         .field("name", &self.name())
+         .field("query_type", &self.query_type)
+         .field("state", &self.state)
+         .finish()
+    }
+}
+
+/// A multicast DNS responder.
+///
+/// The responder answers A and AAAA queries for `<hostname>.local` with the addresses
+/// of the interface, and resolves other `.local` names with one-shot queries.
+/// It talks to the other hosts on the link through a UDP socket that it adds to
+/// the socket set, and joins the multicast DNS groups on the interface while polled.
+///
+/// Services, e.g. a web server of type `_http._tcp`, can be registered with the responder
+/// to be discovered with DNS-SD. The responder probes for other hosts using the instance
+/// name of a service, announces it, answers the PTR, SRV and TXT queries for it and
+/// for the enumeration of service types, and withdraws it when it is unregistered.
+///
+/// If the responder is given a [cache](../socket/struct.DnsCache.html), the addresses
+/// received in answer to its queries are stored in it until their TTL expires, and queries
+/// for names found in it complete without being sent. Queries that time out are not cached.
+///
+/// The responder does not probe for other hosts using the same hostname, nor does it
+/// announce its address records; the hostname must be unique on the link. Legacy unicast
+/// queries are only answered for the hostname, and known answers are not suppressed.
+pub struct Responder<'a> {
+    udp_handle: SocketHandle,
+    hostname: Hostname,
+    queries: [Option<Query>; MAX_QUERY_COUNT],
+    services: [Option<Service>; MAX_SERVICE_COUNT],
+    cache: Option<DnsCache<'a>>,
+}
+
+impl<'a> fmt::Debug for Responder<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Responder")
+         .field("udp_handle", &self.udp_handle)
+         .field("hostname", &self.hostname())
+         .field("queries", &self.queries)
+         .field("services", &self.services)
+         .field("cache", &self.cache)
+         .finish()
+    }
+}
+
+impl<'a> Responder<'a> {
+    /// Create an mDNS responder for the given hostname, which must be a single label,
+    /// and add its UDP socket, created with the given buffers, to the socket set.
+    ///
+    /// # Panics
+    /// This function panics if the hostname is not a valid label.
+    pub fn new<'b, 'c, 'd>(sockets: &mut SocketSet<'b, 'c, 'd>,
+                           rx_buffer: UdpSocketBuffer<'c, 'd>,
+                           tx_buffer: UdpSocketBuffer<'c, 'd>,
+                           hostname: &str) -> Responder<'a> {
+        let mut udp_socket = UdpSocket::new(rx_buffer, tx_buffer);
+        // See RFC 6762 § 11.
+        udp_socket.set_hop_limit(Some(255));
+        let udp_handle = sockets.add(udp_socket);
+
+        let hostname = match Hostname::new(hostname, LOCAL_DOMAIN) {
+            Ok(hostname) => hostname,
+            Err(_) => panic!("hostname {:?} is not a valid label", hostname)
+        };
+        Responder {
+            udp_handle: udp_handle,
+            hostname: hostname,
+            queries: [None; MAX_QUERY_COUNT],
+            services: [None; MAX_SERVICE_COUNT],
+            cache: None,
+        }
+    }
+
+    /// Return the handle of the UDP socket used by the responder.
+    pub fn udp_handle(&self) -> SocketHandle {
+        self.udp_handle
+    }
+
+    /// Return the name the responder answers queries for, i.e. `<hostname>.local.`
+    pub fn hostname(&self) -> DnsName {
+        self.hostname.name()
+    }
+
+    /// Set the hostname, which is validated like [Identity::set_hostname] does.
+    ///
+    /// If the [identity] of the interface has a hostname, it replaces this one
+    /// the next time the responder is polled.
+    ///
+    /// [Identity::set_hostname]: ../iface/struct.Identity.html#method.set_hostname
+    /// [identity]: ../iface/struct.Identity.html
+    pub fn set_hostname(&mut self, hostname: &str) -> Result<()> {
+        self.hostname = Hostname::new(hostname, LOCAL_DOMAIN)?;
+        Ok(())
+    }
+
+    /// Return the cache of addresses, if any.
+    pub fn cache(&self) -> Option<&DnsCache<'a>> {
+        self.cache.as_ref()
+    }
+
+    /// Return the cache of addresses, if any, e.g. to flush it when the network changes.
+    pub fn cache_mut(&mut self) -> Option<&mut DnsCache<'a>> {
+        self.cache.as_mut()
+    }
+
+    /// Set the cache of addresses, or disable caching.
+    pub fn set_cache(&mut self, cache: Option<DnsCache<'a>>) {
+        self.cache = cache
+    }
+
+    /// Start a query for the addresses of `name`, which must be in the `local` domain,
+    /// e.g. `printer.local`. The query type must be `DnsType::A` or `DnsType::Aaaa`.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the name or the query type
+    /// are not valid, and `Err(Error::Exhausted)` if `MAX_QUERY_COUNT` queries
+    /// are pending already.
+    pub fn query(&mut self, name: &str, query_type: DnsType) -> Result<QueryHandle> {
+        match query_type {
+            #[cfg(feature = "proto-ipv4")]
+            DnsType::A => (),
+            #[cfg(feature = "proto-ipv6")]
+            DnsType::Aaaa => (),
+            _ => return Err(Error::Illegal)
+        }
+
+        let mut query = Query {
+            name: [0; MAX_NAME_LEN],
+            name_len: 0,
+            query_type: query_type,
+            state: State::Pending { send_count: 0, next_send: 0 },
+        };
+        query.name_len = encode_name(name, &mut query.name).map_err(|_| Error::Illegal)?;
+        match query.name().labels().last() {
+            Some(Ok(label)) if label.eq_ignore_ascii_case(b"local") => (),
+            _ => return Err(Error::Illegal)
+        }
+
+        for (index, slot) in self.queries.iter_mut().enumerate() {
+            if slot.is_none() {
+                *slot = Some(query);
+                return Ok(QueryHandle(index))
+            }
+        }
+        Err(Error::Exhausted)
+    }
+
+    /// Return the result of a query, and free it unless it is still pending.
+    ///
+    /// This function returns `Err(Error::Exhausted)` if the query is still pending,
+    /// and `Err(Error::Unaddressable)` if no host answered in time.
+    ///
+    /// # Panics
+    /// This function panics if the handle does not refer to a query.
+    pub fn query_result(&mut self, handle: QueryHandle) ->
+                       Result<[Option<IpAddress>; MAX_ADDRESS_COUNT]> {
+        let slot = &mut self.queries[handle.0];
+        let result = match *slot {
+            Some(Query { state: State::Pending { .. }, .. }) =>
+                return Err(Error::Exhausted),
+            Some(Query { state: State::Completed(addresses), .. }) =>
+                Ok(addresses),
+            Some(Query { state: State::Failed, .. }) =>
+                Err(Error::Unaddressable),
+            None => panic!("handle does not refer to a valid query")
+        };
+        *slot = None;
+        result
+    }
+
+    /// Cancel a query, and free it.
+    ///
+    /// # Panics
+    /// This function panics if the handle does not refer to a query.
+    pub fn cancel_query(&mut self, handle: QueryHandle) {
+        match self.queries[handle.0] {
+            ref mut slot @ Some(_) => *slot = None,
+            None => panic!("handle does not refer to a valid query")
+        }
+    }
+
+    /// Register a service with the given instance name, which is a single label that may
+    /// contain any characters, e.g. `Living Room`, service type, e.g. `_http._tcp`, port,
+    /// and TXT strings, e.g. `path=/index.html`. The service is served by the host with
+    /// the hostname of the responder.
+    ///
+    /// The service is probed for when the responder is polled next, and announced once
+    /// no other host is found using the instance name; see [service_state].
+    ///
+    /// This function returns `Err(Error::Illegal)` if the instance name, the service type
+    /// or a TXT string is not valid, and `Err(Error::Exhausted)` if the instance name or
+    /// the TXT strings are too long, or `MAX_SERVICE_COUNT` services are registered already.
+    ///
+    /// [service_state]: #method.service_state
+    pub fn register_service(&mut self, instance: &str, service_type: &str, port: u16,
+                            txt: &[&str]) -> Result<ServiceHandle> {
+        let service = Service::new(instance, service_type, port, txt)?;
+        for (index, slot) in self.services.iter_mut().enumerate() {
+            if slot.is_none() {
+                *slot = Some(service);
+                return Ok(ServiceHandle(index))
+            }
+        }
+        Err(Error::Exhausted)
+    }
+
+    /// Return the state of a service.
+    ///
+    /// # Panics
+    /// This function panics if the handle does not refer to a service.
+    pub fn service_state(&self, handle: ServiceHandle) -> ServiceState {
+        match self.services[handle.0] {
+            Some(ref service) if service.status != Status::Withdrawing => service.state(),
+            _ => panic!("handle does not refer to a valid service")
+        }
+    }
+
+    /// Unregister a service. If it was announced, its records are withdrawn when
+    /// the responder is polled next, before it is freed.
+    ///
+    /// # Panics
+    /// This function panics if the handle does not refer to a service.
+    pub fn unregister_service(&mut self, handle: ServiceHandle) {
+        let slot = &mut self.services[handle.0];
+        let withdraw = match *slot {
+            Some(ref service) if service.status != Status::Withdrawing => service.is_answered(),
+            _ => panic!("handle does not refer to a valid service")
+        };
+        if withdraw {
+            if let Some(ref mut service) = *slot { service.status = Status::Withdrawing }
+        } else {
+            *slot = None
+        }
+    }
+
+    /// Return the time, in milliseconds, at which the responder should be polled next,
+    /// assuming no packets arrive in the meantime, or `None` if no query is pending
+    /// and no service is being probed for, announced or withdrawn.
+    pub fn next_poll(&self, now: u64) -> Option<u64> {
+        let queries = self.queries.iter().filter_map(|slot| {
+            match *slot {
+                Some(Query { state: State::Pending { next_send, .. }, .. }) =>
+                    Some(cmp::max(next_send, now)),
+                _ => None
+            }
+        });
+        let services = self.services.iter().filter_map(|slot| {
+            match *slot {
+                Some(Service { status: Status::Probing { next_send, .. }, .. }) |
+                Some(Service { status: Status::Announcing { next_send, .. }, .. }) =>
+                    Some(cmp::max(next_send, now)),
+                Some(Service { status: Status::Withdrawing, .. }) => Some(now),
+                _ => None
+            }
+        });
+        queries.chain(services).min()
+    }
+
+    /// Adopt the hostname of the interface, if it has one, join the multicast DNS groups
+    /// on the interface, answer the queries received by the UDP socket, and send any
+    /// queries that are due.
+    ///
+    /// Returns `Err(Error::Exhausted)` if the interface has no space for the multicast
+    /// groups.
+    pub fn poll<'b, 'c, DeviceT>(&mut self, iface: &mut EthernetInterface<'b, 'c, DeviceT>,
+                                 sockets: &mut SocketSet, now: u64) -> Result<()>
+            where DeviceT: for<'d> Device<'d> {
+        if let Some(hostname) = iface.identity().hostname() {
+            if self.hostname.label() != hostname.as_bytes() {
+                net_debug!("mDNS adopting hostname {} of the interface", hostname);
+                self.set_hostname(hostname)?;
+            }
+        }
+
+        #[cfg(feature = "proto-ipv4")]
+        iface.join_multicast_group(MDNS_IPV4_GROUP)?;
+        #[cfg(feature = "proto-ipv6")]
+        iface.join_multicast_group(MDNS_IPV6_GROUP)?;
+
+        let mut udp_socket = sockets.get::<UdpSocket>(self.udp_handle);
+        if !udp_socket.is_open() {
+            udp_socket.bind(MDNS_PORT)?;
+        }
+
+        loop {
+            let mut buffer = [0; MAX_MESSAGE_LEN];
+            let response = match udp_socket.recv() {
+                Ok((payload, endpoint)) =>
+                    self.ingress(iface.ip_addrs(), payload, endpoint, &mut buffer, now)
+                        .map(|(length, endpoint)| (&buffer[..length], endpoint)),
+                Err(_) => break
+            };
+            if let Some((response, endpoint)) = response {
+                match udp_socket.send_slice(response, endpoint) {
+                    Ok(()) => {
+                        net_trace!("mDNS sent response to {}", endpoint);
+                    }
+                    Err(_) => {
+                        net_debug!("mDNS dropped response to {}", endpoint);
+                    }
+                }
+            }
+        }
+
+        self.egress(iface.ip_addrs(), &mut udp_socket, now);
+        Ok(())
+    }
+
+    /// Process a received message, and build a response into `response` if one
+    /// has to be sent, returning its length and destination.
+    fn ingress(&mut self, ip_addrs: &[IpCidr], payload: &[u8], endpoint: IpEndpoint,
+               response: &mut [u8], now: u64) -> Option<(usize, IpEndpoint)> {
+        let packet = match DnsPacket::new_checked(payload) {
+            Ok(packet) => packet,
+            Err(_) => {
+                net_trace!("mDNS ignoring malformed message");
+                return None
+            }
+        };
+        if packet.opcode() != DnsOpcode::Query || packet.rcode() != DnsRcode::NoError {
+            return None
+        }
+
+        let result = if packet.is_response() {
+            // Responses not sent from the mDNS port are not trusted.
+            if endpoint.port != MDNS_PORT { return None }
+            self.process_response(&packet, now).map(|()| None)
+        } else {
+            self.process_query(ip_addrs, &packet, endpoint, response)
+        };
+        match result {
+            Ok(response) => response,
+            Err(_) => {
+                net_trace!("mDNS ignoring malformed message");
+                None
+            }
+        }
+    }
+
+    fn process_query(&self, ip_addrs: &[IpCidr], packet: &DnsPacket<&[u8]>,
+                     endpoint: IpEndpoint, response: &mut [u8]) ->
+                    Result<Option<(usize, IpEndpoint)>> {
+        let address_query = self.hostname.address_query(packet)?;
+
+        // Queries not sent from the mDNS port come from simple resolvers, which expect
+        // a unicast response that repeats the question, as in unicast DNS.
+        let legacy = endpoint.port != MDNS_PORT;
+        if legacy && address_query.is_none() { return Ok(None) }
+        let (class, ttl) = if legacy {
+            (CLASS_IN, LEGACY_RECORD_TTL)
+        } else {
+            (CLASS_IN | CLASS_CACHE_FLUSH, RECORD_TTL)
+        };
+
+        let mut length = 12;
+        let mut answer_count = 0;
+        if let Some(address_query) = address_query {
+            if legacy {
+                let question = DnsQuestion {
+                    name: self.hostname.as_bytes(),
+                    type_: address_query.question_type
+                };
+                question.emit(&mut response[length..]);
+                length += question.buffer_len();
+            }
+
+            let (answers_len, count) =
+                address_query.emit_answers(&self.hostname, ip_addrs, class, ttl,
+                                           &mut response[length..]);
+            length += answers_len;
+            answer_count += count;
+        }
+
+        if !legacy {
+            for (index, service) in self.services.iter().enumerate() {
+                let service = match *service {
+                    Some(ref service) if service.is_answered() => service,
+                    _ => continue
+                };
+                let mut records = service.question_records(packet)?;
+                // Every service type is enumerated once.
+                let type_name = DnsName::new(service.type_bytes(), service.type_bytes());
+                if self.services[..index].iter().filter_map(|other| other.as_ref())
+                        .any(|other| other.is_answered() &&
+                                     type_name.eq_ignore_case(&DnsName::new(other.type_bytes(),
+                                                                            other.type_bytes()))) {
+                    records &= !ENUM_RECORD
+                }
+
+                let (answers_len, count) =
+                    service.emit_records(records, &self.hostname, class, SERVICE_RECORD_TTL,
+                                         &mut response[length..]);
+                length += answers_len;
+                answer_count += count;
+            }
+        }
+        if answer_count == 0 { return Ok(None) }
+
+        {
+            let mut packet_out = DnsPacket::new(&mut response[..length]);
+            packet_out.set_transaction_id(if legacy { packet.transaction_id() } else { 0 });
+            packet_out.clear_flags();
+            packet_out.set_response(true);
+            packet_out.set_authoritative(true);
+            packet_out.set_opcode(DnsOpcode::Query);
+            packet_out.set_rcode(DnsRcode::NoError);
+            packet_out.set_question_count(if legacy { 1 } else { 0 });
+            packet_out.set_answer_record_count(answer_count as u16);
+            packet_out.set_authority_record_count(0);
+            packet_out.set_additional_record_count(0);
+        }
+        net_trace!("mDNS answering query for {} from {}", self.hostname(), endpoint);
+
+        let dst_endpoint = if legacy {
+            endpoint
+        } else {
+            match endpoint.addr {
+                #[cfg(feature = "proto-ipv4")]
+                IpAddress::Ipv4(_) => IpEndpoint::new(MDNS_IPV4_GROUP.into(), MDNS_PORT),
+                #[cfg(feature = "proto-ipv6")]
+                IpAddress::Ipv6(_) => IpEndpoint::new(MDNS_IPV6_GROUP.into(), MDNS_PORT),
+                _ => return Ok(None)
+            }
+        };
+        Ok(Some((length, dst_endpoint)))
+    }
+
+    fn process_response(&mut self, packet: &DnsPacket<&[u8]>, now: u64) -> Result<()> {
+        let message = packet.message();
+
+        let mut records = packet.payload();
+        for _ in 0..packet.question_count() {
+            let (next_records, _) = DnsQuestion::parse(records)?;
+            records = next_records;
+        }
+
+        let record_count = packet.answer_record_count() as usize +
+                           packet.authority_record_count() as usize +
+                           packet.additional_record_count() as usize;
+        for _ in 0..record_count {
+            let (next_records, record) = DnsRecord::parse(records)?;
+            records = next_records;
+
+            for service in self.services.iter_mut().filter_map(|slot| slot.as_mut()) {
+                if service.conflicts(message, &record, &self.hostname) {
+                    net_debug!("mDNS service {} is used by another host", service.name());
+                    service.status = Status::Conflict;
+                }
+            }
+
+            let (record_type, address) = match record.data {
+                #[cfg(feature = "proto-ipv4")]
+                DnsRecordData::A(addr) => (DnsType::A, IpAddress::Ipv4(addr)),
+                #[cfg(feature = "proto-ipv6")]
+                DnsRecordData::Aaaa(addr) => (DnsType::Aaaa, IpAddress::Ipv6(addr)),
+                _ => continue
+            };
+            let name = DnsName::new(message, record.name);
+
+            for query in self.queries.iter_mut().filter_map(|slot| slot.as_mut()) {
+                if query.query_type != record_type || !name.eq_ignore_case(&query.name()) {
+                    continue
+                }
+
+                let addresses = match query.state {
+                    State::Pending { .. } => {
+                        net_trace!("mDNS resolved {} to {}", name, address);
+                        let mut addresses = [None; MAX_ADDRESS_COUNT];
+                        addresses[0] = Some(address);
+                        query.state = State::Completed(addresses);
+                        addresses
+                    }
+                    State::Completed(ref mut addresses) => {
+                        if addresses.contains(&Some(address)) { continue }
+                        if let Some(slot) = addresses.iter_mut().find(|slot| slot.is_none()) {
+                            *slot = Some(address);
+                        }
+                        *addresses
+                    }
+                    State::Failed => continue
+                };
+                if let Some(ref mut cache) = self.cache {
+                    let _ = cache.insert_encoded(&query.name[..query.name_len], query.query_type,
+                                                 addresses, record.ttl, now);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn egress(&mut self, ip_addrs: &[IpCidr], udp_socket: &mut UdpSocket, now: u64) {
+        let groups = multicast_groups(ip_addrs);
+
+        for query in self.queries.iter_mut().filter_map(|slot| slot.as_mut()) {
+            let send_count = match query.state {
+                State::Pending { send_count, next_send } if now >= next_send => send_count,
+                _ => continue
+            };
+            if let (0, Some(cache)) = (send_count, self.cache.as_mut()) {
+                let name = &query.name[..query.name_len];
+                if let Ok(addresses) = cache.lookup_encoded(name, query.query_type, now) {
+                    net_trace!("mDNS answered query for {} from the cache", query.name());
+                    query.state = State::Completed(addresses);
+                    continue
+                }
+            }
+            if send_count == QUERY_SEND_COUNT {
+                net_debug!("mDNS query for {} timed out", query.name());
+                query.state = State::Failed;
+                continue
+            }
+
+            let mut buffer = [0; 12 + MAX_NAME_LEN + 4];
+            let question = DnsQuestion {
+                name: &query.name[..query.name_len],
+                type_: query.query_type
+            };
+            let length = 12 + question.buffer_len();
+            {
+                let mut packet = DnsPacket::new(&mut buffer[..length]);
+                packet.set_transaction_id(0);
+                packet.clear_flags();
+                packet.set_opcode(DnsOpcode::Query);
+                packet.set_question_count(1);
+                packet.set_answer_record_count(0);
+                packet.set_authority_record_count(0);
+                packet.set_additional_record_count(0);
+                question.emit(packet.payload_mut());
+            }
+
+            // If the interface has no addresses yet, the query is not sent at all,
+            // but still times out.
+            for group in groups.iter().filter_map(|group| *group) {
+                let endpoint = IpEndpoint::new(group, MDNS_PORT);
+                match udp_socket.send_slice(&buffer[..length], endpoint) {
+                    Ok(()) => {
+                        net_trace!("mDNS sent query for {} to {}", query.name(), endpoint);
+                    }
+                    Err(_) => {
+                        net_debug!("mDNS dropped query for {}", query.name());
+                    }
+                }
+            }
+
+            query.state = State::Pending {
+                send_count: send_count + 1,
+                next_send: now + QUERY_INTERVAL
+            };
+        }
+
+        for slot in self.services.iter_mut() {
+            let withdrawn = match *slot {
+                Some(ref mut service) =>
+                    Self::egress_service(service, &self.hostname, &groups, udp_socket, now),
+                None => continue
+            };
+            if withdrawn { *slot = None }
+        }
+    }
+
+    /// Send a probe, an announcement or a goodbye for a service if one is due,
+    /// and return whether it was withdrawn.
+    fn egress_service(service: &mut Service, hostname: &Hostname, groups: &[Option<IpAddress>],
+                      udp_socket: &mut UdpSocket, now: u64) -> bool {
+        // The instance name is unique once the last probe has gone unanswered for a while.
+        if let Status::Probing { send_count: PROBE_SEND_COUNT, next_send } = service.status {
+            if now >= next_send {
+                net_debug!("mDNS announcing service {}", service.name());
+                service.status = Status::Announcing { send_count: 0, next_send: now }
+            }
+        }
+
+        let mut buffer = [0; MAX_MESSAGE_LEN];
+        let length = match service.status {
+            Status::Probing { next_send, .. } |
+            Status::Announcing { next_send, .. } if now < next_send => return false,
+            Status::Probing { .. } =>
+                emit_probe(service, hostname, &mut buffer),
+            Status::Announcing { .. } =>
+                emit_announcement(service, hostname, SERVICE_RECORD_TTL, &mut buffer),
+            Status::Withdrawing =>
+                emit_announcement(service, hostname, 0, &mut buffer),
+            Status::Registered | Status::Conflict => return false
+        };
+
+        for group in groups.iter().filter_map(|group| *group) {
+            let endpoint = IpEndpoint::new(group, MDNS_PORT);
+            match udp_socket.send_slice(&buffer[..length], endpoint) {
+                Ok(()) => {
+                    net_trace!("mDNS sent records of {} to {}", service.name(), endpoint);
+                }
+                Err(_) => {
+                    net_debug!("mDNS dropped records of {}", service.name());
+                }
+            }
+        }
+
+        service.status = match service.status {
+            Status::Probing { send_count, .. } =>
+                Status::Probing { send_count: send_count + 1, next_send: now + PROBE_INTERVAL },
+            Status::Announcing { send_count, .. } if send_count + 1 < ANNOUNCE_SEND_COUNT =>
+                Status::Announcing { send_count: send_count + 1,
+                                     next_send: now + ANNOUNCE_INTERVAL },
+            Status::Announcing { .. } => Status::Registered,
+            status => return status == Status::Withdrawing
+        };
+        false
+    }
+}
+
+/// Build a probe for a service into the buffer, asking for any record with its instance
+/// name and proposing its SRV and TXT records, and return its length.
+fn emit_probe(service: &Service, hostname: &Hostname, buffer: &mut [u8]) -> usize {
+    let question = DnsQuestion { name: service.name_bytes(), type_: DnsType::Unknown(TYPE_ANY) };
+    question.emit(&mut buffer[12..]);
+    let mut length = 12 + question.buffer_len();
+    let (records_len, record_count) =
+        service.emit_records(SRV_RECORD | TXT_RECORD, hostname, CLASS_IN, SERVICE_RECORD_TTL,
+                             &mut buffer[length..]);
+    length += records_len;
+
+    let mut packet = DnsPacket::new(&mut buffer[..length]);
+    packet.set_transaction_id(0);
+    packet.clear_flags();
+    packet.set_opcode(DnsOpcode::Query);
+    packet.set_question_count(1);
+    packet.set_answer_record_count(0);
+    packet.set_authority_record_count(record_count as u16);
+    packet.set_additional_record_count(0);
+    length
+}
+
+/// Build an unsolicited response with the records of a service and the given TTL into
+/// the buffer, announcing the service, or withdrawing it if the TTL is zero, and return
+/// its length.
+fn emit_announcement(service: &Service, hostname: &Hostname, ttl: u32,
+                     buffer: &mut [u8]) -> usize {
+    let (records_len, record_count) =
+        service.emit_records(ALL_RECORDS, hostname, CLASS_IN | CLASS_CACHE_FLUSH, ttl,
+                             &mut buffer[12..]);
+    let length = 12 + records_len;
+
+    let mut packet = DnsPacket::new(&mut buffer[..length]);
+    packet.set_transaction_id(0);
+    packet.clear_flags();
+    packet.set_response(true);
+    packet.set_authoritative(true);
+    packet.set_opcode(DnsOpcode::Query);
+    packet.set_rcode(DnsRcode::NoError);
+    packet.set_question_count(0);
+    packet.set_answer_record_count(record_count as u16);
+    packet.set_authority_record_count(0);
+    packet.set_additional_record_count(0);
+    length
+}
+
+/// Return the multicast DNS groups of the address families the interface has an address of.
+fn multicast_groups(ip_addrs: &[IpCidr]) -> [Option<IpAddress>; 2] {
+    let mut groups = [None; 2];
+    for cidr in ip_addrs {
+        match cidr.address() {
+            #[cfg(feature = "proto-ipv4")]
+            IpAddress::Ipv4(addr) if !addr.is_unspecified() =>
+                groups[0] = Some(MDNS_IPV4_GROUP.into()),
+            #[cfg(feature = "proto-ipv6")]
+            IpAddress::Ipv6(addr) if !addr.is_unspecified() =>
+                groups[1] = Some(MDNS_IPV6_GROUP.into()),
+            _ => ()
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use std::collections::BTreeMap;
+    use phy::Loopback;
+    use wire::EthernetAddress;
+    #[cfg(feature = "proto-ipv4")]
+    use wire::{Ipv4Address, IpProtocol, IpRepr, UdpRepr};
+    use iface::{EthernetInterfaceBuilder, NeighborCache};
+    use socket::UdpPacketBuffer;
+    use super::*;
+
+    #[cfg(feature = "proto-ipv4")]
+    const LOCAL_IP: Ipv4Address = Ipv4Address([192, 168, 1, 10]);
+    #[cfg(feature = "proto-ipv4")]
+    const REMOTE_IP: Ipv4Address = Ipv4Address([192, 168, 1, 20]);
+
+    fn buffer() -> UdpSocketBuffer<'static, 'static> {
+        UdpSocketBuffer::new(vec![UdpPacketBuffer::new(vec![0; 600]),
+                                  UdpPacketBuffer::new(vec![0; 600])])
+    }
+
+    fn setup() -> (EthernetInterface<'static, 'static, Loopback>,
+                   SocketSet<'static, 'static, 'static>, Responder<'static>) {
+        let iface = EthernetInterfaceBuilder::new(Loopback::new())
+                .ethernet_addr(EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]))
+                .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+                .ip_addrs(vec![
+                    #[cfg(feature = "proto-ipv4")]
+                    IpCidr::new(LOCAL_IP.into(), 24)
+                ])
+                .multicast_groups(vec![])
+                .finalize();
+        let mut sockets = SocketSet::new(vec![]);
+        let responder = Responder::new(&mut sockets, buffer(), buffer(), "device");
+        (iface, sockets, responder)
+    }
+
+    fn sent(sockets: &mut SocketSet, responder: &Responder) -> Option<(IpEndpoint, Vec<u8>)> {
+        let mut result = None;
+        let _ = sockets.get::<UdpSocket>(responder.udp_handle())
+            .dispatch(0, |(ip_repr, udp_repr)| {
+                assert_eq!(udp_repr.src_port, MDNS_PORT);
+                result = Some((IpEndpoint::new(ip_repr.dst_addr(), udp_repr.dst_port),
+                               udp_repr.payload.to_vec()));
+                Ok(())
+            });
+        result
+    }
+
+    #[cfg(feature = "proto-ipv4")]
+    fn recv(sockets: &mut SocketSet, responder: &Responder,
+            src: IpEndpoint, dst_addr: IpAddress, bytes: &[u8]) {
+        let udp_repr = UdpRepr {
+            src_port: src.port,
+            dst_port: MDNS_PORT,
+            payload:  bytes
+        };
+        let ip_repr = IpRepr::Unspecified {
+            src_addr: src.addr,
+            dst_addr: dst_addr,
+            protocol: IpProtocol::Udp,
+            payload_len: udp_repr.buffer_len(),
+            hop_limit: 255,
+            ecn: 0,
+            dscp: 0,
+            flow_label: 0
+        };
+        sockets.get::<UdpSocket>(responder.udp_handle())
+            .process_lent(0, None, &ip_repr, &udp_repr, None).unwrap();
+    }
+
+    /// Build a message with the given transaction ID, questions and answer records.
+    #[cfg(feature = "proto-ipv4")]
+    fn message(transaction_id: u16, response: bool,
+               questions: &[DnsQuestion], answers: &[DnsRecord]) -> Vec<u8> {
+        let mut bytes = vec![0; 12];
+        for question in questions {
+            let start = bytes.len();
+            bytes.resize(start + question.buffer_len(), 0);
+            question.emit(&mut bytes[start..]);
+        }
+        for answer in answers {
+            let start = bytes.len();
+            bytes.resize(start + answer.buffer_len(), 0);
+            answer.emit(&mut bytes[start..]);
+        }
+        {
+            let mut packet = DnsPacket::new(&mut bytes[..]);
+            packet.set_transaction_id(transaction_id);
+            packet.set_response(response);
+            packet.set_question_count(questions.len() as u16);
+            packet.set_answer_record_count(answers.len() as u16);
+        }
+        bytes
+    }
+
+    #[cfg(feature = "proto-ipv4")]
+    fn name(name: &str) -> Vec<u8> {
+        let mut bytes = vec![0; MAX_NAME_LEN];
+        let length = encode_name(name, &mut bytes).unwrap();
+        bytes.truncate(length);
+        bytes
+    }
+
+    #[test]
+    fn test_hostname() {
+        let (_iface, _sockets, mut responder) = setup();
+        assert_eq!(format!("{}", responder.hostname()), "device.local.");
+
+        assert_eq!(responder.set_hostname(""), Err(Error::Illegal));
+        assert_eq!(responder.set_hostname("device.example"), Err(Error::Illegal));
+        assert_eq!(responder.set_hostname(&"x".repeat(64)), Err(Error::Illegal));
+        assert_eq!(format!("{}", responder.hostname()), "device.local.");
+
+        assert_eq!(responder.set_hostname("sensor-2"), Ok(()));
+        assert_eq!(format!("{}", responder.hostname()), "sensor-2.local.");
+    }
+
+    #[test]
+    fn test_interface_hostname() {
+        let (mut iface, mut sockets, mut responder) = setup();
+        iface.identity_mut().set_hostname(Some("sensor-3")).unwrap();
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 0), Ok(()));
+        assert_eq!(format!("{}", responder.hostname()), "sensor-3.local.");
+
+        // The hostname of the interface takes precedence.
+        assert_eq!(responder.set_hostname("sensor-2"), Ok(()));
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 0), Ok(()));
+        assert_eq!(format!("{}", responder.hostname()), "sensor-3.local.");
+
+        iface.identity_mut().set_hostname(None).unwrap();
+        assert_eq!(responder.set_hostname("sensor-2"), Ok(()));
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 0), Ok(()));
+        assert_eq!(format!("{}", responder.hostname()), "sensor-2.local.");
+    }
+
+    #[test]
+    fn test_join_groups() {
+        let (mut iface, mut sockets, mut responder) = setup();
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 0), Ok(()));
+        #[cfg(feature = "proto-ipv4")]
+        assert!(iface.has_multicast_group(MDNS_IPV4_GROUP));
+        #[cfg(feature = "proto-ipv6")]
+        assert!(iface.has_multicast_group(MDNS_IPV6_GROUP));
+        assert!(sent(&mut sockets, &responder).is_none());
+        assert_eq!(responder.next_poll(0), None);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_answer_query() {
+        let (mut iface, mut sockets, mut responder) = setup();
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 0), Ok(()));
+
+        let hostname = name("Device.LOCAL");
+        let query = message(0, false, &[DnsQuestion { name: &hostname, type_: DnsType::A }], &[]);
+        recv(&mut sockets, &responder, IpEndpoint::new(REMOTE_IP.into(), MDNS_PORT),
+             MDNS_IPV4_GROUP.into(), &query);
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 0), Ok(()));
+
+        let (endpoint, payload) = sent(&mut sockets, &responder).unwrap();
+        assert_eq!(endpoint, IpEndpoint::new(MDNS_IPV4_GROUP.into(), MDNS_PORT));
+        let packet = DnsPacket::new_checked(&payload[..]).unwrap();
+        assert_eq!(packet.transaction_id(), 0);
+        assert!(packet.is_response());
+        assert!(packet.authoritative());
+        assert_eq!(packet.question_count(), 0);
+        assert_eq!(packet.answer_record_count(), 1);
+        let (rest, record) = DnsRecord::parse(packet.payload()).unwrap();
+        assert_eq!(rest, &[]);
+        assert_eq!(record, DnsRecord {
+            name: &name("device.local"),
+            class: CLASS_IN | CLASS_CACHE_FLUSH,
+            ttl: RECORD_TTL,
+            data: DnsRecordData::A(LOCAL_IP),
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_answer_legacy_query() {
+        let (mut iface, mut sockets, mut responder) = setup();
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 0), Ok(()));
+
+        let hostname = name("device.local");
+        let query = message(0x1234, false,
+                            &[DnsQuestion { name: &hostname, type_: DnsType::A }], &[]);
+        let src = IpEndpoint::new(REMOTE_IP.into(), 49152);
+        recv(&mut sockets, &responder, src, MDNS_IPV4_GROUP.into(), &query);
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 0), Ok(()));
+
+        let (endpoint, payload) = sent(&mut sockets, &responder).unwrap();
+        assert_eq!(endpoint, src);
+        let packet = DnsPacket::new_checked(&payload[..]).unwrap();
+        assert_eq!(packet.transaction_id(), 0x1234);
+        assert_eq!(packet.question_count(), 1);
+        assert_eq!(packet.answer_record_count(), 1);
+        let (rest, question) = DnsQuestion::parse(packet.payload()).unwrap();
+        assert_eq!(question, DnsQuestion { name: &hostname, type_: DnsType::A });
+        let (_, record) = DnsRecord::parse(rest).unwrap();
+        assert_eq!(record.class, CLASS_IN);
+        assert_eq!(record.ttl, LEGACY_RECORD_TTL);
+        assert_eq!(record.data, DnsRecordData::A(LOCAL_IP));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_ignore_other_queries() {
+        let (mut iface, mut sockets, mut responder) = setup();
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 0), Ok(()));
+
+        let other = name("other.local");
+        let hostname = name("device.local");
+        for question in &[DnsQuestion { name: &other, type_: DnsType::A },
+                          DnsQuestion { name: &hostname, type_: DnsType::Txt }] {
+            let query = message(0, false, &[*question], &[]);
+            recv(&mut sockets, &responder, IpEndpoint::new(REMOTE_IP.into(), MDNS_PORT),
+                 MDNS_IPV4_GROUP.into(), &query);
+        }
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 0), Ok(()));
+        assert!(sent(&mut sockets, &responder).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_resolve() {
+        let (mut iface, mut sockets, mut responder) = setup();
+        let handle = responder.query("printer.local", DnsType::A).unwrap();
+        assert_eq!(responder.next_poll(0), Some(0));
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 0), Ok(()));
+
+        let printer = name("printer.local");
+        let (endpoint, payload) = sent(&mut sockets, &responder).unwrap();
+        assert_eq!(endpoint, IpEndpoint::new(MDNS_IPV4_GROUP.into(), MDNS_PORT));
+        assert_eq!(payload, message(0, false,
+                                    &[DnsQuestion { name: &printer, type_: DnsType::A }], &[]));
+        assert_eq!(responder.query_result(handle), Err(Error::Exhausted));
+
+        let printer_upper = name("PRINTER.local");
+        let response = message(0, true, &[], &[DnsRecord {
+            name: &printer_upper,
+            class: CLASS_IN | CLASS_CACHE_FLUSH,
+            ttl: RECORD_TTL,
+            data: DnsRecordData::A(REMOTE_IP),
+        }]);
+        recv(&mut sockets, &responder, IpEndpoint::new(REMOTE_IP.into(), MDNS_PORT),
+             MDNS_IPV4_GROUP.into(), &response);
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 100), Ok(()));
+        assert_eq!(responder.query_result(handle),
+                   Ok([Some(REMOTE_IP.into()), None, None, None]));
+        assert_eq!(responder.next_poll(100), None);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_resolve_cached() {
+        let (mut iface, mut sockets, mut responder) = setup();
+        responder.set_cache(Some(DnsCache::new(vec![None; 2])));
+        let handle = responder.query("printer.local", DnsType::A).unwrap();
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 0), Ok(()));
+        assert!(sent(&mut sockets, &responder).is_some());
+
+        let printer = name("printer.local");
+        let response = message(0, true, &[], &[DnsRecord {
+            name: &printer,
+            class: CLASS_IN | CLASS_CACHE_FLUSH,
+            ttl: RECORD_TTL,
+            data: DnsRecordData::A(REMOTE_IP),
+        }]);
+        recv(&mut sockets, &responder, IpEndpoint::new(REMOTE_IP.into(), MDNS_PORT),
+             MDNS_IPV4_GROUP.into(), &response);
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 100), Ok(()));
+        assert!(responder.query_result(handle).is_ok());
+
+        // The address is cached until its TTL expires.
+        let handle = responder.query("Printer.local", DnsType::A).unwrap();
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 120_099), Ok(()));
+        assert!(sent(&mut sockets, &responder).is_none());
+        assert_eq!(responder.query_result(handle),
+                   Ok([Some(REMOTE_IP.into()), None, None, None]));
+
+        let handle = responder.query("printer.local", DnsType::A).unwrap();
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 120_100), Ok(()));
+        assert!(sent(&mut sockets, &responder).is_some());
+        assert_eq!(responder.query_result(handle), Err(Error::Exhausted));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_resolve_timeout() {
+        let (mut iface, mut sockets, mut responder) = setup();
+        let handle = responder.query("printer.local", DnsType::A).unwrap();
+
+        for &now in &[0, 1_000, 2_000] {
+            assert_eq!(responder.poll(&mut iface, &mut sockets, now), Ok(()));
+            assert!(sent(&mut sockets, &responder).is_some());
+            assert_eq!(responder.poll(&mut iface, &mut sockets, now + 500), Ok(()));
+            assert!(sent(&mut sockets, &responder).is_none());
+        }
+        assert_eq!(responder.query_result(handle), Err(Error::Exhausted));
+
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 3_000), Ok(()));
+        assert!(sent(&mut sockets, &responder).is_none());
+        assert_eq!(responder.query_result(handle), Err(Error::Unaddressable));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_register_service() {
+        let (mut iface, mut sockets, mut responder) = setup();
+        let handle = responder.register_service("Web", "_http._tcp", 80, &["path=/"]).unwrap();
+        assert_eq!(responder.service_state(handle), ServiceState::Probing);
+        assert_eq!(responder.next_poll(0), Some(0));
+
+        // The instance name is probed for three times.
+        for &now in &[0, 250, 500] {
+            assert_eq!(responder.poll(&mut iface, &mut sockets, now), Ok(()));
+            let (endpoint, payload) = sent(&mut sockets, &responder).unwrap();
+            assert_eq!(endpoint, IpEndpoint::new(MDNS_IPV4_GROUP.into(), MDNS_PORT));
+            let packet = DnsPacket::new_checked(&payload[..]).unwrap();
+            assert!(!packet.is_response());
+            assert_eq!(packet.question_count(), 1);
+            assert_eq!(packet.authority_record_count(), 2);
+            assert_eq!(responder.next_poll(now), Some(now + 250));
+        }
+
+        // Then announced twice.
+        for &now in &[750, 1_750] {
+            assert_eq!(responder.poll(&mut iface, &mut sockets, now), Ok(()));
+            assert_eq!(responder.service_state(handle),
+                       if now == 750 { ServiceState::Announcing } else { ServiceState::Registered });
+            let (_, payload) = sent(&mut sockets, &responder).unwrap();
+            let packet = DnsPacket::new_checked(&payload[..]).unwrap();
+            assert!(packet.is_response());
+            assert_eq!(packet.answer_record_count(), 4);
+        }
+        assert_eq!(responder.next_poll(1_750), None);
+
+        // Browsing for the service type is answered with the instance name.
+        let service_type = name("_http._tcp.local");
+        let query = message(0, false,
+                            &[DnsQuestion { name: &service_type, type_: DnsType::Ptr }], &[]);
+        recv(&mut sockets, &responder, IpEndpoint::new(REMOTE_IP.into(), MDNS_PORT),
+             MDNS_IPV4_GROUP.into(), &query);
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 2_000), Ok(()));
+        let (_, payload) = sent(&mut sockets, &responder).unwrap();
+        let packet = DnsPacket::new_checked(&payload[..]).unwrap();
+        assert_eq!(packet.answer_record_count(), 1);
+        let (_, record) = DnsRecord::parse(packet.payload()).unwrap();
+        assert_eq!(record, DnsRecord {
+            name: &service_type,
+            class: CLASS_IN,
+            ttl: SERVICE_RECORD_TTL,
+            data: DnsRecordData::Other(DnsType::Ptr, &name("Web._http._tcp.local")),
+        });
+
+        // The service is withdrawn when unregistered.
+        responder.unregister_service(handle);
+        assert_eq!(responder.next_poll(2_000), Some(2_000));
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 2_000), Ok(()));
+        let (_, payload) = sent(&mut sockets, &responder).unwrap();
+        let packet = DnsPacket::new_checked(&payload[..]).unwrap();
+        let (_, record) = DnsRecord::parse(packet.payload()).unwrap();
+        assert_eq!(record.ttl, 0);
+        assert_eq!(responder.next_poll(2_000), None);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_service_conflict() {
+        let (mut iface, mut sockets, mut responder) = setup();
+        let handle = responder.register_service("Web", "_http._tcp", 80, &[]).unwrap();
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 0), Ok(()));
+        assert!(sent(&mut sockets, &responder).is_some());
+
+        // Another host answers the probe.
+        let instance = name("Web._http._tcp.local");
+        let response = message(0, true, &[], &[DnsRecord {
+            name: &instance,
+            class: CLASS_IN | CLASS_CACHE_FLUSH,
+            ttl: SERVICE_RECORD_TTL,
+            data: DnsRecordData::Other(DnsType::Txt, b"\x00"),
+        }]);
+        recv(&mut sockets, &responder, IpEndpoint::new(REMOTE_IP.into(), MDNS_PORT),
+             MDNS_IPV4_GROUP.into(), &response);
+        assert_eq!(responder.poll(&mut iface, &mut sockets, 250), Ok(()));
+        assert_eq!(responder.service_state(handle), ServiceState::Conflict);
+        assert!(sent(&mut sockets, &responder).is_none());
+        assert_eq!(responder.next_poll(250), None);
+
+        responder.unregister_service(handle);
+        assert!(responder.register_service("Web (2)", "_http._tcp", 80, &[]).is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_query_errors() {
+        let (_iface, _sockets, mut responder) = setup();
+        assert_eq!(responder.query("printer.example.com", DnsType::A), Err(Error::Illegal));
+        assert_eq!(responder.query("local", DnsType::Cname), Err(Error::Illegal));
+        assert_eq!(responder.query("printer..local", DnsType::A), Err(Error::Illegal));
+
+        for _ in 0..MAX_QUERY_COUNT {
+            assert!(responder.query("printer.local", DnsType::A).is_ok());
+        }
+        assert_eq!(responder.query("printer.local", DnsType::A), Err(Error::Exhausted));
+    }
+}
@@ -0,0 +1,373 @@
+// Heads up! Before working on this file you should read RFC 6763,
+// which describes how services are described with PTR, SRV and TXT records (§ 4-6),
+// and RFC 6762 § 8, which describes how they are probed for and announced.
+
+use core::fmt;
+use byteorder::{ByteOrder, NetworkEndian};
+
+use {Error, Result};
+use wire::{DnsPacket, DnsQuestion, DnsRecord, DnsRecordData, DnsName, DnsType};
+use wire::dns::{encode_name, MAX_NAME_LEN, CLASS_IN};
+use super::Hostname;
+
+/// The maximum number of services a responder can register at once.
+pub const MAX_SERVICE_COUNT: usize = 4;
+
+/// The maximum length of the encoded TXT record of a service; RFC 6763 § 6.2
+/// recommends keeping it below 200 octets.
+pub const MAX_TXT_LEN: usize = 200;
+
+/// The query type asking for records of every type.
+const TYPE_ANY: u16 = 255;
+/// The encoded domain of the services.
+const LOCAL_DOMAIN: &[u8] = b"\x05local\x00";
+/// The encoded name enumerating the types of the services on the link. See RFC 6763 § 9.
+const SERVICES_NAME: &[u8] = b"\x09_services\x07_dns-sd\x04_udp\x05local\x00";
+
+/// The records describing a service, as a set of flags.
+pub const PTR_RECORD:  u8 = 0x01;
+pub const SRV_RECORD:  u8 = 0x02;
+pub const TXT_RECORD:  u8 = 0x04;
+pub const ENUM_RECORD: u8 = 0x08;
+pub const ALL_RECORDS: u8 = PTR_RECORD | SRV_RECORD | TXT_RECORD | ENUM_RECORD;
+
+/// A handle, identifying a service registered with an mDNS responder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ServiceHandle(pub(super) usize);
+
+/// The state of a service registered with an mDNS responder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceState {
+    /// The responder is checking that no other host uses the instance name.
+    Probing,
+    /// The responder is announcing the service, and answers queries for it.
+    Announcing,
+    /// The service is announced, and the responder answers queries for it.
+    Registered,
+    /// Another host uses the instance name; the service is neither announced nor
+    /// answered for, and should be registered again with another instance name.
+    Conflict,
+}
+
+/// The state of a service, with the timers of the messages sent for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Probed for `send_count` times, and to be probed again at `next_send`.
+    Probing { send_count: u8, next_send: u64 },
+    /// Announced `send_count` times, and to be announced again at `next_send`.
+    Announcing { send_count: u8, next_send: u64 },
+    Registered,
+    Conflict,
+    /// Unregistered, and to be withdrawn by sending its records with a zero TTL.
+    Withdrawing,
+}
+
+/// A service registered with an mDNS responder.
+#[derive(Clone, Copy)]
+pub struct Service {
+    /// The encoded instance name, `<instance>.<service type>.local`.
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    /// The offset of the service type in the instance name.
+    type_offset: usize,
+    port: u16,
+    txt: [u8; MAX_TXT_LEN],
+    txt_len: usize,
+    pub status: Status,
+}
+
+impl fmt::Debug for Service {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Service")
+         .field("name", &self.name())
+         .field("port", &self.port)
+         .field("status", &self.status)
+         .finish()
+    }
+}
+
+impl Service {
+    /// Create a service with the given instance name, which is a single label that may
+    /// contain any characters, service type, e.g. `_http._tcp`, port and TXT strings,
+    /// e.g. `path=/index.html`.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the instance name, the service type
+    /// or a TXT string is not valid, and `Err(Error::Exhausted)` if the instance name or
+    /// the TXT record are too long.
+    pub fn new(instance: &str, service_type: &str, port: u16,
+               txt: &[&str]) -> Result<Service> {
+        let mut service = Service {
+            name: [0; MAX_NAME_LEN],
+            name_len: 0,
+            type_offset: 0,
+            port: port,
+            txt: [0; MAX_TXT_LEN],
+            txt_len: 0,
+            status: Status::Probing { send_count: 0, next_send: 0 },
+        };
+
+        let instance = instance.as_bytes();
+        if instance.len() == 0 || instance.len() > 63 { return Err(Error::Illegal) }
+        service.name[0] = instance.len() as u8;
+        service.name[1..1 + instance.len()].copy_from_slice(instance);
+        service.type_offset = 1 + instance.len();
+
+        // See RFC 6763 § 7.
+        let type_len = encode_name(service_type, &mut service.name[service.type_offset..])
+            .map_err(|_| Error::Illegal)?;
+        {
+            let type_name = &service.name[service.type_offset..service.type_offset + type_len];
+            let mut labels = DnsName::new(type_name, type_name).labels();
+            match (labels.next(), labels.next(), labels.next()) {
+                (Some(Ok(name)), Some(Ok(protocol)), None)
+                        if name.len() > 1 && name.len() <= 16 && name[0] == b'_' &&
+                           (protocol.eq_ignore_ascii_case(b"_tcp") ||
+                            protocol.eq_ignore_ascii_case(b"_udp")) => (),
+                _ => return Err(Error::Illegal)
+            }
+        }
+        service.name_len = service.type_offset + type_len - 1;
+        if service.name_len + LOCAL_DOMAIN.len() > MAX_NAME_LEN { return Err(Error::Exhausted) }
+        service.name[service.name_len..service.name_len + LOCAL_DOMAIN.len()]
+            .copy_from_slice(LOCAL_DOMAIN);
+        service.name_len += LOCAL_DOMAIN.len();
+
+        // See RFC 6763 § 6.
+        for string in txt {
+            let string = string.as_bytes();
+            if string.len() == 0 || string.len() > 255 || string[0] == b'=' {
+                return Err(Error::Illegal)
+            }
+            if service.txt_len + 1 + string.len() > MAX_TXT_LEN { return Err(Error::Exhausted) }
+            service.txt[service.txt_len] = string.len() as u8;
+            service.txt[service.txt_len + 1..service.txt_len + 1 + string.len()]
+                .copy_from_slice(string);
+            service.txt_len += 1 + string.len();
+        }
+        // An empty TXT record is a single empty string.
+        if service.txt_len == 0 { service.txt_len = 1 }
+        Ok(service)
+    }
+
+    /// Return the encoded instance name.
+    pub fn name_bytes(&self) -> &[u8] {
+        &self.name[..self.name_len]
+    }
+
+    /// Return the instance name.
+    pub fn name(&self) -> DnsName {
+        DnsName::new(self.name_bytes(), self.name_bytes())
+    }
+
+    /// Return the encoded service type, `<service type>.local`.
+    pub fn type_bytes(&self) -> &[u8] {
+        &self.name[self.type_offset..self.name_len]
+    }
+
+    /// Return the state of the service.
+    pub fn state(&self) -> ServiceState {
+        match self.status {
+            Status::Probing { .. } => ServiceState::Probing,
+            Status::Announcing { .. } | Status::Withdrawing => ServiceState::Announcing,
+            Status::Registered => ServiceState::Registered,
+            Status::Conflict => ServiceState::Conflict,
+        }
+    }
+
+    /// Query whether queries for the service are answered.
+    pub fn is_answered(&self) -> bool {
+        match self.status {
+            Status::Announcing { .. } | Status::Registered => true,
+            _ => false
+        }
+    }
+
+    /// Find the questions about the service in a query, and return which records
+    /// they ask for.
+    pub fn question_records(&self, packet: &DnsPacket<&[u8]>) -> Result<u8> {
+        let message = packet.message();
+        let (name, type_name) = (self.name(), DnsName::new(self.type_bytes(), self.type_bytes()));
+        let services_name = DnsName::new(SERVICES_NAME, SERVICES_NAME);
+
+        let mut records = 0;
+        let mut questions = packet.payload();
+        for _ in 0..packet.question_count() {
+            let (next_questions, question) = DnsQuestion::parse(questions)?;
+            questions = next_questions;
+
+            let question_name = DnsName::new(message, question.name);
+            let any = question.type_ == DnsType::Unknown(TYPE_ANY);
+            if question_name.eq_ignore_case(&type_name) {
+                if any || question.type_ == DnsType::Ptr { records |= PTR_RECORD }
+            } else if question_name.eq_ignore_case(&name) {
+                if any || question.type_ == DnsType::Srv { records |= SRV_RECORD }
+                if any || question.type_ == DnsType::Txt { records |= TXT_RECORD }
+            } else if question_name.eq_ignore_case(&services_name) {
+                if any || question.type_ == DnsType::Ptr { records |= ENUM_RECORD }
+            }
+        }
+        Ok(records)
+    }
+
+    /// Query whether a record received from another host conflicts with the service,
+    /// i.e. has its instance name while it is being probed for, or describes another
+    /// service with that name afterwards. See RFC 6762 § 8.2 and § 9.
+    pub fn conflicts(&self, message: &[u8], record: &DnsRecord, hostname: &Hostname) -> bool {
+        if !DnsName::new(message, record.name).eq_ignore_case(&self.name()) {
+            return false
+        }
+
+        match (self.status, record.data) {
+            (Status::Probing { .. }, _) => true,
+            (Status::Announcing { .. }, DnsRecordData::Other(DnsType::Srv, data)) |
+            (Status::Registered, DnsRecordData::Other(DnsType::Srv, data)) => {
+                if data.len() < 7 { return false }
+                NetworkEndian::read_u16(&data[4..6]) != self.port ||
+                    !DnsName::new(message, &data[6..]).eq_ignore_case(&hostname.name())
+            }
+            _ => false
+        }
+    }
+
+    /// Emit the given records of the service with the given TTL, using `unique_class`
+    /// as the class of the SRV and TXT records, which are unique to this host, and return
+    /// the length of the records and their count. Records that do not fit into the buffer
+    /// are left out.
+    pub fn emit_records(&self, records: u8, hostname: &Hostname, unique_class: u16, ttl: u32,
+                        buffer: &mut [u8]) -> (usize, usize) {
+        let mut srv_data = [0; 6 + MAX_NAME_LEN];
+        NetworkEndian::write_u16(&mut srv_data[4..6], self.port);
+        srv_data[6..6 + hostname.as_bytes().len()].copy_from_slice(hostname.as_bytes());
+        let srv_data = &srv_data[..6 + hostname.as_bytes().len()];
+
+        let candidates = [
+            (PTR_RECORD, DnsRecord {
+                name: self.type_bytes(),
+                class: CLASS_IN,
+                ttl: ttl,
+                data: DnsRecordData::Other(DnsType::Ptr, self.name_bytes())
+            }),
+            (SRV_RECORD, DnsRecord {
+                name: self.name_bytes(),
+                class: unique_class,
+                ttl: ttl,
+                data: DnsRecordData::Other(DnsType::Srv, srv_data)
+            }),
+            (TXT_RECORD, DnsRecord {
+                name: self.name_bytes(),
+                class: unique_class,
+                ttl: ttl,
+                data: DnsRecordData::Other(DnsType::Txt, &self.txt[..self.txt_len])
+            }),
+            (ENUM_RECORD, DnsRecord {
+                name: SERVICES_NAME,
+                class: CLASS_IN,
+                ttl: ttl,
+                data: DnsRecordData::Other(DnsType::Ptr, self.type_bytes())
+            }),
+        ];
+
+        let mut length = 0;
+        let mut count = 0;
+        for &(flag, ref record) in candidates.iter() {
+            if records & flag == 0 { continue }
+            if record.buffer_len() > buffer.len() - length {
+                net_debug!("mDNS has no room for a record of {}", self.name());
+                continue
+            }
+            record.emit(&mut buffer[length..]);
+            length += record.buffer_len();
+            count += 1;
+        }
+        (length, count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use super::*;
+
+    fn query(questions: &[(&[u8], DnsType)]) -> Vec<u8> {
+        let mut bytes = vec![0; 12];
+        for &(name, type_) in questions {
+            let question = DnsQuestion { name: name, type_: type_ };
+            let start = bytes.len();
+            bytes.resize(start + question.buffer_len(), 0);
+            question.emit(&mut bytes[start..]);
+        }
+        DnsPacket::new(&mut bytes[..]).set_question_count(questions.len() as u16);
+        bytes
+    }
+
+    #[test]
+    fn test_new() {
+        let service = Service::new("Living Room", "_http._tcp", 80, &["path=/"]).unwrap();
+        assert_eq!(service.name_bytes(), &b"\x0bLiving Room\x05_http\x04_tcp\x05local\x00"[..]);
+        assert_eq!(service.type_bytes(), &b"\x05_http\x04_tcp\x05local\x00"[..]);
+        assert_eq!(&service.txt[..service.txt_len], b"\x06path=/");
+        assert_eq!(service.state(), ServiceState::Probing);
+        assert_eq!(&Service::new("a.b", "_ipp._tcp", 631, &[]).unwrap().txt[..1], b"\x00");
+
+        assert!(Service::new("", "_http._tcp", 80, &[]).is_err());
+        assert!(Service::new("x", "http._tcp", 80, &[]).is_err());
+        assert!(Service::new("x", "_http._sctp", 80, &[]).is_err());
+        assert!(Service::new("x", "_http._tcp.local", 80, &[]).is_err());
+        assert!(Service::new("x", "_http._tcp", 80, &["=value"]).is_err());
+        assert_eq!(Service::new("x", "_http._tcp", 80, &[&"x".repeat(200)]).err(),
+                   Some(Error::Exhausted));
+    }
+
+    #[test]
+    fn test_question_records() {
+        let service = Service::new("printer", "_ipp._tcp", 631, &[]).unwrap();
+        let bytes = query(&[(b"\x04_ipp\x04_TCP\x05local\x00", DnsType::Ptr),
+                            (b"\x07printer\x04_ipp\x04_tcp\x05local\x00", DnsType::Txt)]);
+        let packet = DnsPacket::new_checked(&bytes[..]).unwrap();
+        assert_eq!(service.question_records(&packet), Ok(PTR_RECORD | TXT_RECORD));
+
+        let bytes = query(&[(SERVICES_NAME, DnsType::Ptr),
+                            (b"\x07printer\x04_ipp\x04_tcp\x05local\x00",
+                             DnsType::Unknown(TYPE_ANY)),
+                            (b"\x05other\x04_ipp\x04_tcp\x05local\x00", DnsType::Srv)]);
+        let packet = DnsPacket::new_checked(&bytes[..]).unwrap();
+        assert_eq!(service.question_records(&packet),
+                   Ok(ENUM_RECORD | SRV_RECORD | TXT_RECORD));
+    }
+
+    #[test]
+    fn test_emit_records() {
+        let hostname = Hostname::new("device", LOCAL_DOMAIN).unwrap();
+        let service = Service::new("printer", "_ipp._tcp", 631, &["rp=ipp"]).unwrap();
+        let mut buffer = [0; 512];
+        let (length, count) = service.emit_records(PTR_RECORD | SRV_RECORD, &hostname,
+                                                   CLASS_IN, 4500, &mut buffer);
+        assert_eq!(count, 2);
+
+        let (rest, ptr) = DnsRecord::parse(&buffer[..length]).unwrap();
+        assert_eq!(ptr, DnsRecord {
+            name: service.type_bytes(),
+            class: CLASS_IN,
+            ttl: 4500,
+            data: DnsRecordData::Other(DnsType::Ptr, service.name_bytes())
+        });
+        let (rest, srv) = DnsRecord::parse(rest).unwrap();
+        assert_eq!(rest, &[]);
+        assert_eq!(srv.data, DnsRecordData::Other(DnsType::Srv,
+                                                  b"\x00\x00\x00\x00\x02\x77\
+                                                    \x06device\x05local\x00"));
+
+        // Any record of the instance name conflicts while it is being probed for,
+        // and only a record describing another service afterwards.
+        assert!(service.conflicts(&buffer[..length], &srv, &hostname));
+        let mut registered = service;
+        registered.status = Status::Registered;
+        assert!(!registered.conflicts(&buffer[..length], &srv, &hostname));
+        assert!(registered.conflicts(&buffer[..length], &srv,
+                                     &Hostname::new("other", LOCAL_DOMAIN).unwrap()));
+
+        // Records that do not fit are left out.
+        assert_eq!(service.emit_records(ALL_RECORDS, &hostname, CLASS_IN, 4500,
+                                        &mut buffer[..60]).1, 1);
+    }
+}
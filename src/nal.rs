@@ -0,0 +1,464 @@
+//! An implementation of the [embedded-nal] traits.
+//!
+//! The [Stack](struct.Stack.html) type implements the `TcpClientStack` and `UdpClientStack`
+//! traits over an interface and a socket set, so that drivers and applications written
+//! against _embedded-nal_ can use _smoltcp_ as their network stack.
+//!
+//! [embedded-nal]: https://docs.rs/embedded-nal
+
+use core::net::{IpAddr, SocketAddr};
+use managed::ManagedSlice;
+use embedded_nal::nb;
+#[cfg(feature = "socket-tcp")]
+use embedded_nal::{TcpClientStack, TcpError, TcpErrorKind};
+#[cfg(feature = "socket-udp")]
+use embedded_nal::UdpClientStack;
+
+use {Error, Result};
+use phy::Device;
+use iface::EthernetInterface;
+use wire::{IpAddress, IpEndpoint};
+#[cfg(feature = "proto-ipv4")]
+use wire::Ipv4Address;
+#[cfg(feature = "proto-ipv6")]
+use wire::Ipv6Address;
+use socket::{Socket, SocketSet, SocketHandle};
+#[cfg(feature = "socket-tcp")]
+use socket::{TcpSocket, TcpState};
+#[cfg(feature = "socket-udp")]
+use socket::UdpSocket;
+
+/// The first port of the dynamic port range, used as local ports of the sockets.
+const EPHEMERAL_PORT_START: u16 = 49152;
+
+/// A network stack implementing the _embedded-nal_ traits.
+///
+/// The stack owns an interface and a socket set, and a pool of TCP and UDP sockets
+/// in that set that it hands out. A socket that is handed out is bound to a local port
+/// chosen from the dynamic port range once it is connected, and is returned to the pool
+/// once it is closed; a TCP socket is only handed out again once its connection is
+/// closed completely.
+///
+/// Every operation polls the interface with the timestamp returned by the clock
+/// function, in milliseconds. An application that uses other sockets in the set as well
+/// has to call [poll](#method.poll) for them to make progress.
+pub struct Stack<'a, 'b: 'a, 'c: 'a + 'b, DeviceT, ClockT>
+        where DeviceT: for<'d> Device<'d>, ClockT: FnMut() -> u64 {
+    iface:     EthernetInterface<'b, 'c, DeviceT>,
+    sockets:   SocketSet<'a, 'b, 'c>,
+    pool:      ManagedSlice<'a, Option<SocketHandle>>,
+    clock:     ClockT,
+    next_port: u16,
+}
+
+impl<'a, 'b, 'c, DeviceT, ClockT> Stack<'a, 'b, 'c, DeviceT, ClockT>
+        where DeviceT: for<'d> Device<'d>, ClockT: FnMut() -> u64 {
+    /// Create a stack over the given interface and socket set, with the provided pool
+    /// storage and clock function.
+    ///
+    /// The pool is empty; sockets are added to it with [add_socket](#method.add_socket).
+    pub fn new<T>(iface: EthernetInterface<'b, 'c, DeviceT>, sockets: SocketSet<'a, 'b, 'c>,
+                  pool: T, clock: ClockT) -> Stack<'a, 'b, 'c, DeviceT, ClockT>
+            where T: Into<ManagedSlice<'a, Option<SocketHandle>>> {
+        Stack {
+            iface:     iface,
+            sockets:   sockets,
+            pool:      pool.into(),
+            clock:     clock,
+            next_port: EPHEMERAL_PORT_START,
+        }
+    }
+
+    /// Return the interface.
+    pub fn interface(&mut self) -> &mut EthernetInterface<'b, 'c, DeviceT> {
+        &mut self.iface
+    }
+
+    /// Return the socket set.
+    pub fn sockets(&mut self) -> &mut SocketSet<'a, 'b, 'c> {
+        &mut self.sockets
+    }
+
+    /// Add a TCP or UDP socket in the set to the pool of sockets handed out by the stack.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the socket is in the pool already,
+    /// and `Err(Error::Exhausted)` if the pool storage is fixed-size (not a `Vec`)
+    /// and is full.
+    pub fn add_socket(&mut self, handle: SocketHandle) -> Result<()> {
+        if self.pool.iter().any(|slot| *slot == Some(handle)) {
+            return Err(Error::Illegal)
+        }
+        self.release(handle)
+    }
+
+    /// Poll the interface, as [EthernetInterface::poll] does.
+    ///
+    /// [EthernetInterface::poll]: ../iface/struct.EthernetInterface.html#method.poll
+    pub fn poll(&mut self) -> Result<bool> {
+        let timestamp = (self.clock)();
+        self.iface.poll(&mut self.sockets, timestamp)
+    }
+
+    /// Poll the interface, logging rather than returning the errors; these concern
+    /// individual packets, and not the operation in progress.
+    fn progress(&mut self) {
+        if let Err(err) = self.poll() {
+            net_debug!("poll error: {}", err);
+        }
+    }
+
+    /// Take a socket for which the given function returns true out of the pool.
+    fn acquire<F>(&mut self, f: F) -> Result<SocketHandle>
+            where F: Fn(&Socket) -> bool {
+        let sockets = &self.sockets;
+        let slot = self.pool.iter_mut().find(|slot| match **slot {
+            Some(handle) => sockets.iter().any(|socket| socket.handle() == handle && f(socket)),
+            None => false
+        });
+        match slot.and_then(|slot| slot.take()) {
+            Some(handle) => Ok(handle),
+            None => Err(Error::Exhausted)
+        }
+    }
+
+    /// Return a socket to the pool.
+    fn release(&mut self, handle: SocketHandle) -> Result<()> {
+        let index = match self.pool.iter().position(|slot| slot.is_none()) {
+            Some(index) => index,
+            None => match self.pool {
+                ManagedSlice::Borrowed(_) => return Err(Error::Exhausted),
+                #[cfg(any(feature = "std", feature = "alloc"))]
+                ManagedSlice::Owned(ref mut pool) => {
+                    pool.push(None);
+                    pool.len() - 1
+                }
+            }
+        };
+
+        self.pool[index] = Some(handle);
+        Ok(())
+    }
+
+    fn local_port(&mut self) -> u16 {
+        let port = self.next_port;
+        self.next_port = port.checked_add(1).unwrap_or(EPHEMERAL_PORT_START);
+        port
+    }
+}
+
+/// A TCP socket handed out by a [Stack](struct.Stack.html).
+#[cfg(feature = "socket-tcp")]
+#[derive(Debug, PartialEq, Eq)]
+pub struct TcpHandle {
+    handle:     SocketHandle,
+    connecting: bool,
+}
+
+#[cfg(feature = "socket-tcp")]
+impl TcpHandle {
+    /// Return the handle of the socket in the socket set.
+    #[inline]
+    pub fn handle(&self) -> SocketHandle {
+        self.handle
+    }
+}
+
+#[cfg(feature = "socket-tcp")]
+impl TcpError for Error {
+    fn kind(&self) -> TcpErrorKind {
+        match self {
+            &Error::Illegal => TcpErrorKind::PipeClosed,
+            _ => TcpErrorKind::Other
+        }
+    }
+}
+
+/// The TCP client stack.
+///
+/// Connecting returns `Error::Illegal` if the connection is refused, reset or times out,
+/// and sending returns it once the transmit half of the connection is closed; the kind
+/// of this error is `TcpErrorKind::PipeClosed`. Receiving returns zero octets once
+/// the remote end closes the connection.
+#[cfg(feature = "socket-tcp")]
+impl<'a, 'b, 'c, DeviceT, ClockT> TcpClientStack for Stack<'a, 'b, 'c, DeviceT, ClockT>
+        where DeviceT: for<'d> Device<'d>, ClockT: FnMut() -> u64 {
+    type TcpSocket = TcpHandle;
+    type Error = Error;
+
+    fn socket(&mut self) -> Result<TcpHandle> {
+        let handle = self.acquire(|socket| match socket {
+            &Socket::Tcp(ref socket) => !socket.is_open(),
+            _ => false
+        })?;
+        Ok(TcpHandle { handle: handle, connecting: false })
+    }
+
+    fn connect(&mut self, socket: &mut TcpHandle,
+               remote: SocketAddr) -> nb::Result<(), Error> {
+        self.progress();
+        if !socket.connecting {
+            let remote_endpoint = endpoint(remote)?;
+            let local_port = self.local_port();
+            self.sockets.get::<TcpSocket>(socket.handle)
+                .connect(remote_endpoint, local_port)?;
+            socket.connecting = true;
+            self.progress();
+        }
+
+        match self.sockets.get::<TcpSocket>(socket.handle).state() {
+            TcpState::SynSent | TcpState::SynReceived => Err(nb::Error::WouldBlock),
+            TcpState::Closed => {
+                socket.connecting = false;
+                Err(nb::Error::Other(Error::Illegal))
+            }
+            _ => Ok(())
+        }
+    }
+
+    fn send(&mut self, socket: &mut TcpHandle, buffer: &[u8]) -> nb::Result<usize, Error> {
+        self.progress();
+        let size = {
+            let mut socket = self.sockets.get::<TcpSocket>(socket.handle);
+            match socket.state() {
+                TcpState::SynSent | TcpState::SynReceived => 0,
+                _ => socket.send_slice(buffer)?
+            }
+        };
+        if size == 0 && !buffer.is_empty() { return Err(nb::Error::WouldBlock) }
+
+        self.progress();
+        Ok(size)
+    }
+
+    fn receive(&mut self, socket: &mut TcpHandle,
+               buffer: &mut [u8]) -> nb::Result<usize, Error> {
+        self.progress();
+        let mut socket = self.sockets.get::<TcpSocket>(socket.handle);
+        if socket.can_recv() { return Ok(socket.recv_slice(buffer)?) }
+        match socket.state() {
+            TcpState::SynSent | TcpState::SynReceived | TcpState::Established |
+            TcpState::FinWait1 | TcpState::FinWait2 => Err(nb::Error::WouldBlock),
+            _ => Ok(0)
+        }
+    }
+
+    fn close(&mut self, socket: TcpHandle) -> Result<()> {
+        self.sockets.get::<TcpSocket>(socket.handle).close();
+        self.progress();
+        self.release(socket.handle)
+    }
+}
+
+/// The UDP client stack.
+///
+/// Sending returns `Error::Truncated` if the packet does not fit into a buffer of
+/// the socket, and receiving returns `Error::Unaddressable` once if an error was
+/// reported for the connection, e.g. with an ICMP port unreachable message.
+#[cfg(feature = "socket-udp")]
+impl<'a, 'b, 'c, DeviceT, ClockT> UdpClientStack for Stack<'a, 'b, 'c, DeviceT, ClockT>
+        where DeviceT: for<'d> Device<'d>, ClockT: FnMut() -> u64 {
+    type UdpSocket = SocketHandle;
+    type Error = Error;
+
+    fn socket(&mut self) -> Result<SocketHandle> {
+        self.acquire(|socket| match socket {
+            &Socket::Udp(ref socket) => !socket.is_open(),
+            _ => false
+        })
+    }
+
+    fn connect(&mut self, handle: &mut SocketHandle, remote: SocketAddr) -> Result<()> {
+        let remote_endpoint = endpoint(remote)?;
+        if !self.sockets.get::<UdpSocket>(*handle).is_open() {
+            let local_port = self.local_port();
+            self.sockets.get::<UdpSocket>(*handle).bind(local_port)?;
+        }
+        self.sockets.get::<UdpSocket>(*handle).connect(remote_endpoint)
+    }
+
+    fn send(&mut self, handle: &mut SocketHandle, buffer: &[u8]) -> nb::Result<(), Error> {
+        self.progress();
+        match self.sockets.get::<UdpSocket>(*handle).send_slice_connected(buffer) {
+            Ok(()) => (),
+            Err(Error::Exhausted) => return Err(nb::Error::WouldBlock),
+            Err(err) => return Err(nb::Error::Other(err))
+        }
+
+        self.progress();
+        Ok(())
+    }
+
+    fn receive(&mut self, handle: &mut SocketHandle,
+               buffer: &mut [u8]) -> nb::Result<(usize, SocketAddr), Error> {
+        self.progress();
+        match self.sockets.get::<UdpSocket>(*handle).recv_slice(buffer) {
+            Ok((size, endpoint)) => Ok((size, socket_addr(endpoint))),
+            Err(Error::Exhausted) => Err(nb::Error::WouldBlock),
+            Err(err) => Err(nb::Error::Other(err))
+        }
+    }
+
+    fn close(&mut self, handle: SocketHandle) -> Result<()> {
+        self.sockets.get::<UdpSocket>(handle).close();
+        self.release(handle)
+    }
+}
+
+/// Convert a socket address to an endpoint.
+///
+/// This function returns `Err(Error::Unaddressable)` if the address family
+/// of the address is not supported.
+fn endpoint(addr: SocketAddr) -> Result<IpEndpoint> {
+    let address = match addr.ip() {
+        #[cfg(feature = "proto-ipv4")]
+        IpAddr::V4(address) => IpAddress::Ipv4(Ipv4Address(address.octets())),
+        #[cfg(feature = "proto-ipv6")]
+        IpAddr::V6(address) => IpAddress::Ipv6(Ipv6Address(address.octets())),
+        #[allow(unreachable_patterns)]
+        _ => return Err(Error::Unaddressable)
+    };
+    Ok(IpEndpoint::new(address, addr.port()))
+}
+
+/// Convert an endpoint of a received packet to a socket address.
+#[cfg(feature = "socket-udp")]
+fn socket_addr(endpoint: IpEndpoint) -> SocketAddr {
+    let address = match endpoint.addr {
+        #[cfg(feature = "proto-ipv4")]
+        IpAddress::Ipv4(address) => IpAddr::from(address.0),
+        #[cfg(feature = "proto-ipv6")]
+        IpAddress::Ipv6(address) => IpAddr::from(address.0),
+        _ => IpAddr::from([0; 4])
+    };
+    SocketAddr::new(address, endpoint.port)
+}
+
+#[cfg(all(test, feature = "proto-ipv4"))]
+mod test {
+    use std::collections::BTreeMap;
+    use embedded_nal::nb::block;
+    use phy::Loopback;
+    use iface::{EthernetInterfaceBuilder, NeighborCache};
+    use wire::{EthernetAddress, IpCidr};
+    use super::*;
+
+    const LOCAL_ADDR: Ipv4Address = Ipv4Address([127, 0, 0, 1]);
+    const SERVER_PORT: u16 = 1234;
+
+    fn server_addr() -> SocketAddr {
+        SocketAddr::new(IpAddr::from(LOCAL_ADDR.0), SERVER_PORT)
+    }
+
+    fn stack() -> Stack<'static, 'static, 'static, Loopback, impl FnMut() -> u64> {
+        let iface = EthernetInterfaceBuilder::new(Loopback::new())
+                .ethernet_addr(EthernetAddress::default())
+                .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+                .ip_addrs(vec![IpCidr::new(LOCAL_ADDR.into(), 8)])
+                .finalize();
+        let mut now = 0;
+        Stack::new(iface, SocketSet::new(vec![]), vec![], move || { now += 1; now })
+    }
+
+    #[cfg(feature = "socket-tcp")]
+    fn tcp_socket() -> TcpSocket<'static> {
+        use socket::TcpSocketBuffer;
+        TcpSocket::new(TcpSocketBuffer::new(vec![0; 64]), TcpSocketBuffer::new(vec![0; 64]))
+    }
+
+    #[cfg(feature = "socket-udp")]
+    fn udp_socket() -> UdpSocket<'static, 'static> {
+        use socket::{UdpSocketBuffer, UdpPacketBuffer};
+        UdpSocket::new(UdpSocketBuffer::new(vec![UdpPacketBuffer::new(vec![0; 64])]),
+                       UdpSocketBuffer::new(vec![UdpPacketBuffer::new(vec![0; 64])]))
+    }
+
+    #[test]
+    #[cfg(feature = "socket-tcp")]
+    fn test_tcp() {
+        let mut stack = stack();
+        let server = stack.sockets().add(tcp_socket());
+        stack.sockets().get::<TcpSocket>(server).listen(SERVER_PORT).unwrap();
+        let client = stack.sockets().add(tcp_socket());
+        stack.add_socket(client).unwrap();
+        assert_eq!(stack.add_socket(client), Err(Error::Illegal));
+
+        let mut socket = TcpClientStack::socket(&mut stack).unwrap();
+        assert_eq!(socket.handle(), client);
+        assert_eq!(TcpClientStack::socket(&mut stack), Err(Error::Exhausted));
+        block!(TcpClientStack::connect(&mut stack, &mut socket, server_addr())).unwrap();
+
+        assert_eq!(block!(TcpClientStack::send(&mut stack, &mut socket, b"abc")), Ok(3));
+        {
+            let mut server = stack.sockets().get::<TcpSocket>(server);
+            let mut buffer = [0; 16];
+            assert_eq!(server.recv_slice(&mut buffer), Ok(3));
+            assert_eq!(&buffer[..3], b"abc");
+            assert_eq!(server.send_slice(b"defgh"), Ok(5));
+            server.close();
+        }
+
+        let mut buffer = [0; 16];
+        assert_eq!(block!(TcpClientStack::receive(&mut stack, &mut socket, &mut buffer)),
+                   Ok(5));
+        assert_eq!(&buffer[..5], b"defgh");
+        assert_eq!(block!(TcpClientStack::receive(&mut stack, &mut socket, &mut buffer)),
+                   Ok(0));
+
+        // The socket is handed out again once the connection is closed.
+        assert_eq!(TcpClientStack::close(&mut stack, socket), Ok(()));
+        stack.poll().unwrap();
+        assert!(TcpClientStack::socket(&mut stack).is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "socket-tcp")]
+    fn test_tcp_refused() {
+        let mut stack = stack();
+        let client = stack.sockets().add(tcp_socket());
+        stack.add_socket(client).unwrap();
+
+        let mut socket = TcpClientStack::socket(&mut stack).unwrap();
+        let error = block!(TcpClientStack::connect(&mut stack, &mut socket, server_addr()))
+            .unwrap_err();
+        assert_eq!(error, Error::Illegal);
+        assert_eq!(error.kind(), TcpErrorKind::PipeClosed);
+        assert_eq!(TcpClientStack::send(&mut stack, &mut socket, b"abc"),
+                   Err(nb::Error::Other(Error::Illegal)));
+    }
+
+    #[test]
+    #[cfg(feature = "socket-udp")]
+    fn test_udp() {
+        let mut stack = stack();
+        let server = stack.sockets().add(udp_socket());
+        stack.sockets().get::<UdpSocket>(server).bind(SERVER_PORT).unwrap();
+        let client = stack.sockets().add(udp_socket());
+        stack.add_socket(client).unwrap();
+
+        let mut socket = UdpClientStack::socket(&mut stack).unwrap();
+        assert_eq!(socket, client);
+        UdpClientStack::connect(&mut stack, &mut socket, server_addr()).unwrap();
+        block!(UdpClientStack::send(&mut stack, &mut socket, b"abc")).unwrap();
+        // The packet is sent once the address of the server is resolved.
+        while !stack.sockets().get::<UdpSocket>(server).can_recv() {
+            stack.poll().unwrap();
+        }
+        {
+            let mut server = stack.sockets().get::<UdpSocket>(server);
+            let mut buffer = [0; 16];
+            let (size, endpoint) = server.recv_slice(&mut buffer).unwrap();
+            assert_eq!(&buffer[..size], b"abc");
+            assert_eq!(endpoint.port, EPHEMERAL_PORT_START);
+            server.send_slice(b"defgh", endpoint).unwrap();
+        }
+
+        let mut buffer = [0; 16];
+        assert_eq!(block!(UdpClientStack::receive(&mut stack, &mut socket, &mut buffer)),
+                   Ok((5, server_addr())));
+        assert_eq!(&buffer[..5], b"defgh");
+
+        assert_eq!(UdpClientStack::close(&mut stack, socket), Ok(()));
+        assert!(!stack.sockets().get::<UdpSocket>(client).is_open());
+        assert_eq!(UdpClientStack::socket(&mut stack), Ok(client));
+    }
+}
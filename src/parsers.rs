@@ -264,6 +264,24 @@ impl<'a> Parser<'a> {
         Ok(Ipv6Address::from_parts(&addr))
     }
 
+    /// Accept an IPv6 address, optionally followed by a zone index, e.g. `fe80::1%2`.
+    /// See RFC 4007 § 11.
+    ///
+    /// Only link-local addresses may have a zone index. An interface is attached to
+    /// a single link, so the index is not kept.
+    #[cfg(feature = "proto-ipv6")]
+    fn accept_ipv6_scoped(&mut self) -> Result<Ipv6Address> {
+        let ip = self.accept_ipv6(true)?;
+        if self.lookahead_char(b'%') {
+            self.accept_char(b'%')?;
+            self.accept_number(9, 1_000_000_000, false)?;
+            if !ip.is_link_local() {
+                return Err(())
+            }
+        }
+        Ok(ip)
+    }
+
     fn accept_ipv4_octets(&mut self) -> Result<[u8; 4]> {
         let mut octets = [0u8; 4];
         for n in 0..4 {
@@ -282,6 +300,10 @@ impl<'a> Parser<'a> {
     }
 
     fn accept_ip(&mut self) -> Result<IpAddress> {
+        if self.try(|p| p.accept_char(b'*')).is_some() {
+            return Ok(IpAddress::Unspecified)
+        }
+
         #[cfg(feature = "proto-ipv4")]
         match self.try(|p| p.accept_ipv4()) {
             Some(ipv4) => return Ok(IpAddress::Ipv4(ipv4)),
@@ -289,7 +311,7 @@ impl<'a> Parser<'a> {
         }
 
         #[cfg(feature = "proto-ipv6")]
-        match self.try(|p| p.accept_ipv6(false)) {
+        match self.try(|p| p.accept_ipv6_scoped()) {
             Some(ipv6) => return Ok(IpAddress::Ipv6(ipv6)),
             None => ()
         }
@@ -297,6 +319,19 @@ impl<'a> Parser<'a> {
         Err(())
     }
 
+    fn accept_unspecified_endpoint(&mut self) -> Result<IpEndpoint> {
+        self.accept_char(b'*')?;
+
+        let port = if self.accept_eof().is_ok() {
+            0
+        } else {
+            self.accept_char(b':')?;
+            self.accept_number(5, 65536, false)?
+        };
+
+        Ok(IpEndpoint { addr: IpAddress::Unspecified, port: port as u16 })
+    }
+
     #[cfg(feature = "proto-ipv4")]
     fn accept_ipv4_endpoint(&mut self) -> Result<IpEndpoint> {
         let ip = self.accept_ipv4()?;
@@ -305,7 +340,7 @@ impl<'a> Parser<'a> {
             0
         } else {
             self.accept_char(b':')?;
-            self.accept_number(5, 65535, false)?
+            self.accept_number(5, 65536, false)?
         };
 
         Ok(IpEndpoint { addr: IpAddress::Ipv4(ip), port: port as u16 })
@@ -315,19 +350,24 @@ impl<'a> Parser<'a> {
     fn accept_ipv6_endpoint(&mut self) -> Result<IpEndpoint> {
         if self.lookahead_char(b'[') {
             self.accept_char(b'[')?;
-            let ip = self.accept_ipv6(false)?;
+            let ip = self.accept_ipv6_scoped()?;
             self.accept_char(b']')?;
             self.accept_char(b':')?;
-            let port = self.accept_number(5, 65535, false)?;
+            let port = self.accept_number(5, 65536, false)?;
 
             Ok(IpEndpoint { addr: IpAddress::Ipv6(ip), port: port as u16 })
         } else {
-            let ip = self.accept_ipv6(false)?;
+            let ip = self.accept_ipv6_scoped()?;
             Ok(IpEndpoint { addr: IpAddress::Ipv6(ip), port: 0 })
         }
     }
 
     fn accept_ip_endpoint(&mut self) -> Result<IpEndpoint> {
+        match self.try(|p| p.accept_unspecified_endpoint()) {
+            Some(endpoint) => return Ok(endpoint),
+            None => ()
+        }
+
         #[cfg(feature = "proto-ipv4")]
         match self.try(|p| p.accept_ipv4_endpoint()) {
             Some(ipv4) => return Ok(ipv4),
@@ -367,16 +407,17 @@ impl FromStr for Ipv4Address {
 impl FromStr for Ipv6Address {
     type Err = ();
 
-    /// Parse a string representation of an IPv6 address.
+    /// Parse a string representation of an IPv6 address, with an optional zone index
+    /// if it is link-local.
     fn from_str(s: &str) -> Result<Ipv6Address> {
-        Parser::new(s).until_eof(|p| p.accept_ipv6(false))
+        Parser::new(s).until_eof(|p| p.accept_ipv6_scoped())
     }
 }
 
 impl FromStr for IpAddress {
     type Err = ();
 
-    /// Parse a string representation of an IP address.
+    /// Parse a string representation of an IP address, or `*` for the unspecified address.
     fn from_str(s: &str) -> Result<IpAddress> {
         Parser::new(s).until_eof(|p| p.accept_ip())
     }
@@ -405,7 +446,7 @@ impl FromStr for Ipv6Cidr {
     fn from_str(s: &str) -> Result<Ipv6Cidr> {
         // https://tools.ietf.org/html/rfc4291#section-2.3
         Parser::new(s).until_eof(|p| {
-            let ip = p.accept_ipv6(true)?;
+            let ip = p.accept_ipv6_scoped()?;
             p.accept_char(b'/')?;
             let prefix_len = p.accept_number(3, 129, false)? as u8;
             Ok(Ipv6Cidr::new(ip, prefix_len))
@@ -437,6 +478,10 @@ impl FromStr for IpCidr {
 impl FromStr for IpEndpoint {
     type Err = ();
 
+    /// Parse a string representation of an IP endpoint, i.e. an address with an optional
+    /// port, which is written after a colon, and requires an IPv6 address to be bracketed,
+    /// e.g. `192.168.1.1:80` or `[fe80::1%2]:80`. The address may be `*` for an endpoint
+    /// with an unspecified address.
     fn from_str(s: &str) -> Result<IpEndpoint> {
         Parser::new(s).until_eof(|p| Ok(p.accept_ip_endpoint()?))
     }
@@ -644,6 +689,20 @@ mod test {
             IpEndpoint::from_str("127.0.0.1:12345"),
             Ok(IpEndpoint { addr: IpAddress::v4(127, 0, 0, 1), port: 12345 })
         );
+        assert_eq!(
+            IpEndpoint::from_str("127.0.0.1:65535"),
+            Ok(IpEndpoint { addr: IpAddress::v4(127, 0, 0, 1), port: 65535 })
+        );
+        assert_eq!(IpEndpoint::from_str("127.0.0.1:65536"), Err(()));
+        assert_eq!(IpEndpoint::from_str("*:80"), Ok(IpEndpoint::from(80)));
+
+        for endpoint in &[IpEndpoint::new(IpAddress::v4(192, 168, 1, 1), 80),
+                          IpEndpoint::new(IpAddress::v4(10, 0, 0, 1), 0),
+                          IpEndpoint::from(5353)] {
+            assert_eq!(IpEndpoint::from_str(&format!("{}", endpoint)), Ok(*endpoint));
+        }
+        assert_eq!(IpAddress::from_str(&format!("{}", IpAddress::Unspecified)),
+                   Ok(IpAddress::Unspecified));
     }
 
     #[test]
@@ -659,5 +718,37 @@ mod test {
             IpEndpoint::from_str("[fe80::1]:12345"),
             Ok(IpEndpoint { addr: IpAddress::v6(0xfe80, 0, 0, 0, 0, 0, 0, 1), port: 12345 })
         );
+        assert_eq!(
+            IpEndpoint::from_str("[fe80::1%2]:12345"),
+            Ok(IpEndpoint { addr: IpAddress::v6(0xfe80, 0, 0, 0, 0, 0, 0, 1), port: 12345 })
+        );
+        assert_eq!(
+            IpEndpoint::from_str("[::]:80"),
+            Ok(IpEndpoint { addr: IpAddress::v6(0, 0, 0, 0, 0, 0, 0, 0), port: 80 })
+        );
+        assert_eq!(IpEndpoint::from_str("[fe80::1]"), Err(()));
+        assert_eq!(IpEndpoint::from_str("[2001:db8::1%2]:80"), Err(()));
+
+        for endpoint in &[IpEndpoint::new(IpAddress::v6(0xfe80, 0, 0, 0, 0, 0, 0, 1), 80),
+                          IpEndpoint::new(IpAddress::v6(0, 0, 0, 0, 0, 0, 0, 0), 0),
+                          IpEndpoint::new(IpAddress::v6(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1), 443)] {
+            assert_eq!(format!("{}", endpoint).chars().next(), Some('['));
+            assert_eq!(IpEndpoint::from_str(&format!("{}", endpoint)), Ok(*endpoint));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv6")]
+    fn test_scope_id() {
+        let link_local = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        assert_eq!(Ipv6Address::from_str("fe80::1%2"), Ok(link_local));
+        assert_eq!(Ipv6Address::from_str("fe80::%2"),
+                   Ok(Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 0)));
+        assert_eq!(IpAddress::from_str("fe80::1%2"), Ok(IpAddress::Ipv6(link_local)));
+        assert_eq!(Ipv6Cidr::from_str("fe80::1%2/64"), Ok(Ipv6Cidr::new(link_local, 64)));
+        // Only link-local addresses are scoped to a zone.
+        assert_eq!(Ipv6Address::from_str("2001:db8::1%2"), Err(()));
+        assert_eq!(Ipv6Address::from_str("fe80::1%"), Err(()));
+        assert_eq!(Ipv6Address::from_str("fe80::1%eth0"), Err(()));
     }
 }
@@ -0,0 +1,868 @@
+#[cfg(feature = "std")]
+use std::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::Vec;
+use core::cmp;
+use core::cell::RefCell;
+
+use {Error, Result};
+use wire::{EthernetAddress, EthernetFrame};
+#[cfg(any(feature = "proto-igmp", feature = "proto-ipv6"))]
+use wire::{EthernetProtocol, IpProtocol};
+#[cfg(feature = "proto-igmp")]
+use wire::{Ipv4Packet, IgmpPacket, IgmpMessage, IgmpRepr};
+#[cfg(feature = "proto-ipv6")]
+use wire::{Ipv6Packet, Ipv6HopByHopHeader, Icmpv6Packet, Icmpv6Message, MldRepr, MldRecordType};
+#[cfg(feature = "proto-igmp")]
+use phy::ChecksumCapabilities;
+use phy::{self, DeviceCapabilities, Device, Medium, RxToken as _RxToken, TxToken as _TxToken};
+
+/// The default number of addresses in the learning table.
+const DEFAULT_TABLE_SIZE: usize = 64;
+
+/// The time after which a learned address is forgotten, in milliseconds; the default
+/// ageing time of IEEE 802.1D.
+const AGEING_TIME: u64 = 300_000;
+
+/// The number of multicast groups the bridge learns the listeners of at most.
+#[cfg(any(feature = "proto-igmp", feature = "proto-ipv6"))]
+const GROUP_TABLE_SIZE: usize = 64;
+
+/// The time after which a listener of a group that was not reported again is forgotten,
+/// in milliseconds; the default Group Membership Interval of IGMPv2 (RFC 2236 § 8.4)
+/// and the Multicast Address Listening Interval of MLDv2 (RFC 3810 § 9.4).
+const MEMBERSHIP_TIME: u64 = 260_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Port {
+    A,
+    B
+}
+
+impl Port {
+    fn other(self) -> Port {
+        match self {
+            Port::A => Port::B,
+            Port::B => Port::A
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    addr:    EthernetAddress,
+    port:    Port,
+    seen_at: u64
+}
+
+/// A port on which there are listeners of a multicast group.
+#[derive(Debug, Clone, Copy)]
+struct Membership {
+    group:   EthernetAddress,
+    port:    Port,
+    seen_at: u64
+}
+
+#[derive(Debug)]
+struct State {
+    local_addr: EthernetAddress,
+    table:      Vec<Entry>,
+    table_size: usize,
+    timestamp:  u64,
+    snooping:   bool,
+    groups:     Vec<Membership>,
+    /// The time a multicast router, or a host whose reports are not understood,
+    /// was last seen on port A and B respectively; all multicast is forwarded to
+    /// such a port.
+    flood_seen_at: [Option<u64>; 2],
+}
+
+impl State {
+    fn learn(&mut self, addr: EthernetAddress, port: Port, timestamp: u64) {
+        if let Some(entry) = self.table.iter_mut().find(|entry| entry.addr == addr) {
+            if entry.port != port {
+                net_trace!("bridge: {} moved to port {:?}", addr, port);
+            }
+            entry.port = port;
+            entry.seen_at = timestamp;
+            return
+        }
+
+        if self.table.len() >= self.table_size {
+            // Forget the address seen the longest time ago.
+            let oldest = self.table.iter().enumerate()
+                .min_by_key(|&(_, entry)| entry.seen_at)
+                .map(|(index, _)| index);
+            match oldest {
+                Some(index) => { self.table.swap_remove(index); }
+                None => return
+            }
+        }
+        self.table.push(Entry { addr, port, seen_at: timestamp })
+    }
+
+    fn lookup(&self, addr: &EthernetAddress, timestamp: u64) -> Option<Port> {
+        self.table.iter()
+            .find(|entry| entry.addr == *addr &&
+                          timestamp.saturating_sub(entry.seen_at) < AGEING_TIME)
+            .map(|entry| entry.port)
+    }
+
+    #[cfg(any(feature = "proto-igmp", feature = "proto-ipv6"))]
+    /// Learn that there are listeners of the group with the given hardware address
+    /// on `port`.
+    fn join(&mut self, group: EthernetAddress, port: Port, timestamp: u64) {
+        if let Some(membership) = self.groups.iter_mut()
+                .find(|membership| membership.group == group && membership.port == port) {
+            membership.seen_at = timestamp;
+            return
+        }
+
+        net_trace!("bridge: listeners of {} on port {:?}", group, port);
+        if self.groups.len() >= GROUP_TABLE_SIZE {
+            let oldest = self.groups.iter().enumerate()
+                .min_by_key(|&(_, membership)| membership.seen_at)
+                .map(|(index, _)| index);
+            if let Some(index) = oldest {
+                self.groups.swap_remove(index);
+            }
+        }
+        self.groups.push(Membership { group, port, seen_at: timestamp })
+    }
+
+    #[cfg(any(feature = "proto-igmp", feature = "proto-ipv6"))]
+    /// Forget the listeners of the group with the given hardware address on `port`.
+    fn leave(&mut self, group: EthernetAddress, port: Port) {
+        net_trace!("bridge: no more listeners of {} on port {:?}", group, port);
+        self.groups.retain(|membership| !(membership.group == group && membership.port == port))
+    }
+
+    #[cfg(any(feature = "proto-igmp", feature = "proto-ipv6"))]
+    /// Forward all multicast frames to `port` for a while.
+    fn flood(&mut self, port: Port, timestamp: u64) {
+        self.flood_seen_at[port as usize] = Some(timestamp)
+    }
+
+    /// Return whether a frame sent to the given multicast address has to go through `port`.
+    fn forwards_multicast(&self, dst_addr: &EthernetAddress, port: Port, timestamp: u64) -> bool {
+        if !self.snooping || !is_snooped(dst_addr) { return true }
+        if let Some(seen_at) = self.flood_seen_at[port as usize] {
+            if timestamp.saturating_sub(seen_at) < MEMBERSHIP_TIME { return true }
+        }
+        self.groups.iter().any(|membership| {
+            membership.group == *dst_addr && membership.port == port &&
+                timestamp.saturating_sub(membership.seen_at) < MEMBERSHIP_TIME
+        })
+    }
+
+    #[cfg(any(feature = "proto-igmp", feature = "proto-ipv6"))]
+    /// Learn the listeners of multicast groups from an IGMP or MLD message in a frame
+    /// received through `port`, if there is any.
+    fn snoop(&mut self, frame: &EthernetFrame<&[u8]>, port: Port, timestamp: u64) {
+        if !self.snooping { return }
+        match frame.ethertype() {
+            #[cfg(feature = "proto-igmp")]
+            EthernetProtocol::Ipv4 => self.snoop_igmp(frame.payload(), port, timestamp),
+            #[cfg(feature = "proto-ipv6")]
+            EthernetProtocol::Ipv6 => self.snoop_mld(frame.payload(), port, timestamp),
+            _ => ()
+        }
+    }
+
+    #[cfg(feature = "proto-igmp")]
+    fn snoop_igmp(&mut self, packet: &[u8], port: Port, timestamp: u64) {
+        let ipv4_packet = match Ipv4Packet::new_checked(packet) {
+            Ok(ipv4_packet) => ipv4_packet,
+            Err(_) => return
+        };
+        if ipv4_packet.protocol() != IpProtocol::Igmp { return }
+        let igmp_packet = match IgmpPacket::new_checked(ipv4_packet.payload()) {
+            Ok(igmp_packet) => igmp_packet,
+            Err(_) => return
+        };
+        match IgmpRepr::parse(&igmp_packet, &ChecksumCapabilities::default()) {
+            Ok(IgmpRepr::MembershipQuery { .. }) =>
+                self.flood(port, timestamp),
+            Ok(IgmpRepr::MembershipReport { group_addr, .. }) =>
+                self.join(ipv4_multicast_addr(group_addr.as_bytes()), port, timestamp),
+            Ok(IgmpRepr::LeaveGroup { group_addr }) =>
+                self.leave(ipv4_multicast_addr(group_addr.as_bytes()), port),
+            Err(_) if igmp_packet.msg_type() == IgmpMessage::MembershipReportV3 =>
+                self.flood(port, timestamp),
+            Err(_) => ()
+        }
+    }
+
+    #[cfg(feature = "proto-ipv6")]
+    fn snoop_mld(&mut self, packet: &[u8], port: Port, timestamp: u64) {
+        let ipv6_packet = match Ipv6Packet::new_checked(packet) {
+            Ok(ipv6_packet) => ipv6_packet,
+            Err(_) => return
+        };
+        // MLD messages are sent with a Router Alert option in a Hop-by-Hop Options header.
+        let (next_header, payload) = match ipv6_packet.next_header() {
+            IpProtocol::HopByHop => match Ipv6HopByHopHeader::new_checked(ipv6_packet.payload()) {
+                Ok(header) => (header.next_header(), header.payload()),
+                Err(_) => return
+            },
+            next_header => (next_header, ipv6_packet.payload())
+        };
+        if next_header != IpProtocol::Icmpv6 { return }
+        let icmp_packet = match Icmpv6Packet::new_checked(payload) {
+            Ok(icmp_packet) => icmp_packet,
+            Err(_) => return
+        };
+        match MldRepr::parse(&icmp_packet) {
+            Ok(MldRepr::Query { .. }) =>
+                self.flood(port, timestamp),
+            Ok(MldRepr::Report { mcast_addr }) =>
+                self.join(ipv6_multicast_addr(mcast_addr.as_bytes()), port, timestamp),
+            Ok(MldRepr::Done { mcast_addr }) =>
+                self.leave(ipv6_multicast_addr(mcast_addr.as_bytes()), port),
+            Ok(MldRepr::ReportV2 { record_type, mcast_addr }) => match record_type {
+                MldRecordType::ModeIsExclude | MldRecordType::ChangeToExclude =>
+                    self.join(ipv6_multicast_addr(mcast_addr.as_bytes()), port, timestamp),
+                MldRecordType::ModeIsInclude | MldRecordType::ChangeToInclude =>
+                    self.leave(ipv6_multicast_addr(mcast_addr.as_bytes()), port),
+                _ => ()
+            },
+            Err(_) if icmp_packet.msg_type() == Icmpv6Message::MldReportV2 =>
+                self.flood(port, timestamp),
+            _ => ()
+        }
+    }
+}
+
+/// Return the hardware address of an IPv4 multicast group; see RFC 1112 § 6.4.
+#[cfg(feature = "proto-igmp")]
+fn ipv4_multicast_addr(b: &[u8]) -> EthernetAddress {
+    EthernetAddress([0x01, 0x00, 0x5e, b[1] & 0x7f, b[2], b[3]])
+}
+
+/// Return the hardware address of an IPv6 multicast group; see RFC 2464 § 7.
+#[cfg(feature = "proto-ipv6")]
+fn ipv6_multicast_addr(b: &[u8]) -> EthernetAddress {
+    EthernetAddress([0x33, 0x33, b[12], b[13], b[14], b[15]])
+}
+
+/// Return whether the frames sent to the given multicast address are forwarded only to
+/// the ports with listeners when snooping. The groups of link-local scope, such as those
+/// of the routing protocols, and the solicited-node groups of neighbor discovery,
+/// are always forwarded; see RFC 4541 § 2.1.2.
+fn is_snooped(addr: &EthernetAddress) -> bool {
+    let b = addr.as_bytes();
+    match (b[0], b[1], b[2]) {
+        // 224.0.0.0/24
+        (0x01, 0x00, 0x5e) => !(b[3] == 0x00 && b[4] == 0x00),
+        // ff02::/112 and ff02::1:ff00:0/104
+        (0x33, 0x33, _) => !(b[2] == 0x00 && b[3] == 0x00 && b[4] == 0x00) && b[2] != 0xff,
+        _ => false
+    }
+}
+
+/// A bridge device.
+///
+/// A bridge is a device that joins two Ethernet devices, its ports, into one segment:
+/// the frames received through either port are forwarded through the other one, unless
+/// they are addressed to a host that is known to be on the port they came from,
+/// which the bridge learns from the source addresses of the frames it receives.
+/// The frames addressed to the local interface, i.e. to the address of the bridge,
+/// and the broadcast and multicast frames, are received through the bridge.
+/// The frames transmitted through the bridge go through the port of their destination,
+/// or through both ports if it is unknown. Multicast frames go through both ports too,
+/// unless the bridge [snoops] on multicast group membership.
+///
+/// Frames are forwarded while the interface [polls] the bridge for received frames,
+/// so a device that passes traffic through has to poll its interface
+/// as soon as either port becomes readable. The ports should receive all frames,
+/// i.e. be promiscuous, and their checksum capabilities are not used.
+///
+/// [polls]: ../iface/struct.EthernetInterface.html#method.poll
+/// [snoops]: #method.set_snooping
+#[derive(Debug)]
+pub struct Bridge<A: for<'a> Device<'a>, B: for<'a> Device<'a>> {
+    port_a:    A,
+    port_b:    B,
+    state:     RefCell<State>,
+    next_port: Port,
+    rx_buffer: Vec<u8>,
+}
+
+impl<A: for<'a> Device<'a>, B: for<'a> Device<'a>> Bridge<A, B> {
+    /// Create a bridge joining the given devices, through which the local interface,
+    /// with the given Ethernet address, is attached to the segment.
+    pub fn new(port_a: A, port_b: B, local_addr: EthernetAddress) -> Bridge<A, B> {
+        let mtu = cmp::max(port_a.capabilities().max_transmission_unit,
+                           port_b.capabilities().max_transmission_unit);
+        Bridge {
+            port_a, port_b,
+            state: RefCell::new(State {
+                local_addr,
+                table:      Vec::new(),
+                table_size: DEFAULT_TABLE_SIZE,
+                timestamp:  0,
+                snooping:   false,
+                groups:     Vec::new(),
+                flood_seen_at: [None; 2],
+            }),
+            next_port: Port::A,
+            rx_buffer: vec![0; mtu],
+        }
+    }
+
+    /// Return the Ethernet address of the local interface.
+    pub fn local_addr(&self) -> EthernetAddress {
+        self.state.borrow().local_addr
+    }
+
+    /// Set the Ethernet address of the local interface, which has to follow the address
+    /// of the interface if it changes.
+    pub fn set_local_addr(&mut self, addr: EthernetAddress) {
+        self.state.borrow_mut().local_addr = addr
+    }
+
+    /// Return the number of addresses the bridge learns at most; 64 by default.
+    pub fn table_size(&self) -> usize {
+        self.state.borrow().table_size
+    }
+
+    /// Set the number of addresses the bridge learns at most. Once the table is full,
+    /// the address seen the longest time ago is forgotten to learn a new one.
+    pub fn set_table_size(&mut self, size: usize) {
+        let mut state = self.state.borrow_mut();
+        state.table_size = size;
+        state.table.truncate(size)
+    }
+
+    /// Return whether the bridge snoops on multicast group membership; false by default.
+    pub fn snooping(&self) -> bool {
+        self.state.borrow().snooping
+    }
+
+    /// Set whether the bridge snoops on multicast group membership.
+    ///
+    /// When snooping, the bridge learns on which ports there are listeners of
+    /// which multicast groups from the IGMP and MLD reports it sees, and forwards
+    /// the frames sent to a group only through the ports with listeners of that group,
+    /// or with a multicast router, which the bridge recognizes from the queries it sends.
+    /// The frames sent to the groups of link-local scope, and to those of hosts
+    /// whose reports the bridge does not understand, are forwarded through every port.
+    /// This keeps a port with a slow link, such as a radio, from being flooded
+    /// with multicast traffic no host on it listens to.
+    pub fn set_snooping(&mut self, snooping: bool) {
+        let mut state = self.state.borrow_mut();
+        state.snooping = snooping;
+        state.groups.clear();
+        state.flood_seen_at = [None; 2]
+    }
+
+    /// Return the underlying devices, consuming the bridge.
+    pub fn into_inner(self) -> (A, B) {
+        (self.port_a, self.port_b)
+    }
+}
+
+/// Transmit `frame` through `device`.
+fn send<D: for<'a> Device<'a>>(device: &mut D, timestamp: u64, frame: &[u8]) -> Result<()> {
+    let token = device.transmit().ok_or(Error::Exhausted)?;
+    token.consume(timestamp, frame.len(), |buffer| {
+        buffer.copy_from_slice(frame);
+        Ok(())
+    })
+}
+
+/// Learn the source of a frame received through `port`, forward it through `other`
+/// if it has to be, and return whether it is received by the local interface.
+fn process<D>(state: &RefCell<State>, port: Port, other: &mut D,
+              frame: &[u8]) -> bool
+    where D: for<'a> Device<'a>
+{
+    let mut state = state.borrow_mut();
+    let timestamp = state.timestamp;
+    let (src_addr, dst_addr) = match EthernetFrame::new_checked(frame) {
+        Ok(eth_frame) => {
+            #[cfg(any(feature = "proto-igmp", feature = "proto-ipv6"))]
+            state.snoop(&eth_frame, port, timestamp);
+            (eth_frame.src_addr(), eth_frame.dst_addr())
+        }
+        // Let the interface account for the frame.
+        Err(_) => return true
+    };
+
+    if src_addr == state.local_addr {
+        net_trace!("bridge: dropping a frame of the local interface from port {:?}", port);
+        return false
+    }
+    if src_addr.is_unicast() {
+        state.learn(src_addr, port, timestamp)
+    }
+
+    if dst_addr == state.local_addr { return true }
+    let forward = if dst_addr.is_multicast() {
+        state.forwards_multicast(&dst_addr, port.other(), timestamp)
+    } else {
+        state.lookup(&dst_addr, timestamp) != Some(port)
+    };
+    drop(state);
+    if forward {
+        if let Err(err) = send(other, timestamp, frame) {
+            net_debug!("bridge: cannot forward a frame to port {:?}: {}", port.other(), err);
+        }
+    }
+    dst_addr.is_multicast()
+}
+
+/// Receive a frame through `port`, if there is any, and process it, copying it into
+/// `buffer` if it is received by the local interface, and returning its length
+/// and whether it was truncated in that case.
+fn receive_from<D, O>(device: &mut D, other: &mut O, port: Port, state: &RefCell<State>,
+                      buffer: &mut [u8]) -> Option<Option<(usize, bool)>>
+    where D: for<'a> Device<'a>, O: for<'a> Device<'a>
+{
+    let (rx, _tx) = device.receive()?;
+    let timestamp = state.borrow().timestamp;
+    let truncated = rx.is_truncated();
+    let result = rx.consume(timestamp, |frame| {
+        if truncated {
+            net_debug!("bridge: dropping a truncated frame from port {:?}", port);
+            return Ok(None)
+        }
+        if !process(state, port, other, frame) { return Ok(None) }
+        let len = cmp::min(frame.len(), buffer.len());
+        buffer[..len].copy_from_slice(&frame[..len]);
+        Ok(Some((len, len < frame.len())))
+    });
+    Some(result.unwrap_or(None))
+}
+
+impl<'a, A, B> Device<'a> for Bridge<A, B>
+    where A: for<'b> Device<'b> + 'a,
+          B: for<'b> Device<'b> + 'a,
+{
+    type RxToken = RxToken<'a>;
+    type TxToken = TxToken<'a, A, B>;
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        DeviceCapabilities {
+            max_transmission_unit: cmp::min(self.port_a.capabilities().max_transmission_unit,
+                                            self.port_b.capabilities().max_transmission_unit),
+            medium: Medium::Ethernet,
+            ..DeviceCapabilities::default()
+        }
+    }
+
+    fn add_multicast_filter(&mut self, addr: EthernetAddress) {
+        self.port_a.add_multicast_filter(addr);
+        self.port_b.add_multicast_filter(addr)
+    }
+
+    fn remove_multicast_filter(&mut self, addr: EthernetAddress) {
+        self.port_a.remove_multicast_filter(addr);
+        self.port_b.remove_multicast_filter(addr)
+    }
+
+    fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+        let &mut Self {
+            ref mut port_a, ref mut port_b, ref state, ref mut next_port,
+            ref mut rx_buffer
+        } = self;
+
+        // Forward frames until one is received by the local interface, or neither port
+        // has any frame left, taking turns between the ports.
+        let mut idle = 0;
+        let mut received = None;
+        while received.is_none() && idle < 2 {
+            let port = *next_port;
+            *next_port = port.other();
+            let result = match port {
+                Port::A => receive_from(port_a, port_b, port, state, rx_buffer),
+                Port::B => receive_from(port_b, port_a, port, state, rx_buffer)
+            };
+            match result {
+                Some(local) => {
+                    idle = 0;
+                    received = local
+                }
+                None => idle += 1
+            }
+        }
+
+        let (len, truncated) = received?;
+        let rx = RxToken { state, buffer: &rx_buffer[..len], truncated };
+        let tx = TxToken { state, port_a, port_b };
+        Some((rx, tx))
+    }
+
+    fn transmit(&'a mut self) -> Option<Self::TxToken> {
+        let &mut Self { ref mut port_a, ref mut port_b, ref state, .. } = self;
+        Some(TxToken { state, port_a, port_b })
+    }
+}
+
+#[doc(hidden)]
+pub struct RxToken<'a> {
+    state:     &'a RefCell<State>,
+    buffer:    &'a [u8],
+    truncated: bool
+}
+
+impl<'a> phy::RxToken for RxToken<'a> {
+    fn consume<R, F>(self, timestamp: u64, f: F) -> Result<R>
+        where F: FnOnce(&[u8]) -> Result<R>
+    {
+        self.state.borrow_mut().timestamp = timestamp;
+        f(self.buffer)
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+#[doc(hidden)]
+pub struct TxToken<'a, A: for<'b> Device<'b> + 'a, B: for<'b> Device<'b> + 'a> {
+    state:  &'a RefCell<State>,
+    port_a: &'a mut A,
+    port_b: &'a mut B,
+}
+
+impl<'a, A, B> phy::TxToken for TxToken<'a, A, B>
+    where A: for<'b> Device<'b>,
+          B: for<'b> Device<'b>,
+{
+    fn consume<R, F>(self, timestamp: u64, len: usize, f: F) -> Result<R>
+        where F: FnOnce(&mut [u8]) -> Result<R>
+    {
+        let Self { state, port_a, port_b } = self;
+        state.borrow_mut().timestamp = timestamp;
+
+        let mut frame = vec![0; len];
+        let result = f(&mut frame)?;
+        let (to_a, to_b) = match EthernetFrame::new_checked(&frame[..]) {
+            Ok(eth_frame) if eth_frame.dst_addr().is_multicast() => {
+                let state = state.borrow();
+                let dst_addr = eth_frame.dst_addr();
+                (state.forwards_multicast(&dst_addr, Port::A, timestamp),
+                 state.forwards_multicast(&dst_addr, Port::B, timestamp))
+            }
+            Ok(eth_frame) => match state.borrow().lookup(&eth_frame.dst_addr(), timestamp) {
+                Some(Port::A) => (true, false),
+                Some(Port::B) => (false, true),
+                None => (true, true)
+            },
+            Err(_) => (true, true)
+        };
+        // A multicast frame no host listens to is sent successfully through no port.
+        if !to_a && !to_b { return Ok(result) }
+        let result_a = if to_a { send(port_a, timestamp, &frame) } else { Err(Error::Exhausted) };
+        let result_b = if to_b { send(port_b, timestamp, &frame) } else { Err(Error::Exhausted) };
+        match (result_a, result_b) {
+            (Ok(()), _) | (_, Ok(())) => Ok(result),
+            (Err(err), _) => Err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+    use super::*;
+
+    const LOCAL: EthernetAddress = EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+    const HOST_A: EthernetAddress = EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x0a]);
+    const HOST_B: EthernetAddress = EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x0b]);
+
+    /// A port of the bridge, which keeps the frames it is to receive apart from those
+    /// transmitted through it.
+    #[derive(Debug, Default)]
+    struct Link {
+        rx: VecDeque<Vec<u8>>,
+        tx: VecDeque<Vec<u8>>
+    }
+
+    struct LinkRxToken(Vec<u8>);
+
+    impl phy::RxToken for LinkRxToken {
+        fn consume<R, F>(self, _timestamp: u64, f: F) -> Result<R>
+            where F: FnOnce(&[u8]) -> Result<R>
+        {
+            f(&self.0)
+        }
+    }
+
+    struct LinkTxToken<'a>(&'a mut VecDeque<Vec<u8>>);
+
+    impl<'a> phy::TxToken for LinkTxToken<'a> {
+        fn consume<R, F>(self, _timestamp: u64, len: usize, f: F) -> Result<R>
+            where F: FnOnce(&mut [u8]) -> Result<R>
+        {
+            let mut frame = vec![0; len];
+            let result = f(&mut frame)?;
+            self.0.push_back(frame);
+            Ok(result)
+        }
+    }
+
+    impl<'a> Device<'a> for Link {
+        type RxToken = LinkRxToken;
+        type TxToken = LinkTxToken<'a>;
+
+        fn capabilities(&self) -> DeviceCapabilities {
+            DeviceCapabilities {
+                max_transmission_unit: 1514,
+                ..DeviceCapabilities::default()
+            }
+        }
+
+        fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+            let &mut Link { ref mut rx, ref mut tx } = self;
+            rx.pop_front().map(move |frame| (LinkRxToken(frame), LinkTxToken(tx)))
+        }
+
+        fn transmit(&'a mut self) -> Option<Self::TxToken> {
+            Some(LinkTxToken(&mut self.tx))
+        }
+    }
+
+    fn bridge() -> Bridge<Link, Link> {
+        Bridge::new(Link::default(), Link::default(), LOCAL)
+    }
+
+    fn frame(src_addr: EthernetAddress, dst_addr: EthernetAddress) -> Vec<u8> {
+        let mut buffer = vec![0; 60];
+        {
+            let mut frame = EthernetFrame::new(&mut buffer[..]);
+            frame.set_src_addr(src_addr);
+            frame.set_dst_addr(dst_addr);
+        }
+        buffer
+    }
+
+    fn recv<D: for<'a> Device<'a>>(device: &mut D) -> Option<Vec<u8>> {
+        let (rx, _tx) = device.receive()?;
+        Some(rx.consume(0, |frame| Ok(frame.to_vec())).unwrap())
+    }
+
+    #[test]
+    fn test_local() {
+        let mut bridge = bridge();
+        bridge.port_a.rx.push_back(frame(HOST_A, LOCAL));
+        assert_eq!(recv(&mut bridge), Some(frame(HOST_A, LOCAL)));
+        assert_eq!(recv(&mut bridge), None);
+        // The frame was not forwarded.
+        assert_eq!(bridge.port_b.tx.pop_front(), None);
+    }
+
+    #[test]
+    fn test_forward_unknown() {
+        let mut bridge = bridge();
+        bridge.port_a.rx.push_back(frame(HOST_A, HOST_B));
+        assert_eq!(recv(&mut bridge), None);
+        assert_eq!(bridge.port_b.tx.pop_front(), Some(frame(HOST_A, HOST_B)));
+        assert_eq!(bridge.port_a.tx.pop_front(), None);
+    }
+
+    #[test]
+    fn test_broadcast() {
+        let mut bridge = bridge();
+        bridge.port_b.rx.push_back(frame(HOST_B, EthernetAddress::BROADCAST));
+        assert_eq!(recv(&mut bridge), Some(frame(HOST_B, EthernetAddress::BROADCAST)));
+        assert_eq!(bridge.port_a.tx.pop_front(), Some(frame(HOST_B, EthernetAddress::BROADCAST)));
+    }
+
+    #[test]
+    fn test_learning() {
+        let mut bridge = bridge();
+        // Host B is learned to be on port B...
+        bridge.port_b.rx.push_back(frame(HOST_B, LOCAL));
+        assert_eq!(recv(&mut bridge), Some(frame(HOST_B, LOCAL)));
+
+        // ... so that frames from port B to it are filtered...
+        bridge.port_b.rx.push_back(frame(HOST_A, HOST_B));
+        assert_eq!(recv(&mut bridge), None);
+        assert_eq!(bridge.port_a.tx.pop_front(), None);
+
+        // ... frames from port A to it are forwarded to port B...
+        bridge.port_a.rx.push_back(frame(HOST_A, HOST_B));
+        assert_eq!(recv(&mut bridge), None);
+        assert_eq!(bridge.port_b.tx.pop_front(), Some(frame(HOST_A, HOST_B)));
+
+        // ... and the local interface transmits frames to it only through port B.
+        let local_frame = frame(LOCAL, HOST_B);
+        assert_eq!(bridge.transmit().unwrap().consume(0, 60, |buffer| {
+            buffer.copy_from_slice(&local_frame);
+            Ok(())
+        }), Ok(()));
+        assert_eq!(bridge.port_a.tx.pop_front(), None);
+        assert_eq!(bridge.port_b.tx.pop_front(), Some(local_frame));
+    }
+
+    #[test]
+    fn test_flood() {
+        let mut bridge = bridge();
+        let local_frame = frame(LOCAL, HOST_A);
+        assert_eq!(bridge.transmit().unwrap().consume(0, 60, |buffer| {
+            buffer.copy_from_slice(&local_frame);
+            Ok(())
+        }), Ok(()));
+        assert_eq!(bridge.port_a.tx.pop_front(), Some(local_frame.clone()));
+        assert_eq!(bridge.port_b.tx.pop_front(), Some(local_frame));
+    }
+
+    #[test]
+    fn test_table_size() {
+        let mut bridge = bridge();
+        bridge.set_table_size(1);
+        bridge.port_a.rx.push_back(frame(HOST_A, LOCAL));
+        bridge.port_b.rx.push_back(frame(HOST_B, LOCAL));
+        assert!(recv(&mut bridge).is_some());
+        assert!(recv(&mut bridge).is_some());
+        let state = bridge.state.borrow();
+        assert_eq!(state.table.len(), 1);
+        assert_eq!(state.lookup(&HOST_B, 0), Some(Port::B));
+    }
+
+    #[cfg(feature = "proto-igmp")]
+    const GROUP: EthernetAddress = EthernetAddress([0x01, 0x00, 0x5e, 0x01, 0x02, 0x03]);
+
+    #[cfg(feature = "proto-igmp")]
+    fn igmp(src_addr: EthernetAddress, dst_addr: EthernetAddress, repr: IgmpRepr) -> Vec<u8> {
+        use wire::{Ipv4Address, Ipv4Repr};
+
+        let ip_repr = Ipv4Repr {
+            src_addr:    Ipv4Address::new(10, 0, 0, 11),
+            dst_addr:    Ipv4Address::new(224, 1, 2, 3),
+            protocol:    IpProtocol::Igmp,
+            payload_len: repr.buffer_len(),
+            hop_limit:   1,
+            ecn:         0,
+            dscp:        0
+        };
+        let mut buffer = frame(src_addr, dst_addr);
+        buffer.resize(14 + ip_repr.buffer_len() + repr.buffer_len(), 0);
+        {
+            let mut eth_frame = EthernetFrame::new(&mut buffer[..]);
+            eth_frame.set_ethertype(EthernetProtocol::Ipv4);
+            let mut ip_packet = Ipv4Packet::new(eth_frame.payload_mut());
+            ip_repr.emit(&mut ip_packet, &ChecksumCapabilities::default());
+            repr.emit(&mut IgmpPacket::new(ip_packet.payload_mut()),
+                      &ChecksumCapabilities::default());
+        }
+        buffer
+    }
+
+    #[test]
+    #[cfg(feature = "proto-igmp")]
+    fn test_snooping_igmp() {
+        use wire::{Ipv4Address, IgmpVersion};
+
+        let mut bridge = bridge();
+        bridge.set_snooping(true);
+
+        // Nobody listens to the group on port B...
+        bridge.port_a.rx.push_back(frame(HOST_A, GROUP));
+        assert_eq!(recv(&mut bridge), Some(frame(HOST_A, GROUP)));
+        assert_eq!(bridge.port_b.tx.pop_front(), None);
+
+        // ... until host B reports listening to it...
+        let report = igmp(HOST_B, GROUP, IgmpRepr::MembershipReport {
+            group_addr: Ipv4Address::new(224, 1, 2, 3),
+            version:    IgmpVersion::Version2
+        });
+        bridge.port_b.rx.push_back(report);
+        assert!(recv(&mut bridge).is_some());
+        bridge.port_a.rx.push_back(frame(HOST_A, GROUP));
+        assert!(recv(&mut bridge).is_some());
+        assert_eq!(bridge.port_b.tx.pop_front(), Some(frame(HOST_A, GROUP)));
+        // ... while the local interface only sends to it through port B...
+        assert_eq!(bridge.transmit().unwrap().consume(0, 60, |buffer| {
+            buffer.copy_from_slice(&frame(LOCAL, GROUP));
+            Ok(())
+        }), Ok(()));
+        assert_eq!(bridge.port_a.tx.pop_front(), None);
+        assert_eq!(bridge.port_b.tx.pop_front(), Some(frame(LOCAL, GROUP)));
+
+        // ... and leaves it.
+        let leave = igmp(HOST_B, GROUP, IgmpRepr::LeaveGroup {
+            group_addr: Ipv4Address::new(224, 1, 2, 3)
+        });
+        bridge.port_b.rx.push_back(leave);
+        assert!(recv(&mut bridge).is_some());
+        bridge.port_a.rx.push_back(frame(HOST_A, GROUP));
+        assert!(recv(&mut bridge).is_some());
+        assert_eq!(bridge.port_b.tx.pop_front(), None);
+
+        // The groups of link-local scope are always forwarded.
+        let all_systems = EthernetAddress([0x01, 0x00, 0x5e, 0x00, 0x00, 0x01]);
+        bridge.port_a.rx.push_back(frame(HOST_A, all_systems));
+        assert!(recv(&mut bridge).is_some());
+        assert_eq!(bridge.port_b.tx.pop_front(), Some(frame(HOST_A, all_systems)));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-igmp")]
+    fn test_snooping_router() {
+        use wire::{Ipv4Address, IgmpVersion};
+
+        let mut bridge = bridge();
+        bridge.set_snooping(true);
+
+        // A multicast router on port B queries for listeners...
+        let query = igmp(HOST_B, EthernetAddress([0x01, 0x00, 0x5e, 0x00, 0x00, 0x01]),
+                         IgmpRepr::MembershipQuery {
+            max_resp_time: 10_000,
+            group_addr:    Ipv4Address::UNSPECIFIED,
+            version:       IgmpVersion::Version2
+        });
+        bridge.port_b.rx.push_back(query);
+        assert!(recv(&mut bridge).is_some());
+        assert!(bridge.port_a.tx.pop_front().is_some());
+
+        // ... so all multicast traffic is forwarded to it, until the query is too old.
+        bridge.port_a.rx.push_back(frame(HOST_A, GROUP));
+        assert!(recv(&mut bridge).is_some());
+        assert_eq!(bridge.port_b.tx.pop_front(), Some(frame(HOST_A, GROUP)));
+        bridge.state.borrow_mut().timestamp = MEMBERSHIP_TIME;
+        bridge.port_a.rx.push_back(frame(HOST_A, GROUP));
+        assert!(recv(&mut bridge).is_some());
+        assert_eq!(bridge.port_b.tx.pop_front(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv6")]
+    fn test_snooping_mld() {
+        use wire::Ipv6Address;
+
+        let group = EthernetAddress([0x33, 0x33, 0x00, 0x00, 0x00, 0xfb]);
+        let mut bridge = bridge();
+        bridge.set_snooping(true);
+
+        // An MLDv1 report for ff05::fb with a Router Alert option.
+        let mut report = frame(HOST_B, group);
+        report.resize(14 + 40 + 8 + 24, 0);
+        {
+            let mut eth_frame = EthernetFrame::new(&mut report[..]);
+            eth_frame.set_ethertype(EthernetProtocol::Ipv6);
+            let mut ip_packet = Ipv6Packet::new(eth_frame.payload_mut());
+            ip_packet.set_version(6);
+            ip_packet.set_payload_len(8 + 24);
+            ip_packet.set_next_header(IpProtocol::HopByHop);
+            ip_packet.set_hop_limit(1);
+            let payload = ip_packet.payload_mut();
+            payload[..8].copy_from_slice(&[0x3a, 0x00, 0x05, 0x02, 0x00, 0x00, 0x01, 0x00]);
+            MldRepr::Report {
+                mcast_addr: Ipv6Address::new(0xff05, 0, 0, 0, 0, 0, 0, 0xfb)
+            }.emit(&mut Icmpv6Packet::new(&mut payload[8..]));
+        }
+        bridge.port_b.rx.push_back(report);
+        assert!(recv(&mut bridge).is_some());
+
+        bridge.port_a.rx.push_back(frame(HOST_A, group));
+        assert!(recv(&mut bridge).is_some());
+        assert_eq!(bridge.port_b.tx.pop_front(), Some(frame(HOST_A, group)));
+        let other_group = EthernetAddress([0x33, 0x33, 0x00, 0x00, 0x01, 0xfb]);
+        bridge.port_a.rx.push_back(frame(HOST_A, other_group));
+        assert!(recv(&mut bridge).is_some());
+        assert_eq!(bridge.port_b.tx.pop_front(), None);
+    }
+}
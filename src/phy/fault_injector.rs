@@ -1,11 +1,12 @@
 use core::cell::RefCell;
 
 use {Error, Result};
+use wire::EthernetAddress;
 use phy::{self, DeviceCapabilities, Device};
 
 // We use our own RNG to stay compatible with #![no_std].
 // The use of the RNG below has a slight bias, but it doesn't matter.
-fn xorshift32(state: &mut u32) -> u32 {
+pub(crate) fn xorshift32(state: &mut u32) -> u32 {
     let mut x = *state;
     x ^= x << 13;
     x ^= x >> 17;
@@ -14,18 +15,39 @@ fn xorshift32(state: &mut u32) -> u32 {
     x
 }
 
+// xorshift32 never leaves the all-zeroes state, so that seed is replaced.
+pub(crate) fn rng_seed(seed: u32) -> u32 {
+    if seed == 0 { 0x2545f491 } else { seed }
+}
+
 // This could be fixed once associated consts are stable.
 const MTU: usize = 1536;
 
+// The number of received packets that can be held back at once to be reordered or duplicated.
+const HELD_PACKETS: usize = 4;
+
 #[derive(Debug, Clone, Copy, Default)]
 struct Config {
-    corrupt_pct: u8,
-    drop_pct:    u8,
-    reorder_pct: u8,
-    max_size:    usize,
-    max_tx_rate: u64,
-    max_rx_rate: u64,
-    interval:    u64,
+    corrupt_pct:     u8,
+    drop_pct:        u8,
+    reorder_pct:     u8,
+    reorder_window:  u8,
+    duplicate_pct:   u8,
+    burst_enter_pct: u8,
+    burst_leave_pct: u8,
+    burst_drop_pct:  u8,
+    max_size:        usize,
+    max_tx_rate:     u64,
+    max_rx_rate:     u64,
+    interval:        u64,
+}
+
+/// A received packet held back to be received again later, once `countdown` reaches zero.
+#[derive(Debug, Clone, Copy)]
+struct HeldPacket {
+    buffer:    [u8; MTU],
+    len:       usize,
+    countdown: u8,
 }
 
 #[derive(Debug, Clone)]
@@ -34,6 +56,8 @@ struct State {
     refilled_at: u64,
     tx_bucket:   u64,
     rx_bucket:   u64,
+    burst:       bool,
+    held:        [Option<HeldPacket>; HELD_PACKETS],
 }
 
 impl State {
@@ -41,6 +65,46 @@ impl State {
         xorshift32(&mut self.rng_seed) % 100 < pct as u32
     }
 
+    /// Advance the Gilbert-Elliott model by one packet, and decide whether to drop it
+    /// with the loss probability of the state the model is in.
+    fn maybe_drop(&mut self, config: &Config) -> bool {
+        if config.burst_enter_pct > 0 {
+            self.burst = if self.burst {
+                !self.maybe(config.burst_leave_pct)
+            } else {
+                self.maybe(config.burst_enter_pct)
+            };
+        }
+        let pct = if self.burst { config.burst_drop_pct } else { config.drop_pct };
+        self.maybe(pct)
+    }
+
+    /// Hold back a copy of `buffer` for `countdown` calls to `receive`, if there is room.
+    fn hold(&mut self, buffer: &[u8], countdown: u8) -> bool {
+        if buffer.len() > MTU { return false }
+        match self.held.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                let mut packet = HeldPacket { buffer: [0; MTU], len: buffer.len(), countdown };
+                packet.buffer[..buffer.len()].copy_from_slice(buffer);
+                *slot = Some(packet);
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Count down the held packets, and return the index of one that is due, if any.
+    fn tick(&mut self) -> Option<usize> {
+        let mut due = None;
+        for (index, slot) in self.held.iter_mut().enumerate() {
+            if let Some(ref mut packet) = *slot {
+                packet.countdown = packet.countdown.saturating_sub(1);
+                if packet.countdown == 0 && due.is_none() { due = Some(index) }
+            }
+        }
+        due
+    }
+
     fn corrupt<T: AsMut<[u8]>>(&mut self, mut buffer: T) {
         let buffer = buffer.as_mut();
         // We introduce a single bitflip, as the most likely, and the hardest to detect, error.
@@ -87,6 +151,14 @@ impl State {
 /// A fault injector is a device that alters packets traversing through it to simulate
 /// adverse network conditions (such as random packet loss or corruption), or software
 /// or hardware limitations (such as a limited number or size of usable network buffers).
+///
+/// Besides independent random losses, the packets can be lost in bursts, following
+/// a Gilbert-Elliott model. The received packets can also be reordered and duplicated,
+/// by holding back a copy of them to be received again later; up to four packets
+/// can be held back at once.
+///
+/// All of the faults are drawn from a pseudorandom number generator, so that
+/// the same seed and the same traffic reproduce the same faults.
 #[derive(Debug)]
 pub struct FaultInjector<D: for<'a> Device<'a>> {
     inner:      D,
@@ -98,18 +170,25 @@ impl<D: for<'a> Device<'a>> FaultInjector<D> {
     /// Create a fault injector device, using the given random number generator seed.
     pub fn new(inner: D, seed: u32) -> FaultInjector<D> {
         let state = State {
-            rng_seed:    seed,
+            rng_seed:    rng_seed(seed),
             refilled_at: 0,
             tx_bucket:   0,
             rx_bucket:   0,
+            burst:       false,
+            held:        [None; HELD_PACKETS],
         };
         FaultInjector {
             inner: inner,
             state: RefCell::new(state),
-            config: Config::default(),
+            config: Config { reorder_window: 1, ..Config::default() },
         }
     }
 
+    /// Reset the random number generator with the given seed.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.state.borrow_mut().rng_seed = rng_seed(seed)
+    }
+
     /// Return the underlying device, consuming the fault injector.
     pub fn into_inner(self) -> D {
         self.inner
@@ -130,14 +209,35 @@ impl<D: for<'a> Device<'a>> FaultInjector<D> {
         self.config.max_size
     }
 
+    /// Return the probability of reordering a received packet, in percents.
+    pub fn reorder_chance(&self) -> u8 {
+        self.config.reorder_pct
+    }
+
+    /// Return the maximum number of packets a reordered packet is received after.
+    pub fn reorder_window(&self) -> u8 {
+        self.config.reorder_window
+    }
+
+    /// Return the probability of duplicating a received packet, in percents.
+    pub fn duplicate_chance(&self) -> u8 {
+        self.config.duplicate_pct
+    }
+
+    /// Return the probabilities of entering and leaving a loss burst, and of dropping
+    /// a packet during one, in percents.
+    pub fn burst_loss(&self) -> (u8, u8, u8) {
+        (self.config.burst_enter_pct, self.config.burst_leave_pct, self.config.burst_drop_pct)
+    }
+
     /// Return the maximum packet transmission rate, in packets per second.
     pub fn max_tx_rate(&self) -> u64 {
-        self.config.max_rx_rate
+        self.config.max_tx_rate
     }
 
     /// Return the maximum packet reception rate, in packets per second.
     pub fn max_rx_rate(&self) -> u64 {
-        self.config.max_tx_rate
+        self.config.max_rx_rate
     }
 
     /// Return the interval for packet rate limiting, in milliseconds.
@@ -163,6 +263,56 @@ impl<D: for<'a> Device<'a>> FaultInjector<D> {
         self.config.drop_pct = pct
     }
 
+    /// Set the probability of reordering a received packet, in percents.
+    ///
+    /// A reordered packet is held back, and received after the next one to a few,
+    /// as set by [set_reorder_window](#method.set_reorder_window), or after as many
+    /// attempts to receive a packet if there are fewer.
+    ///
+    /// # Panics
+    /// This function panics if the probability is not between 0% and 100%.
+    pub fn set_reorder_chance(&mut self, pct: u8) {
+        if pct > 100 { panic!("percentage out of range") }
+        self.config.reorder_pct = pct
+    }
+
+    /// Set the maximum number of packets a reordered packet is received after.
+    ///
+    /// # Panics
+    /// This function panics if the window is empty.
+    pub fn set_reorder_window(&mut self, packets: u8) {
+        if packets == 0 { panic!("empty reordering window") }
+        self.config.reorder_window = packets
+    }
+
+    /// Set the probability of duplicating a received packet, in percents.
+    ///
+    /// # Panics
+    /// This function panics if the probability is not between 0% and 100%.
+    pub fn set_duplicate_chance(&mut self, pct: u8) {
+        if pct > 100 { panic!("percentage out of range") }
+        self.config.duplicate_pct = pct
+    }
+
+    /// Set the probabilities of entering a loss burst with any packet, of leaving it
+    /// with any packet during it, and of dropping a packet during it, in percents.
+    ///
+    /// Outside of a burst, the packets are dropped with the probability set by
+    /// [set_drop_chance](#method.set_drop_chance). A probability of 0% of entering
+    /// a burst disables them.
+    ///
+    /// # Panics
+    /// This function panics if a probability is not between 0% and 100%.
+    pub fn set_burst_loss(&mut self, enter_pct: u8, leave_pct: u8, drop_pct: u8) {
+        if enter_pct > 100 || leave_pct > 100 || drop_pct > 100 {
+            panic!("percentage out of range")
+        }
+        self.config.burst_enter_pct = enter_pct;
+        self.config.burst_leave_pct = leave_pct;
+        self.config.burst_drop_pct  = drop_pct;
+        self.state.borrow_mut().burst = false
+    }
+
     /// Set the maximum packet size, in octets.
     pub fn set_max_packet_size(&mut self, size: usize) {
         self.config.max_size = size
@@ -196,17 +346,49 @@ impl<'a, D> Device<'a> for FaultInjector<D>
         if caps.max_transmission_unit > MTU {
             caps.max_transmission_unit = MTU;
         }
+        // Packets are held and dropped into buffers of the MTU, so none may be larger.
+        caps.max_tso_size = None;
         caps
     }
 
+    fn add_multicast_filter(&mut self, addr: EthernetAddress) {
+        self.inner.add_multicast_filter(addr)
+    }
+
+    fn remove_multicast_filter(&mut self, addr: EthernetAddress) {
+        self.inner.remove_multicast_filter(addr)
+    }
+
     fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
         let &mut Self { ref mut inner, ref state, config } = self;
+
+        let due = state.borrow_mut().tick();
+        if let Some(index) = due {
+            let tx_token = inner.transmit()?;
+            let packet = state.borrow_mut().held[index].take().unwrap();
+            let rx = RxToken {
+                state:   &state,
+                config:  config,
+                token:   None,
+                corrupt: packet.buffer,
+                held:    packet.len,
+            };
+            let tx = TxToken {
+                state:   &state,
+                config:  config,
+                token:   tx_token,
+                junk:    [0; MTU],
+            };
+            return Some((rx, tx))
+        }
+
         inner.receive().map(|(rx_token, tx_token)| {
             let rx = RxToken {
                 state:   &state,
                 config:  config,
-                token:   rx_token,
+                token:   Some(rx_token),
                 corrupt: [0; MTU],
+                held:    0,
             };
             let tx = TxToken {
                 state:   &state,
@@ -233,39 +415,66 @@ impl<'a, D> Device<'a> for FaultInjector<D>
 pub struct RxToken<'a, Rx: phy::RxToken> {
     state:   &'a RefCell<State>,
     config:  Config,
-    token:   Rx,
+    token:   Option<Rx>,
     corrupt: [u8; MTU],
+    held:    usize,
 }
 
 impl<'a, Rx: phy::RxToken> phy::RxToken for RxToken<'a, Rx> {
     fn consume<R, F>(self, timestamp: u64, f: F) -> Result<R>
         where F: FnOnce(&[u8]) -> Result<R>
     {
-        if self.state.borrow_mut().maybe(self.config.drop_pct) {
+        let Self { token, config, state, mut corrupt, held } = self;
+        let token = match token {
+            // A packet that was held back has been through the faults already.
+            None => return f(&corrupt[..held]),
+            Some(token) => token
+        };
+
+        if state.borrow_mut().maybe_drop(&config) {
             net_trace!("rx: randomly dropping a packet");
             return Err(Error::Exhausted)
         }
-        if !self.state.borrow_mut().maybe_receive(&self.config, timestamp) {
+        if !state.borrow_mut().maybe_receive(&config, timestamp) {
             net_trace!("rx: dropping a packet because of rate limiting");
             return Err(Error::Exhausted)
         }
-        let Self { token, config, state, mut corrupt } = self;
         token.consume(timestamp, |buffer| {
             if config.max_size > 0 && buffer.as_ref().len() > config.max_size {
                 net_trace!("rx: dropping a packet that is too large");
                 return Err(Error::Exhausted)
             }
-            if state.borrow_mut().maybe(config.corrupt_pct) {
+            let buffer = if state.borrow_mut().maybe(config.corrupt_pct) {
                 net_trace!("rx: randomly corrupting a packet");
-                let mut corrupt = &mut corrupt[..buffer.len()];
+                let corrupt = &mut corrupt[..buffer.len()];
                 corrupt.copy_from_slice(buffer);
-                state.borrow_mut().corrupt(&mut corrupt);
-                f(&mut corrupt)
+                state.borrow_mut().corrupt(&mut *corrupt);
+                &*corrupt
             } else {
-                f(buffer)
+                buffer
+            };
+
+            let mut state = state.borrow_mut();
+            if state.maybe(config.reorder_pct) {
+                // Let between one packet and the whole window through before this one.
+                let overtaking = xorshift32(&mut state.rng_seed) % config.reorder_window as u32;
+                if state.hold(buffer, (overtaking as u8).saturating_add(2)) {
+                    net_trace!("rx: randomly reordering a packet");
+                    return Err(Error::Exhausted)
+                }
+            }
+            if state.maybe(config.duplicate_pct) && state.hold(buffer, 1) {
+                net_trace!("rx: randomly duplicating a packet");
             }
+            drop(state);
+
+            f(buffer)
         })
     }
+
+    fn is_truncated(&self) -> bool {
+        self.token.as_ref().map_or(false, |token| token.is_truncated())
+    }
 }
 
 #[doc(hidden)]
@@ -280,7 +489,7 @@ impl<'a, Tx: phy::TxToken> phy::TxToken for TxToken<'a, Tx> {
     fn consume<R, F>(mut self, timestamp: u64, len: usize, f: F) -> Result<R>
         where F: FnOnce(&mut [u8]) -> Result<R>
     {
-        let drop = if self.state.borrow_mut().maybe(self.config.drop_pct) {
+        let drop = if self.state.borrow_mut().maybe_drop(&self.config) {
             net_trace!("tx: randomly dropping a packet");
             true
         } else if self.config.max_size > 0 && len > self.config.max_size {
@@ -307,3 +516,98 @@ impl<'a, Tx: phy::TxToken> phy::TxToken for TxToken<'a, Tx> {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use phy::{Loopback, RxToken, TxToken};
+    use super::*;
+
+    fn send<D: for<'a> Device<'a>>(device: &mut D, byte: u8) {
+        device.transmit().unwrap().consume(0, 64, |buffer| {
+            for octet in buffer.iter_mut() { *octet = byte }
+            Ok(())
+        }).unwrap();
+    }
+
+    fn recv_all<D: for<'a> Device<'a>>(device: &mut D) -> Vec<u8> {
+        let mut received = Vec::new();
+        while let Some((rx, _tx)) = device.receive() {
+            if let Ok(byte) = rx.consume(0, |buffer| Ok(buffer[0])) {
+                received.push(byte)
+            }
+        }
+        received
+    }
+
+    fn injector(seed: u32) -> FaultInjector<Loopback> {
+        FaultInjector::new(Loopback::new(), seed)
+    }
+
+    #[test]
+    fn test_zero_seed() {
+        let mut device = injector(0);
+        device.set_drop_chance(50);
+        for byte in 0..100 { send(&mut device, byte) }
+        let received = device.into_inner().receive().is_some();
+        assert!(received);
+    }
+
+    #[test]
+    fn test_reproducible() {
+        let run = |seed| {
+            let mut device = injector(seed);
+            device.set_drop_chance(20);
+            device.set_reorder_chance(20);
+            device.set_reorder_window(3);
+            device.set_duplicate_chance(20);
+            device.set_burst_loss(5, 50, 80);
+            for byte in 0..100 { send(&mut device, byte) }
+            recv_all(&mut device)
+        };
+        assert_eq!(run(12345), run(12345));
+        assert!(run(12345) != run(54321));
+    }
+
+    #[test]
+    fn test_reorder() {
+        let mut device = injector(1);
+        for byte in 0..3 { send(&mut device, byte) }
+        device.set_reorder_chance(100);
+        device.set_reorder_window(1);
+        {
+            let (rx, _tx) = device.receive().unwrap();
+            assert_eq!(rx.consume(0, |_| Ok(())), Err(Error::Exhausted));
+        }
+        device.set_reorder_chance(0);
+        assert_eq!(recv_all(&mut device), vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn test_reorder_idle() {
+        let mut device = injector(1);
+        device.set_reorder_chance(100);
+        send(&mut device, 0);
+        // A packet held back is received after a few attempts even without traffic.
+        assert_eq!(recv_all(&mut device), vec![]);
+        assert_eq!(recv_all(&mut device), vec![0]);
+    }
+
+    #[test]
+    fn test_duplicate() {
+        let mut device = injector(1);
+        device.set_duplicate_chance(100);
+        for byte in 0..2 { send(&mut device, byte) }
+        assert_eq!(recv_all(&mut device), vec![0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn test_burst_loss() {
+        let mut device = injector(1);
+        // Enter a burst with the first packet and never leave it.
+        device.set_burst_loss(100, 0, 100);
+        for byte in 0..10 { send(&mut device, byte) }
+        assert_eq!(device.burst_loss(), (100, 0, 100));
+        assert_eq!(recv_all(&mut device.into_inner()), vec![]);
+    }
+}
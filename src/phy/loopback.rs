@@ -4,14 +4,69 @@ use std::vec::Vec;
 use std::collections::VecDeque;
 #[cfg(feature = "alloc")]
 use alloc::{Vec, VecDeque};
+use core::cmp;
+use core::cell::RefCell;
 
-use Result;
-use phy::{self, Device, DeviceCapabilities};
+use {Error, Result};
+use phy::{self, Device, DeviceCapabilities, Medium, RxLoan};
+use super::fault_injector::{xorshift32, rng_seed};
+
+#[derive(Debug)]
+struct Packet {
+    buffer: Vec<u8>,
+    due_at: u64,
+}
+
+#[derive(Debug)]
+struct State {
+    queue:     VecDeque<Packet>,
+    latency:   u64,
+    jitter:    u64,
+    bandwidth: u64,
+    rng_seed:  u32,
+    /// The time the link finishes sending the packets transmitted so far.
+    idle_at:   u64,
+}
+
+impl State {
+    /// Return the time a packet of `len` octets transmitted at `timestamp` is received.
+    fn due_at(&mut self, timestamp: u64, len: usize) -> u64 {
+        if self.latency == 0 && self.jitter == 0 && self.bandwidth == 0 { return 0 }
+
+        // The packets are sent one after another, at the bandwidth of the link.
+        let mut sent_at = timestamp;
+        if self.bandwidth > 0 {
+            let duration = (len as u64 * 1000 + self.bandwidth - 1) / self.bandwidth;
+            sent_at = cmp::max(timestamp, self.idle_at) + duration;
+            self.idle_at = sent_at;
+        }
+        let mut due_at = sent_at + self.latency;
+        if self.jitter > 0 {
+            due_at += xorshift32(&mut self.rng_seed) as u64 % (self.jitter + 1);
+        }
+        // The link does not reorder the packets.
+        cmp::max(due_at, self.queue.back().map_or(0, |packet| packet.due_at))
+    }
+}
 
 /// A loopback device.
+///
+/// The loopback can model a link with a latency, a jitter and a bandwidth, in which case
+/// a packet is only received once it is due, according to the timestamps passed
+/// to the tokens, e.g. by [poll]; otherwise, packets are received right away.
+/// The jitter is drawn from a deterministic pseudo-random sequence, so that tests
+/// of timing-dependent behavior, e.g. of TCP retransmissions, are reproducible.
+///
+/// The loopback lends the packets it receives out, so that sockets can receive them
+/// without copying them; see [RxToken::consume_lend].
+///
+/// [poll]: ../iface/struct.EthernetInterface.html#method.poll
+/// [RxToken::consume_lend]: trait.RxToken.html#method.consume_lend
 #[derive(Debug)]
 pub struct Loopback {
-    queue: VecDeque<Vec<u8>>,
+    state:  RefCell<State>,
+    lent:   Vec<Option<Vec<u8>>>,
+    medium: Medium,
 }
 
 impl Loopback {
@@ -20,62 +75,280 @@ impl Loopback {
     /// Every packet transmitted through this device will be received through it
     /// in FIFO order.
     pub fn new() -> Loopback {
+        Loopback::with_medium(Medium::Ethernet)
+    }
+
+    /// Creates a loopback device of the given medium.
+    pub fn with_medium(medium: Medium) -> Loopback {
         Loopback {
-            queue: VecDeque::new(),
+            state: RefCell::new(State {
+                queue:     VecDeque::new(),
+                latency:   0,
+                jitter:    0,
+                bandwidth: 0,
+                rng_seed:  rng_seed(0),
+                idle_at:   0,
+            }),
+            lent:   Vec::new(),
+            medium: medium,
         }
     }
+
+    /// Return the one-way latency of the link, in milliseconds.
+    pub fn latency(&self) -> u64 {
+        self.state.borrow().latency
+    }
+
+    /// Return the largest delay added to the latency of the link, in milliseconds.
+    pub fn jitter(&self) -> u64 {
+        self.state.borrow().jitter
+    }
+
+    /// Return the bandwidth of the link, in octets per second, or 0 if it is not limited.
+    pub fn bandwidth(&self) -> u64 {
+        self.state.borrow().bandwidth
+    }
+
+    /// Set the one-way latency of the link, in milliseconds.
+    pub fn set_latency(&mut self, millis: u64) {
+        self.state.borrow_mut().latency = millis
+    }
+
+    /// Set the largest delay added to the latency of the link, in milliseconds.
+    /// Each packet is delayed by a pseudo-random amount up to it, but never overtakes
+    /// the packets transmitted before it.
+    pub fn set_jitter(&mut self, millis: u64) {
+        self.state.borrow_mut().jitter = millis
+    }
+
+    /// Set the bandwidth of the link, in octets per second; a packet is received
+    /// once the link had the time to send it after the packets before it.
+    /// A bandwidth of 0 removes the limit.
+    pub fn set_bandwidth(&mut self, octets_per_sec: u64) {
+        self.state.borrow_mut().bandwidth = octets_per_sec
+    }
+
+    /// Seed the pseudo-random sequence the jitter is drawn from.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.state.borrow_mut().rng_seed = rng_seed(seed)
+    }
+
+    /// Return the time the next packet is due to be received, if there is any.
+    pub fn poll_at(&self) -> Option<u64> {
+        self.state.borrow().queue.front().map(|packet| packet.due_at)
+    }
 }
 
 impl<'a> Device<'a> for Loopback {
-    type RxToken = RxToken;
+    type RxToken = RxToken<'a>;
     type TxToken = TxToken<'a>;
 
     fn capabilities(&self) -> DeviceCapabilities {
+        let max_transmission_unit = match self.medium {
+            #[cfg(feature = "proto-sixlowpan")]
+            Medium::Ieee802154 => 125,
+            _ => 65535
+        };
         DeviceCapabilities {
-            max_transmission_unit: 65535,
+            max_transmission_unit,
+            medium: self.medium,
             ..DeviceCapabilities::default()
         }
     }
 
     fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
-        self.queue.pop_front().map(move |buffer| {
-            let rx = RxToken { buffer: buffer };
-            let tx = TxToken { queue: &mut self.queue };
-            (rx, tx)
-        })
+        let &mut Loopback { ref state, ref mut lent, .. } = self;
+        if state.borrow().queue.is_empty() { return None }
+        let rx = RxToken { state: state, lent: lent };
+        let tx = TxToken { state: state };
+        Some((rx, tx))
     }
 
     fn transmit(&'a mut self) -> Option<Self::TxToken> {
         Some(TxToken {
-            queue: &mut self.queue,
+            state: &self.state,
         })
     }
+
+    fn lent(&self, loan: &RxLoan) -> Option<&[u8]> {
+        match self.lent.get(loan.id()) {
+            Some(&Some(ref buffer)) => loan.slice(buffer),
+            _ => None
+        }
+    }
+
+    fn release(&mut self, loan: RxLoan) {
+        if let Some(slot) = self.lent.get_mut(loan.id()) {
+            *slot = None
+        }
+    }
 }
 
 #[doc(hidden)]
-pub struct RxToken {
-    buffer: Vec<u8>,
+pub struct RxToken<'a> {
+    state: &'a RefCell<State>,
+    lent:  &'a mut Vec<Option<Vec<u8>>>,
 }
 
-impl phy::RxToken for RxToken {
-    fn consume<R, F: FnOnce(&[u8]) -> Result<R>>(self, _timestamp: u64, f: F) -> Result<R> {
-        f(&self.buffer)
+impl<'a> RxToken<'a> {
+    fn dequeue(&self, timestamp: u64) -> Result<Packet> {
+        let mut state = self.state.borrow_mut();
+        match state.queue.front() {
+            Some(packet) if packet.due_at <= timestamp => (),
+            _ => return Err(Error::Exhausted)
+        }
+        Ok(state.queue.pop_front().unwrap())
+    }
+}
+
+impl<'a> phy::RxToken for RxToken<'a> {
+    fn consume<R, F: FnOnce(&[u8]) -> Result<R>>(self, timestamp: u64, f: F) -> Result<R> {
+        let packet = self.dequeue(timestamp)?;
+        f(&packet.buffer)
+    }
+
+    fn consume_lend<R, F>(self, timestamp: u64, f: F) -> Result<R>
+        where F: FnOnce(&[u8], Option<RxLoan>) -> (Result<R>, bool)
+    {
+        let packet = self.dequeue(timestamp)?;
+        let id = self.lent.iter().position(|slot| slot.is_none())
+                     .unwrap_or(self.lent.len());
+        let (result, kept) = f(&packet.buffer, Some(RxLoan::new(id, packet.buffer.len())));
+        if kept {
+            if id == self.lent.len() {
+                self.lent.push(Some(packet.buffer))
+            } else {
+                self.lent[id] = Some(packet.buffer)
+            }
+        }
+        result
     }
 }
 
 #[doc(hidden)]
 pub struct TxToken<'a> {
-    queue: &'a mut VecDeque<Vec<u8>>,
+    state: &'a RefCell<State>,
 }
 
 impl<'a> phy::TxToken for TxToken<'a> {
-    fn consume<R, F>(self, _timestamp: u64, len: usize, f: F) -> Result<R>
+    fn consume<R, F>(self, timestamp: u64, len: usize, f: F) -> Result<R>
         where F: FnOnce(&mut [u8]) -> Result<R>
     {
         let mut buffer = Vec::new();
         buffer.resize(len, 0);
         let result = f(&mut buffer);
-        self.queue.push_back(buffer);
+        let mut state = self.state.borrow_mut();
+        let due_at = state.due_at(timestamp, len);
+        state.queue.push_back(Packet { buffer, due_at });
         result
     }
 }
+
+#[cfg(test)]
+mod test {
+    use phy::{RxToken, TxToken};
+    use super::*;
+
+    fn send(device: &mut Loopback, timestamp: u64, len: usize) {
+        device.transmit().unwrap().consume(timestamp, len, |buffer| {
+            for byte in buffer.iter_mut() { *byte = len as u8 }
+            Ok(())
+        }).unwrap()
+    }
+
+    fn recv(device: &mut Loopback, timestamp: u64) -> Option<Result<usize>> {
+        let (rx, _tx) = device.receive()?;
+        Some(rx.consume(timestamp, |buffer| Ok(buffer.len())))
+    }
+
+    #[test]
+    fn test_immediate() {
+        let mut device = Loopback::new();
+        send(&mut device, 1_000, 10);
+        assert_eq!(device.poll_at(), Some(0));
+        assert_eq!(recv(&mut device, 0), Some(Ok(10)));
+        assert_eq!(recv(&mut device, 0), None);
+    }
+
+    #[test]
+    fn test_latency() {
+        let mut device = Loopback::new();
+        device.set_latency(50);
+        assert_eq!(device.latency(), 50);
+        send(&mut device, 1_000, 10);
+        assert_eq!(device.poll_at(), Some(1_050));
+        assert_eq!(recv(&mut device, 1_049), Some(Err(Error::Exhausted)));
+        assert_eq!(recv(&mut device, 1_050), Some(Ok(10)));
+        assert_eq!(recv(&mut device, 1_050), None);
+    }
+
+    #[test]
+    fn test_bandwidth() {
+        let mut device = Loopback::new();
+        // 1 octet per millisecond.
+        device.set_bandwidth(1_000);
+        assert_eq!(device.bandwidth(), 1_000);
+        send(&mut device, 0, 100);
+        send(&mut device, 0, 100);
+        send(&mut device, 500, 100);
+        assert_eq!(recv(&mut device, 99), Some(Err(Error::Exhausted)));
+        assert_eq!(recv(&mut device, 100), Some(Ok(100)));
+        // The second packet waits for the first one to be sent.
+        assert_eq!(device.poll_at(), Some(200));
+        assert_eq!(recv(&mut device, 200), Some(Ok(100)));
+        // The third packet finds the link idle.
+        assert_eq!(device.poll_at(), Some(600));
+    }
+
+    #[test]
+    fn test_lend() {
+        let mut device = Loopback::new();
+        send(&mut device, 0, 10);
+        send(&mut device, 0, 20);
+        let mut loans = vec![];
+        for _ in 0..2 {
+            let (rx, _tx) = device.receive().unwrap();
+            rx.consume_lend(0, |buffer, loan| {
+                assert_eq!(loan.map(|loan| loan.len()), Some(buffer.len()));
+                loans.push(loan.unwrap());
+                (Ok(()), true)
+            }).unwrap();
+        }
+        assert_eq!(device.lent(&loans[1]), Some(&[20; 20][..]));
+        device.release(loans[0]);
+        assert_eq!(device.lent(&loans[0]), None);
+
+        // The buffer of a released packet is reused.
+        send(&mut device, 0, 5);
+        let (rx, _tx) = device.receive().unwrap();
+        let loan = rx.consume_lend(0, |_, loan| (Ok(loan.unwrap()), true)).unwrap();
+        assert_eq!(loan.id(), loans[0].id());
+        assert_eq!(device.lent(&loan), Some(&[5; 5][..]));
+    }
+
+    #[test]
+    fn test_jitter() {
+        let due_at = |seed| {
+            let mut device = Loopback::new();
+            device.set_latency(10);
+            device.set_jitter(20);
+            device.set_seed(seed);
+            let mut due_at = vec![];
+            for _ in 0..10 {
+                send(&mut device, 0, 10);
+                due_at.push(device.state.borrow().queue.back().unwrap().due_at);
+            }
+            due_at
+        };
+
+        let first = due_at(1);
+        // The delays are reproducible...
+        assert_eq!(due_at(1), first);
+        // ... within the jitter, and do not reorder the packets.
+        for (index, &due_at) in first.iter().enumerate() {
+            assert!(due_at >= 10 && due_at <= 30);
+            if index > 0 { assert!(due_at >= first[index - 1]) }
+        }
+        assert!(first.iter().any(|&due_at| due_at != first[0]));
+    }
+}
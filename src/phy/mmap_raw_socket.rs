@@ -0,0 +1,141 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::io;
+use std::os::unix::io::{RawFd, AsRawFd};
+
+use {Error, Result};
+use phy::{self, sys, DeviceCapabilities, Device};
+use wire::EthernetFrame;
+
+/// A socket that captures or transmits the complete frame, like [RawSocket], through
+/// RX and TX rings shared with the kernel (`PACKET_MMAP` with `TPACKET_V3`).
+///
+/// The frames are received and sent in place in the rings, without a system call
+/// for each of them: the kernel hands the received frames over in blocks, and
+/// the frames to send are handed over in batches, when the device is next asked
+/// to receive a frame, when the TX ring is full, or when [flush] is called.
+/// Since an interface is always asked to receive once it is done sending, only
+/// the frames sent outside of [poll] have to be flushed explicitly.
+///
+/// This requires Linux 4.11 or later, and the same privileges as [RawSocket].
+///
+/// [RawSocket]: struct.RawSocket.html
+/// [flush]: #method.flush
+/// [poll]: ../iface/struct.EthernetInterface.html#method.poll
+#[derive(Debug)]
+pub struct MmapRawSocket {
+    lower:  Rc<RefCell<sys::MmapRawSocketDesc>>,
+    mtu:    usize
+}
+
+impl AsRawFd for MmapRawSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.lower.borrow().as_raw_fd()
+    }
+}
+
+impl MmapRawSocket {
+    /// The default size of the blocks of the rings, in octets.
+    pub const DEFAULT_BLOCK_SIZE: usize = 1 << 16;
+
+    /// The default number of blocks of each ring.
+    pub const DEFAULT_BLOCK_COUNT: usize = 32;
+
+    /// Creates a socket bound to the interface called `name`, with rings of
+    /// the default size, i.e. 2 MiB each.
+    pub fn new(name: &str) -> io::Result<MmapRawSocket> {
+        Self::with_ring_size(name, Self::DEFAULT_BLOCK_SIZE, Self::DEFAULT_BLOCK_COUNT)
+    }
+
+    /// Creates a socket bound to the interface called `name`, with rings of
+    /// `block_count` blocks of `block_size` octets each.
+    ///
+    /// The block size must be a multiple of the page size, and large enough for
+    /// a frame of the size of the MTU of the interface.
+    pub fn with_ring_size(name: &str, block_size: usize,
+                          block_count: usize) -> io::Result<MmapRawSocket> {
+        let mut lower = sys::MmapRawSocketDesc::new(name, block_size, block_count)?;
+        lower.bind_interface()?;
+        let mtu = lower.interface_mtu()? + EthernetFrame::<&[u8]>::header_len();
+        Ok(MmapRawSocket {
+            lower: Rc::new(RefCell::new(lower)),
+            mtu:   mtu
+        })
+    }
+
+    /// Hands the frames sent so far over to the kernel.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.lower.borrow_mut().flush()
+    }
+}
+
+impl<'a> Device<'a> for MmapRawSocket {
+    type RxToken = RxToken;
+    type TxToken = TxToken;
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        DeviceCapabilities {
+            max_transmission_unit: self.mtu,
+            ..DeviceCapabilities::default()
+        }
+    }
+
+    fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+        let mut lower = self.lower.borrow_mut();
+        if let Err(err) = lower.flush() { panic!("{}", err) }
+        let (offset, len, truncated) = lower.recv()?;
+        let rx = RxToken { lower: self.lower.clone(), offset, len, truncated };
+        let tx = TxToken { lower: self.lower.clone() };
+        Some((rx, tx))
+    }
+
+    fn transmit(&'a mut self) -> Option<Self::TxToken> {
+        let mut lower = self.lower.borrow_mut();
+        if !lower.tx_available() {
+            if let Err(err) = lower.flush() { panic!("{}", err) }
+            return None
+        }
+        Some(TxToken { lower: self.lower.clone() })
+    }
+}
+
+#[doc(hidden)]
+pub struct RxToken {
+    lower:     Rc<RefCell<sys::MmapRawSocketDesc>>,
+    offset:    usize,
+    len:       usize,
+    truncated: bool
+}
+
+impl phy::RxToken for RxToken {
+    fn consume<R, F: FnOnce(&[u8]) -> Result<R>>(self, _timestamp: u64, f: F) -> Result<R> {
+        let lower = self.lower.borrow();
+        f(lower.rx_frame(self.offset, self.len))
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+#[doc(hidden)]
+pub struct TxToken {
+    lower:  Rc<RefCell<sys::MmapRawSocketDesc>>,
+}
+
+impl phy::TxToken for TxToken {
+    fn consume<R, F: FnOnce(&mut [u8]) -> Result<R>>(self, _timestamp: u64, len: usize, f: F)
+        -> Result<R>
+    {
+        let mut lower = self.lower.borrow_mut();
+        let result = match lower.tx_buffer(len) {
+            Some(buffer) => f(buffer)?,
+            None => {
+                if let Err(err) = lower.flush() { panic!("{}", err) }
+                return Err(Error::Exhausted)
+            }
+        };
+        lower.tx_commit(len);
+        Ok(result)
+    }
+}
@@ -5,11 +5,19 @@ for transmitting and receiving frames, [Device](trait.Device.html)
 and implementations of it:
 
   * the [_loopback_](struct.Loopback.html), for zero dependency testing;
-  * _middleware_ [Tracer](struct.Tracer.html) and
-    [FaultInjector](struct.FaultInjector.html), to facilitate debugging;
+  * _middleware_ [Tracer](struct.Tracer.html),
+    [FaultInjector](struct.FaultInjector.html) and [Shaper](struct.Shaper.html),
+    to facilitate debugging and limit the traffic,
+    [VlanDevice](struct.VlanDevice.html), to attach an interface to a VLAN,
+    and [Bridge](struct.Bridge.html), to pass traffic between two devices;
+  * the [_pcap replay_](struct.PcapReplay.html), to reproduce the traffic of a capture;
+  * the [SpiEthDevice](struct.SpiEthDevice.html) adapter, to drive an Ethernet controller
+    of a microcontroller through a driver of the [SpiEthDriver](trait.SpiEthDriver.html) trait;
   * _adapters_ [RawSocket](struct.RawSocket.html) and
     [TapInterface](struct.TapInterface.html), to transmit and receive frames
-    on the host OS.
+    on the host OS, and [UtunInterface](struct.UtunInterface.html) and
+    [WintunInterface](struct.WintunInterface.html), to transmit and receive packets
+    on macOS and Windows, respectively.
 
 # Examples
 
@@ -84,32 +92,70 @@ impl<'a> phy::TxToken for StmPhyTxToken<'a> {
 */
 
 use Result;
+use wire::{EthernetAddress, IpAddress};
 
-#[cfg(any(feature = "phy-raw_socket", feature = "phy-tap_interface"))]
+#[cfg(any(feature = "phy-raw_socket", feature = "phy-tap_interface", feature = "phy-xdp",
+          all(feature = "phy-wintun", windows)))]
 mod sys;
 
 mod tracer;
 mod fault_injector;
+mod shaper;
 mod pcap_writer;
+mod spi_eth;
+#[cfg(any(feature = "std", feature = "alloc"))]
+mod pcap_replay;
 #[cfg(any(feature = "std", feature = "alloc"))]
 mod loopback;
-#[cfg(all(feature = "phy-raw_socket", target_os = "linux"))]
+#[cfg(any(feature = "std", feature = "alloc"))]
+mod vlan;
+#[cfg(any(feature = "std", feature = "alloc"))]
+mod bridge;
+#[cfg(all(feature = "phy-raw_socket",
+          any(target_os = "linux", target_os = "freebsd", target_os = "openbsd")))]
 mod raw_socket;
-#[cfg(all(feature = "phy-tap_interface", target_os = "linux"))]
+#[cfg(all(feature = "phy-raw_socket", target_os = "linux"))]
+mod mmap_raw_socket;
+#[cfg(all(feature = "phy-tap_interface",
+          any(target_os = "linux", target_os = "freebsd", target_os = "openbsd")))]
 mod tap_interface;
-
-#[cfg(any(feature = "phy-raw_socket", feature = "phy-tap_interface"))]
+#[cfg(all(feature = "phy-xdp", target_os = "linux"))]
+mod xdp;
+#[cfg(all(feature = "phy-tap_interface", target_os = "macos"))]
+mod utun_interface;
+#[cfg(all(feature = "phy-wintun", windows))]
+mod wintun;
+
+#[cfg(any(feature = "phy-raw_socket", feature = "phy-tap_interface", feature = "phy-xdp"))]
 pub use self::sys::wait;
 
-pub use self::tracer::Tracer;
+pub use self::tracer::{Tracer, TracerDirection, TracerSink};
 pub use self::fault_injector::FaultInjector;
+pub use self::shaper::Shaper;
 pub use self::pcap_writer::{PcapLinkType, PcapMode, PcapSink, PcapWriter};
+pub use self::spi_eth::{SpiEthDevice, SpiEthDriver};
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub use self::pcap_replay::PcapReplay;
 #[cfg(any(feature = "std", feature = "alloc"))]
 pub use self::loopback::Loopback;
-#[cfg(all(feature = "phy-raw_socket", target_os = "linux"))]
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub use self::vlan::VlanDevice;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub use self::bridge::Bridge;
+#[cfg(all(feature = "phy-raw_socket",
+          any(target_os = "linux", target_os = "freebsd", target_os = "openbsd")))]
 pub use self::raw_socket::RawSocket;
-#[cfg(all(feature = "phy-tap_interface", target_os = "linux"))]
+#[cfg(all(feature = "phy-raw_socket", target_os = "linux"))]
+pub use self::mmap_raw_socket::MmapRawSocket;
+#[cfg(all(feature = "phy-tap_interface",
+          any(target_os = "linux", target_os = "freebsd", target_os = "openbsd")))]
 pub use self::tap_interface::TapInterface;
+#[cfg(all(feature = "phy-xdp", target_os = "linux"))]
+pub use self::xdp::XdpDevice;
+#[cfg(all(feature = "phy-tap_interface", target_os = "macos"))]
+pub use self::utun_interface::UtunInterface;
+#[cfg(all(feature = "phy-wintun", windows))]
+pub use self::wintun::WintunInterface;
 
 /// A tracer device for Ethernet frames.
 pub type EthernetTracer<T> = Tracer<T, super::wire::EthernetFrame<&'static [u8]>>;
@@ -125,6 +171,12 @@ pub enum Checksum {
     Tx,
     /// Ignore checksum completely.
     None,
+    /// Ignore checksum when receiving, and only fill in the checksum of the pseudo-header
+    /// before sending, for the device to compute the checksum of the rest of the packet.
+    ///
+    /// This is what devices offloading the TCP and UDP checksums commonly expect;
+    /// protocols without a pseudo-header treat it like `None`.
+    Partial,
 }
 
 impl Default for Checksum {
@@ -149,18 +201,35 @@ impl Checksum {
             _ => false
         }
     }
+
+    /// Returns whether only the pseudo-header checksum should be filled in when sending.
+    pub fn partial(&self) -> bool {
+        match *self {
+            Checksum::Partial => true,
+            _ => false
+        }
+    }
 }
 
 /// A description of checksum behavior for every supported protocol.
+///
+/// The checksums of TCP and UDP are described separately for IPv4 and IPv6, since
+/// devices often only offload them for one of the two.
 #[derive(Debug, Clone, Default)]
 pub struct ChecksumCapabilities {
     pub ipv4: Checksum,
     pub udpv4: Checksum,
     pub tcpv4: Checksum,
+    #[cfg(feature = "proto-ipv6")]
+    pub udpv6: Checksum,
+    #[cfg(feature = "proto-ipv6")]
+    pub tcpv6: Checksum,
     #[cfg(feature = "proto-ipv4")]
     pub icmpv4: Checksum,
     #[cfg(feature = "proto-ipv6")]
     pub icmpv6: Checksum,
+    #[cfg(feature = "proto-igmp")]
+    pub igmp: Checksum,
     dummy: (),
 }
 
@@ -172,13 +241,58 @@ impl ChecksumCapabilities {
             ipv4: Checksum::None,
             udpv4: Checksum::None,
             tcpv4: Checksum::None,
+            #[cfg(feature = "proto-ipv6")]
+            udpv6: Checksum::None,
+            #[cfg(feature = "proto-ipv6")]
+            tcpv6: Checksum::None,
             #[cfg(feature = "proto-ipv4")]
             icmpv4: Checksum::None,
             #[cfg(feature = "proto-ipv6")]
             icmpv6: Checksum::None,
+            #[cfg(feature = "proto-igmp")]
+            igmp: Checksum::None,
             ..Self::default()
         }
     }
+
+    /// Return the checksum behavior for UDP packets sent from or to `addr`.
+    pub(crate) fn udp(&self, addr: &IpAddress) -> Checksum {
+        match *addr {
+            #[cfg(feature = "proto-ipv6")]
+            IpAddress::Ipv6(_) => self.udpv6,
+            _ => self.udpv4
+        }
+    }
+
+    /// Return the checksum behavior for TCP segments sent from or to `addr`.
+    pub(crate) fn tcp(&self, addr: &IpAddress) -> Checksum {
+        match *addr {
+            #[cfg(feature = "proto-ipv6")]
+            IpAddress::Ipv6(_) => self.tcpv6,
+            _ => self.tcpv4
+        }
+    }
+}
+
+/// The medium of a device, i.e. what its frames consist of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Medium {
+    /// Ethernet II frames, with an Ethernet header.
+    Ethernet,
+    /// Bare IPv4 or IPv6 packets, without any link-layer header, as sent and received
+    /// e.g. through a tun device or a point-to-point link.
+    Ip,
+    /// IEEE 802.15.4 MAC frames, without the frame check sequence, carrying IPv6 packets
+    /// adapted by 6LoWPAN. The maximum transmission unit of such a device is the length of
+    /// its longest MAC frame, normally 125 octets.
+    #[cfg(feature = "proto-sixlowpan")]
+    Ieee802154,
+}
+
+impl Default for Medium {
+    fn default() -> Medium {
+        Medium::Ethernet
+    }
 }
 
 /// A description of device capabilities.
@@ -193,6 +307,10 @@ pub struct DeviceCapabilities {
     /// by this function.
     ///
     /// For Ethernet, MTU will fall between 576 (for IPv4) or 1280 (for IPv6) and 9216 octets.
+    ///
+    /// The MTU may change at runtime, e.g. once a point-to-point link is negotiated;
+    /// the interface takes the new one over the next time it is polled, and sizes
+    /// the packets sent from then on, including TCP segments, to it.
     pub max_transmission_unit: usize,
 
     /// Maximum burst size, in terms of MTU.
@@ -207,6 +325,60 @@ pub struct DeviceCapabilities {
     /// The set of protocols for which checksum can be computed in hardware.
     pub checksum: ChecksumCapabilities,
 
+    /// Maximum size of the TCP packets the device splits into segments itself,
+    /// in the same terms as `max_transmission_unit`.
+    ///
+    /// A device that performs TCP segmentation offload accepts packets larger than the MTU,
+    /// which carry as much payload as several segments, together with the size of the
+    /// segments; see [TxToken::set_segment_size](trait.TxToken.html#method.set_segment_size).
+    /// It has to split them into segments of that size, with the headers copied and
+    /// the sequence numbers, lengths and checksums adjusted.
+    ///
+    /// If `None`, the device does not segment packets.
+    pub max_tso_size: Option<usize>,
+
+    /// The medium of the device.
+    ///
+    /// The frames sent and received through the device start with an Ethernet header,
+    /// unless the medium is IP.
+    pub medium: Medium,
+
+    /// Whether the device pads Ethernet frames shorter than the minimum frame size
+    /// to that size itself.
+    ///
+    /// If not, which is the default, the interface pads them with zeros before sending them,
+    /// since many controllers drop such runt frames. This is ignored for the IP medium.
+    pub pads_frames: bool,
+
+    /// Whether the Ethernet frames received from the device still end with their
+    /// frame check sequence.
+    ///
+    /// If so, the interface verifies the FCS of every frame, drops the frames whose
+    /// FCS is wrong, and strips it from the others. This is ignored for the IP medium.
+    pub rx_fcs: bool,
+
+    /// Whether the Ethernet frames sent through the device have to end with their
+    /// frame check sequence, because the device does not compute it itself.
+    ///
+    /// If so, the interface computes the FCS of every frame, after the padding,
+    /// and appends it, so that the frames are 4 octets longer than the MTU at most.
+    /// Such a device cannot segment TCP packets, since it could not compute the FCS
+    /// of the segments either. This is ignored for the IP medium.
+    pub tx_fcs: bool,
+
+    /// Whether the device timestamps frames in hardware, e.g. with a PTP hardware clock.
+    ///
+    /// If so, the device returns the time at which each frame was received from
+    /// [RxToken::hw_timestamp], and the time at which a frame was sent, if it was asked to
+    /// with [TxToken::request_hw_timestamp], from [Device::tx_hw_timestamp]. Hardware
+    /// timestamps are in nanoseconds, on the clock of the device, which is unrelated
+    /// to the timestamps the interface is polled with.
+    ///
+    /// [RxToken::hw_timestamp]: trait.RxToken.html#method.hw_timestamp
+    /// [TxToken::request_hw_timestamp]: trait.TxToken.html#method.request_hw_timestamp
+    /// [Device::tx_hw_timestamp]: trait.Device.html#method.tx_hw_timestamp
+    pub hw_timestamping: bool,
+
     /// Only present to prevent people from trying to initialize every field of DeviceLimits,
     /// which would not let us add new fields in the future.
     dummy: ()
@@ -234,6 +406,82 @@ pub trait Device<'a> {
 
     /// Get a description of device capabilities.
     fn capabilities(&self) -> DeviceCapabilities;
+
+    /// Add a hardware address to the multicast filter of the device, so that the frames
+    /// sent to it are received.
+    ///
+    /// The default implementation does nothing, which suits devices that receive every
+    /// multicast frame.
+    fn add_multicast_filter(&mut self, _addr: EthernetAddress) {}
+
+    /// Remove a hardware address from the multicast filter of the device.
+    fn remove_multicast_filter(&mut self, _addr: EthernetAddress) {}
+
+    /// Return the part of a packet lent out with the given loan, e.g. with
+    /// [RxLoan::slice](struct.RxLoan.html#method.slice), if it has not been released.
+    ///
+    /// See [RxToken::consume_lend](trait.RxToken.html#method.consume_lend).
+    /// The default implementation returns `None`.
+    fn lent(&self, _loan: &RxLoan) -> Option<&[u8]> { None }
+
+    /// Release the packet lent out with the given loan, so that its buffer is reused.
+    ///
+    /// The default implementation does nothing.
+    fn release(&mut self, _loan: RxLoan) {}
+
+    /// Return the hardware timestamp, in nanoseconds, of the last frame sent with
+    /// a [timestamp request](trait.TxToken.html#method.request_hw_timestamp), once it
+    /// is known, and forget it.
+    ///
+    /// A device only has to remember the timestamp of one frame; requesting another one
+    /// before this one is returned may lose it. The default implementation returns `None`.
+    fn tx_hw_timestamp(&mut self) -> Option<u64> { None }
+}
+
+/// A received packet, or a part of it, which a device keeps in place for the application.
+///
+/// A loan identifies the packet by a number the device chooses, and is valid until it is
+/// passed to [Device::release]; releasing it twice has no effect.
+///
+/// [Device::release]: trait.Device.html#method.release
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RxLoan {
+    id:     usize,
+    offset: usize,
+    len:    usize,
+}
+
+impl RxLoan {
+    /// Create a loan of the whole packet of `len` octets identified by `id`.
+    pub fn new(id: usize, len: usize) -> RxLoan {
+        RxLoan { id, offset: 0, len }
+    }
+
+    /// Return the number the device identifies the packet with.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Return the offset of the lent part in the packet.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Return the length of the lent part.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return the lent part of the given packet, or `None` if it is too short.
+    pub fn slice<'a>(&self, packet: &'a [u8]) -> Option<&'a [u8]> {
+        packet.get(self.offset..self.offset + self.len)
+    }
+
+    /// Return a loan of the part of `len` octets at `offset` of the lent part.
+    pub(crate) fn narrow(&self, offset: usize, len: usize) -> RxLoan {
+        debug_assert!(offset + len <= self.len);
+        RxLoan { id: self.id, offset: self.offset + offset, len }
+    }
 }
 
 /// A token to receive a single network packet.
@@ -243,10 +491,44 @@ pub trait RxToken {
     /// This method receives a packet and then calls the given closure `f` with the raw
     /// packet bytes as argument.
     ///
+    /// A device may also hold the packet back, e.g. until it is due, by returning
+    /// `Err(Error::Exhausted)` without calling `f`; the interface then stops receiving
+    /// packets until it is polled again.
+    ///
     /// The timestamp must be a number of milliseconds, monotonically increasing since an
     /// arbitrary moment in time, such as system startup.
     fn consume<R, F>(self, timestamp: u64, f: F) -> Result<R>
         where F: FnOnce(&[u8]) -> Result<R>;
+
+    /// Returns whether the packet received with this token was truncated, i.e. only
+    /// its beginning fit into the buffer of the device.
+    ///
+    /// The interface drops truncated packets rather than processing a part of them.
+    /// The default implementation returns `false`.
+    fn is_truncated(&self) -> bool { false }
+
+    /// Returns the time, in nanoseconds on the clock of the device, at which the packet
+    /// to be received with this token arrived, if the device timestamps frames in hardware.
+    ///
+    /// The default implementation returns `None`.
+    fn hw_timestamp(&self) -> Option<u64> { None }
+
+    /// Consumes the token to receive a single network packet, which the device may
+    /// lend out to the application instead of dropping it once `f` returns.
+    ///
+    /// A device that can keep the packet in place, e.g. in a ring of DMA buffers, passes
+    /// `f` a loan of it along with the packet. If `f` returns `true` along with its result,
+    /// the device keeps the packet, and returns it from [Device::lent] until the loan is
+    /// passed to [Device::release]. The interface uses this to let sockets receive
+    /// payloads without copying them. The default implementation lends nothing.
+    ///
+    /// [Device::lent]: trait.Device.html#method.lent
+    /// [Device::release]: trait.Device.html#method.release
+    fn consume_lend<R, F>(self, timestamp: u64, f: F) -> Result<R>
+        where Self: Sized, F: FnOnce(&[u8], Option<RxLoan>) -> (Result<R>, bool)
+    {
+        self.consume(timestamp, |buffer| f(buffer, None).0)
+    }
 }
 
 /// A token to transmit a single network packet.
@@ -262,4 +544,44 @@ pub trait TxToken {
     /// arbitrary moment in time, such as system startup.
     fn consume<R, F>(self, timestamp: u64, len: usize, f: F) -> Result<R>
         where F: FnOnce(&mut [u8]) -> Result<R>;
+
+    /// Requests the TCP packet sent with this token to be split into segments carrying
+    /// up to `segment_size` octets of payload each.
+    ///
+    /// This is only called for packets larger than the MTU, and only if the device has
+    /// a `max_tso_size` capability. The default implementation does nothing.
+    fn set_segment_size(&mut self, _segment_size: usize) {}
+
+    /// Requests the packet sent with this token to be timestamped in hardware, so that
+    /// the time at which it left is returned from [Device::tx_hw_timestamp] later.
+    ///
+    /// This is only called if the device has the `hw_timestamping` capability.
+    /// The default implementation does nothing.
+    ///
+    /// [Device::tx_hw_timestamp]: trait.Device.html#method.tx_hw_timestamp
+    fn request_hw_timestamp(&mut self) {}
+
+    /// Consumes the token to send a single network packet, made of a header of `header_len`
+    /// octets, which the closure `f` constructs as in [consume](#tymethod.consume),
+    /// followed by the contents of the `payload` buffers.
+    ///
+    /// The interface sends e.g. TCP segments this way, with their payload straight from
+    /// the socket buffer. A device that transmits a packet from several buffers, e.g. with
+    /// a chain of DMA descriptors, can send the payload buffers in place. The default
+    /// implementation copies them after the header, into a buffer from `consume`.
+    fn consume_gather<R, F>(self, timestamp: u64, header_len: usize, payload: &[&[u8]],
+                            f: F) -> Result<R>
+        where Self: Sized, F: FnOnce(&mut [u8]) -> Result<R>
+    {
+        let len = payload.iter().fold(header_len, |len, buffer| len + buffer.len());
+        self.consume(timestamp, len, |tx_buffer| {
+            let result = f(&mut tx_buffer[..header_len])?;
+            let mut offset = header_len;
+            for buffer in payload {
+                tx_buffer[offset..offset + buffer.len()].copy_from_slice(buffer);
+                offset += buffer.len();
+            }
+            Ok(result)
+        })
+    }
 }
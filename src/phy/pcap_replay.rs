@@ -0,0 +1,562 @@
+#[cfg(feature = "std")]
+use std::vec::Vec;
+#[cfg(feature = "std")]
+use std::{io, fs, path};
+#[cfg(feature = "alloc")]
+use alloc::Vec;
+use core::cmp;
+use byteorder::{ByteOrder, BigEndian, LittleEndian};
+
+use {Error, Result};
+use phy::{self, Device, DeviceCapabilities, Medium, PcapLinkType};
+
+/// The largest number of interfaces of a pcapng section whose frames are replayed.
+const MAX_INTERFACES: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// The libpcap format, with microsecond or nanosecond timestamps.
+    Pcap { nanos: bool },
+    /// The pcapng format.
+    PcapNg
+}
+
+/// An interface described in a pcapng section.
+#[derive(Debug, Clone, Copy)]
+struct Interface {
+    link_type: PcapLinkType,
+    /// The resolution of the timestamps, as the `if_tsresol` option.
+    tsresol:   u8
+}
+
+/// A frame of the capture, whether it was captured only in part, and the position
+/// of the record following it.
+#[derive(Debug, Clone, Copy)]
+struct Record {
+    frame:     (usize, usize),
+    truncated: bool,
+    timestamp: u64,
+    next:      usize
+}
+
+#[derive(Debug, Clone)]
+struct Cursor {
+    format:     Format,
+    big_endian: bool,
+    link_type:  Option<PcapLinkType>,
+    offset:     usize,
+    interfaces: [Option<Interface>; MAX_INTERFACES],
+    // The timestamp of the last frame, used for the frames without a timestamp.
+    last_timestamp: u64,
+    realtime:   bool,
+    // The moment the first frame was replayed at, and its timestamp in the capture.
+    origin:     Option<(u64, u64)>
+}
+
+impl Cursor {
+    fn read_u16(&self, data: &[u8], at: usize) -> Option<u16> {
+        let bytes = data.get(at..at + 2)?;
+        if self.big_endian {
+            Some(BigEndian::read_u16(bytes))
+        } else {
+            Some(LittleEndian::read_u16(bytes))
+        }
+    }
+
+    fn read_u32(&self, data: &[u8], at: usize) -> Option<u32> {
+        let bytes = data.get(at..at + 4)?;
+        if self.big_endian {
+            Some(BigEndian::read_u32(bytes))
+        } else {
+            Some(LittleEndian::read_u32(bytes))
+        }
+    }
+
+    /// Return the next frame of the capture, if there is any, skipping and interpreting
+    /// the records that are not frames.
+    ///
+    /// The cursor is not moved past the frame, so that it can be returned again.
+    fn next_record(&mut self, data: &[u8]) -> Option<Record> {
+        loop {
+            let record = match self.format {
+                Format::Pcap { nanos } => return self.pcap_record(data, nanos),
+                Format::PcapNg => self.pcapng_block(data)?
+            };
+            match record {
+                Some(record) => return Some(record),
+                None => continue
+            }
+        }
+    }
+
+    fn pcap_record(&mut self, data: &[u8], nanos: bool) -> Option<Record> {
+        let seconds   = self.read_u32(data, self.offset)? as u64;
+        let fraction  = self.read_u32(data, self.offset + 4)? as u64;
+        let frame_len = self.read_u32(data, self.offset + 8)? as usize;
+        let orig_len  = self.read_u32(data, self.offset + 12)? as usize;
+        let start = self.offset + 16;
+        if data.len() < start + frame_len {
+            net_debug!("pcap: truncated record at offset {}", self.offset);
+            return None
+        }
+
+        let fraction = if nanos { fraction / 1_000_000 } else { fraction / 1_000 };
+        Some(Record {
+            frame:     (start, frame_len),
+            truncated: orig_len > frame_len,
+            timestamp: seconds * 1_000 + fraction,
+            next:      start + frame_len
+        })
+    }
+
+    /// Interpret the next block of a pcapng capture, and return the frame it holds,
+    /// if any. The cursor is moved past the blocks that do not hold a frame.
+    fn pcapng_block(&mut self, data: &[u8]) -> Option<Option<Record>> {
+        let offset = self.offset;
+        let block_type = self.read_u32(data, offset)?;
+        if block_type == 0x0a0d0d0a {
+            // The byte order of a section is given by its header.
+            let magic = data.get(offset + 8..offset + 12)?;
+            if magic == [0x1a, 0x2b, 0x3c, 0x4d] {
+                self.big_endian = true
+            } else if magic == [0x4d, 0x3c, 0x2b, 0x1a] {
+                self.big_endian = false
+            } else {
+                net_debug!("pcapng: invalid section header at offset {}", offset);
+                return None
+            }
+            self.interfaces = [None; MAX_INTERFACES];
+        }
+
+        let block_len = self.read_u32(data, offset + 4)? as usize;
+        if block_len < 12 || block_len % 4 != 0 || data.len() < offset + block_len {
+            net_debug!("pcapng: truncated block at offset {}", offset);
+            return None
+        }
+        let block_end = offset + block_len - 4;
+
+        let record = match block_type {
+            // Interface Description Block
+            0x00000001 => {
+                let link_type = PcapLinkType::from(self.read_u16(data, offset + 8)? as u32);
+                let tsresol = self.if_tsresol(data, offset + 16, block_end);
+                if self.link_type.is_none() {
+                    self.link_type = Some(link_type)
+                }
+                match self.interfaces.iter_mut().find(|interface| interface.is_none()) {
+                    Some(slot) => *slot = Some(Interface { link_type, tsresol }),
+                    None => {
+                        net_debug!("pcapng: too many interfaces, ignoring frames");
+                    }
+                }
+                None
+            }
+            // Enhanced Packet Block
+            0x00000006 => {
+                let index = self.read_u32(data, offset + 8)? as usize;
+                let timestamp = (self.read_u32(data, offset + 12)? as u64) << 32 |
+                                 self.read_u32(data, offset + 16)? as u64;
+                let frame_len = self.read_u32(data, offset + 20)? as usize;
+                let orig_len = self.read_u32(data, offset + 24)? as usize;
+                let start = offset + 28;
+                match self.interfaces.get(index).and_then(|interface| *interface) {
+                    Some(interface) if Some(interface.link_type) == self.link_type &&
+                                       start + frame_len <= block_end => {
+                        Some(Record {
+                            frame:     (start, frame_len),
+                            truncated: orig_len > frame_len,
+                            timestamp: to_millis(timestamp, interface.tsresol),
+                            next:      offset + block_len
+                        })
+                    }
+                    _ => None
+                }
+            }
+            // Simple Packet Block, whose frames come from the first interface
+            0x00000003 => {
+                let orig_len = self.read_u32(data, offset + 8)? as usize;
+                let start = offset + 12;
+                let frame_len = cmp::min(orig_len, block_end.saturating_sub(start));
+                match self.interfaces[0] {
+                    Some(interface) if Some(interface.link_type) == self.link_type => {
+                        Some(Record {
+                            frame:     (start, frame_len),
+                            truncated: orig_len > frame_len,
+                            timestamp: self.last_timestamp,
+                            next:      offset + block_len
+                        })
+                    }
+                    _ => None
+                }
+            }
+            _ => None
+        };
+
+        if record.is_none() {
+            self.offset = offset + block_len
+        }
+        Some(record)
+    }
+
+    /// Return the `if_tsresol` option among the options of an Interface Description Block,
+    /// or its default value, i.e. microseconds.
+    fn if_tsresol(&self, data: &[u8], mut offset: usize, end: usize) -> u8 {
+        while offset + 4 <= end {
+            let (code, len) = match (self.read_u16(data, offset), self.read_u16(data, offset + 2)) {
+                (Some(code), Some(len)) => (code, len as usize),
+                _ => break
+            };
+            match code {
+                0 => break,
+                9 if len == 1 => return data[offset + 4],
+                _ => ()
+            }
+            offset += 4 + ((len + 3) & !3);
+        }
+        6
+    }
+}
+
+/// Convert a timestamp of the given `if_tsresol` resolution to milliseconds.
+fn to_millis(timestamp: u64, tsresol: u8) -> u64 {
+    if tsresol & 0x80 == 0 {
+        let exponent = (tsresol & 0x7f) as u32;
+        if exponent >= 3 {
+            10u64.checked_pow(exponent - 3).map_or(0, |unit| timestamp / unit)
+        } else {
+            timestamp * 10u64.pow(3 - exponent)
+        }
+    } else {
+        let exponent = (tsresol & 0x7f) as u32;
+        if exponent >= 64 { return 0 }
+        let unit = 1u64 << exponent;
+        timestamp / unit * 1_000 + timestamp % unit * 1_000 / unit
+    }
+}
+
+/// A device that replays the frames of a packet capture.
+///
+/// The capture, in the [libpcap] or [pcapng] format, is held in memory; its link type
+/// determines the medium of the device. The frames of a pcapng capture are replayed
+/// from the interfaces with the link type of the first one. Every frame is received
+/// once, in order, and the frames transmitted through the device are discarded;
+/// wrap it in a [PcapWriter] to capture them.
+///
+/// By default, the frames are received as fast as they are asked for. In real time mode,
+/// they are received no sooner than the time between them in the capture allows,
+/// measured from the moment the first one is received, against the timestamps passed
+/// to the device. A frame that is not due yet is refused with `Err(Error::Exhausted)`,
+/// so that [poll] returns; [poll_at](#method.poll_at) returns when it is due.
+///
+/// [libpcap]: https://wiki.wireshark.org/Development/LibpcapFileFormat
+/// [pcapng]: https://github.com/pcapng/pcapng
+/// [PcapWriter]: struct.PcapWriter.html
+/// [poll]: ../iface/struct.EthernetInterface.html#method.poll
+#[derive(Debug)]
+pub struct PcapReplay<T: AsRef<[u8]>> {
+    data:   T,
+    cursor: Cursor,
+    medium: Medium,
+}
+
+impl<T: AsRef<[u8]>> PcapReplay<T> {
+    /// Creates a device replaying the given capture.
+    ///
+    /// Returns `Err(Error::Unrecognized)` if the capture is not in a supported format,
+    /// or its link type is neither Ethernet nor IP, and `Err(Error::Truncated)`
+    /// if it does not even hold a complete header.
+    pub fn new(data: T) -> Result<PcapReplay<T>> {
+        let cursor = {
+            let data = data.as_ref();
+            if data.len() < 4 { return Err(Error::Truncated) }
+            let (format, big_endian) = match (data[0], data[1], data[2], data[3]) {
+                (0xa1, 0xb2, 0xc3, 0xd4) => (Format::Pcap { nanos: false }, true),
+                (0xd4, 0xc3, 0xb2, 0xa1) => (Format::Pcap { nanos: false }, false),
+                (0xa1, 0xb2, 0x3c, 0x4d) => (Format::Pcap { nanos: true  }, true),
+                (0x4d, 0x3c, 0xb2, 0xa1) => (Format::Pcap { nanos: true  }, false),
+                (0x0a, 0x0d, 0x0d, 0x0a) => (Format::PcapNg, false),
+                _ => return Err(Error::Unrecognized)
+            };
+            let mut cursor = Cursor {
+                format, big_endian,
+                link_type:  None,
+                offset:     0,
+                interfaces: [None; MAX_INTERFACES],
+                last_timestamp: 0,
+                realtime:   false,
+                origin:     None
+            };
+
+            match format {
+                Format::Pcap { .. } => {
+                    if data.len() < 24 { return Err(Error::Truncated) }
+                    let link_type = cursor.read_u32(data, 20).ok_or(Error::Truncated)?;
+                    cursor.link_type = Some(PcapLinkType::from(link_type));
+                    cursor.offset = 24;
+                }
+                Format::PcapNg => {
+                    // Look for the first interface without moving the cursor.
+                    let mut probe = cursor.clone();
+                    probe.next_record(data);
+                    cursor.link_type = probe.link_type;
+                }
+            }
+            cursor
+        };
+
+        let medium = match cursor.link_type {
+            Some(PcapLinkType::Ethernet) => Medium::Ethernet,
+            Some(PcapLinkType::Ip) => Medium::Ip,
+            #[cfg(feature = "proto-sixlowpan")]
+            Some(PcapLinkType::Ieee802154) => Medium::Ieee802154,
+            _ => return Err(Error::Unrecognized)
+        };
+        Ok(PcapReplay { data, cursor, medium })
+    }
+
+    /// Return the link type of the capture.
+    pub fn link_type(&self) -> PcapLinkType {
+        self.cursor.link_type.unwrap()
+    }
+
+    /// Query whether the frames are replayed in real time.
+    pub fn realtime(&self) -> bool {
+        self.cursor.realtime
+    }
+
+    /// Set whether the frames are replayed in real time.
+    pub fn set_realtime(&mut self, realtime: bool) {
+        self.cursor.realtime = realtime
+    }
+
+    /// Return the moment at which the next frame is due, `Some(0)` meaning immediately,
+    /// or `None` if every frame was replayed.
+    pub fn poll_at(&mut self) -> Option<u64> {
+        let &mut Self { ref data, ref mut cursor, .. } = self;
+        let record = cursor.next_record(data.as_ref())?;
+        match cursor.origin {
+            Some((started_at, first)) if cursor.realtime =>
+                Some(started_at + record.timestamp.saturating_sub(first)),
+            _ => Some(0)
+        }
+    }
+
+    /// Return the capture, consuming the device.
+    pub fn into_inner(self) -> T {
+        self.data
+    }
+}
+
+#[cfg(feature = "std")]
+impl PcapReplay<Vec<u8>> {
+    /// Creates a device replaying the capture in the file at the given path.
+    pub fn open<P: AsRef<path::Path>>(path: P) -> io::Result<PcapReplay<Vec<u8>>> {
+        PcapReplay::new(fs::read(path)?).map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("cannot replay capture: {}", err))
+        })
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + 'a> Device<'a> for PcapReplay<T> {
+    type RxToken = RxToken<'a>;
+    type TxToken = TxToken;
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        DeviceCapabilities {
+            max_transmission_unit: 65535,
+            medium: self.medium,
+            ..DeviceCapabilities::default()
+        }
+    }
+
+    fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+        let &mut Self { ref data, ref mut cursor, .. } = self;
+        let data = data.as_ref();
+        let record = cursor.next_record(data)?;
+        Some((RxToken { data, cursor, record }, TxToken))
+    }
+
+    fn transmit(&'a mut self) -> Option<Self::TxToken> {
+        Some(TxToken)
+    }
+}
+
+#[doc(hidden)]
+pub struct RxToken<'a> {
+    data:   &'a [u8],
+    cursor: &'a mut Cursor,
+    record: Record
+}
+
+impl<'a> phy::RxToken for RxToken<'a> {
+    fn consume<R, F: FnOnce(&[u8]) -> Result<R>>(self, timestamp: u64, f: F) -> Result<R> {
+        let RxToken { data, cursor, record } = self;
+        if cursor.realtime {
+            let (started_at, first) = *cursor.origin.get_or_insert((timestamp, record.timestamp));
+            if record.timestamp.saturating_sub(first) > timestamp.saturating_sub(started_at) {
+                return Err(Error::Exhausted)
+            }
+        }
+
+        cursor.offset = record.next;
+        cursor.last_timestamp = record.timestamp;
+        let (start, len) = record.frame;
+        f(&data[start..start + len])
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.record.truncated
+    }
+}
+
+#[doc(hidden)]
+pub struct TxToken;
+
+impl phy::TxToken for TxToken {
+    fn consume<R, F>(self, _timestamp: u64, len: usize, f: F) -> Result<R>
+        where F: FnOnce(&mut [u8]) -> Result<R>
+    {
+        let mut buffer = Vec::new();
+        buffer.resize(len, 0);
+        f(&mut buffer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use byteorder::NativeEndian;
+    use phy::{PcapSink, RxToken as _RxToken};
+    use super::*;
+
+    fn receive<T: AsRef<[u8]>>(replay: &mut PcapReplay<T>,
+                               timestamp: u64) -> Option<Result<Vec<u8>>> {
+        replay.receive().map(|(token, _)| token.consume(timestamp, |frame| Ok(frame.to_vec())))
+    }
+
+    fn pcap() -> Vec<u8> {
+        let sink = RefCell::new(Vec::new());
+        sink.global_header(PcapLinkType::Ethernet);
+        sink.packet(1_000, &[1; 14]);
+        sink.packet(1_100, &[2; 20]);
+        sink.into_inner()
+    }
+
+    #[test]
+    fn test_pcap() {
+        let mut replay = PcapReplay::new(pcap()).unwrap();
+        assert_eq!(replay.link_type(), PcapLinkType::Ethernet);
+        assert_eq!(replay.capabilities().medium, Medium::Ethernet);
+        assert_eq!(receive(&mut replay, 0), Some(Ok(vec![1; 14])));
+        assert_eq!(replay.poll_at(), Some(0));
+        assert_eq!(receive(&mut replay, 0), Some(Ok(vec![2; 20])));
+        assert_eq!(replay.poll_at(), None);
+        assert_eq!(receive(&mut replay, 0), None);
+    }
+
+    #[test]
+    fn test_pcap_realtime() {
+        let mut replay = PcapReplay::new(pcap()).unwrap();
+        replay.set_realtime(true);
+        assert_eq!(receive(&mut replay, 5_000), Some(Ok(vec![1; 14])));
+        assert_eq!(replay.poll_at(), Some(5_100));
+        assert_eq!(receive(&mut replay, 5_050), Some(Err(Error::Exhausted)));
+        assert_eq!(receive(&mut replay, 5_100), Some(Ok(vec![2; 20])));
+    }
+
+    #[test]
+    fn test_pcap_big_endian_nanos() {
+        let mut data = vec![0xa1, 0xb2, 0x3c, 0x4d, 0, 2, 0, 4];
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&[0, 0, 0xff, 0xff, 0, 0, 0, 101]);
+        data.extend_from_slice(&[0, 0, 0, 1, 0x1d, 0xcd, 0x65, 0x00, 0, 0, 0, 2, 0, 0, 0, 2]);
+        data.extend_from_slice(&[0x45, 0x00]);
+        let mut replay = PcapReplay::new(&data[..]).unwrap();
+        assert_eq!(replay.capabilities().medium, Medium::Ip);
+        replay.set_realtime(true);
+        assert_eq!(receive(&mut replay, 0), Some(Ok(vec![0x45, 0x00])));
+        assert_eq!(replay.cursor.last_timestamp, 1_500);
+    }
+
+    #[test]
+    fn test_pcap_snapped() {
+        let mut data = pcap();
+        // Record the first frame as captured from a longer one.
+        NativeEndian::write_u32(&mut data[24 + 12..], 60);
+        let mut replay = PcapReplay::new(data).unwrap();
+        let (token, _) = replay.receive().unwrap();
+        assert!(token.is_truncated());
+        assert_eq!(token.consume(0, |frame| Ok(frame.len())), Ok(14));
+        assert!(!replay.receive().unwrap().0.is_truncated());
+    }
+
+    #[test]
+    fn test_pcap_truncated() {
+        let mut data = pcap();
+        let len = data.len();
+        data.truncate(len - 1);
+        let mut replay = PcapReplay::new(data).unwrap();
+        assert_eq!(receive(&mut replay, 0), Some(Ok(vec![1; 14])));
+        assert_eq!(receive(&mut replay, 0), None);
+
+        assert_eq!(PcapReplay::new(&[0xd4, 0xc3, 0xb2, 0xa1][..]).err(), Some(Error::Truncated));
+        assert_eq!(PcapReplay::new(&[0; 24][..]).err(), Some(Error::Unrecognized));
+    }
+
+    fn block(data: &mut Vec<u8>, block_type: u32, body: &[u8]) {
+        let mut bytes = [0; 4];
+        LittleEndian::write_u32(&mut bytes, block_type);
+        data.extend_from_slice(&bytes);
+        LittleEndian::write_u32(&mut bytes, 12 + body.len() as u32);
+        data.extend_from_slice(&bytes);
+        data.extend_from_slice(body);
+        data.extend_from_slice(&bytes);
+    }
+
+    fn enhanced_packet(data: &mut Vec<u8>, interface: u8, timestamp: u64, frame: &[u8]) {
+        let mut body = vec![interface, 0, 0, 0];
+        let mut bytes = [0; 4];
+        LittleEndian::write_u32(&mut bytes, (timestamp >> 32) as u32);
+        body.extend_from_slice(&bytes);
+        LittleEndian::write_u32(&mut bytes, timestamp as u32);
+        body.extend_from_slice(&bytes);
+        LittleEndian::write_u32(&mut bytes, frame.len() as u32);
+        body.extend_from_slice(&bytes);
+        body.extend_from_slice(&bytes);
+        body.extend_from_slice(frame);
+        while body.len() % 4 != 0 { body.push(0) }
+        block(data, 6, &body);
+    }
+
+    #[test]
+    fn test_pcapng() {
+        let mut data = Vec::new();
+        block(&mut data, 0x0a0d0d0a, &[0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0,
+                                       0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        // An Ethernet interface with nanosecond timestamps, and an IP one.
+        block(&mut data, 1, &[1, 0, 0, 0, 0, 0, 0, 0, 9, 0, 1, 0, 9, 0, 0, 0, 0, 0, 0, 0]);
+        block(&mut data, 1, &[101, 0, 0, 0, 0, 0, 0, 0]);
+        enhanced_packet(&mut data, 0, 2_000_000_000, &[1; 14]);
+        enhanced_packet(&mut data, 1, 2_000_000_000, &[0x45; 20]);
+        // A Name Resolution Block, which is skipped.
+        block(&mut data, 4, &[0, 0, 0, 0]);
+        enhanced_packet(&mut data, 0, 2_250_000_000, &[3; 15]);
+
+        let mut replay = PcapReplay::new(data).unwrap();
+        assert_eq!(replay.link_type(), PcapLinkType::Ethernet);
+        replay.set_realtime(true);
+        assert_eq!(receive(&mut replay, 100), Some(Ok(vec![1; 14])));
+        assert_eq!(replay.poll_at(), Some(350));
+        assert_eq!(receive(&mut replay, 349), Some(Err(Error::Exhausted)));
+        assert_eq!(receive(&mut replay, 350), Some(Ok(vec![3; 15])));
+        assert_eq!(receive(&mut replay, 1_000), None);
+    }
+
+    #[test]
+    fn test_to_millis() {
+        assert_eq!(to_millis(1_234_567, 6), 1_234);
+        assert_eq!(to_millis(1_234_567_890, 9), 1_234);
+        assert_eq!(to_millis(12, 1), 1_200);
+        assert_eq!(to_millis(3 << 9, 0x80 | 10), 1_500);
+    }
+}
@@ -5,15 +5,18 @@ use std::io::Write;
 use byteorder::{ByteOrder, NativeEndian};
 
 use Result;
-use phy::{self, DeviceCapabilities, Device};
+use wire::EthernetAddress;
+use phy::{self, DeviceCapabilities, Device, RxLoan};
 
 enum_with_unknown! {
     /// Captured packet header type.
     pub doc enum PcapLinkType(u32) {
         /// Ethernet frames
-        Ethernet =   1,
+        Ethernet   =   1,
         /// IPv4 or IPv6 packets (depending on the version field)
-        Ip       = 101
+        Ip         = 101,
+        /// IEEE 802.15.4 frames, without the frame check sequence
+        Ieee802154 = 230
     }
 }
 
@@ -83,6 +86,87 @@ pub trait PcapSink {
         self.packet_header(timestamp, packet.len());
         self.write(packet)
     }
+
+    /// Write the pcapng Section Header Block into the sink, which starts a capture.
+    ///
+    /// See also the note for [global_header](#method.global_header).
+    fn section_header(&self) {
+        self.write_u32(0x0a0d0d0a);       // block type
+        self.write_u32(28);               // block total length
+        self.write_u32(0x1a2b3c4d);       // byte-order magic
+        self.write_u16(1);                // major version
+        self.write_u16(0);                // minor version
+        self.write_u32(0xffffffff);       // section length (= unspecified)
+        self.write_u32(0xffffffff);
+        self.write_u32(28);               // block total length
+    }
+
+    /// Write a pcapng Interface Description Block into the sink, with the given name
+    /// (if it is not empty) and nanosecond timestamps.
+    ///
+    /// The interfaces are numbered from zero, in the order they are described.
+    /// See also the note for [global_header](#method.global_header).
+    fn interface_description(&self, link_type: PcapLinkType, name: &str) {
+        let name_len = if name.is_empty() { 0 } else { 4 + (name.len() + 3) / 4 * 4 };
+        let block_len = 32 + name_len;
+
+        self.write_u32(0x00000001);       // block type
+        self.write_u32(block_len as u32); // block total length
+        self.write_u16(u32::from(link_type) as u16); // link-layer header type
+        self.write_u16(0);                // reserved
+        self.write_u32(65535);            // maximum packet length
+        if !name.is_empty() {
+            self.write_u16(2);            // option if_name
+            self.write_u16(name.len() as u16);
+            self.write(name.as_bytes());
+            self.write(&[0; 3][..(4 - name.len() % 4) % 4]);
+        }
+        self.write_u16(9);                // option if_tsresol
+        self.write_u16(1);
+        self.write(&[9, 0, 0, 0]);        // nanoseconds
+        self.write_u32(0);                // option opt_endofopt
+        self.write_u32(block_len as u32); // block total length
+    }
+
+    /// Write the pcapng Enhanced Packet Block header of a packet captured on the given
+    /// interface into the sink, which has to be followed by the packet data and
+    /// the [trailer](#method.enhanced_packet_trailer).
+    ///
+    /// See also the note for [global_header](#method.global_header).
+    ///
+    /// # Panics
+    /// This function panics if `length` is greater than 65535.
+    fn enhanced_packet_header(&self, interface_id: u32, timestamp: u64, length: usize) {
+        assert!(length <= 65535);
+
+        let nanos = timestamp * 1_000_000;
+        let block_len = 32 + (length + 3) / 4 * 4;
+        self.write_u32(0x00000006);       // block type
+        self.write_u32(block_len as u32); // block total length
+        self.write_u32(interface_id);     // interface ID
+        self.write_u32((nanos >> 32) as u32); // timestamp (high)
+        self.write_u32(nanos as u32);     // timestamp (low)
+        self.write_u32(length as u32);    // captured length
+        self.write_u32(length as u32);    // original length
+    }
+
+    /// Write the padding of a packet of the given length and the end of its pcapng
+    /// Enhanced Packet Block into the sink.
+    fn enhanced_packet_trailer(&self, length: usize) {
+        let block_len = 32 + (length + 3) / 4 * 4;
+        self.write(&[0; 3][..(4 - length % 4) % 4]);
+        self.write_u32(block_len as u32); // block total length
+    }
+
+    /// Write a pcapng Enhanced Packet Block holding a packet captured on the given
+    /// interface into the sink.
+    ///
+    /// See also the note for [global_header](#method.global_header).
+    fn enhanced_packet(&self, interface_id: u32, timestamp: u64, packet: &[u8]) {
+        self.enhanced_packet_header(interface_id, timestamp, packet.len());
+        self.write(packet);
+        self.enhanced_packet_trailer(packet.len())
+    }
 }
 
 impl<T: AsRef<PcapSink>> PcapSink for T {
@@ -102,35 +186,70 @@ impl<T: Write> PcapSink for RefCell<T> {
         PcapSink::write(self, packet);
         self.borrow_mut().flush().expect("cannot flush")
     }
+
+    fn enhanced_packet(&self, interface_id: u32, timestamp: u64, packet: &[u8]) {
+        self.enhanced_packet_header(interface_id, timestamp, packet.len());
+        PcapSink::write(self, packet);
+        self.enhanced_packet_trailer(packet.len());
+        self.borrow_mut().flush().expect("cannot flush")
+    }
 }
 
 /// A packet capture writer device.
 ///
 /// Every packet transmitted or received through this device is timestamped
-/// and written (in the [libpcap] format) using the provided [sink].
+/// and written (in the [libpcap] or [pcapng] format) using the provided [sink].
 /// Note that writes are fine-grained, and buffering is recommended.
 ///
+/// In the pcapng format, the packets of several devices can be written into the same
+/// capture, each device being described as an interface of its own, and the timestamps
+/// have a nanosecond resolution.
+///
 /// The packet sink should be cheaply cloneable, as it is cloned on every
 /// transmitted packet. For example, `&'a mut Vec<u8>` is cheaply cloneable
 /// but `&std::io::File`
 ///
 /// [libpcap]: https://wiki.wireshark.org/Development/LibpcapFileFormat
+/// [pcapng]: https://github.com/pcapng/pcapng
 /// [sink]: trait.PcapSink.html
 #[derive(Debug)]
 pub struct PcapWriter<D, S>
     where D: for<'a> Device<'a>,
           S: PcapSink + Clone,
 {
-    lower: D,
-    sink:  S,
-    mode:  PcapMode,
+    lower:     D,
+    sink:      S,
+    mode:      PcapMode,
+    // The pcapng interface of the device, or `None` in the libpcap format.
+    interface: Option<u32>,
 }
 
 impl<D: for<'a> Device<'a>, S: PcapSink + Clone> PcapWriter<D, S> {
     /// Creates a packet capture writer.
     pub fn new(lower: D, sink: S, mode: PcapMode, link_type: PcapLinkType) -> PcapWriter<D, S> {
         sink.global_header(link_type);
-        PcapWriter { lower, sink, mode }
+        PcapWriter { lower, sink, mode, interface: None }
+    }
+
+    /// Creates a packet capture writer in the pcapng format, which starts the capture
+    /// and describes the device as its first interface, with the given name.
+    pub fn new_pcapng(lower: D, sink: S, mode: PcapMode, link_type: PcapLinkType,
+                      name: &str) -> PcapWriter<D, S> {
+        sink.section_header();
+        sink.interface_description(link_type, name);
+        PcapWriter { lower, sink, mode, interface: Some(0) }
+    }
+
+    /// Creates a packet capture writer in the pcapng format, which writes into
+    /// a capture started by [new_pcapng](#method.new_pcapng) and describes the device
+    /// as another interface, with the given name.
+    ///
+    /// The interfaces are numbered in the order they are described, so `interface_id`
+    /// must be the number of writers created for the capture before this one.
+    pub fn add_pcapng_interface(lower: D, sink: S, mode: PcapMode, link_type: PcapLinkType,
+                                name: &str, interface_id: u32) -> PcapWriter<D, S> {
+        sink.interface_description(link_type, name);
+        PcapWriter { lower, sink, mode, interface: Some(interface_id) }
     }
 }
 
@@ -143,64 +262,162 @@ impl<'a, D, S> Device<'a> for PcapWriter<D, S>
 
     fn capabilities(&self) -> DeviceCapabilities { self.lower.capabilities() }
 
+    fn add_multicast_filter(&mut self, addr: EthernetAddress) {
+        self.lower.add_multicast_filter(addr)
+    }
+
+    fn remove_multicast_filter(&mut self, addr: EthernetAddress) {
+        self.lower.remove_multicast_filter(addr)
+    }
+
+    fn lent(&self, loan: &RxLoan) -> Option<&[u8]> { self.lower.lent(loan) }
+
+    fn release(&mut self, loan: RxLoan) { self.lower.release(loan) }
+
+    fn tx_hw_timestamp(&mut self) -> Option<u64> { self.lower.tx_hw_timestamp() }
+
     fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
-        let &mut Self { ref mut lower, ref sink, mode, .. } = self;
+        let &mut Self { ref mut lower, ref sink, mode, interface } = self;
         lower.receive().map(|(rx_token, tx_token)| {
-            let rx = RxToken { token: rx_token, sink: sink.clone(), mode: mode };
-            let tx = TxToken { token: tx_token, sink: sink.clone(), mode: mode };
+            let rx = RxToken { token: rx_token, sink: sink.clone(), mode, interface };
+            let tx = TxToken { token: tx_token, sink: sink.clone(), mode, interface };
             (rx, tx)
         })
     }
 
     fn transmit(&'a mut self) -> Option<Self::TxToken> {
-        let &mut Self { ref mut lower, ref sink, mode } = self;
+        let &mut Self { ref mut lower, ref sink, mode, interface } = self;
         lower.transmit().map(|token| {
-            TxToken { token, sink: sink.clone(), mode: mode }
+            TxToken { token, sink: sink.clone(), mode, interface }
         })
     }
 }
 
+fn write_packet<S: PcapSink>(sink: &S, interface: Option<u32>, timestamp: u64, packet: &[u8]) {
+    match interface {
+        Some(interface_id) => sink.enhanced_packet(interface_id, timestamp, packet),
+        None => sink.packet(timestamp, packet)
+    }
+}
+
 #[doc(hidden)]
 pub struct RxToken<Rx: phy::RxToken, S: PcapSink> {
-    token: Rx,
-    sink:  S,
-    mode:  PcapMode,
+    token:     Rx,
+    sink:      S,
+    mode:      PcapMode,
+    interface: Option<u32>,
 }
 
 impl<Rx: phy::RxToken, S: PcapSink> phy::RxToken for RxToken<Rx, S> {
     fn consume<R, F: FnOnce(&[u8]) -> Result<R>>(self, timestamp: u64, f: F) -> Result<R> {
-        let Self { token, sink, mode } = self;
+        let Self { token, sink, mode, interface } = self;
         token.consume(timestamp, |buffer| {
             match mode {
                 PcapMode::Both | PcapMode::RxOnly =>
-                    sink.packet(timestamp, buffer.as_ref()),
+                    write_packet(&sink, interface, timestamp, buffer.as_ref()),
                 PcapMode::TxOnly => ()
             }
             f(buffer)
         })
     }
+
+    fn is_truncated(&self) -> bool {
+        self.token.is_truncated()
+    }
+
+    fn hw_timestamp(&self) -> Option<u64> {
+        self.token.hw_timestamp()
+    }
+
+    fn consume_lend<R, F>(self, timestamp: u64, f: F) -> Result<R>
+        where F: FnOnce(&[u8], Option<RxLoan>) -> (Result<R>, bool)
+    {
+        let Self { token, sink, mode, interface } = self;
+        token.consume_lend(timestamp, |buffer, loan| {
+            match mode {
+                PcapMode::Both | PcapMode::RxOnly =>
+                    write_packet(&sink, interface, timestamp, buffer.as_ref()),
+                PcapMode::TxOnly => ()
+            }
+            f(buffer, loan)
+        })
+    }
 }
 
 #[doc(hidden)]
 pub struct TxToken<Tx: phy::TxToken, S: PcapSink> {
-    token: Tx,
-    sink:  S,
-    mode:  PcapMode
+    token:     Tx,
+    sink:      S,
+    mode:      PcapMode,
+    interface: Option<u32>,
 }
 
 impl<Tx: phy::TxToken, S: PcapSink> phy::TxToken for TxToken<Tx, S> {
     fn consume<R, F>(self, timestamp: u64, len: usize, f: F) -> Result<R>
         where F: FnOnce(&mut [u8]) -> Result<R>
     {
-        let Self { token, sink, mode } = self;
+        let Self { token, sink, mode, interface } = self;
         token.consume(timestamp, len, |buffer| {
             let result = f(buffer);
             match mode {
                 PcapMode::Both | PcapMode::TxOnly =>
-                    sink.packet(timestamp, &buffer),
+                    write_packet(&sink, interface, timestamp, &buffer),
                 PcapMode::RxOnly => ()
             };
             result
         })
     }
+
+    fn set_segment_size(&mut self, segment_size: usize) {
+        self.token.set_segment_size(segment_size)
+    }
+
+    fn request_hw_timestamp(&mut self) {
+        self.token.request_hw_timestamp()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::vec::Vec;
+    use phy::{Loopback, PcapReplay, RxToken as _RxToken, TxToken as _TxToken};
+    use super::*;
+
+    fn transmit<D: for<'a> Device<'a>>(device: &mut D, timestamp: u64, frame: &[u8]) {
+        let token = device.transmit().unwrap();
+        token.consume(timestamp, frame.len(), |buffer| {
+            buffer.copy_from_slice(frame);
+            Ok(())
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_pcapng_interfaces() {
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        let sink = buffer.clone() as Rc<PcapSink>;
+        let mut first = PcapWriter::new_pcapng(Loopback::new(), sink.clone(), PcapMode::TxOnly,
+                                               PcapLinkType::Ethernet, "eth0");
+        let mut second = PcapWriter::add_pcapng_interface(Loopback::new(), sink.clone(),
+                                                          PcapMode::TxOnly,
+                                                          PcapLinkType::Ethernet, "", 1);
+        transmit(&mut first, 1_000, &[1; 14]);
+        transmit(&mut second, 1_250, &[2; 17]);
+
+        let data = buffer.borrow();
+        // A section header, an interface with a name and one without,
+        // and two packets padded to a multiple of four octets.
+        assert_eq!(data.len(), 28 + 40 + 32 + 48 + 52);
+        assert_eq!(&data[68 + 8..68 + 10], &[1, 0]);
+        assert_eq!(NativeEndian::read_u32(&data[148 + 8..148 + 12]), 1);
+
+        let mut replay = PcapReplay::new(&data[..]).unwrap();
+        replay.set_realtime(true);
+        let (token, _) = replay.receive().unwrap();
+        assert_eq!(token.consume(0, |frame| Ok(frame.to_vec())), Ok(vec![1; 14]));
+        assert_eq!(replay.poll_at(), Some(250));
+        let (token, _) = replay.receive().unwrap();
+        assert_eq!(token.consume(250, |frame| Ok(frame.to_vec())), Ok(vec![2; 17]));
+    }
 }
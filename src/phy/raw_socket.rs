@@ -6,12 +6,22 @@ use std::os::unix::io::{RawFd, AsRawFd};
 
 use Result;
 use phy::{self, sys, DeviceCapabilities, Device};
+#[cfg(target_os = "linux")]
+use phy::Checksum;
+
+/// The largest frame sent or received with segmentation offload, as large as an IP packet
+/// may be with the Ethernet header.
+#[cfg(target_os = "linux")]
+const MAX_OFFLOAD_SIZE: usize = 14 + 0xffff;
 
 /// A socket that captures or transmits the complete frame.
+///
+/// On Linux, this is a packet socket; on FreeBSD and OpenBSD, it is a BPF device.
 #[derive(Debug)]
 pub struct RawSocket {
-    lower:  Rc<RefCell<sys::RawSocketDesc>>,
-    mtu:    usize
+    lower:   Rc<RefCell<sys::RawSocketDesc>>,
+    mtu:     usize,
+    offload: bool
 }
 
 impl AsRawFd for RawSocket {
@@ -30,8 +40,28 @@ impl RawSocket {
         lower.bind_interface()?;
         let mtu = lower.interface_mtu()?;
         Ok(RawSocket {
-            lower: Rc::new(RefCell::new(lower)),
-            mtu:   mtu
+            lower:   Rc::new(RefCell::new(lower)),
+            mtu:     mtu,
+            offload: false
+        })
+    }
+
+    /// Creates a raw socket, bound to the interface called `name`, which leaves
+    /// the TCP checksums and the segmentation of large TCP packets to the host,
+    /// and receives the segments the host has coalesced, with their checksums completed.
+    ///
+    /// The host segments the packets in software if the interface cannot, which is still
+    /// considerably cheaper than sending the segments one at a time.
+    #[cfg(target_os = "linux")]
+    pub fn with_segmentation_offload(name: &str) -> io::Result<RawSocket> {
+        let mut lower = sys::RawSocketDesc::new(name)?;
+        lower.enable_vnet_header()?;
+        lower.bind_interface()?;
+        let mtu = lower.interface_mtu()?;
+        Ok(RawSocket {
+            lower:   Rc::new(RefCell::new(lower)),
+            mtu:     mtu,
+            offload: true
         })
     }
 }
@@ -41,20 +71,50 @@ impl<'a> Device<'a> for RawSocket {
     type TxToken = TxToken;
 
     fn capabilities(&self) -> DeviceCapabilities {
-        DeviceCapabilities {
+        #[allow(unused_mut)]
+        let mut caps = DeviceCapabilities {
             max_transmission_unit: self.mtu,
             ..DeviceCapabilities::default()
+        };
+        #[cfg(target_os = "linux")]
+        {
+            if self.offload {
+                caps.max_tso_size = Some(MAX_OFFLOAD_SIZE);
+                caps.checksum.tcpv4 = Checksum::Partial;
+                #[cfg(feature = "proto-ipv6")]
+                {
+                    caps.checksum.tcpv6 = Checksum::Partial;
+                }
+            }
         }
+        caps
     }
 
     fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
         let mut lower = self.lower.borrow_mut();
-        let mut buffer = vec![0; self.mtu];
-        match lower.recv(&mut buffer[..]) {
-            Ok(size) => {
-                buffer.resize(size, 0);
-                let rx = RxToken { buffer };
-                let tx = TxToken { lower: self.lower.clone() };
+        #[cfg(target_os = "linux")]
+        let result = if self.offload {
+            let mut buffer = vec![0; MAX_OFFLOAD_SIZE];
+            lower.recv_with_vnet_header(&mut buffer[..]).map(|size| (buffer, size))
+        } else {
+            let mut buffer = vec![0; self.mtu];
+            lower.recv(&mut buffer[..]).map(|size| (buffer, size))
+        };
+        #[cfg(not(target_os = "linux"))]
+        let result = {
+            let mut buffer = vec![0; self.mtu];
+            lower.recv(&mut buffer[..]).map(|size| (buffer, size))
+        };
+        match result {
+            Ok((mut buffer, size)) => {
+                let truncated = size > buffer.len();
+                buffer.truncate(size);
+                let rx = RxToken { buffer, truncated };
+                let tx = TxToken {
+                    lower:        self.lower.clone(),
+                    offload:      self.offload,
+                    segment_size: None
+                };
                 Some((rx, tx))
             }
             Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
@@ -66,25 +126,34 @@ impl<'a> Device<'a> for RawSocket {
 
     fn transmit(&'a mut self) -> Option<Self::TxToken> {
         Some(TxToken {
-            lower: self.lower.clone(),
+            lower:        self.lower.clone(),
+            offload:      self.offload,
+            segment_size: None
         })
     }
 }
 
 #[doc(hidden)]
 pub struct RxToken {
-    buffer: Vec<u8>
+    buffer:    Vec<u8>,
+    truncated: bool
 }
 
 impl phy::RxToken for RxToken {
     fn consume<R, F: FnOnce(&[u8]) -> Result<R>>(self, _timestamp: u64, f: F) -> Result<R> {
         f(&self.buffer[..])
     }
+
+    fn is_truncated(&self) -> bool {
+        self.truncated
+    }
 }
 
 #[doc(hidden)]
 pub struct TxToken {
-    lower:  Rc<RefCell<sys::RawSocketDesc>>,
+    lower:        Rc<RefCell<sys::RawSocketDesc>>,
+    offload:      bool,
+    segment_size: Option<usize>
 }
 
 impl phy::TxToken for TxToken {
@@ -94,7 +163,20 @@ impl phy::TxToken for TxToken {
         let mut lower = self.lower.borrow_mut();
         let mut buffer = vec![0; len];
         let result = f(&mut buffer);
+        #[cfg(target_os = "linux")]
+        {
+            if self.offload {
+                lower.send_with_vnet_header(&buffer[..], self.segment_size).unwrap();
+                return result
+            }
+        }
         lower.send(&mut buffer[..]).unwrap();
         result
     }
+
+    fn set_segment_size(&mut self, segment_size: usize) {
+        if self.offload {
+            self.segment_size = Some(segment_size)
+        }
+    }
 }
@@ -0,0 +1,320 @@
+use core::cmp;
+use core::cell::RefCell;
+
+use {Error, Result};
+use wire::EthernetAddress;
+use phy::{self, DeviceCapabilities, Device, RxLoan};
+
+/// A token bucket, holding up to `burst` octets and refilled with `rate` octets per second.
+///
+/// The level is kept in thousandths of an octet, which are refilled at `rate` per millisecond.
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    rate:        u64,
+    burst:       u64,
+    level:       i64,
+    refilled_at: Option<u64>,
+}
+
+impl Bucket {
+    fn new(rate: u64, burst: u64) -> Bucket {
+        Bucket { rate, burst, level: 0, refilled_at: None }
+    }
+
+    fn refill(&mut self, timestamp: u64) {
+        let capacity = (self.burst * 1000) as i64;
+        self.level = match self.refilled_at {
+            None => capacity,
+            Some(refilled_at) => {
+                let refill = timestamp.saturating_sub(refilled_at).saturating_mul(self.rate);
+                cmp::min(self.level.saturating_add(cmp::min(refill, i64::max_value() as u64)
+                                                   as i64), capacity)
+            }
+        };
+        self.refilled_at = Some(timestamp);
+    }
+
+    /// Take `len` octets out of the bucket if it holds as many. If `overdraw` is set,
+    /// a full bucket admits a packet larger than the burst size, and goes into debt.
+    fn take(&mut self, timestamp: u64, len: usize, overdraw: bool) -> bool {
+        if self.rate == 0 { return true }
+
+        self.refill(timestamp);
+        let len = (len as u64 * 1000) as i64;
+        if self.level >= len || (overdraw && self.level == (self.burst * 1000) as i64) {
+            self.level -= len;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    tx: Bucket,
+    rx: Bucket,
+}
+
+/// A traffic shaper device.
+///
+/// A traffic shaper is a device that limits the rate of the packets transmitted through
+/// another device with a token bucket, and optionally polices the rate of the packets
+/// received from it with another one. Each bucket is refilled at a given rate, in octets
+/// per second, up to a given burst size, in octets.
+///
+/// A packet that cannot be transmitted yet is refused with `Err(Error::Exhausted)` before it
+/// is even built, so that it stays in its socket until a later [poll] finds enough tokens
+/// in the bucket; a full bucket lets a packet larger than the burst size through. A received
+/// packet that exceeds the rate is dropped, also with `Err(Error::Exhausted)`.
+///
+/// [poll]: ../iface/struct.EthernetInterface.html#method.poll
+#[derive(Debug)]
+pub struct Shaper<D: for<'a> Device<'a>> {
+    inner: D,
+    state: RefCell<State>,
+}
+
+impl<D: for<'a> Device<'a>> Shaper<D> {
+    /// Create a traffic shaper device, without any limit.
+    pub fn new(inner: D) -> Shaper<D> {
+        Shaper { inner, state: RefCell::new(State::default()) }
+    }
+
+    /// Return the underlying device, consuming the traffic shaper.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Return the transmission rate, in octets per second, or 0 if it is not limited.
+    pub fn tx_rate(&self) -> u64 {
+        self.state.borrow().tx.rate
+    }
+
+    /// Return the transmission burst size, in octets.
+    pub fn tx_burst(&self) -> u64 {
+        self.state.borrow().tx.burst
+    }
+
+    /// Return the reception rate, in octets per second, or 0 if it is not limited.
+    pub fn rx_rate(&self) -> u64 {
+        self.state.borrow().rx.rate
+    }
+
+    /// Return the reception burst size, in octets.
+    pub fn rx_burst(&self) -> u64 {
+        self.state.borrow().rx.burst
+    }
+
+    /// Limit the transmission rate to `rate` octets per second, with bursts
+    /// of up to `burst` octets. A rate of 0 removes the limit.
+    pub fn set_tx_rate(&mut self, rate: u64, burst: u64) {
+        self.state.borrow_mut().tx = Bucket::new(rate, burst)
+    }
+
+    /// Limit the reception rate to `rate` octets per second, with bursts
+    /// of up to `burst` octets, which should be at least the size of the largest packet.
+    /// A rate of 0 removes the limit.
+    pub fn set_rx_rate(&mut self, rate: u64, burst: u64) {
+        self.state.borrow_mut().rx = Bucket::new(rate, burst)
+    }
+}
+
+impl<'a, D> Device<'a> for Shaper<D>
+    where D: for<'b> Device<'b>,
+{
+    type RxToken = RxToken<'a, <D as Device<'a>>::RxToken>;
+    type TxToken = TxToken<'a, <D as Device<'a>>::TxToken>;
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        self.inner.capabilities()
+    }
+
+    fn add_multicast_filter(&mut self, addr: EthernetAddress) {
+        self.inner.add_multicast_filter(addr)
+    }
+
+    fn remove_multicast_filter(&mut self, addr: EthernetAddress) {
+        self.inner.remove_multicast_filter(addr)
+    }
+
+    fn lent(&self, loan: &RxLoan) -> Option<&[u8]> { self.inner.lent(loan) }
+
+    fn release(&mut self, loan: RxLoan) { self.inner.release(loan) }
+
+    fn tx_hw_timestamp(&mut self) -> Option<u64> { self.inner.tx_hw_timestamp() }
+
+    fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+        let &mut Self { ref mut inner, ref state } = self;
+        inner.receive().map(|(rx_token, tx_token)| {
+            let rx = RxToken { state: state, token: rx_token };
+            let tx = TxToken { state: state, token: tx_token };
+            (rx, tx)
+        })
+    }
+
+    fn transmit(&'a mut self) -> Option<Self::TxToken> {
+        let &mut Self { ref mut inner, ref state } = self;
+        inner.transmit().map(|token| TxToken { state: state, token: token })
+    }
+}
+
+#[doc(hidden)]
+pub struct RxToken<'a, Rx: phy::RxToken> {
+    state: &'a RefCell<State>,
+    token: Rx,
+}
+
+impl<'a, Rx: phy::RxToken> phy::RxToken for RxToken<'a, Rx> {
+    fn consume<R, F>(self, timestamp: u64, f: F) -> Result<R>
+        where F: FnOnce(&[u8]) -> Result<R>
+    {
+        let Self { state, token } = self;
+        token.consume(timestamp, |buffer| {
+            if !state.borrow_mut().rx.take(timestamp, buffer.len(), false) {
+                net_trace!("rx: dropping a packet exceeding the rate");
+                return Err(Error::Exhausted)
+            }
+            f(buffer)
+        })
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.token.is_truncated()
+    }
+
+    fn hw_timestamp(&self) -> Option<u64> {
+        self.token.hw_timestamp()
+    }
+
+    fn consume_lend<R, F>(self, timestamp: u64, f: F) -> Result<R>
+        where F: FnOnce(&[u8], Option<RxLoan>) -> (Result<R>, bool)
+    {
+        let Self { state, token } = self;
+        token.consume_lend(timestamp, |buffer, loan| {
+            if !state.borrow_mut().rx.take(timestamp, buffer.len(), false) {
+                net_trace!("rx: dropping a packet exceeding the rate");
+                return (Err(Error::Exhausted), false)
+            }
+            f(buffer, loan)
+        })
+    }
+}
+
+#[doc(hidden)]
+pub struct TxToken<'a, Tx: phy::TxToken> {
+    state: &'a RefCell<State>,
+    token: Tx,
+}
+
+impl<'a, Tx: phy::TxToken> phy::TxToken for TxToken<'a, Tx> {
+    fn consume<R, F>(self, timestamp: u64, len: usize, f: F) -> Result<R>
+        where F: FnOnce(&mut [u8]) -> Result<R>
+    {
+        if !self.state.borrow_mut().tx.take(timestamp, len, true) {
+            net_trace!("tx: delaying a packet exceeding the rate");
+            return Err(Error::Exhausted)
+        }
+        self.token.consume(timestamp, len, f)
+    }
+
+    fn consume_gather<R, F>(self, timestamp: u64, header_len: usize, payload: &[&[u8]],
+                            f: F) -> Result<R>
+        where F: FnOnce(&mut [u8]) -> Result<R>
+    {
+        let len = payload.iter().fold(header_len, |len, buffer| len + buffer.len());
+        if !self.state.borrow_mut().tx.take(timestamp, len, true) {
+            net_trace!("tx: delaying a packet exceeding the rate");
+            return Err(Error::Exhausted)
+        }
+        self.token.consume_gather(timestamp, header_len, payload, f)
+    }
+
+    fn set_segment_size(&mut self, segment_size: usize) {
+        self.token.set_segment_size(segment_size)
+    }
+
+    fn request_hw_timestamp(&mut self) {
+        self.token.request_hw_timestamp()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use phy::{Loopback, RxToken, TxToken};
+    use super::*;
+
+    fn send<D: for<'a> Device<'a>>(device: &mut D, timestamp: u64, len: usize) -> Result<()> {
+        device.transmit().unwrap().consume(timestamp, len, |buffer| {
+            for byte in buffer.iter_mut() { *byte = 0x2a }
+            Ok(())
+        })
+    }
+
+    fn recv<D: for<'a> Device<'a>>(device: &mut D, timestamp: u64) -> Option<Result<Vec<u8>>> {
+        let (rx, _tx) = device.receive()?;
+        Some(rx.consume(timestamp, |buffer| Ok(buffer.to_vec())))
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let mut device = Shaper::new(Loopback::new());
+        for _ in 0..100 {
+            assert_eq!(send(&mut device, 0, 1500), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_tx_rate() {
+        let mut device = Shaper::new(Loopback::new());
+        device.set_tx_rate(100_000, 3000);
+        assert_eq!(device.tx_rate(), 100_000);
+        assert_eq!(device.tx_burst(), 3000);
+
+        // The bucket starts full.
+        assert_eq!(send(&mut device, 0, 1000), Ok(()));
+        assert_eq!(send(&mut device, 0, 1000), Ok(()));
+        assert_eq!(send(&mut device, 0, 1000), Ok(()));
+        assert_eq!(send(&mut device, 0, 1000), Err(Error::Exhausted));
+        // 100 octets per millisecond.
+        assert_eq!(send(&mut device, 9, 1000), Err(Error::Exhausted));
+        assert_eq!(send(&mut device, 10, 1000), Ok(()));
+        assert_eq!(send(&mut device, 10, 1), Err(Error::Exhausted));
+        // The bucket does not fill beyond the burst size.
+        assert_eq!(send(&mut device, 1000, 3000), Ok(()));
+        assert_eq!(send(&mut device, 1000, 1), Err(Error::Exhausted));
+
+        // Only the packets that were let through were transmitted.
+        let mut inner = device.into_inner();
+        let mut count = 0;
+        while let Some(result) = recv(&mut inner, 0) {
+            assert!(result.is_ok());
+            count += 1;
+        }
+        assert_eq!(count, 5);
+    }
+
+    #[test]
+    fn test_tx_oversized() {
+        let mut device = Shaper::new(Loopback::new());
+        device.set_tx_rate(1_000, 100);
+        assert_eq!(send(&mut device, 0, 1500), Ok(()));
+        // The bucket has to fill up again after going into debt.
+        assert_eq!(send(&mut device, 1_000, 1), Err(Error::Exhausted));
+        assert_eq!(send(&mut device, 1_500, 1500), Ok(()));
+    }
+
+    #[test]
+    fn test_rx_policing() {
+        let mut device = Shaper::new(Loopback::new());
+        device.set_rx_rate(1_000, 1500);
+        for _ in 0..3 {
+            assert_eq!(send(&mut device, 0, 1000), Ok(()));
+        }
+        assert_eq!(recv(&mut device, 0), Some(Ok(vec![0x2a; 1000])));
+        assert_eq!(recv(&mut device, 0), Some(Err(Error::Exhausted)));
+        assert_eq!(recv(&mut device, 1_000), Some(Ok(vec![0x2a; 1000])));
+        assert_eq!(recv(&mut device, 1_000), None);
+    }
+}
@@ -0,0 +1,251 @@
+use core::{cmp, fmt};
+use managed::ManagedSlice;
+
+use {Error, Result};
+use wire::EthernetAddress;
+use phy::{self, DeviceCapabilities, Device, Medium};
+
+/// A driver of an Ethernet controller that sends and receives whole frames synchronously,
+/// such as the ENC28J60 or the W5500 in MAC raw mode, attached to a microcontroller
+/// through SPI.
+///
+/// The frames are Ethernet frames without the frame check sequence, which the controller
+/// appends and checks itself.
+pub trait SpiEthDriver {
+    /// The error of the driver, e.g. of an SPI transfer.
+    type Error: fmt::Debug;
+
+    /// Copy the next frame received by the controller into `buffer`, if there is any,
+    /// and return its length.
+    ///
+    /// A frame that does not fit into the buffer is truncated, and its length before
+    /// truncation is returned nonetheless.
+    fn receive_frame(&mut self, buffer: &mut [u8])
+                    -> ::core::result::Result<Option<usize>, Self::Error>;
+
+    /// Send `frame` through the controller, waiting for it to accept the frame
+    /// if it is still busy.
+    fn send_frame(&mut self, frame: &[u8]) -> ::core::result::Result<(), Self::Error>;
+
+    /// Add a hardware address to the multicast filter of the controller.
+    ///
+    /// The default implementation does nothing, which suits controllers that receive
+    /// every multicast frame.
+    fn add_multicast_filter(&mut self, _addr: EthernetAddress) {}
+
+    /// Remove a hardware address from the multicast filter of the controller.
+    fn remove_multicast_filter(&mut self, _addr: EthernetAddress) {}
+}
+
+/// An adapter of an Ethernet controller driver to a device.
+///
+/// The adapter receives each frame into its receive buffer and builds each frame
+/// in its transmit buffer before handing it to the [driver], so that the driver only
+/// has to transfer whole frames. The MTU of the device is the size of the smaller buffer;
+/// frames received that are larger are reported as truncated. An error of the driver
+/// makes the device appear as if no frame was received, or the transmit token return
+/// `Err(Error::Exhausted)`, respectively.
+///
+/// The buffers can be borrowed, e.g. from static arrays, so that the adapter does not
+/// need a heap.
+///
+/// [driver]: trait.SpiEthDriver.html
+#[derive(Debug)]
+pub struct SpiEthDevice<'a, D: SpiEthDriver> {
+    driver:    D,
+    rx_buffer: ManagedSlice<'a, u8>,
+    tx_buffer: ManagedSlice<'a, u8>,
+}
+
+impl<'a, D: SpiEthDriver> SpiEthDevice<'a, D> {
+    /// Create an adapter of the given driver, with the given receive and transmit buffers.
+    pub fn new<RxBufferT, TxBufferT>(driver: D, rx_buffer: RxBufferT,
+                                     tx_buffer: TxBufferT) -> SpiEthDevice<'a, D>
+        where RxBufferT: Into<ManagedSlice<'a, u8>>,
+              TxBufferT: Into<ManagedSlice<'a, u8>>
+    {
+        SpiEthDevice {
+            driver:    driver,
+            rx_buffer: rx_buffer.into(),
+            tx_buffer: tx_buffer.into(),
+        }
+    }
+
+    /// Return a reference to the driver, e.g. to check the link state of the controller.
+    pub fn driver(&self) -> &D {
+        &self.driver
+    }
+
+    /// Return a mutable reference to the driver.
+    pub fn driver_mut(&mut self) -> &mut D {
+        &mut self.driver
+    }
+
+    /// Return the driver, consuming the adapter.
+    pub fn into_inner(self) -> D {
+        self.driver
+    }
+}
+
+impl<'a, 'b, D: SpiEthDriver + 'a> Device<'a> for SpiEthDevice<'b, D> {
+    type RxToken = RxToken<'a>;
+    type TxToken = TxToken<'a, D>;
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        DeviceCapabilities {
+            max_transmission_unit: cmp::min(self.rx_buffer.len(), self.tx_buffer.len()),
+            medium: Medium::Ethernet,
+            ..DeviceCapabilities::default()
+        }
+    }
+
+    fn add_multicast_filter(&mut self, addr: EthernetAddress) {
+        self.driver.add_multicast_filter(addr)
+    }
+
+    fn remove_multicast_filter(&mut self, addr: EthernetAddress) {
+        self.driver.remove_multicast_filter(addr)
+    }
+
+    fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+        let &mut Self { ref mut driver, ref mut rx_buffer, ref mut tx_buffer } = self;
+        match driver.receive_frame(&mut rx_buffer[..]) {
+            Ok(Some(len)) => {
+                let rx = RxToken {
+                    buffer:    &rx_buffer[..cmp::min(len, rx_buffer.len())],
+                    truncated: len > rx_buffer.len()
+                };
+                let tx = TxToken { driver, buffer: &mut tx_buffer[..] };
+                Some((rx, tx))
+            }
+            Ok(None) => None,
+            Err(err) => {
+                net_debug!("spi-eth: cannot receive a frame: {:?}", err);
+                None
+            }
+        }
+    }
+
+    fn transmit(&'a mut self) -> Option<Self::TxToken> {
+        let &mut Self { ref mut driver, ref mut tx_buffer, .. } = self;
+        Some(TxToken { driver, buffer: &mut tx_buffer[..] })
+    }
+}
+
+#[doc(hidden)]
+pub struct RxToken<'a> {
+    buffer:    &'a [u8],
+    truncated: bool
+}
+
+impl<'a> phy::RxToken for RxToken<'a> {
+    fn consume<R, F>(self, _timestamp: u64, f: F) -> Result<R>
+        where F: FnOnce(&[u8]) -> Result<R>
+    {
+        f(self.buffer)
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+#[doc(hidden)]
+pub struct TxToken<'a, D: SpiEthDriver + 'a> {
+    driver: &'a mut D,
+    buffer: &'a mut [u8]
+}
+
+impl<'a, D: SpiEthDriver> phy::TxToken for TxToken<'a, D> {
+    fn consume<R, F>(self, _timestamp: u64, len: usize, f: F) -> Result<R>
+        where F: FnOnce(&mut [u8]) -> Result<R>
+    {
+        if len > self.buffer.len() { return Err(Error::Truncated) }
+
+        let frame = &mut self.buffer[..len];
+        let result = f(frame)?;
+        match self.driver.send_frame(frame) {
+            Ok(()) => Ok(result),
+            Err(err) => {
+                net_debug!("spi-eth: cannot send a frame: {:?}", err);
+                Err(Error::Exhausted)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+    use phy::{RxToken, TxToken};
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct MockDriver {
+        rx_queue: VecDeque<Vec<u8>>,
+        tx_queue: Vec<Vec<u8>>,
+        fail:     bool
+    }
+
+    impl SpiEthDriver for MockDriver {
+        type Error = ();
+
+        fn receive_frame(&mut self, buffer: &mut [u8])
+                        -> ::core::result::Result<Option<usize>, ()> {
+            if self.fail { return Err(()) }
+            Ok(self.rx_queue.pop_front().map(|frame| {
+                let len = cmp::min(frame.len(), buffer.len());
+                buffer[..len].copy_from_slice(&frame[..len]);
+                frame.len()
+            }))
+        }
+
+        fn send_frame(&mut self, frame: &[u8]) -> ::core::result::Result<(), ()> {
+            if self.fail { return Err(()) }
+            self.tx_queue.push(frame.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_receive() {
+        let mut driver = MockDriver::default();
+        driver.rx_queue.push_back(vec![0xaa; 60]);
+        driver.rx_queue.push_back(vec![0xbb; 100]);
+        let mut device = SpiEthDevice::new(driver, vec![0; 80], vec![0; 1514]);
+        assert_eq!(device.capabilities().max_transmission_unit, 80);
+
+        let (rx, _tx) = device.receive().unwrap();
+        assert!(!rx.is_truncated());
+        assert_eq!(rx.consume(0, |frame| Ok(frame.to_vec())), Ok(vec![0xaa; 60]));
+
+        let (rx, _tx) = device.receive().unwrap();
+        assert!(rx.is_truncated());
+        assert_eq!(rx.consume(0, |frame| Ok(frame.len())), Ok(80));
+
+        assert!(device.receive().is_none());
+    }
+
+    #[test]
+    fn test_transmit() {
+        let mut device = SpiEthDevice::new(MockDriver::default(), vec![0; 1514], vec![0; 1514]);
+        assert_eq!(device.transmit().unwrap().consume(0, 60, |frame| {
+            for byte in frame.iter_mut() { *byte = 0xcc }
+            Ok(())
+        }), Ok(()));
+        assert_eq!(device.transmit().unwrap().consume(0, 1515, |_| Ok(())),
+                   Err(Error::Truncated));
+        assert_eq!(device.driver().tx_queue, vec![vec![0xcc; 60]]);
+    }
+
+    #[test]
+    fn test_driver_error() {
+        let mut device = SpiEthDevice::new(MockDriver::default(), vec![0; 1514], vec![0; 1514]);
+        device.driver_mut().fail = true;
+        device.driver_mut().rx_queue.push_back(vec![0xaa; 60]);
+        assert!(device.receive().is_none());
+        assert_eq!(device.transmit().unwrap().consume(0, 60, |_| Ok(())),
+                   Err(Error::Exhausted));
+    }
+}
@@ -0,0 +1,133 @@
+use std::{cmp, io};
+use std::vec::Vec;
+use std::os::unix::io::{RawFd, AsRawFd};
+use libc;
+use byteorder::{ByteOrder, NativeEndian};
+use super::*;
+
+/// A BPF device, which is the way to capture and transmit complete frames on the BSDs.
+///
+/// A read returns as many captured frames as fit into the buffer of the device,
+/// each preceded by a `bpf_hdr` and aligned to `BPF_ALIGNMENT`; they are handed out
+/// one at a time.
+#[derive(Debug)]
+pub struct RawSocketDesc {
+    lower:  libc::c_int,
+    ifreq:  ifreq,
+    buffer: Vec<u8>,
+    offset: usize,
+    len:    usize
+}
+
+impl AsRawFd for RawSocketDesc {
+    fn as_raw_fd(&self) -> RawFd {
+        self.lower
+    }
+}
+
+fn open_bpf() -> io::Result<libc::c_int> {
+    unsafe {
+        let lower = libc::open("/dev/bpf\0".as_ptr() as *const libc::c_char,
+                               libc::O_RDWR | libc::O_NONBLOCK);
+        if lower != -1 { return Ok(lower) }
+    }
+    let err = io::Error::last_os_error();
+    if err.raw_os_error() != Some(libc::ENOENT) { return Err(err) }
+
+    // Without a cloning device, look for an unused numbered one.
+    for index in 0..256 {
+        let path = format!("/dev/bpf{}\0", index);
+        unsafe {
+            let lower = libc::open(path.as_ptr() as *const libc::c_char,
+                                   libc::O_RDWR | libc::O_NONBLOCK);
+            if lower != -1 { return Ok(lower) }
+        }
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EBUSY) { return Err(err) }
+    }
+    Err(io::Error::new(io::ErrorKind::NotFound, "no BPF device available"))
+}
+
+fn uint_ioctl(lower: libc::c_int, cmd: libc::c_ulong,
+              mut value: libc::c_uint) -> io::Result<libc::c_uint> {
+    unsafe {
+        let res = libc::ioctl(lower, cmd, &mut value as *mut libc::c_uint);
+        if res == -1 { return Err(io::Error::last_os_error()) }
+    }
+    Ok(value)
+}
+
+impl RawSocketDesc {
+    pub fn new(name: &str) -> io::Result<RawSocketDesc> {
+        Ok(RawSocketDesc {
+            lower:  open_bpf()?,
+            ifreq:  ifreq_for(name),
+            buffer: Vec::new(),
+            offset: 0,
+            len:    0
+        })
+    }
+
+    pub fn interface_mtu(&mut self) -> io::Result<usize> {
+        ifreq_mtu(&mut self.ifreq)
+    }
+
+    pub fn bind_interface(&mut self) -> io::Result<()> {
+        ifreq_ioctl(self.lower, &mut self.ifreq, imp::BIOCSETIF)?;
+        // Return the frames as soon as they are captured rather than when the buffer fills,
+        // and leave the source address of the transmitted ones alone.
+        uint_ioctl(self.lower, imp::BIOCIMMEDIATE, 1)?;
+        uint_ioctl(self.lower, imp::BIOCSHDRCMPLT, 1)?;
+        // Like a packet socket, do not capture the frames transmitted through the device.
+        #[cfg(target_os = "freebsd")]
+        uint_ioctl(self.lower, imp::BIOCSDIRECTION, imp::BPF_D_IN)?;
+        #[cfg(target_os = "openbsd")]
+        uint_ioctl(self.lower, imp::BIOCSDIRFILT, imp::BPF_DIRECTION_OUT)?;
+
+        let buffer_len = uint_ioctl(self.lower, imp::BIOCGBLEN, 0)?;
+        self.buffer = vec![0; buffer_len as usize];
+        Ok(())
+    }
+
+    /// Receive a frame, truncated to the size of `buffer` if it is larger,
+    /// and return its length before truncation, including any part that was not captured.
+    pub fn recv(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if self.offset >= self.len {
+            unsafe {
+                let len = libc::read(self.lower, self.buffer.as_mut_ptr() as *mut libc::c_void,
+                                     self.buffer.len());
+                if len == -1 { return Err(io::Error::last_os_error()) }
+                if len == 0 { return Err(io::Error::from(io::ErrorKind::WouldBlock)) }
+                self.offset = 0;
+                self.len = len as usize;
+            }
+        }
+
+        let header = &self.buffer[self.offset..self.len];
+        let caplen = NativeEndian::read_u32(&header[imp::BPF_TSTAMP_LEN..]) as usize;
+        let datalen = NativeEndian::read_u32(&header[imp::BPF_TSTAMP_LEN + 4..]) as usize;
+        let hdrlen = NativeEndian::read_u16(&header[imp::BPF_TSTAMP_LEN + 8..]) as usize;
+        let frame  = &header[cmp::min(hdrlen, header.len())..
+                             cmp::min(hdrlen + caplen, header.len())];
+
+        let len = cmp::min(frame.len(), buffer.len());
+        buffer[..len].copy_from_slice(&frame[..len]);
+        self.offset += (hdrlen + caplen + imp::BPF_ALIGNMENT - 1) & !(imp::BPF_ALIGNMENT - 1);
+        Ok(cmp::max(len, datalen))
+    }
+
+    pub fn send(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        unsafe {
+            let len = libc::write(self.lower, buffer.as_ptr() as *const libc::c_void,
+                                  buffer.len());
+            if len == -1 { Err(io::Error::last_os_error()).unwrap() }
+            Ok(len as usize)
+        }
+    }
+}
+
+impl Drop for RawSocketDesc {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.lower); }
+    }
+}
@@ -0,0 +1,103 @@
+use std::io;
+use std::ffi::CString;
+use std::os::unix::io::{RawFd, AsRawFd};
+use libc;
+use phy::Medium;
+use super::*;
+
+#[derive(Debug)]
+pub struct TapInterfaceDesc {
+    lower:       libc::c_int,
+    ifreq:       ifreq,
+    tun:         bool,
+    with_family: bool
+}
+
+impl AsRawFd for TapInterfaceDesc {
+    fn as_raw_fd(&self) -> RawFd {
+        self.lower
+    }
+}
+
+impl TapInterfaceDesc {
+    /// Open the device of the interface called `name`, i.e. `/dev/tapN` or `/dev/tunN`,
+    /// which creates the interface if it does not exist.
+    pub fn new(name: &str) -> io::Result<TapInterfaceDesc> {
+        let tun = if name.starts_with("tun") {
+            true
+        } else if name.starts_with("tap") {
+            false
+        } else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "invalid tap or tun interface name"))
+        };
+
+        let path = CString::new(format!("/dev/{}", name))?;
+        let lower = unsafe {
+            let lower = libc::open(path.as_ptr(), libc::O_RDWR | libc::O_NONBLOCK);
+            if lower == -1 { return Err(io::Error::last_os_error()) }
+            lower
+        };
+
+        Ok(TapInterfaceDesc {
+            lower:       lower,
+            ifreq:       ifreq_for(name),
+            tun:         tun,
+            with_family: false
+        })
+    }
+
+    /// Check that the interface carries packets of the given medium, and have the packets
+    /// of tun interfaces preceded by their protocol family, so that they can be IPv6 packets.
+    pub fn attach_interface(&mut self, medium: Medium) -> io::Result<()> {
+        match (medium, self.tun) {
+            (Medium::Ethernet, false) => Ok(()),
+            (Medium::Ip, true) => {
+                #[cfg(target_os = "freebsd")]
+                unsafe {
+                    let mut enable: libc::c_int = 1;
+                    let res = libc::ioctl(self.lower, imp::TUNSIFHEAD,
+                                          &mut enable as *mut libc::c_int);
+                    if res == -1 { return Err(io::Error::last_os_error()) }
+                }
+                // OpenBSD always precedes the packets with their protocol family.
+                self.with_family = true;
+                Ok(())
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                    "interface does not carry packets of this medium"))
+        }
+    }
+
+    pub fn interface_mtu(&mut self) -> io::Result<usize> {
+        ifreq_mtu(&mut self.ifreq)
+    }
+
+    pub fn recv(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if self.with_family { return recv_with_family(self.lower, buffer) }
+
+        unsafe {
+            let len = libc::read(self.lower, buffer.as_mut_ptr() as *mut libc::c_void,
+                                 buffer.len());
+            if len == -1 { return Err(io::Error::last_os_error()) }
+            Ok(len as usize)
+        }
+    }
+
+    pub fn send(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        if self.with_family { return send_with_family(self.lower, buffer) }
+
+        unsafe {
+            let len = libc::write(self.lower, buffer.as_ptr() as *const libc::c_void,
+                                  buffer.len());
+            if len == -1 { Err(io::Error::last_os_error()).unwrap() }
+            Ok(len as usize)
+        }
+    }
+}
+
+impl Drop for TapInterfaceDesc {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.lower); }
+    }
+}
@@ -0,0 +1,27 @@
+#[cfg(feature = "phy-raw_socket")]
+use std::mem;
+use libc;
+
+#[cfg(any(feature = "phy-raw_socket",
+          feature = "phy-tap_interface"))]
+pub const SIOCGIFMTU:     libc::c_ulong = 0xc0206933;
+
+#[cfg(feature = "phy-tap_interface")]
+pub const TUNSIFHEAD:     libc::c_ulong = 0x80047460;
+
+#[cfg(feature = "phy-raw_socket")]
+pub const BIOCGBLEN:      libc::c_ulong = 0x40044266;
+#[cfg(feature = "phy-raw_socket")]
+pub const BIOCSETIF:      libc::c_ulong = 0x8020426c;
+#[cfg(feature = "phy-raw_socket")]
+pub const BIOCIMMEDIATE:  libc::c_ulong = 0x80044270;
+#[cfg(feature = "phy-raw_socket")]
+pub const BIOCSHDRCMPLT:  libc::c_ulong = 0x80044275;
+#[cfg(feature = "phy-raw_socket")]
+pub const BIOCSDIRECTION: libc::c_ulong = 0x80044277;
+#[cfg(feature = "phy-raw_socket")]
+pub const BPF_D_IN:       libc::c_uint  = 0;
+#[cfg(feature = "phy-raw_socket")]
+pub const BPF_ALIGNMENT:  usize = mem::size_of::<libc::c_long>();
+#[cfg(feature = "phy-raw_socket")]
+pub const BPF_TSTAMP_LEN: usize = mem::size_of::<libc::timeval>();
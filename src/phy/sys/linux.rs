@@ -1,9 +1,11 @@
 use libc;
 
 #[cfg(any(feature = "phy-raw_socket",
-          feature = "phy-tap_interface"))]
+          feature = "phy-tap_interface",
+          feature = "phy-xdp"))]
 pub const SIOCGIFMTU:   libc::c_ulong = 0x8921;
-#[cfg(any(feature = "phy-raw_socket"))]
+#[cfg(any(feature = "phy-raw_socket",
+          feature = "phy-xdp"))]
 pub const SIOCGIFINDEX: libc::c_ulong = 0x8933;
 #[cfg(any(feature = "phy-raw_socket"))]
 pub const ETH_P_ALL:    libc::c_short = 0x0003;
@@ -11,7 +13,67 @@ pub const ETH_P_ALL:    libc::c_short = 0x0003;
 #[cfg(feature = "phy-tap_interface")]
 pub const TUNSETIFF:    libc::c_ulong = 0x400454CA;
 #[cfg(feature = "phy-tap_interface")]
+pub const IFF_TUN:      libc::c_int   = 0x0001;
+#[cfg(feature = "phy-tap_interface")]
 pub const IFF_TAP:      libc::c_int   = 0x0002;
 #[cfg(feature = "phy-tap_interface")]
 pub const IFF_NO_PI:    libc::c_int   = 0x1000;
 
+
+#[cfg(feature = "phy-raw_socket")]
+pub const SOL_PACKET:             libc::c_int = 263;
+#[cfg(feature = "phy-raw_socket")]
+pub const PACKET_RX_RING:         libc::c_int = 5;
+#[cfg(feature = "phy-raw_socket")]
+pub const PACKET_VERSION:         libc::c_int = 10;
+#[cfg(feature = "phy-raw_socket")]
+pub const PACKET_TX_RING:         libc::c_int = 13;
+#[cfg(feature = "phy-raw_socket")]
+pub const PACKET_LOSS:            libc::c_int = 14;
+#[cfg(feature = "phy-raw_socket")]
+pub const PACKET_VNET_HDR:        libc::c_int = 15;
+#[cfg(feature = "phy-raw_socket")]
+pub const TPACKET_V3:             libc::c_int = 2;
+#[cfg(feature = "phy-raw_socket")]
+pub const TP_STATUS_KERNEL:       u32 = 0;
+#[cfg(feature = "phy-raw_socket")]
+pub const TP_STATUS_USER:         u32 = 1 << 0;
+#[cfg(feature = "phy-raw_socket")]
+pub const TP_STATUS_SEND_REQUEST: u32 = 1 << 0;
+#[cfg(feature = "phy-raw_socket")]
+pub const TP_STATUS_SENDING:      u32 = 1 << 1;
+#[cfg(feature = "phy-raw_socket")]
+pub const VIRTIO_NET_HDR_LEN:          usize = 10;
+#[cfg(feature = "phy-raw_socket")]
+pub const VIRTIO_NET_HDR_F_NEEDS_CSUM: u8 = 1;
+#[cfg(feature = "phy-raw_socket")]
+pub const VIRTIO_NET_HDR_GSO_TCPV4:    u8 = 1;
+#[cfg(feature = "phy-raw_socket")]
+pub const VIRTIO_NET_HDR_GSO_TCPV6:    u8 = 4;
+
+#[cfg(feature = "phy-xdp")]
+pub const AF_XDP:                         libc::c_int = 44;
+#[cfg(feature = "phy-xdp")]
+pub const SOL_XDP:                        libc::c_int = 283;
+#[cfg(feature = "phy-xdp")]
+pub const XDP_MMAP_OFFSETS:               libc::c_int = 1;
+#[cfg(feature = "phy-xdp")]
+pub const XDP_RX_RING:                    libc::c_int = 2;
+#[cfg(feature = "phy-xdp")]
+pub const XDP_TX_RING:                    libc::c_int = 3;
+#[cfg(feature = "phy-xdp")]
+pub const XDP_UMEM_REG:                   libc::c_int = 4;
+#[cfg(feature = "phy-xdp")]
+pub const XDP_UMEM_FILL_RING:             libc::c_int = 5;
+#[cfg(feature = "phy-xdp")]
+pub const XDP_UMEM_COMPLETION_RING:       libc::c_int = 6;
+#[cfg(feature = "phy-xdp")]
+pub const XDP_PGOFF_RX_RING:              u64 = 0;
+#[cfg(feature = "phy-xdp")]
+pub const XDP_PGOFF_TX_RING:              u64 = 0x80000000;
+#[cfg(feature = "phy-xdp")]
+pub const XDP_UMEM_PGOFF_FILL_RING:       u64 = 0x100000000;
+#[cfg(feature = "phy-xdp")]
+pub const XDP_UMEM_PGOFF_COMPLETION_RING: u64 = 0x180000000;
+#[cfg(feature = "phy-xdp")]
+pub const XDP_PACKET_HEADROOM:            usize = 256;
@@ -0,0 +1,17 @@
+#[cfg(feature = "phy-tap_interface")]
+use libc;
+
+#[cfg(feature = "phy-tap_interface")]
+pub const SIOCGIFMTU:        libc::c_ulong = 0xc0206933;
+#[cfg(feature = "phy-tap_interface")]
+pub const CTLIOCGINFO:       libc::c_ulong = 0xc0644e03;
+#[cfg(feature = "phy-tap_interface")]
+pub const AF_SYSTEM:         libc::c_int   = 32;
+#[cfg(feature = "phy-tap_interface")]
+pub const AF_SYS_CONTROL:    u16           = 2;
+#[cfg(feature = "phy-tap_interface")]
+pub const SYSPROTO_CONTROL:  libc::c_int   = 2;
+#[cfg(feature = "phy-tap_interface")]
+pub const UTUN_OPT_IFNAME:   libc::c_int   = 2;
+#[cfg(feature = "phy-tap_interface")]
+pub const UTUN_CONTROL_NAME: &'static str  = "com.apple.net.utun_control";
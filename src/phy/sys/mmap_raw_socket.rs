@@ -0,0 +1,282 @@
+use std::{cmp, mem, ptr, slice, io};
+use std::sync::atomic::{fence, Ordering};
+use std::os::unix::io::{RawFd, AsRawFd};
+use libc;
+use wire::EthernetFrame;
+use super::*;
+
+#[repr(C)]
+#[derive(Debug)]
+struct tpacket_req3 {
+    tp_block_size:       u32,
+    tp_block_nr:         u32,
+    tp_frame_size:       u32,
+    tp_frame_nr:         u32,
+    tp_retire_blk_tov:   u32,
+    tp_sizeof_priv:      u32,
+    tp_feature_req_word: u32
+}
+
+/// The header of a block of the RX ring, i.e. a `tpacket_block_desc`
+/// with a `tpacket_hdr_v1`.
+#[repr(C)]
+#[allow(dead_code)]
+struct tpacket_block_desc {
+    version:             u32,
+    offset_to_priv:      u32,
+    block_status:        u32,
+    num_pkts:            u32,
+    offset_to_first_pkt: u32,
+    blk_len:             u32,
+    seq_num:             u64,
+    ts_first_pkt:        [u32; 2],
+    ts_last_pkt:         [u32; 2]
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct tpacket3_hdr {
+    tp_next_offset: u32,
+    tp_sec:         u32,
+    tp_nsec:        u32,
+    tp_snaplen:     u32,
+    tp_len:         u32,
+    tp_status:      u32,
+    tp_mac:         u16,
+    tp_net:         u16,
+    tp_rxhash:      u32,
+    tp_vlan_tci:    u32,
+    tp_vlan_tpid:   u16,
+    tp_padding:     u16,
+    tp_padding2:    [u8; 8]
+}
+
+/// The offset of the frame in a slot of the TX ring, i.e. `TPACKET3_HDRLEN` without
+/// the `sockaddr_ll` that the kernel leaves room for.
+const TX_FRAME_OFFSET: usize = (mem::size_of::<tpacket3_hdr>() + 15) & !15;
+
+/// The time after which the kernel hands a block of the RX ring that is not full
+/// over to us, in milliseconds.
+const RX_BLOCK_TIMEOUT: u32 = 1;
+
+#[derive(Debug)]
+pub struct MmapRawSocketDesc {
+    socket:      RawSocketDesc,
+    ring:        *mut u8,
+    block_size:  usize,
+    block_count: usize,
+    slot_size:   usize,
+    slot_count:  usize,
+    // The block of the RX ring we are reading from, the number of packets already
+    // read from it, and the offset of the next one, if the kernel handed it over to us.
+    rx_block:    usize,
+    rx_read:     Option<(u32, usize)>,
+    // The next slot of the TX ring, and whether frames wait for the kernel to be told
+    // about them.
+    tx_slot:     usize,
+    tx_pending:  bool
+}
+
+impl AsRawFd for MmapRawSocketDesc {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+impl MmapRawSocketDesc {
+    /// Create a socket with RX and TX rings of `block_count` blocks of `block_size`
+    /// octets each, the TX ring being divided in slots holding one frame of the size
+    /// of the MTU of the interface. The blocks are enlarged if a slot does not fit.
+    pub fn new(name: &str, block_size: usize,
+               block_count: usize) -> io::Result<MmapRawSocketDesc> {
+        let mut socket = RawSocketDesc::new(name)?;
+        let frame_size = socket.interface_mtu()? + EthernetFrame::<&[u8]>::header_len();
+        // The kernel leaves room for a `sockaddr_ll` after the header of a slot.
+        let frame_size = cmp::max(frame_size, mem::size_of::<libc::sockaddr_ll>());
+        let slot_size = (TX_FRAME_OFFSET + frame_size + 15) & !15;
+        if block_count == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "ring too small"))
+        }
+        // A block holds at least one slot, e.g. on the loopback interface.
+        let block_size = cmp::max(block_size, slot_size.next_power_of_two());
+        let slot_count = (block_size / slot_size) * block_count;
+
+        let fd = socket.as_raw_fd();
+        setsockopt(fd, imp::PACKET_VERSION, &imp::TPACKET_V3)?;
+        setsockopt(fd, imp::PACKET_LOSS, &(1 as libc::c_int))?;
+        setsockopt(fd, imp::PACKET_RX_RING, &tpacket_req3 {
+            tp_block_size:       block_size as u32,
+            tp_block_nr:         block_count as u32,
+            tp_frame_size:       slot_size as u32,
+            tp_frame_nr:         slot_count as u32,
+            tp_retire_blk_tov:   RX_BLOCK_TIMEOUT,
+            tp_sizeof_priv:      0,
+            tp_feature_req_word: 0
+        })?;
+        setsockopt(fd, imp::PACKET_TX_RING, &tpacket_req3 {
+            tp_block_size:       block_size as u32,
+            tp_block_nr:         block_count as u32,
+            tp_frame_size:       slot_size as u32,
+            tp_frame_nr:         slot_count as u32,
+            tp_retire_blk_tov:   0,
+            tp_sizeof_priv:      0,
+            tp_feature_req_word: 0
+        })?;
+
+        // The TX ring follows the RX ring in the mapping.
+        let ring = unsafe {
+            let ring = libc::mmap(ptr::null_mut(), 2 * block_size * block_count,
+                                  libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED, fd, 0);
+            if ring == libc::MAP_FAILED { return Err(io::Error::last_os_error()) }
+            ring as *mut u8
+        };
+
+        Ok(MmapRawSocketDesc {
+            socket, ring, block_size, block_count, slot_size, slot_count,
+            rx_block:   0,
+            rx_read:    None,
+            tx_slot:    0,
+            tx_pending: false
+        })
+    }
+
+    pub fn interface_mtu(&mut self) -> io::Result<usize> {
+        self.socket.interface_mtu()
+    }
+
+    pub fn bind_interface(&mut self) -> io::Result<()> {
+        self.socket.bind_interface()
+    }
+
+    /// Return the offset and the length in the mapping of the next frame received,
+    /// and whether it was truncated to fit into a block, if any.
+    ///
+    /// The frame stays valid until this function is called again, which hands
+    /// the blocks of the RX ring that were read entirely back to the kernel.
+    pub fn recv(&mut self) -> Option<(usize, usize, bool)> {
+        loop {
+            let block_offset = self.rx_block * self.block_size;
+            let block = unsafe { self.ring.offset(block_offset as isize) }
+                as *mut tpacket_block_desc;
+
+            let (read, offset) = match self.rx_read {
+                Some(read) => read,
+                None => unsafe {
+                    let status = ptr::read_volatile(&(*block).block_status);
+                    if status & imp::TP_STATUS_USER == 0 { return None }
+                    fence(Ordering::Acquire);
+                    (0, ptr::read_volatile(&(*block).offset_to_first_pkt) as usize)
+                }
+            };
+
+            unsafe {
+                if read < ptr::read_volatile(&(*block).num_pkts) {
+                    let header = self.ring.offset((block_offset + offset) as isize)
+                        as *const tpacket3_hdr;
+                    let frame_offset = block_offset + offset + (*header).tp_mac as usize;
+                    let frame_len = (*header).tp_snaplen as usize;
+                    let truncated = (*header).tp_len > (*header).tp_snaplen;
+                    self.rx_read = Some((read + 1, offset + (*header).tp_next_offset as usize));
+                    return Some((frame_offset, frame_len, truncated))
+                }
+
+                fence(Ordering::Release);
+                ptr::write_volatile(&mut (*block).block_status, imp::TP_STATUS_KERNEL);
+            }
+            self.rx_read = None;
+            self.rx_block = (self.rx_block + 1) % self.block_count;
+        }
+    }
+
+    /// Return a frame received by [recv](#method.recv).
+    pub fn rx_frame(&self, offset: usize, len: usize) -> &[u8] {
+        assert!(offset + len <= self.block_size * self.block_count);
+        unsafe { slice::from_raw_parts(self.ring.offset(offset as isize), len) }
+    }
+
+    fn tx_header(&self) -> *mut tpacket3_hdr {
+        // The slots do not cross the boundaries of the blocks.
+        let slots_per_block = self.block_size / self.slot_size;
+        let offset = self.block_size * self.block_count +
+                     self.block_size * (self.tx_slot / slots_per_block) +
+                     self.slot_size * (self.tx_slot % slots_per_block);
+        unsafe { self.ring.offset(offset as isize) as *mut tpacket3_hdr }
+    }
+
+    /// Query whether the next slot of the TX ring is free.
+    pub fn tx_available(&self) -> bool {
+        let status = unsafe { ptr::read_volatile(&(*self.tx_header()).tp_status) };
+        status & (imp::TP_STATUS_SEND_REQUEST | imp::TP_STATUS_SENDING) == 0
+    }
+
+    /// Return the largest frame that fits into a slot of the TX ring.
+    pub fn tx_capacity(&self) -> usize {
+        self.slot_size - TX_FRAME_OFFSET
+    }
+
+    /// Return the buffer of a frame of the given length in the next slot of the TX ring,
+    /// which is sent once it is committed with [tx_commit](#method.tx_commit), or `None`
+    /// if the slot is not free.
+    pub fn tx_buffer(&mut self, len: usize) -> Option<&mut [u8]> {
+        if !self.tx_available() || len > self.tx_capacity() { return None }
+        fence(Ordering::Acquire);
+        unsafe {
+            let frame = (self.tx_header() as *mut u8).offset(TX_FRAME_OFFSET as isize);
+            Some(slice::from_raw_parts_mut(frame, len))
+        }
+    }
+
+    /// Hand the frame of the given length in the next slot of the TX ring over
+    /// to the kernel. It is sent once the kernel is told with [flush](#method.flush).
+    pub fn tx_commit(&mut self, len: usize) {
+        let header = self.tx_header();
+        unsafe {
+            (*header).tp_len = len as u32;
+            (*header).tp_snaplen = len as u32;
+            (*header).tp_next_offset = 0;
+            fence(Ordering::Release);
+            ptr::write_volatile(&mut (*header).tp_status, imp::TP_STATUS_SEND_REQUEST);
+        }
+        self.tx_slot = (self.tx_slot + 1) % self.slot_count;
+        self.tx_pending = true;
+    }
+
+    /// Tell the kernel to send the frames committed to the TX ring, if there are any.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if !self.tx_pending { return Ok(()) }
+
+        unsafe {
+            let res = libc::send(self.as_raw_fd(), ptr::null(), 0, libc::MSG_DONTWAIT);
+            if res == -1 {
+                let err = io::Error::last_os_error();
+                // The frames are sent on the next attempt.
+                if err.kind() == io::ErrorKind::WouldBlock ||
+                        err.raw_os_error() == Some(libc::ENOBUFS) {
+                    return Ok(())
+                }
+                return Err(err)
+            }
+        }
+        self.tx_pending = false;
+        Ok(())
+    }
+}
+
+impl Drop for MmapRawSocketDesc {
+    fn drop(&mut self) {
+        let _ = self.flush();
+        unsafe {
+            libc::munmap(self.ring as *mut libc::c_void, 2 * self.block_size * self.block_count);
+        }
+    }
+}
+
+fn setsockopt<T>(lower: libc::c_int, name: libc::c_int, value: &T) -> io::Result<()> {
+    unsafe {
+        let res = libc::setsockopt(lower, imp::SOL_PACKET, name,
+                                   value as *const T as *const libc::c_void,
+                                   mem::size_of::<T>() as libc::socklen_t);
+        if res == -1 { return Err(io::Error::last_os_error()) }
+    }
+    Ok(())
+}
@@ -1,24 +1,67 @@
 #![allow(unsafe_code)]
 
+#[cfg(any(feature = "phy-raw_socket", feature = "phy-tap_interface", feature = "phy-xdp"))]
 use libc;
+#[cfg(any(feature = "phy-raw_socket", feature = "phy-tap_interface", feature = "phy-xdp"))]
 use std::{mem, ptr, io};
+#[cfg(any(feature = "phy-raw_socket", feature = "phy-tap_interface", feature = "phy-xdp"))]
 use std::os::unix::io::RawFd;
+#[cfg(all(feature = "phy-tap_interface",
+          any(target_os = "macos", target_os = "freebsd", target_os = "openbsd")))]
+use byteorder::{ByteOrder, NetworkEndian};
 
 #[cfg(target_os = "linux")]
 #[path = "linux.rs"]
 mod imp;
+#[cfg(target_os = "macos")]
+#[path = "macos.rs"]
+mod imp;
+#[cfg(target_os = "freebsd")]
+#[path = "freebsd.rs"]
+mod imp;
+#[cfg(target_os = "openbsd")]
+#[path = "openbsd.rs"]
+mod imp;
+#[cfg(windows)]
+#[path = "windows.rs"]
+mod imp;
 
 #[cfg(all(feature = "phy-raw_socket", target_os = "linux"))]
 pub mod raw_socket;
+#[cfg(all(feature = "phy-raw_socket", any(target_os = "freebsd", target_os = "openbsd")))]
+#[path = "bpf.rs"]
+pub mod raw_socket;
+#[cfg(all(feature = "phy-raw_socket", target_os = "linux"))]
+pub mod mmap_raw_socket;
 #[cfg(all(feature = "phy-tap_interface", target_os = "linux"))]
 pub mod tap_interface;
+#[cfg(all(feature = "phy-tap_interface", any(target_os = "freebsd", target_os = "openbsd")))]
+#[path = "bsd_tap_interface.rs"]
+pub mod tap_interface;
+#[cfg(all(feature = "phy-xdp", target_os = "linux"))]
+pub mod xdp;
+#[cfg(all(feature = "phy-tap_interface", target_os = "macos"))]
+pub mod utun_interface;
+#[cfg(all(feature = "phy-wintun", windows))]
+pub mod wintun;
 
-#[cfg(all(feature = "phy-raw_socket", target_os = "linux"))]
+#[cfg(all(feature = "phy-raw_socket",
+          any(target_os = "linux", target_os = "freebsd", target_os = "openbsd")))]
 pub use self::raw_socket::RawSocketDesc;
-#[cfg(all(feature = "phy-tap_interface", target_os = "linux"))]
+#[cfg(all(feature = "phy-raw_socket", target_os = "linux"))]
+pub use self::mmap_raw_socket::MmapRawSocketDesc;
+#[cfg(all(feature = "phy-tap_interface",
+          any(target_os = "linux", target_os = "freebsd", target_os = "openbsd")))]
 pub use self::tap_interface::TapInterfaceDesc;
+#[cfg(all(feature = "phy-xdp", target_os = "linux"))]
+pub use self::xdp::XdpDesc;
+#[cfg(all(feature = "phy-tap_interface", target_os = "macos"))]
+pub use self::utun_interface::UtunInterfaceDesc;
+#[cfg(all(feature = "phy-wintun", windows))]
+pub use self::wintun::WintunDesc;
 
 /// Wait until given file descriptor becomes readable, but no longer than given timeout.
+#[cfg(any(feature = "phy-raw_socket", feature = "phy-tap_interface", feature = "phy-xdp"))]
 pub fn wait(fd: RawFd, millis: Option<u64>) -> io::Result<()> {
     unsafe {
         let mut readfds = mem::uninitialized::<libc::fd_set>();
@@ -46,7 +89,8 @@ pub fn wait(fd: RawFd, millis: Option<u64>) -> io::Result<()> {
     }
 }
 
-#[cfg(all(target_os = "linux", any(feature = "phy-tap_interface", feature = "phy-raw_socket")))]
+#[cfg(all(target_os = "linux", any(feature = "phy-tap_interface", feature = "phy-raw_socket",
+                                     feature = "phy-xdp")))]
 #[repr(C)]
 #[derive(Debug)]
 struct ifreq {
@@ -54,19 +98,36 @@ struct ifreq {
     ifr_data: libc::c_int /* ifr_ifindex or ifr_mtu */
 }
 
-#[cfg(all(target_os = "linux", any(feature = "phy-tap_interface", feature = "phy-raw_socket")))]
+/// An `ifreq` of the size expected by the BSD kernels, which copy all of it.
+#[cfg(any(all(any(target_os = "freebsd", target_os = "openbsd"),
+              any(feature = "phy-tap_interface", feature = "phy-raw_socket")),
+          all(target_os = "macos", feature = "phy-tap_interface")))]
+#[repr(C)]
+#[derive(Debug)]
+struct ifreq {
+    ifr_name: [libc::c_char; libc::IF_NAMESIZE],
+    ifr_data: libc::c_int, /* ifr_mtu */
+    _padding: [u8; 12]
+}
+
+#[cfg(any(all(target_os = "linux", any(feature = "phy-tap_interface", feature = "phy-raw_socket",
+                                         feature = "phy-xdp")),
+          all(any(target_os = "freebsd", target_os = "openbsd"),
+              any(feature = "phy-tap_interface", feature = "phy-raw_socket")),
+          all(target_os = "macos", feature = "phy-tap_interface")))]
 fn ifreq_for(name: &str) -> ifreq {
-    let mut ifreq = ifreq {
-        ifr_name: [0; libc::IF_NAMESIZE],
-        ifr_data: 0
-    };
+    let mut ifreq: ifreq = unsafe { mem::zeroed() };
     for (i, byte) in name.as_bytes().iter().enumerate() {
         ifreq.ifr_name[i] = *byte as libc::c_char
     }
     ifreq
 }
 
-#[cfg(all(target_os = "linux", any(feature = "phy-tap_interface", feature = "phy-raw_socket")))]
+#[cfg(any(all(target_os = "linux", any(feature = "phy-tap_interface", feature = "phy-raw_socket",
+                                         feature = "phy-xdp")),
+          all(any(target_os = "freebsd", target_os = "openbsd"),
+              any(feature = "phy-tap_interface", feature = "phy-raw_socket")),
+          all(target_os = "macos", feature = "phy-tap_interface")))]
 fn ifreq_ioctl(lower: libc::c_int, ifreq: &mut ifreq,
                cmd: libc::c_ulong) -> io::Result<libc::c_int> {
     unsafe {
@@ -76,3 +137,64 @@ fn ifreq_ioctl(lower: libc::c_int, ifreq: &mut ifreq,
 
     Ok(ifreq.ifr_data)
 }
+
+/// Query the MTU of the interface described by `ifreq`, through a temporary socket.
+#[cfg(any(all(target_os = "linux", any(feature = "phy-tap_interface", feature = "phy-raw_socket",
+                                         feature = "phy-xdp")),
+          all(any(target_os = "freebsd", target_os = "openbsd"),
+              any(feature = "phy-tap_interface", feature = "phy-raw_socket")),
+          all(target_os = "macos", feature = "phy-tap_interface")))]
+fn ifreq_mtu(ifreq: &mut ifreq) -> io::Result<usize> {
+    let lower = unsafe {
+        let lower = libc::socket(libc::AF_INET, libc::SOCK_DGRAM, libc::IPPROTO_IP);
+        if lower == -1 { return Err(io::Error::last_os_error()) }
+        lower
+    };
+
+    let mtu = ifreq_ioctl(lower, ifreq, imp::SIOCGIFMTU).map(|mtu| mtu as usize);
+
+    unsafe { libc::close(lower); }
+
+    mtu
+}
+
+/// Receive a packet preceded by its protocol family, as on the tun interfaces of the BSDs,
+/// without the protocol family.
+#[cfg(all(feature = "phy-tap_interface",
+          any(target_os = "macos", target_os = "freebsd", target_os = "openbsd")))]
+fn recv_with_family(lower: libc::c_int, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut header = [0u8; 4];
+    let iov = [
+        libc::iovec { iov_base: header.as_mut_ptr() as *mut libc::c_void, iov_len: 4 },
+        libc::iovec { iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
+                      iov_len:  buffer.len() }
+    ];
+    unsafe {
+        let len = libc::readv(lower, iov.as_ptr(), iov.len() as libc::c_int);
+        if len == -1 { return Err(io::Error::last_os_error()) }
+        Ok((len as usize).saturating_sub(header.len()))
+    }
+}
+
+/// Send a packet preceded by its protocol family, as given by its version field.
+#[cfg(all(feature = "phy-tap_interface",
+          any(target_os = "macos", target_os = "freebsd", target_os = "openbsd")))]
+fn send_with_family(lower: libc::c_int, buffer: &[u8]) -> io::Result<usize> {
+    let family = match buffer.get(0).map(|byte| byte >> 4) {
+        Some(6) => libc::AF_INET6,
+        _ => libc::AF_INET
+    };
+    let mut header = [0u8; 4];
+    NetworkEndian::write_u32(&mut header, family as u32);
+
+    let iov = [
+        libc::iovec { iov_base: header.as_mut_ptr() as *mut libc::c_void, iov_len: 4 },
+        libc::iovec { iov_base: buffer.as_ptr() as *mut libc::c_void,
+                      iov_len:  buffer.len() }
+    ];
+    unsafe {
+        let len = libc::writev(lower, iov.as_ptr(), iov.len() as libc::c_int);
+        if len == -1 { return Err(io::Error::last_os_error()) }
+        Ok((len as usize).saturating_sub(header.len()))
+    }
+}
@@ -0,0 +1,22 @@
+use libc;
+
+#[cfg(any(feature = "phy-raw_socket",
+          feature = "phy-tap_interface"))]
+pub const SIOCGIFMTU:        libc::c_ulong = 0xc020697e;
+
+#[cfg(feature = "phy-raw_socket")]
+pub const BIOCGBLEN:         libc::c_ulong = 0x40044266;
+#[cfg(feature = "phy-raw_socket")]
+pub const BIOCSETIF:         libc::c_ulong = 0x8020426c;
+#[cfg(feature = "phy-raw_socket")]
+pub const BIOCIMMEDIATE:     libc::c_ulong = 0x80044270;
+#[cfg(feature = "phy-raw_socket")]
+pub const BIOCSHDRCMPLT:     libc::c_ulong = 0x80044275;
+#[cfg(feature = "phy-raw_socket")]
+pub const BIOCSDIRFILT:      libc::c_ulong = 0x8004427d;
+#[cfg(feature = "phy-raw_socket")]
+pub const BPF_DIRECTION_OUT: libc::c_uint  = 1 << 1;
+#[cfg(feature = "phy-raw_socket")]
+pub const BPF_ALIGNMENT:     usize = 4;
+#[cfg(feature = "phy-raw_socket")]
+pub const BPF_TSTAMP_LEN:    usize = 8;
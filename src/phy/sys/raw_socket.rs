@@ -1,6 +1,8 @@
 use std::{mem, io};
 use std::os::unix::io::{RawFd, AsRawFd};
 use libc;
+use byteorder::{ByteOrder, NativeEndian, NetworkEndian};
+use wire::ip::checksum;
 use super::*;
 
 #[derive(Debug)]
@@ -55,10 +57,12 @@ impl RawSocketDesc {
         Ok(())
     }
 
+    /// Receive a frame, truncated to the size of `buffer` if it is larger,
+    /// and return its length before truncation.
     pub fn recv(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
         unsafe {
             let len = libc::recv(self.lower, buffer.as_mut_ptr() as *mut libc::c_void,
-                                 buffer.len(), 0);
+                                 buffer.len(), libc::MSG_TRUNC);
             if len == -1 { return Err(io::Error::last_os_error()) }
             Ok(len as usize)
         }
@@ -74,6 +78,113 @@ impl RawSocketDesc {
     }
 }
 
+impl RawSocketDesc {
+    /// Precede every frame sent and received with a virtio-net header, which carries
+    /// the checksum and segmentation offload requests.
+    pub fn enable_vnet_header(&mut self) -> io::Result<()> {
+        let value: libc::c_int = 1;
+        unsafe {
+            let res = libc::setsockopt(self.lower, imp::SOL_PACKET, imp::PACKET_VNET_HDR,
+                                       &value as *const libc::c_int as *const libc::c_void,
+                                       mem::size_of::<libc::c_int>() as libc::socklen_t);
+            if res == -1 { return Err(io::Error::last_os_error()) }
+        }
+        Ok(())
+    }
+
+    /// Receive a frame preceded by a virtio-net header, without the header, completing
+    /// its checksum if the header requests it.
+    ///
+    /// Since the segments received by the interface may have been coalesced, the frame
+    /// can be as large as an IP packet may be.
+    pub fn recv_with_vnet_header(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let mut header = [0u8; imp::VIRTIO_NET_HDR_LEN];
+        let iov = [
+            libc::iovec { iov_base: header.as_mut_ptr() as *mut libc::c_void,
+                          iov_len:  header.len() },
+            libc::iovec { iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
+                          iov_len:  buffer.len() }
+        ];
+        let len = unsafe {
+            let len = libc::readv(self.lower, iov.as_ptr(), iov.len() as libc::c_int);
+            if len == -1 { return Err(io::Error::last_os_error()) }
+            (len as usize).saturating_sub(header.len())
+        };
+
+        if header[0] & imp::VIRTIO_NET_HDR_F_NEEDS_CSUM != 0 {
+            // The checksum field holds the checksum of the pseudo-header, and the checksum
+            // of everything from the start offset on completes it.
+            let start  = NativeEndian::read_u16(&header[6..]) as usize;
+            let offset = start + NativeEndian::read_u16(&header[8..]) as usize;
+            if offset + 2 <= len {
+                // As in UDP, a checksum of zero is sent as all-ones.
+                let sum = match !checksum::data(&buffer[start..len]) {
+                    0 => 0xffff,
+                    sum => sum
+                };
+                NetworkEndian::write_u16(&mut buffer[offset..], sum);
+            }
+        }
+        Ok(len)
+    }
+
+    /// Send a frame preceded by a virtio-net header, which requests the checksum of
+    /// a TCP segment, whose checksum field holds the checksum of the pseudo-header, to be
+    /// completed, and the segment to be split into segments carrying up to `segment_size`
+    /// octets of payload if that is given.
+    pub fn send_with_vnet_header(&mut self, buffer: &[u8],
+                                 segment_size: Option<usize>) -> io::Result<usize> {
+        let mut header = [0u8; imp::VIRTIO_NET_HDR_LEN];
+        if let Some((csum_start, header_len, gso_type)) = tcp_offsets(buffer) {
+            header[0] = imp::VIRTIO_NET_HDR_F_NEEDS_CSUM;
+            NativeEndian::write_u16(&mut header[6..], csum_start as u16);
+            NativeEndian::write_u16(&mut header[8..], 16);
+            if let Some(segment_size) = segment_size {
+                header[1] = gso_type;
+                NativeEndian::write_u16(&mut header[2..], header_len as u16);
+                NativeEndian::write_u16(&mut header[4..], segment_size as u16);
+            }
+        }
+
+        let iov = [
+            libc::iovec { iov_base: header.as_mut_ptr() as *mut libc::c_void,
+                          iov_len:  header.len() },
+            libc::iovec { iov_base: buffer.as_ptr() as *mut libc::c_void,
+                          iov_len:  buffer.len() }
+        ];
+        unsafe {
+            let len = libc::writev(self.lower, iov.as_ptr(), iov.len() as libc::c_int);
+            if len == -1 { return Err(io::Error::last_os_error()) }
+            Ok((len as usize).saturating_sub(header.len()))
+        }
+    }
+}
+
+/// Return the offset of the TCP header in an Ethernet frame, the length of the headers
+/// up to the TCP payload, and the virtio-net segmentation type, if the frame carries
+/// a TCP segment.
+fn tcp_offsets(frame: &[u8]) -> Option<(usize, usize, u8)> {
+    const ETHERNET_HEADER_LEN: usize = 14;
+    const IPPROTO_TCP: u8 = 6;
+
+    if frame.len() < ETHERNET_HEADER_LEN { return None }
+    let (ip_header_len, protocol, gso_type) =
+        match NetworkEndian::read_u16(&frame[12..]) {
+            0x0800 => ((frame.get(ETHERNET_HEADER_LEN)? & 0x0f) as usize * 4,
+                       *frame.get(ETHERNET_HEADER_LEN + 9)?,
+                       imp::VIRTIO_NET_HDR_GSO_TCPV4),
+            0x86dd => (40,
+                       *frame.get(ETHERNET_HEADER_LEN + 6)?,
+                       imp::VIRTIO_NET_HDR_GSO_TCPV6),
+            _ => return None
+        };
+    if protocol != IPPROTO_TCP { return None }
+
+    let tcp_start = ETHERNET_HEADER_LEN + ip_header_len;
+    let tcp_header_len = (frame.get(tcp_start + 12)? >> 4) as usize * 4;
+    Some((tcp_start, tcp_start + tcp_header_len, gso_type))
+}
+
 impl Drop for RawSocketDesc {
     fn drop(&mut self) {
         unsafe { libc::close(self.lower); }
@@ -1,6 +1,7 @@
 use std::io;
 use std::os::unix::io::{RawFd, AsRawFd};
 use libc;
+use phy::Medium;
 use super::*;
 
 #[derive(Debug)]
@@ -30,23 +31,21 @@ impl TapInterfaceDesc {
         })
     }
 
-    pub fn attach_interface(&mut self) -> io::Result<()> {
-        self.ifreq.ifr_data = imp::IFF_TAP | imp::IFF_NO_PI;
+    pub fn attach_interface(&mut self, medium: Medium) -> io::Result<()> {
+        let mode = match medium {
+            Medium::Ethernet => imp::IFF_TAP,
+            Medium::Ip => imp::IFF_TUN,
+            #[cfg(feature = "proto-sixlowpan")]
+            Medium::Ieee802154 =>
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          "IEEE 802.15.4 is not supported by tap interfaces")),
+        };
+        self.ifreq.ifr_data = mode | imp::IFF_NO_PI;
         ifreq_ioctl(self.lower, &mut self.ifreq, imp::TUNSETIFF).map(|_| ())
     }
 
     pub fn interface_mtu(&mut self) -> io::Result<usize> {
-        let lower = unsafe {
-            let lower = libc::socket(libc::AF_INET, libc::SOCK_DGRAM, libc::IPPROTO_IP);
-            if lower == -1 { return Err(io::Error::last_os_error()) }
-            lower
-        };
-
-        let mtu = ifreq_ioctl(lower, &mut self.ifreq, imp::SIOCGIFMTU).map(|mtu| mtu as usize);
-
-        unsafe { libc::close(lower); }
-
-        mtu
+        ifreq_mtu(&mut self.ifreq)
     }
 
     pub fn recv(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
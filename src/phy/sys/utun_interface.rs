@@ -0,0 +1,118 @@
+use std::{mem, io};
+use std::os::unix::io::{RawFd, AsRawFd};
+use std::string::String;
+use libc;
+use super::*;
+
+#[repr(C)]
+struct ctl_info {
+    ctl_id:   u32,
+    ctl_name: [libc::c_char; 96]
+}
+
+#[repr(C)]
+struct sockaddr_ctl {
+    sc_len:      u8,
+    sc_family:   u8,
+    ss_sysaddr:  u16,
+    sc_id:       u32,
+    sc_unit:     u32,
+    sc_reserved: [u32; 5]
+}
+
+#[derive(Debug)]
+pub struct UtunInterfaceDesc {
+    lower: libc::c_int
+}
+
+impl AsRawFd for UtunInterfaceDesc {
+    fn as_raw_fd(&self) -> RawFd {
+        self.lower
+    }
+}
+
+impl UtunInterfaceDesc {
+    /// Create the utun interface called `name`, i.e. `utun` followed by its number,
+    /// or the first one available if `name` is `utun`.
+    pub fn new(name: &str) -> io::Result<UtunInterfaceDesc> {
+        let invalid_name = || io::Error::new(io::ErrorKind::InvalidInput,
+                                             "invalid utun interface name");
+        // The units are numbered from one, zero meaning the first one available.
+        let unit = if name == "utun" {
+            0
+        } else if name.starts_with("utun") {
+            name[4..].parse::<u32>().map(|number| number + 1).map_err(|_| invalid_name())?
+        } else {
+            return Err(invalid_name())
+        };
+
+        let lower = unsafe {
+            let lower = libc::socket(imp::AF_SYSTEM, libc::SOCK_DGRAM, imp::SYSPROTO_CONTROL);
+            if lower == -1 { return Err(io::Error::last_os_error()) }
+            lower
+        };
+        // Close the socket if anything below fails.
+        let desc = UtunInterfaceDesc { lower };
+
+        let mut info = ctl_info { ctl_id: 0, ctl_name: [0; 96] };
+        for (i, byte) in imp::UTUN_CONTROL_NAME.as_bytes().iter().enumerate() {
+            info.ctl_name[i] = *byte as libc::c_char
+        }
+
+        unsafe {
+            let res = libc::ioctl(lower, imp::CTLIOCGINFO, &mut info as *mut ctl_info);
+            if res == -1 { return Err(io::Error::last_os_error()) }
+
+            let sockaddr = sockaddr_ctl {
+                sc_len:      mem::size_of::<sockaddr_ctl>() as u8,
+                sc_family:   imp::AF_SYSTEM as u8,
+                ss_sysaddr:  imp::AF_SYS_CONTROL,
+                sc_id:       info.ctl_id,
+                sc_unit:     unit,
+                sc_reserved: [0; 5]
+            };
+            let res = libc::connect(lower,
+                                    &sockaddr as *const sockaddr_ctl as *const libc::sockaddr,
+                                    mem::size_of::<sockaddr_ctl>() as libc::socklen_t);
+            if res == -1 { return Err(io::Error::last_os_error()) }
+
+            let res = libc::fcntl(lower, libc::F_SETFL, libc::O_NONBLOCK);
+            if res == -1 { return Err(io::Error::last_os_error()) }
+        }
+
+        Ok(desc)
+    }
+
+    /// Return the name of the interface, which the kernel chose if it was not given.
+    pub fn interface_name(&self) -> io::Result<String> {
+        let mut name = [0u8; libc::IF_NAMESIZE];
+        let mut len = name.len() as libc::socklen_t;
+        unsafe {
+            let res = libc::getsockopt(self.lower, imp::SYSPROTO_CONTROL, imp::UTUN_OPT_IFNAME,
+                                       name.as_mut_ptr() as *mut libc::c_void, &mut len);
+            if res == -1 { return Err(io::Error::last_os_error()) }
+        }
+        let len = name.iter().position(|&byte| byte == 0).unwrap_or(len as usize);
+        Ok(String::from_utf8_lossy(&name[..len]).into_owned())
+    }
+
+    pub fn interface_mtu(&mut self) -> io::Result<usize> {
+        ifreq_mtu(&mut ifreq_for(&self.interface_name()?))
+    }
+
+    /// Receive a packet, without the protocol family that precedes it.
+    pub fn recv(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        recv_with_family(self.lower, buffer)
+    }
+
+    /// Send a packet, preceded by its protocol family, as given by its version field.
+    pub fn send(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        send_with_family(self.lower, buffer)
+    }
+}
+
+impl Drop for UtunInterfaceDesc {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.lower); }
+    }
+}
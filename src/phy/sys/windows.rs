@@ -0,0 +1,20 @@
+#[cfg(feature = "phy-wintun")]
+pub const LOAD_LIBRARY_SEARCH_APPLICATION_DIR: u32 = 0x00000200;
+#[cfg(feature = "phy-wintun")]
+pub const LOAD_LIBRARY_SEARCH_SYSTEM32:        u32 = 0x00000800;
+#[cfg(feature = "phy-wintun")]
+pub const INFINITE:                            u32 = 0xffffffff;
+#[cfg(feature = "phy-wintun")]
+pub const WAIT_FAILED:                         u32 = 0xffffffff;
+#[cfg(feature = "phy-wintun")]
+pub const ERROR_HANDLE_EOF:                    i32 = 38;
+#[cfg(feature = "phy-wintun")]
+pub const ERROR_BUFFER_OVERFLOW:               i32 = 111;
+#[cfg(feature = "phy-wintun")]
+pub const ERROR_NO_MORE_ITEMS:                 i32 = 259;
+#[cfg(feature = "phy-wintun")]
+pub const WINTUN_MIN_RING_CAPACITY:            u32 = 0x20000;
+#[cfg(feature = "phy-wintun")]
+pub const WINTUN_MAX_RING_CAPACITY:            u32 = 0x4000000;
+#[cfg(feature = "phy-wintun")]
+pub const WINTUN_MAX_IP_PACKET_SIZE:           usize = 0xffff;
@@ -0,0 +1,199 @@
+use std::{cmp, fmt, mem, ptr, slice, io};
+use std::vec::Vec;
+use std::os::raw::c_void;
+use super::*;
+
+type Handle = *mut c_void;
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn LoadLibraryExW(file_name: *const u16, file: Handle, flags: u32) -> Handle;
+    fn GetProcAddress(module: Handle, proc_name: *const u8) -> *mut c_void;
+    fn FreeLibrary(module: Handle) -> i32;
+    fn WaitForSingleObject(handle: Handle, millis: u32) -> u32;
+}
+
+/// Encode `string` as a null-terminated UTF-16 string, as expected by the wide
+/// variants of the Windows API.
+fn wide_string(string: &str) -> Vec<u16> {
+    string.encode_utf16().chain(Some(0)).collect()
+}
+
+/// The entry points of `wintun.dll`, which is loaded at run time, as recommended
+/// by its authors, since it is distributed alongside the application rather than
+/// with the system.
+struct Wintun {
+    library:                Handle,
+    create_adapter:         unsafe extern "system" fn(*const u16, *const u16,
+                                                      *const c_void) -> Handle,
+    close_adapter:          unsafe extern "system" fn(Handle),
+    start_session:          unsafe extern "system" fn(Handle, u32) -> Handle,
+    end_session:            unsafe extern "system" fn(Handle),
+    get_read_wait_event:    unsafe extern "system" fn(Handle) -> Handle,
+    receive_packet:         unsafe extern "system" fn(Handle, *mut u32) -> *mut u8,
+    release_receive_packet: unsafe extern "system" fn(Handle, *const u8),
+    allocate_send_packet:   unsafe extern "system" fn(Handle, u32) -> *mut u8,
+    send_packet:            unsafe extern "system" fn(Handle, *const u8)
+}
+
+impl Wintun {
+    fn load() -> io::Result<Wintun> {
+        let library = unsafe {
+            let library = LoadLibraryExW(wide_string("wintun.dll").as_ptr(), ptr::null_mut(),
+                                         imp::LOAD_LIBRARY_SEARCH_APPLICATION_DIR |
+                                         imp::LOAD_LIBRARY_SEARCH_SYSTEM32);
+            if library.is_null() { return Err(io::Error::last_os_error()) }
+            library
+        };
+
+        let symbol = |name: &[u8]| -> io::Result<*mut c_void> {
+            let symbol = unsafe { GetProcAddress(library, name.as_ptr()) };
+            if symbol.is_null() {
+                let err = io::Error::last_os_error();
+                unsafe { FreeLibrary(library); }
+                return Err(err)
+            }
+            Ok(symbol)
+        };
+
+        unsafe {
+            Ok(Wintun {
+                create_adapter:
+                    mem::transmute(symbol(b"WintunCreateAdapter\0")?),
+                close_adapter:
+                    mem::transmute(symbol(b"WintunCloseAdapter\0")?),
+                start_session:
+                    mem::transmute(symbol(b"WintunStartSession\0")?),
+                end_session:
+                    mem::transmute(symbol(b"WintunEndSession\0")?),
+                get_read_wait_event:
+                    mem::transmute(symbol(b"WintunGetReadWaitEvent\0")?),
+                receive_packet:
+                    mem::transmute(symbol(b"WintunReceivePacket\0")?),
+                release_receive_packet:
+                    mem::transmute(symbol(b"WintunReleaseReceivePacket\0")?),
+                allocate_send_packet:
+                    mem::transmute(symbol(b"WintunAllocateSendPacket\0")?),
+                send_packet:
+                    mem::transmute(symbol(b"WintunSendPacket\0")?),
+                library
+            })
+        }
+    }
+}
+
+impl Drop for Wintun {
+    fn drop(&mut self) {
+        unsafe { FreeLibrary(self.library); }
+    }
+}
+
+pub struct WintunDesc {
+    wintun:  Wintun,
+    adapter: Handle,
+    session: Handle
+}
+
+impl fmt::Debug for WintunDesc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WintunDesc")
+         .field("adapter", &self.adapter)
+         .field("session", &self.session)
+         .finish()
+    }
+}
+
+impl WintunDesc {
+    /// Create the Wintun adapter called `name`, and start a session on it with rings
+    /// of `ring_capacity` octets, a power of two between 128 KiB and 64 MiB.
+    pub fn new(name: &str, ring_capacity: u32) -> io::Result<WintunDesc> {
+        if !ring_capacity.is_power_of_two() ||
+                ring_capacity < imp::WINTUN_MIN_RING_CAPACITY ||
+                ring_capacity > imp::WINTUN_MAX_RING_CAPACITY {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "invalid Wintun ring capacity"))
+        }
+
+        let wintun = Wintun::load()?;
+        unsafe {
+            let adapter = (wintun.create_adapter)(wide_string(name).as_ptr(),
+                                                  wide_string("smoltcp").as_ptr(),
+                                                  ptr::null());
+            if adapter.is_null() { return Err(io::Error::last_os_error()) }
+
+            let session = (wintun.start_session)(adapter, ring_capacity);
+            if session.is_null() {
+                let err = io::Error::last_os_error();
+                (wintun.close_adapter)(adapter);
+                return Err(err)
+            }
+
+            Ok(WintunDesc { wintun, adapter, session })
+        }
+    }
+
+    /// Wait until a packet can be received, but no longer than given timeout.
+    pub fn wait(&self, millis: Option<u64>) -> io::Result<()> {
+        let millis = millis.map(|millis| cmp::min(millis, (imp::INFINITE - 1) as u64) as u32)
+                           .unwrap_or(imp::INFINITE);
+        unsafe {
+            let event = (self.wintun.get_read_wait_event)(self.session);
+            let res = WaitForSingleObject(event, millis);
+            if res == imp::WAIT_FAILED { return Err(io::Error::last_os_error()) }
+        }
+        Ok(())
+    }
+
+    /// Receive a packet, truncated to the size of `buffer` if it is larger,
+    /// and return its length before truncation.
+    pub fn recv(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        unsafe {
+            let mut size = 0;
+            let packet = (self.wintun.receive_packet)(self.session, &mut size);
+            if packet.is_null() { return Err(last_ring_error()) }
+
+            let len = cmp::min(size as usize, buffer.len());
+            buffer[..len].copy_from_slice(slice::from_raw_parts(packet, len));
+            (self.wintun.release_receive_packet)(self.session, packet);
+            Ok(size as usize)
+        }
+    }
+
+    pub fn send(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        if buffer.len() > imp::WINTUN_MAX_IP_PACKET_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "packet too large for Wintun"))
+        }
+
+        unsafe {
+            let packet = (self.wintun.allocate_send_packet)(self.session, buffer.len() as u32);
+            if packet.is_null() { return Err(last_ring_error()) }
+
+            slice::from_raw_parts_mut(packet, buffer.len()).copy_from_slice(buffer);
+            (self.wintun.send_packet)(self.session, packet);
+            Ok(buffer.len())
+        }
+    }
+}
+
+impl Drop for WintunDesc {
+    fn drop(&mut self) {
+        unsafe {
+            (self.wintun.end_session)(self.session);
+            (self.wintun.close_adapter)(self.adapter);
+        }
+    }
+}
+
+/// Return the error of the last operation on a ring, reporting an empty receive ring
+/// or a full send ring as `WouldBlock`.
+fn last_ring_error() -> io::Error {
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(imp::ERROR_NO_MORE_ITEMS) | Some(imp::ERROR_BUFFER_OVERFLOW) =>
+            io::Error::new(io::ErrorKind::WouldBlock, err),
+        Some(imp::ERROR_HANDLE_EOF) =>
+            io::Error::new(io::ErrorKind::BrokenPipe, "Wintun adapter is gone"),
+        _ => err
+    }
+}
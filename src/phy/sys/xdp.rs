@@ -0,0 +1,394 @@
+use std::{cmp, mem, ptr, slice, io};
+use std::vec::Vec;
+use std::sync::atomic::{fence, Ordering};
+use std::os::unix::io::{RawFd, AsRawFd};
+use libc;
+use wire::EthernetFrame;
+use super::*;
+
+#[repr(C)]
+#[derive(Debug)]
+struct xdp_umem_reg {
+    addr:            u64,
+    len:             u64,
+    chunk_size:      u32,
+    headroom:        u32,
+    flags:           u32,
+    tx_metadata_len: u32
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct xdp_ring_offset {
+    producer: u64,
+    consumer: u64,
+    desc:     u64,
+    flags:    u64
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct xdp_mmap_offsets {
+    rx: xdp_ring_offset,
+    tx: xdp_ring_offset,
+    fr: xdp_ring_offset,
+    cr: xdp_ring_offset
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct xdp_desc {
+    addr:    u64,
+    len:     u32,
+    options: u32
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct sockaddr_xdp {
+    sxdp_family:         u16,
+    sxdp_flags:          u16,
+    sxdp_ifindex:        u32,
+    sxdp_queue_id:       u32,
+    sxdp_shared_umem_fd: u32
+}
+
+/// A ring shared with the kernel, holding either frame descriptors (RX and TX rings)
+/// or frame addresses (fill and completion rings).
+///
+/// The producer and consumer indices run freely, and are reduced modulo the size
+/// of the ring, which is a power of two.
+#[derive(Debug)]
+struct Ring<T: Copy> {
+    map:      *mut u8,
+    map_len:  usize,
+    producer: *mut u32,
+    consumer: *mut u32,
+    entries:  *mut T,
+    size:     u32
+}
+
+impl<T: Copy> Ring<T> {
+    fn map(fd: libc::c_int, offsets: &xdp_ring_offset, page_offset: u64,
+           size: u32) -> io::Result<Ring<T>> {
+        let map_len = offsets.desc as usize + size as usize * mem::size_of::<T>();
+        let map = unsafe {
+            let map = libc::mmap(ptr::null_mut(), map_len, libc::PROT_READ | libc::PROT_WRITE,
+                                 libc::MAP_SHARED | libc::MAP_POPULATE, fd,
+                                 page_offset as libc::off_t);
+            if map == libc::MAP_FAILED { return Err(io::Error::last_os_error()) }
+            map as *mut u8
+        };
+        unsafe {
+            Ok(Ring {
+                map, map_len, size,
+                producer: map.offset(offsets.producer as isize) as *mut u32,
+                consumer: map.offset(offsets.consumer as isize) as *mut u32,
+                entries:  map.offset(offsets.desc as isize) as *mut T
+            })
+        }
+    }
+
+    fn producer(&self) -> u32 {
+        unsafe { ptr::read_volatile(self.producer) }
+    }
+
+    fn consumer(&self) -> u32 {
+        unsafe { ptr::read_volatile(self.consumer) }
+    }
+
+    /// Return the next entry the kernel produced, if any, without consuming it.
+    fn peek(&self) -> Option<T> {
+        let consumer = self.consumer();
+        if self.producer() == consumer { return None }
+        fence(Ordering::Acquire);
+        let index = consumer & (self.size - 1);
+        unsafe { Some(ptr::read_volatile(self.entries.offset(index as isize))) }
+    }
+
+    /// Consume the entry returned by [peek](#method.peek).
+    fn consume(&mut self) {
+        let consumer = self.consumer();
+        fence(Ordering::Release);
+        unsafe { ptr::write_volatile(self.consumer, consumer.wrapping_add(1)) }
+    }
+
+    /// Query whether there is room for another entry for the kernel.
+    fn can_produce(&self) -> bool {
+        self.producer().wrapping_sub(self.consumer()) < self.size
+    }
+
+    /// Hand an entry over to the kernel.
+    ///
+    /// # Panics
+    /// This function panics if the ring is full.
+    fn produce(&mut self, entry: T) {
+        assert!(self.can_produce());
+        let producer = self.producer();
+        let index = producer & (self.size - 1);
+        unsafe {
+            ptr::write_volatile(self.entries.offset(index as isize), entry);
+            fence(Ordering::Release);
+            ptr::write_volatile(self.producer, producer.wrapping_add(1));
+        }
+    }
+}
+
+impl<T: Copy> Drop for Ring<T> {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.map as *mut libc::c_void, self.map_len); }
+    }
+}
+
+#[derive(Debug)]
+pub struct XdpDesc {
+    lower:      libc::c_int,
+    umem:       *mut u8,
+    umem_len:   usize,
+    frame_size: usize,
+    mtu:        usize,
+    fill:       Ring<u64>,
+    completion: Ring<u64>,
+    rx:         Ring<xdp_desc>,
+    tx:         Ring<xdp_desc>,
+    // The frame of the UMEM last returned by `recv`, which goes back to the fill ring
+    // on the next call.
+    rx_frame:   Option<u64>,
+    // The frames of the UMEM that are not handed over to the kernel for sending.
+    tx_frames:  Vec<u64>,
+    tx_pending: bool
+}
+
+impl AsRawFd for XdpDesc {
+    fn as_raw_fd(&self) -> RawFd {
+        self.lower
+    }
+}
+
+impl XdpDesc {
+    /// Create a socket bound to the given queue of the interface called `name`,
+    /// with a UMEM of `frame_count` frames of `frame_size` octets each, half of
+    /// which are used for receiving and half for sending.
+    ///
+    /// Both `frame_size` and `frame_count` must be powers of two, and `frame_size`
+    /// must be between 2048 and the page size.
+    pub fn new(name: &str, queue_id: u32, frame_size: usize,
+               frame_count: usize) -> io::Result<XdpDesc> {
+        if !frame_size.is_power_of_two() || !frame_count.is_power_of_two() || frame_count < 2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid UMEM size"))
+        }
+        let ring_size = (frame_count / 2) as u32;
+
+        let (ifindex, mtu) = interface_info(name)?;
+        let mtu = cmp::min(mtu + EthernetFrame::<&[u8]>::header_len(),
+                           frame_size.saturating_sub(imp::XDP_PACKET_HEADROOM));
+
+        let lower = unsafe {
+            let lower = libc::socket(imp::AF_XDP, libc::SOCK_RAW, 0);
+            if lower == -1 { return Err(io::Error::last_os_error()) }
+            lower
+        };
+        // Close the socket if anything below fails.
+        let socket = Socket(lower);
+
+        let umem_len = frame_size * frame_count;
+        let umem = unsafe {
+            let umem = libc::mmap(ptr::null_mut(), umem_len, libc::PROT_READ | libc::PROT_WRITE,
+                                  libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0);
+            if umem == libc::MAP_FAILED { return Err(io::Error::last_os_error()) }
+            umem as *mut u8
+        };
+        let umem_guard = Umem(umem, umem_len);
+
+        setsockopt(lower, imp::XDP_UMEM_REG, &xdp_umem_reg {
+            addr:            umem as u64,
+            len:             umem_len as u64,
+            chunk_size:      frame_size as u32,
+            headroom:        0,
+            flags:           0,
+            tx_metadata_len: 0
+        })?;
+        setsockopt(lower, imp::XDP_UMEM_FILL_RING, &ring_size)?;
+        setsockopt(lower, imp::XDP_UMEM_COMPLETION_RING, &ring_size)?;
+        setsockopt(lower, imp::XDP_RX_RING, &ring_size)?;
+        setsockopt(lower, imp::XDP_TX_RING, &ring_size)?;
+
+        let mut offsets = xdp_mmap_offsets::default();
+        unsafe {
+            let mut len = mem::size_of::<xdp_mmap_offsets>() as libc::socklen_t;
+            let res = libc::getsockopt(lower, imp::SOL_XDP, imp::XDP_MMAP_OFFSETS,
+                                       &mut offsets as *mut _ as *mut libc::c_void, &mut len);
+            if res == -1 { return Err(io::Error::last_os_error()) }
+        }
+
+        let mut fill = Ring::map(lower, &offsets.fr, imp::XDP_UMEM_PGOFF_FILL_RING, ring_size)?;
+        let completion = Ring::map(lower, &offsets.cr, imp::XDP_UMEM_PGOFF_COMPLETION_RING,
+                                   ring_size)?;
+        let rx = Ring::map(lower, &offsets.rx, imp::XDP_PGOFF_RX_RING, ring_size)?;
+        let tx = Ring::map(lower, &offsets.tx, imp::XDP_PGOFF_TX_RING, ring_size)?;
+
+        // The first half of the UMEM is handed over to the kernel for receiving,
+        // and the second half is kept for sending.
+        for index in 0..ring_size as u64 {
+            fill.produce(index * frame_size as u64);
+        }
+        let tx_frames = (ring_size as u64..frame_count as u64)
+            .map(|index| index * frame_size as u64)
+            .collect();
+
+        let sockaddr = sockaddr_xdp {
+            sxdp_family:         imp::AF_XDP as u16,
+            sxdp_flags:          0,
+            sxdp_ifindex:        ifindex as u32,
+            sxdp_queue_id:       queue_id,
+            sxdp_shared_umem_fd: 0
+        };
+        unsafe {
+            let res = libc::bind(lower, &sockaddr as *const sockaddr_xdp as *const libc::sockaddr,
+                                 mem::size_of::<sockaddr_xdp>() as libc::socklen_t);
+            if res == -1 { return Err(io::Error::last_os_error()) }
+        }
+
+        mem::forget(socket);
+        mem::forget(umem_guard);
+        Ok(XdpDesc {
+            lower, umem, umem_len, frame_size, mtu, fill, completion, rx, tx, tx_frames,
+            rx_frame:   None,
+            tx_pending: false
+        })
+    }
+
+    /// Return the largest frame that can be received or sent, which is bounded both
+    /// by the MTU of the interface and the size of the frames of the UMEM.
+    pub fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    /// Return the address and the length in the UMEM of the next frame received,
+    /// if any.
+    ///
+    /// The frame stays valid until this function is called again, which hands
+    /// it back to the kernel.
+    pub fn recv(&mut self) -> Option<(u64, usize)> {
+        if let Some(addr) = self.rx_frame.take() {
+            // The fill ring is as large as the half of the UMEM used for receiving.
+            self.fill.produce(addr);
+        }
+
+        let desc = self.rx.peek()?;
+        self.rx.consume();
+        // The kernel places the frame after some headroom in the frame of the UMEM.
+        self.rx_frame = Some(desc.addr & !(self.frame_size as u64 - 1));
+        Some((desc.addr, desc.len as usize))
+    }
+
+    /// Return a frame received by [recv](#method.recv).
+    pub fn rx_frame(&self, addr: u64, len: usize) -> &[u8] {
+        assert!(addr as usize % self.frame_size + len <= self.frame_size);
+        unsafe { slice::from_raw_parts(self.umem.offset(addr as isize), len) }
+    }
+
+    /// Take back the frames the kernel sent.
+    fn reclaim(&mut self) {
+        while let Some(addr) = self.completion.peek() {
+            self.completion.consume();
+            self.tx_frames.push(addr);
+        }
+    }
+
+    /// Query whether a frame can be sent.
+    pub fn tx_available(&mut self) -> bool {
+        self.reclaim();
+        !self.tx_frames.is_empty() && self.tx.can_produce()
+    }
+
+    /// Return the buffer of a frame of the given length, which is sent once it is
+    /// committed with [tx_commit](#method.tx_commit), or `None` if no frame can be sent.
+    pub fn tx_buffer(&mut self, len: usize) -> Option<&mut [u8]> {
+        if !self.tx_available() || len > self.mtu { return None }
+        let addr = *self.tx_frames.last().unwrap();
+        unsafe { Some(slice::from_raw_parts_mut(self.umem.offset(addr as isize), len)) }
+    }
+
+    /// Hand the frame of the given length returned by [tx_buffer](#method.tx_buffer)
+    /// over to the kernel. It is sent once the kernel is told with [flush](#method.flush).
+    pub fn tx_commit(&mut self, len: usize) {
+        let addr = self.tx_frames.pop().unwrap();
+        self.tx.produce(xdp_desc { addr, len: len as u32, options: 0 });
+        self.tx_pending = true;
+    }
+
+    /// Tell the kernel to send the frames committed so far, if there are any.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if !self.tx_pending { return Ok(()) }
+
+        unsafe {
+            let res = libc::sendto(self.lower, ptr::null(), 0, libc::MSG_DONTWAIT,
+                                   ptr::null(), 0);
+            if res == -1 {
+                let err = io::Error::last_os_error();
+                // The frames are sent on the next attempt.
+                match err.raw_os_error() {
+                    Some(libc::EAGAIN) | Some(libc::EBUSY) | Some(libc::ENOBUFS) =>
+                        return Ok(()),
+                    _ => return Err(err)
+                }
+            }
+        }
+        self.tx_pending = false;
+        Ok(())
+    }
+}
+
+impl Drop for XdpDesc {
+    fn drop(&mut self) {
+        let _ = self.flush();
+        // The rings are unmapped when they are dropped, after the socket is closed.
+        unsafe {
+            libc::close(self.lower);
+            libc::munmap(self.umem as *mut libc::c_void, self.umem_len);
+        }
+    }
+}
+
+/// Closes a socket that is being set up, unless forgotten.
+struct Socket(libc::c_int);
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0); }
+    }
+}
+
+/// Unmaps a UMEM that is being set up, unless forgotten.
+struct Umem(*mut u8, usize);
+
+impl Drop for Umem {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.0 as *mut libc::c_void, self.1); }
+    }
+}
+
+/// Return the index and the MTU of the interface called `name`. AF_XDP sockets
+/// do not support these requests, so they are made through another socket.
+fn interface_info(name: &str) -> io::Result<(libc::c_int, usize)> {
+    let socket = unsafe {
+        let lower = libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0);
+        if lower == -1 { return Err(io::Error::last_os_error()) }
+        Socket(lower)
+    };
+    let mut ifreq = ifreq_for(name);
+    let ifindex = ifreq_ioctl(socket.0, &mut ifreq, imp::SIOCGIFINDEX)?;
+    let mtu = ifreq_ioctl(socket.0, &mut ifreq, imp::SIOCGIFMTU)?;
+    Ok((ifindex, mtu as usize))
+}
+
+fn setsockopt<T>(lower: libc::c_int, name: libc::c_int, value: &T) -> io::Result<()> {
+    unsafe {
+        let res = libc::setsockopt(lower, imp::SOL_XDP, name,
+                                   value as *const T as *const libc::c_void,
+                                   mem::size_of::<T>() as libc::socklen_t);
+        if res == -1 { return Err(io::Error::last_os_error()) }
+    }
+    Ok(())
+}
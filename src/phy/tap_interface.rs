@@ -5,13 +5,18 @@ use std::io;
 use std::os::unix::io::{RawFd, AsRawFd};
 
 use Result;
-use phy::{self, sys, DeviceCapabilities, Device};
+use phy::{self, sys, DeviceCapabilities, Device, Medium};
 
-/// A virtual Ethernet interface.
+/// A virtual Ethernet interface, or a virtual IP interface (i.e. a tun device).
+///
+/// On FreeBSD and OpenBSD, the interface is opened through its device, e.g. `/dev/tap0`,
+/// so that its name determines its medium: tap interfaces carry Ethernet frames,
+/// and tun interfaces carry IP packets. The interface remains after the device is dropped.
 #[derive(Debug)]
 pub struct TapInterface {
     lower:  Rc<RefCell<sys::TapInterfaceDesc>>,
-    mtu:    usize
+    mtu:    usize,
+    medium: Medium
 }
 
 impl AsRawFd for TapInterface {
@@ -27,12 +32,21 @@ impl TapInterface {
     /// no special privileges are needed. Otherwise, this requires superuser privileges
     /// or a corresponding capability set on the executable.
     pub fn new(name: &str) -> io::Result<TapInterface> {
+        TapInterface::with_medium(name, Medium::Ethernet)
+    }
+
+    /// Attaches to a TAP interface, if the medium is Ethernet, or to a TUN interface,
+    /// if it is IP, called `name`, or creates it if it does not exist.
+    ///
+    /// See [new](#method.new) for the privileges this requires.
+    pub fn with_medium(name: &str, medium: Medium) -> io::Result<TapInterface> {
         let mut lower = sys::TapInterfaceDesc::new(name)?;
-        lower.attach_interface()?;
+        lower.attach_interface(medium)?;
         let mtu = lower.interface_mtu()?;
         Ok(TapInterface {
-            lower: Rc::new(RefCell::new(lower)),
-            mtu:   mtu
+            lower:  Rc::new(RefCell::new(lower)),
+            mtu:    mtu,
+            medium: medium
         })
     }
 }
@@ -44,6 +58,7 @@ impl<'a> Device<'a> for TapInterface {
     fn capabilities(&self) -> DeviceCapabilities {
         DeviceCapabilities {
             max_transmission_unit: self.mtu,
+            medium: self.medium,
             ..DeviceCapabilities::default()
         }
     }
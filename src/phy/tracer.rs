@@ -1,21 +1,82 @@
+use core::marker::PhantomData;
+
 use Result;
 use wire::pretty_print::{PrettyPrint, PrettyPrinter};
-use phy::{self, DeviceCapabilities, Device};
+use wire::EthernetAddress;
+use phy::{self, DeviceCapabilities, Device, RxLoan};
+
+/// The direction of a packet traversing a tracer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TracerDirection {
+    /// The packet was received from the underlying device.
+    Rx,
+    /// The packet was transmitted through the underlying device.
+    Tx
+}
+
+/// A sink of the packets traversing a tracer.
+///
+/// The sink is given the direction and timestamp of each packet along with its listing,
+/// which it may format, e.g. into a log or a ring buffer in memory, or inspect
+/// in its own way. The listing starts with `<- ` for received packets and `-> `
+/// for transmitted ones.
+pub trait TracerSink<P: PrettyPrint> {
+    /// Record a packet traversing the tracer.
+    fn trace(&self, direction: TracerDirection, timestamp: u64, printer: PrettyPrinter<P>);
+}
+
+impl<P: PrettyPrint> TracerSink<P> for fn(u64, PrettyPrinter<P>) {
+    fn trace(&self, _direction: TracerDirection, timestamp: u64, printer: PrettyPrinter<P>) {
+        self(timestamp, printer)
+    }
+}
+
+impl<'a, P: PrettyPrint, T: TracerSink<P> + ?Sized> TracerSink<P> for &'a T {
+    fn trace(&self, direction: TracerDirection, timestamp: u64, printer: PrettyPrinter<P>) {
+        (**self).trace(direction, timestamp, printer)
+    }
+}
 
 /// A tracer device.
 ///
 /// A tracer is a device that pretty prints all packets traversing it
-/// using the provided writer function, and then passes them to another
+/// using the provided writer function or [sink], and then passes them to another
 /// device.
-pub struct Tracer<D: for<'a> Device<'a>, P: PrettyPrint> {
-    inner:  D,
-    writer: fn(u64, PrettyPrinter<P>),
+///
+/// The sink should be cheaply cloneable, as it is cloned on every packet;
+/// e.g. a reference to a sink is a sink as well.
+///
+/// [sink]: trait.TracerSink.html
+pub struct Tracer<D, P, S = fn(u64, PrettyPrinter<P>)>
+    where D: for<'a> Device<'a>,
+          P: PrettyPrint,
+          S: TracerSink<P> + Clone,
+{
+    inner:   D,
+    sink:    S,
+    phantom: PhantomData<P>,
 }
 
 impl<D: for<'a> Device<'a>, P: PrettyPrint> Tracer<D, P> {
     /// Create a tracer device.
     pub fn new(inner: D, writer: fn(timestamp: u64, printer: PrettyPrinter<P>)) -> Tracer<D, P> {
-        Tracer { inner, writer }
+        Tracer::with_sink(inner, writer)
+    }
+}
+
+impl<D, P, S> Tracer<D, P, S>
+    where D: for<'a> Device<'a>,
+          P: PrettyPrint,
+          S: TracerSink<P> + Clone,
+{
+    /// Create a tracer device that passes the packets to the given sink.
+    pub fn with_sink(inner: D, sink: S) -> Tracer<D, P, S> {
+        Tracer { inner, sink, phantom: PhantomData }
+    }
+
+    /// Return a reference to the sink.
+    pub fn sink(&self) -> &S {
+        &self.sink
     }
 
     /// Return the underlying device, consuming the tracer.
@@ -24,65 +85,156 @@ impl<D: for<'a> Device<'a>, P: PrettyPrint> Tracer<D, P> {
     }
 }
 
-impl<'a, D, P> Device<'a> for Tracer<D, P>
+impl<'a, D, P, S> Device<'a> for Tracer<D, P, S>
     where D: for<'b> Device<'b>,
           P: PrettyPrint + 'a,
+          S: TracerSink<P> + Clone + 'a,
 {
-    type RxToken = RxToken<<D as Device<'a>>::RxToken, P>;
-    type TxToken = TxToken<<D as Device<'a>>::TxToken, P>;
+    type RxToken = RxToken<<D as Device<'a>>::RxToken, P, S>;
+    type TxToken = TxToken<<D as Device<'a>>::TxToken, P, S>;
 
     fn capabilities(&self) -> DeviceCapabilities { self.inner.capabilities() }
 
+    fn add_multicast_filter(&mut self, addr: EthernetAddress) {
+        self.inner.add_multicast_filter(addr)
+    }
+
+    fn remove_multicast_filter(&mut self, addr: EthernetAddress) {
+        self.inner.remove_multicast_filter(addr)
+    }
+
+    fn lent(&self, loan: &RxLoan) -> Option<&[u8]> { self.inner.lent(loan) }
+
+    fn release(&mut self, loan: RxLoan) { self.inner.release(loan) }
+
+    fn tx_hw_timestamp(&mut self) -> Option<u64> { self.inner.tx_hw_timestamp() }
+
     fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
-        let &mut Self { ref mut inner, writer, .. } = self;
+        let &mut Self { ref mut inner, ref sink, .. } = self;
         inner.receive().map(|(rx_token, tx_token)| {
-            let rx = RxToken { token: rx_token, writer: writer };
-            let tx = TxToken { token: tx_token, writer: writer };
+            let rx = RxToken { token: rx_token, sink: sink.clone(), phantom: PhantomData };
+            let tx = TxToken { token: tx_token, sink: sink.clone(), phantom: PhantomData };
             (rx, tx)
         })
     }
 
     fn transmit(&'a mut self) -> Option<Self::TxToken> {
-        let &mut Self { ref mut inner, writer } = self;
+        let &mut Self { ref mut inner, ref sink, .. } = self;
         inner.transmit().map(|tx_token| {
-            TxToken { token: tx_token, writer: writer }
+            TxToken { token: tx_token, sink: sink.clone(), phantom: PhantomData }
         })
     }
 }
 
 #[doc(hidden)]
-pub struct RxToken<Rx: phy::RxToken, P: PrettyPrint> {
-    token:     Rx,
-    writer:    fn(u64, PrettyPrinter<P>)
+pub struct RxToken<Rx: phy::RxToken, P: PrettyPrint, S: TracerSink<P>> {
+    token:   Rx,
+    sink:    S,
+    phantom: PhantomData<P>
 }
 
-impl<Rx: phy::RxToken, P: PrettyPrint> phy::RxToken for RxToken<Rx, P> {
+impl<Rx: phy::RxToken, P: PrettyPrint, S: TracerSink<P>> phy::RxToken for RxToken<Rx, P, S> {
     fn consume<R, F>(self, timestamp: u64, f: F) -> Result<R>
         where F: FnOnce(&[u8]) -> Result<R>
     {
-        let Self { token, writer } = self;
+        let Self { token, sink, .. } = self;
         token.consume(timestamp, |buffer| {
-            writer(timestamp, PrettyPrinter::<P>::new("<- ", &buffer));
+            sink.trace(TracerDirection::Rx, timestamp,
+                       PrettyPrinter::<P>::new("<- ", &buffer));
             f(buffer)
         })
     }
+
+    fn is_truncated(&self) -> bool {
+        self.token.is_truncated()
+    }
+
+    fn hw_timestamp(&self) -> Option<u64> {
+        self.token.hw_timestamp()
+    }
+
+    fn consume_lend<R, F>(self, timestamp: u64, f: F) -> Result<R>
+        where F: FnOnce(&[u8], Option<RxLoan>) -> (Result<R>, bool)
+    {
+        let Self { token, sink, .. } = self;
+        token.consume_lend(timestamp, |buffer, loan| {
+            sink.trace(TracerDirection::Rx, timestamp,
+                       PrettyPrinter::<P>::new("<- ", &buffer));
+            f(buffer, loan)
+        })
+    }
 }
 
 #[doc(hidden)]
-pub struct TxToken<Tx: phy::TxToken, P: PrettyPrint> {
-    token:     Tx,
-    writer:    fn(u64, PrettyPrinter<P>)
+pub struct TxToken<Tx: phy::TxToken, P: PrettyPrint, S: TracerSink<P>> {
+    token:   Tx,
+    sink:    S,
+    phantom: PhantomData<P>
 }
 
-impl<Tx: phy::TxToken, P: PrettyPrint> phy::TxToken for TxToken<Tx, P> {
+impl<Tx: phy::TxToken, P: PrettyPrint, S: TracerSink<P>> phy::TxToken for TxToken<Tx, P, S> {
     fn consume<R, F>(self, timestamp: u64, len: usize, f: F) -> Result<R>
         where F: FnOnce(&mut [u8]) -> Result<R>
     {
-        let Self { token, writer } = self;
+        let Self { token, sink, .. } = self;
         token.consume(timestamp, len, |buffer| {
             let result = f(buffer);
-            writer(timestamp, PrettyPrinter::<P>::new("-> ", &buffer));
+            sink.trace(TracerDirection::Tx, timestamp,
+                       PrettyPrinter::<P>::new("-> ", &buffer));
             result
         })
     }
+
+    fn set_segment_size(&mut self, segment_size: usize) {
+        self.token.set_segment_size(segment_size)
+    }
+
+    fn request_hw_timestamp(&mut self) {
+        self.token.request_hw_timestamp()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::string::String;
+    use std::vec::Vec;
+    use wire::EthernetFrame;
+    use phy::{Loopback, RxToken, TxToken};
+    use super::*;
+
+    const FRAME: [u8; 14] = [
+        0x02, 0x02, 0x02, 0x02, 0x02, 0x02,
+        0x02, 0x02, 0x02, 0x02, 0x02, 0x01,
+        0x88, 0xb5
+    ];
+
+    #[derive(Debug, Default)]
+    struct Ring {
+        events: RefCell<Vec<(TracerDirection, u64, String)>>
+    }
+
+    impl<P: PrettyPrint> TracerSink<P> for Ring {
+        fn trace(&self, direction: TracerDirection, timestamp: u64, printer: PrettyPrinter<P>) {
+            self.events.borrow_mut().push((direction, timestamp, format!("{}", printer)))
+        }
+    }
+
+    #[test]
+    fn test_sink() {
+        let ring = Ring::default();
+        let mut device = Tracer::<_, EthernetFrame<&[u8]>, _>::with_sink(Loopback::new(), &ring);
+        device.transmit().unwrap().consume(10, FRAME.len(), |buffer| {
+            buffer.copy_from_slice(&FRAME);
+            Ok(())
+        }).unwrap();
+        let (rx, _tx) = device.receive().unwrap();
+        rx.consume(20, |buffer| Ok(assert_eq!(buffer, &FRAME[..]))).unwrap();
+
+        let listing = "EthernetII src=02-02-02-02-02-01 dst=02-02-02-02-02-02 type=0x88b5";
+        assert_eq!(*ring.events.borrow(), vec![
+            (TracerDirection::Tx, 10, format!("-> {}", listing)),
+            (TracerDirection::Rx, 20, format!("<- {}", listing)),
+        ]);
+    }
 }
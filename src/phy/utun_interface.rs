@@ -0,0 +1,111 @@
+use std::cell::RefCell;
+use std::vec::Vec;
+use std::rc::Rc;
+use std::io;
+use std::string::String;
+use std::os::unix::io::{RawFd, AsRawFd};
+
+use Result;
+use phy::{self, sys, DeviceCapabilities, Device, Medium};
+
+/// A virtual IP interface of macOS, i.e. a utun device.
+///
+/// The packets are received and sent without the protocol family that precedes them
+/// on the control socket of the interface, so that the medium of the device is IP.
+#[derive(Debug)]
+pub struct UtunInterface {
+    lower:  Rc<RefCell<sys::UtunInterfaceDesc>>,
+    mtu:    usize
+}
+
+impl AsRawFd for UtunInterface {
+    fn as_raw_fd(&self) -> RawFd {
+        self.lower.borrow().as_raw_fd()
+    }
+}
+
+impl UtunInterface {
+    /// Creates a utun interface called `name`, e.g. `utun3`, or the first one available
+    /// if `name` is `utun`. The interface is destroyed when the device is dropped.
+    ///
+    /// This requires superuser privileges.
+    pub fn new(name: &str) -> io::Result<UtunInterface> {
+        let mut lower = sys::UtunInterfaceDesc::new(name)?;
+        let mtu = lower.interface_mtu()?;
+        Ok(UtunInterface {
+            lower: Rc::new(RefCell::new(lower)),
+            mtu:   mtu
+        })
+    }
+
+    /// Returns the name of the interface, e.g. to configure its addresses.
+    pub fn name(&self) -> io::Result<String> {
+        self.lower.borrow().interface_name()
+    }
+}
+
+impl<'a> Device<'a> for UtunInterface {
+    type RxToken = RxToken;
+    type TxToken = TxToken;
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        DeviceCapabilities {
+            max_transmission_unit: self.mtu,
+            medium: Medium::Ip,
+            ..DeviceCapabilities::default()
+        }
+    }
+
+    fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+        let mut lower = self.lower.borrow_mut();
+        let mut buffer = vec![0; self.mtu];
+        match lower.recv(&mut buffer[..]) {
+            Ok(size) => {
+                buffer.resize(size, 0);
+                let rx = RxToken { buffer };
+                let tx = TxToken { lower: self.lower.clone() };
+                Some((rx, tx))
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                None
+            }
+            Err(err) => panic!("{}", err)
+        }
+    }
+
+    fn transmit(&'a mut self) -> Option<Self::TxToken> {
+        Some(TxToken {
+            lower: self.lower.clone(),
+        })
+    }
+}
+
+#[doc(hidden)]
+pub struct RxToken {
+    buffer: Vec<u8>
+}
+
+impl phy::RxToken for RxToken {
+    fn consume<R, F>(self, _timestamp: u64, f: F) -> Result<R>
+        where F: FnOnce(&[u8]) -> Result<R>
+    {
+        f(&self.buffer[..])
+    }
+}
+
+#[doc(hidden)]
+pub struct TxToken {
+    lower: Rc<RefCell<sys::UtunInterfaceDesc>>,
+}
+
+impl phy::TxToken for TxToken {
+    fn consume<R, F>(self, _timestamp: u64, len: usize, f: F) -> Result<R>
+        where F: FnOnce(&mut [u8]) -> Result<R>
+    {
+        let mut lower = self.lower.borrow_mut();
+        let mut buffer = vec![0; len];
+        let result = f(&mut buffer);
+        lower.send(&mut buffer[..]).unwrap();
+        result
+    }
+}
@@ -0,0 +1,268 @@
+#[cfg(feature = "std")]
+use std::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::Vec;
+use byteorder::{ByteOrder, NetworkEndian};
+
+use {Error, Result};
+use wire::{EthernetAddress, EthernetProtocol};
+use phy::{self, DeviceCapabilities, Device};
+
+/// The length of an 802.1Q tag, i.e. of the tag protocol identifier and the tag control
+/// information.
+const TAG_LEN: usize = 4;
+
+/// The offset of the tag in a frame, i.e. the length of the addresses that precede it.
+const TAG_OFFSET: usize = 12;
+
+/// A VLAN device.
+///
+/// A VLAN device is an Ethernet device that inserts an 802.1Q tag, carrying its VLAN
+/// identifier and priority, into all frames transmitted through another device,
+/// and only passes on the frames received from it that carry a tag with its VLAN identifier,
+/// with the tag removed. The frames of other VLANs, and the untagged frames,
+/// are dropped, making the receive token return `Err(Error::Exhausted)`.
+///
+/// Several logical interfaces can be attached to the VLANs of a trunk port
+/// by wrapping a device opened on the same physical interface in a VLAN device
+/// for each of them, e.g. a [RawSocket] each. On Linux, the tags are only visible
+/// to raw sockets if the VLAN offload of the interface is disabled,
+/// e.g. with `ethtool -K eth0 rxvlan off txvlan off`.
+///
+/// [RawSocket]: struct.RawSocket.html
+#[derive(Debug)]
+pub struct VlanDevice<D: for<'a> Device<'a>> {
+    inner: D,
+    tci:   u16
+}
+
+impl<D: for<'a> Device<'a>> VlanDevice<D> {
+    /// Create a VLAN device with the given VLAN identifier and priority code point.
+    ///
+    /// # Panics
+    /// This function panics if the VLAN identifier is reserved, i.e. 0 or 4095,
+    /// or out of range, or if the priority is not between 0 and 7.
+    pub fn new(inner: D, vlan_id: u16, priority: u8) -> VlanDevice<D> {
+        if vlan_id == 0 || vlan_id >= 0xfff { panic!("VLAN identifier out of range") }
+        if priority > 7 { panic!("priority out of range") }
+        VlanDevice { inner, tci: (priority as u16) << 13 | vlan_id }
+    }
+
+    /// Return the VLAN identifier.
+    pub fn vlan_id(&self) -> u16 {
+        self.tci & 0xfff
+    }
+
+    /// Return the priority code point of the transmitted frames.
+    pub fn priority(&self) -> u8 {
+        (self.tci >> 13) as u8
+    }
+
+    /// Return the underlying device, consuming the VLAN device.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<'a, D> Device<'a> for VlanDevice<D>
+    where D: for<'b> Device<'b>,
+{
+    type RxToken = RxToken<<D as Device<'a>>::RxToken>;
+    type TxToken = TxToken<<D as Device<'a>>::TxToken>;
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = self.inner.capabilities();
+        caps.max_transmission_unit -= TAG_LEN;
+        caps.max_tso_size = caps.max_tso_size.map(|size| size - TAG_LEN);
+        caps
+    }
+
+    fn add_multicast_filter(&mut self, addr: EthernetAddress) {
+        self.inner.add_multicast_filter(addr)
+    }
+
+    fn remove_multicast_filter(&mut self, addr: EthernetAddress) {
+        self.inner.remove_multicast_filter(addr)
+    }
+
+    fn tx_hw_timestamp(&mut self) -> Option<u64> { self.inner.tx_hw_timestamp() }
+
+    fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+        let tci = self.tci;
+        self.inner.receive().map(|(rx_token, tx_token)| {
+            let rx = RxToken { token: rx_token, tci: tci };
+            let tx = TxToken { token: tx_token, tci: tci };
+            (rx, tx)
+        })
+    }
+
+    fn transmit(&'a mut self) -> Option<Self::TxToken> {
+        let tci = self.tci;
+        self.inner.transmit().map(|tx_token| {
+            TxToken { token: tx_token, tci: tci }
+        })
+    }
+}
+
+#[doc(hidden)]
+pub struct RxToken<Rx: phy::RxToken> {
+    token: Rx,
+    tci:   u16
+}
+
+impl<Rx: phy::RxToken> phy::RxToken for RxToken<Rx> {
+    fn consume<R, F>(self, timestamp: u64, f: F) -> Result<R>
+        where F: FnOnce(&[u8]) -> Result<R>
+    {
+        let Self { token, tci } = self;
+        token.consume(timestamp, |buffer| {
+            if buffer.len() < TAG_OFFSET + TAG_LEN ||
+                    EthernetProtocol::from(NetworkEndian::read_u16(&buffer[TAG_OFFSET..])) !=
+                        EthernetProtocol::Vlan ||
+                    NetworkEndian::read_u16(&buffer[TAG_OFFSET + 2..]) & 0xfff != tci & 0xfff {
+                net_trace!("rx: dropping a frame of another VLAN");
+                return Err(Error::Exhausted)
+            }
+
+            let mut frame = Vec::with_capacity(buffer.len() - TAG_LEN);
+            frame.extend_from_slice(&buffer[..TAG_OFFSET]);
+            frame.extend_from_slice(&buffer[TAG_OFFSET + TAG_LEN..]);
+            f(&frame)
+        })
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.token.is_truncated()
+    }
+
+    fn hw_timestamp(&self) -> Option<u64> {
+        self.token.hw_timestamp()
+    }
+}
+
+/// Insert a tag into a frame emitted `TAG_LEN` octets into `buffer`.
+fn insert_tag(buffer: &mut [u8], tci: u16) {
+    // Move the addresses in front of the tag, which goes before the EtherType.
+    for i in 0..TAG_OFFSET {
+        buffer[i] = buffer[i + TAG_LEN]
+    }
+    NetworkEndian::write_u16(&mut buffer[TAG_OFFSET..], EthernetProtocol::Vlan.into());
+    NetworkEndian::write_u16(&mut buffer[TAG_OFFSET + 2..], tci);
+}
+
+#[doc(hidden)]
+pub struct TxToken<Tx: phy::TxToken> {
+    token: Tx,
+    tci:   u16
+}
+
+impl<Tx: phy::TxToken> phy::TxToken for TxToken<Tx> {
+    fn consume<R, F>(self, timestamp: u64, len: usize, f: F) -> Result<R>
+        where F: FnOnce(&mut [u8]) -> Result<R>
+    {
+        if len < TAG_OFFSET { return Err(Error::Truncated) }
+
+        let Self { token, tci } = self;
+        token.consume(timestamp, len + TAG_LEN, |buffer| {
+            let result = f(&mut buffer[TAG_LEN..])?;
+            insert_tag(buffer, tci);
+            Ok(result)
+        })
+    }
+
+    fn consume_gather<R, F>(self, timestamp: u64, header_len: usize, payload: &[&[u8]],
+                            f: F) -> Result<R>
+        where F: FnOnce(&mut [u8]) -> Result<R>
+    {
+        if header_len < TAG_OFFSET { return Err(Error::Truncated) }
+
+        let Self { token, tci } = self;
+        token.consume_gather(timestamp, header_len + TAG_LEN, payload, |buffer| {
+            let result = f(&mut buffer[TAG_LEN..])?;
+            insert_tag(buffer, tci);
+            Ok(result)
+        })
+    }
+
+    fn set_segment_size(&mut self, segment_size: usize) {
+        self.token.set_segment_size(segment_size)
+    }
+
+    fn request_hw_timestamp(&mut self) {
+        self.token.request_hw_timestamp()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use phy::{Loopback, RxToken, TxToken};
+    use super::*;
+
+    const FRAME: [u8; 18] = [
+        0x02, 0x02, 0x02, 0x02, 0x02, 0x02,
+        0x02, 0x02, 0x02, 0x02, 0x02, 0x01,
+        0x08, 0x00,
+        0xaa, 0xbb, 0xcc, 0xdd
+    ];
+
+    fn send<D: for<'a> Device<'a>>(device: &mut D, frame: &[u8]) {
+        device.transmit().unwrap().consume(0, frame.len(), |buffer| {
+            buffer.copy_from_slice(frame);
+            Ok(())
+        }).unwrap();
+    }
+
+    fn recv<D: for<'a> Device<'a>>(device: &mut D) -> Result<Vec<u8>> {
+        let (rx, _tx) = device.receive().unwrap();
+        rx.consume(0, |buffer| Ok(buffer.to_vec()))
+    }
+
+    #[test]
+    fn test_tag() {
+        let mut device = VlanDevice::new(Loopback::new(), 100, 5);
+        assert_eq!(device.vlan_id(), 100);
+        assert_eq!(device.priority(), 5);
+        send(&mut device, &FRAME);
+
+        let mut inner = device.into_inner();
+        let frame = recv(&mut inner).unwrap();
+        assert_eq!(&frame[..12], &FRAME[..12]);
+        assert_eq!(&frame[12..16], &[0x81, 0x00, 0xa0, 0x64]);
+        assert_eq!(&frame[16..], &FRAME[12..]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut device = VlanDevice::new(Loopback::new(), 100, 0);
+        send(&mut device, &FRAME);
+        assert_eq!(recv(&mut device), Ok(FRAME.to_vec()));
+    }
+
+    #[test]
+    fn test_gather() {
+        let mut device = VlanDevice::new(Loopback::new(), 100, 0);
+        device.transmit().unwrap().consume_gather(0, 16, &[&FRAME[16..]], |buffer| {
+            buffer.copy_from_slice(&FRAME[..16]);
+            Ok(())
+        }).unwrap();
+        assert_eq!(recv(&mut device), Ok(FRAME.to_vec()));
+    }
+
+    #[test]
+    fn test_other_vlan() {
+        let mut device = VlanDevice::new(Loopback::new(), 100, 0);
+        send(&mut device, &FRAME);
+
+        let mut device = VlanDevice::new(device.into_inner(), 200, 0);
+        assert_eq!(recv(&mut device), Err(Error::Exhausted));
+    }
+
+    #[test]
+    fn test_untagged() {
+        let mut inner = Loopback::new();
+        send(&mut inner, &FRAME);
+
+        let mut device = VlanDevice::new(inner, 100, 0);
+        assert_eq!(recv(&mut device), Err(Error::Exhausted));
+    }
+}
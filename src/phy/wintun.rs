@@ -0,0 +1,135 @@
+use std::cell::RefCell;
+use std::vec::Vec;
+use std::rc::Rc;
+use std::io;
+
+use {Error, Result};
+use phy::{self, sys, DeviceCapabilities, Device, Medium};
+
+/// A virtual IP interface of Windows, i.e. a Wintun adapter.
+///
+/// The adapter is created through `wintun.dll`, which is not part of Windows,
+/// and has to be distributed alongside the application, either in its directory
+/// or in the system directory. Like the macOS [UtunInterface], the adapter carries
+/// IP packets, so that the medium of the device is IP.
+///
+/// Since the adapter has no file descriptor, waiting for it to become readable is done
+/// with [wait] rather than with [phy::wait].
+///
+/// [UtunInterface]: struct.UtunInterface.html
+/// [wait]: #method.wait
+/// [phy::wait]: fn.wait.html
+#[derive(Debug)]
+pub struct WintunInterface {
+    lower:  Rc<RefCell<sys::WintunDesc>>,
+    mtu:    usize
+}
+
+impl WintunInterface {
+    /// The default capacity of the rings shared with the driver, in octets.
+    pub const DEFAULT_RING_CAPACITY: u32 = 0x400000;
+
+    /// Creates a Wintun adapter called `name`, with rings of the default capacity,
+    /// i.e. 4 MiB. The adapter is removed when the device is dropped.
+    ///
+    /// The MTU of the device should be the one configured for the IP interfaces
+    /// of the adapter, e.g. with `netsh interface ipv4 set subinterface`.
+    ///
+    /// This requires Administrator privileges.
+    pub fn new(name: &str, mtu: usize) -> io::Result<WintunInterface> {
+        Self::with_ring_capacity(name, mtu, Self::DEFAULT_RING_CAPACITY)
+    }
+
+    /// Creates a Wintun adapter called `name`, with rings of `ring_capacity` octets,
+    /// which must be a power of two between 128 KiB and 64 MiB.
+    pub fn with_ring_capacity(name: &str, mtu: usize,
+                              ring_capacity: u32) -> io::Result<WintunInterface> {
+        let lower = sys::WintunDesc::new(name, ring_capacity)?;
+        Ok(WintunInterface {
+            lower: Rc::new(RefCell::new(lower)),
+            mtu:   mtu
+        })
+    }
+
+    /// Waits until a packet can be received, but no longer than given timeout.
+    pub fn wait(&self, millis: Option<u64>) -> io::Result<()> {
+        self.lower.borrow().wait(millis)
+    }
+}
+
+impl<'a> Device<'a> for WintunInterface {
+    type RxToken = RxToken;
+    type TxToken = TxToken;
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        DeviceCapabilities {
+            max_transmission_unit: self.mtu,
+            medium: Medium::Ip,
+            ..DeviceCapabilities::default()
+        }
+    }
+
+    fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+        let mut lower = self.lower.borrow_mut();
+        let mut buffer = vec![0; self.mtu];
+        match lower.recv(&mut buffer[..]) {
+            Ok(size) => {
+                let truncated = size > buffer.len();
+                buffer.truncate(size);
+                let rx = RxToken { buffer, truncated };
+                let tx = TxToken { lower: self.lower.clone() };
+                Some((rx, tx))
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                None
+            }
+            Err(err) => panic!("{}", err)
+        }
+    }
+
+    fn transmit(&'a mut self) -> Option<Self::TxToken> {
+        Some(TxToken {
+            lower: self.lower.clone(),
+        })
+    }
+}
+
+#[doc(hidden)]
+pub struct RxToken {
+    buffer:    Vec<u8>,
+    truncated: bool
+}
+
+impl phy::RxToken for RxToken {
+    fn consume<R, F>(self, _timestamp: u64, f: F) -> Result<R>
+        where F: FnOnce(&[u8]) -> Result<R>
+    {
+        f(&self.buffer[..])
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+#[doc(hidden)]
+pub struct TxToken {
+    lower: Rc<RefCell<sys::WintunDesc>>,
+}
+
+impl phy::TxToken for TxToken {
+    fn consume<R, F>(self, _timestamp: u64, len: usize, f: F) -> Result<R>
+        where F: FnOnce(&mut [u8]) -> Result<R>
+    {
+        let mut lower = self.lower.borrow_mut();
+        let mut buffer = vec![0; len];
+        let result = f(&mut buffer)?;
+        match lower.send(&buffer[..]) {
+            Ok(_) => Ok(result),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                Err(Error::Exhausted)
+            }
+            Err(err) => panic!("{}", err)
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::io;
+use std::os::unix::io::{RawFd, AsRawFd};
+
+use {Error, Result};
+use phy::{self, sys, DeviceCapabilities, Device};
+
+/// A device that receives and transmits complete frames through an AF_XDP socket,
+/// bypassing the network stack of the kernel.
+///
+/// The frames are received and sent in place in a memory area shared with the kernel
+/// (the UMEM), half of which holds the frames being received, and half the frames
+/// being sent. Like with [MmapRawSocket], the frames to send are handed over in
+/// batches, when the device is next asked to receive a frame, when no more frames
+/// can be sent, or when [flush] is called.
+///
+/// The socket only receives the frames that an XDP program attached to the interface
+/// redirects to it, i.e. to the index of its queue in an `XSKMAP` into which
+/// the application inserted its file descriptor. Loading such a program is left
+/// to the application, e.g. through libbpf or libxdp; the frames can be sent
+/// without it. The kernel uses zero-copy mode if the driver of the interface
+/// supports it, and copies the frames otherwise.
+///
+/// This requires Linux 5.4 or later, and superuser privileges or the corresponding
+/// capability bits set on the executable.
+///
+/// [MmapRawSocket]: struct.MmapRawSocket.html
+/// [flush]: #method.flush
+#[derive(Debug)]
+pub struct XdpDevice {
+    lower:  Rc<RefCell<sys::XdpDesc>>,
+    mtu:    usize
+}
+
+impl AsRawFd for XdpDevice {
+    fn as_raw_fd(&self) -> RawFd {
+        self.lower.borrow().as_raw_fd()
+    }
+}
+
+impl XdpDevice {
+    /// The default size of the frames of the UMEM, in octets.
+    pub const DEFAULT_FRAME_SIZE: usize = 4096;
+
+    /// The default number of frames of the UMEM.
+    pub const DEFAULT_FRAME_COUNT: usize = 4096;
+
+    /// Creates a device bound to the given queue of the interface called `name`,
+    /// with a UMEM of the default size, i.e. 16 MiB.
+    pub fn new(name: &str, queue_id: u32) -> io::Result<XdpDevice> {
+        Self::with_umem_size(name, queue_id, Self::DEFAULT_FRAME_SIZE, Self::DEFAULT_FRAME_COUNT)
+    }
+
+    /// Creates a device bound to the given queue of the interface called `name`,
+    /// with a UMEM of `frame_count` frames of `frame_size` octets each.
+    ///
+    /// Both must be powers of two, and the frame size must be between 2048 octets
+    /// and the page size. The MTU of the device is that of the interface, unless
+    /// the frames are smaller.
+    pub fn with_umem_size(name: &str, queue_id: u32, frame_size: usize,
+                          frame_count: usize) -> io::Result<XdpDevice> {
+        let lower = sys::XdpDesc::new(name, queue_id, frame_size, frame_count)?;
+        let mtu = lower.mtu();
+        Ok(XdpDevice {
+            lower: Rc::new(RefCell::new(lower)),
+            mtu:   mtu
+        })
+    }
+
+    /// Hands the frames sent so far over to the kernel.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.lower.borrow_mut().flush()
+    }
+}
+
+impl<'a> Device<'a> for XdpDevice {
+    type RxToken = RxToken;
+    type TxToken = TxToken;
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        DeviceCapabilities {
+            max_transmission_unit: self.mtu,
+            ..DeviceCapabilities::default()
+        }
+    }
+
+    fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+        let mut lower = self.lower.borrow_mut();
+        if let Err(err) = lower.flush() { panic!("{}", err) }
+        let (addr, len) = lower.recv()?;
+        let rx = RxToken { lower: self.lower.clone(), addr, len };
+        let tx = TxToken { lower: self.lower.clone() };
+        Some((rx, tx))
+    }
+
+    fn transmit(&'a mut self) -> Option<Self::TxToken> {
+        let mut lower = self.lower.borrow_mut();
+        if !lower.tx_available() {
+            if let Err(err) = lower.flush() { panic!("{}", err) }
+            return None
+        }
+        Some(TxToken { lower: self.lower.clone() })
+    }
+}
+
+#[doc(hidden)]
+pub struct RxToken {
+    lower:  Rc<RefCell<sys::XdpDesc>>,
+    addr:   u64,
+    len:    usize
+}
+
+impl phy::RxToken for RxToken {
+    fn consume<R, F: FnOnce(&[u8]) -> Result<R>>(self, _timestamp: u64, f: F) -> Result<R> {
+        let lower = self.lower.borrow();
+        f(lower.rx_frame(self.addr, self.len))
+    }
+}
+
+#[doc(hidden)]
+pub struct TxToken {
+    lower:  Rc<RefCell<sys::XdpDesc>>,
+}
+
+impl phy::TxToken for TxToken {
+    fn consume<R, F: FnOnce(&mut [u8]) -> Result<R>>(self, _timestamp: u64, len: usize, f: F)
+        -> Result<R>
+    {
+        let mut lower = self.lower.borrow_mut();
+        let result = match lower.tx_buffer(len) {
+            Some(buffer) => f(buffer)?,
+            None => {
+                if let Err(err) = lower.flush() { panic!("{}", err) }
+                return Err(Error::Exhausted)
+            }
+        };
+        lower.tx_commit(len);
+        Ok(result)
+    }
+}
@@ -0,0 +1,14 @@
+/*! Deterministic network simulation.
+
+The `sim` module connects interfaces through an in-memory [network](struct.Network.html),
+whose links delay and lose frames as scripted by a test, and polls them according
+to a virtual clock, so that end-to-end behaviors, e.g. TCP retransmissions or simultaneous
+closes, can be exercised reproducibly within `cargo test`, without the privileges needed
+to create tap interfaces, and without waiting for timeouts in real time.
+*/
+
+mod network;
+mod simulation;
+
+pub use self::network::{Network, Port, Link};
+pub use self::simulation::Simulation;
@@ -0,0 +1,433 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::boxed::Box;
+use std::vec::Vec;
+use std::collections::BTreeMap;
+use core::fmt;
+
+use Result;
+use phy::{self, Device, DeviceCapabilities};
+
+/// The largest Ethernet frame a port sends and receives by default.
+const DEFAULT_MTU: usize = 1514;
+
+/// The properties of the one-way link between two ports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Link {
+    /// The time a frame takes to cross the link, in milliseconds.
+    pub latency:  u64,
+    /// The largest delay added to the latency of a frame, in milliseconds.
+    /// Frames never overtake the frames sent before them over the same link.
+    pub jitter:   u64,
+    /// The probability that a frame is lost, in percents.
+    pub loss_pct: u8,
+}
+
+#[derive(Debug)]
+struct Frame {
+    buffer: Vec<u8>,
+    due_at: u64,
+}
+
+/// The frames in flight to a port, in the order they are due.
+#[derive(Debug, Default)]
+struct Queue {
+    frames: Vec<Frame>,
+}
+
+impl Queue {
+    fn push(&mut self, frame: Frame) {
+        let index = self.frames.iter().position(|other| other.due_at > frame.due_at)
+                        .unwrap_or(self.frames.len());
+        self.frames.insert(index, frame)
+    }
+
+    fn pop(&mut self, now: u64) -> Option<Vec<u8>> {
+        match self.frames.first() {
+            Some(frame) if frame.due_at <= now => Some(self.frames.remove(0).buffer),
+            _ => None
+        }
+    }
+}
+
+type Filter = Box<FnMut(u64, usize, usize, &[u8]) -> bool>;
+
+struct State {
+    now:          u64,
+    rng_seed:     u32,
+    queues:       Vec<Queue>,
+    default_link: Link,
+    links:        BTreeMap<(usize, usize), Link>,
+    /// The time the last frame sent over each link is due, so that jitter does not
+    /// reorder the frames.
+    last_due_at:  BTreeMap<(usize, usize), u64>,
+    filter:       Option<Filter>,
+    mtu:          usize,
+    delivered:    u64,
+    dropped:      u64,
+}
+
+impl State {
+    fn random(&mut self) -> u32 {
+        // xorshift32; the sequence only needs to be reproducible.
+        let mut x = self.rng_seed;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng_seed = x;
+        x
+    }
+
+    fn link(&self, from: usize, to: usize) -> Link {
+        self.links.get(&(from, to)).cloned().unwrap_or(self.default_link)
+    }
+
+    /// Send a frame from a port to every other port, as if they were all attached
+    /// to a hub, through the links between them.
+    fn send(&mut self, from: usize, buffer: &[u8]) {
+        for to in 0..self.queues.len() {
+            if to == from { continue }
+
+            let link = self.link(from, to);
+            let deliver = match self.filter {
+                Some(ref mut filter) => filter(self.now, from, to, buffer),
+                None => true
+            };
+            if !deliver || (link.loss_pct > 0 && self.random() % 100 < link.loss_pct as u32) {
+                net_trace!("sim: dropped a frame of {} octets from port {} to port {}",
+                           buffer.len(), from, to);
+                self.dropped += 1;
+                continue
+            }
+
+            let mut due_at = self.now + link.latency;
+            if link.jitter > 0 {
+                due_at += self.random() as u64 % (link.jitter + 1);
+            }
+            if let Some(&last_due_at) = self.last_due_at.get(&(from, to)) {
+                if due_at < last_due_at { due_at = last_due_at }
+            }
+            self.last_due_at.insert((from, to), due_at);
+            self.queues[to].push(Frame { buffer: buffer.to_vec(), due_at });
+            self.delivered += 1;
+        }
+    }
+}
+
+/// A simulated network.
+///
+/// The network connects any number of [ports](struct.Port.html) as if they were all
+/// attached to an Ethernet hub: every frame sent through a port is delivered to every
+/// other port, and the interfaces discard the frames that are not addressed to them.
+/// The frames are delivered according to a virtual clock, after the latency of the link
+/// they cross, and may be lost, either at random, or as decided by a filter,
+/// which lets a test drop e.g. exactly the second segment of a connection.
+///
+/// Randomness is drawn from a pseudo-random sequence seeded when the network is created,
+/// so that a simulation is reproducible.
+///
+/// A network is a handle to shared state; clones of it refer to the same network.
+#[derive(Clone)]
+pub struct Network {
+    state: Rc<RefCell<State>>,
+}
+
+impl Network {
+    /// Create a network without any port, with links that neither delay nor lose frames,
+    /// drawing randomness from a pseudo-random sequence with the given seed.
+    pub fn new(seed: u32) -> Network {
+        Network {
+            state: Rc::new(RefCell::new(State {
+                now:          0,
+                // xorshift32 never leaves the all-zeroes state, so that seed is replaced.
+                rng_seed:     if seed == 0 { 0x2545f491 } else { seed },
+                queues:       Vec::new(),
+                default_link: Link::default(),
+                links:        BTreeMap::new(),
+                last_due_at:  BTreeMap::new(),
+                filter:       None,
+                mtu:          DEFAULT_MTU,
+                delivered:    0,
+                dropped:      0,
+            }))
+        }
+    }
+
+    /// Add a port to the network.
+    pub fn add_port(&self) -> Port {
+        let mut state = self.state.borrow_mut();
+        state.queues.push(Queue::default());
+        Port { network: self.clone(), id: state.queues.len() - 1 }
+    }
+
+    /// Return the number of ports of the network.
+    pub fn port_count(&self) -> usize {
+        self.state.borrow().queues.len()
+    }
+
+    /// Return the time of the virtual clock, in milliseconds.
+    pub fn now(&self) -> u64 {
+        self.state.borrow().now
+    }
+
+    /// Set the time of the virtual clock, in milliseconds.
+    ///
+    /// # Panics
+    /// This function panics if the clock would go backwards.
+    pub fn set_now(&self, now: u64) {
+        let mut state = self.state.borrow_mut();
+        assert!(now >= state.now, "the virtual clock must not go backwards");
+        state.now = now
+    }
+
+    /// Return the time the next frame in flight is due, if there is any.
+    pub fn poll_at(&self) -> Option<u64> {
+        self.state.borrow().queues.iter()
+            .filter_map(|queue| queue.frames.first())
+            .map(|frame| frame.due_at)
+            .min()
+    }
+
+    /// Return the properties of the link from one port to another.
+    pub fn link(&self, from: usize, to: usize) -> Link {
+        self.state.borrow().link(from, to)
+    }
+
+    /// Set the properties of the link from one port to another.
+    pub fn set_link(&self, from: usize, to: usize, link: Link) {
+        self.state.borrow_mut().links.insert((from, to), link);
+    }
+
+    /// Set the properties of the links in both directions between two ports.
+    pub fn set_links(&self, port_a: usize, port_b: usize, link: Link) {
+        self.set_link(port_a, port_b, link);
+        self.set_link(port_b, port_a, link)
+    }
+
+    /// Set the properties of the links that were not set individually.
+    pub fn set_default_link(&self, link: Link) {
+        self.state.borrow_mut().default_link = link
+    }
+
+    /// Set a filter that is called with the time, the sending and the receiving port
+    /// and the contents of every frame about to cross a link, and returns whether
+    /// the frame is delivered. The filter must not call the methods of the network.
+    pub fn set_filter<F>(&self, filter: F)
+            where F: FnMut(u64, usize, usize, &[u8]) -> bool + 'static {
+        self.state.borrow_mut().filter = Some(Box::new(filter))
+    }
+
+    /// Remove the filter, if any.
+    pub fn clear_filter(&self) {
+        self.state.borrow_mut().filter = None
+    }
+
+    /// Return the largest frame the ports send and receive.
+    pub fn mtu(&self) -> usize {
+        self.state.borrow().mtu
+    }
+
+    /// Set the largest frame the ports send and receive. The default is 1514 octets,
+    /// i.e. that of Ethernet.
+    pub fn set_mtu(&self, mtu: usize) {
+        self.state.borrow_mut().mtu = mtu
+    }
+
+    /// Return the number of frames that crossed a link, or are crossing one.
+    pub fn delivered(&self) -> u64 {
+        self.state.borrow().delivered
+    }
+
+    /// Return the number of frames lost on a link, or dropped by the filter.
+    pub fn dropped(&self) -> u64 {
+        self.state.borrow().dropped
+    }
+}
+
+impl fmt::Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.borrow();
+        f.debug_struct("Network")
+            .field("now", &state.now)
+            .field("ports", &state.queues.len())
+            .field("delivered", &state.delivered)
+            .field("dropped", &state.dropped)
+            .finish()
+    }
+}
+
+/// A port of a simulated network.
+///
+/// A port is the device of an interface attached to the network. It receives
+/// the frames that are due according to the virtual clock of the network,
+/// and sends frames at the time of that clock.
+#[derive(Debug)]
+pub struct Port {
+    network: Network,
+    id:      usize,
+}
+
+impl Port {
+    /// Return the index of the port in the network.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Return the network the port is attached to.
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+}
+
+impl<'a> Device<'a> for Port {
+    type RxToken = RxToken;
+    type TxToken = TxToken<'a>;
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = self.network.mtu();
+        caps
+    }
+
+    fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+        let buffer = {
+            let mut state = self.network.state.borrow_mut();
+            let now = state.now;
+            state.queues[self.id].pop(now)?
+        };
+        Some((RxToken { buffer }, TxToken { port: self }))
+    }
+
+    fn transmit(&'a mut self) -> Option<Self::TxToken> {
+        Some(TxToken { port: self })
+    }
+}
+
+#[doc(hidden)]
+pub struct RxToken {
+    buffer: Vec<u8>,
+}
+
+impl phy::RxToken for RxToken {
+    fn consume<R, F: FnOnce(&[u8]) -> Result<R>>(self, _timestamp: u64, f: F) -> Result<R> {
+        f(&self.buffer)
+    }
+}
+
+#[doc(hidden)]
+pub struct TxToken<'a> {
+    port: &'a Port,
+}
+
+impl<'a> phy::TxToken for TxToken<'a> {
+    fn consume<R, F>(self, _timestamp: u64, len: usize, f: F) -> Result<R>
+        where F: FnOnce(&mut [u8]) -> Result<R>
+    {
+        let mut buffer = Vec::new();
+        buffer.resize(len, 0);
+        let result = f(&mut buffer);
+        if result.is_ok() {
+            self.port.network.state.borrow_mut().send(self.port.id, &buffer);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use phy::{RxToken, TxToken};
+    use super::*;
+
+    fn send(port: &mut Port, len: usize) {
+        port.transmit().unwrap().consume(0, len, |buffer| {
+            for byte in buffer.iter_mut() { *byte = len as u8 }
+            Ok(())
+        }).unwrap()
+    }
+
+    fn recv(port: &mut Port) -> Option<usize> {
+        let (rx, _tx) = port.receive()?;
+        Some(rx.consume(0, |buffer| Ok(buffer.len())).unwrap())
+    }
+
+    #[test]
+    fn test_hub() {
+        let network = Network::new(1);
+        let (mut a, mut b, mut c) = (network.add_port(), network.add_port(), network.add_port());
+        send(&mut a, 10);
+        assert_eq!(recv(&mut a), None);
+        assert_eq!(recv(&mut b), Some(10));
+        assert_eq!(recv(&mut c), Some(10));
+        assert_eq!(recv(&mut b), None);
+        assert_eq!(network.delivered(), 2);
+    }
+
+    #[test]
+    fn test_latency() {
+        let network = Network::new(1);
+        let (mut a, mut b) = (network.add_port(), network.add_port());
+        network.set_link(0, 1, Link { latency: 50, ..Link::default() });
+        network.set_now(1_000);
+        send(&mut a, 10);
+        send(&mut b, 20);
+        assert_eq!(network.poll_at(), Some(1_000));
+        assert_eq!(recv(&mut a), Some(20));
+        assert_eq!(network.poll_at(), Some(1_050));
+        network.set_now(1_049);
+        assert_eq!(recv(&mut b), None);
+        network.set_now(1_050);
+        assert_eq!(recv(&mut b), Some(10));
+        assert_eq!(network.poll_at(), None);
+    }
+
+    #[test]
+    fn test_jitter() {
+        let due_at = |seed| {
+            let network = Network::new(seed);
+            let mut a = network.add_port();
+            let _b = network.add_port();
+            network.set_default_link(Link { latency: 10, jitter: 20, loss_pct: 0 });
+            for _ in 0..10 { send(&mut a, 10) }
+            let state = network.state.borrow();
+            state.queues[1].frames.iter().map(|frame| frame.due_at).collect::<Vec<_>>()
+        };
+
+        let first = due_at(1);
+        assert_eq!(due_at(1), first);
+        for (index, &due_at) in first.iter().enumerate() {
+            assert!(due_at >= 10 && due_at <= 30);
+            if index > 0 { assert!(due_at >= first[index - 1]) }
+        }
+        assert!(first.iter().any(|&due_at| due_at != first[0]));
+    }
+
+    #[test]
+    fn test_loss() {
+        let network = Network::new(1);
+        let (mut a, mut b) = (network.add_port(), network.add_port());
+        network.set_links(0, 1, Link { loss_pct: 50, ..Link::default() });
+        for _ in 0..100 { send(&mut a, 10) }
+        let mut received = 0;
+        while recv(&mut b).is_some() { received += 1 }
+        assert!(received > 25 && received < 75);
+        assert_eq!(network.delivered() + network.dropped(), 100);
+    }
+
+    #[test]
+    fn test_filter() {
+        let network = Network::new(1);
+        let (mut a, mut b) = (network.add_port(), network.add_port());
+        network.set_filter(|_now, from, _to, buffer| from != 0 || buffer.len() != 20);
+        send(&mut a, 10);
+        send(&mut a, 20);
+        send(&mut b, 20);
+        assert_eq!(recv(&mut b), Some(10));
+        assert_eq!(recv(&mut b), None);
+        assert_eq!(recv(&mut a), Some(20));
+        assert_eq!(network.dropped(), 1);
+
+        network.clear_filter();
+        send(&mut a, 20);
+        assert_eq!(recv(&mut b), Some(20));
+    }
+}
@@ -0,0 +1,323 @@
+use std::vec::Vec;
+use core::cmp;
+
+use iface::EthernetInterface;
+use socket::SocketSet;
+use super::network::{Network, Port};
+
+/// The number of times the nodes are polled at most at the same time, so that frames
+/// that cross links without latency are exchanged without advancing the clock,
+/// but a storm of frames does not stall the simulation.
+const MAX_ROUNDS: usize = 64;
+
+struct Node<'a, 'b: 'a, 'c: 'a + 'b> {
+    iface:   EthernetInterface<'b, 'c, Port>,
+    sockets: SocketSet<'a, 'b, 'c>,
+}
+
+/// A simulation of interfaces attached to a network.
+///
+/// The simulation owns the interfaces and their sockets, and polls them according to
+/// the virtual clock of its [network](struct.Network.html), which it advances
+/// straight to the next time an interface or a frame in flight has to be attended to,
+/// so that e.g. a TCP retransmission timeout of a second elapses instantly.
+///
+/// Node `n` is the interface attached to port `n` of the network; the links between
+/// nodes are set up with the methods of the network.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::collections::BTreeMap;
+/// use smoltcp::iface::{EthernetInterfaceBuilder, NeighborCache};
+/// use smoltcp::socket::SocketSet;
+/// use smoltcp::wire::{EthernetAddress, IpCidr, IpAddress};
+/// use smoltcp::sim::{Simulation, Link};
+///
+/// let mut sim = Simulation::new(1);
+/// for index in 0..2 {
+///     sim.add_node(SocketSet::new(vec![]), |port| {
+///         EthernetInterfaceBuilder::new(port)
+///             .ethernet_addr(EthernetAddress([0x02, 0, 0, 0, 0, 1 + index]))
+///             .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+///             .ip_addrs(vec![IpCidr::new(IpAddress::v4(10, 0, 0, 1 + index), 24)])
+///             .finalize()
+///     });
+/// }
+/// sim.network().set_links(0, 1, Link { latency: 20, ..Link::default() });
+/// // Add sockets to the nodes with `sim.sockets(0)`, and let them talk.
+/// sim.advance(1_000);
+/// assert_eq!(sim.now(), 1_000);
+/// ```
+pub struct Simulation<'a, 'b: 'a, 'c: 'a + 'b> {
+    network: Network,
+    nodes:   Vec<Node<'a, 'b, 'c>>,
+}
+
+impl<'a, 'b: 'a, 'c: 'a + 'b> Simulation<'a, 'b, 'c> {
+    /// Create a simulation without any node, on a network whose randomness is drawn
+    /// from a pseudo-random sequence with the given seed.
+    pub fn new(seed: u32) -> Simulation<'a, 'b, 'c> {
+        Simulation {
+            network: Network::new(seed),
+            nodes:   Vec::new(),
+        }
+    }
+
+    /// Return the network the nodes are attached to.
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    /// Return the time of the virtual clock, in milliseconds.
+    pub fn now(&self) -> u64 {
+        self.network.now()
+    }
+
+    /// Add a node with the given sockets, whose interface is created by the given
+    /// function from a new port of the network, and return the index of the node.
+    pub fn add_node<F>(&mut self, sockets: SocketSet<'a, 'b, 'c>, f: F) -> usize
+            where F: FnOnce(Port) -> EthernetInterface<'b, 'c, Port> {
+        let iface = f(self.network.add_port());
+        self.nodes.push(Node { iface, sockets });
+        self.nodes.len() - 1
+    }
+
+    /// Return the number of nodes.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Return the interface of the given node.
+    ///
+    /// # Panics
+    /// This function panics if there is no such node.
+    pub fn iface(&mut self, node: usize) -> &mut EthernetInterface<'b, 'c, Port> {
+        &mut self.nodes[node].iface
+    }
+
+    /// Return the sockets of the given node.
+    ///
+    /// # Panics
+    /// This function panics if there is no such node.
+    pub fn sockets(&mut self, node: usize) -> &mut SocketSet<'a, 'b, 'c> {
+        &mut self.nodes[node].sockets
+    }
+
+    /// Poll the interfaces of all nodes at the current time, until they have no more
+    /// frames to exchange.
+    pub fn poll(&mut self) {
+        let now = self.network.now();
+        for _ in 0..MAX_ROUNDS {
+            let mut active = false;
+            for (index, node) in self.nodes.iter_mut().enumerate() {
+                match node.iface.poll(&mut node.sockets, now) {
+                    Ok(processed) => active |= processed,
+                    Err(err) => {
+                        net_debug!("sim: node {}: poll error: {}", index, err);
+                        active = true
+                    }
+                }
+            }
+            let frames_due = self.network.poll_at().map_or(false, |due_at| due_at <= now);
+            if !active && !frames_due { break }
+        }
+    }
+
+    /// Return the next time an interface or a frame in flight has to be attended to,
+    /// if there is any.
+    pub fn poll_at(&self) -> Option<u64> {
+        let now = self.network.now();
+        self.nodes.iter()
+            .filter_map(|node| node.iface.poll_at(&node.sockets, now))
+            .chain(self.network.poll_at())
+            .min()
+    }
+
+    /// Run the simulation for the given duration, in milliseconds.
+    pub fn advance(&mut self, duration: u64) {
+        self.run_until(duration, |_| false);
+    }
+
+    /// Run the simulation until the given condition holds, or the given duration,
+    /// in milliseconds, elapses; return whether the condition holds.
+    ///
+    /// The condition is checked after every poll, and is given the simulation,
+    /// e.g. to look at the state of sockets.
+    pub fn run_until<F>(&mut self, duration: u64, mut condition: F) -> bool
+            where F: FnMut(&mut Simulation<'a, 'b, 'c>) -> bool {
+        let deadline = self.network.now() + duration;
+        loop {
+            self.poll();
+            if condition(self) { return true }
+
+            let now = self.network.now();
+            if now >= deadline { return false }
+            // Whatever is due now was attended to by the last poll, so the clock
+            // always advances.
+            let next = self.poll_at().map_or(deadline, |next| cmp::max(next, now + 1));
+            self.network.set_now(cmp::min(next, deadline));
+        }
+    }
+}
+
+#[cfg(all(test, feature = "proto-ipv4", feature = "socket-tcp"))]
+mod test {
+    use std::collections::BTreeMap;
+    use wire::{EthernetAddress, EthernetFrame, EthernetProtocol, IpAddress, IpCidr,
+               IpProtocol, Ipv4Packet, TcpPacket};
+    use iface::{EthernetInterfaceBuilder, NeighborCache};
+    use socket::{TcpSocket, TcpSocketBuffer, TcpState, SocketHandle};
+    use super::super::Link;
+    use super::*;
+
+    const PORT: u16 = 1234;
+
+    fn addr(node: u8) -> IpAddress {
+        IpAddress::v4(10, 0, 0, 1 + node)
+    }
+
+    fn tcp_socket() -> TcpSocket<'static> {
+        TcpSocket::new(TcpSocketBuffer::new(vec![0; 4096]), TcpSocketBuffer::new(vec![0; 4096]))
+    }
+
+    /// Set up a client node and a server node, with a connection from the former
+    /// to the latter being opened.
+    fn setup(seed: u32, link: Link) -> (Simulation<'static, 'static, 'static>,
+                                        SocketHandle, SocketHandle) {
+        let mut sim = Simulation::new(seed);
+        let mut handles = vec![];
+        for node in 0..2 {
+            let mut sockets = SocketSet::new(vec![]);
+            handles.push(sockets.add(tcp_socket()));
+            sim.add_node(sockets, |port| {
+                EthernetInterfaceBuilder::new(port)
+                    .ethernet_addr(EthernetAddress([0x02, 0, 0, 0, 0, 1 + node]))
+                    .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+                    .ip_addrs(vec![IpCidr::new(addr(node), 24)])
+                    .finalize()
+            });
+        }
+        sim.network().set_links(0, 1, link);
+
+        let (client, server) = (handles[0], handles[1]);
+        sim.sockets(1).get::<TcpSocket>(server).listen(PORT).unwrap();
+        sim.sockets(0).get::<TcpSocket>(client).connect((addr(1), PORT), 49152).unwrap();
+        (sim, client, server)
+    }
+
+    fn state(sim: &mut Simulation, node: usize, handle: SocketHandle) -> TcpState {
+        sim.sockets(node).get::<TcpSocket>(handle).state()
+    }
+
+    /// Return the flags and the payload length of a TCP segment in a frame.
+    fn tcp_segment(frame: &[u8]) -> Option<(bool, bool, usize)> {
+        let frame = EthernetFrame::new_checked(frame).ok()?;
+        if frame.ethertype() != EthernetProtocol::Ipv4 { return None }
+        let packet = Ipv4Packet::new_checked(frame.payload()).ok()?;
+        if packet.protocol() != IpProtocol::Tcp { return None }
+        let segment = TcpPacket::new_checked(packet.payload()).ok()?;
+        Some((segment.syn(), segment.fin(), segment.payload().len()))
+    }
+
+    /// Send data from the client to the server, and return when it was all received.
+    fn transfer(sim: &mut Simulation, client: SocketHandle, server: SocketHandle,
+                len: usize) -> Option<u64> {
+        let data = (0..len).map(|index| index as u8).collect::<Vec<_>>();
+        let mut sent = 0;
+        let mut received = vec![];
+        let done = sim.run_until(60_000, |sim| {
+            {
+                let mut socket = sim.sockets(0).get::<TcpSocket>(client);
+                if socket.can_send() {
+                    sent += socket.send_slice(&data[sent..]).unwrap();
+                }
+            }
+            let mut socket = sim.sockets(1).get::<TcpSocket>(server);
+            let mut buffer = [0; 4096];
+            while socket.can_recv() {
+                let size = socket.recv_slice(&mut buffer).unwrap();
+                received.extend_from_slice(&buffer[..size]);
+            }
+            received.len() == len
+        });
+        assert_eq!(received, data);
+        if done { Some(sim.now()) } else { None }
+    }
+
+    #[test]
+    fn test_connect_latency() {
+        let (mut sim, client, server) = setup(1, Link { latency: 25, ..Link::default() });
+        assert!(sim.run_until(1_000, |sim| state(sim, 0, client) == TcpState::Established));
+        // An ARP request and reply, then a SYN and a SYN-ACK.
+        assert_eq!(sim.now(), 100);
+        assert!(sim.run_until(1_000, |sim| state(sim, 1, server) == TcpState::Established));
+        assert_eq!(sim.now(), 125);
+    }
+
+    #[test]
+    fn test_retransmission() {
+        let (mut sim, client, server) = setup(1, Link { latency: 10, ..Link::default() });
+        // Lose the first segment carrying data.
+        let mut lost = false;
+        sim.network().set_filter(move |_now, from, _to, frame| {
+            match tcp_segment(frame) {
+                Some((false, false, len)) if from == 0 && len > 0 && !lost => {
+                    lost = true;
+                    false
+                }
+                _ => true
+            }
+        });
+        assert!(sim.run_until(1_000, |sim| state(sim, 0, client) == TcpState::Established));
+        let established_at = sim.now();
+
+        let done_at = transfer(&mut sim, client, server, 1_000).unwrap();
+        assert_eq!(sim.network().dropped(), 1);
+        // The data only arrives once it is retransmitted after a timeout.
+        assert!(done_at - established_at > 100);
+    }
+
+    #[test]
+    fn test_lossy_transfer_reproducible() {
+        let run = |seed| {
+            let link = Link { latency: 5, jitter: 10, loss_pct: 10 };
+            let (mut sim, client, server) = setup(seed, link);
+            let done_at = transfer(&mut sim, client, server, 20_000).unwrap();
+            (done_at, sim.network().delivered(), sim.network().dropped())
+        };
+
+        let first = run(7);
+        assert!(first.2 > 0);
+        assert_eq!(run(7), first);
+    }
+
+    #[test]
+    fn test_simultaneous_close() {
+        let (mut sim, client, server) = setup(1, Link { latency: 10, ..Link::default() });
+        assert!(sim.run_until(1_000, |sim| state(sim, 1, server) == TcpState::Established));
+
+        // Both FINs are in flight at the same time.
+        let fins = ::std::rc::Rc::new(::core::cell::Cell::new(0));
+        let fins_sent = fins.clone();
+        sim.network().set_filter(move |_now, _from, _to, frame| {
+            if let Some((_, true, _)) = tcp_segment(frame) {
+                fins_sent.set(fins_sent.get() + 1)
+            }
+            true
+        });
+        sim.sockets(0).get::<TcpSocket>(client).close();
+        sim.sockets(1).get::<TcpSocket>(server).close();
+        assert!(sim.run_until(1_000, |sim| {
+            state(sim, 0, client) == TcpState::TimeWait &&
+                state(sim, 1, server) == TcpState::TimeWait
+        }));
+        assert_eq!(fins.get(), 2);
+
+        // Both sides wait out TIME-WAIT.
+        assert!(sim.run_until(60_000, |sim| {
+            state(sim, 0, client) == TcpState::Closed &&
+                state(sim, 1, server) == TcpState::Closed
+        }));
+    }
+}
@@ -0,0 +1,597 @@
+// Heads up! Before working on this file you should read RFC 3416, which describes
+// the GetRequest and GetNextRequest PDUs (§ 4.2.1, § 4.2.2), RFC 1901, which describes
+// the SNMPv2c message, and RFC 1213, which defines the objects the agent serves.
+
+use core::fmt;
+
+use {Error, Result};
+use phy::Device;
+use wire::{IpAddress, IpEndpoint, Ipv4Address};
+use socket::{Socket, SocketSet, SocketHandle, UdpSocket, UdpSocketBuffer};
+#[cfg(feature = "socket-tcp")]
+use socket::TcpState;
+use iface::{EthernetInterface, InterfaceStats};
+use super::ber::*;
+
+/// The UDP port the agent receives requests on.
+pub const SNMP_PORT: u16 = 161;
+/// The maximum length of a community string.
+pub const MAX_COMMUNITY_LEN: usize = 32;
+
+/// The maximum length of a message received or sent by the agent.
+const MAX_MESSAGE_LEN: usize = 1472;
+
+const VERSION_2C: i32 = 1;
+
+const PDU_GET_REQUEST:      u8 = 0xa0;
+const PDU_GET_NEXT_REQUEST: u8 = 0xa1;
+const PDU_RESPONSE:         u8 = 0xa2;
+
+const ERROR_TOO_BIG: i32 = 1;
+
+const SYS_DESCR: &[u8] = b"smoltcp";
+const IF_DESCR:  &[u8] = b"ethernet";
+/// The `ethernetCsmacd` interface type.
+const IF_TYPE_ETHERNET: i32 = 6;
+
+const MIB_2:      [u32; 6] = [1, 3, 6, 1, 2, 1];
+const SYSTEM:     u32 = 1;
+const INTERFACES: u32 = 2;
+const TCP:        u32 = 6;
+const UDP:        u32 = 7;
+
+/// The state of the interface the objects served by the agent are read from.
+struct Mib<'a> {
+    ethernet_addr: [u8; 6],
+    stats:         InterfaceStats,
+    uptime:        u32,
+    connections:   &'a [Option<Connection>],
+}
+
+/// A row of the TCP connection table or of the UDP listener table.
+#[derive(Debug, Clone, Copy)]
+enum Connection {
+    #[cfg(feature = "socket-tcp")]
+    Tcp { state: i32, local: ([u8; 4], u16), remote: ([u8; 4], u16) },
+    Udp { local: ([u8; 4], u16) },
+}
+
+/// The maximum number of TCP and UDP sockets listed in the connection tables.
+const MAX_CONNECTION_COUNT: usize = 32;
+
+/// An SNMPv2c agent.
+///
+/// The agent answers GetRequest and GetNextRequest PDUs with the given community
+/// for the `system` and `interfaces` groups of MIB-II, as well as the TCP connection
+/// table and the UDP listener table of the IPv4 sockets in the socket set, so that
+/// the interface can be monitored with standard tools such as `snmpwalk`. It receives
+/// requests through a UDP socket that it adds to the socket set.
+///
+/// Other PDUs, requests of other versions, and requests with another community
+/// are ignored.
+pub struct Agent {
+    udp_handle:    SocketHandle,
+    community:     [u8; MAX_COMMUNITY_LEN],
+    community_len: usize,
+    started_at:    Option<u64>,
+}
+
+impl fmt::Debug for Agent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Agent")
+         .field("udp_handle", &self.udp_handle)
+         .field("started_at", &self.started_at)
+         .finish()
+    }
+}
+
+impl Agent {
+    /// Create an SNMP agent answering requests with the given community,
+    /// and add its UDP socket, created with the given buffers, to the socket set.
+    ///
+    /// # Panics
+    /// This function panics if the community is longer than `MAX_COMMUNITY_LEN`.
+    pub fn new<'a, 'b, 'c>(sockets: &mut SocketSet<'a, 'b, 'c>,
+                           rx_buffer: UdpSocketBuffer<'b, 'c>,
+                           tx_buffer: UdpSocketBuffer<'b, 'c>,
+                           community: &str) -> Agent {
+        assert!(community.len() <= MAX_COMMUNITY_LEN, "community {:?} is too long", community);
+        let udp_handle = sockets.add(UdpSocket::new(rx_buffer, tx_buffer));
+
+        let mut agent = Agent {
+            udp_handle:    udp_handle,
+            community:     [0; MAX_COMMUNITY_LEN],
+            community_len: community.len(),
+            started_at:    None,
+        };
+        agent.community[..community.len()].copy_from_slice(community.as_bytes());
+        agent
+    }
+
+    /// Return the handle of the UDP socket used by the agent.
+    pub fn udp_handle(&self) -> SocketHandle {
+        self.udp_handle
+    }
+
+    fn community(&self) -> &[u8] {
+        &self.community[..self.community_len]
+    }
+
+    /// Answer the requests received by the UDP socket.
+    ///
+    /// The system uptime reported by the agent counts from the first time it is polled.
+    pub fn poll<'b, 'c, DeviceT>(&mut self, iface: &EthernetInterface<'b, 'c, DeviceT>,
+                                 sockets: &mut SocketSet, now: u64) -> Result<()>
+            where DeviceT: for<'d> Device<'d> {
+        let started_at = *self.started_at.get_or_insert(now);
+        {
+            let mut udp_socket = sockets.get::<UdpSocket>(self.udp_handle);
+            if !udp_socket.is_open() {
+                udp_socket.bind(SNMP_PORT)?;
+            }
+        }
+
+        loop {
+            let mut request = [0; MAX_MESSAGE_LEN];
+            let (length, endpoint) = match sockets.get::<UdpSocket>(self.udp_handle)
+                                                  .recv_slice(&mut request) {
+                Ok(result) => result,
+                Err(_) => break
+            };
+
+            let mut connections = [None; MAX_CONNECTION_COUNT];
+            list_connections(sockets, &mut connections);
+            let mib = Mib {
+                ethernet_addr: iface.ethernet_addr().0,
+                stats:         iface.stats(),
+                uptime:        ((now - started_at) / 10) as u32,
+                connections:   &connections,
+            };
+
+            let mut response = [0; MAX_MESSAGE_LEN];
+            let length = match self.process(&mib, &request[..length], &mut response) {
+                Some(length) => length,
+                None => continue
+            };
+            match sockets.get::<UdpSocket>(self.udp_handle)
+                         .send_slice(&response[..length], endpoint) {
+                Ok(()) => {
+                    net_trace!("SNMP sent response to {}", endpoint);
+                }
+                Err(_) => {
+                    net_debug!("SNMP dropped response to {}", endpoint);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Process a received message, and build a response into `response` if one
+    /// has to be sent back, returning its length.
+    fn process(&self, mib: &Mib, request: &[u8], response: &mut [u8]) -> Option<usize> {
+        let (version, community, pdu_type, request_id, varbinds) = match parse(request) {
+            Ok(parsed) => parsed,
+            Err(_) => {
+                net_trace!("SNMP ignoring malformed message");
+                return None
+            }
+        };
+        if version != VERSION_2C {
+            net_trace!("SNMP ignoring message of version {}", version);
+            return None
+        }
+        if community != self.community() {
+            net_debug!("SNMP ignoring message with unknown community");
+            return None
+        }
+        if pdu_type != PDU_GET_REQUEST && pdu_type != PDU_GET_NEXT_REQUEST {
+            net_trace!("SNMP ignoring PDU of type 0x{:02x}", pdu_type);
+            return None
+        }
+
+        match self.respond(mib, pdu_type, request_id, Some(varbinds), response) {
+            Ok(length) => Some(length),
+            Err(Error::Exhausted) => {
+                net_debug!("SNMP response too big");
+                self.respond(mib, pdu_type, request_id, None, response).ok()
+            }
+            Err(_) => {
+                net_trace!("SNMP ignoring malformed message");
+                None
+            }
+        }
+    }
+
+    /// Emit a response to the given variable bindings, or, if there are none,
+    /// a `tooBig` error.
+    fn respond(&self, mib: &Mib, pdu_type: u8, request_id: i32, varbinds: Option<Reader>,
+               response: &mut [u8]) -> Result<usize> {
+        let mut writer = Writer::new(response);
+        let message = writer.begin(TAG_SEQUENCE)?;
+        writer.write_integer(VERSION_2C)?;
+        writer.write_octet_string(self.community())?;
+        let pdu = writer.begin(PDU_RESPONSE)?;
+        writer.write_integer(request_id)?;
+        writer.write_integer(if varbinds.is_some() { 0 } else { ERROR_TOO_BIG })?;
+        writer.write_integer(0)?;
+        let list = writer.begin(TAG_SEQUENCE)?;
+        if let Some(mut varbinds) = varbinds {
+            while !varbinds.is_empty() {
+                let oid = varbinds.enter(TAG_SEQUENCE)?.read_oid()?;
+                let (oid, value) = if pdu_type == PDU_GET_REQUEST {
+                    (oid, get(mib, &oid))
+                } else {
+                    get_next(mib, &oid).unwrap_or((oid, Value::EndOfMibView))
+                };
+                let varbind = writer.begin(TAG_SEQUENCE)?;
+                writer.write_oid(&oid)?;
+                writer.write_value(&value)?;
+                writer.end(varbind);
+            }
+        }
+        writer.end(list);
+        writer.end(pdu);
+        writer.end(message);
+        Ok(writer.len())
+    }
+}
+
+/// Parse a message, and return its version, community, PDU type, request ID
+/// and variable bindings.
+fn parse<'a>(request: &'a [u8]) -> Result<(i32, &'a [u8], u8, i32, Reader<'a>)> {
+    let mut message = Reader::new(request).enter(TAG_SEQUENCE)?;
+    let version = message.read_integer()?;
+    let community = message.read_octet_string()?;
+    let (pdu_type, pdu) = message.read()?;
+    let mut pdu = Reader::new(pdu);
+    let request_id = pdu.read_integer()?;
+    let _error_status = pdu.read_integer()?;
+    let _error_index = pdu.read_integer()?;
+    let varbinds = pdu.enter(TAG_SEQUENCE)?;
+    Ok((version, community, pdu_type, request_id, varbinds))
+}
+
+/// List the IPv4 TCP connections and UDP listeners in the socket set, as many as fit
+/// into `connections`.
+fn list_connections(sockets: &SocketSet, connections: &mut [Option<Connection>]) {
+    fn ipv4_endpoint(endpoint: IpEndpoint) -> Option<([u8; 4], u16)> {
+        match endpoint.addr {
+            IpAddress::Ipv4(addr) => Some((addr.0, endpoint.port)),
+            IpAddress::Unspecified => Some((Ipv4Address::UNSPECIFIED.0, endpoint.port)),
+            _ => None
+        }
+    }
+
+    let rows = sockets.iter().filter_map(|socket| match *socket {
+        #[cfg(feature = "socket-tcp")]
+        Socket::Tcp(ref socket) if socket.state() != TcpState::Closed => {
+            let state = match socket.state() {
+                TcpState::Closed      => 1,
+                TcpState::Listen      => 2,
+                TcpState::SynSent     => 3,
+                TcpState::SynReceived => 4,
+                TcpState::Established => 5,
+                TcpState::FinWait1    => 6,
+                TcpState::FinWait2    => 7,
+                TcpState::CloseWait   => 8,
+                TcpState::LastAck     => 9,
+                TcpState::Closing     => 10,
+                TcpState::TimeWait    => 11,
+            };
+            let local = ipv4_endpoint(socket.local_endpoint())?;
+            let remote = ipv4_endpoint(socket.remote_endpoint())?;
+            Some(Connection::Tcp { state, local, remote })
+        }
+        Socket::Udp(ref socket) if socket.is_open() =>
+            ipv4_endpoint(socket.endpoint()).map(|local| Connection::Udp { local }),
+        _ => None
+    });
+    for (slot, row) in connections.iter_mut().zip(rows) {
+        *slot = Some(row)
+    }
+}
+
+/// Call `f` with every object instance served by the agent, the length of the
+/// identifier of its object, and its value.
+fn for_each_instance<'a, F>(mib: &'a Mib, mut f: F)
+        where F: FnMut(&Oid, usize, Value<'a>) {
+    let mut instance = |group: u32, suffix: &[u32], object_len: usize, value: Value<'a>| {
+        let mut oid = Oid::new(&MIB_2);
+        oid.extend(&[group]);
+        oid.extend(suffix);
+        f(&oid, MIB_2.len() + 1 + object_len, value)
+    };
+
+    // sysDescr, sysObjectID and sysUpTime.
+    instance(SYSTEM, &[1, 0], 1, Value::OctetString(SYS_DESCR));
+    instance(SYSTEM, &[2, 0], 1, Value::ObjectId(Oid::new(&[0, 0])));
+    instance(SYSTEM, &[3, 0], 1, Value::TimeTicks(mib.uptime));
+
+    // ifNumber, and the columns of the only row of ifTable.
+    instance(INTERFACES, &[1, 0], 1, Value::Integer(1));
+    let counters = &mib.stats.ethernet;
+    let columns = [
+        (1,  Value::Integer(1)),
+        (2,  Value::OctetString(IF_DESCR)),
+        (3,  Value::Integer(IF_TYPE_ETHERNET)),
+        (6,  Value::OctetString(&mib.ethernet_addr)),
+        (7,  Value::Integer(1)),
+        (8,  Value::Integer(1)),
+        (10, Value::Counter32(counters.in_octets as u32)),
+        (11, Value::Counter32(counters.in_packets as u32)),
+        (13, Value::Counter32(counters.in_discards as u32)),
+        (14, Value::Counter32(counters.in_errors as u32)),
+        (15, Value::Counter32(counters.in_unknown_protos as u32)),
+        (16, Value::Counter32(counters.out_octets as u32)),
+        (17, Value::Counter32(counters.out_packets as u32)),
+        (19, Value::Counter32(counters.out_discards as u32)),
+        (20, Value::Counter32(counters.out_errors as u32)),
+    ];
+    for &(column, value) in columns.iter() {
+        instance(INTERFACES, &[2, 1, column, 1], 3, value);
+    }
+
+    for connection in mib.connections.iter().filter_map(|slot| *slot) {
+        match connection {
+            #[cfg(feature = "socket-tcp")]
+            Connection::Tcp { state, local: (local_addr, local_port),
+                              remote: (remote_addr, remote_port) } => {
+                // tcpConnTable, indexed by the local and remote endpoints.
+                let a = |addr: [u8; 4]| [addr[0] as u32, addr[1] as u32,
+                                         addr[2] as u32, addr[3] as u32];
+                let (l, r) = (a(local_addr), a(remote_addr));
+                let columns = [
+                    (1, Value::Integer(state)),
+                    (2, Value::IpAddress(local_addr)),
+                    (3, Value::Integer(local_port as i32)),
+                    (4, Value::IpAddress(remote_addr)),
+                    (5, Value::Integer(remote_port as i32)),
+                ];
+                for &(column, value) in columns.iter() {
+                    instance(TCP, &[13, 1, column, l[0], l[1], l[2], l[3], local_port as u32,
+                                    r[0], r[1], r[2], r[3], remote_port as u32], 3, value);
+                }
+            }
+            Connection::Udp { local: (local_addr, local_port) } => {
+                // udpTable, indexed by the local endpoint.
+                let l = local_addr;
+                let columns = [
+                    (1, Value::IpAddress(local_addr)),
+                    (2, Value::Integer(local_port as i32)),
+                ];
+                for &(column, value) in columns.iter() {
+                    instance(UDP, &[5, 1, column, l[0] as u32, l[1] as u32, l[2] as u32,
+                                    l[3] as u32, local_port as u32], 3, value);
+                }
+            }
+        }
+    }
+}
+
+/// Return the value of the given object instance, or the exception if there is none.
+fn get<'a>(mib: &'a Mib, oid: &Oid) -> Value<'a> {
+    let mut result = Value::NoSuchObject;
+    for_each_instance(mib, |instance, object_len, value| {
+        if instance == oid {
+            result = value
+        } else if result == Value::NoSuchObject && oid.as_slice().len() >= object_len &&
+                  oid.as_slice()[..object_len] == instance.as_slice()[..object_len] {
+            result = Value::NoSuchInstance
+        }
+    });
+    result
+}
+
+/// Return the first object instance following the given identifier, and its value.
+fn get_next<'a>(mib: &'a Mib, oid: &Oid) -> Option<(Oid, Value<'a>)> {
+    let mut result: Option<(Oid, Value<'a>)> = None;
+    for_each_instance(mib, |instance, _, value| {
+        if instance > oid && result.map(|(next, _)| instance < &next).unwrap_or(true) {
+            result = Some((*instance, value))
+        }
+    });
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use std::collections::BTreeMap;
+    use phy::Loopback;
+    use wire::{EthernetAddress, IpCidr, IpProtocol, IpRepr, UdpRepr};
+    use iface::{EthernetInterfaceBuilder, NeighborCache};
+    use socket::UdpPacketBuffer;
+    #[cfg(feature = "socket-tcp")]
+    use socket::{TcpSocket, TcpSocketBuffer};
+    use super::*;
+
+    const LOCAL_IP: Ipv4Address = Ipv4Address([192, 168, 1, 10]);
+    const REMOTE: IpEndpoint = IpEndpoint {
+        addr: IpAddress::Ipv4(Ipv4Address([192, 168, 1, 20])),
+        port: 50000
+    };
+
+    fn buffer() -> UdpSocketBuffer<'static, 'static> {
+        UdpSocketBuffer::new((0..4).map(|_| UdpPacketBuffer::new(vec![0; 1500]))
+                                   .collect::<Vec<_>>())
+    }
+
+    fn setup() -> (EthernetInterface<'static, 'static, Loopback>,
+                   SocketSet<'static, 'static, 'static>, Agent) {
+        let iface = EthernetInterfaceBuilder::new(Loopback::new())
+                .ethernet_addr(EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]))
+                .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+                .ip_addrs(vec![IpCidr::new(LOCAL_IP.into(), 24)])
+                .finalize();
+        let mut sockets = SocketSet::new(vec![]);
+        let mut agent = Agent::new(&mut sockets, buffer(), buffer(), "public");
+        agent.poll(&iface, &mut sockets, 0).unwrap();
+        (iface, sockets, agent)
+    }
+
+    fn request(community: &[u8], pdu_type: u8, oids: &[&[u32]]) -> Vec<u8> {
+        let mut buffer = vec![0; MAX_MESSAGE_LEN];
+        let length = {
+            let mut writer = Writer::new(&mut buffer);
+            let message = writer.begin(TAG_SEQUENCE).unwrap();
+            writer.write_integer(VERSION_2C).unwrap();
+            writer.write_octet_string(community).unwrap();
+            let pdu = writer.begin(pdu_type).unwrap();
+            writer.write_integer(0x1234).unwrap();
+            writer.write_integer(0).unwrap();
+            writer.write_integer(0).unwrap();
+            let list = writer.begin(TAG_SEQUENCE).unwrap();
+            for oid in oids {
+                let varbind = writer.begin(TAG_SEQUENCE).unwrap();
+                writer.write_oid(&Oid::new(oid)).unwrap();
+                writer.write_value(&Value::Null).unwrap();
+                writer.end(varbind);
+            }
+            writer.end(list);
+            writer.end(pdu);
+            writer.end(message);
+            writer.len()
+        };
+        buffer.truncate(length);
+        buffer
+    }
+
+    fn recv(sockets: &mut SocketSet, agent: &Agent, bytes: &[u8]) {
+        let udp_repr = UdpRepr {
+            src_port: REMOTE.port,
+            dst_port: SNMP_PORT,
+            payload:  bytes
+        };
+        let ip_repr = IpRepr::Unspecified {
+            src_addr: REMOTE.addr,
+            dst_addr: LOCAL_IP.into(),
+            protocol: IpProtocol::Udp,
+            payload_len: udp_repr.buffer_len(),
+            hop_limit: 64,
+            ecn: 0,
+            dscp: 0,
+            flow_label: 0
+        };
+        sockets.get::<UdpSocket>(agent.udp_handle())
+            .process_lent(0, None, &ip_repr, &udp_repr, None).unwrap();
+    }
+
+    fn sent(sockets: &mut SocketSet, agent: &Agent) -> Option<Vec<u8>> {
+        let mut result = None;
+        let _ = sockets.get::<UdpSocket>(agent.udp_handle())
+            .dispatch(0, |(ip_repr, udp_repr)| {
+                assert_eq!(ip_repr.dst_addr(), REMOTE.addr);
+                assert_eq!(udp_repr.dst_port, REMOTE.port);
+                result = Some(udp_repr.payload.to_vec());
+                Ok(())
+            });
+        result
+    }
+
+    /// Parse a response, and return its error status and variable bindings.
+    fn parse_response(bytes: &[u8]) -> (i32, Vec<(Oid, Vec<u8>)>) {
+        let mut message = Reader::new(bytes).enter(TAG_SEQUENCE).unwrap();
+        assert_eq!(message.read_integer(), Ok(VERSION_2C));
+        assert_eq!(message.read_octet_string(), Ok(&b"public"[..]));
+        let mut pdu = message.enter(PDU_RESPONSE).unwrap();
+        assert_eq!(pdu.read_integer(), Ok(0x1234));
+        let error_status = pdu.read_integer().unwrap();
+        assert_eq!(pdu.read_integer(), Ok(0));
+        let mut list = pdu.enter(TAG_SEQUENCE).unwrap();
+        let mut varbinds = vec![];
+        while !list.is_empty() {
+            let mut varbind = list.enter(TAG_SEQUENCE).unwrap();
+            let oid = varbind.read_oid().unwrap();
+            let (tag, contents) = varbind.read().unwrap();
+            let mut value = vec![tag];
+            value.extend_from_slice(contents);
+            varbinds.push((oid, value));
+        }
+        (error_status, varbinds)
+    }
+
+    fn exchange(iface: &EthernetInterface<'static, 'static, Loopback>,
+                sockets: &mut SocketSet<'static, 'static, 'static>, agent: &mut Agent,
+                pdu_type: u8, oids: &[&[u32]]) -> (i32, Vec<(Oid, Vec<u8>)>) {
+        recv(sockets, agent, &request(b"public", pdu_type, oids));
+        agent.poll(iface, sockets, 1_000).unwrap();
+        parse_response(&sent(sockets, agent).expect("no response"))
+    }
+
+    #[test]
+    fn test_bind() {
+        let (_iface, mut sockets, agent) = setup();
+        assert_eq!(sockets.get::<UdpSocket>(agent.udp_handle()).endpoint().port, SNMP_PORT);
+    }
+
+    #[test]
+    fn test_get() {
+        let (iface, mut sockets, mut agent) = setup();
+        let (error_status, varbinds) =
+            exchange(&iface, &mut sockets, &mut agent, PDU_GET_REQUEST,
+                     &[&[1, 3, 6, 1, 2, 1, 1, 1, 0], &[1, 3, 6, 1, 2, 1, 1, 3, 0],
+                       &[1, 3, 6, 1, 2, 1, 2, 2, 1, 6, 1], &[1, 3, 6, 1, 2, 1, 1, 1, 1],
+                       &[1, 3, 6, 1, 2, 1, 99, 0]]);
+        assert_eq!(error_status, 0);
+        assert_eq!(varbinds.len(), 5);
+        assert_eq!(varbinds[0].1, b"\x04smoltcp");
+        assert_eq!(varbinds[1].1, &[TAG_TIME_TICKS, 100]);
+        assert_eq!(varbinds[2].1, &[TAG_OCTET_STRING, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(varbinds[3].1, &[TAG_NO_SUCH_INSTANCE]);
+        assert_eq!(varbinds[4].1, &[TAG_NO_SUCH_OBJECT]);
+    }
+
+    #[test]
+    fn test_get_next() {
+        let (iface, mut sockets, mut agent) = setup();
+        let (_, varbinds) =
+            exchange(&iface, &mut sockets, &mut agent, PDU_GET_NEXT_REQUEST,
+                     &[&[1, 3, 6, 1, 2, 1], &[1, 3, 6, 1, 2, 1, 1, 3, 0],
+                       &[1, 3, 6, 1, 2, 1, 2, 2, 1, 20, 1],
+                       &[1, 3, 6, 1, 2, 1, 7, 5, 1, 2, 0, 0, 0, 0, 161]]);
+        assert_eq!(varbinds[0].0, Oid::new(&[1, 3, 6, 1, 2, 1, 1, 1, 0]));
+        assert_eq!(varbinds[1].0, Oid::new(&[1, 3, 6, 1, 2, 1, 2, 1, 0]));
+        // The UDP listener table lists the socket of the agent itself.
+        assert_eq!(varbinds[2].0, Oid::new(&[1, 3, 6, 1, 2, 1, 7, 5, 1, 1, 0, 0, 0, 0, 161]));
+        assert_eq!(varbinds[3].1, &[TAG_END_OF_MIB_VIEW]);
+    }
+
+    #[test]
+    #[cfg(feature = "socket-tcp")]
+    fn test_tcp_connections() {
+        let (iface, mut sockets, mut agent) = setup();
+        let mut tcp_socket = TcpSocket::new(TcpSocketBuffer::new(vec![0; 64]),
+                                            TcpSocketBuffer::new(vec![0; 64]));
+        tcp_socket.listen(80).unwrap();
+        sockets.add(tcp_socket);
+
+        let (_, varbinds) =
+            exchange(&iface, &mut sockets, &mut agent, PDU_GET_NEXT_REQUEST,
+                     &[&[1, 3, 6, 1, 2, 1, 6, 13]]);
+        assert_eq!(varbinds[0].0, Oid::new(&[1, 3, 6, 1, 2, 1, 6, 13, 1, 1,
+                                             0, 0, 0, 0, 80, 0, 0, 0, 0, 0]));
+        // listen(2)
+        assert_eq!(varbinds[0].1, &[TAG_INTEGER, 2]);
+    }
+
+    #[test]
+    fn test_ignored() {
+        let (iface, mut sockets, mut agent) = setup();
+        recv(&mut sockets, &agent, &request(b"private", PDU_GET_REQUEST,
+                                            &[&[1, 3, 6, 1, 2, 1, 1, 1, 0]]));
+        recv(&mut sockets, &agent, &request(b"public", 0xa3, &[&[1, 3, 6, 1, 2, 1, 1, 1, 0]]));
+        recv(&mut sockets, &agent, &[0x30, 0x03, 0x02, 0x01]);
+        agent.poll(&iface, &mut sockets, 0).unwrap();
+        assert_eq!(sent(&mut sockets, &agent), None);
+    }
+
+    #[test]
+    fn test_too_big() {
+        let (iface, mut sockets, mut agent) = setup();
+        let oids: Vec<&[u32]> = (0..70).map(|_| &[1, 3, 6, 1, 2, 1, 1, 1, 0][..]).collect();
+        let (error_status, varbinds) =
+            exchange(&iface, &mut sockets, &mut agent, PDU_GET_REQUEST, &oids);
+        assert_eq!(error_status, ERROR_TOO_BIG);
+        assert!(varbinds.is_empty());
+    }
+}
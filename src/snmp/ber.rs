@@ -0,0 +1,404 @@
+use core::{cmp, fmt};
+
+use {Error, Result};
+
+pub const TAG_INTEGER:          u8 = 0x02;
+pub const TAG_OCTET_STRING:     u8 = 0x04;
+pub const TAG_NULL:             u8 = 0x05;
+pub const TAG_OBJECT_ID:        u8 = 0x06;
+pub const TAG_SEQUENCE:         u8 = 0x30;
+pub const TAG_IP_ADDRESS:       u8 = 0x40;
+pub const TAG_COUNTER32:        u8 = 0x41;
+pub const TAG_GAUGE32:          u8 = 0x42;
+pub const TAG_TIME_TICKS:       u8 = 0x43;
+pub const TAG_COUNTER64:        u8 = 0x46;
+pub const TAG_NO_SUCH_OBJECT:   u8 = 0x80;
+pub const TAG_NO_SUCH_INSTANCE: u8 = 0x81;
+pub const TAG_END_OF_MIB_VIEW:  u8 = 0x82;
+
+/// The maximum number of sub-identifiers in an object identifier.
+pub const MAX_OID_LEN: usize = 32;
+
+/// An object identifier, stored without a heap.
+#[derive(Clone, Copy)]
+pub struct Oid {
+    arcs: [u32; MAX_OID_LEN],
+    len:  usize,
+}
+
+impl Oid {
+    /// Create an object identifier from the given sub-identifiers.
+    ///
+    /// # Panics
+    /// This function panics if there are more than `MAX_OID_LEN` sub-identifiers.
+    pub fn new(arcs: &[u32]) -> Oid {
+        let mut oid = Oid { arcs: [0; MAX_OID_LEN], len: 0 };
+        oid.extend(arcs);
+        oid
+    }
+
+    /// Return the sub-identifiers.
+    pub fn as_slice(&self) -> &[u32] {
+        &self.arcs[..self.len]
+    }
+
+    /// Append the given sub-identifiers.
+    ///
+    /// # Panics
+    /// This function panics if the identifier would get longer than `MAX_OID_LEN`.
+    pub fn extend(&mut self, arcs: &[u32]) {
+        assert!(self.len + arcs.len() <= MAX_OID_LEN, "object identifier too long");
+        self.arcs[self.len..self.len + arcs.len()].copy_from_slice(arcs);
+        self.len += arcs.len();
+    }
+
+    /// Query whether this object identifier starts with the given one.
+    pub fn starts_with(&self, prefix: &Oid) -> bool {
+        self.as_slice().starts_with(prefix.as_slice())
+    }
+}
+
+impl PartialEq for Oid {
+    fn eq(&self, other: &Oid) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for Oid {}
+
+impl PartialOrd for Oid {
+    fn partial_cmp(&self, other: &Oid) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Oid {
+    fn cmp(&self, other: &Oid) -> cmp::Ordering {
+        self.as_slice().cmp(other.as_slice())
+    }
+}
+
+impl fmt::Debug for Oid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl fmt::Display for Oid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, arc) in self.as_slice().iter().enumerate() {
+            if i > 0 { write!(f, ".")? }
+            write!(f, "{}", arc)?
+        }
+        Ok(())
+    }
+}
+
+/// The value of a variable binding.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Value<'a> {
+    Integer(i32),
+    OctetString(&'a [u8]),
+    Null,
+    ObjectId(Oid),
+    IpAddress([u8; 4]),
+    Counter32(u32),
+    Gauge32(u32),
+    TimeTicks(u32),
+    Counter64(u64),
+    NoSuchObject,
+    NoSuchInstance,
+    EndOfMibView,
+}
+
+/// A reader of a sequence of BER-encoded elements.
+#[derive(Debug)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Read an element, and return its tag and contents.
+    pub fn read(&mut self) -> Result<(u8, &'a [u8])> {
+        if self.data.len() < 2 { return Err(Error::Truncated) }
+        let tag = self.data[0];
+        let (len, header_len) = match self.data[1] {
+            len if len < 0x80 => (len as usize, 2),
+            0x81 | 0x82 | 0x83 => {
+                let count = (self.data[1] & 0x7f) as usize;
+                if self.data.len() < 2 + count { return Err(Error::Truncated) }
+                let len = self.data[2..2 + count].iter()
+                              .fold(0, |len, &b| (len << 8) | b as usize);
+                (len, 2 + count)
+            }
+            _ => return Err(Error::Malformed)
+        };
+        if self.data.len() < header_len + len { return Err(Error::Truncated) }
+        let contents = &self.data[header_len..header_len + len];
+        self.data = &self.data[header_len + len..];
+        Ok((tag, contents))
+    }
+
+    /// Read an element with the given tag, and return its contents.
+    pub fn expect(&mut self, tag: u8) -> Result<&'a [u8]> {
+        match self.read()? {
+            (actual, contents) if actual == tag => Ok(contents),
+            _ => Err(Error::Malformed)
+        }
+    }
+
+    /// Read a constructed element with the given tag, and return a reader of its contents.
+    pub fn enter(&mut self, tag: u8) -> Result<Reader<'a>> {
+        self.expect(tag).map(Reader::new)
+    }
+
+    pub fn read_integer(&mut self) -> Result<i32> {
+        let contents = self.expect(TAG_INTEGER)?;
+        if contents.is_empty() || contents.len() > 4 { return Err(Error::Malformed) }
+        let sign = if contents[0] & 0x80 != 0 { -1 } else { 0 };
+        Ok(contents.iter().fold(sign, |value, &b| (value << 8) | b as i32))
+    }
+
+    pub fn read_octet_string(&mut self) -> Result<&'a [u8]> {
+        self.expect(TAG_OCTET_STRING)
+    }
+
+    pub fn read_oid(&mut self) -> Result<Oid> {
+        let contents = self.expect(TAG_OBJECT_ID)?;
+        let mut oid = Oid::new(&[]);
+        let mut arc: u32 = 0;
+        for (i, &b) in contents.iter().enumerate() {
+            if arc > 0x01ff_ffff { return Err(Error::Malformed) }
+            arc = (arc << 7) | (b & 0x7f) as u32;
+            if b & 0x80 != 0 {
+                if i + 1 == contents.len() { return Err(Error::Malformed) }
+                continue
+            }
+            let room = if oid.len == 0 { 2 } else { 1 };
+            if oid.len + room > MAX_OID_LEN { return Err(Error::Malformed) }
+            if oid.len == 0 {
+                let first = cmp::min(arc / 40, 2);
+                oid.extend(&[first, arc - first * 40]);
+            } else {
+                oid.extend(&[arc]);
+            }
+            arc = 0;
+        }
+        if oid.len == 0 { return Err(Error::Malformed) }
+        Ok(oid)
+    }
+}
+
+/// A writer of BER-encoded elements into a buffer.
+///
+/// Constructed elements are written with a three octet length, which is patched
+/// once their contents are written.
+#[derive(Debug)]
+pub struct Writer<'a> {
+    buffer: &'a mut [u8],
+    pos:    usize,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(buffer: &'a mut [u8]) -> Writer<'a> {
+        Writer { buffer, pos: 0 }
+    }
+
+    /// Return the number of octets written.
+    pub fn len(&self) -> usize {
+        self.pos
+    }
+
+    fn put(&mut self, data: &[u8]) -> Result<()> {
+        if self.buffer.len() - self.pos < data.len() { return Err(Error::Exhausted) }
+        self.buffer[self.pos..self.pos + data.len()].copy_from_slice(data);
+        self.pos += data.len();
+        Ok(())
+    }
+
+    fn put_header(&mut self, tag: u8, len: usize) -> Result<()> {
+        if len < 0x80 {
+            self.put(&[tag, len as u8])
+        } else if len < 0x100 {
+            self.put(&[tag, 0x81, len as u8])
+        } else {
+            self.put(&[tag, 0x82, (len >> 8) as u8, len as u8])
+        }
+    }
+
+    /// Start a constructed element with the given tag, and return the position
+    /// to pass to [end](#method.end) once its contents are written.
+    pub fn begin(&mut self, tag: u8) -> Result<usize> {
+        self.put(&[tag, 0x82, 0, 0])?;
+        Ok(self.pos)
+    }
+
+    /// Finish a constructed element started with [begin](#method.begin).
+    pub fn end(&mut self, start: usize) {
+        let len = self.pos - start;
+        self.buffer[start - 2] = (len >> 8) as u8;
+        self.buffer[start - 1] = len as u8;
+    }
+
+    fn write_unsigned(&mut self, tag: u8, value: u64) -> Result<()> {
+        let mut bytes = [0; 9];
+        bytes[1..].copy_from_slice(&value.to_be_bytes());
+        // Keep a leading zero octet if the most significant bit is set.
+        let mut start = 0;
+        while start < 8 && bytes[start] == 0 && bytes[start + 1] & 0x80 == 0 {
+            start += 1
+        }
+        self.put_header(tag, 9 - start)?;
+        self.put(&bytes[start..])
+    }
+
+    pub fn write_integer(&mut self, value: i32) -> Result<()> {
+        let bytes = value.to_be_bytes();
+        let mut start = 0;
+        while start < 3 && ((bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0) ||
+                            (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0)) {
+            start += 1
+        }
+        self.put_header(TAG_INTEGER, 4 - start)?;
+        self.put(&bytes[start..])
+    }
+
+    pub fn write_octet_string(&mut self, value: &[u8]) -> Result<()> {
+        self.put_header(TAG_OCTET_STRING, value.len())?;
+        self.put(value)
+    }
+
+    pub fn write_null(&mut self, tag: u8) -> Result<()> {
+        self.put_header(tag, 0)
+    }
+
+    pub fn write_oid(&mut self, oid: &Oid) -> Result<()> {
+        let mut contents = [0; MAX_OID_LEN * 5];
+        let mut len = 0;
+        let arcs = oid.as_slice();
+        let (first, rest) = match arcs.len() {
+            0 => (0, &arcs[..]),
+            1 => (arcs[0] * 40, &arcs[1..]),
+            _ => (arcs[0] * 40 + arcs[1], &arcs[2..])
+        };
+        for &arc in Some(first).iter().chain(rest.iter()) {
+            let mut count = 1;
+            while count < 5 && arc >> (7 * count) != 0 {
+                count += 1
+            }
+            for i in (0..count).rev() {
+                let more = if i > 0 { 0x80 } else { 0 };
+                contents[len] = ((arc >> (7 * i)) & 0x7f) as u8 | more;
+                len += 1;
+            }
+        }
+        self.put_header(TAG_OBJECT_ID, len)?;
+        self.put(&contents[..len])
+    }
+
+    pub fn write_value(&mut self, value: &Value) -> Result<()> {
+        match *value {
+            Value::Integer(value) => self.write_integer(value),
+            Value::OctetString(value) => self.write_octet_string(value),
+            Value::Null => self.write_null(TAG_NULL),
+            Value::ObjectId(ref oid) => self.write_oid(oid),
+            Value::IpAddress(addr) => {
+                self.put_header(TAG_IP_ADDRESS, 4)?;
+                self.put(&addr)
+            }
+            Value::Counter32(value) => self.write_unsigned(TAG_COUNTER32, value as u64),
+            Value::Gauge32(value) => self.write_unsigned(TAG_GAUGE32, value as u64),
+            Value::TimeTicks(value) => self.write_unsigned(TAG_TIME_TICKS, value as u64),
+            Value::Counter64(value) => self.write_unsigned(TAG_COUNTER64, value),
+            Value::NoSuchObject => self.write_null(TAG_NO_SUCH_OBJECT),
+            Value::NoSuchInstance => self.write_null(TAG_NO_SUCH_INSTANCE),
+            Value::EndOfMibView => self.write_null(TAG_END_OF_MIB_VIEW),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn written<F: FnOnce(&mut Writer) -> Result<()>>(f: F) -> ([u8; 64], usize) {
+        let mut buffer = [0; 64];
+        let len = {
+            let mut writer = Writer::new(&mut buffer);
+            f(&mut writer).unwrap();
+            writer.len()
+        };
+        (buffer, len)
+    }
+
+    #[test]
+    fn test_integer() {
+        for &(value, bytes) in &[(0, &[0x02, 0x01, 0x00][..]),
+                                 (127, &[0x02, 0x01, 0x7f][..]),
+                                 (128, &[0x02, 0x02, 0x00, 0x80][..]),
+                                 (-1, &[0x02, 0x01, 0xff][..]),
+                                 (-129, &[0x02, 0x02, 0xff, 0x7f][..])] {
+            let (buffer, len) = written(|w| w.write_integer(value));
+            assert_eq!(&buffer[..len], bytes);
+            assert_eq!(Reader::new(bytes).read_integer(), Ok(value));
+        }
+    }
+
+    #[test]
+    fn test_unsigned() {
+        let (buffer, len) = written(|w| w.write_value(&Value::Counter32(0x8000_0000)));
+        assert_eq!(&buffer[..len], &[0x41, 0x05, 0x00, 0x80, 0x00, 0x00, 0x00]);
+        let (buffer, len) = written(|w| w.write_value(&Value::TimeTicks(0)));
+        assert_eq!(&buffer[..len], &[0x43, 0x01, 0x00]);
+    }
+
+    #[test]
+    fn test_oid() {
+        let bytes = [0x06, 0x08, 0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x83, 0x00];
+        let oid = Oid::new(&[1, 3, 6, 1, 2, 1, 1, 384]);
+        assert_eq!(Reader::new(&bytes).read_oid(), Ok(oid));
+        let (buffer, len) = written(|w| w.write_oid(&oid));
+        assert_eq!(&buffer[..len], &bytes[..]);
+        assert_eq!(Reader::new(&[0x06, 0x01, 0x81]).read_oid(), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn test_oid_order() {
+        assert!(Oid::new(&[1, 3, 6]) < Oid::new(&[1, 3, 6, 1]));
+        assert!(Oid::new(&[1, 3, 6, 2]) > Oid::new(&[1, 3, 6, 1, 5]));
+        assert!(Oid::new(&[1, 3, 6, 1]).starts_with(&Oid::new(&[1, 3])));
+    }
+
+    #[test]
+    fn test_sequence() {
+        let (buffer, len) = written(|w| {
+            let start = w.begin(TAG_SEQUENCE)?;
+            w.write_octet_string(b"public")?;
+            w.write_null(TAG_NULL)?;
+            w.end(start);
+            Ok(())
+        });
+        let mut reader = Reader::new(&buffer[..len]);
+        let mut sequence = reader.enter(TAG_SEQUENCE).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(sequence.read_octet_string(), Ok(&b"public"[..]));
+        assert_eq!(sequence.expect(TAG_NULL), Ok(&[][..]));
+        assert!(sequence.is_empty());
+    }
+
+    #[test]
+    fn test_truncated() {
+        assert_eq!(Reader::new(&[0x30, 0x05, 0x00]).read(), Err(Error::Truncated));
+        assert_eq!(Reader::new(&[0x30, 0x84, 0, 0, 0, 0]).read(), Err(Error::Malformed));
+        let mut buffer = [0; 2];
+        assert_eq!(Writer::new(&mut buffer).write_octet_string(b"x"), Err(Error::Exhausted));
+    }
+}
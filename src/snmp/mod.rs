@@ -0,0 +1,12 @@
+/*! Simple Network Management Protocol.
+
+The `snmp` module implements an SNMPv2c agent, on top of a UDP socket, that answers
+GetRequest and GetNextRequest PDUs for the statistics of an interface and the TCP
+and UDP connection tables of a socket set, as well as the BER encoding of the messages.
+*/
+
+mod ber;
+mod agent;
+
+pub use self::ber::{Oid, Value, MAX_OID_LEN};
+pub use self::agent::{Agent, SNMP_PORT, MAX_COMMUNITY_LEN};
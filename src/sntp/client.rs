@@ -0,0 +1,559 @@
+// Heads up! Before working on this file you should read RFC 4330, which describes SNTPv4,
+// and RFC 5905 § 8, which describes the on-wire protocol and the Kiss-o'-Death packets.
+
+use core::cmp;
+
+use {Error, Result};
+use wire::{IpAddress, IpEndpoint};
+use wire::{NtpPacket, NtpRepr, NtpTimestamp, NtpLeapIndicator, NtpMode, NTP_SERVER_PORT};
+use socket::{SocketSet, SocketHandle, UdpSocket, UdpSocketBuffer};
+
+/// The maximum number of servers the client queries.
+pub const MAX_SERVER_COUNT: usize = 4;
+
+/// The default interval between two queries, in milliseconds.
+const DEFAULT_POLL_INTERVAL: u64 = 64 * 1000;
+/// The shortest interval between two queries, as recommended by RFC 4330 § 10.
+const MIN_POLL_INTERVAL: u64 = 15 * 1000;
+/// The longest interval between two queries a server can ask for with RATE kisses.
+const MAX_POLL_INTERVAL: u64 = (1 << 17) * 1000;
+/// How long to wait for a response before querying the next server.
+const REQUEST_TIMEOUT: u64 = 5 * 1000;
+
+const NTP_VERSION: u8 = 4;
+
+/// The bits of the fraction of a transmit timestamp below the millisecond resolution
+/// of the clock, which are randomized so that responses cannot be easily forged.
+const NONCE_MASK: u32 = (1 << 22) - 1;
+
+/// An adjustment of the clock, computed from the response of a server.
+///
+/// All times are in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Adjustment {
+    /// The offset of the clock of the server from the estimate of the client, i.e.
+    /// the time to add to the local clock. Before the first adjustment, the estimate
+    /// is the Unix epoch, unless it was set with [set_unix_time].
+    ///
+    /// [set_unix_time]: struct.Client.html#method.set_unix_time
+    pub offset:    i64,
+    /// The round-trip delay to the server.
+    pub delay:     u64,
+    /// The current Unix time, with the offset applied.
+    pub unix_time: u64,
+    /// The server that sent the response.
+    pub server:    IpAddress,
+    /// The stratum of the server, i.e. 1 for a primary server.
+    pub stratum:   u8,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Request {
+    server:    IpAddress,
+    sent_at:   u64,
+    transmit:  NtpTimestamp,
+}
+
+/// An SNTP client.
+///
+/// The client queries the configured servers in turn, through a UDP socket that it adds
+/// to the socket set, moving on to the next server if one does not respond in time,
+/// and reports the offset of the local clock from the clock of the server from [poll],
+/// where it is up to the application to adjust its clock.
+///
+/// The client keeps an estimate of the Unix time as an offset from the timestamps
+/// it is polled with, which it corrects with every adjustment, and which is returned
+/// by [unix_time].
+///
+/// [poll]: #method.poll
+/// [unix_time]: #method.unix_time
+#[derive(Debug)]
+pub struct Client {
+    udp_handle:    SocketHandle,
+    local_port:    u16,
+    servers:       [Option<IpAddress>; MAX_SERVER_COUNT],
+    server_index:  usize,
+    /// The interval between two queries, as configured.
+    poll_interval: u64,
+    /// The interval between two queries, as lengthened by RATE kisses.
+    interval:      u64,
+    /// The Unix time, in milliseconds, minus the timestamp the client is polled with.
+    unix_offset:   Option<i64>,
+    request:       Option<Request>,
+    next_request:  u64,
+    rand_state:    u32,
+}
+
+impl Client {
+    /// Create an SNTP client querying the given servers from the given local port,
+    /// and add its UDP socket, created with the given buffers, to the socket set.
+    ///
+    /// # Panics
+    /// This function panics if more than `MAX_SERVER_COUNT` servers are given.
+    pub fn new<'a, 'b, 'c>(sockets: &mut SocketSet<'a, 'b, 'c>,
+                           rx_buffer: UdpSocketBuffer<'b, 'c>,
+                           tx_buffer: UdpSocketBuffer<'b, 'c>,
+                           local_port: u16, servers: &[IpAddress], now: u64) -> Client {
+        let udp_handle = sockets.add(UdpSocket::new(rx_buffer, tx_buffer));
+
+        let mut client = Client {
+            udp_handle:    udp_handle,
+            local_port:    local_port,
+            servers:       [None; MAX_SERVER_COUNT],
+            server_index:  0,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            interval:      DEFAULT_POLL_INTERVAL,
+            unix_offset:   None,
+            request:       None,
+            next_request:  now,
+            rand_state:    (now as u32) ^ ((now >> 32) as u32) ^ 0x6a09e667,
+        };
+        client.set_servers(servers);
+        client
+    }
+
+    /// Return the handle of the UDP socket used by the client.
+    pub fn udp_handle(&self) -> SocketHandle {
+        self.udp_handle
+    }
+
+    /// Return the servers the client queries.
+    pub fn servers(&self) -> &[Option<IpAddress>] {
+        &self.servers
+    }
+
+    /// Set the servers the client queries, and abandon the pending query.
+    ///
+    /// # Panics
+    /// This function panics if more than `MAX_SERVER_COUNT` servers are given.
+    pub fn set_servers(&mut self, servers: &[IpAddress]) {
+        assert!(servers.len() <= MAX_SERVER_COUNT, "too many SNTP servers");
+        self.servers = [None; MAX_SERVER_COUNT];
+        for (slot, server) in self.servers.iter_mut().zip(servers) {
+            *slot = Some(*server);
+        }
+        self.server_index = 0;
+        self.request = None;
+    }
+
+    /// Return the interval between two queries, in milliseconds.
+    pub fn poll_interval(&self) -> u64 {
+        self.poll_interval
+    }
+
+    /// Set the interval between two queries, in milliseconds, which cannot be shorter
+    /// than 15 seconds. This also resets any longer interval a server asked for.
+    pub fn set_poll_interval(&mut self, interval: u64) {
+        let interval = cmp::max(interval, MIN_POLL_INTERVAL);
+        self.poll_interval = interval;
+        self.interval = interval;
+    }
+
+    /// Set the estimate of the Unix time, in milliseconds, at the given moment, e.g. from
+    /// a real-time clock, so that the offsets reported later are relative to it.
+    pub fn set_unix_time(&mut self, now: u64, unix_time: u64) {
+        self.unix_offset = Some(unix_time as i64 - now as i64);
+    }
+
+    /// Return the Unix time, in milliseconds, at the given moment, unless the client was
+    /// never synchronized and no time was set.
+    pub fn unix_time(&self, now: u64) -> Option<u64> {
+        self.unix_offset.map(|offset| (now as i64 + offset) as u64)
+    }
+
+    /// Return the time, in milliseconds, at which the client should be polled next,
+    /// assuming no packets arrive in the meantime.
+    pub fn next_poll(&self, now: u64) -> u64 {
+        let next_poll = match self.request {
+            Some(request) => request.sent_at + REQUEST_TIMEOUT,
+            None => self.next_request
+        };
+        cmp::max(next_poll, now)
+    }
+
+    /// Process the responses received by the UDP socket, and send a query if one is due.
+    ///
+    /// Every valid response is reported to `f` as an adjustment of the clock.
+    pub fn poll<F>(&mut self, sockets: &mut SocketSet, now: u64, mut f: F) -> Result<()>
+            where F: FnMut(Adjustment) {
+        {
+            let mut udp_socket = sockets.get::<UdpSocket>(self.udp_handle);
+            if !udp_socket.is_open() {
+                udp_socket.bind(self.local_port)?;
+            }
+
+            loop {
+                let mut buffer = [0; 128];
+                let (length, endpoint) = match udp_socket.recv_slice(&mut buffer) {
+                    Ok(result) => result,
+                    Err(_) => break
+                };
+                if let Some(adjustment) = self.ingress(&buffer[..length], endpoint, now) {
+                    f(adjustment)
+                }
+            }
+        }
+
+        if let Some(request) = self.request {
+            if now < request.sent_at + REQUEST_TIMEOUT { return Ok(()) }
+            net_debug!("SNTP request to {} timed out", request.server);
+            self.request = None;
+            self.next_server(now);
+        }
+
+        if now >= self.next_request {
+            self.egress(sockets, now)?;
+        }
+        Ok(())
+    }
+
+    /// Move on to the next server, waiting for the poll interval once every server
+    /// was queried.
+    fn next_server(&mut self, now: u64) {
+        self.server_index += 1;
+        if self.server_index >= MAX_SERVER_COUNT || self.servers[self.server_index].is_none() {
+            self.server_index = 0;
+            self.next_request = now + self.interval;
+        } else {
+            self.next_request = now;
+        }
+    }
+
+    fn ingress(&mut self, payload: &[u8], endpoint: IpEndpoint,
+               now: u64) -> Option<Adjustment> {
+        let request = match self.request {
+            Some(request) if endpoint.addr == request.server &&
+                             endpoint.port == NTP_SERVER_PORT => request,
+            _ => {
+                net_trace!("SNTP ignoring unrelated packet from {}", endpoint);
+                return None
+            }
+        };
+        let ntp_repr = match NtpRepr::parse(&NtpPacket::new(payload)) {
+            Ok(repr) => repr,
+            Err(_) => {
+                net_debug!("SNTP ignoring malformed packet from {}", endpoint);
+                return None
+            }
+        };
+        if ntp_repr.mode != NtpMode::Server ||
+                ntp_repr.originate_timestamp != request.transmit {
+            net_debug!("SNTP ignoring unexpected packet from {}", endpoint);
+            return None
+        }
+        self.request = None;
+
+        if ntp_repr.stratum == 0 {
+            self.kiss(&ntp_repr, request.server, now);
+            return None
+        }
+        if ntp_repr.leap_indicator == NtpLeapIndicator::Alarm ||
+                ntp_repr.version < 3 || ntp_repr.version > NTP_VERSION ||
+                ntp_repr.transmit_timestamp.is_zero() {
+            net_debug!("SNTP ignoring unsynchronized server {}", request.server);
+            self.next_server(now);
+            return None
+        }
+        self.next_request = now + self.interval;
+
+        let unix_offset = self.unix_offset.unwrap_or(0);
+        let (offset, delay) = clock_offset(unix_offset + request.sent_at as i64,
+                                           ntp_repr.receive_timestamp.to_unix_millis(),
+                                           ntp_repr.transmit_timestamp.to_unix_millis(),
+                                           unix_offset + now as i64);
+        self.unix_offset = Some(unix_offset + offset);
+        net_debug!("SNTP offset {} ms, delay {} ms from {}", offset, delay, request.server);
+
+        Some(Adjustment {
+            offset:    offset,
+            delay:     delay,
+            unix_time: (unix_offset + offset + now as i64) as u64,
+            server:    request.server,
+            stratum:   ntp_repr.stratum,
+        })
+    }
+
+    /// Process a Kiss-o'-Death packet, as described in RFC 5905 § 7.4.
+    fn kiss(&mut self, ntp_repr: &NtpRepr, server: IpAddress, now: u64) {
+        match &ntp_repr.reference_id {
+            b"RATE" => {
+                self.interval = cmp::min(self.interval * 2, MAX_POLL_INTERVAL);
+                net_debug!("SNTP server {} asked for a poll interval of {} ms",
+                           server, self.interval);
+                self.next_request = now + self.interval;
+            }
+            b"DENY" | b"RSTR" => {
+                net_debug!("SNTP server {} denied access", server);
+                for slot in self.servers.iter_mut() {
+                    if *slot == Some(server) { *slot = None }
+                }
+                self.compact_servers();
+                self.next_server_after_removal(now);
+            }
+            _ => {
+                net_debug!("SNTP ignoring unknown kiss code from {}", server);
+                self.next_server(now);
+            }
+        }
+    }
+
+    fn compact_servers(&mut self) {
+        let mut servers = [None; MAX_SERVER_COUNT];
+        for (slot, server) in servers.iter_mut().zip(self.servers.iter().filter_map(|s| *s)) {
+            *slot = Some(server);
+        }
+        self.servers = servers;
+    }
+
+    fn next_server_after_removal(&mut self, now: u64) {
+        // The server at the current index, if any, is the one after the removed one.
+        if self.server_index < MAX_SERVER_COUNT && self.servers[self.server_index].is_some() {
+            self.next_request = now;
+        } else {
+            self.server_index = 0;
+            self.next_request = now + self.interval;
+        }
+    }
+
+    fn egress(&mut self, sockets: &mut SocketSet, now: u64) -> Result<()> {
+        let server = match self.servers[self.server_index] {
+            Some(server) => server,
+            None => {
+                self.next_request = now + self.interval;
+                return Err(Error::Unaddressable)
+            }
+        };
+
+        let unix_time = self.unix_offset.unwrap_or(0) + now as i64;
+        let mut transmit = NtpTimestamp::from_unix_millis(unix_time);
+        transmit.fraction = (transmit.fraction & !NONCE_MASK) |
+                            (self.random_u32() & NONCE_MASK);
+
+        let ntp_repr = NtpRepr {
+            leap_indicator:      NtpLeapIndicator::NoWarning,
+            version:             NTP_VERSION,
+            mode:                NtpMode::Client,
+            stratum:             0,
+            poll:                0,
+            precision:           0,
+            root_delay:          0,
+            root_dispersion:     0,
+            reference_id:        [0; 4],
+            reference_timestamp: NtpTimestamp::default(),
+            originate_timestamp: NtpTimestamp::default(),
+            receive_timestamp:   NtpTimestamp::default(),
+            transmit_timestamp:  transmit,
+        };
+        let mut buffer = [0; 48];
+        ntp_repr.emit(&mut NtpPacket::new(&mut buffer[..]));
+
+        let endpoint = IpEndpoint::new(server, NTP_SERVER_PORT);
+        sockets.get::<UdpSocket>(self.udp_handle).send_slice(&buffer, endpoint)?;
+        net_trace!("SNTP sent request to {}", endpoint);
+        self.request = Some(Request { server, sent_at: now, transmit });
+        Ok(())
+    }
+
+    fn random_u32(&mut self) -> u32 {
+        // xorshift32
+        let mut x = self.rand_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rand_state = x;
+        x
+    }
+}
+
+/// Compute the offset of the clock of the server and the round-trip delay from
+/// the times the request was sent and received, and the response was sent and received,
+/// as described in RFC 4330 § 5.
+fn clock_offset(t1: i64, t2: i64, t3: i64, t4: i64) -> (i64, u64) {
+    let offset = ((t2 - t1) + (t3 - t4)) / 2;
+    let delay = cmp::max((t4 - t1) - (t3 - t2), 0) as u64;
+    (offset, delay)
+}
+
+#[cfg(all(test, feature = "proto-ipv4"))]
+mod test {
+    use std::vec::Vec;
+    use wire::{Ipv4Address, IpProtocol, IpRepr, UdpRepr};
+    use socket::UdpPacketBuffer;
+    use super::*;
+
+    const SERVER_1: IpAddress = IpAddress::Ipv4(Ipv4Address([192, 168, 1, 1]));
+    const SERVER_2: IpAddress = IpAddress::Ipv4(Ipv4Address([192, 168, 1, 2]));
+    const LOCAL_IP: IpAddress = IpAddress::Ipv4(Ipv4Address([192, 168, 1, 10]));
+    const LOCAL_PORT: u16 = 50123;
+
+    /// 2020-01-01 00:00:00 UTC.
+    const UNIX_TIME: i64 = 1_577_836_800_000;
+
+    fn buffer() -> UdpSocketBuffer<'static, 'static> {
+        UdpSocketBuffer::new((0..4).map(|_| UdpPacketBuffer::new(vec![0; 128]))
+                                   .collect::<Vec<_>>())
+    }
+
+    fn setup() -> (SocketSet<'static, 'static, 'static>, Client) {
+        let mut sockets = SocketSet::new(vec![]);
+        let client = Client::new(&mut sockets, buffer(), buffer(), LOCAL_PORT,
+                                 &[SERVER_1, SERVER_2], 0);
+        (sockets, client)
+    }
+
+    fn sent(sockets: &mut SocketSet, client: &Client) -> Option<(IpAddress, NtpRepr)> {
+        let mut result = None;
+        let _ = sockets.get::<UdpSocket>(client.udp_handle())
+            .dispatch(0, |(ip_repr, udp_repr)| {
+                assert_eq!(udp_repr.src_port, LOCAL_PORT);
+                assert_eq!(udp_repr.dst_port, NTP_SERVER_PORT);
+                let repr = NtpRepr::parse(&NtpPacket::new(udp_repr.payload)).unwrap();
+                result = Some((ip_repr.dst_addr(), repr));
+                Ok(())
+            });
+        result
+    }
+
+    fn recv(sockets: &mut SocketSet, client: &Client, server: IpAddress, ntp_repr: &NtpRepr) {
+        let mut payload = [0; 48];
+        ntp_repr.emit(&mut NtpPacket::new(&mut payload[..]));
+        let udp_repr = UdpRepr {
+            src_port: NTP_SERVER_PORT,
+            dst_port: LOCAL_PORT,
+            payload:  &payload
+        };
+        let ip_repr = IpRepr::Unspecified {
+            src_addr: server,
+            dst_addr: LOCAL_IP,
+            protocol: IpProtocol::Udp,
+            payload_len: udp_repr.buffer_len(),
+            hop_limit: 64,
+            ecn: 0,
+            dscp: 0,
+            flow_label: 0
+        };
+        sockets.get::<UdpSocket>(client.udp_handle())
+            .process_lent(0, None, &ip_repr, &udp_repr, None).unwrap();
+    }
+
+    /// Build the response of a server whose clock is at `UNIX_TIME` when it receives
+    /// the request, and which takes 10 ms to respond.
+    fn response(request: &NtpRepr) -> NtpRepr {
+        NtpRepr {
+            leap_indicator:      NtpLeapIndicator::NoWarning,
+            version:             NTP_VERSION,
+            mode:                NtpMode::Server,
+            stratum:             2,
+            poll:                6,
+            precision:           -20,
+            root_delay:          0,
+            root_dispersion:     0,
+            reference_id:        [192, 168, 0, 1],
+            reference_timestamp: NtpTimestamp::from_unix_millis(UNIX_TIME - 1_000),
+            originate_timestamp: request.transmit_timestamp,
+            receive_timestamp:   NtpTimestamp::from_unix_millis(UNIX_TIME),
+            transmit_timestamp:  NtpTimestamp::from_unix_millis(UNIX_TIME + 10),
+        }
+    }
+
+    #[test]
+    fn test_clock_offset() {
+        // The server is 1 s ahead, and the network takes 20 ms each way.
+        assert_eq!(clock_offset(0, 1_020, 1_030, 50), (1_000, 40));
+        assert_eq!(clock_offset(1_000, 20, 30, 1_050), (-1_000, 40));
+    }
+
+    #[test]
+    fn test_synchronize() {
+        let (mut sockets, mut client) = setup();
+        assert_eq!(client.unix_time(0), None);
+        client.poll(&mut sockets, 1_000, |_| panic!()).unwrap();
+        let (server, request) = sent(&mut sockets, &client).expect("no request");
+        assert_eq!(server, SERVER_1);
+        assert_eq!(request.mode, NtpMode::Client);
+        assert_eq!(request.version, NTP_VERSION);
+
+        // The response arrives 50 ms after the request was sent.
+        recv(&mut sockets, &client, SERVER_1, &response(&request));
+        let mut adjustments = vec![];
+        client.poll(&mut sockets, 1_050, |adjustment| adjustments.push(adjustment)).unwrap();
+        assert_eq!(adjustments.len(), 1);
+        let adjustment = adjustments[0];
+        assert_eq!(adjustment.delay, 40);
+        assert_eq!(adjustment.server, SERVER_1);
+        assert_eq!(adjustment.stratum, 2);
+        assert_eq!(adjustment.unix_time, (UNIX_TIME + 30) as u64);
+        assert_eq!(client.unix_time(2_050), Some((UNIX_TIME + 1_030) as u64));
+
+        // The next request is sent after the poll interval.
+        assert_eq!(client.next_poll(1_050), 1_050 + DEFAULT_POLL_INTERVAL);
+        client.poll(&mut sockets, 1_050 + DEFAULT_POLL_INTERVAL, |_| panic!()).unwrap();
+        let (server, request) = sent(&mut sockets, &client).expect("no request");
+        assert_eq!(server, SERVER_1);
+        assert_eq!(request.transmit_timestamp.to_unix_millis(),
+                   UNIX_TIME + 30 + DEFAULT_POLL_INTERVAL as i64);
+    }
+
+    #[test]
+    fn test_ignored() {
+        let (mut sockets, mut client) = setup();
+        client.poll(&mut sockets, 0, |_| panic!()).unwrap();
+        let (_, request) = sent(&mut sockets, &client).unwrap();
+
+        // From another server, with another originate timestamp, or unsynchronized.
+        recv(&mut sockets, &client, SERVER_2, &response(&request));
+        let mut forged = response(&request);
+        forged.originate_timestamp.fraction ^= 1;
+        recv(&mut sockets, &client, SERVER_1, &forged);
+        client.poll(&mut sockets, 10, |_| panic!()).unwrap();
+
+        let mut alarm = response(&request);
+        alarm.leap_indicator = NtpLeapIndicator::Alarm;
+        recv(&mut sockets, &client, SERVER_1, &alarm);
+        client.poll(&mut sockets, 20, |_| panic!()).unwrap();
+        // The unsynchronized server is skipped.
+        let (server, _) = sent(&mut sockets, &client).expect("no request");
+        assert_eq!(server, SERVER_2);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let (mut sockets, mut client) = setup();
+        client.poll(&mut sockets, 0, |_| panic!()).unwrap();
+        assert_eq!(sent(&mut sockets, &client).unwrap().0, SERVER_1);
+        assert_eq!(client.next_poll(0), REQUEST_TIMEOUT);
+
+        client.poll(&mut sockets, REQUEST_TIMEOUT, |_| panic!()).unwrap();
+        assert_eq!(sent(&mut sockets, &client).unwrap().0, SERVER_2);
+
+        // Once every server was queried, the client waits for the poll interval.
+        client.poll(&mut sockets, 2 * REQUEST_TIMEOUT, |_| panic!()).unwrap();
+        assert_eq!(sent(&mut sockets, &client), None);
+        assert_eq!(client.next_poll(2 * REQUEST_TIMEOUT),
+                   2 * REQUEST_TIMEOUT + DEFAULT_POLL_INTERVAL);
+    }
+
+    #[test]
+    fn test_kiss_of_death() {
+        let (mut sockets, mut client) = setup();
+        client.poll(&mut sockets, 0, |_| panic!()).unwrap();
+        let (_, request) = sent(&mut sockets, &client).unwrap();
+        let mut kiss = response(&request);
+        kiss.stratum = 0;
+        kiss.reference_id = *b"RATE";
+        recv(&mut sockets, &client, SERVER_1, &kiss);
+        client.poll(&mut sockets, 10, |_| panic!()).unwrap();
+        assert_eq!(client.next_poll(10), 10 + 2 * DEFAULT_POLL_INTERVAL);
+
+        client.poll(&mut sockets, 10 + 2 * DEFAULT_POLL_INTERVAL, |_| panic!()).unwrap();
+        let (_, request) = sent(&mut sockets, &client).unwrap();
+        let mut kiss = response(&request);
+        kiss.stratum = 0;
+        kiss.reference_id = *b"DENY";
+        recv(&mut sockets, &client, SERVER_1, &kiss);
+        client.poll(&mut sockets, 20 + 2 * DEFAULT_POLL_INTERVAL, |_| panic!()).unwrap();
+        // The server that denied access is not queried again.
+        assert_eq!(client.servers(), &[Some(SERVER_2), None, None, None]);
+        assert_eq!(sent(&mut sockets, &client).unwrap().0, SERVER_2);
+    }
+}
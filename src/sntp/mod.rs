@@ -0,0 +1,10 @@
+/*! Simple Network Time Protocol.
+
+The `sntp` module implements an SNTPv4 client, on top of a UDP socket, that periodically
+queries time servers and reports the offset of the local clock from theirs, so that
+the application can discipline its clock.
+*/
+
+mod client;
+
+pub use self::client::{Client, Adjustment, MAX_SERVER_COUNT};
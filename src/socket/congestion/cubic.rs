@@ -0,0 +1,222 @@
+use core::cmp;
+
+use super::{Controller, initial_window};
+
+const DEFAULT_MSS: usize = 536;
+
+/// The multiplicative decrease factor.
+const BETA: f64 = 0.7;
+/// The scaling constant of the cubic function, in segments per second cubed.
+const C: f64 = 0.4;
+/// The additive increase factor that makes the estimated Reno window grow
+/// as fast as the window of a Reno sender with the same average window.
+const ALPHA: f64 = 3.0 * (1.0 - BETA) / (1.0 + BETA);
+
+/// Return the cube root of a non-negative number.
+fn cbrt(x: f64) -> f64 {
+    if x <= 0.0 { return 0.0 }
+
+    // Newton's method converges for any positive initial guess.
+    let mut y = 1.0 + x / 3.0;
+    for _ in 0..64 {
+        let next = (2.0 * y + x / (y * y)) / 3.0;
+        if next >= y { break }
+        y = next;
+    }
+    y
+}
+
+/// The CUBIC congestion controller.
+///
+/// After a loss, the congestion window grows as a cubic function of the time elapsed since,
+/// first quickly and then slowly while approaching the window at which the loss occurred,
+/// and then quickly again while probing for more bandwidth. The window never grows slower
+/// than that of a Reno sender would.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cubic {
+    cwnd:        usize,
+    ssthresh:    usize,
+    mss:         usize,
+    /// The window just before the last reduction.
+    w_max:       usize,
+    /// The window that a Reno sender would have.
+    w_est:       usize,
+    /// The start of the current congestion avoidance epoch.
+    epoch_start: Option<u64>,
+    /// The time it takes the cubic function to reach `origin`, in seconds.
+    k:           f64,
+    /// The window of the plateau of the cubic function.
+    origin:      usize,
+}
+
+impl Cubic {
+    /// Create a controller for a connection with the default maximum segment size.
+    pub fn new() -> Cubic {
+        Cubic {
+            cwnd:        initial_window(DEFAULT_MSS),
+            ssthresh:    usize::max_value(),
+            mss:         DEFAULT_MSS,
+            w_max:       0,
+            w_est:       0,
+            epoch_start: None,
+            k:           0.0,
+            origin:      0,
+        }
+    }
+
+    /// Return the slow start threshold.
+    pub fn ssthresh(&self) -> usize {
+        self.ssthresh
+    }
+
+    fn reduce(&mut self) {
+        // Release some bandwidth to new flows if the window keeps decreasing
+        // (fast convergence).
+        if self.cwnd < self.w_max {
+            self.w_max = (self.cwnd as f64 * (1.0 + BETA) / 2.0) as usize;
+        } else {
+            self.w_max = self.cwnd;
+        }
+        self.ssthresh = cmp::max((self.cwnd as f64 * BETA) as usize, 2 * self.mss);
+        self.epoch_start = None;
+    }
+}
+
+impl Default for Cubic {
+    fn default() -> Cubic {
+        Cubic::new()
+    }
+}
+
+impl Controller for Cubic {
+    fn window(&self) -> usize {
+        self.cwnd
+    }
+
+    fn set_mss(&mut self, mss: usize) {
+        self.mss  = mss;
+        self.cwnd = initial_window(mss);
+    }
+
+    fn on_ack(&mut self, timestamp: u64, len: usize) {
+        if self.cwnd < self.ssthresh {
+            // Slow start.
+            self.cwnd += cmp::min(len, self.mss);
+            return
+        }
+
+        let (cwnd, mss) = (self.cwnd as f64, self.mss as f64);
+        let epoch_start = match self.epoch_start {
+            Some(epoch_start) => epoch_start,
+            None => {
+                if self.cwnd < self.w_max {
+                    self.k = cbrt((self.w_max - self.cwnd) as f64 / mss / C);
+                    self.origin = self.w_max;
+                } else {
+                    self.k = 0.0;
+                    self.origin = self.cwnd;
+                }
+                self.w_est = self.cwnd;
+                self.epoch_start = Some(timestamp);
+                timestamp
+            }
+        };
+
+        // Grow towards the cubic function, by at most half of the window per round trip.
+        let t = timestamp.saturating_sub(epoch_start) as f64 / 1000.0 - self.k;
+        let mut target = self.origin as f64 + C * t * t * t * mss;
+        if target > 1.5 * cwnd { target = 1.5 * cwnd }
+        if target > cwnd {
+            self.cwnd += cmp::max(((target - cwnd) / cwnd * mss) as usize, 1);
+        }
+
+        // Grow at least as fast as a Reno sender would.
+        self.w_est += cmp::max((ALPHA * mss * mss / cwnd) as usize, 1);
+        if self.w_est > self.cwnd {
+            self.cwnd = self.w_est;
+        }
+    }
+
+    fn on_loss(&mut self, _timestamp: u64) {
+        self.reduce();
+        self.cwnd = self.ssthresh;
+    }
+
+    fn on_retransmit_timeout(&mut self, _timestamp: u64) {
+        self.reduce();
+        self.cwnd = self.mss;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cbrt() {
+        assert_eq!(cbrt(0.0), 0.0);
+        assert!((cbrt(27.0) - 3.0).abs() < 1e-9);
+        assert!((cbrt(7.5) - 1.957434).abs() < 1e-6);
+        assert!((cbrt(0.001) - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_loss() {
+        let mut cubic = Cubic::new();
+        cubic.set_mss(1000);
+        cubic.cwnd = 10_000;
+        cubic.on_loss(0);
+        assert_eq!(cubic.ssthresh(), 7000);
+        assert_eq!(cubic.window(), 7000);
+        assert_eq!(cubic.w_max, 10_000);
+
+        // A loss before the window has recovered reduces the plateau further.
+        cubic.on_loss(0);
+        assert_eq!(cubic.w_max, 5950);
+        assert_eq!(cubic.window(), 4900);
+    }
+
+    #[test]
+    fn test_cubic_growth() {
+        let mut cubic = Cubic::new();
+        cubic.set_mss(1000);
+        cubic.cwnd = 100_000;
+        cubic.on_loss(0);
+        assert_eq!(cubic.window(), 70_000);
+
+        // An acknowledgement every millisecond.
+        let mut timestamp = 0;
+        let mut window_at = |cubic: &mut Cubic, until: u64| {
+            while timestamp < until {
+                cubic.on_ack(timestamp, 1000);
+                timestamp += 1;
+            }
+            cubic.window()
+        };
+
+        // The window grows quickly at first, stays close to the plateau around K,
+        // which is about 4.2 s, and then grows quickly again.
+        let at_1s = window_at(&mut cubic, 1_000);
+        assert!(at_1s > 85_000 && at_1s < 100_000, "{}", at_1s);
+        let at_4s = window_at(&mut cubic, 4_000);
+        assert!(at_4s > 99_000 && at_4s < 101_000, "{}", at_4s);
+        let at_5s = window_at(&mut cubic, 5_000);
+        assert!(at_5s > 100_000 && at_5s < 102_000, "{}", at_5s);
+        let at_8s = window_at(&mut cubic, 8_000);
+        assert!(at_8s > 115_000, "{}", at_8s);
+    }
+
+    #[test]
+    fn test_retransmit_timeout() {
+        let mut cubic = Cubic::new();
+        cubic.set_mss(1000);
+        cubic.cwnd = 10_000;
+        cubic.on_retransmit_timeout(0);
+        assert_eq!(cubic.ssthresh(), 7000);
+        assert_eq!(cubic.window(), 1000);
+        for _ in 0..6 {
+            cubic.on_ack(0, 1000);
+        }
+        assert_eq!(cubic.window(), 7000);
+    }
+}
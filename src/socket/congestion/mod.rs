@@ -0,0 +1,181 @@
+// Heads up! Before working on this file you should read RFC 5681, which describes
+// the congestion control algorithms every TCP sender is expected to implement.
+
+use core::fmt;
+
+mod reno;
+mod cubic;
+
+pub use self::reno::Reno;
+pub use self::cubic::Cubic;
+
+/// A TCP congestion control algorithm.
+///
+/// A congestion controller maintains the congestion window, which limits the amount of data
+/// a TCP sender may have in flight in addition to the window advertised by the receiver.
+/// All sizes are in octets, and all timestamps are in milliseconds.
+pub trait Controller {
+    /// Return the congestion window.
+    fn window(&self) -> usize;
+
+    /// Set the maximum segment size of the connection, and reset the congestion window
+    /// to the initial window for this segment size.
+    ///
+    /// This function is called when the connection is synchronized.
+    fn set_mss(&mut self, mss: usize);
+
+    /// Process an acknowledgement of `len` previously unacknowledged octets.
+    fn on_ack(&mut self, timestamp: u64, len: usize);
+
+    /// Process a loss of a segment that was detected without waiting for the retransmission
+    /// timeout, e.g. with duplicate acknowledgements.
+    fn on_loss(&mut self, timestamp: u64);
+
+    /// Process an expiry of the retransmission timeout.
+    fn on_retransmit_timeout(&mut self, timestamp: u64);
+}
+
+/// The congestion control algorithm used by a TCP socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// No congestion control; the sender is only limited by the window of the receiver.
+    None,
+    /// TCP Reno, as described in [RFC 5681].
+    ///
+    /// [RFC 5681]: https://tools.ietf.org/html/rfc5681
+    Reno,
+    /// CUBIC, as described in [RFC 8312].
+    ///
+    /// [RFC 8312]: https://tools.ietf.org/html/rfc8312
+    Cubic,
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Algorithm::None  => write!(f, "none"),
+            &Algorithm::Reno  => write!(f, "reno"),
+            &Algorithm::Cubic => write!(f, "cubic")
+        }
+    }
+}
+
+/// Return the initial congestion window for the given maximum segment size,
+/// according to [RFC 5681 § 3.1].
+///
+/// [RFC 5681 § 3.1]: https://tools.ietf.org/html/rfc5681#section-3.1
+fn initial_window(mss: usize) -> usize {
+    if mss > 2190 {
+        2 * mss
+    } else if mss > 1095 {
+        3 * mss
+    } else {
+        4 * mss
+    }
+}
+
+/// A congestion controller implementing any of the algorithms, without needing
+/// dynamic dispatch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum AnyController {
+    None,
+    Reno(Reno),
+    Cubic(Cubic),
+}
+
+impl AnyController {
+    pub fn new(algorithm: Algorithm) -> AnyController {
+        match algorithm {
+            Algorithm::None  => AnyController::None,
+            Algorithm::Reno  => AnyController::Reno(Reno::new()),
+            Algorithm::Cubic => AnyController::Cubic(Cubic::new()),
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            &AnyController::None     => Algorithm::None,
+            &AnyController::Reno(_)  => Algorithm::Reno,
+            &AnyController::Cubic(_) => Algorithm::Cubic,
+        }
+    }
+
+    pub fn ssthresh(&self) -> usize {
+        match self {
+            &AnyController::None => usize::max_value(),
+            &AnyController::Reno(ref controller) => controller.ssthresh(),
+            &AnyController::Cubic(ref controller) => controller.ssthresh(),
+        }
+    }
+}
+
+impl Controller for AnyController {
+    fn window(&self) -> usize {
+        match self {
+            &AnyController::None => usize::max_value(),
+            &AnyController::Reno(ref controller) => controller.window(),
+            &AnyController::Cubic(ref controller) => controller.window(),
+        }
+    }
+
+    fn set_mss(&mut self, mss: usize) {
+        match self {
+            &mut AnyController::None => (),
+            &mut AnyController::Reno(ref mut controller) => controller.set_mss(mss),
+            &mut AnyController::Cubic(ref mut controller) => controller.set_mss(mss),
+        }
+    }
+
+    fn on_ack(&mut self, timestamp: u64, len: usize) {
+        match self {
+            &mut AnyController::None => (),
+            &mut AnyController::Reno(ref mut controller) => controller.on_ack(timestamp, len),
+            &mut AnyController::Cubic(ref mut controller) => controller.on_ack(timestamp, len),
+        }
+    }
+
+    fn on_loss(&mut self, timestamp: u64) {
+        match self {
+            &mut AnyController::None => (),
+            &mut AnyController::Reno(ref mut controller) => controller.on_loss(timestamp),
+            &mut AnyController::Cubic(ref mut controller) => controller.on_loss(timestamp),
+        }
+    }
+
+    fn on_retransmit_timeout(&mut self, timestamp: u64) {
+        match self {
+            &mut AnyController::None => (),
+            &mut AnyController::Reno(ref mut controller) =>
+                controller.on_retransmit_timeout(timestamp),
+            &mut AnyController::Cubic(ref mut controller) =>
+                controller.on_retransmit_timeout(timestamp),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_initial_window() {
+        assert_eq!(initial_window(536), 2144);
+        assert_eq!(initial_window(1460), 4380);
+        assert_eq!(initial_window(9000), 18000);
+    }
+
+    #[test]
+    fn test_any_controller() {
+        let mut controller = AnyController::new(Algorithm::None);
+        controller.on_retransmit_timeout(0);
+        assert_eq!(controller.window(), usize::max_value());
+
+        let mut controller = AnyController::new(Algorithm::Reno);
+        assert_eq!(controller.algorithm(), Algorithm::Reno);
+        controller.set_mss(1460);
+        assert_eq!(controller.window(), 4380);
+        controller.on_retransmit_timeout(0);
+        assert_eq!(controller.window(), 1460);
+        assert_eq!(controller.ssthresh(), 2920);
+    }
+}
@@ -0,0 +1,125 @@
+use core::cmp;
+
+use super::{Controller, initial_window};
+
+const DEFAULT_MSS: usize = 536;
+
+/// The TCP Reno congestion controller.
+///
+/// The congestion window grows by one segment per acknowledged segment during slow start,
+/// and by one segment per round trip during congestion avoidance. It is halved on a loss,
+/// and collapsed to one segment on a retransmission timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reno {
+    cwnd:     usize,
+    ssthresh: usize,
+    mss:      usize,
+}
+
+impl Reno {
+    /// Create a controller for a connection with the default maximum segment size.
+    pub fn new() -> Reno {
+        Reno {
+            cwnd:     initial_window(DEFAULT_MSS),
+            ssthresh: usize::max_value(),
+            mss:      DEFAULT_MSS,
+        }
+    }
+
+    /// Return the slow start threshold.
+    pub fn ssthresh(&self) -> usize {
+        self.ssthresh
+    }
+
+    fn reduce_ssthresh(&mut self) {
+        self.ssthresh = cmp::max(self.cwnd / 2, 2 * self.mss);
+    }
+}
+
+impl Default for Reno {
+    fn default() -> Reno {
+        Reno::new()
+    }
+}
+
+impl Controller for Reno {
+    fn window(&self) -> usize {
+        self.cwnd
+    }
+
+    fn set_mss(&mut self, mss: usize) {
+        self.mss  = mss;
+        self.cwnd = initial_window(mss);
+    }
+
+    fn on_ack(&mut self, _timestamp: u64, len: usize) {
+        if self.cwnd < self.ssthresh {
+            // Slow start.
+            self.cwnd += cmp::min(len, self.mss);
+        } else {
+            // Congestion avoidance.
+            self.cwnd += cmp::max(self.mss * self.mss / self.cwnd, 1);
+        }
+    }
+
+    fn on_loss(&mut self, _timestamp: u64) {
+        self.reduce_ssthresh();
+        self.cwnd = self.ssthresh;
+    }
+
+    fn on_retransmit_timeout(&mut self, _timestamp: u64) {
+        self.reduce_ssthresh();
+        self.cwnd = self.mss;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_slow_start() {
+        let mut reno = Reno::new();
+        reno.set_mss(1000);
+        assert_eq!(reno.window(), 4000);
+        for _ in 0..4 {
+            reno.on_ack(0, 1000);
+        }
+        assert_eq!(reno.window(), 8000);
+        // Acknowledging several segments at once still grows by at most one segment.
+        reno.on_ack(0, 4000);
+        assert_eq!(reno.window(), 9000);
+    }
+
+    #[test]
+    fn test_congestion_avoidance() {
+        let mut reno = Reno::new();
+        reno.set_mss(1000);
+        reno.on_loss(0);
+        assert_eq!(reno.ssthresh(), 2000);
+        assert_eq!(reno.window(), 2000);
+        reno.on_ack(0, 1000);
+        assert_eq!(reno.window(), 2500);
+        reno.on_ack(0, 1000);
+        assert_eq!(reno.window(), 2900);
+    }
+
+    #[test]
+    fn test_retransmit_timeout() {
+        let mut reno = Reno::new();
+        reno.set_mss(1000);
+        for _ in 0..8 {
+            reno.on_ack(0, 1000);
+        }
+        reno.on_retransmit_timeout(0);
+        assert_eq!(reno.ssthresh(), 6000);
+        assert_eq!(reno.window(), 1000);
+        // Slow start resumes up to the threshold.
+        for _ in 0..5 {
+            reno.on_ack(0, 1000);
+        }
+        assert_eq!(reno.window(), 6000);
+        reno.on_ack(0, 1000);
+        assert_eq!(reno.window(), 6166);
+    }
+}
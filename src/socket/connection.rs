@@ -0,0 +1,71 @@
+use wire::{IpProtocol, IpEndpoint};
+use time::Instant;
+use super::{Socket, SocketHandle};
+#[cfg(feature = "socket-tcp")]
+use super::TcpState;
+
+/// An open TCP or UDP socket, as reported by
+/// [SocketSet::connections](struct.SocketSet.html#method.connections), e.g. to show it
+/// in the same way as `ss` or `netstat` do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Connection {
+    /// The handle of the socket.
+    pub handle:          SocketHandle,
+    /// The transport protocol of the socket, either TCP or UDP.
+    pub protocol:        IpProtocol,
+    /// The local endpoint the socket is bound to, or listening on.
+    pub local_endpoint:  IpEndpoint,
+    /// The remote endpoint of the socket, or an unspecified one if the socket is
+    /// listening, or is not connected.
+    pub remote_endpoint: IpEndpoint,
+    /// The state of the connection of a TCP socket, or `None` for a UDP socket.
+    #[cfg(feature = "socket-tcp")]
+    pub tcp_state:       Option<TcpState>,
+    /// The amount of received data the application has not dequeued yet, in octets
+    /// for a TCP socket, and in packets for a UDP socket.
+    pub recv_queue:      usize,
+    /// The amount of data enqueued by the application that was not sent, or, for
+    /// a TCP socket, not acknowledged yet; in octets for a TCP socket, and in packets
+    /// for a UDP socket.
+    pub send_queue:      usize,
+    /// The time at which the last packet was received, if any and if it is recorded.
+    pub rx_timestamp:    Option<Instant>,
+    /// The time at which the last packet was handed to the interface, if any.
+    pub tx_timestamp:    Option<Instant>,
+}
+
+impl Connection {
+    /// Return the connection of the given socket, if it is an open TCP or UDP socket.
+    pub(crate) fn new(socket: &Socket) -> Option<Connection> {
+        match *socket {
+            #[cfg(feature = "socket-udp")]
+            Socket::Udp(ref socket) if socket.is_open() =>
+                Some(Connection {
+                    handle:          socket.handle(),
+                    protocol:        IpProtocol::Udp,
+                    local_endpoint:  socket.endpoint(),
+                    remote_endpoint: socket.remote_endpoint(),
+                    #[cfg(feature = "socket-tcp")]
+                    tcp_state:       None,
+                    recv_queue:      socket.recv_buffer_stats().len,
+                    send_queue:      socket.send_buffer_stats().len,
+                    rx_timestamp:    None,
+                    tx_timestamp:    socket.tx_timestamp(),
+                }),
+            #[cfg(feature = "socket-tcp")]
+            Socket::Tcp(ref socket) if socket.state() != TcpState::Closed =>
+                Some(Connection {
+                    handle:          socket.handle(),
+                    protocol:        IpProtocol::Tcp,
+                    local_endpoint:  socket.local_endpoint(),
+                    remote_endpoint: socket.remote_endpoint(),
+                    tcp_state:       Some(socket.state()),
+                    recv_queue:      socket.recv_queue(),
+                    send_queue:      socket.send_queue(),
+                    rx_timestamp:    socket.rx_timestamp(),
+                    tx_timestamp:    socket.tx_timestamp(),
+                }),
+            _ => None
+        }
+    }
+}
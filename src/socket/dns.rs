@@ -0,0 +1,827 @@
+use core::{cmp, fmt};
+#[cfg(feature = "async")]
+use core::task::Waker;
+use managed::ManagedSlice;
+use byteorder::{ByteOrder, NetworkEndian};
+
+use {Error, Result};
+use wire::{IpProtocol, IpAddress, IpRepr, UdpRepr,
+           DnsPacket, DnsQuestion, DnsRecord, DnsRecordData, DnsName,
+           DnsType, DnsOpcode, DnsRcode, DNS_SERVER_PORT};
+use wire::dns::{encode_name, MAX_NAME_LEN};
+use socket::{Socket, SocketMeta, SocketHandle, DnsCache};
+#[cfg(feature = "async")]
+use socket::WakerRegistration;
+
+/// The maximum number of addresses returned for a query.
+pub const MAX_ADDRESS_COUNT: usize = 4;
+
+/// The delay before the first retransmission of a query, in milliseconds.
+/// It is doubled with every retransmission until it reaches `MAX_RETRANSMIT_DELAY`.
+const RETRANSMIT_DELAY: u64 = 1_000;
+const MAX_RETRANSMIT_DELAY: u64 = 8_000;
+/// The time after which a query without an answer fails, in milliseconds.
+const QUERY_TIMEOUT: u64 = 20_000;
+
+/// A handle, identifying a query in a DNS socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct QueryHandle(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PendingState {
+    transaction_id: u16,
+    port: u16,
+    /// The index of the server the next query is sent to.
+    server_index: usize,
+    /// When the next query is sent.
+    retransmit_at: u64,
+    retransmit_delay: u64,
+    /// When the query fails, or `None` if it was not sent yet.
+    timeout_at: Option<u64>,
+    /// When the query was last sent.
+    sent_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Waiting to be sent, or for an answer.
+    Pending(PendingState),
+    /// Answered with at least one address.
+    Completed([Option<IpAddress>; MAX_ADDRESS_COUNT]),
+    /// Answered without any addresses, or timed out.
+    Failed,
+}
+
+/// A query in a DNS socket.
+///
+/// The contents are private; this type only exists so that storage for queries
+/// can be allocated by the application.
+#[derive(Clone)]
+pub struct DnsQuery {
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    query_type: DnsType,
+    state: State,
+}
+
+impl fmt::Debug for DnsQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = &self.name[..self.name_len];
+        f.debug_struct("DnsQuery")
+         .field("name", &DnsName::new(name, name))
+         .field("query_type", &self.query_type)
+         .field("state", &self.state)
+         .finish()
+    }
+}
+
+/// A stub resolver socket.
+///
+/// A DNS socket sends A and AAAA queries to a list of recursive DNS servers over UDP,
+/// retransmitting them and trying every server in turn, until an answer arrives
+/// or the query times out. CNAME records in the answer are followed, but not queried for.
+///
+/// Transaction IDs and source ports are derived from the time queries are first sent,
+/// and are not suitable as a defense against spoofed answers.
+///
+/// If the socket is given a [cache](struct.DnsCache.html), answers are stored in it until
+/// their TTL expires, and queries for names found in it complete without being sent.
+/// Answers without any addresses are only cached if they carry an SOA record, as described
+/// in RFC 2308.
+#[derive(Debug)]
+pub struct DnsSocket<'a> {
+    pub(crate) meta: SocketMeta,
+    servers: ManagedSlice<'a, IpAddress>,
+    queries: ManagedSlice<'a, Option<DnsQuery>>,
+    cache: Option<DnsCache<'a>>,
+    rand_state: u32,
+    #[cfg(feature = "async")]
+    rx_waker:   WakerRegistration,
+    #[cfg(feature = "async")]
+    tx_waker:   WakerRegistration,
+}
+
+impl<'a> DnsSocket<'a> {
+    /// Create a DNS socket with the given servers and storage for queries.
+    ///
+    /// Unspecified addresses in the server list are skipped, so that they can be
+    /// used as placeholders, e.g. until a DHCP lease is obtained.
+    pub fn new<ServersT, QueriesT>(servers: ServersT, queries: QueriesT) -> DnsSocket<'a>
+            where ServersT: Into<ManagedSlice<'a, IpAddress>>,
+                  QueriesT: Into<ManagedSlice<'a, Option<DnsQuery>>> {
+        DnsSocket {
+            meta:       SocketMeta::default(),
+            servers:    servers.into(),
+            queries:    queries.into(),
+            cache:      None,
+            rand_state: 0x1b873593,
+            #[cfg(feature = "async")]
+            rx_waker:   WakerRegistration::new(),
+            #[cfg(feature = "async")]
+            tx_waker:   WakerRegistration::new(),
+        }
+    }
+
+    /// Return the socket handle.
+    #[inline]
+    pub fn handle(&self) -> SocketHandle {
+        self.meta.handle
+    }
+
+    /// Register a waker to be woken once a query completes or fails.
+    ///
+    /// Only one waker is registered at a time, and it is woken at most once; it has to be
+    /// registered again to be woken again. The waker may be woken spuriously.
+    #[cfg(feature = "async")]
+    pub fn register_recv_waker(&mut self, waker: &Waker) {
+        self.rx_waker.register(waker)
+    }
+
+    /// Register a waker to be woken once a query is sent to a server.
+    ///
+    /// See also [register_recv_waker](#method.register_recv_waker).
+    #[cfg(feature = "async")]
+    pub fn register_send_waker(&mut self, waker: &Waker) {
+        self.tx_waker.register(waker)
+    }
+
+    /// Return the DNS servers.
+    pub fn servers(&self) -> &[IpAddress] {
+        self.servers.as_ref()
+    }
+
+    /// Update the DNS servers.
+    ///
+    /// Pending queries are sent to the new servers from their next retransmission on.
+    pub fn update_servers<F: FnOnce(&mut ManagedSlice<'a, IpAddress>)>(&mut self, f: F) {
+        f(&mut self.servers);
+    }
+
+    /// Return the cache of answers, if any.
+    pub fn cache(&self) -> Option<&DnsCache<'a>> {
+        self.cache.as_ref()
+    }
+
+    /// Return the cache of answers, if any, e.g. to flush it when the network changes.
+    pub fn cache_mut(&mut self) -> Option<&mut DnsCache<'a>> {
+        self.cache.as_mut()
+    }
+
+    /// Set the cache of answers, or disable caching.
+    pub fn set_cache(&mut self, cache: Option<DnsCache<'a>>) {
+        self.cache = cache
+    }
+
+    /// Start a query for the addresses of `name`, which must be fully qualified,
+    /// e.g. `example.com`. The query type must be `DnsType::A` or `DnsType::Aaaa`.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the name or the query type
+    /// are not valid, `Err(Error::Unaddressable)` if there are no servers,
+    /// and `Err(Error::Exhausted)` if there is no space for another query.
+    pub fn query(&mut self, name: &str, query_type: DnsType) -> Result<QueryHandle> {
+        match query_type {
+            #[cfg(feature = "proto-ipv4")]
+            DnsType::A => (),
+            #[cfg(feature = "proto-ipv6")]
+            DnsType::Aaaa => (),
+            _ => return Err(Error::Illegal)
+        }
+        if self.servers.iter().all(|server| server.is_unspecified()) {
+            return Err(Error::Unaddressable)
+        }
+
+        let mut query = DnsQuery {
+            name: [0; MAX_NAME_LEN],
+            name_len: 0,
+            query_type: query_type,
+            state: State::Pending(PendingState {
+                transaction_id: 0,
+                port: 0,
+                server_index: 0,
+                retransmit_at: 0,
+                retransmit_delay: RETRANSMIT_DELAY,
+                timeout_at: None,
+                sent_at: 0,
+            }),
+        };
+        query.name_len = encode_name(name, &mut query.name).map_err(|_| Error::Illegal)?;
+        if query.name_len == 1 { return Err(Error::Illegal) }
+
+        for (index, slot) in self.queries.iter_mut().enumerate() {
+            if slot.is_none() {
+                *slot = Some(query);
+                return Ok(QueryHandle(index))
+            }
+        }
+
+        match self.queries {
+            ManagedSlice::Borrowed(_) => Err(Error::Exhausted),
+            #[cfg(any(feature = "std", feature = "alloc"))]
+            ManagedSlice::Owned(ref mut queries) => {
+                queries.push(Some(query));
+                Ok(QueryHandle(queries.len() - 1))
+            }
+        }
+    }
+
+    /// Return the result of a query, and free it unless it is still pending.
+    ///
+    /// This function returns `Err(Error::Exhausted)` if the query is still pending,
+    /// and `Err(Error::Unaddressable)` if the name has no addresses of the requested type,
+    /// or no server answered in time.
+    ///
+    /// # Panics
+    /// This function panics if the handle does not refer to a query.
+    pub fn query_result(&mut self, handle: QueryHandle) ->
+                       Result<[Option<IpAddress>; MAX_ADDRESS_COUNT]> {
+        let slot = self.queries.get_mut(handle.0)
+            .expect("handle does not refer to a valid query");
+        let result = match *slot {
+            Some(DnsQuery { state: State::Pending(_), .. }) =>
+                return Err(Error::Exhausted),
+            Some(DnsQuery { state: State::Completed(addresses), .. }) =>
+                Ok(addresses),
+            Some(DnsQuery { state: State::Failed, .. }) =>
+                Err(Error::Unaddressable),
+            None => panic!("handle does not refer to a valid query")
+        };
+        *slot = None;
+        result
+    }
+
+    /// Cancel a query, and free it.
+    ///
+    /// # Panics
+    /// This function panics if the handle does not refer to a query.
+    pub fn cancel_query(&mut self, handle: QueryHandle) {
+        match self.queries.get_mut(handle.0) {
+            Some(slot @ &mut Some(_)) => *slot = None,
+            _ => panic!("handle does not refer to a valid query")
+        }
+    }
+
+    pub(crate) fn accepts(&self, ip_repr: &IpRepr, repr: &UdpRepr) -> bool {
+        if repr.src_port != DNS_SERVER_PORT { return false }
+        if !self.servers.iter().any(|server| *server == ip_repr.src_addr()) { return false }
+
+        self.queries.iter().any(|slot| {
+            match *slot {
+                Some(DnsQuery { state: State::Pending(ref pending), .. }) =>
+                    pending.timeout_at.is_some() && pending.port == repr.dst_port,
+                _ => false
+            }
+        })
+    }
+
+    pub(crate) fn process(&mut self, ip_repr: &IpRepr, repr: &UdpRepr) -> Result<()> {
+        debug_assert!(self.accepts(ip_repr, repr));
+
+        let handle = self.meta.handle;
+        let packet = DnsPacket::new_checked(repr.payload)?;
+        let message = packet.message();
+
+        for slot in self.queries.iter_mut() {
+            let query = match *slot {
+                Some(ref mut query) => query,
+                None => continue
+            };
+            let mut pending = match query.state {
+                State::Pending(pending) if pending.port == repr.dst_port &&
+                                           pending.transaction_id == packet.transaction_id() =>
+                    pending,
+                _ => continue
+            };
+
+            if !packet.is_response() || packet.opcode() != DnsOpcode::Query ||
+                    packet.question_count() != 1 {
+                return Err(Error::Malformed)
+            }
+            let (mut records, question) = DnsQuestion::parse(packet.payload())?;
+            let query_name = query.name;
+            let query_name = &query_name[..query.name_len];
+            let mut name = DnsName::new(query_name, query_name);
+            if question.type_ != query.query_type ||
+                    !DnsName::new(message, question.name).eq_ignore_case(&name) {
+                return Err(Error::Malformed)
+            }
+
+            match packet.rcode() {
+                DnsRcode::NoError => (),
+                DnsRcode::NXDomain => {
+                    net_debug!("{}: {} does not exist", handle, name);
+                    if let Some(ref mut cache) = self.cache {
+                        let answer_count = packet.answer_record_count();
+                        if let Some(ttl) = negative_ttl(&packet, records, answer_count) {
+                            let _ = cache.insert_encoded(query_name, query.query_type,
+                                                         [None; MAX_ADDRESS_COUNT], ttl,
+                                                         pending.sent_at);
+                        }
+                    }
+                    query.state = State::Failed;
+                    #[cfg(feature = "async")]
+                    self.rx_waker.wake();
+                    return Ok(())
+                }
+                rcode => {
+                    // Try the next server right away.
+                    net_debug!("{}: query for {} failed with {:?}, retrying",
+                               handle, name, rcode);
+                    pending.retransmit_at = 0;
+                    query.state = State::Pending(pending);
+                    return Ok(())
+                }
+            }
+
+            let mut addresses = [None; MAX_ADDRESS_COUNT];
+            let mut address_count = 0;
+            // The answer is cached for as long as every record it was derived from.
+            let mut ttl = u32::max_value();
+            for _ in 0..packet.answer_record_count() {
+                let (next_records, record) = DnsRecord::parse(records)?;
+                records = next_records;
+                if !DnsName::new(message, record.name).eq_ignore_case(&name) { continue }
+                ttl = cmp::min(ttl, record.ttl);
+
+                let address = match (query.query_type, record.data) {
+                    #[cfg(feature = "proto-ipv4")]
+                    (DnsType::A, DnsRecordData::A(address)) =>
+                        IpAddress::Ipv4(address),
+                    #[cfg(feature = "proto-ipv6")]
+                    (DnsType::Aaaa, DnsRecordData::Aaaa(address)) =>
+                        IpAddress::Ipv6(address),
+                    (_, DnsRecordData::Cname(canonical_name)) => {
+                        name = DnsName::new(message, canonical_name);
+                        continue
+                    }
+                    _ => continue
+                };
+                if address_count < MAX_ADDRESS_COUNT {
+                    addresses[address_count] = Some(address);
+                    address_count += 1;
+                }
+            }
+
+            let ttl = if address_count == 0 {
+                net_debug!("{}: {} has no addresses", handle, name);
+                query.state = State::Failed;
+                negative_ttl(&packet, records, 0)
+            } else {
+                net_trace!("{}: {} resolved to {} addresses", handle, name, address_count);
+                query.state = State::Completed(addresses);
+                Some(ttl)
+            };
+            if let (Some(cache), Some(ttl)) = (self.cache.as_mut(), ttl) {
+                let _ = cache.insert_encoded(query_name, query.query_type, addresses, ttl,
+                                             pending.sent_at);
+            }
+            #[cfg(feature = "async")]
+            self.rx_waker.wake();
+            return Ok(())
+        }
+
+        Err(Error::Dropped)
+    }
+
+    pub(crate) fn dispatch<F>(&mut self, timestamp: u64, emit: F) -> Result<()>
+            where F: FnOnce((IpRepr, UdpRepr)) -> Result<()> {
+        let handle = self.meta.handle;
+
+        for slot in self.queries.iter_mut() {
+            let query = match *slot {
+                Some(ref mut query) => query,
+                None => continue
+            };
+            let mut pending = match query.state {
+                State::Pending(pending) => pending,
+                _ => continue
+            };
+            let name = &query.name[..query.name_len];
+
+            if let (None, Some(cache)) = (pending.timeout_at, self.cache.as_mut()) {
+                let state = match cache.lookup_encoded(name, query.query_type, timestamp) {
+                    Ok(addresses) => State::Completed(addresses),
+                    Err(Error::Unaddressable) => State::Failed,
+                    Err(_) => State::Pending(pending)
+                };
+                if state != State::Pending(pending) {
+                    net_trace!("{}: answered query for {} from the cache",
+                               handle, DnsName::new(name, name));
+                    query.state = state;
+                    #[cfg(feature = "async")]
+                    self.rx_waker.wake();
+                    continue
+                }
+            }
+
+            if pending.timeout_at.map_or(false, |timeout_at| timestamp >= timeout_at) {
+                net_debug!("{}: query for {} timed out", handle, DnsName::new(name, name));
+                query.state = State::Failed;
+                #[cfg(feature = "async")]
+                self.rx_waker.wake();
+                continue
+            }
+            if timestamp < pending.retransmit_at { continue }
+
+            let server = match nth_server(&self.servers, pending.server_index) {
+                Some(server) => server,
+                None => {
+                    net_debug!("{}: no server to query for {}", handle, DnsName::new(name, name));
+                    query.state = State::Failed;
+                    #[cfg(feature = "async")]
+                    self.rx_waker.wake();
+                    continue
+                }
+            };
+
+            if pending.timeout_at.is_none() {
+                pending.transaction_id = random_u32(&mut self.rand_state, timestamp) as u16;
+                pending.port = 49152 + (random_u32(&mut self.rand_state, timestamp) % 16384) as u16;
+            }
+
+            let mut buffer = [0; 12 + MAX_NAME_LEN + 4];
+            let question = DnsQuestion { name: name, type_: query.query_type };
+            let length = 12 + question.buffer_len();
+            {
+                let mut packet = DnsPacket::new(&mut buffer[..length]);
+                packet.set_transaction_id(pending.transaction_id);
+                packet.clear_flags();
+                packet.set_opcode(DnsOpcode::Query);
+                packet.set_recursion_desired(true);
+                packet.set_question_count(1);
+                packet.set_answer_record_count(0);
+                packet.set_authority_record_count(0);
+                packet.set_additional_record_count(0);
+                question.emit(packet.payload_mut());
+            }
+
+            let repr = UdpRepr {
+                src_port: pending.port,
+                dst_port: DNS_SERVER_PORT,
+                payload:  &buffer[..length]
+            };
+            let ip_repr = IpRepr::Unspecified {
+                src_addr:    server.to_unspecified(),
+                dst_addr:    server,
+                protocol:    IpProtocol::Udp,
+                payload_len: repr.buffer_len(),
+                hop_limit:   64,
+                ecn:         0,
+                dscp:        0,
+                flow_label:  0,
+            };
+            emit((ip_repr, repr))?;
+            net_trace!("{}: sent query for {} to {}", handle, DnsName::new(name, name), server);
+
+            pending.server_index += 1;
+            pending.sent_at = timestamp;
+            pending.timeout_at = pending.timeout_at.or(Some(timestamp + QUERY_TIMEOUT));
+            pending.retransmit_at = timestamp + pending.retransmit_delay;
+            pending.retransmit_delay = cmp::min(pending.retransmit_delay * 2,
+                                                MAX_RETRANSMIT_DELAY);
+            query.state = State::Pending(pending);
+            #[cfg(feature = "async")]
+            self.tx_waker.wake();
+            return Ok(())
+        }
+
+        Err(Error::Exhausted)
+    }
+
+    pub(crate) fn poll_at(&self) -> Option<u64> {
+        self.queries.iter().filter_map(|slot| {
+            match *slot {
+                Some(DnsQuery { state: State::Pending(ref pending), .. }) => {
+                    match pending.timeout_at {
+                        Some(timeout_at) => Some(cmp::min(pending.retransmit_at, timeout_at)),
+                        None => Some(pending.retransmit_at)
+                    }
+                }
+                _ => None
+            }
+        }).min()
+    }
+}
+
+/// Return the server to send the `index`th query to, going around the servers in turn.
+fn nth_server(servers: &[IpAddress], index: usize) -> Option<IpAddress> {
+    let count = servers.iter().filter(|server| !server.is_unspecified()).count();
+    if count == 0 { return None }
+    servers.iter().filter(|server| !server.is_unspecified()).nth(index % count).cloned()
+}
+
+/// Return the time a negative answer may be cached for, in seconds, i.e. the smaller of
+/// the TTL and the MINIMUM field of the SOA record in its authority section, if there is one.
+/// `records` starts `skip_count` records before the authority section.
+fn negative_ttl(packet: &DnsPacket<&[u8]>, mut records: &[u8], skip_count: u16) -> Option<u32> {
+    for _ in 0..skip_count {
+        records = DnsRecord::parse(records).ok()?.0;
+    }
+    for _ in 0..packet.authority_record_count() {
+        let (next_records, record) = DnsRecord::parse(records).ok()?;
+        records = next_records;
+        match record.data {
+            // MINIMUM is the last field of the SOA record.
+            DnsRecordData::Other(DnsType::Soa, data) if data.len() >= 4 => {
+                let minimum = NetworkEndian::read_u32(&data[data.len() - 4..]);
+                return Some(cmp::min(record.ttl, minimum))
+            }
+            _ => ()
+        }
+    }
+    None
+}
+
+fn random_u32(state: &mut u32, timestamp: u64) -> u32 {
+    // xorshift32, perturbed by the current time.
+    let mut x = *state ^ (timestamp as u32);
+    if x == 0 { x = 0x1b873593 }
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    *state = x;
+    x
+}
+
+impl<'a, 'b> Into<Socket<'a, 'b>> for DnsSocket<'a> {
+    fn into(self) -> Socket<'a, 'b> {
+        Socket::Dns(self)
+    }
+}
+
+#[cfg(all(test, feature = "proto-ipv4"))]
+mod test {
+    use std::vec::Vec;
+    use wire::Ipv4Address;
+    use wire::dns::CLASS_IN;
+    use super::*;
+
+    const SERVER_1: IpAddress = IpAddress::Ipv4(Ipv4Address([192, 168, 1, 1]));
+    const SERVER_2: IpAddress = IpAddress::Ipv4(Ipv4Address([192, 168, 1, 2]));
+    const ADDRESS: Ipv4Address = Ipv4Address([93, 184, 216, 34]);
+
+    fn socket(servers: Vec<IpAddress>) -> DnsSocket<'static> {
+        DnsSocket::new(servers, vec![None; 2])
+    }
+
+    fn sent(socket: &mut DnsSocket, timestamp: u64) -> Result<(IpRepr, u16, Vec<u8>)> {
+        let mut result = None;
+        socket.dispatch(timestamp, |(ip_repr, udp_repr)| {
+            assert_eq!(udp_repr.dst_port, DNS_SERVER_PORT);
+            result = Some((ip_repr, udp_repr.src_port, udp_repr.payload.to_vec()));
+            Ok(())
+        })?;
+        Ok(result.unwrap())
+    }
+
+    /// Build a response to a query, with the given response code and answer records.
+    fn response(query: &[u8], rcode: DnsRcode, answers: &[DnsRecord]) -> Vec<u8> {
+        let mut bytes = query.to_vec();
+        for answer in answers {
+            let start = bytes.len();
+            bytes.resize(start + answer.buffer_len(), 0);
+            answer.emit(&mut bytes[start..]);
+        }
+        let mut packet = DnsPacket::new(&mut bytes[..]);
+        packet.set_response(true);
+        packet.set_recursion_available(true);
+        packet.set_rcode(rcode);
+        packet.set_answer_record_count(answers.len() as u16);
+        bytes
+    }
+
+    fn receive(socket: &mut DnsSocket, server: IpAddress, port: u16,
+               payload: &[u8]) -> Result<()> {
+        let repr = UdpRepr {
+            src_port: DNS_SERVER_PORT,
+            dst_port: port,
+            payload:  payload
+        };
+        let ip_repr = IpRepr::Unspecified {
+            src_addr:    server,
+            dst_addr:    server.to_unspecified(),
+            protocol:    IpProtocol::Udp,
+            payload_len: repr.buffer_len(),
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0,
+            flow_label:  0
+        };
+        if !socket.accepts(&ip_repr, &repr) { return Err(Error::Dropped) }
+        socket.process(&ip_repr, &repr)
+    }
+
+    #[test]
+    fn test_query() {
+        let mut socket = socket(vec![SERVER_1]);
+        let handle = socket.query("example.com", DnsType::A).unwrap();
+        assert_eq!(socket.poll_at(), Some(0));
+
+        let (ip_repr, port, payload) = sent(&mut socket, 0).unwrap();
+        assert_eq!(ip_repr.dst_addr(), SERVER_1);
+        assert!(port >= 49152);
+        let packet = DnsPacket::new_checked(&payload[..]).unwrap();
+        assert_eq!(packet.is_response(), false);
+        assert_eq!(packet.recursion_desired(), true);
+        assert_eq!(packet.question_count(), 1);
+        let (_, question) = DnsQuestion::parse(packet.payload()).unwrap();
+        assert_eq!(question.type_, DnsType::A);
+        assert_eq!(question.name, &b"\x07example\x03com\x00"[..]);
+        assert_eq!(socket.query_result(handle), Err(Error::Exhausted));
+        assert_eq!(sent(&mut socket, 0), Err(Error::Exhausted));
+        assert_eq!(socket.poll_at(), Some(RETRANSMIT_DELAY));
+
+        let answer = DnsRecord {
+            name: question.name,
+            class: CLASS_IN,
+            ttl: 60,
+            data: DnsRecordData::A(ADDRESS),
+        };
+        let bytes = response(&payload, DnsRcode::NoError, &[answer]);
+        assert_eq!(receive(&mut socket, SERVER_1, port, &bytes), Ok(()));
+        assert_eq!(socket.query_result(handle),
+                   Ok([Some(IpAddress::Ipv4(ADDRESS)), None, None, None]));
+        assert_eq!(socket.poll_at(), None);
+    }
+
+    #[test]
+    fn test_cname() {
+        let mut socket = socket(vec![SERVER_1]);
+        let handle = socket.query("www.example.com", DnsType::A).unwrap();
+        let (_, port, payload) = sent(&mut socket, 0).unwrap();
+
+        let mut name = [0; MAX_NAME_LEN];
+        let name_len = encode_name("example.com", &mut name).unwrap();
+        let cname = DnsRecord {
+            name: &[0xc0, 0x0c],
+            class: CLASS_IN,
+            ttl: 60,
+            data: DnsRecordData::Cname(&name[..name_len]),
+        };
+        let answer = DnsRecord {
+            name: &name[..name_len],
+            class: CLASS_IN,
+            ttl: 60,
+            data: DnsRecordData::A(ADDRESS),
+        };
+        let bytes = response(&payload, DnsRcode::NoError, &[cname, answer]);
+        assert_eq!(receive(&mut socket, SERVER_1, port, &bytes), Ok(()));
+        assert_eq!(socket.query_result(handle),
+                   Ok([Some(IpAddress::Ipv4(ADDRESS)), None, None, None]));
+    }
+
+    #[test]
+    fn test_nxdomain() {
+        let mut socket = socket(vec![SERVER_1]);
+        let handle = socket.query("example.com", DnsType::A).unwrap();
+        let (_, port, payload) = sent(&mut socket, 0).unwrap();
+
+        let bytes = response(&payload, DnsRcode::NXDomain, &[]);
+        assert_eq!(receive(&mut socket, SERVER_1, port, &bytes), Ok(()));
+        assert_eq!(socket.query_result(handle), Err(Error::Unaddressable));
+        // The query was freed.
+        assert_eq!(socket.query("example.com", DnsType::A), Ok(handle));
+    }
+
+    #[test]
+    fn test_retransmit_and_rotate_servers() {
+        let mut socket = socket(vec![SERVER_1, SERVER_2]);
+        let handle = socket.query("example.com", DnsType::A).unwrap();
+
+        let (ip_repr, port, payload) = sent(&mut socket, 0).unwrap();
+        assert_eq!(ip_repr.dst_addr(), SERVER_1);
+        let (ip_repr, _, _) = sent(&mut socket, 1_000).unwrap();
+        assert_eq!(ip_repr.dst_addr(), SERVER_2);
+        assert_eq!(socket.poll_at(), Some(3_000));
+
+        // A server failure moves on to the next server immediately.
+        let bytes = response(&payload, DnsRcode::ServFail, &[]);
+        assert_eq!(receive(&mut socket, SERVER_2, port, &bytes), Ok(()));
+        let (ip_repr, retransmit_port, _) = sent(&mut socket, 1_500).unwrap();
+        assert_eq!(ip_repr.dst_addr(), SERVER_1);
+        assert_eq!(retransmit_port, port);
+        assert_eq!(socket.query_result(handle), Err(Error::Exhausted));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut socket = socket(vec![SERVER_1]);
+        let handle = socket.query("example.com", DnsType::A).unwrap();
+
+        let mut timestamp = 0;
+        while let Ok(_) = sent(&mut socket, timestamp) {
+            timestamp = socket.poll_at().unwrap();
+        }
+        assert_eq!(timestamp, QUERY_TIMEOUT);
+        assert_eq!(socket.query_result(handle), Err(Error::Unaddressable));
+    }
+
+    #[test]
+    fn test_ignore_unrelated() {
+        let mut socket = socket(vec![SERVER_1]);
+        let handle = socket.query("example.com", DnsType::A).unwrap();
+        let (_, port, payload) = sent(&mut socket, 0).unwrap();
+        let bytes = response(&payload, DnsRcode::NXDomain, &[]);
+
+        // Not from a configured server.
+        assert_eq!(receive(&mut socket, SERVER_2, port, &bytes), Err(Error::Dropped));
+        // Not to the port of the query.
+        assert_eq!(receive(&mut socket, SERVER_1, port ^ 1, &bytes), Err(Error::Dropped));
+        // Not with the transaction ID of the query.
+        let mut other_bytes = bytes.clone();
+        other_bytes[0] ^= 1;
+        assert_eq!(receive(&mut socket, SERVER_1, port, &other_bytes), Err(Error::Dropped));
+        assert_eq!(socket.query_result(handle), Err(Error::Exhausted));
+    }
+
+    #[test]
+    fn test_query_errors() {
+        let mut socket = socket(vec![SERVER_1]);
+        assert_eq!(socket.query("example..com", DnsType::A), Err(Error::Illegal));
+        assert_eq!(socket.query(".", DnsType::A), Err(Error::Illegal));
+        assert_eq!(socket.query("example.com", DnsType::Txt), Err(Error::Illegal));
+
+        let mut socket = DnsSocket::new(vec![IpAddress::v4(0, 0, 0, 0)], vec![None; 1]);
+        assert_eq!(socket.query("example.com", DnsType::A), Err(Error::Unaddressable));
+
+        let mut servers = [SERVER_1];
+        let mut queries = [None];
+        let mut socket = DnsSocket::new(&mut servers[..], &mut queries[..]);
+        assert!(socket.query("example.com", DnsType::A).is_ok());
+        assert_eq!(socket.query("example.com", DnsType::A), Err(Error::Exhausted));
+    }
+
+    #[test]
+    fn test_cache() {
+        let mut socket = socket(vec![SERVER_1]);
+        socket.set_cache(Some(DnsCache::new(vec![None; 2])));
+        let handle = socket.query("example.com", DnsType::A).unwrap();
+        let (_, port, payload) = sent(&mut socket, 1_000).unwrap();
+        let (_, question) = DnsQuestion::parse(&payload[12..]).unwrap();
+
+        let answer = DnsRecord {
+            name: question.name,
+            class: CLASS_IN,
+            ttl: 60,
+            data: DnsRecordData::A(ADDRESS),
+        };
+        let bytes = response(&payload, DnsRcode::NoError, &[answer]);
+        assert_eq!(receive(&mut socket, SERVER_1, port, &bytes), Ok(()));
+        assert!(socket.query_result(handle).is_ok());
+
+        // The answer is cached from the time the query was sent.
+        let handle = socket.query("example.com", DnsType::A).unwrap();
+        assert_eq!(sent(&mut socket, 60_999), Err(Error::Exhausted));
+        assert_eq!(socket.query_result(handle),
+                   Ok([Some(IpAddress::Ipv4(ADDRESS)), None, None, None]));
+        socket.query("example.com", DnsType::A).unwrap();
+        assert!(sent(&mut socket, 61_000).is_ok());
+    }
+
+    #[test]
+    fn test_cache_negative() {
+        let mut socket = socket(vec![SERVER_1]);
+        socket.set_cache(Some(DnsCache::new(vec![None; 2])));
+        let handle = socket.query("example.com", DnsType::A).unwrap();
+        let (_, port, payload) = sent(&mut socket, 0).unwrap();
+
+        // Without an SOA record, the answer is not cached.
+        let bytes = response(&payload, DnsRcode::NXDomain, &[]);
+        assert_eq!(receive(&mut socket, SERVER_1, port, &bytes), Ok(()));
+        assert_eq!(socket.query_result(handle), Err(Error::Unaddressable));
+        let handle = socket.query("example.com", DnsType::A).unwrap();
+        let (_, port, payload) = sent(&mut socket, 0).unwrap();
+
+        // The answer is cached for the smaller of the TTL and the MINIMUM of the SOA record.
+        let mut soa_data = b"\x02ns\xc0\x0c\x0ahostmaster\xc0\x0c".to_vec();
+        soa_data.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0, 30]);
+        let soa = DnsRecord {
+            name: &[0xc0, 0x0c],
+            class: CLASS_IN,
+            ttl: 3_600,
+            data: DnsRecordData::Other(DnsType::Soa, &soa_data),
+        };
+        let mut bytes = response(&payload, DnsRcode::NXDomain, &[soa]);
+        {
+            let mut packet = DnsPacket::new(&mut bytes[..]);
+            packet.set_answer_record_count(0);
+            packet.set_authority_record_count(1);
+        }
+        assert_eq!(receive(&mut socket, SERVER_1, port, &bytes), Ok(()));
+        assert_eq!(socket.query_result(handle), Err(Error::Unaddressable));
+
+        let handle = socket.query("example.com", DnsType::A).unwrap();
+        assert_eq!(sent(&mut socket, 29_999), Err(Error::Exhausted));
+        assert_eq!(socket.query_result(handle), Err(Error::Unaddressable));
+        socket.query("example.com", DnsType::A).unwrap();
+        assert!(sent(&mut socket, 30_000).is_ok());
+    }
+
+    #[test]
+    fn test_cancel_query() {
+        let mut socket = socket(vec![SERVER_1]);
+        let handle = socket.query("example.com", DnsType::A).unwrap();
+        socket.cancel_query(handle);
+        assert_eq!(sent(&mut socket, 0), Err(Error::Exhausted));
+        assert_eq!(socket.poll_at(), None);
+    }
+}
@@ -0,0 +1,306 @@
+use core::{cmp, fmt};
+use managed::ManagedSlice;
+
+use {Error, Result};
+use wire::{IpAddress, DnsName, DnsType};
+use wire::dns::{encode_name, MAX_NAME_LEN};
+
+/// The maximum number of addresses cached for a name.
+pub const MAX_ADDRESS_COUNT: usize = 4;
+
+/// The longest time answers are cached for by default, in seconds.
+const DEFAULT_MAX_TTL: u32 = 86_400;
+/// The longest time negative answers are cached for by default, in seconds.
+const DEFAULT_MAX_NEGATIVE_TTL: u32 = 300;
+
+/// An entry of a DNS cache.
+///
+/// The contents are private; this type only exists so that storage for entries
+/// can be allocated by the application.
+#[derive(Clone)]
+pub struct DnsCacheEntry {
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    query_type: DnsType,
+    /// The addresses of the name, or `None` if it has none.
+    addresses: Option<[Option<IpAddress>; MAX_ADDRESS_COUNT]>,
+    expires_at: u64,
+    used_at: u64,
+}
+
+impl DnsCacheEntry {
+    fn matches(&self, name: &[u8], query_type: DnsType) -> bool {
+        let own_name = &self.name[..self.name_len];
+        self.query_type == query_type &&
+            DnsName::new(own_name, own_name).eq_ignore_case(&DnsName::new(name, name))
+    }
+}
+
+impl fmt::Debug for DnsCacheEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = &self.name[..self.name_len];
+        f.debug_struct("DnsCacheEntry")
+         .field("name", &DnsName::new(name, name))
+         .field("query_type", &self.query_type)
+         .field("addresses", &self.addresses)
+         .field("expires_at", &self.expires_at)
+         .finish()
+    }
+}
+
+/// A cache of the answers to DNS queries.
+///
+/// The cache maps a name and a query type to the addresses of the name, or to the fact
+/// that it has none, until the TTL of the answer expires. It holds as many entries as
+/// its storage has slots; once it is full, the least recently used entry is evicted.
+///
+/// A cache can be given to a [DnsSocket] or an [MdnsResponder], so that repeated queries
+/// for the same name are answered without sending anything.
+///
+/// [DnsSocket]: struct.DnsSocket.html
+/// [MdnsResponder]: ../mdns/struct.MdnsResponder.html
+#[derive(Debug)]
+pub struct DnsCache<'a> {
+    entries: ManagedSlice<'a, Option<DnsCacheEntry>>,
+    max_ttl: u32,
+    max_negative_ttl: u32,
+}
+
+impl<'a> DnsCache<'a> {
+    /// Create a DNS cache with the given storage for entries.
+    pub fn new<T>(storage: T) -> DnsCache<'a>
+            where T: Into<ManagedSlice<'a, Option<DnsCacheEntry>>> {
+        DnsCache {
+            entries:          storage.into(),
+            max_ttl:          DEFAULT_MAX_TTL,
+            max_negative_ttl: DEFAULT_MAX_NEGATIVE_TTL,
+        }
+    }
+
+    /// Return the longest time answers are cached for, in seconds.
+    pub fn max_ttl(&self) -> u32 {
+        self.max_ttl
+    }
+
+    /// Set the longest time answers are cached for, in seconds, regardless of their TTL.
+    ///
+    /// The default is one day.
+    pub fn set_max_ttl(&mut self, max_ttl: u32) {
+        self.max_ttl = max_ttl
+    }
+
+    /// Return the longest time negative answers are cached for, in seconds.
+    pub fn max_negative_ttl(&self) -> u32 {
+        self.max_negative_ttl
+    }
+
+    /// Set the longest time negative answers, i.e. answers without any addresses,
+    /// are cached for, in seconds, regardless of their TTL.
+    ///
+    /// The default is five minutes.
+    pub fn set_max_negative_ttl(&mut self, max_negative_ttl: u32) {
+        self.max_negative_ttl = max_negative_ttl
+    }
+
+    /// Look up the addresses of `name` of the given query type.
+    ///
+    /// This function returns `Err(Error::Unaddressable)` if the name is cached as having
+    /// no addresses, `Err(Error::Exhausted)` if it is not cached, or its entry expired,
+    /// and `Err(Error::Illegal)` if the name is not valid.
+    pub fn lookup(&mut self, name: &str, query_type: DnsType, now: u64) ->
+                 Result<[Option<IpAddress>; MAX_ADDRESS_COUNT]> {
+        let mut buffer = [0; MAX_NAME_LEN];
+        let name_len = encode_name(name, &mut buffer).map_err(|_| Error::Illegal)?;
+        self.lookup_encoded(&buffer[..name_len], query_type, now)
+    }
+
+    /// Cache the addresses of `name` of the given query type for `ttl` seconds,
+    /// replacing any entry for the same name and type. An empty list of addresses
+    /// caches the name as having none, and a TTL of zero removes the entry.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the name is not valid,
+    /// and `Err(Error::Exhausted)` if the cache has no storage.
+    pub fn insert(&mut self, name: &str, query_type: DnsType, addresses: &[IpAddress],
+                  ttl: u32, now: u64) -> Result<()> {
+        let mut buffer = [0; MAX_NAME_LEN];
+        let name_len = encode_name(name, &mut buffer).map_err(|_| Error::Illegal)?;
+        let mut entry_addresses = [None; MAX_ADDRESS_COUNT];
+        for (slot, address) in entry_addresses.iter_mut().zip(addresses) {
+            *slot = Some(*address)
+        }
+        self.insert_encoded(&buffer[..name_len], query_type, entry_addresses, ttl, now)
+    }
+
+    /// Remove the entry for `name` of the given query type, if there is one.
+    pub fn remove(&mut self, name: &str, query_type: DnsType) {
+        let mut buffer = [0; MAX_NAME_LEN];
+        if let Ok(name_len) = encode_name(name, &mut buffer) {
+            self.remove_encoded(&buffer[..name_len], query_type)
+        }
+    }
+
+    /// Remove every entry.
+    pub fn flush(&mut self) {
+        for slot in self.entries.iter_mut() {
+            *slot = None
+        }
+    }
+
+    pub(crate) fn lookup_encoded(&mut self, name: &[u8], query_type: DnsType, now: u64) ->
+                                Result<[Option<IpAddress>; MAX_ADDRESS_COUNT]> {
+        for slot in self.entries.iter_mut() {
+            let expired = match *slot {
+                Some(ref entry) if entry.matches(name, query_type) => now >= entry.expires_at,
+                _ => continue
+            };
+            if expired {
+                *slot = None;
+                break
+            }
+            if let Some(ref mut entry) = *slot {
+                entry.used_at = now;
+                return entry.addresses.ok_or(Error::Unaddressable)
+            }
+        }
+        Err(Error::Exhausted)
+    }
+
+    pub(crate) fn insert_encoded(&mut self, name: &[u8], query_type: DnsType,
+                                 addresses: [Option<IpAddress>; MAX_ADDRESS_COUNT],
+                                 ttl: u32, now: u64) -> Result<()> {
+        let addresses = if addresses[0].is_some() { Some(addresses) } else { None };
+        let ttl = if addresses.is_none() {
+            cmp::min(ttl, self.max_negative_ttl)
+        } else {
+            cmp::min(ttl, self.max_ttl)
+        };
+        if ttl == 0 || name.len() > MAX_NAME_LEN {
+            self.remove_encoded(name, query_type);
+            return Ok(())
+        }
+
+        let mut entry = DnsCacheEntry {
+            name: [0; MAX_NAME_LEN],
+            name_len: name.len(),
+            query_type: query_type,
+            addresses: addresses,
+            expires_at: now + ttl as u64 * 1000,
+            used_at: now,
+        };
+        entry.name[..name.len()].copy_from_slice(name);
+
+        // Replace the entry for the same name, a free or expired one,
+        // or the least recently used one, in this order.
+        let index = {
+            let rank = |slot: &Option<DnsCacheEntry>| {
+                match *slot {
+                    Some(ref other) if other.matches(name, query_type) => (0, 0),
+                    None => (1, 0),
+                    Some(ref other) if now >= other.expires_at => (1, 0),
+                    Some(ref other) => (2, other.used_at),
+                }
+            };
+            match self.entries.iter().enumerate().min_by_key(|&(_, slot)| rank(slot)) {
+                Some((index, _)) => index,
+                None => return Err(Error::Exhausted)
+            }
+        };
+        self.entries[index] = Some(entry);
+        Ok(())
+    }
+
+    fn remove_encoded(&mut self, name: &[u8], query_type: DnsType) {
+        for slot in self.entries.iter_mut() {
+            let matches = match *slot {
+                Some(ref entry) => entry.matches(name, query_type),
+                None => false
+            };
+            if matches { *slot = None }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "proto-ipv4"))]
+mod test {
+    use std::vec::Vec;
+    use wire::Ipv4Address;
+    use super::*;
+
+    const ADDRESS_1: IpAddress = IpAddress::Ipv4(Ipv4Address([192, 168, 1, 1]));
+    const ADDRESS_2: IpAddress = IpAddress::Ipv4(Ipv4Address([192, 168, 1, 2]));
+
+    fn cache(size: usize) -> DnsCache<'static> {
+        DnsCache::new((0..size).map(|_| None).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_lookup() {
+        let mut cache = cache(2);
+        assert_eq!(cache.lookup("example.com", DnsType::A, 0), Err(Error::Exhausted));
+
+        assert_eq!(cache.insert("example.com", DnsType::A, &[ADDRESS_1, ADDRESS_2], 60, 0),
+                   Ok(()));
+        assert_eq!(cache.lookup("EXAMPLE.com", DnsType::A, 59_999),
+                   Ok([Some(ADDRESS_1), Some(ADDRESS_2), None, None]));
+        assert_eq!(cache.lookup("example.com", DnsType::Aaaa, 0), Err(Error::Exhausted));
+        // The entry expires with its TTL.
+        assert_eq!(cache.lookup("example.com", DnsType::A, 60_000), Err(Error::Exhausted));
+    }
+
+    #[test]
+    fn test_negative() {
+        let mut cache = cache(2);
+        assert_eq!(cache.insert("missing.example.com", DnsType::A, &[], 3_600, 0), Ok(()));
+        assert_eq!(cache.lookup("missing.example.com", DnsType::A, 0),
+                   Err(Error::Unaddressable));
+        // Negative answers are only cached for five minutes.
+        assert_eq!(cache.lookup("missing.example.com", DnsType::A, 300_000),
+                   Err(Error::Exhausted));
+    }
+
+    #[test]
+    fn test_max_ttl() {
+        let mut cache = cache(1);
+        cache.set_max_ttl(10);
+        assert_eq!(cache.insert("example.com", DnsType::A, &[ADDRESS_1], 60, 0), Ok(()));
+        assert_eq!(cache.lookup("example.com", DnsType::A, 10_000), Err(Error::Exhausted));
+    }
+
+    #[test]
+    fn test_replace_and_remove() {
+        let mut cache = cache(2);
+        assert_eq!(cache.insert("example.com", DnsType::A, &[ADDRESS_1], 60, 0), Ok(()));
+        assert_eq!(cache.insert("example.com", DnsType::A, &[ADDRESS_2], 60, 0), Ok(()));
+        assert_eq!(cache.lookup("example.com", DnsType::A, 0),
+                   Ok([Some(ADDRESS_2), None, None, None]));
+        assert_eq!(cache.entries.iter().filter(|slot| slot.is_some()).count(), 1);
+
+        // A TTL of zero removes the entry.
+        assert_eq!(cache.insert("example.com", DnsType::A, &[ADDRESS_1], 0, 0), Ok(()));
+        assert_eq!(cache.lookup("example.com", DnsType::A, 0), Err(Error::Exhausted));
+
+        assert_eq!(cache.insert("example.com", DnsType::A, &[ADDRESS_1], 60, 0), Ok(()));
+        cache.remove("example.com", DnsType::A);
+        assert_eq!(cache.lookup("example.com", DnsType::A, 0), Err(Error::Exhausted));
+    }
+
+    #[test]
+    fn test_evict_least_recently_used() {
+        let mut cache = cache(2);
+        assert_eq!(cache.insert("a.example.com", DnsType::A, &[ADDRESS_1], 60, 0), Ok(()));
+        assert_eq!(cache.insert("b.example.com", DnsType::A, &[ADDRESS_2], 60, 1), Ok(()));
+        assert!(cache.lookup("a.example.com", DnsType::A, 2).is_ok());
+
+        assert_eq!(cache.insert("c.example.com", DnsType::A, &[ADDRESS_1], 60, 3), Ok(()));
+        assert!(cache.lookup("a.example.com", DnsType::A, 4).is_ok());
+        assert_eq!(cache.lookup("b.example.com", DnsType::A, 4), Err(Error::Exhausted));
+        assert!(cache.lookup("c.example.com", DnsType::A, 4).is_ok());
+
+        cache.flush();
+        assert_eq!(cache.lookup("a.example.com", DnsType::A, 4), Err(Error::Exhausted));
+
+        let mut cache = DnsCache::new(&mut [][..]);
+        assert_eq!(cache.insert("example.com", DnsType::A, &[ADDRESS_1], 60, 0),
+                   Err(Error::Exhausted));
+    }
+}
@@ -0,0 +1,75 @@
+use core::fmt;
+
+#[cfg(feature = "proto-ipv4")]
+use wire::Icmpv4DstUnreachable;
+
+/// An error reported by a remote host or router for the packets sent by a socket,
+/// using an ICMP error message.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Error {
+    /// The network of the remote host is unreachable.
+    NetUnreachable,
+    /// The remote host is unreachable.
+    HostUnreachable,
+    /// The remote host does not support the transport protocol.
+    ProtoUnreachable,
+    /// No application on the remote host is listening on the remote port.
+    PortUnreachable,
+    /// Communication with the remote host is administratively prohibited.
+    Prohibited,
+    /// The hop limit of the packets was exceeded in transit.
+    TimeExceeded,
+}
+
+impl Error {
+    /// Return the error corresponding to an ICMPv4 destination unreachable message, if any.
+    ///
+    /// Fragmentation required messages concern the path MTU rather than the reachability
+    /// of the remote host, and have no corresponding error.
+    #[cfg(feature = "proto-ipv4")]
+    pub(crate) fn from_icmpv4(reason: Icmpv4DstUnreachable) -> Option<Error> {
+        match reason {
+            Icmpv4DstUnreachable::NetUnreachable |
+            Icmpv4DstUnreachable::DstNetUnknown |
+            Icmpv4DstUnreachable::NetUnreachToS => Some(Error::NetUnreachable),
+            Icmpv4DstUnreachable::ProtoUnreachable => Some(Error::ProtoUnreachable),
+            Icmpv4DstUnreachable::PortUnreachable => Some(Error::PortUnreachable),
+            Icmpv4DstUnreachable::NetProhibited |
+            Icmpv4DstUnreachable::HostProhibited |
+            Icmpv4DstUnreachable::CommProhibited |
+            Icmpv4DstUnreachable::HostPrecedViol |
+            Icmpv4DstUnreachable::PrecedCutoff => Some(Error::Prohibited),
+            Icmpv4DstUnreachable::FragRequired => None,
+            _ => Some(Error::HostUnreachable)
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Error::NetUnreachable   => write!(f, "network unreachable"),
+            &Error::HostUnreachable  => write!(f, "host unreachable"),
+            &Error::ProtoUnreachable => write!(f, "protocol unreachable"),
+            &Error::PortUnreachable  => write!(f, "port unreachable"),
+            &Error::Prohibited       => write!(f, "communication prohibited"),
+            &Error::TimeExceeded     => write!(f, "time exceeded"),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "proto-ipv4"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_icmpv4() {
+        assert_eq!(Error::from_icmpv4(Icmpv4DstUnreachable::PortUnreachable),
+                   Some(Error::PortUnreachable));
+        assert_eq!(Error::from_icmpv4(Icmpv4DstUnreachable::CommProhibited),
+                   Some(Error::Prohibited));
+        assert_eq!(Error::from_icmpv4(Icmpv4DstUnreachable::DstHostUnknown),
+                   Some(Error::HostUnreachable));
+        assert_eq!(Error::from_icmpv4(Icmpv4DstUnreachable::FragRequired), None);
+    }
+}
@@ -0,0 +1,413 @@
+use byteorder::{ByteOrder, NetworkEndian};
+
+use {Error, Result};
+
+/// The maximum number of instructions in a filter program.
+pub const MAX_PROGRAM_LEN: usize = 4096;
+/// The number of words of scratch memory a filter program can use.
+const MEMORY_LEN: usize = 16;
+
+/// An instruction of a classic BPF program.
+///
+/// Instructions are laid out as `struct sock_filter` is on Linux, so that the programs
+/// output by `tcpdump -dd` can be used as they are. The associated constants can be
+/// combined to form the opcodes, e.g. `Instruction::LD | Instruction::H | Instruction::ABS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Instruction {
+    pub code: u16,
+    /// The number of instructions to skip if a conditional jump is taken.
+    pub jt:   u8,
+    /// The number of instructions to skip if a conditional jump is not taken.
+    pub jf:   u8,
+    pub k:    u32,
+}
+
+#[allow(missing_docs)]
+impl Instruction {
+    // Instruction classes.
+    pub const LD:   u16 = 0x00;
+    pub const LDX:  u16 = 0x01;
+    pub const ST:   u16 = 0x02;
+    pub const STX:  u16 = 0x03;
+    pub const ALU:  u16 = 0x04;
+    pub const JMP:  u16 = 0x05;
+    pub const RET:  u16 = 0x06;
+    pub const MISC: u16 = 0x07;
+
+    // Load sizes.
+    pub const W: u16 = 0x00;
+    pub const H: u16 = 0x08;
+    pub const B: u16 = 0x10;
+
+    // Load modes.
+    pub const IMM: u16 = 0x00;
+    pub const ABS: u16 = 0x20;
+    pub const IND: u16 = 0x40;
+    pub const MEM: u16 = 0x60;
+    pub const LEN: u16 = 0x80;
+    pub const MSH: u16 = 0xa0;
+
+    // Arithmetic operations.
+    pub const ADD: u16 = 0x00;
+    pub const SUB: u16 = 0x10;
+    pub const MUL: u16 = 0x20;
+    pub const DIV: u16 = 0x30;
+    pub const OR:  u16 = 0x40;
+    pub const AND: u16 = 0x50;
+    pub const LSH: u16 = 0x60;
+    pub const RSH: u16 = 0x70;
+    pub const NEG: u16 = 0x80;
+    pub const MOD: u16 = 0x90;
+    pub const XOR: u16 = 0xa0;
+
+    // Jumps.
+    pub const JA:   u16 = 0x00;
+    pub const JEQ:  u16 = 0x10;
+    pub const JGT:  u16 = 0x20;
+    pub const JGE:  u16 = 0x30;
+    pub const JSET: u16 = 0x40;
+
+    // Operand sources.
+    pub const K: u16 = 0x00;
+    pub const X: u16 = 0x08;
+    pub const A: u16 = 0x10;
+
+    // Register transfers.
+    pub const TAX: u16 = 0x00;
+    pub const TXA: u16 = 0x80;
+
+    /// Create an instruction.
+    pub const fn new(code: u16, jt: u8, jf: u8, k: u32) -> Instruction {
+        Instruction { code, jt, jf, k }
+    }
+
+    fn class(&self) -> u16 { self.code & 0x07 }
+    fn size(&self) -> u16 { self.code & 0x18 }
+    fn mode(&self) -> u16 { self.code & 0xe0 }
+    fn op(&self) -> u16 { self.code & 0xf0 }
+    fn src(&self) -> u16 { self.code & 0x08 }
+
+    /// Query whether the instruction is valid at the given index of a program
+    /// of the given length.
+    fn is_valid(&self, index: usize, program_len: usize) -> bool {
+        let in_program = |skip: u32| (index as u64 + 1 + skip as u64) < program_len as u64;
+        let in_memory = self.k < MEMORY_LEN as u32;
+        if self.code > 0xff { return false }
+        match self.class() {
+            Instruction::LD => match (self.mode(), self.size()) {
+                (Instruction::ABS, size) | (Instruction::IND, size) => size != 0x18,
+                (Instruction::IMM, Instruction::W) | (Instruction::LEN, Instruction::W) => true,
+                (Instruction::MEM, Instruction::W) => in_memory,
+                _ => false
+            },
+            Instruction::LDX => match (self.mode(), self.size()) {
+                (Instruction::IMM, Instruction::W) | (Instruction::LEN, Instruction::W) => true,
+                (Instruction::MEM, Instruction::W) => in_memory,
+                (Instruction::MSH, Instruction::B) => true,
+                _ => false
+            },
+            Instruction::ST | Instruction::STX =>
+                self.code & !0x07 == 0 && in_memory,
+            Instruction::ALU => match self.op() {
+                Instruction::DIV | Instruction::MOD =>
+                    self.src() == Instruction::X || self.k != 0,
+                Instruction::NEG => self.src() == Instruction::K,
+                op => op <= Instruction::XOR
+            },
+            Instruction::JMP => match self.op() {
+                Instruction::JA =>
+                    self.src() == Instruction::K && in_program(self.k),
+                Instruction::JEQ | Instruction::JGT | Instruction::JGE | Instruction::JSET =>
+                    in_program(self.jt as u32) && in_program(self.jf as u32),
+                _ => false
+            },
+            Instruction::RET => match self.code & !0x07 {
+                Instruction::K | Instruction::X | Instruction::A => true,
+                _ => false
+            },
+            _ => match self.code & !0x07 {
+                Instruction::TAX | Instruction::TXA => true,
+                _ => false
+            }
+        }
+    }
+}
+
+/// A packet filter, running a classic BPF program.
+///
+/// The program is run over every packet a socket receives; it accepts the packet
+/// if it returns a non-zero value, and rejects it otherwise. Loads out of the bounds
+/// of the packet and divisions by zero reject the packet, as on Linux. The value
+/// the program returns does not truncate the packet.
+///
+/// Jumps only go forward, so every program terminates.
+#[derive(Debug, Clone, Copy)]
+pub struct Filter<'a> {
+    program: &'a [Instruction],
+}
+
+impl<'a> Filter<'a> {
+    /// Create a filter running the given program.
+    ///
+    /// Returns `Err(Error::Illegal)` if the program is empty, longer than
+    /// `MAX_PROGRAM_LEN`, has an unknown instruction, a jump out of the program,
+    /// an access out of the scratch memory, a division by a constant zero,
+    /// or does not end with a return instruction.
+    pub fn new(program: &'a [Instruction]) -> Result<Filter<'a>> {
+        if program.is_empty() || program.len() > MAX_PROGRAM_LEN {
+            return Err(Error::Illegal)
+        }
+        if program[program.len() - 1].class() != Instruction::RET {
+            return Err(Error::Illegal)
+        }
+        for (index, instruction) in program.iter().enumerate() {
+            if !instruction.is_valid(index, program.len()) {
+                return Err(Error::Illegal)
+            }
+        }
+        Ok(Filter { program })
+    }
+
+    /// Return the program of the filter.
+    pub fn program(&self) -> &'a [Instruction] {
+        self.program
+    }
+
+    /// Run the program over a packet made of `header` followed by `payload`,
+    /// and return whether it accepts the packet.
+    pub(crate) fn matches(&self, header: &[u8], payload: &[u8]) -> bool {
+        let packet = Packet { header, payload };
+        let (mut a, mut x) = (0u32, 0u32);
+        let mut memory = [0u32; MEMORY_LEN];
+        let mut pc = 0;
+
+        loop {
+            let insn = self.program[pc];
+            pc += 1;
+            match insn.class() {
+                Instruction::LD => {
+                    a = match insn.mode() {
+                        Instruction::IMM => insn.k,
+                        Instruction::MEM => memory[insn.k as usize],
+                        Instruction::LEN => packet.len() as u32,
+                        Instruction::ABS => match packet.load(insn.k, insn.size()) {
+                            Some(value) => value,
+                            None => return false
+                        },
+                        _ => match packet.load(x.wrapping_add(insn.k), insn.size()) {
+                            Some(value) => value,
+                            None => return false
+                        }
+                    }
+                }
+                Instruction::LDX => {
+                    x = match insn.mode() {
+                        Instruction::IMM => insn.k,
+                        Instruction::MEM => memory[insn.k as usize],
+                        Instruction::LEN => packet.len() as u32,
+                        _ => match packet.load(insn.k, Instruction::B) {
+                            Some(value) => (value & 0xf) << 2,
+                            None => return false
+                        }
+                    }
+                }
+                Instruction::ST => memory[insn.k as usize] = a,
+                Instruction::STX => memory[insn.k as usize] = x,
+                Instruction::ALU => {
+                    let operand = if insn.src() == Instruction::X { x } else { insn.k };
+                    a = match insn.op() {
+                        Instruction::ADD => a.wrapping_add(operand),
+                        Instruction::SUB => a.wrapping_sub(operand),
+                        Instruction::MUL => a.wrapping_mul(operand),
+                        Instruction::DIV => match a.checked_div(operand) {
+                            Some(value) => value,
+                            None => return false
+                        },
+                        Instruction::MOD => match a.checked_rem(operand) {
+                            Some(value) => value,
+                            None => return false
+                        },
+                        Instruction::OR  => a | operand,
+                        Instruction::AND => a & operand,
+                        Instruction::XOR => a ^ operand,
+                        Instruction::LSH => a.checked_shl(operand).unwrap_or(0),
+                        Instruction::RSH => a.checked_shr(operand).unwrap_or(0),
+                        _ => a.wrapping_neg()
+                    }
+                }
+                Instruction::JMP => {
+                    let operand = if insn.src() == Instruction::X { x } else { insn.k };
+                    let taken = match insn.op() {
+                        Instruction::JA => {
+                            pc += insn.k as usize;
+                            continue
+                        }
+                        Instruction::JEQ => a == operand,
+                        Instruction::JGT => a > operand,
+                        Instruction::JGE => a >= operand,
+                        _ => a & operand != 0
+                    };
+                    pc += if taken { insn.jt as usize } else { insn.jf as usize };
+                }
+                Instruction::RET => {
+                    let value = match insn.code & !0x07 {
+                        Instruction::X => x,
+                        Instruction::A => a,
+                        _ => insn.k
+                    };
+                    return value != 0
+                }
+                _ => {
+                    if insn.code & !0x07 == Instruction::TAX { x = a } else { a = x }
+                }
+            }
+        }
+    }
+}
+
+/// A packet split in two parts, e.g. an IP header emitted by the socket and the payload.
+struct Packet<'a> {
+    header:  &'a [u8],
+    payload: &'a [u8],
+}
+
+impl<'a> Packet<'a> {
+    fn len(&self) -> usize {
+        self.header.len() + self.payload.len()
+    }
+
+    fn byte(&self, offset: usize) -> u8 {
+        if offset < self.header.len() {
+            self.header[offset]
+        } else {
+            self.payload[offset - self.header.len()]
+        }
+    }
+
+    /// Load a big-endian value of the given size at the given offset, if it is
+    /// within the packet.
+    fn load(&self, offset: u32, size: u16) -> Option<u32> {
+        let width = match size {
+            Instruction::W => 4,
+            Instruction::H => 2,
+            _ => 1
+        };
+        let offset = offset as usize;
+        if offset.checked_add(width).map_or(true, |end| end > self.len()) {
+            return None
+        }
+        if offset + width <= self.header.len() {
+            return Some(load(&self.header[offset..offset + width]))
+        }
+        if offset >= self.header.len() {
+            let offset = offset - self.header.len();
+            return Some(load(&self.payload[offset..offset + width]))
+        }
+        let mut bytes = [0; 4];
+        for (index, byte) in bytes[..width].iter_mut().enumerate() {
+            *byte = self.byte(offset + index)
+        }
+        Some(load(&bytes[..width]))
+    }
+}
+
+fn load(bytes: &[u8]) -> u32 {
+    match bytes.len() {
+        4 => NetworkEndian::read_u32(bytes),
+        2 => NetworkEndian::read_u16(bytes) as u32,
+        _ => bytes[0] as u32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Accepts IPv4 UDP datagrams sent to port 53 in Ethernet frames.
+    static DNS_PROGRAM: [Instruction; 9] = [
+        Instruction::new(0x28, 0, 0, 12),       // ldh [12]
+        Instruction::new(0x15, 0, 6, 0x0800),   // jeq #0x800, jf ret #0
+        Instruction::new(0x30, 0, 0, 23),       // ldb [23]
+        Instruction::new(0x15, 0, 4, 17),       // jeq #17, jf ret #0
+        Instruction::new(0xb1, 0, 0, 14),       // ldxb 4*([14]&0xf)
+        Instruction::new(0x48, 0, 0, 16),       // ldh [x+16]
+        Instruction::new(0x15, 0, 1, 53),       // jeq #53, jf ret #0
+        Instruction::new(0x06, 0, 0, 0xffff),   // ret #65535
+        Instruction::new(0x06, 0, 0, 0),        // ret #0
+    ];
+
+    fn frame(protocol: u8, dst_port: u16) -> [u8; 42] {
+        let mut frame = [0; 42];
+        frame[12..14].copy_from_slice(&[0x08, 0x00]);
+        frame[14] = 0x45;
+        frame[23] = protocol;
+        NetworkEndian::write_u16(&mut frame[36..38], dst_port);
+        frame
+    }
+
+    #[test]
+    fn test_matches() {
+        let filter = Filter::new(&DNS_PROGRAM[..]).unwrap();
+        assert!(filter.matches(&frame(17, 53), &[]));
+        assert!(!filter.matches(&frame(17, 54), &[]));
+        assert!(!filter.matches(&frame(6, 53), &[]));
+        // The packet may be split anywhere, even within a load.
+        let frame = frame(17, 53);
+        assert!(filter.matches(&frame[..37], &frame[37..]));
+        assert!(filter.matches(&frame[..14], &frame[14..]));
+        // Loads out of the packet reject it.
+        assert!(!filter.matches(&frame[..30], &[]));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let program = [
+            Instruction::new(Instruction::LD | Instruction::LEN, 0, 0, 0),
+            Instruction::new(Instruction::ST, 0, 0, 3),
+            Instruction::new(Instruction::LDX | Instruction::MEM, 0, 0, 3),
+            Instruction::new(Instruction::ALU | Instruction::MUL | Instruction::X, 0, 0, 0),
+            Instruction::new(Instruction::ALU | Instruction::MOD | Instruction::K, 0, 0, 7),
+            Instruction::new(Instruction::JMP | Instruction::JEQ | Instruction::K, 1, 0, 2),
+            Instruction::new(Instruction::RET | Instruction::K, 0, 0, 0),
+            Instruction::new(Instruction::RET | Instruction::A, 0, 0, 0),
+        ];
+        let filter = Filter::new(&program[..]).unwrap();
+        // 3 * 3 % 7 == 2
+        assert!(filter.matches(&[0; 3], &[]));
+        // 4 * 4 % 7 == 2
+        assert!(filter.matches(&[0; 2], &[0; 2]));
+        assert!(!filter.matches(&[0; 5], &[]));
+
+        let program = [
+            Instruction::new(Instruction::LDX | Instruction::IMM, 0, 0, 0),
+            Instruction::new(Instruction::ALU | Instruction::DIV | Instruction::X, 0, 0, 0),
+            Instruction::new(Instruction::RET | Instruction::K, 0, 0, 1),
+        ];
+        // Division by zero rejects the packet.
+        assert!(!Filter::new(&program[..]).unwrap().matches(&[], &[]));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let ret = Instruction::new(Instruction::RET | Instruction::K, 0, 0, 1);
+        assert_eq!(Filter::new(&[][..]).err(), Some(Error::Illegal));
+        // Does not end with a return.
+        assert_eq!(Filter::new(&[Instruction::new(Instruction::LD | Instruction::IMM,
+                                                  0, 0, 0)][..]).err(),
+                   Some(Error::Illegal));
+        // Jumps out of the program.
+        assert_eq!(Filter::new(&[Instruction::new(Instruction::JMP | Instruction::JA,
+                                                  0, 0, 1), ret][..]).err(),
+                   Some(Error::Illegal));
+        // Stores out of the memory.
+        assert_eq!(Filter::new(&[Instruction::new(Instruction::ST, 0, 0, 16), ret][..]).err(),
+                   Some(Error::Illegal));
+        // Divides by a constant zero.
+        assert_eq!(Filter::new(&[Instruction::new(Instruction::ALU | Instruction::DIV,
+                                                  0, 0, 0), ret][..]).err(),
+                   Some(Error::Illegal));
+        // Has an unknown opcode.
+        assert_eq!(Filter::new(&[Instruction::new(0xff, 0, 0, 0), ret][..]).err(),
+                   Some(Error::Illegal));
+    }
+}
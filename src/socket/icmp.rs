@@ -1,13 +1,19 @@
 use core::cmp;
-use managed::Managed;
+#[cfg(feature = "async")]
+use core::task::Waker;
 
 use {Error, Result};
 use phy::{ChecksumCapabilities, DeviceCapabilities};
 use socket::{Socket, SocketMeta, SocketHandle};
-use storage::{Resettable, RingBuffer};
+#[cfg(feature = "async")]
+use socket::WakerRegistration;
+use storage::{self, RingBuffer, BufferStats};
 use wire::{IpAddress, IpEndpoint, IpProtocol, IpRepr};
-use wire::{Ipv4Address, Ipv4Repr};
-use wire::{Icmpv4Packet, Icmpv4Repr};
+use wire::IcmpRepr;
+#[cfg(feature = "proto-ipv4")]
+use wire::{Ipv4Address, Ipv4Repr, Icmpv4Packet, Icmpv4Repr};
+#[cfg(feature = "proto-ipv6")]
+use wire::{Ipv6Address, Ipv6Repr, Icmpv6Packet, Icmpv6Repr};
 use wire::{UdpPacket, UdpRepr};
 
 /// Type of endpoint to bind the ICMP socket to. See [IcmpSocket::bind] for
@@ -18,7 +24,8 @@ use wire::{UdpPacket, UdpRepr};
 pub enum Endpoint {
     Unspecified,
     Ident(u16),
-    Udp(IpEndpoint)
+    Udp(IpEndpoint),
+    Errors
 }
 
 impl Endpoint {
@@ -26,6 +33,7 @@ impl Endpoint {
         match *self {
             Endpoint::Ident(_) => true,
             Endpoint::Udp(endpoint) => endpoint.port != 0,
+            Endpoint::Errors => true,
             Endpoint::Unspecified => false
         }
     }
@@ -35,58 +43,19 @@ impl Default for Endpoint {
     fn default() -> Endpoint { Endpoint::Unspecified }
 }
 
-/// A buffered ICMPv4 packet.
-#[derive(Debug)]
-pub struct PacketBuffer<'a> {
-    endpoint: IpAddress,
-    size:     usize,
-    payload:  Managed<'a, [u8]>
-}
-
-impl<'a> PacketBuffer<'a> {
-    /// Create a buffered packet.
-    pub fn new<T>(payload: T) -> PacketBuffer<'a>
-            where T: Into<Managed<'a, [u8]>> {
-        PacketBuffer {
-            endpoint: IpAddress::default(),
-            size:     0,
-            payload:  payload.into()
-        }
-    }
-
-    fn as_ref<'b>(&'b self) -> &'b [u8] {
-        &self.payload[..self.size]
-    }
+/// A buffered ICMP packet.
+pub type PacketBuffer<'a> = storage::PacketBuffer<'a, IpAddress>;
 
-    fn as_mut<'b>(&'b mut self) -> &'b mut [u8] {
-        &mut self.payload[..self.size]
-    }
-
-    fn resize<'b>(&'b mut self, size: usize) -> Result<&'b mut Self> {
-        if self.payload.len() >= size {
-            self.size = size;
-            Ok(self)
-        } else {
-            Err(Error::Truncated)
-        }
-    }
-}
-
-impl<'a> Resettable for PacketBuffer<'a> {
-    fn reset(&mut self) {
-        self.size = 0;
-    }
-}
-
-/// An ICMPv4 packet ring buffer.
+/// An ICMP packet ring buffer.
 pub type SocketBuffer<'a, 'b: 'a> = RingBuffer<'a, PacketBuffer<'b>>;
 
-/// An ICMPv4 socket
+/// An ICMP socket
 ///
-/// An ICMPv4 socket is bound to a specific [IcmpEndpoint] which may
-/// be a sepecific UDP port to listen for ICMP error messages related
-/// to the port or a specific ICMP identifier value. See [bind] for
-/// more details.
+/// An ICMP socket sends and receives ICMPv4 and ICMPv6 packets, depending on
+/// the address of the remote endpoint, and is bound to a specific [IcmpEndpoint]
+/// which may be a sepecific UDP port to listen for ICMP error messages related
+/// to the port, a specific ICMP identifier value, or all error messages.
+/// See [bind] for more details.
 ///
 /// [IcmpEndpoint]: enum.IcmpEndpoint.html
 /// [bind]: #method.bind
@@ -98,11 +67,17 @@ pub struct IcmpSocket<'a, 'b: 'a> {
     /// The endpoint this socket is communicating with
     endpoint:  Endpoint,
     /// The time-to-live (IPv4) or hop limit (IPv6) value used in outgoing packets.
-    hop_limit: Option<u8>
+    hop_limit: Option<u8>,
+    /// The Differentiated Services Code Point used in outgoing packets.
+    dscp:      u8,
+    #[cfg(feature = "async")]
+    rx_waker:  WakerRegistration,
+    #[cfg(feature = "async")]
+    tx_waker:  WakerRegistration
 }
 
 impl<'a, 'b> IcmpSocket<'a, 'b> {
-    /// Create an ICMPv4 socket with the given buffers.
+    /// Create an ICMP socket with the given buffers.
     pub fn new(rx_buffer: SocketBuffer<'a, 'b>,
                tx_buffer: SocketBuffer<'a, 'b>) -> IcmpSocket<'a, 'b> {
         IcmpSocket {
@@ -110,7 +85,12 @@ impl<'a, 'b> IcmpSocket<'a, 'b> {
             rx_buffer: rx_buffer,
             tx_buffer: tx_buffer,
             endpoint:  Endpoint::default(),
-            hop_limit: None
+            hop_limit: None,
+            dscp:      0,
+            #[cfg(feature = "async")]
+            rx_waker:  WakerRegistration::new(),
+            #[cfg(feature = "async")]
+            tx_waker:  WakerRegistration::new()
         }
     }
 
@@ -120,6 +100,24 @@ impl<'a, 'b> IcmpSocket<'a, 'b> {
         self.meta.handle
     }
 
+    /// Register a waker to be woken once a packet is received.
+    ///
+    /// Only one waker is registered at a time, and it is woken at most once; it has to be
+    /// registered again to be woken again. The waker may be woken spuriously.
+    #[cfg(feature = "async")]
+    pub fn register_recv_waker(&mut self, waker: &Waker) {
+        self.rx_waker.register(waker)
+    }
+
+    /// Register a waker to be woken once a packet is sent, freeing space in the
+    /// transmit buffer.
+    ///
+    /// See also [register_recv_waker](#method.register_recv_waker).
+    #[cfg(feature = "async")]
+    pub fn register_send_waker(&mut self, waker: &Waker) {
+        self.tx_waker.register(waker)
+    }
+
     /// Return the time-to-live (IPv4) or hop limit (IPv6) value used in outgoing packets.
     ///
     /// See also the [set_hop_limit](#method.set_hop_limit) method
@@ -147,6 +145,32 @@ impl<'a, 'b> IcmpSocket<'a, 'b> {
         self.hop_limit = hop_limit
     }
 
+    /// Return the Differentiated Services Code Point used in outgoing packets.
+    ///
+    /// See also the [set_dscp](#method.set_dscp) method
+    pub fn dscp(&self) -> u8 {
+        self.dscp
+    }
+
+    /// Set the Differentiated Services Code Point used in outgoing packets.
+    ///
+    /// The code point is carried in the upper six bits of the IPv4 Type of Service or
+    /// IPv6 Traffic Class field, and lets the network prioritize the traffic of this socket;
+    /// see [RFC 2474]. A socket without an explicitly set code point uses the default one (0).
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the code point does not fit in six bits.
+    ///
+    /// [RFC 2474]: https://tools.ietf.org/html/rfc2474
+    pub fn set_dscp(&mut self, dscp: u8) {
+        if dscp > 0x3f {
+            panic!("the DSCP value of a packet must fit in six bits")
+        }
+
+        self.dscp = dscp
+    }
+
     /// Bind the socket to the given endpoint.
     ///
     /// This function returns `Err(Error::Illegal)` if the socket was open
@@ -197,9 +221,30 @@ impl<'a, 'b> IcmpSocket<'a, 'b> {
     /// icmp_socket.bind(IcmpEndpoint::Ident(0x1234)).unwrap();
     /// ```
     ///
+    /// ## Bind to ICMP error messages:
+    ///
+    /// To [recv] all Destination Unreachable and Time Exceeded messages, regardless of
+    /// the packet they were returned for, the socket may be bound using [IcmpEndpoint::Errors].
+    /// This is useful e.g. for a traceroute tool, which sends packets with an increasing
+    /// hop limit and collects the errors returned by the routers along the path.
+    ///
+    /// ```
+    /// # use smoltcp::socket::{Socket, IcmpSocket, IcmpPacketBuffer, IcmpSocketBuffer};
+    /// # let rx_buffer = IcmpSocketBuffer::new(vec![IcmpPacketBuffer::new(vec![0; 20])]);
+    /// # let tx_buffer = IcmpSocketBuffer::new(vec![IcmpPacketBuffer::new(vec![0; 20])]);
+    /// use smoltcp::socket::IcmpEndpoint;
+    ///
+    /// let mut icmp_socket = // ...
+    /// # IcmpSocket::new(rx_buffer, tx_buffer);
+    ///
+    /// // Bind to ICMP error messages
+    /// icmp_socket.bind(IcmpEndpoint::Errors).unwrap();
+    /// ```
+    ///
     /// [is_specified]: enum.IcmpEndpoint.html#method.is_specified
     /// [IcmpEndpoint::Ident]: enum.IcmpEndpoint.html#variant.Ident
     /// [IcmpEndpoint::Udp]: enum.IcmpEndpoint.html#variant.Udp
+    /// [IcmpEndpoint::Errors]: enum.IcmpEndpoint.html#variant.Errors
     /// [send]: #method.send
     /// [recv]: #method.recv
     pub fn bind<T: Into<Endpoint>>(&mut self, endpoint: T) -> Result<()> {
@@ -214,6 +259,12 @@ impl<'a, 'b> IcmpSocket<'a, 'b> {
         Ok(())
     }
 
+    /// Return the endpoint the socket is bound to.
+    #[inline]
+    pub fn endpoint(&self) -> Endpoint {
+        self.endpoint
+    }
+
     /// Check whether the transmit buffer is full.
     #[inline]
     pub fn can_send(&self) -> bool {
@@ -226,6 +277,22 @@ impl<'a, 'b> IcmpSocket<'a, 'b> {
         !self.rx_buffer.is_empty()
     }
 
+    /// Return the occupancy statistics of the transmit buffer, in packets.
+    pub fn send_buffer_stats(&self) -> BufferStats {
+        self.tx_buffer.stats()
+    }
+
+    /// Return the occupancy statistics of the receive buffer, in packets.
+    pub fn recv_buffer_stats(&self) -> BufferStats {
+        self.rx_buffer.stats()
+    }
+
+    /// Restart the occupancy statistics of both buffers from their current contents.
+    pub fn reset_buffer_stats(&mut self) {
+        self.tx_buffer.reset_stats();
+        self.rx_buffer.reset_stats();
+    }
+
     /// Check whether the socket is open.
     #[inline]
     pub fn is_open(&self) -> bool {
@@ -244,10 +311,10 @@ impl<'a, 'b> IcmpSocket<'a, 'b> {
         }
 
         let packet_buf = self.tx_buffer.enqueue_one_with(|buf| buf.resize(size))?;
-        packet_buf.endpoint = endpoint;
+        *packet_buf.metadata_mut() = endpoint;
         net_trace!("{}:{}: buffer to send {} octets",
-                   self.meta.handle, packet_buf.endpoint, size);
-        Ok(&mut packet_buf.as_mut()[..size])
+                   self.meta.handle, packet_buf.metadata(), size);
+        Ok(packet_buf.payload_mut())
     }
 
     /// Enqueue a packet to be sent to a given remote address, and fill it from a slice.
@@ -264,10 +331,11 @@ impl<'a, 'b> IcmpSocket<'a, 'b> {
     ///
     /// This function returns `Err(Error::Exhausted)` if the receive buffer is empty.
     pub fn recv(&mut self) -> Result<(&[u8], IpAddress)> {
+        self.rx_buffer.release_dequeued();
         let packet_buf = self.rx_buffer.dequeue_one()?;
         net_trace!("{}:{}: receive {} buffered octets",
-                   self.meta.handle, packet_buf.endpoint, packet_buf.size);
-        Ok((&packet_buf.as_ref(), packet_buf.endpoint))
+                   self.meta.handle, packet_buf.metadata(), packet_buf.len());
+        Ok((packet_buf.payload(), *packet_buf.metadata()))
     }
 
     /// Dequeue a packet received from a remote endpoint, copy the payload into the given slice,
@@ -283,13 +351,14 @@ impl<'a, 'b> IcmpSocket<'a, 'b> {
 
     /// Filter determining which packets received by the interface are appended to
     /// the given sockets received buffer.
-    pub(crate) fn accepts(&self, ip_repr: &IpRepr, icmp_repr: &Icmpv4Repr,
+    pub(crate) fn accepts(&self, ip_repr: &IpRepr, icmp_repr: &IcmpRepr,
                           cksum: &ChecksumCapabilities) -> bool {
         match (&self.endpoint, icmp_repr) {
             // If we are bound to ICMP errors associated to a UDP port, only
             // accept Destination Unreachable messages with the data containing
             // a UDP packet send from the local port we are bound to.
-            (&Endpoint::Udp(endpoint), &Icmpv4Repr::DstUnreachable { data, .. })
+            #[cfg(feature = "proto-ipv4")]
+            (&Endpoint::Udp(endpoint), &IcmpRepr::Ipv4(Icmpv4Repr::DstUnreachable { data, .. }))
                     if endpoint.addr.is_unspecified() || endpoint.addr == ip_repr.dst_addr() => {
                 let packet = UdpPacket::new(data);
                 match UdpRepr::parse(&packet, &ip_repr.src_addr(), &ip_repr.dst_addr(), cksum) {
@@ -297,39 +366,94 @@ impl<'a, 'b> IcmpSocket<'a, 'b> {
                     Err(_) => false,
                 }
             }
+            // The checksum of a UDP packet over IPv6 is mandatory, and covers the addresses
+            // of the packet it was sent in, rather than of the packet carrying the error.
+            #[cfg(feature = "proto-ipv6")]
+            (&Endpoint::Udp(endpoint), &IcmpRepr::Ipv6(Icmpv6Repr::DstUnreachable {
+                header, data, ..
+            })) if endpoint.addr.is_unspecified() || endpoint.addr == ip_repr.dst_addr() => {
+                let packet = UdpPacket::new(data);
+                match UdpRepr::parse(&packet, &header.src_addr.into(), &header.dst_addr.into(),
+                                     cksum) {
+                    Ok(repr) => endpoint.port == repr.src_port,
+                    Err(_) => false,
+                }
+            }
             // If we are bound to a specific ICMP identifier value, only accept an
             // Echo Request/Reply with the identifier field matching the endpoint
             // port.
-            (&Endpoint::Ident(bound_ident), &Icmpv4Repr::EchoRequest { ident, .. }) |
-            (&Endpoint::Ident(bound_ident), &Icmpv4Repr::EchoReply { ident, .. }) =>
+            #[cfg(feature = "proto-ipv4")]
+            (&Endpoint::Ident(bound_ident),
+             &IcmpRepr::Ipv4(Icmpv4Repr::EchoRequest { ident, .. })) |
+            (&Endpoint::Ident(bound_ident),
+             &IcmpRepr::Ipv4(Icmpv4Repr::EchoReply { ident, .. })) =>
                 ident == bound_ident,
+            #[cfg(feature = "proto-ipv6")]
+            (&Endpoint::Ident(bound_ident),
+             &IcmpRepr::Ipv6(Icmpv6Repr::EchoRequest { ident, .. })) |
+            (&Endpoint::Ident(bound_ident),
+             &IcmpRepr::Ipv6(Icmpv6Repr::EchoReply { ident, .. })) =>
+                ident == bound_ident,
+            // If we are bound to error messages, accept every Destination Unreachable
+            // and Time Exceeded message, whichever packet it was returned for.
+            #[cfg(feature = "proto-ipv4")]
+            (&Endpoint::Errors, &IcmpRepr::Ipv4(Icmpv4Repr::DstUnreachable { .. })) |
+            (&Endpoint::Errors, &IcmpRepr::Ipv4(Icmpv4Repr::TimeExceeded { .. })) =>
+                true,
+            #[cfg(feature = "proto-ipv6")]
+            (&Endpoint::Errors, &IcmpRepr::Ipv6(Icmpv6Repr::DstUnreachable { .. })) |
+            (&Endpoint::Errors, &IcmpRepr::Ipv6(Icmpv6Repr::TimeExceeded { .. })) =>
+                true,
             _ => false,
         }
     }
 
-    pub(crate) fn process(&mut self, ip_repr: &IpRepr, icmp_repr: &Icmpv4Repr,
+    pub(crate) fn process(&mut self, ip_repr: &IpRepr, icmp_repr: &IcmpRepr,
                           cksum: &ChecksumCapabilities) -> Result<()> {
-        let packet_buf = self.rx_buffer.enqueue_one_with(|buf| buf.resize(icmp_repr.buffer_len()))?;
-        packet_buf.endpoint = ip_repr.src_addr();
-        net_trace!("{}:{}: receiving {} octets",
-                   self.meta.handle, packet_buf.endpoint, packet_buf.size);
-        let mut packet = Icmpv4Packet::new(packet_buf.as_mut());
-        icmp_repr.emit(&mut packet, cksum);
+        match icmp_repr {
+            #[cfg(feature = "proto-ipv4")]
+            &IcmpRepr::Ipv4(ref icmp_repr) => {
+                self.rx_buffer.release_dequeued();
+                let packet_buf = self.rx_buffer.enqueue_one_with(|buf| {
+                    buf.resize(icmp_repr.buffer_len())
+                })?;
+                *packet_buf.metadata_mut() = ip_repr.src_addr();
+                icmp_repr.emit(&mut Icmpv4Packet::new(packet_buf.payload_mut()), cksum);
+                net_trace!("{}:{}: receiving {} octets",
+                           self.meta.handle, packet_buf.metadata(), packet_buf.len());
+            }
+            #[cfg(feature = "proto-ipv6")]
+            &IcmpRepr::Ipv6(ref icmp_repr) => {
+                self.rx_buffer.release_dequeued();
+                let packet_buf = self.rx_buffer.enqueue_one_with(|buf| {
+                    buf.resize(icmp_repr.buffer_len())
+                })?;
+                *packet_buf.metadata_mut() = ip_repr.src_addr();
+                icmp_repr.emit(&mut Icmpv6Packet::new(packet_buf.payload_mut()), cksum);
+                net_trace!("{}:{}: receiving {} octets",
+                           self.meta.handle, packet_buf.metadata(), packet_buf.len());
+            }
+            &IcmpRepr::__Nonexhaustive => unreachable!()
+        }
+        #[cfg(feature = "async")]
+        self.rx_waker.wake();
         Ok(())
     }
 
     pub(crate) fn dispatch<F>(&mut self, caps: &DeviceCapabilities, emit: F) -> Result<()>
-        where F: FnOnce((IpRepr, Icmpv4Repr)) -> Result<()>
+        where F: FnOnce((IpRepr, IcmpRepr)) -> Result<()>
     {
         let handle = self.meta.handle;
         let hop_limit = self.hop_limit.unwrap_or(64);
+        let dscp = self.dscp;
         let checksum = &caps.checksum;
         self.tx_buffer.dequeue_one_with(|packet_buf| {
             net_trace!("{}:{}: sending {} octets",
-                       handle, packet_buf.endpoint, packet_buf.size);
-            match packet_buf.endpoint {
+                       handle, packet_buf.metadata(), packet_buf.len());
+            match *packet_buf.metadata() {
+                #[cfg(feature = "proto-ipv4")]
                 IpAddress::Ipv4(ipv4_addr) => {
-                    let packet = Icmpv4Packet::new(packet_buf.as_ref());
+                    let packet = Icmpv4Packet::new(packet_buf.payload());
                     let repr = Icmpv4Repr::parse(&packet, checksum)?;
                     let ip_repr = IpRepr::Ipv4(Ipv4Repr {
                         src_addr:    Ipv4Address::default(),
@@ -337,12 +461,34 @@ impl<'a, 'b> IcmpSocket<'a, 'b> {
                         protocol:    IpProtocol::Icmp,
                         payload_len: repr.buffer_len(),
                         hop_limit:   hop_limit,
+                        ecn:         0,
+                        dscp:        dscp,
+                    });
+                    emit((ip_repr, IcmpRepr::Ipv4(repr)))
+                },
+                #[cfg(feature = "proto-ipv6")]
+                IpAddress::Ipv6(ipv6_addr) => {
+                    let packet = Icmpv6Packet::new(packet_buf.payload());
+                    let repr = Icmpv6Repr::parse(&packet, checksum)?;
+                    let ip_repr = IpRepr::Ipv6(Ipv6Repr {
+                        src_addr:    Ipv6Address::default(),
+                        dst_addr:    ipv6_addr,
+                        next_header: IpProtocol::Icmpv6,
+                        payload_len: repr.buffer_len(),
+                        hop_limit:   hop_limit,
+                        flow_label:  0,
+                        ecn:         0,
+                        dscp:        dscp,
                     });
-                    emit((ip_repr, repr))
+                    emit((ip_repr, IcmpRepr::Ipv6(repr)))
                 },
                 _ => Err(Error::Unaddressable)
             }
-        })
+        })?;
+        self.tx_buffer.release_dequeued();
+        #[cfg(feature = "async")]
+        self.tx_waker.wake();
+        Ok(())
     }
 
     pub(crate) fn poll_at(&self) -> Option<u64> {
@@ -360,10 +506,10 @@ impl<'a, 'b> Into<Socket<'a, 'b>> for IcmpSocket<'a, 'b> {
     }
 }
 
-#[cfg(test)]
-mod test {
+#[cfg(all(test, feature = "proto-ipv4"))]
+mod test_ipv4 {
     use phy::DeviceCapabilities;
-    use wire::{IpAddress, Icmpv4DstUnreachable};
+    use wire::{IpAddress, Icmpv4DstUnreachable, Icmpv4TimeExceeded};
     use super::*;
 
     fn buffer(packets: usize) -> SocketBuffer<'static, 'static> {
@@ -403,7 +549,9 @@ mod test {
         dst_addr: REMOTE_IPV4,
         protocol: IpProtocol::Icmp,
         payload_len: 24,
-        hop_limit: 0x40
+        hop_limit: 0x40,
+        ecn: 0,
+        dscp: 0
     });
 
     static REMOTE_IP_REPR: IpRepr = IpRepr::Ipv4(Ipv4Repr {
@@ -411,7 +559,9 @@ mod test {
         dst_addr: LOCAL_IPV4,
         protocol: IpProtocol::Icmp,
         payload_len: 24,
-        hop_limit: 0x40
+        hop_limit: 0x40,
+        ecn: 0,
+        dscp: 0
     });
 
     #[test]
@@ -444,7 +594,7 @@ mod test {
 
         assert_eq!(socket.dispatch(&caps, |(ip_repr, icmp_repr)| {
             assert_eq!(ip_repr, LOCAL_IP_REPR);
-            assert_eq!(icmp_repr, ECHO_REPR);
+            assert_eq!(icmp_repr, ECHO_REPR.into());
             Err(Error::Unaddressable)
         }), Err(Error::Unaddressable));
         // buffer is not taken off of the tx queue due to the error
@@ -452,7 +602,7 @@ mod test {
 
         assert_eq!(socket.dispatch(&caps, |(ip_repr, icmp_repr)| {
             assert_eq!(ip_repr, LOCAL_IP_REPR);
-            assert_eq!(icmp_repr, ECHO_REPR);
+            assert_eq!(icmp_repr, ECHO_REPR.into());
             Ok(())
         }), Ok(()));
         // buffer is taken off of the queue this time
@@ -478,11 +628,31 @@ mod test {
                 protocol: IpProtocol::Icmp,
                 payload_len: ECHO_REPR.buffer_len(),
                 hop_limit: 0x2a,
+                ecn: 0,
+                dscp: 0,
             }));
             Ok(())
         }), Ok(()));
     }
 
+    #[test]
+    fn test_set_dscp() {
+        let mut s = socket(buffer(0), buffer(1));
+        let caps = DeviceCapabilities::default();
+
+        let mut bytes = [0xff; 24];
+        let mut packet = Icmpv4Packet::new(&mut bytes);
+        ECHO_REPR.emit(&mut packet, &caps.checksum);
+
+        s.set_dscp(46);
+
+        assert_eq!(s.send_slice(&packet.into_inner()[..], REMOTE_IP), Ok(()));
+        assert_eq!(s.dispatch(&caps, |(ip_repr, _)| {
+            assert_eq!(ip_repr.dscp(), 46);
+            Ok(())
+        }), Ok(()));
+    }
+
     #[test]
     fn test_recv_process() {
         let mut socket = socket(buffer(1), buffer(1));
@@ -498,13 +668,13 @@ mod test {
         ECHO_REPR.emit(&mut packet, &caps.checksum);
         let data = &packet.into_inner()[..];
 
-        assert!(socket.accepts(&REMOTE_IP_REPR, &ECHO_REPR, &caps.checksum));
-        assert_eq!(socket.process(&REMOTE_IP_REPR, &ECHO_REPR, &caps.checksum),
+        assert!(socket.accepts(&REMOTE_IP_REPR, &ECHO_REPR.into(), &caps.checksum));
+        assert_eq!(socket.process(&REMOTE_IP_REPR, &ECHO_REPR.into(), &caps.checksum),
                    Ok(()));
         assert!(socket.can_recv());
 
-        assert!(socket.accepts(&REMOTE_IP_REPR, &ECHO_REPR, &caps.checksum));
-        assert_eq!(socket.process(&REMOTE_IP_REPR, &ECHO_REPR, &caps.checksum),
+        assert!(socket.accepts(&REMOTE_IP_REPR, &ECHO_REPR.into(), &caps.checksum));
+        assert_eq!(socket.process(&REMOTE_IP_REPR, &ECHO_REPR.into(), &caps.checksum),
                    Err(Error::Exhausted));
 
         assert_eq!(socket.recv(), Ok((&data[..], REMOTE_IP)));
@@ -528,7 +698,7 @@ mod test {
 
         // Ensure that a packet with an identifier that isn't the bound
         // ID is not accepted
-        assert!(!socket.accepts(&REMOTE_IP_REPR, &icmp_repr, &caps.checksum));
+        assert!(!socket.accepts(&REMOTE_IP_REPR, &icmp_repr.into(), &caps.checksum));
     }
 
     #[test]
@@ -551,7 +721,9 @@ mod test {
                 dst_addr: REMOTE_IPV4,
                 protocol: IpProtocol::Icmp,
                 payload_len: 12,
-                hop_limit: 0x40
+                hop_limit: 0x40,
+                ecn: 0,
+                dscp: 0
             },
             data: data
         };
@@ -560,15 +732,18 @@ mod test {
             dst_addr: LOCAL_IP,
             protocol: IpProtocol::Icmp,
             payload_len: icmp_repr.buffer_len(),
-            hop_limit: 0x40
+            hop_limit: 0x40,
+            ecn: 0,
+            dscp: 0,
+            flow_label: 0
         };
 
         assert!(!socket.can_recv());
 
         // Ensure we can accept ICMP error response to the bound
         // UDP port
-        assert!(socket.accepts(&ip_repr, &icmp_repr, &caps.checksum));
-        assert_eq!(socket.process(&ip_repr, &icmp_repr, &caps.checksum),
+        assert!(socket.accepts(&ip_repr, &icmp_repr.into(), &caps.checksum));
+        assert_eq!(socket.process(&ip_repr, &icmp_repr.into(), &caps.checksum),
                    Ok(()));
         assert!(socket.can_recv());
 
@@ -578,4 +753,189 @@ mod test {
         assert_eq!(socket.recv(), Ok((&packet.into_inner()[..], REMOTE_IP)));
         assert!(!socket.can_recv());
     }
+
+    #[test]
+    fn test_accepts_errors() {
+        let mut socket = socket(buffer(1), buffer(1));
+        assert_eq!(socket.bind(Endpoint::Errors), Ok(()));
+
+        let caps = DeviceCapabilities::default();
+        let icmp_repr = Icmpv4Repr::TimeExceeded {
+            reason: Icmpv4TimeExceeded::TtlExpired,
+            header: Ipv4Repr {
+                src_addr: LOCAL_IPV4,
+                dst_addr: REMOTE_IPV4,
+                protocol: IpProtocol::Udp,
+                payload_len: 18,
+                hop_limit: 0x01,
+                ecn: 0,
+                dscp: 0
+            },
+            data: &[0xff; 8]
+        };
+
+        // Ensure that error messages are accepted whichever packet they
+        // were returned for, but echo messages are not
+        assert!(socket.accepts(&REMOTE_IP_REPR, &icmp_repr.into(), &caps.checksum));
+        assert!(!socket.accepts(&REMOTE_IP_REPR, &ECHO_REPR.into(), &caps.checksum));
+    }
+}
+
+#[cfg(all(test, feature = "proto-ipv6"))]
+mod test_ipv6 {
+    use phy::DeviceCapabilities;
+    use wire::{IpAddress, Icmpv6DstUnreachable};
+    use super::*;
+
+    fn buffer(packets: usize) -> SocketBuffer<'static, 'static> {
+        let mut storage = vec![];
+        for _ in 0..packets {
+            storage.push(PacketBuffer::new(vec![0; 66]))
+        }
+        SocketBuffer::new(storage)
+    }
+
+    fn socket(rx_buffer: SocketBuffer<'static, 'static>,
+              tx_buffer: SocketBuffer<'static, 'static>) -> IcmpSocket<'static, 'static> {
+        IcmpSocket::new(rx_buffer, tx_buffer)
+    }
+
+    const REMOTE_IPV6: Ipv6Address = Ipv6Address([0xfe, 0x80, 0, 0, 0, 0, 0, 0,
+                                                  0, 0, 0, 0, 0, 0, 0, 2]);
+    const LOCAL_IPV6: Ipv6Address  = Ipv6Address([0xfe, 0x80, 0, 0, 0, 0, 0, 0,
+                                                  0, 0, 0, 0, 0, 0, 0, 1]);
+    const REMOTE_IP: IpAddress     = IpAddress::Ipv6(REMOTE_IPV6);
+    const LOCAL_IP: IpAddress      = IpAddress::Ipv6(LOCAL_IPV6);
+    const LOCAL_PORT:  u16         = 53;
+    const LOCAL_END:   IpEndpoint  = IpEndpoint { addr: LOCAL_IP,  port: LOCAL_PORT  };
+
+    static ECHO_REPR: Icmpv6Repr = Icmpv6Repr::EchoRequest {
+            ident:  0x1234,
+            seq_no: 0x5678,
+            data:   &[0xff; 16]
+    };
+
+    static UDP_REPR: UdpRepr = UdpRepr {
+        src_port: 53,
+        dst_port: 9090,
+        payload:  &[0xff; 10]
+    };
+
+    static LOCAL_IP_REPR: IpRepr = IpRepr::Ipv6(Ipv6Repr {
+        src_addr: Ipv6Address::UNSPECIFIED,
+        dst_addr: REMOTE_IPV6,
+        next_header: IpProtocol::Icmpv6,
+        payload_len: 24,
+        hop_limit: 0x40,
+        flow_label: 0,
+        ecn: 0,
+        dscp: 0
+    });
+
+    static REMOTE_IP_REPR: IpRepr = IpRepr::Ipv6(Ipv6Repr {
+        src_addr: REMOTE_IPV6,
+        dst_addr: LOCAL_IPV6,
+        next_header: IpProtocol::Icmpv6,
+        payload_len: 24,
+        hop_limit: 0x40,
+        flow_label: 0,
+        ecn: 0,
+        dscp: 0
+    });
+
+    #[test]
+    fn test_send_dispatch() {
+        let mut socket = socket(buffer(0), buffer(1));
+        let caps = DeviceCapabilities::default();
+
+        let mut bytes = [0xff; 24];
+        let mut packet = Icmpv6Packet::new(&mut bytes);
+        ECHO_REPR.emit(&mut packet, &caps.checksum);
+
+        assert_eq!(socket.send_slice(&packet.into_inner()[..], REMOTE_IP), Ok(()));
+        assert_eq!(socket.dispatch(&caps, |(ip_repr, icmp_repr)| {
+            assert_eq!(ip_repr, LOCAL_IP_REPR);
+            assert_eq!(icmp_repr, ECHO_REPR.into());
+            Ok(())
+        }), Ok(()));
+        assert!(socket.can_send());
+    }
+
+    #[test]
+    fn test_recv_process() {
+        let mut socket = socket(buffer(1), buffer(1));
+        assert_eq!(socket.bind(Endpoint::Ident(0x1234)), Ok(()));
+
+        let caps = DeviceCapabilities::default();
+
+        let mut bytes = [0xff; 24];
+        let mut packet = Icmpv6Packet::new(&mut bytes);
+        ECHO_REPR.emit(&mut packet, &caps.checksum);
+        let data = &packet.into_inner()[..];
+
+        assert!(socket.accepts(&REMOTE_IP_REPR, &ECHO_REPR.into(), &caps.checksum));
+        assert_eq!(socket.process(&REMOTE_IP_REPR, &ECHO_REPR.into(), &caps.checksum),
+                   Ok(()));
+        assert_eq!(socket.recv(), Ok((&data[..], REMOTE_IP)));
+
+        // Ensure that a packet with an identifier that isn't the bound
+        // ID is not accepted
+        let icmp_repr = Icmpv6Repr::EchoReply {
+            ident:  0x4321,
+            seq_no: 0x5678,
+            data:   &[0xff; 16]
+        };
+        assert!(!socket.accepts(&REMOTE_IP_REPR, &icmp_repr.into(), &caps.checksum));
+    }
+
+    #[test]
+    fn test_accepts_udp() {
+        let mut socket = socket(buffer(1), buffer(1));
+        assert_eq!(socket.bind(Endpoint::Udp(LOCAL_END)), Ok(()));
+
+        let caps = DeviceCapabilities::default();
+
+        let mut bytes = [0xff; 18];
+        let mut packet = UdpPacket::new(&mut bytes);
+        UDP_REPR.emit(&mut packet, &LOCAL_IP, &REMOTE_IP, &caps.checksum);
+
+        let data = &packet.into_inner()[..];
+
+        let icmp_repr = Icmpv6Repr::DstUnreachable {
+            reason: Icmpv6DstUnreachable::PortUnreachable,
+            header: Ipv6Repr {
+                src_addr: LOCAL_IPV6,
+                dst_addr: REMOTE_IPV6,
+                next_header: IpProtocol::Udp,
+                payload_len: 18,
+                hop_limit: 0x40,
+                flow_label: 0,
+                ecn: 0,
+                dscp: 0
+            },
+            data: data
+        };
+        let ip_repr = IpRepr::Ipv6(Ipv6Repr {
+            src_addr: REMOTE_IPV6,
+            dst_addr: LOCAL_IPV6,
+            next_header: IpProtocol::Icmpv6,
+            payload_len: icmp_repr.buffer_len(),
+            hop_limit: 0x40,
+            flow_label: 0,
+            ecn: 0,
+            dscp: 0
+        });
+
+        // Ensure we can accept ICMP error response to the bound
+        // UDP port
+        assert!(socket.accepts(&ip_repr, &icmp_repr.into(), &caps.checksum));
+        assert_eq!(socket.process(&ip_repr, &icmp_repr.into(), &caps.checksum),
+                   Ok(()));
+        assert!(socket.can_recv());
+
+        let mut bytes = [0x00; 66];
+        let mut packet = Icmpv6Packet::new(&mut bytes[..]);
+        icmp_repr.emit(&mut packet, &caps.checksum);
+        assert_eq!(socket.recv(), Ok((&packet.into_inner()[..], REMOTE_IP)));
+    }
 }
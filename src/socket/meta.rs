@@ -1,5 +1,5 @@
 use wire::IpAddress;
-use super::SocketHandle;
+use super::{SocketHandle, SocketReadiness};
 
 /// Neighbor dependency.
 ///
@@ -34,6 +34,8 @@ pub struct Meta {
     pub(crate) handle: SocketHandle,
     /// See [NeighborState](struct.NeighborState.html).
     neighbor_state:    NeighborState,
+    /// The readiness of the socket when it was last reported to the application.
+    pub(crate) readiness: SocketReadiness,
 }
 
 impl Meta {
@@ -57,18 +59,22 @@ impl Meta {
         }
     }
 
-    pub(crate) fn egress_permitted<F>(&mut self, has_neighbor: F) -> bool
+    pub(crate) fn egress_permitted<F>(&mut self, timestamp: u64, has_neighbor: F) -> bool
         where F: Fn(IpAddress) -> bool
     {
         match self.neighbor_state {
             NeighborState::Active =>
                 true,
-            NeighborState::Waiting { neighbor, .. } => {
+            NeighborState::Waiting { neighbor, silent_until } => {
                 if has_neighbor(neighbor) {
                     net_trace!("{}: neighbor {} discovered, unsilencing",
                                self.handle, neighbor);
                     self.neighbor_state = NeighborState::Active;
                     true
+                } else if timestamp >= silent_until {
+                    net_trace!("{}: neighbor {} silence timer expired, rediscovering",
+                               self.handle, neighbor);
+                    true
                 } else {
                     false
                 }
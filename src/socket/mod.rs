@@ -11,49 +11,138 @@ The interface implemented by this module uses explicit buffering: you decide on
 size for a buffer, allocate it, and let the networking stack use it.
 */
 
+use core::fmt;
 use core::marker::PhantomData;
 
 mod meta;
 #[cfg(feature = "socket-raw")]
 mod raw;
-#[cfg(all(feature = "socket-icmp", feature = "proto-ipv4"))]
+#[cfg(all(feature = "socket-icmp", any(feature = "proto-ipv4", feature = "proto-ipv6")))]
 mod icmp;
+#[cfg(all(feature = "socket-icmp", any(feature = "proto-ipv4", feature = "proto-ipv6")))]
+mod ping;
 #[cfg(feature = "socket-udp")]
 mod udp;
 #[cfg(feature = "socket-tcp")]
 mod tcp;
+#[cfg(feature = "socket-tcp")]
+mod tcp_listener;
+#[cfg(feature = "socket-tcp")]
+mod tcp_cookie;
+#[cfg(feature = "socket-tcp")]
+mod tcp_connector;
+#[cfg(all(feature = "socket-tcp", feature = "async", feature = "std"))]
+mod tcp_stream;
+#[cfg(all(feature = "socket-tcp", feature = "std"))]
+mod tcp_blocking;
+#[cfg(feature = "socket-tcp")]
+mod congestion;
+#[cfg(feature = "socket-dns")]
+mod dns;
+#[cfg(any(feature = "socket-dns", feature = "proto-mdns"))]
+mod dns_cache;
+#[cfg(feature = "socket-packet")]
+mod packet;
+#[cfg(any(feature = "socket-raw", feature = "socket-packet"))]
+mod filter;
+#[cfg(any(feature = "socket-udp", feature = "socket-tcp"))]
+mod error;
+#[cfg(feature = "async")]
+mod waker;
 mod set;
 mod ref_;
+mod readiness;
+#[cfg(any(feature = "socket-udp", feature = "socket-tcp"))]
+mod connection;
 
 pub(crate) use self::meta::Meta as SocketMeta;
+#[cfg(feature = "async")]
+pub(crate) use self::waker::WakerRegistration;
 
 #[cfg(feature = "socket-raw")]
 pub use self::raw::{PacketBuffer as RawPacketBuffer,
                     SocketBuffer as RawSocketBuffer,
                     RawSocket};
 
-#[cfg(all(feature = "socket-icmp", feature = "proto-ipv4"))]
+#[cfg(all(feature = "socket-icmp", any(feature = "proto-ipv4", feature = "proto-ipv6")))]
 pub use self::icmp::{PacketBuffer as IcmpPacketBuffer,
                      SocketBuffer as IcmpSocketBuffer,
                      Endpoint as IcmpEndpoint,
                      IcmpSocket};
+#[cfg(all(feature = "socket-icmp", any(feature = "proto-ipv4", feature = "proto-ipv6")))]
+pub use self::ping::{Event as PingEvent,
+                     Pinger};
 
 #[cfg(feature = "socket-udp")]
 pub use self::udp::{PacketBuffer as UdpPacketBuffer,
+                    PacketMetadata as UdpPacketMetadata,
+                    Migration as UdpMigration,
+                    MULTICAST_GROUP_COUNT as UDP_MULTICAST_GROUP_COUNT,
                     SocketBuffer as UdpSocketBuffer,
-                    UdpSocket};
+                    UdpSocket,
+                    UdpSocketBuilder};
 
 #[cfg(feature = "socket-tcp")]
 pub use self::tcp::{SocketBuffer as TcpSocketBuffer,
                     State as TcpState,
-                    TcpSocket};
+                    Event as TcpEvent,
+                    EventBuffer as TcpEventBuffer,
+                    Stats as TcpStats,
+                    ClosedConnection as TcpClosedConnection,
+                    Md5SignatureFn as TcpMd5SignatureFn,
+                    TcpSocket,
+                    TcpSocketBuilder};
+#[cfg(feature = "socket-tcp")]
+pub use self::tcp_listener::TcpListener;
+#[cfg(feature = "socket-tcp")]
+pub use self::tcp_connector::{Event as TcpConnectEvent,
+                              TcpConnector};
+#[cfg(all(feature = "socket-tcp", feature = "async", feature = "std"))]
+pub use self::tcp_stream::TcpStream;
+#[cfg(all(feature = "socket-tcp", feature = "std"))]
+pub use self::tcp_blocking::BlockingTcpStream;
+#[cfg(feature = "socket-tcp")]
+pub use self::congestion::{Controller as CongestionController,
+                           Algorithm as CongestionAlgorithm,
+                           Reno as RenoController,
+                           Cubic as CubicController};
+
+#[cfg(feature = "socket-dns")]
+pub use self::dns::{DnsQuery,
+                    QueryHandle as DnsQueryHandle,
+                    MAX_ADDRESS_COUNT as DNS_MAX_ADDRESS_COUNT,
+                    DnsSocket};
+#[cfg(any(feature = "socket-dns", feature = "proto-mdns"))]
+pub use self::dns_cache::{DnsCache, DnsCacheEntry,
+                          MAX_ADDRESS_COUNT as DNS_CACHE_MAX_ADDRESS_COUNT};
+
+#[cfg(any(feature = "socket-udp", feature = "socket-tcp"))]
+pub use self::error::Error as SocketError;
+
+#[cfg(feature = "socket-packet")]
+pub use self::packet::{PacketBuffer as PacketPacketBuffer,
+                       SocketBuffer as PacketSocketBuffer,
+                       PacketSocket};
+
+#[cfg(any(feature = "socket-raw", feature = "socket-packet"))]
+pub use self::filter::{Filter as PacketFilter,
+                       Instruction as FilterInstruction,
+                       MAX_PROGRAM_LEN as FILTER_MAX_PROGRAM_LEN};
 
 pub use self::set::{Set as SocketSet, Item as SocketSetItem, Handle as SocketHandle};
-pub use self::set::{Iter as SocketSetIter, IterMut as SocketSetIterMut};
+pub use self::set::{Iter as SocketSetIter, IterMut as SocketSetIterMut,
+                    ReadinessChanges as SocketReadinessChanges};
+#[cfg(any(feature = "socket-udp", feature = "socket-tcp"))]
+pub use self::set::Connections as SocketConnections;
 
 pub use self::ref_::Ref as SocketRef;
 pub(crate) use self::ref_::Session as SocketSession;
 
+pub use self::readiness::Readiness as SocketReadiness;
+
+#[cfg(any(feature = "socket-udp", feature = "socket-tcp"))]
+pub use self::connection::Connection as SocketConnection;
+
 /// A network socket.
 ///
 /// This enumeration abstracts the various types of sockets based on the IP protocol.
@@ -68,12 +157,16 @@ pub(crate) use self::ref_::Session as SocketSession;
 pub enum Socket<'a, 'b: 'a> {
     #[cfg(feature = "socket-raw")]
     Raw(RawSocket<'a, 'b>),
-    #[cfg(all(feature = "socket-icmp", feature = "proto-ipv4"))]
+    #[cfg(all(feature = "socket-icmp", any(feature = "proto-ipv4", feature = "proto-ipv6")))]
     Icmp(IcmpSocket<'a, 'b>),
     #[cfg(feature = "socket-udp")]
     Udp(UdpSocket<'a, 'b>),
     #[cfg(feature = "socket-tcp")]
     Tcp(TcpSocket<'a>),
+    #[cfg(feature = "socket-dns")]
+    Dns(DnsSocket<'a>),
+    #[cfg(feature = "socket-packet")]
+    Packet(PacketSocket<'a, 'b>),
     #[doc(hidden)]
     __Nonexhaustive(PhantomData<(&'a (), &'b ())>)
 }
@@ -89,12 +182,17 @@ macro_rules! dispatch_socket {
         match $self_ {
             #[cfg(feature = "socket-raw")]
             &$( $mut_ )* Socket::Raw(ref $( $mut_ )* $socket) => $code,
-            #[cfg(all(feature = "socket-icmp", feature = "proto-ipv4"))]
+            #[cfg(all(feature = "socket-icmp",
+                      any(feature = "proto-ipv4", feature = "proto-ipv6")))]
             &$( $mut_ )* Socket::Icmp(ref $( $mut_ )* $socket) => $code,
             #[cfg(feature = "socket-udp")]
             &$( $mut_ )* Socket::Udp(ref $( $mut_ )* $socket) => $code,
             #[cfg(feature = "socket-tcp")]
             &$( $mut_ )* Socket::Tcp(ref $( $mut_ )* $socket) => $code,
+            #[cfg(feature = "socket-dns")]
+            &$( $mut_ )* Socket::Dns(ref $( $mut_ )* $socket) => $code,
+            #[cfg(feature = "socket-packet")]
+            &$( $mut_ )* Socket::Packet(ref $( $mut_ )* $socket) => $code,
             &$( $mut_ )* Socket::__Nonexhaustive(_) => unreachable!()
         }
     };
@@ -115,6 +213,33 @@ impl<'a, 'b> Socket<'a, 'b> {
         dispatch_socket!(mut self, |socket| &mut socket.meta)
     }
 
+    /// Return the readiness of the socket.
+    ///
+    /// See [SocketSet::readiness_changes](struct.SocketSet.html#method.readiness_changes).
+    pub fn readiness(&self) -> SocketReadiness {
+        match *self {
+            #[cfg(feature = "socket-raw")]
+            Socket::Raw(ref socket) =>
+                SocketReadiness::new(socket.can_recv(), socket.can_send(), false),
+            #[cfg(all(feature = "socket-icmp",
+                      any(feature = "proto-ipv4", feature = "proto-ipv6")))]
+            Socket::Icmp(ref socket) =>
+                SocketReadiness::new(socket.can_recv(), socket.can_send(), !socket.is_open()),
+            #[cfg(feature = "socket-udp")]
+            Socket::Udp(ref socket) =>
+                SocketReadiness::new(socket.can_recv(), socket.can_send(), !socket.is_open()),
+            #[cfg(feature = "socket-tcp")]
+            Socket::Tcp(ref socket) =>
+                SocketReadiness::new(socket.can_recv(), socket.can_send(), !socket.is_open()),
+            #[cfg(feature = "socket-dns")]
+            Socket::Dns(_) => SocketReadiness::EMPTY,
+            #[cfg(feature = "socket-packet")]
+            Socket::Packet(ref socket) =>
+                SocketReadiness::new(socket.can_recv(), socket.can_send(), false),
+            Socket::__Nonexhaustive(_) => unreachable!()
+        }
+    }
+
     pub(crate) fn poll_at(&self) -> Option<u64> {
         dispatch_socket!(self, |socket| socket.poll_at())
     }
@@ -128,13 +253,65 @@ impl<'a, 'b> SocketSession for Socket<'a, 'b> {
 
 /// A conversion trait for network sockets.
 pub trait AnySocket<'a, 'b>: SocketSession + Sized {
+    /// The type of the handles identifying sockets of this type.
+    type Handle: TypedHandle<'a, 'b, Socket = Self>;
+
     fn downcast<'c>(socket_ref: SocketRef<'c, Socket<'a, 'b>>) ->
                    Option<SocketRef<'c, Self>>;
 }
 
+/// A handle, identifying a socket of a particular type in a set.
+///
+/// Typed handles are returned by [SocketSet::add_typed], and the sockets they identify
+/// are accessed with [SocketSet::get_typed]; since the type of the socket is known
+/// from the handle, accessing it as a socket of a wrong type is a compile-time error.
+/// A typed handle can be converted into an untyped [SocketHandle], e.g. to store
+/// the handles of sockets of several types together.
+///
+/// [SocketSet::add_typed]: struct.SocketSet.html#method.add_typed
+/// [SocketSet::get_typed]: struct.SocketSet.html#method.get_typed
+/// [SocketHandle]: struct.SocketHandle.html
+pub trait TypedHandle<'a, 'b>: Copy + Into<SocketHandle> {
+    /// The type of the socket identified by the handle.
+    type Socket: AnySocket<'a, 'b, Handle = Self>;
+
+    #[doc(hidden)]
+    fn from_untyped(handle: SocketHandle) -> Self;
+}
+
+macro_rules! typed_handle {
+    ($( #[$attr:meta] )* $handle:ident) => {
+        $( #[$attr] )*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+        pub struct $handle(SocketHandle);
+
+        impl $handle {
+            /// Return the untyped handle of the socket.
+            #[inline]
+            pub fn untyped(&self) -> SocketHandle {
+                self.0
+            }
+        }
+
+        impl From<$handle> for SocketHandle {
+            fn from(handle: $handle) -> SocketHandle {
+                handle.0
+            }
+        }
+
+        impl fmt::Display for $handle {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+    }
+}
+
 macro_rules! from_socket {
-    ($socket:ty, $variant:ident) => {
-        impl<'a, 'b> AnySocket<'a, 'b> for $socket {
+    ($socket:ty, $variant:ident, $handle:ident) => {
+        impl<'a, 'b: 'a> AnySocket<'a, 'b> for $socket {
+            type Handle = $handle;
+
             fn downcast<'c>(ref_: SocketRef<'c, Socket<'a, 'b>>) ->
                            Option<SocketRef<'c, Self>> {
                 match SocketRef::into_inner(ref_) {
@@ -143,14 +320,51 @@ macro_rules! from_socket {
                 }
             }
         }
+
+        impl<'a, 'b: 'a> TypedHandle<'a, 'b> for $handle {
+            type Socket = $socket;
+
+            fn from_untyped(handle: SocketHandle) -> $handle {
+                $handle(handle)
+            }
+        }
     }
 }
 
 #[cfg(feature = "socket-raw")]
-from_socket!(RawSocket<'a, 'b>, Raw);
-#[cfg(all(feature = "socket-icmp", feature = "proto-ipv4"))]
-from_socket!(IcmpSocket<'a, 'b>, Icmp);
+typed_handle!(
+    /// A handle, identifying a raw socket in a set.
+    RawSocketHandle);
+#[cfg(all(feature = "socket-icmp", any(feature = "proto-ipv4", feature = "proto-ipv6")))]
+typed_handle!(
+    /// A handle, identifying an ICMP socket in a set.
+    IcmpSocketHandle);
+#[cfg(feature = "socket-udp")]
+typed_handle!(
+    /// A handle, identifying an UDP socket in a set.
+    UdpSocketHandle);
+#[cfg(feature = "socket-tcp")]
+typed_handle!(
+    /// A handle, identifying a TCP socket in a set.
+    TcpSocketHandle);
+#[cfg(feature = "socket-dns")]
+typed_handle!(
+    /// A handle, identifying a DNS socket in a set.
+    DnsSocketHandle);
+#[cfg(feature = "socket-packet")]
+typed_handle!(
+    /// A handle, identifying a packet socket in a set.
+    PacketSocketHandle);
+
+#[cfg(feature = "socket-raw")]
+from_socket!(RawSocket<'a, 'b>, Raw, RawSocketHandle);
+#[cfg(all(feature = "socket-icmp", any(feature = "proto-ipv4", feature = "proto-ipv6")))]
+from_socket!(IcmpSocket<'a, 'b>, Icmp, IcmpSocketHandle);
 #[cfg(feature = "socket-udp")]
-from_socket!(UdpSocket<'a, 'b>, Udp);
+from_socket!(UdpSocket<'a, 'b>, Udp, UdpSocketHandle);
 #[cfg(feature = "socket-tcp")]
-from_socket!(TcpSocket<'a>, Tcp);
+from_socket!(TcpSocket<'a>, Tcp, TcpSocketHandle);
+#[cfg(feature = "socket-dns")]
+from_socket!(DnsSocket<'a>, Dns, DnsSocketHandle);
+#[cfg(feature = "socket-packet")]
+from_socket!(PacketSocket<'a, 'b>, Packet, PacketSocketHandle);
@@ -0,0 +1,513 @@
+use core::cmp::min;
+#[cfg(feature = "async")]
+use core::task::Waker;
+
+use {Error, Result};
+use wire::{EthernetAddress, EthernetProtocol, EthernetFrame, WolRepr};
+use time::Instant;
+use socket::{Socket, SocketMeta, SocketHandle, PacketFilter};
+#[cfg(feature = "async")]
+use socket::WakerRegistration;
+use storage::{self, RingBuffer, BufferStats};
+
+/// A buffered Ethernet frame, along with the time it was received at, in milliseconds,
+/// and its hardware timestamp, if any.
+pub type PacketBuffer<'a> = storage::PacketBuffer<'a, (u64, Option<u64>)>;
+
+/// An Ethernet frame ring buffer.
+pub type SocketBuffer<'a, 'b: 'a> = RingBuffer<'a, PacketBuffer<'b>>;
+
+/// A link-layer packet socket.
+///
+/// A packet socket is bound to a specific EtherType, and owns transmit and receive
+/// frame buffers. It sends and receives entire Ethernet frames, bypassing the IP layer,
+/// so that protocols the stack does not implement can be handled by the application.
+///
+/// Received frames are copied to every packet socket bound to their EtherType whose
+/// [filter] accepts them and whose receive buffer has room for them, if they are sent to
+/// the hardware address of the interface, to the broadcast address, or to any multicast
+/// address, and are then processed by the interface as usual.
+///
+/// [filter]: #method.set_filter
+#[derive(Debug)]
+pub struct PacketSocket<'a, 'b: 'a> {
+    pub(crate) meta: SocketMeta,
+    ethertype: EthernetProtocol,
+    rx_buffer: SocketBuffer<'a, 'b>,
+    tx_buffer: SocketBuffer<'a, 'b>,
+    tx_timestamp: Option<u64>,
+    hw_timestamping: bool,
+    tx_hw_timestamp: Option<u64>,
+    filter: Option<PacketFilter<'a>>,
+    #[cfg(feature = "async")]
+    rx_waker: WakerRegistration,
+    #[cfg(feature = "async")]
+    tx_waker: WakerRegistration,
+}
+
+impl<'a, 'b> PacketSocket<'a, 'b> {
+    /// Create a packet socket bound to the given EtherType, with the given buffers.
+    pub fn new(ethertype: EthernetProtocol,
+               rx_buffer: SocketBuffer<'a, 'b>,
+               tx_buffer: SocketBuffer<'a, 'b>) -> PacketSocket<'a, 'b> {
+        PacketSocket {
+            meta: SocketMeta::default(),
+            ethertype,
+            rx_buffer,
+            tx_buffer,
+            tx_timestamp: None,
+            hw_timestamping: false,
+            tx_hw_timestamp: None,
+            filter: None,
+            #[cfg(feature = "async")]
+            rx_waker: WakerRegistration::new(),
+            #[cfg(feature = "async")]
+            tx_waker: WakerRegistration::new(),
+        }
+    }
+
+    /// Return the socket handle.
+    #[inline]
+    pub fn handle(&self) -> SocketHandle {
+        self.meta.handle
+    }
+
+    /// Return the EtherType the socket is bound to.
+    #[inline]
+    pub fn ethertype(&self) -> EthernetProtocol {
+        self.ethertype
+    }
+
+    /// Register a waker to be woken once a frame is received.
+    ///
+    /// Only one waker is registered at a time, and it is woken at most once; it has to be
+    /// registered again to be woken again. The waker may be woken spuriously.
+    #[cfg(feature = "async")]
+    pub fn register_recv_waker(&mut self, waker: &Waker) {
+        self.rx_waker.register(waker)
+    }
+
+    /// Register a waker to be woken once a frame is sent, freeing space in the
+    /// transmit buffer.
+    ///
+    /// See also [register_recv_waker](#method.register_recv_waker).
+    #[cfg(feature = "async")]
+    pub fn register_send_waker(&mut self, waker: &Waker) {
+        self.tx_waker.register(waker)
+    }
+
+    /// Return the filter of the received frames.
+    ///
+    /// See also the [set_filter](#method.set_filter) method
+    pub fn filter(&self) -> Option<&PacketFilter<'a>> {
+        self.filter.as_ref()
+    }
+
+    /// Set the filter of the received frames, similar to `SO_ATTACH_FILTER`.
+    ///
+    /// The filter runs over each frame, starting with the Ethernet header, and the frames
+    /// it rejects are not enqueued, so that uninteresting traffic does not fill up
+    /// the receive buffer.
+    pub fn set_filter(&mut self, filter: Option<PacketFilter<'a>>) {
+        self.filter = filter
+    }
+
+    /// Return whether the frames sent are timestamped in hardware.
+    ///
+    /// See also the [set_hw_timestamping](#method.set_hw_timestamping) method.
+    pub fn hw_timestamping(&self) -> bool {
+        self.hw_timestamping
+    }
+
+    /// Set whether the frames sent are timestamped in hardware, if the device supports it,
+    /// e.g. so that a PTP master can send the time a Sync message left at in a Follow_Up
+    /// message; see [tx_hw_timestamp](#method.tx_hw_timestamp). The default is not to.
+    ///
+    /// Received frames are timestamped in hardware regardless, if the device supports it.
+    pub fn set_hw_timestamping(&mut self, hw_timestamping: bool) {
+        self.hw_timestamping = hw_timestamping
+    }
+
+    /// Check whether the transmit buffer is full.
+    #[inline]
+    pub fn can_send(&self) -> bool {
+        !self.tx_buffer.is_full()
+    }
+
+    /// Check whether the receive buffer is not empty.
+    #[inline]
+    pub fn can_recv(&self) -> bool {
+        !self.rx_buffer.is_empty()
+    }
+
+    /// Return the occupancy statistics of the transmit buffer, in packets.
+    pub fn send_buffer_stats(&self) -> BufferStats {
+        self.tx_buffer.stats()
+    }
+
+    /// Return the occupancy statistics of the receive buffer, in packets.
+    pub fn recv_buffer_stats(&self) -> BufferStats {
+        self.rx_buffer.stats()
+    }
+
+    /// Restart the occupancy statistics of both buffers from their current contents.
+    pub fn reset_buffer_stats(&mut self) {
+        self.tx_buffer.reset_stats();
+        self.rx_buffer.reset_stats();
+    }
+
+    /// Enqueue a frame to send, and return a pointer to it.
+    ///
+    /// The frame includes the Ethernet header, and is transmitted as it is; in particular,
+    /// its source address should be the hardware address of the interface.
+    ///
+    /// This function returns `Err(Error::Exhausted)` if the transmit buffer is full,
+    /// and `Err(Error::Truncated)` if the size is greater than the transmit frame
+    /// buffer size.
+    ///
+    /// If the buffer is filled in a way that does not match the socket's EtherType,
+    /// the frame will be silently dropped.
+    pub fn send(&mut self, size: usize) -> Result<&mut [u8]> {
+        let packet_buf = self.tx_buffer.enqueue_one_with(|buf| buf.resize(size))?;
+        net_trace!("{}:{}: buffer to send {} octets",
+                   self.meta.handle, self.ethertype, packet_buf.len());
+        Ok(packet_buf.payload_mut())
+    }
+
+    /// Enqueue a frame to send, and fill it from a slice.
+    ///
+    /// See also [send](#method.send).
+    pub fn send_slice(&mut self, data: &[u8]) -> Result<()> {
+        self.send(data.len())?.copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Enqueue a Wake-on-LAN magic packet, broadcast in a frame from the given
+    /// hardware address, which should be that of the interface.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the socket is not bound to
+    /// the Wake-on-LAN EtherType, and the errors of [send](#method.send) otherwise.
+    pub fn send_magic_packet(&mut self, src_addr: EthernetAddress,
+                             repr: &WolRepr) -> Result<()> {
+        if self.ethertype != EthernetProtocol::WakeOnLan { return Err(Error::Illegal) }
+
+        let size = EthernetFrame::<&[u8]>::buffer_len(repr.buffer_len());
+        let mut frame = EthernetFrame::new(self.send(size)?);
+        frame.set_dst_addr(EthernetAddress::BROADCAST);
+        frame.set_src_addr(src_addr);
+        frame.set_ethertype(EthernetProtocol::WakeOnLan);
+        repr.emit(frame.payload_mut());
+        Ok(())
+    }
+
+    /// Return the time at which the last frame was handed to the interface for
+    /// transmission, if any.
+    pub fn tx_timestamp(&self) -> Option<Instant> {
+        self.tx_timestamp.map(|timestamp| Instant::from_millis(timestamp as i64))
+    }
+
+    /// Return the hardware timestamp, in nanoseconds on the clock of the device,
+    /// of the last frame sent, if hardware timestamping is enabled.
+    ///
+    /// The timestamp is cleared when a frame is handed to the device, and set
+    /// once the device returns it, when the interface is polled later.
+    pub fn tx_hw_timestamp(&self) -> Option<u64> {
+        self.tx_hw_timestamp
+    }
+
+    /// Dequeue a frame, and return a pointer to it.
+    ///
+    /// The frame includes the Ethernet header.
+    ///
+    /// This function returns `Err(Error::Exhausted)` if the receive buffer is empty.
+    pub fn recv(&mut self) -> Result<&[u8]> {
+        let (frame, _) = self.recv_with_timestamp()?;
+        Ok(frame)
+    }
+
+    /// Dequeue a frame, and return a pointer to it as well as the time at which
+    /// it was received by the interface.
+    ///
+    /// See also [recv](#method.recv).
+    pub fn recv_with_timestamp(&mut self) -> Result<(&[u8], Instant)> {
+        self.rx_buffer.release_dequeued();
+        let packet_buf = self.rx_buffer.dequeue_one()?;
+        net_trace!("{}:{}: receive {} buffered octets",
+                   self.meta.handle, self.ethertype, packet_buf.len());
+        let (timestamp, _) = *packet_buf.metadata();
+        Ok((packet_buf.payload(), Instant::from_millis(timestamp as i64)))
+    }
+
+    /// Dequeue a frame, and return a pointer to it as well as the time, in nanoseconds
+    /// on the clock of the device, at which it was received, if the device timestamps
+    /// frames in hardware.
+    ///
+    /// See also [recv](#method.recv).
+    pub fn recv_with_hw_timestamp(&mut self) -> Result<(&[u8], Option<u64>)> {
+        self.rx_buffer.release_dequeued();
+        let packet_buf = self.rx_buffer.dequeue_one()?;
+        net_trace!("{}:{}: receive {} buffered octets",
+                   self.meta.handle, self.ethertype, packet_buf.len());
+        let (_, hw_timestamp) = *packet_buf.metadata();
+        Ok((packet_buf.payload(), hw_timestamp))
+    }
+
+    /// Dequeue a frame, and copy it into the given slice.
+    ///
+    /// See also [recv](#method.recv).
+    pub fn recv_slice(&mut self, data: &mut [u8]) -> Result<usize> {
+        let buffer = self.recv()?;
+        let length = min(data.len(), buffer.len());
+        data[..length].copy_from_slice(&buffer[..length]);
+        Ok(length)
+    }
+
+    pub(crate) fn accepts<T: AsRef<[u8]> + ?Sized>(&self, frame: &EthernetFrame<&T>) -> bool {
+        frame.ethertype() == self.ethertype
+    }
+
+    pub(crate) fn process(&mut self, timestamp: u64, hw_timestamp: Option<u64>,
+                          frame: &[u8]) -> Result<()> {
+        debug_assert!(self.accepts(&EthernetFrame::new(frame)));
+
+        if let Some(ref filter) = self.filter {
+            if !filter.matches(frame, &[]) {
+                net_trace!("{}:{}: filtering out {} octets",
+                           self.meta.handle, self.ethertype, frame.len());
+                return Err(Error::Dropped)
+            }
+        }
+
+        self.rx_buffer.release_dequeued();
+        let packet_buf = self.rx_buffer.enqueue_one_with(|buf| buf.resize(frame.len()))?;
+        packet_buf.payload_mut().copy_from_slice(frame);
+        *packet_buf.metadata_mut() = (timestamp, hw_timestamp);
+        net_trace!("{}:{}: receiving {} octets",
+                   self.meta.handle, self.ethertype, packet_buf.len());
+        #[cfg(feature = "async")]
+        self.rx_waker.wake();
+        Ok(())
+    }
+
+    pub(crate) fn dispatch<F>(&mut self, timestamp: u64, emit: F) -> Result<()>
+            where F: FnOnce(&[u8]) -> Result<()> {
+        let handle    = self.meta.handle;
+        let ethertype = self.ethertype;
+        self.tx_buffer.dequeue_one_with(|packet_buf| {
+            let frame = packet_buf.payload();
+            match EthernetFrame::new_checked(frame) {
+                Ok(ref checked) if checked.ethertype() == ethertype => {
+                    net_trace!("{}:{}: sending {} octets",
+                               handle, ethertype, frame.len());
+                    emit(frame)
+                }
+                Ok(_) => {
+                    net_debug!("{}:{}: dropping outgoing frame (mismatched EtherType)",
+                               handle, ethertype);
+                    // Return Ok(()) so the frame is dequeued.
+                    Ok(())
+                }
+                Err(error) => {
+                    net_debug!("{}:{}: dropping outgoing frame ({})",
+                               handle, ethertype, error);
+                    Ok(())
+                }
+            }
+        })?;
+        self.tx_buffer.release_dequeued();
+        self.tx_timestamp = Some(timestamp);
+        if self.hw_timestamping { self.tx_hw_timestamp = None }
+        #[cfg(feature = "async")]
+        self.tx_waker.wake();
+        Ok(())
+    }
+
+    pub(crate) fn set_tx_hw_timestamp(&mut self, hw_timestamp: u64) {
+        self.tx_hw_timestamp = Some(hw_timestamp)
+    }
+
+    pub(crate) fn poll_at(&self) -> Option<u64> {
+        if self.tx_buffer.is_empty() {
+            None
+        } else {
+            Some(0)
+        }
+    }
+}
+
+impl<'a, 'b> Into<Socket<'a, 'b>> for PacketSocket<'a, 'b> {
+    fn into(self) -> Socket<'a, 'b> {
+        Socket::Packet(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ETHERTYPE: EthernetProtocol = EthernetProtocol::Ptp;
+
+    static FRAME_BYTES: [u8; 18] = [
+        0x01, 0x1b, 0x19, 0x00, 0x00, 0x00,
+        0x02, 0x02, 0x02, 0x02, 0x02, 0x02,
+        0x88, 0xf7,
+        0xaa, 0x00, 0x00, 0xff
+    ];
+
+    fn buffer(packets: usize) -> SocketBuffer<'static, 'static> {
+        let mut storage = vec![];
+        for _ in 0..packets {
+            storage.push(PacketBuffer::new(vec![0; 32]))
+        }
+        SocketBuffer::new(storage)
+    }
+
+    fn socket(rx_buffer: SocketBuffer<'static, 'static>,
+              tx_buffer: SocketBuffer<'static, 'static>) -> PacketSocket<'static, 'static> {
+        PacketSocket::new(ETHERTYPE, rx_buffer, tx_buffer)
+    }
+
+    #[test]
+    fn test_send_truncated() {
+        let mut socket = socket(buffer(0), buffer(1));
+        assert_eq!(socket.send_slice(&[0; 64][..]), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn test_send_dispatch() {
+        let mut socket = socket(buffer(0), buffer(1));
+
+        assert!(socket.can_send());
+        assert_eq!(socket.dispatch(0, |_| unreachable!()), Err(Error::Exhausted));
+
+        assert_eq!(socket.send_slice(&FRAME_BYTES[..]), Ok(()));
+        assert_eq!(socket.send_slice(b""), Err(Error::Exhausted));
+        assert!(!socket.can_send());
+
+        assert_eq!(socket.dispatch(0, |frame| {
+            assert_eq!(frame, &FRAME_BYTES[..]);
+            Err(Error::Unaddressable)
+        }), Err(Error::Unaddressable));
+        assert!(!socket.can_send());
+
+        assert_eq!(socket.dispatch(0, |frame| {
+            assert_eq!(frame, &FRAME_BYTES[..]);
+            Ok(())
+        }), Ok(()));
+        assert!(socket.can_send());
+    }
+
+    #[test]
+    fn test_send_magic_packet() {
+        let repr = WolRepr { target: EthernetAddress([0x02, 0, 0, 0, 0, 2]), password: None };
+        let src_addr = EthernetAddress([0x02, 0, 0, 0, 0, 1]);
+
+        let mut socket = socket(buffer(0), buffer(1));
+        assert_eq!(socket.send_magic_packet(src_addr, &repr), Err(Error::Illegal));
+
+        let tx_buffer = SocketBuffer::new(vec![PacketBuffer::new(vec![0; 128])]);
+        let mut socket = PacketSocket::new(EthernetProtocol::WakeOnLan, buffer(0), tx_buffer);
+        assert_eq!(socket.send_magic_packet(src_addr, &repr), Ok(()));
+        assert_eq!(socket.dispatch(0, |frame| {
+            let frame = EthernetFrame::new(frame);
+            assert_eq!(frame.dst_addr(), EthernetAddress::BROADCAST);
+            assert_eq!(frame.src_addr(), src_addr);
+            assert_eq!(WolRepr::parse(frame.payload()), Ok(repr));
+            Ok(())
+        }), Ok(()));
+    }
+
+    #[test]
+    fn test_send_illegal() {
+        let mut socket = socket(buffer(0), buffer(1));
+
+        let mut wrong_ethertype = FRAME_BYTES.clone();
+        EthernetFrame::new(&mut wrong_ethertype[..]).set_ethertype(EthernetProtocol::Ipv4);
+        assert_eq!(socket.send_slice(&wrong_ethertype[..]), Ok(()));
+        assert_eq!(socket.dispatch(0, |_| unreachable!()), Ok(()));
+
+        assert_eq!(socket.send_slice(&FRAME_BYTES[..4]), Ok(()));
+        assert_eq!(socket.dispatch(0, |_| unreachable!()), Ok(()));
+    }
+
+    #[test]
+    fn test_recv_process() {
+        let mut socket = socket(buffer(1), buffer(0));
+        assert!(!socket.can_recv());
+        assert_eq!(socket.recv(), Err(Error::Exhausted));
+
+        let frame = EthernetFrame::new(&FRAME_BYTES[..]);
+        assert_eq!(frame.dst_addr(), EthernetAddress([0x01, 0x1b, 0x19, 0x00, 0x00, 0x00]));
+        assert!(socket.accepts(&frame));
+        assert_eq!(socket.process(0, None, &FRAME_BYTES[..]), Ok(()));
+        assert!(socket.can_recv());
+        assert_eq!(socket.process(0, None, &FRAME_BYTES[..]), Err(Error::Exhausted));
+
+        let mut data = [0; 16];
+        assert_eq!(socket.recv_slice(&mut data[..]), Ok(16));
+        assert_eq!(&data[..], &FRAME_BYTES[..16]);
+        assert!(!socket.can_recv());
+    }
+
+    #[test]
+    fn test_filter() {
+        use socket::FilterInstruction as Insn;
+
+        // Accept the frames sent to a multicast address.
+        static PROGRAM: [Insn; 4] = [
+            Insn::new(Insn::LD | Insn::B | Insn::ABS, 0, 0, 0),
+            Insn::new(Insn::JMP | Insn::JSET | Insn::K, 0, 1, 0x01),
+            Insn::new(Insn::RET | Insn::K, 0, 0, 0xffff),
+            Insn::new(Insn::RET | Insn::K, 0, 0, 0),
+        ];
+        let mut socket = socket(buffer(1), buffer(0));
+        socket.set_filter(Some(PacketFilter::new(&PROGRAM).unwrap()));
+
+        let mut unicast = FRAME_BYTES.clone();
+        unicast[0] = 0x02;
+        assert_eq!(socket.process(0, None, &unicast[..]), Err(Error::Dropped));
+        assert!(!socket.can_recv());
+        assert_eq!(socket.process(0, None, &FRAME_BYTES[..]), Ok(()));
+        assert_eq!(socket.recv(), Ok(&FRAME_BYTES[..]));
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let mut socket = socket(buffer(1), buffer(1));
+
+        assert_eq!(socket.process(1_000, None, &FRAME_BYTES[..]), Ok(()));
+        assert_eq!(socket.recv_with_timestamp(),
+                   Ok((&FRAME_BYTES[..], Instant::from_millis(1_000))));
+
+        assert_eq!(socket.tx_timestamp(), None);
+        assert_eq!(socket.send_slice(&FRAME_BYTES[..]), Ok(()));
+        assert_eq!(socket.dispatch(2_000, |_| Ok(())), Ok(()));
+        assert_eq!(socket.tx_timestamp(), Some(Instant::from_millis(2_000)));
+    }
+
+    #[test]
+    fn test_hw_timestamps() {
+        let mut socket = socket(buffer(1), buffer(1));
+
+        assert_eq!(socket.process(1_000, Some(1_000_000_123), &FRAME_BYTES[..]), Ok(()));
+        assert_eq!(socket.recv_with_hw_timestamp(),
+                   Ok((&FRAME_BYTES[..], Some(1_000_000_123))));
+
+        socket.set_hw_timestamping(true);
+        socket.set_tx_hw_timestamp(1);
+        assert_eq!(socket.send_slice(&FRAME_BYTES[..]), Ok(()));
+        assert_eq!(socket.dispatch(2_000, |_| Ok(())), Ok(()));
+        assert_eq!(socket.tx_hw_timestamp(), None);
+        socket.set_tx_hw_timestamp(2_000_000_456);
+        assert_eq!(socket.tx_hw_timestamp(), Some(2_000_000_456));
+    }
+
+    #[test]
+    fn test_doesnt_accept_wrong_ethertype() {
+        let socket = socket(buffer(1), buffer(0));
+
+        let mut wrong_ethertype = FRAME_BYTES.clone();
+        EthernetFrame::new(&mut wrong_ethertype[..]).set_ethertype(EthernetProtocol::Arp);
+        assert!(!socket.accepts(&EthernetFrame::new(&wrong_ethertype[..])));
+    }
+}
@@ -0,0 +1,413 @@
+use managed::ManagedSlice;
+
+use {Error, Result};
+use phy::ChecksumCapabilities;
+use time::Duration;
+use wire::IpAddress;
+#[cfg(feature = "proto-ipv4")]
+use wire::{Icmpv4Packet, Icmpv4Repr};
+#[cfg(feature = "proto-ipv6")]
+use wire::{Icmpv6Packet, Icmpv6Repr};
+use super::{SocketSet, SocketHandle, IcmpSocket, IcmpEndpoint};
+
+/// An event reported by a pinger.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Event {
+    /// An echo reply was received for the echo request with the given sequence number,
+    /// carrying `len` octets of data, after the given round-trip time.
+    Reply {
+        seq_no: u16,
+        len:    usize,
+        rtt:    Duration
+    },
+    /// No echo reply was received for the echo request with the given sequence number
+    /// before the timeout.
+    Timeout {
+        seq_no: u16
+    }
+}
+
+/// A pinger.
+///
+/// A pinger sends ICMPv4 or ICMPv6 echo requests to a remote address, depending on
+/// its version, through an ICMP socket, and matches the echo replies to the requests.
+/// The echo requests carry the identifier of the pinger and consecutive sequence
+/// numbers; the socket is bound to the identifier when the first request is sent.
+/// The pinger keeps track of the requests that are waiting for a reply, with
+/// the provided storage, and reports either a reply, with its round-trip time,
+/// or a timeout for each of them.
+///
+/// The socket is owned by a socket set; the pinger only refers to it by its handle.
+#[derive(Debug)]
+pub struct Pinger<'a> {
+    handle:      SocketHandle,
+    remote_addr: IpAddress,
+    ident:       u16,
+    seq_no:      u16,
+    timeout:     u64,
+    sent:        u64,
+    received:    u64,
+    waiting:     ManagedSlice<'a, Option<(u16, u64)>>,
+}
+
+impl<'a> Pinger<'a> {
+    /// Create a pinger of the given remote address, using the ICMP socket with the given
+    /// handle and identifier, with the provided storage for the requests waiting for
+    /// a reply.
+    pub fn new<T, U>(handle: SocketHandle, remote_addr: T, ident: u16,
+                     waiting: U) -> Pinger<'a>
+            where T: Into<IpAddress>, U: Into<ManagedSlice<'a, Option<(u16, u64)>>> {
+        Pinger {
+            handle:      handle,
+            remote_addr: remote_addr.into(),
+            ident:       ident,
+            seq_no:      0,
+            timeout:     5_000,
+            sent:        0,
+            received:    0,
+            waiting:     waiting.into(),
+        }
+    }
+
+    /// Return the handle of the socket.
+    #[inline]
+    pub fn handle(&self) -> SocketHandle {
+        self.handle
+    }
+
+    /// Return the remote address.
+    #[inline]
+    pub fn remote_addr(&self) -> IpAddress {
+        self.remote_addr
+    }
+
+    /// Return the identifier of the echo requests.
+    #[inline]
+    pub fn ident(&self) -> u16 {
+        self.ident
+    }
+
+    /// Return the sequence number of the next echo request.
+    #[inline]
+    pub fn next_seq_no(&self) -> u16 {
+        self.seq_no
+    }
+
+    /// Return the timeout after which a request without a reply is reported.
+    ///
+    /// See also the [set_timeout](#method.set_timeout) method.
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout)
+    }
+
+    /// Set the timeout after which a request without a reply is reported.
+    ///
+    /// The default value is 5 seconds.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout.total_millis()
+    }
+
+    /// Return the number of echo requests sent.
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// Return the number of echo replies received.
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// Return the number of echo requests waiting for a reply.
+    pub fn waiting(&self) -> usize {
+        self.waiting.iter().filter(|slot| slot.is_some()).count()
+    }
+
+    /// Send an echo request with the given data, and return its sequence number.
+    ///
+    /// This function returns `Err(Error::Exhausted)` if the storage for the requests
+    /// waiting for a reply is fixed-size (not a `Vec`) and is full, as well as
+    /// the errors of [IcmpSocket::bind] and [IcmpSocket::send], e.g.
+    /// `Err(Error::Exhausted)` if the transmit buffer of the socket is full.
+    ///
+    /// # Panics
+    /// This function panics if the handle does not refer to an ICMP socket in the set.
+    ///
+    /// [IcmpSocket::bind]: struct.IcmpSocket.html#method.bind
+    /// [IcmpSocket::send]: struct.IcmpSocket.html#method.send
+    pub fn send(&mut self, sockets: &mut SocketSet, timestamp: u64,
+                checksum_caps: &ChecksumCapabilities, data: &[u8]) -> Result<u16> {
+        let index = match self.waiting.iter().position(|slot| slot.is_none()) {
+            Some(index) => index,
+            None => match self.waiting {
+                ManagedSlice::Borrowed(_) => return Err(Error::Exhausted),
+                #[cfg(any(feature = "std", feature = "alloc"))]
+                ManagedSlice::Owned(ref mut waiting) => {
+                    waiting.push(None);
+                    waiting.len() - 1
+                }
+            }
+        };
+
+        let mut socket = sockets.get::<IcmpSocket>(self.handle);
+        if !socket.is_open() {
+            socket.bind(IcmpEndpoint::Ident(self.ident))?
+        }
+
+        let seq_no = self.seq_no;
+        match self.remote_addr {
+            #[cfg(feature = "proto-ipv4")]
+            IpAddress::Ipv4(_) => {
+                let icmp_repr = Icmpv4Repr::EchoRequest {
+                    ident:  self.ident,
+                    seq_no: seq_no,
+                    data:   data
+                };
+                let payload = socket.send(icmp_repr.buffer_len(), self.remote_addr)?;
+                icmp_repr.emit(&mut Icmpv4Packet::new(payload), checksum_caps);
+            }
+            #[cfg(feature = "proto-ipv6")]
+            IpAddress::Ipv6(_) => {
+                let icmp_repr = Icmpv6Repr::EchoRequest {
+                    ident:  self.ident,
+                    seq_no: seq_no,
+                    data:   data
+                };
+                let payload = socket.send(icmp_repr.buffer_len(), self.remote_addr)?;
+                icmp_repr.emit(&mut Icmpv6Packet::new(payload), checksum_caps);
+            }
+            _ => return Err(Error::Unaddressable)
+        }
+
+        net_trace!("[{}]: sending echo request {} to {}", self.handle, seq_no, self.remote_addr);
+        self.waiting[index] = Some((seq_no, timestamp));
+        self.seq_no = seq_no.wrapping_add(1);
+        self.sent += 1;
+        Ok(seq_no)
+    }
+
+    /// Receive the echo replies queued in the socket, discard the packets that are not
+    /// a reply to a waiting request, and return the next event, if any.
+    ///
+    /// This function should be called until it returns `None` whenever the interface
+    /// has been polled.
+    ///
+    /// # Panics
+    /// This function panics if the handle does not refer to an ICMP socket in the set.
+    pub fn poll(&mut self, sockets: &mut SocketSet, timestamp: u64,
+                checksum_caps: &ChecksumCapabilities) -> Option<Event> {
+        let mut socket = sockets.get::<IcmpSocket>(self.handle);
+        while socket.can_recv() {
+            let reply = match socket.recv() {
+                Ok((payload, addr)) if addr == self.remote_addr =>
+                    Self::parse_reply(self.ident, payload, addr, checksum_caps),
+                _ => None
+            };
+            let (seq_no, len) = match reply {
+                Some(reply) => reply,
+                None => continue
+            };
+
+            let slot = self.waiting.iter_mut().find(|slot| {
+                match **slot {
+                    Some((waiting_seq_no, _)) => waiting_seq_no == seq_no,
+                    None => false
+                }
+            });
+            if let Some(slot) = slot {
+                let (_, sent_at) = slot.take().unwrap();
+                self.received += 1;
+                return Some(Event::Reply {
+                    seq_no: seq_no,
+                    len:    len,
+                    rtt:    Duration::from_millis(timestamp.saturating_sub(sent_at))
+                })
+            }
+        }
+
+        let timeout = self.timeout;
+        for slot in self.waiting.iter_mut() {
+            match *slot {
+                Some((seq_no, sent_at)) if sent_at + timeout <= timestamp => {
+                    *slot = None;
+                    return Some(Event::Timeout { seq_no: seq_no })
+                }
+                _ => ()
+            }
+        }
+        None
+    }
+
+    /// Return the time at which the next request waiting for a reply times out.
+    pub fn poll_at(&self) -> Option<u64> {
+        self.waiting.iter()
+            .filter_map(|slot| slot.map(|(_, sent_at)| sent_at + self.timeout))
+            .min()
+    }
+
+    /// Return the sequence number and the data length of an echo reply with the given
+    /// identifier.
+    fn parse_reply(ident: u16, payload: &[u8], addr: IpAddress,
+                   checksum_caps: &ChecksumCapabilities) -> Option<(u16, usize)> {
+        match addr {
+            #[cfg(feature = "proto-ipv4")]
+            IpAddress::Ipv4(_) => {
+                let packet = Icmpv4Packet::new_checked(payload).ok()?;
+                match Icmpv4Repr::parse(&packet, checksum_caps).ok()? {
+                    Icmpv4Repr::EchoReply { ident: reply_ident, seq_no, data }
+                            if reply_ident == ident =>
+                        Some((seq_no, data.len())),
+                    _ => None
+                }
+            }
+            #[cfg(feature = "proto-ipv6")]
+            IpAddress::Ipv6(_) => {
+                let packet = Icmpv6Packet::new_checked(payload).ok()?;
+                match Icmpv6Repr::parse(&packet, checksum_caps).ok()? {
+                    Icmpv6Repr::EchoReply { ident: reply_ident, seq_no, data }
+                            if reply_ident == ident =>
+                        Some((seq_no, data.len())),
+                    _ => None
+                }
+            }
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use phy::DeviceCapabilities;
+    use wire::{IpRepr, IpProtocol, IcmpRepr};
+    use socket::{IcmpPacketBuffer, IcmpSocketBuffer};
+    #[cfg(feature = "proto-ipv4")]
+    use wire::{Ipv4Address, Ipv4Repr};
+    #[cfg(feature = "proto-ipv6")]
+    use wire::{Ipv6Address, Ipv6Repr};
+    use super::*;
+
+    const IDENT: u16 = 0x22b;
+
+    fn socket_set() -> (SocketSet<'static, 'static, 'static>, SocketHandle) {
+        let mut sockets = SocketSet::new(vec![]);
+        let rx_buffer = IcmpSocketBuffer::new(vec![IcmpPacketBuffer::new(vec![0; 64]),
+                                                   IcmpPacketBuffer::new(vec![0; 64])]);
+        let tx_buffer = IcmpSocketBuffer::new(vec![IcmpPacketBuffer::new(vec![0; 64]),
+                                                   IcmpPacketBuffer::new(vec![0; 64])]);
+        let handle = sockets.add(IcmpSocket::new(rx_buffer, tx_buffer));
+        (sockets, handle)
+    }
+
+    fn dispatch(sockets: &mut SocketSet, handle: SocketHandle) -> IpRepr {
+        let caps = DeviceCapabilities::default();
+        let mut ip_repr = None;
+        sockets.get::<IcmpSocket>(handle).dispatch(&caps, |(repr, _)| {
+            ip_repr = Some(repr);
+            Ok(())
+        }).unwrap();
+        ip_repr.unwrap()
+    }
+
+    fn reply(sockets: &mut SocketSet, handle: SocketHandle, ip_repr: IpRepr,
+             icmp_repr: IcmpRepr) {
+        let caps = DeviceCapabilities::default();
+        let mut socket = sockets.get::<IcmpSocket>(handle);
+        assert!(socket.accepts(&ip_repr, &icmp_repr, &caps.checksum));
+        socket.process(&ip_repr, &icmp_repr, &caps.checksum).unwrap();
+    }
+
+    #[cfg(feature = "proto-ipv4")]
+    const REMOTE_IPV4: Ipv4Address = Ipv4Address([192, 168, 1, 2]);
+
+    #[cfg(feature = "proto-ipv4")]
+    fn reply_ipv4(sockets: &mut SocketSet, handle: SocketHandle, icmp_repr: Icmpv4Repr) {
+        let ip_repr = IpRepr::Ipv4(Ipv4Repr {
+            src_addr:    REMOTE_IPV4,
+            dst_addr:    Ipv4Address([192, 168, 1, 1]),
+            protocol:    IpProtocol::Icmp,
+            payload_len: icmp_repr.buffer_len(),
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0
+        });
+        reply(sockets, handle, ip_repr, icmp_repr.into())
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_reply() {
+        let (mut sockets, handle) = socket_set();
+        let caps = DeviceCapabilities::default();
+        let mut waiting = [None; 2];
+        let mut pinger = Pinger::new(handle, REMOTE_IPV4, IDENT, &mut waiting[..]);
+
+        assert_eq!(pinger.send(&mut sockets, 1000, &caps.checksum, b"abcdef"), Ok(0));
+        assert_eq!(pinger.send(&mut sockets, 1500, &caps.checksum, b"abcdef"), Ok(1));
+        assert_eq!(pinger.send(&mut sockets, 2000, &caps.checksum, b"abcdef"),
+                   Err(Error::Exhausted));
+        assert_eq!(dispatch(&mut sockets, handle).dst_addr(), IpAddress::Ipv4(REMOTE_IPV4));
+        assert_eq!(pinger.waiting(), 2);
+        assert_eq!(pinger.poll_at(), Some(6000));
+
+        // Replies with another identifier, or to requests that are not waiting,
+        // are discarded.
+        reply_ipv4(&mut sockets, handle,
+                   Icmpv4Repr::EchoReply { ident: IDENT, seq_no: 5, data: b"abcdef" });
+        reply_ipv4(&mut sockets, handle,
+                   Icmpv4Repr::EchoReply { ident: IDENT, seq_no: 1, data: b"abcdef" });
+        assert_eq!(pinger.poll(&mut sockets, 1520, &caps.checksum),
+                   Some(Event::Reply { seq_no: 1, len: 6, rtt: Duration::from_millis(20) }));
+        assert_eq!(pinger.poll(&mut sockets, 1520, &caps.checksum), None);
+        assert_eq!(pinger.sent(), 2);
+        assert_eq!(pinger.received(), 1);
+        assert_eq!(pinger.waiting(), 1);
+        assert_eq!(pinger.poll_at(), Some(6000));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_timeout() {
+        let (mut sockets, handle) = socket_set();
+        let caps = DeviceCapabilities::default();
+        let mut pinger = Pinger::new(handle, REMOTE_IPV4, IDENT, vec![]);
+        pinger.set_timeout(Duration::from_millis(1000));
+
+        assert_eq!(pinger.send(&mut sockets, 0, &caps.checksum, &[]), Ok(0));
+        assert_eq!(pinger.poll(&mut sockets, 999, &caps.checksum), None);
+        assert_eq!(pinger.poll(&mut sockets, 1000, &caps.checksum),
+                   Some(Event::Timeout { seq_no: 0 }));
+        assert_eq!(pinger.poll_at(), None);
+
+        // A reply after the timeout is discarded.
+        reply_ipv4(&mut sockets, handle,
+                   Icmpv4Repr::EchoReply { ident: IDENT, seq_no: 0, data: &[] });
+        assert_eq!(pinger.poll(&mut sockets, 1100, &caps.checksum), None);
+        assert_eq!(pinger.received(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv6")]
+    fn test_reply_ipv6() {
+        let (mut sockets, handle) = socket_set();
+        let caps = DeviceCapabilities::default();
+        let remote_addr = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 2);
+        let mut pinger = Pinger::new(handle, remote_addr, IDENT, vec![]);
+
+        assert_eq!(pinger.send(&mut sockets, 1000, &caps.checksum, b"abcdef"), Ok(0));
+        assert_eq!(dispatch(&mut sockets, handle).dst_addr(), IpAddress::Ipv6(remote_addr));
+
+        let icmp_repr = Icmpv6Repr::EchoReply { ident: IDENT, seq_no: 0, data: b"abcdef" };
+        let ip_repr = IpRepr::Ipv6(Ipv6Repr {
+            src_addr:    remote_addr,
+            dst_addr:    Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1),
+            next_header: IpProtocol::Icmpv6,
+            payload_len: icmp_repr.buffer_len(),
+            hop_limit:   64,
+            flow_label:  0,
+            ecn:         0,
+            dscp:        0
+        });
+        reply(&mut sockets, handle, ip_repr, icmp_repr.into());
+        assert_eq!(pinger.poll(&mut sockets, 1042, &caps.checksum),
+                   Some(Event::Reply { seq_no: 0, len: 6, rtt: Duration::from_millis(42) }));
+    }
+}
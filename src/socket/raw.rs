@@ -1,56 +1,25 @@
 use core::cmp::min;
-use managed::Managed;
+#[cfg(feature = "async")]
+use core::task::Waker;
 
 use {Error, Result};
-use phy::ChecksumCapabilities;
+use phy::{ChecksumCapabilities, RxLoan};
 use wire::{IpVersion, IpRepr, IpProtocol};
 #[cfg(feature = "proto-ipv4")]
 use wire::{Ipv4Repr, Ipv4Packet};
 #[cfg(feature = "proto-ipv6")]
 use wire::{Ipv6Repr, Ipv6Packet};
-use socket::{Socket, SocketMeta, SocketHandle};
-use storage::{Resettable, RingBuffer};
+use socket::{Socket, SocketMeta, SocketHandle, PacketFilter};
+#[cfg(feature = "async")]
+use socket::WakerRegistration;
+use storage::{self, RingBuffer, BufferStats};
 
-/// A buffered raw IP packet.
-#[derive(Debug)]
-pub struct PacketBuffer<'a> {
-    size:    usize,
-    payload: Managed<'a, [u8]>,
-}
-
-impl<'a> PacketBuffer<'a> {
-    /// Create a buffered packet.
-    pub fn new<T>(payload: T) -> PacketBuffer<'a>
-            where T: Into<Managed<'a, [u8]>> {
-        PacketBuffer {
-            size:    0,
-            payload: payload.into(),
-        }
-    }
-
-    fn as_ref<'b>(&'b self) -> &'b [u8] {
-        &self.payload[..self.size]
-    }
-
-    fn as_mut<'b>(&'b mut self) -> &'b mut [u8] {
-        &mut self.payload[..self.size]
-    }
-
-    fn resize<'b>(&'b mut self, size: usize) -> Result<&'b mut Self> {
-        if self.payload.len() >= size {
-            self.size = size;
-            Ok(self)
-        } else {
-            Err(Error::Truncated)
-        }
-    }
-}
+/// The length of the longest IP header emitted from a representation.
+const MAX_HEADER_LEN: usize = 40;
 
-impl<'a> Resettable for PacketBuffer<'a> {
-    fn reset(&mut self) {
-        self.size = 0;
-    }
-}
+/// A buffered raw IP packet, along with the loan of the packet if it was left
+/// in a buffer of the device rather than copied.
+pub type PacketBuffer<'a> = storage::PacketBuffer<'a, Option<RxLoan>>;
 
 /// A raw IP packet ring buffer.
 pub type SocketBuffer<'a, 'b: 'a> = RingBuffer<'a, PacketBuffer<'b>>;
@@ -59,6 +28,20 @@ pub type SocketBuffer<'a, 'b: 'a> = RingBuffer<'a, PacketBuffer<'b>>;
 ///
 /// A raw socket is bound to a specific IP protocol, and owns
 /// transmit and receive packet buffers.
+///
+/// The packets sent and received by a raw socket include the IP header. By default,
+/// that header is parsed and reserialized, so only the fields the stack understands
+/// are kept; in [header included] mode, packets are passed through as they are.
+/// Either way, the interface routes outgoing packets and resolves the hardware address
+/// of their destination.
+///
+/// Every received packet is copied to all the raw sockets bound to its IP version and
+/// protocol whose [filter] accepts it and whose receive buffer has room for it, and is
+/// then processed by the interface as usual, so that e.g. a socket capturing all traffic
+/// can coexist with one that handles a protocol.
+///
+/// [header included]: #method.set_header_included
+/// [filter]: #method.set_filter
 #[derive(Debug)]
 pub struct RawSocket<'a, 'b: 'a> {
     pub(crate) meta: SocketMeta,
@@ -66,6 +49,18 @@ pub struct RawSocket<'a, 'b: 'a> {
     ip_protocol: IpProtocol,
     rx_buffer:   SocketBuffer<'a, 'b>,
     tx_buffer:   SocketBuffer<'a, 'b>,
+    /// The time-to-live (IPv4) or hop limit (IPv6) value that overrides the one
+    /// in outgoing packets.
+    hop_limit:   Option<u8>,
+    /// Whether packets are sent and received without reserializing their IP header.
+    header_included: bool,
+    /// Whether packets are left in the buffers of the device when it lends them.
+    zero_copy:   bool,
+    filter:      Option<PacketFilter<'a>>,
+    #[cfg(feature = "async")]
+    rx_waker:    WakerRegistration,
+    #[cfg(feature = "async")]
+    tx_waker:    WakerRegistration,
 }
 
 impl<'a, 'b> RawSocket<'a, 'b> {
@@ -80,6 +75,14 @@ impl<'a, 'b> RawSocket<'a, 'b> {
             ip_protocol,
             rx_buffer,
             tx_buffer,
+            hop_limit: None,
+            header_included: false,
+            zero_copy: false,
+            filter: None,
+            #[cfg(feature = "async")]
+            rx_waker: WakerRegistration::new(),
+            #[cfg(feature = "async")]
+            tx_waker: WakerRegistration::new(),
         }
     }
 
@@ -101,6 +104,104 @@ impl<'a, 'b> RawSocket<'a, 'b> {
         self.ip_protocol
     }
 
+    /// Register a waker to be woken once a packet is received.
+    ///
+    /// Only one waker is registered at a time, and it is woken at most once; it has to be
+    /// registered again to be woken again. The waker may be woken spuriously.
+    #[cfg(feature = "async")]
+    pub fn register_recv_waker(&mut self, waker: &Waker) {
+        self.rx_waker.register(waker)
+    }
+
+    /// Register a waker to be woken once a packet is sent, freeing space in the
+    /// transmit buffer.
+    ///
+    /// See also [register_recv_waker](#method.register_recv_waker).
+    #[cfg(feature = "async")]
+    pub fn register_send_waker(&mut self, waker: &Waker) {
+        self.tx_waker.register(waker)
+    }
+
+    /// Return the time-to-live (IPv4) or hop limit (IPv6) value used in outgoing packets.
+    ///
+    /// See also the [set_hop_limit](#method.set_hop_limit) method
+    pub fn hop_limit(&self) -> Option<u8> {
+        self.hop_limit
+    }
+
+    /// Set the time-to-live (IPv4) or hop limit (IPv6) value used in outgoing packets.
+    ///
+    /// A socket without an explicitly set hop limit value sends packets with the hop limit
+    /// value in their header; otherwise, that value is replaced.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if a hop limit value of 0 is given. See [RFC 1122 § 3.2.1.7].
+    ///
+    /// [RFC 1122 § 3.2.1.7]: https://tools.ietf.org/html/rfc1122#section-3.2.1.7
+    pub fn set_hop_limit(&mut self, hop_limit: Option<u8>) {
+        // A host MUST NOT send a datagram with a hop limit value of 0
+        if let Some(0) = hop_limit {
+            panic!("the time-to-live value of a packet must not be zero")
+        }
+
+        self.hop_limit = hop_limit
+    }
+
+    /// Check whether the socket is in header included mode.
+    ///
+    /// See also the [set_header_included](#method.set_header_included) method
+    pub fn header_included(&self) -> bool {
+        self.header_included
+    }
+
+    /// Set whether the socket is in header included mode, similar to `IP_HDRINCL`.
+    ///
+    /// In this mode, outgoing packets are transmitted exactly as they were enqueued,
+    /// including any IP options and the identification and flags fields, except that
+    /// an unspecified source address is replaced with an address of the interface,
+    /// the IPv4 header checksum is filled in, and the hop limit is replaced if one was
+    /// [set](#method.set_hop_limit). Incoming packets are received exactly as they
+    /// arrived.
+    pub fn set_header_included(&mut self, header_included: bool) {
+        self.header_included = header_included
+    }
+
+    /// Check whether packets are received without being copied when possible.
+    ///
+    /// See also the [set_zero_copy](#method.set_zero_copy) method
+    pub fn zero_copy(&self) -> bool {
+        self.zero_copy
+    }
+
+    /// Set whether packets are received without being copied when possible.
+    ///
+    /// If enabled, the socket is in [header included](#method.set_header_included) mode,
+    /// and the device lends the packets it receives out, they are left in the buffers of
+    /// the device, as for [UdpSocket::set_zero_copy]. Such packets are dequeued with
+    /// [recv_lent](#method.recv_lent). Zero-copy receive is disabled by default.
+    ///
+    /// [UdpSocket::set_zero_copy]: struct.UdpSocket.html#method.set_zero_copy
+    pub fn set_zero_copy(&mut self, zero_copy: bool) {
+        self.zero_copy = zero_copy
+    }
+
+    /// Return the filter of the received packets.
+    ///
+    /// See also the [set_filter](#method.set_filter) method
+    pub fn filter(&self) -> Option<&PacketFilter<'a>> {
+        self.filter.as_ref()
+    }
+
+    /// Set the filter of the received packets, similar to `SO_ATTACH_FILTER`.
+    ///
+    /// The filter runs over each packet as it would be received, starting with
+    /// the IP header, and the packets it rejects are not enqueued, so that
+    /// uninteresting traffic does not fill up the receive buffer.
+    pub fn set_filter(&mut self, filter: Option<PacketFilter<'a>>) {
+        self.filter = filter
+    }
+
     /// Check whether the transmit buffer is full.
     #[inline]
     pub fn can_send(&self) -> bool {
@@ -113,6 +214,22 @@ impl<'a, 'b> RawSocket<'a, 'b> {
         !self.rx_buffer.is_empty()
     }
 
+    /// Return the occupancy statistics of the transmit buffer, in packets.
+    pub fn send_buffer_stats(&self) -> BufferStats {
+        self.tx_buffer.stats()
+    }
+
+    /// Return the occupancy statistics of the receive buffer, in packets.
+    pub fn recv_buffer_stats(&self) -> BufferStats {
+        self.rx_buffer.stats()
+    }
+
+    /// Restart the occupancy statistics of both buffers from their current contents.
+    pub fn reset_buffer_stats(&mut self) {
+        self.tx_buffer.reset_stats();
+        self.rx_buffer.reset_stats();
+    }
+
     /// Enqueue a packet to send, and return a pointer to its payload.
     ///
     /// This function returns `Err(Error::Exhausted)` if the size is greater than
@@ -127,8 +244,8 @@ impl<'a, 'b> RawSocket<'a, 'b> {
         let packet_buf = self.tx_buffer.enqueue_one_with(|buf| buf.resize(size))?;
         net_trace!("{}:{}:{}: buffer to send {} octets",
                    self.meta.handle, self.ip_version, self.ip_protocol,
-                   packet_buf.size);
-        Ok(packet_buf.as_mut())
+                   packet_buf.len());
+        Ok(packet_buf.payload_mut())
     }
 
     /// Enqueue a packet to send, and fill it from a slice.
@@ -143,14 +260,38 @@ impl<'a, 'b> RawSocket<'a, 'b> {
     ///
     /// This function returns `Err(Error::Exhausted)` if the receive buffer is empty.
     ///
+    /// If the packet was lent rather than copied, this function returns
+    /// `Err(Error::Illegal)`; see [recv_lent](#method.recv_lent).
+    ///
     /// **Note:** The IP header is parsed and reserialized, and may not match
     /// the header actually received bit for bit.
     pub fn recv(&mut self) -> Result<&[u8]> {
-        let packet_buf = self.rx_buffer.dequeue_one()?;
+        self.rx_buffer.release_dequeued();
+        let packet_buf = self.rx_buffer.dequeue_one_with(|packet_buf| {
+            match *packet_buf.metadata() {
+                Some(_) => Err(Error::Illegal),
+                None => Ok(packet_buf)
+            }
+        })?;
         net_trace!("{}:{}:{}: receive {} buffered octets",
                    self.meta.handle, self.ip_version, self.ip_protocol,
-                   packet_buf.size);
-        Ok(&packet_buf.as_ref())
+                   packet_buf.len());
+        Ok(packet_buf.payload())
+    }
+
+    /// Dequeue a packet that was lent rather than copied, and return its loan.
+    ///
+    /// This function returns `Err(Error::Exhausted)` if the receive buffer is empty,
+    /// and `Err(Error::Illegal)` if the packet was copied. See also
+    /// [set_zero_copy](#method.set_zero_copy).
+    pub fn recv_lent(&mut self) -> Result<RxLoan> {
+        self.rx_buffer.release_dequeued();
+        let loan = self.rx_buffer.dequeue_one_with(|packet_buf| {
+            packet_buf.metadata().ok_or(Error::Illegal)
+        })?;
+        net_trace!("{}:{}:{}: receive {} lent octets",
+                   self.meta.handle, self.ip_version, self.ip_protocol, loan.len());
+        Ok(loan)
     }
 
     /// Dequeue a packet, and copy the payload into the given slice.
@@ -176,25 +317,86 @@ impl<'a, 'b> RawSocket<'a, 'b> {
 
         let header_len = ip_repr.buffer_len();
         let total_len  = header_len + payload.len();
+        if let Some(ref filter) = self.filter {
+            let mut header = [0; MAX_HEADER_LEN];
+            ip_repr.emit(&mut header[..header_len], &checksum_caps);
+            if !filter.matches(&header[..header_len], payload) {
+                net_trace!("{}:{}:{}: filtering out {} octets",
+                           self.meta.handle, self.ip_version, self.ip_protocol, total_len);
+                return Err(Error::Dropped)
+            }
+        }
+
+        self.rx_buffer.release_dequeued();
         let packet_buf = self.rx_buffer.enqueue_one_with(|buf| buf.resize(total_len))?;
-        ip_repr.emit(&mut packet_buf.as_mut()[..header_len], &checksum_caps);
-        packet_buf.as_mut()[header_len..].copy_from_slice(payload);
+        ip_repr.emit(&mut packet_buf.payload_mut()[..header_len], &checksum_caps);
+        packet_buf.payload_mut()[header_len..].copy_from_slice(payload);
+        *packet_buf.metadata_mut() = None;
+        net_trace!("{}:{}:{}: receiving {} octets",
+                   self.meta.handle, self.ip_version, self.ip_protocol,
+                   packet_buf.len());
+        #[cfg(feature = "async")]
+        self.rx_waker.wake();
+        Ok(())
+    }
+
+    pub(crate) fn process_packet(&mut self, ip_packet: &[u8]) -> Result<()> {
+        self.process_packet_lent(ip_packet, None)
+    }
+
+    /// Process a packet in header included mode, leaving it in the device if it is lent.
+    pub(crate) fn process_packet_lent(&mut self, ip_packet: &[u8], lent: Option<RxLoan>) ->
+                                     Result<()> {
+        debug_assert!(self.header_included);
+
+        if let Some(ref filter) = self.filter {
+            if !filter.matches(ip_packet, &[]) {
+                net_trace!("{}:{}:{}: filtering out {} octets",
+                           self.meta.handle, self.ip_version, self.ip_protocol,
+                           ip_packet.len());
+                return Err(Error::Dropped)
+            }
+        }
+
+        let size = if lent.is_some() { 0 } else { ip_packet.len() };
+        self.rx_buffer.release_dequeued();
+        let packet_buf = self.rx_buffer.enqueue_one_with(|buf| buf.resize(size))?;
+        packet_buf.payload_mut().copy_from_slice(&ip_packet[..size]);
+        *packet_buf.metadata_mut() = lent;
         net_trace!("{}:{}:{}: receiving {} octets",
                    self.meta.handle, self.ip_version, self.ip_protocol,
-                   packet_buf.size);
+                   packet_buf.len());
+        #[cfg(feature = "async")]
+        self.rx_waker.wake();
         Ok(())
     }
 
+    /// Dispatch a packet.
+    ///
+    /// The emitted payload is the payload of the packet, or, in header included mode,
+    /// the entire packet.
     pub(crate) fn dispatch<F>(&mut self, checksum_caps: &ChecksumCapabilities, emit: F) ->
                              Result<()>
             where F: FnOnce((IpRepr, &[u8])) -> Result<()> {
-        fn prepare<'a>(protocol: IpProtocol, buffer: &'a mut [u8],
-                   checksum_caps: &ChecksumCapabilities) -> Result<(IpRepr, &'a [u8])> {
+        fn prepare<'a>(protocol: IpProtocol, hop_limit: Option<u8>, header_included: bool,
+                       buffer: &'a mut [u8], checksum_caps: &ChecksumCapabilities) ->
+                      Result<(IpRepr, &'a [u8])> {
             match IpVersion::of_packet(buffer.as_ref())? {
                 #[cfg(feature = "proto-ipv4")]
                 IpVersion::Ipv4 => {
                     let mut packet = Ipv4Packet::new_checked(buffer.as_mut())?;
                     if packet.protocol() != protocol { return Err(Error::Unaddressable) }
+                    if let Some(hop_limit) = hop_limit {
+                        packet.set_hop_limit(hop_limit);
+                    }
+                    if header_included {
+                        // The interface fills in the checksum once the source address
+                        // is known.
+                        let packet = Ipv4Packet::new(&*packet.into_inner());
+                        let ipv4_repr = Ipv4Repr::parse(&packet, &ChecksumCapabilities::ignored())?;
+                        let total_len = packet.total_len() as usize;
+                        return Ok((IpRepr::Ipv4(ipv4_repr), &packet.into_inner()[..total_len]))
+                    }
                     if checksum_caps.ipv4.tx() {
                         packet.fill_checksum();
                     } else {
@@ -211,8 +413,15 @@ impl<'a, 'b> RawSocket<'a, 'b> {
                 IpVersion::Ipv6 => {
                     let mut packet = Ipv6Packet::new_checked(buffer.as_mut())?;
                     if packet.next_header() != protocol { return Err(Error::Unaddressable) }
+                    if let Some(hop_limit) = hop_limit {
+                        packet.set_hop_limit(hop_limit);
+                    }
                     let packet = Ipv6Packet::new(&*packet.into_inner());
                     let ipv6_repr = Ipv6Repr::parse(&packet)?;
+                    if header_included {
+                        let total_len = packet.total_len();
+                        return Ok((IpRepr::Ipv6(ipv6_repr), &packet.into_inner()[..total_len]))
+                    }
                     Ok((IpRepr::Ipv6(ipv6_repr), packet.payload()))
                 }
                 IpVersion::Unspecified => unreachable!(),
@@ -223,12 +432,16 @@ impl<'a, 'b> RawSocket<'a, 'b> {
         let handle      = self.meta.handle;
         let ip_protocol = self.ip_protocol;
         let ip_version  = self.ip_version;
+        let hop_limit   = self.hop_limit;
+        let header_included = self.header_included;
         self.tx_buffer.dequeue_one_with(|packet_buf| {
-            match prepare(ip_protocol, packet_buf.as_mut(), &checksum_caps) {
+            match prepare(ip_protocol, hop_limit, header_included,
+                          packet_buf.payload_mut(), &checksum_caps) {
                 Ok((ip_repr, raw_packet)) => {
+                    let total_len = if header_included { raw_packet.len() }
+                                    else { ip_repr.buffer_len() + raw_packet.len() };
                     net_trace!("{}:{}:{}: sending {} octets",
-                               handle, ip_version, ip_protocol,
-                               ip_repr.buffer_len() + raw_packet.len());
+                               handle, ip_version, ip_protocol, total_len);
                     emit((ip_repr, raw_packet))
                 }
                 Err(error) => {
@@ -239,7 +452,11 @@ impl<'a, 'b> RawSocket<'a, 'b> {
                     Ok(())
                 }
             }
-        })
+        })?;
+        self.tx_buffer.release_dequeued();
+        #[cfg(feature = "async")]
+        self.tx_waker.wake();
+        Ok(())
     }
 
     pub(crate) fn poll_at(&self) -> Option<u64> {
@@ -291,7 +508,9 @@ mod test {
             dst_addr: Ipv4Address([10, 0, 0, 2]),
             protocol: IpProtocol::Unknown(IP_PROTO),
             payload_len: 4,
-            hop_limit: 64
+            hop_limit: 64,
+            ecn: 0,
+            dscp: 0
         });
         pub const PACKET_BYTES: [u8; 24] = [
             0x45, 0x00, 0x00, 0x18,
@@ -325,7 +544,10 @@ mod test {
                                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02]),
             next_header: IpProtocol::Unknown(IP_PROTO),
             payload_len: 4,
-            hop_limit: 64
+            hop_limit: 64,
+            ecn: 0,
+            dscp: 0,
+            flow_label: 0
         });
 
         pub const PACKET_BYTES: [u8; 44] = [
@@ -386,6 +608,20 @@ mod test {
                     assert!(socket.can_send());
                 }
 
+                #[test]
+                fn test_set_hop_limit() {
+                    let checksum_caps = &ChecksumCapabilities::default();
+                    let mut socket = $socket(buffer(0), buffer(1));
+                    socket.set_hop_limit(Some(0x2a));
+
+                    assert_eq!(socket.send_slice(&$packet[..]), Ok(()));
+                    assert_eq!(socket.dispatch(&checksum_caps, |(ip_repr, ip_payload)| {
+                        assert_eq!(ip_repr.hop_limit(), 0x2a);
+                        assert_eq!(ip_payload, &$payload);
+                        Ok(())
+                    }), Ok(()));
+                }
+
                 #[test]
                 fn test_recv_truncated_slice() {
                     let mut socket = $socket(buffer(1), buffer(0));
@@ -465,6 +701,78 @@ mod test {
         }
     }
 
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_header_included() {
+        let checksum_caps = &ChecksumCapabilities::default();
+        let mut socket = ipv4_locals::socket(buffer(1), buffer(1));
+        socket.set_header_included(true);
+
+        // The identification field would be cleared by reserializing the header.
+        let mut packet_bytes = ipv4_locals::PACKET_BYTES.clone();
+        Ipv4Packet::new(&mut packet_bytes).set_ident(0x1234);
+
+        assert_eq!(socket.send_slice(&packet_bytes[..]), Ok(()));
+        assert_eq!(socket.dispatch(&checksum_caps, |(ip_repr, ip_packet)| {
+            assert_eq!(ip_repr, ipv4_locals::HEADER_REPR);
+            assert_eq!(ip_packet, &packet_bytes[..]);
+            Ok(())
+        }), Ok(()));
+
+        assert_eq!(socket.process_packet(&packet_bytes[..]), Ok(()));
+        assert_eq!(socket.recv(), Ok(&packet_bytes[..]));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_recv_lent() {
+        let mut socket = ipv4_locals::socket(buffer(2), buffer(0));
+        socket.set_header_included(true);
+        socket.set_zero_copy(true);
+
+        let packet_bytes = ipv4_locals::PACKET_BYTES.clone();
+        let loan = RxLoan::new(3, 14 + packet_bytes.len()).narrow(14, packet_bytes.len());
+        assert_eq!(socket.process_packet_lent(&packet_bytes[..], Some(loan)), Ok(()));
+        assert_eq!(socket.process_packet(&packet_bytes[..]), Ok(()));
+
+        assert_eq!(socket.recv(), Err(Error::Illegal));
+        assert_eq!(socket.recv_lent(), Ok(loan));
+        assert_eq!(socket.recv_lent(), Err(Error::Illegal));
+        assert_eq!(socket.recv(), Ok(&packet_bytes[..]));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_filter() {
+        use socket::FilterInstruction as Insn;
+
+        // Accept the packets whose payload starts with 0xaa.
+        static PROGRAM: [Insn; 4] = [
+            Insn::new(Insn::LD | Insn::B | Insn::ABS, 0, 0, 20),
+            Insn::new(Insn::JMP | Insn::JEQ | Insn::K, 0, 1, 0xaa),
+            Insn::new(Insn::RET | Insn::K, 0, 0, 0xffff),
+            Insn::new(Insn::RET | Insn::K, 0, 0, 0),
+        ];
+        let mut socket = ipv4_locals::socket(buffer(2), buffer(0));
+        socket.set_filter(Some(PacketFilter::new(&PROGRAM).unwrap()));
+
+        let checksum_caps = ChecksumCapabilities::default();
+        assert_eq!(socket.process(&ipv4_locals::HEADER_REPR, &[0xbb, 0x00, 0x00, 0xff],
+                                  &checksum_caps),
+                   Err(Error::Dropped));
+        assert!(!socket.can_recv());
+        assert_eq!(socket.process(&ipv4_locals::HEADER_REPR, &ipv4_locals::PACKET_PAYLOAD,
+                                  &checksum_caps),
+                   Ok(()));
+
+        socket.set_header_included(true);
+        let mut packet_bytes = ipv4_locals::PACKET_BYTES.clone();
+        packet_bytes[20] = 0xbb;
+        assert_eq!(socket.process_packet(&packet_bytes[..]), Err(Error::Dropped));
+        packet_bytes[20] = 0xaa;
+        assert_eq!(socket.process_packet(&packet_bytes[..]), Ok(()));
+    }
+
     #[test]
     fn test_recv_process() {
         #[cfg(feature = "proto-ipv4")]
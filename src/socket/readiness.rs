@@ -0,0 +1,94 @@
+use core::fmt;
+
+/// The readiness of a socket, as a set of flags.
+///
+/// A socket is readable if data may be received from it, writable if data may be sent
+/// through it, and closed if it neither sends nor receives packets, e.g. because it is
+/// not bound, or its connection was closed. DNS sockets are never ready in any way;
+/// the results of their queries are polled instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Readiness(u8);
+
+impl Readiness {
+    /// No readiness.
+    pub const EMPTY:    Readiness = Readiness(0);
+    /// Data may be received from the socket.
+    pub const READABLE: Readiness = Readiness(0x01);
+    /// Data may be sent through the socket.
+    pub const WRITABLE: Readiness = Readiness(0x02);
+    /// The socket neither sends nor receives packets.
+    pub const CLOSED:   Readiness = Readiness(0x04);
+
+    pub(crate) fn new(readable: bool, writable: bool, closed: bool) -> Readiness {
+        let mut readiness = Readiness::EMPTY;
+        if readable { readiness = readiness | Readiness::READABLE }
+        if writable { readiness = readiness | Readiness::WRITABLE }
+        if closed   { readiness = readiness | Readiness::CLOSED }
+        readiness
+    }
+
+    /// Return the flags as a bitmap.
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// Query whether no flag is set.
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Query whether every flag of the given readiness is set.
+    pub fn contains(&self, other: Readiness) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Query whether data may be received from the socket.
+    pub fn is_readable(&self) -> bool {
+        self.contains(Readiness::READABLE)
+    }
+
+    /// Query whether data may be sent through the socket.
+    pub fn is_writable(&self) -> bool {
+        self.contains(Readiness::WRITABLE)
+    }
+
+    /// Query whether the socket neither sends nor receives packets.
+    pub fn is_closed(&self) -> bool {
+        self.contains(Readiness::CLOSED)
+    }
+}
+
+impl ::core::ops::BitOr for Readiness {
+    type Output = Readiness;
+
+    fn bitor(self, other: Readiness) -> Readiness {
+        Readiness(self.0 | other.0)
+    }
+}
+
+impl fmt::Display for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() { return write!(f, "-") }
+        if self.is_readable() { write!(f, "r")? }
+        if self.is_writable() { write!(f, "w")? }
+        if self.is_closed()   { write!(f, "c")? }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_flags() {
+        let readiness = Readiness::new(true, false, true);
+        assert_eq!(readiness, Readiness::READABLE | Readiness::CLOSED);
+        assert_eq!(readiness.bits(), 0x05);
+        assert!(readiness.is_readable());
+        assert!(!readiness.is_writable());
+        assert!(readiness.is_closed());
+        assert!(Readiness::new(false, false, false).is_empty());
+        assert_eq!(format!("{}", readiness), "rc");
+    }
+}
@@ -1,7 +1,9 @@
-use core::{fmt, slice};
+use core::{fmt, iter, slice};
 use managed::ManagedSlice;
 
-use super::{Socket, SocketRef, AnySocket};
+use super::{Socket, SocketRef, SocketReadiness, AnySocket, TypedHandle};
+#[cfg(any(feature = "socket-udp", feature = "socket-tcp"))]
+use super::SocketConnection;
 #[cfg(feature = "socket-tcp")]
 use super::TcpState;
 
@@ -27,10 +29,14 @@ impl fmt::Display for Handle {
 
 /// An extensible set of sockets.
 ///
+/// If the storage of the set is a `Vec`, the set grows as sockets are added to it,
+/// and can be created empty.
+///
 /// The lifetimes `'b` and `'c` are used when storing a `Socket<'b, 'c>`.
 #[derive(Debug)]
 pub struct Set<'a, 'b: 'a, 'c: 'a + 'b> {
-    sockets: ManagedSlice<'a, Option<Item<'b, 'c>>>
+    sockets:    ManagedSlice<'a, Option<Item<'b, 'c>>>,
+    generation: u32
 }
 
 impl<'a, 'b: 'a, 'c: 'a + 'b> Set<'a, 'b, 'c> {
@@ -39,7 +45,8 @@ impl<'a, 'b: 'a, 'c: 'a + 'b> Set<'a, 'b, 'c> {
             where SocketsT: Into<ManagedSlice<'a, Option<Item<'b, 'c>>>> {
         let sockets = sockets.into();
         Set {
-            sockets: sockets
+            sockets:    sockets,
+            generation: 0
         }
     }
 
@@ -60,6 +67,7 @@ impl<'a, 'b: 'a, 'c: 'a + 'b> Set<'a, 'b, 'c> {
         }
 
         let socket = socket.into();
+        self.generation = self.generation.wrapping_add(1);
 
         for (index, slot) in self.sockets.iter_mut().enumerate() {
             if slot.is_none() {
@@ -80,14 +88,30 @@ impl<'a, 'b: 'a, 'c: 'a + 'b> Set<'a, 'b, 'c> {
         }
     }
 
+    /// Add a socket to the set with the reference count 1, and return its typed handle.
+    ///
+    /// # Panics
+    /// This function panics if the storage is fixed-size (not a `Vec`) and is full.
+    pub fn add_typed<T>(&mut self, socket: T) -> T::Handle
+        where T: AnySocket<'b, 'c> + Into<Socket<'b, 'c>>
+    {
+        T::Handle::from_untyped(self.add(socket))
+    }
+
     /// Get a socket from the set by its handle, as mutable.
     ///
+    /// See also [get_typed](#method.get_typed), which determines the type of the socket
+    /// from its handle.
+    ///
     /// # Panics
     /// This function may panic if the handle does not belong to this socket set
     /// or the socket has the wrong type.
     pub fn get<T: AnySocket<'b, 'c>>(&mut self, handle: Handle) -> SocketRef<T> {
         match self.sockets[handle.0].as_mut() {
             Some(item) => {
+                if may_rebind(&item.socket) {
+                    self.generation = self.generation.wrapping_add(1)
+                }
                 T::downcast(SocketRef::new(&mut item.socket))
                   .expect("handle refers to a socket of a wrong type")
             }
@@ -95,12 +119,42 @@ impl<'a, 'b: 'a, 'c: 'a + 'b> Set<'a, 'b, 'c> {
         }
     }
 
+    /// Get a socket from the set by its typed handle, as mutable.
+    ///
+    /// # Panics
+    /// This function may panic if the handle does not belong to this socket set.
+    pub fn get_typed<H: TypedHandle<'b, 'c>>(&mut self, handle: H) -> SocketRef<H::Socket> {
+        self.get::<H::Socket>(handle.into())
+    }
+
+    /// Get a socket from the set by its handle, as mutable, whatever its type.
+    ///
+    /// # Panics
+    /// This function may panic if the handle does not belong to this socket set.
+    pub(crate) fn get_any(&mut self, handle: Handle) -> SocketRef<Socket<'b, 'c>> {
+        match self.sockets[handle.0].as_mut() {
+            Some(item) => SocketRef::new(&mut item.socket),
+            None => panic!("handle does not refer to a valid socket")
+        }
+    }
+
+    /// Get a socket from the set by its handle, as mutable, if the handle refers
+    /// to a socket of the given type.
+    pub(crate) fn try_get<T: AnySocket<'b, 'c>>(&mut self, handle: Handle) ->
+                         Option<SocketRef<T>> {
+        match self.sockets.get_mut(handle.0) {
+            Some(&mut Some(ref mut item)) => T::downcast(SocketRef::new(&mut item.socket)),
+            _ => None
+        }
+    }
+
     /// Remove a socket from the set, without changing its state.
     ///
     /// # Panics
     /// This function may panic if the handle does not belong to this socket set.
     pub fn remove(&mut self, handle: Handle) -> Socket<'b, 'c> {
         net_trace!("[{}]: removing", handle.0);
+        self.generation = self.generation.wrapping_add(1);
         match self.sockets[handle.0].take() {
             Some(item) => item.socket,
             None => panic!("handle does not refer to a valid socket")
@@ -144,7 +198,8 @@ impl<'a, 'b: 'a, 'c: 'a + 'b> Set<'a, 'b, 'c> {
                     #[cfg(feature = "socket-raw")]
                     &mut Socket::Raw(_) =>
                         may_remove = true,
-                    #[cfg(all(feature = "socket-icmp", feature = "proto-ipv4"))]
+                    #[cfg(all(feature = "socket-icmp",
+                              any(feature = "proto-ipv4", feature = "proto-ipv6")))]
                     &mut Socket::Icmp(_) =>
                         may_remove = true,
                     #[cfg(feature = "socket-udp")]
@@ -157,11 +212,18 @@ impl<'a, 'b: 'a, 'c: 'a + 'b> Set<'a, 'b, 'c> {
                         } else {
                             socket.close()
                         },
+                    #[cfg(feature = "socket-dns")]
+                    &mut Socket::Dns(_) =>
+                        may_remove = true,
+                    #[cfg(feature = "socket-packet")]
+                    &mut Socket::Packet(_) =>
+                        may_remove = true,
                     &mut Socket::__Nonexhaustive(_) => unreachable!()
                 }
             }
             if may_remove {
                 net_trace!("[{}]: pruning", index);
+                self.generation = self.generation.wrapping_add(1);
                 *item = None
             }
         }
@@ -174,8 +236,64 @@ impl<'a, 'b: 'a, 'c: 'a + 'b> Set<'a, 'b, 'c> {
 
     /// Iterate every socket in this set, as SocketRef.
     pub fn iter_mut<'d>(&'d mut self) -> IterMut<'d, 'b, 'c> {
+        self.generation = self.generation.wrapping_add(1);
         IterMut { lower: self.sockets.iter_mut() }
     }
+
+    /// Iterate the sockets whose readiness changed since it was last reported,
+    /// with their readiness, and remember it as reported.
+    ///
+    /// After [polling] an interface, this lets the application service only
+    /// the sockets that became readable, writable or closed, or stopped being so,
+    /// instead of checking every socket. The readiness of a socket added to the set
+    /// is reported once, unless it is empty. The readiness of sockets that are not
+    /// iterated, e.g. because the iterator is dropped early, is reported the next time.
+    ///
+    /// [polling]: ../iface/struct.EthernetInterface.html#method.poll_readiness
+    pub fn readiness_changes<'d>(&'d mut self) -> ReadinessChanges<'d, 'b, 'c> {
+        ReadinessChanges { lower: self.sockets.iter_mut().enumerate() }
+    }
+
+    /// Iterate the open TCP and UDP sockets in this set, with their endpoints, state
+    /// and queues, e.g. to show them in a device shell.
+    #[cfg(any(feature = "socket-udp", feature = "socket-tcp"))]
+    pub fn connections<'d>(&'d self) -> Connections<'d, 'b, 'c> {
+        Connections { lower: self.iter() }
+    }
+
+    /// Iterate every socket in this set, as SocketRef, without changing the generation
+    /// of the set; the caller must not change the endpoints the sockets are bound to.
+    pub(crate) fn iter_mut_untracked<'d>(&'d mut self) -> IterMut<'d, 'b, 'c> {
+        IterMut { lower: self.sockets.iter_mut() }
+    }
+
+    /// Return the generation of the set, which changes whenever a socket is added or
+    /// removed, or a socket that may have been bound to another endpoint is accessed.
+    pub(crate) fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Return the handle of the first socket after the given one, or of the first
+    /// socket in the set.
+    pub(crate) fn next_handle(&self, after: Option<Handle>) -> Option<Handle> {
+        let first = after.map_or(0, |handle| handle.0 + 1);
+        self.sockets.iter().enumerate().skip(first)
+            .find(|&(_, slot)| slot.is_some())
+            .map(|(index, _)| Handle(index))
+    }
+}
+
+/// Return whether the application may bind the socket to another endpoint through
+/// a reference to it, which changes the packets the socket receives.
+fn may_rebind(socket: &Socket) -> bool {
+    match *socket {
+        #[cfg(all(feature = "socket-icmp",
+                  any(feature = "proto-ipv4", feature = "proto-ipv6")))]
+        Socket::Icmp(_) => true,
+        #[cfg(feature = "socket-udp")]
+        Socket::Udp(_) => true,
+        _ => false
+    }
 }
 
 /// Immutable socket set iterator.
@@ -219,3 +337,137 @@ impl<'a, 'b: 'a, 'c: 'a + 'b> Iterator for IterMut<'a, 'b, 'c> {
         None
     }
 }
+
+/// An iterator over the sockets whose readiness changed.
+///
+/// This struct is created by the
+/// [readiness_changes](struct.SocketSet.html#method.readiness_changes)
+/// on [socket sets](struct.SocketSet.html).
+pub struct ReadinessChanges<'a, 'b: 'a, 'c: 'a + 'b> {
+    lower: iter::Enumerate<slice::IterMut<'a, Option<Item<'b, 'c>>>>
+}
+
+impl<'a, 'b: 'a, 'c: 'a + 'b> Iterator for ReadinessChanges<'a, 'b, 'c> {
+    type Item = (Handle, SocketReadiness);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((index, item_opt)) = self.lower.next() {
+            if let Some(item) = item_opt.as_mut() {
+                let readiness = item.socket.readiness();
+                let meta = item.socket.meta_mut();
+                if meta.readiness != readiness {
+                    meta.readiness = readiness;
+                    return Some((Handle(index), readiness))
+                }
+            }
+        }
+        None
+    }
+}
+
+/// An iterator over the open TCP and UDP sockets in a set.
+///
+/// This struct is created by the [connections](struct.SocketSet.html#method.connections)
+/// on [socket sets](struct.SocketSet.html).
+#[cfg(any(feature = "socket-udp", feature = "socket-tcp"))]
+pub struct Connections<'a, 'b: 'a, 'c: 'a + 'b> {
+    lower: Iter<'a, 'b, 'c>
+}
+
+#[cfg(any(feature = "socket-udp", feature = "socket-tcp"))]
+impl<'a, 'b: 'a, 'c: 'a + 'b> Iterator for Connections<'a, 'b, 'c> {
+    type Item = SocketConnection;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(socket) = self.lower.next() {
+            if let Some(connection) = SocketConnection::new(socket) {
+                return Some(connection)
+            }
+        }
+        None
+    }
+}
+
+#[cfg(all(test, feature = "socket-tcp", feature = "socket-udp"))]
+mod test {
+    use socket::{TcpSocket, TcpSocketBuffer, UdpSocket, UdpSocketBuffer};
+    use super::*;
+
+    #[test]
+    fn test_typed_handles() {
+        let mut sockets = Set::new(vec![]);
+        let tcp_handle = sockets.add_typed(
+            TcpSocket::new(TcpSocketBuffer::new(vec![0; 64]), TcpSocketBuffer::new(vec![0; 64])));
+        let udp_handle = sockets.add_typed(
+            UdpSocket::new(UdpSocketBuffer::new(vec![]), UdpSocketBuffer::new(vec![])));
+        assert_eq!(sockets.get_typed(tcp_handle).handle(), tcp_handle.untyped());
+        assert_eq!(sockets.get_typed(udp_handle).handle(), udp_handle.untyped());
+
+        let handles: [Handle; 2] = [tcp_handle.into(), udp_handle.into()];
+        sockets.get::<TcpSocket>(handles[0]).listen(80).unwrap();
+        assert!(sockets.get_typed(tcp_handle).is_listening());
+        assert!(!sockets.get::<UdpSocket>(handles[1]).is_open());
+    }
+
+    #[test]
+    fn test_readiness_changes() {
+        use std::vec::Vec;
+        use socket::UdpPacketBuffer;
+
+        let mut sockets = Set::new(vec![]);
+        let tcp_handle = sockets.add(
+            TcpSocket::new(TcpSocketBuffer::new(vec![0; 64]), TcpSocketBuffer::new(vec![0; 64])));
+        let udp_handle = sockets.add(
+            UdpSocket::new(UdpSocketBuffer::new(vec![UdpPacketBuffer::new(vec![0; 64])]),
+                           UdpSocketBuffer::new(vec![UdpPacketBuffer::new(vec![0; 64])])));
+
+        // The readiness of new sockets is reported once.
+        assert_eq!(sockets.readiness_changes().collect::<Vec<_>>(), vec![
+            (tcp_handle, SocketReadiness::CLOSED),
+            (udp_handle, SocketReadiness::WRITABLE | SocketReadiness::CLOSED)
+        ]);
+        assert_eq!(sockets.readiness_changes().next(), None);
+
+        sockets.get::<UdpSocket>(udp_handle).bind(68).unwrap();
+        assert_eq!(sockets.readiness_changes().collect::<Vec<_>>(), vec![
+            (udp_handle, SocketReadiness::WRITABLE)
+        ]);
+        sockets.get::<UdpSocket>(udp_handle).send_slice(b"abcdef",
+            (::wire::ip::test::MOCK_IP_ADDR_2, 67).into()).unwrap();
+        assert_eq!(sockets.readiness_changes().collect::<Vec<_>>(), vec![
+            (udp_handle, SocketReadiness::EMPTY)
+        ]);
+    }
+
+    #[test]
+    fn test_connections() {
+        use std::vec::Vec;
+        use socket::{TcpState, UdpPacketBuffer};
+        use wire::{IpProtocol, IpEndpoint};
+
+        let mut sockets = Set::new(vec![]);
+        let tcp_handle = sockets.add(
+            TcpSocket::new(TcpSocketBuffer::new(vec![0; 64]), TcpSocketBuffer::new(vec![0; 64])));
+        let udp_handle = sockets.add(
+            UdpSocket::new(UdpSocketBuffer::new(vec![UdpPacketBuffer::new(vec![0; 64])]),
+                           UdpSocketBuffer::new(vec![UdpPacketBuffer::new(vec![0; 64])])));
+        assert_eq!(sockets.connections().next(), None);
+
+        sockets.get::<TcpSocket>(tcp_handle).listen(80).unwrap();
+        sockets.get::<UdpSocket>(udp_handle).bind(68).unwrap();
+        sockets.get::<UdpSocket>(udp_handle).send_slice(b"abcdef",
+            (::wire::ip::test::MOCK_IP_ADDR_2, 67).into()).unwrap();
+        let connections = sockets.connections().collect::<Vec<_>>();
+        assert_eq!(connections.len(), 2);
+        assert_eq!(connections[0].handle, tcp_handle);
+        assert_eq!(connections[0].protocol, IpProtocol::Tcp);
+        assert_eq!(connections[0].local_endpoint, IpEndpoint::from(80));
+        assert_eq!(connections[0].tcp_state, Some(TcpState::Listen));
+        assert_eq!(connections[1].handle, udp_handle);
+        assert_eq!(connections[1].protocol, IpProtocol::Udp);
+        assert_eq!(connections[1].local_endpoint, IpEndpoint::from(68));
+        assert_eq!(connections[1].remote_endpoint, IpEndpoint::default());
+        assert_eq!(connections[1].tcp_state, None);
+        assert_eq!((connections[1].recv_queue, connections[1].send_queue), (0, 1));
+    }
+}
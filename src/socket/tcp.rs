@@ -3,12 +3,23 @@
 // a new feature.
 
 use core::{cmp, fmt};
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "async")]
+use core::task::Waker;
+
+use managed::ManagedSlice;
 
 use {Error, Result};
 use phy::DeviceCapabilities;
-use wire::{IpProtocol, IpRepr, IpAddress, IpEndpoint, TcpSeqNumber, TcpRepr, TcpControl};
-use socket::{Socket, SocketMeta, SocketHandle};
-use storage::{Assembler, RingBuffer};
+use wire::{IpProtocol, IpRepr, IpAddress, IpEndpoint, TcpSeqNumber, TcpPacket, TcpRepr,
+           TcpControl, TCP_SACK_RANGE_COUNT};
+use socket::{Socket, SocketMeta, SocketHandle, SocketError};
+#[cfg(feature = "async")]
+use socket::WakerRegistration;
+use socket::congestion::{Controller, Algorithm, AnyController};
+use socket::tcp_cookie;
+use storage::{Assembler, RingBuffer, BufferStats};
+use time::{Duration, Instant};
 
 /// A TCP socket ring buffer.
 pub type SocketBuffer<'a> = RingBuffer<'a, u8>;
@@ -49,6 +60,85 @@ impl fmt::Display for State {
     }
 }
 
+/// An event in the life of a connection, reported by a TCP socket.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Event {
+    /// The connection was established.
+    Established,
+    /// The remote end closed its side of the connection, i.e. sent a FIN.
+    RemoteClosed,
+    /// The remote end reset the connection.
+    Reset,
+    /// The retransmission timer expired, and unacknowledged data is sent again.
+    RetransmitTimeout,
+    /// The remote end closed its receive window, i.e. advertised a window of zero octets.
+    ZeroWindow,
+    /// The remote end acknowledged data, freeing at least the send low-water mark
+    /// in a transmit buffer that had less free space.
+    Writable
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Event::Established       => write!(f, "established"),
+            &Event::RemoteClosed      => write!(f, "closed by remote end"),
+            &Event::Reset             => write!(f, "reset"),
+            &Event::RetransmitTimeout => write!(f, "retransmission timeout"),
+            &Event::ZeroWindow        => write!(f, "zero window"),
+            &Event::Writable          => write!(f, "writable")
+        }
+    }
+}
+
+/// A buffer of the events reported by a TCP socket, with their timestamps.
+pub type EventBuffer<'a> = RingBuffer<'a, (u64, Event)>;
+
+/// A function computing a TCP MD5 signature, i.e. the MD5 digest of the given octets,
+/// in order, followed by the key.
+///
+/// See also the [set_md5_signature](struct.TcpSocket.html#method.set_md5_signature) method.
+pub type Md5SignatureFn = fn(key: &[u8], data: &[&[u8]]) -> [u8; 16];
+
+/// Statistics about the traffic of a TCP socket.
+///
+/// The counters start from zero whenever the socket starts listening or connecting,
+/// and keep their values after the connection is closed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// Number of segments sent, including retransmissions and pure acknowledgements.
+    pub segments_sent:     u64,
+    /// Number of segments received, including dropped ones.
+    pub segments_received: u64,
+    /// Number of data octets sent, including retransmitted ones.
+    pub bytes_sent:        u64,
+    /// Number of data octets received into the receive buffer, including duplicate ones.
+    pub bytes_received:    u64,
+    /// Number of retransmissions, either after a timeout or a fast retransmit.
+    pub retransmits:       u64,
+    /// Number of duplicate acknowledgements received.
+    pub dup_acks:          u64,
+    /// Number of segments received that were discarded as unacceptable.
+    pub segments_dropped:  u64,
+    /// Number of challenge ACKs sent in response to suspicious RSTs, SYNs and ACKs.
+    pub challenge_acks:    u64,
+    /// The smoothed round-trip time, if it has been measured.
+    pub rtt:               Option<Duration>,
+}
+
+/// The endpoints and the final statistics of a closed connection of a TCP socket.
+///
+/// See also [TcpSocket::closed_connection](struct.TcpSocket.html#method.closed_connection).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClosedConnection {
+    /// The local endpoint of the connection.
+    pub local_endpoint:  IpEndpoint,
+    /// The remote endpoint of the connection.
+    pub remote_endpoint: IpEndpoint,
+    /// The statistics of the socket when the connection was closed.
+    pub stats:           Stats,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum Timer {
     Idle {
@@ -58,13 +148,29 @@ enum Timer {
         expires_at: u64,
         delay:      u64
     },
+    Persist {
+        expires_at: u64,
+        delay:      u64
+    },
     Close {
         expires_at: u64
     }
 }
 
-const RETRANSMIT_DELAY: u64 = 100;
-const CLOSE_DELAY:      u64 = 10_000;
+const RETRANSMIT_DELAY:  u64 = 100;
+const CLOSE_DELAY:       u64 = 10_000;
+const ACK_DELAY:         u64 = 10;
+const PERSIST_DELAY:     u64 = 100;
+const PERSIST_DELAY_MAX: u64 = 60_000;
+const RTO_MIN:           u64 = 10;
+const RTO_MAX:           u64 = 60_000;
+/// The default number of challenge ACKs a socket sends per second.
+const CHALLENGE_ACK_LIMIT: u32 = 10;
+
+/// The ECN-Capable Transport codepoint that new data is sent with.
+const ECN_ECT0: u8 = 0b10;
+/// The codepoint that routers mark packets with when they experience congestion.
+const ECN_CE:   u8 = 0b11;
 
 impl Default for Timer {
     fn default() -> Timer {
@@ -93,6 +199,16 @@ impl Timer {
         }
     }
 
+    fn should_probe(&self, timestamp: u64) -> bool {
+        match *self {
+            Timer::Persist { expires_at, .. }
+                    if timestamp >= expires_at => {
+                true
+            }
+            _ => false
+        }
+    }
+
     fn should_close(&self, timestamp: u64) -> bool {
         match *self {
             Timer::Close { expires_at }
@@ -107,6 +223,7 @@ impl Timer {
         match *self {
             Timer::Idle { keep_alive_at } => keep_alive_at,
             Timer::Retransmit { expires_at, .. } => Some(expires_at),
+            Timer::Persist { expires_at, .. } => Some(expires_at),
             Timer::Close { expires_at } => Some(expires_at),
         }
     }
@@ -136,29 +253,61 @@ impl Timer {
         }
     }
 
-    fn set_for_retransmit(&mut self, timestamp: u64) {
+    fn set_for_retransmit(&mut self, timestamp: u64, delay: u64, max_delay: u64) {
         match *self {
             Timer::Idle { .. } => {
                 *self = Timer::Retransmit {
-                    expires_at: timestamp + RETRANSMIT_DELAY,
-                    delay:      RETRANSMIT_DELAY,
+                    expires_at: timestamp + delay,
+                    delay:      delay,
                 }
             }
             Timer::Retransmit { expires_at, delay }
                     if timestamp >= expires_at => {
                 *self = Timer::Retransmit {
                     expires_at: timestamp + delay,
-                    delay:      delay * 2
+                    delay:      cmp::min(delay * 2, max_delay)
                 }
             }
             Timer::Retransmit { .. } => (),
+            Timer::Persist { .. } => {
+                *self = Timer::Retransmit {
+                    expires_at: timestamp + delay,
+                    delay:      delay,
+                }
+            }
             Timer::Close { .. } => ()
         }
     }
 
-    fn set_for_close(&mut self, timestamp: u64) {
+    fn set_for_persist(&mut self, timestamp: u64) {
+        match *self {
+            Timer::Idle { .. } => {
+                *self = Timer::Persist {
+                    expires_at: timestamp + PERSIST_DELAY,
+                    delay:      PERSIST_DELAY,
+                }
+            }
+            _ => ()
+        }
+    }
+
+    fn rewind_persist(&mut self, timestamp: u64) {
+        match *self {
+            Timer::Persist { expires_at, delay }
+                    if timestamp >= expires_at => {
+                let delay = cmp::min(delay * 2, PERSIST_DELAY_MAX);
+                *self = Timer::Persist {
+                    expires_at: timestamp + delay,
+                    delay:      delay
+                }
+            }
+            _ => ()
+        }
+    }
+
+    fn set_for_close(&mut self, timestamp: u64, delay: u64) {
         *self = Timer::Close {
-            expires_at: timestamp + CLOSE_DELAY
+            expires_at: timestamp + delay
         }
     }
 
@@ -168,6 +317,169 @@ impl Timer {
             _ => false,
         }
     }
+
+    fn is_persist(&self) -> bool {
+        match *self {
+            Timer::Persist {..} => true,
+            _ => false,
+        }
+    }
+}
+
+/// An estimator of the round-trip time of a connection, and of the retransmission timeout
+/// derived from it, according to [RFC 6298].
+///
+/// [RFC 6298]: https://tools.ietf.org/html/rfc6298
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RttEstimator {
+    /// The smoothed round-trip time, in microseconds, if it has been measured.
+    srtt:    Option<u64>,
+    /// The round-trip time variation, in microseconds.
+    rttvar:  u64,
+    /// The retransmission timeout.
+    rto:     u64,
+    /// The retransmission timeout before the round-trip time has been measured.
+    initial: u64,
+    /// The lower bound of the retransmission timeout.
+    min:     u64,
+    /// The upper bound of the retransmission timeout.
+    max:     u64
+}
+
+impl Default for RttEstimator {
+    fn default() -> RttEstimator {
+        RttEstimator {
+            srtt:    None,
+            rttvar:  0,
+            rto:     RETRANSMIT_DELAY,
+            initial: RETRANSMIT_DELAY,
+            min:     RTO_MIN,
+            max:     RTO_MAX
+        }
+    }
+}
+
+impl RttEstimator {
+    /// Forget the round-trip time measured, keeping the configuration.
+    fn reset(&mut self) {
+        self.srtt   = None;
+        self.rttvar = 0;
+        self.rto    = self.initial;
+    }
+
+    fn configure(&mut self, initial: u64, min: u64, max: u64) {
+        self.initial = initial;
+        self.min     = min;
+        self.max     = max;
+        self.rto     = match self.srtt {
+            None => initial,
+            Some(_) => cmp::max(min, cmp::min(self.rto, max))
+        };
+    }
+
+    fn sample(&mut self, rtt: Duration) {
+        let rtt = rtt.total_micros();
+        let (srtt, rttvar) = match self.srtt {
+            None => (rtt, rtt / 2),
+            Some(srtt) => {
+                let delta = if srtt > rtt { srtt - rtt } else { rtt - srtt };
+                ((7 * srtt + rtt) / 8, (3 * self.rttvar + delta) / 4)
+            }
+        };
+        self.srtt   = Some(srtt);
+        self.rttvar = rttvar;
+        // The timers have a granularity of one millisecond.
+        let rto = (srtt + cmp::max(1000, 4 * rttvar) + 999) / 1000;
+        self.rto    = cmp::max(self.min, cmp::min(rto, self.max));
+    }
+
+    fn retransmission_timeout(&self) -> u64 {
+        self.rto
+    }
+
+    fn max_retransmission_timeout(&self) -> u64 {
+        self.max
+    }
+
+    /// Return the smoothed round-trip time, or the retransmission timeout if it
+    /// has not been measured.
+    fn round_trip_time(&self) -> u64 {
+        self.srtt.map_or(self.rto, |srtt| (srtt + 999) / 1000)
+    }
+}
+
+const SCOREBOARD_SIZE: usize = 4;
+
+/// A scoreboard of the octets past the cumulative acknowledgement that the remote end
+/// has selectively acknowledged, according to [RFC 2018].
+///
+/// The ranges are kept sorted and disjoint; if there are more of them than can be tracked,
+/// the highest ones are forgotten, which only results in spurious retransmissions.
+///
+/// [RFC 2018]: https://tools.ietf.org/html/rfc2018
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Scoreboard {
+    ranges: [Option<(TcpSeqNumber, TcpSeqNumber)>; SCOREBOARD_SIZE]
+}
+
+impl Scoreboard {
+    fn is_empty(&self) -> bool {
+        self.ranges[0].is_none()
+    }
+
+    /// Record that the octets in `start..end` were received by the remote end.
+    fn add(&mut self, mut start: TcpSeqNumber, mut end: TcpSeqNumber) {
+        if start >= end { return }
+
+        // Merge every range overlapping or adjacent to the new one into it.
+        let mut ranges = [None; SCOREBOARD_SIZE + 1];
+        let mut count = 0;
+        for &(range_start, range_end) in self.ranges.iter().flat_map(|r| r) {
+            if range_end < start || range_start > end {
+                ranges[count] = Some((range_start, range_end));
+                count += 1;
+            } else {
+                if range_start < start { start = range_start }
+                if range_end > end { end = range_end }
+            }
+        }
+
+        // Insert the merged range, keeping the ranges sorted.
+        let index = ranges[..count].iter()
+            .position(|range| range.map_or(false, |(range_start, _)| range_start > start))
+            .unwrap_or(count);
+        for i in (index..count).rev() {
+            ranges[i + 1] = ranges[i];
+        }
+        ranges[index] = Some((start, end));
+
+        self.ranges.copy_from_slice(&ranges[..SCOREBOARD_SIZE]);
+    }
+
+    /// Forget the octets before `seq`, which were acknowledged cumulatively.
+    fn acknowledge(&mut self, seq: TcpSeqNumber) {
+        let mut ranges = [None; SCOREBOARD_SIZE];
+        let mut count = 0;
+        for &(start, end) in self.ranges.iter().flat_map(|r| r) {
+            if end <= seq { continue }
+            ranges[count] = Some((if start < seq { seq } else { start }, end));
+            count += 1;
+        }
+        self.ranges = ranges;
+    }
+
+    /// Return the first sequence number at or after `seq` that was not selectively
+    /// acknowledged, and how many octets starting at it were not, if that is limited.
+    fn next_hole(&self, mut seq: TcpSeqNumber) -> (TcpSeqNumber, Option<usize>) {
+        for &(start, end) in self.ranges.iter().flat_map(|r| r) {
+            if seq < start {
+                return (seq, Some(start - seq))
+            } else if seq < end {
+                seq = end
+            }
+        }
+        (seq, None)
+    }
 }
 
 /// A Transmission Control Protocol socket.
@@ -175,7 +487,9 @@ impl Timer {
 /// A TCP socket may passively listen for connections or actively connect to another endpoint.
 /// Note that, for listening sockets, there is no "backlog"; to be able to simultaneously
 /// accept several connections, as many sockets must be allocated, or any new connection
-/// attempts will be reset.
+/// attempts will be reset. A [TcpListener] manages such a set of sockets.
+///
+/// [TcpListener]: struct.TcpListener.html
 #[derive(Debug)]
 pub struct TcpSocket<'a> {
     pub(crate) meta: SocketMeta,
@@ -186,13 +500,75 @@ pub struct TcpSocket<'a> {
     tx_buffer:       SocketBuffer<'a>,
     /// Interval after which, if no inbound packets are received, the connection is aborted.
     timeout:         Option<u64>,
+    /// Interval after which, if data sent remains unacknowledged, the connection is aborted.
+    user_timeout:    Option<u64>,
+    /// The timestamp since which data sent has remained unacknowledged, if any has.
+    unacked_since:   Option<u64>,
     /// Interval at which keep-alive packets will be sent.
     keep_alive:      Option<u64>,
+    /// Interval at which keep-alive packets will be sent while the previous ones are
+    /// unanswered, if different.
+    keep_alive_interval: Option<u64>,
+    /// Number of unanswered keep-alive packets after which the connection is aborted.
+    keep_alive_count: Option<u8>,
+    /// Number of keep-alive packets sent since a packet was last received.
+    keep_alive_probes: u8,
+    /// Number of retransmission timeouts after which the connection is aborted.
+    max_retransmits: Option<u8>,
+    /// Number of retransmission timeouts since new data was last acknowledged.
+    retransmits:     u8,
+    /// Interval by which the acknowledgement of received data may be delayed.
+    ack_delay:       Option<u64>,
+    /// The time at which a delayed acknowledgement is due, if there is one.
+    ack_delay_until: Option<u64>,
+    /// Whether small segments are held back while data is in flight (Nagle's algorithm).
+    nagle:           bool,
+    /// The free space in the transmit buffer below which the socket is not writable.
+    send_lowat:      usize,
+    /// The largest maximum segment size to announce and to send, if limited below what
+    /// the MTU of the interface implies.
+    max_segment_size: Option<u16>,
+    /// How far past the start of the receive window out-of-order data is accepted.
+    reassembly_max:  Option<usize>,
+    /// The largest receive window to advertise, if limited below the receive buffer size.
+    recv_win_clamp:  Option<usize>,
+    /// Duration of the TIME-WAIT state.
+    time_wait_timeout: u64,
+    /// Whether a new SYN may reopen a connection in the TIME-WAIT state.
+    time_wait_reuse: bool,
+    /// The number of half-open connections on the local endpoint beyond which SYNs
+    /// are answered with SYN cookies, if any, and the secret the cookies are keyed with.
+    syn_cookies:     Option<(usize, [u8; 16])>,
+    /// The number of challenge ACKs sent per second, if limited.
+    challenge_ack_limit: Option<u32>,
+    /// The start of the second the challenge ACKs are counted in, and their number.
+    challenge_acks:  (u64, u32),
+    /// The function computing TCP MD5 signatures and the key they are computed with,
+    /// if the segments of the connection are signed.
+    md5_signing:     Option<(Md5SignatureFn, &'a [u8])>,
+    /// The traffic counters, without the round-trip time.
+    stats:           Stats,
+    /// The largest receive window to advertise, as tuned to the rate the buffer is drained at.
+    recv_win_limit:  usize,
+    /// The number of octets dequeued from the receive buffer since the drain rate was
+    /// last measured.
+    recv_drained:    usize,
+    /// The time at which the drain rate was last measured.
+    recv_drained_at: Option<u64>,
     /// The time-to-live (IPv4) or hop limit (IPv6) value used in outgoing packets.
     hop_limit:       Option<u8>,
+    /// The Differentiated Services Code Point used in outgoing packets.
+    dscp:            u8,
+    /// The IPv6 flow label used in outgoing packets, or 0 to use the one assigned
+    /// by the interface.
+    flow_label:      u32,
     /// Address passed to listen(). Listen address is set when listen() is called and
     /// used every time the socket is reset back to the LISTEN state.
     listen_address:  IpAddress,
+    /// Whether the connection was opened with connect(), rather than accepted while
+    /// listening; an RST in the SYN-RECEIVED state then refuses it instead of
+    /// resuming listening.
+    active_open:     bool,
     /// Current local endpoint. This is used for both filtering the incoming packets and
     /// setting the source address. When listening or initiating connection on/from
     /// an unspecified address, this field is updated with the chosen source address before
@@ -220,20 +596,86 @@ pub struct TcpSocket<'a> {
     /// The speculative remote window size.
     /// I.e. the actual remote window size minus the count of in-flight octets.
     remote_win_len:  usize,
+    /// The largest window the remote end has advertised.
+    remote_win_max:  usize,
     /// The maximum number of data octets that the remote side may receive.
     remote_mss:      usize,
+    /// The MTU of the path to the remote end, as far as the interface knows.
+    path_mtu:        Option<usize>,
     /// The timestamp of the last packet received.
     remote_last_ts:  Option<u64>,
+    /// Whether the remote end has permitted selective acknowledgements in its SYN.
+    remote_has_sack: bool,
+    /// The octets in the transmit buffer that the remote end has acknowledged selectively.
+    remote_sacked:   Scoreboard,
+    /// The sequence number of the last segment received out of order. The SACK block
+    /// containing it is reported first.
+    remote_last_ooo: Option<TcpSeqNumber>,
+    /// Whether the remote end has sent a timestamp option in its SYN.
+    remote_has_ts:   bool,
+    /// The last timestamp value received in order, which is echoed to the remote end.
+    remote_ts_recent: u32,
+    /// The round-trip time estimator, which is fed from the echoed timestamps.
+    rtte:            RttEstimator,
+    /// The time a segment was sent at, and the sequence number following it, which
+    /// measure the round-trip time with the resolution of the clock instead of that
+    /// of the timestamps.
+    rtt_timer:       Option<(Instant, TcpSeqNumber)>,
+    /// The congestion controller, which limits the count of in-flight octets
+    /// in addition to the remote window.
+    congestion:      AnyController,
+    /// The count of consecutive duplicate acknowledgements received.
+    remote_dup_acks: u8,
+    /// The last sequence number sent when fast recovery was entered, if it is in progress.
+    /// Fast recovery ends once everything up to it is acknowledged.
+    recovery_seq:    Option<TcpSeqNumber>,
+    /// Whether Explicit Congestion Notification is requested in, and accepted from, a SYN.
+    ecn:             bool,
+    /// Whether ECN has been negotiated with the remote end.
+    remote_has_ecn:  bool,
+    /// Whether a congestion mark was received, and is echoed until the remote end
+    /// indicates that it has reduced its congestion window.
+    ecn_echo:        bool,
+    /// Whether the congestion window was reduced because of an echoed mark, and the next
+    /// segment with new data should indicate so.
+    ecn_cwr:         bool,
+    /// The last sequence number sent when the congestion window was reduced because
+    /// of an echoed mark. Further marks are ignored until data after it is acknowledged.
+    ecn_recovery_seq: Option<TcpSeqNumber>,
+    /// The sequence number following the last octet ever sent. Only the data from it on
+    /// has never been sent before.
+    remote_max_seq:  Option<TcpSeqNumber>,
+    /// The sequence number following the last octet of urgent data enqueued, until
+    /// the remote end acknowledges it.
+    urgent_seq:      Option<TcpSeqNumber>,
+    /// The sequence number following the last octet of urgent data received, until
+    /// it is dequeued.
+    remote_urgent_seq: Option<TcpSeqNumber>,
+    /// The last error reported for the connection, if any.
+    error:           Option<SocketError>,
+    /// The last connection, once it is closed and its endpoints are cleared.
+    closed_connection: Option<ClosedConnection>,
+    /// The time at which the last segment was received, if any.
+    rx_timestamp:    Option<u64>,
+    /// The time at which the last segment was handed to the interface, if any.
+    tx_timestamp:    Option<u64>,
+    /// The events not taken by the application yet.
+    events:          EventBuffer<'a>,
+    #[cfg(feature = "async")]
+    rx_waker:        WakerRegistration,
+    #[cfg(feature = "async")]
+    tx_waker:        WakerRegistration,
 }
 
 const DEFAULT_MSS: usize = 536;
+const RECV_WINDOW_INITIAL: usize = 8192;
 
 impl<'a> TcpSocket<'a> {
     /// Create a socket using the given buffers.
     pub fn new<T>(rx_buffer: T, tx_buffer: T) -> TcpSocket<'a>
             where T: Into<SocketBuffer<'a>> {
         let (rx_buffer, tx_buffer) = (rx_buffer.into(), tx_buffer.into());
-        if rx_buffer.capacity() > <u16>::max_value() as usize {
+        if rx_buffer.max_capacity() > <u16>::max_value() as usize {
             panic!("buffers larger than {} require window scaling, which is not implemented",
                    <u16>::max_value())
         }
@@ -246,9 +688,36 @@ impl<'a> TcpSocket<'a> {
             tx_buffer:       tx_buffer,
             rx_buffer:       rx_buffer,
             timeout:         None,
+            user_timeout:    None,
+            unacked_since:   None,
             keep_alive:      None,
+            keep_alive_interval: None,
+            keep_alive_count: None,
+            keep_alive_probes: 0,
+            max_retransmits: None,
+            retransmits:     0,
+            ack_delay:       Some(ACK_DELAY),
+            ack_delay_until: None,
+            nagle:           true,
+            send_lowat:      1,
+            max_segment_size: None,
+            reassembly_max:  None,
+            recv_win_clamp:  None,
+            time_wait_timeout: CLOSE_DELAY,
+            time_wait_reuse: false,
+            syn_cookies:     None,
+            challenge_ack_limit: Some(CHALLENGE_ACK_LIMIT),
+            challenge_acks:  (0, 0),
+            md5_signing:     None,
+            stats:           Stats::default(),
+            recv_win_limit:  RECV_WINDOW_INITIAL,
+            recv_drained:    0,
+            recv_drained_at: None,
             hop_limit:       None,
+            dscp:            0,
+            flow_label:      0,
             listen_address:  IpAddress::default(),
+            active_open:     false,
             local_endpoint:  IpEndpoint::default(),
             remote_endpoint: IpEndpoint::default(),
             local_seq_no:    TcpSeqNumber::default(),
@@ -257,8 +726,37 @@ impl<'a> TcpSocket<'a> {
             remote_last_ack: None,
             remote_last_win: 0,
             remote_win_len:  0,
+            remote_win_max:  0,
             remote_mss:      DEFAULT_MSS,
+            path_mtu:        None,
             remote_last_ts:  None,
+            remote_has_sack: false,
+            remote_sacked:   Scoreboard::default(),
+            remote_last_ooo: None,
+            remote_has_ts:   false,
+            remote_ts_recent: 0,
+            rtte:            RttEstimator::default(),
+            rtt_timer:       None,
+            congestion:      AnyController::new(Algorithm::None),
+            remote_dup_acks: 0,
+            recovery_seq:    None,
+            ecn:             false,
+            remote_has_ecn:  false,
+            ecn_echo:        false,
+            ecn_cwr:         false,
+            ecn_recovery_seq: None,
+            remote_max_seq:  None,
+            urgent_seq:      None,
+            remote_urgent_seq: None,
+            error:           None,
+            closed_connection: None,
+            rx_timestamp:    None,
+            tx_timestamp:    None,
+            events:          EventBuffer::new(ManagedSlice::Borrowed(&mut [])),
+            #[cfg(feature = "async")]
+            rx_waker:        WakerRegistration::new(),
+            #[cfg(feature = "async")]
+            tx_waker:        WakerRegistration::new(),
         }
     }
 
@@ -268,6 +766,14 @@ impl<'a> TcpSocket<'a> {
         self.meta.handle
     }
 
+    /// Return a builder for a TCP socket, which checks its options for consistency
+    /// when the socket is created.
+    ///
+    /// See [TcpSocketBuilder](struct.TcpSocketBuilder.html).
+    pub fn builder() -> TcpSocketBuilder<'a> {
+        TcpSocketBuilder::new()
+    }
+
     /// Return the timeout duration.
     ///
     /// See also the [set_timeout](#method.set_timeout) method.
@@ -290,6 +796,156 @@ impl<'a> TcpSocket<'a> {
         self.timeout = duration
     }
 
+    /// Return the user timeout duration.
+    ///
+    /// See also the [set_user_timeout](#method.set_user_timeout) method.
+    pub fn user_timeout(&self) -> Option<Duration> {
+        self.user_timeout.map(Duration::from_millis)
+    }
+
+    /// Set the user timeout duration.
+    ///
+    /// A socket with a user timeout duration set will abort the connection if data or control
+    /// flags it has sent remain unacknowledged for longer than the specified duration, as
+    /// described in [RFC 5482]. The duration is counted from the moment the data is sent,
+    /// and restarted whenever the remote endpoint acknowledges some of it.
+    ///
+    /// Unlike with the [timeout](#method.set_timeout), packets that the remote endpoint sends
+    /// without acknowledging anything new, such as answers to keep-alive packets, do not
+    /// extend the connection.
+    ///
+    /// [RFC 5482]: https://tools.ietf.org/html/rfc5482
+    pub fn set_user_timeout(&mut self, duration: Option<Duration>) {
+        self.user_timeout = duration.map(|duration| duration.total_millis())
+    }
+
+    /// Return the duration of the TIME-WAIT state.
+    ///
+    /// See also the [set_time_wait_timeout](#method.set_time_wait_timeout) method.
+    pub fn time_wait_timeout(&self) -> Duration {
+        Duration::from_millis(self.time_wait_timeout)
+    }
+
+    /// Set the duration of the TIME-WAIT state.
+    ///
+    /// Once both ends have closed the connection, the socket lingers in the TIME-WAIT state
+    /// for this duration, 10 seconds by default, to answer a retransmitted FIN and to keep
+    /// stray segments of the connection from being mistaken for those of the next one.
+    /// A shorter duration makes the socket available again sooner, at the cost of
+    /// that protection. It is kept when the socket is reused for another connection.
+    pub fn set_time_wait_timeout(&mut self, duration: Duration) {
+        self.time_wait_timeout = duration.total_millis()
+    }
+
+    /// Return whether a new SYN may reopen a connection in the TIME-WAIT state.
+    ///
+    /// See also the [set_time_wait_reuse](#method.set_time_wait_reuse) method.
+    pub fn time_wait_reuse(&self) -> bool {
+        self.time_wait_reuse
+    }
+
+    /// Set whether a new SYN may reopen a connection in the TIME-WAIT state.
+    ///
+    /// If enabled, a socket in the TIME-WAIT state that receives a SYN from the same remote
+    /// endpoint answers it as if it were listening, as allowed by [RFC 1122 § 4.2.2.13],
+    /// instead of waiting for the TIME-WAIT state to end. The SYN is only accepted if it
+    /// cannot belong to the old connection: its timestamp must be newer than those of
+    /// the old connection if both used timestamps, as described in [RFC 6191], and its
+    /// sequence number must be past the end of the old connection otherwise. The socket
+    /// must also have no data left to be received. It is kept when the socket is reused for
+    /// another connection.
+    ///
+    /// [RFC 1122 § 4.2.2.13]: https://tools.ietf.org/html/rfc1122#section-4.2.2.13
+    /// [RFC 6191]: https://tools.ietf.org/html/rfc6191
+    pub fn set_time_wait_reuse(&mut self, enabled: bool) {
+        self.time_wait_reuse = enabled
+    }
+
+    /// Return the number of half-open connections beyond which SYNs are answered
+    /// with SYN cookies, if they are enabled.
+    ///
+    /// See also the [set_syn_cookies](#method.set_syn_cookies) method.
+    pub fn syn_cookies(&self) -> Option<usize> {
+        self.syn_cookies.map(|(threshold, _)| threshold)
+    }
+
+    /// Set the number of half-open connections beyond which SYNs are answered
+    /// with SYN cookies, and the secret the cookies are derived from, or disable them.
+    ///
+    /// Every socket listening on an endpoint that receives a SYN stays in the SYN-RECEIVED
+    /// state until the connection is established or times out, so a flood of SYNs from
+    /// forged addresses quickly exhausts a [backlog] of sockets. Once `threshold` sockets
+    /// are in the SYN-RECEIVED state on its local endpoint, a listening socket with SYN
+    /// cookies enabled answers SYNs statelessly instead, as described in [RFC 4987 § 3.6],
+    /// with a SYN|ACK whose sequence number authenticates the connection and encodes
+    /// the maximum segment size of the remote end; it only leaves the LISTEN state once
+    /// it receives an acknowledgement of such a SYN|ACK. Connections established this way
+    /// use neither selective acknowledgements, timestamps nor ECN, which a cookie
+    /// cannot encode.
+    ///
+    /// The threshold has to be less than the number of sockets listening on the endpoint,
+    /// or no socket is left listening to answer SYNs. The secret should be random,
+    /// and unknown to the remote hosts. It is kept when the socket is reused for
+    /// another connection.
+    ///
+    /// [backlog]: struct.TcpListener.html
+    /// [RFC 4987 § 3.6]: https://tools.ietf.org/html/rfc4987#section-3.6
+    pub fn set_syn_cookies(&mut self, threshold: Option<usize>, secret: [u8; 16]) {
+        self.syn_cookies = threshold.map(|threshold| (threshold, secret))
+    }
+
+    /// Return the number of challenge ACKs the socket sends per second, if limited.
+    ///
+    /// See also the [set_challenge_ack_limit](#method.set_challenge_ack_limit) method.
+    pub fn challenge_ack_limit(&self) -> Option<u32> {
+        self.challenge_ack_limit
+    }
+
+    /// Set the number of challenge ACKs the socket sends per second, or remove the limit.
+    ///
+    /// As described in [RFC 5961], a synchronized connection only accepts a RST whose
+    /// sequence number is exactly the next one expected, so that an attacker who cannot
+    /// see the traffic has to guess it rather than hit anywhere in the receive window.
+    /// A RST elsewhere in the window, a SYN, or an ACK of data that was never sent or was
+    /// acknowledged more than a window ago, is answered with a "challenge ACK", to which
+    /// a remote end that really lost the connection replies with a RST at the right
+    /// sequence number; RSTs outside of the window are dropped. The challenge ACKs are
+    /// limited to 10 per second by default, so that they cannot be used to flood the
+    /// remote end. The limit applies to each socket separately, as a shared one would let
+    /// an attacker infer the state of other connections, and it is kept when the socket
+    /// is reused for another connection.
+    ///
+    /// [RFC 5961]: https://tools.ietf.org/html/rfc5961
+    pub fn set_challenge_ack_limit(&mut self, limit: Option<u32>) {
+        self.challenge_ack_limit = limit
+    }
+
+    /// Return the key the segments of the connection are signed with, if they are.
+    ///
+    /// See also the [set_md5_signature](#method.set_md5_signature) method.
+    pub fn md5_signature_key(&self) -> Option<&'a [u8]> {
+        self.md5_signing.map(|(_, key)| key)
+    }
+
+    /// Set the key the segments of the connection are signed with, and the function
+    /// computing the signatures, or stop signing them.
+    ///
+    /// With a key, every segment sent carries a TCP MD5 signature as described in
+    /// [RFC 2385], which BGP peers commonly require, and every segment received without
+    /// a valid signature is dropped as if it had never arrived. The key has to be shared
+    /// with the remote end beforehand. As smoltcp does not implement MD5 itself,
+    /// `function` computes the signatures; it is passed the key, and the octets
+    /// the signature covers other than the key.
+    ///
+    /// The signature takes up 18 octets of option space, so that fewer SACK blocks fit
+    /// into a segment, and segmentation offload is not used for the connection, since
+    /// every segment has to be signed on its own.
+    ///
+    /// [RFC 2385]: https://tools.ietf.org/html/rfc2385
+    pub fn set_md5_signature(&mut self, key: Option<&'a [u8]>, function: Md5SignatureFn) {
+        self.md5_signing = key.map(|key| (function, key))
+    }
+
     /// Return the keep-alive interval.
     ///
     /// See also the [set_keep_alive](#method.set_keep_alive) method.
@@ -308,7 +964,10 @@ impl<'a> TcpSocket<'a> {
     ///   * The remote endpoint has crashed and does not answer.
     ///
     /// The keep-alive functionality together with the timeout functionality allows to react
-    /// to these error conditions.
+    /// to these error conditions. Alternatively, the connection can be aborted after a number
+    /// of unanswered keep-alive packets; see [set_keep_alive_count].
+    ///
+    /// [set_keep_alive_count]: #method.set_keep_alive_count
     pub fn set_keep_alive(&mut self, interval: Option<u64>) {
         self.keep_alive = interval;
         if self.keep_alive.is_some() {
@@ -318,115 +977,671 @@ impl<'a> TcpSocket<'a> {
         }
     }
 
-    /// Return the time-to-live (IPv4) or hop limit (IPv6) value used in outgoing packets.
+    /// Return the interval between unanswered keep-alive packets.
     ///
-    /// See also the [set_hop_limit](#method.set_hop_limit) method
-    pub fn hop_limit(&self) -> Option<u8> {
-        self.hop_limit
+    /// See also the [set_keep_alive_interval](#method.set_keep_alive_interval) method.
+    pub fn keep_alive_interval(&self) -> Option<u64> {
+        self.keep_alive_interval
     }
 
-    /// Set the time-to-live (IPv4) or hop limit (IPv6) value used in outgoing packets.
+    /// Set the interval between unanswered keep-alive packets.
     ///
-    /// A socket without an explicitly set hop limit value uses the default [IANA recommended]
-    /// value (64).
+    /// Once a keep-alive packet has been sent after the connection was idle for the
+    /// [keep-alive interval](#method.set_keep_alive), the following ones are sent at this
+    /// interval until the remote endpoint answers. This allows detecting a dead connection
+    /// quickly without waking up often while it is healthy. If no interval is set,
+    /// the keep-alive interval is used.
+    pub fn set_keep_alive_interval(&mut self, interval: Option<u64>) {
+        self.keep_alive_interval = interval
+    }
+
+    /// Return the number of unanswered keep-alive packets after which the connection
+    /// is aborted.
     ///
-    /// # Panics
+    /// See also the [set_keep_alive_count](#method.set_keep_alive_count) method.
+    pub fn keep_alive_count(&self) -> Option<u8> {
+        self.keep_alive_count
+    }
+
+    /// Set the number of unanswered keep-alive packets after which the connection is aborted.
     ///
-    /// This function panics if a hop limit value of 0 is given. See [RFC 1122 § 3.2.1.7].
+    /// A socket with a count set will abort the connection if the remote endpoint does not
+    /// answer any of that many consecutive keep-alive packets by the time the next one
+    /// would be sent. If no count is set, keep-alive packets are sent indefinitely.
+    pub fn set_keep_alive_count(&mut self, count: Option<u8>) {
+        self.keep_alive_count = count
+    }
+
+    /// Return the acknowledgement delay.
     ///
-    /// [IANA recommended]: https://www.iana.org/assignments/ip-parameters/ip-parameters.xhtml
-    /// [RFC 1122 § 3.2.1.7]: https://tools.ietf.org/html/rfc1122#section-3.2.1.7
-    pub fn set_hop_limit(&mut self, hop_limit: Option<u8>) {
-        // A host MUST NOT send a datagram with a hop limit value of 0
-        if let Some(0) = hop_limit {
-            panic!("the time-to-live value of a packet must not be zero")
-        }
+    /// See also the [set_ack_delay](#method.set_ack_delay) method.
+    pub fn ack_delay(&self) -> Option<u64> {
+        self.ack_delay
+    }
 
-        self.hop_limit = hop_limit
+    /// Set the acknowledgement delay, in milliseconds.
+    ///
+    /// When the acknowledgement delay is set, the socket does not acknowledge received data
+    /// right away, but waits for the given interval, so that the acknowledgement can be sent
+    /// together with outgoing data or a window update. Even so, every second segment is
+    /// acknowledged immediately, and so is any segment that is out of order or carries a FIN.
+    /// When the acknowledgement delay is `None`, every segment is acknowledged immediately.
+    ///
+    /// The acknowledgement delay is 10 ms by default, and is kept when the socket is reused
+    /// for another connection.
+    pub fn set_ack_delay(&mut self, delay: Option<u64>) {
+        self.ack_delay = delay;
+        if self.ack_delay.is_none() {
+            self.ack_delay_until = None;
+        }
     }
 
-    /// Return the local endpoint.
-    #[inline]
-    pub fn local_endpoint(&self) -> IpEndpoint {
-        self.local_endpoint
+    /// Return whether Nagle's algorithm is enabled.
+    ///
+    /// See also the [set_nagle_enabled](#method.set_nagle_enabled) method.
+    pub fn nagle_enabled(&self) -> bool {
+        self.nagle
     }
 
-    /// Return the remote endpoint.
-    #[inline]
-    pub fn remote_endpoint(&self) -> IpEndpoint {
-        self.remote_endpoint
+    /// Enable or disable Nagle's algorithm.
+    ///
+    /// When Nagle's algorithm is enabled, the socket does not send a segment smaller than
+    /// the maximum segment size while any data it has sent is unacknowledged, so that many
+    /// small writes are coalesced into fewer segments. Disabling it (the equivalent of
+    /// the `TCP_NODELAY` option) sends every write right away, at the cost of more segments.
+    ///
+    /// Nagle's algorithm is enabled by default, and the setting is kept when the socket
+    /// is reused for another connection.
+    pub fn set_nagle_enabled(&mut self, enabled: bool) {
+        self.nagle = enabled
     }
 
-    /// Return the connection state, in terms of the TCP state machine.
-    #[inline]
-    pub fn state(&self) -> State {
-        self.state
+    /// Return the send low-water mark.
+    ///
+    /// See also the [set_send_lowat](#method.set_send_lowat) method.
+    pub fn send_lowat(&self) -> usize {
+        self.send_lowat
     }
 
-    fn reset(&mut self) {
-        self.state           = State::Closed;
-        self.timer           = Timer::default();
-        self.assembler       = Assembler::new(self.rx_buffer.capacity());
-        self.tx_buffer.clear();
-        self.rx_buffer.clear();
-        self.keep_alive      = None;
-        self.timeout         = None;
-        self.hop_limit       = None;
-        self.listen_address  = IpAddress::default();
-        self.local_endpoint  = IpEndpoint::default();
-        self.remote_endpoint = IpEndpoint::default();
-        self.local_seq_no    = TcpSeqNumber::default();
-        self.remote_seq_no   = TcpSeqNumber::default();
-        self.remote_last_seq = TcpSeqNumber::default();
-        self.remote_last_ack = None;
-        self.remote_last_win = 0;
-        self.remote_win_len  = 0;
-        self.remote_mss      = DEFAULT_MSS;
-        self.remote_last_ts  = None;
+    /// Set the send low-water mark, in octets.
+    ///
+    /// The socket is only writable (see [can_send](#method.can_send)), and the waker
+    /// registered with [register_send_waker](#method.register_send_waker) is only woken
+    /// by acknowledgements, once at least this many octets of the transmit buffer are free,
+    /// so that an application is not woken to write a few octets at a time into a nearly
+    /// full buffer. A mark larger than the transmit buffer is reached once it is empty.
+    ///
+    /// The send low-water mark is 1 octet by default, like `SO_SNDLOWAT`, and is kept
+    /// when the socket is reused for another connection.
+    pub fn set_send_lowat(&mut self, octets: usize) {
+        self.send_lowat = cmp::max(octets, 1)
     }
 
-    /// Start listening on the given endpoint.
+    /// Return the limit of the maximum segment size, if any.
     ///
-    /// This function returns `Err(Error::Illegal)` if the socket was already open
-    /// (see [is_open](#method.is_open)), and `Err(Error::Unaddressable)`
-    /// if the port in the given endpoint is zero.
-    pub fn listen<T>(&mut self, local_endpoint: T) -> Result<()>
-            where T: Into<IpEndpoint> {
-        let local_endpoint = local_endpoint.into();
-        if local_endpoint.port == 0 { return Err(Error::Unaddressable) }
+    /// See also the [set_max_segment_size](#method.set_max_segment_size) method.
+    pub fn max_segment_size(&self) -> Option<u16> {
+        self.max_segment_size
+    }
 
-        if self.is_open() { return Err(Error::Illegal) }
+    /// Set the limit of the maximum segment size.
+    ///
+    /// The maximum segment size announced in a SYN or SYN|ACK is derived from the MTU of
+    /// the interface; if a limit is set, a smaller value is announced, and no larger segments
+    /// are sent either, regardless of the maximum segment size the remote end announces.
+    /// This is useful e.g. when the packets are tunneled, and would otherwise be fragmented
+    /// by the tunnel. The limit applies once a connection is established; it is kept when
+    /// the socket is reused for another connection.
+    pub fn set_max_segment_size(&mut self, max_segment_size: Option<u16>) {
+        self.max_segment_size = max_segment_size
+    }
 
-        self.reset();
-        self.listen_address  = local_endpoint.addr;
-        self.local_endpoint  = local_endpoint;
-        self.remote_endpoint = IpEndpoint::default();
-        self.set_state(State::Listen);
-        Ok(())
+    /// Return the initial retransmission timeout.
+    ///
+    /// See also the [set_initial_rto](#method.set_initial_rto) method.
+    pub fn initial_rto(&self) -> Duration {
+        Duration::from_millis(self.rtte.initial)
     }
 
-    /// Connect to a given endpoint.
+    /// Set the initial retransmission timeout.
     ///
-    /// The local port must be provided explicitly. Assuming `fn get_ephemeral_port() -> u16`
-    /// allocates a port between 49152 and 65535, a connection may be established as follows:
+    /// The initial retransmission timeout is used until the round-trip time has been measured;
+    /// it is clamped to the [retransmission timeout bounds](#method.set_rto_bounds).
+    /// The default suits a local network; on a link with a long round-trip time, such as
+    /// a satellite link, a longer timeout avoids spurious retransmissions of the SYN.
+    /// It is kept when the socket is reused for another connection.
+    pub fn set_initial_rto(&mut self, duration: Duration) {
+        let (min, max) = (self.rtte.min, self.rtte.max);
+        let initial = cmp::max(min, cmp::min(duration.total_millis(), max));
+        self.rtte.configure(initial, min, max)
+    }
+
+    /// Return the lower and upper bounds of the retransmission timeout.
     ///
-    /// ```rust,ignore
-    /// socket.connect((IpAddress::v4(10, 0, 0, 1), 80), get_ephemeral_port())
-    /// ```
+    /// See also the [set_rto_bounds](#method.set_rto_bounds) method.
+    pub fn rto_bounds(&self) -> (Duration, Duration) {
+        (Duration::from_millis(self.rtte.min), Duration::from_millis(self.rtte.max))
+    }
+
+    /// Set the lower and upper bounds of the retransmission timeout.
     ///
-    /// The local address may optionally be provided.
+    /// The retransmission timeout derived from the measured round-trip time, as well as
+    /// the timeout backed off after every retransmission, is kept within these bounds.
+    /// They are kept when the socket is reused for another connection.
     ///
-    /// This function returns an error if the socket was open; see [is_open](#method.is_open).
-    /// It also returns an error if the local or remote port is zero, or if the remote address
-    /// is unspecified.
-    pub fn connect<T, U>(&mut self, remote_endpoint: T, local_endpoint: U) -> Result<()>
-            where T: Into<IpEndpoint>, U: Into<IpEndpoint> {
-        let remote_endpoint = remote_endpoint.into();
-        let local_endpoint  = local_endpoint.into();
+    /// # Panics
+    /// This function panics if the lower bound is greater than the upper bound.
+    pub fn set_rto_bounds(&mut self, min: Duration, max: Duration) {
+        let (min, max) = (min.total_millis(), max.total_millis());
+        assert!(min <= max, "lower bound of the retransmission timeout exceeds upper bound");
+        let initial = cmp::max(min, cmp::min(self.rtte.initial, max));
+        self.rtte.configure(initial, min, max)
+    }
 
-        if self.is_open() { return Err(Error::Illegal) }
-        if !remote_endpoint.is_specified() { return Err(Error::Unaddressable) }
-        if local_endpoint.port == 0 { return Err(Error::Unaddressable) }
+    /// Return the number of retransmissions after which the connection is aborted, if any.
+    ///
+    /// See also the [set_max_retransmits](#method.set_max_retransmits) method.
+    pub fn max_retransmits(&self) -> Option<u8> {
+        self.max_retransmits
+    }
+
+    /// Set the number of retransmissions after which the connection is aborted.
+    ///
+    /// A socket with a retransmission limit set will abort the connection once that many
+    /// consecutive retransmissions have timed out without the remote endpoint acknowledging
+    /// any new data, instead of backing off indefinitely. The count is restarted whenever
+    /// the remote endpoint acknowledges some data. The limit is kept when the socket is
+    /// reused for another connection.
+    pub fn set_max_retransmits(&mut self, count: Option<u8>) {
+        self.max_retransmits = count
+    }
+
+    /// Return the traffic statistics of the socket.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            rtt: self.rtte.srtt.map(Duration::from_micros),
+            ..self.stats
+        }
+    }
+
+    /// Return the maximum number of data octets in a segment sent to the remote end,
+    /// before accounting for the TCP options.
+    ///
+    /// This is the maximum segment size the remote end announced, or 536 octets if it did not
+    /// announce one, limited by the [maximum segment size] set on the socket and by
+    /// the MTU of the path to the remote end, if the interface learned it.
+    ///
+    /// [maximum segment size]: #method.set_max_segment_size
+    pub fn send_mss(&self) -> usize {
+        let path_mtu = match self.path_mtu {
+            Some(path_mtu) => path_mtu,
+            None => return self.remote_mss
+        };
+        // The IP and TCP headers, without options.
+        let header_len = match self.remote_endpoint.addr {
+            #[cfg(feature = "proto-ipv6")]
+            IpAddress::Ipv6(_) => 40 + 20,
+            _ => 20 + 20
+        };
+        cmp::min(self.remote_mss, path_mtu.saturating_sub(header_len))
+    }
+
+    /// Return whether selective acknowledgements (RFC 2018) were negotiated with
+    /// the remote end, i.e. whether it permitted them in its SYN.
+    ///
+    /// Window scaling (RFC 7323 § 2) is never negotiated, since the socket does not send
+    /// the option; the windows of both ends are limited to 65535 octets.
+    pub fn sack_negotiated(&self) -> bool {
+        self.remote_has_sack
+    }
+
+    /// Return whether timestamps (RFC 7323 § 3) were negotiated with the remote end,
+    /// i.e. whether it sent the option in its SYN.
+    pub fn timestamps_negotiated(&self) -> bool {
+        self.remote_has_ts
+    }
+
+    /// Return the congestion window, in octets, or `usize::max_value()` if congestion
+    /// control is disabled.
+    ///
+    /// See also the [set_congestion_control](#method.set_congestion_control) method.
+    pub fn congestion_window(&self) -> usize {
+        self.congestion.window()
+    }
+
+    /// Return the slow start threshold, in octets, which is `usize::max_value()` until
+    /// the first loss, or if congestion control is disabled.
+    pub fn slow_start_threshold(&self) -> usize {
+        self.congestion.ssthresh()
+    }
+
+    /// Return the smoothed round-trip time of the connection, or `None` if it has not
+    /// been measured yet.
+    pub fn smoothed_rtt(&self) -> Option<Duration> {
+        self.rtte.srtt.map(Duration::from_micros)
+    }
+
+    /// Return the variation of the round-trip time of the connection, or `None` if
+    /// the round-trip time has not been measured yet.
+    pub fn rtt_variation(&self) -> Option<Duration> {
+        self.rtte.srtt.map(|_| Duration::from_micros(self.rtte.rttvar))
+    }
+
+    /// Return the current retransmission timeout of the connection.
+    pub fn retransmission_timeout(&self) -> Duration {
+        Duration::from_millis(self.rtte.retransmission_timeout())
+    }
+
+    /// Return the limit of the receive window, if any.
+    ///
+    /// See also the [set_recv_window_clamp](#method.set_recv_window_clamp) method.
+    pub fn recv_window_clamp(&self) -> Option<usize> {
+        self.recv_win_clamp
+    }
+
+    /// Set the limit of the receive window.
+    ///
+    /// The receive window advertised to the remote endpoint starts small, and grows
+    /// up to the size of the receive buffer as long as the application drains the buffer
+    /// quickly enough. If a limit is set, the window never grows past it, e.g. to keep
+    /// the remote endpoint from sending more than the device can queue at once.
+    /// The limit is kept when the socket is reused for another connection.
+    pub fn set_recv_window_clamp(&mut self, clamp: Option<usize>) {
+        self.recv_win_clamp = clamp
+    }
+
+    /// Return whether Explicit Congestion Notification is enabled.
+    ///
+    /// See also the [set_ecn_enabled](#method.set_ecn_enabled) method.
+    pub fn ecn_enabled(&self) -> bool {
+        self.ecn
+    }
+
+    /// Enable or disable Explicit Congestion Notification.
+    ///
+    /// When ECN is enabled, the socket negotiates it with the remote end during connection
+    /// setup, as described in [RFC 3168]. Once negotiated, new data is sent as ECN-capable,
+    /// congestion marks that routers place on received packets are echoed to the remote
+    /// end, and echoed marks reduce the congestion window like a loss would, without any
+    /// packets being dropped.
+    ///
+    /// ECN is disabled by default, and the setting is kept when the socket is reused
+    /// for another connection.
+    ///
+    /// [RFC 3168]: https://tools.ietf.org/html/rfc3168
+    pub fn set_ecn_enabled(&mut self, enabled: bool) {
+        self.ecn = enabled
+    }
+
+    /// Return the number of holes in the received data that can be tracked.
+    ///
+    /// See also the [set_reassembly_holes](#method.set_reassembly_holes) method.
+    pub fn reassembly_holes(&self) -> usize {
+        self.assembler.hole_limit()
+    }
+
+    /// Set the number of holes in the received data that can be tracked.
+    ///
+    /// Segments that arrive out of order are placed into the receive buffer as long as
+    /// the holes between them can be tracked, and are dropped otherwise. Four holes are
+    /// tracked by default, and the setting is kept when the socket is reused for another
+    /// connection.
+    ///
+    /// This function returns `Err(Error::Illegal)` if more holes than that are already
+    /// being tracked.
+    ///
+    /// # Panics
+    /// This function panics if `holes` is zero or greater than [ASSEMBLER_MAX_HOLES].
+    ///
+    /// [ASSEMBLER_MAX_HOLES]: ../storage/constant.ASSEMBLER_MAX_HOLES.html
+    pub fn set_reassembly_holes(&mut self, holes: usize) -> Result<()> {
+        self.assembler.set_hole_limit(holes).map_err(|()| Error::Illegal)
+    }
+
+    /// Return the maximum reassembly distance.
+    ///
+    /// See also the [set_reassembly_distance](#method.set_reassembly_distance) method.
+    pub fn reassembly_distance(&self) -> Option<usize> {
+        self.reassembly_max
+    }
+
+    /// Set the maximum reassembly distance, in octets.
+    ///
+    /// Segments that arrive out of order are dropped if they end further than the given
+    /// distance past the data received in order. By default, out of order segments
+    /// are accepted anywhere in the receive window. The setting is kept when the socket
+    /// is reused for another connection.
+    pub fn set_reassembly_distance(&mut self, distance: Option<usize>) {
+        self.reassembly_max = distance
+    }
+
+    /// Return the congestion control algorithm.
+    ///
+    /// See also the [set_congestion_control](#method.set_congestion_control) method.
+    pub fn congestion_control(&self) -> Algorithm {
+        self.congestion.algorithm()
+    }
+
+    /// Set the congestion control algorithm.
+    ///
+    /// By default, there is no congestion control, and the amount of data in flight
+    /// is only limited by the window of the remote end. The algorithm is kept when
+    /// the socket is reused for another connection. Changing the algorithm during
+    /// a connection restarts it with the initial window.
+    pub fn set_congestion_control(&mut self, algorithm: Algorithm) {
+        self.congestion = AnyController::new(algorithm);
+        self.congestion.set_mss(self.remote_mss);
+    }
+
+    /// Return the time-to-live (IPv4) or hop limit (IPv6) value used in outgoing packets.
+    ///
+    /// See also the [set_hop_limit](#method.set_hop_limit) method
+    pub fn hop_limit(&self) -> Option<u8> {
+        self.hop_limit
+    }
+
+    /// Set the time-to-live (IPv4) or hop limit (IPv6) value used in outgoing packets.
+    ///
+    /// A socket without an explicitly set hop limit value uses the default [IANA recommended]
+    /// value (64).
+    ///
+    /// # Panics
+    ///
+    /// This function panics if a hop limit value of 0 is given. See [RFC 1122 § 3.2.1.7].
+    ///
+    /// [IANA recommended]: https://www.iana.org/assignments/ip-parameters/ip-parameters.xhtml
+    /// [RFC 1122 § 3.2.1.7]: https://tools.ietf.org/html/rfc1122#section-3.2.1.7
+    pub fn set_hop_limit(&mut self, hop_limit: Option<u8>) {
+        // A host MUST NOT send a datagram with a hop limit value of 0
+        if let Some(0) = hop_limit {
+            panic!("the time-to-live value of a packet must not be zero")
+        }
+
+        self.hop_limit = hop_limit
+    }
+
+    /// Return the Differentiated Services Code Point used in outgoing packets.
+    ///
+    /// See also the [set_dscp](#method.set_dscp) method
+    pub fn dscp(&self) -> u8 {
+        self.dscp
+    }
+
+    /// Set the Differentiated Services Code Point used in outgoing packets.
+    ///
+    /// The code point is carried in the upper six bits of the IPv4 Type of Service or
+    /// IPv6 Traffic Class field, and lets the network prioritize the traffic of this socket;
+    /// see [RFC 2474]. A socket without an explicitly set code point uses the default one (0).
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the code point does not fit in six bits.
+    ///
+    /// [RFC 2474]: https://tools.ietf.org/html/rfc2474
+    pub fn set_dscp(&mut self, dscp: u8) {
+        if dscp > 0x3f {
+            panic!("the DSCP value of a packet must fit in six bits")
+        }
+
+        self.dscp = dscp
+    }
+
+    /// Return the IPv6 flow label used in outgoing packets.
+    ///
+    /// See also the [set_flow_label](#method.set_flow_label) method
+    pub fn flow_label(&self) -> u32 {
+        self.flow_label
+    }
+
+    /// Set the IPv6 flow label used in outgoing packets.
+    ///
+    /// A nonzero label takes precedence over the [flow label policy] of the interface,
+    /// e.g. to keep the label an application chose for a flow; see [RFC 6437]. A socket
+    /// without an explicitly set label (0) is labeled according to the policy.
+    /// The label is not used in IPv4 packets.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the label does not fit in twenty bits.
+    ///
+    /// [flow label policy]: ../iface/enum.FlowLabelPolicy.html
+    /// [RFC 6437]: https://tools.ietf.org/html/rfc6437
+    pub fn set_flow_label(&mut self, flow_label: u32) {
+        if flow_label > 0xfffff {
+            panic!("the flow label of a packet must fit in twenty bits")
+        }
+
+        self.flow_label = flow_label
+    }
+
+    /// Return the local endpoint.
+    #[inline]
+    pub fn local_endpoint(&self) -> IpEndpoint {
+        self.local_endpoint
+    }
+
+    /// Return the remote endpoint.
+    #[inline]
+    pub fn remote_endpoint(&self) -> IpEndpoint {
+        self.remote_endpoint
+    }
+
+    /// Return the endpoints and the final statistics of the last connection of the socket,
+    /// if it is closed.
+    ///
+    /// The endpoints of a closed connection are cleared, so that [local_endpoint] and
+    /// [remote_endpoint] return unspecified endpoints, and the statistics may change
+    /// as the socket is reset; both are kept here, e.g. for logging, until the socket
+    /// listens or connects again.
+    ///
+    /// [local_endpoint]: #method.local_endpoint
+    /// [remote_endpoint]: #method.remote_endpoint
+    pub fn closed_connection(&self) -> Option<ClosedConnection> {
+        if self.state != State::Closed { return None }
+
+        if self.remote_endpoint.is_specified() {
+            // The connection was aborted, and the reset is not sent yet.
+            Some(self.connection())
+        } else {
+            self.closed_connection
+        }
+    }
+
+    fn connection(&self) -> ClosedConnection {
+        ClosedConnection {
+            local_endpoint:  self.local_endpoint,
+            remote_endpoint: self.remote_endpoint,
+            stats:           self.stats(),
+        }
+    }
+
+    /// Clear the endpoints of the connection, keeping them and the final statistics
+    /// for [closed_connection](#method.closed_connection).
+    fn forget_endpoints(&mut self) {
+        if self.remote_endpoint.is_specified() {
+            self.closed_connection = Some(self.connection());
+        }
+        self.local_endpoint  = IpEndpoint::default();
+        self.remote_endpoint = IpEndpoint::default();
+    }
+
+    /// Return the connection state, in terms of the TCP state machine.
+    #[inline]
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Return the last error reported for the connection, if any, and clear it.
+    ///
+    /// Errors are reported by the remote host or by a router on the path to it, using
+    /// ICMP error messages. An error reported while the socket is in the `SYN-SENT` state
+    /// aborts the connection attempt, without sending a reset packet; errors reported later
+    /// may be transient, and do not change the state of the socket.
+    ///
+    /// The error is cleared as well when the socket is opened again.
+    pub fn take_error(&mut self) -> Option<SocketError> {
+        self.error.take()
+    }
+
+    /// Set the buffer the socket reports the events of its connections into.
+    ///
+    /// The socket has no event buffer by default, and reports no events; with one,
+    /// it records the establishment of a connection, its closing or reset by
+    /// the remote end, the expiry of the retransmission timer, the closing of
+    /// the remote window and the freeing of space in the transmit buffer up to the
+    /// send low-water mark, each with the time it happened, so that the application
+    /// can follow the health of a connection without comparing its state between polls.
+    /// Once the buffer is full, the oldest event is dropped to record a new one.
+    /// The events are kept when the socket is opened again.
+    ///
+    /// See also the [take_event](#method.take_event) method.
+    pub fn set_event_buffer<T>(&mut self, buffer: T)
+            where T: Into<EventBuffer<'a>> {
+        self.events = buffer.into()
+    }
+
+    /// Return the oldest event reported by the socket, and the time it happened,
+    /// if any, and remove it from the event buffer.
+    pub fn take_event(&mut self) -> Option<(u64, Event)> {
+        self.events.dequeue_one().ok().map(|event| *event)
+    }
+
+    /// Register a waker to be woken once data is received, the remote end closes
+    /// the connection, or the state of the socket changes otherwise.
+    ///
+    /// Only one waker is registered at a time, and it is woken at most once; it has to be
+    /// registered again to be woken again. The waker may be woken spuriously.
+    #[cfg(feature = "async")]
+    pub fn register_recv_waker(&mut self, waker: &Waker) {
+        self.rx_waker.register(waker)
+    }
+
+    /// Register a waker to be woken once the remote end acknowledges data, freeing space
+    /// in the transmit buffer up to the [send low-water mark](#method.set_send_lowat),
+    /// or the state of the socket changes.
+    ///
+    /// See also [register_recv_waker](#method.register_recv_waker).
+    #[cfg(feature = "async")]
+    pub fn register_send_waker(&mut self, waker: &Waker) {
+        self.tx_waker.register(waker)
+    }
+
+    /// Return the time at which the last segment of the connection was received
+    /// by the interface, if any.
+    pub fn rx_timestamp(&self) -> Option<Instant> {
+        self.rx_timestamp.map(|timestamp| Instant::from_millis(timestamp as i64))
+    }
+
+    /// Return the time at which the last segment of the connection was handed to
+    /// the interface for transmission, if any.
+    ///
+    /// Once all data in the transmit buffer has been acknowledged, this is no earlier
+    /// than the time at which the last octet enqueued was sent.
+    pub fn tx_timestamp(&self) -> Option<Instant> {
+        self.tx_timestamp.map(|timestamp| Instant::from_millis(timestamp as i64))
+    }
+
+    fn reset(&mut self) {
+        self.state           = State::Closed;
+        self.timer           = Timer::default();
+        self.assembler       = Assembler::with_holes(self.rx_buffer.capacity(),
+                                                     self.assembler.hole_limit());
+        self.tx_buffer.clear();
+        self.rx_buffer.clear();
+        self.keep_alive      = None;
+        self.keep_alive_interval = None;
+        self.keep_alive_count = None;
+        self.keep_alive_probes = 0;
+        self.ack_delay_until = None;
+        self.timeout         = None;
+        self.user_timeout    = None;
+        self.unacked_since   = None;
+        self.hop_limit       = None;
+        self.listen_address  = IpAddress::default();
+        self.active_open     = false;
+        self.forget_endpoints();
+        self.local_seq_no    = TcpSeqNumber::default();
+        self.remote_seq_no   = TcpSeqNumber::default();
+        self.remote_last_seq = TcpSeqNumber::default();
+        self.remote_last_ack = None;
+        self.remote_last_win = 0;
+        self.remote_win_len  = 0;
+        self.remote_win_max  = 0;
+        self.remote_mss      = DEFAULT_MSS;
+        self.path_mtu        = None;
+        self.remote_last_ts  = None;
+        self.remote_has_sack = false;
+        self.remote_sacked   = Scoreboard::default();
+        self.remote_last_ooo = None;
+        self.remote_has_ts   = false;
+        self.remote_ts_recent = 0;
+        self.rtte.reset();
+        self.rtt_timer       = None;
+        self.retransmits     = 0;
+        self.recv_win_limit  = RECV_WINDOW_INITIAL;
+        self.recv_drained    = 0;
+        self.recv_drained_at = None;
+        self.congestion      = AnyController::new(self.congestion.algorithm());
+        self.remote_dup_acks = 0;
+        self.recovery_seq    = None;
+        self.remote_has_ecn  = false;
+        self.ecn_echo        = false;
+        self.ecn_cwr         = false;
+        self.ecn_recovery_seq = None;
+        self.remote_max_seq  = None;
+        self.urgent_seq      = None;
+        self.remote_urgent_seq = None;
+        self.error           = None;
+        self.rx_timestamp    = None;
+        self.tx_timestamp    = None;
+    }
+
+    /// Start listening on the given endpoint.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the socket was already open
+    /// (see [is_open](#method.is_open)), and `Err(Error::Unaddressable)`
+    /// if the port in the given endpoint is zero.
+    pub fn listen<T>(&mut self, local_endpoint: T) -> Result<()>
+            where T: Into<IpEndpoint> {
+        let local_endpoint = local_endpoint.into();
+        if local_endpoint.port == 0 { return Err(Error::Unaddressable) }
+
+        if self.is_open() { return Err(Error::Illegal) }
+
+        self.reset();
+        self.stats           = Stats::default();
+        self.closed_connection = None;
+        self.listen_address  = local_endpoint.addr;
+        self.local_endpoint  = local_endpoint;
+        self.remote_endpoint = IpEndpoint::default();
+        self.set_state(State::Listen);
+        Ok(())
+    }
+
+    /// Connect to a given endpoint.
+    ///
+    /// If the local port is zero, the interface allocates an ephemeral port to the socket
+    /// before sending the SYN; see [EthernetInterface::port_allocator]. A connection
+    /// may be established as follows:
+    ///
+    /// ```rust,ignore
+    /// socket.connect((IpAddress::v4(10, 0, 0, 1), 80), 0)
+    /// ```
+    ///
+    /// The local address may optionally be provided.
+    ///
+    /// This function returns an error if the socket was open; see [is_open](#method.is_open).
+    /// It also returns an error if the remote port is zero, or if the remote address
+    /// is unspecified.
+    ///
+    /// [EthernetInterface::port_allocator]: ../iface/struct.EthernetInterface.html#method.port_allocator
+    pub fn connect<T, U>(&mut self, remote_endpoint: T, local_endpoint: U) -> Result<()>
+            where T: Into<IpEndpoint>, U: Into<IpEndpoint> {
+        let remote_endpoint = remote_endpoint.into();
+        let local_endpoint  = local_endpoint.into();
+
+        if self.is_open() { return Err(Error::Illegal) }
+        if !remote_endpoint.is_specified() { return Err(Error::Unaddressable) }
 
         // If local address is not provided, use an unspecified address but a specified protocol.
         // This lets us lower IpRepr later to determine IP header size and calculate MSS,
@@ -441,6 +1656,9 @@ impl<'a> TcpSocket<'a> {
         let local_seq_no = self.local_seq_no;
 
         self.reset();
+        self.stats           = Stats::default();
+        self.closed_connection = None;
+        self.active_open     = true;
         self.local_endpoint  = local_endpoint;
         self.remote_endpoint = remote_endpoint;
         self.local_seq_no    = local_seq_no;
@@ -449,8 +1667,17 @@ impl<'a> TcpSocket<'a> {
         Ok(())
     }
 
+    /// Set the local port of a socket that connected without one.
+    pub(crate) fn set_local_port(&mut self, port: u16) {
+        debug_assert!(self.state == State::SynSent && self.local_endpoint.port == 0);
+        self.local_endpoint.port = port;
+    }
+
     /// Close the transmit half of the full-duplex connection.
     ///
+    /// If the connection is not established yet, the socket is closed right away;
+    /// use [shutdown_send](#method.shutdown_send) to only act on a connection.
+    ///
     /// Note that there is no corresponding function for the receive half of the full-duplex
     /// connection; only the remote end can close it. If you no longer wish to receive any
     /// data and would like to reuse the socket right away, use [abort](#method.abort).
@@ -477,6 +1704,27 @@ impl<'a> TcpSocket<'a> {
         }
     }
 
+    /// Shut down the transmit half of the connection, keeping the receive half open.
+    ///
+    /// A FIN is sent once all data in the transmit buffer has been sent; after that,
+    /// [send](#method.send) fails, but [recv](#method.recv) keeps returning data until
+    /// the remote endpoint closes its transmit half as well.
+    ///
+    /// Unlike [close](#method.close), which closes a socket that is not connected yet,
+    /// this function returns `Err(Error::Illegal)` if there is no connection to half-close,
+    /// i.e. in the `CLOSED`, `LISTEN` and `SYN-SENT` states. If the transmit half
+    /// is already closed, it does nothing.
+    pub fn shutdown_send(&mut self) -> Result<()> {
+        match self.state {
+            State::Closed | State::Listen | State::SynSent =>
+                Err(Error::Illegal),
+            _ => {
+                self.close();
+                Ok(())
+            }
+        }
+    }
+
     /// Aborts the connection, if any.
     ///
     /// This function instantly closes the socket. One reset packet will be sent to the remote
@@ -580,12 +1828,18 @@ impl<'a> TcpSocket<'a> {
     }
 
     /// Check whether the transmit half of the full-duplex connection is open
-    /// (see [may_send](#method.may_send), and the transmit buffer is not full.
+    /// (see [may_send](#method.may_send), and the transmit buffer is not full,
+    /// or has as much free space as the [send low-water mark](#method.set_send_lowat).
     #[inline]
     pub fn can_send(&self) -> bool {
         if !self.may_send() { return false }
 
-        !self.tx_buffer.is_full()
+        !self.tx_buffer.is_full() && self.send_lowat_reached()
+    }
+
+    /// Check whether the free space in the transmit buffer reaches the send low-water mark.
+    fn send_lowat_reached(&self) -> bool {
+        self.tx_buffer.window() >= cmp::min(self.send_lowat, self.tx_buffer.capacity())
     }
 
     /// Check whether the receive half of the full-duplex connection buffer is open
@@ -642,19 +1896,112 @@ impl<'a> TcpSocket<'a> {
         })
     }
 
-    fn recv_impl<'b, F, R>(&'b mut self, f: F) -> Result<R>
-            where F: FnOnce(&'b mut SocketBuffer<'a>) -> (usize, R) {
-        // We may have received some data inside the initial SYN, but until the connection
-        // is fully open we must not dequeue any data, as it may be overwritten by e.g.
-        // another (stale) SYN. (We do not support TCP Fast Open.)
-        if !self.may_recv() { return Err(Error::Illegal) }
+    /// Enqueue a sequence of octets to be sent, and fill it from several slices in turn.
+    ///
+    /// The slices may be e.g. `&[u8]` or `std::io::IoSlice`. This function returns
+    /// the amount of bytes actually enqueued, which is limited by the amount of free space
+    /// in the transmit buffer; down to zero.
+    ///
+    /// See also [send_slice](#method.send_slice).
+    pub fn send_vectored<T>(&mut self, data: &[T]) -> Result<usize>
+            where T: Deref<Target = [u8]> {
+        self.send_impl(|tx_buffer| {
+            let mut size = 0;
+            for slice in data {
+                let enqueued = tx_buffer.enqueue_slice(slice);
+                size += enqueued;
+                if enqueued < slice.len() { break }
+            }
+            (size, size)
+        })
+    }
 
-        let _old_length = self.rx_buffer.len();
-        let (size, result) = f(&mut self.rx_buffer);
-        self.remote_seq_no += size;
-        if size > 0 {
-            #[cfg(any(test, feature = "verbose"))]
-            net_trace!("{}:{}:{}: rx buffer: dequeueing {} octets (now {})",
+    /// Enqueue octets to be sent, and fill them by calling `reader` with the free space
+    /// of the transmit buffer, until it is full or `reader` returns less than it was
+    /// offered; `reader` returns how many octets it wrote.
+    ///
+    /// The data is written straight into the transmit buffer, e.g. from a log kept in
+    /// flash, so an application streaming a large amount of data may call this function
+    /// whenever the socket becomes writable, without copying through a temporary buffer.
+    /// This function returns the amount of bytes enqueued.
+    ///
+    /// See also [send](#method.send).
+    pub fn send_from<F>(&mut self, mut reader: F) -> Result<usize>
+            where F: FnMut(&mut [u8]) -> usize {
+        self.send_impl(|tx_buffer| {
+            let mut size = 0;
+            // The free space is in at most two pieces, before and after the end
+            // of the storage.
+            for _ in 0..2 {
+                let (written, full) = tx_buffer.enqueue_many_with(|free| {
+                    let written = reader(free);
+                    (written, written == free.len())
+                });
+                size += written;
+                if !full || written == 0 { break }
+            }
+            (size, size)
+        })
+    }
+
+    /// Enqueue as many octets as fit in the transmit buffer from the given buffer,
+    /// and dequeue them from it.
+    ///
+    /// The octets are copied straight from one buffer into the other. This function
+    /// returns the amount of bytes moved, which is limited by the amount of free space
+    /// in the transmit buffer; down to zero.
+    ///
+    /// See also [send_slice](#method.send_slice).
+    pub fn send_from_buffer(&mut self, source: &mut RingBuffer<u8>) -> Result<usize> {
+        self.send_impl(|tx_buffer| {
+            let mut size = 0;
+            // The data is in at most two pieces, before and after the end of the storage.
+            for _ in 0..2 {
+                let (moved, ()) = source.dequeue_many_with(|data| {
+                    (tx_buffer.enqueue_slice(data), ())
+                });
+                size += moved;
+                if moved == 0 { break }
+            }
+            (size, size)
+        })
+    }
+
+    /// Enqueue a sequence of urgent octets to be sent, and fill it from a slice.
+    ///
+    /// The octets are sent in sequence with the rest of the data, and the remote endpoint
+    /// is notified with the urgent pointer that urgent data is pending until they
+    /// are acknowledged. Only the end of the urgent data is indicated; if more urgent data
+    /// is enqueued before the earlier one is acknowledged, it extends the earlier one.
+    ///
+    /// This function otherwise behaves identically to [send_slice](#method.send_slice).
+    pub fn send_urgent(&mut self, data: &[u8]) -> Result<usize> {
+        let size = self.send_slice(data)?;
+        if size > 0 {
+            self.urgent_seq = Some(self.local_seq_no + self.tx_buffer.len());
+        }
+        Ok(size)
+    }
+
+    fn recv_impl<'b, F, R>(&'b mut self, f: F) -> Result<R>
+            where F: FnOnce(&'b mut SocketBuffer<'a>) -> (usize, R) {
+        // We may have received some data inside the initial SYN, but until the connection
+        // is fully open we must not dequeue any data, as it may be overwritten by e.g.
+        // another (stale) SYN. (We do not support TCP Fast Open.)
+        if !self.may_recv() { return Err(Error::Illegal) }
+
+        let _old_length = self.rx_buffer.len();
+        let (size, result) = f(&mut self.rx_buffer);
+        self.remote_seq_no += size;
+        self.recv_drained += size;
+        match self.remote_urgent_seq {
+            Some(urgent_seq) if urgent_seq <= self.remote_seq_no =>
+                self.remote_urgent_seq = None,
+            _ => ()
+        }
+        if size > 0 {
+            #[cfg(any(test, feature = "verbose"))]
+            net_trace!("{}:{}:{}: rx buffer: dequeueing {} octets (now {})",
                        self.meta.handle, self.local_endpoint, self.remote_endpoint,
                        size, _old_length - size);
         }
@@ -686,6 +2033,26 @@ impl<'a> TcpSocket<'a> {
         })
     }
 
+    /// Dequeue a sequence of received octets, and fill several slices from it in turn.
+    ///
+    /// The slices may be e.g. `&mut [u8]` or `std::io::IoSliceMut`. This function returns
+    /// the amount of bytes actually dequeued, which is limited by the amount of octets
+    /// in the receive buffer; down to zero.
+    ///
+    /// See also [recv_slice](#method.recv_slice).
+    pub fn recv_vectored<T>(&mut self, data: &mut [T]) -> Result<usize>
+            where T: DerefMut<Target = [u8]> {
+        self.recv_impl(|rx_buffer| {
+            let mut size = 0;
+            for slice in data.iter_mut() {
+                let dequeued = rx_buffer.dequeue_slice(slice);
+                size += dequeued;
+                if dequeued < slice.len() { break }
+            }
+            (size, size)
+        })
+    }
+
     /// Peek at a sequence of received octets without removing them from
     /// the receive buffer, and return a pointer to it.
     ///
@@ -715,6 +2082,19 @@ impl<'a> TcpSocket<'a> {
         Ok(buffer.len())
     }
 
+    /// Return the amount of octets that have to be dequeued to receive all urgent data
+    /// the remote endpoint has sent, if there is any such data.
+    ///
+    /// The urgent data is not taken out of the stream but received in sequence with
+    /// the rest of the data, as recommended in [RFC 6093]; the octets up to the mark may
+    /// not all be in the receive buffer yet. Once they have been dequeued, this function
+    /// returns `None`.
+    ///
+    /// [RFC 6093]: https://tools.ietf.org/html/rfc6093
+    pub fn recv_urgent_mark(&self) -> Option<usize> {
+        self.remote_urgent_seq.map(|urgent_seq| urgent_seq - self.remote_seq_no)
+    }
+
     /// Return the amount of octets queued in the transmit buffer.
     ///
     /// Note that the Berkeley sockets interface does not have an equivalent of this API.
@@ -729,6 +2109,100 @@ impl<'a> TcpSocket<'a> {
         self.rx_buffer.len()
     }
 
+    /// Return the occupancy statistics of the transmit buffer, in octets.
+    pub fn send_buffer_stats(&self) -> BufferStats {
+        self.tx_buffer.stats()
+    }
+
+    /// Return the occupancy statistics of the receive buffer, in octets.
+    pub fn recv_buffer_stats(&self) -> BufferStats {
+        self.rx_buffer.stats()
+    }
+
+    /// Restart the occupancy statistics of both buffers from their current contents.
+    pub fn reset_buffer_stats(&mut self) {
+        self.tx_buffer.reset_stats();
+        self.rx_buffer.reset_stats();
+    }
+
+    /// Resize the receive buffer of a closed socket to the given capacity.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the socket is open
+    /// (see [is_open](#method.is_open)) or the buffer is not empty, and
+    /// `Err(Error::Exhausted)` if the buffer storage is fixed-size (not a `Vec`).
+    ///
+    /// # Panics
+    /// This function panics if the capacity is larger than 65535, as window scaling
+    /// is not implemented.
+    #[cfg(any(feature = "std", feature = "alloc"))]
+    pub fn resize_recv_buffer(&mut self, capacity: usize) -> Result<()> {
+        if capacity > <u16>::max_value() as usize {
+            panic!("buffers larger than {} require window scaling, which is not implemented",
+                   <u16>::max_value())
+        }
+        if self.is_open() { return Err(Error::Illegal) }
+
+        self.rx_buffer.resize_with(capacity, || 0)?;
+        self.assembler = Assembler::with_holes(self.rx_buffer.capacity(),
+                                               self.assembler.hole_limit());
+        Ok(())
+    }
+
+    /// Resize the transmit buffer of a closed socket to the given capacity.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the socket is open
+    /// (see [is_open](#method.is_open)) or the buffer is not empty, and
+    /// `Err(Error::Exhausted)` if the buffer storage is fixed-size (not a `Vec`).
+    #[cfg(any(feature = "std", feature = "alloc"))]
+    pub fn resize_send_buffer(&mut self, capacity: usize) -> Result<()> {
+        if self.is_open() { return Err(Error::Illegal) }
+
+        self.tx_buffer.resize_with(capacity, || 0)
+    }
+
+    /// Set the maximum segment size of the segments sent to the one the remote end announced,
+    /// if any, clamped to the limit set on the socket.
+    fn set_remote_mss(&mut self, max_seg_size: Option<u16>) {
+        if let Some(max_seg_size) = max_seg_size {
+            self.remote_mss = max_seg_size as usize
+        }
+        if let Some(max_segment_size) = self.max_segment_size {
+            self.remote_mss = cmp::min(self.remote_mss, max_segment_size as usize)
+        }
+    }
+
+    /// Limit the segments sent to the remote end to the MTU of the path to it, which
+    /// the interface learns from ICMP messages. See RFC 1191 § 3.1 and RFC 8201 § 5.2.
+    pub(crate) fn set_path_mtu(&mut self, mtu: usize) {
+        self.path_mtu = Some(mtu)
+    }
+
+    /// Return the maximum number of data octets in a segment with the options of `repr`,
+    /// given the maximum before accounting for the options.
+    ///
+    /// The SACK blocks, the timestamp and the signature count against the segment size.
+    pub(crate) fn segment_size(send_mss: usize, repr: &TcpRepr) -> usize {
+        let options_len = repr.header_len() - TcpRepr {
+            sack_ranges: [None; TCP_SACK_RANGE_COUNT],
+            timestamp:   None,
+            md5_signature: None,
+            ..*repr
+        }.header_len();
+        send_mss.saturating_sub(options_len)
+    }
+
+    fn report(&mut self, timestamp: u64, event: Event) {
+        if self.events.capacity() == 0 { return }
+        net_trace!("{}:{}:{}: event: {}",
+                   self.meta.handle, self.local_endpoint, self.remote_endpoint, event);
+        if self.events.is_full() {
+            let _ = self.events.dequeue_one();
+        }
+        if let Ok(slot) = self.events.enqueue_one() {
+            *slot = (timestamp, event)
+        }
+    }
+
     fn set_state(&mut self, state: State) {
         if self.state != state {
             if self.remote_endpoint.addr.is_unspecified() {
@@ -740,6 +2214,11 @@ impl<'a> TcpSocket<'a> {
                            self.meta.handle, self.local_endpoint, self.remote_endpoint,
                            self.state, state);
             }
+            #[cfg(feature = "async")]
+            {
+                self.rx_waker.wake();
+                self.tx_waker.wake();
+            }
         }
         self.state = state
     }
@@ -753,6 +2232,13 @@ impl<'a> TcpSocket<'a> {
             ack_number:   None,
             window_len:   0,
             max_seg_size: None,
+            sack_permitted: false,
+            sack_ranges:  [None; TCP_SACK_RANGE_COUNT],
+            timestamp:    None,
+            md5_signature: None,
+            ece:          false,
+            cwr:          false,
+            urgent_at:    None,
             payload:      &[]
         };
         let ip_reply_repr = IpRepr::Unspecified {
@@ -760,7 +2246,10 @@ impl<'a> TcpSocket<'a> {
             dst_addr:    ip_repr.src_addr(),
             protocol:    IpProtocol::Tcp,
             payload_len: reply_repr.buffer_len(),
-            hop_limit:   64
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0,
+            flow_label:  0
         };
         (ip_reply_repr, reply_repr)
     }
@@ -781,8 +2270,41 @@ impl<'a> TcpSocket<'a> {
         (ip_reply_repr, reply_repr)
     }
 
-    fn ack_reply(&self, ip_repr: &IpRepr, repr: &TcpRepr) -> (IpRepr, TcpRepr<'static>) {
-        let (ip_reply_repr, mut reply_repr) = Self::reply(ip_repr, repr);
+    /// Answer a SYN received in the LISTEN state with a SYN|ACK carrying a SYN cookie,
+    /// without leaving the LISTEN state.
+    pub(crate) fn syn_cookie_reply(&self, now: Instant, caps: &DeviceCapabilities,
+                                   ip_repr: &IpRepr, repr: &TcpRepr) ->
+                                  Option<(IpRepr, TcpRepr<'static>)> {
+        let secret = match (self.state, self.syn_cookies) {
+            (State::Listen, Some((_, secret))) => secret,
+            _ => return None
+        };
+        let local_endpoint  = IpEndpoint::new(ip_repr.dst_addr(), repr.dst_port);
+        let remote_endpoint = IpEndpoint::new(ip_repr.src_addr(), repr.src_port);
+        net_trace!("{}:{}:{}: received SYN, answering with SYN cookie",
+                   self.meta.handle, local_endpoint, remote_endpoint);
+
+        let (mut ip_reply_repr, mut reply_repr) = Self::reply(ip_repr, repr);
+        reply_repr.control    = TcpControl::Syn;
+        reply_repr.seq_number = tcp_cookie::encode(&secret, now.total_millis() as u64,
+                                                   local_endpoint, remote_endpoint,
+                                                   repr.seq_number, repr.max_seg_size);
+        reply_repr.ack_number = Some(repr.seq_number + 1);
+        reply_repr.window_len = self.recv_window() as u16;
+        let mut max_segment_size = caps.max_transmission_unit
+            .saturating_sub(ip_repr.buffer_len())
+            .saturating_sub(reply_repr.header_len());
+        if let Some(limit) = self.max_segment_size {
+            max_segment_size = cmp::min(max_segment_size, limit as usize)
+        }
+        reply_repr.max_seg_size = Some(max_segment_size as u16);
+        ip_reply_repr.set_payload_len(reply_repr.buffer_len());
+        Some((ip_reply_repr, reply_repr))
+    }
+
+    fn ack_reply(&self, timestamp: u64, ip_repr: &IpRepr,
+                 repr: &TcpRepr) -> (IpRepr, TcpRepr<'static>) {
+        let (mut ip_reply_repr, mut reply_repr) = Self::reply(ip_repr, repr);
 
         // From RFC 793:
         // [...] an empty acknowledgment segment containing the current send-sequence number
@@ -790,18 +2312,91 @@ impl<'a> TcpSocket<'a> {
         // to be received.
         reply_repr.seq_number = self.remote_last_seq;
         reply_repr.ack_number = self.remote_last_ack;
-        reply_repr.window_len = self.rx_buffer.window() as u16;
+        reply_repr.window_len = self.recv_window() as u16;
+        reply_repr.sack_ranges = self.sack_ranges();
+        reply_repr.timestamp = self.timestamp_option(timestamp);
+        reply_repr.ece = self.remote_has_ecn && self.ecn_echo;
+        ip_reply_repr.set_payload_len(reply_repr.buffer_len());
 
         (ip_reply_repr, reply_repr)
     }
 
+    /// Return a challenge ACK in reply to the given segment, unless the socket has already
+    /// sent as many as it may this second, per [RFC 5961 § 7].
+    ///
+    /// [RFC 5961 § 7]: https://tools.ietf.org/html/rfc5961#section-7
+    fn challenge_ack_reply(&mut self, timestamp: u64, ip_repr: &IpRepr, repr: &TcpRepr) ->
+                          Result<Option<(IpRepr, TcpRepr<'static>)>> {
+        if let Some(limit) = self.challenge_ack_limit {
+            let (since, count) = self.challenge_acks;
+            let count = if timestamp >= since + 1000 {
+                self.challenge_acks.0 = timestamp;
+                0
+            } else {
+                count
+            };
+            if count >= limit {
+                net_debug!("{}:{}:{}: not sending a challenge ACK, rate limited",
+                           self.meta.handle, self.local_endpoint, self.remote_endpoint);
+                return Err(Error::Dropped)
+            }
+            self.challenge_acks.1 = count + 1;
+        }
+        self.stats.challenge_acks += 1;
+        Ok(Some(self.ack_reply(timestamp, ip_repr, repr)))
+    }
+
+    /// Return the timestamp option to send at the given time: always in a SYN, and later
+    /// only if the remote end has sent one in its SYN, per [RFC 7323 § 3.2].
+    ///
+    /// [RFC 7323 § 3.2]: https://tools.ietf.org/html/rfc7323#section-3.2
+    fn timestamp_option(&self, timestamp: u64) -> Option<(u32, u32)> {
+        match self.state {
+            State::SynSent => Some((timestamp as u32, 0)),
+            _ if self.remote_has_ts => Some((timestamp as u32, self.remote_ts_recent)),
+            _ => None
+        }
+    }
+
+    /// Return the SACK blocks describing the out-of-order data in the receive buffer,
+    /// starting with the one containing the last segment received out of order,
+    /// or no blocks if the remote end has not permitted selective acknowledgements.
+    fn sack_ranges(&self) -> [Option<(u32, u32)>; TCP_SACK_RANGE_COUNT] {
+        let mut sack_ranges = [None; TCP_SACK_RANGE_COUNT];
+        if !self.remote_has_sack { return sack_ranges }
+
+        let window_start = self.remote_seq_no + self.rx_buffer.len();
+        let to_range = |(start, end)| ((window_start + start).0 as u32,
+                                       (window_start + end).0 as u32);
+        let is_last = |&(start, end): &(usize, usize)| {
+            self.remote_last_ooo.map_or(false, |seq| {
+                window_start + start <= seq && seq < window_start + end
+            })
+        };
+
+        let first = self.assembler.iter_data().find(&is_last);
+        let rest = self.assembler.iter_data().filter(|range| !is_last(range));
+        for (slot, range) in sack_ranges.iter_mut().zip(first.into_iter().chain(rest)) {
+            *slot = Some(to_range(range));
+        }
+        sack_ranges
+    }
+
+    /// Return the sequence number of the first octet that was not acknowledged by
+    /// the remote endpoint; it advances whenever new data is acknowledged.
+    pub(crate) fn unacknowledged_seq_no(&self) -> TcpSeqNumber {
+        self.local_seq_no
+    }
+
     pub(crate) fn accepts(&self, ip_repr: &IpRepr, repr: &TcpRepr) -> bool {
         if self.state == State::Closed { return false }
 
         // If we're still listening for SYNs and the packet has an ACK, it cannot
-        // be destined to this socket, but another one may well listen on the same
-        // local endpoint.
-        if self.state == State::Listen && repr.ack_number.is_some() { return false }
+        // be destined to this socket, unless it acknowledges a SYN|ACK carrying
+        // a SYN cookie, but another one may well listen on the same local endpoint.
+        if self.state == State::Listen && repr.ack_number.is_some() &&
+            !(self.syn_cookies.is_some() &&
+              repr.control.quash_psh() == TcpControl::None) { return false }
 
         // Reject packets with a wrong destination.
         if self.local_endpoint.port != repr.dst_port { return false }
@@ -817,10 +2412,277 @@ impl<'a> TcpSocket<'a> {
         true
     }
 
-    pub(crate) fn process(&mut self, timestamp: u64, ip_repr: &IpRepr, repr: &TcpRepr) ->
+    /// Check whether a SYN may reopen the connection in the TIME-WAIT state,
+    /// per RFC 1122 § 4.2.2.13 and RFC 6191.
+    fn time_wait_reopens(&self, repr: &TcpRepr) -> bool {
+        if !(self.time_wait_reuse && self.state == State::TimeWait) { return false }
+        if repr.control != TcpControl::Syn || repr.ack_number.is_some() { return false }
+        if !self.rx_buffer.is_empty() { return false }
+
+        match repr.timestamp {
+            Some((tsval, _)) if self.remote_has_ts =>
+                (tsval.wrapping_sub(self.remote_ts_recent) as i32) > 0,
+            _ =>
+                repr.seq_number > self.remote_seq_no
+        }
+    }
+
+    #[cfg(feature = "proto-ipv4")]
+    /// Check whether an error returned for a segment sent from `local_endpoint`
+    /// to `remote_endpoint`, starting at `seq_number`, concerns the connection
+    /// of this socket.
+    pub(crate) fn accepts_error(&self, local_endpoint: IpEndpoint, remote_endpoint: IpEndpoint,
+                                seq_number: TcpSeqNumber) -> bool {
+        match self.state {
+            State::Closed | State::Listen | State::TimeWait => return false,
+            _ => ()
+        }
+
+        if self.local_endpoint.port != local_endpoint.port { return false }
+        if !self.local_endpoint.addr.is_unspecified() &&
+            self.local_endpoint.addr != local_endpoint.addr { return false }
+        if self.remote_endpoint != remote_endpoint { return false }
+
+        // Only accept errors for segments that may be in flight, so that guessing
+        // the endpoints of a connection is not enough to disrupt it (RFC 5927 § 4.1).
+        let max_seq = match self.remote_max_seq {
+            Some(max_seq) if max_seq > self.remote_last_seq => max_seq,
+            _ => self.remote_last_seq
+        };
+        seq_number >= self.local_seq_no && seq_number < max_seq
+    }
+
+    #[cfg(feature = "proto-ipv4")]
+    pub(crate) fn process_error(&mut self, error: SocketError) {
+        net_debug!("{}:{}:{}: {}",
+                   self.meta.handle, self.local_endpoint, self.remote_endpoint, error);
+        self.error = Some(error);
+        #[cfg(feature = "async")]
+        self.rx_waker.wake();
+        #[cfg(feature = "async")]
+        self.tx_waker.wake();
+
+        // Give up on a connection attempt right away instead of waiting for it to time out;
+        // errors on a synchronized connection are treated as soft (RFC 1122 § 4.2.3.9).
+        if self.state == State::SynSent {
+            self.set_state(State::Closed);
+            self.forget_endpoints();
+        }
+    }
+
+    /// Check that a segment accepted by the socket carries a valid TCP MD5 signature,
+    /// if the segments of the connection are signed, and count it as dropped if not.
+    ///
+    /// The packet holds the whole segment, which `repr` is parsed from.
+    pub(crate) fn verify_signature<T>(&mut self, ip_repr: &IpRepr, packet: &TcpPacket<T>,
+                                      repr: &TcpRepr) -> bool
+            where T: AsRef<[u8]> {
+        let (function, key) = match self.md5_signing {
+            Some(signing) => signing,
+            None => return true
+        };
+        let valid = match repr.md5_signature {
+            Some(signature) => {
+                let header = packet.signed_header(&ip_repr.src_addr(), &ip_repr.dst_addr());
+                function(key, &[header.as_bytes(), repr.payload]) == signature
+            }
+            None => false
+        };
+        if !valid {
+            net_debug!("{}:{}:{}: dropping a segment without a valid signature",
+                       self.meta.handle, self.local_endpoint,
+                       IpEndpoint::new(ip_repr.src_addr(), repr.src_port));
+            self.stats.segments_received += 1;
+            self.stats.segments_dropped += 1;
+        }
+        valid
+    }
+
+    /// Sign a segment about to be sent with a TCP MD5 signature, if the segments
+    /// of the connection are signed.
+    pub(crate) fn sign_segment(&self, ip_repr: &mut IpRepr, repr: &mut TcpRepr) {
+        let (function, key) = match self.md5_signing {
+            Some(signing) => signing,
+            None => return
+        };
+        repr.md5_signature = Some([0; 16]);
+        Self::fit_sack_ranges(repr);
+        let header = repr.signed_header(&ip_repr.src_addr(), &ip_repr.dst_addr());
+        repr.md5_signature = Some(function(key, &[header.as_bytes(), repr.payload]));
+        ip_repr.set_payload_len(repr.buffer_len());
+    }
+
+    /// Leave out as many SACK blocks as necessary for the options to fit into the header.
+    fn fit_sack_ranges(repr: &mut TcpRepr) {
+        let mut count = TCP_SACK_RANGE_COUNT;
+        while repr.header_len() > 60 && count > 0 {
+            count -= 1;
+            repr.sack_ranges[count] = None;
+        }
+    }
+
+    pub(crate) fn process(&mut self, now: Instant, ip_repr: &IpRepr, repr: &TcpRepr) ->
                          Result<Option<(IpRepr, TcpRepr<'static>)>> {
         debug_assert!(self.accepts(ip_repr, repr));
 
+        self.stats.segments_received += 1;
+        let result = self.process_segment(now, ip_repr, repr);
+        if let Err(Error::Dropped) = result {
+            self.stats.segments_dropped += 1;
+        }
+        result
+    }
+
+    /// Process a segment received along with others in one poll, like [process], except that
+    /// an acknowledgement of new data that leaves none out of order is not sent right away,
+    /// but when the socket is dispatched after the received frames are processed, so that
+    /// it acknowledges the next segments of the connection as well.
+    ///
+    /// [process]: #method.process
+    pub(crate) fn process_batched(&mut self, now: Instant, ip_repr: &IpRepr, repr: &TcpRepr) ->
+                                 Result<Option<(IpRepr, TcpRepr<'static>)>> {
+        let remote_last_ack = self.remote_last_ack;
+        let result = self.process(now, ip_repr, repr);
+        let deferrable = match result {
+            Ok(Some((_, ref reply))) =>
+                reply.control == TcpControl::None && reply.payload.is_empty() &&
+                self.assembler.is_empty() &&
+                match (remote_last_ack, reply.ack_number) {
+                    (Some(last_ack), Some(ack_number)) => last_ack < ack_number,
+                    _ => false
+                },
+            _ => false
+        };
+        if !deferrable { return result }
+
+        net_trace!("{}:{}:{}: deferring ACK until dispatch",
+                   self.meta.handle, self.local_endpoint, self.remote_endpoint);
+        self.remote_last_ack = remote_last_ack;
+        self.ack_delay_until = Some(now.total_millis() as u64);
+        Ok(None)
+    }
+
+    /// Receive the payload of a segment that continues the last one the socket processed
+    /// in sequence, with the same acknowledgement, window and options, without processing
+    /// its header again, and return whether it could.
+    ///
+    /// The interface coalesces the segments of a connection it receives in one poll
+    /// this way, so that their headers are processed once, like generic receive offload.
+    pub(crate) fn coalesce(&mut self, now: Instant, ip_repr: &IpRepr, repr: &TcpRepr) -> bool {
+        let timestamp = now.total_millis() as u64;
+
+        // Only segments that carry nothing but data may skip the processing of their header.
+        if repr.control.quash_psh() != TcpControl::None || repr.urgent_at.is_some() ||
+                repr.max_seg_size.is_some() || repr.sack_permitted ||
+                repr.sack_ranges.iter().any(Option::is_some) || repr.ece || repr.cwr ||
+                ip_repr.ecn() == ECN_CE {
+            return false
+        }
+        if self.state != State::Established || !self.assembler.is_empty() { return false }
+        if repr.seq_number != self.remote_seq_no + self.rx_buffer.len() { return false }
+        self.grow_recv_buffer();
+        let payload_len = repr.payload.len();
+        if payload_len == 0 || payload_len > self.rx_buffer.window() { return false }
+
+        net_trace!("{}:{}:{}: rx buffer: coalescing {} octets (now {})",
+                   self.meta.handle, self.local_endpoint, self.remote_endpoint,
+                   payload_len, self.rx_buffer.len() + payload_len);
+        self.rx_buffer.enqueue_slice(repr.payload);
+        self.remote_last_ooo = Some(repr.seq_number);
+        self.remote_last_ts = Some(timestamp);
+        self.rx_timestamp = Some(timestamp);
+        self.stats.segments_received += 1;
+        self.stats.bytes_received += payload_len as u64;
+        #[cfg(feature = "async")]
+        self.rx_waker.wake();
+
+        // The coalesced segments are acknowledged like the segments they continue.
+        if let Some(ack_delay) = self.ack_delay {
+            self.ack_delay_until = match self.ack_delay_until {
+                None    => Some(timestamp + ack_delay),
+                Some(_) => Some(timestamp)
+            };
+        }
+        true
+    }
+
+    fn process_segment(&mut self, now: Instant, ip_repr: &IpRepr, repr: &TcpRepr) ->
+                      Result<Option<(IpRepr, TcpRepr<'static>)>> {
+        let timestamp = now.total_millis() as u64;
+
+        // A new SYN may reopen a connection in TIME-WAIT; it is then handled as if
+        // the socket were listening.
+        if self.time_wait_reopens(repr) {
+            net_debug!("{}:{}:{}: SYN in TIME-WAIT, reopening connection",
+                       self.meta.handle, self.local_endpoint, self.remote_endpoint);
+            let listen_address = self.listen_address;
+            let local_port     = self.local_endpoint.port;
+            self.reset();
+            self.stats           = Stats::default();
+            self.closed_connection = None;
+            self.listen_address  = listen_address;
+            self.local_endpoint  = IpEndpoint::new(listen_address, local_port);
+            self.set_state(State::Listen);
+        }
+
+        // An ACK in the LISTEN state may acknowledge a SYN|ACK carrying a SYN cookie;
+        // if the cookie is valid, the connection is set up as if we had received the SYN
+        // and sent the SYN|ACK, and the ACK is processed in the SYN-RECEIVED state.
+        if let (State::Listen, Some(ack_number)) = (self.state, repr.ack_number) {
+            let secret = match self.syn_cookies {
+                Some((_, secret)) => secret,
+                None => return Err(Error::Dropped)
+            };
+            let local_endpoint  = IpEndpoint::new(ip_repr.dst_addr(), repr.dst_port);
+            let remote_endpoint = IpEndpoint::new(ip_repr.src_addr(), repr.src_port);
+            let max_seg_size = tcp_cookie::decode(&secret, timestamp,
+                                                  local_endpoint, remote_endpoint,
+                                                  repr.seq_number - 1, ack_number - 1);
+            match max_seg_size {
+                Some(max_seg_size) => {
+                    net_trace!("{}:{}:{}: received ACK of SYN cookie",
+                               self.meta.handle, local_endpoint, remote_endpoint);
+                    self.local_endpoint  = local_endpoint;
+                    self.remote_endpoint = remote_endpoint;
+                    self.local_seq_no    = ack_number - 1;
+                    self.remote_seq_no   = repr.seq_number;
+                    self.remote_last_seq = ack_number;
+                    self.remote_last_ack = Some(repr.seq_number);
+                    self.set_remote_mss(Some(max_seg_size));
+                    self.congestion.set_mss(self.remote_mss);
+                    self.set_state(State::SynReceived);
+                }
+                None => {
+                    net_debug!("{}:{}:{}: invalid SYN cookie",
+                               self.meta.handle, local_endpoint, remote_endpoint);
+                    return Ok(Some(Self::rst_reply(ip_repr, repr)))
+                }
+            }
+        }
+
+        // A SYN in the SYN-RECEIVED state that repeats the one we've received means either
+        // that our SYN|ACK was lost, or, after a simultaneous open, that the remote end
+        // acknowledges our SYN with a SYN|ACK of its own (RFC 793 § 3.4, figure 8).
+        let stripped_repr;
+        let repr = if self.state == State::SynReceived && repr.control == TcpControl::Syn &&
+                          repr.seq_number + 1 == self.remote_seq_no {
+            if repr.ack_number.is_none() {
+                net_debug!("{}:{}:{}: duplicate SYN, will retransmit SYN|ACK",
+                           self.meta.handle, self.local_endpoint, self.remote_endpoint);
+                self.remote_last_seq = self.local_seq_no;
+                return Ok(None)
+            }
+            // Process the SYN|ACK as an ACK of our SYN, past the SYN we've already received.
+            stripped_repr = TcpRepr {
+                control:    TcpControl::None,
+                seq_number: repr.seq_number + 1,
+                ..*repr
+            };
+            &stripped_repr
+        } else {
+            repr
+        };
+
         // Consider how much the sequence number space differs from the transmit buffer space.
         let (sent_syn, sent_fin) = match self.state {
             // In SYN-SENT or SYN-RECEIVED, we've just sent a SYN.
@@ -833,6 +2695,42 @@ impl<'a> TcpSocket<'a> {
         };
         let control_len = (sent_syn as usize) + (sent_fin as usize);
 
+        let window_start  = self.remote_seq_no + self.rx_buffer.len();
+        let window_end    = self.remote_seq_no + self.rx_buffer.capacity();
+        let segment_start = repr.seq_number;
+        let segment_end   = repr.seq_number + repr.segment_len();
+
+        // Protect synchronized connections against blind resets, per RFC 5961 § 3.2
+        // and § 4.2: a RST is only accepted at exactly the next expected sequence number,
+        // and a RST elsewhere in the receive window, or a SYN, is answered with
+        // a challenge ACK. (A SYN in SYN-RECEIVED is handled above, and one in TIME-WAIT
+        // either reopens the connection or is an old duplicate.)
+        match (self.state, repr.control) {
+            (State::Listen, _) | (State::SynSent, _) => (),
+            (_, TcpControl::Rst) if segment_start == window_start => (),
+            (_, TcpControl::Rst) if window_start < segment_start &&
+                                    segment_start < window_end => {
+                net_debug!("{}:{}:{}: RST not at the start of the receive window \
+                            ({} not {}), will send challenge ACK",
+                           self.meta.handle, self.local_endpoint, self.remote_endpoint,
+                           segment_start, window_start);
+                return self.challenge_ack_reply(timestamp, ip_repr, repr)
+            }
+            (_, TcpControl::Rst) => {
+                net_debug!("{}:{}:{}: RST not in receive window ({} not in {}..{})",
+                           self.meta.handle, self.local_endpoint, self.remote_endpoint,
+                           segment_start, window_start, window_end);
+                return Err(Error::Dropped)
+            }
+            (State::SynReceived, TcpControl::Syn) | (State::TimeWait, TcpControl::Syn) => (),
+            (_, TcpControl::Syn) => {
+                net_debug!("{}:{}:{}: SYN in a synchronized state, will send challenge ACK",
+                           self.meta.handle, self.local_endpoint, self.remote_endpoint);
+                return self.challenge_ack_reply(timestamp, ip_repr, repr)
+            }
+            _ => ()
+        }
+
         // Reject unacceptable acknowledgements.
         match (self.state, repr) {
             // An RST received in response to initial SYN is acceptable if it acknowledges
@@ -856,6 +2754,9 @@ impl<'a> TcpSocket<'a> {
             }
             // Any other RST need only have a valid sequence number.
             (_, &TcpRepr { control: TcpControl::Rst, .. }) => (),
+            // A SYN without an acknowledgement in the SYN-SENT state means the remote end
+            // is opening the connection simultaneously.
+            (State::SynSent, &TcpRepr { control: TcpControl::Syn, ack_number: None, .. }) => (),
             // The initial SYN cannot contain an acknowledgement.
             (State::Listen, &TcpRepr { ack_number: None, .. }) => (),
             // This case is handled above.
@@ -870,6 +2771,18 @@ impl<'a> TcpSocket<'a> {
             (_, &TcpRepr { ack_number: Some(ack_number), .. }) => {
                 let unacknowledged = self.tx_buffer.len() + control_len;
 
+                // An acknowledgement of data acknowledged more than a window ago cannot
+                // come from the remote end, and may be an attempt to inject data
+                // (RFC 5961 § 5.2).
+                if ack_number + self.remote_win_max < self.local_seq_no {
+                    net_debug!("{}:{}:{}: ACK too old ({} before {}...{}), \
+                                will send challenge ACK",
+                               self.meta.handle, self.local_endpoint, self.remote_endpoint,
+                               ack_number, self.local_seq_no - self.remote_win_max,
+                               self.local_seq_no + unacknowledged);
+                    return self.challenge_ack_reply(timestamp, ip_repr, &repr)
+                }
+
                 if ack_number < self.local_seq_no {
                     net_debug!("{}:{}:{}: duplicate ACK ({} not in {}...{})",
                                self.meta.handle, self.local_endpoint, self.remote_endpoint,
@@ -882,15 +2795,29 @@ impl<'a> TcpSocket<'a> {
                     net_debug!("{}:{}:{}: unacceptable ACK ({} not in {}...{})",
                                self.meta.handle, self.local_endpoint, self.remote_endpoint,
                                ack_number, self.local_seq_no, self.local_seq_no + unacknowledged);
-                    return Ok(Some(self.ack_reply(ip_repr, &repr)))
+                    return self.challenge_ack_reply(timestamp, ip_repr, &repr)
                 }
             }
         }
 
-        let window_start  = self.remote_seq_no + self.rx_buffer.len();
-        let window_end    = self.remote_seq_no + self.rx_buffer.capacity();
-        let segment_start = repr.seq_number;
-        let segment_end   = repr.seq_number + repr.segment_len();
+        // Reject old duplicate segments by their timestamps (PAWS), and remember
+        // the timestamp of the next segment expected to echo it back, per RFC 7323 § 4.3
+        // and § 5.3. RSTs are accepted regardless of their timestamp.
+        match repr.timestamp {
+            Some((tsval, _)) if self.remote_has_ts && repr.control != TcpControl::Rst => {
+                if (tsval.wrapping_sub(self.remote_ts_recent) as i32) < 0 {
+                    net_debug!("{}:{}:{}: segment timestamp {} is older than {}, \
+                                will send an ACK",
+                               self.meta.handle, self.local_endpoint, self.remote_endpoint,
+                               tsval, self.remote_ts_recent);
+                    return Ok(Some(self.ack_reply(timestamp, ip_repr, &repr)))
+                }
+                if self.remote_last_ack.map_or(true, |ack| segment_start <= ack) {
+                    self.remote_ts_recent = tsval;
+                }
+            }
+            _ => ()
+        }
 
         let payload_offset;
         match self.state {
@@ -929,10 +2856,10 @@ impl<'a> TcpSocket<'a> {
                     // If we're in the TIME-WAIT state, restart the TIME-WAIT timeout, since
                     // the remote end may not have realized we've closed the connection.
                     if self.state == State::TimeWait {
-                        self.timer.set_for_close(timestamp);
+                        self.timer.set_for_close(timestamp, self.time_wait_timeout);
                     }
 
-                    return Ok(Some(self.ack_reply(ip_repr, &repr)))
+                    return Ok(Some(self.ack_reply(timestamp, ip_repr, &repr)))
                 }
             }
         }
@@ -970,14 +2897,23 @@ impl<'a> TcpSocket<'a> {
             control = TcpControl::None;
         }
 
+        // Remember the persist timer, since the state transitions below reset it.
+        let timer = self.timer;
+
+        // Acknowledge a FIN right away, along with any data received before it.
+        if control == TcpControl::Fin {
+            self.ack_delay_until = None;
+        }
+
         // Validate and update the state.
         match (self.state, control) {
             // RSTs are not accepted in the LISTEN state.
             (State::Listen, TcpControl::Rst) =>
                 return Err(Error::Dropped),
 
-            // RSTs in SYN-RECEIVED flip the socket back to the LISTEN state.
-            (State::SynReceived, TcpControl::Rst) => {
+            // RSTs in SYN-RECEIVED flip the socket back to the LISTEN state, unless
+            // the connection was opened actively, in which case it is refused.
+            (State::SynReceived, TcpControl::Rst) if !self.active_open => {
                 net_trace!("{}:{}:{}: received RST",
                            self.meta.handle, self.local_endpoint, self.remote_endpoint);
                 self.local_endpoint.addr = self.listen_address;
@@ -990,9 +2926,9 @@ impl<'a> TcpSocket<'a> {
             (_, TcpControl::Rst) => {
                 net_trace!("{}:{}:{}: received RST",
                            self.meta.handle, self.local_endpoint, self.remote_endpoint);
+                self.report(timestamp, Event::Reset);
                 self.set_state(State::Closed);
-                self.local_endpoint  = IpEndpoint::default();
-                self.remote_endpoint = IpEndpoint::default();
+                self.forget_endpoints();
                 return Ok(None)
             }
 
@@ -1006,9 +2942,15 @@ impl<'a> TcpSocket<'a> {
                 self.local_seq_no    = TcpSeqNumber(-repr.seq_number.0);
                 self.remote_seq_no   = repr.seq_number + 1;
                 self.remote_last_seq = self.local_seq_no;
-                if let Some(max_seg_size) = repr.max_seg_size {
-                    self.remote_mss = max_seg_size as usize
+                self.set_remote_mss(repr.max_seg_size);
+                self.remote_has_sack = repr.sack_permitted;
+                if let Some((tsval, _)) = repr.timestamp {
+                    self.remote_has_ts    = true;
+                    self.remote_ts_recent = tsval;
                 }
+                // An ECN-setup SYN has both the ECE and CWR flags set.
+                self.remote_has_ecn  = self.ecn && repr.ece && repr.cwr;
+                self.congestion.set_mss(self.remote_mss);
                 self.set_state(State::SynReceived);
                 self.timer.set_for_idle(timestamp, self.keep_alive);
             }
@@ -1016,6 +2958,7 @@ impl<'a> TcpSocket<'a> {
             // ACK packets in the SYN-RECEIVED state change it to ESTABLISHED.
             (State::SynReceived, TcpControl::None) => {
                 self.set_state(State::Established);
+                self.report(timestamp, Event::Established);
                 self.timer.set_for_idle(timestamp, self.keep_alive);
             }
 
@@ -1028,6 +2971,28 @@ impl<'a> TcpSocket<'a> {
                 self.timer.set_for_idle(timestamp, self.keep_alive);
             }
 
+            // SYN packets in the SYN-SENT state change it to SYN-RECEIVED; both ends are
+            // opening the connection simultaneously, and we acknowledge the remote SYN
+            // with a SYN|ACK carrying our initial sequence number again.
+            (State::SynSent, TcpControl::Syn) if repr.ack_number.is_none() => {
+                net_trace!("{}:{}:{}: received SYN (simultaneous open)",
+                           self.meta.handle, self.local_endpoint, self.remote_endpoint);
+                self.local_endpoint  = IpEndpoint::new(ip_repr.dst_addr(), repr.dst_port);
+                self.remote_seq_no   = repr.seq_number + 1;
+                self.remote_last_seq = self.local_seq_no;
+                self.set_remote_mss(repr.max_seg_size);
+                self.remote_has_sack = repr.sack_permitted;
+                if let Some((tsval, _)) = repr.timestamp {
+                    self.remote_has_ts    = true;
+                    self.remote_ts_recent = tsval;
+                }
+                // An ECN-setup SYN has both the ECE and CWR flags set.
+                self.remote_has_ecn  = self.ecn && repr.ece && repr.cwr;
+                self.congestion.set_mss(self.remote_mss);
+                self.set_state(State::SynReceived);
+                self.timer.set_for_idle(timestamp, self.keep_alive);
+            }
+
             // SYN|ACK packets in the SYN-SENT state change it to ESTABLISHED.
             (State::SynSent, TcpControl::Syn) => {
                 net_trace!("{}:{}:{}: received SYN|ACK",
@@ -1036,10 +3001,17 @@ impl<'a> TcpSocket<'a> {
                 self.remote_seq_no   = repr.seq_number + 1;
                 self.remote_last_seq = self.local_seq_no + 1;
                 self.remote_last_ack = Some(repr.seq_number);
-                if let Some(max_seg_size) = repr.max_seg_size {
-                    self.remote_mss = max_seg_size as usize;
+                self.set_remote_mss(repr.max_seg_size);
+                self.remote_has_sack = repr.sack_permitted;
+                if let Some((tsval, _)) = repr.timestamp {
+                    self.remote_has_ts    = true;
+                    self.remote_ts_recent = tsval;
                 }
+                // An ECN-setup SYN|ACK has only the ECE flag set.
+                self.remote_has_ecn  = self.ecn && repr.ece && !repr.cwr;
+                self.congestion.set_mss(self.remote_mss);
                 self.set_state(State::Established);
+                self.report(timestamp, Event::Established);
                 self.timer.set_for_idle(timestamp, self.keep_alive);
             }
 
@@ -1073,25 +3045,31 @@ impl<'a> TcpSocket<'a> {
                 self.remote_seq_no  += 1;
                 if ack_of_fin {
                     self.set_state(State::TimeWait);
-                    self.timer.set_for_close(timestamp);
+                    self.timer.set_for_close(timestamp, self.time_wait_timeout);
                 } else {
                     self.set_state(State::Closing);
                     self.timer.set_for_idle(timestamp, self.keep_alive);
                 }
             }
 
+            // ACK packets in FIN-WAIT-2 state carry data of the half-closed connection,
+            // which we still receive.
+            (State::FinWait2, TcpControl::None) => {
+                self.timer.set_for_idle(timestamp, self.keep_alive);
+            }
+
             // FIN packets in FIN-WAIT-2 state change it to TIME-WAIT.
             (State::FinWait2, TcpControl::Fin) => {
                 self.remote_seq_no  += 1;
                 self.set_state(State::TimeWait);
-                self.timer.set_for_close(timestamp);
+                self.timer.set_for_close(timestamp, self.time_wait_timeout);
             }
 
             // ACK packets in CLOSING state change it to TIME-WAIT.
             (State::Closing, TcpControl::None) => {
                 if ack_of_fin {
                     self.set_state(State::TimeWait);
-                    self.timer.set_for_close(timestamp);
+                    self.timer.set_for_close(timestamp, self.time_wait_timeout);
                 } else {
                     self.timer.set_for_idle(timestamp, self.keep_alive);
                 }
@@ -1106,8 +3084,7 @@ impl<'a> TcpSocket<'a> {
             (State::LastAck, TcpControl::None) => {
                 // Clear the remote endpoint, or we'll send an RST there.
                 self.set_state(State::Closed);
-                self.local_endpoint  = IpEndpoint::default();
-                self.remote_endpoint = IpEndpoint::default();
+                self.forget_endpoints();
             }
 
             _ => {
@@ -1117,9 +3094,56 @@ impl<'a> TcpSocket<'a> {
             }
         }
 
+        // Count duplicate acknowledgements, as defined in RFC 5681 § 2, and retransmit
+        // the segment they indicate as lost after three of them (fast retransmit).
+        let is_dup_ack = repr.ack_number == Some(self.local_seq_no) &&
+            repr.payload.is_empty() && control == TcpControl::None &&
+            repr.window_len as usize == self.remote_win_len &&
+            self.remote_last_seq > self.local_seq_no;
+        if is_dup_ack {
+            self.remote_dup_acks = self.remote_dup_acks.saturating_add(1);
+            self.stats.dup_acks += 1;
+            if self.remote_dup_acks == 3 && self.recovery_seq.is_none() {
+                net_debug!("{}:{}:{}: fast retransmit at {}",
+                           self.meta.handle, self.local_endpoint, self.remote_endpoint,
+                           self.local_seq_no);
+                self.stats.retransmits += 1;
+                self.recovery_seq    = Some(self.remote_last_seq);
+                self.remote_last_seq = self.local_seq_no;
+                self.congestion.on_loss(timestamp);
+            }
+        } else if ack_len > 0 {
+            self.remote_dup_acks = 0;
+            match (self.recovery_seq, repr.ack_number) {
+                (Some(recovery_seq), Some(ack_number)) if ack_number >= recovery_seq => {
+                    net_trace!("{}:{}:{}: fast recovery complete",
+                               self.meta.handle, self.local_endpoint, self.remote_endpoint);
+                    self.recovery_seq = None
+                }
+                _ => ()
+            }
+        }
+
+        if control == TcpControl::Fin {
+            self.report(timestamp, Event::RemoteClosed);
+        }
+        if repr.window_len == 0 && self.remote_win_len != 0 {
+            self.report(timestamp, Event::ZeroWindow);
+        }
+
         // Update remote state.
         self.remote_last_ts = Some(timestamp);
+        self.rx_timestamp = Some(timestamp);
+        self.keep_alive_probes = 0;
         self.remote_win_len = repr.window_len as usize;
+        self.remote_win_max = cmp::max(self.remote_win_max, self.remote_win_len);
+
+        // A segment that leaves the remote window closed does not restart the probing.
+        if timer.is_persist() && self.remote_win_len == 0 {
+            if let Timer::Idle { .. } = self.timer {
+                self.timer = timer
+            }
+        }
 
         if ack_len > 0 {
             // Dequeue acknowledged octets.
@@ -1127,13 +3151,95 @@ impl<'a> TcpSocket<'a> {
             net_trace!("{}:{}:{}: tx buffer: dequeueing {} octets (now {})",
                        self.meta.handle, self.local_endpoint, self.remote_endpoint,
                        ack_len, self.tx_buffer.len() - ack_len);
+            let was_writable = self.send_lowat_reached();
             self.tx_buffer.dequeue_allocated(ack_len);
+            if self.send_lowat_reached() {
+                #[cfg(feature = "async")]
+                self.tx_waker.wake();
+                if !was_writable {
+                    self.report(timestamp, Event::Writable);
+                }
+            }
+            self.congestion.on_ack(timestamp, ack_len);
+
+            // The echoed timestamp tells when the acknowledged segment was sent, even if
+            // it was retransmitted. Some implementations echo zero when they have nothing
+            // to echo, so such samples are ignored. If the acknowledged segment was timed,
+            // and not retransmitted since, the clock tells it more precisely; without
+            // timestamps, only such a segment is sampled, per Karn's algorithm.
+            let timed_rtt = match (self.rtt_timer, repr.ack_number) {
+                (Some((sent_at, timed_seq)), Some(ack_number)) if ack_number >= timed_seq => {
+                    self.rtt_timer = None;
+                    Some(now - sent_at)
+                }
+                _ => None
+            };
+            let rtt = match repr.timestamp {
+                Some((_, tsecr)) if self.remote_has_ts && tsecr != 0 =>
+                    Some(timed_rtt.unwrap_or_else(|| {
+                        Duration::from_millis((timestamp as u32).wrapping_sub(tsecr) as u64)
+                    })),
+                _ => timed_rtt
+            };
+            if let Some(rtt) = rtt {
+                self.rtte.sample(rtt);
+                net_trace!("{}:{}:{}: rtt sample {} us, rto {} ms",
+                           self.meta.handle, self.local_endpoint, self.remote_endpoint,
+                           rtt.total_micros(), self.rtte.retransmission_timeout());
+            }
+        }
+
+        // Echo congestion marks until the remote end indicates that it has reduced its
+        // congestion window, and reduce ours at most once per window of data when it echoes
+        // them, per RFC 3168 § 6.1.
+        if self.remote_has_ecn && control != TcpControl::Syn {
+            if repr.cwr {
+                self.ecn_echo = false
+            }
+            if ip_repr.ecn() == ECN_CE {
+                net_trace!("{}:{}:{}: received a congestion mark",
+                           self.meta.handle, self.local_endpoint, self.remote_endpoint);
+                self.ecn_echo = true
+            }
+            match (self.ecn_recovery_seq, repr.ack_number) {
+                (Some(recovery_seq), Some(ack_number)) if ack_number > recovery_seq =>
+                    self.ecn_recovery_seq = None,
+                _ => ()
+            }
+            if repr.ece && self.ecn_recovery_seq.is_none() {
+                net_debug!("{}:{}:{}: congestion echoed, reducing the window",
+                           self.meta.handle, self.local_endpoint, self.remote_endpoint);
+                self.ecn_recovery_seq = Some(self.remote_last_seq);
+                self.ecn_cwr = true;
+                self.congestion.on_loss(timestamp);
+            }
         }
 
         if let Some(ack_number) = repr.ack_number {
+            // Restart the user timeout if the remote end has acknowledged anything new,
+            // and stop it once everything we have sent is acknowledged.
+            if self.unacked_since.is_some() {
+                match self.remote_max_seq {
+                    Some(max_seq) if ack_number >= max_seq =>
+                        self.unacked_since = None,
+                    _ if ack_number > self.local_seq_no =>
+                        self.unacked_since = Some(timestamp),
+                    _ => ()
+                }
+            }
+            if ack_number > self.local_seq_no {
+                self.retransmits = 0;
+            }
+
             // We've processed everything in the incoming segment, so advance the local
             // sequence number past it.
             self.local_seq_no = ack_number;
+            // Urgent data is no longer pending once it is acknowledged.
+            match self.urgent_seq {
+                Some(urgent_seq) if ack_number >= urgent_seq =>
+                    self.urgent_seq = None,
+                _ => ()
+            }
             // During retransmission, if an earlier segment got lost but later was
             // successfully received, self.local_seq_no can move past self.remote_last_seq.
             // Do not attempt to retransmit the latter segments; not only this is pointless
@@ -1142,13 +3248,59 @@ impl<'a> TcpSocket<'a> {
             if self.remote_last_seq < self.local_seq_no {
                 self.remote_last_seq = self.local_seq_no
             }
-        }
-
-        let payload_len = repr.payload.len();
-        if payload_len == 0 { return Ok(None) }
 
+            if self.remote_has_sack {
+                // Record the octets the remote end has received past the acknowledged ones,
+                // disregarding any blocks outside of the data we have sent.
+                self.remote_sacked.acknowledge(self.local_seq_no);
+                let sent_end = self.local_seq_no + self.tx_buffer.len();
+                for &(left, right) in repr.sack_ranges.iter().flat_map(|r| r) {
+                    let (left, right) = (TcpSeqNumber(left as i32), TcpSeqNumber(right as i32));
+                    if self.local_seq_no < left && left < right && right <= sent_end {
+                        self.remote_sacked.add(left, right);
+                    }
+                }
+                if !self.remote_sacked.is_empty() {
+                    net_trace!("{}:{}:{}: scoreboard: {:?}",
+                               self.meta.handle, self.local_endpoint, self.remote_endpoint,
+                               self.remote_sacked.ranges);
+                }
+            }
+        }
+
+        // Record the end of urgent data the remote end has sent, which may be past
+        // the data in the segment.
+        if let Some(urgent_at) = repr.urgent_at {
+            let urgent_seq = repr.seq_number + urgent_at as usize;
+            match self.remote_urgent_seq {
+                _ if urgent_seq <= self.remote_seq_no => (),
+                Some(remote_urgent_seq) if urgent_seq <= remote_urgent_seq => (),
+                _ => {
+                    net_trace!("{}:{}:{}: urgent data until {}",
+                               self.meta.handle, self.local_endpoint, self.remote_endpoint,
+                               urgent_seq);
+                    self.remote_urgent_seq = Some(urgent_seq)
+                }
+            }
+        }
+
+        let payload_len = repr.payload.len();
+        if payload_len == 0 { return Ok(None) }
+
+        self.grow_recv_buffer();
         let assembler_was_empty = self.assembler.is_empty();
 
+        match self.reassembly_max {
+            Some(reassembly_max) if payload_offset > 0 &&
+                                    payload_offset + payload_len > reassembly_max => {
+                net_debug!("{}:{}:{}: assembler: {} octets at offset {} are too far ahead",
+                           self.meta.handle, self.local_endpoint, self.remote_endpoint,
+                           payload_len, payload_offset);
+                return Err(Error::Dropped)
+            }
+            _ => ()
+        }
+
         // Try adding payload octets to the assembler.
         match self.assembler.add(payload_offset, payload_len) {
             Ok(()) => {
@@ -1158,6 +3310,8 @@ impl<'a> TcpSocket<'a> {
                            self.meta.handle, self.local_endpoint, self.remote_endpoint,
                            payload_len, payload_offset);
                 self.rx_buffer.write_unallocated(payload_offset, repr.payload);
+                self.remote_last_ooo = Some(segment_start);
+                self.stats.bytes_received += payload_len as u64;
             }
             Err(()) => {
                 net_debug!("{}:{}:{}: assembler: too many holes to add {} octets at offset {}",
@@ -1174,6 +3328,8 @@ impl<'a> TcpSocket<'a> {
                        self.meta.handle, self.local_endpoint, self.remote_endpoint,
                        contig_len, self.rx_buffer.len() + contig_len);
             self.rx_buffer.enqueue_unallocated(contig_len);
+            #[cfg(feature = "async")]
+            self.rx_waker.wake();
         }
 
         if !self.assembler.is_empty() {
@@ -1193,8 +3349,20 @@ impl<'a> TcpSocket<'a> {
             net_trace!("{}:{}:{}: ACKing incoming segment",
                        self.meta.handle, self.local_endpoint, self.remote_endpoint);
             self.remote_last_ack = Some(self.remote_seq_no + self.rx_buffer.len());
-            Ok(Some(self.ack_reply(ip_repr, &repr)))
+            self.ack_delay_until = None;
+            Ok(Some(self.ack_reply(timestamp, ip_repr, &repr)))
         } else {
+            match self.ack_delay {
+                Some(ack_delay) if control != TcpControl::Fin => {
+                    // Delay the acknowledgement of the first segment, and acknowledge
+                    // the second one right away.
+                    self.ack_delay_until = match self.ack_delay_until {
+                        None    => Some(timestamp + ack_delay),
+                        Some(_) => Some(timestamp)
+                    };
+                }
+                _ => ()
+            }
             Ok(None)
         }
     }
@@ -1208,6 +3376,33 @@ impl<'a> TcpSocket<'a> {
         }
     }
 
+    fn keep_alive_exhausted(&self, timestamp: u64) -> bool {
+        match self.keep_alive_count {
+            Some(count) =>
+                self.keep_alive_probes >= count && self.timer.should_keep_alive(timestamp),
+            None =>
+                false
+        }
+    }
+
+    fn retransmits_exhausted(&self, timestamp: u64) -> bool {
+        match self.max_retransmits {
+            Some(count) =>
+                self.retransmits >= count && self.timer.should_retransmit(timestamp).is_some(),
+            None =>
+                false
+        }
+    }
+
+    fn user_timed_out(&self, timestamp: u64) -> bool {
+        match (self.unacked_since, self.user_timeout) {
+            (Some(unacked_since), Some(user_timeout)) =>
+                timestamp >= unacked_since + user_timeout,
+            (_, _) =>
+                false
+        }
+    }
+
     fn seq_to_transmit(&self) -> bool {
         let control;
         match self.state {
@@ -1219,12 +3414,44 @@ impl<'a> TcpSocket<'a> {
         }
 
         if self.remote_win_len > 0 {
-            self.remote_last_seq < self.local_seq_no + self.tx_buffer.len() + control.len()
+            let (next_seq, _) = self.remote_sacked.next_hole(self.remote_last_seq);
+            let data_end = self.local_seq_no + self.tx_buffer.len();
+            if next_seq < data_end && self.congestion_window_left() == 0 {
+                // We have data to transmit, but the congestion window is full.
+                false
+            } else if next_seq < data_end && control != TcpControl::Fin &&
+                    self.nagle && self.remote_last_seq != self.local_seq_no &&
+                    data_end - next_seq < self.send_mss() {
+                // We have less than a full segment of data to transmit, and some data
+                // is unacknowledged; wait for it to be acknowledged first.
+                false
+            } else {
+                next_seq < data_end + control.len()
+            }
         } else {
             false
         }
     }
 
+    /// Return how many more octets the congestion window permits to be in flight.
+    fn congestion_window_left(&self) -> usize {
+        let in_flight = self.remote_last_seq - self.local_seq_no;
+        self.congestion.window().saturating_sub(in_flight)
+    }
+
+    fn window_to_probe(&self) -> bool {
+        match self.state {
+            State::Established | State::FinWait1 | State::CloseWait | State::LastAck => {
+                // Probe the remote window if it is closed while we have data or a FIN to send,
+                // and nothing in flight that would elicit an update of it.
+                self.remote_win_len == 0 && self.remote_last_seq == self.local_seq_no &&
+                    (self.tx_buffer.len() > 0 ||
+                     self.state == State::FinWait1 || self.state == State::LastAck)
+            }
+            _ => false
+        }
+    }
+
     fn ack_to_transmit(&self) -> bool {
         if let Some(remote_last_ack) = self.remote_last_ack {
             remote_last_ack < self.remote_seq_no + self.rx_buffer.len()
@@ -1233,14 +3460,74 @@ impl<'a> TcpSocket<'a> {
         }
     }
 
+    fn ack_delay_expired(&self, timestamp: u64) -> bool {
+        match self.ack_delay_until {
+            Some(ack_delay_until) => timestamp >= ack_delay_until,
+            None => true
+        }
+    }
+
     fn window_to_update(&self) -> bool {
-        self.rx_buffer.window() as u16 > self.remote_last_win
+        self.recv_window() as u16 > self.remote_last_win
+    }
+
+    /// Return the receive window to advertise.
+    fn recv_window(&self) -> usize {
+        let mut limit = self.recv_win_limit;
+        if let Some(clamp) = self.recv_win_clamp {
+            limit = cmp::min(limit, clamp)
+        }
+        cmp::min(self.rx_buffer.window(), limit.saturating_sub(self.rx_buffer.len()))
+    }
+
+    /// Grow the receive window if the receive buffer is drained quickly enough.
+    ///
+    /// Once per round-trip time, the window is grown to twice the amount of octets
+    /// dequeued during it, as in Linux's dynamic right-sizing. A sender cannot send more
+    /// than a window per round trip, so the window doubles while the application keeps up
+    /// with it, and stays put once the application, rather than the window, is the limit.
+    fn tune_recv_window(&mut self, timestamp: u64) {
+        if !self.may_recv() { return }
+
+        match self.recv_drained_at {
+            Some(drained_at) if timestamp < drained_at + self.rtte.round_trip_time() => return,
+            Some(_) if self.recv_drained * 2 > self.recv_win_limit => {
+                self.recv_win_limit = cmp::min(self.recv_drained * 2,
+                                               self.rx_buffer.max_capacity());
+                net_trace!("{}:{}:{}: receive window limit grown to {}",
+                           self.meta.handle, self.local_endpoint, self.remote_endpoint,
+                           self.recv_win_limit);
+            }
+            _ => ()
+        }
+        self.recv_drained    = 0;
+        self.recv_drained_at = Some(timestamp);
+    }
+
+    /// Grow a [growable] receive buffer that ran full, once it is empty and no octets
+    /// are waiting in the assembler.
+    ///
+    /// [growable]: ../storage/struct.RingBuffer.html#method.growable
+    fn grow_recv_buffer(&mut self) {
+        if !self.assembler.is_empty() { return }
+
+        if self.rx_buffer.grow_if_idle() {
+            net_trace!("{}:{}:{}: rx buffer: grown to {} octets",
+                       self.meta.handle, self.local_endpoint, self.remote_endpoint,
+                       self.rx_buffer.capacity());
+            self.assembler = Assembler::with_holes(self.rx_buffer.capacity(),
+                                                   self.assembler.hole_limit());
+        }
     }
 
-    pub(crate) fn dispatch<F>(&mut self, timestamp: u64, caps: &DeviceCapabilities,
+    pub(crate) fn dispatch<F>(&mut self, now: Instant, caps: &DeviceCapabilities,
                               emit: F) -> Result<()>
             where F: FnOnce((IpRepr, TcpRepr)) -> Result<()> {
         if !self.remote_endpoint.is_specified() { return Err(Error::Exhausted) }
+        // Wait for the interface to allocate an ephemeral port.
+        if self.local_endpoint.port == 0 { return Err(Error::Exhausted) }
+
+        let timestamp = now.total_millis() as u64;
 
         if self.remote_last_ts.is_none() {
             // We get here in exactly two cases:
@@ -1253,12 +3540,32 @@ impl<'a> TcpSocket<'a> {
             self.remote_last_ts = Some(timestamp);
         }
 
+        self.tune_recv_window(timestamp);
+        self.grow_recv_buffer();
+
         // Check if any state needs to be changed because of a timer.
         if self.timed_out(timestamp) {
             // If a timeout expires, we should abort the connection.
             net_debug!("{}:{}:{}: timeout exceeded",
                        self.meta.handle, self.local_endpoint, self.remote_endpoint);
             self.set_state(State::Closed);
+        } else if self.user_timed_out(timestamp) {
+            // Likewise if data we have sent remains unacknowledged for too long.
+            net_debug!("{}:{}:{}: user timeout exceeded",
+                       self.meta.handle, self.local_endpoint, self.remote_endpoint);
+            self.set_state(State::Closed);
+        } else if self.keep_alive_exhausted(timestamp) {
+            // Likewise if the remote endpoint has not answered enough keep-alive packets.
+            net_debug!("{}:{}:{}: {} keep-alive packets unanswered",
+                       self.meta.handle, self.local_endpoint, self.remote_endpoint,
+                       self.keep_alive_probes);
+            self.set_state(State::Closed);
+        } else if self.retransmits_exhausted(timestamp) {
+            // Likewise if the remote endpoint has not acknowledged enough retransmissions.
+            net_debug!("{}:{}:{}: {} retransmissions unacknowledged",
+                       self.meta.handle, self.local_endpoint, self.remote_endpoint,
+                       self.retransmits);
+            self.set_state(State::Closed);
         } else if !self.seq_to_transmit() {
             if let Some(retransmit_delta) = self.timer.should_retransmit(timestamp) {
                 // If a retransmit timer expired, we should resend data starting at the last ACK.
@@ -1266,16 +3573,34 @@ impl<'a> TcpSocket<'a> {
                            self.meta.handle, self.local_endpoint, self.remote_endpoint,
                            retransmit_delta);
                 self.remote_last_seq = self.local_seq_no;
+                self.retransmits = self.retransmits.saturating_add(1);
+                self.stats.retransmits += 1;
+                self.congestion.on_retransmit_timeout(timestamp);
+                // A retransmission timeout ends fast recovery.
+                self.remote_dup_acks = 0;
+                self.recovery_seq    = None;
+                self.report(timestamp, Event::RetransmitTimeout);
             }
         }
 
+        // Start probing the remote window once it is closed, and stop once it is open.
+        if self.window_to_probe() {
+            self.timer.set_for_persist(timestamp);
+        } else if self.timer.is_persist() {
+            self.timer.set_for_idle(timestamp, self.keep_alive);
+        }
+
+        // Do not retransmit the octets the remote end has acknowledged selectively.
+        let (next_seq, hole_len) = self.remote_sacked.next_hole(self.remote_last_seq);
+        self.remote_last_seq = next_seq;
+
         // Decide whether we're sending a packet.
         if self.seq_to_transmit() {
             // If we have data to transmit and it fits into partner's window, do it.
             net_trace!("{}:{}:{}: outgoing segment will send data or flags",
                        self.meta.handle, self.local_endpoint, self.remote_endpoint);
-        } else if self.ack_to_transmit() {
-            // If we have data to acknowledge, do it.
+        } else if self.ack_to_transmit() && self.ack_delay_expired(timestamp) {
+            // If we have data to acknowledge, and cannot delay it any longer, do it.
             net_trace!("{}:{}:{}: outgoing segment will acknowledge",
                        self.meta.handle, self.local_endpoint, self.remote_endpoint);
         } else if self.window_to_update() {
@@ -1290,6 +3615,10 @@ impl<'a> TcpSocket<'a> {
             // If we have packets to retransmit, do it.
             net_trace!("{}:{}:{}: retransmit timer expired",
                        self.meta.handle, self.local_endpoint, self.remote_endpoint);
+        } else if self.timer.should_probe(timestamp) {
+            // If we need to probe a closed window, do it.
+            net_trace!("{}:{}:{}: persist timer expired",
+                       self.meta.handle, self.local_endpoint, self.remote_endpoint);
         } else if self.timer.should_keep_alive(timestamp) {
             // If we need to transmit a keep-alive packet, do it.
             net_trace!("{}:{}:{}: keep-alive timer expired",
@@ -1311,6 +3640,9 @@ impl<'a> TcpSocket<'a> {
             dst_addr:     self.remote_endpoint.addr,
             protocol:     IpProtocol::Tcp,
             hop_limit:    self.hop_limit.unwrap_or(64),
+            ecn:          0,
+            dscp:         self.dscp,
+            flow_label:   self.flow_label,
             payload_len:  0
         }.lower(&[])?;
 
@@ -1322,11 +3654,22 @@ impl<'a> TcpSocket<'a> {
             control:      TcpControl::None,
             seq_number:   self.remote_last_seq,
             ack_number:   Some(self.remote_seq_no + self.rx_buffer.len()),
-            window_len:   self.rx_buffer.window() as u16,
+            window_len:   self.recv_window() as u16,
             max_seg_size: None,
+            sack_permitted: false,
+            sack_ranges:  self.sack_ranges(),
+            timestamp:    self.timestamp_option(timestamp),
+            md5_signature: self.md5_signing.map(|_| [0; 16]),
+            ece:          false,
+            cwr:          false,
+            urgent_at:    None,
             payload:      &[]
         };
 
+        // The signature is filled in once the segment is complete, but it takes up room
+        // in the header all the same.
+        Self::fit_sack_ranges(&mut repr);
+
         match self.state {
             // We transmit an RST in the CLOSED state. If we ended up in the CLOSED state
             // with a specified endpoint, it means that the socket was aborted.
@@ -1352,8 +3695,25 @@ impl<'a> TcpSocket<'a> {
             State::Established | State::FinWait1 | State::CloseWait | State::LastAck => {
                 // Extract as much data as the remote side can receive in this packet
                 // from the transmit buffer.
+                // The octets the remote end has acknowledged selectively are not sent again.
                 let offset = self.remote_last_seq - self.local_seq_no;
-                let size = cmp::min(self.remote_win_len, self.remote_mss);
+                let segment_size = Self::segment_size(self.send_mss(), &repr);
+                let mut max_size = segment_size;
+                // A device that splits packets into segments itself is handed as many
+                // full segments as fit into the largest packet it accepts, unless
+                // every segment has to be signed.
+                if let (Some(max_tso_size), None) = (caps.max_tso_size, self.md5_signing) {
+                    let tso_size = max_tso_size.saturating_sub(ip_repr.buffer_len() +
+                                                               repr.header_len());
+                    if segment_size > 0 && tso_size > segment_size {
+                        max_size = tso_size - tso_size % segment_size;
+                    }
+                }
+                let mut size = cmp::min(self.remote_win_len, max_size);
+                size = cmp::min(size, self.congestion_window_left());
+                if let Some(hole_len) = hole_len {
+                    size = cmp::min(size, hole_len)
+                }
                 repr.payload = self.tx_buffer.get_allocated(offset, size);
                 // If we've sent everything we had in the buffer, follow it with the PSH or FIN
                 // flags, depending on whether the transmit half of the connection is open.
@@ -1368,20 +3728,20 @@ impl<'a> TcpSocket<'a> {
                 }
             }
 
-            // We do not transmit anything in the FIN-WAIT-2 state.
-            State::FinWait2 => return Err(Error::Exhausted),
-
-            // We do not transmit data or control flags in the CLOSING or TIME-WAIT states,
-            // but we may retransmit an ACK.
-            State::Closing | State::TimeWait => ()
+            // We do not transmit data or control flags in the FIN-WAIT-2, CLOSING or
+            // TIME-WAIT states, but we may acknowledge received data or retransmit an ACK.
+            State::FinWait2 | State::Closing | State::TimeWait => ()
         }
 
         // There might be more than one reason to send a packet. E.g. the keep-alive timer
         // has expired, and we also have data in transmit buffer. Since any packet that occupies
         // sequence space will elicit an ACK, we only need to send an explicit packet if we
         // couldn't fill the sequence space with anything.
+        // A window probe is the same packet as a keep-alive, since either just has to elicit
+        // an ACK.
         let is_keep_alive;
-        if self.timer.should_keep_alive(timestamp) && repr.is_empty() {
+        let is_window_probe = self.timer.should_probe(timestamp);
+        if (self.timer.should_keep_alive(timestamp) || is_window_probe) && repr.is_empty() {
             repr.seq_number = repr.seq_number - 1;
             repr.payload    = b"\x00"; // RFC 1122 says we should do this
             is_keep_alive = true;
@@ -1390,7 +3750,10 @@ impl<'a> TcpSocket<'a> {
         }
 
         // Trace a summary of what will be sent.
-        if is_keep_alive {
+        if is_keep_alive && is_window_probe {
+            net_trace!("{}:{}:{}: sending a window probe",
+                       self.meta.handle, self.local_endpoint, self.remote_endpoint);
+        } else if is_keep_alive {
             net_trace!("{}:{}:{}: sending a keep-alive",
                        self.meta.handle, self.local_endpoint, self.remote_endpoint);
         } else if repr.payload.len() > 0 {
@@ -1418,8 +3781,40 @@ impl<'a> TcpSocket<'a> {
             // Fill the MSS option. See RFC 6691 for an explanation of this calculation.
             let mut max_segment_size = caps.max_transmission_unit;
             max_segment_size -= ip_repr.buffer_len();
-            max_segment_size -= repr.header_len();
+            max_segment_size -= TcpRepr { timestamp: None, md5_signature: None, ..repr }
+                .header_len();
+            if let Some(limit) = self.max_segment_size {
+                max_segment_size = cmp::min(max_segment_size, limit as usize)
+            }
             repr.max_seg_size = Some(max_segment_size as u16);
+            // Only permit selective acknowledgements in a SYN|ACK if the remote end did.
+            repr.sack_permitted = repr.ack_number.is_none() || self.remote_has_sack;
+            // Request ECN in a SYN, and only agree to it in a SYN|ACK if the remote end did.
+            match repr.ack_number {
+                None => {
+                    repr.ece = self.ecn;
+                    repr.cwr = self.ecn;
+                }
+                Some(_) => repr.ece = self.remote_has_ecn
+            }
+        } else if self.remote_has_ecn && repr.control != TcpControl::Rst {
+            repr.ece = self.ecn_echo;
+            // Only data that has never been sent before is ECN-capable, per RFC 3168 § 6.1.5,
+            // and the first such segment after a window reduction indicates it.
+            let is_new = self.remote_max_seq.map_or(true, |max_seq| repr.seq_number >= max_seq);
+            if repr.payload.len() > 0 && !is_keep_alive && is_new {
+                ip_repr.set_ecn(ECN_ECT0);
+                repr.cwr = self.ecn_cwr;
+            }
+        }
+
+        // Point at the end of urgent data until it is acknowledged. The urgent pointer
+        // cannot reach further than 65535 octets past the start of the segment.
+        match (self.urgent_seq, repr.control) {
+            (_, TcpControl::Syn) | (_, TcpControl::Rst) => (),
+            (Some(urgent_seq), _) if urgent_seq > repr.seq_number =>
+                repr.urgent_at = Some(cmp::min(urgent_seq - repr.seq_number, 0xffff) as u16),
+            _ => ()
         }
 
         // Actually send the packet. If this succeeds, it means the packet is in
@@ -1430,31 +3825,66 @@ impl<'a> TcpSocket<'a> {
         // to not waste time waiting for the retransmit timer on packets that we know
         // for sure will not be successfully transmitted.
         ip_repr.set_payload_len(repr.buffer_len());
+        self.sign_segment(&mut ip_repr, &mut repr);
         emit((ip_repr, repr))?;
+        self.tx_timestamp = Some(timestamp);
+        self.stats.segments_sent += 1;
 
         // We've sent something, whether useful data or a keep-alive packet, so rewind
-        // the keep-alive timer.
-        self.timer.rewind_keep_alive(timestamp, self.keep_alive);
+        // the keep-alive timer. Keep-alive packets after the first unanswered one follow
+        // each other at their own interval.
+        if is_keep_alive && !is_window_probe {
+            self.keep_alive_probes = self.keep_alive_probes.saturating_add(1);
+            self.timer.rewind_keep_alive(timestamp,
+                                         self.keep_alive_interval.or(self.keep_alive));
+        } else {
+            self.timer.rewind_keep_alive(timestamp, self.keep_alive);
+        }
+        // Likewise, back off the persist timer if we've probed the window.
+        self.timer.rewind_persist(timestamp);
 
         // Leave the rest of the state intact if sending a keep-alive packet, since those
         // carry a fake segment.
         if is_keep_alive { return Ok(()) }
 
         // We've sent a packet successfully, so we can update the internal state now.
+        self.stats.bytes_sent += repr.payload.len() as u64;
         self.remote_last_seq = repr.seq_number + repr.segment_len();
         self.remote_last_ack = repr.ack_number;
         self.remote_last_win = repr.window_len;
+        self.ack_delay_until = None;
+        if repr.cwr && repr.control != TcpControl::Syn {
+            self.ecn_cwr = false;
+        }
+        // Time one segment at once, and forget it once any part of it is retransmitted,
+        // since it would then be ambiguous which copy an acknowledgement responds to.
+        match (self.rtt_timer, self.remote_max_seq) {
+            (None, Some(max_seq)) if repr.seq_number < max_seq => (),
+            (None, _) if repr.segment_len() > 0 =>
+                self.rtt_timer = Some((now, self.remote_last_seq)),
+            (Some((_, timed_seq)), _) if repr.seq_number < timed_seq &&
+                                         repr.segment_len() > 0 =>
+                self.rtt_timer = None,
+            _ => ()
+        }
+        match self.remote_max_seq {
+            Some(max_seq) if max_seq >= self.remote_last_seq => (),
+            _ => self.remote_max_seq = Some(self.remote_last_seq)
+        }
+        if repr.segment_len() > 0 && self.unacked_since.is_none() {
+            self.unacked_since = Some(timestamp);
+        }
 
         if !self.seq_to_transmit() && repr.segment_len() > 0 {
             // If we've transmitted all data we could (and there was something at all,
             // data or flag, to transmit, not just an ACK), wind up the retransmit timer.
-            self.timer.set_for_retransmit(timestamp);
+            self.timer.set_for_retransmit(timestamp, self.rtte.retransmission_timeout(),
+                                          self.rtte.max_retransmission_timeout());
         }
 
         if self.state == State::Closed {
             // When aborting a connection, forget about it after sending a single RST packet.
-            self.local_endpoint  = IpEndpoint::default();
-            self.remote_endpoint = IpEndpoint::default();
+            self.forget_endpoints();
         }
 
         Ok(())
@@ -1471,9 +3901,15 @@ impl<'a> TcpSocket<'a> {
         } else if self.state == State::Closed {
             // Socket was aborted, we have an RST packet to transmit.
             Some(0)
-        } else if self.seq_to_transmit() || self.ack_to_transmit() || self.window_to_update() {
+        } else if self.seq_to_transmit() || self.window_to_update() {
             // We have a data or flag packet to transmit.
             Some(0)
+        } else if self.window_to_probe() && !self.timer.is_persist() {
+            // The remote window has closed, we need to start the persist timer.
+            Some(0)
+        } else if self.ack_to_transmit() && self.ack_delay_until.is_none() {
+            // We have an acknowledgement to transmit that cannot be delayed.
+            Some(0)
         } else {
             let timeout_poll_at;
             match (self.remote_last_ts, self.timeout) {
@@ -1486,8 +3922,26 @@ impl<'a> TcpSocket<'a> {
                     timeout_poll_at = None
             }
 
+            // If we have data that is unacknowledged, we need to poll at the moment
+            // when the user timeout would expire.
+            let user_timeout_poll_at;
+            match (self.unacked_since, self.user_timeout) {
+                (Some(unacked_since), Some(user_timeout)) =>
+                    user_timeout_poll_at = Some(unacked_since + user_timeout),
+                (_, _) =>
+                    user_timeout_poll_at = None
+            }
+
+            // If we are delaying an acknowledgement, we need to poll when it is due.
+            let ack_delay_poll_at;
+            if self.ack_to_transmit() {
+                ack_delay_poll_at = self.ack_delay_until
+            } else {
+                ack_delay_poll_at = None
+            }
+
             // We wait for the earliest of our timers to fire.
-            [self.timer.poll_at(), timeout_poll_at]
+            [self.timer.poll_at(), timeout_poll_at, user_timeout_poll_at, ack_delay_poll_at]
                 .iter()
                 .filter_map(|x| *x)
                 .min()
@@ -1495,6 +3949,213 @@ impl<'a> TcpSocket<'a> {
     }
 }
 
+/// A builder structure used for creating a TCP socket.
+///
+/// Unlike the setters of a socket, the builder checks the options for consistency
+/// with each other once they are all known.
+///
+/// # Examples
+///
+/// ```
+/// use smoltcp::socket::{TcpSocket, TcpSocketBuffer};
+///
+/// let socket = TcpSocket::builder()
+///     .rx_buffer(TcpSocketBuffer::new(vec![0; 1024]))
+///     .tx_buffer(TcpSocketBuffer::new(vec![0; 1024]))
+///     .timeout(Some(60_000))
+///     .keep_alive(Some(10_000))
+///     .hop_limit(Some(32))
+///     .finalize();
+/// ```
+#[derive(Debug)]
+pub struct TcpSocketBuilder<'a> {
+    rx_buffer:           Option<SocketBuffer<'a>>,
+    tx_buffer:           Option<SocketBuffer<'a>>,
+    timeout:             Option<u64>,
+    user_timeout:        Option<Duration>,
+    keep_alive:          Option<u64>,
+    keep_alive_interval: Option<u64>,
+    keep_alive_count:    Option<u8>,
+    ack_delay:           Option<u64>,
+    nagle:               bool,
+    max_segment_size:    Option<u16>,
+    hop_limit:           Option<u8>,
+    dscp:                u8,
+    flow_label:          u32,
+}
+
+impl<'a> TcpSocketBuilder<'a> {
+    /// Create a builder used for creating a TCP socket, with the defaults
+    /// of [TcpSocket::new](struct.TcpSocket.html#method.new).
+    pub fn new() -> TcpSocketBuilder<'a> {
+        TcpSocketBuilder {
+            rx_buffer:           None,
+            tx_buffer:           None,
+            timeout:             None,
+            user_timeout:        None,
+            keep_alive:          None,
+            keep_alive_interval: None,
+            keep_alive_count:    None,
+            ack_delay:           Some(ACK_DELAY),
+            nagle:               true,
+            max_segment_size:    None,
+            hop_limit:           None,
+            dscp:                0,
+            flow_label:          0,
+        }
+    }
+
+    /// Set the receive buffer. It is required, and must not be empty.
+    pub fn rx_buffer<T>(mut self, buffer: T) -> Self
+            where T: Into<SocketBuffer<'a>> {
+        self.rx_buffer = Some(buffer.into());
+        self
+    }
+
+    /// Set the transmit buffer. It is required.
+    pub fn tx_buffer<T>(mut self, buffer: T) -> Self
+            where T: Into<SocketBuffer<'a>> {
+        self.tx_buffer = Some(buffer.into());
+        self
+    }
+
+    /// Set the timeout duration.
+    ///
+    /// See also [TcpSocket::set_timeout](struct.TcpSocket.html#method.set_timeout).
+    pub fn timeout(mut self, duration: Option<u64>) -> Self {
+        self.timeout = duration;
+        self
+    }
+
+    /// Set the user timeout duration.
+    ///
+    /// See also [TcpSocket::set_user_timeout](struct.TcpSocket.html#method.set_user_timeout).
+    pub fn user_timeout(mut self, duration: Option<Duration>) -> Self {
+        self.user_timeout = duration;
+        self
+    }
+
+    /// Set the keep-alive interval.
+    ///
+    /// See also [TcpSocket::set_keep_alive](struct.TcpSocket.html#method.set_keep_alive).
+    pub fn keep_alive(mut self, interval: Option<u64>) -> Self {
+        self.keep_alive = interval;
+        self
+    }
+
+    /// Set the interval between unanswered keep-alive packets.
+    ///
+    /// See also [TcpSocket::set_keep_alive_interval]
+    /// (struct.TcpSocket.html#method.set_keep_alive_interval).
+    pub fn keep_alive_interval(mut self, interval: Option<u64>) -> Self {
+        self.keep_alive_interval = interval;
+        self
+    }
+
+    /// Set the number of unanswered keep-alive packets after which the connection
+    /// is aborted.
+    ///
+    /// See also [TcpSocket::set_keep_alive_count]
+    /// (struct.TcpSocket.html#method.set_keep_alive_count).
+    pub fn keep_alive_count(mut self, count: Option<u8>) -> Self {
+        self.keep_alive_count = count;
+        self
+    }
+
+    /// Set the acknowledgement delay.
+    ///
+    /// See also [TcpSocket::set_ack_delay](struct.TcpSocket.html#method.set_ack_delay).
+    pub fn ack_delay(mut self, delay: Option<u64>) -> Self {
+        self.ack_delay = delay;
+        self
+    }
+
+    /// Enable or disable Nagle's algorithm.
+    ///
+    /// See also [TcpSocket::set_nagle_enabled](struct.TcpSocket.html#method.set_nagle_enabled).
+    pub fn nagle_enabled(mut self, enabled: bool) -> Self {
+        self.nagle = enabled;
+        self
+    }
+
+    /// Set the limit of the maximum segment size.
+    ///
+    /// See also [TcpSocket::set_max_segment_size]
+    /// (struct.TcpSocket.html#method.set_max_segment_size).
+    pub fn max_segment_size(mut self, max_segment_size: Option<u16>) -> Self {
+        self.max_segment_size = max_segment_size;
+        self
+    }
+
+    /// Set the time-to-live (IPv4) or hop limit (IPv6) value used in outgoing packets.
+    ///
+    /// See also [TcpSocket::set_hop_limit](struct.TcpSocket.html#method.set_hop_limit).
+    pub fn hop_limit(mut self, hop_limit: Option<u8>) -> Self {
+        self.hop_limit = hop_limit;
+        self
+    }
+
+    /// Set the Differentiated Services Code Point used in outgoing packets.
+    ///
+    /// See also [TcpSocket::set_dscp](struct.TcpSocket.html#method.set_dscp).
+    pub fn dscp(mut self, dscp: u8) -> Self {
+        self.dscp = dscp;
+        self
+    }
+
+    /// Set the IPv6 flow label used in outgoing packets.
+    ///
+    /// See also [TcpSocket::set_flow_label](struct.TcpSocket.html#method.set_flow_label).
+    pub fn flow_label(mut self, flow_label: u32) -> Self {
+        self.flow_label = flow_label;
+        self
+    }
+
+    /// Create a TCP socket with the options set.
+    ///
+    /// # Panics
+    /// This function panics if a buffer was not set, if the receive buffer is empty,
+    /// if the keep-alive interval is not shorter than the timeout, so that the connection
+    /// would time out before a keep-alive packet is sent, if a keep-alive count or an interval
+    /// between unanswered keep-alive packets is set without keep-alive, if the maximum
+    /// segment size is zero, or if any option is invalid on its own, e.g. a zero hop limit.
+    pub fn finalize(self) -> TcpSocket<'a> {
+        let (rx_buffer, tx_buffer) = match (self.rx_buffer, self.tx_buffer) {
+            (Some(rx_buffer), Some(tx_buffer)) => (rx_buffer, tx_buffer),
+            _ => panic!("a required option was not set")
+        };
+        if rx_buffer.max_capacity() == 0 {
+            panic!("the receive buffer must not be empty")
+        }
+        if let (Some(keep_alive), Some(timeout)) = (self.keep_alive, self.timeout) {
+            if keep_alive >= timeout {
+                panic!("the keep-alive interval must be shorter than the timeout")
+            }
+        }
+        if self.keep_alive.is_none() &&
+                (self.keep_alive_interval.is_some() || self.keep_alive_count.is_some()) {
+            panic!("keep-alive options were set without enabling keep-alive")
+        }
+        if let Some(0) = self.max_segment_size {
+            panic!("the maximum segment size must not be zero")
+        }
+
+        let mut socket = TcpSocket::new(rx_buffer, tx_buffer);
+        socket.set_timeout(self.timeout);
+        socket.set_user_timeout(self.user_timeout);
+        socket.set_keep_alive(self.keep_alive);
+        socket.set_keep_alive_interval(self.keep_alive_interval);
+        socket.set_keep_alive_count(self.keep_alive_count);
+        socket.set_ack_delay(self.ack_delay);
+        socket.set_nagle_enabled(self.nagle);
+        socket.set_max_segment_size(self.max_segment_size);
+        socket.set_hop_limit(self.hop_limit);
+        socket.set_dscp(self.dscp);
+        socket.set_flow_label(self.flow_label);
+        socket
+    }
+}
+
 impl<'a> Into<Socket<'a, 'static>> for TcpSocket<'a> {
     fn into(self) -> Socket<'a, 'static> {
         Socket::Tcp(self)
@@ -1533,25 +4194,43 @@ mod test {
     const SEND_IP_TEMPL: IpRepr = IpRepr::Unspecified {
         src_addr: MOCK_IP_ADDR_1, dst_addr: MOCK_IP_ADDR_2,
         protocol: IpProtocol::Tcp, payload_len: 20,
-        hop_limit: 64
+        hop_limit: 64,
+        ecn: 0,
+        dscp: 0,
+        flow_label: 0
     };
     const SEND_TEMPL: TcpRepr<'static> = TcpRepr {
         src_port: REMOTE_PORT, dst_port: LOCAL_PORT,
         control: TcpControl::None,
         seq_number: TcpSeqNumber(0), ack_number: Some(TcpSeqNumber(0)),
         window_len: 256, max_seg_size: None,
+        sack_permitted: false, sack_ranges: [None; TCP_SACK_RANGE_COUNT],
+        timestamp: None,
+        md5_signature: None,
+        ece: false,
+        cwr: false,
+        urgent_at: None,
         payload: &[]
     };
     const _RECV_IP_TEMPL: IpRepr = IpRepr::Unspecified {
         src_addr: MOCK_IP_ADDR_1, dst_addr: MOCK_IP_ADDR_2,
         protocol: IpProtocol::Tcp, payload_len: 20,
-        hop_limit: 64
+        hop_limit: 64,
+        ecn: 0,
+        dscp: 0,
+        flow_label: 0
     };
     const RECV_TEMPL:  TcpRepr<'static> = TcpRepr {
         src_port: LOCAL_PORT, dst_port: REMOTE_PORT,
         control: TcpControl::None,
         seq_number: TcpSeqNumber(0), ack_number: Some(TcpSeqNumber(0)),
         window_len: 64, max_seg_size: None,
+        sack_permitted: false, sack_ranges: [None; TCP_SACK_RANGE_COUNT],
+        timestamp: None,
+        md5_signature: None,
+        ece: false,
+        cwr: false,
+        urgent_at: None,
         payload: &[]
     };
 
@@ -1566,17 +4245,25 @@ mod test {
 
     fn send(socket: &mut TcpSocket, timestamp: u64, repr: &TcpRepr) ->
            Result<Option<TcpRepr<'static>>> {
+        send_at(socket, Instant::from_millis(timestamp as i64), repr)
+    }
+
+    fn send_at(socket: &mut TcpSocket, now: Instant, repr: &TcpRepr) ->
+              Result<Option<TcpRepr<'static>>> {
         let ip_repr = IpRepr::Unspecified {
             src_addr:    MOCK_IP_ADDR_2,
             dst_addr:    MOCK_IP_ADDR_1,
             protocol:    IpProtocol::Tcp,
             payload_len: repr.buffer_len(),
-            hop_limit:   64
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0,
+            flow_label:  0
         };
         net_trace!("send: {}", repr);
 
         assert!(socket.accepts(&ip_repr, repr));
-        match socket.process(timestamp, &ip_repr, repr) {
+        match socket.process(now, &ip_repr, repr) {
             Ok(Some((_ip_repr, repr))) => {
                 net_trace!("recv: {}", repr);
                 Ok(Some(repr))
@@ -1586,11 +4273,16 @@ mod test {
         }
     }
 
-    fn recv<F>(socket: &mut TcpSocket, timestamp: u64, mut f: F)
+    fn recv<F>(socket: &mut TcpSocket, timestamp: u64, f: F)
+            where F: FnMut(Result<TcpRepr>) {
+        recv_at(socket, Instant::from_millis(timestamp as i64), f)
+    }
+
+    fn recv_at<F>(socket: &mut TcpSocket, now: Instant, mut f: F)
             where F: FnMut(Result<TcpRepr>) {
         let mut caps = DeviceCapabilities::default();
         caps.max_transmission_unit = 1520;
-        let result = socket.dispatch(timestamp, &caps, |(ip_repr, tcp_repr)| {
+        let result = socket.dispatch(now, &caps, |(ip_repr, tcp_repr)| {
             let ip_repr = ip_repr.lower(&[IpCidr::new(LOCAL_END.addr, 24)]).unwrap();
 
             assert_eq!(ip_repr.protocol(), IpProtocol::Tcp);
@@ -1686,7 +4378,10 @@ mod test {
 
         let rx_buffer = SocketBuffer::new(vec![0; 64]);
         let tx_buffer = SocketBuffer::new(vec![0; 64]);
-        TcpSocket::new(rx_buffer, tx_buffer)
+        let mut socket = TcpSocket::new(rx_buffer, tx_buffer);
+        socket.set_ack_delay(None);
+        socket.set_nagle_enabled(false);
+        socket
     }
 
     fn socket_syn_received() -> TcpSocket<'static> {
@@ -1698,6 +4393,7 @@ mod test {
         s.remote_seq_no   = REMOTE_SEQ + 1;
         s.remote_last_seq = LOCAL_SEQ;
         s.remote_win_len  = 256;
+        s.remote_win_max  = 256;
         s
     }
 
@@ -1820,6 +4516,21 @@ mod test {
         assert_eq!(s.state, State::Closed);
     }
 
+    #[test]
+    fn test_closed_resize_buffers() {
+        let mut s = socket();
+        assert_eq!(s.resize_recv_buffer(128), Ok(()));
+        assert_eq!(s.resize_send_buffer(32), Ok(()));
+        assert_eq!(s.rx_buffer.capacity(), 128);
+        assert_eq!(s.assembler.total_size(), 128);
+        assert_eq!(s.tx_buffer.capacity(), 32);
+
+        s.listen(LOCAL_PORT).unwrap();
+        assert_eq!(s.resize_recv_buffer(64), Err(Error::Illegal));
+        assert_eq!(s.resize_send_buffer(64), Err(Error::Illegal));
+        assert_eq!(s.rx_buffer.capacity(), 128);
+    }
+
     // =========================================================================================//
     // Tests for the LISTEN state.
     // =========================================================================================//
@@ -1977,6 +4688,53 @@ mod test {
         assert_eq!(s.state, State::FinWait1);
     }
 
+    #[test]
+    fn test_syn_received_dup_syn() {
+        let mut s = socket_syn_received();
+        recv!(s, [TcpRepr {
+            control: TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            max_seg_size: Some(BASE_MSS),
+            ..RECV_TEMPL
+        }]);
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: None,
+            ..SEND_TEMPL
+        });
+        recv!(s, [TcpRepr {
+            control: TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            max_seg_size: Some(BASE_MSS),
+            ..RECV_TEMPL
+        }]);
+        assert_eq!(s.state, State::SynReceived);
+    }
+
+    #[test]
+    fn test_syn_received_syn_ack() {
+        let mut s = socket_syn_received();
+        recv!(s, [TcpRepr {
+            control: TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            max_seg_size: Some(BASE_MSS),
+            ..RECV_TEMPL
+        }]);
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: Some(LOCAL_SEQ + 1),
+            max_seg_size: Some(BASE_MSS),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::Established);
+        sanity!(s, socket_established());
+    }
+
     // =========================================================================================//
     // Tests for the SYN-SENT state.
     // =========================================================================================//
@@ -1986,8 +4744,6 @@ mod test {
         let mut s = socket();
         assert_eq!(s.connect((IpAddress::Unspecified, 80), LOCAL_END),
                    Err(Error::Unaddressable));
-        assert_eq!(s.connect(REMOTE_END, (MOCK_UNSPECIFIED, 0)),
-                   Err(Error::Unaddressable));
         assert_eq!(s.connect((MOCK_UNSPECIFIED, 0), LOCAL_END),
                    Err(Error::Unaddressable));
         assert_eq!(s.connect((IpAddress::Unspecified, 80), LOCAL_END),
@@ -2005,6 +4761,8 @@ mod test {
             seq_number: LOCAL_SEQ,
             ack_number: None,
             max_seg_size: Some(BASE_MSS),
+            sack_permitted: true,
+            timestamp:  Some((0, 0)),
             ..RECV_TEMPL
         }]);
         send!(s, TcpRepr {
@@ -2018,27 +4776,163 @@ mod test {
     }
 
     #[test]
-    fn test_connect_unspecified_local() {
-        let mut s = socket();
-        assert_eq!(s.connect(REMOTE_END, (MOCK_UNSPECIFIED, 80)),
-                   Ok(()));
-        s.abort();
-        assert_eq!(s.connect(REMOTE_END, (IpAddress::Unspecified, 80)),
-                   Ok(()));
-        s.abort();
-    }
-
-    #[test]
-    fn test_connect_specified_local() {
+    fn test_connect_ephemeral_port() {
         let mut s = socket();
-        assert_eq!(s.connect(REMOTE_END, (MOCK_IP_ADDR_2, 80)),
-                   Ok(()));
+        s.local_seq_no = LOCAL_SEQ;
+        s.connect(REMOTE_END, 0).unwrap();
+        assert_eq!(s.state, State::SynSent);
+        // Nothing is sent until a port is allocated.
+        recv!(s, []);
+        s.set_local_port(LOCAL_END.port);
+        recv!(s, [TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: None,
+            max_seg_size: Some(BASE_MSS),
+            sack_permitted: true,
+            timestamp:  Some((0, 0)),
+            ..RECV_TEMPL
+        }]);
     }
 
     #[test]
-    fn test_connect_twice() {
+    fn test_connect_max_segment_size() {
         let mut s = socket();
-        assert_eq!(s.connect(REMOTE_END, (IpAddress::Unspecified, 80)),
+        s.set_max_segment_size(Some(1000));
+        s.local_seq_no = LOCAL_SEQ;
+        s.connect(REMOTE_END, LOCAL_END.port).unwrap();
+        recv!(s, [TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: None,
+            max_seg_size: Some(1000),
+            sack_permitted: true,
+            timestamp:  Some((0, 0)),
+            ..RECV_TEMPL
+        }]);
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: Some(LOCAL_SEQ + 1),
+            max_seg_size: Some(BASE_MSS),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.remote_mss, 1000);
+    }
+
+    #[test]
+    fn test_listen_max_segment_size() {
+        let mut s = socket_listen();
+        s.set_max_segment_size(Some(4));
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: None,
+            ..SEND_TEMPL
+        });
+        recv!(s, [TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            max_seg_size: Some(4),
+            ..RECV_TEMPL
+        }]);
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
+        // Segments are no larger than the limit, even though the remote end sent no MSS.
+        s.send_slice(b"abcdef").unwrap();
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcd"[..],
+            ..RECV_TEMPL
+        }, TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 4,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"ef"[..],
+            ..RECV_TEMPL
+        }]);
+    }
+
+    #[test]
+    fn test_path_mtu() {
+        let mut s = socket_established();
+        // Leave room for 4 octets of data after the IP and TCP headers.
+        let header_len = match REMOTE_END.addr {
+            #[cfg(feature = "proto-ipv6")]
+            IpAddress::Ipv6(_) => 40 + 20,
+            _ => 20 + 20
+        };
+        s.set_path_mtu(header_len + 4);
+        s.send_slice(b"abcdef").unwrap();
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcd"[..],
+            ..RECV_TEMPL
+        }, TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 4,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"ef"[..],
+            ..RECV_TEMPL
+        }]);
+    }
+
+    #[test]
+    fn test_segmentation_offload() {
+        let mut s = socket_established();
+        // Leave room for 4 octets of data in a segment, and 10 in a packet the device
+        // splits into segments.
+        let header_len = match REMOTE_END.addr {
+            #[cfg(feature = "proto-ipv6")]
+            IpAddress::Ipv6(_) => 40 + 20,
+            _ => 20 + 20
+        };
+        s.set_path_mtu(header_len + 4);
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = 1520;
+        caps.max_tso_size = Some(header_len + 10);
+
+        // Only as many full segments as fit are sent at once.
+        s.send_slice(b"abcdefghijk").unwrap();
+        let send_mss = s.send_mss();
+        assert_eq!(s.dispatch(Instant::from_millis(0), &caps, |(_, repr)| {
+            assert_eq!(repr.payload, &b"abcdefgh"[..]);
+            assert_eq!(TcpSocket::segment_size(send_mss, &repr), 4);
+            Ok(())
+        }), Ok(()));
+        assert_eq!(s.dispatch(Instant::from_millis(0), &caps, |(_, repr)| {
+            assert_eq!(repr.seq_number, LOCAL_SEQ + 1 + 8);
+            assert_eq!(repr.payload, &b"ijk"[..]);
+            Ok(())
+        }), Ok(()));
+    }
+
+    #[test]
+    fn test_connect_unspecified_local() {
+        let mut s = socket();
+        assert_eq!(s.connect(REMOTE_END, (MOCK_UNSPECIFIED, 80)),
+                   Ok(()));
+        s.abort();
+        assert_eq!(s.connect(REMOTE_END, (IpAddress::Unspecified, 80)),
+                   Ok(()));
+        s.abort();
+    }
+
+    #[test]
+    fn test_connect_specified_local() {
+        let mut s = socket();
+        assert_eq!(s.connect(REMOTE_END, (MOCK_IP_ADDR_2, 80)),
+                   Ok(()));
+    }
+
+    #[test]
+    fn test_connect_twice() {
+        let mut s = socket();
+        assert_eq!(s.connect(REMOTE_END, (IpAddress::Unspecified, 80)),
                    Ok(()));
         assert_eq!(s.connect(REMOTE_END, (IpAddress::Unspecified, 80)),
                    Err(Error::Illegal));
@@ -2060,6 +4954,8 @@ mod test {
             seq_number: LOCAL_SEQ,
             ack_number: None,
             max_seg_size: Some(BASE_MSS),
+            sack_permitted: true,
+            timestamp:  Some((0, 0)),
             ..RECV_TEMPL
         }]);
         send!(s, TcpRepr {
@@ -2104,675 +5000,788 @@ mod test {
     }
 
     #[test]
-    fn test_syn_sent_rst_bad_ack() {
+    #[cfg(feature = "proto-ipv4")]
+    fn test_syn_sent_error() {
         let mut s = socket_syn_sent();
-        send!(s, TcpRepr {
-            control: TcpControl::Rst,
-            seq_number: REMOTE_SEQ,
-            ack_number: Some(TcpSeqNumber(1234)),
-            ..SEND_TEMPL
-        }, Err(Error::Dropped));
-        assert_eq!(s.state, State::SynSent);
-    }
+        // The SYN has been sent.
+        s.remote_last_seq = LOCAL_SEQ + 1;
+        assert!(s.accepts_error(LOCAL_END, REMOTE_END, LOCAL_SEQ));
+        assert!(!s.accepts_error(LOCAL_END, REMOTE_END, LOCAL_SEQ + 1));
+        assert!(!s.accepts_error(LOCAL_END, IpEndpoint { port: REMOTE_PORT + 1, ..REMOTE_END },
+                                 LOCAL_SEQ));
 
-    #[test]
-    fn test_syn_sent_close() {
-        let mut s = socket();
-        s.close();
+        s.process_error(SocketError::PortUnreachable);
         assert_eq!(s.state, State::Closed);
+        assert_eq!(s.take_error(), Some(SocketError::PortUnreachable));
+        assert_eq!(s.take_error(), None);
     }
 
-    // =========================================================================================//
-    // Tests for the ESTABLISHED state.
-    // =========================================================================================//
-
     #[test]
-    fn test_established_recv() {
+    #[cfg(feature = "proto-ipv4")]
+    fn test_established_error() {
         let mut s = socket_established();
-        send!(s, TcpRepr {
-            seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1),
-            payload: &b"abcdef"[..],
-            ..SEND_TEMPL
-        });
-        recv!(s, [TcpRepr {
-            seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 6),
-            window_len: 58,
-            ..RECV_TEMPL
-        }]);
-        assert_eq!(s.rx_buffer.dequeue_many(6), &b"abcdef"[..]);
+        assert!(!s.accepts_error(LOCAL_END, REMOTE_END, LOCAL_SEQ + 1));
+        // Six octets are in flight.
+        s.remote_last_seq = LOCAL_SEQ + 1 + 6;
+        assert!(s.accepts_error(LOCAL_END, REMOTE_END, LOCAL_SEQ + 1));
+        assert!(s.accepts_error(LOCAL_END, REMOTE_END, LOCAL_SEQ + 1 + 5));
+        assert!(!s.accepts_error(LOCAL_END, REMOTE_END, LOCAL_SEQ + 1 + 6));
+        assert!(!s.accepts_error(LOCAL_END, REMOTE_END, LOCAL_SEQ));
+
+        // Errors on a synchronized connection are soft.
+        s.process_error(SocketError::HostUnreachable);
+        assert_eq!(s.state, State::Established);
+        assert_eq!(s.take_error(), Some(SocketError::HostUnreachable));
+
+        s.process_error(SocketError::TimeExceeded);
+        s.abort();
+        assert_eq!(s.connect(REMOTE_END, LOCAL_END), Ok(()));
+        assert_eq!(s.take_error(), None);
     }
 
     #[test]
-    fn test_established_send() {
-        let mut s = socket_established();
-        // First roundtrip after establishing.
-        s.send_slice(b"abcdef").unwrap();
+    fn test_events() {
+        let mut s = socket_syn_sent();
+        assert_eq!(s.take_event(), None);
+        s.set_event_buffer(EventBuffer::new(vec![(0, Event::Reset); 4]));
         recv!(s, [TcpRepr {
-            seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1),
-            payload: &b"abcdef"[..],
+            control:    TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: None,
+            max_seg_size: Some(BASE_MSS),
+            sack_permitted: true,
+            timestamp:  Some((0, 0)),
             ..RECV_TEMPL
         }]);
-        assert_eq!(s.tx_buffer.len(), 6);
-        send!(s, TcpRepr {
+        send!(s, time 10, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
+        send!(s, time 20, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            ack_number: Some(LOCAL_SEQ + 1),
+            window_len: 0,
             ..SEND_TEMPL
         });
-        assert_eq!(s.tx_buffer.len(), 0);
-        // Second roundtrip.
-        s.send_slice(b"foobar").unwrap();
-        recv!(s, [TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 6,
-            ack_number: Some(REMOTE_SEQ + 1),
-            payload: &b"foobar"[..],
-            ..RECV_TEMPL
-        }]);
-        send!(s, TcpRepr {
+        send!(s, time 30, TcpRepr {
+            control:    TcpControl::Fin,
             seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 6 + 6),
+            ack_number: Some(LOCAL_SEQ + 1),
             ..SEND_TEMPL
         });
-        assert_eq!(s.tx_buffer.len(), 0);
+        assert_eq!(s.take_event(), Some((10, Event::Established)));
+        assert_eq!(s.take_event(), Some((20, Event::ZeroWindow)));
+        assert_eq!(s.take_event(), Some((30, Event::RemoteClosed)));
+        assert_eq!(s.take_event(), None);
     }
 
     #[test]
-    fn test_established_send_no_ack_send() {
+    fn test_events_overflow() {
         let mut s = socket_established();
+        s.set_event_buffer(EventBuffer::new(vec![(0, Event::Reset); 1]));
         s.send_slice(b"abcdef").unwrap();
-        recv!(s, [TcpRepr {
+        recv!(s, time 0, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload: &b"abcdef"[..],
+            payload:    &b"abcdef"[..],
             ..RECV_TEMPL
-        }]);
-        s.send_slice(b"foobar").unwrap();
-        recv!(s, [TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 6,
+        }));
+        recv!(s, time 100, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload: &b"foobar"[..],
+            payload:    &b"abcdef"[..],
             ..RECV_TEMPL
-        }]);
-    }
-
-    #[test]
-    fn test_established_send_buf_gt_win() {
-        let mut data = [0; 32];
-        for (i, elem) in data.iter_mut().enumerate() {
-            *elem = i as u8
-        }
+        }));
+        assert_eq!(s.take_event(), Some((100, Event::RetransmitTimeout)));
 
-        let mut s = socket_established();
-        s.remote_win_len = 16;
-        s.send_slice(&data[..]).unwrap();
-        recv!(s, [TcpRepr {
+        recv!(s, time 300, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload: &data[0..16],
-            ..RECV_TEMPL
-        }, TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 16,
-            ack_number: Some(REMOTE_SEQ + 1),
-            payload: &data[16..32],
+            payload:    &b"abcdef"[..],
             ..RECV_TEMPL
-        }]);
+        }));
+        // The reset replaces the retransmission timeout in the full buffer.
+        send!(s, time 400, TcpRepr {
+            control:    TcpControl::Rst,
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: None,
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.take_event(), Some((400, Event::Reset)));
+        assert_eq!(s.take_event(), None);
     }
 
     #[test]
-    fn test_established_send_wrap() {
+    fn test_send_lowat() {
         let mut s = socket_established();
-        let local_seq_start = TcpSeqNumber(i32::MAX - 1);
-        s.local_seq_no = local_seq_start + 1;
-        s.remote_last_seq = local_seq_start + 1;
-        s.send_slice(b"abc").unwrap();
-        recv!(s, time 1000, Ok(TcpRepr {
-            seq_number: local_seq_start + 1,
+        s.set_event_buffer(EventBuffer::new(vec![(0, Event::Reset); 4]));
+        #[cfg(feature = "async")]
+        let (tx_waker, tx_counter) = ::socket::waker::test::waker();
+        #[cfg(feature = "async")]
+        s.register_send_waker(&tx_waker);
+
+        s.set_send_lowat(10);
+        s.send_slice(&[0; 60]).unwrap();
+        assert!(!s.can_send());
+        recv!(s, time 0, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"abc"[..],
+            payload:    &[0; 60][..],
             ..RECV_TEMPL
         }));
-    }
 
-    #[test]
-    fn test_established_no_ack() {
-        let mut s = socket_established();
-        send!(s, TcpRepr {
+        // Acknowledging 3 octets leaves 7 free, which is not enough...
+        send!(s, time 10, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
-            ack_number: None,
+            ack_number: Some(LOCAL_SEQ + 1 + 3),
             ..SEND_TEMPL
-        }, Err(Error::Dropped));
-    }
+        });
+        assert!(!s.can_send());
+        #[cfg(feature = "async")]
+        assert_eq!(tx_counter.count(), 0);
 
-    #[test]
-    fn test_established_bad_ack() {
-        let mut s = socket_established();
-        // Already acknowledged data.
-        send!(s, TcpRepr {
+        // ... but 3 more are.
+        send!(s, time 20, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(TcpSeqNumber(LOCAL_SEQ.0 - 1)),
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
             ..SEND_TEMPL
-        }, Err(Error::Dropped));
-        assert_eq!(s.local_seq_no, LOCAL_SEQ + 1);
-        // Data not yet transmitted.
-        send!(s, TcpRepr {
+        });
+        assert!(s.can_send());
+        #[cfg(feature = "async")]
+        assert_eq!(tx_counter.count(), 1);
+        assert_eq!(s.take_event(), Some((20, Event::Writable)));
+
+        // A mark larger than the buffer is reached once it is empty.
+        s.set_send_lowat(100);
+        assert!(!s.can_send());
+        send!(s, time 30, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 10),
+            ack_number: Some(LOCAL_SEQ + 1 + 60),
             ..SEND_TEMPL
-        }, Ok(Some(TcpRepr {
-            seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1),
-            ..RECV_TEMPL
-        })));
-        assert_eq!(s.local_seq_no, LOCAL_SEQ + 1);
+        });
+        assert!(s.can_send());
+        assert_eq!(s.take_event(), Some((30, Event::Writable)));
+        assert_eq!(s.take_event(), None);
     }
 
     #[test]
-    fn test_established_bad_seq() {
-        let mut s = socket_established();
-        // Data outside of receive window.
+    fn test_syn_sent_rst_bad_ack() {
+        let mut s = socket_syn_sent();
         send!(s, TcpRepr {
-            seq_number: REMOTE_SEQ + 1 + 256,
-            ack_number: Some(LOCAL_SEQ + 1),
-            ..SEND_TEMPL
-        }, Ok(Some(TcpRepr {
-            seq_number: LOCAL_SEQ + 1,
+            control: TcpControl::Rst,
+            seq_number: REMOTE_SEQ,
+            ack_number: Some(TcpSeqNumber(1234)),
+            ..SEND_TEMPL
+        }, Err(Error::Dropped));
+        assert_eq!(s.state, State::SynSent);
+    }
+
+    #[test]
+    fn test_syn_sent_close() {
+        let mut s = socket();
+        s.close();
+        assert_eq!(s.state, State::Closed);
+    }
+
+    #[test]
+    fn test_syn_sent_syn() {
+        let mut s = socket();
+        s.local_seq_no = LOCAL_SEQ;
+        s.connect(REMOTE_END, LOCAL_END).unwrap();
+        recv!(s, [TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: None,
+            max_seg_size: Some(BASE_MSS),
+            sack_permitted: true,
+            timestamp:  Some((0, 0)),
+            ..RECV_TEMPL
+        }]);
+        // The remote end opens the connection at the same time.
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: None,
+            max_seg_size: Some(BASE_MSS - 80),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::SynReceived);
+        recv!(s, [TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
             ack_number: Some(REMOTE_SEQ + 1),
+            max_seg_size: Some(BASE_MSS),
             ..RECV_TEMPL
-        })));
+        }]);
+        // Its SYN|ACK acknowledges our SYN.
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: Some(LOCAL_SEQ + 1),
+            max_seg_size: Some(BASE_MSS - 80),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::Established);
+        assert_eq!(s.local_seq_no, LOCAL_SEQ + 1);
         assert_eq!(s.remote_seq_no, REMOTE_SEQ + 1);
+        assert_eq!(s.remote_mss, BASE_MSS as usize - 80);
     }
 
     #[test]
-    fn test_established_fin() {
+    fn test_syn_sent_syn_rst() {
+        let mut s = socket();
+        s.local_seq_no = LOCAL_SEQ;
+        s.connect(REMOTE_END, LOCAL_END).unwrap();
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: None,
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::SynReceived);
+        // An actively opened connection is refused rather than resuming listening.
+        send!(s, TcpRepr {
+            control:    TcpControl::Rst,
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: None,
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::Closed);
+        assert_eq!(s.remote_endpoint, IpEndpoint::default());
+    }
+
+    // =========================================================================================//
+    // Tests for the ESTABLISHED state.
+    // =========================================================================================//
+
+    #[test]
+    fn test_established_recv() {
         let mut s = socket_established();
         send!(s, TcpRepr {
-            control: TcpControl::Fin,
             seq_number: REMOTE_SEQ + 1,
             ack_number: Some(LOCAL_SEQ + 1),
+            payload: &b"abcdef"[..],
             ..SEND_TEMPL
         });
         recv!(s, [TcpRepr {
             seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 58,
             ..RECV_TEMPL
         }]);
-        assert_eq!(s.state, State::CloseWait);
-        sanity!(s, socket_close_wait());
+        assert_eq!(s.rx_buffer.dequeue_many(6), &b"abcdef"[..]);
     }
 
     #[test]
-    fn test_established_fin_after_missing() {
+    fn test_established_recv_vectored() {
         let mut s = socket_established();
-        send!(s, TcpRepr {
-            control: TcpControl::Fin,
-            seq_number: REMOTE_SEQ + 1 + 6,
-            ack_number: Some(LOCAL_SEQ + 1),
-            payload: &b"123456"[..],
-            ..SEND_TEMPL
-        }, Ok(Some(TcpRepr {
-            seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1),
-            ..RECV_TEMPL
-        })));
-        assert_eq!(s.state, State::Established);
         send!(s, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
             ack_number: Some(LOCAL_SEQ + 1),
             payload: &b"abcdef"[..],
             ..SEND_TEMPL
-        }, Ok(Some(TcpRepr {
-            seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 6 + 6),
-            window_len: 52,
-            ..RECV_TEMPL
-        })));
-        assert_eq!(s.state, State::Established);
+        });
+        let (mut header, mut body) = ([0; 2], [0; 8]);
+        assert_eq!(s.recv_vectored(&mut [&mut header[..], &mut body[..]]), Ok(6));
+        assert_eq!(&header, b"ab");
+        assert_eq!(&body[..4], b"cdef");
+        assert_eq!(s.recv_vectored(&mut [&mut header[..]]), Ok(0));
     }
 
     #[test]
-    fn test_established_send_fin() {
+    #[cfg(feature = "async")]
+    fn test_established_wakers() {
+        use socket::waker::test::waker;
+
         let mut s = socket_established();
-        s.send_slice(b"abcdef").unwrap();
+        let (rx_waker, rx_counter) = waker();
+        let (tx_waker, tx_counter) = waker();
+        s.register_recv_waker(&rx_waker);
+        s.register_send_waker(&tx_waker);
+
         send!(s, TcpRepr {
-            control: TcpControl::Fin,
             seq_number: REMOTE_SEQ + 1,
             ack_number: Some(LOCAL_SEQ + 1),
+            payload: &b"abcdef"[..],
             ..SEND_TEMPL
         });
-        assert_eq!(s.state, State::CloseWait);
+        assert_eq!((rx_counter.count(), tx_counter.count()), (1, 0));
+
+        s.register_recv_waker(&rx_waker);
+        s.send_slice(b"abcdef").unwrap();
         recv!(s, [TcpRepr {
             seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 58,
             payload: &b"abcdef"[..],
             ..RECV_TEMPL
         }]);
+        assert_eq!((rx_counter.count(), tx_counter.count()), (1, 0));
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 6,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            ..SEND_TEMPL
+        });
+        assert_eq!((rx_counter.count(), tx_counter.count()), (1, 1));
+
+        // Closing the connection wakes the receiver.
+        send!(s, TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: REMOTE_SEQ + 1 + 6,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::CloseWait);
+        assert_eq!((rx_counter.count(), tx_counter.count()), (2, 1));
     }
 
     #[test]
-    fn test_established_rst() {
+    fn test_established_timestamps() {
         let mut s = socket_established();
-        send!(s, TcpRepr {
-            control: TcpControl::Rst,
+        assert_eq!(s.rx_timestamp(), None);
+        assert_eq!(s.tx_timestamp(), None);
+        send!(s, time 1_000, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
             ack_number: Some(LOCAL_SEQ + 1),
+            payload: &b"abcdef"[..],
             ..SEND_TEMPL
         });
-        assert_eq!(s.state, State::Closed);
+        assert_eq!(s.rx_timestamp(), Some(Instant::from_millis(1_000)));
+        recv!(s, time 2_000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 58,
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.tx_timestamp(), Some(Instant::from_millis(2_000)));
+
+        s.abort();
+        assert_eq!(s.connect(REMOTE_END, LOCAL_END), Ok(()));
+        assert_eq!(s.rx_timestamp(), None);
+        assert_eq!(s.tx_timestamp(), None);
     }
 
     #[test]
-    fn test_established_rst_no_ack() {
+    fn test_established_peek() {
         let mut s = socket_established();
         send!(s, TcpRepr {
-            control: TcpControl::Rst,
             seq_number: REMOTE_SEQ + 1,
-            ack_number: None,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload: &b"abcdef"[..],
             ..SEND_TEMPL
         });
-        assert_eq!(s.state, State::Closed);
-    }
-
-    #[test]
-    fn test_established_close() {
-        let mut s = socket_established();
-        s.close();
-        assert_eq!(s.state, State::FinWait1);
-        sanity!(s, socket_fin_wait_1());
+        assert_eq!(s.peek(4), Ok(&b"abcd"[..]));
+        let mut buffer = [0; 8];
+        assert_eq!(s.peek_slice(&mut buffer[..]), Ok(6));
+        assert_eq!(&buffer[..6], b"abcdef");
+        // Peeking does not dequeue anything, nor open the window.
+        assert_eq!(s.recv_queue(), 6);
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 58,
+            ..RECV_TEMPL
+        }]);
     }
 
     #[test]
-    fn test_established_abort() {
+    fn test_established_send_vectored() {
         let mut s = socket_established();
-        s.abort();
-        assert_eq!(s.state, State::Closed);
+        assert_eq!(s.send_vectored(&[&b"abc"[..], &b""[..], &b"def"[..]]), Ok(6));
         recv!(s, [TcpRepr {
-            control: TcpControl::Rst,
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
+            payload: &b"abcdef"[..],
             ..RECV_TEMPL
         }]);
+        // Only as many octets as fit into the transmit buffer are enqueued.
+        assert_eq!(s.send_vectored(&[&[0; 50][..], &[0; 50][..]]), Ok(58));
     }
 
-    // =========================================================================================//
-    // Tests for the FIN-WAIT-1 state.
-    // =========================================================================================//
-
     #[test]
-    fn test_fin_wait_1_fin_ack() {
-        let mut s = socket_fin_wait_1();
+    fn test_established_send() {
+        let mut s = socket_established();
+        // First roundtrip after establishing.
+        s.send_slice(b"abcdef").unwrap();
         recv!(s, [TcpRepr {
-            control: TcpControl::Fin,
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
+            payload: &b"abcdef"[..],
             ..RECV_TEMPL
         }]);
+        assert_eq!(s.tx_buffer.len(), 6);
         send!(s, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 1),
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
             ..SEND_TEMPL
         });
-        assert_eq!(s.state, State::FinWait2);
-        sanity!(s, socket_fin_wait_2());
-    }
-
-    #[test]
-    fn test_fin_wait_1_fin_fin() {
-        let mut s = socket_fin_wait_1();
+        assert_eq!(s.tx_buffer.len(), 0);
+        // Second roundtrip.
+        s.send_slice(b"foobar").unwrap();
         recv!(s, [TcpRepr {
-            control: TcpControl::Fin,
-            seq_number: LOCAL_SEQ + 1,
+            seq_number: LOCAL_SEQ + 1 + 6,
             ack_number: Some(REMOTE_SEQ + 1),
+            payload: &b"foobar"[..],
             ..RECV_TEMPL
         }]);
         send!(s, TcpRepr {
-            control: TcpControl::Fin,
             seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1),
+            ack_number: Some(LOCAL_SEQ + 1 + 6 + 6),
             ..SEND_TEMPL
         });
-        assert_eq!(s.state, State::Closing);
-        sanity!(s, socket_closing());
+        assert_eq!(s.tx_buffer.len(), 0);
     }
 
     #[test]
-    fn test_fin_wait_1_fin_with_data_queued() {
+    fn test_established_send_no_ack_send() {
         let mut s = socket_established();
-        s.remote_win_len = 6;
-        s.send_slice(b"abcdef123456").unwrap();
-        s.close();
-        recv!(s, Ok(TcpRepr {
+        s.send_slice(b"abcdef").unwrap();
+        recv!(s, [TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"abcdef"[..],
+            payload: &b"abcdef"[..],
             ..RECV_TEMPL
-        }));
-        send!(s, TcpRepr {
-            seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 6),
-            ..SEND_TEMPL
-        });
-        assert_eq!(s.state, State::FinWait1);
+        }]);
+        s.send_slice(b"foobar").unwrap();
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload: &b"foobar"[..],
+            ..RECV_TEMPL
+        }]);
     }
 
     #[test]
-    fn test_fin_wait_1_close() {
-        let mut s = socket_fin_wait_1();
-        s.close();
-        assert_eq!(s.state, State::FinWait1);
-    }
+    fn test_established_send_buf_gt_win() {
+        let mut data = [0; 32];
+        for (i, elem) in data.iter_mut().enumerate() {
+            *elem = i as u8
+        }
 
-    // =========================================================================================//
-    // Tests for the FIN-WAIT-2 state.
-    // =========================================================================================//
+        let mut s = socket_established();
+        s.remote_win_len = 16;
+        s.send_slice(&data[..]).unwrap();
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload: &data[0..16],
+            ..RECV_TEMPL
+        }, TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 16,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload: &data[16..32],
+            ..RECV_TEMPL
+        }]);
+    }
 
     #[test]
-    fn test_fin_wait_2_fin() {
-        let mut s = socket_fin_wait_2();
-        send!(s, time 1_000, TcpRepr {
-            control: TcpControl::Fin,
-            seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 1),
-            ..SEND_TEMPL
-        });
-        assert_eq!(s.state, State::TimeWait);
-        sanity!(s, socket_time_wait(false));
+    fn test_established_send_wrap() {
+        let mut s = socket_established();
+        let local_seq_start = TcpSeqNumber(i32::MAX - 1);
+        s.local_seq_no = local_seq_start + 1;
+        s.remote_last_seq = local_seq_start + 1;
+        s.send_slice(b"abc").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: local_seq_start + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abc"[..],
+            ..RECV_TEMPL
+        }));
     }
 
     #[test]
-    fn test_fin_wait_2_close() {
-        let mut s = socket_fin_wait_2();
-        s.close();
-        assert_eq!(s.state, State::FinWait2);
+    fn test_established_no_ack() {
+        let mut s = socket_established();
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: None,
+            ..SEND_TEMPL
+        }, Err(Error::Dropped));
     }
 
-    // =========================================================================================//
-    // Tests for the CLOSING state.
-    // =========================================================================================//
-
     #[test]
-    fn test_closing_ack_fin() {
-        let mut s = socket_closing();
-        recv!(s, [TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 1),
-            ..RECV_TEMPL
-        }]);
-        send!(s, time 1_000, TcpRepr {
-            seq_number: REMOTE_SEQ + 1 + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 1),
+    fn test_established_bad_ack() {
+        let mut s = socket_established();
+        // Already acknowledged data.
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(TcpSeqNumber(LOCAL_SEQ.0 - 1)),
             ..SEND_TEMPL
-        });
-        assert_eq!(s.state, State::TimeWait);
-        sanity!(s, socket_time_wait(true));
+        }, Err(Error::Dropped));
+        assert_eq!(s.local_seq_no, LOCAL_SEQ + 1);
+        // Data not yet transmitted.
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 10),
+            ..SEND_TEMPL
+        }, Ok(Some(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        })));
+        assert_eq!(s.local_seq_no, LOCAL_SEQ + 1);
     }
 
     #[test]
-    fn test_closing_close() {
-        let mut s = socket_closing();
-        s.close();
-        assert_eq!(s.state, State::Closing);
+    fn test_established_bad_seq() {
+        let mut s = socket_established();
+        // Data outside of receive window.
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 256,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        }, Ok(Some(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        })));
+        assert_eq!(s.remote_seq_no, REMOTE_SEQ + 1);
     }
 
-    // =========================================================================================//
-    // Tests for the TIME-WAIT state.
-    // =========================================================================================//
-
     #[test]
-    fn test_time_wait_from_fin_wait_2_ack() {
-        let mut s = socket_time_wait(false);
+    fn test_established_fin() {
+        let mut s = socket_established();
+        send!(s, TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
         recv!(s, [TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 1,
+            seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1 + 1),
             ..RECV_TEMPL
         }]);
+        assert_eq!(s.state, State::CloseWait);
+        sanity!(s, socket_close_wait());
     }
 
     #[test]
-    fn test_time_wait_from_closing_no_ack() {
-        let mut s = socket_time_wait(true);
-        recv!(s, []);
-    }
-
-    #[test]
-    fn test_time_wait_close() {
-        let mut s = socket_time_wait(false);
-        s.close();
-        assert_eq!(s.state, State::TimeWait);
-    }
-
-    #[test]
-    fn test_time_wait_retransmit() {
-        let mut s = socket_time_wait(false);
-        recv!(s, [TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 1),
-            ..RECV_TEMPL
-        }]);
-        send!(s, time 5_000, TcpRepr {
+    fn test_established_fin_after_missing() {
+        let mut s = socket_established();
+        send!(s, TcpRepr {
             control: TcpControl::Fin,
-            seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 1),
+            seq_number: REMOTE_SEQ + 1 + 6,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload: &b"123456"[..],
             ..SEND_TEMPL
         }, Ok(Some(TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
             ..RECV_TEMPL
         })));
-        assert_eq!(s.timer, Timer::Close { expires_at: 5_000 + CLOSE_DELAY });
-    }
-
-    #[test]
-    fn test_time_wait_timeout() {
-        let mut s = socket_time_wait(false);
-        recv!(s, [TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 1),
+        assert_eq!(s.state, State::Established);
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload: &b"abcdef"[..],
+            ..SEND_TEMPL
+        }, Ok(Some(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6 + 6),
+            window_len: 52,
             ..RECV_TEMPL
-        }]);
-        assert_eq!(s.state, State::TimeWait);
-        recv!(s, time 60_000, Err(Error::Exhausted));
-        assert_eq!(s.state, State::Closed);
+        })));
+        assert_eq!(s.state, State::Established);
     }
 
-    // =========================================================================================//
-    // Tests for the CLOSE-WAIT state.
-    // =========================================================================================//
-
     #[test]
-    fn test_close_wait_ack() {
-        let mut s = socket_close_wait();
+    fn test_established_send_fin() {
+        let mut s = socket_established();
         s.send_slice(b"abcdef").unwrap();
+        send!(s, TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::CloseWait);
         recv!(s, [TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1 + 1),
             payload: &b"abcdef"[..],
             ..RECV_TEMPL
         }]);
-        send!(s, TcpRepr {
-            seq_number: REMOTE_SEQ + 1 + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 6),
-            ..SEND_TEMPL
-        });
     }
 
     #[test]
-    fn test_close_wait_close() {
-        let mut s = socket_close_wait();
-        s.close();
-        assert_eq!(s.state, State::LastAck);
-        sanity!(s, socket_last_ack());
+    fn test_established_rst() {
+        let mut s = socket_established();
+        send!(s, TcpRepr {
+            control: TcpControl::Rst,
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::Closed);
     }
 
-    // =========================================================================================//
-    // Tests for the LAST-ACK state.
-    // =========================================================================================//
     #[test]
-    fn test_last_ack_fin_ack() {
-        let mut s = socket_last_ack();
-        recv!(s, [TcpRepr {
-            control: TcpControl::Fin,
-            seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 1),
-            ..RECV_TEMPL
-        }]);
-        assert_eq!(s.state, State::LastAck);
+    fn test_established_rst_no_ack() {
+        let mut s = socket_established();
         send!(s, TcpRepr {
-            seq_number: REMOTE_SEQ + 1 + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 1),
+            control: TcpControl::Rst,
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: None,
             ..SEND_TEMPL
         });
         assert_eq!(s.state, State::Closed);
     }
 
     #[test]
-    fn test_last_ack_close() {
-        let mut s = socket_last_ack();
-        s.close();
-        assert_eq!(s.state, State::LastAck);
+    fn test_established_rst_in_window() {
+        let mut s = socket_established();
+        send!(s, TcpRepr {
+            control: TcpControl::Rst,
+            seq_number: REMOTE_SEQ + 1 + 10,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        }, Ok(Some(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        })));
+        assert_eq!(s.state, State::Established);
+        assert_eq!(s.stats().challenge_acks, 1);
     }
 
-    // =========================================================================================//
-    // Tests for transitioning through multiple states.
-    // =========================================================================================//
-
     #[test]
-    fn test_listen() {
-        let mut s = socket();
-        s.listen(IpEndpoint::new(IpAddress::default(), LOCAL_PORT)).unwrap();
-        assert_eq!(s.state, State::Listen);
+    fn test_established_rst_out_of_window() {
+        let mut s = socket_established();
+        send!(s, TcpRepr {
+            control: TcpControl::Rst,
+            seq_number: REMOTE_SEQ + 1 + 256,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        }, Err(Error::Dropped));
+        assert_eq!(s.state, State::Established);
+        assert_eq!(s.stats().challenge_acks, 0);
     }
 
     #[test]
-    fn test_three_way_handshake() {
-        let mut s = socket_listen();
+    fn test_established_syn() {
+        let mut s = socket_established();
         send!(s, TcpRepr {
             control: TcpControl::Syn,
-            seq_number: REMOTE_SEQ,
+            seq_number: REMOTE_SEQ + 100,
             ack_number: None,
             ..SEND_TEMPL
-        });
-        assert_eq!(s.state(), State::SynReceived);
-        assert_eq!(s.local_endpoint(), LOCAL_END);
-        assert_eq!(s.remote_endpoint(), REMOTE_END);
-        recv!(s, [TcpRepr {
-            control: TcpControl::Syn,
-            seq_number: LOCAL_SEQ,
+        }, Ok(Some(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
-            max_seg_size: Some(BASE_MSS),
             ..RECV_TEMPL
-        }]);
+        })));
+        assert_eq!(s.state, State::Established);
+    }
+
+    #[test]
+    fn test_established_ack_too_old() {
+        let mut s = socket_established();
         send!(s, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1),
+            ack_number: Some(TcpSeqNumber(LOCAL_SEQ.0 - 1000)),
             ..SEND_TEMPL
-        });
-        assert_eq!(s.state(), State::Established);
+        }, Ok(Some(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        })));
         assert_eq!(s.local_seq_no, LOCAL_SEQ + 1);
-        assert_eq!(s.remote_seq_no, REMOTE_SEQ + 1);
     }
 
     #[test]
-    fn test_remote_close() {
+    fn test_challenge_ack_limit() {
         let mut s = socket_established();
-        send!(s, TcpRepr {
-            control: TcpControl::Fin,
-            seq_number: REMOTE_SEQ + 1,
+        s.set_challenge_ack_limit(Some(2));
+        let rst = TcpRepr {
+            control: TcpControl::Rst,
+            seq_number: REMOTE_SEQ + 1 + 10,
             ack_number: Some(LOCAL_SEQ + 1),
             ..SEND_TEMPL
-        });
-        assert_eq!(s.state, State::CloseWait);
-        recv!(s, [TcpRepr {
-            seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 1),
-            ..RECV_TEMPL
-        }]);
-        s.close();
-        assert_eq!(s.state, State::LastAck);
-        recv!(s, [TcpRepr {
-            control: TcpControl::Fin,
+        };
+        let challenge_ack = TcpRepr {
             seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ack_number: Some(REMOTE_SEQ + 1),
             ..RECV_TEMPL
-        }]);
-        send!(s, TcpRepr {
-            seq_number: REMOTE_SEQ + 1 + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 1),
-            ..SEND_TEMPL
-        });
-        assert_eq!(s.state, State::Closed);
+        };
+        send!(s, time 1_000, rst, Ok(Some(challenge_ack)));
+        send!(s, time 1_500, rst, Ok(Some(challenge_ack)));
+        send!(s, time 1_999, rst, Err(Error::Dropped));
+        send!(s, time 2_000, rst, Ok(Some(challenge_ack)));
+        assert_eq!(s.stats().challenge_acks, 3);
+
+        s.set_challenge_ack_limit(None);
+        for _ in 0..10 {
+            send!(s, time 2_000, rst, Ok(Some(challenge_ack)));
+        }
+        assert_eq!(s.state, State::Established);
     }
 
     #[test]
-    fn test_local_close() {
+    fn test_established_close() {
         let mut s = socket_established();
         s.close();
         assert_eq!(s.state, State::FinWait1);
+        sanity!(s, socket_fin_wait_1());
+    }
+
+    #[test]
+    fn test_established_abort() {
+        let mut s = socket_established();
+        s.abort();
+        assert_eq!(s.state, State::Closed);
         recv!(s, [TcpRepr {
-            control: TcpControl::Fin,
+            control: TcpControl::Rst,
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
             ..RECV_TEMPL
         }]);
-        send!(s, TcpRepr {
-            seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 1),
-            ..SEND_TEMPL
-        });
-        assert_eq!(s.state, State::FinWait2);
-        send!(s, TcpRepr {
-            control: TcpControl::Fin,
-            seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 1),
-            ..SEND_TEMPL
-        });
-        assert_eq!(s.state, State::TimeWait);
-        recv!(s, [TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 1),
-            ..RECV_TEMPL
-        }]);
     }
 
     #[test]
-    fn test_simultaneous_close() {
+    fn test_established_rst_closed_connection() {
         let mut s = socket_established();
-        s.close();
-        assert_eq!(s.state, State::FinWait1);
-        recv!(s, [TcpRepr { // due to reordering, this is logically located...
-            control: TcpControl::Fin,
-            seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1),
-            ..RECV_TEMPL
-        }]);
+        assert_eq!(s.closed_connection(), None);
         send!(s, TcpRepr {
-            control: TcpControl::Fin,
+            control: TcpControl::Rst,
             seq_number: REMOTE_SEQ + 1,
             ack_number: Some(LOCAL_SEQ + 1),
             ..SEND_TEMPL
         });
-        assert_eq!(s.state, State::Closing);
+        assert_eq!(s.remote_endpoint(), IpEndpoint::default());
+        let closed = s.closed_connection().unwrap();
+        assert_eq!((closed.local_endpoint, closed.remote_endpoint), (LOCAL_END, REMOTE_END));
+        assert_eq!(closed.stats.segments_received, 1);
+
+        s.listen(LOCAL_PORT).unwrap();
+        assert_eq!(s.closed_connection(), None);
+    }
+
+    #[test]
+    fn test_established_abort_closed_connection() {
+        let mut s = socket_established();
+        s.abort();
+        assert_eq!(s.closed_connection().map(|closed| closed.remote_endpoint),
+                   Some(REMOTE_END));
         recv!(s, [TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            control: TcpControl::Rst,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
             ..RECV_TEMPL
         }]);
-        // ... at this point
-        send!(s, TcpRepr {
-            seq_number: REMOTE_SEQ + 1 + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 1),
-            ..SEND_TEMPL
-        });
-        assert_eq!(s.state, State::TimeWait);
-        recv!(s, []);
+        assert_eq!(s.closed_connection().map(|closed| closed.remote_endpoint),
+                   Some(REMOTE_END));
     }
 
+    // =========================================================================================//
+    // Tests for the FIN-WAIT-1 state.
+    // =========================================================================================//
+
     #[test]
-    fn test_simultaneous_close_combined_fin_ack() {
-        let mut s = socket_established();
-        s.close();
-        assert_eq!(s.state, State::FinWait1);
+    fn test_fin_wait_1_fin_ack() {
+        let mut s = socket_fin_wait_1();
         recv!(s, [TcpRepr {
             control: TcpControl::Fin,
             seq_number: LOCAL_SEQ + 1,
@@ -2780,911 +5789,3451 @@ mod test {
             ..RECV_TEMPL
         }]);
         send!(s, TcpRepr {
-            control: TcpControl::Fin,
             seq_number: REMOTE_SEQ + 1,
             ack_number: Some(LOCAL_SEQ + 1 + 1),
             ..SEND_TEMPL
         });
-        assert_eq!(s.state, State::TimeWait);
-        recv!(s, [TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 1),
-            ..RECV_TEMPL
-        }]);
-    }
-
-    #[test]
-    fn test_fin_with_data() {
-        let mut s = socket_established();
-        s.send_slice(b"abcdef").unwrap();
-        s.close();
-        recv!(s, [TcpRepr {
-            control:    TcpControl::Fin,
-            seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"abcdef"[..],
-            ..RECV_TEMPL
-        }])
+        assert_eq!(s.state, State::FinWait2);
+        sanity!(s, socket_fin_wait_2());
     }
 
     #[test]
-    fn test_mutual_close_with_data_1() {
-        let mut s = socket_established();
-        s.send_slice(b"abcdef").unwrap();
-        s.close();
-        assert_eq!(s.state, State::FinWait1);
+    fn test_fin_wait_1_fin_fin() {
+        let mut s = socket_fin_wait_1();
         recv!(s, [TcpRepr {
             control: TcpControl::Fin,
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"abcdef"[..],
             ..RECV_TEMPL
         }]);
         send!(s, TcpRepr {
             control: TcpControl::Fin,
             seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 6 + 1),
+            ack_number: Some(LOCAL_SEQ + 1),
             ..SEND_TEMPL
         });
+        assert_eq!(s.state, State::Closing);
+        sanity!(s, socket_closing());
     }
 
     #[test]
-    fn test_mutual_close_with_data_2() {
+    fn test_fin_wait_1_fin_with_data_queued() {
         let mut s = socket_established();
-        s.send_slice(b"abcdef").unwrap();
+        s.remote_win_len = 6;
+        s.send_slice(b"abcdef123456").unwrap();
         s.close();
-        assert_eq!(s.state, State::FinWait1);
-        recv!(s, [TcpRepr {
-            control: TcpControl::Fin,
+        recv!(s, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
             payload:    &b"abcdef"[..],
             ..RECV_TEMPL
-        }]);
+        }));
         send!(s, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 6 + 1),
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
             ..SEND_TEMPL
         });
-        assert_eq!(s.state, State::FinWait2);
-        send!(s, TcpRepr {
+        assert_eq!(s.state, State::FinWait1);
+    }
+
+    #[test]
+    fn test_fin_wait_1_close() {
+        let mut s = socket_fin_wait_1();
+        s.close();
+        assert_eq!(s.state, State::FinWait1);
+    }
+
+    // =========================================================================================//
+    // Tests for the FIN-WAIT-2 state.
+    // =========================================================================================//
+
+    #[test]
+    fn test_fin_wait_2_fin() {
+        let mut s = socket_fin_wait_2();
+        send!(s, time 1_000, TcpRepr {
             control: TcpControl::Fin,
             seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 6 + 1),
+            ack_number: Some(LOCAL_SEQ + 1 + 1),
             ..SEND_TEMPL
         });
+        assert_eq!(s.state, State::TimeWait);
+        sanity!(s, socket_time_wait(false));
+    }
+
+    #[test]
+    fn test_fin_wait_2_close() {
+        let mut s = socket_fin_wait_2();
+        s.close();
+        assert_eq!(s.state, State::FinWait2);
+    }
+
+    // =========================================================================================//
+    // Tests for the CLOSING state.
+    // =========================================================================================//
+
+    #[test]
+    fn test_closing_ack_fin() {
+        let mut s = socket_closing();
         recv!(s, [TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 6 + 1,
+            seq_number: LOCAL_SEQ + 1 + 1,
             ack_number: Some(REMOTE_SEQ + 1 + 1),
             ..RECV_TEMPL
         }]);
+        send!(s, time 1_000, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 1),
+            ..SEND_TEMPL
+        });
         assert_eq!(s.state, State::TimeWait);
+        sanity!(s, socket_time_wait(true));
+    }
+
+    #[test]
+    fn test_closing_close() {
+        let mut s = socket_closing();
+        s.close();
+        assert_eq!(s.state, State::Closing);
     }
 
     // =========================================================================================//
-    // Tests for retransmission on packet loss.
+    // Tests for the TIME-WAIT state.
     // =========================================================================================//
 
     #[test]
-    fn test_duplicate_seq_ack() {
-        let mut s = socket_recved();
-        // remote retransmission
-        send!(s, TcpRepr {
-            seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1),
-            payload:    &b"abcdef"[..],
-            ..SEND_TEMPL
-        }, Ok(Some(TcpRepr {
-            seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 6),
-            window_len: 58,
+    fn test_time_wait_from_fin_wait_2_ack() {
+        let mut s = socket_time_wait(false);
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 1),
             ..RECV_TEMPL
-        })));
+        }]);
     }
 
     #[test]
-    fn test_data_retransmit() {
-        let mut s = socket_established();
-        s.send_slice(b"abcdef").unwrap();
-        recv!(s, time 1000, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"abcdef"[..],
-            ..RECV_TEMPL
-        }));
-        recv!(s, time 1050, Err(Error::Exhausted));
-        recv!(s, time 1100, Ok(TcpRepr {
+    fn test_time_wait_from_closing_no_ack() {
+        let mut s = socket_time_wait(true);
+        recv!(s, []);
+    }
+
+    #[test]
+    fn test_time_wait_close() {
+        let mut s = socket_time_wait(false);
+        s.close();
+        assert_eq!(s.state, State::TimeWait);
+    }
+
+    #[test]
+    fn test_time_wait_retransmit() {
+        let mut s = socket_time_wait(false);
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ..RECV_TEMPL
+        }]);
+        send!(s, time 5_000, TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 1),
+            ..SEND_TEMPL
+        }, Ok(Some(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ..RECV_TEMPL
+        })));
+        assert_eq!(s.timer, Timer::Close { expires_at: 5_000 + CLOSE_DELAY });
+    }
+
+    #[test]
+    fn test_time_wait_timeout() {
+        let mut s = socket_time_wait(false);
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ..RECV_TEMPL
+        }]);
+        assert_eq!(s.state, State::TimeWait);
+        recv!(s, time 60_000, Err(Error::Exhausted));
+        assert_eq!(s.state, State::Closed);
+    }
+
+    #[test]
+    fn test_time_wait_timeout_configured() {
+        let mut s = socket_fin_wait_2();
+        s.set_time_wait_timeout(Duration::from_millis(500));
+        assert_eq!(s.time_wait_timeout(), Duration::from_millis(500));
+        send!(s, time 1_000, TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::TimeWait);
+        assert_eq!(s.timer, Timer::Close { expires_at: 1_500 });
+    }
+
+    #[test]
+    fn test_time_wait_reuse() {
+        let mut s = socket_time_wait(false);
+        s.set_time_wait_reuse(true);
+        assert!(s.time_wait_reuse());
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ + 100,
+            ack_number: None,
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::SynReceived);
+        assert_eq!(s.local_endpoint, LOCAL_END);
+        assert_eq!(s.remote_endpoint, REMOTE_END);
+        recv!(s, [TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: LOCAL_SEQ - 100,
+            ack_number: Some(REMOTE_SEQ + 100 + 1),
+            max_seg_size: Some(BASE_MSS),
+            ..RECV_TEMPL
+        }]);
+    }
+
+    #[test]
+    fn test_time_wait_reuse_old_syn() {
+        let mut s = socket_time_wait(false);
+        s.set_time_wait_reuse(true);
+        // A SYN that may belong to the old connection does not reopen it.
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: None,
+            ..SEND_TEMPL
+        }, Err(Error::Dropped));
+        assert_eq!(s.state, State::TimeWait);
+    }
+
+    #[test]
+    fn test_time_wait_reuse_timestamp() {
+        let mut s = socket_time_wait(false);
+        s.set_time_wait_reuse(true);
+        s.remote_has_ts    = true;
+        s.remote_ts_recent = 1000;
+        // With timestamps, the timestamp decides regardless of the sequence number.
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ + 100,
+            ack_number: None,
+            timestamp:  Some((900, 0)),
+            ..SEND_TEMPL
+        }, Err(Error::Dropped));
+        assert_eq!(s.state, State::TimeWait);
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: None,
+            timestamp:  Some((1100, 0)),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::SynReceived);
+    }
+
+    #[test]
+    fn test_time_wait_no_reuse() {
+        let mut s = socket_time_wait(false);
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ + 100,
+            ack_number: None,
+            ..SEND_TEMPL
+        }, Err(Error::Dropped));
+        assert_eq!(s.state, State::TimeWait);
+    }
+
+    // =========================================================================================//
+    // Tests for the CLOSE-WAIT state.
+    // =========================================================================================//
+
+    #[test]
+    fn test_close_wait_ack() {
+        let mut s = socket_close_wait();
+        s.send_slice(b"abcdef").unwrap();
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            payload: &b"abcdef"[..],
+            ..RECV_TEMPL
+        }]);
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            ..SEND_TEMPL
+        });
+    }
+
+    #[test]
+    fn test_close_wait_close() {
+        let mut s = socket_close_wait();
+        s.close();
+        assert_eq!(s.state, State::LastAck);
+        sanity!(s, socket_last_ack());
+    }
+
+    // =========================================================================================//
+    // Tests for the LAST-ACK state.
+    // =========================================================================================//
+    #[test]
+    fn test_last_ack_fin_ack() {
+        let mut s = socket_last_ack();
+        recv!(s, [TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ..RECV_TEMPL
+        }]);
+        assert_eq!(s.state, State::LastAck);
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::Closed);
+    }
+
+    #[test]
+    fn test_last_ack_close() {
+        let mut s = socket_last_ack();
+        s.close();
+        assert_eq!(s.state, State::LastAck);
+    }
+
+    // =========================================================================================//
+    // Tests for transitioning through multiple states.
+    // =========================================================================================//
+
+    #[test]
+    fn test_listen() {
+        let mut s = socket();
+        s.listen(IpEndpoint::new(IpAddress::default(), LOCAL_PORT)).unwrap();
+        assert_eq!(s.state, State::Listen);
+    }
+
+    #[test]
+    fn test_three_way_handshake() {
+        let mut s = socket_listen();
+        send!(s, TcpRepr {
+            control: TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: None,
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state(), State::SynReceived);
+        assert_eq!(s.local_endpoint(), LOCAL_END);
+        assert_eq!(s.remote_endpoint(), REMOTE_END);
+        recv!(s, [TcpRepr {
+            control: TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            max_seg_size: Some(BASE_MSS),
+            ..RECV_TEMPL
+        }]);
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state(), State::Established);
+        assert_eq!(s.local_seq_no, LOCAL_SEQ + 1);
+        assert_eq!(s.remote_seq_no, REMOTE_SEQ + 1);
+    }
+
+    #[test]
+    fn test_remote_close() {
+        let mut s = socket_established();
+        send!(s, TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::CloseWait);
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ..RECV_TEMPL
+        }]);
+        s.close();
+        assert_eq!(s.state, State::LastAck);
+        recv!(s, [TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ..RECV_TEMPL
+        }]);
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::Closed);
+    }
+
+    #[test]
+    fn test_local_close() {
+        let mut s = socket_established();
+        s.close();
+        assert_eq!(s.state, State::FinWait1);
+        recv!(s, [TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        }]);
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::FinWait2);
+        send!(s, TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::TimeWait);
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ..RECV_TEMPL
+        }]);
+    }
+
+    #[test]
+    fn test_shutdown_send() {
+        let mut s = socket_established();
+        assert_eq!(s.shutdown_send(), Ok(()));
+        assert_eq!(s.state, State::FinWait1);
+        assert_eq!(s.send_slice(b"abcdef"), Err(Error::Illegal));
+        recv!(s, [TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        }]);
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::FinWait2);
+        // The receive half stays open until the remote endpoint closes it.
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 1),
+            payload: &b"abcdef"[..],
+            ..SEND_TEMPL
+        });
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 58,
+            ..RECV_TEMPL
+        }]);
+        assert!(s.may_recv());
+        let mut buffer = [0; 6];
+        assert_eq!(s.recv_slice(&mut buffer[..]), Ok(6));
+        assert_eq!(&buffer, b"abcdef");
+        send!(s, TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: REMOTE_SEQ + 1 + 6,
+            ack_number: Some(LOCAL_SEQ + 1 + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::TimeWait);
+        assert!(!s.may_recv());
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6 + 1),
+            ..RECV_TEMPL
+        }]);
+    }
+
+    #[test]
+    fn test_shutdown_send_close_wait() {
+        let mut s = socket_close_wait();
+        assert_eq!(s.shutdown_send(), Ok(()));
+        assert_eq!(s.state, State::LastAck);
+        assert_eq!(s.shutdown_send(), Ok(()));
+        assert_eq!(s.state, State::LastAck);
+    }
+
+    #[test]
+    fn test_shutdown_send_not_connected() {
+        let mut s = socket();
+        assert_eq!(s.shutdown_send(), Err(Error::Illegal));
+        let mut s = socket_listen();
+        assert_eq!(s.shutdown_send(), Err(Error::Illegal));
+        assert_eq!(s.state, State::Listen);
+        let mut s = socket_syn_sent();
+        assert_eq!(s.shutdown_send(), Err(Error::Illegal));
+        assert_eq!(s.state, State::SynSent);
+    }
+
+    #[test]
+    fn test_simultaneous_close() {
+        let mut s = socket_established();
+        s.close();
+        assert_eq!(s.state, State::FinWait1);
+        recv!(s, [TcpRepr { // due to reordering, this is logically located...
+            control: TcpControl::Fin,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        }]);
+        send!(s, TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::Closing);
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ..RECV_TEMPL
+        }]);
+        // ... at this point
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::TimeWait);
+        recv!(s, []);
+    }
+
+    #[test]
+    fn test_simultaneous_close_combined_fin_ack() {
+        let mut s = socket_established();
+        s.close();
+        assert_eq!(s.state, State::FinWait1);
+        recv!(s, [TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        }]);
+        send!(s, TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::TimeWait);
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ..RECV_TEMPL
+        }]);
+    }
+
+    #[test]
+    fn test_fin_with_data() {
+        let mut s = socket_established();
+        s.send_slice(b"abcdef").unwrap();
+        s.close();
+        recv!(s, [TcpRepr {
+            control:    TcpControl::Fin,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }])
+    }
+
+    #[test]
+    fn test_mutual_close_with_data_1() {
+        let mut s = socket_established();
+        s.send_slice(b"abcdef").unwrap();
+        s.close();
+        assert_eq!(s.state, State::FinWait1);
+        recv!(s, [TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }]);
+        send!(s, TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6 + 1),
+            ..SEND_TEMPL
+        });
+    }
+
+    #[test]
+    fn test_mutual_close_with_data_2() {
+        let mut s = socket_established();
+        s.send_slice(b"abcdef").unwrap();
+        s.close();
+        assert_eq!(s.state, State::FinWait1);
+        recv!(s, [TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }]);
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6 + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state, State::FinWait2);
+        send!(s, TcpRepr {
+            control: TcpControl::Fin,
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6 + 1),
+            ..SEND_TEMPL
+        });
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 6 + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ..RECV_TEMPL
+        }]);
+        assert_eq!(s.state, State::TimeWait);
+    }
+
+    // =========================================================================================//
+    // Tests for retransmission on packet loss.
+    // =========================================================================================//
+
+    #[test]
+    fn test_duplicate_seq_ack() {
+        let mut s = socket_recved();
+        // remote retransmission
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..SEND_TEMPL
+        }, Ok(Some(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 58,
+            ..RECV_TEMPL
+        })));
+    }
+
+    #[test]
+    fn test_data_retransmit() {
+        let mut s = socket_established();
+        s.send_slice(b"abcdef").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        recv!(s, time 1050, Err(Error::Exhausted));
+        recv!(s, time 1100, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+    }
+
+    #[test]
+    fn test_data_retransmit_bursts() {
+        let mut s = socket_established();
+        s.remote_win_len = 6;
+        s.send_slice(b"abcdef012345").unwrap();
+
+        recv!(s, time 0, Ok(TcpRepr {
+            control:    TcpControl::None,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }), exact);
+        s.remote_win_len = 6;
+        recv!(s, time 0, Ok(TcpRepr {
+            control:    TcpControl::Psh,
+            seq_number: LOCAL_SEQ + 1 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"012345"[..],
+            ..RECV_TEMPL
+        }), exact);
+        s.remote_win_len = 6;
+        recv!(s, time 0, Err(Error::Exhausted));
+
+        recv!(s, time 50, Err(Error::Exhausted));
+
+        recv!(s, time 100, Ok(TcpRepr {
+            control:    TcpControl::None,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }), exact);
+        s.remote_win_len = 6;
+        recv!(s, time 150, Ok(TcpRepr {
+            control:    TcpControl::Psh,
+            seq_number: LOCAL_SEQ + 1 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"012345"[..],
+            ..RECV_TEMPL
+        }), exact);
+        s.remote_win_len = 6;
+        recv!(s, time 200, Err(Error::Exhausted));
+    }
+
+    #[test]
+    fn test_send_data_after_syn_ack_retransmit() {
+        let mut s = socket_syn_received();
+        recv!(s, time 50, Ok(TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            max_seg_size: Some(BASE_MSS),
+            ..RECV_TEMPL
+        }));
+        recv!(s, time 150, Ok(TcpRepr { // retransmit
+            control:    TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            max_seg_size: Some(BASE_MSS),
+            ..RECV_TEMPL
+        }));
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.state(), State::Established);
+        s.send_slice(b"abcdef").unwrap();
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }])
+    }
+
+    #[test]
+    fn test_established_retransmit_for_dup_ack() {
+        let mut s = socket_established();
+        // Duplicate ACKs do not replace the retransmission timer
+        s.send_slice(b"abc").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abc"[..],
+            ..RECV_TEMPL
+        }));
+        // Retransmit timer is on because all data was sent
+        assert_eq!(s.tx_buffer.len(), 3);
+        // ACK nothing new
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
+        // Retransmit
+        recv!(s, time 4000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abc"[..],
+            ..RECV_TEMPL
+        }));
+    }
+
+    #[test]
+    fn test_established_retransmit_reset_after_ack() {
+        let mut s = socket_established();
+        s.remote_win_len = 6;
+        s.send_slice(b"abcdef").unwrap();
+        s.send_slice(b"123456").unwrap();
+        s.send_slice(b"ABCDEF").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        send!(s, time 1005, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            window_len: 6,
+            ..SEND_TEMPL
+        });
+        recv!(s, time 1010, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"123456"[..],
+            ..RECV_TEMPL
+        }));
+        send!(s, time 1015, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6 + 6),
+            window_len: 6,
+            ..SEND_TEMPL
+        });
+        recv!(s, time 1020, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 6 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"ABCDEF"[..],
+            ..RECV_TEMPL
+        }));
+    }
+
+    #[test]
+    fn test_established_queue_during_retransmission() {
+        let mut s = socket_established();
+        s.remote_mss = 6;
+        s.send_slice(b"abcdef123456ABCDEF").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        })); // this one is dropped
+        recv!(s, time 1005, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"123456"[..],
+            ..RECV_TEMPL
+        })); // this one is received
+        recv!(s, time 1010, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 6 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"ABCDEF"[..],
+            ..RECV_TEMPL
+        })); // also dropped
+        recv!(s, time 2000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        })); // retransmission
+        send!(s, time 2005, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6 + 6),
+            ..SEND_TEMPL
+        }); // acknowledgement of both segments
+        recv!(s, time 2010, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 6 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"ABCDEF"[..],
+            ..RECV_TEMPL
+        })); // retransmission of only unacknowledged data
+    }
+
+    #[test]
+    fn test_close_wait_retransmit_reset_after_ack() {
+        let mut s = socket_close_wait();
+        s.remote_win_len = 6;
+        s.send_slice(b"abcdef").unwrap();
+        s.send_slice(b"123456").unwrap();
+        s.send_slice(b"ABCDEF").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        send!(s, time 1005, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            window_len: 6,
+            ..SEND_TEMPL
+        });
+        recv!(s, time 1010, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 6,
+            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            payload:    &b"123456"[..],
+            ..RECV_TEMPL
+        }));
+        send!(s, time 1015, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6 + 6),
+            window_len: 6,
+            ..SEND_TEMPL
+        });
+        recv!(s, time 1020, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 6 + 6,
+            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            payload:    &b"ABCDEF"[..],
+            ..RECV_TEMPL
+        }));
+    }
+
+    #[test]
+    fn test_fin_wait_1_retransmit_reset_after_ack() {
+        let mut s = socket_established();
+        s.remote_win_len = 6;
+        s.send_slice(b"abcdef").unwrap();
+        s.send_slice(b"123456").unwrap();
+        s.send_slice(b"ABCDEF").unwrap();
+        s.close();
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        send!(s, time 1005, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            window_len: 6,
+            ..SEND_TEMPL
+        });
+        recv!(s, time 1010, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"123456"[..],
+            ..RECV_TEMPL
+        }));
+        send!(s, time 1015, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6 + 6),
+            window_len: 6,
+            ..SEND_TEMPL
+        });
+        recv!(s, time 1020, Ok(TcpRepr {
+            control:    TcpControl::Fin,
+            seq_number: LOCAL_SEQ + 1 + 6 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"ABCDEF"[..],
+            ..RECV_TEMPL
+        }));
+    }
+
+    // =========================================================================================//
+    // Tests for window management.
+    // =========================================================================================//
+
+    #[test]
+    fn test_maximum_segment_size() {
+        let mut s = socket_listen();
+        s.tx_buffer = SocketBuffer::new(vec![0; 32767]);
+        send!(s, TcpRepr {
+            control: TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: None,
+            max_seg_size: Some(1000),
+            ..SEND_TEMPL
+        });
+        recv!(s, [TcpRepr {
+            control: TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            max_seg_size: Some(BASE_MSS),
+            ..RECV_TEMPL
+        }]);
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            window_len: 32767,
+            ..SEND_TEMPL
+        });
+        s.send_slice(&[0; 1200][..]).unwrap();
+        recv!(s, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload: &[0; 1000][..],
+            ..RECV_TEMPL
+        }));
+    }
+
+    // =========================================================================================//
+    // Tests for flow control.
+    // =========================================================================================//
+
+    #[test]
+    fn test_psh_transmit() {
+        let mut s = socket_established();
+        s.remote_win_len = 6;
+        s.send_slice(b"abcdef").unwrap();
+        s.send_slice(b"123456").unwrap();
+        recv!(s, time 0, Ok(TcpRepr {
+            control:    TcpControl::None,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }), exact);
+        recv!(s, time 0, Ok(TcpRepr {
+            control:    TcpControl::Psh,
+            seq_number: LOCAL_SEQ + 1 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"123456"[..],
+            ..RECV_TEMPL
+        }), exact);
+    }
+
+    #[test]
+    fn test_psh_receive() {
+        let mut s = socket_established();
+        send!(s, TcpRepr {
+            control:    TcpControl::Psh,
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..SEND_TEMPL
+        });
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 58,
+            ..RECV_TEMPL
+        }]);
+    }
+
+    #[test]
+    fn test_zero_window_ack() {
+        let mut s = socket_established();
+        s.rx_buffer = SocketBuffer::new(vec![0; 6]);
+        s.assembler = Assembler::new(s.rx_buffer.capacity());
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..SEND_TEMPL
+        });
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 0,
+            ..RECV_TEMPL
+        }]);
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 6,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"123456"[..],
+            ..SEND_TEMPL
+        }, Ok(Some(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 0,
+            ..RECV_TEMPL
+        })));
+    }
+
+    #[test]
+    fn test_growable_rx_buffer() {
+        let mut s = socket_established();
+        s.rx_buffer = SocketBuffer::growable(6, 12, || 0);
+        s.assembler = Assembler::new(s.rx_buffer.capacity());
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..SEND_TEMPL
+        });
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 0,
+            ..RECV_TEMPL
+        }]);
+        s.recv(|buffer| {
+            assert_eq!(&buffer[..3], b"abc");
+            (3, ())
+        }).unwrap();
+        // The buffer does not grow while it holds octets.
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 3,
+            ..RECV_TEMPL
+        }]);
+        s.recv(|buffer| {
+            assert_eq!(buffer, b"def");
+            (buffer.len(), ())
+        }).unwrap();
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 12,
+            ..RECV_TEMPL
+        }]);
+        assert_eq!(s.rx_buffer.capacity(), 12);
+        assert_eq!(s.assembler.total_size(), 12);
+    }
+
+    #[test]
+    fn test_zero_window_ack_on_window_growth() {
+        let mut s = socket_established();
+        s.rx_buffer = SocketBuffer::new(vec![0; 6]);
+        s.assembler = Assembler::new(s.rx_buffer.capacity());
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..SEND_TEMPL
+        });
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 0,
+            ..RECV_TEMPL
+        }]);
+        recv!(s, time 0, Err(Error::Exhausted));
+        s.recv(|buffer| {
+            assert_eq!(&buffer[..3], b"abc");
+            (3, ())
+        }).unwrap();
+        recv!(s, time 0, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 3,
+            ..RECV_TEMPL
+        }));
+        recv!(s, time 0, Err(Error::Exhausted));
+        s.recv(|buffer| {
+            assert_eq!(buffer, b"def");
+            (buffer.len(), ())
+        }).unwrap();
+        recv!(s, time 0, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 6,
+            ..RECV_TEMPL
+        }));
+    }
+
+    #[test]
+    fn test_recv_window_clamp() {
+        let mut s = socket_established();
+        s.set_recv_window_clamp(Some(32));
+        assert_eq!(s.recv_window_clamp(), Some(32));
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..SEND_TEMPL
+        });
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 26,
+            ..RECV_TEMPL
+        }]);
+    }
+
+    #[test]
+    fn test_recv_window_tuning() {
+        let mut s = socket_established();
+        s.rx_buffer = SocketBuffer::new(vec![0; 32768]);
+        s.assembler = Assembler::new(s.rx_buffer.capacity());
+        // The window starts out smaller than the buffer.
+        recv!(s, time 0, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            window_len: 8192,
+            ..RECV_TEMPL
+        }));
+        let data = vec![0; 8192];
+        send!(s, time 10, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &data[..],
+            ..SEND_TEMPL
+        });
+        recv!(s, time 10, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 8192),
+            window_len: 0,
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.recv_slice(&mut [0; 8192]), Ok(8192));
+        recv!(s, time 50, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 8192),
+            window_len: 8192,
+            ..RECV_TEMPL
+        }));
+        // Once a round trip has passed, the window grows to twice the amount drained.
+        recv!(s, time 100, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 8192),
+            window_len: 16384,
+            ..RECV_TEMPL
+        }));
+        // Without anything drained, it stays put.
+        recv!(s, time 200, Err(Error::Exhausted));
+        assert_eq!(s.recv_win_limit, 16384);
+    }
+
+    #[test]
+    fn test_fill_peer_window() {
+        let mut s = socket_established();
+        s.remote_mss = 6;
+        s.send_slice(b"abcdef123456!@#$%^").unwrap();
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }, TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"123456"[..],
+            ..RECV_TEMPL
+        }, TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 6 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"!@#$%^"[..],
+            ..RECV_TEMPL
+        }]);
+    }
+
+    // =========================================================================================//
+    // Tests for timeouts.
+    // =========================================================================================//
+
+    #[test]
+    fn test_listen_timeout() {
+        let mut s = socket_listen();
+        s.set_timeout(Some(100));
+        assert_eq!(s.poll_at(), None);
+    }
+
+    #[test]
+    fn test_connect_timeout() {
+        let mut s = socket();
+        s.local_seq_no = LOCAL_SEQ;
+        s.connect(REMOTE_END, LOCAL_END.port).unwrap();
+        s.set_timeout(Some(100));
+        recv!(s, time 150, Ok(TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: None,
+            max_seg_size: Some(BASE_MSS),
+            sack_permitted: true,
+            timestamp:  Some((150, 0)),
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.state, State::SynSent);
+        assert_eq!(s.poll_at(), Some(250));
+        recv!(s, time 250, Ok(TcpRepr {
+            control:    TcpControl::Rst,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(TcpSeqNumber(0)),
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.state, State::Closed);
+    }
+
+    #[test]
+    fn test_established_timeout() {
+        let mut s = socket_established();
+        s.set_timeout(Some(200));
+        recv!(s, time 250, Err(Error::Exhausted));
+        assert_eq!(s.poll_at(), Some(450));
+        s.send_slice(b"abcdef").unwrap();
+        assert_eq!(s.poll_at(), Some(0));
+        recv!(s, time 255, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.poll_at(), Some(355));
+        recv!(s, time 355, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.poll_at(), Some(455));
+        recv!(s, time 500, Ok(TcpRepr {
+            control:    TcpControl::Rst,
+            seq_number: LOCAL_SEQ + 1 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.state, State::Closed);
+    }
+
+    #[test]
+    fn test_established_keep_alive_timeout() {
+        let mut s = socket_established();
+        s.set_keep_alive(Some(50));
+        s.set_timeout(Some(100));
+        recv!(s, time 100, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &[0],
+            ..RECV_TEMPL
+        }));
+        recv!(s, time 100, Err(Error::Exhausted));
+        assert_eq!(s.poll_at(), Some(150));
+        send!(s, time 105, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.poll_at(), Some(155));
+        recv!(s, time 155, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &[0],
+            ..RECV_TEMPL
+        }));
+        recv!(s, time 155, Err(Error::Exhausted));
+        assert_eq!(s.poll_at(), Some(205));
+        recv!(s, time 200, Err(Error::Exhausted));
+        recv!(s, time 205, Ok(TcpRepr {
+            control:    TcpControl::Rst,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        }));
+        recv!(s, time 205, Err(Error::Exhausted));
+        assert_eq!(s.state, State::Closed);
+    }
+
+    #[test]
+    fn test_fin_wait_1_timeout() {
+        let mut s = socket_fin_wait_1();
+        s.set_timeout(Some(200));
+        recv!(s, time 100, Ok(TcpRepr {
+            control:    TcpControl::Fin,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.poll_at(), Some(200));
+        recv!(s, time 400, Ok(TcpRepr {
+            control:    TcpControl::Rst,
+            seq_number: LOCAL_SEQ + 1 + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.state, State::Closed);
+    }
+
+    #[test]
+    fn test_last_ack_timeout() {
+        let mut s = socket_last_ack();
+        s.set_timeout(Some(200));
+        recv!(s, time 100, Ok(TcpRepr {
+            control:    TcpControl::Fin,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.poll_at(), Some(200));
+        recv!(s, time 400, Ok(TcpRepr {
+            control:    TcpControl::Rst,
+            seq_number: LOCAL_SEQ + 1 + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.state, State::Closed);
+    }
+
+    #[test]
+    fn test_closed_timeout() {
+        let mut s = socket_established();
+        s.set_timeout(Some(200));
+        s.remote_last_ts = Some(100);
+        s.abort();
+        assert_eq!(s.poll_at(), Some(0));
+        recv!(s, time 100, Ok(TcpRepr {
+            control:    TcpControl::Rst,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.poll_at(), None);
+    }
+
+    #[test]
+    fn test_user_timeout() {
+        let mut s = socket_established();
+        s.set_user_timeout(Some(Duration::from_millis(1000)));
+        assert_eq!(s.user_timeout(), Some(Duration::from_millis(1000)));
+        recv!(s, time 500, Err(Error::Exhausted));
+        assert_eq!(s.poll_at(), None);
+        s.send_slice(b"abcdef").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        // Packets that do not acknowledge the data do not extend the connection.
+        send!(s, time 1050, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
+        recv!(s, time 1100, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        recv!(s, time 1300, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.poll_at(), Some(1500));
+        recv!(s, time 1500, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.poll_at(), Some(1900));
+        recv!(s, time 1900, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        // The user timeout expires before the next retransmission.
+        assert_eq!(s.poll_at(), Some(2000));
+        recv!(s, time 2000, Ok(TcpRepr {
+            control:    TcpControl::Rst,
+            seq_number: LOCAL_SEQ + 1 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.state, State::Closed);
+    }
+
+    #[test]
+    fn test_user_timeout_acknowledged() {
+        let mut s = socket_established();
+        s.remote_mss = 6;
+        s.set_user_timeout(Some(Duration::from_millis(1000)));
+        s.send_slice(b"abcdef123456").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"123456"[..],
+            ..RECV_TEMPL
+        }));
+        // Acknowledging some of the data restarts the user timeout...
+        send!(s, time 1500, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.unacked_since, Some(1500));
+        // ... and acknowledging all of it stops it.
+        send!(s, time 1600, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 12),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.unacked_since, None);
+        recv!(s, time 3000, Err(Error::Exhausted));
+        assert_eq!(s.state, State::Established);
+    }
+
+    #[test]
+    fn test_max_retransmits() {
+        let mut s = socket_established();
+        s.set_max_retransmits(Some(2));
+        assert_eq!(s.max_retransmits(), Some(2));
+        s.send_slice(b"abcdef").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        recv!(s, time 1100, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        recv!(s, time 1300, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        // The connection is aborted instead of retransmitting a third time.
+        assert_eq!(s.poll_at(), Some(1500));
+        recv!(s, time 1500, Ok(TcpRepr {
+            control:    TcpControl::Rst,
+            seq_number: LOCAL_SEQ + 1 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.state, State::Closed);
+    }
+
+    #[test]
+    fn test_max_retransmits_acknowledged() {
+        let mut s = socket_established();
+        s.remote_mss = 6;
+        s.set_max_retransmits(Some(1));
+        s.send_slice(b"abcdef123456").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"123456"[..],
+            ..RECV_TEMPL
+        }));
+        recv!(s, time 1100, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.retransmits, 1);
+        // Acknowledging some of the data restarts the count.
+        send!(s, time 1150, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.retransmits, 0);
+        assert_eq!(s.state, State::Established);
+    }
+
+    #[test]
+    fn test_rto_bounds() {
+        let mut s = socket_established();
+        s.set_initial_rto(Duration::from_millis(1000));
+        assert_eq!(s.initial_rto(), Duration::from_millis(1000));
+        s.set_rto_bounds(Duration::from_millis(500), Duration::from_millis(1500));
+        assert_eq!(s.rto_bounds(), (Duration::from_millis(500), Duration::from_millis(1500)));
+        s.send_slice(b"abcdef").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.poll_at(), Some(2000));
+        recv!(s, time 2000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.poll_at(), Some(3000));
+        recv!(s, time 3000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        // The backed off timeout is clamped to the upper bound.
+        assert_eq!(s.poll_at(), Some(4500));
+        recv!(s, time 4500, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.poll_at(), Some(6000));
+    }
+
+    #[test]
+    fn test_rto_bounds_clamp_initial() {
+        let mut s = socket();
+        s.set_rto_bounds(Duration::from_millis(500), Duration::from_millis(1500));
+        assert_eq!(s.initial_rto(), Duration::from_millis(500));
+        s.set_initial_rto(Duration::from_millis(2000));
+        assert_eq!(s.initial_rto(), Duration::from_millis(1500));
+        // The configuration is kept across connections.
+        s.reset();
+        assert_eq!(s.initial_rto(), Duration::from_millis(1500));
+        assert_eq!(s.rtte.retransmission_timeout(), 1500);
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds upper bound")]
+    fn test_rto_bounds_inverted() {
+        let mut s = socket();
+        s.set_rto_bounds(Duration::from_millis(1500), Duration::from_millis(500));
+    }
+
+    // =========================================================================================//
+    // Tests for keep-alive.
+    // =========================================================================================//
+
+    #[test]
+    fn test_responds_to_keep_alive() {
+        let mut s = socket_established();
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        }, Ok(Some(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        })));
+    }
+
+    #[test]
+    fn test_sends_keep_alive() {
+        let mut s = socket_established();
+        s.set_keep_alive(Some(100));
+
+        // drain the forced keep-alive packet
+        assert_eq!(s.poll_at(), Some(0));
+        recv!(s, time 0, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &[0],
+            ..RECV_TEMPL
+        }));
+
+        assert_eq!(s.poll_at(), Some(100));
+        recv!(s, time 95, Err(Error::Exhausted));
+        recv!(s, time 100, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &[0],
+            ..RECV_TEMPL
+        }));
+
+        assert_eq!(s.poll_at(), Some(200));
+        recv!(s, time 195, Err(Error::Exhausted));
+        recv!(s, time 200, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &[0],
+            ..RECV_TEMPL
+        }));
+
+        send!(s, time 250, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.poll_at(), Some(350));
+        recv!(s, time 345, Err(Error::Exhausted));
+        recv!(s, time 350, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"\x00"[..],
+            ..RECV_TEMPL
+        }));
+    }
+
+    #[test]
+    fn test_keep_alive_interval_count() {
+        let mut s = socket_established();
+        s.set_keep_alive(Some(1000));
+        s.set_keep_alive_interval(Some(100));
+        s.set_keep_alive_count(Some(3));
+
+        // drain the forced keep-alive packet, and answer it
+        recv!(s, time 0, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &[0],
+            ..RECV_TEMPL
+        }));
+        send!(s, time 10, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.keep_alive_probes, 0);
+
+        // The first keep-alive packet follows the idle interval, and the unanswered ones
+        // the probe interval.
+        assert_eq!(s.poll_at(), Some(1010));
+        for &at in &[1010, 1110, 1210] {
+            recv!(s, time at - 1, Err(Error::Exhausted));
+            recv!(s, time at, Ok(TcpRepr {
+                seq_number: LOCAL_SEQ,
+                ack_number: Some(REMOTE_SEQ + 1),
+                payload:    &[0],
+                ..RECV_TEMPL
+            }));
+        }
+        assert_eq!(s.poll_at(), Some(1310));
+        recv!(s, time 1310, Ok(TcpRepr {
+            control:    TcpControl::Rst,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.state, State::Closed);
+    }
+
+    #[test]
+    fn test_keep_alive_count_answered() {
+        let mut s = socket_established();
+        s.set_keep_alive(Some(100));
+        s.set_keep_alive_count(Some(1));
+        recv!(s, time 0, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &[0],
+            ..RECV_TEMPL
+        }));
+        send!(s, time 50, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
+        // An answered keep-alive packet does not count, so another one is sent.
+        recv!(s, time 150, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &[0],
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.state, State::Established);
+        recv!(s, time 250, Ok(TcpRepr {
+            control:    TcpControl::Rst,
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        }));
+    }
+
+    // =========================================================================================//
+    // Tests for time-to-live and DSCP configuration.
+    // =========================================================================================//
+
+    #[test]
+    fn test_set_hop_limit() {
+        let mut s = socket_syn_received();
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = 1520;
+
+        s.set_hop_limit(Some(0x2a));
+        assert_eq!(s.dispatch(Instant::from_millis(0), &caps, |(ip_repr, _)| {
+            assert_eq!(ip_repr.hop_limit(), 0x2a);
+            Ok(())
+        }), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "the time-to-live value of a packet must not be zero")]
+    fn test_set_hop_limit_zero() {
+        let mut s = socket_syn_received();
+        s.set_hop_limit(Some(0));
+    }
+
+    #[test]
+    fn test_set_dscp() {
+        let mut s = socket_syn_received();
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = 1520;
+
+        s.set_dscp(46);
+        assert_eq!(s.dispatch(Instant::from_millis(0), &caps, |(ip_repr, _)| {
+            assert_eq!(ip_repr.dscp(), 46);
+            Ok(())
+        }), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "the DSCP value of a packet must fit in six bits")]
+    fn test_set_dscp_too_large() {
+        let mut s = socket_syn_received();
+        s.set_dscp(64);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv6")]
+    fn test_set_flow_label() {
+        let mut s = socket_syn_received();
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = 1520;
+
+        s.set_flow_label(0x12345);
+        assert_eq!(s.dispatch(Instant::from_millis(0), &caps, |(ip_repr, _)| {
+            assert_eq!(ip_repr.flow_label(), 0x12345);
+            Ok(())
+        }), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "the flow label of a packet must fit in twenty bits")]
+    fn test_set_flow_label_too_large() {
+        let mut s = socket_syn_received();
+        s.set_flow_label(0x100000);
+    }
+
+    #[test]
+    fn test_builder() {
+        let s = TcpSocket::builder()
+            .rx_buffer(SocketBuffer::new(vec![0; 64]))
+            .tx_buffer(SocketBuffer::new(vec![0; 64]))
+            .timeout(Some(2_000))
+            .keep_alive(Some(1_000))
+            .keep_alive_count(Some(3))
+            .nagle_enabled(false)
+            .max_segment_size(Some(536))
+            .hop_limit(Some(32))
+            .dscp(46)
+            .flow_label(0x12345)
+            .finalize();
+        assert_eq!(s.state(), State::Closed);
+        assert_eq!(s.timeout(), Some(2_000));
+        assert_eq!(s.keep_alive(), Some(1_000));
+        assert_eq!(s.keep_alive_count(), Some(3));
+        assert_eq!(s.nagle_enabled(), false);
+        assert_eq!(s.max_segment_size(), Some(536));
+        assert_eq!(s.hop_limit(), Some(32));
+        assert_eq!(s.dscp(), 46);
+        assert_eq!(s.flow_label(), 0x12345);
+    }
+
+    #[test]
+    #[should_panic(expected = "a required option was not set")]
+    fn test_builder_no_buffer() {
+        TcpSocket::builder()
+            .rx_buffer(SocketBuffer::new(vec![0; 64]))
+            .finalize();
+    }
+
+    #[test]
+    #[should_panic(expected = "the receive buffer must not be empty")]
+    fn test_builder_empty_rx_buffer() {
+        TcpSocket::builder()
+            .rx_buffer(SocketBuffer::new(vec![]))
+            .tx_buffer(SocketBuffer::new(vec![0; 64]))
+            .finalize();
+    }
+
+    #[test]
+    #[should_panic(expected = "the keep-alive interval must be shorter than the timeout")]
+    fn test_builder_keep_alive_after_timeout() {
+        TcpSocket::builder()
+            .rx_buffer(SocketBuffer::new(vec![0; 64]))
+            .tx_buffer(SocketBuffer::new(vec![0; 64]))
+            .timeout(Some(1_000))
+            .keep_alive(Some(1_000))
+            .finalize();
+    }
+
+    #[test]
+    #[should_panic(expected = "keep-alive options were set without enabling keep-alive")]
+    fn test_builder_keep_alive_count_without_keep_alive() {
+        TcpSocket::builder()
+            .rx_buffer(SocketBuffer::new(vec![0; 64]))
+            .tx_buffer(SocketBuffer::new(vec![0; 64]))
+            .keep_alive_count(Some(3))
+            .finalize();
+    }
+
+    // =========================================================================================//
+    // Tests for selective acknowledgements.
+    // =========================================================================================//
+
+    fn sack_range(start: TcpSeqNumber, end: TcpSeqNumber) -> Option<(u32, u32)> {
+        Some((start.0 as u32, end.0 as u32))
+    }
+
+    #[test]
+    fn test_scoreboard() {
+        let seq = |offset| LOCAL_SEQ + offset;
+        let mut scoreboard = Scoreboard::default();
+        assert_eq!(scoreboard.next_hole(seq(0)), (seq(0), None));
+
+        scoreboard.add(seq(20), seq(30));
+        scoreboard.add(seq(5), seq(10));
+        scoreboard.add(seq(10), seq(12));
+        assert_eq!(scoreboard.ranges, [Some((seq(5), seq(12))), Some((seq(20), seq(30))),
+                                       None, None]);
+        assert_eq!(scoreboard.next_hole(seq(0)), (seq(0), Some(5)));
+        assert_eq!(scoreboard.next_hole(seq(6)), (seq(12), Some(8)));
+        assert_eq!(scoreboard.next_hole(seq(25)), (seq(30), None));
+
+        scoreboard.add(seq(11), seq(21));
+        assert_eq!(scoreboard.ranges, [Some((seq(5), seq(30))), None, None, None]);
+
+        scoreboard.acknowledge(seq(8));
+        assert_eq!(scoreboard.ranges, [Some((seq(8), seq(30))), None, None, None]);
+        scoreboard.acknowledge(seq(30));
+        assert!(scoreboard.is_empty());
+    }
+
+    #[test]
+    fn test_listen_syn_sack() {
+        let mut s = socket_listen();
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: None,
+            sack_permitted: true,
+            ..SEND_TEMPL
+        });
+        assert!(s.remote_has_sack);
+        recv!(s, [TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            max_seg_size: Some(BASE_MSS),
+            sack_permitted: true,
+            ..RECV_TEMPL
+        }]);
+    }
+
+    #[test]
+    fn test_syn_sent_syn_ack_no_sack() {
+        let mut s = socket_syn_sent();
+        s.remote_has_sack = true;
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: Some(LOCAL_SEQ + 1),
+            max_seg_size: Some(BASE_MSS),
+            ..SEND_TEMPL
+        });
+        assert!(!s.remote_has_sack);
+    }
+
+    #[test]
+    fn test_sack_out_of_order() {
+        let mut s = socket_established();
+        s.remote_has_sack = true;
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 6,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"ghi"[..],
+            ..SEND_TEMPL
+        }, Ok(Some(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"abcdef"[..],
+            sack_ranges: [sack_range(REMOTE_SEQ + 1 + 6, REMOTE_SEQ + 1 + 9), None, None],
             ..RECV_TEMPL
-        }));
+        })));
+        // The block containing the most recent segment is reported first.
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 12,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"mno"[..],
+            ..SEND_TEMPL
+        }, Ok(Some(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            sack_ranges: [sack_range(REMOTE_SEQ + 1 + 12, REMOTE_SEQ + 1 + 15),
+                          sack_range(REMOTE_SEQ + 1 + 6, REMOTE_SEQ + 1 + 9), None],
+            ..RECV_TEMPL
+        })));
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 3,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"def"[..],
+            ..SEND_TEMPL
+        }, Ok(Some(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            sack_ranges: [sack_range(REMOTE_SEQ + 1 + 3, REMOTE_SEQ + 1 + 9),
+                          sack_range(REMOTE_SEQ + 1 + 12, REMOTE_SEQ + 1 + 15), None],
+            ..RECV_TEMPL
+        })));
+        // Once the data is in order, no blocks are reported.
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"abc"[..],
+            ..SEND_TEMPL
+        }, Ok(Some(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 9),
+            window_len: 55,
+            sack_ranges: [sack_range(REMOTE_SEQ + 1 + 12, REMOTE_SEQ + 1 + 15), None, None],
+            ..RECV_TEMPL
+        })));
     }
 
     #[test]
-    fn test_data_retransmit_bursts() {
+    fn test_sack_out_of_order_not_permitted() {
         let mut s = socket_established();
-        s.remote_win_len = 6;
-        s.send_slice(b"abcdef012345").unwrap();
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 6,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"ghi"[..],
+            ..SEND_TEMPL
+        }, Ok(Some(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        })));
+    }
 
-        recv!(s, time 0, Ok(TcpRepr {
-            control:    TcpControl::None,
+    #[test]
+    fn test_sack_retransmit() {
+        let mut s = socket_established();
+        s.remote_has_sack = true;
+        s.remote_mss = 6;
+        s.send_slice(b"abcdef123456ABCDEF").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
             payload:    &b"abcdef"[..],
             ..RECV_TEMPL
-        }), exact);
-        s.remote_win_len = 6;
-        recv!(s, time 0, Ok(TcpRepr {
-            control:    TcpControl::Psh,
+        })); // this one is dropped
+        recv!(s, time 1005, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1 + 6,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"012345"[..],
+            payload:    &b"123456"[..],
             ..RECV_TEMPL
-        }), exact);
-        s.remote_win_len = 6;
-        recv!(s, time 0, Err(Error::Exhausted));
+        })); // this one is received
+        recv!(s, time 1010, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 6 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"ABCDEF"[..],
+            ..RECV_TEMPL
+        })); // also dropped
+        send!(s, time 1050, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            sack_ranges: [sack_range(LOCAL_SEQ + 1 + 6, LOCAL_SEQ + 1 + 12), None, None],
+            ..SEND_TEMPL
+        }); // selective acknowledgement of the second segment
+        recv!(s, time 1110, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        })); // retransmission of the first segment
+        recv!(s, time 1115, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 6 + 6,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"ABCDEF"[..],
+            ..RECV_TEMPL
+        })); // retransmission of the third segment, skipping the second one
+        recv!(s, time 1120, Err(Error::Exhausted));
+        send!(s, time 1150, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 18),
+            ..SEND_TEMPL
+        });
+        assert!(s.remote_sacked.is_empty());
+        assert_eq!(s.tx_buffer.len(), 0);
+    }
 
-        recv!(s, time 50, Err(Error::Exhausted));
+    // =========================================================================================//
+    // Tests for fast retransmit.
+    // =========================================================================================//
 
-        recv!(s, time 100, Ok(TcpRepr {
-            control:    TcpControl::None,
+    fn socket_fast_retransmit() -> TcpSocket<'static> {
+        let mut s = socket_established();
+        s.remote_mss = 6;
+        s.send_slice(b"abcdef123456ABCDEF").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
             payload:    &b"abcdef"[..],
             ..RECV_TEMPL
-        }), exact);
-        s.remote_win_len = 6;
-        recv!(s, time 150, Ok(TcpRepr {
-            control:    TcpControl::Psh,
+        })); // this one is dropped
+        recv!(s, time 1000, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1 + 6,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"012345"[..],
+            payload:    &b"123456"[..],
             ..RECV_TEMPL
-        }), exact);
-        s.remote_win_len = 6;
-        recv!(s, time 200, Err(Error::Exhausted));
+        }));
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 12,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"ABCDEF"[..],
+            ..RECV_TEMPL
+        }));
+        s
     }
 
     #[test]
-    fn test_send_data_after_syn_ack_retransmit() {
-        let mut s = socket_syn_received();
-        recv!(s, time 50, Ok(TcpRepr {
-            control:    TcpControl::Syn,
+    fn test_fast_retransmit() {
+        let mut s = socket_fast_retransmit();
+        s.set_congestion_control(Algorithm::Reno);
+        for &timestamp in &[1010, 1011, 1012] {
+            recv!(s, time timestamp, Err(Error::Exhausted));
+            send!(s, time timestamp, TcpRepr {
+                seq_number: REMOTE_SEQ + 1,
+                ack_number: Some(LOCAL_SEQ + 1),
+                ..SEND_TEMPL
+            });
+        }
+        assert_eq!(s.remote_dup_acks, 3);
+        assert_eq!(s.recovery_seq, Some(LOCAL_SEQ + 1 + 18));
+        // The congestion window has been reduced without waiting for a timeout.
+        assert_eq!(s.congestion.window(), 12);
+        recv!(s, time 1012, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        // Further duplicate acknowledgements do not cause another retransmission.
+        send!(s, time 1013, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.remote_last_seq, LOCAL_SEQ + 1 + 6);
+        send!(s, time 1020, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 18),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.remote_dup_acks, 0);
+        assert_eq!(s.recovery_seq, None);
+        assert_eq!(s.tx_buffer.len(), 0);
+    }
+
+    #[test]
+    fn test_fast_retransmit_window_update() {
+        let mut s = socket_fast_retransmit();
+        for &window_len in &[256, 255, 254] {
+            send!(s, time 1010, TcpRepr {
+                seq_number: REMOTE_SEQ + 1,
+                ack_number: Some(LOCAL_SEQ + 1),
+                window_len: window_len,
+                ..SEND_TEMPL
+            });
+        }
+        // Acknowledgements that update the window are not duplicates.
+        assert_eq!(s.remote_dup_acks, 1);
+        recv!(s, time 1010, Err(Error::Exhausted));
+    }
+
+    // =========================================================================================//
+    // Tests for zero window probing.
+    // =========================================================================================//
+
+    #[test]
+    fn test_zero_window_probe() {
+        let mut s = socket_established();
+        s.remote_win_len = 0;
+        s.send_slice(b"abcdef").unwrap();
+        recv!(s, time 1000, Err(Error::Exhausted));
+        assert_eq!(s.poll_at(), Some(1000 + PERSIST_DELAY));
+        recv!(s, time 1100, Ok(TcpRepr {
             seq_number: LOCAL_SEQ,
             ack_number: Some(REMOTE_SEQ + 1),
-            max_seg_size: Some(BASE_MSS),
+            payload:    &b"\x00"[..],
             ..RECV_TEMPL
         }));
-        recv!(s, time 150, Ok(TcpRepr { // retransmit
-            control:    TcpControl::Syn,
+        assert_eq!(s.poll_at(), Some(1100 + PERSIST_DELAY * 2));
+        // The window is still closed; the probing continues with the same backoff.
+        send!(s, time 1110, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            window_len: 0,
+            ..SEND_TEMPL
+        });
+        recv!(s, time 1200, Err(Error::Exhausted));
+        recv!(s, time 1300, Ok(TcpRepr {
             seq_number: LOCAL_SEQ,
             ack_number: Some(REMOTE_SEQ + 1),
-            max_seg_size: Some(BASE_MSS),
+            payload:    &b"\x00"[..],
             ..RECV_TEMPL
         }));
-        send!(s, TcpRepr {
+        assert_eq!(s.poll_at(), Some(1300 + PERSIST_DELAY * 4));
+        // The window opens; the data is sent.
+        send!(s, time 1310, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
             ack_number: Some(LOCAL_SEQ + 1),
+            window_len: 64,
             ..SEND_TEMPL
         });
-        assert_eq!(s.state(), State::Established);
-        s.send_slice(b"abcdef").unwrap();
-        recv!(s, [TcpRepr {
+        recv!(s, time 1310, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
             payload:    &b"abcdef"[..],
             ..RECV_TEMPL
-        }])
+        }));
+        assert!(s.timer.is_retransmit());
     }
 
     #[test]
-    fn test_established_retransmit_for_dup_ack() {
+    fn test_zero_window_probe_backoff_limit() {
         let mut s = socket_established();
-        // Duplicate ACKs do not replace the retransmission timer
-        s.send_slice(b"abc").unwrap();
+        s.remote_win_len = 0;
+        s.send_slice(b"abcdef").unwrap();
+        s.timer = Timer::Persist { expires_at: 1000, delay: PERSIST_DELAY_MAX };
         recv!(s, time 1000, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ + 1,
+            seq_number: LOCAL_SEQ,
             ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"\x00"[..],
+            ..RECV_TEMPL
+        }));
+        assert_eq!(s.poll_at(), Some(1000 + PERSIST_DELAY_MAX));
+    }
+
+    #[test]
+    fn test_zero_window_probe_no_data() {
+        let mut s = socket_established();
+        s.remote_win_len = 0;
+        s.send_slice(b"abcdef").unwrap();
+        recv!(s, time 1000, Err(Error::Exhausted));
+        assert!(s.timer.is_persist());
+        // Once there is nothing to send, there is nothing to probe for.
+        s.tx_buffer.clear();
+        recv!(s, time 1050, Err(Error::Exhausted));
+        assert_eq!(s.timer, Timer::Idle { keep_alive_at: None });
+    }
+
+    // =========================================================================================//
+    // Tests for delayed acknowledgements.
+    // =========================================================================================//
+
+    fn socket_established_with_ack_delay() -> TcpSocket<'static> {
+        let mut s = socket_established();
+        s.set_ack_delay(Some(ACK_DELAY));
+        s
+    }
+
+    #[test]
+    fn test_delayed_ack() {
+        let mut s = socket_established_with_ack_delay();
+        send!(s, time 1000, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
             payload:    &b"abc"[..],
+            ..SEND_TEMPL
+        });
+        recv!(s, time 1000, Err(Error::Exhausted));
+        assert_eq!(s.poll_at(), Some(1000 + ACK_DELAY));
+        recv!(s, time 1000 + ACK_DELAY, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 3),
+            window_len: 61,
             ..RECV_TEMPL
         }));
-        // Retransmit timer is on because all data was sent
-        assert_eq!(s.tx_buffer.len(), 3);
-        // ACK nothing new
-        send!(s, TcpRepr {
+        recv!(s, time 1000 + ACK_DELAY, Err(Error::Exhausted));
+    }
+
+    #[test]
+    fn test_delayed_ack_every_second_segment() {
+        let mut s = socket_established_with_ack_delay();
+        send!(s, time 1000, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
             ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"abc"[..],
             ..SEND_TEMPL
         });
-        // Retransmit
-        recv!(s, time 4000, Ok(TcpRepr {
+        send!(s, time 1005, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 3,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"def"[..],
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.poll_at(), Some(1005));
+        recv!(s, time 1005, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1),
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 58,
+            ..RECV_TEMPL
+        }));
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let mut s = socket_established_with_ack_delay();
+        send!(s, time 1000, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
             payload:    &b"abc"[..],
+            ..SEND_TEMPL
+        });
+        let now = Instant::from_millis(1000);
+        let ip_repr = IpRepr::Unspecified {
+            src_addr:    MOCK_IP_ADDR_2,
+            dst_addr:    MOCK_IP_ADDR_1,
+            protocol:    IpProtocol::Tcp,
+            payload_len: 3,
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0,
+            flow_label:  0
+        };
+        // Segments out of sequence, or past the window, are not coalesced.
+        assert!(!s.coalesce(now, &ip_repr, &TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 4,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"def"[..],
+            ..SEND_TEMPL
+        }));
+        assert!(!s.coalesce(now, &ip_repr, &TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 3,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &[0; 62][..],
+            ..SEND_TEMPL
+        }));
+        assert!(!s.coalesce(now, &ip_repr, &TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 3,
+            ack_number: Some(LOCAL_SEQ + 1),
+            urgent_at:  Some(1),
+            payload:    &b"def"[..],
+            ..SEND_TEMPL
+        }));
+        assert!(s.coalesce(now, &ip_repr, &TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 3,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"def"[..],
+            ..SEND_TEMPL
+        }));
+        assert_eq!(s.stats().segments_received, 2);
+        assert_eq!(s.poll_at(), Some(1000));
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 58,
             ..RECV_TEMPL
         }));
+        assert_eq!(s.recv_slice(&mut [0; 6]), Ok(6));
     }
 
     #[test]
-    fn test_established_retransmit_reset_after_ack() {
-        let mut s = socket_established();
-        s.remote_win_len = 6;
-        s.send_slice(b"abcdef").unwrap();
-        s.send_slice(b"123456").unwrap();
-        s.send_slice(b"ABCDEF").unwrap();
+    fn test_delayed_ack_piggyback() {
+        let mut s = socket_established_with_ack_delay();
+        send!(s, time 1000, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"abc"[..],
+            ..SEND_TEMPL
+        });
+        s.send_slice(b"xyz").unwrap();
         recv!(s, time 1000, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"abcdef"[..],
+            ack_number: Some(REMOTE_SEQ + 1 + 3),
+            window_len: 61,
+            payload:    &b"xyz"[..],
             ..RECV_TEMPL
         }));
-        send!(s, time 1005, TcpRepr {
+        // The acknowledgement was sent along with the data.
+        recv!(s, time 1000 + ACK_DELAY, Err(Error::Exhausted));
+    }
+
+    #[test]
+    fn test_delayed_ack_fin() {
+        let mut s = socket_established_with_ack_delay();
+        send!(s, time 1000, TcpRepr {
+            control:    TcpControl::Fin,
             seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 6),
-            window_len: 6,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"abc"[..],
             ..SEND_TEMPL
         });
-        recv!(s, time 1010, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 6,
+        assert_eq!(s.state, State::CloseWait);
+        assert_eq!(s.poll_at(), Some(0));
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 3 + 1),
+            window_len: 61,
+            ..RECV_TEMPL
+        }));
+    }
+
+    #[test]
+    fn test_delayed_ack_disabled() {
+        let mut s = socket_established_with_ack_delay();
+        send!(s, time 1000, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"abc"[..],
+            ..SEND_TEMPL
+        });
+        s.set_ack_delay(None);
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 3),
+            window_len: 61,
+            ..RECV_TEMPL
+        }));
+    }
+
+    // =========================================================================================//
+    // Tests for Nagle's algorithm.
+    // =========================================================================================//
+
+    #[test]
+    fn test_nagle() {
+        let mut s = socket_established();
+        s.set_nagle_enabled(true);
+        s.send_slice(b"abc").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"123456"[..],
+            payload:    &b"abc"[..],
             ..RECV_TEMPL
         }));
-        send!(s, time 1015, TcpRepr {
+        // The first segment is unacknowledged, so the small writes are held back.
+        s.send_slice(b"def").unwrap();
+        s.send_slice(b"ghi").unwrap();
+        recv!(s, time 1010, Err(Error::Exhausted));
+        send!(s, time 1020, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 6 + 6),
-            window_len: 6,
+            ack_number: Some(LOCAL_SEQ + 1 + 3),
             ..SEND_TEMPL
         });
         recv!(s, time 1020, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 6 + 6,
+            seq_number: LOCAL_SEQ + 1 + 3,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"ABCDEF"[..],
+            payload:    &b"defghi"[..],
             ..RECV_TEMPL
         }));
     }
 
     #[test]
-    fn test_established_queue_during_retransmission() {
+    fn test_nagle_full_segment() {
         let mut s = socket_established();
+        s.set_nagle_enabled(true);
         s.remote_mss = 6;
-        s.send_slice(b"abcdef123456ABCDEF").unwrap();
+        s.send_slice(b"abc").unwrap();
         recv!(s, time 1000, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"abcdef"[..],
-            ..RECV_TEMPL
-        })); // this one is dropped
-        recv!(s, time 1005, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 6,
-            ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"123456"[..],
+            payload:    &b"abc"[..],
             ..RECV_TEMPL
-        })); // this one is received
+        }));
+        // A full segment is sent even while data is unacknowledged, but the rest is not.
+        s.send_slice(b"defghijk").unwrap();
         recv!(s, time 1010, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 6 + 6,
+            seq_number: LOCAL_SEQ + 1 + 3,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"ABCDEF"[..],
+            payload:    &b"defghi"[..],
             ..RECV_TEMPL
-        })); // also dropped
-        recv!(s, time 2000, Ok(TcpRepr {
+        }));
+        recv!(s, time 1010, Err(Error::Exhausted));
+    }
+
+    #[test]
+    fn test_nagle_fin() {
+        let mut s = socket_established();
+        s.set_nagle_enabled(true);
+        s.send_slice(b"abc").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"abcdef"[..],
+            payload:    &b"abc"[..],
             ..RECV_TEMPL
-        })); // retransmission
-        send!(s, time 2005, TcpRepr {
-            seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 6 + 6),
-            ..SEND_TEMPL
-        }); // acknowledgement of both segments
-        recv!(s, time 2010, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 6 + 6,
+        }));
+        // The last data is not held back when the connection is being closed.
+        s.send_slice(b"def").unwrap();
+        s.close();
+        recv!(s, time 1010, Ok(TcpRepr {
+            control:    TcpControl::Fin,
+            seq_number: LOCAL_SEQ + 1 + 3,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"ABCDEF"[..],
+            payload:    &b"def"[..],
             ..RECV_TEMPL
-        })); // retransmission of only unacknowledged data
+        }));
+    }
+
+    // =========================================================================================//
+    // Tests for congestion control.
+    // =========================================================================================//
+
+    fn socket_congestion(algorithm: Algorithm) -> TcpSocket<'static> {
+        let mut s = socket_established();
+        s.remote_mss = 6;
+        s.set_congestion_control(algorithm);
+        s.send_slice(b"abcdef123456ABCDEF789012ghijkl").unwrap();
+        s
     }
 
     #[test]
-    fn test_close_wait_retransmit_reset_after_ack() {
-        let mut s = socket_close_wait();
-        s.remote_win_len = 6;
-        s.send_slice(b"abcdef").unwrap();
-        s.send_slice(b"123456").unwrap();
-        s.send_slice(b"ABCDEF").unwrap();
+    fn test_congestion_control_reset() {
+        let mut s = socket_established();
+        assert_eq!(s.congestion_control(), Algorithm::None);
+        s.set_congestion_control(Algorithm::Cubic);
+        s.abort();
+        assert_eq!(s.congestion_control(), Algorithm::Cubic);
+    }
+
+    #[test]
+    fn test_congestion_initial_window() {
+        let mut s = socket_congestion(Algorithm::Reno);
         recv!(s, time 1000, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ack_number: Some(REMOTE_SEQ + 1),
             payload:    &b"abcdef"[..],
             ..RECV_TEMPL
         }));
-        send!(s, time 1005, TcpRepr {
-            seq_number: REMOTE_SEQ + 1 + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 6),
-            window_len: 6,
-            ..SEND_TEMPL
-        });
-        recv!(s, time 1010, Ok(TcpRepr {
+        recv!(s, time 1000, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1 + 6,
-            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ack_number: Some(REMOTE_SEQ + 1),
             payload:    &b"123456"[..],
             ..RECV_TEMPL
         }));
-        send!(s, time 1015, TcpRepr {
-            seq_number: REMOTE_SEQ + 1 + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 6 + 6),
-            window_len: 6,
-            ..SEND_TEMPL
-        });
-        recv!(s, time 1020, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 6 + 6,
-            ack_number: Some(REMOTE_SEQ + 1 + 1),
-            payload:    &b"ABCDEF"[..],
-            ..RECV_TEMPL
-        }));
-    }
-
-    #[test]
-    fn test_fin_wait_1_retransmit_reset_after_ack() {
-        let mut s = socket_established();
-        s.remote_win_len = 6;
-        s.send_slice(b"abcdef").unwrap();
-        s.send_slice(b"123456").unwrap();
-        s.send_slice(b"ABCDEF").unwrap();
-        s.close();
         recv!(s, time 1000, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ + 1,
+            seq_number: LOCAL_SEQ + 1 + 12,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"ABCDEF"[..],
+            ..RECV_TEMPL
+        }));
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 18,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"abcdef"[..],
+            payload:    &b"789012"[..],
             ..RECV_TEMPL
         }));
-        send!(s, time 1005, TcpRepr {
+        // The initial window of four segments is full.
+        recv!(s, time 1000, Err(Error::Exhausted));
+        send!(s, time 1010, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
             ack_number: Some(LOCAL_SEQ + 1 + 6),
-            window_len: 6,
             ..SEND_TEMPL
         });
+        // The window has grown by one segment in slow start.
+        assert_eq!(s.congestion.window(), 30);
         recv!(s, time 1010, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 6,
+            seq_number: LOCAL_SEQ + 1 + 24,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"123456"[..],
+            payload:    &b"ghijkl"[..],
             ..RECV_TEMPL
         }));
-        send!(s, time 1015, TcpRepr {
-            seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 6 + 6),
-            window_len: 6,
-            ..SEND_TEMPL
-        });
-        recv!(s, time 1020, Ok(TcpRepr {
-            control:    TcpControl::Fin,
-            seq_number: LOCAL_SEQ + 1 + 6 + 6,
+        recv!(s, time 1010, Err(Error::Exhausted));
+    }
+
+    #[test]
+    fn test_congestion_retransmit_timeout() {
+        let mut s = socket_congestion(Algorithm::Cubic);
+        for _ in 0..4 {
+            recv(&mut s, 1000, |result| assert!(result.is_ok()));
+        }
+        recv!(s, time 1000, Err(Error::Exhausted));
+        recv!(s, time 1100, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"ABCDEF"[..],
+            payload:    &b"abcdef"[..],
             ..RECV_TEMPL
         }));
+        // The window has collapsed to one segment.
+        assert_eq!(s.congestion.window(), 6);
+        recv!(s, time 1100, Err(Error::Exhausted));
     }
 
     // =========================================================================================//
-    // Tests for window management.
+    // Tests for reassembly.
     // =========================================================================================//
 
     #[test]
-    fn test_maximum_segment_size() {
-        let mut s = socket_listen();
-        s.tx_buffer = SocketBuffer::new(vec![0; 32767]);
-        send!(s, TcpRepr {
-            control: TcpControl::Syn,
-            seq_number: REMOTE_SEQ,
-            ack_number: None,
-            max_seg_size: Some(1000),
-            ..SEND_TEMPL
-        });
-        recv!(s, [TcpRepr {
-            control: TcpControl::Syn,
-            seq_number: LOCAL_SEQ,
-            ack_number: Some(REMOTE_SEQ + 1),
-            max_seg_size: Some(BASE_MSS),
-            ..RECV_TEMPL
-        }]);
+    fn test_reassembly_holes() {
+        let mut s = socket_established();
+        assert_eq!(s.reassembly_holes(), 4);
+        assert_eq!(s.set_reassembly_holes(8), Ok(()));
+        for &offset in &[1, 3, 5, 7, 9] {
+            send!(s, TcpRepr {
+                seq_number: REMOTE_SEQ + 1 + offset,
+                ack_number: Some(LOCAL_SEQ + 1),
+                payload:    &b"x"[..],
+                ..SEND_TEMPL
+            }, Ok(Some(TcpRepr {
+                seq_number: LOCAL_SEQ + 1,
+                ack_number: Some(REMOTE_SEQ + 1),
+                ..RECV_TEMPL
+            })));
+        }
+        assert_eq!(s.set_reassembly_holes(4), Err(Error::Illegal));
+
+        // The holes are filled in the reverse order.
+        for &offset in &[8, 6, 4, 2] {
+            send!(s, TcpRepr {
+                seq_number: REMOTE_SEQ + 1 + offset,
+                ack_number: Some(LOCAL_SEQ + 1),
+                payload:    &b"y"[..],
+                ..SEND_TEMPL
+            }, Ok(Some(TcpRepr {
+                seq_number: LOCAL_SEQ + 1,
+                ack_number: Some(REMOTE_SEQ + 1),
+                ..RECV_TEMPL
+            })));
+        }
         send!(s, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
             ack_number: Some(LOCAL_SEQ + 1),
-            window_len: 32767,
+            payload:    &b"y"[..],
             ..SEND_TEMPL
-        });
-        s.send_slice(&[0; 1200][..]).unwrap();
-        recv!(s, Ok(TcpRepr {
+        }, Ok(Some(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1),
-            payload: &[0; 1000][..],
+            ack_number: Some(REMOTE_SEQ + 1 + 10),
+            window_len: 54,
             ..RECV_TEMPL
-        }));
-    }
+        })));
+        assert_eq!(s.rx_buffer.dequeue_many(10), &b"yxyxyxyxyx"[..]);
 
-    // =========================================================================================//
-    // Tests for flow control.
-    // =========================================================================================//
+        // The setting is kept for the next connection.
+        s.abort();
+        assert_eq!(s.reassembly_holes(), 8);
+    }
 
     #[test]
-    fn test_psh_transmit() {
+    fn test_reassembly_distance() {
         let mut s = socket_established();
-        s.remote_win_len = 6;
-        s.send_slice(b"abcdef").unwrap();
-        s.send_slice(b"123456").unwrap();
-        recv!(s, time 0, Ok(TcpRepr {
-            control:    TcpControl::None,
+        s.set_reassembly_distance(Some(8));
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 6,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"ghi"[..],
+            ..SEND_TEMPL
+        }, Err(Error::Dropped));
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 3,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"def"[..],
+            ..SEND_TEMPL
+        }, Ok(Some(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"abcdef"[..],
             ..RECV_TEMPL
-        }), exact);
-        recv!(s, time 0, Ok(TcpRepr {
-            control:    TcpControl::Psh,
-            seq_number: LOCAL_SEQ + 1 + 6,
-            ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"123456"[..],
+        })));
+        // Data received in order is accepted regardless of its length.
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"abcdefghijkl"[..],
+            ..SEND_TEMPL
+        }, Ok(Some(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 12),
+            window_len: 52,
             ..RECV_TEMPL
-        }), exact);
+        })));
     }
 
     #[test]
-    fn test_psh_receive() {
+    fn test_out_of_order() {
         let mut s = socket_established();
         send!(s, TcpRepr {
-            control:    TcpControl::Psh,
+            seq_number: REMOTE_SEQ + 1 + 3,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"def"[..],
+            ..SEND_TEMPL
+        }, Ok(Some(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            ..RECV_TEMPL
+        })));
+        s.recv(|buffer| {
+            assert_eq!(buffer, b"");
+            (buffer.len(), ())
+        }).unwrap();
+        send!(s, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
             ack_number: Some(LOCAL_SEQ + 1),
             payload:    &b"abcdef"[..],
             ..SEND_TEMPL
-        });
-        recv!(s, [TcpRepr {
+        }, Ok(Some(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1 + 6),
             window_len: 58,
             ..RECV_TEMPL
-        }]);
+        })));
+        s.recv(|buffer| {
+            assert_eq!(buffer, b"abcdef");
+            (buffer.len(), ())
+        }).unwrap();
     }
 
     #[test]
-    fn test_zero_window_ack() {
+    fn test_out_of_order_batched() {
         let mut s = socket_established();
-        s.rx_buffer = SocketBuffer::new(vec![0; 6]);
-        s.assembler = Assembler::new(s.rx_buffer.capacity());
         send!(s, TcpRepr {
-            seq_number: REMOTE_SEQ + 1,
+            seq_number: REMOTE_SEQ + 1 + 3,
             ack_number: Some(LOCAL_SEQ + 1),
-            payload:    &b"abcdef"[..],
+            payload:    &b"def"[..],
             ..SEND_TEMPL
-        });
-        recv!(s, [TcpRepr {
+        }, Ok(Some(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 6),
-            window_len: 0,
+            ack_number: Some(REMOTE_SEQ + 1),
             ..RECV_TEMPL
-        }]);
-        send!(s, TcpRepr {
-            seq_number: REMOTE_SEQ + 1 + 6,
+        })));
+        // The segment filling the gap is acknowledged once the socket is dispatched.
+        let repr = TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
             ack_number: Some(LOCAL_SEQ + 1),
-            payload:    &b"123456"[..],
+            payload:    &b"abc"[..],
             ..SEND_TEMPL
-        }, Ok(Some(TcpRepr {
+        };
+        let ip_repr = IpRepr::Unspecified {
+            src_addr:    MOCK_IP_ADDR_2,
+            dst_addr:    MOCK_IP_ADDR_1,
+            protocol:    IpProtocol::Tcp,
+            payload_len: repr.buffer_len(),
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0,
+            flow_label:  0
+        };
+        assert_eq!(s.process_batched(Instant::from_millis(0), &ip_repr, &repr), Ok(None));
+        recv!(s, [TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1 + 6),
-            window_len: 0,
+            window_len: 58,
             ..RECV_TEMPL
-        })));
+        }]);
     }
 
     #[test]
-    fn test_zero_window_ack_on_window_growth() {
+    fn test_buffer_wraparound_rx() {
         let mut s = socket_established();
         s.rx_buffer = SocketBuffer::new(vec![0; 6]);
         s.assembler = Assembler::new(s.rx_buffer.capacity());
         send!(s, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
             ack_number: Some(LOCAL_SEQ + 1),
-            payload:    &b"abcdef"[..],
+            payload:    &b"abc"[..],
             ..SEND_TEMPL
         });
-        recv!(s, [TcpRepr {
-            seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 6),
-            window_len: 0,
-            ..RECV_TEMPL
-        }]);
-        recv!(s, time 0, Err(Error::Exhausted));
         s.recv(|buffer| {
-            assert_eq!(&buffer[..3], b"abc");
-            (3, ())
+            assert_eq!(buffer, b"abc");
+            (buffer.len(), ())
         }).unwrap();
-        recv!(s, time 0, Ok(TcpRepr {
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 3,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"defghi"[..],
+            ..SEND_TEMPL
+        });
+        let mut data = [0; 6];
+        assert_eq!(s.recv_slice(&mut data[..]), Ok(6));
+        assert_eq!(data, &b"defghi"[..]);
+    }
+
+    #[test]
+    fn test_buffer_wraparound_tx() {
+        let mut s = socket_established();
+        s.tx_buffer = SocketBuffer::new(vec![0; 6]);
+        assert_eq!(s.send_slice(b"abc"), Ok(3));
+        recv!(s, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 6),
-            window_len: 3,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abc"[..],
             ..RECV_TEMPL
         }));
-        recv!(s, time 0, Err(Error::Exhausted));
-        s.recv(|buffer| {
-            assert_eq!(buffer, b"def");
-            (buffer.len(), ())
-        }).unwrap();
-        recv!(s, time 0, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 6),
-            window_len: 6,
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 3),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.send_slice(b"defghi"), Ok(6));
+        recv!(s, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 3,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"def"[..],
+            ..RECV_TEMPL
+        }));
+        // "defghi" not contiguous in tx buffer
+        recv!(s, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 3 + 3,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"ghi"[..],
             ..RECV_TEMPL
         }));
     }
 
     #[test]
-    fn test_fill_peer_window() {
+    fn test_send_from_wraparound() {
         let mut s = socket_established();
-        s.remote_mss = 6;
-        s.send_slice(b"abcdef123456!@#$%^").unwrap();
+        s.tx_buffer = SocketBuffer::new(vec![0; 6]);
+        assert_eq!(s.send_slice(b"abc"), Ok(3));
         recv!(s, [TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"abcdef"[..],
-            ..RECV_TEMPL
-        }, TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 6,
-            ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"123456"[..],
-            ..RECV_TEMPL
-        }, TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 6 + 6,
-            ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"!@#$%^"[..],
+            payload:    &b"abc"[..],
             ..RECV_TEMPL
         }]);
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 3),
+            ..SEND_TEMPL
+        });
+
+        // The reader fills the free space on both sides of the end of the storage.
+        let mut source = &b"defghijk"[..];
+        assert_eq!(s.send_from(|free| {
+            let size = cmp::min(free.len(), source.len());
+            free[..size].copy_from_slice(&source[..size]);
+            source = &source[size..];
+            size
+        }), Ok(6));
+        assert_eq!(source, &b"jk"[..]);
+        let mut data = [0; 6];
+        assert_eq!(s.tx_buffer.read_allocated(0, &mut data[..]), 6);
+        assert_eq!(&data[..], &b"defghi"[..]);
+    }
+
+    #[test]
+    fn test_send_from_buffer() {
+        let mut s = socket_established();
+        s.tx_buffer = SocketBuffer::new(vec![0; 6]);
+
+        // The data in the source buffer wraps around the end of its storage.
+        let mut source = SocketBuffer::new(vec![0; 8]);
+        assert_eq!(source.enqueue_slice(b"xxxxxx"), 6);
+        assert_eq!(source.dequeue_many(6).len(), 6);
+        assert_eq!(source.enqueue_slice(b"abcdefgh"), 8);
+
+        assert_eq!(s.send_from_buffer(&mut source), Ok(6));
+        assert_eq!(source.len(), 2);
+        assert_eq!(s.send_from_buffer(&mut source), Ok(0));
+        let mut data = [0; 6];
+        assert_eq!(s.tx_buffer.read_allocated(0, &mut data[..]), 6);
+        assert_eq!(&data[..], &b"abcdef"[..]);
+
+        s.close();
+        assert_eq!(s.send_from_buffer(&mut source), Err(Error::Illegal));
     }
 
     // =========================================================================================//
-    // Tests for timeouts.
+    // Tests for timestamps.
     // =========================================================================================//
 
     #[test]
-    fn test_listen_timeout() {
+    fn test_listen_syn_timestamp() {
         let mut s = socket_listen();
-        s.set_timeout(Some(100));
-        assert_eq!(s.poll_at(), None);
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: None,
+            timestamp:  Some((500, 0)),
+            ..SEND_TEMPL
+        });
+        assert!(s.remote_has_ts);
+        recv!(s, time 10, Ok(TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            max_seg_size: Some(BASE_MSS),
+            timestamp:  Some((10, 500)),
+            ..RECV_TEMPL
+        }));
     }
 
     #[test]
-    fn test_connect_timeout() {
-        let mut s = socket();
-        s.local_seq_no = LOCAL_SEQ;
-        s.connect(REMOTE_END, LOCAL_END.port).unwrap();
-        s.set_timeout(Some(100));
-        recv!(s, time 150, Ok(TcpRepr {
+    fn test_syn_sent_syn_ack_no_timestamp() {
+        let mut s = socket_syn_sent();
+        send!(s, TcpRepr {
             control:    TcpControl::Syn,
-            seq_number: LOCAL_SEQ,
-            ack_number: None,
+            seq_number: REMOTE_SEQ,
+            ack_number: Some(LOCAL_SEQ + 1),
             max_seg_size: Some(BASE_MSS),
+            ..SEND_TEMPL
+        });
+        assert!(!s.remote_has_ts);
+        recv!(s, time 10, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
             ..RECV_TEMPL
         }));
-        assert_eq!(s.state, State::SynSent);
-        assert_eq!(s.poll_at(), Some(250));
-        recv!(s, time 250, Ok(TcpRepr {
-            control:    TcpControl::Rst,
+    }
+
+    #[test]
+    fn test_negotiated_parameters() {
+        let mut s = socket_syn_sent();
+        s.set_congestion_control(Algorithm::Reno);
+        assert!(!s.sack_negotiated());
+        assert!(!s.timestamps_negotiated());
+        assert_eq!(s.send_mss(), DEFAULT_MSS);
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: Some(LOCAL_SEQ + 1),
+            max_seg_size: Some(1000),
+            sack_permitted: true,
+            timestamp:  Some((500, 0)),
+            ..SEND_TEMPL
+        });
+        assert!(s.sack_negotiated());
+        assert!(s.timestamps_negotiated());
+        assert_eq!(s.send_mss(), 1000);
+        assert_eq!(s.congestion_window(), 4000);
+        assert_eq!(s.slow_start_threshold(), usize::max_value());
+        assert_eq!(s.smoothed_rtt(), None);
+        assert_eq!(s.rtt_variation(), None);
+
+        recv!(s, time 1000, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(TcpSeqNumber(0)),
+            ack_number: Some(REMOTE_SEQ + 1),
+            timestamp:  Some((1000, 500)),
             ..RECV_TEMPL
         }));
-        assert_eq!(s.state, State::Closed);
+        s.send_slice(b"abcdef").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"abcdef"[..],
+            timestamp:  Some((1000, 500)),
+            ..RECV_TEMPL
+        }));
+        send!(s, time 1050, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            timestamp:  Some((600, 1000)),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.smoothed_rtt(), Some(Duration::from_millis(50)));
+        assert_eq!(s.rtt_variation(), Some(Duration::from_millis(25)));
+        assert_eq!(s.retransmission_timeout(), Duration::from_millis(150));
     }
 
     #[test]
-    fn test_established_timeout() {
+    fn test_timestamp_rtt() {
         let mut s = socket_established();
-        s.set_timeout(Some(200));
-        recv!(s, time 250, Err(Error::Exhausted));
-        assert_eq!(s.poll_at(), Some(450));
+        s.remote_has_ts    = true;
+        s.remote_ts_recent = 100;
         s.send_slice(b"abcdef").unwrap();
-        assert_eq!(s.poll_at(), Some(0));
-        recv!(s, time 255, Ok(TcpRepr {
+        recv!(s, time 1000, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
             payload:    &b"abcdef"[..],
+            timestamp:  Some((1000, 100)),
             ..RECV_TEMPL
         }));
-        assert_eq!(s.poll_at(), Some(355));
-        recv!(s, time 355, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ + 1,
+        send!(s, time 1050, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            timestamp:  Some((200, 1000)),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.remote_ts_recent, 200);
+        // The first sample of 50 ms sets the timeout to 50 + 4 * 25 ms.
+        assert_eq!(s.rtte.retransmission_timeout(), 150);
+
+        s.send_slice(b"xyz").unwrap();
+        recv!(s, time 2000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 6,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"abcdef"[..],
+            payload:    &b"xyz"[..],
+            timestamp:  Some((2000, 200)),
             ..RECV_TEMPL
         }));
-        assert_eq!(s.poll_at(), Some(455));
-        recv!(s, time 500, Ok(TcpRepr {
-            control:    TcpControl::Rst,
+        recv!(s, time 2149, Err(Error::Exhausted));
+        recv!(s, time 2150, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1 + 6,
             ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"xyz"[..],
+            timestamp:  Some((2150, 200)),
             ..RECV_TEMPL
         }));
-        assert_eq!(s.state, State::Closed);
     }
 
     #[test]
-    fn test_established_keep_alive_timeout() {
+    fn test_timed_rtt() {
         let mut s = socket_established();
-        s.set_keep_alive(Some(50));
-        s.set_timeout(Some(100));
-        recv!(s, time 100, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ,
+        s.remote_has_ts    = true;
+        s.remote_ts_recent = 100;
+        s.send_slice(b"abcdef").unwrap();
+        recv_at(&mut s, Instant::from_micros(1_000_250), |result| {
+            assert_eq!(result.map(|repr| repr.payload.len()), Ok(6));
+        });
+        send_at(&mut s, Instant::from_micros(1_000_750), &TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            timestamp:  Some((200, 1000)),
+            ..SEND_TEMPL
+        }).unwrap();
+        // The echoed timestamp only tells that the round trip took less than 1 ms.
+        assert_eq!(s.stats().rtt, Some(Duration::from_micros(500)));
+
+        // A retransmitted segment is not timed.
+        s.send_slice(b"xyz").unwrap();
+        recv_at(&mut s, Instant::from_millis(2_000), |result| {
+            assert_eq!(result.map(|repr| repr.payload.len()), Ok(3));
+        });
+        recv_at(&mut s, Instant::from_millis(4_000), |result| {
+            assert_eq!(result.map(|repr| repr.payload.len()), Ok(3));
+        });
+        assert_eq!(s.rtt_timer, None);
+    }
+
+    #[test]
+    fn test_timed_rtt_no_timestamps() {
+        let mut s = socket_established();
+        s.send_slice(b"abcdef").unwrap();
+        recv_at(&mut s, Instant::from_millis(1_000), |result| {
+            assert_eq!(result.map(|repr| repr.payload.len()), Ok(6));
+        });
+        send_at(&mut s, Instant::from_millis(1_040), &TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            ..SEND_TEMPL
+        }).unwrap();
+        assert_eq!(s.stats().rtt, Some(Duration::from_millis(40)));
+
+        // The acknowledgement of a retransmitted segment is not sampled.
+        s.send_slice(b"xyz").unwrap();
+        recv_at(&mut s, Instant::from_millis(2_000), |result| {
+            assert_eq!(result.map(|repr| repr.payload.len()), Ok(3));
+        });
+        recv_at(&mut s, Instant::from_millis(4_000), |result| {
+            assert_eq!(result.map(|repr| repr.payload.len()), Ok(3));
+        });
+        send_at(&mut s, Instant::from_millis(4_010), &TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 9),
+            ..SEND_TEMPL
+        }).unwrap();
+        assert_eq!(s.stats().rtt, Some(Duration::from_millis(40)));
+    }
+
+    #[test]
+    fn test_rtt_estimator() {
+        let mut rtte = RttEstimator::default();
+        assert_eq!(rtte.retransmission_timeout(), RETRANSMIT_DELAY);
+        rtte.sample(Duration::from_millis(100));
+        assert_eq!(rtte.retransmission_timeout(), 300);
+        rtte.sample(Duration::from_millis(100));
+        // srtt = 100, rttvar = (3 * 50 + 0) / 4 = 37.5
+        assert_eq!(rtte.retransmission_timeout(), 100 + 150);
+        rtte.sample(Duration::from_millis(0));
+        rtte.sample(Duration::from_millis(0));
+        rtte.sample(Duration::from_millis(0));
+        assert!(rtte.retransmission_timeout() >= RTO_MIN);
+        rtte.sample(Duration::from_millis(1_000_000));
+        assert_eq!(rtte.retransmission_timeout(), RTO_MAX);
+    }
+
+    #[test]
+    fn test_paws() {
+        let mut s = socket_established();
+        s.remote_has_ts    = true;
+        s.remote_ts_recent = 200;
+        // A segment with an older timestamp is an old duplicate, and is only acknowledged.
+        send!(s, time 10, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"abc"[..],
+            timestamp:  Some((100, 0)),
+            ..SEND_TEMPL
+        }, Ok(Some(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &[0],
+            timestamp:  Some((10, 200)),
             ..RECV_TEMPL
-        }));
-        recv!(s, time 100, Err(Error::Exhausted));
-        assert_eq!(s.poll_at(), Some(150));
-        send!(s, time 105, TcpRepr {
+        })));
+        assert_eq!(s.rx_buffer.len(), 0);
+        assert_eq!(s.remote_ts_recent, 200);
+
+        // Timestamps are compared modulo 2**32.
+        s.remote_ts_recent = 0xffff_fff0;
+        send!(s, time 20, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
             ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"abc"[..],
+            timestamp:  Some((5, 0)),
             ..SEND_TEMPL
         });
-        assert_eq!(s.poll_at(), Some(155));
-        recv!(s, time 155, Ok(TcpRepr {
+        assert_eq!(s.rx_buffer.dequeue_many(3), &b"abc"[..]);
+        assert_eq!(s.remote_ts_recent, 5);
+    }
+
+    // =========================================================================================//
+    // Tests for explicit congestion notification.
+    // =========================================================================================//
+
+    fn socket_established_with_ecn() -> TcpSocket<'static> {
+        let mut s = socket_established();
+        s.set_ecn_enabled(true);
+        s.remote_has_ecn = true;
+        s
+    }
+
+    fn send_with_ecn(socket: &mut TcpSocket, timestamp: u64, ecn: u8, repr: &TcpRepr) ->
+                    Result<Option<TcpRepr<'static>>> {
+        let ip_repr = IpRepr::Unspecified {
+            src_addr:    MOCK_IP_ADDR_2,
+            dst_addr:    MOCK_IP_ADDR_1,
+            protocol:    IpProtocol::Tcp,
+            payload_len: repr.buffer_len(),
+            hop_limit:   64,
+            ecn:         ecn,
+            dscp:        0,
+            flow_label:  0
+        };
+        assert!(socket.accepts(&ip_repr, repr));
+        let now = Instant::from_millis(timestamp as i64);
+        socket.process(now, &ip_repr, repr).map(|reply| reply.map(|(_, repr)| repr))
+    }
+
+    fn recv_ecn(socket: &mut TcpSocket, timestamp: u64) -> u8 {
+        let mut ecn = None;
+        let now = Instant::from_millis(timestamp as i64);
+        socket.dispatch(now, &DeviceCapabilities::default(), |(ip_repr, _)| {
+            ecn = Some(ip_repr.ecn());
+            Ok(())
+        }).unwrap();
+        ecn.unwrap()
+    }
+
+    #[test]
+    fn test_ecn_enabled_reset() {
+        let mut s = socket_established();
+        assert!(!s.ecn_enabled());
+        s.set_ecn_enabled(true);
+        s.remote_has_ecn = true;
+        s.reset();
+        assert!(s.ecn_enabled());
+        assert!(!s.remote_has_ecn);
+    }
+
+    #[test]
+    fn test_connect_ecn() {
+        let mut s = socket();
+        s.set_ecn_enabled(true);
+        s.local_seq_no = LOCAL_SEQ;
+        s.connect(REMOTE_END, LOCAL_END).unwrap();
+        recv!(s, [TcpRepr {
+            control:    TcpControl::Syn,
             seq_number: LOCAL_SEQ,
-            ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &[0],
-            ..RECV_TEMPL
-        }));
-        recv!(s, time 155, Err(Error::Exhausted));
-        assert_eq!(s.poll_at(), Some(205));
-        recv!(s, time 200, Err(Error::Exhausted));
-        recv!(s, time 205, Ok(TcpRepr {
-            control:    TcpControl::Rst,
-            seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1),
+            ack_number: None,
+            max_seg_size: Some(BASE_MSS),
+            sack_permitted: true,
+            timestamp:  Some((0, 0)),
+            ece:        true,
+            cwr:        true,
             ..RECV_TEMPL
-        }));
-        recv!(s, time 205, Err(Error::Exhausted));
-        assert_eq!(s.state, State::Closed);
+        }]);
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ece:        true,
+            ..SEND_TEMPL
+        });
+        assert!(s.remote_has_ecn);
     }
 
     #[test]
-    fn test_fin_wait_1_timeout() {
-        let mut s = socket_fin_wait_1();
-        s.set_timeout(Some(200));
-        recv!(s, time 100, Ok(TcpRepr {
-            control:    TcpControl::Fin,
-            seq_number: LOCAL_SEQ + 1,
+    fn test_syn_sent_syn_ack_no_ecn() {
+        // A SYN|ACK with both ECE and CWR set does not agree to ECN.
+        let mut s = socket_syn_sent();
+        s.set_ecn_enabled(true);
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ece:        true,
+            cwr:        true,
+            ..SEND_TEMPL
+        });
+        assert!(!s.remote_has_ecn);
+    }
+
+    #[test]
+    fn test_listen_syn_ecn() {
+        let mut s = socket_listen();
+        s.set_ecn_enabled(true);
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: None,
+            ece:        true,
+            cwr:        true,
+            ..SEND_TEMPL
+        });
+        assert!(s.remote_has_ecn);
+        recv!(s, [TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
             ack_number: Some(REMOTE_SEQ + 1),
+            max_seg_size: Some(BASE_MSS),
+            ece:        true,
             ..RECV_TEMPL
-        }));
-        assert_eq!(s.poll_at(), Some(200));
-        recv!(s, time 400, Ok(TcpRepr {
-            control:    TcpControl::Rst,
-            seq_number: LOCAL_SEQ + 1 + 1,
+        }]);
+    }
+
+    #[test]
+    fn test_listen_syn_ecn_disabled() {
+        let mut s = socket_listen();
+        send!(s, TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: None,
+            ece:        true,
+            cwr:        true,
+            ..SEND_TEMPL
+        });
+        assert!(!s.remote_has_ecn);
+        recv!(s, [TcpRepr {
+            control:    TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
             ack_number: Some(REMOTE_SEQ + 1),
+            max_seg_size: Some(BASE_MSS),
             ..RECV_TEMPL
-        }));
-        assert_eq!(s.state, State::Closed);
+        }]);
+    }
+
+    #[test]
+    fn test_ecn_capable_data() {
+        let mut s = socket_established_with_ecn();
+        s.send_slice(b"abcdef").unwrap();
+        assert_eq!(recv_ecn(&mut s, 1000), ECN_ECT0);
+        // Retransmitted data is not ECN-capable.
+        assert_eq!(recv_ecn(&mut s, 1000 + RETRANSMIT_DELAY), 0);
+        send!(s, time 1200, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            payload:    &b"xyz"[..],
+            ..SEND_TEMPL
+        });
+        // Neither are acknowledgements.
+        assert_eq!(recv_ecn(&mut s, 1200), 0);
+        s.send_slice(b"ghi").unwrap();
+        assert_eq!(recv_ecn(&mut s, 1300), ECN_ECT0);
+    }
+
+    #[test]
+    fn test_ecn_not_negotiated() {
+        let mut s = socket_established();
+        s.set_ecn_enabled(true);
+        s.send_slice(b"abcdef").unwrap();
+        assert_eq!(recv_ecn(&mut s, 1000), 0);
     }
 
     #[test]
-    fn test_last_ack_timeout() {
-        let mut s = socket_last_ack();
-        s.set_timeout(Some(200));
-        recv!(s, time 100, Ok(TcpRepr {
-            control:    TcpControl::Fin,
+    fn test_ecn_echo() {
+        let mut s = socket_established_with_ecn();
+        assert_eq!(send_with_ecn(&mut s, 0, ECN_CE, &TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"abc"[..],
+            ..SEND_TEMPL
+        }), Ok(None));
+        recv!(s, [TcpRepr {
             seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 1),
+            ack_number: Some(REMOTE_SEQ + 1 + 3),
+            window_len: 61,
+            ece:        true,
             ..RECV_TEMPL
-        }));
-        assert_eq!(s.poll_at(), Some(200));
-        recv!(s, time 400, Ok(TcpRepr {
-            control:    TcpControl::Rst,
-            seq_number: LOCAL_SEQ + 1 + 1,
-            ack_number: Some(REMOTE_SEQ + 1 + 1),
+        }]);
+        // The mark is echoed until the remote end reduces its window.
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 3,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"def"[..],
+            ..SEND_TEMPL
+        });
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 58,
+            ece:        true,
             ..RECV_TEMPL
-        }));
-        assert_eq!(s.state, State::Closed);
+        }]);
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 6,
+            ack_number: Some(LOCAL_SEQ + 1),
+            payload:    &b"ghi"[..],
+            cwr:        true,
+            ..SEND_TEMPL
+        });
+        recv!(s, [TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 9),
+            window_len: 55,
+            ..RECV_TEMPL
+        }]);
     }
 
     #[test]
-    fn test_closed_timeout() {
-        let mut s = socket_established();
-        s.set_timeout(Some(200));
-        s.remote_last_ts = Some(100);
-        s.abort();
-        assert_eq!(s.poll_at(), Some(0));
-        recv!(s, time 100, Ok(TcpRepr {
-            control:    TcpControl::Rst,
-            seq_number: LOCAL_SEQ + 1,
+    fn test_ecn_congestion_window() {
+        let mut s = socket_congestion(Algorithm::Reno);
+        s.set_ecn_enabled(true);
+        s.remote_has_ecn = true;
+        for _ in 0..4 {
+            assert_eq!(recv_ecn(&mut s, 1000), ECN_ECT0);
+        }
+        send!(s, time 1010, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            ece:        true,
+            ..SEND_TEMPL
+        });
+        // The window has grown to 30 octets, and then been halved.
+        assert_eq!(s.congestion.window(), 15);
+        // Marks echoed for the data sent before the reduction are not reacted to again.
+        send!(s, time 1020, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 24),
+            ece:        true,
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.congestion.window(), 17);
+        // The next new data indicates the reduction.
+        recv!(s, time 1020, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 24,
             ack_number: Some(REMOTE_SEQ + 1),
+            payload:    &b"ghijkl"[..],
+            cwr:        true,
             ..RECV_TEMPL
         }));
-        assert_eq!(s.poll_at(), None);
+        assert!(!s.ecn_cwr);
+        // Marks echoed for the data sent after it are.
+        send!(s, time 1030, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 30),
+            ece:        true,
+            ..SEND_TEMPL
+        });
+        assert!(s.congestion.window() < 17);
     }
 
     // =========================================================================================//
-    // Tests for keep-alive.
+    // Tests for urgent data.
     // =========================================================================================//
 
     #[test]
-    fn test_responds_to_keep_alive() {
+    fn test_send_urgent() {
         let mut s = socket_established();
-        send!(s, TcpRepr {
-            seq_number: REMOTE_SEQ,
-            ack_number: Some(LOCAL_SEQ + 1),
-            ..SEND_TEMPL
-        }, Ok(Some(TcpRepr {
+        s.send_slice(b"abc").unwrap();
+        assert_eq!(s.send_urgent(b"def"), Ok(3));
+        s.send_slice(b"ghi").unwrap();
+        recv!(s, [TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
+            urgent_at:  Some(6),
+            payload:    &b"abcdefghi"[..],
             ..RECV_TEMPL
-        })));
-    }
-
-    #[test]
-    fn test_sends_keep_alive() {
-        let mut s = socket_established();
-        s.set_keep_alive(Some(100));
-
-        // drain the forced keep-alive packet
-        assert_eq!(s.poll_at(), Some(0));
-        recv!(s, time 0, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ,
-            ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &[0],
-            ..RECV_TEMPL
-        }));
-
-        assert_eq!(s.poll_at(), Some(100));
-        recv!(s, time 95, Err(Error::Exhausted));
+        }]);
+        // Until the urgent data is acknowledged, the urgent pointer is repeated.
         recv!(s, time 100, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ,
-            ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &[0],
-            ..RECV_TEMPL
-        }));
-
-        assert_eq!(s.poll_at(), Some(200));
-        recv!(s, time 195, Err(Error::Exhausted));
-        recv!(s, time 200, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ,
+            seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &[0],
+            urgent_at:  Some(6),
+            payload:    &b"abcdefghi"[..],
             ..RECV_TEMPL
         }));
-
-        send!(s, time 250, TcpRepr {
+        send!(s, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1),
+            ack_number: Some(LOCAL_SEQ + 1 + 3),
             ..SEND_TEMPL
         });
-        assert_eq!(s.poll_at(), Some(350));
-        recv!(s, time 345, Err(Error::Exhausted));
-        recv!(s, time 350, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ,
+        assert_eq!(s.urgent_seq, Some(LOCAL_SEQ + 1 + 6));
+        send!(s, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 9),
+            ..SEND_TEMPL
+        });
+        assert_eq!(s.urgent_seq, None);
+        s.send_slice(b"jkl").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 9,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"\x00"[..],
+            payload:    &b"jkl"[..],
             ..RECV_TEMPL
         }));
     }
 
-    // =========================================================================================//
-    // Tests for time-to-live configuration.
-    // =========================================================================================//
-
-    #[test]
-    fn test_set_hop_limit() {
-        let mut s = socket_syn_received();
-        let mut caps = DeviceCapabilities::default();
-        caps.max_transmission_unit = 1520;
-
-        s.set_hop_limit(Some(0x2a));
-        assert_eq!(s.dispatch(0, &caps, |(ip_repr, _)| {
-            assert_eq!(ip_repr.hop_limit(), 0x2a);
-            Ok(())
-        }), Ok(()));
-    }
-
-    #[test]
-    #[should_panic(expected = "the time-to-live value of a packet must not be zero")]
-    fn test_set_hop_limit_zero() {
-        let mut s = socket_syn_received();
-        s.set_hop_limit(Some(0));
-    }
-
-    // =========================================================================================//
-    // Tests for reassembly.
-    // =========================================================================================//
-
     #[test]
-    fn test_out_of_order() {
+    fn test_recv_urgent() {
         let mut s = socket_established();
+        assert_eq!(s.recv_urgent_mark(), None);
         send!(s, TcpRepr {
-            seq_number: REMOTE_SEQ + 1 + 3,
+            seq_number: REMOTE_SEQ + 1,
             ack_number: Some(LOCAL_SEQ + 1),
-            payload:    &b"def"[..],
+            urgent_at:  Some(4),
+            payload:    &b"abcdef"[..],
             ..SEND_TEMPL
-        }, Ok(Some(TcpRepr {
+        });
+        recv!(s, [TcpRepr {
             seq_number: LOCAL_SEQ + 1,
-            ack_number: Some(REMOTE_SEQ + 1),
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 58,
             ..RECV_TEMPL
-        })));
-        s.recv(|buffer| {
-            assert_eq!(buffer, b"");
-            (buffer.len(), ())
-        }).unwrap();
+        }]);
+        assert_eq!(s.recv_urgent_mark(), Some(4));
+        // The urgent data stays in the stream.
+        let mut buffer = [0; 3];
+        assert_eq!(s.recv_slice(&mut buffer[..]), Ok(3));
+        assert_eq!(&buffer, b"abc");
+        assert_eq!(s.recv_urgent_mark(), Some(1));
+        // A retransmitted urgent pointer does not move the mark.
         send!(s, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
             ack_number: Some(LOCAL_SEQ + 1),
+            urgent_at:  Some(4),
             payload:    &b"abcdef"[..],
             ..SEND_TEMPL
         }, Ok(Some(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1 + 6),
-            window_len: 58,
+            window_len: 61,
             ..RECV_TEMPL
         })));
-        s.recv(|buffer| {
-            assert_eq!(buffer, b"abcdef");
-            (buffer.len(), ())
-        }).unwrap();
-    }
+        assert_eq!(s.recv_urgent_mark(), Some(1));
+        assert_eq!(s.recv_slice(&mut buffer[..]), Ok(3));
+        assert_eq!(s.recv_urgent_mark(), None);
 
-    #[test]
-    fn test_buffer_wraparound_rx() {
-        let mut s = socket_established();
-        s.rx_buffer = SocketBuffer::new(vec![0; 6]);
-        s.assembler = Assembler::new(s.rx_buffer.capacity());
-        send!(s, TcpRepr {
-            seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1),
-            payload:    &b"abc"[..],
-            ..SEND_TEMPL
-        });
-        s.recv(|buffer| {
-            assert_eq!(buffer, b"abc");
-            (buffer.len(), ())
-        }).unwrap();
+        // The mark may be past the data received so far.
         send!(s, TcpRepr {
-            seq_number: REMOTE_SEQ + 1 + 3,
+            seq_number: REMOTE_SEQ + 1 + 6,
             ack_number: Some(LOCAL_SEQ + 1),
-            payload:    &b"defghi"[..],
+            urgent_at:  Some(10),
+            payload:    &b"ghi"[..],
             ..SEND_TEMPL
         });
-        let mut data = [0; 6];
-        assert_eq!(s.recv_slice(&mut data[..]), Ok(6));
-        assert_eq!(data, &b"defghi"[..]);
+        assert_eq!(s.recv_urgent_mark(), Some(10));
     }
 
+    // =========================================================================================//
+    // Tests for statistics.
+    // =========================================================================================//
+
     #[test]
-    fn test_buffer_wraparound_tx() {
-        let mut s = socket_established();
-        s.tx_buffer = SocketBuffer::new(vec![0; 6]);
-        assert_eq!(s.send_slice(b"abc"), Ok(3));
-        recv!(s, Ok(TcpRepr {
+    fn test_stats() {
+        let mut s = socket_fast_retransmit();
+        for &timestamp in &[1010, 1011, 1012] {
+            send!(s, time timestamp, TcpRepr {
+                seq_number: REMOTE_SEQ + 1,
+                ack_number: Some(LOCAL_SEQ + 1),
+                ..SEND_TEMPL
+            });
+        }
+        recv!(s, time 1012, Ok(TcpRepr {
             seq_number: LOCAL_SEQ + 1,
             ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"abc"[..],
+            payload:    &b"abcdef"[..],
             ..RECV_TEMPL
         }));
-        send!(s, TcpRepr {
+        send!(s, time 1020, TcpRepr {
             seq_number: REMOTE_SEQ + 1,
-            ack_number: Some(LOCAL_SEQ + 1 + 3),
+            ack_number: Some(LOCAL_SEQ + 1 + 18),
+            payload:    &b"xyz"[..],
             ..SEND_TEMPL
         });
-        assert_eq!(s.send_slice(b"defghi"), Ok(6));
-        recv!(s, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 3,
-            ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"def"[..],
-            ..RECV_TEMPL
-        }));
-        // "defghi" not contiguous in tx buffer
-        recv!(s, Ok(TcpRepr {
-            seq_number: LOCAL_SEQ + 1 + 3 + 3,
-            ack_number: Some(REMOTE_SEQ + 1),
-            payload:    &b"ghi"[..],
-            ..RECV_TEMPL
-        }));
+        // An acknowledgement of data acknowledged before is dropped.
+        send!(s, time 1030, TcpRepr {
+            seq_number: REMOTE_SEQ + 1 + 3,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        }, Err(Error::Dropped));
+        assert_eq!(s.stats(), Stats {
+            segments_sent:     4,
+            segments_received: 5,
+            bytes_sent:        24,
+            bytes_received:    3,
+            retransmits:       1,
+            dup_acks:          3,
+            segments_dropped:  1,
+            challenge_acks:    0,
+            rtt:               None,
+        });
+        s.rtte.sample(Duration::from_millis(100));
+        assert_eq!(s.stats().rtt, Some(Duration::from_millis(100)));
+
+        // The counters start over with the next connection.
+        s.abort();
+        s.listen(LOCAL_END).unwrap();
+        assert_eq!(s.stats(), Stats::default());
     }
 
     // =========================================================================================//
@@ -3730,7 +9279,10 @@ mod test {
             dst_addr:    MOCK_IP_ADDR_1,
             protocol:    IpProtocol::Tcp,
             payload_len: tcp_repr.buffer_len(),
-            hop_limit:   64
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0,
+            flow_label:  0
         };
         assert!(s.accepts(&ip_repr, &tcp_repr));
 
@@ -3739,7 +9291,10 @@ mod test {
             dst_addr:    MOCK_IP_ADDR_1,
             protocol:    IpProtocol::Tcp,
             payload_len: tcp_repr.buffer_len(),
-            hop_limit:   64
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0,
+            flow_label:  0
         };
         assert!(!s.accepts(&ip_repr_wrong_src, &tcp_repr));
 
@@ -3748,11 +9303,91 @@ mod test {
             dst_addr:    MOCK_IP_ADDR_3,
             protocol:    IpProtocol::Tcp,
             payload_len: tcp_repr.buffer_len(),
-            hop_limit:   64
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0,
+            flow_label:  0
         };
         assert!(!s.accepts(&ip_repr_wrong_dst, &tcp_repr));
     }
 
+    fn xor_signature(key: &[u8], data: &[&[u8]]) -> [u8; 16] {
+        let mut digest = [0; 16];
+        let octets = data.iter().flat_map(|data| data.iter()).chain(key.iter());
+        for (index, octet) in octets.enumerate() {
+            digest[index % 16] ^= *octet
+        }
+        digest
+    }
+
+    #[test]
+    fn test_md5_signature() {
+        use phy::ChecksumCapabilities;
+
+        let mut s = socket_established();
+        s.set_md5_signature(Some(b"secret"), xor_signature);
+        assert_eq!(s.md5_signature_key(), Some(&b"secret"[..]));
+        s.send_slice(b"abcdef").unwrap();
+        recv(&mut s, 1000, |result| {
+            let repr = result.unwrap();
+            assert_eq!(repr.payload, b"abcdef");
+            let header = repr.signed_header(&MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2);
+            assert_eq!(repr.md5_signature,
+                       Some(xor_signature(b"secret", &[header.as_bytes(), b"abcdef"])));
+        });
+
+        let ip_repr = IpRepr::Unspecified {
+            src_addr:    MOCK_IP_ADDR_2,
+            dst_addr:    MOCK_IP_ADDR_1,
+            protocol:    IpProtocol::Tcp,
+            payload_len: 0,
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0,
+            flow_label:  0
+        };
+        let mut repr = TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            md5_signature: Some([0; 16]),
+            payload: &b"xyz"[..],
+            ..SEND_TEMPL
+        };
+        let header = repr.signed_header(&MOCK_IP_ADDR_2, &MOCK_IP_ADDR_1);
+        repr.md5_signature = Some(xor_signature(b"secret", &[header.as_bytes(), b"xyz"]));
+        let verify = |s: &mut TcpSocket, repr: &TcpRepr| {
+            let mut bytes = vec![0; repr.buffer_len()];
+            repr.emit(&mut TcpPacket::new(&mut bytes), &MOCK_IP_ADDR_2, &MOCK_IP_ADDR_1,
+                      &ChecksumCapabilities::default());
+            s.verify_signature(&ip_repr, &TcpPacket::new(&bytes[..]), repr)
+        };
+        assert!(verify(&mut s, &repr));
+        assert!(!verify(&mut s, &TcpRepr { md5_signature: None, ..repr }));
+        assert!(!verify(&mut s, &TcpRepr { payload: &b"xyw"[..], ..repr }));
+        assert_eq!(s.stats().segments_dropped, 2);
+
+        s.set_md5_signature(None, xor_signature);
+        assert_eq!(s.md5_signature_key(), None);
+        assert!(verify(&mut s, &TcpRepr { md5_signature: None, ..repr }));
+    }
+
+    #[test]
+    fn test_md5_signature_sack_ranges() {
+        let mut s = socket_established();
+        s.set_md5_signature(Some(b"secret"), xor_signature);
+        let mut repr = TcpRepr {
+            timestamp:   Some((1, 2)),
+            sack_ranges: [Some((1, 2)), Some((3, 4)), Some((5, 6))],
+            ..RECV_TEMPL
+        };
+        let mut ip_repr = _RECV_IP_TEMPL;
+        s.sign_segment(&mut ip_repr, &mut repr);
+        // Only one SACK block fits along with the timestamp and the signature.
+        assert_eq!(repr.sack_ranges, [Some((1, 2)), None, None]);
+        assert_eq!(repr.header_len(), 60);
+        assert_eq!(ip_repr.payload_len(), 60);
+    }
+
     // =========================================================================================//
     // Timer tests
     // =========================================================================================//
@@ -3761,11 +9396,11 @@ mod test {
     fn test_timer_retransmit() {
         let mut r = Timer::default();
         assert_eq!(r.should_retransmit(1000), None);
-        r.set_for_retransmit(1000);
+        r.set_for_retransmit(1000, RETRANSMIT_DELAY, RTO_MAX);
         assert_eq!(r.should_retransmit(1000), None);
         assert_eq!(r.should_retransmit(1050), None);
         assert_eq!(r.should_retransmit(1101), Some(101));
-        r.set_for_retransmit(1101);
+        r.set_for_retransmit(1101, RETRANSMIT_DELAY, RTO_MAX);
         assert_eq!(r.should_retransmit(1101), None);
         assert_eq!(r.should_retransmit(1150), None);
         assert_eq!(r.should_retransmit(1200), None);
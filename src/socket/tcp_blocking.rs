@@ -0,0 +1,230 @@
+use std::io;
+
+use super::{SocketSet, SocketHandle, TcpSocket, TcpState};
+
+/// An adapter implementing the `Read` and `Write` traits of the standard library
+/// for a TCP socket.
+///
+/// The adapter borrows the socket set that owns the socket. Whenever an operation cannot
+/// make progress, the adapter calls the poll function with the socket set; that function
+/// is expected to poll the interface, and ideally to wait until the interface has
+/// something to do, e.g. using [phy_wait] and [poll_delay]. An error returned by
+/// the poll function, e.g. once a deadline has passed, is returned by the operation.
+///
+/// Reads wait for a connection to be established, and return end of stream once the remote
+/// end closes it. Writes return `ErrorKind::BrokenPipe` once the transmit half of
+/// the connection is closed, and flushes wait until all data is acknowledged.
+///
+/// [phy_wait]: ../phy/fn.wait.html
+/// [poll_delay]: ../iface/struct.EthernetInterface.html#method.poll_delay
+#[derive(Debug)]
+pub struct BlockingTcpStream<'r, 'a: 'r, 'b: 'a, 'c: 'a + 'b, F> {
+    sockets: &'r mut SocketSet<'a, 'b, 'c>,
+    handle:  SocketHandle,
+    poll:    F,
+}
+
+impl<'r, 'a, 'b, 'c, F> BlockingTcpStream<'r, 'a, 'b, 'c, F>
+        where F: FnMut(&mut SocketSet<'a, 'b, 'c>) -> io::Result<()> {
+    /// Create an adapter for the TCP socket with the given handle in a socket set,
+    /// polled with the given function.
+    ///
+    /// # Panics
+    /// The operations of the adapter panic if the handle does not refer to a TCP socket
+    /// in the set.
+    pub fn new(sockets: &'r mut SocketSet<'a, 'b, 'c>, handle: SocketHandle,
+               poll: F) -> BlockingTcpStream<'r, 'a, 'b, 'c, F> {
+        BlockingTcpStream { sockets, handle, poll }
+    }
+
+    /// Return the handle of the socket.
+    #[inline]
+    pub fn handle(&self) -> SocketHandle {
+        self.handle
+    }
+
+    /// Return the socket set, e.g. to access the socket directly.
+    pub fn sockets(&mut self) -> &mut SocketSet<'a, 'b, 'c> {
+        self.sockets
+    }
+
+    /// Poll until the given function returns a result for the socket.
+    fn poll_until<G, R>(&mut self, mut f: G) -> io::Result<R>
+            where G: FnMut(&mut TcpSocket) -> Option<io::Result<R>> {
+        loop {
+            if let Some(result) = f(&mut self.sockets.get::<TcpSocket>(self.handle)) {
+                return result
+            }
+            (self.poll)(self.sockets)?
+        }
+    }
+}
+
+impl<'r, 'a, 'b, 'c, F> io::Read for BlockingTcpStream<'r, 'a, 'b, 'c, F>
+        where F: FnMut(&mut SocketSet<'a, 'b, 'c>) -> io::Result<()> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_vectored(&mut [io::IoSliceMut::new(buf)])
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut]) -> io::Result<usize> {
+        if bufs.iter().all(|buf| buf.is_empty()) { return Ok(0) }
+
+        self.poll_until(|socket| {
+            if socket.can_recv() {
+                // This cannot fail, since there is data in the receive buffer.
+                return Some(Ok(socket.recv_vectored(bufs).unwrap_or(0)))
+            }
+            match socket.state() {
+                TcpState::Listen | TcpState::SynSent | TcpState::SynReceived |
+                TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2 => None,
+                _ => Some(Ok(0))
+            }
+        })
+    }
+}
+
+impl<'r, 'a, 'b, 'c, F> io::Write for BlockingTcpStream<'r, 'a, 'b, 'c, F>
+        where F: FnMut(&mut SocketSet<'a, 'b, 'c>) -> io::Result<()> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_vectored(&[io::IoSlice::new(buf)])
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice]) -> io::Result<usize> {
+        if bufs.iter().all(|buf| buf.is_empty()) { return Ok(0) }
+
+        self.poll_until(|socket| {
+            if socket.can_send() {
+                // This cannot fail, since the transmit half of the connection is open.
+                return Some(Ok(socket.send_vectored(bufs).unwrap_or(0)))
+            }
+            match socket.state() {
+                TcpState::Listen | TcpState::SynSent | TcpState::SynReceived |
+                TcpState::Established | TcpState::CloseWait => None,
+                _ => Some(Err(io::ErrorKind::BrokenPipe.into()))
+            }
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.poll_until(|socket| {
+            if socket.send_queue() == 0 { return Some(Ok(())) }
+            match socket.state() {
+                TcpState::Closed | TcpState::TimeWait | TcpState::FinWait2 =>
+                    Some(Err(io::ErrorKind::BrokenPipe.into())),
+                _ => None
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use phy::DeviceCapabilities;
+    use wire::{IpEndpoint, IpProtocol, IpRepr, TcpRepr, TcpControl, TcpSeqNumber,
+               TCP_SACK_RANGE_COUNT};
+    use wire::ip::test::{MOCK_IP_ADDR_1, MOCK_IP_ADDR_2};
+    use socket::TcpSocketBuffer;
+    use time::Instant;
+    use super::*;
+
+    const LOCAL_END: IpEndpoint = IpEndpoint { addr: MOCK_IP_ADDR_1, port: 80 };
+    const REMOTE_PORT: u16 = 49500;
+
+    // A passive open acknowledges the SYN with the negated sequence number.
+    const REMOTE_SEQ: i32 = -10000;
+    const LOCAL_SEQ: i32 = 10000;
+
+    fn socket_set() -> (SocketSet<'static, 'static, 'static>, SocketHandle) {
+        let mut sockets = SocketSet::new(vec![]);
+        let rx_buffer = TcpSocketBuffer::new(vec![0; 64]);
+        let tx_buffer = TcpSocketBuffer::new(vec![0; 64]);
+        let mut socket = TcpSocket::new(rx_buffer, tx_buffer);
+        socket.listen(LOCAL_END).unwrap();
+        let handle = sockets.add(socket);
+        (sockets, handle)
+    }
+
+    fn send(socket: &mut TcpSocket, control: TcpControl, seq_number: i32,
+            ack_number: Option<i32>, payload: &[u8]) {
+        let repr = TcpRepr {
+            src_port:     REMOTE_PORT,
+            dst_port:     LOCAL_END.port,
+            control:      control,
+            seq_number:   TcpSeqNumber(seq_number),
+            ack_number:   ack_number.map(TcpSeqNumber),
+            window_len:   256,
+            max_seg_size: None,
+            sack_permitted: false,
+            sack_ranges:  [None; TCP_SACK_RANGE_COUNT],
+            timestamp:    None,
+            md5_signature: None,
+            ece:          false,
+            cwr:          false,
+            urgent_at:    None,
+            payload:      payload
+        };
+        let ip_repr = IpRepr::Unspecified {
+            src_addr:    MOCK_IP_ADDR_2,
+            dst_addr:    MOCK_IP_ADDR_1,
+            protocol:    IpProtocol::Tcp,
+            payload_len: repr.buffer_len(),
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0,
+            flow_label:  0
+        };
+        assert!(socket.accepts(&ip_repr, &repr));
+        socket.process(Instant::from_millis(0), &ip_repr, &repr).unwrap();
+    }
+
+    fn dispatch(socket: &mut TcpSocket) {
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = 1520;
+        let _ = socket.dispatch(Instant::from_millis(0), &caps, |_| Ok(()));
+    }
+
+    #[test]
+    fn test_read_write() {
+        let (mut sockets, handle) = socket_set();
+        // Play the part of the remote end, one step per poll.
+        let mut step = 0;
+        let mut stream = BlockingTcpStream::new(&mut sockets, handle, |sockets| {
+            let mut socket = sockets.get::<TcpSocket>(handle);
+            dispatch(&mut socket);
+            match step {
+                0 => send(&mut socket, TcpControl::Syn, REMOTE_SEQ, None, &[]),
+                1 => send(&mut socket, TcpControl::None,
+                          REMOTE_SEQ + 1, Some(LOCAL_SEQ + 1), &[]),
+                2 => send(&mut socket, TcpControl::None,
+                          REMOTE_SEQ + 1, Some(LOCAL_SEQ + 1), b"abcdef"),
+                3 => send(&mut socket, TcpControl::None,
+                          REMOTE_SEQ + 7, Some(LOCAL_SEQ + 4), &[]),
+                4 => send(&mut socket, TcpControl::Fin,
+                          REMOTE_SEQ + 7, Some(LOCAL_SEQ + 4), &[]),
+                _ => return Err(io::ErrorKind::TimedOut.into())
+            }
+            step += 1;
+            Ok(())
+        });
+
+        let mut buf = [0; 16];
+        assert_eq!(stream.read(&mut buf).unwrap(), 6);
+        assert_eq!(&buf[..6], b"abcdef");
+        assert_eq!(stream.write_vectored(&[io::IoSlice::new(b"f"),
+                                           io::IoSlice::new(b"oo")]).unwrap(), 3);
+        stream.flush().unwrap();
+        assert_eq!(stream.read(&mut buf).unwrap(), 0);
+        assert_eq!(stream.write(b"bar").unwrap(), 3);
+        assert_eq!(stream.flush().unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn test_write_closed() {
+        let (mut sockets, handle) = socket_set();
+        sockets.get::<TcpSocket>(handle).close();
+        let mut stream = BlockingTcpStream::new(&mut sockets, handle, |_| unreachable!());
+        assert_eq!(stream.write(b"foo").unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(stream.read(&mut [0; 16]).unwrap(), 0);
+    }
+}
@@ -0,0 +1,374 @@
+use {Error, Result};
+use time::Duration;
+use wire::{IpAddress, IpEndpoint};
+use super::{SocketSet, SocketHandle, TcpSocket, TcpState};
+
+/// An event reported by a TCP connector.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Event {
+    /// A connection was established to the given remote endpoint, with the socket
+    /// that has the given handle. The other connection attempt, if any, was aborted.
+    Connected {
+        handle:          SocketHandle,
+        remote_endpoint: IpEndpoint
+    },
+    /// All connection attempts failed.
+    Failed
+}
+
+/// The state of a connection attempt.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Attempt {
+    /// There is no connection attempt.
+    None,
+    /// A connection to the endpoint will be attempted once the delay has passed,
+    /// or the other attempt has failed.
+    Waiting(IpEndpoint),
+    /// A connection to the endpoint is being attempted.
+    Connecting(IpEndpoint),
+    /// The connection attempt failed.
+    Failed
+}
+
+/// A dual-stack TCP connector.
+///
+/// A connector races connection attempts to an IPv6 and an IPv4 endpoint of the same
+/// remote host, using two TCP sockets, as described in RFC 8305 ("Happy Eyeballs").
+/// The first endpoint, preferably the IPv6 one, is attempted at once, and the other one
+/// once the connection attempt delay has passed without a connection, or as soon as
+/// the first attempt failed. The first connection to be established wins, and
+/// the other attempt is aborted, so that a broken path of one of the address families
+/// costs no more than the delay.
+///
+/// The sockets are owned by a socket set; the connector only refers to them by their
+/// handles.
+#[derive(Debug)]
+pub struct TcpConnector {
+    handles:    [SocketHandle; 2],
+    attempts:   [Attempt; 2],
+    local_port: u16,
+    delay:      u64,
+    started_at: u64,
+}
+
+impl TcpConnector {
+    /// Create a connector using the TCP sockets with the given handles.
+    pub fn new(first: SocketHandle, second: SocketHandle) -> TcpConnector {
+        TcpConnector {
+            handles:    [first, second],
+            attempts:   [Attempt::None; 2],
+            local_port: 0,
+            delay:      250,
+            started_at: 0,
+        }
+    }
+
+    /// Return the handles of the sockets.
+    #[inline]
+    pub fn handles(&self) -> (SocketHandle, SocketHandle) {
+        (self.handles[0], self.handles[1])
+    }
+
+    /// Return the delay after which the second endpoint is attempted.
+    ///
+    /// See also the [set_delay](#method.set_delay) method.
+    pub fn delay(&self) -> Duration {
+        Duration::from_millis(self.delay)
+    }
+
+    /// Set the delay after which the second endpoint is attempted.
+    ///
+    /// The default value is 250 milliseconds, as recommended by RFC 8305 § 5.
+    pub fn set_delay(&mut self, delay: Duration) {
+        self.delay = delay.total_millis()
+    }
+
+    /// Return whether a connection is being attempted.
+    pub fn is_connecting(&self) -> bool {
+        self.attempts.iter().any(|attempt| {
+            match *attempt {
+                Attempt::Waiting(_) | Attempt::Connecting(_) => true,
+                _ => false
+            }
+        })
+    }
+
+    /// Attempt to connect to the given port of a remote host with the given addresses,
+    /// e.g. the results of its A and AAAA queries, from the given local port.
+    ///
+    /// The first IPv6 address and the first IPv4 address are attempted, in that order.
+    ///
+    /// This function returns `Err(Error::Unaddressable)` if there are no such addresses,
+    /// as well as the errors of [connect_endpoints](#method.connect_endpoints).
+    pub fn connect(&mut self, sockets: &mut SocketSet, timestamp: u64, addrs: &[IpAddress],
+                   remote_port: u16, local_port: u16) -> Result<()> {
+        let ipv6_addr = addrs.iter().cloned().find(|addr| {
+            match *addr {
+                #[cfg(feature = "proto-ipv6")]
+                IpAddress::Ipv6(_) => true,
+                _ => false
+            }
+        });
+        let ipv4_addr = addrs.iter().cloned().find(|addr| {
+            match *addr {
+                #[cfg(feature = "proto-ipv4")]
+                IpAddress::Ipv4(_) => true,
+                _ => false
+            }
+        });
+
+        let (first, second) = match (ipv6_addr, ipv4_addr) {
+            (Some(ipv6_addr), ipv4_addr) => (ipv6_addr, ipv4_addr),
+            (None, Some(ipv4_addr)) => (ipv4_addr, None),
+            (None, None) => return Err(Error::Unaddressable)
+        };
+        self.connect_endpoints(sockets, timestamp, IpEndpoint::new(first, remote_port),
+                               second.map(|addr| IpEndpoint::new(addr, remote_port)),
+                               local_port)
+    }
+
+    /// Attempt to connect to the first endpoint, then to the second one if any,
+    /// from the given local port.
+    ///
+    /// This function returns `Err(Error::Illegal)` if a connection is being attempted,
+    /// as well as the errors of [TcpSocket::connect] for the first endpoint.
+    ///
+    /// # Panics
+    /// This function panics if the handles do not refer to TCP sockets in the set.
+    ///
+    /// [TcpSocket::connect]: struct.TcpSocket.html#method.connect
+    pub fn connect_endpoints(&mut self, sockets: &mut SocketSet, timestamp: u64,
+                             first: IpEndpoint, second: Option<IpEndpoint>,
+                             local_port: u16) -> Result<()> {
+        if self.is_connecting() { return Err(Error::Illegal) }
+
+        sockets.get::<TcpSocket>(self.handles[0]).connect(first, local_port)?;
+        net_trace!("[{}]: attempting to connect to {}", self.handles[0], first);
+        self.attempts = [
+            Attempt::Connecting(first),
+            second.map(Attempt::Waiting).unwrap_or(Attempt::None)
+        ];
+        self.local_port = local_port;
+        self.started_at = timestamp;
+        Ok(())
+    }
+
+    /// Abort the connection attempts, if any.
+    ///
+    /// # Panics
+    /// This function panics if the handles do not refer to TCP sockets in the set.
+    pub fn abort(&mut self, sockets: &mut SocketSet) {
+        for (attempt, &handle) in self.attempts.iter_mut().zip(self.handles.iter()) {
+            if let Attempt::Connecting(_) = *attempt {
+                sockets.get::<TcpSocket>(handle).abort()
+            }
+            *attempt = Attempt::None
+        }
+    }
+
+    /// Check the progress of the connection attempts, start the second one when
+    /// it is due, and return the outcome once it is known.
+    ///
+    /// This function should be called whenever the interface has been polled, and
+    /// at the time returned by [poll_at](#method.poll_at).
+    ///
+    /// # Panics
+    /// This function panics if the handles do not refer to TCP sockets in the set.
+    pub fn poll(&mut self, sockets: &mut SocketSet, timestamp: u64) -> Option<Event> {
+        for index in 0..2 {
+            let remote_endpoint = match self.attempts[index] {
+                Attempt::Connecting(remote_endpoint) => remote_endpoint,
+                _ => continue
+            };
+
+            let handle = self.handles[index];
+            let state = sockets.get::<TcpSocket>(handle).state();
+            match state {
+                TcpState::SynSent | TcpState::SynReceived => (),
+                TcpState::Closed => {
+                    net_trace!("[{}]: failed to connect to {}", handle, remote_endpoint);
+                    self.attempts[index] = Attempt::Failed
+                }
+                _ => {
+                    net_trace!("[{}]: connected to {}", handle, remote_endpoint);
+                    self.attempts[index] = Attempt::None;
+                    self.abort(sockets);
+                    return Some(Event::Connected {
+                        handle:          handle,
+                        remote_endpoint: remote_endpoint
+                    })
+                }
+            }
+        }
+
+        if let Attempt::Waiting(remote_endpoint) = self.attempts[1] {
+            if self.attempts[0] == Attempt::Failed || self.started_at + self.delay <= timestamp {
+                let handle = self.handles[1];
+                net_trace!("[{}]: attempting to connect to {}", handle, remote_endpoint);
+                self.attempts[1] =
+                    match sockets.get::<TcpSocket>(handle).connect(remote_endpoint,
+                                                                   self.local_port) {
+                        Ok(()) => Attempt::Connecting(remote_endpoint),
+                        Err(_) => Attempt::Failed
+                    };
+            }
+        }
+
+        match self.attempts {
+            [Attempt::Failed, Attempt::Failed] | [Attempt::Failed, Attempt::None] => {
+                self.attempts = [Attempt::None; 2];
+                Some(Event::Failed)
+            }
+            _ => None
+        }
+    }
+
+    /// Return the time at which the second endpoint is due to be attempted, if it is
+    /// waiting for the delay to pass.
+    pub fn poll_at(&self) -> Option<u64> {
+        match self.attempts[1] {
+            Attempt::Waiting(_) => Some(self.started_at + self.delay),
+            _ => None
+        }
+    }
+}
+
+#[cfg(all(test, feature = "proto-ipv4", feature = "proto-ipv6"))]
+mod test {
+    use std::vec::Vec;
+    use phy::DeviceCapabilities;
+    use time::Instant;
+    use wire::{Ipv4Address, Ipv6Address, IpProtocol, IpRepr};
+    use wire::{TcpControl, TcpRepr, TcpSeqNumber, TCP_SACK_RANGE_COUNT};
+    use socket::TcpSocketBuffer;
+    use super::*;
+
+    const IPV6_ADDR: IpAddress = IpAddress::Ipv6(Ipv6Address([
+        0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1
+    ]));
+    const IPV4_ADDR: IpAddress = IpAddress::Ipv4(Ipv4Address([192, 0, 2, 1]));
+
+    fn socket_set<'a>() -> (SocketSet<'static, 'a, 'static>, TcpConnector) {
+        let mut sockets = SocketSet::new(vec![]);
+        let handles: Vec<SocketHandle> = (0..2).map(|_| {
+            let rx_buffer = TcpSocketBuffer::new(vec![0; 64]);
+            let tx_buffer = TcpSocketBuffer::new(vec![0; 64]);
+            sockets.add(TcpSocket::new(rx_buffer, tx_buffer))
+        }).collect();
+        let connector = TcpConnector::new(handles[0], handles[1]);
+        (sockets, connector)
+    }
+
+    fn state(sockets: &mut SocketSet, handle: SocketHandle) -> TcpState {
+        sockets.get::<TcpSocket>(handle).state()
+    }
+
+    fn establish(sockets: &mut SocketSet, handle: SocketHandle) {
+        let mut socket = sockets.get::<TcpSocket>(handle);
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = 1520;
+        let mut syn = None;
+        socket.dispatch(Instant::from_millis(0), &caps, |(ip_repr, repr)| {
+            syn = Some((ip_repr, repr.src_port, repr.dst_port, repr.seq_number));
+            Ok(())
+        }).unwrap();
+        let (ip_repr, src_port, dst_port, seq_number) = syn.unwrap();
+
+        let repr = TcpRepr {
+            src_port:     dst_port,
+            dst_port:     src_port,
+            control:      TcpControl::Syn,
+            seq_number:   TcpSeqNumber(-10000),
+            ack_number:   Some(seq_number + 1),
+            window_len:   256,
+            max_seg_size: None,
+            sack_permitted: false,
+            sack_ranges:  [None; TCP_SACK_RANGE_COUNT],
+            timestamp:    None,
+            md5_signature: None,
+            ece:          false,
+            cwr:          false,
+            urgent_at:    None,
+            payload:      &[]
+        };
+        let ip_repr = IpRepr::Unspecified {
+            src_addr:    ip_repr.dst_addr(),
+            dst_addr:    ip_repr.src_addr(),
+            protocol:    IpProtocol::Tcp,
+            payload_len: repr.buffer_len(),
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0,
+            flow_label:  0
+        };
+        assert!(socket.accepts(&ip_repr, &repr));
+        socket.process(Instant::from_millis(0), &ip_repr, &repr).unwrap();
+        assert_eq!(socket.state(), TcpState::Established);
+    }
+
+    #[test]
+    fn test_connect_ipv6_first() {
+        let (mut sockets, mut connector) = socket_set();
+        let (first, second) = connector.handles();
+        assert_eq!(connector.connect(&mut sockets, 0, &[IPV4_ADDR, IPV6_ADDR], 80, 49500),
+                   Ok(()));
+        assert!(connector.is_connecting());
+        assert_eq!(sockets.get::<TcpSocket>(first).remote_endpoint(),
+                   IpEndpoint::new(IPV6_ADDR, 80));
+        assert_eq!(state(&mut sockets, second), TcpState::Closed);
+        assert_eq!(connector.poll_at(), Some(250));
+        assert_eq!(connector.connect(&mut sockets, 0, &[IPV6_ADDR], 80, 49500),
+                   Err(Error::Illegal));
+
+        // The second endpoint is attempted once the delay has passed.
+        assert_eq!(connector.poll(&mut sockets, 249), None);
+        assert_eq!(state(&mut sockets, second), TcpState::Closed);
+        assert_eq!(connector.poll(&mut sockets, 250), None);
+        assert_eq!(sockets.get::<TcpSocket>(second).remote_endpoint(),
+                   IpEndpoint::new(IPV4_ADDR, 80));
+        assert_eq!(connector.poll_at(), None);
+
+        // The first connection wins, and the other attempt is aborted.
+        establish(&mut sockets, second);
+        assert_eq!(connector.poll(&mut sockets, 300), Some(Event::Connected {
+            handle:          second,
+            remote_endpoint: IpEndpoint::new(IPV4_ADDR, 80)
+        }));
+        assert_eq!(state(&mut sockets, first), TcpState::Closed);
+        assert!(!connector.is_connecting());
+        assert_eq!(connector.poll(&mut sockets, 300), None);
+    }
+
+    #[test]
+    fn test_connect_fallback() {
+        let (mut sockets, mut connector) = socket_set();
+        let (first, second) = connector.handles();
+        assert_eq!(connector.connect(&mut sockets, 0, &[IPV6_ADDR, IPV4_ADDR], 80, 49500),
+                   Ok(()));
+
+        // The second endpoint is attempted as soon as the first attempt failed.
+        sockets.get::<TcpSocket>(first).abort();
+        assert_eq!(connector.poll(&mut sockets, 10), None);
+        assert_eq!(state(&mut sockets, second), TcpState::SynSent);
+
+        sockets.get::<TcpSocket>(second).abort();
+        assert_eq!(connector.poll(&mut sockets, 20), Some(Event::Failed));
+        assert!(!connector.is_connecting());
+    }
+
+    #[test]
+    fn test_connect_single() {
+        let (mut sockets, mut connector) = socket_set();
+        let (first, _) = connector.handles();
+        assert_eq!(connector.connect(&mut sockets, 0, &[], 80, 49500),
+                   Err(Error::Unaddressable));
+        assert_eq!(connector.connect(&mut sockets, 0, &[IPV4_ADDR], 80, 49500), Ok(()));
+        assert_eq!(connector.poll_at(), None);
+
+        establish(&mut sockets, first);
+        assert_eq!(connector.poll(&mut sockets, 10), Some(Event::Connected {
+            handle:          first,
+            remote_endpoint: IpEndpoint::new(IPV4_ADDR, 80)
+        }));
+    }
+}
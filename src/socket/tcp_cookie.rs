@@ -0,0 +1,171 @@
+// Heads up! Before working on this file you should read RFC 4987 § 3.6, which describes
+// SYN cookies, and the SipHash paper by Aumasson and Bernstein.
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use wire::{IpAddress, IpEndpoint, TcpSeqNumber};
+
+/// The period of the counter encoded in a cookie, in milliseconds.
+const COUNTER_PERIOD: u64 = 64_000;
+
+/// The number of counter periods a cookie stays valid for, after the one it was issued in.
+const COUNTER_MAX_AGE: u32 = 1;
+
+/// The maximum segment sizes a cookie may encode, indexed by its MSS bits.
+const MSS_TABLE: [u16; 8] = [536, 1024, 1220, 1280, 1360, 1400, 1440, 1460];
+
+fn sip_round(v: &mut [u64; 4]) {
+    v[0] = v[0].wrapping_add(v[1]); v[1] = v[1].rotate_left(13);
+    v[1] ^= v[0];                   v[0] = v[0].rotate_left(32);
+    v[2] = v[2].wrapping_add(v[3]); v[3] = v[3].rotate_left(16);
+    v[3] ^= v[2];
+    v[0] = v[0].wrapping_add(v[3]); v[3] = v[3].rotate_left(21);
+    v[3] ^= v[0];
+    v[2] = v[2].wrapping_add(v[1]); v[1] = v[1].rotate_left(17);
+    v[1] ^= v[2];                   v[2] = v[2].rotate_left(32);
+}
+
+fn compress(v: &mut [u64; 4], m: u64) {
+    v[3] ^= m;
+    sip_round(v);
+    sip_round(v);
+    v[0] ^= m;
+}
+
+/// Compute the SipHash-2-4 of `data` with the given key.
+fn siphash(key: &[u8; 16], data: &[u8]) -> u64 {
+    let (k0, k1) = (LittleEndian::read_u64(&key[..8]), LittleEndian::read_u64(&key[8..]));
+    let mut v = [k0 ^ 0x736f6d6570736575, k1 ^ 0x646f72616e646f6d,
+                 k0 ^ 0x6c7967656e657261, k1 ^ 0x7465646279746573];
+
+    let tail_len = data.len() % 8;
+    let (blocks, tail) = data.split_at(data.len() - tail_len);
+    for block in blocks.chunks(8) {
+        compress(&mut v, LittleEndian::read_u64(block))
+    }
+    let mut last = [0; 8];
+    last[..tail_len].copy_from_slice(tail);
+    last[7] = data.len() as u8;
+    compress(&mut v, LittleEndian::read_u64(&last));
+
+    v[2] ^= 0xff;
+    for _ in 0..4 {
+        sip_round(&mut v)
+    }
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
+fn addr_bytes(addr: &IpAddress) -> &[u8] {
+    match addr {
+        #[cfg(feature = "proto-ipv4")]
+        &IpAddress::Ipv4(ref addr) => addr.as_bytes(),
+        #[cfg(feature = "proto-ipv6")]
+        &IpAddress::Ipv6(ref addr) => addr.as_bytes(),
+        _ => &[]
+    }
+}
+
+/// Compute the 24-bit message authentication code of a cookie.
+fn mac(secret: &[u8; 16], local: IpEndpoint, remote: IpEndpoint,
+       remote_seq: TcpSeqNumber, counter: u32, mss_index: u32) -> u32 {
+    let mut data = [0; 48];
+    let mut len = 0;
+    for bytes in [addr_bytes(&local.addr), addr_bytes(&remote.addr)].iter() {
+        data[len..len + bytes.len()].copy_from_slice(bytes);
+        len += bytes.len();
+    }
+    LittleEndian::write_u16(&mut data[len..], local.port);
+    LittleEndian::write_u16(&mut data[len + 2..], remote.port);
+    LittleEndian::write_i32(&mut data[len + 4..], remote_seq.0);
+    LittleEndian::write_u32(&mut data[len + 8..], counter << 3 | mss_index);
+    siphash(secret, &data[..len + 12]) as u32 & 0xffffff
+}
+
+fn counter(timestamp: u64) -> u32 {
+    (timestamp / COUNTER_PERIOD) as u32 & 0x1f
+}
+
+/// Return the initial sequence number to answer a SYN starting at `remote_seq`,
+/// and announcing the given maximum segment size.
+///
+/// The cookie consists of a 5-bit counter of 64 second periods, the 3-bit index of
+/// the largest segment size in a table that does not exceed the announced one, and
+/// a 24-bit SipHash of those and of the connection, keyed with the secret.
+pub(crate) fn encode(secret: &[u8; 16], timestamp: u64, local: IpEndpoint, remote: IpEndpoint,
+                     remote_seq: TcpSeqNumber, max_seg_size: Option<u16>) -> TcpSeqNumber {
+    let max_seg_size = max_seg_size.unwrap_or(MSS_TABLE[0]);
+    let mss_index = MSS_TABLE.iter().rposition(|&mss| mss <= max_seg_size).unwrap_or(0) as u32;
+    let counter = counter(timestamp);
+    let mac = mac(secret, local, remote, remote_seq, counter, mss_index);
+    TcpSeqNumber((counter << 27 | mss_index << 24 | mac) as i32)
+}
+
+/// Check the cookie that was used as the initial sequence number to answer a SYN
+/// starting at `remote_seq`, and return the maximum segment size it encodes if it is
+/// valid and recent.
+pub(crate) fn decode(secret: &[u8; 16], timestamp: u64, local: IpEndpoint, remote: IpEndpoint,
+                     remote_seq: TcpSeqNumber, cookie: TcpSeqNumber) -> Option<u16> {
+    let cookie = cookie.0 as u32;
+    let (counter_bits, mss_index) = (cookie >> 27, cookie >> 24 & 0x7);
+    if counter(timestamp).wrapping_sub(counter_bits) & 0x1f > COUNTER_MAX_AGE {
+        return None
+    }
+    if mac(secret, local, remote, remote_seq, counter_bits, mss_index) != cookie & 0xffffff {
+        return None
+    }
+    Some(MSS_TABLE[mss_index as usize])
+}
+
+#[cfg(test)]
+mod test {
+    use wire::ip::test::{MOCK_IP_ADDR_1, MOCK_IP_ADDR_2};
+    use super::*;
+
+    const SECRET: [u8; 16] = [0x5a; 16];
+    const LOCAL: IpEndpoint = IpEndpoint { addr: MOCK_IP_ADDR_1, port: 80 };
+    const REMOTE: IpEndpoint = IpEndpoint { addr: MOCK_IP_ADDR_2, port: 49500 };
+
+    #[test]
+    fn test_siphash() {
+        // Test vectors from the reference implementation.
+        let mut key = [0; 16];
+        for (i, b) in key.iter_mut().enumerate() {
+            *b = i as u8
+        }
+        let data = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14];
+        assert_eq!(siphash(&key, &[]), 0x726fdb47dd0e0e31);
+        assert_eq!(siphash(&key, &data[..8]), 0x93f5f5799a932462);
+        assert_eq!(siphash(&key, &data), 0xa129ca6149be45e5);
+    }
+
+    #[test]
+    fn test_cookie() {
+        let cookie = encode(&SECRET, 1_000, LOCAL, REMOTE, TcpSeqNumber(-10000), Some(1460));
+        assert_eq!(decode(&SECRET, 1_000, LOCAL, REMOTE, TcpSeqNumber(-10000), cookie),
+                   Some(1460));
+        assert_eq!(decode(&SECRET, 127_999, LOCAL, REMOTE, TcpSeqNumber(-10000), cookie),
+                   Some(1460));
+        let cookie = encode(&SECRET, 1_000, LOCAL, REMOTE, TcpSeqNumber(-10000), Some(1300));
+        assert_eq!(decode(&SECRET, 1_000, LOCAL, REMOTE, TcpSeqNumber(-10000), cookie),
+                   Some(1280));
+        let cookie = encode(&SECRET, 1_000, LOCAL, REMOTE, TcpSeqNumber(-10000), None);
+        assert_eq!(decode(&SECRET, 1_000, LOCAL, REMOTE, TcpSeqNumber(-10000), cookie),
+                   Some(536));
+    }
+
+    #[test]
+    fn test_cookie_invalid() {
+        let cookie = encode(&SECRET, 1_000, LOCAL, REMOTE, TcpSeqNumber(-10000), Some(1460));
+        // Too old.
+        assert_eq!(decode(&SECRET, 128_000, LOCAL, REMOTE, TcpSeqNumber(-10000), cookie), None);
+        // Another connection.
+        let other = IpEndpoint { port: 49501, ..REMOTE };
+        assert_eq!(decode(&SECRET, 1_000, LOCAL, other, TcpSeqNumber(-10000), cookie), None);
+        assert_eq!(decode(&SECRET, 1_000, LOCAL, REMOTE, TcpSeqNumber(-9999), cookie), None);
+        // Another secret.
+        assert_eq!(decode(&[0; 16], 1_000, LOCAL, REMOTE, TcpSeqNumber(-10000), cookie), None);
+        // A forged segment size.
+        let forged = TcpSeqNumber(cookie.0 ^ 1 << 24);
+        assert_eq!(decode(&SECRET, 1_000, LOCAL, REMOTE, TcpSeqNumber(-10000), forged), None);
+    }
+}
@@ -0,0 +1,285 @@
+use managed::ManagedSlice;
+
+use {Error, Result};
+use wire::IpEndpoint;
+use super::{SocketSet, SocketHandle, TcpSocket, TcpState};
+
+/// A TCP listener.
+///
+/// A single TCP socket in the `LISTEN` state stops listening as soon as it receives
+/// a SYN, and any further SYNs for its local endpoint are answered with a RST until
+/// the socket is closed and listening again. A listener keeps a backlog of TCP sockets
+/// that all listen on the same local endpoint, hands out the sockets that have
+/// established a connection, and starts listening again with the sockets that are
+/// returned to it.
+///
+/// The sockets are owned by a socket set; the listener only refers to them by their
+/// handles.
+#[derive(Debug)]
+pub struct TcpListener<'a> {
+    local_endpoint: IpEndpoint,
+    backlog:        ManagedSlice<'a, Option<SocketHandle>>,
+}
+
+impl<'a> TcpListener<'a> {
+    /// Create a listener on the given endpoint, with the provided backlog storage.
+    ///
+    /// The backlog is empty; sockets are added to it with [add](#method.add).
+    pub fn new<T, U>(local_endpoint: T, backlog: U) -> TcpListener<'a>
+            where T: Into<IpEndpoint>, U: Into<ManagedSlice<'a, Option<SocketHandle>>> {
+        TcpListener {
+            local_endpoint: local_endpoint.into(),
+            backlog:        backlog.into(),
+        }
+    }
+
+    /// Return the local endpoint.
+    #[inline]
+    pub fn local_endpoint(&self) -> IpEndpoint {
+        self.local_endpoint
+    }
+
+    /// Add a TCP socket to the backlog, and start listening with it.
+    ///
+    /// This function returns the errors of [TcpSocket::listen], e.g. `Err(Error::Illegal)`
+    /// if the socket is open, as well as `Err(Error::Exhausted)` if the backlog storage
+    /// is fixed-size (not a `Vec`) and is full.
+    ///
+    /// # Panics
+    /// This function panics if the handle does not refer to a TCP socket in the set.
+    ///
+    /// [TcpSocket::listen]: struct.TcpSocket.html#method.listen
+    pub fn add(&mut self, sockets: &mut SocketSet, handle: SocketHandle) -> Result<()> {
+        if self.backlog.iter().any(|slot| *slot == Some(handle)) {
+            return Err(Error::Illegal)
+        }
+
+        let index = match self.backlog.iter().position(|slot| slot.is_none()) {
+            Some(index) => index,
+            None => match self.backlog {
+                ManagedSlice::Borrowed(_) => return Err(Error::Exhausted),
+                #[cfg(any(feature = "std", feature = "alloc"))]
+                ManagedSlice::Owned(ref mut backlog) => {
+                    backlog.push(None);
+                    backlog.len() - 1
+                }
+            }
+        };
+
+        sockets.get::<TcpSocket>(handle).listen(self.local_endpoint)?;
+        self.backlog[index] = Some(handle);
+        Ok(())
+    }
+
+    /// Remove a TCP socket from the backlog, without changing its state.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the socket is not in the backlog.
+    pub fn remove(&mut self, handle: SocketHandle) -> Result<()> {
+        match self.backlog.iter_mut().find(|slot| **slot == Some(handle)) {
+            Some(slot) => {
+                *slot = None;
+                Ok(())
+            }
+            None => Err(Error::Illegal)
+        }
+    }
+
+    /// Return the number of sockets in the backlog that are listening for a connection.
+    pub fn listening(&self, sockets: &mut SocketSet) -> usize {
+        self.backlog.iter()
+            .filter_map(|slot| *slot)
+            .filter(|&handle| sockets.get::<TcpSocket>(handle).is_listening())
+            .count()
+    }
+
+    /// Take a socket that has established a connection out of the backlog, and return
+    /// its handle.
+    ///
+    /// The socket is no longer managed by the listener; once the connection is closed,
+    /// it can be returned to the backlog with [add](#method.add). Sockets in the backlog
+    /// that were closed without establishing a connection, e.g. because of a timeout,
+    /// start listening again.
+    pub fn accept(&mut self, sockets: &mut SocketSet) -> Option<SocketHandle> {
+        let mut accepted = None;
+        for slot in self.backlog.iter_mut() {
+            let handle = match *slot {
+                Some(handle) => handle,
+                None => continue
+            };
+
+            let mut socket = sockets.get::<TcpSocket>(handle);
+            match socket.state() {
+                TcpState::Listen | TcpState::SynReceived => (),
+                TcpState::Closed => {
+                    net_trace!("[{}]: listening again on {}", handle, self.local_endpoint);
+                    // This cannot fail, since the socket is closed and the endpoint
+                    // was accepted once already.
+                    let _ = socket.listen(self.local_endpoint);
+                }
+                _ if accepted.is_none() => {
+                    accepted = Some(handle);
+                    *slot = None;
+                }
+                _ => ()
+            }
+        }
+        accepted
+    }
+
+    /// Close all sockets in the backlog that have not established a connection,
+    /// and remove them from the backlog.
+    pub fn close(&mut self, sockets: &mut SocketSet) {
+        for slot in self.backlog.iter_mut() {
+            let handle = match *slot {
+                Some(handle) => handle,
+                None => continue
+            };
+
+            let mut socket = sockets.get::<TcpSocket>(handle);
+            match socket.state() {
+                TcpState::Listen | TcpState::Closed => socket.close(),
+                TcpState::SynReceived => socket.abort(),
+                _ => continue
+            }
+            *slot = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use wire::{IpProtocol, IpRepr, TcpRepr, TcpControl, TcpSeqNumber, TCP_SACK_RANGE_COUNT};
+    use wire::ip::test::{MOCK_IP_ADDR_1, MOCK_IP_ADDR_2};
+    use time::Instant;
+    use socket::{AnySocket, TcpSocketBuffer};
+    use super::*;
+
+    const LOCAL_END: IpEndpoint = IpEndpoint { addr: MOCK_IP_ADDR_1, port: 80 };
+
+    fn socket_set<'a>(count: usize) -> (SocketSet<'static, 'a, 'static>, Vec<SocketHandle>) {
+        let mut sockets = SocketSet::new(vec![]);
+        let handles = (0..count).map(|_| {
+            let rx_buffer = TcpSocketBuffer::new(vec![0; 64]);
+            let tx_buffer = TcpSocketBuffer::new(vec![0; 64]);
+            sockets.add(TcpSocket::new(rx_buffer, tx_buffer))
+        }).collect();
+        (sockets, handles)
+    }
+
+    fn send(sockets: &mut SocketSet, remote_port: u16, control: TcpControl,
+            seq_number: i32, ack_number: Option<i32>) -> Option<SocketHandle> {
+        let repr = TcpRepr {
+            src_port:     remote_port,
+            dst_port:     LOCAL_END.port,
+            control:      control,
+            seq_number:   TcpSeqNumber(seq_number),
+            ack_number:   ack_number.map(TcpSeqNumber),
+            window_len:   256,
+            max_seg_size: None,
+            sack_permitted: false,
+            sack_ranges:  [None; TCP_SACK_RANGE_COUNT],
+            timestamp:    None,
+            md5_signature: None,
+            ece:          false,
+            cwr:          false,
+            urgent_at:    None,
+            payload:      &[]
+        };
+        let ip_repr = IpRepr::Unspecified {
+            src_addr:    MOCK_IP_ADDR_2,
+            dst_addr:    MOCK_IP_ADDR_1,
+            protocol:    IpProtocol::Tcp,
+            payload_len: repr.buffer_len(),
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0,
+            flow_label:  0
+        };
+        for mut socket in sockets.iter_mut().filter_map(TcpSocket::downcast) {
+            if !socket.accepts(&ip_repr, &repr) { continue }
+            socket.process(Instant::from_millis(0), &ip_repr, &repr).unwrap();
+            return Some(socket.handle())
+        }
+        None
+    }
+
+    fn connect(sockets: &mut SocketSet, remote_port: u16) -> Option<SocketHandle> {
+        // A passive open acknowledges the SYN with the negated sequence number.
+        send(sockets, remote_port, TcpControl::Syn, -10000, None)?;
+        send(sockets, remote_port, TcpControl::None, -9999, Some(10001))
+    }
+
+    #[test]
+    fn test_add() {
+        let (mut sockets, handles) = socket_set(2);
+        let mut backlog = [None; 1];
+        let mut listener = TcpListener::new(LOCAL_END, &mut backlog[..]);
+        assert_eq!(listener.add(&mut sockets, handles[0]), Ok(()));
+        assert_eq!(listener.add(&mut sockets, handles[0]), Err(Error::Illegal));
+        assert_eq!(listener.add(&mut sockets, handles[1]), Err(Error::Exhausted));
+        assert_eq!(sockets.get::<TcpSocket>(handles[0]).local_endpoint(), LOCAL_END);
+        assert!(sockets.get::<TcpSocket>(handles[0]).is_listening());
+        assert!(!sockets.get::<TcpSocket>(handles[1]).is_listening());
+        assert_eq!(listener.listening(&mut sockets), 1);
+    }
+
+    #[test]
+    fn test_accept() {
+        let (mut sockets, handles) = socket_set(2);
+        let mut listener = TcpListener::new(LOCAL_END, vec![]);
+        for &handle in &handles {
+            listener.add(&mut sockets, handle).unwrap();
+        }
+        assert_eq!(listener.accept(&mut sockets), None);
+
+        let first = connect(&mut sockets, 49500).unwrap();
+        assert_eq!(listener.listening(&mut sockets), 1);
+        let second = connect(&mut sockets, 49501).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(listener.listening(&mut sockets), 0);
+
+        assert_eq!(listener.accept(&mut sockets), Some(first));
+        assert_eq!(listener.accept(&mut sockets), Some(second));
+        assert_eq!(listener.accept(&mut sockets), None);
+        assert_eq!(sockets.get::<TcpSocket>(first).state(), TcpState::Established);
+
+        // Once closed, a socket can be returned to the backlog.
+        sockets.get::<TcpSocket>(first).abort();
+        assert_eq!(listener.add(&mut sockets, first), Ok(()));
+        assert_eq!(listener.listening(&mut sockets), 1);
+    }
+
+    #[test]
+    fn test_accept_relisten() {
+        let (mut sockets, handles) = socket_set(1);
+        let mut listener = TcpListener::new(LOCAL_END, vec![]);
+        listener.add(&mut sockets, handles[0]).unwrap();
+
+        send(&mut sockets, 49500, TcpControl::Syn, -10000, None).unwrap();
+        sockets.get::<TcpSocket>(handles[0]).abort();
+        assert_eq!(listener.listening(&mut sockets), 0);
+        assert_eq!(listener.accept(&mut sockets), None);
+        assert_eq!(listener.listening(&mut sockets), 1);
+    }
+
+    #[test]
+    fn test_close() {
+        let (mut sockets, handles) = socket_set(3);
+        let mut listener = TcpListener::new(LOCAL_END, vec![]);
+        for &handle in &handles {
+            listener.add(&mut sockets, handle).unwrap();
+        }
+        let established = connect(&mut sockets, 49500).unwrap();
+        send(&mut sockets, 49501, TcpControl::Syn, -10000, None).unwrap();
+
+        listener.close(&mut sockets);
+        assert_eq!(listener.listening(&mut sockets), 0);
+        assert_eq!(sockets.get::<TcpSocket>(established).state(), TcpState::Established);
+        assert_eq!(listener.accept(&mut sockets), Some(established));
+        for &handle in &handles {
+            if handle == established { continue }
+            assert_eq!(sockets.get::<TcpSocket>(handle).state(), TcpState::Closed);
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use core::cell::RefCell;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::io;
+use futures_io::{AsyncRead, AsyncWrite};
+
+use super::{SocketSet, SocketHandle, TcpSocket, TcpState};
+
+/// An adapter implementing the `AsyncRead` and `AsyncWrite` traits of the [futures] crate
+/// for a TCP socket.
+///
+/// The socket is owned by a socket set shared through a `RefCell`, which is borrowed for
+/// the duration of every operation; the interface has to be polled with the same set
+/// for the operations to make progress. A pending operation registers the waker of its
+/// task with the socket, and is retried once the interface wakes it.
+///
+/// Reads wait for a connection to be established, and return end of stream once the remote
+/// end closes it. Writes return `ErrorKind::BrokenPipe` once the transmit half of
+/// the connection is closed, and flushes wait until all data is acknowledged.
+/// Closing the stream closes the socket, and waits until the remote end acknowledges
+/// the FIN.
+///
+/// [futures]: https://docs.rs/futures
+#[derive(Debug)]
+pub struct TcpStream<'r, 'a: 'r, 'b: 'a, 'c: 'a + 'b> {
+    sockets: &'r RefCell<SocketSet<'a, 'b, 'c>>,
+    handle:  SocketHandle,
+}
+
+impl<'r, 'a, 'b, 'c> TcpStream<'r, 'a, 'b, 'c> {
+    /// Create an adapter for the TCP socket with the given handle in a socket set.
+    ///
+    /// # Panics
+    /// The operations of the adapter panic if the handle does not refer to a TCP socket
+    /// in the set, or if the set is already borrowed.
+    pub fn new(sockets: &'r RefCell<SocketSet<'a, 'b, 'c>>,
+               handle: SocketHandle) -> TcpStream<'r, 'a, 'b, 'c> {
+        TcpStream { sockets, handle }
+    }
+
+    /// Return the handle of the socket.
+    #[inline]
+    pub fn handle(&self) -> SocketHandle {
+        self.handle
+    }
+
+    fn with_socket<F, R>(&self, f: F) -> R
+            where F: FnOnce(&mut TcpSocket) -> R {
+        let mut sockets = self.sockets.borrow_mut();
+        let mut socket = sockets.get::<TcpSocket>(self.handle);
+        f(&mut socket)
+    }
+}
+
+impl<'r, 'a, 'b, 'c> AsyncRead for TcpStream<'r, 'a, 'b, 'c> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) ->
+                Poll<io::Result<usize>> {
+        self.poll_read_vectored(cx, &mut [io::IoSliceMut::new(buf)])
+    }
+
+    fn poll_read_vectored(self: Pin<&mut Self>, cx: &mut Context,
+                          bufs: &mut [io::IoSliceMut]) -> Poll<io::Result<usize>> {
+        if bufs.iter().all(|buf| buf.is_empty()) { return Poll::Ready(Ok(0)) }
+
+        self.with_socket(|socket| {
+            if socket.can_recv() {
+                // This cannot fail, since there is data in the receive buffer.
+                return Poll::Ready(Ok(socket.recv_vectored(bufs).unwrap_or(0)))
+            }
+            match socket.state() {
+                TcpState::Listen | TcpState::SynSent | TcpState::SynReceived |
+                TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2 => {
+                    socket.register_recv_waker(cx.waker());
+                    Poll::Pending
+                }
+                _ => Poll::Ready(Ok(0))
+            }
+        })
+    }
+}
+
+impl<'r, 'a, 'b, 'c> AsyncWrite for TcpStream<'r, 'a, 'b, 'c> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) ->
+                 Poll<io::Result<usize>> {
+        self.poll_write_vectored(cx, &[io::IoSlice::new(buf)])
+    }
+
+    fn poll_write_vectored(self: Pin<&mut Self>, cx: &mut Context,
+                           bufs: &[io::IoSlice]) -> Poll<io::Result<usize>> {
+        self.with_socket(|socket| {
+            if socket.can_send() {
+                // This cannot fail, since the transmit half of the connection is open.
+                return Poll::Ready(Ok(socket.send_vectored(bufs).unwrap_or(0)))
+            }
+            match socket.state() {
+                TcpState::Listen | TcpState::SynSent | TcpState::SynReceived |
+                TcpState::Established | TcpState::CloseWait => {
+                    socket.register_send_waker(cx.waker());
+                    Poll::Pending
+                }
+                _ => Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
+            }
+        })
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.with_socket(|socket| {
+            if socket.send_queue() == 0 { return Poll::Ready(Ok(())) }
+            match socket.state() {
+                TcpState::Closed | TcpState::TimeWait | TcpState::FinWait2 =>
+                    Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+                _ => {
+                    socket.register_send_waker(cx.waker());
+                    Poll::Pending
+                }
+            }
+        })
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.with_socket(|socket| {
+            socket.close();
+            match socket.state() {
+                TcpState::Closed | TcpState::TimeWait | TcpState::FinWait2 =>
+                    Poll::Ready(Ok(())),
+                _ => {
+                    socket.register_send_waker(cx.waker());
+                    Poll::Pending
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use phy::DeviceCapabilities;
+    use wire::{IpEndpoint, IpProtocol, IpRepr, TcpRepr, TcpControl, TcpSeqNumber,
+               TCP_SACK_RANGE_COUNT};
+    use wire::ip::test::{MOCK_IP_ADDR_1, MOCK_IP_ADDR_2};
+    use socket::waker::test::waker;
+    use socket::TcpSocketBuffer;
+    use time::Instant;
+    use super::*;
+
+    const LOCAL_END: IpEndpoint = IpEndpoint { addr: MOCK_IP_ADDR_1, port: 80 };
+    const REMOTE_PORT: u16 = 49500;
+
+    // A passive open acknowledges the SYN with the negated sequence number.
+    const REMOTE_SEQ: i32 = -10000;
+    const LOCAL_SEQ: i32 = 10000;
+
+    fn socket_set() -> (RefCell<SocketSet<'static, 'static, 'static>>, SocketHandle) {
+        let mut sockets = SocketSet::new(vec![]);
+        let rx_buffer = TcpSocketBuffer::new(vec![0; 64]);
+        let tx_buffer = TcpSocketBuffer::new(vec![0; 64]);
+        let mut socket = TcpSocket::new(rx_buffer, tx_buffer);
+        socket.listen(LOCAL_END).unwrap();
+        let handle = sockets.add(socket);
+        (RefCell::new(sockets), handle)
+    }
+
+    fn send(stream: &TcpStream, control: TcpControl, seq_number: i32,
+            ack_number: Option<i32>, payload: &[u8]) {
+        let repr = TcpRepr {
+            src_port:     REMOTE_PORT,
+            dst_port:     LOCAL_END.port,
+            control:      control,
+            seq_number:   TcpSeqNumber(seq_number),
+            ack_number:   ack_number.map(TcpSeqNumber),
+            window_len:   256,
+            max_seg_size: None,
+            sack_permitted: false,
+            sack_ranges:  [None; TCP_SACK_RANGE_COUNT],
+            timestamp:    None,
+            md5_signature: None,
+            ece:          false,
+            cwr:          false,
+            urgent_at:    None,
+            payload:      payload
+        };
+        let ip_repr = IpRepr::Unspecified {
+            src_addr:    MOCK_IP_ADDR_2,
+            dst_addr:    MOCK_IP_ADDR_1,
+            protocol:    IpProtocol::Tcp,
+            payload_len: repr.buffer_len(),
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0,
+            flow_label:  0
+        };
+        stream.with_socket(|socket| {
+            assert!(socket.accepts(&ip_repr, &repr));
+            socket.process(Instant::from_millis(0), &ip_repr, &repr).unwrap();
+        })
+    }
+
+    fn dispatch(stream: &TcpStream) {
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = 1520;
+        stream.with_socket(|socket| {
+            socket.dispatch(Instant::from_millis(0), &caps, |_| Ok(())).unwrap();
+        })
+    }
+
+    #[test]
+    fn test_read_write() {
+        let (sockets, handle) = socket_set();
+        let mut stream = TcpStream::new(&sockets, handle);
+        let (waker, counter) = waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut buf = [0; 16];
+
+        // Reads wait for the connection to be established.
+        assert!(Pin::new(&mut stream).poll_read(&mut cx, &mut buf).is_pending());
+        send(&stream, TcpControl::Syn, REMOTE_SEQ, None, &[]);
+        assert_eq!(counter.count(), 1);
+        dispatch(&stream);
+        send(&stream, TcpControl::None, REMOTE_SEQ + 1, Some(LOCAL_SEQ + 1), &[]);
+
+        assert!(Pin::new(&mut stream).poll_read(&mut cx, &mut buf).is_pending());
+        send(&stream, TcpControl::None, REMOTE_SEQ + 1, Some(LOCAL_SEQ + 1), b"abcdef");
+        assert_eq!(counter.count(), 2);
+        match Pin::new(&mut stream).poll_read(&mut cx, &mut buf) {
+            Poll::Ready(Ok(6)) => assert_eq!(&buf[..6], b"abcdef"),
+            result => panic!("unexpected result {:?}", result)
+        }
+
+        // Flushes wait for the data to be acknowledged.
+        match Pin::new(&mut stream).poll_write(&mut cx, b"foo") {
+            Poll::Ready(Ok(3)) => (),
+            result => panic!("unexpected result {:?}", result)
+        }
+        assert!(Pin::new(&mut stream).poll_flush(&mut cx).is_pending());
+        dispatch(&stream);
+        send(&stream, TcpControl::None, REMOTE_SEQ + 7, Some(LOCAL_SEQ + 4), &[]);
+        assert_eq!(counter.count(), 3);
+        match Pin::new(&mut stream).poll_flush(&mut cx) {
+            Poll::Ready(Ok(())) => (),
+            result => panic!("unexpected result {:?}", result)
+        }
+
+        // Once the remote end closes the connection, reads return end of stream.
+        send(&stream, TcpControl::Fin, REMOTE_SEQ + 7, Some(LOCAL_SEQ + 4), &[]);
+        match Pin::new(&mut stream).poll_read(&mut cx, &mut buf) {
+            Poll::Ready(Ok(0)) => (),
+            result => panic!("unexpected result {:?}", result)
+        }
+    }
+
+    #[test]
+    fn test_close() {
+        let (sockets, handle) = socket_set();
+        let mut stream = TcpStream::new(&sockets, handle);
+        let (waker, counter) = waker();
+        let mut cx = Context::from_waker(&waker);
+
+        send(&stream, TcpControl::Syn, REMOTE_SEQ, None, &[]);
+        dispatch(&stream);
+        send(&stream, TcpControl::None, REMOTE_SEQ + 1, Some(LOCAL_SEQ + 1), &[]);
+
+        // Closing waits for the FIN to be acknowledged.
+        assert!(Pin::new(&mut stream).poll_close(&mut cx).is_pending());
+        dispatch(&stream);
+        send(&stream, TcpControl::None, REMOTE_SEQ + 1, Some(LOCAL_SEQ + 2), &[]);
+        assert_eq!(counter.count(), 1);
+        match Pin::new(&mut stream).poll_close(&mut cx) {
+            Poll::Ready(Ok(())) => (),
+            result => panic!("unexpected result {:?}", result)
+        }
+
+        // Writes fail once the transmit half of the connection is closed.
+        match Pin::new(&mut stream).poll_write(&mut cx, b"foo") {
+            Poll::Ready(Err(ref err)) if err.kind() == io::ErrorKind::BrokenPipe => (),
+            result => panic!("unexpected result {:?}", result)
+        }
+    }
+}
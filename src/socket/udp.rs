@@ -1,55 +1,68 @@
 use core::cmp::min;
-use managed::Managed;
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "async")]
+use core::task::Waker;
 
 use {Error, Result};
-use wire::{IpProtocol, IpRepr, IpEndpoint, UdpRepr};
-use socket::{Socket, SocketMeta, SocketHandle};
-use storage::{Resettable, RingBuffer};
-
-/// A buffered UDP packet.
-#[derive(Debug)]
-pub struct PacketBuffer<'a> {
-    endpoint: IpEndpoint,
-    size:     usize,
-    payload:  Managed<'a, [u8]>
+use wire::{IpProtocol, IpRepr, IpAddress, IpEndpoint, UdpRepr};
+use phy::RxLoan;
+use time::Instant;
+use socket::{Socket, SocketMeta, SocketHandle, SocketError};
+#[cfg(feature = "async")]
+use socket::WakerRegistration;
+use storage::{self, RingBuffer, BufferStats};
+
+/// The maximum number of multicast groups a single UDP socket can join.
+pub const MULTICAST_GROUP_COUNT: usize = 4;
+
+/// The metadata of a received UDP packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketMetadata {
+    /// The endpoint the packet was sent from.
+    pub endpoint:   IpEndpoint,
+    /// The address the packet was sent to. If the socket is bound to an unspecified
+    /// address, this is the local address a reply should be sent from (see
+    /// [send_from](struct.UdpSocket.html#method.send_from)), unless it is a broadcast
+    /// or multicast address.
+    pub local_addr: IpAddress,
+    /// The time-to-live (IPv4) or hop limit (IPv6) value of the packet.
+    pub hop_limit:  u8,
+    /// The time at which the packet was received by the interface.
+    pub timestamp:  Instant,
+    /// The time at which the packet was received, in nanoseconds on the clock of
+    /// the device, if the device timestamps packets in hardware.
+    pub hw_timestamp: Option<u64>,
+    /// The payload left in a buffer of the device, if it was lent rather than copied.
+    lent:           Option<RxLoan>
 }
 
-impl<'a> PacketBuffer<'a> {
-    /// Create a buffered packet.
-    pub fn new<T>(payload: T) -> PacketBuffer<'a>
-            where T: Into<Managed<'a, [u8]>> {
-        PacketBuffer {
-            endpoint: IpEndpoint::default(),
-            size:     0,
-            payload:  payload.into()
-        }
-    }
-
-    fn as_ref<'b>(&'b self) -> &'b [u8] {
-        &self.payload[..self.size]
-    }
-
-    fn as_mut<'b>(&'b mut self) -> &'b mut [u8] {
-        &mut self.payload[..self.size]
-    }
-
-    fn resize<'b>(&'b mut self, size: usize) -> Result<&'b mut Self> {
-        if self.payload.len() >= size {
-            self.size = size;
-            Ok(self)
-        } else {
-            Err(Error::Truncated)
+impl Default for PacketMetadata {
+    fn default() -> PacketMetadata {
+        PacketMetadata {
+            endpoint:   IpEndpoint::default(),
+            local_addr: IpAddress::default(),
+            hop_limit:  0,
+            timestamp:  Instant::from_millis(0),
+            hw_timestamp: None,
+            lent:       None
         }
     }
 }
 
-impl<'a> Resettable for PacketBuffer<'a> {
-    fn reset(&mut self) {
-        self.endpoint = Default::default();
-        self.size = 0;
-    }
+/// A change of the local address of an UDP socket.
+///
+/// See [rebind](struct.UdpSocket.html#method.rebind).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Migration {
+    /// The address the socket was bound to.
+    pub from: IpAddress,
+    /// The address the socket is bound to.
+    pub to:   IpAddress
 }
 
+/// A buffered UDP packet.
+pub type PacketBuffer<'a> = storage::PacketBuffer<'a, PacketMetadata>;
+
 /// An UDP packet ring buffer.
 pub type SocketBuffer<'a, 'b: 'a> = RingBuffer<'a, PacketBuffer<'b>>;
 
@@ -61,10 +74,38 @@ pub type SocketBuffer<'a, 'b: 'a> = RingBuffer<'a, PacketBuffer<'b>>;
 pub struct UdpSocket<'a, 'b: 'a> {
     pub(crate) meta: SocketMeta,
     endpoint:  IpEndpoint,
+    /// The endpoint the socket is connected to, or an unspecified endpoint if it is not
+    /// connected.
+    remote_endpoint: IpEndpoint,
+    /// The error reported for the connection since the last receive, if any.
+    error:     Option<SocketError>,
     rx_buffer: SocketBuffer<'a, 'b>,
     tx_buffer: SocketBuffer<'a, 'b>,
     /// The time-to-live (IPv4) or hop limit (IPv6) value used in outgoing packets.
-    hop_limit: Option<u8>
+    hop_limit: Option<u8>,
+    /// The Differentiated Services Code Point used in outgoing packets.
+    dscp:      u8,
+    /// The IPv6 flow label used in outgoing packets, or 0 to use the one assigned
+    /// by the interface.
+    flow_label: u32,
+    /// The multicast groups joined by the socket.
+    multicast_groups: [Option<IpAddress>; MULTICAST_GROUP_COUNT],
+    /// Whether broadcast packets may be sent and received.
+    broadcast: bool,
+    /// Whether payloads are left in the buffers of the device when it lends them.
+    zero_copy: bool,
+    /// The time at which the last packet was handed to the interface for transmission.
+    tx_timestamp: Option<u64>,
+    /// Whether the packets sent are timestamped in hardware.
+    hw_timestamping: bool,
+    /// The hardware timestamp of the last packet sent.
+    tx_hw_timestamp: Option<u64>,
+    /// The change of the local address since it was last reported, if any.
+    migration: Option<Migration>,
+    #[cfg(feature = "async")]
+    rx_waker:  WakerRegistration,
+    #[cfg(feature = "async")]
+    tx_waker:  WakerRegistration
 }
 
 impl<'a, 'b> UdpSocket<'a, 'b> {
@@ -74,12 +115,35 @@ impl<'a, 'b> UdpSocket<'a, 'b> {
         UdpSocket {
             meta:      SocketMeta::default(),
             endpoint:  IpEndpoint::default(),
+            remote_endpoint: IpEndpoint::default(),
+            error:     None,
             rx_buffer: rx_buffer,
             tx_buffer: tx_buffer,
-            hop_limit: None
+            hop_limit: None,
+            dscp:      0,
+            flow_label: 0,
+            multicast_groups: [None; MULTICAST_GROUP_COUNT],
+            broadcast: false,
+            zero_copy: false,
+            tx_timestamp: None,
+            hw_timestamping: false,
+            tx_hw_timestamp: None,
+            migration: None,
+            #[cfg(feature = "async")]
+            rx_waker:  WakerRegistration::new(),
+            #[cfg(feature = "async")]
+            tx_waker:  WakerRegistration::new()
         }
     }
 
+    /// Return a builder for an UDP socket, which checks its options for consistency
+    /// when the socket is created.
+    ///
+    /// See [UdpSocketBuilder](struct.UdpSocketBuilder.html).
+    pub fn builder() -> UdpSocketBuilder<'a, 'b> {
+        UdpSocketBuilder::new()
+    }
+
     /// Return the socket handle.
     #[inline]
     pub fn handle(&self) -> SocketHandle {
@@ -92,6 +156,14 @@ impl<'a, 'b> UdpSocket<'a, 'b> {
         self.endpoint
     }
 
+    /// Return the connected endpoint.
+    ///
+    /// The endpoint is unspecified if the socket is not connected.
+    #[inline]
+    pub fn remote_endpoint(&self) -> IpEndpoint {
+        self.remote_endpoint
+    }
+
     /// Return the time-to-live (IPv4) or hop limit (IPv6) value used in outgoing packets.
     ///
     /// See also the [set_hop_limit](#method.set_hop_limit) method
@@ -119,6 +191,60 @@ impl<'a, 'b> UdpSocket<'a, 'b> {
         self.hop_limit = hop_limit
     }
 
+    /// Return the Differentiated Services Code Point used in outgoing packets.
+    ///
+    /// See also the [set_dscp](#method.set_dscp) method
+    pub fn dscp(&self) -> u8 {
+        self.dscp
+    }
+
+    /// Set the Differentiated Services Code Point used in outgoing packets.
+    ///
+    /// The code point is carried in the upper six bits of the IPv4 Type of Service or
+    /// IPv6 Traffic Class field, and lets the network prioritize the traffic of this socket;
+    /// see [RFC 2474]. A socket without an explicitly set code point uses the default one (0).
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the code point does not fit in six bits.
+    ///
+    /// [RFC 2474]: https://tools.ietf.org/html/rfc2474
+    pub fn set_dscp(&mut self, dscp: u8) {
+        if dscp > 0x3f {
+            panic!("the DSCP value of a packet must fit in six bits")
+        }
+
+        self.dscp = dscp
+    }
+
+    /// Return the IPv6 flow label used in outgoing packets.
+    ///
+    /// See also the [set_flow_label](#method.set_flow_label) method
+    pub fn flow_label(&self) -> u32 {
+        self.flow_label
+    }
+
+    /// Set the IPv6 flow label used in outgoing packets.
+    ///
+    /// A nonzero label takes precedence over the [flow label policy] of the interface,
+    /// e.g. to keep the label an application chose for a flow; see [RFC 6437]. A socket
+    /// without an explicitly set label (0) is labeled according to the policy.
+    /// The label is not used in IPv4 packets.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the label does not fit in twenty bits.
+    ///
+    /// [flow label policy]: ../iface/enum.FlowLabelPolicy.html
+    /// [RFC 6437]: https://tools.ietf.org/html/rfc6437
+    pub fn set_flow_label(&mut self, flow_label: u32) {
+        if flow_label > 0xfffff {
+            panic!("the flow label of a packet must fit in twenty bits")
+        }
+
+        self.flow_label = flow_label
+    }
+
     /// Bind the socket to the given endpoint.
     ///
     /// This function returns `Err(Error::Illegal)` if the socket was open
@@ -134,6 +260,252 @@ impl<'a, 'b> UdpSocket<'a, 'b> {
         Ok(())
     }
 
+    /// Move an open socket to the given local address, keeping its port, its connection
+    /// and the packets in its buffers.
+    ///
+    /// This allows an application to fail over from one interface to another, e.g. from
+    /// a wireless to a cellular link, without losing data: the packets that are waiting
+    /// to be sent from the previous address, or from any address if the socket is now
+    /// bound to a specific one, are sent from the new address instead, through
+    /// the interface that owns it. The change is reported by
+    /// [take_migration](#method.take_migration), so that the protocol on top of
+    /// the socket may e.g. tell the remote endpoint about it.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the socket is not open
+    /// (see [is_open](#method.is_open)), and `Err(Error::Unaddressable)` if the socket
+    /// is connected to a remote endpoint of a different address family.
+    pub fn rebind<T: Into<IpAddress>>(&mut self, addr: T) -> Result<()> {
+        let addr = addr.into();
+        if !self.is_open() { return Err(Error::Illegal) }
+        if !addr.is_unspecified() && self.remote_endpoint.is_specified() {
+            match (addr, self.remote_endpoint.addr) {
+                #[cfg(feature = "proto-ipv4")]
+                (IpAddress::Ipv4(_), IpAddress::Ipv4(_)) => (),
+                #[cfg(feature = "proto-ipv6")]
+                (IpAddress::Ipv6(_), IpAddress::Ipv6(_)) => (),
+                _ => return Err(Error::Unaddressable)
+            }
+        }
+        if addr == self.endpoint.addr { return Ok(()) }
+
+        let from = self.endpoint.addr;
+        self.tx_buffer.for_each_allocated(|packet_buf| {
+            let local_addr = &mut packet_buf.metadata_mut().local_addr;
+            if *local_addr == from || !addr.is_unspecified() {
+                *local_addr = IpAddress::Unspecified
+            }
+        });
+        net_debug!("{}:{}: rebinding to {}", self.meta.handle, self.endpoint, addr);
+        self.endpoint.addr = addr;
+        self.migration = match self.migration {
+            Some(Migration { from: first, .. }) if first == addr => None,
+            Some(Migration { from: first, .. }) => Some(Migration { from: first, to: addr }),
+            None => Some(Migration { from: from, to: addr })
+        };
+        Ok(())
+    }
+
+    /// Return the change of the local address since it was last reported, if any,
+    /// and clear it.
+    ///
+    /// Several changes in a row are reported as one, from the first to the last address.
+    pub fn take_migration(&mut self) -> Option<Migration> {
+        self.migration.take()
+    }
+
+    /// Connect the socket to the given remote endpoint.
+    ///
+    /// A connected socket only receives packets sent from the connected endpoint, may send
+    /// packets to it with [send_connected](#method.send_connected), and reports
+    /// the errors the remote host returns for them, if any, from [recv](#method.recv).
+    /// A connected socket may be connected again to another endpoint.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the socket is not bound
+    /// (see [bind](#method.bind)), and `Err(Error::Unaddressable)` if the remote address
+    /// or port are unspecified.
+    pub fn connect<T: Into<IpEndpoint>>(&mut self, remote_endpoint: T) -> Result<()> {
+        let remote_endpoint = remote_endpoint.into();
+        if !self.is_open() { return Err(Error::Illegal) }
+        if !remote_endpoint.is_specified() { return Err(Error::Unaddressable) }
+
+        self.remote_endpoint = remote_endpoint;
+        self.error = None;
+        Ok(())
+    }
+
+    /// Close the socket.
+    ///
+    /// The socket is unbound and disconnected, and the packets in its buffers are dropped.
+    pub fn close(&mut self) {
+        self.endpoint = IpEndpoint::default();
+        self.remote_endpoint = IpEndpoint::default();
+        self.error = None;
+        self.migration = None;
+        self.rx_buffer.clear();
+        self.tx_buffer.clear();
+    }
+
+    /// Resize the receive buffer of a closed socket to the given capacity, in packets,
+    /// filling it with packet buffers returned by `f`.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the socket is open
+    /// (see [is_open](#method.is_open)), and `Err(Error::Exhausted)` if the buffer
+    /// storage is fixed-size (not a `Vec`).
+    #[cfg(any(feature = "std", feature = "alloc"))]
+    pub fn resize_recv_buffer<F>(&mut self, capacity: usize, f: F) -> Result<()>
+            where F: FnMut() -> PacketBuffer<'b> {
+        if self.is_open() { return Err(Error::Illegal) }
+
+        self.rx_buffer.clear();
+        self.rx_buffer.resize_with(capacity, f)
+    }
+
+    /// Resize the transmit buffer of a closed socket to the given capacity, in packets,
+    /// filling it with packet buffers returned by `f`.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the socket is open
+    /// (see [is_open](#method.is_open)), and `Err(Error::Exhausted)` if the buffer
+    /// storage is fixed-size (not a `Vec`).
+    #[cfg(any(feature = "std", feature = "alloc"))]
+    pub fn resize_send_buffer<F>(&mut self, capacity: usize, f: F) -> Result<()>
+            where F: FnMut() -> PacketBuffer<'b> {
+        if self.is_open() { return Err(Error::Illegal) }
+
+        self.tx_buffer.clear();
+        self.tx_buffer.resize_with(capacity, f)
+    }
+
+    /// Return the error reported for the connection since the last receive, if any,
+    /// and clear it.
+    ///
+    /// Errors are reported by the remote host or by a router on the path to it, using
+    /// ICMP error messages, and only for connected sockets.
+    pub fn take_error(&mut self) -> Option<SocketError> {
+        self.error.take()
+    }
+
+    /// Register a waker to be woken once a packet is received, or an error is reported
+    /// for the connection.
+    ///
+    /// Only one waker is registered at a time, and it is woken at most once; it has to be
+    /// registered again to be woken again. The waker may be woken spuriously.
+    #[cfg(feature = "async")]
+    pub fn register_recv_waker(&mut self, waker: &Waker) {
+        self.rx_waker.register(waker)
+    }
+
+    /// Register a waker to be woken once a packet is sent, freeing space in the
+    /// transmit buffer.
+    ///
+    /// See also [register_recv_waker](#method.register_recv_waker).
+    #[cfg(feature = "async")]
+    pub fn register_send_waker(&mut self, waker: &Waker) {
+        self.tx_waker.register(waker)
+    }
+
+    /// Check whether broadcast packets may be sent and received.
+    ///
+    /// See also the [set_broadcast](#method.set_broadcast) method
+    pub fn broadcast(&self) -> bool {
+        self.broadcast
+    }
+
+    /// Set whether broadcast packets may be sent and received.
+    ///
+    /// A socket with broadcast enabled may send packets to the limited broadcast address
+    /// (255.255.255.255) and to the directed broadcast address of a subnet of the interface,
+    /// and receives the packets sent to these addresses if it is bound to their port and to
+    /// an unspecified address. Broadcast is disabled by default.
+    pub fn set_broadcast(&mut self, broadcast: bool) {
+        self.broadcast = broadcast
+    }
+
+    /// Check whether payloads are received without being copied when possible.
+    ///
+    /// See also the [set_zero_copy](#method.set_zero_copy) method
+    pub fn zero_copy(&self) -> bool {
+        self.zero_copy
+    }
+
+    /// Set whether payloads are received without being copied when possible.
+    ///
+    /// If enabled, and the device lends the packets it receives out (see
+    /// [RxToken::consume_lend]), the payloads are left in the buffers of the device
+    /// instead of being copied into the receive buffer, which then only holds their
+    /// metadata. Such payloads are dequeued with [recv_lent](#method.recv_lent), read with
+    /// [EthernetInterface::lent_payload], and have to be released with
+    /// [EthernetInterface::release_payload]; the device runs out of buffers otherwise.
+    /// Zero-copy receive is disabled by default.
+    ///
+    /// [RxToken::consume_lend]: ../phy/trait.RxToken.html#method.consume_lend
+    /// [EthernetInterface::lent_payload]: ../iface/struct.EthernetInterface.html#method.lent_payload
+    /// [EthernetInterface::release_payload]: ../iface/struct.EthernetInterface.html#method.release_payload
+    pub fn set_zero_copy(&mut self, zero_copy: bool) {
+        self.zero_copy = zero_copy
+    }
+
+    /// Return whether the packets sent are timestamped in hardware.
+    ///
+    /// See also the [set_hw_timestamping](#method.set_hw_timestamping) method.
+    pub fn hw_timestamping(&self) -> bool {
+        self.hw_timestamping
+    }
+
+    /// Set whether the packets sent are timestamped in hardware, if the device supports it,
+    /// e.g. so that a PTP master can send the time a Sync message left at in a Follow_Up
+    /// message; see [tx_hw_timestamp](#method.tx_hw_timestamp). The default is not to.
+    ///
+    /// Received packets are timestamped in hardware regardless, if the device supports it;
+    /// see [recv_with_meta](#method.recv_with_meta).
+    pub fn set_hw_timestamping(&mut self, hw_timestamping: bool) {
+        self.hw_timestamping = hw_timestamping
+    }
+
+    /// Join the given multicast group, so that the datagrams sent to it are received.
+    ///
+    /// The interface receives the datagrams sent to each group joined by any of its UDP
+    /// sockets, and delivers them to every socket bound to their destination port and
+    /// to an unspecified address or to the group address itself.
+    ///
+    /// This function returns `Err(Error::Unaddressable)` if the address is not multicast,
+    /// and `Err(Error::Exhausted)` if the socket has already joined
+    /// [UDP_MULTICAST_GROUP_COUNT](constant.UDP_MULTICAST_GROUP_COUNT.html) groups.
+    /// Joining a group more than once has no effect.
+    pub fn join_multicast_group<T: Into<IpAddress>>(&mut self, addr: T) -> Result<()> {
+        let addr = addr.into();
+        if !addr.is_multicast() { return Err(Error::Unaddressable) }
+        if self.has_multicast_group(addr) { return Ok(()) }
+
+        match self.multicast_groups.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                net_trace!("{}:{}: joining multicast group {}",
+                           self.meta.handle, self.endpoint, addr);
+                *slot = Some(addr);
+                Ok(())
+            }
+            None => Err(Error::Exhausted)
+        }
+    }
+
+    /// Leave the given multicast group, if it was joined.
+    pub fn leave_multicast_group<T: Into<IpAddress>>(&mut self, addr: T) {
+        let addr = addr.into();
+        for slot in self.multicast_groups.iter_mut() {
+            if *slot == Some(addr) { *slot = None }
+        }
+    }
+
+    /// Check whether the socket has joined the given multicast group.
+    pub fn has_multicast_group<T: Into<IpAddress>>(&self, addr: T) -> bool {
+        let addr = addr.into();
+        self.multicast_groups.iter().any(|probe| *probe == Some(addr))
+    }
+
+    /// Return the multicast groups joined by the socket.
+    pub(crate) fn multicast_groups(&self) -> &[Option<IpAddress>] {
+        &self.multicast_groups[..]
+    }
+
     /// Check whether the socket is open.
     #[inline]
     pub fn is_open(&self) -> bool {
@@ -152,22 +524,73 @@ impl<'a, 'b> UdpSocket<'a, 'b> {
         !self.rx_buffer.is_empty()
     }
 
+    /// Return the occupancy statistics of the transmit buffer, in packets.
+    pub fn send_buffer_stats(&self) -> BufferStats {
+        self.tx_buffer.stats()
+    }
+
+    /// Return the occupancy statistics of the receive buffer, in packets.
+    pub fn recv_buffer_stats(&self) -> BufferStats {
+        self.rx_buffer.stats()
+    }
+
+    /// Restart the occupancy statistics of both buffers from their current contents.
+    pub fn reset_buffer_stats(&mut self) {
+        self.tx_buffer.reset_stats();
+        self.rx_buffer.reset_stats();
+    }
+
     /// Enqueue a packet to be sent to a given remote endpoint, and return a pointer
     /// to its payload.
     ///
     /// This function returns `Err(Error::Exhausted)` if the transmit buffer is full,
     /// `Err(Error::Truncated)` if the requested size is larger than the packet buffer
-    /// size, and `Err(Error::Unaddressable)` if local or remote port, or remote address,
-    /// are unspecified.
+    /// size, `Err(Error::Unaddressable)` if local or remote port, or remote address,
+    /// are unspecified, and `Err(Error::Illegal)` if the remote address is the limited
+    /// broadcast address and broadcast is not enabled (see [set_broadcast]).
+    ///
+    /// Packets sent to the directed broadcast address of a subnet while broadcast is
+    /// not enabled are dropped by the interface.
+    ///
+    /// [set_broadcast]: #method.set_broadcast
     pub fn send(&mut self, size: usize, endpoint: IpEndpoint) -> Result<&mut [u8]> {
+        self.send_from(size, IpAddress::Unspecified, endpoint)
+    }
+
+    /// Enqueue a packet to be sent from a given local address to a given remote endpoint,
+    /// and return a pointer to its payload.
+    ///
+    /// This allows a socket bound to an unspecified address to choose the source address
+    /// of each packet, e.g. to reply from the address a request was sent to. The local
+    /// address should be one of the addresses of the interface; if it is unspecified,
+    /// the packet is sent from the bound address, or an address of the interface.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the socket is bound to another
+    /// address, and `Err(Error::Unaddressable)` if the local and remote addresses are
+    /// of different families. It otherwise behaves identically to [send](#method.send).
+    pub fn send_from(&mut self, size: usize, local_addr: IpAddress,
+                     endpoint: IpEndpoint) -> Result<&mut [u8]> {
         if self.endpoint.port == 0 { return Err(Error::Unaddressable) }
         if !endpoint.is_specified() { return Err(Error::Unaddressable) }
+        if endpoint.addr.is_broadcast() && !self.broadcast { return Err(Error::Illegal) }
+        if !local_addr.is_unspecified() {
+            if !self.endpoint.addr.is_unspecified() &&
+               self.endpoint.addr != local_addr { return Err(Error::Illegal) }
+            match (local_addr, endpoint.addr) {
+                #[cfg(feature = "proto-ipv4")]
+                (IpAddress::Ipv4(_), IpAddress::Ipv4(_)) => (),
+                #[cfg(feature = "proto-ipv6")]
+                (IpAddress::Ipv6(_), IpAddress::Ipv6(_)) => (),
+                _ => return Err(Error::Unaddressable)
+            }
+        }
 
         let packet_buf = self.tx_buffer.enqueue_one_with(|buf| buf.resize(size))?;
-        packet_buf.endpoint = endpoint;
+        packet_buf.metadata_mut().endpoint = endpoint;
+        packet_buf.metadata_mut().local_addr = local_addr;
         net_trace!("{}:{}:{}: buffer to send {} octets",
-                   self.meta.handle, self.endpoint, packet_buf.endpoint, size);
-        Ok(&mut packet_buf.as_mut()[..size])
+                   self.meta.handle, self.endpoint, endpoint, size);
+        Ok(packet_buf.payload_mut())
     }
 
     /// Enqueue a packet to be sent to a given remote endpoint, and fill it from a slice.
@@ -178,16 +601,142 @@ impl<'a, 'b> UdpSocket<'a, 'b> {
         Ok(())
     }
 
+    /// Enqueue a packet to be sent to a given remote endpoint, and fill it from several
+    /// slices in turn, e.g. `&[u8]` or `std::io::IoSlice`.
+    ///
+    /// See also [send](#method.send).
+    pub fn send_vectored<T>(&mut self, data: &[T], endpoint: IpEndpoint) -> Result<()>
+            where T: Deref<Target = [u8]> {
+        let size = data.iter().map(|slice| slice.len()).sum();
+        let mut buffer = self.send(size, endpoint)?;
+        for slice in data {
+            let (head, tail) = buffer.split_at_mut(slice.len());
+            head.copy_from_slice(slice);
+            buffer = tail;
+        }
+        Ok(())
+    }
+
+    /// Enqueue a packet to be sent from a given local address to a given remote endpoint,
+    /// and fill it from a slice.
+    ///
+    /// See also [send_from](#method.send_from).
+    pub fn send_slice_from(&mut self, data: &[u8], local_addr: IpAddress,
+                           endpoint: IpEndpoint) -> Result<()> {
+        self.send_from(data.len(), local_addr, endpoint)?.copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Enqueue a packet to be sent to the connected endpoint, and return a pointer
+    /// to its payload.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the socket is not connected
+    /// (see [connect](#method.connect)), and otherwise behaves identically to
+    /// [send](#method.send).
+    pub fn send_connected(&mut self, size: usize) -> Result<&mut [u8]> {
+        if !self.remote_endpoint.is_specified() { return Err(Error::Illegal) }
+
+        let remote_endpoint = self.remote_endpoint;
+        self.send(size, remote_endpoint)
+    }
+
+    /// Enqueue a packet to be sent to the connected endpoint, and fill it from a slice.
+    ///
+    /// See also [send_connected](#method.send_connected).
+    pub fn send_slice_connected(&mut self, data: &[u8]) -> Result<()> {
+        self.send_connected(data.len())?.copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Return the time at which the last packet was handed to the interface for
+    /// transmission, if any.
+    ///
+    /// Once the transmit buffer is empty, this is the time at which the last packet
+    /// enqueued was sent.
+    pub fn tx_timestamp(&self) -> Option<Instant> {
+        self.tx_timestamp.map(|timestamp| Instant::from_millis(timestamp as i64))
+    }
+
+    /// Return the hardware timestamp, in nanoseconds on the clock of the device,
+    /// of the last packet sent, if hardware timestamping is enabled.
+    ///
+    /// The timestamp is cleared when a packet is handed to the device, and set
+    /// once the device returns it, when the interface is polled later.
+    pub fn tx_hw_timestamp(&self) -> Option<u64> {
+        self.tx_hw_timestamp
+    }
+
     /// Dequeue a packet received from a remote endpoint, and return the endpoint as well
     /// as a pointer to the payload.
     ///
     /// This function returns `Err(Error::Exhausted)` if the receive buffer is empty.
+    /// If the socket is connected and an error was reported for the connection, e.g. with
+    /// an ICMP port unreachable message, this function returns `Err(Error::Unaddressable)`
+    /// once instead; see also [take_error](#method.take_error).
     pub fn recv(&mut self) -> Result<(&[u8], IpEndpoint)> {
-        let packet_buf = self.rx_buffer.dequeue_one()?;
+        let (buffer, meta) = self.recv_with_meta()?;
+        Ok((buffer, meta.endpoint))
+    }
+
+    /// Dequeue a packet received from a remote endpoint, and return its metadata as well
+    /// as a pointer to the payload.
+    ///
+    /// The metadata includes the destination address of the packet, which a socket bound
+    /// to an unspecified address may use to choose the source address of a reply,
+    /// the hop limit it arrived with, and the time at which it was received.
+    ///
+    /// This function otherwise behaves identically to [recv](#method.recv).
+    ///
+    /// If the payload was lent rather than copied, this function returns
+    /// `Err(Error::Illegal)`; see [recv_lent](#method.recv_lent).
+    pub fn recv_with_meta(&mut self) -> Result<(&[u8], PacketMetadata)> {
+        if self.error.take().is_some() {
+            return Err(Error::Unaddressable)
+        }
+
+        self.rx_buffer.release_dequeued();
+        let packet_buf = self.rx_buffer.dequeue_one_with(|packet_buf| {
+            match packet_buf.metadata().lent {
+                Some(_) => Err(Error::Illegal),
+                None => Ok(packet_buf)
+            }
+        })?;
         net_trace!("{}:{}:{}: receive {} buffered octets",
                    self.meta.handle, self.endpoint,
-                   packet_buf.endpoint, packet_buf.size);
-        Ok((&packet_buf.as_ref(), packet_buf.endpoint))
+                   packet_buf.metadata().endpoint, packet_buf.len());
+        Ok((packet_buf.payload(), *packet_buf.metadata()))
+    }
+
+    /// Dequeue a packet whose payload was lent rather than copied, and return its
+    /// metadata as well as the loan of the payload.
+    ///
+    /// If the payload was copied, this function returns `Err(Error::Illegal)`; see
+    /// [recv_with_meta](#method.recv_with_meta). See also [set_zero_copy](#method.set_zero_copy).
+    ///
+    /// This function otherwise behaves identically to [recv](#method.recv).
+    pub fn recv_lent(&mut self) -> Result<(RxLoan, PacketMetadata)> {
+        if self.error.take().is_some() {
+            return Err(Error::Unaddressable)
+        }
+
+        self.rx_buffer.release_dequeued();
+        let meta = self.rx_buffer.dequeue_one_with(|packet_buf| {
+            let meta = *packet_buf.metadata();
+            meta.lent.map(|loan| (loan, meta)).ok_or(Error::Illegal)
+        })?;
+        net_trace!("{}:{}:{}: receive {} lent octets",
+                   self.meta.handle, self.endpoint, meta.1.endpoint, meta.0.len());
+        Ok(meta)
+    }
+
+    /// Dequeue a packet received from a remote endpoint, and return the endpoint and
+    /// the time at which the packet was received by the interface, as well as a pointer
+    /// to the payload.
+    ///
+    /// This function otherwise behaves identically to [recv](#method.recv).
+    pub fn recv_with_timestamp(&mut self) -> Result<(&[u8], IpEndpoint, Instant)> {
+        let (buffer, meta) = self.recv_with_meta()?;
+        Ok((buffer, meta.endpoint, meta.timestamp))
     }
 
     /// Dequeue a packet received from a remote endpoint, copy the payload into the given slice,
@@ -201,50 +750,156 @@ impl<'a, 'b> UdpSocket<'a, 'b> {
         Ok((length, endpoint))
     }
 
+    /// Dequeue a packet received from a remote endpoint, copy the payload into several
+    /// slices in turn, e.g. `&mut [u8]` or `std::io::IoSliceMut`, and return the amount
+    /// of octets copied as well as the endpoint.
+    ///
+    /// See also [recv](#method.recv).
+    pub fn recv_vectored<T>(&mut self, data: &mut [T]) -> Result<(usize, IpEndpoint)>
+            where T: DerefMut<Target = [u8]> {
+        let (mut buffer, endpoint) = self.recv()?;
+        let mut size = 0;
+        for slice in data.iter_mut() {
+            let length = min(slice.len(), buffer.len());
+            slice[..length].copy_from_slice(&buffer[..length]);
+            buffer = &buffer[length..];
+            size += length;
+        }
+        Ok((size, endpoint))
+    }
+
+    /// Peek at a packet received from a remote endpoint, and return the endpoint as well
+    /// as a pointer to the payload, without removing the packet from the receive buffer.
+    ///
+    /// This function otherwise behaves identically to [recv](#method.recv).
+    pub fn peek(&mut self) -> Result<(&[u8], IpEndpoint)> {
+        let packet_buf = self.rx_buffer.peek_one()?;
+        if packet_buf.metadata().lent.is_some() { return Err(Error::Illegal) }
+        net_trace!("{}:{}:{}: peek {} buffered octets",
+                   self.meta.handle, self.endpoint,
+                   packet_buf.metadata().endpoint, packet_buf.len());
+        Ok((packet_buf.payload(), packet_buf.metadata().endpoint))
+    }
+
+    /// Peek at a packet received from a remote endpoint, copy the payload into the given slice,
+    /// and return the amount of octets copied as well as the endpoint, without removing
+    /// the packet from the receive buffer.
+    ///
+    /// This function otherwise behaves identically to [recv_slice](#method.recv_slice).
+    pub fn peek_slice(&mut self, data: &mut [u8]) -> Result<(usize, IpEndpoint)> {
+        let (buffer, endpoint) = self.peek()?;
+        let length = min(data.len(), buffer.len());
+        data[..length].copy_from_slice(&buffer[..length]);
+        Ok((length, endpoint))
+    }
+
     pub(crate) fn accepts(&self, ip_repr: &IpRepr, repr: &UdpRepr) -> bool {
         if self.endpoint.port != repr.dst_port { return false }
         if !self.endpoint.addr.is_unspecified() &&
            self.endpoint.addr != ip_repr.dst_addr() { return false }
+        if self.remote_endpoint.is_specified() &&
+           (self.remote_endpoint.addr != ip_repr.src_addr() ||
+            self.remote_endpoint.port != repr.src_port) { return false }
+
+        true
+    }
+
+    #[cfg(feature = "proto-ipv4")]
+    /// Check whether an error returned for a packet sent from `local_endpoint`
+    /// to `remote_endpoint` concerns the connection of this socket.
+    pub(crate) fn accepts_error(&self, local_endpoint: IpEndpoint,
+                                remote_endpoint: IpEndpoint) -> bool {
+        if !self.remote_endpoint.is_specified() { return false }
+        if self.remote_endpoint != remote_endpoint { return false }
+        if self.endpoint.port != local_endpoint.port { return false }
+        if !self.endpoint.addr.is_unspecified() &&
+           self.endpoint.addr != local_endpoint.addr { return false }
 
         true
     }
 
-    pub(crate) fn process(&mut self, ip_repr: &IpRepr, repr: &UdpRepr) -> Result<()> {
+    #[cfg(feature = "proto-ipv4")]
+    pub(crate) fn process_error(&mut self, error: SocketError) {
+        net_debug!("{}:{}:{}: {}",
+                   self.meta.handle, self.endpoint, self.remote_endpoint, error);
+        self.error = Some(error);
+        #[cfg(feature = "async")]
+        self.rx_waker.wake();
+    }
+
+    /// Process a packet, leaving the payload in the device if it is lent.
+    pub(crate) fn process_lent(&mut self, timestamp: u64, hw_timestamp: Option<u64>,
+                               ip_repr: &IpRepr, repr: &UdpRepr,
+                               lent: Option<RxLoan>) -> Result<()> {
         debug_assert!(self.accepts(ip_repr, repr));
 
-        let packet_buf = self.rx_buffer.enqueue_one_with(|buf| buf.resize(repr.payload.len()))?;
-        packet_buf.as_mut().copy_from_slice(repr.payload);
-        packet_buf.endpoint = IpEndpoint { addr: ip_repr.src_addr(), port: repr.src_port };
+        let size = if lent.is_some() { 0 } else { repr.payload.len() };
+        self.rx_buffer.release_dequeued();
+        let packet_buf = self.rx_buffer.enqueue_one_with(|buf| buf.resize(size))?;
+        if lent.is_none() {
+            packet_buf.payload_mut().copy_from_slice(repr.payload);
+        }
+        *packet_buf.metadata_mut() = PacketMetadata {
+            endpoint:   IpEndpoint { addr: ip_repr.src_addr(), port: repr.src_port },
+            local_addr: ip_repr.dst_addr(),
+            hop_limit:  ip_repr.hop_limit(),
+            timestamp:  Instant::from_millis(timestamp as i64),
+            hw_timestamp: hw_timestamp,
+            lent:       lent
+        };
         net_trace!("{}:{}:{}: receiving {} octets",
                    self.meta.handle, self.endpoint,
-                   packet_buf.endpoint, packet_buf.size);
+                   packet_buf.metadata().endpoint, packet_buf.len());
+        #[cfg(feature = "async")]
+        self.rx_waker.wake();
         Ok(())
     }
 
-    pub(crate) fn dispatch<F>(&mut self, emit: F) -> Result<()>
+    pub(crate) fn dispatch<F>(&mut self, timestamp: u64, emit: F) -> Result<()>
             where F: FnOnce((IpRepr, UdpRepr)) -> Result<()> {
         let handle   = self.handle();
         let endpoint = self.endpoint;
         let hop_limit = self.hop_limit.unwrap_or(64);
+        let dscp     = self.dscp;
+        let flow_label = self.flow_label;
         self.tx_buffer.dequeue_one_with(|packet_buf| {
+            let meta = *packet_buf.metadata();
             net_trace!("{}:{}:{}: sending {} octets",
                        handle, endpoint,
-                       packet_buf.endpoint, packet_buf.size);
+                       meta.endpoint, packet_buf.len());
 
             let repr = UdpRepr {
                 src_port: endpoint.port,
-                dst_port: packet_buf.endpoint.port,
-                payload:  &packet_buf.as_ref()[..]
+                dst_port: meta.endpoint.port,
+                payload:  packet_buf.payload()
+            };
+            let src_addr = if meta.local_addr.is_unspecified() {
+                endpoint.addr
+            } else {
+                meta.local_addr
             };
             let ip_repr = IpRepr::Unspecified {
-                src_addr:    endpoint.addr,
-                dst_addr:    packet_buf.endpoint.addr,
+                src_addr:    src_addr,
+                dst_addr:    meta.endpoint.addr,
                 protocol:    IpProtocol::Udp,
                 payload_len: repr.buffer_len(),
                 hop_limit:   hop_limit,
+                ecn:         0,
+                dscp:        dscp,
+                flow_label:  flow_label,
             };
             emit((ip_repr, repr))
-        })
+        })?;
+        self.tx_buffer.release_dequeued();
+        self.tx_timestamp = Some(timestamp);
+        if self.hw_timestamping { self.tx_hw_timestamp = None }
+        #[cfg(feature = "async")]
+        self.tx_waker.wake();
+        Ok(())
+    }
+
+    pub(crate) fn set_tx_hw_timestamp(&mut self, hw_timestamp: u64) {
+        self.tx_hw_timestamp = Some(hw_timestamp)
     }
 
     pub(crate) fn poll_at(&self) -> Option<u64> {
@@ -256,6 +911,141 @@ impl<'a, 'b> UdpSocket<'a, 'b> {
     }
 }
 
+/// A builder structure used for creating an UDP socket.
+///
+/// Unlike the setters of a socket, the builder checks the options for consistency
+/// with each other once they are all known.
+///
+/// # Examples
+///
+/// ```
+/// use smoltcp::socket::{UdpSocket, UdpSocketBuffer, UdpPacketBuffer};
+///
+/// let socket = UdpSocket::builder()
+///     .rx_buffer(UdpSocketBuffer::new(vec![UdpPacketBuffer::new(vec![0; 512])]))
+///     .tx_buffer(UdpSocketBuffer::new(vec![UdpPacketBuffer::new(vec![0; 512])]))
+///     .endpoint(68)
+///     .broadcast(true)
+///     .finalize();
+/// ```
+#[derive(Debug)]
+pub struct UdpSocketBuilder<'a, 'b: 'a> {
+    rx_buffer: Option<SocketBuffer<'a, 'b>>,
+    tx_buffer: Option<SocketBuffer<'a, 'b>>,
+    endpoint:  Option<IpEndpoint>,
+    hop_limit: Option<u8>,
+    dscp:      u8,
+    flow_label: u32,
+    broadcast: bool,
+    zero_copy: bool,
+}
+
+impl<'a, 'b> UdpSocketBuilder<'a, 'b> {
+    /// Create a builder used for creating an UDP socket, with the defaults
+    /// of [UdpSocket::new](struct.UdpSocket.html#method.new).
+    pub fn new() -> UdpSocketBuilder<'a, 'b> {
+        UdpSocketBuilder {
+            rx_buffer: None,
+            tx_buffer: None,
+            endpoint:  None,
+            hop_limit: None,
+            dscp:      0,
+            flow_label: 0,
+            broadcast: false,
+            zero_copy: false,
+        }
+    }
+
+    /// Set the receive buffer. It is required, and must hold at least one packet.
+    pub fn rx_buffer(mut self, buffer: SocketBuffer<'a, 'b>) -> Self {
+        self.rx_buffer = Some(buffer);
+        self
+    }
+
+    /// Set the transmit buffer. It is required.
+    pub fn tx_buffer(mut self, buffer: SocketBuffer<'a, 'b>) -> Self {
+        self.tx_buffer = Some(buffer);
+        self
+    }
+
+    /// Set the endpoint the socket is bound to.
+    ///
+    /// See also [UdpSocket::bind](struct.UdpSocket.html#method.bind).
+    pub fn endpoint<T: Into<IpEndpoint>>(mut self, endpoint: T) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Set the time-to-live (IPv4) or hop limit (IPv6) value used in outgoing packets.
+    ///
+    /// See also [UdpSocket::set_hop_limit](struct.UdpSocket.html#method.set_hop_limit).
+    pub fn hop_limit(mut self, hop_limit: Option<u8>) -> Self {
+        self.hop_limit = hop_limit;
+        self
+    }
+
+    /// Set the Differentiated Services Code Point used in outgoing packets.
+    ///
+    /// See also [UdpSocket::set_dscp](struct.UdpSocket.html#method.set_dscp).
+    pub fn dscp(mut self, dscp: u8) -> Self {
+        self.dscp = dscp;
+        self
+    }
+
+    /// Set the IPv6 flow label used in outgoing packets.
+    ///
+    /// See also [UdpSocket::set_flow_label](struct.UdpSocket.html#method.set_flow_label).
+    pub fn flow_label(mut self, flow_label: u32) -> Self {
+        self.flow_label = flow_label;
+        self
+    }
+
+    /// Set whether broadcast packets may be sent and received.
+    ///
+    /// See also [UdpSocket::set_broadcast](struct.UdpSocket.html#method.set_broadcast).
+    pub fn broadcast(mut self, broadcast: bool) -> Self {
+        self.broadcast = broadcast;
+        self
+    }
+
+    /// Set whether payloads are received without being copied when possible.
+    ///
+    /// See also [UdpSocket::set_zero_copy](struct.UdpSocket.html#method.set_zero_copy).
+    pub fn zero_copy(mut self, zero_copy: bool) -> Self {
+        self.zero_copy = zero_copy;
+        self
+    }
+
+    /// Create an UDP socket with the options set.
+    ///
+    /// # Panics
+    /// This function panics if a buffer was not set, if the receive buffer cannot hold
+    /// any packets, if the port of the endpoint is zero, or if any option is invalid
+    /// on its own, e.g. a zero hop limit.
+    pub fn finalize(self) -> UdpSocket<'a, 'b> {
+        let (rx_buffer, tx_buffer) = match (self.rx_buffer, self.tx_buffer) {
+            (Some(rx_buffer), Some(tx_buffer)) => (rx_buffer, tx_buffer),
+            _ => panic!("a required option was not set")
+        };
+        if rx_buffer.max_capacity() == 0 {
+            panic!("the receive buffer must not be empty")
+        }
+
+        let mut socket = UdpSocket::new(rx_buffer, tx_buffer);
+        if let Some(endpoint) = self.endpoint {
+            if socket.bind(endpoint).is_err() {
+                panic!("the port of the endpoint must not be zero")
+            }
+        }
+        socket.set_hop_limit(self.hop_limit);
+        socket.set_dscp(self.dscp);
+        socket.set_flow_label(self.flow_label);
+        socket.set_broadcast(self.broadcast);
+        socket.set_zero_copy(self.zero_copy);
+        socket
+    }
+}
+
 impl<'a, 'b> Into<Socket<'a, 'b>> for UdpSocket<'a, 'b> {
     fn into(self) -> Socket<'a, 'b> {
         Socket::Udp(self)
@@ -298,6 +1088,9 @@ mod test {
         protocol: IpProtocol::Udp,
         payload_len: 8 + 6,
         hop_limit: 64,
+        ecn: 0,
+        dscp: 0,
+        flow_label: 0,
     };
 
     const LOCAL_UDP_REPR: UdpRepr = UdpRepr {
@@ -320,7 +1113,9 @@ mod test {
                 dst_addr: dst,
                 protocol: IpProtocol::Udp,
                 payload_len: 8 + 6,
-                hop_limit: 64
+                hop_limit: 64,
+                ecn: 0,
+                dscp: 0
             }),
             #[cfg(feature = "proto-ipv6")]
             (IpAddress::Ipv6(src), IpAddress::Ipv6(dst)) => IpRepr::Ipv6(Ipv6Repr {
@@ -328,7 +1123,10 @@ mod test {
                 dst_addr: dst,
                 next_header: IpProtocol::Udp,
                 payload_len: 8 + 6,
-                hop_limit: 64
+                hop_limit: 64,
+                ecn: 0,
+                dscp: 0,
+                flow_label: 0
             }),
             _ => unreachable!()
         }
@@ -382,21 +1180,21 @@ mod test {
         assert_eq!(socket.bind(LOCAL_END), Ok(()));
 
         assert!(socket.can_send());
-        assert_eq!(socket.dispatch(|_| unreachable!()),
+        assert_eq!(socket.dispatch(0, |_| unreachable!()),
                    Err(Error::Exhausted));
 
         assert_eq!(socket.send_slice(b"abcdef", REMOTE_END), Ok(()));
         assert_eq!(socket.send_slice(b"123456", REMOTE_END), Err(Error::Exhausted));
         assert!(!socket.can_send());
 
-        assert_eq!(socket.dispatch(|(ip_repr, udp_repr)| {
+        assert_eq!(socket.dispatch(0, |(ip_repr, udp_repr)| {
             assert_eq!(ip_repr, LOCAL_IP_REPR);
             assert_eq!(udp_repr, LOCAL_UDP_REPR);
             Err(Error::Unaddressable)
         }), Err(Error::Unaddressable));
         assert!(!socket.can_send());
 
-        assert_eq!(socket.dispatch(|(ip_repr, udp_repr)| {
+        assert_eq!(socket.dispatch(0, |(ip_repr, udp_repr)| {
             assert_eq!(ip_repr, LOCAL_IP_REPR);
             assert_eq!(udp_repr, LOCAL_UDP_REPR);
             Ok(())
@@ -404,6 +1202,73 @@ mod test {
         assert!(socket.can_send());
     }
 
+    #[test]
+    fn test_send_from() {
+        let mut socket = socket(buffer(0), buffer(1));
+        assert_eq!(socket.bind(LOCAL_PORT), Ok(()));
+
+        assert_eq!(socket.send_slice_from(b"abcdef", MOCK_IP_ADDR_3, REMOTE_END), Ok(()));
+        assert_eq!(socket.dispatch(0, |(ip_repr, udp_repr)| {
+            assert_eq!(ip_repr.src_addr(), MOCK_IP_ADDR_3);
+            assert_eq!(ip_repr.dst_addr(), MOCK_IP_ADDR_2);
+            assert_eq!(udp_repr, LOCAL_UDP_REPR);
+            Ok(())
+        }), Ok(()));
+
+        // The source address of a later packet does not stick.
+        assert_eq!(socket.send_slice(b"abcdef", REMOTE_END), Ok(()));
+        assert_eq!(socket.dispatch(0, |(ip_repr, _)| {
+            assert!(ip_repr.src_addr().is_unspecified());
+            Ok(())
+        }), Ok(()));
+    }
+
+    #[test]
+    fn test_send_from_bound() {
+        let mut socket = socket(buffer(0), buffer(1));
+        assert_eq!(socket.bind(LOCAL_END), Ok(()));
+
+        assert_eq!(socket.send_slice_from(b"abcdef", MOCK_IP_ADDR_3, REMOTE_END),
+                   Err(Error::Illegal));
+        assert_eq!(socket.send_slice_from(b"abcdef", MOCK_IP_ADDR_1, REMOTE_END), Ok(()));
+        assert_eq!(socket.dispatch(0, |(ip_repr, udp_repr)| {
+            assert_eq!(ip_repr, LOCAL_IP_REPR);
+            assert_eq!(udp_repr, LOCAL_UDP_REPR);
+            Ok(())
+        }), Ok(()));
+    }
+
+    #[test]
+    fn test_rebind() {
+        let mut socket = socket(buffer(0), buffer(2));
+        assert_eq!(socket.rebind(MOCK_IP_ADDR_3), Err(Error::Illegal));
+        assert_eq!(socket.bind(LOCAL_END), Ok(()));
+        assert_eq!(socket.send_slice_from(b"abcdef", MOCK_IP_ADDR_1, REMOTE_END), Ok(()));
+        assert_eq!(socket.send_slice(b"abcdef", REMOTE_END), Ok(()));
+
+        assert_eq!(socket.rebind(MOCK_IP_ADDR_3), Ok(()));
+        assert_eq!(socket.endpoint(), IpEndpoint::new(MOCK_IP_ADDR_3, LOCAL_PORT));
+        assert_eq!(socket.take_migration(),
+                   Some(Migration { from: MOCK_IP_ADDR_1, to: MOCK_IP_ADDR_3 }));
+        assert_eq!(socket.take_migration(), None);
+
+        // The queued packets are sent from the new address.
+        for _ in 0..2 {
+            assert_eq!(socket.dispatch(0, |(ip_repr, udp_repr)| {
+                assert_eq!(ip_repr.src_addr(), MOCK_IP_ADDR_3);
+                assert_eq!(udp_repr, LOCAL_UDP_REPR);
+                Ok(())
+            }), Ok(()));
+        }
+        assert_eq!(socket.send_slice_from(b"abcdef", MOCK_IP_ADDR_1, REMOTE_END),
+                   Err(Error::Illegal));
+
+        // Moving back and forth is not a change.
+        assert_eq!(socket.rebind(MOCK_IP_ADDR_1), Ok(()));
+        assert_eq!(socket.rebind(MOCK_IP_ADDR_3), Ok(()));
+        assert_eq!(socket.take_migration(), None);
+    }
+
     #[test]
     fn test_recv_process() {
         let mut socket = socket(buffer(1), buffer(0));
@@ -413,24 +1278,289 @@ mod test {
         assert_eq!(socket.recv(), Err(Error::Exhausted));
 
         assert!(socket.accepts(&remote_ip_repr(), &REMOTE_UDP_REPR));
-        assert_eq!(socket.process(&remote_ip_repr(), &REMOTE_UDP_REPR),
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
                    Ok(()));
         assert!(socket.can_recv());
 
         assert!(socket.accepts(&remote_ip_repr(), &REMOTE_UDP_REPR));
-        assert_eq!(socket.process(&remote_ip_repr(), &REMOTE_UDP_REPR),
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
                    Err(Error::Exhausted));
         assert_eq!(socket.recv(), Ok((&b"abcdef"[..], REMOTE_END)));
         assert!(!socket.can_recv());
     }
 
+    #[test]
+    fn test_buffer_stats() {
+        let mut socket = socket(buffer(2), buffer(1));
+        assert_eq!(socket.bind(LOCAL_PORT), Ok(()));
+
+        for _ in 0..3 {
+            let _ = socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None);
+        }
+        assert_eq!(socket.recv(), Ok((&b"abcdef"[..], REMOTE_END)));
+        assert_eq!(socket.recv_buffer_stats(), BufferStats {
+            len:            1,
+            capacity:       2,
+            high_watermark: 2,
+            overflows:      1,
+        });
+        assert_eq!(socket.send_buffer_stats(), BufferStats { capacity: 1,
+                                                            ..BufferStats::default() });
+
+        socket.reset_buffer_stats();
+        assert_eq!(socket.recv_buffer_stats(), BufferStats { len: 1, capacity: 2,
+                                                            high_watermark: 1,
+                                                            overflows: 0 });
+    }
+
+    #[test]
+    fn test_send_recv_vectored() {
+        let mut socket = socket(buffer(1), buffer(1));
+        assert_eq!(socket.bind(LOCAL_END), Ok(()));
+
+        assert_eq!(socket.send_vectored(&[&b"abc"[..], &b""[..], &b"def"[..]], REMOTE_END), Ok(()));
+        assert_eq!(socket.dispatch(0, |(_, udp_repr)| {
+            assert_eq!(udp_repr, LOCAL_UDP_REPR);
+            Ok(())
+        }), Ok(()));
+        assert_eq!(socket.send_vectored(&[&[0; 10][..], &[0; 10][..]], REMOTE_END),
+                   Err(Error::Truncated));
+
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
+                   Ok(()));
+        let (mut header, mut body) = ([0; 2], [0; 8]);
+        assert_eq!(socket.recv_vectored(&mut [&mut header[..], &mut body[..]]),
+                   Ok((6, REMOTE_END)));
+        assert_eq!(&header, b"ab");
+        assert_eq!(&body[..4], b"cdef");
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn test_wakers() {
+        use socket::waker::test::waker;
+
+        let mut socket = socket(buffer(1), buffer(1));
+        assert_eq!(socket.bind(LOCAL_END), Ok(()));
+        let (rx_waker, rx_counter) = waker();
+        let (tx_waker, tx_counter) = waker();
+        socket.register_recv_waker(&rx_waker);
+        socket.register_send_waker(&tx_waker);
+
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
+                   Ok(()));
+        assert_eq!((rx_counter.count(), tx_counter.count()), (1, 0));
+
+        assert_eq!(socket.send_slice(b"abcdef", REMOTE_END), Ok(()));
+        assert_eq!(socket.dispatch(0, |_| Err(Error::Unaddressable)),
+                   Err(Error::Unaddressable));
+        assert_eq!(tx_counter.count(), 0);
+        assert_eq!(socket.dispatch(0, |_| Ok(())), Ok(()));
+        assert_eq!((rx_counter.count(), tx_counter.count()), (1, 1));
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let mut socket = socket(buffer(1), buffer(1));
+        assert_eq!(socket.bind(LOCAL_END), Ok(()));
+
+        assert_eq!(socket.process_lent(1_000, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
+                   Ok(()));
+        assert_eq!(socket.recv_with_timestamp(),
+                   Ok((&b"abcdef"[..], REMOTE_END, Instant::from_millis(1_000))));
+
+        assert_eq!(socket.tx_timestamp(), None);
+        assert_eq!(socket.send_slice(b"abcdef", REMOTE_END), Ok(()));
+        assert_eq!(socket.dispatch(2_000, |_| Err(Error::Unaddressable)),
+                   Err(Error::Unaddressable));
+        assert_eq!(socket.tx_timestamp(), None);
+        assert_eq!(socket.dispatch(3_000, |_| Ok(())), Ok(()));
+        assert_eq!(socket.tx_timestamp(), Some(Instant::from_millis(3_000)));
+    }
+
+    #[test]
+    fn test_hw_timestamps() {
+        let mut socket = socket(buffer(1), buffer(1));
+        assert_eq!(socket.bind(LOCAL_END), Ok(()));
+
+        assert_eq!(socket.process_lent(1_000, Some(1_000_000_123), &remote_ip_repr(),
+                                       &REMOTE_UDP_REPR, None), Ok(()));
+        assert_eq!(socket.recv_with_meta().unwrap().1.hw_timestamp, Some(1_000_000_123));
+
+        socket.set_hw_timestamping(true);
+        socket.set_tx_hw_timestamp(1);
+        assert_eq!(socket.send_slice(b"abcdef", REMOTE_END), Ok(()));
+        assert_eq!(socket.dispatch(2_000, |_| Ok(())), Ok(()));
+        assert_eq!(socket.tx_hw_timestamp(), None);
+        socket.set_tx_hw_timestamp(2_000_000_456);
+        assert_eq!(socket.tx_hw_timestamp(), Some(2_000_000_456));
+    }
+
+    #[test]
+    fn test_connect() {
+        let mut socket = socket(buffer(1), buffer(1));
+        assert_eq!(socket.connect(REMOTE_END), Err(Error::Illegal));
+        assert_eq!(socket.bind(LOCAL_PORT), Ok(()));
+        assert_eq!(socket.send_slice_connected(b"abcdef"), Err(Error::Illegal));
+        assert_eq!(socket.connect(IpEndpoint { port: 0, ..REMOTE_END }),
+                   Err(Error::Unaddressable));
+        assert_eq!(socket.connect(REMOTE_END), Ok(()));
+        assert_eq!(socket.remote_endpoint(), REMOTE_END);
+
+        assert_eq!(socket.send_slice_connected(b"abcdef"), Ok(()));
+        assert_eq!(socket.dispatch(0, |(ip_repr, udp_repr)| {
+            assert_eq!(ip_repr.dst_addr(), REMOTE_END.addr);
+            assert_eq!(udp_repr.dst_port, REMOTE_END.port);
+            Ok(())
+        }), Ok(()));
+    }
+
+    #[test]
+    fn test_close() {
+        let mut socket = socket(buffer(1), buffer(1));
+        assert_eq!(socket.bind(LOCAL_PORT), Ok(()));
+        assert_eq!(socket.connect(REMOTE_END), Ok(()));
+        assert_eq!(socket.send_slice_connected(b"abcdef"), Ok(()));
+        socket.close();
+        assert!(!socket.is_open());
+        assert_eq!(socket.remote_endpoint(), IpEndpoint::default());
+        assert!(socket.can_send());
+        assert_eq!(socket.bind(LOCAL_PORT), Ok(()));
+    }
+
+    #[test]
+    fn test_resize_buffers() {
+        let mut socket = socket(buffer(1), buffer(1));
+        assert_eq!(socket.resize_recv_buffer(2, || PacketBuffer::new(vec![0; 16])), Ok(()));
+        assert_eq!(socket.resize_send_buffer(0, || unreachable!()), Ok(()));
+        assert!(!socket.can_send());
+
+        assert_eq!(socket.bind(LOCAL_PORT), Ok(()));
+        assert_eq!(socket.resize_send_buffer(1, || PacketBuffer::new(vec![0; 16])),
+                   Err(Error::Illegal));
+
+        assert!(socket.accepts(&remote_ip_repr(), &REMOTE_UDP_REPR));
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
+                   Ok(()));
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
+                   Ok(()));
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
+                   Err(Error::Exhausted));
+    }
+
+    #[test]
+    fn test_growable_buffers() {
+        let mut socket = socket(SocketBuffer::growable(1, 2, || PacketBuffer::growable(16)),
+                                SocketBuffer::growable(0, 1, || PacketBuffer::growable(4)));
+        assert_eq!(socket.bind(LOCAL_PORT), Ok(()));
+
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
+                   Ok(()));
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
+                   Err(Error::Exhausted));
+        assert_eq!(socket.recv(), Ok((&b"abcdef"[..], REMOTE_END)));
+        // The buffer ran full, and grows once it is empty.
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
+                   Ok(()));
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
+                   Ok(()));
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
+                   Err(Error::Exhausted));
+
+        // The packet buffers do not grow beyond their limit.
+        assert_eq!(socket.send_slice(b"abcd", REMOTE_END), Ok(()));
+        assert_eq!(socket.dispatch(0, |_| Ok(())), Ok(()));
+        assert_eq!(socket.send_slice(b"abcdef", REMOTE_END), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn test_pooled_buffers() {
+        use storage::PacketPool;
+
+        let mut storage = [0; 32];
+        let mut chunks = [None, None];
+        let pool = PacketPool::new(&mut storage[..], 16, &mut chunks[..]);
+        let (rx_quota, tx_quota) = (pool.quota(2), pool.quota(1));
+        let mut rx_packets = [PacketBuffer::pooled(&rx_quota), PacketBuffer::pooled(&rx_quota),
+                              PacketBuffer::pooled(&rx_quota)];
+        let mut tx_packets = [PacketBuffer::pooled(&tx_quota)];
+        {
+            let mut socket = UdpSocket::new(SocketBuffer::new(&mut rx_packets[..]),
+                                            SocketBuffer::new(&mut tx_packets[..]));
+            assert_eq!(socket.bind(LOCAL_PORT), Ok(()));
+
+            assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
+                       Ok(()));
+            assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
+                       Ok(()));
+            // The quota is used up, although there is room for another packet.
+            assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
+                       Err(Error::Exhausted));
+            assert_eq!(socket.send_slice(b"abcd", REMOTE_END), Err(Error::Exhausted));
+
+            assert_eq!(socket.recv(), Ok((&b"abcdef"[..], REMOTE_END)));
+            assert_eq!(socket.recv(), Ok((&b"abcdef"[..], REMOTE_END)));
+            assert_eq!(pool.available(), 1);
+            assert_eq!(socket.send_slice(b"abcd", REMOTE_END), Ok(()));
+            assert_eq!(socket.dispatch(0, |_| Ok(())), Ok(()));
+            assert_eq!(tx_quota.used(), 0);
+        }
+        drop(rx_packets);
+        assert_eq!(pool.available(), 2);
+    }
+
+    #[test]
+    fn test_connect_filter() {
+        let mut socket = socket(buffer(1), buffer(0));
+        assert_eq!(socket.bind(LOCAL_PORT), Ok(()));
+        assert_eq!(socket.connect(IpEndpoint { port: REMOTE_PORT + 1, ..REMOTE_END }), Ok(()));
+        assert!(!socket.accepts(&remote_ip_repr(), &REMOTE_UDP_REPR));
+        assert_eq!(socket.connect(REMOTE_END), Ok(()));
+        assert!(socket.accepts(&remote_ip_repr(), &REMOTE_UDP_REPR));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_connect_unreachable() {
+        let mut socket = socket(buffer(1), buffer(0));
+        assert_eq!(socket.bind(LOCAL_PORT), Ok(()));
+        assert!(!socket.accepts_error(LOCAL_END, REMOTE_END));
+        assert_eq!(socket.connect(REMOTE_END), Ok(()));
+        assert!(socket.accepts_error(LOCAL_END, REMOTE_END));
+        assert!(!socket.accepts_error(LOCAL_END,
+                                      IpEndpoint { port: REMOTE_PORT + 1, ..REMOTE_END }));
+
+        socket.process_error(SocketError::PortUnreachable);
+        assert_eq!(socket.recv(), Err(Error::Unaddressable));
+        assert_eq!(socket.recv(), Err(Error::Exhausted));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_take_error() {
+        let mut socket = socket(buffer(1), buffer(0));
+        assert_eq!(socket.bind(LOCAL_PORT), Ok(()));
+        assert_eq!(socket.connect(REMOTE_END), Ok(()));
+        assert_eq!(socket.take_error(), None);
+
+        socket.process_error(SocketError::TimeExceeded);
+        assert_eq!(socket.take_error(), Some(SocketError::TimeExceeded));
+        assert_eq!(socket.take_error(), None);
+        assert_eq!(socket.recv(), Err(Error::Exhausted));
+
+        // Connecting again discards the error.
+        socket.process_error(SocketError::HostUnreachable);
+        assert_eq!(socket.connect(REMOTE_END), Ok(()));
+        assert_eq!(socket.take_error(), None);
+    }
+
     #[test]
     fn test_recv_truncated_slice() {
         let mut socket = socket(buffer(1), buffer(0));
         assert_eq!(socket.bind(LOCAL_PORT), Ok(()));
 
         assert!(socket.accepts(&remote_ip_repr(), &REMOTE_UDP_REPR));
-        assert_eq!(socket.process(&remote_ip_repr(), &REMOTE_UDP_REPR),
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
                    Ok(()));
 
         let mut slice = [0; 4];
@@ -438,6 +1568,66 @@ mod test {
         assert_eq!(&slice, b"abcd");
     }
 
+    #[test]
+    fn test_recv_with_meta() {
+        let mut socket = socket(buffer(1), buffer(0));
+        assert_eq!(socket.bind(LOCAL_PORT), Ok(()));
+
+        assert_eq!(socket.recv_with_meta(), Err(Error::Exhausted));
+
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
+                   Ok(()));
+        assert_eq!(socket.recv_with_meta(), Ok((&b"abcdef"[..], PacketMetadata {
+            endpoint:   REMOTE_END,
+            local_addr: MOCK_IP_ADDR_1,
+            hop_limit:  64,
+            timestamp:  Instant::from_millis(0),
+            hw_timestamp: None,
+            lent:       None
+        })));
+    }
+
+    #[test]
+    fn test_recv_lent() {
+        let mut socket = socket(buffer(2), buffer(0));
+        assert_eq!(socket.bind(LOCAL_PORT), Ok(()));
+        socket.set_zero_copy(true);
+
+        // The payload of a lent packet does not need to fit in the buffer.
+        let loan = RxLoan::new(7, 1500).narrow(42, 100);
+        let udp_repr = UdpRepr { payload: &[0; 100][..], ..REMOTE_UDP_REPR };
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &udp_repr, Some(loan)),
+                   Ok(()));
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
+                   Ok(()));
+
+        assert_eq!(socket.peek(), Err(Error::Illegal));
+        assert_eq!(socket.recv(), Err(Error::Illegal));
+        let (lent, meta) = socket.recv_lent().unwrap();
+        assert_eq!((lent.id(), lent.offset(), lent.len()), (7, 42, 100));
+        assert_eq!(meta.endpoint, REMOTE_END);
+
+        assert_eq!(socket.recv_lent(), Err(Error::Illegal));
+        assert_eq!(socket.recv(), Ok((&b"abcdef"[..], REMOTE_END)));
+    }
+
+    #[test]
+    fn test_peek_process() {
+        let mut socket = socket(buffer(1), buffer(0));
+        assert_eq!(socket.bind(LOCAL_PORT), Ok(()));
+
+        assert_eq!(socket.peek(), Err(Error::Exhausted));
+
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &REMOTE_UDP_REPR, None),
+                   Ok(()));
+        assert_eq!(socket.peek(), Ok((&b"abcdef"[..], REMOTE_END)));
+        let mut slice = [0; 4];
+        assert_eq!(socket.peek_slice(&mut slice[..]), Ok((4, REMOTE_END)));
+        assert_eq!(&slice, b"abcd");
+        assert_eq!(socket.recv(), Ok((&b"abcdef"[..], REMOTE_END)));
+        assert_eq!(socket.peek(), Err(Error::Exhausted));
+    }
+
     #[test]
     fn test_recv_truncated_packet() {
         let mut socket = socket(buffer(1), buffer(0));
@@ -445,7 +1635,7 @@ mod test {
 
         let udp_repr = UdpRepr { payload: &[0; 100][..], ..REMOTE_UDP_REPR };
         assert!(socket.accepts(&remote_ip_repr(), &udp_repr));
-        assert_eq!(socket.process(&remote_ip_repr(), &udp_repr),
+        assert_eq!(socket.process_lent(0, None, &remote_ip_repr(), &udp_repr, None),
                    Err(Error::Truncated));
     }
 
@@ -456,18 +1646,132 @@ mod test {
 
         s.set_hop_limit(Some(0x2a));
         assert_eq!(s.send_slice(b"abcdef", REMOTE_END), Ok(()));
-        assert_eq!(s.dispatch(|(ip_repr, _)| {
+        assert_eq!(s.dispatch(0, |(ip_repr, _)| {
             assert_eq!(ip_repr, IpRepr::Unspecified{
                 src_addr: MOCK_IP_ADDR_1,
                 dst_addr: MOCK_IP_ADDR_2,
                 protocol: IpProtocol::Udp,
                 payload_len: 8 + 6,
                 hop_limit: 0x2a,
+                ecn: 0,
+                dscp: 0,
+                flow_label: 0,
             });
             Ok(())
         }), Ok(()));
     }
 
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_multicast_groups() {
+        let mut s = socket(buffer(0), buffer(0));
+        let groups = [
+            IpAddress::v4(224, 0, 0, 1), IpAddress::v4(224, 0, 0, 2),
+            IpAddress::v4(224, 0, 0, 3), IpAddress::v4(224, 0, 0, 4),
+        ];
+        assert_eq!(s.join_multicast_group(IpAddress::v4(10, 0, 0, 1)),
+                   Err(Error::Unaddressable));
+        for &group in &groups[..] {
+            assert_eq!(s.join_multicast_group(group), Ok(()));
+        }
+        assert_eq!(s.join_multicast_group(groups[0]), Ok(()));
+        assert_eq!(s.join_multicast_group(IpAddress::v4(224, 0, 0, 5)),
+                   Err(Error::Exhausted));
+        assert!(s.has_multicast_group(groups[1]));
+
+        s.leave_multicast_group(groups[1]);
+        assert!(!s.has_multicast_group(groups[1]));
+        assert_eq!(s.join_multicast_group(IpAddress::v4(224, 0, 0, 5)), Ok(()));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_send_broadcast() {
+        use wire::Ipv4Address;
+
+        let mut s = socket(buffer(0), buffer(1));
+        assert_eq!(s.bind(LOCAL_PORT), Ok(()));
+
+        let broadcast = IpEndpoint::new(Ipv4Address::BROADCAST.into(), REMOTE_PORT);
+        assert_eq!(s.send_slice(b"abcdef", broadcast), Err(Error::Illegal));
+        s.set_broadcast(true);
+        assert_eq!(s.send_slice(b"abcdef", broadcast), Ok(()));
+    }
+
+    #[test]
+    fn test_set_dscp() {
+        let mut s = socket(buffer(0), buffer(1));
+        assert_eq!(s.bind(LOCAL_END), Ok(()));
+
+        s.set_dscp(46);
+        assert_eq!(s.send_slice(b"abcdef", REMOTE_END), Ok(()));
+        assert_eq!(s.dispatch(0, |(ip_repr, _)| {
+            assert_eq!(ip_repr.dscp(), 46);
+            Ok(())
+        }), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "the DSCP value of a packet must fit in six bits")]
+    fn test_set_dscp_too_large() {
+        let mut s = socket(buffer(0), buffer(1));
+        s.set_dscp(64);
+    }
+
+    #[test]
+    fn test_set_flow_label() {
+        let mut s = socket(buffer(0), buffer(1));
+        assert_eq!(s.bind(LOCAL_END), Ok(()));
+
+        s.set_flow_label(0x12345);
+        assert_eq!(s.send_slice(b"abcdef", REMOTE_END), Ok(()));
+        assert_eq!(s.dispatch(0, |(ip_repr, _)| {
+            assert_eq!(ip_repr.flow_label(), 0x12345);
+            Ok(())
+        }), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "the flow label of a packet must fit in twenty bits")]
+    fn test_set_flow_label_too_large() {
+        let mut s = socket(buffer(0), buffer(1));
+        s.set_flow_label(0x100000);
+    }
+
+    #[test]
+    fn test_builder() {
+        let s = UdpSocket::builder()
+            .rx_buffer(buffer(1))
+            .tx_buffer(buffer(1))
+            .endpoint(LOCAL_END)
+            .hop_limit(Some(32))
+            .broadcast(true)
+            .finalize();
+        assert_eq!(s.endpoint(), LOCAL_END);
+        assert_eq!(s.hop_limit(), Some(32));
+        assert_eq!(s.broadcast(), true);
+        assert_eq!(s.zero_copy(), false);
+    }
+
+    #[test]
+    #[should_panic(expected = "the receive buffer must not be empty")]
+    fn test_builder_empty_rx_buffer() {
+        UdpSocket::builder()
+            .rx_buffer(buffer(0))
+            .tx_buffer(buffer(1))
+            .finalize();
+    }
+
+    #[test]
+    #[should_panic(expected = "the port of the endpoint must not be zero")]
+    fn test_builder_zero_port() {
+        UdpSocket::builder()
+            .rx_buffer(buffer(1))
+            .tx_buffer(buffer(1))
+            .endpoint(IpEndpoint::new(MOCK_IP_ADDR_1, 0))
+            .finalize();
+    }
+
     #[test]
     fn test_doesnt_accept_wrong_port() {
         let mut socket = socket(buffer(1), buffer(0));
@@ -489,7 +1793,9 @@ mod test {
                     dst_addr: dst,
                     protocol: IpProtocol::Udp,
                     payload_len: 8 + 6,
-                    hop_limit: 64
+                    hop_limit: 64,
+                    ecn: 0,
+                    dscp: 0
                 }),
                 #[cfg(feature = "proto-ipv6")]
                 (IpAddress::Ipv6(src), IpAddress::Ipv6(dst)) => IpRepr::Ipv6(Ipv6Repr {
@@ -497,7 +1803,10 @@ mod test {
                     dst_addr: dst,
                     next_header: IpProtocol::Udp,
                     payload_len: 8 + 6,
-                    hop_limit: 64
+                    hop_limit: 64,
+                    ecn: 0,
+                    dscp: 0,
+                    flow_label: 0
                 }),
                 _ => unreachable!()
             }
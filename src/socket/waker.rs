@@ -0,0 +1,92 @@
+use core::task::Waker;
+
+/// A slot for the waker of a task waiting on a socket.
+///
+/// At most one waker is registered at a time; it is woken at most once, and has to be
+/// registered again afterwards.
+#[derive(Debug, Default)]
+pub struct WakerRegistration {
+    waker: Option<Waker>,
+}
+
+impl WakerRegistration {
+    /// Create an empty registration.
+    pub const fn new() -> WakerRegistration {
+        WakerRegistration { waker: None }
+    }
+
+    /// Register a waker, replacing the waker registered previously, if any.
+    ///
+    /// A waker that is replaced by a waker of another task is woken, so that the task
+    /// it belongs to does not wait forever.
+    pub fn register(&mut self, waker: &Waker) {
+        match self.waker {
+            Some(ref registered) if registered.will_wake(waker) => return,
+            _ => ()
+        }
+        if let Some(replaced) = self.waker.replace(waker.clone()) {
+            replaced.wake()
+        }
+    }
+
+    /// Wake the registered waker, if any, and unregister it.
+    pub fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake()
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+    use super::*;
+
+    pub(crate) struct Counter(AtomicUsize);
+
+    impl Counter {
+        pub(crate) fn count(&self) -> usize {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    impl Wake for Counter {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Return a waker that counts the times it is woken.
+    pub(crate) fn waker() -> (Waker, Arc<Counter>) {
+        let counter = Arc::new(Counter(AtomicUsize::new(0)));
+        (Waker::from(counter.clone()), counter)
+    }
+
+    #[test]
+    fn test_wake_once() {
+        let (waker, counter) = waker();
+        let mut registration = WakerRegistration::new();
+        registration.wake();
+        registration.register(&waker);
+        registration.register(&waker);
+        registration.wake();
+        registration.wake();
+        assert_eq!(counter.count(), 1);
+    }
+
+    #[test]
+    fn test_replace() {
+        let (first, first_counter) = waker();
+        let (second, second_counter) = waker();
+        let mut registration = WakerRegistration::new();
+        registration.register(&first);
+        registration.register(&second);
+        assert_eq!(first_counter.count(), 1);
+        assert_eq!(second_counter.count(), 0);
+        registration.wake();
+        assert_eq!(first_counter.count(), 1);
+        assert_eq!(second_counter.count(), 1);
+    }
+}
@@ -62,15 +62,22 @@ impl Contig {
     }
 }
 
-const CONTIG_COUNT: usize = 4;
+/// The maximum number of holes an assembler can be configured to track.
+pub const MAX_HOLES: usize = 16;
+
+const DEFAULT_HOLES: usize = 4;
 
 /// A buffer (re)assembler.
 ///
-/// Currently, up to a hardcoded limit of four holes can be tracked in the buffer.
+/// Up to a configurable limit of holes, four by default and no more than [MAX_HOLES],
+/// can be tracked in the buffer.
+///
+/// [MAX_HOLES]: constant.MAX_HOLES.html
 #[derive(Debug)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
 pub struct Assembler {
-    contigs: [Contig; CONTIG_COUNT]
+    contigs: [Contig; MAX_HOLES],
+    limit:   usize
 }
 
 impl fmt::Display for Assembler {
@@ -88,9 +95,43 @@ impl fmt::Display for Assembler {
 impl Assembler {
     /// Create a new buffer assembler for buffers of the given size.
     pub fn new(size: usize) -> Assembler {
-        let mut contigs = [Contig::empty(); CONTIG_COUNT];
+        Assembler::with_holes(size, DEFAULT_HOLES)
+    }
+
+    /// Create a new buffer assembler for buffers of the given size, tracking up to
+    /// the given number of holes.
+    ///
+    /// # Panics
+    /// This function panics if `holes` is zero or greater than [MAX_HOLES].
+    ///
+    /// [MAX_HOLES]: constant.MAX_HOLES.html
+    pub fn with_holes(size: usize, holes: usize) -> Assembler {
+        assert!(holes > 0 && holes <= MAX_HOLES, "invalid assembler hole limit {}", holes);
+
+        let mut contigs = [Contig::empty(); MAX_HOLES];
         contigs[0] = Contig::hole(size);
-        Assembler { contigs }
+        Assembler { contigs, limit: holes }
+    }
+
+    /// Return the number of holes that can be tracked.
+    pub fn hole_limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Change the number of holes that can be tracked, and return `Ok(())`,
+    /// or return `Err(())` if more discontiguities than that are already recorded.
+    ///
+    /// # Panics
+    /// This function panics if `holes` is zero or greater than [MAX_HOLES].
+    ///
+    /// [MAX_HOLES]: constant.MAX_HOLES.html
+    pub fn set_hole_limit(&mut self, holes: usize) -> Result<(), ()> {
+        assert!(holes > 0 && holes <= MAX_HOLES, "invalid assembler hole limit {}", holes);
+
+        let used = self.contigs.iter().take_while(|contig| !contig.is_empty()).count();
+        if used > holes { return Err(()) }
+        self.limit = holes;
+        Ok(())
     }
 
     /// FIXME(whitequark): remove this once I'm certain enough that the assembler works well.
@@ -107,7 +148,7 @@ impl Assembler {
     }
 
     fn back(&self) -> Contig {
-        self.contigs[self.contigs.len() - 1]
+        self.contigs[self.limit - 1]
     }
 
     /// Return whether the assembler contains no data.
@@ -120,7 +161,7 @@ impl Assembler {
     fn remove_contig_at(&mut self, at: usize) -> &mut Contig {
         debug_assert!(!self.contigs[at].is_empty());
 
-        for i in at..self.contigs.len() - 1 {
+        for i in at..self.limit - 1 {
             self.contigs[i] = self.contigs[i + 1];
             if !self.contigs[i].has_data() {
                 self.contigs[i + 1] = Contig::empty();
@@ -139,7 +180,7 @@ impl Assembler {
 
         if !self.back().is_empty() { return Err(()) }
 
-        for i in (at + 1..self.contigs.len()).rev() {
+        for i in (at + 1..self.limit).rev() {
             self.contigs[i] = self.contigs[i - 1];
         }
 
@@ -151,7 +192,7 @@ impl Assembler {
     /// or return `Err(())` if too many discontiguities are already recorded.
     pub fn add(&mut self, mut offset: usize, mut size: usize) -> Result<(), ()> {
         let mut index = 0;
-        while index != self.contigs.len() && size != 0 {
+        while index != self.limit && size != 0 {
             let contig = self.contigs[index];
 
             if offset >= contig.total_size() {
@@ -181,9 +222,10 @@ impl Assembler {
             } else if offset + size < contig.hole_size {
                 // The range being added covers a part of the hole but not of the data
                 // in this contig, add a new contig containing the range.
-                self.contigs[index].shrink_hole_by(offset + size);
+                // Make room for it first, so that nothing changes if there is none.
                 let inserted = self.add_contig_at(index)?;
                 *inserted = Contig::hole_and_data(offset, size);
+                self.contigs[index + 1].shrink_hole_by(offset + size);
                 index += 2;
             } else {
                 unreachable!()
@@ -216,6 +258,37 @@ impl Assembler {
             Some(front.data_size)
         }
     }
+
+    /// Iterate over the contiguous ranges of present data, as `(start, end)` offsets
+    /// relative to the start of the buffer.
+    pub fn iter_data(&self) -> DataIter {
+        DataIter { contigs: &self.contigs[..self.limit], index: 0, offset: 0 }
+    }
+}
+
+/// An iterator over the ranges of present data in an assembler.
+///
+/// This struct is created by the [iter_data](struct.Assembler.html#method.iter_data) method.
+pub struct DataIter<'a> {
+    contigs: &'a [Contig],
+    index:   usize,
+    offset:  usize
+}
+
+impl<'a> Iterator for DataIter<'a> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        while let Some(contig) = self.contigs.get(self.index) {
+            self.index += 1;
+            let start = self.offset + contig.hole_size;
+            self.offset += contig.total_size();
+            if contig.has_data() {
+                return Some((start, self.offset))
+            }
+        }
+        None
+    }
 }
 
 #[cfg(test)]
@@ -225,11 +298,11 @@ mod test {
 
     impl From<Vec<(usize, usize)>> for Assembler {
         fn from(vec: Vec<(usize, usize)>) -> Assembler {
-            let mut contigs = [Contig::empty(); CONTIG_COUNT];
+            let mut contigs = [Contig::empty(); MAX_HOLES];
             for (i, &(hole_size, data_size)) in vec.iter().enumerate() {
                 contigs[i] = Contig { hole_size, data_size };
             }
-            Assembler { contigs }
+            Assembler { contigs, limit: DEFAULT_HOLES }
         }
     }
 
@@ -349,4 +422,78 @@ mod test {
         assert_eq!(assr.remove_front(), Some(4));
         assert_eq!(assr, contigs![(4, 4), (4, 0)]);
     }
+
+    #[test]
+    fn test_iter_data() {
+        let assr = contigs![(0, 4), (4, 4), (2, 0)];
+        assert_eq!(assr.iter_data().collect::<Vec<_>>(), vec![(0, 4), (8, 12)]);
+        let assr = contigs![(12, 0)];
+        assert_eq!(assr.iter_data().next(), None);
+    }
+
+    #[test]
+    fn test_hole_limit() {
+        let mut assr = Assembler::new(16);
+        for &offset in &[1, 3, 5] {
+            assert_eq!(assr.add(offset, 1), Ok(()));
+        }
+        assert_eq!(assr.add(7, 1), Err(()));
+        assert_eq!(assr.add(15, 1), Ok(()));
+        assert_eq!(assr, contigs![(1, 1), (1, 1), (1, 1), (9, 1)]);
+
+        let mut assr = Assembler::with_holes(16, 8);
+        for &offset in &[1, 3, 5, 7, 9] {
+            assert_eq!(assr.add(offset, 1), Ok(()));
+        }
+        assert_eq!(assr.iter_data().count(), 5);
+    }
+
+    #[test]
+    fn test_set_hole_limit() {
+        let mut assr = Assembler::with_holes(16, 8);
+        for &offset in &[1, 3, 5, 7, 9] {
+            assert_eq!(assr.add(offset, 1), Ok(()));
+        }
+        assert_eq!(assr.set_hole_limit(4), Err(()));
+        assert_eq!(assr.hole_limit(), 8);
+        assert_eq!(assr.set_hole_limit(MAX_HOLES), Ok(()));
+        assert_eq!(assr.add(11, 1), Ok(()));
+        assert_eq!(assr.iter_data().count(), 6);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid assembler hole limit")]
+    fn test_hole_limit_too_large() {
+        Assembler::with_holes(16, MAX_HOLES + 1);
+    }
+
+    #[test]
+    fn test_interleaved_reverse() {
+        // Every odd octet first, then every even one, from the back to the front.
+        let mut assr = Assembler::with_holes(32, MAX_HOLES);
+        for offset in (0..16).map(|i| i * 2 + 1) {
+            assert_eq!(assr.add(offset, 1), Ok(()));
+        }
+        assert_eq!(assr.iter_data().count(), 16);
+        for offset in (0..16).rev().map(|i| i * 2) {
+            assert_eq!(assr.add(offset, 1), Ok(()));
+        }
+        assert_eq!(assr, Assembler { limit: MAX_HOLES, ..contigs![(0, 32)] });
+        assert_eq!(assr.remove_front(), Some(32));
+    }
+
+    #[test]
+    fn test_interleaved_overlapping() {
+        // Ranges that each fill one hole and overlap the data on both sides of it.
+        let mut assr = Assembler::with_holes(32, MAX_HOLES);
+        for offset in (0..8).map(|i| i * 4 + 2) {
+            assert_eq!(assr.add(offset, 2), Ok(()));
+        }
+        for offset in (0..7).rev().map(|i| i * 4 + 3) {
+            assert_eq!(assr.add(offset, 4), Ok(()));
+        }
+        assert_eq!(assr.iter_data().collect::<Vec<_>>(), vec![(2, 32)]);
+        assert_eq!(assr.add(0, 3), Ok(()));
+        assert_eq!(assr.remove_front(), Some(32));
+    }
 }
@@ -2,14 +2,20 @@
 
 The `storage` module provides containers for use in other modules.
 The containers support both pre-allocated memory, without the `std`
-or `alloc` crates being available, and heap-allocated memory.
+or `alloc` crates being available, and heap-allocated memory. Packet buffers
+may also draw their memory from a pool shared with other buffers.
 */
 
 mod assembler;
+mod packet_buffer;
+mod packet_pool;
 mod ring_buffer;
 
-pub use self::assembler::Assembler;
-pub use self::ring_buffer::RingBuffer;
+pub use self::assembler::{Assembler, DataIter as AssemblerDataIter,
+                          MAX_HOLES as ASSEMBLER_MAX_HOLES};
+pub use self::packet_buffer::PacketBuffer;
+pub use self::packet_pool::{PacketPool, PoolQuota};
+pub use self::ring_buffer::{RingBuffer, BufferStats};
 
 /// A trait for setting a value to a known state.
 ///
@@ -0,0 +1,205 @@
+#[cfg(feature = "std")]
+use std::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::Vec;
+use core::mem;
+use managed::Managed;
+
+use {Error, Result};
+use super::{PoolQuota, Resettable};
+
+/// A buffered packet.
+///
+/// A packet buffer holds the payload of a packet along with metadata of type `H`,
+/// e.g. the endpoint the packet was received from and the time it was received at,
+/// so that a socket can keep both in the elements of a single [ring buffer].
+///
+/// [ring buffer]: struct.RingBuffer.html
+#[derive(Debug)]
+pub struct PacketBuffer<'a, H> {
+    metadata: H,
+    size:     usize,
+    payload:  Managed<'a, [u8]>,
+    /// The size the payload may grow to, if it is heap-allocated.
+    #[cfg(any(feature = "std", feature = "alloc"))]
+    max_size: usize,
+    /// The quota the payload is drawn from, if it is pooled.
+    quota:    Option<&'a PoolQuota<'a>>,
+}
+
+impl<'a, H: Default> PacketBuffer<'a, H> {
+    /// Create a buffered packet using the given payload storage.
+    pub fn new<T>(payload: T) -> PacketBuffer<'a, H>
+            where T: Into<Managed<'a, [u8]>> {
+        PacketBuffer {
+            metadata: H::default(),
+            size:     0,
+            payload:  payload.into(),
+            #[cfg(any(feature = "std", feature = "alloc"))]
+            max_size: 0,
+            quota:    None,
+        }
+    }
+
+    /// Create a buffered packet with heap-allocated storage, which grows to hold
+    /// packets of up to `max_size` octets.
+    #[cfg(any(feature = "std", feature = "alloc"))]
+    pub fn growable(max_size: usize) -> PacketBuffer<'a, H> {
+        let mut packet = PacketBuffer::new(Managed::Owned(Vec::new().into_boxed_slice()));
+        packet.max_size = max_size;
+        packet
+    }
+
+    /// Create a buffered packet whose storage is a chunk of a [packet pool], taken
+    /// through the given quota while the buffer holds a packet.
+    ///
+    /// A packet larger than the chunks of the pool does not fit into the buffer, and
+    /// storing one fails with `Err(Error::Exhausted)` if the quota is used up or the pool
+    /// has no chunks left.
+    ///
+    /// [packet pool]: struct.PacketPool.html
+    pub fn pooled(quota: &'a PoolQuota<'a>) -> PacketBuffer<'a, H> {
+        let mut packet = PacketBuffer::new(&mut [][..]);
+        packet.quota = Some(quota);
+        packet
+    }
+}
+
+impl<'a, H> PacketBuffer<'a, H> {
+    /// Return the metadata of the packet.
+    pub fn metadata(&self) -> &H {
+        &self.metadata
+    }
+
+    /// Return a mutable reference to the metadata of the packet.
+    pub fn metadata_mut(&mut self) -> &mut H {
+        &mut self.metadata
+    }
+
+    /// Return the length of the packet.
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// Return the largest packet the buffer can hold without growing.
+    pub fn capacity(&self) -> usize {
+        match self.quota {
+            Some(quota) => quota.chunk_size(),
+            None => self.payload.len()
+        }
+    }
+
+    /// Return the payload of the packet.
+    pub fn payload(&self) -> &[u8] {
+        &self.payload[..self.size]
+    }
+
+    /// Return a mutable reference to the payload of the packet.
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        &mut self.payload[..self.size]
+    }
+
+    /// Set the length of the packet, growing the storage if it is growable or taking
+    /// a chunk if it is pooled, and return a reference to the buffer, or return
+    /// `Err(Error::Truncated)` if the packet does not fit, or `Err(Error::Exhausted)`
+    /// if no chunk is available.
+    pub fn resize(&mut self, size: usize) -> Result<&mut Self> {
+        if let Some(quota) = self.quota {
+            if self.payload.len() == 0 && size > 0 && size <= quota.chunk_size() {
+                self.payload = Managed::Borrowed(quota.allocate().ok_or(Error::Exhausted)?);
+            }
+        }
+        #[cfg(any(feature = "std", feature = "alloc"))]
+        {
+            if self.payload.len() < size && size <= self.max_size {
+                let mut payload = Vec::new();
+                payload.resize(size, 0);
+                self.payload = Managed::Owned(payload.into_boxed_slice())
+            }
+        }
+        if self.payload.len() >= size {
+            self.size = size;
+            Ok(self)
+        } else {
+            Err(Error::Truncated)
+        }
+    }
+
+    /// Return the chunk of a pooled buffer to its pool, discarding the packet.
+    pub(crate) fn release(&mut self) {
+        if let Some(quota) = self.quota {
+            self.size = 0;
+            if let Managed::Borrowed(chunk) = mem::replace(&mut self.payload,
+                                                           Managed::Borrowed(&mut [])) {
+                if chunk.len() > 0 { quota.release(chunk) }
+            }
+        }
+    }
+}
+
+impl<'a, H: Default> Resettable for PacketBuffer<'a, H> {
+    fn reset(&mut self) {
+        self.metadata = H::default();
+        self.size = 0;
+        self.release();
+    }
+}
+
+impl<'a, H> Drop for PacketBuffer<'a, H> {
+    fn drop(&mut self) {
+        self.release()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resize() {
+        let mut packet: PacketBuffer<u32> = PacketBuffer::new(vec![0; 4]);
+        assert_eq!(packet.capacity(), 4);
+        assert_eq!(packet.resize(5).map(|_| ()), Err(Error::Truncated));
+        packet.resize(3).unwrap().payload_mut().copy_from_slice(&[1, 2, 3]);
+        *packet.metadata_mut() = 42;
+        assert_eq!(packet.payload(), &[1, 2, 3]);
+        assert_eq!(*packet.metadata(), 42);
+
+        packet.reset();
+        assert_eq!(packet.len(), 0);
+        assert_eq!(*packet.metadata(), 0);
+    }
+
+    #[test]
+    fn test_growable() {
+        let mut packet: PacketBuffer<()> = PacketBuffer::growable(8);
+        assert_eq!(packet.capacity(), 0);
+        assert_eq!(packet.resize(6).map(|buf| buf.len()), Ok(6));
+        assert_eq!(packet.capacity(), 6);
+        assert_eq!(packet.resize(9).map(|_| ()), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn test_pooled() {
+        use managed::ManagedSlice;
+        use storage::PacketPool;
+
+        let mut storage = [0; 8];
+        let pool = PacketPool::new(&mut storage[..], 4, ManagedSlice::Owned(vec![]));
+        let quota = pool.quota(1);
+        let mut packet_a: PacketBuffer<()> = PacketBuffer::pooled(&quota);
+        let mut packet_b: PacketBuffer<()> = PacketBuffer::pooled(&quota);
+        assert_eq!(packet_a.capacity(), 4);
+        assert_eq!(packet_a.resize(5).map(|_| ()), Err(Error::Truncated));
+        assert_eq!(packet_a.resize(3).map(|buf| buf.len()), Ok(3));
+        assert_eq!(pool.available(), 1);
+        assert_eq!(packet_b.resize(3).map(|_| ()), Err(Error::Exhausted));
+        assert_eq!(packet_b.resize(0).map(|buf| buf.len()), Ok(0));
+
+        packet_a.reset();
+        assert_eq!(pool.available(), 2);
+        assert_eq!(packet_b.resize(3).map(|buf| buf.len()), Ok(3));
+        drop(packet_b);
+        assert_eq!(quota.used(), 0);
+    }
+}
@@ -0,0 +1,217 @@
+use core::cell::{Cell, RefCell};
+use managed::ManagedSlice;
+
+/// A pool of fixed-size chunks of memory for packets.
+///
+/// A packet pool lets the buffers of several sockets, and those the interface
+/// reassembles and fragments datagrams in, draw their storage from memory they share,
+/// so that the total memory for packets is bounded without being partitioned among
+/// the buffers beforehand. A buffer takes a chunk when a packet is stored in it,
+/// and returns the chunk once the packet is consumed.
+///
+/// Each buffer draws chunks through a [quota](struct.PoolQuota.html), which limits
+/// the chunks the buffers sharing it hold at once, so that e.g. a socket that
+/// is not read from cannot take the whole pool.
+///
+/// # Examples
+///
+/// On systems with heap, a pool of four chunks of 1500 octets can be created with:
+///
+/// ```rust
+/// # extern crate smoltcp;
+/// # extern crate managed;
+/// use managed::ManagedSlice;
+/// use smoltcp::storage::PacketPool;
+/// # fn main() {
+/// let mut storage = vec![0; 4 * 1500];
+/// let pool = PacketPool::new(&mut storage[..], 1500, ManagedSlice::Owned(vec![]));
+/// assert_eq!(pool.capacity(), 4);
+/// # }
+/// ```
+///
+/// On systems without heap, use:
+///
+/// ```rust
+/// use smoltcp::storage::PacketPool;
+/// let mut storage = [0; 4 * 1500];
+/// let mut chunks = [None, None, None, None];
+/// let pool = PacketPool::new(&mut storage[..], 1500, &mut chunks[..]);
+/// assert_eq!(pool.capacity(), 4);
+/// ```
+#[derive(Debug)]
+pub struct PacketPool<'a> {
+    chunks:     RefCell<ManagedSlice<'a, Option<&'a mut [u8]>>>,
+    chunk_size: usize,
+    capacity:   usize,
+}
+
+impl<'a> PacketPool<'a> {
+    /// Create a packet pool, dividing the given storage into chunks of `chunk_size` octets,
+    /// and keeping them in the given slots.
+    ///
+    /// If the slots are fixed-size (not a `Vec`), the chunks that do not fit into them
+    /// are left unused, as is the remainder of the storage that is smaller than a chunk.
+    ///
+    /// # Panics
+    /// This function panics if the chunk size is zero.
+    pub fn new<T>(storage: &'a mut [u8], chunk_size: usize, chunks: T) -> PacketPool<'a>
+            where T: Into<ManagedSlice<'a, Option<&'a mut [u8]>>> {
+        assert!(chunk_size > 0, "the chunk size must not be zero");
+
+        let mut chunks = chunks.into();
+        for slot in chunks.iter_mut() {
+            *slot = None
+        }
+        let mut capacity = 0;
+        for chunk in storage.chunks_mut(chunk_size) {
+            if chunk.len() < chunk_size { break }
+            match chunks {
+                ManagedSlice::Borrowed(ref mut slots) => {
+                    if capacity == slots.len() { break }
+                    slots[capacity] = Some(chunk)
+                }
+                #[cfg(any(feature = "std", feature = "alloc"))]
+                ManagedSlice::Owned(ref mut slots) => {
+                    if capacity == slots.len() {
+                        slots.push(Some(chunk))
+                    } else {
+                        slots[capacity] = Some(chunk)
+                    }
+                }
+            }
+            capacity += 1;
+        }
+
+        PacketPool { chunks: RefCell::new(chunks), chunk_size, capacity }
+    }
+
+    /// Return the size of the chunks in the pool, which is the largest packet
+    /// a buffer drawing from it can hold.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Return the number of chunks in the pool.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Return the number of chunks that are not held by any buffer.
+    pub fn available(&self) -> usize {
+        self.chunks.borrow().iter().filter(|slot| slot.is_some()).count()
+    }
+
+    /// Create a quota, through which buffers may hold up to `limit` chunks
+    /// of the pool at once.
+    pub fn quota(&'a self, limit: usize) -> PoolQuota<'a> {
+        PoolQuota { pool: self, limit, used: Cell::new(0) }
+    }
+
+    fn take(&self) -> Option<&'a mut [u8]> {
+        let mut chunks = self.chunks.borrow_mut();
+        chunks.iter_mut().find(|slot| slot.is_some()).and_then(|slot| slot.take())
+    }
+
+    fn put(&self, chunk: &'a mut [u8]) {
+        let mut chunks = self.chunks.borrow_mut();
+        let slot = chunks.iter_mut().find(|slot| slot.is_none())
+                         .expect("a chunk returned to a full pool");
+        *slot = Some(chunk)
+    }
+}
+
+/// A share of a [packet pool](struct.PacketPool.html).
+///
+/// The buffers created with a quota, e.g. those of a single socket, hold at most
+/// as many chunks of the pool at once as the limit of the quota.
+#[derive(Debug)]
+pub struct PoolQuota<'a> {
+    pool:  &'a PacketPool<'a>,
+    limit: usize,
+    used:  Cell<usize>,
+}
+
+impl<'a> PoolQuota<'a> {
+    /// Return the number of chunks the buffers may hold at once.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Return the number of chunks the buffers hold.
+    pub fn used(&self) -> usize {
+        self.used.get()
+    }
+
+    /// Return the size of the chunks of the pool.
+    pub fn chunk_size(&self) -> usize {
+        self.pool.chunk_size
+    }
+
+    /// Take a chunk from the pool, or return `None` if the quota is used up
+    /// or the pool has no chunks left.
+    pub(crate) fn allocate(&self) -> Option<&'a mut [u8]> {
+        if self.used.get() >= self.limit {
+            net_trace!("packet pool: quota of {} chunks used up", self.limit);
+            return None
+        }
+        let chunk = self.pool.take();
+        match chunk {
+            Some(_) => self.used.set(self.used.get() + 1),
+            None => net_trace!("packet pool: no chunks left")
+        }
+        chunk
+    }
+
+    /// Return a chunk taken with [allocate](#method.allocate) to the pool.
+    pub(crate) fn release(&self, chunk: &'a mut [u8]) {
+        self.used.set(self.used.get() - 1);
+        self.pool.put(chunk)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let mut storage = [0; 10];
+        let mut chunks = [None, None];
+        let pool = PacketPool::new(&mut storage[..], 3, &mut chunks[..]);
+        assert_eq!(pool.capacity(), 2);
+        assert_eq!(pool.available(), 2);
+
+        let mut storage = [0; 10];
+        let pool = PacketPool::new(&mut storage[..], 3, ManagedSlice::Owned(vec![]));
+        assert_eq!(pool.capacity(), 3);
+    }
+
+    #[test]
+    fn test_quota() {
+        let mut storage = [0; 12];
+        let pool = PacketPool::new(&mut storage[..], 4, ManagedSlice::Owned(vec![]));
+        let quota_a = pool.quota(2);
+        let quota_b = pool.quota(2);
+
+        let chunk_1 = quota_a.allocate().unwrap();
+        let chunk_2 = quota_a.allocate().unwrap();
+        assert_eq!(chunk_1.len(), 4);
+        assert_eq!(quota_a.used(), 2);
+        assert!(quota_a.allocate().is_none());
+
+        let chunk_3 = quota_b.allocate().unwrap();
+        assert!(quota_b.allocate().is_none());
+        assert_eq!(pool.available(), 0);
+
+        quota_a.release(chunk_1);
+        assert_eq!(pool.available(), 1);
+        assert_eq!(quota_a.used(), 1);
+        let chunk_4 = quota_b.allocate().unwrap();
+        assert_eq!(quota_b.used(), 2);
+
+        quota_a.release(chunk_2);
+        quota_b.release(chunk_3);
+        quota_b.release(chunk_4);
+        assert_eq!(pool.available(), 3);
+    }
+}
@@ -1,11 +1,32 @@
 // Uncomment the #[must_use]s here once [RFC 1940] hits stable.
 // [RFC 1940]: https://github.com/rust-lang/rust/issues/43302
 
+#[cfg(feature = "std")]
+use std::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::Vec;
 use core::cmp;
 use managed::ManagedSlice;
 
 use {Error, Result};
-use super::Resettable;
+use super::{PacketBuffer, Resettable};
+
+/// Statistics about the occupancy of a ring buffer, for sizing buffers after
+/// the traffic they see.
+///
+/// See also [RingBuffer::stats](struct.RingBuffer.html#method.stats).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BufferStats {
+    /// Number of elements currently in the buffer.
+    pub len:            usize,
+    /// Maximum number of elements in the buffer.
+    pub capacity:       usize,
+    /// The largest number of elements the buffer has held at once.
+    pub high_watermark: usize,
+    /// Number of times elements could not be enqueued, in full or in part, because
+    /// the buffer was full, or, for packets, because their storage was exhausted.
+    pub overflows:      u64,
+}
 
 /// A ring buffer.
 ///
@@ -26,6 +47,15 @@ pub struct RingBuffer<'a, T: 'a> {
     storage: ManagedSlice<'a, T>,
     read_at: usize,
     length:  usize,
+    /// The capacity a growable buffer may grow to, and the function returning
+    /// the elements it is filled with.
+    growth:  Option<(usize, fn() -> T)>,
+    /// Whether the buffer ran full since it last grew.
+    ran_full: bool,
+    /// The largest number of elements the buffer has held at once.
+    high_watermark: usize,
+    /// Number of times elements could not be enqueued because the buffer was full.
+    overflows: u64,
 }
 
 impl<'a, T: 'a> RingBuffer<'a, T> {
@@ -39,6 +69,27 @@ impl<'a, T: 'a> RingBuffer<'a, T> {
             storage: storage.into(),
             read_at: 0,
             length:  0,
+            growth:  None,
+            ran_full: false,
+            high_watermark: 0,
+            overflows: 0,
+        }
+    }
+
+    /// Create a ring buffer with heap-allocated storage of the given capacity,
+    /// filled with elements returned by `f`, that grows up to `max_capacity`.
+    ///
+    /// A buffer that runs full doubles its capacity, within that limit, the next time
+    /// it is empty, so that the elements held in it never have to be moved.
+    #[cfg(any(feature = "std", feature = "alloc"))]
+    pub fn growable(capacity: usize, max_capacity: usize, f: fn() -> T) -> RingBuffer<'a, T> {
+        let mut storage = Vec::with_capacity(capacity);
+        while storage.len() < capacity {
+            storage.push(f())
+        }
+        RingBuffer {
+            growth: Some((max_capacity, f)),
+            ..RingBuffer::new(ManagedSlice::Owned(storage))
         }
     }
 
@@ -53,6 +104,42 @@ impl<'a, T: 'a> RingBuffer<'a, T> {
         self.storage.len()
     }
 
+    /// Return the number of elements the ring buffer may grow to hold,
+    /// which is its capacity unless it is [growable](#method.growable).
+    pub fn max_capacity(&self) -> usize {
+        match self.growth {
+            Some((max_capacity, _)) => cmp::max(max_capacity, self.capacity()),
+            None => self.capacity()
+        }
+    }
+
+    /// Record whether the buffer is full, and grow it if it ran full before
+    /// and is now empty. Return whether the buffer grew.
+    pub(crate) fn grow_if_idle(&mut self) -> bool {
+        if self.is_full() { self.ran_full = true }
+        if !self.ran_full || !self.is_empty() { return false }
+        self.grow()
+    }
+
+    #[cfg(any(feature = "std", feature = "alloc"))]
+    fn grow(&mut self) -> bool {
+        let (max_capacity, f) = match self.growth {
+            Some(growth) => growth,
+            None => return false
+        };
+        let capacity = cmp::min(cmp::max(self.capacity() * 2, 1), max_capacity);
+        if capacity <= self.capacity() { return false }
+
+        net_trace!("ring buffer: growing from {} to {} elements", self.capacity(), capacity);
+        self.ran_full = false;
+        self.resize_with(capacity, f).is_ok()
+    }
+
+    #[cfg(not(any(feature = "std", feature = "alloc")))]
+    fn grow(&mut self) -> bool {
+        false
+    }
+
     /// Clear the ring buffer, and reset every element.
     pub fn reset(&mut self)
             where T: Resettable {
@@ -62,6 +149,29 @@ impl<'a, T: 'a> RingBuffer<'a, T> {
         }
     }
 
+    /// Resize the storage of an empty ring buffer to the given capacity, filling it
+    /// with elements returned by `f`.
+    ///
+    /// This function returns `Err(Error::Illegal)` if the buffer is not empty,
+    /// and `Err(Error::Exhausted)` if the storage is fixed-size (not a `Vec`).
+    #[cfg(any(feature = "std", feature = "alloc"))]
+    pub fn resize_with<F>(&mut self, capacity: usize, mut f: F) -> Result<()>
+            where F: FnMut() -> T {
+        if !self.is_empty() { return Err(Error::Illegal) }
+
+        match self.storage {
+            ManagedSlice::Borrowed(_) => return Err(Error::Exhausted),
+            ManagedSlice::Owned(ref mut storage) => {
+                storage.truncate(capacity);
+                while storage.len() < capacity {
+                    storage.push(f())
+                }
+            }
+        }
+        self.read_at = 0;
+        Ok(())
+    }
+
     /// Return the current number of elements in the ring buffer.
     pub fn len(&self) -> usize {
         self.length
@@ -81,6 +191,22 @@ impl<'a, T: 'a> RingBuffer<'a, T> {
     pub fn is_full(&self) -> bool {
         self.window() == 0
     }
+
+    /// Return the occupancy statistics of the buffer.
+    pub fn stats(&self) -> BufferStats {
+        BufferStats {
+            len:            self.len(),
+            capacity:       self.capacity(),
+            high_watermark: self.high_watermark,
+            overflows:      self.overflows,
+        }
+    }
+
+    /// Restart the occupancy statistics from the current number of elements.
+    pub fn reset_stats(&mut self) {
+        self.high_watermark = self.len();
+        self.overflows = 0;
+    }
 }
 
 /// This is the "discrete" ring buffer interface: it operates with single elements,
@@ -90,14 +216,23 @@ impl<'a, T: 'a> RingBuffer<'a, T> {
     /// returns successfully, or return `Err(Error::Exhausted)` if the buffer is full.
     pub fn enqueue_one_with<'b, R, F>(&'b mut self, f: F) -> Result<R>
             where F: FnOnce(&'b mut T) -> Result<R> {
-        if self.is_full() { return Err(Error::Exhausted) }
+        self.grow_if_idle();
+        if self.is_full() {
+            self.overflows += 1;
+            return Err(Error::Exhausted)
+        }
 
         let index = (self.read_at + self.length) % self.capacity();
         match f(&mut self.storage[index]) {
             Ok(result) => {
                 self.length += 1;
+                self.high_watermark = cmp::max(self.high_watermark, self.length);
                 Ok(result)
             }
+            Err(Error::Exhausted) => {
+                self.overflows += 1;
+                Err(Error::Exhausted)
+            }
             Err(error) => Err(error)
         }
     }
@@ -134,6 +269,37 @@ impl<'a, T: 'a> RingBuffer<'a, T> {
     pub fn dequeue_one(&mut self) -> Result<&mut T> {
         self.dequeue_one_with(Ok)
     }
+
+    /// Return a reference to the element that would be dequeued next, without
+    /// dequeueing it, or return `Err(Error::Exhausted)` if the buffer is empty.
+    pub fn peek_one(&self) -> Result<&T> {
+        if self.is_empty() { return Err(Error::Exhausted) }
+
+        Ok(&self.storage[self.read_at])
+    }
+
+    /// Call `f` with every element that is enqueued, in the order they would be
+    /// dequeued.
+    pub(crate) fn for_each_allocated<F>(&mut self, mut f: F)
+            where F: FnMut(&mut T) {
+        let capacity = self.capacity();
+        for offset in 0..self.length {
+            f(&mut self.storage[(self.read_at + offset) % capacity])
+        }
+    }
+}
+
+impl<'a, 'b: 'a, H: 'b> RingBuffer<'a, PacketBuffer<'b, H>> {
+    /// Return the storage of the packet dequeued last to its pool, if it is pooled.
+    ///
+    /// The payload of a dequeued packet stays in place until the caller is done with it;
+    /// this function is called once it is.
+    pub(crate) fn release_dequeued(&mut self) {
+        if self.is_full() { return }
+
+        let index = (self.read_at + self.capacity() - 1) % self.capacity();
+        self.storage[index].release()
+    }
 }
 
 /// This is the "continuous" ring buffer interface: it operates with element slices,
@@ -147,11 +313,13 @@ impl<'a, T: 'a> RingBuffer<'a, T> {
     /// than the size of the slice passed into it.
     pub fn enqueue_many_with<'b, R, F>(&'b mut self, f: F) -> (usize, R)
             where F: FnOnce(&'b mut [T]) -> (usize, R) {
+        self.grow_if_idle();
         let write_at = (self.read_at + self.length) % self.capacity();
         let max_size = cmp::min(self.window(), self.capacity() - write_at);
         let (size, result) = f(&mut self.storage[write_at..write_at + max_size]);
         assert!(size <= max_size);
         self.length += size;
+        self.high_watermark = cmp::max(self.high_watermark, self.length);
         (size, result)
     }
 
@@ -173,6 +341,7 @@ impl<'a, T: 'a> RingBuffer<'a, T> {
     // #[must_use]
     pub fn enqueue_slice(&mut self, data: &[T]) -> usize
             where T: Copy {
+        let data_len = data.len();
         let (size_1, data) = self.enqueue_many_with(|buf| {
             let size = cmp::min(buf.len(), data.len());
             buf[..size].copy_from_slice(&data[..size]);
@@ -183,6 +352,7 @@ impl<'a, T: 'a> RingBuffer<'a, T> {
             buf[..size].copy_from_slice(&data[..size]);
             (size, ())
         });
+        if size_1 + size_2 < data_len { self.overflows += 1 }
         size_1 + size_2
     }
 
@@ -261,6 +431,7 @@ impl<'a, T: 'a> RingBuffer<'a, T> {
     // #[must_use]
     pub fn write_unallocated(&mut self, offset: usize, data: &[T]) -> usize
             where T: Copy {
+        let data_len = data.len();
         let (size_1, offset, data) = {
             let slice = self.get_unallocated(offset, data.len());
             let slice_len = slice.len();
@@ -273,6 +444,7 @@ impl<'a, T: 'a> RingBuffer<'a, T> {
             slice.copy_from_slice(&data[..slice_len]);
             slice_len
         };
+        if size_1 + size_2 < data_len { self.overflows += 1 }
         size_1 + size_2
     }
 
@@ -283,6 +455,7 @@ impl<'a, T: 'a> RingBuffer<'a, T> {
     pub fn enqueue_unallocated(&mut self, count: usize) {
         assert!(count <= self.window());
         self.length += count;
+        self.high_watermark = cmp::max(self.high_watermark, self.length);
     }
 
     /// Return the largest contiguous slice of allocated buffer elements starting
@@ -366,6 +539,60 @@ mod test {
         assert_eq!(ring.window(), 0);
     }
 
+    #[test]
+    fn test_buffer_resize_with() {
+        let mut ring = RingBuffer::new(vec![0; 2]);
+        assert_eq!(ring.enqueue_slice(&[1]), 1);
+        assert_eq!(ring.resize_with(4, || 0), Err(Error::Illegal));
+        assert_eq!(ring.dequeue_slice(&mut [0; 1]), 1);
+        assert_eq!(ring.resize_with(4, || 0), Ok(()));
+        assert_eq!(ring.capacity(), 4);
+        assert_eq!(ring.enqueue_slice(&[1, 2, 3, 4, 5]), 4);
+        assert!(ring.is_full());
+
+        ring.clear();
+        assert_eq!(ring.resize_with(1, || 0), Ok(()));
+        assert_eq!(ring.capacity(), 1);
+
+        let mut storage = [0; 2];
+        let mut ring = RingBuffer::new(&mut storage[..]);
+        assert_eq!(ring.resize_with(4, || 0), Err(Error::Exhausted));
+        assert_eq!(ring.capacity(), 2);
+    }
+
+    #[test]
+    fn test_buffer_growable() {
+        let mut ring = RingBuffer::growable(2, 5, || 0);
+        assert_eq!(ring.capacity(), 2);
+        assert_eq!(ring.max_capacity(), 5);
+        assert_eq!(ring.enqueue_slice(&[1, 2, 3]), 2);
+        // The buffer does not grow while it holds elements.
+        assert_eq!(ring.enqueue_slice(&[3]), 0);
+        assert_eq!(ring.capacity(), 2);
+        assert_eq!(ring.dequeue_one(), Ok(&mut 1));
+        assert_eq!(ring.enqueue_slice(&[3]), 1);
+        assert_eq!(ring.capacity(), 2);
+
+        let mut data = [0; 2];
+        assert_eq!(ring.dequeue_slice(&mut data), 2);
+        assert_eq!(data, [2, 3]);
+        assert_eq!(ring.enqueue_slice(&[4, 5, 6, 7, 8]), 4);
+        assert_eq!(ring.capacity(), 4);
+
+        let mut data = [0; 4];
+        assert_eq!(ring.dequeue_slice(&mut data), 4);
+        assert_eq!(data, [4, 5, 6, 7]);
+        assert_eq!(ring.enqueue_one_with(|e| { *e = 9; Ok(()) }), Ok(()));
+        assert_eq!(ring.capacity(), 5);
+        assert_eq!(ring.dequeue_one(), Ok(&mut 9));
+
+        let mut ring = RingBuffer::new(vec![0; 1]);
+        assert_eq!(ring.max_capacity(), 1);
+        assert_eq!(ring.enqueue_slice(&[1, 2]), 1);
+        assert_eq!(ring.dequeue_one(), Ok(&mut 1));
+        assert_eq!(ring.enqueue_slice(&[1, 2]), 1);
+    }
+
     #[test]
     fn test_buffer_enqueue_dequeue_one_with() {
         let mut ring = RingBuffer::new(vec![0; 5]);
@@ -417,6 +644,19 @@ mod test {
         assert!(ring.is_empty());
     }
 
+    #[test]
+    fn test_buffer_peek_one() {
+        let mut ring = RingBuffer::new(vec![0; 2]);
+        assert_eq!(ring.peek_one(), Err(Error::Exhausted));
+
+        *ring.enqueue_one().unwrap() = 1;
+        *ring.enqueue_one().unwrap() = 2;
+        assert_eq!(ring.peek_one(), Ok(&1));
+        assert_eq!(ring.len(), 2);
+        assert_eq!(*ring.dequeue_one().unwrap(), 1);
+        assert_eq!(ring.peek_one(), Ok(&2));
+    }
+
     #[test]
     fn test_buffer_enqueue_many_with() {
         let mut ring = RingBuffer::new(vec![b'.'; 12]);
@@ -680,4 +920,44 @@ mod test {
         assert_eq!(&data[..], b"mno\x00\x00\x00");
 
     }
+
+    #[test]
+    fn test_buffer_stats() {
+        let mut ring = RingBuffer::new(vec![b'.'; 8]);
+        assert_eq!(ring.stats(), BufferStats { capacity: 8, ..BufferStats::default() });
+
+        assert_eq!(ring.enqueue_slice(b"abcdef"), 6);
+        assert_eq!(ring.dequeue_many(4), b"abcd");
+        assert_eq!(ring.enqueue_slice(b"ghijkl"), 6);
+        assert_eq!(ring.enqueue_slice(b"mn"), 0);
+        assert_eq!(ring.enqueue_slice(b""), 0);
+        ring.clear();
+        assert_eq!(ring.write_unallocated(2, b"opqrst"), 6);
+        ring.enqueue_unallocated(8);
+        assert_eq!(ring.dequeue_many(8), b"ijopqrst");
+        assert_eq!(ring.write_unallocated(4, b"uvwxyz"), 4);
+        assert_eq!(ring.stats(), BufferStats {
+            len:            0,
+            capacity:       8,
+            high_watermark: 8,
+            overflows:      2,
+        });
+
+        ring.enqueue_slice(b"abc");
+        ring.reset_stats();
+        assert_eq!(ring.stats(), BufferStats { len: 3, capacity: 8, high_watermark: 3,
+                                               overflows: 0 });
+
+        let mut ring = RingBuffer::new(vec![0; 1]);
+        assert_eq!(ring.enqueue_one(), Ok(&mut 0));
+        assert_eq!(ring.enqueue_one(), Err(Error::Exhausted));
+        assert_eq!(ring.enqueue_one_with(|_| -> Result<()> { Err(Error::Exhausted) }),
+                   Err(Error::Exhausted));
+        ring.dequeue_one().unwrap();
+        assert_eq!(ring.enqueue_one_with(|_| -> Result<()> { Err(Error::Exhausted) }),
+                   Err(Error::Exhausted));
+        assert_eq!(ring.enqueue_one_with(|_| -> Result<()> { Err(Error::Truncated) }),
+                   Err(Error::Truncated));
+        assert_eq!(ring.stats().overflows, 3);
+    }
 }
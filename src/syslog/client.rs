@@ -0,0 +1,273 @@
+// Heads up! Before working on this file you should read RFC 5426, which describes
+// the transport of syslog messages over UDP.
+
+use core::cmp;
+
+use {Error, Result};
+use wire::IpEndpoint;
+use socket::{SocketSet, SocketHandle, UdpSocket, UdpSocketBuffer};
+use super::record::{Record, Header, Facility, MAX_MESSAGE_LEN};
+
+/// The rate at which records may be sent by default, in records per second.
+const DEFAULT_RATE: u32 = 10;
+/// The number of records that may be sent at once by default.
+const DEFAULT_BURST: u32 = 20;
+
+/// A syslog client.
+///
+/// The client sends records to a collector through a UDP socket that it adds to the socket
+/// set, with the facility, host name, application name and process identifier it is
+/// configured with. Records are timestamped once the client is given the time with
+/// [set_unix_time], e.g. from an SNTP client.
+///
+/// The rate of records is limited with a token bucket: a burst of records may be sent
+/// at once, after which records are dropped until tokens are replenished at the configured
+/// rate. Dropped records are counted, and the count is returned by [dropped].
+///
+/// [set_unix_time]: #method.set_unix_time
+/// [dropped]: #method.dropped
+#[derive(Debug)]
+pub struct Client<'a> {
+    udp_handle:  SocketHandle,
+    local_port:  u16,
+    server:      IpEndpoint,
+    facility:    Facility,
+    hostname:    Option<&'a str>,
+    app_name:    Option<&'a str>,
+    proc_id:     Option<&'a str>,
+    /// The Unix time, in milliseconds, minus the timestamp the client is used with.
+    unix_offset: Option<i64>,
+    rate_limit:  Option<(u32, u32)>,
+    /// The tokens in the bucket, in thousandths of a record.
+    tokens:      u64,
+    refilled_at: u64,
+    dropped:     u64,
+}
+
+impl<'a> Client<'a> {
+    /// Create a syslog client sending records from the given local port to the given
+    /// collector, and add its UDP socket, created with the given transmit buffer,
+    /// to the socket set.
+    pub fn new<'b, 'c, 'd>(sockets: &mut SocketSet<'b, 'c, 'd>,
+                           tx_buffer: UdpSocketBuffer<'c, 'd>,
+                           local_port: u16, server: IpEndpoint) -> Client<'a> {
+        let rx_buffer = UdpSocketBuffer::new(&mut [][..]);
+        let udp_handle = sockets.add(UdpSocket::new(rx_buffer, tx_buffer));
+        Client {
+            udp_handle:  udp_handle,
+            local_port:  local_port,
+            server:      server,
+            facility:    Facility::User,
+            hostname:    None,
+            app_name:    None,
+            proc_id:     None,
+            unix_offset: None,
+            rate_limit:  Some((DEFAULT_RATE, DEFAULT_BURST)),
+            tokens:      DEFAULT_BURST as u64 * 1000,
+            refilled_at: 0,
+            dropped:     0,
+        }
+    }
+
+    /// Return the handle of the UDP socket used by the client.
+    pub fn udp_handle(&self) -> SocketHandle {
+        self.udp_handle
+    }
+
+    /// Return the collector records are sent to.
+    pub fn server(&self) -> IpEndpoint {
+        self.server
+    }
+
+    /// Set the collector records are sent to.
+    pub fn set_server(&mut self, server: IpEndpoint) {
+        self.server = server
+    }
+
+    /// Return the facility of the records.
+    pub fn facility(&self) -> Facility {
+        self.facility
+    }
+
+    /// Set the facility of the records. The default is `Facility::User`.
+    pub fn set_facility(&mut self, facility: Facility) {
+        self.facility = facility
+    }
+
+    /// Set the host name, the application name and the process identifier included
+    /// in the records. Characters other than printable ASCII ones are replaced with
+    /// underscores.
+    pub fn set_origin(&mut self, hostname: Option<&'a str>, app_name: Option<&'a str>,
+                      proc_id: Option<&'a str>) {
+        self.hostname = hostname;
+        self.app_name = app_name;
+        self.proc_id = proc_id;
+    }
+
+    /// Set the Unix time, in milliseconds, at the given moment, so that records
+    /// are timestamped.
+    pub fn set_unix_time(&mut self, now: u64, unix_time: u64) {
+        self.unix_offset = Some(unix_time as i64 - now as i64);
+    }
+
+    /// Return the rate limit, as the number of records per second, and the number
+    /// of records that may be sent at once.
+    ///
+    /// See also the [set_rate_limit](#method.set_rate_limit) method.
+    pub fn rate_limit(&self) -> Option<(u32, u32)> {
+        self.rate_limit
+    }
+
+    /// Set the rate limit, as the number of records per second, and the number
+    /// of records that may be sent at once, or disable it.
+    ///
+    /// The default is 10 records per second, in bursts of up to 20 records.
+    ///
+    /// # Panics
+    /// This function panics if the number of records that may be sent at once is zero.
+    pub fn set_rate_limit(&mut self, rate_limit: Option<(u32, u32)>) {
+        if let Some((_, burst)) = rate_limit {
+            assert!(burst > 0, "the burst of a syslog rate limit must not be empty");
+            self.tokens = burst as u64 * 1000;
+        }
+        self.rate_limit = rate_limit
+    }
+
+    /// Return the number of records dropped because of the rate limit, or because
+    /// the transmit buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Send a record to the collector.
+    ///
+    /// This function returns `Err(Error::Exhausted)`, and drops the record, if the rate
+    /// limit was reached or the transmit buffer is full. Records longer than
+    /// [MAX_MESSAGE_LEN](constant.MAX_MESSAGE_LEN.html) are truncated.
+    pub fn log(&mut self, sockets: &mut SocketSet, record: &Record, now: u64) -> Result<()> {
+        if !self.take_token(now) {
+            self.dropped += 1;
+            return Err(Error::Exhausted)
+        }
+
+        let mut udp_socket = sockets.get::<UdpSocket>(self.udp_handle);
+        if !udp_socket.is_open() {
+            udp_socket.bind(self.local_port)?;
+        }
+
+        let header = Header {
+            facility:  self.facility,
+            timestamp: self.unix_offset.map(|offset| offset + now as i64),
+            hostname:  self.hostname,
+            app_name:  self.app_name,
+            proc_id:   self.proc_id,
+        };
+        let mut buffer = [0; MAX_MESSAGE_LEN];
+        let length = record.emit(&header, &mut buffer);
+        match udp_socket.send_slice(&buffer[..length], self.server) {
+            Err(Error::Exhausted) => {
+                self.dropped += 1;
+                Err(Error::Exhausted)
+            }
+            result => result
+        }
+    }
+
+    fn take_token(&mut self, now: u64) -> bool {
+        let (rate, burst) = match self.rate_limit {
+            Some(rate_limit) => rate_limit,
+            None => return true
+        };
+        // A rate of one record per second adds a thousandth of a record per millisecond.
+        let elapsed = now.saturating_sub(self.refilled_at);
+        self.tokens = cmp::min(self.tokens.saturating_add(elapsed * rate as u64),
+                               burst as u64 * 1000);
+        self.refilled_at = now;
+        if self.tokens >= 1000 {
+            self.tokens -= 1000;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(all(test, feature = "proto-ipv4"))]
+mod test {
+    use std::vec::Vec;
+    use wire::{IpAddress, Ipv4Address};
+    use socket::UdpPacketBuffer;
+    use super::super::{Severity, SERVER_PORT};
+    use super::*;
+
+    const SERVER: IpAddress = IpAddress::Ipv4(Ipv4Address([192, 168, 1, 1]));
+    const LOCAL_PORT: u16 = 50514;
+
+    fn setup(packets: usize) -> (SocketSet<'static, 'static, 'static>, Client<'static>) {
+        let mut sockets = SocketSet::new(vec![]);
+        let tx_buffer = UdpSocketBuffer::new((0..packets)
+            .map(|_| UdpPacketBuffer::new(vec![0; MAX_MESSAGE_LEN]))
+            .collect::<Vec<_>>());
+        let client = Client::new(&mut sockets, tx_buffer, LOCAL_PORT,
+                                 IpEndpoint::new(SERVER, SERVER_PORT));
+        (sockets, client)
+    }
+
+    fn sent(sockets: &mut SocketSet, client: &Client) -> Option<Vec<u8>> {
+        let mut result = None;
+        let _ = sockets.get::<UdpSocket>(client.udp_handle())
+            .dispatch(0, |(ip_repr, udp_repr)| {
+                assert_eq!(ip_repr.dst_addr(), SERVER);
+                assert_eq!(udp_repr.src_port, LOCAL_PORT);
+                assert_eq!(udp_repr.dst_port, SERVER_PORT);
+                result = Some(udp_repr.payload.to_vec());
+                Ok(())
+            });
+        result
+    }
+
+    #[test]
+    fn test_log() {
+        let (mut sockets, mut client) = setup(4);
+        client.set_facility(Facility::Daemon);
+        client.set_origin(Some("sensor-12"), Some("fw"), Some("1"));
+        let record = Record::new(Severity::Error, "sensor failed");
+
+        assert_eq!(client.log(&mut sockets, &record, 1_000), Ok(()));
+        assert_eq!(sent(&mut sockets, &client).unwrap(),
+                   &b"<27>1 - sensor-12 fw 1 - - sensor failed"[..]);
+
+        client.set_unix_time(1_000, 1_577_836_800_000);
+        assert_eq!(client.log(&mut sockets, &record, 1_500), Ok(()));
+        assert_eq!(sent(&mut sockets, &client).unwrap(),
+                   &b"<27>1 2020-01-01T00:00:00.500Z sensor-12 fw 1 - - sensor failed"[..]);
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let (mut sockets, mut client) = setup(8);
+        client.set_rate_limit(Some((2, 3)));
+        let record = Record::new(Severity::Debug, "tick");
+
+        for _ in 0..3 {
+            assert_eq!(client.log(&mut sockets, &record, 0), Ok(()));
+        }
+        assert_eq!(client.log(&mut sockets, &record, 0), Err(Error::Exhausted));
+        assert_eq!(client.log(&mut sockets, &record, 499), Err(Error::Exhausted));
+        assert_eq!(client.dropped(), 2);
+
+        // One token is replenished every 500 ms.
+        assert_eq!(client.log(&mut sockets, &record, 500), Ok(()));
+        assert_eq!(client.log(&mut sockets, &record, 600), Err(Error::Exhausted));
+        assert_eq!(client.log(&mut sockets, &record, 10_000), Ok(()));
+        assert_eq!(client.dropped(), 3);
+
+        client.set_rate_limit(None);
+        for _ in 0..3 {
+            assert_eq!(client.log(&mut sockets, &record, 10_000), Ok(()));
+        }
+        // The transmit buffer is full.
+        assert_eq!(client.log(&mut sockets, &record, 10_000), Err(Error::Exhausted));
+        assert_eq!(client.dropped(), 4);
+    }
+}
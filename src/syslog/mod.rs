@@ -0,0 +1,13 @@
+/*! Syslog.
+
+The `syslog` module implements a syslog client, that formats log records, optionally with
+structured data, as described in RFC 5424, and sends them to a collector over a UDP socket,
+as described in RFC 5426, limiting the rate at which they are sent so that a flood
+of records cannot congest the network.
+*/
+
+mod record;
+mod client;
+
+pub use self::record::{Record, Element, Facility, Severity, SERVER_PORT, MAX_MESSAGE_LEN};
+pub use self::client::Client;
@@ -0,0 +1,297 @@
+// Heads up! Before working on this file you should read RFC 5424 § 6, which describes
+// the format of syslog messages.
+
+use core::fmt::{self, Write};
+use core::cmp;
+
+/// The port syslog collectors listen on, as described in RFC 5426.
+pub const SERVER_PORT: u16 = 514;
+
+/// The longest message sent, which every collector must accept over IPv4
+/// according to RFC 5426 § 3.2. Longer records are truncated.
+pub const MAX_MESSAGE_LEN: usize = 480;
+
+enum_with_unknown! {
+    /// The facility a record originates from.
+    pub enum Facility(u8) {
+        Kernel   = 0,
+        User     = 1,
+        Mail     = 2,
+        Daemon   = 3,
+        Auth     = 4,
+        Syslog   = 5,
+        Printer  = 6,
+        News     = 7,
+        Uucp     = 8,
+        Cron     = 9,
+        AuthPriv = 10,
+        Ftp      = 11,
+        Ntp      = 12,
+        Audit    = 13,
+        Alert    = 14,
+        Clock    = 15,
+        Local0   = 16,
+        Local1   = 17,
+        Local2   = 18,
+        Local3   = 19,
+        Local4   = 20,
+        Local5   = 21,
+        Local6   = 22,
+        Local7   = 23
+    }
+}
+
+enum_with_unknown! {
+    /// The severity of a record.
+    pub enum Severity(u8) {
+        Emergency     = 0,
+        Alert         = 1,
+        Critical      = 2,
+        Error         = 3,
+        Warning       = 4,
+        Notice        = 5,
+        Informational = 6,
+        Debug         = 7
+    }
+}
+
+/// A structured data element of a record, i.e. a named group of parameters,
+/// as described in RFC 5424 § 6.3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Element<'a> {
+    /// The identifier of the element, e.g. `meta`, or `name@enterprise-number`
+    /// for elements that are not registered with IANA.
+    pub id:     &'a str,
+    /// The names and values of the parameters.
+    pub params: &'a [(&'a str, &'a str)],
+}
+
+/// A log record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record<'a> {
+    pub severity:        Severity,
+    /// The type of the record, e.g. `LINKDOWN`.
+    pub msg_id:          Option<&'a str>,
+    pub structured_data: &'a [Element<'a>],
+    /// The free-form message.
+    pub message:         &'a str,
+}
+
+impl<'a> Record<'a> {
+    /// Create a record with the given severity and message, and without
+    /// a message identifier or structured data.
+    pub fn new(severity: Severity, message: &'a str) -> Record<'a> {
+        Record { severity, msg_id: None, structured_data: &[], message }
+    }
+
+    /// Format the record as a syslog message with the given header into the buffer,
+    /// truncating it if it does not fit, and return its length.
+    pub(crate) fn emit(&self, header: &Header, buffer: &mut [u8]) -> usize {
+        let mut writer = Writer { buffer, len: 0 };
+        let _ = self.write(header, &mut writer);
+        writer.len
+    }
+
+    fn write(&self, header: &Header, writer: &mut Writer) -> fmt::Result {
+        let facility: u8 = header.facility.into();
+        let severity: u8 = self.severity.into();
+        write!(writer, "<{}>1 ", (facility << 3) | (severity & 0x7))?;
+        match header.timestamp {
+            Some(unix_millis) => write_timestamp(writer, unix_millis)?,
+            None => writer.write_str("-")?
+        }
+        writer.write_str(" ")?;
+        write_field(writer, header.hostname, 255)?;
+        writer.write_str(" ")?;
+        write_field(writer, header.app_name, 48)?;
+        writer.write_str(" ")?;
+        write_field(writer, header.proc_id, 128)?;
+        writer.write_str(" ")?;
+        write_field(writer, self.msg_id, 32)?;
+        writer.write_str(" ")?;
+
+        if self.structured_data.is_empty() {
+            writer.write_str("-")?;
+        }
+        for element in self.structured_data {
+            writer.write_str("[")?;
+            write_name(writer, element.id)?;
+            for &(name, value) in element.params {
+                writer.write_str(" ")?;
+                write_name(writer, name)?;
+                writer.write_str("=\"")?;
+                for c in value.chars() {
+                    if c == '"' || c == '\\' || c == ']' {
+                        writer.write_char('\\')?;
+                    }
+                    writer.write_char(c)?;
+                }
+                writer.write_str("\"")?;
+            }
+            writer.write_str("]")?;
+        }
+
+        if !self.message.is_empty() {
+            writer.write_str(" ")?;
+            writer.write_str(self.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// The header fields of a message that are common to all records of a client.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Header<'a> {
+    pub facility:  Facility,
+    /// The time of the record, in milliseconds since the Unix epoch.
+    pub timestamp: Option<i64>,
+    pub hostname:  Option<&'a str>,
+    pub app_name:  Option<&'a str>,
+    pub proc_id:   Option<&'a str>,
+}
+
+/// A writer into a buffer, which drops whatever does not fit, without splitting characters.
+struct Writer<'a> {
+    buffer: &'a mut [u8],
+    len:    usize,
+}
+
+impl<'a> Write for Writer<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut size = cmp::min(s.len(), self.buffer.len() - self.len);
+        while !s.is_char_boundary(size) { size -= 1 }
+        self.buffer[self.len..self.len + size].copy_from_slice(&s.as_bytes()[..size]);
+        self.len += size;
+        if size < s.len() { Err(fmt::Error) } else { Ok(()) }
+    }
+}
+
+/// Write a header field, which may only contain printable ASCII characters, replacing
+/// any others with underscores, or a dash if the field is empty.
+fn write_field(writer: &mut Writer, value: Option<&str>, max_len: usize) -> fmt::Result {
+    match value {
+        Some(value) if !value.is_empty() => {
+            for c in value.chars().take(max_len) {
+                writer.write_char(if c > ' ' && c <= '~' { c } else { '_' })?;
+            }
+            Ok(())
+        }
+        _ => writer.write_str("-")
+    }
+}
+
+/// Write the identifier of a structured data element, or the name of a parameter,
+/// which may not contain `=`, `]` and `"` either.
+fn write_name(writer: &mut Writer, name: &str) -> fmt::Result {
+    for c in name.chars().take(32) {
+        let valid = c > ' ' && c <= '~' && c != '=' && c != ']' && c != '"';
+        writer.write_char(if valid { c } else { '_' })?;
+    }
+    Ok(())
+}
+
+/// Write a time, in milliseconds since the Unix epoch, in the format described in
+/// RFC 5424 § 6.2.3, e.g. `2020-01-01T00:00:00.000Z`.
+fn write_timestamp(writer: &mut Writer, unix_millis: i64) -> fmt::Result {
+    let seconds = unix_millis.div_euclid(1000);
+    let millis = unix_millis.rem_euclid(1000);
+    let days = seconds.div_euclid(86400);
+    let seconds = seconds.rem_euclid(86400);
+
+    // Convert the days to a civil date, as described in
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 -
+                       day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    write!(writer, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+           year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60, millis)
+}
+
+#[cfg(test)]
+mod test {
+    use std::str;
+    use std::borrow::ToOwned;
+    use std::string::String;
+    use super::*;
+
+    fn header() -> Header<'static> {
+        Header {
+            facility:  Facility::Local4,
+            timestamp: Some(1_582_977_600_123),
+            hostname:  Some("sensor-12"),
+            app_name:  Some("fw"),
+            proc_id:   None,
+        }
+    }
+
+    fn emit(header: &Header, record: &Record, buffer: &mut [u8]) -> String {
+        let len = record.emit(header, buffer);
+        str::from_utf8(&buffer[..len]).unwrap().to_owned()
+    }
+
+    #[test]
+    fn test_emit() {
+        let mut buffer = [0; MAX_MESSAGE_LEN];
+        let record = Record::new(Severity::Notice, "link up");
+        assert_eq!(emit(&header(), &record, &mut buffer),
+                   "<165>1 2020-02-29T12:00:00.123Z sensor-12 fw - - - link up");
+
+        let header = Header {
+            facility:  Facility::Kernel,
+            timestamp: None,
+            hostname:  None,
+            app_name:  Some("bad name"),
+            proc_id:   Some(""),
+        };
+        let record = Record::new(Severity::Emergency, "");
+        assert_eq!(emit(&header, &record, &mut buffer), "<0>1 - - bad_name - - -");
+    }
+
+    #[test]
+    fn test_emit_structured_data() {
+        let mut buffer = [0; MAX_MESSAGE_LEN];
+        let record = Record {
+            severity:        Severity::Warning,
+            msg_id:          Some("TEMP"),
+            structured_data: &[
+                Element { id: "meta", params: &[("sequenceId", "7")] },
+                Element { id: "temp@32473", params: &[("a=b", "\"hot\" [x]\\")] },
+            ],
+            message:         "too hot",
+        };
+        assert_eq!(emit(&header(), &record, &mut buffer),
+                   "<164>1 2020-02-29T12:00:00.123Z sensor-12 fw - TEMP \
+                    [meta sequenceId=\"7\"][temp@32473 a_b=\"\\\"hot\\\" [x\\]\\\\\"] too hot");
+    }
+
+    #[test]
+    fn test_emit_truncated() {
+        let mut buffer = [0; 53];
+        let record = Record::new(Severity::Notice, "añb");
+        assert_eq!(emit(&header(), &record, &mut buffer),
+                   "<165>1 2020-02-29T12:00:00.123Z sensor-12 fw - - - a");
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let mut buffer = [0; 32];
+        let mut timestamp = |unix_millis| {
+            let mut writer = Writer { buffer: &mut buffer[..], len: 0 };
+            write_timestamp(&mut writer, unix_millis).unwrap();
+            let len = writer.len;
+            str::from_utf8(&buffer[..len]).unwrap().to_owned()
+        };
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(timestamp(951_782_400_000), "2000-02-29T00:00:00.000Z");
+        assert_eq!(timestamp(1_609_459_199_999), "2020-12-31T23:59:59.999Z");
+        assert_eq!(timestamp(-1), "1969-12-31T23:59:59.999Z");
+    }
+}
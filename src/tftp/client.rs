@@ -0,0 +1,568 @@
+// Heads up! Before working on this file you should read RFC 1350, which describes TFTP,
+// and RFC 2347, RFC 2348 and RFC 2349, which describe option negotiation.
+
+use {Error, Result};
+use wire::{IpAddress, IpEndpoint, TftpPacket, TftpRepr, TftpMode, TftpOptions, TftpErrorCode};
+use wire::{TFTP_SERVER_PORT, TFTP_DEFAULT_BLOCK_SIZE};
+use socket::{SocketSet, SocketHandle, UdpSocket, UdpSocketBuffer};
+use super::{File, Direction, Event, MAX_BLOCK_SIZE};
+use super::transfer::{Transfer, DEFAULT_TIMEOUT, MAX_RETRANSMITS};
+
+/// The longest request the client sends, including the file name and the options.
+const MAX_REQUEST_LEN: usize = 512;
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Idle,
+    /// A request was made, and the server did not respond to it yet.
+    Requesting {
+        server:      IpAddress,
+        direction:   Direction,
+        sent_at:     Option<u64>,
+        retransmits: u8,
+    },
+    Transferring(Transfer),
+}
+
+/// A TFTP client.
+///
+/// The client reads a file from a server, or writes a file to a server, one at a time,
+/// through a UDP socket that it adds to the socket set. A transfer is started with
+/// [read] or [write], and carried out by [poll], which reads or writes the contents
+/// of the file, and reports the outcome of the transfer once it is over.
+///
+/// The client asks the server for the size of a file it reads, which is returned by
+/// [transfer_size] once the server responds, and proposes its block size if it is not
+/// the default one. Only the octet mode is used.
+///
+/// [read]: #method.read
+/// [write]: #method.write
+/// [poll]: #method.poll
+/// [transfer_size]: #method.transfer_size
+#[derive(Debug)]
+pub struct Client {
+    udp_handle:    SocketHandle,
+    local_port:    u16,
+    block_size:    usize,
+    timeout:       u64,
+    state:         State,
+    request:       [u8; MAX_REQUEST_LEN],
+    request_len:   usize,
+    transfer_size: Option<usize>,
+}
+
+impl Client {
+    /// Create a TFTP client sending requests from the given local port, and add its
+    /// UDP socket, created with the given buffers, to the socket set.
+    ///
+    /// The packets of the receive buffer should be large enough to hold a data block.
+    pub fn new<'a, 'b, 'c>(sockets: &mut SocketSet<'a, 'b, 'c>,
+                           rx_buffer: UdpSocketBuffer<'b, 'c>,
+                           tx_buffer: UdpSocketBuffer<'b, 'c>,
+                           local_port: u16) -> Client {
+        let udp_handle = sockets.add(UdpSocket::new(rx_buffer, tx_buffer));
+        Client {
+            udp_handle:    udp_handle,
+            local_port:    local_port,
+            block_size:    TFTP_DEFAULT_BLOCK_SIZE,
+            timeout:       DEFAULT_TIMEOUT,
+            state:         State::Idle,
+            request:       [0; MAX_REQUEST_LEN],
+            request_len:   0,
+            transfer_size: None,
+        }
+    }
+
+    /// Return the handle of the UDP socket used by the client.
+    pub fn udp_handle(&self) -> SocketHandle {
+        self.udp_handle
+    }
+
+    /// Return the block size proposed to servers.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Set the block size proposed to servers. Servers may choose a smaller one, or ignore
+    /// the proposal and use the default block size (512).
+    ///
+    /// # Panics
+    /// This function panics if the block size is smaller than 8 or larger than
+    /// [MAX_BLOCK_SIZE](constant.MAX_BLOCK_SIZE.html).
+    pub fn set_block_size(&mut self, block_size: usize) {
+        assert!(block_size >= 8 && block_size <= MAX_BLOCK_SIZE, "invalid TFTP block size");
+        self.block_size = block_size
+    }
+
+    /// Return the retransmission timeout, in milliseconds.
+    pub fn timeout(&self) -> u64 {
+        self.timeout
+    }
+
+    /// Set the retransmission timeout, in milliseconds. The default is one second.
+    pub fn set_timeout(&mut self, timeout: u64) {
+        self.timeout = timeout
+    }
+
+    /// Query whether no transfer is in progress.
+    pub fn is_idle(&self) -> bool {
+        match self.state {
+            State::Idle => true,
+            _ => false
+        }
+    }
+
+    /// Return the size of the file being read, if the server reported it.
+    pub fn transfer_size(&self) -> Option<usize> {
+        self.transfer_size
+    }
+
+    /// Start reading the file with the given name from the given server.
+    ///
+    /// This function returns `Err(Error::Illegal)` if a transfer is in progress,
+    /// and `Err(Error::Exhausted)` if the name of the file is too long.
+    pub fn read(&mut self, server: IpAddress, filename: &str) -> Result<()> {
+        let options = TftpOptions {
+            transfer_size: Some(0),
+            ..self.options()
+        };
+        self.request(server, Direction::Read,
+                     TftpRepr::ReadRequest { filename, mode: TftpMode::Octet, options })
+    }
+
+    /// Start writing the file with the given name to the given server.
+    ///
+    /// This function returns `Err(Error::Illegal)` if a transfer is in progress,
+    /// and `Err(Error::Exhausted)` if the name of the file is too long.
+    pub fn write(&mut self, server: IpAddress, filename: &str) -> Result<()> {
+        let options = self.options();
+        self.request(server, Direction::Write,
+                     TftpRepr::WriteRequest { filename, mode: TftpMode::Octet, options })
+    }
+
+    fn options(&self) -> TftpOptions {
+        TftpOptions {
+            block_size: if self.block_size != TFTP_DEFAULT_BLOCK_SIZE {
+                Some(self.block_size as u16)
+            } else {
+                None
+            },
+            ..TftpOptions::default()
+        }
+    }
+
+    fn request(&mut self, server: IpAddress, direction: Direction,
+               repr: TftpRepr) -> Result<()> {
+        if !self.is_idle() { return Err(Error::Illegal) }
+        if repr.buffer_len() > MAX_REQUEST_LEN { return Err(Error::Exhausted) }
+
+        self.request_len = repr.buffer_len();
+        repr.emit(&mut TftpPacket::new(&mut self.request[..self.request_len]));
+        self.transfer_size = None;
+        self.state = State::Requesting { server, direction, sent_at: None, retransmits: 0 };
+        Ok(())
+    }
+
+    /// Abort the transfer in progress, if any, letting the server know about it.
+    pub fn abort(&mut self, sockets: &mut SocketSet) -> Result<()> {
+        let state = self.state;
+        self.state = State::Idle;
+        if let State::Transferring(mut transfer) = state {
+            let mut udp_socket = sockets.get::<UdpSocket>(self.udp_handle);
+            transfer.abort(&mut udp_socket, TftpErrorCode::Undefined)?;
+        }
+        Ok(())
+    }
+
+    /// Return the time, in milliseconds, at which the client should be polled next,
+    /// assuming no packets arrive in the meantime, if a transfer is in progress.
+    pub fn poll_at(&self) -> Option<u64> {
+        match self.state {
+            State::Idle => None,
+            State::Requesting { sent_at: None, .. } => Some(0),
+            State::Requesting { sent_at: Some(sent_at), .. } => Some(sent_at + self.timeout),
+            State::Transferring(ref transfer) => Some(transfer.poll_at()),
+        }
+    }
+
+    /// Process the packets received by the UDP socket, reading or writing the file,
+    /// and send the request, or retransmit the last packet, if it is due.
+    ///
+    /// Once the transfer is over, its outcome is returned, and the client becomes idle.
+    pub fn poll<F: File>(&mut self, sockets: &mut SocketSet, file: &mut F,
+                         now: u64) -> Result<Option<Event>> {
+        let mut udp_socket = sockets.get::<UdpSocket>(self.udp_handle);
+        if !udp_socket.is_open() {
+            udp_socket.bind(self.local_port)?;
+        }
+
+        let mut buffer = [0; 4 + MAX_BLOCK_SIZE];
+        while let Ok((length, endpoint)) = udp_socket.recv_slice(&mut buffer) {
+            let repr = match TftpRepr::parse(&TftpPacket::new(&buffer[..length])) {
+                Ok(repr) => repr,
+                Err(_) => {
+                    net_debug!("TFTP ignoring malformed packet from {}", endpoint);
+                    continue
+                }
+            };
+            let event = self.ingress(&mut udp_socket, file, endpoint, &repr, now)?;
+            if event.is_some() {
+                self.state = State::Idle;
+                return Ok(event)
+            }
+        }
+
+        let event = match self.state {
+            State::Idle => None,
+            State::Requesting { server, ref mut sent_at, ref mut retransmits, .. } => {
+                match *sent_at {
+                    Some(at) if now < at + self.timeout => None,
+                    Some(_) if *retransmits == MAX_RETRANSMITS => {
+                        net_debug!("TFTP request to {} timed out", server);
+                        Some(Event::TimedOut)
+                    }
+                    _ => {
+                        if sent_at.is_some() { *retransmits += 1 }
+                        *sent_at = Some(now);
+                        let endpoint = IpEndpoint::new(server, TFTP_SERVER_PORT);
+                        udp_socket.send_slice(&self.request[..self.request_len], endpoint)?;
+                        net_trace!("TFTP sent request to {}", endpoint);
+                        None
+                    }
+                }
+            }
+            State::Transferring(ref mut transfer) =>
+                transfer.poll(&mut udp_socket, file, now)?
+        };
+        if event.is_some() {
+            self.state = State::Idle;
+        }
+        Ok(event)
+    }
+
+    fn ingress<F: File>(&mut self, udp_socket: &mut UdpSocket, file: &mut F,
+                        endpoint: IpEndpoint, repr: &TftpRepr, now: u64) ->
+                       Result<Option<Event>> {
+        let (server, direction) = match self.state {
+            State::Idle => {
+                net_trace!("TFTP ignoring packet from {} while idle", endpoint);
+                return Ok(None)
+            }
+            State::Transferring(ref mut transfer) =>
+                return transfer.process(udp_socket, file, endpoint, repr, now),
+            State::Requesting { server, direction, .. } => (server, direction)
+        };
+        if endpoint.addr != server {
+            net_trace!("TFTP ignoring packet from {}", endpoint);
+            return Ok(None)
+        }
+
+        let sending = direction == Direction::Write;
+        let transfer = match (direction, *repr) {
+            (_, TftpRepr::Error { code, .. }) => {
+                net_debug!("TFTP request refused by {}: {}", endpoint, code);
+                return Ok(Some(Event::Refused(code)))
+            }
+            (_, TftpRepr::OptionAck { options }) => {
+                let block_size = match options.block_size {
+                    None => TFTP_DEFAULT_BLOCK_SIZE,
+                    Some(size) if size >= 8 && size as usize <= self.block_size =>
+                        size as usize,
+                    Some(_) => {
+                        let mut transfer = Transfer::new(sending, endpoint, self.block_size,
+                                                         self.timeout, None, now);
+                        return transfer.abort(udp_socket, TftpErrorCode::OptionRefused)
+                                       .map(Some)
+                    }
+                };
+                self.transfer_size = options.transfer_size.map(|size| size as usize);
+                let timeout = options.timeout.map_or(self.timeout,
+                                                     |timeout| timeout as u64 * 1000);
+                let mut transfer = Transfer::new(sending, endpoint, block_size, timeout,
+                                                 None, now);
+                if let Some(event) = transfer.start(udp_socket, file)? {
+                    return Ok(Some(event))
+                }
+                transfer
+            }
+            (Direction::Read, TftpRepr::Data { block_num: 1, .. }) => {
+                let mut transfer = Transfer::new(false, endpoint, TFTP_DEFAULT_BLOCK_SIZE,
+                                                 self.timeout, None, now);
+                if let Some(event) = transfer.process(udp_socket, file, endpoint, repr, now)? {
+                    return Ok(Some(event))
+                }
+                transfer
+            }
+            (Direction::Write, TftpRepr::Ack { block_num: 0 }) => {
+                let mut transfer = Transfer::new(true, endpoint, TFTP_DEFAULT_BLOCK_SIZE,
+                                                 self.timeout, None, now);
+                if let Some(event) = transfer.start(udp_socket, file)? {
+                    return Ok(Some(event))
+                }
+                transfer
+            }
+            _ => {
+                net_debug!("TFTP ignoring unexpected packet from {}", endpoint);
+                return Ok(None)
+            }
+        };
+        net_debug!("TFTP transfer with {} accepted", transfer.peer());
+        self.state = State::Transferring(transfer);
+        Ok(None)
+    }
+}
+
+#[cfg(all(test, feature = "proto-ipv4"))]
+mod test {
+    use std::vec::Vec;
+    use wire::{Ipv4Address, IpProtocol, IpRepr, UdpRepr};
+    use socket::UdpPacketBuffer;
+    use super::*;
+
+    const SERVER: IpAddress = IpAddress::Ipv4(Ipv4Address([192, 168, 1, 1]));
+    const LOCAL_IP: IpAddress = IpAddress::Ipv4(Ipv4Address([192, 168, 1, 10]));
+    const LOCAL_PORT: u16 = 50069;
+    const TRANSFER_PORT: u16 = 41000;
+
+    struct TestFile(Vec<u8>);
+
+    impl File for TestFile {
+        fn read(&mut self, offset: usize, buffer: &mut [u8]) ->
+                ::core::result::Result<usize, TftpErrorCode> {
+            let data = &self.0[offset..];
+            let len = ::core::cmp::min(data.len(), buffer.len());
+            buffer[..len].copy_from_slice(&data[..len]);
+            Ok(len)
+        }
+
+        fn write(&mut self, offset: usize, data: &[u8]) ->
+                ::core::result::Result<(), TftpErrorCode> {
+            if offset + data.len() > 1024 { return Err(TftpErrorCode::DiskFull) }
+            assert_eq!(offset, self.0.len());
+            self.0.extend_from_slice(data);
+            Ok(())
+        }
+    }
+
+    fn buffer() -> UdpSocketBuffer<'static, 'static> {
+        UdpSocketBuffer::new((0..4).map(|_| UdpPacketBuffer::new(vec![0; 600]))
+                                   .collect::<Vec<_>>())
+    }
+
+    fn setup() -> (SocketSet<'static, 'static, 'static>, Client) {
+        let mut sockets = SocketSet::new(vec![]);
+        let client = Client::new(&mut sockets, buffer(), buffer(), LOCAL_PORT);
+        (sockets, client)
+    }
+
+    fn sent(sockets: &mut SocketSet, client: &Client) -> Option<(u16, Vec<u8>)> {
+        let mut result = None;
+        let _ = sockets.get::<UdpSocket>(client.udp_handle())
+            .dispatch(0, |(ip_repr, udp_repr)| {
+                assert_eq!(ip_repr.dst_addr(), SERVER);
+                assert_eq!(udp_repr.src_port, LOCAL_PORT);
+                result = Some((udp_repr.dst_port, udp_repr.payload.to_vec()));
+                Ok(())
+            });
+        result
+    }
+
+    fn recv(sockets: &mut SocketSet, client: &Client, src_port: u16, repr: &TftpRepr) {
+        let mut payload = vec![0; repr.buffer_len()];
+        repr.emit(&mut TftpPacket::new(&mut payload[..]));
+        let udp_repr = UdpRepr {
+            src_port: src_port,
+            dst_port: LOCAL_PORT,
+            payload:  &payload
+        };
+        let ip_repr = IpRepr::Unspecified {
+            src_addr: SERVER,
+            dst_addr: LOCAL_IP,
+            protocol: IpProtocol::Udp,
+            payload_len: udp_repr.buffer_len(),
+            hop_limit: 64,
+            ecn: 0,
+            dscp: 0,
+            flow_label: 0
+        };
+        sockets.get::<UdpSocket>(client.udp_handle())
+            .process_lent(0, None, &ip_repr, &udp_repr, None).unwrap();
+    }
+
+    fn parse(payload: &[u8]) -> TftpRepr {
+        TftpRepr::parse(&TftpPacket::new(payload)).unwrap()
+    }
+
+    #[test]
+    fn test_read() {
+        let (mut sockets, mut client) = setup();
+        let mut file = TestFile(vec![]);
+        client.read(SERVER, "fw.bin").unwrap();
+        assert_eq!(client.read(SERVER, "fw.bin"), Err(Error::Illegal));
+        assert_eq!(client.poll(&mut sockets, &mut file, 0), Ok(None));
+
+        let (port, payload) = sent(&mut sockets, &client).unwrap();
+        assert_eq!(port, TFTP_SERVER_PORT);
+        assert_eq!(parse(&payload), TftpRepr::ReadRequest {
+            filename: "fw.bin",
+            mode:     TftpMode::Octet,
+            options:  TftpOptions { transfer_size: Some(0), ..TftpOptions::default() }
+        });
+
+        // The server ignores the options.
+        let block = [0xaa; 512];
+        recv(&mut sockets, &client, TRANSFER_PORT, &TftpRepr::Data { block_num: 1, data: &block });
+        assert_eq!(client.poll(&mut sockets, &mut file, 10), Ok(None));
+        let (port, payload) = sent(&mut sockets, &client).unwrap();
+        assert_eq!(port, TRANSFER_PORT);
+        assert_eq!(parse(&payload), TftpRepr::Ack { block_num: 1 });
+        assert_eq!(client.transfer_size(), None);
+
+        // The block is duplicated, and acknowledged again.
+        recv(&mut sockets, &client, TRANSFER_PORT, &TftpRepr::Data { block_num: 1, data: &block });
+        assert_eq!(client.poll(&mut sockets, &mut file, 20), Ok(None));
+        assert_eq!(parse(&sent(&mut sockets, &client).unwrap().1), TftpRepr::Ack { block_num: 1 });
+
+        recv(&mut sockets, &client, TRANSFER_PORT, &TftpRepr::Data { block_num: 2, data: &[1, 2] });
+        assert_eq!(client.poll(&mut sockets, &mut file, 30),
+                   Ok(Some(Event::Completed { size: 514 })));
+        assert_eq!(parse(&sent(&mut sockets, &client).unwrap().1), TftpRepr::Ack { block_num: 2 });
+        assert!(client.is_idle());
+        assert_eq!(file.0.len(), 514);
+        assert_eq!(&file.0[512..], &[1, 2]);
+    }
+
+    #[test]
+    fn test_read_options() {
+        let (mut sockets, mut client) = setup();
+        let mut file = TestFile(vec![]);
+        client.set_block_size(256);
+        client.read(SERVER, "fw.bin").unwrap();
+        client.poll(&mut sockets, &mut file, 0).unwrap();
+        assert_eq!(parse(&sent(&mut sockets, &client).unwrap().1), TftpRepr::ReadRequest {
+            filename: "fw.bin",
+            mode:     TftpMode::Octet,
+            options:  TftpOptions {
+                block_size:    Some(256),
+                timeout:       None,
+                transfer_size: Some(0),
+            }
+        });
+
+        let options = TftpOptions {
+            block_size:    Some(128),
+            timeout:       None,
+            transfer_size: Some(200),
+        };
+        recv(&mut sockets, &client, TRANSFER_PORT, &TftpRepr::OptionAck { options });
+        assert_eq!(client.poll(&mut sockets, &mut file, 10), Ok(None));
+        assert_eq!(client.transfer_size(), Some(200));
+        assert_eq!(parse(&sent(&mut sockets, &client).unwrap().1), TftpRepr::Ack { block_num: 0 });
+
+        recv(&mut sockets, &client, TRANSFER_PORT,
+             &TftpRepr::Data { block_num: 1, data: &[0; 128] });
+        assert_eq!(client.poll(&mut sockets, &mut file, 20), Ok(None));
+        recv(&mut sockets, &client, TRANSFER_PORT,
+             &TftpRepr::Data { block_num: 2, data: &[0; 72] });
+        assert_eq!(client.poll(&mut sockets, &mut file, 30),
+                   Ok(Some(Event::Completed { size: 200 })));
+    }
+
+    #[test]
+    fn test_read_disk_full() {
+        let (mut sockets, mut client) = setup();
+        let mut file = TestFile(vec![]);
+        client.read(SERVER, "fw.bin").unwrap();
+        client.poll(&mut sockets, &mut file, 0).unwrap();
+        sent(&mut sockets, &client).unwrap();
+
+        for block_num in 1..3 {
+            recv(&mut sockets, &client, TRANSFER_PORT,
+                 &TftpRepr::Data { block_num, data: &[0; 512] });
+            assert_eq!(client.poll(&mut sockets, &mut file, 10), Ok(None));
+            sent(&mut sockets, &client).unwrap();
+        }
+        recv(&mut sockets, &client, TRANSFER_PORT,
+             &TftpRepr::Data { block_num: 3, data: &[0; 512] });
+        assert_eq!(client.poll(&mut sockets, &mut file, 10),
+                   Ok(Some(Event::Aborted(TftpErrorCode::DiskFull))));
+        assert_eq!(parse(&sent(&mut sockets, &client).unwrap().1),
+                   TftpRepr::Error { code: TftpErrorCode::DiskFull, message: "" });
+    }
+
+    #[test]
+    fn test_write() {
+        let (mut sockets, mut client) = setup();
+        let mut file = TestFile(vec![0x55; 600]);
+        client.write(SERVER, "log.txt").unwrap();
+        client.poll(&mut sockets, &mut file, 0).unwrap();
+        assert_eq!(parse(&sent(&mut sockets, &client).unwrap().1), TftpRepr::WriteRequest {
+            filename: "log.txt",
+            mode:     TftpMode::Octet,
+            options:  TftpOptions::default()
+        });
+
+        recv(&mut sockets, &client, TRANSFER_PORT, &TftpRepr::Ack { block_num: 0 });
+        assert_eq!(client.poll(&mut sockets, &mut file, 10), Ok(None));
+        assert_eq!(parse(&sent(&mut sockets, &client).unwrap().1),
+                   TftpRepr::Data { block_num: 1, data: &[0x55; 512] });
+
+        // Another host cannot interfere with the transfer.
+        recv(&mut sockets, &client, TRANSFER_PORT + 1, &TftpRepr::Ack { block_num: 1 });
+        assert_eq!(client.poll(&mut sockets, &mut file, 20), Ok(None));
+        let (port, payload) = sent(&mut sockets, &client).unwrap();
+        assert_eq!(port, TRANSFER_PORT + 1);
+        assert_eq!(parse(&payload),
+                   TftpRepr::Error { code: TftpErrorCode::UnknownTransferId, message: "" });
+
+        // A duplicate acknowledgement does not cause a retransmission.
+        recv(&mut sockets, &client, TRANSFER_PORT, &TftpRepr::Ack { block_num: 0 });
+        assert_eq!(client.poll(&mut sockets, &mut file, 30), Ok(None));
+        assert_eq!(sent(&mut sockets, &client), None);
+
+        recv(&mut sockets, &client, TRANSFER_PORT, &TftpRepr::Ack { block_num: 1 });
+        assert_eq!(client.poll(&mut sockets, &mut file, 40), Ok(None));
+        assert_eq!(parse(&sent(&mut sockets, &client).unwrap().1),
+                   TftpRepr::Data { block_num: 2, data: &[0x55; 88] });
+
+        recv(&mut sockets, &client, TRANSFER_PORT, &TftpRepr::Ack { block_num: 2 });
+        assert_eq!(client.poll(&mut sockets, &mut file, 50),
+                   Ok(Some(Event::Completed { size: 600 })));
+    }
+
+    #[test]
+    fn test_retransmit() {
+        let (mut sockets, mut client) = setup();
+        let mut file = TestFile(vec![]);
+        client.read(SERVER, "fw.bin").unwrap();
+        assert_eq!(client.poll_at(), Some(0));
+        client.poll(&mut sockets, &mut file, 0).unwrap();
+        sent(&mut sockets, &client).unwrap();
+        assert_eq!(client.poll_at(), Some(DEFAULT_TIMEOUT));
+
+        let mut now = 0;
+        for _ in 0..MAX_RETRANSMITS {
+            now += DEFAULT_TIMEOUT;
+            assert_eq!(client.poll(&mut sockets, &mut file, now), Ok(None));
+            assert_eq!(sent(&mut sockets, &client).unwrap().0, TFTP_SERVER_PORT);
+        }
+        now += DEFAULT_TIMEOUT;
+        assert_eq!(client.poll(&mut sockets, &mut file, now), Ok(Some(Event::TimedOut)));
+        assert_eq!(sent(&mut sockets, &client), None);
+        assert!(client.is_idle());
+        assert_eq!(client.poll_at(), None);
+    }
+
+    #[test]
+    fn test_refused() {
+        let (mut sockets, mut client) = setup();
+        let mut file = TestFile(vec![]);
+        client.read(SERVER, "missing").unwrap();
+        client.poll(&mut sockets, &mut file, 0).unwrap();
+        recv(&mut sockets, &client, TRANSFER_PORT,
+             &TftpRepr::Error { code: TftpErrorCode::FileNotFound, message: "no" });
+        assert_eq!(client.poll(&mut sockets, &mut file, 10),
+                   Ok(Some(Event::Refused(TftpErrorCode::FileNotFound))));
+        assert!(client.is_idle());
+    }
+}
@@ -0,0 +1,70 @@
+/*! Trivial File Transfer Protocol.
+
+The `tftp` module implements a TFTP client and a TFTP server, on top of UDP sockets,
+that transfer one file at a time in either direction, retransmitting lost packets,
+and negotiate the block size, the retransmission timeout and the transfer size.
+The contents of the file are read and written by the application through
+the [File](trait.File.html) trait, e.g. straight from and into flash memory,
+so that a bootloader can download a firmware image.
+*/
+
+use wire::TftpErrorCode;
+
+mod transfer;
+mod client;
+mod server;
+
+pub use self::client::Client;
+pub use self::server::Server;
+
+/// The largest block size that can be negotiated, which keeps data packets
+/// within an Ethernet frame over either IP version.
+pub const MAX_BLOCK_SIZE: usize = 1428;
+
+/// The contents of a file transferred with TFTP.
+///
+/// A file is read and written in blocks at increasing offsets, but the same block
+/// may be read more than once if it has to be retransmitted.
+pub trait File {
+    /// Read the contents of the file at the given offset into the buffer, and return
+    /// how many octets were read, which is less than the length of the buffer only
+    /// at the end of the file.
+    ///
+    /// If the file cannot be read, the transfer is aborted with the returned error.
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<usize, TftpErrorCode>;
+
+    /// Write the data to the file at the given offset.
+    ///
+    /// If the file cannot be written, e.g. because it would be too large, the transfer
+    /// is aborted with the returned error.
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), TftpErrorCode>;
+
+    /// Return the size of the file, if known, which the server reports to a client
+    /// reading it that asks for it.
+    fn size(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// The direction of a transfer, as requested by the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// The client reads the file from the server.
+    Read,
+    /// The client writes the file to the server.
+    Write,
+}
+
+/// The outcome of a transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// The file was transferred completely, and is `size` octets long.
+    Completed { size: usize },
+    /// The peer refused the request or aborted the transfer, with the given error.
+    Refused(TftpErrorCode),
+    /// The file could not be read or written, or the peer misbehaved, and the transfer
+    /// was aborted with the given error.
+    Aborted(TftpErrorCode),
+    /// The peer stopped responding.
+    TimedOut,
+}
@@ -0,0 +1,468 @@
+// Heads up! Before working on this file you should read RFC 1350, which describes TFTP,
+// and RFC 2347, RFC 2348 and RFC 2349, which describe option negotiation.
+
+use core::cmp;
+
+use Result;
+use wire::{IpEndpoint, TftpPacket, TftpRepr, TftpOptions, TftpErrorCode};
+use wire::{TFTP_SERVER_PORT, TFTP_DEFAULT_BLOCK_SIZE};
+use socket::{SocketSet, SocketHandle, UdpSocket, UdpSocketBuffer};
+use super::{File, Direction, Event, MAX_BLOCK_SIZE};
+use super::transfer::{self, Transfer, DEFAULT_TIMEOUT};
+
+/// A TFTP server.
+///
+/// The server listens for requests on a UDP socket bound to the TFTP port, and carries
+/// out one transfer at a time through another UDP socket, bound to the transfer port;
+/// requests made while a transfer is in progress are refused. Both sockets are added
+/// to the socket set.
+///
+/// For every request, [poll] calls back into the application with the name of
+/// the requested file, to open it, and reports the outcome of the transfer once it
+/// is over. Options proposed by clients are accepted, with the block size limited to
+/// the one set with [set_max_block_size]. Files requested in the netascii mode are
+/// transferred without conversion.
+///
+/// [poll]: #method.poll
+/// [set_max_block_size]: #method.set_max_block_size
+#[derive(Debug)]
+pub struct Server<F: File> {
+    udp_handle:      SocketHandle,
+    transfer_handle: SocketHandle,
+    transfer_port:   u16,
+    max_block_size:  usize,
+    transfer:        Option<(Transfer, F)>,
+}
+
+impl<F: File> Server<F> {
+    /// Create a TFTP server carrying out transfers from the given local port, and add
+    /// its UDP sockets, created with the given buffers, to the socket set.
+    ///
+    /// The buffers of the listening socket only need to hold requests and errors,
+    /// while the packets of the buffers of the transfer socket should be large enough
+    /// to hold a data block.
+    pub fn new<'a, 'b, 'c>(sockets: &mut SocketSet<'a, 'b, 'c>,
+                           rx_buffer: UdpSocketBuffer<'b, 'c>,
+                           tx_buffer: UdpSocketBuffer<'b, 'c>,
+                           transfer_rx_buffer: UdpSocketBuffer<'b, 'c>,
+                           transfer_tx_buffer: UdpSocketBuffer<'b, 'c>,
+                           transfer_port: u16) -> Server<F> {
+        let udp_handle = sockets.add(UdpSocket::new(rx_buffer, tx_buffer));
+        let transfer_handle = sockets.add(UdpSocket::new(transfer_rx_buffer,
+                                                         transfer_tx_buffer));
+        Server {
+            udp_handle:      udp_handle,
+            transfer_handle: transfer_handle,
+            transfer_port:   transfer_port,
+            max_block_size:  TFTP_DEFAULT_BLOCK_SIZE,
+            transfer:        None,
+        }
+    }
+
+    /// Return the handle of the UDP socket the server listens on.
+    pub fn udp_handle(&self) -> SocketHandle {
+        self.udp_handle
+    }
+
+    /// Return the handle of the UDP socket transfers are carried out through.
+    pub fn transfer_handle(&self) -> SocketHandle {
+        self.transfer_handle
+    }
+
+    /// Return the largest block size accepted from clients.
+    pub fn max_block_size(&self) -> usize {
+        self.max_block_size
+    }
+
+    /// Set the largest block size accepted from clients. The default is the default
+    /// block size (512).
+    ///
+    /// # Panics
+    /// This function panics if the block size is smaller than 8 or larger than
+    /// [MAX_BLOCK_SIZE](constant.MAX_BLOCK_SIZE.html).
+    pub fn set_max_block_size(&mut self, block_size: usize) {
+        assert!(block_size >= 8 && block_size <= MAX_BLOCK_SIZE, "invalid TFTP block size");
+        self.max_block_size = block_size
+    }
+
+    /// Query whether no transfer is in progress.
+    pub fn is_idle(&self) -> bool {
+        self.transfer.is_none()
+    }
+
+    /// Return the time, in milliseconds, at which the server should be polled next,
+    /// assuming no packets arrive in the meantime, if a transfer is in progress.
+    pub fn poll_at(&self) -> Option<u64> {
+        self.transfer.as_ref().map(|&(ref transfer, _)| transfer.poll_at())
+    }
+
+    /// Process the requests received by the listening socket, opening the requested
+    /// files with `open`, and the packets received by the transfer socket, reading or
+    /// writing the file being transferred, and retransmit the last packet, if it is due.
+    ///
+    /// If `open` returns an error, the request is refused with it. Once a transfer
+    /// is over, its outcome is returned, and the file is dropped.
+    pub fn poll<O>(&mut self, sockets: &mut SocketSet, now: u64, mut open: O) ->
+                  Result<Option<Event>>
+            where O: FnMut(&str, Direction) -> ::core::result::Result<F, TftpErrorCode> {
+        {
+            let mut udp_socket = sockets.get::<UdpSocket>(self.udp_handle);
+            if !udp_socket.is_open() {
+                udp_socket.bind(TFTP_SERVER_PORT)?;
+            }
+        }
+        {
+            let mut transfer_socket = sockets.get::<UdpSocket>(self.transfer_handle);
+            if !transfer_socket.is_open() {
+                transfer_socket.bind(self.transfer_port)?;
+            }
+        }
+
+        let mut buffer = [0; 4 + MAX_BLOCK_SIZE];
+        loop {
+            let (length, endpoint) =
+                match sockets.get::<UdpSocket>(self.udp_handle).recv_slice(&mut buffer) {
+                    Ok(result) => result,
+                    Err(_) => break
+                };
+            let (filename, direction, options) =
+                match TftpRepr::parse(&TftpPacket::new(&buffer[..length])) {
+                    Ok(TftpRepr::ReadRequest { filename, options, .. }) =>
+                        (filename, Direction::Read, options),
+                    Ok(TftpRepr::WriteRequest { filename, options, .. }) =>
+                        (filename, Direction::Write, options),
+                    _ => {
+                        net_debug!("TFTP ignoring packet from {} that is not a request",
+                                   endpoint);
+                        continue
+                    }
+                };
+
+            let mut udp_socket = sockets.get::<UdpSocket>(self.udp_handle);
+            if self.transfer.is_some() {
+                net_debug!("TFTP refusing request from {} during a transfer", endpoint);
+                transfer::send_error(&mut udp_socket, endpoint, TftpErrorCode::Undefined,
+                                     "busy")?;
+                continue
+            }
+            let mut file = match open(filename, direction) {
+                Ok(file) => file,
+                Err(code) => {
+                    net_debug!("TFTP refusing request for {} from {}: {}",
+                               filename, endpoint, code);
+                    transfer::send_error(&mut udp_socket, endpoint, code, "")?;
+                    continue
+                }
+            };
+            net_debug!("TFTP accepting {:?} request for {} from {}",
+                       direction, filename, endpoint);
+            drop(udp_socket);
+
+            let mut transfer = self.accept(endpoint, direction, &options, &file, now);
+            let mut transfer_socket = sockets.get::<UdpSocket>(self.transfer_handle);
+            if let Some(event) = transfer.start(&mut transfer_socket, &mut file)? {
+                return Ok(Some(event))
+            }
+            self.transfer = Some((transfer, file));
+        }
+
+        let mut transfer_socket = sockets.get::<UdpSocket>(self.transfer_handle);
+        let event = match self.transfer {
+            None => {
+                // Stray packets of a finished transfer.
+                while let Ok((_, endpoint)) = transfer_socket.recv_slice(&mut buffer) {
+                    transfer::send_error(&mut transfer_socket, endpoint,
+                                         TftpErrorCode::UnknownTransferId, "")?;
+                }
+                None
+            }
+            Some((ref mut transfer, ref mut file)) => {
+                let mut event = None;
+                while let Ok((length, endpoint)) = transfer_socket.recv_slice(&mut buffer) {
+                    let repr = match TftpRepr::parse(&TftpPacket::new(&buffer[..length])) {
+                        Ok(repr) => repr,
+                        Err(_) => {
+                            net_debug!("TFTP ignoring malformed packet from {}", endpoint);
+                            continue
+                        }
+                    };
+                    event = transfer.process(&mut transfer_socket, file, endpoint, &repr, now)?;
+                    if event.is_some() { break }
+                }
+                match event {
+                    Some(event) => Some(event),
+                    None => transfer.poll(&mut transfer_socket, file, now)?
+                }
+            }
+        };
+        if event.is_some() {
+            self.transfer = None;
+        }
+        Ok(event)
+    }
+
+    /// Negotiate the options proposed by a client, and create the transfer.
+    fn accept(&self, endpoint: IpEndpoint, direction: Direction, options: &TftpOptions,
+              file: &F, now: u64) -> Transfer {
+        let mut accepted = TftpOptions::default();
+        let mut block_size = TFTP_DEFAULT_BLOCK_SIZE;
+        let mut timeout = DEFAULT_TIMEOUT;
+        match options.block_size {
+            Some(size) if size >= 8 => {
+                block_size = cmp::min(size as usize, self.max_block_size);
+                accepted.block_size = Some(block_size as u16);
+            }
+            _ => ()
+        }
+        match options.timeout {
+            Some(seconds) if seconds >= 1 => {
+                timeout = seconds as u64 * 1000;
+                accepted.timeout = Some(seconds);
+            }
+            _ => ()
+        }
+        match (options.transfer_size, direction) {
+            (Some(_), Direction::Read) =>
+                accepted.transfer_size = file.size().map(|size| size as u32),
+            (Some(size), Direction::Write) =>
+                accepted.transfer_size = Some(size),
+            (None, _) => ()
+        }
+
+        let options_ack = if accepted.is_empty() { None } else { Some(accepted) };
+        Transfer::new(direction == Direction::Read, endpoint, block_size, timeout,
+                      options_ack, now)
+    }
+}
+
+#[cfg(all(test, feature = "proto-ipv4"))]
+mod test {
+    use std::vec::Vec;
+    use wire::{IpAddress, Ipv4Address, IpProtocol, IpRepr, UdpRepr, TftpMode};
+    use socket::UdpPacketBuffer;
+    use super::*;
+
+    const CLIENT: IpAddress = IpAddress::Ipv4(Ipv4Address([192, 168, 1, 10]));
+    const LOCAL_IP: IpAddress = IpAddress::Ipv4(Ipv4Address([192, 168, 1, 1]));
+    const CLIENT_PORT: u16 = 50069;
+    const TRANSFER_PORT: u16 = 41000;
+
+    struct TestFile(Vec<u8>);
+
+    impl File for TestFile {
+        fn read(&mut self, offset: usize, buffer: &mut [u8]) ->
+                ::core::result::Result<usize, TftpErrorCode> {
+            let data = &self.0[offset..];
+            let len = cmp::min(data.len(), buffer.len());
+            buffer[..len].copy_from_slice(&data[..len]);
+            Ok(len)
+        }
+
+        fn write(&mut self, offset: usize, data: &[u8]) ->
+                ::core::result::Result<(), TftpErrorCode> {
+            assert_eq!(offset, self.0.len());
+            self.0.extend_from_slice(data);
+            Ok(())
+        }
+
+        fn size(&self) -> Option<usize> {
+            Some(self.0.len())
+        }
+    }
+
+    fn buffer() -> UdpSocketBuffer<'static, 'static> {
+        UdpSocketBuffer::new((0..4).map(|_| UdpPacketBuffer::new(vec![0; 600]))
+                                   .collect::<Vec<_>>())
+    }
+
+    fn setup() -> (SocketSet<'static, 'static, 'static>, Server<TestFile>) {
+        let mut sockets = SocketSet::new(vec![]);
+        let mut server = Server::new(&mut sockets, buffer(), buffer(), buffer(), buffer(),
+                                     TRANSFER_PORT);
+        // Bind the sockets.
+        assert_eq!(server.poll(&mut sockets, 0, open), Ok(None));
+        (sockets, server)
+    }
+
+    fn sent(sockets: &mut SocketSet, handle: SocketHandle) -> Option<(u16, Vec<u8>)> {
+        let mut result = None;
+        let _ = sockets.get::<UdpSocket>(handle)
+            .dispatch(0, |(ip_repr, udp_repr)| {
+                assert_eq!(ip_repr.dst_addr(), CLIENT);
+                assert_eq!(udp_repr.dst_port, CLIENT_PORT);
+                result = Some((udp_repr.src_port, udp_repr.payload.to_vec()));
+                Ok(())
+            });
+        result
+    }
+
+    fn recv(sockets: &mut SocketSet, handle: SocketHandle, dst_port: u16, repr: &TftpRepr) {
+        let mut payload = vec![0; repr.buffer_len()];
+        repr.emit(&mut TftpPacket::new(&mut payload[..]));
+        let udp_repr = UdpRepr {
+            src_port: CLIENT_PORT,
+            dst_port: dst_port,
+            payload:  &payload
+        };
+        let ip_repr = IpRepr::Unspecified {
+            src_addr: CLIENT,
+            dst_addr: LOCAL_IP,
+            protocol: IpProtocol::Udp,
+            payload_len: udp_repr.buffer_len(),
+            hop_limit: 64,
+            ecn: 0,
+            dscp: 0,
+            flow_label: 0
+        };
+        sockets.get::<UdpSocket>(handle).process_lent(0, None, &ip_repr, &udp_repr, None).unwrap();
+    }
+
+    fn parse(payload: &[u8]) -> TftpRepr {
+        TftpRepr::parse(&TftpPacket::new(payload)).unwrap()
+    }
+
+    fn open(filename: &str, direction: Direction) ->
+            ::core::result::Result<TestFile, TftpErrorCode> {
+        match (filename, direction) {
+            ("fw.bin", Direction::Read) => Ok(TestFile(vec![0xaa; 300])),
+            ("log.txt", Direction::Write) => Ok(TestFile(vec![])),
+            ("fw.bin", Direction::Write) => Err(TftpErrorCode::AccessViolation),
+            _ => Err(TftpErrorCode::FileNotFound)
+        }
+    }
+
+    #[test]
+    fn test_read_options() {
+        let (mut sockets, mut server) = setup();
+        server.set_max_block_size(128);
+
+        let options = TftpOptions {
+            block_size:    Some(1024),
+            timeout:       Some(3),
+            transfer_size: Some(0),
+        };
+        recv(&mut sockets, server.udp_handle(), TFTP_SERVER_PORT,
+             &TftpRepr::ReadRequest { filename: "fw.bin", mode: TftpMode::Octet, options });
+        assert_eq!(server.poll(&mut sockets, 0, open), Ok(None));
+        assert!(!server.is_idle());
+        assert_eq!(server.poll_at(), Some(3_000));
+
+        let transfer_handle = server.transfer_handle();
+        let (port, payload) = sent(&mut sockets, transfer_handle).unwrap();
+        assert_eq!(port, TRANSFER_PORT);
+        assert_eq!(parse(&payload), TftpRepr::OptionAck {
+            options: TftpOptions {
+                block_size:    Some(128),
+                timeout:       Some(3),
+                transfer_size: Some(300),
+            }
+        });
+
+        // The option acknowledgement is retransmitted until it is confirmed.
+        assert_eq!(server.poll(&mut sockets, 3_000, open), Ok(None));
+        assert_eq!(parse(&sent(&mut sockets, transfer_handle).unwrap().1),
+                   TftpRepr::OptionAck { options: TftpOptions {
+                       block_size:    Some(128),
+                       timeout:       Some(3),
+                       transfer_size: Some(300),
+                   }});
+
+        recv(&mut sockets, transfer_handle, TRANSFER_PORT, &TftpRepr::Ack { block_num: 0 });
+        assert_eq!(server.poll(&mut sockets, 3_010, open), Ok(None));
+        assert_eq!(parse(&sent(&mut sockets, transfer_handle).unwrap().1),
+                   TftpRepr::Data { block_num: 1, data: &[0xaa; 128] });
+
+        recv(&mut sockets, transfer_handle, TRANSFER_PORT, &TftpRepr::Ack { block_num: 1 });
+        assert_eq!(server.poll(&mut sockets, 3_020, open), Ok(None));
+        assert_eq!(parse(&sent(&mut sockets, transfer_handle).unwrap().1),
+                   TftpRepr::Data { block_num: 2, data: &[0xaa; 128] });
+
+        // The data block is retransmitted when its acknowledgement is lost.
+        assert_eq!(server.poll(&mut sockets, 6_020, open), Ok(None));
+        assert_eq!(parse(&sent(&mut sockets, transfer_handle).unwrap().1),
+                   TftpRepr::Data { block_num: 2, data: &[0xaa; 128] });
+
+        recv(&mut sockets, transfer_handle, TRANSFER_PORT, &TftpRepr::Ack { block_num: 2 });
+        assert_eq!(server.poll(&mut sockets, 6_030, open), Ok(None));
+        assert_eq!(parse(&sent(&mut sockets, transfer_handle).unwrap().1),
+                   TftpRepr::Data { block_num: 3, data: &[0xaa; 44] });
+
+        recv(&mut sockets, transfer_handle, TRANSFER_PORT, &TftpRepr::Ack { block_num: 3 });
+        assert_eq!(server.poll(&mut sockets, 6_040, open),
+                   Ok(Some(Event::Completed { size: 300 })));
+        assert!(server.is_idle());
+    }
+
+    #[test]
+    fn test_write() {
+        let (mut sockets, mut server) = setup();
+        recv(&mut sockets, server.udp_handle(), TFTP_SERVER_PORT,
+             &TftpRepr::WriteRequest {
+                 filename: "log.txt",
+                 mode:     TftpMode::NetAscii,
+                 options:  TftpOptions::default()
+             });
+        assert_eq!(server.poll(&mut sockets, 0, open), Ok(None));
+        let transfer_handle = server.transfer_handle();
+        assert_eq!(parse(&sent(&mut sockets, transfer_handle).unwrap().1),
+                   TftpRepr::Ack { block_num: 0 });
+
+        recv(&mut sockets, transfer_handle, TRANSFER_PORT,
+             &TftpRepr::Data { block_num: 1, data: &[1; 512] });
+        assert_eq!(server.poll(&mut sockets, 10, open), Ok(None));
+        assert_eq!(parse(&sent(&mut sockets, transfer_handle).unwrap().1),
+                   TftpRepr::Ack { block_num: 1 });
+
+        recv(&mut sockets, transfer_handle, TRANSFER_PORT,
+             &TftpRepr::Data { block_num: 2, data: &[] });
+        assert_eq!(server.poll(&mut sockets, 20, open),
+                   Ok(Some(Event::Completed { size: 512 })));
+        assert_eq!(parse(&sent(&mut sockets, transfer_handle).unwrap().1),
+                   TftpRepr::Ack { block_num: 2 });
+    }
+
+    #[test]
+    fn test_refused() {
+        let (mut sockets, mut server) = setup();
+        recv(&mut sockets, server.udp_handle(), TFTP_SERVER_PORT,
+             &TftpRepr::WriteRequest {
+                 filename: "fw.bin",
+                 mode:     TftpMode::Octet,
+                 options:  TftpOptions::default()
+             });
+        assert_eq!(server.poll(&mut sockets, 0, open), Ok(None));
+        assert!(server.is_idle());
+        let udp_handle = server.udp_handle();
+        let (port, payload) = sent(&mut sockets, udp_handle).unwrap();
+        assert_eq!(port, TFTP_SERVER_PORT);
+        assert_eq!(parse(&payload),
+                   TftpRepr::Error { code: TftpErrorCode::AccessViolation, message: "" });
+    }
+
+    #[test]
+    fn test_busy() {
+        let (mut sockets, mut server) = setup();
+        let request = TftpRepr::ReadRequest {
+            filename: "fw.bin",
+            mode:     TftpMode::Octet,
+            options:  TftpOptions::default()
+        };
+        recv(&mut sockets, server.udp_handle(), TFTP_SERVER_PORT, &request);
+        assert_eq!(server.poll(&mut sockets, 0, open), Ok(None));
+        let transfer_handle = server.transfer_handle();
+        assert_eq!(parse(&sent(&mut sockets, transfer_handle).unwrap().1),
+                   TftpRepr::Data { block_num: 1, data: &[0xaa; 300] });
+
+        recv(&mut sockets, server.udp_handle(), TFTP_SERVER_PORT, &request);
+        assert_eq!(server.poll(&mut sockets, 10, open), Ok(None));
+        let udp_handle = server.udp_handle();
+        assert_eq!(parse(&sent(&mut sockets, udp_handle).unwrap().1),
+                   TftpRepr::Error { code: TftpErrorCode::Undefined, message: "busy" });
+
+        recv(&mut sockets, transfer_handle, TRANSFER_PORT,
+             &TftpRepr::Error { code: TftpErrorCode::Undefined, message: "" });
+        assert_eq!(server.poll(&mut sockets, 20, open),
+                   Ok(Some(Event::Refused(TftpErrorCode::Undefined))));
+        assert!(server.is_idle());
+    }
+}
@@ -0,0 +1,190 @@
+use Result;
+use wire::{IpEndpoint, TftpPacket, TftpRepr, TftpOpCode, TftpErrorCode, TftpOptions};
+use socket::UdpSocket;
+use super::{File, Event, MAX_BLOCK_SIZE};
+
+/// The retransmission timeout used unless another one is negotiated, in milliseconds.
+pub const DEFAULT_TIMEOUT: u64 = 1_000;
+
+/// How many times a packet is retransmitted before the transfer is abandoned.
+pub const MAX_RETRANSMITS: u8 = 5;
+
+/// Emit a packet into the buffer, and return its length.
+fn emit(repr: &TftpRepr, buffer: &mut [u8]) -> usize {
+    let len = repr.buffer_len();
+    repr.emit(&mut TftpPacket::new(&mut buffer[..len]));
+    len
+}
+
+/// Send an error packet to the given endpoint.
+pub fn send_error(socket: &mut UdpSocket, endpoint: IpEndpoint,
+                  code: TftpErrorCode, message: &str) -> Result<()> {
+    let mut buffer = [0; 64];
+    let len = emit(&TftpRepr::Error { code, message }, &mut buffer);
+    socket.send_slice(&buffer[..len], endpoint)
+}
+
+/// A transfer in progress, once the request was accepted.
+///
+/// Blocks are sent in lockstep: the sender sends a block, and waits for it to be
+/// acknowledged before sending the next one. The last packet sent is retransmitted
+/// when nothing arrives in time, but never in response to a duplicate packet,
+/// which avoids the Sorcerer's Apprentice syndrome described in RFC 1123 § 4.2.3.1.
+#[derive(Debug, Clone, Copy)]
+pub struct Transfer {
+    /// Whether the file is sent rather than received.
+    sending:     bool,
+    peer:        IpEndpoint,
+    block_size:  usize,
+    timeout:     u64,
+    /// The options acknowledged to the peer, until the peer confirms them.
+    options_ack: Option<TftpOptions>,
+    /// The number of the last block sent or received.
+    block:       u16,
+    /// The offset of the last block sent, or of the end of the last block received.
+    offset:      usize,
+    /// The length of the last block sent.
+    sent_len:    usize,
+    sent_at:     u64,
+    retransmits: u8,
+}
+
+impl Transfer {
+    /// Create a transfer with the given peer. If options are given, they are sent
+    /// first, as an option acknowledgement.
+    pub fn new(sending: bool, peer: IpEndpoint, block_size: usize, timeout: u64,
+               options_ack: Option<TftpOptions>, now: u64) -> Transfer {
+        debug_assert!(block_size <= MAX_BLOCK_SIZE);
+        Transfer {
+            sending, peer, block_size, timeout, options_ack,
+            block:       if sending && options_ack.is_none() { 1 } else { 0 },
+            offset:      0,
+            sent_len:    0,
+            sent_at:     now,
+            retransmits: 0,
+        }
+    }
+
+    /// Return the endpoint of the peer.
+    pub fn peer(&self) -> IpEndpoint {
+        self.peer
+    }
+
+    /// Return the time at which the last packet is retransmitted, or the transfer
+    /// abandoned, unless the peer responds.
+    pub fn poll_at(&self) -> u64 {
+        self.sent_at + self.timeout
+    }
+
+    /// Send the first packet of the transfer: the option acknowledgement, the first block,
+    /// or the acknowledgement of the request.
+    pub fn start<F: File>(&mut self, socket: &mut UdpSocket, file: &mut F) ->
+                         Result<Option<Event>> {
+        self.transmit(socket, file)
+    }
+
+    /// Abort the transfer, letting the peer know about it.
+    pub fn abort(&mut self, socket: &mut UdpSocket, code: TftpErrorCode) -> Result<Event> {
+        net_debug!("TFTP aborting transfer with {}: {}", self.peer, code);
+        send_error(socket, self.peer, code, "")?;
+        Ok(Event::Aborted(code))
+    }
+
+    fn transmit<F: File>(&mut self, socket: &mut UdpSocket, file: &mut F) ->
+                        Result<Option<Event>> {
+        let mut buffer = [0; 4 + MAX_BLOCK_SIZE];
+        let len = match self.options_ack {
+            Some(options) => emit(&TftpRepr::OptionAck { options }, &mut buffer),
+            None if self.sending => {
+                let len = match file.read(self.offset, &mut buffer[4..4 + self.block_size]) {
+                    Ok(len) => len,
+                    Err(code) => return self.abort(socket, code).map(Some)
+                };
+                let mut packet = TftpPacket::new(&mut buffer[..]);
+                packet.set_opcode(TftpOpCode::Data);
+                packet.set_block_number(self.block);
+                self.sent_len = len;
+                4 + len
+            }
+            None => emit(&TftpRepr::Ack { block_num: self.block }, &mut buffer)
+        };
+        socket.send_slice(&buffer[..len], self.peer)?;
+        Ok(None)
+    }
+
+    /// Process a packet received from the given endpoint, and respond to it.
+    pub fn process<F: File>(&mut self, socket: &mut UdpSocket, file: &mut F,
+                            endpoint: IpEndpoint, repr: &TftpRepr, now: u64) ->
+                           Result<Option<Event>> {
+        if endpoint != self.peer {
+            net_debug!("TFTP packet from {} is not part of the transfer", endpoint);
+            send_error(socket, endpoint, TftpErrorCode::UnknownTransferId, "")?;
+            return Ok(None)
+        }
+
+        match (self.sending, *repr) {
+            (_, TftpRepr::Error { code, .. }) => {
+                net_debug!("TFTP transfer aborted by {}: {}", self.peer, code);
+                Ok(Some(Event::Refused(code)))
+            }
+            (true, TftpRepr::Ack { block_num }) if block_num == self.block => {
+                if self.options_ack.take().is_none() {
+                    self.offset += self.sent_len;
+                    if self.sent_len < self.block_size {
+                        net_debug!("TFTP sent {} octets to {}", self.offset, self.peer);
+                        return Ok(Some(Event::Completed { size: self.offset }))
+                    }
+                }
+                self.block = self.block.wrapping_add(1);
+                self.sent_at = now;
+                self.retransmits = 0;
+                self.transmit(socket, file)
+            }
+            (false, TftpRepr::Data { block_num, data })
+                    if block_num == self.block.wrapping_add(1) => {
+                if data.len() > self.block_size {
+                    return self.abort(socket, TftpErrorCode::IllegalOperation).map(Some)
+                }
+                if let Err(code) = file.write(self.offset, data) {
+                    return self.abort(socket, code).map(Some)
+                }
+                self.offset += data.len();
+                self.options_ack = None;
+                self.block = block_num;
+                self.sent_at = now;
+                self.retransmits = 0;
+                self.transmit(socket, file)?;
+                if data.len() < self.block_size {
+                    net_debug!("TFTP received {} octets from {}", self.offset, self.peer);
+                    Ok(Some(Event::Completed { size: self.offset }))
+                } else {
+                    Ok(None)
+                }
+            }
+            (false, TftpRepr::Data { block_num, .. })
+                    if block_num == self.block && self.options_ack.is_none() => {
+                // Our acknowledgement was lost.
+                self.transmit(socket, file)
+            }
+            _ => {
+                net_trace!("TFTP ignoring unexpected packet from {}", self.peer);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Retransmit the last packet if the peer did not respond to it in time,
+    /// or abandon the transfer if it was retransmitted too many times.
+    pub fn poll<F: File>(&mut self, socket: &mut UdpSocket, file: &mut F, now: u64) ->
+                        Result<Option<Event>> {
+        if now < self.poll_at() { return Ok(None) }
+        if self.retransmits == MAX_RETRANSMITS {
+            net_debug!("TFTP transfer with {} timed out", self.peer);
+            return Ok(Some(Event::TimedOut))
+        }
+        net_trace!("TFTP retransmitting to {}", self.peer);
+        self.retransmits += 1;
+        self.sent_at = now;
+        self.transmit(socket, file)
+    }
+}
@@ -1,13 +1,16 @@
 /*! Time structures.
 
 The `time` module contains structures used to represent both
-absolute and relative time.
+absolute and relative time, with a resolution of one microsecond,
+and a trait for the sources of the current time.
 
  - [Instant] is used to represent absolute time.
  - [Duration] is used to represet relative time.
+ - [Clock] is used to query the current time.
 
 [Instant]: struct.Instant.html
 [Duration]: struct.Duration.html
+[Clock]: trait.Clock.html
 */
 
 use core::{ops, fmt};
@@ -15,7 +18,7 @@ use core::{ops, fmt};
 /// A representation of an absolute time value.
 ///
 /// The `Instant` type is a wrapper around a `i64` value that
-/// represents a number of milliseconds, monotonically increasing
+/// represents a number of microseconds, monotonically increasing
 /// since an arbitrary moment in time, such as system startup.
 ///
 /// * A value of `0` is inherently arbitrary.
@@ -23,13 +26,18 @@ use core::{ops, fmt};
 ///   point.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Instant {
-    millis: i64,
+    micros: i64,
 }
 
 impl Instant {
+    /// Create a new `Instant` from a number of microseconds.
+    pub fn from_micros(micros: i64) -> Instant {
+        Instant { micros }
+    }
+
     /// Create a new `Instant` from a number of milliseconds.
     pub fn from_millis(millis: i64) -> Instant {
-        Instant { millis }
+        Instant { micros: millis * 1000 }
     }
 
     /// Create a new `Instant` from the current `SystemTime`.
@@ -41,33 +49,40 @@ impl Instant {
     /// Create a new `Instant` from a `SystemTime`.
     #[cfg(feature = "std")]
     pub fn from_system_time(time: ::std::time::SystemTime) -> Result<Instant, ::std::time::SystemTimeError> {
-        let n = ::std::time::UNIX_EPOCH.duration_since(time)?;
-        Ok(Self::from_millis(n.as_secs() as i64 * 1000 + (n.subsec_nanos() / 1_000_000) as i64))
+        let n = time.duration_since(::std::time::UNIX_EPOCH)?;
+        Ok(Self::from_micros(n.as_secs() as i64 * 1_000_000 + (n.subsec_nanos() / 1000) as i64))
     }
 
     /// The fractional number of milliseconds that have passed
     /// since the beginning of time.
     pub fn millis(&self) -> i64 {
-        self.millis % 1000
+        self.micros / 1000 % 1000
     }
 
     /// The number of whole seconds that have passed since the
     /// beginning of time.
     pub fn secs(&self) -> i64 {
-        self.millis / 1000
+        self.micros / 1_000_000
     }
 
     /// The total number of milliseconds that have passed since
     /// the biginning of time.
     pub fn total_millis(&self) -> i64 {
-        self.millis
+        self.micros / 1000
+    }
+
+    /// The total number of microseconds that have passed since
+    /// the beginning of time.
+    pub fn total_micros(&self) -> i64 {
+        self.micros
     }
 }
 
 #[cfg(feature = "std")]
 impl Into<::std::time::SystemTime> for Instant {
     fn into(self) -> ::std::time::SystemTime {
-        ::std::time::UNIX_EPOCH + ::std::time::Duration::from_millis(self.millis as u64)
+        let since_epoch: ::std::time::Duration = Duration::from_micros(self.micros as u64).into();
+        ::std::time::UNIX_EPOCH + since_epoch
     }
 }
 
@@ -81,7 +96,7 @@ impl ops::Add<Duration> for Instant {
     type Output = Instant;
 
     fn add(self, rhs: Duration) -> Instant {
-        Instant::from_millis(self.millis + rhs.total_millis() as i64)
+        Instant::from_micros(self.micros + rhs.total_micros() as i64)
     }
 }
 
@@ -89,35 +104,59 @@ impl ops::Sub<Duration> for Instant {
     type Output = Instant;
 
     fn sub(self, rhs: Duration) -> Instant {
-        Instant::from_millis(self.millis - rhs.total_millis() as i64)
+        Instant::from_micros(self.micros - rhs.total_micros() as i64)
+    }
+}
+
+/// The time elapsed from `rhs` to `self`, or zero if `rhs` is later, e.g. because
+/// it was given by a clock of a finer resolution.
+impl ops::Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Duration {
+        if self.micros >= rhs.micros {
+            Duration::from_micros((self.micros - rhs.micros) as u64)
+        } else {
+            Duration::from_micros(0)
+        }
     }
 }
 
 /// A relative amount of time.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Duration {
-    millis: u64,
+    micros: u64,
 }
 
 impl Duration {
+    /// Create a new `Duration` from a number of microseconds.
+    pub fn from_micros(micros: u64) -> Duration {
+        Duration { micros }
+    }
+
     /// Create a new `Duration` from a number of milliseconds.
     pub fn from_millis(millis: u64) -> Duration {
-        Duration { millis }
+        Duration { micros: millis * 1000 }
     }
 
     /// The fractional number of milliseconds in this `Duration`.
     pub fn millis(&self) -> u64 {
-        self.millis % 1000
+        self.micros / 1000 % 1000
     }
 
     /// The number of whole seconds in this `Duration`.
     pub fn secs(&self) -> u64 {
-        self.millis / 1000
+        self.micros / 1_000_000
     }
 
     /// The total number of milliseconds in this `Duration`.
     pub fn total_millis(&self) -> u64 {
-        self.millis
+        self.micros / 1000
+    }
+
+    /// The total number of microseconds in this `Duration`.
+    pub fn total_micros(&self) -> u64 {
+        self.micros
     }
 }
 
@@ -131,13 +170,13 @@ impl ops::Add<Duration> for Duration {
     type Output = Duration;
 
     fn add(self, rhs: Duration) -> Duration {
-        Duration::from_millis(self.millis + rhs.total_millis())
+        Duration::from_micros(self.micros + rhs.total_micros())
     }
 }
 
 impl ops::AddAssign<Duration> for Duration {
     fn add_assign(&mut self, rhs: Duration) {
-        self.millis += rhs.total_millis();
+        self.micros += rhs.total_micros();
     }
 }
 
@@ -145,15 +184,15 @@ impl ops::Sub<Duration> for Duration {
     type Output = Duration;
 
     fn sub(self, rhs: Duration) -> Duration {
-        Duration::from_millis(
-            self.millis.checked_sub(rhs.total_millis()).expect("overflow when subtracting durations"))
+        Duration::from_micros(
+            self.micros.checked_sub(rhs.total_micros()).expect("overflow when subtracting durations"))
     }
 }
 
 impl ops::SubAssign<Duration> for Duration {
     fn sub_assign(&mut self, rhs: Duration) {
-        self.millis = self.millis.checked_sub(
-            rhs.total_millis()).expect("overflow when subtracting durations");
+        self.micros = self.micros.checked_sub(
+            rhs.total_micros()).expect("overflow when subtracting durations");
     }
 }
 
@@ -161,13 +200,13 @@ impl ops::Mul<u32> for Duration {
     type Output = Duration;
 
     fn mul(self, rhs: u32) -> Duration {
-        Duration::from_millis(self.millis * rhs as u64)
+        Duration::from_micros(self.micros * rhs as u64)
     }
 }
 
 impl ops::MulAssign<u32> for Duration {
     fn mul_assign(&mut self, rhs: u32) {
-        self.millis *= rhs as u64;
+        self.micros *= rhs as u64;
     }
 }
 
@@ -175,21 +214,21 @@ impl ops::Div<u32> for Duration {
     type Output = Duration;
 
     fn div(self, rhs: u32) -> Duration {
-        Duration::from_millis(self.millis / rhs as u64)
+        Duration::from_micros(self.micros / rhs as u64)
     }
 }
 
 impl ops::DivAssign<u32> for Duration {
     fn div_assign(&mut self, rhs: u32) {
-        self.millis /= rhs as u64;
+        self.micros /= rhs as u64;
     }
 }
 
 #[cfg(feature = "std")]
 impl From<::std::time::Duration> for Duration {
     fn from(other: ::std::time::Duration) -> Duration {
-        Duration::from_millis(
-            other.as_secs() * 1000 + (other.subsec_nanos() / 1_000_000) as u64
+        Duration::from_micros(
+            other.as_secs() * 1_000_000 + (other.subsec_nanos() / 1000) as u64
         )
     }
 }
@@ -197,12 +236,53 @@ impl From<::std::time::Duration> for Duration {
 #[cfg(feature = "std")]
 impl Into<::std::time::Duration> for Duration {
     fn into(self) -> ::std::time::Duration {
-        ::std::time::Duration::from_millis(
-            self.total_millis()
+        ::std::time::Duration::new(
+            self.micros / 1_000_000, (self.micros % 1_000_000) as u32 * 1000
         )
     }
 }
 
+/// A source of the current time.
+///
+/// The instants a clock returns never decrease. On hosted targets, [StdClock] can be
+/// used; on bare-metal targets, this trait can be implemented on top of a hardware
+/// timer, or a closure returning the current time can be used directly.
+///
+/// [StdClock]: struct.StdClock.html
+pub trait Clock {
+    /// Return the current time.
+    fn now(&self) -> Instant;
+}
+
+impl<F: Fn() -> Instant> Clock for F {
+    fn now(&self) -> Instant {
+        self()
+    }
+}
+
+/// A clock measuring the time elapsed since its creation, using the monotonic
+/// clock of the operating system.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct StdClock {
+    epoch: ::std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl StdClock {
+    /// Create a clock starting at zero.
+    pub fn new() -> StdClock {
+        StdClock { epoch: ::std::time::Instant::now() }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for StdClock {
+    fn now(&self) -> Instant {
+        Instant::from_micros(Duration::from(self.epoch.elapsed()).total_micros() as i64)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -223,6 +303,19 @@ mod test {
         assert_eq!(instant.total_millis(), 5674);
     }
 
+    #[test]
+    fn test_instant_micros() {
+        let instant = Instant::from_micros(5_674_321);
+        assert_eq!(instant.secs(), 5);
+        assert_eq!(instant.millis(), 674);
+        assert_eq!(instant.total_millis(), 5674);
+        assert_eq!(instant.total_micros(), 5_674_321);
+        assert_eq!(Instant::from_micros(1_250) - Instant::from_millis(1),
+                   Duration::from_micros(250));
+        assert_eq!(Instant::from_millis(1) - Instant::from_micros(1_250),
+                   Duration::from_micros(0));
+    }
+
     #[test]
     fn test_instant_display() {
         assert_eq!(format!("{}", Instant::from_millis(5674)), "5.674s");
@@ -249,7 +342,7 @@ mod test {
         // std::ops::Mul
         assert_eq!(Duration::from_millis(13) * 22, Duration::from_millis(286));
         // std::ops::Div
-        assert_eq!(Duration::from_millis(53) / 4, Duration::from_millis(13));
+        assert_eq!(Duration::from_millis(53) / 4, Duration::from_micros(13_250));
     }
 
     #[test]
@@ -262,7 +355,7 @@ mod test {
         duration *= 4;
         assert_eq!(duration, Duration::from_millis(20936));
         duration /= 5;
-        assert_eq!(duration, Duration::from_millis(4187));
+        assert_eq!(duration, Duration::from_micros(4_187_200));
     }
 
     #[test]
@@ -283,6 +376,7 @@ mod test {
         assert_eq!(instant.secs(), 4);
         assert_eq!(instant.millis(), 934);
         assert_eq!(instant.total_millis(), 4934);
+        assert_eq!(instant.total_micros(), 4_934_000);
     }
 
     #[test]
@@ -296,4 +390,18 @@ mod test {
         std_duration = duration.into();
         assert_eq!(std_duration, ::std::time::Duration::from_millis(4934));
     }
+
+    #[test]
+    fn test_clock() {
+        let clock = || Instant::from_micros(1_500);
+        assert_eq!(clock.now(), Instant::from_micros(1_500));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_std_clock() {
+        let clock = StdClock::new();
+        let (first, second) = (clock.now(), clock.now());
+        assert!(first >= Instant::from_micros(0) && second >= first);
+    }
 }
@@ -2,6 +2,8 @@ use core::fmt;
 use byteorder::{ByteOrder, NetworkEndian};
 
 use {Error, Result};
+#[cfg(feature = "arbitrary")]
+use arbitrary::{self, Arbitrary, Unstructured};
 
 pub use super::EthernetProtocol as Protocol;
 
@@ -313,6 +315,19 @@ impl Repr {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Repr {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Repr> {
+        Ok(Repr::EthernetIpv4 {
+            operation:            u.arbitrary()?,
+            source_hardware_addr: u.arbitrary()?,
+            source_protocol_addr: u.arbitrary()?,
+            target_hardware_addr: u.arbitrary()?,
+            target_protocol_addr: u.arbitrary()?
+        })
+    }
+}
+
 impl<T: AsRef<[u8]>> fmt::Display for Packet<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match Repr::parse(self) {
@@ -435,4 +450,21 @@ mod test {
         packet_repr().emit(&mut packet);
         assert_eq!(&packet.into_inner()[..], &PACKET_BYTES[..]);
     }
+
+    #[test]
+    #[cfg(feature = "arbitrary")]
+    fn test_arbitrary_round_trip() {
+        use arbitrary::Unstructured;
+        use wire::ip::test::arbitrary_inputs;
+
+        for input in arbitrary_inputs() {
+            let repr: Repr = match Unstructured::new(&input).arbitrary() {
+                Ok(repr) => repr,
+                Err(_) => continue
+            };
+            let mut bytes = vec![0xa5; repr.buffer_len()];
+            repr.emit(&mut Packet::new(&mut bytes));
+            assert_eq!(Repr::parse(&Packet::new(&bytes)), Ok(repr));
+        }
+    }
 }
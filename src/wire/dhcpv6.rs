@@ -0,0 +1,546 @@
+// See https://tools.ietf.org/html/rfc8415 for the DHCPv6 specification,
+// and https://tools.ietf.org/html/rfc3646 for the DNS servers option.
+
+use byteorder::{ByteOrder, NetworkEndian};
+
+use {Error, Result};
+use super::Ipv6Address;
+
+/// The port DHCPv6 servers and relay agents listen on.
+pub const SERVER_PORT: u16 = 547;
+/// The port DHCPv6 clients listen on.
+pub const CLIENT_PORT: u16 = 546;
+
+/// The link-scoped multicast address of all DHCPv6 relay agents and servers.
+pub const ALL_RELAY_AGENTS_AND_SERVERS: Ipv6Address =
+    Ipv6Address([0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02]);
+
+/// The maximum number of DNS servers in a high-level representation.
+pub const MAX_DNS_SERVER_COUNT: usize = 3;
+
+enum_with_unknown! {
+    /// The possible message types of a DHCPv6 packet.
+    pub enum MessageType(u8) {
+        Solicit   = 1,
+        Advertise = 2,
+        Request   = 3,
+        Confirm   = 4,
+        Renew     = 5,
+        Rebind    = 6,
+        Reply     = 7,
+        Release   = 8,
+        Decline   = 9
+    }
+}
+
+enum_with_unknown! {
+    /// The possible values of a DHCPv6 status code option.
+    pub enum StatusCode(u16) {
+        Success      = 0,
+        UnspecFail   = 1,
+        NoAddrsAvail = 2,
+        NoBinding    = 3,
+        NotOnLink    = 4,
+        UseMulticast = 5
+    }
+}
+
+pub(crate) mod field {
+    #![allow(non_snake_case)]
+
+    use wire::field::*;
+
+    pub const MSG_TYPE: usize = 0;
+    pub const TRANSACTION_ID: Field = 1..4;
+    pub const OPTIONS: Rest = 4..;
+
+    pub const OPT_CODE: Field = 0..2;
+    pub const OPT_LEN: Field = 2..4;
+    pub const OPT_DATA: Rest = 4..;
+
+    pub const IA_NA_IAID: Field = 0..4;
+    pub const IA_NA_T1: Field = 4..8;
+    pub const IA_NA_T2: Field = 8..12;
+    pub const IA_NA_OPTIONS: Rest = 12..;
+
+    pub const IAADDR_ADDRESS: Field = 0..16;
+    pub const IAADDR_PREFERRED: Field = 16..20;
+    pub const IAADDR_VALID: Field = 20..24;
+
+    pub const OPT_CLIENTID: u16 = 1;
+    pub const OPT_SERVERID: u16 = 2;
+    pub const OPT_IA_NA: u16 = 3;
+    pub const OPT_IAADDR: u16 = 5;
+    pub const OPT_ORO: u16 = 6;
+    pub const OPT_PREFERENCE: u16 = 7;
+    pub const OPT_ELAPSED_TIME: u16 = 8;
+    pub const OPT_STATUS_CODE: u16 = 13;
+    pub const OPT_DNS_SERVERS: u16 = 23;
+}
+
+/// Parse a single option, returning the rest of the buffer, the option code,
+/// and the option data.
+fn parse_option(buffer: &[u8]) -> Result<(&[u8], u16, &[u8])> {
+    if buffer.len() < field::OPT_DATA.start { return Err(Error::Truncated) }
+    let code = NetworkEndian::read_u16(&buffer[field::OPT_CODE]);
+    let length = NetworkEndian::read_u16(&buffer[field::OPT_LEN]) as usize;
+    let end = field::OPT_DATA.start + length;
+    let data = buffer.get(field::OPT_DATA.start..end).ok_or(Error::Truncated)?;
+    Ok((&buffer[end..], code, data))
+}
+
+/// Emit the header of a single option, returning the buffer for the option data,
+/// and the rest of the buffer.
+fn emit_option(buffer: &mut [u8], code: u16, length: usize) -> (&mut [u8], &mut [u8]) {
+    NetworkEndian::write_u16(&mut buffer[field::OPT_CODE], code);
+    NetworkEndian::write_u16(&mut buffer[field::OPT_LEN], length as u16);
+    buffer[field::OPT_DATA].split_at_mut(length)
+}
+
+/// A read/write wrapper around a DHCPv6 packet buffer.
+#[derive(Debug, PartialEq)]
+pub struct Packet<T: AsRef<[u8]>> {
+    buffer: T
+}
+
+impl<T: AsRef<[u8]>> Packet<T> {
+    /// Imbue a raw octet buffer with DHCPv6 packet structure.
+    pub fn new(buffer: T) -> Packet<T> {
+        Packet { buffer }
+    }
+
+    /// Shorthand for a combination of [new] and [check_len].
+    ///
+    /// [new]: #method.new
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<Packet<T>> {
+        let packet = Self::new(buffer);
+        packet.check_len()?;
+        Ok(packet)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error::Truncated)` if the buffer is too short.
+    pub fn check_len(&self) -> Result<()> {
+        let len = self.buffer.as_ref().len();
+        if len < field::OPTIONS.start {
+            Err(Error::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Consume the packet, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Return the message type field.
+    #[inline]
+    pub fn message_type(&self) -> MessageType {
+        let data = self.buffer.as_ref();
+        MessageType::from(data[field::MSG_TYPE])
+    }
+
+    /// Return the transaction ID field. Only the lower 24 bits are used.
+    #[inline]
+    pub fn transaction_id(&self) -> u32 {
+        let data = self.buffer.as_ref();
+        let field = &data[field::TRANSACTION_ID];
+        (field[0] as u32) << 16 | (field[1] as u32) << 8 | field[2] as u32
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> Packet<&'a T> {
+    /// Return a pointer to the options.
+    #[inline]
+    pub fn options(&self) -> &'a [u8] {
+        let data = self.buffer.as_ref();
+        &data[field::OPTIONS]
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
+    /// Set the message type field.
+    #[inline]
+    pub fn set_message_type(&mut self, value: MessageType) {
+        let data = self.buffer.as_mut();
+        data[field::MSG_TYPE] = value.into()
+    }
+
+    /// Set the transaction ID field. Only the lower 24 bits are used.
+    #[inline]
+    pub fn set_transaction_id(&mut self, value: u32) {
+        let data = self.buffer.as_mut();
+        let field = &mut data[field::TRANSACTION_ID];
+        field[0] = (value >> 16) as u8;
+        field[1] = (value >> 8) as u8;
+        field[2] = value as u8;
+    }
+
+    /// Return a mutable pointer to the options.
+    #[inline]
+    pub fn options_mut(&mut self) -> &mut [u8] {
+        let data = self.buffer.as_mut();
+        &mut data[field::OPTIONS]
+    }
+}
+
+/// An address assigned to an identity association.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct IaAddress {
+    /// The assigned address.
+    pub address: Ipv6Address,
+    /// The preferred lifetime of the address, in seconds.
+    pub preferred_lifetime: u32,
+    /// The valid lifetime of the address, in seconds.
+    pub valid_lifetime: u32,
+}
+
+/// An identity association for non-temporary addresses (IA_NA), carrying at most
+/// one address.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct IaNa {
+    /// The identity association identifier, chosen by the client.
+    pub iaid: u32,
+    /// The time after which the client should renew the addresses (T1), in seconds.
+    pub t1: u32,
+    /// The time after which the client should rebind the addresses (T2), in seconds.
+    pub t2: u32,
+    /// The address assigned to the identity association.
+    pub address: Option<IaAddress>,
+    /// The status of the identity association, if the server reported one.
+    pub status_code: Option<StatusCode>,
+}
+
+impl IaNa {
+    fn buffer_len(&self) -> usize {
+        let mut len = field::OPT_DATA.start + field::IA_NA_OPTIONS.start;
+        if self.address.is_some() { len += field::OPT_DATA.start + field::IAADDR_VALID.end }
+        if self.status_code.is_some() { len += field::OPT_DATA.start + 2 }
+        len
+    }
+
+    fn parse(data: &[u8]) -> Result<IaNa> {
+        if data.len() < field::IA_NA_OPTIONS.start { return Err(Error::Truncated) }
+
+        let mut ia_na = IaNa {
+            iaid: NetworkEndian::read_u32(&data[field::IA_NA_IAID]),
+            t1:   NetworkEndian::read_u32(&data[field::IA_NA_T1]),
+            t2:   NetworkEndian::read_u32(&data[field::IA_NA_T2]),
+            address: None,
+            status_code: None,
+        };
+
+        let mut options = &data[field::IA_NA_OPTIONS];
+        while options.len() > 0 {
+            let (next_options, code, data) = parse_option(options)?;
+            match code {
+                field::OPT_IAADDR if ia_na.address.is_none() => {
+                    if data.len() < field::IAADDR_VALID.end { return Err(Error::Truncated) }
+                    ia_na.address = Some(IaAddress {
+                        address: Ipv6Address::from_bytes(&data[field::IAADDR_ADDRESS]),
+                        preferred_lifetime:
+                            NetworkEndian::read_u32(&data[field::IAADDR_PREFERRED]),
+                        valid_lifetime:
+                            NetworkEndian::read_u32(&data[field::IAADDR_VALID]),
+                    });
+                }
+                field::OPT_STATUS_CODE => {
+                    if data.len() < 2 { return Err(Error::Truncated) }
+                    ia_na.status_code = Some(StatusCode::from(NetworkEndian::read_u16(data)));
+                }
+                _ => ()
+            }
+            options = next_options;
+        }
+
+        Ok(ia_na)
+    }
+
+    fn emit(&self, buffer: &mut [u8]) {
+        NetworkEndian::write_u32(&mut buffer[field::IA_NA_IAID], self.iaid);
+        NetworkEndian::write_u32(&mut buffer[field::IA_NA_T1], self.t1);
+        NetworkEndian::write_u32(&mut buffer[field::IA_NA_T2], self.t2);
+
+        let mut options = &mut buffer[field::IA_NA_OPTIONS];
+        if let Some(address) = self.address {
+            let (data, rest) = emit_option(options, field::OPT_IAADDR, field::IAADDR_VALID.end);
+            data[field::IAADDR_ADDRESS].copy_from_slice(address.address.as_bytes());
+            NetworkEndian::write_u32(&mut data[field::IAADDR_PREFERRED],
+                                     address.preferred_lifetime);
+            NetworkEndian::write_u32(&mut data[field::IAADDR_VALID], address.valid_lifetime);
+            options = rest;
+        }
+        if let Some(status_code) = self.status_code {
+            let (data, _) = emit_option(options, field::OPT_STATUS_CODE, 2);
+            NetworkEndian::write_u16(data, status_code.into());
+        }
+    }
+}
+
+/// A high-level representation of a DHCPv6 message.
+///
+/// Only the options needed to acquire a single non-temporary address and the DNS
+/// servers are represented; any other options are ignored when parsing.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Repr<'a> {
+    /// The message type.
+    pub message_type: MessageType,
+    /// The transaction ID, chosen by the client to match replies to requests.
+    /// Only the lower 24 bits are used.
+    pub transaction_id: u32,
+    /// The DUID of the client.
+    pub client_id: Option<&'a [u8]>,
+    /// The DUID of the server.
+    pub server_id: Option<&'a [u8]>,
+    /// The identity association for non-temporary addresses.
+    pub ia_na: Option<IaNa>,
+    /// The option request option, a list of 16-bit option codes in network byte order.
+    pub option_request: Option<&'a [u8]>,
+    /// The time since the client began the current exchange, in hundredths of a second.
+    pub elapsed_time: Option<u16>,
+    /// The preference of the server, sent in Advertise messages.
+    pub preference: Option<u8>,
+    /// The status of the message, if the server reported one.
+    pub status_code: Option<StatusCode>,
+    /// DNS servers
+    pub dns_servers: Option<[Option<Ipv6Address>; MAX_DNS_SERVER_COUNT]>,
+}
+
+impl<'a> Repr<'a> {
+    /// Return the length of a packet that will be emitted from this high-level representation.
+    pub fn buffer_len(&self) -> usize {
+        let header_len = field::OPT_DATA.start;
+        let mut len = field::OPTIONS.start;
+        if let Some(id) = self.client_id { len += header_len + id.len() }
+        if let Some(id) = self.server_id { len += header_len + id.len() }
+        if let Some(ia_na) = self.ia_na { len += ia_na.buffer_len() }
+        if let Some(list) = self.option_request { len += header_len + list.len() }
+        if self.elapsed_time.is_some() { len += header_len + 2 }
+        if self.preference.is_some() { len += header_len + 1 }
+        if self.status_code.is_some() { len += header_len + 2 }
+        if let Some(dns_servers) = self.dns_servers {
+            len += header_len + dns_servers.iter().filter(|addr| addr.is_some()).count() * 16;
+        }
+        len
+    }
+
+    /// Parse a DHCPv6 packet and return a high-level representation.
+    pub fn parse<T>(packet: &Packet<&'a T>) -> Result<Self>
+            where T: AsRef<[u8]> + ?Sized {
+        let mut repr = Repr {
+            message_type: packet.message_type(),
+            transaction_id: packet.transaction_id(),
+            client_id: None,
+            server_id: None,
+            ia_na: None,
+            option_request: None,
+            elapsed_time: None,
+            preference: None,
+            status_code: None,
+            dns_servers: None,
+        };
+
+        let mut options = packet.options();
+        while options.len() > 0 {
+            let (next_options, code, data) = parse_option(options)?;
+            match code {
+                field::OPT_CLIENTID => repr.client_id = Some(data),
+                field::OPT_SERVERID => repr.server_id = Some(data),
+                field::OPT_IA_NA if repr.ia_na.is_none() =>
+                    repr.ia_na = Some(IaNa::parse(data)?),
+                field::OPT_ORO => repr.option_request = Some(data),
+                field::OPT_ELAPSED_TIME => {
+                    if data.len() != 2 { return Err(Error::Malformed) }
+                    repr.elapsed_time = Some(NetworkEndian::read_u16(data))
+                }
+                field::OPT_PREFERENCE => {
+                    if data.len() != 1 { return Err(Error::Malformed) }
+                    repr.preference = Some(data[0])
+                }
+                field::OPT_STATUS_CODE => {
+                    if data.len() < 2 { return Err(Error::Truncated) }
+                    repr.status_code = Some(StatusCode::from(NetworkEndian::read_u16(data)))
+                }
+                field::OPT_DNS_SERVERS => {
+                    if data.len() % 16 != 0 { return Err(Error::Malformed) }
+                    let mut dns_servers = [None; MAX_DNS_SERVER_COUNT];
+                    for (server, chunk) in dns_servers.iter_mut().zip(data.chunks(16)) {
+                        *server = Some(Ipv6Address::from_bytes(chunk));
+                    }
+                    repr.dns_servers = Some(dns_servers)
+                }
+                _ => ()
+            }
+            options = next_options;
+        }
+
+        Ok(repr)
+    }
+
+    /// Emit a high-level representation into a DHCPv6 packet.
+    pub fn emit<T>(&self, packet: &mut Packet<&mut T>)
+            where T: AsRef<[u8]> + AsMut<[u8]> + ?Sized {
+        packet.set_message_type(self.message_type);
+        packet.set_transaction_id(self.transaction_id);
+
+        let mut options = packet.options_mut();
+        if let Some(id) = self.client_id {
+            let (data, rest) = emit_option(options, field::OPT_CLIENTID, id.len());
+            data.copy_from_slice(id);
+            options = rest;
+        }
+        if let Some(id) = self.server_id {
+            let (data, rest) = emit_option(options, field::OPT_SERVERID, id.len());
+            data.copy_from_slice(id);
+            options = rest;
+        }
+        if let Some(ia_na) = self.ia_na {
+            let length = ia_na.buffer_len() - field::OPT_DATA.start;
+            let (data, rest) = emit_option(options, field::OPT_IA_NA, length);
+            ia_na.emit(data);
+            options = rest;
+        }
+        if let Some(list) = self.option_request {
+            let (data, rest) = emit_option(options, field::OPT_ORO, list.len());
+            data.copy_from_slice(list);
+            options = rest;
+        }
+        if let Some(elapsed_time) = self.elapsed_time {
+            let (data, rest) = emit_option(options, field::OPT_ELAPSED_TIME, 2);
+            NetworkEndian::write_u16(data, elapsed_time);
+            options = rest;
+        }
+        if let Some(preference) = self.preference {
+            let (data, rest) = emit_option(options, field::OPT_PREFERENCE, 1);
+            data[0] = preference;
+            options = rest;
+        }
+        if let Some(status_code) = self.status_code {
+            let (data, rest) = emit_option(options, field::OPT_STATUS_CODE, 2);
+            NetworkEndian::write_u16(data, status_code.into());
+            options = rest;
+        }
+        if let Some(dns_servers) = self.dns_servers {
+            let count = dns_servers.iter().filter(|addr| addr.is_some()).count();
+            let (data, _) = emit_option(options, field::OPT_DNS_SERVERS, count * 16);
+            for (chunk, server) in data.chunks_mut(16).zip(dns_servers.iter().filter_map(|s| *s)) {
+                chunk.copy_from_slice(server.as_bytes());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static CLIENT_DUID: [u8; 10] = [0x00, 0x03, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01];
+    static SERVER_DUID: [u8; 10] = [0x00, 0x03, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x02];
+    static ORO: [u8; 2] = [0x00, 0x17];
+
+    static SOLICIT_BYTES: [u8; 46] = [
+        0x01, 0x12, 0x34, 0x56,
+        // client identifier
+        0x00, 0x01, 0x00, 0x0a, 0x00, 0x03, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01,
+        // IA_NA
+        0x00, 0x03, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00,
+        // option request
+        0x00, 0x06, 0x00, 0x02, 0x00, 0x17,
+        // elapsed time
+        0x00, 0x08, 0x00, 0x02, 0x00, 0x00,
+    ];
+
+    const ADDRESS: Ipv6Address =
+        Ipv6Address([0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00,
+                     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x23]);
+    const DNS_SERVER: Ipv6Address =
+        Ipv6Address([0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00,
+                     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01]);
+
+    fn solicit_repr() -> Repr<'static> {
+        Repr {
+            message_type: MessageType::Solicit,
+            transaction_id: 0x123456,
+            client_id: Some(&CLIENT_DUID),
+            server_id: None,
+            ia_na: Some(IaNa { iaid: 1, t1: 0, t2: 0, address: None, status_code: None }),
+            option_request: Some(&ORO),
+            elapsed_time: Some(0),
+            preference: None,
+            status_code: None,
+            dns_servers: None,
+        }
+    }
+
+    fn reply_repr() -> Repr<'static> {
+        Repr {
+            message_type: MessageType::Reply,
+            transaction_id: 0x123456,
+            client_id: Some(&CLIENT_DUID),
+            server_id: Some(&SERVER_DUID),
+            ia_na: Some(IaNa {
+                iaid: 1, t1: 1800, t2: 2880,
+                address: Some(IaAddress {
+                    address: ADDRESS,
+                    preferred_lifetime: 3600,
+                    valid_lifetime: 7200,
+                }),
+                status_code: Some(StatusCode::Success),
+            }),
+            option_request: None,
+            elapsed_time: None,
+            preference: Some(255),
+            status_code: None,
+            dns_servers: Some([Some(DNS_SERVER), None, None]),
+        }
+    }
+
+    #[test]
+    fn test_deconstruct() {
+        let packet = Packet::new(&SOLICIT_BYTES[..]);
+        assert_eq!(packet.message_type(), MessageType::Solicit);
+        assert_eq!(packet.transaction_id(), 0x123456);
+        assert_eq!(packet.options(), &SOLICIT_BYTES[4..]);
+    }
+
+    #[test]
+    fn test_parse_solicit() {
+        let packet = Packet::new_checked(&SOLICIT_BYTES[..]).unwrap();
+        assert_eq!(Repr::parse(&packet), Ok(solicit_repr()));
+    }
+
+    #[test]
+    fn test_emit_solicit() {
+        let repr = solicit_repr();
+        assert_eq!(repr.buffer_len(), SOLICIT_BYTES.len());
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new(&mut bytes[..]));
+        assert_eq!(&bytes[..], &SOLICIT_BYTES[..]);
+    }
+
+    #[test]
+    fn test_reply_round_trip() {
+        let repr = reply_repr();
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new(&mut bytes[..]));
+        assert_eq!(Repr::parse(&Packet::new(&bytes[..])), Ok(repr));
+    }
+
+    #[test]
+    fn test_parse_truncated() {
+        assert_eq!(Packet::new_checked(&SOLICIT_BYTES[..3]), Err(Error::Truncated));
+        let packet = Packet::new(&SOLICIT_BYTES[..SOLICIT_BYTES.len() - 1]);
+        assert_eq!(Repr::parse(&packet), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn test_parse_ignores_unknown_options() {
+        let mut bytes = SOLICIT_BYTES.to_vec();
+        bytes.extend_from_slice(&[0x00, 0x27, 0x00, 0x01, 0x00]);
+        assert_eq!(Repr::parse(&Packet::new(&bytes[..])), Ok(solicit_repr()));
+    }
+}
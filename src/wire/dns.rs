@@ -0,0 +1,811 @@
+// See https://tools.ietf.org/html/rfc1035 for the DNS specification,
+// and https://tools.ietf.org/html/rfc3596 for AAAA records.
+
+use core::fmt;
+use byteorder::{ByteOrder, NetworkEndian};
+
+use {Error, Result};
+#[cfg(feature = "proto-ipv4")]
+use super::Ipv4Address;
+#[cfg(feature = "proto-ipv6")]
+use super::Ipv6Address;
+
+/// The port DNS servers listen on.
+pub const SERVER_PORT: u16 = 53;
+
+/// The port multicast DNS messages are sent from and to; see RFC 6762.
+pub const MDNS_PORT: u16 = 5353;
+
+/// The IPv4 multicast group of multicast DNS.
+#[cfg(feature = "proto-ipv4")]
+pub const MDNS_IPV4_GROUP: Ipv4Address = Ipv4Address([224, 0, 0, 251]);
+
+/// The IPv6 multicast group of multicast DNS.
+#[cfg(feature = "proto-ipv6")]
+pub const MDNS_IPV6_GROUP: Ipv6Address =
+    Ipv6Address([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xfb]);
+
+/// The port LLMNR messages are sent to; see RFC 4795.
+pub const LLMNR_PORT: u16 = 5355;
+
+/// The IPv4 multicast group of LLMNR.
+#[cfg(feature = "proto-ipv4")]
+pub const LLMNR_IPV4_GROUP: Ipv4Address = Ipv4Address([224, 0, 0, 252]);
+
+/// The IPv6 multicast group of LLMNR.
+#[cfg(feature = "proto-ipv6")]
+pub const LLMNR_IPV6_GROUP: Ipv6Address =
+    Ipv6Address([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0, 0x03]);
+
+/// The maximum length of an encoded domain name.
+pub const MAX_NAME_LEN: usize = 255;
+
+/// The maximum number of compression pointers followed in a single name. Any more than
+/// that, and the name is considered to be looping.
+const MAX_POINTER_HOPS: usize = 32;
+
+enum_with_unknown! {
+    /// The kind of a query.
+    pub enum Opcode(u8) {
+        Query  = 0,
+        Status = 2
+    }
+}
+
+enum_with_unknown! {
+    /// The response code of a message.
+    pub enum Rcode(u8) {
+        NoError  = 0,
+        FormErr  = 1,
+        ServFail = 2,
+        NXDomain = 3,
+        NotImp   = 4,
+        Refused  = 5
+    }
+}
+
+enum_with_unknown! {
+    /// The type of a resource record, or of a query.
+    pub enum Type(u16) {
+        A     = 1,
+        Ns    = 2,
+        Cname = 5,
+        Soa   = 6,
+        Ptr   = 12,
+        Txt   = 16,
+        Aaaa  = 28,
+        Srv   = 33
+    }
+}
+
+/// The Internet class of resource records, the only one in use.
+pub const CLASS_IN: u16 = 1;
+
+pub(crate) mod field {
+    #![allow(non_snake_case)]
+
+    use wire::field::*;
+
+    pub const ID:      Field = 0..2;
+    pub const FLAGS:   Field = 2..4;
+    pub const QDCOUNT: Field = 4..6;
+    pub const ANCOUNT: Field = 6..8;
+    pub const NSCOUNT: Field = 8..10;
+    pub const ARCOUNT: Field = 10..12;
+    pub const PAYLOAD: Rest  = 12..;
+
+    pub const FLG_QR:     u16 = 0x8000;
+    pub const FLG_OPCODE: u16 = 0x7800;
+    pub const FLG_AA:     u16 = 0x0400;
+    pub const FLG_TC:     u16 = 0x0200;
+    pub const FLG_RD:     u16 = 0x0100;
+    pub const FLG_RA:     u16 = 0x0080;
+    pub const FLG_RCODE:  u16 = 0x000f;
+
+    pub const LABEL_POINTER: u8 = 0xc0;
+}
+
+/// A read/write wrapper around a DNS message buffer.
+#[derive(Debug, PartialEq)]
+pub struct Packet<T: AsRef<[u8]>> {
+    buffer: T
+}
+
+impl<T: AsRef<[u8]>> Packet<T> {
+    /// Imbue a raw octet buffer with DNS message structure.
+    pub fn new(buffer: T) -> Packet<T> {
+        Packet { buffer }
+    }
+
+    /// Shorthand for a combination of [new] and [check_len].
+    ///
+    /// [new]: #method.new
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<Packet<T>> {
+        let packet = Self::new(buffer);
+        packet.check_len()?;
+        Ok(packet)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error::Truncated)` if the buffer is too short.
+    pub fn check_len(&self) -> Result<()> {
+        let len = self.buffer.as_ref().len();
+        if len < field::PAYLOAD.start {
+            Err(Error::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Consume the packet, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Return the transaction ID field.
+    #[inline]
+    pub fn transaction_id(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::ID])
+    }
+
+    #[inline]
+    fn flags(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::FLAGS])
+    }
+
+    /// Return the response (QR) flag.
+    #[inline]
+    pub fn is_response(&self) -> bool {
+        self.flags() & field::FLG_QR != 0
+    }
+
+    /// Return the authoritative answer (AA) flag.
+    #[inline]
+    pub fn authoritative(&self) -> bool {
+        self.flags() & field::FLG_AA != 0
+    }
+
+    /// Return the truncation (TC) flag.
+    #[inline]
+    pub fn truncated(&self) -> bool {
+        self.flags() & field::FLG_TC != 0
+    }
+
+    /// Return the recursion desired (RD) flag.
+    #[inline]
+    pub fn recursion_desired(&self) -> bool {
+        self.flags() & field::FLG_RD != 0
+    }
+
+    /// Return the recursion available (RA) flag.
+    #[inline]
+    pub fn recursion_available(&self) -> bool {
+        self.flags() & field::FLG_RA != 0
+    }
+
+    /// Return the opcode field.
+    #[inline]
+    pub fn opcode(&self) -> Opcode {
+        Opcode::from(((self.flags() & field::FLG_OPCODE) >> 11) as u8)
+    }
+
+    /// Return the response code field.
+    #[inline]
+    pub fn rcode(&self) -> Rcode {
+        Rcode::from((self.flags() & field::FLG_RCODE) as u8)
+    }
+
+    /// Return the question count field.
+    #[inline]
+    pub fn question_count(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::QDCOUNT])
+    }
+
+    /// Return the answer record count field.
+    #[inline]
+    pub fn answer_record_count(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::ANCOUNT])
+    }
+
+    /// Return the authority record count field.
+    #[inline]
+    pub fn authority_record_count(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::NSCOUNT])
+    }
+
+    /// Return the additional record count field.
+    #[inline]
+    pub fn additional_record_count(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::ARCOUNT])
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> Packet<&'a T> {
+    /// Return a pointer to the whole message, which compressed names refer into.
+    #[inline]
+    pub fn message(&self) -> &'a [u8] {
+        self.buffer.as_ref()
+    }
+
+    /// Return a pointer to the sections following the header.
+    #[inline]
+    pub fn payload(&self) -> &'a [u8] {
+        let data = self.buffer.as_ref();
+        &data[field::PAYLOAD]
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
+    /// Set the transaction ID field.
+    #[inline]
+    pub fn set_transaction_id(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::ID], value)
+    }
+
+    #[inline]
+    fn set_flags(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::FLAGS], value)
+    }
+
+    /// Clear all flags, and set the opcode and response code fields to zero.
+    #[inline]
+    pub fn clear_flags(&mut self) {
+        self.set_flags(0)
+    }
+
+    /// Set the response (QR) flag.
+    #[inline]
+    pub fn set_response(&mut self, value: bool) {
+        let flags = self.flags() & !field::FLG_QR;
+        self.set_flags(if value { flags | field::FLG_QR } else { flags })
+    }
+
+    /// Set the authoritative answer (AA) flag.
+    #[inline]
+    pub fn set_authoritative(&mut self, value: bool) {
+        let flags = self.flags() & !field::FLG_AA;
+        self.set_flags(if value { flags | field::FLG_AA } else { flags })
+    }
+
+    /// Set the truncation (TC) flag.
+    #[inline]
+    pub fn set_truncated(&mut self, value: bool) {
+        let flags = self.flags() & !field::FLG_TC;
+        self.set_flags(if value { flags | field::FLG_TC } else { flags })
+    }
+
+    /// Set the recursion desired (RD) flag.
+    #[inline]
+    pub fn set_recursion_desired(&mut self, value: bool) {
+        let flags = self.flags() & !field::FLG_RD;
+        self.set_flags(if value { flags | field::FLG_RD } else { flags })
+    }
+
+    /// Set the recursion available (RA) flag.
+    #[inline]
+    pub fn set_recursion_available(&mut self, value: bool) {
+        let flags = self.flags() & !field::FLG_RA;
+        self.set_flags(if value { flags | field::FLG_RA } else { flags })
+    }
+
+    /// Set the opcode field.
+    #[inline]
+    pub fn set_opcode(&mut self, value: Opcode) {
+        let flags = self.flags() & !field::FLG_OPCODE;
+        let value: u8 = value.into();
+        self.set_flags(flags | ((value as u16) << 11) & field::FLG_OPCODE)
+    }
+
+    /// Set the response code field.
+    #[inline]
+    pub fn set_rcode(&mut self, value: Rcode) {
+        let flags = self.flags() & !field::FLG_RCODE;
+        let value: u8 = value.into();
+        self.set_flags(flags | (value as u16) & field::FLG_RCODE)
+    }
+
+    /// Set the question count field.
+    #[inline]
+    pub fn set_question_count(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::QDCOUNT], value)
+    }
+
+    /// Set the answer record count field.
+    #[inline]
+    pub fn set_answer_record_count(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::ANCOUNT], value)
+    }
+
+    /// Set the authority record count field.
+    #[inline]
+    pub fn set_authority_record_count(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::NSCOUNT], value)
+    }
+
+    /// Set the additional record count field.
+    #[inline]
+    pub fn set_additional_record_count(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::ARCOUNT], value)
+    }
+
+    /// Return a mutable pointer to the sections following the header.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let data = self.buffer.as_mut();
+        &mut data[field::PAYLOAD]
+    }
+}
+
+/// Return the length of the (possibly compressed) name at the start of the buffer.
+fn name_len(buffer: &[u8]) -> Result<usize> {
+    let mut offset = 0;
+    loop {
+        let len = *buffer.get(offset).ok_or(Error::Truncated)?;
+        if len & field::LABEL_POINTER == field::LABEL_POINTER {
+            if buffer.len() < offset + 2 { return Err(Error::Truncated) }
+            return Ok(offset + 2)
+        } else if len & field::LABEL_POINTER != 0 {
+            return Err(Error::Malformed)
+        } else if len == 0 {
+            return Ok(offset + 1)
+        }
+        offset += 1 + len as usize;
+    }
+}
+
+/// A domain name, that may be compressed, i.e. refer to other names in the same message.
+#[derive(Clone, Copy)]
+pub struct Name<'a> {
+    message: &'a [u8],
+    name: &'a [u8],
+}
+
+impl<'a> Name<'a> {
+    /// Wrap an encoded name, whose compression pointers refer into `message`.
+    /// For an uncompressed name, `message` may be the name itself.
+    pub fn new(message: &'a [u8], name: &'a [u8]) -> Name<'a> {
+        Name { message, name }
+    }
+
+    /// Return an iterator over the labels of the name.
+    pub fn labels(&self) -> Labels<'a> {
+        Labels { message: self.message, rest: self.name, hops: 0 }
+    }
+
+    /// Query whether two names are the same, ignoring ASCII case.
+    /// Malformed names are not equal to anything.
+    pub fn eq_ignore_case(&self, other: &Name) -> bool {
+        let (mut our_labels, mut their_labels) = (self.labels(), other.labels());
+        loop {
+            match (our_labels.next(), their_labels.next()) {
+                (None, None) => return true,
+                (Some(Ok(ours)), Some(Ok(theirs))) => {
+                    if !ours.eq_ignore_ascii_case(theirs) {
+                        return false
+                    }
+                }
+                _ => return false
+            }
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Name<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl<'a> fmt::Display for Name<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut empty = true;
+        for label in self.labels() {
+            match label {
+                Ok(label) => {
+                    for &octet in label {
+                        if octet.is_ascii_graphic() && octet != b'.' {
+                            write!(f, "{}", octet as char)?
+                        } else {
+                            write!(f, "\\{:03}", octet)?
+                        }
+                    }
+                    write!(f, ".")?;
+                    empty = false;
+                }
+                Err(_) => return write!(f, "<malformed>")
+            }
+        }
+        if empty { write!(f, ".")? }
+        Ok(())
+    }
+}
+
+/// An iterator over the labels of a domain name, created by [Name::labels].
+///
+/// [Name::labels]: struct.Name.html#method.labels
+#[derive(Debug, Clone)]
+pub struct Labels<'a> {
+    message: &'a [u8],
+    rest: &'a [u8],
+    hops: usize,
+}
+
+impl<'a> Iterator for Labels<'a> {
+    type Item = Result<&'a [u8]>;
+
+    fn next(&mut self) -> Option<Result<&'a [u8]>> {
+        loop {
+            let len = match self.rest.first() {
+                Some(&len) => len,
+                None => {
+                    self.rest = &[];
+                    return Some(Err(Error::Truncated))
+                }
+            };
+
+            if len & field::LABEL_POINTER == field::LABEL_POINTER {
+                self.hops += 1;
+                let pointer = match self.rest.get(0..2) {
+                    Some(pointer) if self.hops <= MAX_POINTER_HOPS =>
+                        (NetworkEndian::read_u16(pointer) & 0x3fff) as usize,
+                    Some(_) => {
+                        self.rest = &[0];
+                        return Some(Err(Error::Malformed))
+                    }
+                    None => {
+                        self.rest = &[0];
+                        return Some(Err(Error::Truncated))
+                    }
+                };
+                match self.message.get(pointer..) {
+                    Some(rest) => self.rest = rest,
+                    None => {
+                        self.rest = &[0];
+                        return Some(Err(Error::Truncated))
+                    }
+                }
+            } else if len & field::LABEL_POINTER != 0 {
+                self.rest = &[0];
+                return Some(Err(Error::Malformed))
+            } else if len == 0 {
+                return None
+            } else {
+                let end = 1 + len as usize;
+                match self.rest.get(1..end) {
+                    Some(label) => {
+                        self.rest = &self.rest[end..];
+                        return Some(Ok(label))
+                    }
+                    None => {
+                        self.rest = &[0];
+                        return Some(Err(Error::Truncated))
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Encode a dotted domain name, such as `example.com` or `example.com.`, into the buffer,
+/// and return the length of the encoded name.
+///
+/// Returns `Err(Error::Illegal)` if the name has an empty label or a label longer than
+/// 63 octets, and `Err(Error::Exhausted)` if the buffer is too small.
+#[cfg(any(test, feature = "socket-dns", feature = "proto-mdns"))]
+pub fn encode_name(name: &str, buffer: &mut [u8]) -> Result<usize> {
+    let name = name.as_bytes();
+    let name = if name.last() == Some(&b'.') { &name[..name.len() - 1] } else { name };
+
+    let mut offset = 0;
+    if name.len() > 0 {
+        for label in name.split(|&octet| octet == b'.') {
+            if label.len() == 0 || label.len() > 63 { return Err(Error::Illegal) }
+            let end = offset + 1 + label.len();
+            if end + 1 > buffer.len() || end + 1 > MAX_NAME_LEN { return Err(Error::Exhausted) }
+            buffer[offset] = label.len() as u8;
+            buffer[offset + 1..end].copy_from_slice(label);
+            offset = end;
+        }
+    }
+    if offset + 1 > buffer.len() { return Err(Error::Exhausted) }
+    buffer[offset] = 0;
+    Ok(offset + 1)
+}
+
+/// A question, in the question section of a message.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Question<'a> {
+    /// The encoded name; it may be compressed if the question was parsed from a message.
+    pub name: &'a [u8],
+    /// The type of the records asked for.
+    pub type_: Type,
+}
+
+impl<'a> Question<'a> {
+    /// Parse a question at the start of the buffer, returning the rest of the buffer.
+    pub fn parse(buffer: &'a [u8]) -> Result<(&'a [u8], Question<'a>)> {
+        let name_len = self::name_len(buffer)?;
+        if buffer.len() < name_len + 4 { return Err(Error::Truncated) }
+        let type_ = Type::from(NetworkEndian::read_u16(&buffer[name_len..]));
+        let question = Question { name: &buffer[..name_len], type_ };
+        Ok((&buffer[name_len + 4..], question))
+    }
+
+    /// Return the length of the question when emitted.
+    pub fn buffer_len(&self) -> usize {
+        self.name.len() + 4
+    }
+
+    /// Emit the question into the buffer, in the Internet class, and return the rest
+    /// of the buffer.
+    pub fn emit<'b>(&self, buffer: &'b mut [u8]) -> &'b mut [u8] {
+        let name_len = self.name.len();
+        buffer[..name_len].copy_from_slice(self.name);
+        NetworkEndian::write_u16(&mut buffer[name_len..], self.type_.into());
+        NetworkEndian::write_u16(&mut buffer[name_len + 2..], CLASS_IN);
+        &mut buffer[name_len + 4..]
+    }
+}
+
+/// The data of a resource record.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RecordData<'a> {
+    #[cfg(feature = "proto-ipv4")]
+    A(Ipv4Address),
+    #[cfg(feature = "proto-ipv6")]
+    Aaaa(Ipv6Address),
+    /// The canonical name, which may be compressed.
+    Cname(&'a [u8]),
+    /// Data of any other type.
+    Other(Type, &'a [u8]),
+}
+
+/// A resource record, in the answer, authority or additional section of a message.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Record<'a> {
+    /// The encoded name, which may be compressed.
+    pub name: &'a [u8],
+    /// The class field. In multicast DNS, the most significant bit is a flag.
+    pub class: u16,
+    /// The time the record may be cached for, in seconds.
+    pub ttl: u32,
+    /// The type and data of the record.
+    pub data: RecordData<'a>,
+}
+
+impl<'a> Record<'a> {
+    /// Parse a resource record at the start of the buffer, returning the rest of the buffer.
+    pub fn parse(buffer: &'a [u8]) -> Result<(&'a [u8], Record<'a>)> {
+        let name_len = self::name_len(buffer)?;
+        if buffer.len() < name_len + 10 { return Err(Error::Truncated) }
+        let type_ = Type::from(NetworkEndian::read_u16(&buffer[name_len..]));
+        let class = NetworkEndian::read_u16(&buffer[name_len + 2..]);
+        let ttl = NetworkEndian::read_u32(&buffer[name_len + 4..]);
+        let data_len = NetworkEndian::read_u16(&buffer[name_len + 8..]) as usize;
+        let data_start = name_len + 10;
+        let data = buffer.get(data_start..data_start + data_len).ok_or(Error::Truncated)?;
+
+        let data = match type_ {
+            #[cfg(feature = "proto-ipv4")]
+            Type::A => {
+                if data.len() != 4 { return Err(Error::Malformed) }
+                RecordData::A(Ipv4Address::from_bytes(data))
+            }
+            #[cfg(feature = "proto-ipv6")]
+            Type::Aaaa => {
+                if data.len() != 16 { return Err(Error::Malformed) }
+                RecordData::Aaaa(Ipv6Address::from_bytes(data))
+            }
+            Type::Cname => {
+                if self::name_len(data).map_err(|_| Error::Malformed)? != data.len() {
+                    return Err(Error::Malformed)
+                }
+                RecordData::Cname(data)
+            }
+            _ => RecordData::Other(type_, data)
+        };
+
+        let record = Record { name: &buffer[..name_len], class, ttl, data };
+        Ok((&buffer[data_start + data_len..], record))
+    }
+
+    /// Return the length of the record when emitted.
+    pub fn buffer_len(&self) -> usize {
+        self.name.len() + 10 + self.data_len()
+    }
+
+    fn data_len(&self) -> usize {
+        match self.data {
+            #[cfg(feature = "proto-ipv4")]
+            RecordData::A(_) => 4,
+            #[cfg(feature = "proto-ipv6")]
+            RecordData::Aaaa(_) => 16,
+            RecordData::Cname(name) => name.len(),
+            RecordData::Other(_, data) => data.len(),
+        }
+    }
+
+    /// Emit the record into the buffer, and return the rest of the buffer.
+    ///
+    /// The name and the canonical name of a CNAME record are copied verbatim,
+    /// so they must not be compressed.
+    pub fn emit<'b>(&self, buffer: &'b mut [u8]) -> &'b mut [u8] {
+        let name_len = self.name.len();
+        let data_len = self.data_len();
+        let type_ = match self.data {
+            #[cfg(feature = "proto-ipv4")]
+            RecordData::A(_) => Type::A,
+            #[cfg(feature = "proto-ipv6")]
+            RecordData::Aaaa(_) => Type::Aaaa,
+            RecordData::Cname(_) => Type::Cname,
+            RecordData::Other(type_, _) => type_,
+        };
+
+        buffer[..name_len].copy_from_slice(self.name);
+        NetworkEndian::write_u16(&mut buffer[name_len..], type_.into());
+        NetworkEndian::write_u16(&mut buffer[name_len + 2..], self.class);
+        NetworkEndian::write_u32(&mut buffer[name_len + 4..], self.ttl);
+        NetworkEndian::write_u16(&mut buffer[name_len + 8..], data_len as u16);
+
+        let data = &mut buffer[name_len + 10..name_len + 10 + data_len];
+        match self.data {
+            #[cfg(feature = "proto-ipv4")]
+            RecordData::A(addr) => data.copy_from_slice(addr.as_bytes()),
+            #[cfg(feature = "proto-ipv6")]
+            RecordData::Aaaa(addr) => data.copy_from_slice(addr.as_bytes()),
+            RecordData::Cname(name) => data.copy_from_slice(name),
+            RecordData::Other(_, other) => data.copy_from_slice(other),
+        }
+        &mut buffer[name_len + 10 + data_len..]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // A response for the A records of `www.example.com`, that is a CNAME
+    // for `example.com`.
+    static RESPONSE_BYTES: [u8; 63] = [
+        0x12, 0x34, 0x81, 0x80, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00,
+        // question
+        0x03, 0x77, 0x77, 0x77, 0x07, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65,
+        0x03, 0x63, 0x6f, 0x6d, 0x00, 0x00, 0x01, 0x00, 0x01,
+        // www.example.com CNAME example.com
+        0xc0, 0x0c, 0x00, 0x05, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x02,
+        0xc0, 0x10,
+        // example.com A 93.184.216.34
+        0xc0, 0x10, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x04,
+        0x5d, 0xb8, 0xd8, 0x22,
+    ];
+
+    static QUERY_BYTES: [u8; 33] = [
+        0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x03, 0x77, 0x77, 0x77, 0x07, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65,
+        0x03, 0x63, 0x6f, 0x6d, 0x00, 0x00, 0x01, 0x00, 0x01,
+    ];
+
+    #[test]
+    fn test_deconstruct() {
+        let packet = Packet::new_checked(&RESPONSE_BYTES[..]).unwrap();
+        assert_eq!(packet.transaction_id(), 0x1234);
+        assert_eq!(packet.is_response(), true);
+        assert_eq!(packet.opcode(), Opcode::Query);
+        assert_eq!(packet.authoritative(), false);
+        assert_eq!(packet.truncated(), false);
+        assert_eq!(packet.recursion_desired(), true);
+        assert_eq!(packet.recursion_available(), true);
+        assert_eq!(packet.rcode(), Rcode::NoError);
+        assert_eq!(packet.question_count(), 1);
+        assert_eq!(packet.answer_record_count(), 2);
+        assert_eq!(packet.authority_record_count(), 0);
+        assert_eq!(packet.additional_record_count(), 0);
+    }
+
+    #[test]
+    fn test_construct() {
+        let mut bytes = vec![0xa5; QUERY_BYTES.len()];
+        let mut packet = Packet::new(&mut bytes[..]);
+        packet.set_transaction_id(0x1234);
+        packet.clear_flags();
+        packet.set_opcode(Opcode::Query);
+        packet.set_recursion_desired(true);
+        packet.set_question_count(1);
+        packet.set_answer_record_count(0);
+        packet.set_authority_record_count(0);
+        packet.set_additional_record_count(0);
+
+        let mut name = [0; MAX_NAME_LEN];
+        let name_len = encode_name("www.example.com", &mut name).unwrap();
+        let question = Question { name: &name[..name_len], type_: Type::A };
+        assert_eq!(question.buffer_len(), QUERY_BYTES.len() - 12);
+        question.emit(packet.payload_mut());
+        assert_eq!(&bytes[..], &QUERY_BYTES[..]);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_parse_response() {
+        let packet = Packet::new_checked(&RESPONSE_BYTES[..]).unwrap();
+        let message = packet.message();
+
+        let (rest, question) = Question::parse(packet.payload()).unwrap();
+        assert_eq!(question.type_, Type::A);
+        let mut name = [0; MAX_NAME_LEN];
+        let name_len = encode_name("WWW.example.com.", &mut name).unwrap();
+        let query_name = Name::new(&name[..name_len], &name[..name_len]);
+        assert!(Name::new(message, question.name).eq_ignore_case(&query_name));
+
+        let (rest, cname) = Record::parse(rest).unwrap();
+        assert_eq!(cname.ttl, 3600);
+        assert!(Name::new(message, cname.name).eq_ignore_case(&query_name));
+        let canonical_name = match cname.data {
+            RecordData::Cname(name) => Name::new(message, name),
+            _ => unreachable!()
+        };
+        assert_eq!(format!("{}", canonical_name), "example.com.");
+
+        let (_, a) = Record::parse(rest).unwrap();
+        assert!(Name::new(message, a.name).eq_ignore_case(&canonical_name));
+        assert_eq!(a.class, CLASS_IN);
+        assert_eq!(a.data, RecordData::A(Ipv4Address::new(93, 184, 216, 34)));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv6")]
+    fn test_record_round_trip() {
+        let mut name = [0; MAX_NAME_LEN];
+        let name_len = encode_name("example.com", &mut name).unwrap();
+        let record = Record {
+            name: &name[..name_len],
+            class: CLASS_IN,
+            ttl: 60,
+            data: RecordData::Aaaa(Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+        };
+        let mut bytes = vec![0xa5; record.buffer_len()];
+        assert_eq!(record.emit(&mut bytes).len(), 0);
+        assert_eq!(Record::parse(&bytes), Ok((&[][..], record)));
+    }
+
+    #[test]
+    fn test_encode_name() {
+        let mut name = [0; MAX_NAME_LEN];
+        assert_eq!(encode_name("a.bc", &mut name), Ok(6));
+        assert_eq!(&name[..6], &[1, b'a', 2, b'b', b'c', 0]);
+        assert_eq!(encode_name(".", &mut name), Ok(1));
+        assert_eq!(encode_name("a..b", &mut name), Err(Error::Illegal));
+        let long_label = [b'a'; 64];
+        assert_eq!(encode_name(::core::str::from_utf8(&long_label).unwrap(), &mut name),
+                   Err(Error::Illegal));
+        assert_eq!(encode_name("a.bc", &mut name[..5]), Err(Error::Exhausted));
+    }
+
+    #[test]
+    fn test_pointer_loop() {
+        static LOOP: [u8; 2] = [0xc0, 0x00];
+        let name = Name::new(&LOOP, &LOOP);
+        assert_eq!(name.labels().last(), Some(Err(Error::Malformed)));
+        assert!(!name.eq_ignore_case(&name));
+    }
+
+    #[test]
+    fn test_parse_truncated() {
+        let packet = Packet::new(&RESPONSE_BYTES[..40]);
+        let (rest, _) = Question::parse(packet.payload()).unwrap();
+        assert_eq!(Record::parse(rest), Err(Error::Truncated));
+        assert_eq!(Packet::new_checked(&RESPONSE_BYTES[..11]), Err(Error::Truncated));
+    }
+}
@@ -0,0 +1,621 @@
+// Heads up! Before working on this file you should read IEEE 802.1X-2010 § 11,
+// which describes the EAPOL frame format, and RFC 3748 § 4, which describes
+// the EAP packet format.
+
+use core::fmt;
+use byteorder::{ByteOrder, NetworkEndian};
+
+use {Error, Result};
+use super::EthernetAddress;
+
+/// The group address EAPOL frames are sent to by the Port Access Entities
+/// of a point-to-point LAN.
+pub const PAE_GROUP_ADDRESS: EthernetAddress =
+    EthernetAddress([0x01, 0x80, 0xc2, 0x00, 0x00, 0x03]);
+
+/// The protocol version of 802.1X-2004, which most authenticators accept.
+pub const VERSION_2004: u8 = 2;
+
+enum_with_unknown! {
+    /// EAPOL packet type.
+    pub enum PacketType(u8) {
+        EapPacket = 0,
+        Start     = 1,
+        Logoff    = 2,
+        Key       = 3,
+        AsfAlert  = 4
+    }
+}
+
+impl fmt::Display for PacketType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &PacketType::EapPacket => write!(f, "EAP-Packet"),
+            &PacketType::Start => write!(f, "Start"),
+            &PacketType::Logoff => write!(f, "Logoff"),
+            &PacketType::Key => write!(f, "Key"),
+            &PacketType::AsfAlert => write!(f, "Encapsulated-ASF-Alert"),
+            &PacketType::Unknown(id) => write!(f, "{}", id)
+        }
+    }
+}
+
+enum_with_unknown! {
+    /// EAP packet code.
+    pub enum EapCode(u8) {
+        Request  = 1,
+        Response = 2,
+        Success  = 3,
+        Failure  = 4
+    }
+}
+
+enum_with_unknown! {
+    /// EAP method type.
+    pub enum EapType(u8) {
+        Identity     = 1,
+        Notification = 2,
+        Nak          = 3,
+        Md5Challenge = 4,
+        Tls          = 13,
+        Ttls         = 21,
+        Peap         = 25,
+        Expanded     = 254
+    }
+}
+
+/// A read/write wrapper around an EAPOL frame buffer.
+///
+/// The buffer may extend past the body of the frame, e.g. with the padding
+/// of a short Ethernet frame, which is ignored.
+#[derive(Debug, PartialEq)]
+pub struct Frame<T: AsRef<[u8]>> {
+    buffer: T
+}
+
+/// A read/write wrapper around an EAP packet buffer.
+///
+/// The buffer may extend past the length of the packet, which is ignored.
+#[derive(Debug, PartialEq)]
+pub struct EapPacket<T: AsRef<[u8]>> {
+    buffer: T
+}
+
+mod field {
+    use wire::field::*;
+
+    pub const VERSION:  usize = 0;
+    pub const TYPE:     usize = 1;
+    pub const BODY_LEN: Field = 2..4;
+    pub const BODY:     Rest  = 4..;
+
+    pub const EAP_CODE:       usize = 0;
+    pub const EAP_IDENTIFIER: usize = 1;
+    pub const EAP_LENGTH:     Field = 2..4;
+    pub const EAP_TYPE:       usize = 4;
+    pub const EAP_TYPE_DATA:  Rest  = 5..;
+}
+
+/// The length of an EAPOL frame header.
+pub const HEADER_LEN: usize = field::BODY.start;
+
+/// The length of an EAP packet header.
+pub const EAP_HEADER_LEN: usize = field::EAP_TYPE;
+
+impl<T: AsRef<[u8]>> Frame<T> {
+    /// Imbue a raw octet buffer with EAPOL frame structure.
+    pub fn new(buffer: T) -> Frame<T> {
+        Frame { buffer }
+    }
+
+    /// Shorthand for a combination of [new] and [check_len].
+    ///
+    /// [new]: #method.new
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<Frame<T>> {
+        let frame = Self::new(buffer);
+        frame.check_len()?;
+        Ok(frame)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error::Truncated)` if the buffer is shorter than the header
+    /// and the body.
+    ///
+    /// The result of this check is invalidated by calling [set_body_len].
+    ///
+    /// [set_body_len]: #method.set_body_len
+    pub fn check_len(&self) -> Result<()> {
+        let len = self.buffer.as_ref().len();
+        if len < HEADER_LEN || len < HEADER_LEN + self.body_len() as usize {
+            Err(Error::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Consume the frame, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Return the protocol version field.
+    #[inline]
+    pub fn version(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[field::VERSION]
+    }
+
+    /// Return the packet type field.
+    #[inline]
+    pub fn packet_type(&self) -> PacketType {
+        let data = self.buffer.as_ref();
+        PacketType::from(data[field::TYPE])
+    }
+
+    /// Return the packet body length field.
+    #[inline]
+    pub fn body_len(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::BODY_LEN])
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> Frame<&'a T> {
+    /// Return a pointer to the packet body, without any trailing padding.
+    #[inline]
+    pub fn body(&self) -> &'a [u8] {
+        let data = self.buffer.as_ref();
+        &data[HEADER_LEN..HEADER_LEN + self.body_len() as usize]
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Frame<T> {
+    /// Set the protocol version field.
+    #[inline]
+    pub fn set_version(&mut self, value: u8) {
+        let data = self.buffer.as_mut();
+        data[field::VERSION] = value
+    }
+
+    /// Set the packet type field.
+    #[inline]
+    pub fn set_packet_type(&mut self, value: PacketType) {
+        let data = self.buffer.as_mut();
+        data[field::TYPE] = value.into()
+    }
+
+    /// Set the packet body length field.
+    #[inline]
+    pub fn set_body_len(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::BODY_LEN], value)
+    }
+
+    /// Return a mutable pointer to the packet body, without any trailing padding.
+    #[inline]
+    pub fn body_mut(&mut self) -> &mut [u8] {
+        let body_len = self.body_len() as usize;
+        let data = self.buffer.as_mut();
+        &mut data[HEADER_LEN..HEADER_LEN + body_len]
+    }
+}
+
+impl<T: AsRef<[u8]>> EapPacket<T> {
+    /// Imbue a raw octet buffer with EAP packet structure.
+    pub fn new(buffer: T) -> EapPacket<T> {
+        EapPacket { buffer }
+    }
+
+    /// Shorthand for a combination of [new] and [check_len].
+    ///
+    /// [new]: #method.new
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<EapPacket<T>> {
+        let packet = Self::new(buffer);
+        packet.check_len()?;
+        Ok(packet)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error::Truncated)` if the buffer is shorter than the length field,
+    /// and `Err(Error::Malformed)` if the length field is shorter than the header,
+    /// or a request or response has no type field.
+    ///
+    /// The result of this check is invalidated by calling [set_code] or [set_length].
+    ///
+    /// [set_code]: #method.set_code
+    /// [set_length]: #method.set_length
+    pub fn check_len(&self) -> Result<()> {
+        let len = self.buffer.as_ref().len();
+        if len < EAP_HEADER_LEN {
+            return Err(Error::Truncated)
+        }
+        let length = self.length() as usize;
+        if len < length {
+            Err(Error::Truncated)
+        } else if length < EAP_HEADER_LEN || (self.has_type() && length <= field::EAP_TYPE) {
+            Err(Error::Malformed)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Consume the packet, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Return the code field.
+    #[inline]
+    pub fn code(&self) -> EapCode {
+        let data = self.buffer.as_ref();
+        EapCode::from(data[field::EAP_CODE])
+    }
+
+    /// Return the identifier field.
+    #[inline]
+    pub fn identifier(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[field::EAP_IDENTIFIER]
+    }
+
+    /// Return the length field, which includes the header.
+    #[inline]
+    pub fn length(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::EAP_LENGTH])
+    }
+
+    /// Query whether the packet has a type field, i.e. is a request or a response.
+    #[inline]
+    pub fn has_type(&self) -> bool {
+        match self.code() {
+            EapCode::Request | EapCode::Response => true,
+            _ => false
+        }
+    }
+
+    /// Return the type field.
+    ///
+    /// # Panics
+    /// The function panics if the packet has no type field.
+    #[inline]
+    pub fn type_(&self) -> EapType {
+        assert!(self.has_type(), "EAP packet has no type field");
+        let data = self.buffer.as_ref();
+        EapType::from(data[field::EAP_TYPE])
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> EapPacket<&'a T> {
+    /// Return a pointer to the type data.
+    ///
+    /// # Panics
+    /// The function panics if the packet has no type field.
+    #[inline]
+    pub fn type_data(&self) -> &'a [u8] {
+        assert!(self.has_type(), "EAP packet has no type field");
+        let data = self.buffer.as_ref();
+        &data[field::EAP_TYPE_DATA.start..self.length() as usize]
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> EapPacket<T> {
+    /// Set the code field.
+    #[inline]
+    pub fn set_code(&mut self, value: EapCode) {
+        let data = self.buffer.as_mut();
+        data[field::EAP_CODE] = value.into()
+    }
+
+    /// Set the identifier field.
+    #[inline]
+    pub fn set_identifier(&mut self, value: u8) {
+        let data = self.buffer.as_mut();
+        data[field::EAP_IDENTIFIER] = value
+    }
+
+    /// Set the length field.
+    #[inline]
+    pub fn set_length(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::EAP_LENGTH], value)
+    }
+
+    /// Set the type field.
+    #[inline]
+    pub fn set_type(&mut self, value: EapType) {
+        let data = self.buffer.as_mut();
+        data[field::EAP_TYPE] = value.into()
+    }
+
+    /// Return a mutable pointer to the type data.
+    #[inline]
+    pub fn type_data_mut(&mut self) -> &mut [u8] {
+        let length = self.length() as usize;
+        let data = self.buffer.as_mut();
+        &mut data[field::EAP_TYPE_DATA.start..length]
+    }
+}
+
+/// A high-level representation of an EAP packet.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EapRepr<'a> {
+    Request {
+        identifier: u8,
+        type_:      EapType,
+        data:       &'a [u8]
+    },
+    Response {
+        identifier: u8,
+        type_:      EapType,
+        data:       &'a [u8]
+    },
+    Success {
+        identifier: u8
+    },
+    Failure {
+        identifier: u8
+    },
+}
+
+impl<'a> EapRepr<'a> {
+    /// Parse an EAP packet and return a high-level representation,
+    /// or return `Err(Error::Unrecognized)` if the code is not recognized.
+    pub fn parse<T>(packet: &EapPacket<&'a T>) -> Result<EapRepr<'a>>
+            where T: AsRef<[u8]> + ?Sized {
+        packet.check_len()?;
+        let identifier = packet.identifier();
+        match packet.code() {
+            EapCode::Request =>
+                Ok(EapRepr::Request {
+                    identifier, type_: packet.type_(), data: packet.type_data()
+                }),
+            EapCode::Response =>
+                Ok(EapRepr::Response {
+                    identifier, type_: packet.type_(), data: packet.type_data()
+                }),
+            EapCode::Success => Ok(EapRepr::Success { identifier }),
+            EapCode::Failure => Ok(EapRepr::Failure { identifier }),
+            EapCode::Unknown(_) => Err(Error::Unrecognized)
+        }
+    }
+
+    /// Return the length of a packet that will be emitted from this high-level representation.
+    pub fn buffer_len(&self) -> usize {
+        match self {
+            &EapRepr::Request { data, .. } |
+            &EapRepr::Response { data, .. } => field::EAP_TYPE_DATA.start + data.len(),
+            &EapRepr::Success { .. } |
+            &EapRepr::Failure { .. } => EAP_HEADER_LEN
+        }
+    }
+
+    /// Emit a high-level representation into an EAP packet.
+    pub fn emit<T: AsRef<[u8]> + AsMut<[u8]>>(&self, packet: &mut EapPacket<T>) {
+        packet.set_length(self.buffer_len() as u16);
+        match self {
+            &EapRepr::Request { identifier, type_, data } |
+            &EapRepr::Response { identifier, type_, data } => {
+                packet.set_code(match self {
+                    &EapRepr::Request { .. } => EapCode::Request,
+                    _ => EapCode::Response
+                });
+                packet.set_identifier(identifier);
+                packet.set_type(type_);
+                packet.type_data_mut().copy_from_slice(data);
+            }
+            &EapRepr::Success { identifier } => {
+                packet.set_code(EapCode::Success);
+                packet.set_identifier(identifier);
+            }
+            &EapRepr::Failure { identifier } => {
+                packet.set_code(EapCode::Failure);
+                packet.set_identifier(identifier);
+            }
+        }
+    }
+}
+
+/// A high-level representation of an EAPOL frame.
+///
+/// The body of an `EapPacket` frame is represented with an [EapRepr]; the bodies
+/// of other frames, e.g. the keys of `Key` frames, are left uninterpreted.
+///
+/// [EapRepr]: enum.EapRepr.html
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Repr<'a> {
+    Eap {
+        version: u8,
+        eap:     EapRepr<'a>
+    },
+    Other {
+        version:     u8,
+        packet_type: PacketType,
+        body:        &'a [u8]
+    },
+}
+
+impl<'a> Repr<'a> {
+    /// Parse an EAPOL frame and return a high-level representation.
+    pub fn parse<T>(frame: &Frame<&'a T>) -> Result<Repr<'a>>
+            where T: AsRef<[u8]> + ?Sized {
+        frame.check_len()?;
+        match frame.packet_type() {
+            PacketType::EapPacket => {
+                let packet = EapPacket::new_checked(frame.body())?;
+                // The EAP packet must fill the body.
+                if packet.length() != frame.body_len() { return Err(Error::Malformed) }
+                Ok(Repr::Eap { version: frame.version(), eap: EapRepr::parse(&packet)? })
+            }
+            packet_type =>
+                Ok(Repr::Other {
+                    version: frame.version(), packet_type, body: frame.body()
+                })
+        }
+    }
+
+    /// Return the length of a frame that will be emitted from this high-level representation.
+    pub fn buffer_len(&self) -> usize {
+        match self {
+            &Repr::Eap { ref eap, .. } => HEADER_LEN + eap.buffer_len(),
+            &Repr::Other { body, .. } => HEADER_LEN + body.len()
+        }
+    }
+
+    /// Emit a high-level representation into an EAPOL frame.
+    pub fn emit<T: AsRef<[u8]> + AsMut<[u8]>>(&self, frame: &mut Frame<T>) {
+        match self {
+            &Repr::Eap { version, ref eap } => {
+                frame.set_version(version);
+                frame.set_packet_type(PacketType::EapPacket);
+                frame.set_body_len(eap.buffer_len() as u16);
+                eap.emit(&mut EapPacket::new(frame.body_mut()));
+            }
+            &Repr::Other { version, packet_type, body } => {
+                frame.set_version(version);
+                frame.set_packet_type(packet_type);
+                frame.set_body_len(body.len() as u16);
+                frame.body_mut().copy_from_slice(body);
+            }
+        }
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> fmt::Display for Frame<&'a T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match Repr::parse(self) {
+            Ok(repr) => write!(f, "{}", repr),
+            Err(err) => write!(f, "EAPOL ({})", err)
+        }
+    }
+}
+
+impl<'a> fmt::Display for Repr<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Repr::Eap { version, eap: EapRepr::Request { identifier, type_, data } } =>
+                write!(f, "EAPOL v{} EAP-Request id={} type={:?} len={}",
+                       version, identifier, type_, data.len()),
+            &Repr::Eap { version, eap: EapRepr::Response { identifier, type_, data } } =>
+                write!(f, "EAPOL v{} EAP-Response id={} type={:?} len={}",
+                       version, identifier, type_, data.len()),
+            &Repr::Eap { version, eap: EapRepr::Success { identifier } } =>
+                write!(f, "EAPOL v{} EAP-Success id={}", version, identifier),
+            &Repr::Eap { version, eap: EapRepr::Failure { identifier } } =>
+                write!(f, "EAPOL v{} EAP-Failure id={}", version, identifier),
+            &Repr::Other { version, packet_type, body } =>
+                write!(f, "EAPOL v{} {} len={}", version, packet_type, body.len())
+        }
+    }
+}
+
+use super::pretty_print::{PrettyPrint, PrettyIndent};
+
+impl<T: AsRef<[u8]>> PrettyPrint for Frame<T> {
+    fn pretty_print(buffer: &AsRef<[u8]>, f: &mut fmt::Formatter,
+                    indent: &mut PrettyIndent) -> fmt::Result {
+        write!(f, "{}{}", indent, Frame::new(buffer.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static IDENTITY_BYTES: [u8; 17] =
+        [0x02, 0x00, 0x00, 0x0d,
+         0x02, 0x07, 0x00, 0x0d, 0x01,
+         0x75, 0x73, 0x65, 0x72, 0x40, 0x6c, 0x61, 0x6e];
+
+    static START_BYTES: [u8; 4] =
+        [0x02, 0x01, 0x00, 0x00];
+
+    fn identity_repr() -> Repr<'static> {
+        Repr::Eap {
+            version: VERSION_2004,
+            eap: EapRepr::Response {
+                identifier: 7,
+                type_: EapType::Identity,
+                data: b"user@lan"
+            }
+        }
+    }
+
+    #[test]
+    fn test_deconstruct() {
+        let frame = Frame::new(&IDENTITY_BYTES[..]);
+        assert_eq!(frame.version(), 2);
+        assert_eq!(frame.packet_type(), PacketType::EapPacket);
+        assert_eq!(frame.body_len(), 13);
+        let packet = EapPacket::new(frame.body());
+        assert_eq!(packet.code(), EapCode::Response);
+        assert_eq!(packet.identifier(), 7);
+        assert_eq!(packet.length(), 13);
+        assert_eq!(packet.type_(), EapType::Identity);
+        assert_eq!(packet.type_data(), b"user@lan");
+    }
+
+    #[test]
+    fn test_parse() {
+        let frame = Frame::new(&IDENTITY_BYTES[..]);
+        assert_eq!(Repr::parse(&frame), Ok(identity_repr()));
+
+        // The padding of a short Ethernet frame is ignored.
+        let mut bytes = START_BYTES.to_vec();
+        bytes.resize(46, 0);
+        let frame = Frame::new(&bytes[..]);
+        assert_eq!(Repr::parse(&frame), Ok(Repr::Other {
+            version: VERSION_2004,
+            packet_type: PacketType::Start,
+            body: &[]
+        }));
+
+        let success = [0x01, 0x00, 0x00, 0x04, 0x03, 0x08, 0x00, 0x04];
+        let frame = Frame::new(&success[..]);
+        assert_eq!(Repr::parse(&frame), Ok(Repr::Eap {
+            version: 1,
+            eap: EapRepr::Success { identifier: 8 }
+        }));
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        let frame = Frame::new(&IDENTITY_BYTES[..16]);
+        assert_eq!(Repr::parse(&frame), Err(Error::Truncated));
+
+        // The EAP length does not match the body length.
+        let mut bytes = IDENTITY_BYTES.to_vec();
+        bytes[7] = 0x0c;
+        let frame = Frame::new(&bytes[..]);
+        assert_eq!(Repr::parse(&frame), Err(Error::Malformed));
+
+        // A request without a type.
+        let bytes = [0x02, 0x00, 0x00, 0x04, 0x01, 0x01, 0x00, 0x04];
+        let frame = Frame::new(&bytes[..]);
+        assert_eq!(Repr::parse(&frame), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn test_emit() {
+        let repr = identity_repr();
+        assert_eq!(repr.buffer_len(), IDENTITY_BYTES.len());
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Frame::new(&mut bytes));
+        assert_eq!(&bytes[..], &IDENTITY_BYTES[..]);
+
+        let repr = Repr::Other { version: VERSION_2004, packet_type: PacketType::Start, body: &[] };
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Frame::new(&mut bytes));
+        assert_eq!(&bytes[..], &START_BYTES[..]);
+    }
+
+    #[test]
+    fn test_display() {
+        let frame = Frame::new(&IDENTITY_BYTES[..]);
+        assert_eq!(format!("{}", frame), "EAPOL v2 EAP-Response id=7 type=Identity len=8");
+        let frame = Frame::new(&START_BYTES[..]);
+        assert_eq!(format!("{}", frame), "EAPOL v2 Start len=0");
+    }
+}
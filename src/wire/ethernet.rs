@@ -2,13 +2,19 @@ use core::fmt;
 use byteorder::{ByteOrder, NetworkEndian};
 
 use {Error, Result};
+#[cfg(feature = "arbitrary")]
+use arbitrary::{self, Arbitrary, Unstructured};
 
 enum_with_unknown! {
     /// Ethernet protocol type.
     pub enum EtherType(u16) {
-        Ipv4 = 0x0800,
-        Arp  = 0x0806,
-        Ipv6 = 0x86DD
+        Ipv4      = 0x0800,
+        Arp       = 0x0806,
+        WakeOnLan = 0x0842,
+        Vlan      = 0x8100,
+        Ipv6      = 0x86DD,
+        Eapol     = 0x888E,
+        Ptp       = 0x88F7
     }
 }
 
@@ -18,6 +24,10 @@ impl fmt::Display for EtherType {
             &EtherType::Ipv4 => write!(f, "IPv4"),
             &EtherType::Ipv6 => write!(f, "IPv6"),
             &EtherType::Arp  => write!(f, "ARP"),
+            &EtherType::Vlan => write!(f, "802.1Q"),
+            &EtherType::WakeOnLan => write!(f, "Wake-on-LAN"),
+            &EtherType::Eapol => write!(f, "EAPOL"),
+            &EtherType::Ptp => write!(f, "PTP"),
             &EtherType::Unknown(id) => write!(f, "0x{:04x}", id)
         }
     }
@@ -76,12 +86,41 @@ impl fmt::Display for Address {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Address {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Address> {
+        Ok(Address(u.arbitrary()?))
+    }
+}
+
 /// A read/write wrapper around an Ethernet II frame buffer.
 #[derive(Debug)]
 pub struct Frame<T: AsRef<[u8]>> {
     buffer: T
 }
 
+/// The length of the frame check sequence that trails a frame on the wire.
+pub const FCS_LEN: usize = 4;
+
+/// Compute the frame check sequence of a frame, i.e. the CRC-32 of IEEE 802.3 § 3.2.9
+/// over its contents, which is sent in little-endian order after them.
+pub fn fcs(data: &[u8]) -> u32 {
+    // The table of the reflected polynomial 0xedb88320, a nibble at a time.
+    const TABLE: [u32; 16] = [
+        0x00000000, 0x1db71064, 0x3b6e20c8, 0x26d930ac,
+        0x76dc4190, 0x6b6b51f4, 0x4db26158, 0x5005713c,
+        0xedb88320, 0xf00f9344, 0xd6d6a3e8, 0xcb61b38c,
+        0x9b64c2b0, 0x86d3d2d4, 0xa00ae278, 0xbdbdf21c,
+    ];
+
+    let mut crc = !0u32;
+    for &byte in data {
+        crc = (crc >> 4) ^ TABLE[((crc ^ byte as u32) & 0xf) as usize];
+        crc = (crc >> 4) ^ TABLE[((crc ^ (byte as u32 >> 4)) & 0xf) as usize];
+    }
+    !crc
+}
+
 mod field {
     use wire::field::*;
 
@@ -134,6 +173,12 @@ impl<T: AsRef<[u8]>> Frame<T> {
         field::PAYLOAD.start + payload_len
     }
 
+    /// Return the length of the shortest frame that can be sent, excluding the frame
+    /// check sequence; shorter frames have to be padded to it.
+    pub fn min_len() -> usize {
+        60
+    }
+
     /// Return the destination address field.
     #[inline]
     pub fn dst_addr(&self) -> Address {
@@ -232,6 +277,14 @@ impl<T: AsRef<[u8]>> PrettyPrint for Frame<T> {
                 indent.increase(f)?;
                 super::Ipv6Packet::<&[u8]>::pretty_print(&frame.payload(), f, indent)
             }
+            EtherType::Eapol => {
+                indent.increase(f)?;
+                super::EapolFrame::<&[u8]>::pretty_print(&frame.payload(), f, indent)
+            }
+            EtherType::Ptp => {
+                indent.increase(f)?;
+                super::PtpPacket::<&[u8]>::pretty_print(&frame.payload(), f, indent)
+            }
             _ => Ok(())
         }
     }
@@ -242,6 +295,7 @@ impl<T: AsRef<[u8]>> PrettyPrint for Frame<T> {
 mod test {
     // Tests that are valid with any combination of
     // "proto-*" features.
+    use byteorder::LittleEndian;
     use super::*;
 
     #[test]
@@ -251,6 +305,17 @@ mod test {
         assert!(Address::BROADCAST.is_multicast());
         assert!(Address::BROADCAST.is_local());
     }
+
+    #[test]
+    fn test_fcs() {
+        assert_eq!(fcs(b""), 0);
+        assert_eq!(fcs(b"123456789"), 0xcbf43926);
+        // The CRC of a frame followed by its FCS is the residue.
+        let mut frame = [0xa5; 64];
+        let value = fcs(&frame[..60]);
+        LittleEndian::write_u32(&mut frame[60..], value);
+        assert_eq!(fcs(&frame), 0x2144df1c);
+    }
 }
 
 #[cfg(test)]
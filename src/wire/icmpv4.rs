@@ -2,6 +2,8 @@ use core::{cmp, fmt};
 use byteorder::{ByteOrder, NetworkEndian};
 
 use {Error, Result};
+#[cfg(feature = "arbitrary")]
+use arbitrary::{self, Arbitrary, Unstructured};
 use phy::ChecksumCapabilities;
 use super::ip::checksum;
 use super::{Ipv4Packet, Ipv4Repr};
@@ -32,6 +34,20 @@ enum_with_unknown! {
     }
 }
 
+impl Message {
+    /// Query whether the message reports an error. No ICMP error messages are sent
+    /// about ICMP error messages; see [RFC 1812 § 4.3.2.7].
+    ///
+    /// [RFC 1812 § 4.3.2.7]: https://tools.ietf.org/html/rfc1812#section-4.3.2.7
+    pub fn is_error(&self) -> bool {
+        match *self {
+            Message::DstUnreachable | Message::Redirect |
+            Message::TimeExceeded | Message::ParamProblem => true,
+            _ => false
+        }
+    }
+}
+
 impl fmt::Display for Message {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -153,6 +169,19 @@ enum_with_unknown! {
     }
 }
 
+impl fmt::Display for TimeExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &TimeExceeded::TtlExpired =>
+                write!(f, "time-to-live exceeded in transit"),
+            &TimeExceeded::FragExpired =>
+                write!(f, "fragment reassembly time exceeded"),
+            &TimeExceeded::Unknown(id) =>
+                write!(f, "{}", id)
+        }
+    }
+}
+
 enum_with_unknown! {
     /// Internet protocol control message subtype for type "Parameter Problem".
     pub doc enum ParamProblem(u8) {
@@ -183,6 +212,8 @@ mod field {
     pub const ECHO_IDENT: Field = 4..6;
     pub const ECHO_SEQNO: Field = 6..8;
 
+    pub const NEXT_HOP_MTU: Field = 6..8;
+
     pub const HEADER_END: usize = 8;
 }
 
@@ -263,6 +294,17 @@ impl<T: AsRef<[u8]>> Packet<T> {
         NetworkEndian::read_u16(&data[field::ECHO_SEQNO])
     }
 
+    /// Return the next-hop MTU field (for fragmentation needed packets),
+    /// or zero if the router that sent the packet predates RFC 1191.
+    ///
+    /// # Panics
+    /// This function may panic if this packet is not a destination unreachable packet.
+    #[inline]
+    pub fn next_hop_mtu(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::NEXT_HOP_MTU])
+    }
+
     /// Return the header length.
     /// The result depends on the value of the message type field.
     pub fn header_len(&self) -> usize {
@@ -337,6 +379,16 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
         NetworkEndian::write_u16(&mut data[field::ECHO_SEQNO], value)
     }
 
+    /// Set the next-hop MTU field (for fragmentation needed packets).
+    ///
+    /// # Panics
+    /// This function may panic if this packet is not a destination unreachable packet.
+    #[inline]
+    pub fn set_next_hop_mtu(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::NEXT_HOP_MTU], value)
+    }
+
     /// Compute and fill in the header checksum.
     pub fn fill_checksum(&mut self) {
         self.set_checksum(0);
@@ -376,6 +428,11 @@ pub enum Repr<'a> {
         header: Ipv4Repr,
         data:   &'a [u8]
     },
+    TimeExceeded {
+        reason: TimeExceeded,
+        header: Ipv4Repr,
+        data:   &'a [u8]
+    },
     #[doc(hidden)]
     __Nonexhaustive
 }
@@ -386,6 +443,29 @@ impl<'a> Repr<'a> {
     pub fn parse<T>(packet: &Packet<&'a T>, checksum_caps: &ChecksumCapabilities)
                    -> Result<Repr<'a>>
                 where T: AsRef<[u8]> + ?Sized {
+        fn parse_datagram<'a, T>(packet: &Packet<&'a T>) -> Result<(Ipv4Repr, &'a [u8])>
+                where T: AsRef<[u8]> + ?Sized {
+            // The datagram is usually truncated to fit into the message.
+            let ip_packet = Ipv4Packet::new(packet.data());
+            ip_packet.check_header_len()?;
+
+            let payload = &packet.data()[ip_packet.header_len() as usize..];
+            // RFC 792 requires exactly eight bytes to be returned.
+            // We allow more, since there isn't a reason not to, but require at least eight.
+            if payload.len() < 8 { return Err(Error::Truncated) }
+
+            let header = Ipv4Repr {
+                src_addr: ip_packet.src_addr(),
+                dst_addr: ip_packet.dst_addr(),
+                protocol: ip_packet.protocol(),
+                payload_len: payload.len(),
+                hop_limit: ip_packet.hop_limit(),
+                ecn: ip_packet.ecn(),
+                dscp: ip_packet.dscp()
+            };
+            Ok((header, payload))
+        }
+
         // Valid checksum is expected.
         if checksum_caps.icmpv4.rx() && !packet.verify_checksum() { return Err(Error::Checksum) }
 
@@ -407,23 +487,20 @@ impl<'a> Repr<'a> {
             },
 
             (Message::DstUnreachable, code) => {
-                let ip_packet = Ipv4Packet::new_checked(packet.data())?;
-
-                let payload = &packet.data()[ip_packet.header_len() as usize..];
-                // RFC 792 requires exactly eight bytes to be returned.
-                // We allow more, since there isn't a reason not to, but require at least eight.
-                if payload.len() < 8 { return Err(Error::Truncated) }
-
+                let (header, payload) = parse_datagram(packet)?;
                 Ok(Repr::DstUnreachable {
                     reason: DstUnreachable::from(code),
-                    header: Ipv4Repr {
-                        src_addr: ip_packet.src_addr(),
-                        dst_addr: ip_packet.dst_addr(),
-                        protocol: ip_packet.protocol(),
-                        payload_len: payload.len(),
-                        hop_limit: ip_packet.hop_limit()
-                    },
-                    data: payload
+                    header: header,
+                    data:   payload
+                })
+            }
+
+            (Message::TimeExceeded, code) => {
+                let (header, payload) = parse_datagram(packet)?;
+                Ok(Repr::TimeExceeded {
+                    reason: TimeExceeded::from(code),
+                    header: header,
+                    data:   payload
                 })
             }
             _ => Err(Error::Unrecognized)
@@ -437,7 +514,8 @@ impl<'a> Repr<'a> {
             &Repr::EchoReply { data, .. } => {
                 field::ECHO_SEQNO.end + data.len()
             },
-            &Repr::DstUnreachable { header, data, .. } => {
+            &Repr::DstUnreachable { header, data, .. } |
+            &Repr::TimeExceeded { header, data, .. } => {
                 field::UNUSED.end + header.buffer_len() + data.len()
             }
             &Repr::__Nonexhaustive => unreachable!()
@@ -478,6 +556,16 @@ impl<'a> Repr<'a> {
                 payload.copy_from_slice(&data[..])
             }
 
+            &Repr::TimeExceeded { reason, header, data } => {
+                packet.set_msg_type(Message::TimeExceeded);
+                packet.set_msg_code(reason.into());
+
+                let mut ip_packet = Ipv4Packet::new(packet.data_mut());
+                header.emit(&mut ip_packet, checksum_caps);
+                let payload = &mut ip_packet.into_inner()[header.buffer_len()..];
+                payload.copy_from_slice(&data[..])
+            }
+
             &Repr::__Nonexhaustive => unreachable!()
         }
 
@@ -491,6 +579,38 @@ impl<'a> Repr<'a> {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Repr<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Repr<'a>> {
+        match u.int_in_range(0..=3)? {
+            0 => Ok(Repr::EchoRequest {
+                ident:  u.arbitrary()?,
+                seq_no: u.arbitrary()?,
+                data:   u.arbitrary()?
+            }),
+            1 => Ok(Repr::EchoReply {
+                ident:  u.arbitrary()?,
+                seq_no: u.arbitrary()?,
+                data:   u.arbitrary()?
+            }),
+            kind => {
+                // At least eight octets of the original datagram are required, and
+                // the embedded header must describe exactly the octets that follow it.
+                let mut header: Ipv4Repr = u.arbitrary()?;
+                let max_len = cmp::min(u.len(), 0xffff - header.buffer_len());
+                let length = u.int_in_range(8..=cmp::max(8, max_len))?;
+                let data = u.bytes(length)?;
+                header.payload_len = data.len();
+                if kind == 2 {
+                    Ok(Repr::DstUnreachable { reason: u.arbitrary()?, header: header, data: data })
+                } else {
+                    Ok(Repr::TimeExceeded { reason: u.arbitrary()?, header: header, data: data })
+                }
+            }
+        }
+    }
+}
+
 impl<'a, T: AsRef<[u8]> + ?Sized> fmt::Display for Packet<&'a T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match Repr::parse(self, &ChecksumCapabilities::default()) {
@@ -501,6 +621,8 @@ impl<'a, T: AsRef<[u8]> + ?Sized> fmt::Display for Packet<&'a T> {
                 match self.msg_type() {
                     Message::DstUnreachable =>
                         write!(f, " code={:?}", DstUnreachable::from(self.msg_code())),
+                    Message::TimeExceeded =>
+                        write!(f, " code={:?}", TimeExceeded::from(self.msg_code())),
                     _ => write!(f, " code={}", self.msg_code())
                 }
             }
@@ -520,6 +642,9 @@ impl<'a> fmt::Display for Repr<'a> {
             &Repr::DstUnreachable { reason, .. } =>
                 write!(f, "ICMPv4 destination unreachable ({})",
                        reason),
+            &Repr::TimeExceeded { reason, .. } =>
+                write!(f, "ICMPv4 time exceeded ({})",
+                       reason),
             &Repr::__Nonexhaustive => unreachable!()
         }
     }
@@ -537,7 +662,7 @@ impl<T: AsRef<[u8]>> PrettyPrint for Packet<T> {
         write!(f, "{}{}", indent, packet)?;
 
         match packet.msg_type() {
-            Message::DstUnreachable => {
+            Message::DstUnreachable | Message::TimeExceeded => {
                 indent.increase(f)?;
                 super::Ipv4Packet::<&[u8]>::pretty_print(&packet.data(), f, indent)
             }
@@ -548,6 +673,7 @@ impl<T: AsRef<[u8]>> PrettyPrint for Packet<T> {
 
 #[cfg(test)]
 mod test {
+    use wire::{Ipv4Address, IpProtocol};
     use super::*;
 
     static ECHO_PACKET_BYTES: [u8; 12] =
@@ -607,6 +733,62 @@ mod test {
         assert_eq!(&packet.into_inner()[..], &ECHO_PACKET_BYTES[..]);
     }
 
+    #[test]
+    fn test_time_exceeded_round_trip() {
+        let data = [0xbf, 0x00, 0x00, 0x35, 0x00, 0x0c, 0x12, 0x4d];
+        let repr = Repr::TimeExceeded {
+            reason: TimeExceeded::TtlExpired,
+            header: Ipv4Repr {
+                src_addr:    Ipv4Address([0x0a, 0x00, 0x00, 0x01]),
+                dst_addr:    Ipv4Address([0x0a, 0x00, 0x00, 0x02]),
+                protocol:    IpProtocol::Udp,
+                payload_len: 8,
+                hop_limit:   1,
+                ecn:         0,
+                dscp:        0
+            },
+            data: &data
+        };
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new(&mut bytes), &ChecksumCapabilities::default());
+        let packet = Packet::new(&bytes);
+        assert_eq!(packet.msg_type(), Message::TimeExceeded);
+        assert_eq!(packet.msg_code(), 0);
+        assert!(packet.verify_checksum());
+        assert_eq!(Repr::parse(&packet, &ChecksumCapabilities::default()), Ok(repr));
+    }
+
+    #[test]
+    fn test_truncated_datagram() {
+        // The message quotes the header and the first 8 octets of a 1500 octet datagram.
+        let mut bytes = [0u8; 8 + 20 + 8];
+        bytes[0] = 0x03;
+        bytes[1] = 0x04;
+        bytes[8..12].copy_from_slice(&[0x45, 0x00, 0x05, 0xdc]);
+        bytes[17] = 0x11;
+        bytes[20..24].copy_from_slice(&[0x0a, 0x00, 0x00, 0x01]);
+        bytes[24..28].copy_from_slice(&[0x0a, 0x00, 0x00, 0x02]);
+        let repr = Repr::parse(&Packet::new(&bytes[..]), &ChecksumCapabilities::ignored());
+        match repr {
+            Ok(Repr::DstUnreachable { reason: DstUnreachable::FragRequired, header, data }) => {
+                assert_eq!(header.dst_addr, Ipv4Address([0x0a, 0x00, 0x00, 0x02]));
+                assert_eq!(header.protocol, IpProtocol::Udp);
+                assert_eq!(data, &[0; 8]);
+            }
+            repr => panic!("unexpected {:?}", repr)
+        }
+    }
+
+    #[test]
+    fn test_next_hop_mtu() {
+        let mut bytes = [0x03, 0x04, 0x00, 0x00,
+                         0x00, 0x00, 0x00, 0x00];
+        let mut packet = Packet::new(&mut bytes[..]);
+        packet.set_next_hop_mtu(1400);
+        assert_eq!(packet.next_hop_mtu(), 1400);
+        assert_eq!(&packet.into_inner()[4..], &[0x00, 0x00, 0x05, 0x78]);
+    }
+
     #[test]
     fn test_check_len() {
         let bytes = [0x0b, 0x00, 0x00, 0x00,
@@ -615,4 +797,22 @@ mod test {
         assert_eq!(Packet::new_checked(&bytes[..4]), Err(Error::Truncated));
         assert!(Packet::new_checked(&bytes[..]).is_ok());
     }
+
+    #[test]
+    #[cfg(feature = "arbitrary")]
+    fn test_arbitrary_round_trip() {
+        use arbitrary::Unstructured;
+        use wire::ip::test::arbitrary_inputs;
+
+        for input in arbitrary_inputs() {
+            let repr: Repr = match Unstructured::new(&input).arbitrary() {
+                Ok(repr) => repr,
+                Err(_) => continue
+            };
+            let mut bytes = vec![0xa5; repr.buffer_len()];
+            repr.emit(&mut Packet::new(&mut bytes), &ChecksumCapabilities::default());
+            assert_eq!(Repr::parse(&Packet::new(&bytes), &ChecksumCapabilities::default()),
+                       Ok(repr));
+        }
+    }
 }
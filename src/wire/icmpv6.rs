@@ -2,9 +2,13 @@ use core::{cmp, fmt};
 use byteorder::{ByteOrder, NetworkEndian};
 
 use {Error, Result};
+#[cfg(feature = "arbitrary")]
+use arbitrary::{self, Arbitrary, Unstructured};
 use phy::ChecksumCapabilities;
 use super::ip::checksum;
 use super::{Ipv6Packet, Ipv6Repr};
+use super::NdiscRepr;
+use super::MldRepr;
 
 enum_with_unknown! {
     /// Internet protocol control message type.
@@ -20,7 +24,25 @@ enum_with_unknown! {
         /// Echo Request
         EchoRequest    = 0x80,
         /// Echo Reply
-        EchoReply      = 0x81
+        EchoReply      = 0x81,
+        /// Multicast Listener Query
+        MldQuery       = 0x82,
+        /// Multicast Listener Report
+        MldReport      = 0x83,
+        /// Multicast Listener Done
+        MldDone        = 0x84,
+        /// Router Solicitation
+        RouterSolicit  = 0x85,
+        /// Router Advertisement
+        RouterAdvert   = 0x86,
+        /// Neighbor Solicitation
+        NeighborSolicit = 0x87,
+        /// Neighbor Advertisement
+        NeighborAdvert = 0x88,
+        /// Redirect
+        Redirect       = 0x89,
+        /// Version 2 Multicast Listener Report
+        MldReportV2    = 0x8f
     }
 }
 
@@ -33,6 +55,31 @@ impl Message {
     pub fn is_error(&self) -> bool {
         (u8::from(*self) & 0x80) != 0x80
     }
+
+    /// Return a boolean value indicating if the message is a
+    /// [Neighbor Discovery] message.
+    ///
+    /// [Neighbor Discovery]: https://tools.ietf.org/html/rfc4861
+    pub fn is_ndisc(&self) -> bool {
+        match *self {
+            Message::RouterSolicit | Message::RouterAdvert |
+            Message::NeighborSolicit | Message::NeighborAdvert |
+            Message::Redirect => true,
+            _ => false
+        }
+    }
+
+    /// Return a boolean value indicating if the message is a
+    /// [Multicast Listener Discovery] message.
+    ///
+    /// [Multicast Listener Discovery]: https://tools.ietf.org/html/rfc3810
+    pub fn is_mld(&self) -> bool {
+        match *self {
+            Message::MldQuery | Message::MldReport |
+            Message::MldDone | Message::MldReportV2 => true,
+            _ => false
+        }
+    }
 }
 
 impl fmt::Display for Message {
@@ -44,6 +91,15 @@ impl fmt::Display for Message {
             &Message::ParamProblem   => write!(f, "parameter problem"),
             &Message::EchoReply      => write!(f, "echo reply"),
             &Message::EchoRequest    => write!(f, "echo request"),
+            &Message::MldQuery       => write!(f, "multicast listener query"),
+            &Message::MldReport      => write!(f, "multicast listener report"),
+            &Message::MldDone        => write!(f, "multicast listener done"),
+            &Message::MldReportV2    => write!(f, "version 2 multicast listener report"),
+            &Message::RouterSolicit  => write!(f, "router solicitation"),
+            &Message::RouterAdvert   => write!(f, "router advertisement"),
+            &Message::NeighborSolicit => write!(f, "neighbor solicitation"),
+            &Message::NeighborAdvert => write!(f, "neighbor advertisement"),
+            &Message::Redirect       => write!(f, "redirect"),
             &Message::Unknown(id) => write!(f, "{}", id)
         }
     }
@@ -93,15 +149,42 @@ impl fmt::Display for DstUnreachable {
     }
 }
 
+enum_with_unknown! {
+    /// Internet protocol control message subtype for type "Parameter Problem".
+    pub doc enum ParamProblem(u8) {
+        /// Erroneous header field encountered.
+        ErroneousHdrField  = 0,
+        /// Unrecognized Next Header type encountered.
+        UnrecognizedNxtHdr = 1,
+        /// Unrecognized IPv6 option encountered.
+        UnrecognizedOption = 2
+    }
+}
+
+impl fmt::Display for ParamProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ParamProblem::ErroneousHdrField =>
+                write!(f, "erroneous header field encountered"),
+            &ParamProblem::UnrecognizedNxtHdr =>
+                write!(f, "unrecognized next header type encountered"),
+            &ParamProblem::UnrecognizedOption =>
+                write!(f, "unrecognized IPv6 option encountered"),
+            &ParamProblem::Unknown(id) =>
+                write!(f, "{}", id)
+        }
+    }
+}
+
 /// A read/write wrapper around an Internet Control Message Protocol version 6 packet buffer.
 #[derive(Debug, PartialEq)]
 pub struct Packet<T: AsRef<[u8]>> {
-    buffer: T
+    pub(super) buffer: T
 }
 
 // Ranges and constants describing key boundaries in the ICMPv6 header.
 // See https://tools.ietf.org/html/rfc4443 for details.
-mod field {
+pub(super) mod field {
     use wire::field::*;
 
     pub const TYPE:       usize = 0;
@@ -115,6 +198,18 @@ mod field {
     pub const ECHO_SEQNO: Field = 6..8;
 
     pub const HEADER_END: usize = 8;
+
+    // Router Solicitation and Router Advertisement headers;
+    // see https://tools.ietf.org/html/rfc4861#section-4.1 and following.
+    pub const ROUTER_SOLICIT_END: usize = 8;
+    pub const ROUTER_ADVERT_END:  usize = 16;
+    // Neighbor Solicitation, Neighbor Advertisement and Redirect headers.
+    pub const NEIGHBOR_END:       usize = 24;
+    pub const REDIRECT_END:       usize = 40;
+
+    // Multicast Listener Discovery headers; see https://tools.ietf.org/html/rfc3810#section-5.
+    pub const MLD_END:            usize = 24;
+    pub const MLD_REPORT_V2_END:  usize = 8;
 }
 
 impl<T: AsRef<[u8]>> Packet<T> {
@@ -139,6 +234,8 @@ impl<T: AsRef<[u8]>> Packet<T> {
         let len = self.buffer.as_ref().len();
         if len < field::HEADER_END {
             Err(Error::Truncated)
+        } else if len < self.header_len() {
+            Err(Error::Truncated)
         } else {
             Ok(())
         }
@@ -208,6 +305,15 @@ impl<T: AsRef<[u8]>> Packet<T> {
             Message::ParamProblem   => field::POINTER.end,
             Message::EchoRequest    => field::ECHO_SEQNO.end,
             Message::EchoReply      => field::ECHO_SEQNO.end,
+            Message::RouterSolicit  => field::ROUTER_SOLICIT_END,
+            Message::RouterAdvert   => field::ROUTER_ADVERT_END,
+            Message::NeighborSolicit => field::NEIGHBOR_END,
+            Message::NeighborAdvert => field::NEIGHBOR_END,
+            Message::Redirect       => field::REDIRECT_END,
+            Message::MldQuery       => field::MLD_END,
+            Message::MldReport      => field::MLD_END,
+            Message::MldDone        => field::MLD_END,
+            Message::MldReportV2    => field::MLD_REPORT_V2_END,
             // For packets that are not included in RFC 4443, do not
             // include the last 32 bits of the ICMPv6 header in
             // `header_bytes`. This must be done so that these bytes
@@ -338,6 +444,7 @@ pub enum Repr<'a> {
         data:   &'a [u8]
     },
     ParamProblem {
+        reason:  ParamProblem,
         pointer: u32,
         header:  Ipv6Repr,
         data:    &'a [u8]
@@ -352,6 +459,8 @@ pub enum Repr<'a> {
         seq_no: u16,
         data:   &'a [u8]
     },
+    Ndisc(NdiscRepr),
+    Mld(MldRepr),
     #[doc(hidden)]
     __Nonexhaustive
 }
@@ -365,7 +474,9 @@ impl<'a> Repr<'a> {
         fn create_packet_from_payload<'a, T>(packet: &Packet<&'a T>)
                                             -> Result<(&'a [u8], Ipv6Repr)>
                 where T: AsRef<[u8]> + ?Sized {
-            let ip_packet = Ipv6Packet::new_checked(packet.payload())?;
+            // The packet is usually truncated to fit into the message.
+            let ip_packet = Ipv6Packet::new(packet.payload());
+            ip_packet.check_header_len()?;
 
             let payload = &packet.payload()[ip_packet.header_len() as usize..];
             if payload.len() < 8 { return Err(Error::Truncated) }
@@ -374,7 +485,10 @@ impl<'a> Repr<'a> {
                 dst_addr: ip_packet.dst_addr(),
                 next_header: ip_packet.next_header(),
                 payload_len: payload.len(),
-                hop_limit: ip_packet.hop_limit()
+                hop_limit: ip_packet.hop_limit(),
+                ecn: ip_packet.traffic_class() & 0x03,
+                dscp: ip_packet.traffic_class() >> 2,
+                flow_label: ip_packet.flow_label()
             };
             Ok((payload, repr))
         }
@@ -405,9 +519,10 @@ impl<'a> Repr<'a> {
                     data: payload
                 })
             },
-            (Message::ParamProblem, 0) => {
+            (Message::ParamProblem, code) => {
                 let (payload, repr) = create_packet_from_payload(packet)?;
                 Ok(Repr::ParamProblem {
+                    reason:  ParamProblem::from(code),
                     pointer: packet.param_problem_ptr(),
                     header: repr,
                     data: payload
@@ -427,6 +542,12 @@ impl<'a> Repr<'a> {
                     data:   packet.payload()
                 })
             },
+            (msg_type, 0) if msg_type.is_ndisc() => {
+                NdiscRepr::parse(packet).map(Repr::Ndisc)
+            },
+            (msg_type, 0) if msg_type.is_mld() => {
+                MldRepr::parse(packet).map(Repr::Mld)
+            },
             _ => Err(Error::Unrecognized)
         }
     }
@@ -442,6 +563,12 @@ impl<'a> Repr<'a> {
             &Repr::EchoReply { data, .. } => {
                 field::ECHO_SEQNO.end + data.len()
             },
+            &Repr::Ndisc(ndisc) => {
+                ndisc.buffer_len()
+            },
+            &Repr::Mld(mld) => {
+                mld.buffer_len()
+            },
             &Repr::__Nonexhaustive => unreachable!()
         }
     }
@@ -480,9 +607,9 @@ impl<'a> Repr<'a> {
                 emit_contained_packet(packet.payload_mut(), header, &data);
             },
 
-            &Repr::ParamProblem { pointer, header, data } => {
+            &Repr::ParamProblem { reason, pointer, header, data } => {
                 packet.set_msg_type(Message::ParamProblem);
-                packet.set_msg_code(0);
+                packet.set_msg_code(reason.into());
                 packet.set_param_problem_ptr(pointer);
 
                 emit_contained_packet(packet.payload_mut(), header, &data);
@@ -506,6 +633,14 @@ impl<'a> Repr<'a> {
                 packet.payload_mut()[..data_len].copy_from_slice(&data[..data_len])
             },
 
+            &Repr::Ndisc(ndisc) => {
+                ndisc.emit(packet)
+            },
+
+            &Repr::Mld(mld) => {
+                mld.emit(packet)
+            },
+
             &Repr::__Nonexhaustive => unreachable!(),
         }
 
@@ -518,6 +653,55 @@ impl<'a> Repr<'a> {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Repr<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Repr<'a>> {
+        // At least eight octets of the original datagram are required, and
+        // the embedded header must describe exactly the octets that follow it.
+        fn original<'a>(u: &mut Unstructured<'a>) -> arbitrary::Result<(Ipv6Repr, &'a [u8])> {
+            let mut header: Ipv6Repr = u.arbitrary()?;
+            let max_len = cmp::min(u.len(), 0xffff);
+            let length = u.int_in_range(8..=cmp::max(8, max_len))?;
+            let data = u.bytes(length)?;
+            header.payload_len = data.len();
+            Ok((header, data))
+        }
+
+        match u.int_in_range(0..=5)? {
+            0 => {
+                let reason = u.arbitrary()?;
+                let (header, data) = original(u)?;
+                Ok(Repr::DstUnreachable { reason: reason, header: header, data: data })
+            }
+            1 => {
+                let mtu = u.arbitrary()?;
+                let (header, data) = original(u)?;
+                Ok(Repr::PktTooBig { mtu: mtu, header: header, data: data })
+            }
+            2 => {
+                let (header, data) = original(u)?;
+                Ok(Repr::TimeExceeded { header: header, data: data })
+            }
+            3 => {
+                let reason = u.arbitrary()?;
+                let pointer = u.arbitrary()?;
+                let (header, data) = original(u)?;
+                Ok(Repr::ParamProblem { reason: reason, pointer: pointer, header: header, data: data })
+            }
+            4 => Ok(Repr::EchoRequest {
+                ident:  u.arbitrary()?,
+                seq_no: u.arbitrary()?,
+                data:   u.arbitrary()?
+            }),
+            _ => Ok(Repr::EchoReply {
+                ident:  u.arbitrary()?,
+                seq_no: u.arbitrary()?,
+                data:   u.arbitrary()?
+            })
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use wire::{Ipv6Address, Ipv6Repr, IpProtocol};
@@ -590,7 +774,10 @@ mod test {
                                        0x00, 0x00, 0x00, 0x02]),
                 next_header: IpProtocol::Udp,
                 payload_len: 12,
-                hop_limit: 0x40
+                hop_limit: 0x40,
+                ecn: 0,
+                dscp: 0,
+                flow_label: 0
             },
             data: &PKT_TOO_BIG_UDP_PAYLOAD,
         }
@@ -677,4 +864,22 @@ mod test {
         repr.emit(&mut packet, &ChecksumCapabilities::default());
         assert_eq!(&packet.into_inner()[..], &PKT_TOO_BIG_BYTES[..]);
     }
+
+    #[test]
+    #[cfg(feature = "arbitrary")]
+    fn test_arbitrary_round_trip() {
+        use arbitrary::Unstructured;
+        use wire::ip::test::arbitrary_inputs;
+
+        for input in arbitrary_inputs() {
+            let repr: Repr = match Unstructured::new(&input).arbitrary() {
+                Ok(repr) => repr,
+                Err(_) => continue
+            };
+            let mut bytes = vec![0xa5; repr.buffer_len()];
+            repr.emit(&mut Packet::new(&mut bytes), &ChecksumCapabilities::default());
+            assert_eq!(Repr::parse(&Packet::new(&bytes), &ChecksumCapabilities::default()),
+                       Ok(repr));
+        }
+    }
 }
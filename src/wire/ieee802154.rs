@@ -0,0 +1,757 @@
+// Heads up! Before working on this file you should read IEEE 802.15.4-2006 § 7.2,
+// which describes the general MAC frame format.
+
+use core::fmt;
+use byteorder::{ByteOrder, LittleEndian};
+
+use {Error, Result};
+use super::pretty_print::{PrettyPrint, PrettyIndent};
+
+enum_with_unknown! {
+    /// IEEE 802.15.4 frame type.
+    pub enum FrameType(u8) {
+        Beacon          = 0b000,
+        Data            = 0b001,
+        Acknowledgement = 0b010,
+        MacCommand      = 0b011
+    }
+}
+
+impl fmt::Display for FrameType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &FrameType::Beacon => write!(f, "Beacon"),
+            &FrameType::Data => write!(f, "Data"),
+            &FrameType::Acknowledgement => write!(f, "Ack"),
+            &FrameType::MacCommand => write!(f, "MAC command"),
+            &FrameType::Unknown(id) => write!(f, "0b{:03b}", id)
+        }
+    }
+}
+
+enum_with_unknown! {
+    /// IEEE 802.15.4 addressing mode of the source or destination address.
+    pub enum AddressingMode(u8) {
+        Absent   = 0b00,
+        Short    = 0b10,
+        Extended = 0b11
+    }
+}
+
+impl AddressingMode {
+    /// Return the length of an address in this mode, in octets.
+    fn len(&self) -> usize {
+        match *self {
+            AddressingMode::Short => 2,
+            AddressingMode::Extended => 8,
+            _ => 0
+        }
+    }
+}
+
+/// An IEEE 802.15.4 PAN identifier.
+#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
+pub struct Pan(pub u16);
+
+impl Pan {
+    /// The broadcast PAN identifier, which every device accepts.
+    pub const BROADCAST: Pan = Pan(0xffff);
+}
+
+impl fmt::Display for Pan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:04x}", self.0)
+    }
+}
+
+/// An IEEE 802.15.4 address, either a short address assigned by the coordinator
+/// of the PAN, or the extended address (EUI-64) of the device.
+///
+/// The octets are stored in big-endian order, i.e. in the order they are usually
+/// written in; on the wire they are sent in little-endian order.
+#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Address {
+    Short([u8; 2]),
+    Extended([u8; 8])
+}
+
+impl Address {
+    /// The broadcast address.
+    pub const BROADCAST: Address = Address::Short([0xff; 2]);
+
+    /// Construct an address from a sequence of octets, in big-endian, whose length
+    /// selects a short or an extended address.
+    ///
+    /// # Panics
+    /// The function panics if `data` is neither two nor eight octets long.
+    pub fn from_bytes(data: &[u8]) -> Address {
+        match data.len() {
+            2 => {
+                let mut bytes = [0; 2];
+                bytes.copy_from_slice(data);
+                Address::Short(bytes)
+            }
+            8 => {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(data);
+                Address::Extended(bytes)
+            }
+            _ => panic!("not an IEEE 802.15.4 address")
+        }
+    }
+
+    /// Return an address as a sequence of octets, in big-endian.
+    pub fn as_bytes(&self) -> &[u8] {
+        match *self {
+            Address::Short(ref bytes) => bytes,
+            Address::Extended(ref bytes) => bytes
+        }
+    }
+
+    /// Query whether this address is the broadcast address.
+    pub fn is_broadcast(&self) -> bool {
+        *self == Self::BROADCAST
+    }
+
+    /// Return the addressing mode of the address.
+    pub fn mode(&self) -> AddressingMode {
+        match *self {
+            Address::Short(_) => AddressingMode::Short,
+            Address::Extended(_) => AddressingMode::Extended
+        }
+    }
+
+    /// Return the IPv6 interface identifier derived from the address, as described in
+    /// RFC 4944 § 6 and RFC 6282 § 3.2.2.
+    ///
+    /// The identifier of an extended address is its modified EUI-64, and the one of
+    /// a short address is `0000:00ff:fe00:XXXX`, i.e. with the PAN identifier taken as 0.
+    pub fn as_interface_id(&self) -> [u8; 8] {
+        match *self {
+            Address::Short(bytes) =>
+                [0x00, 0x00, 0x00, 0xff, 0xfe, 0x00, bytes[0], bytes[1]],
+            Address::Extended(bytes) => {
+                let mut iid = bytes;
+                iid[0] ^= 0x02;
+                iid
+            }
+        }
+    }
+
+    /// Return the address an IPv6 interface identifier was derived from; this is
+    /// the inverse of [as_interface_id].
+    ///
+    /// [as_interface_id]: #method.as_interface_id
+    ///
+    /// # Panics
+    /// The function panics if `iid` is not eight octets long.
+    pub fn from_interface_id(iid: &[u8]) -> Address {
+        if iid[..6] == [0x00, 0x00, 0x00, 0xff, 0xfe, 0x00] {
+            Address::Short([iid[6], iid[7]])
+        } else {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(iid);
+            bytes[0] ^= 0x02;
+            Address::Extended(bytes)
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Address::Short(bytes) =>
+                write!(f, "0x{:02x}{:02x}", bytes[0], bytes[1]),
+            Address::Extended(bytes) =>
+                write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+                       bytes[0], bytes[1], bytes[2], bytes[3],
+                       bytes[4], bytes[5], bytes[6], bytes[7])
+        }
+    }
+}
+
+/// A read/write wrapper around an IEEE 802.15.4 MAC frame buffer, without
+/// the frame check sequence, which the radio computes and verifies.
+#[derive(Debug)]
+pub struct Frame<T: AsRef<[u8]>> {
+    buffer: T
+}
+
+// Format of the MAC header; the fields following the sequence number are only present
+// if the addressing modes call for them, and have variable lengths:
+//
+// +---------------+----------+---------+---------+---------+---------+
+// | Frame Control | Sequence | Dst PAN | Dst     | Src PAN | Src     |
+// | (2)           | (1)      | (0/2)   | (0/2/8) | (0/2)   | (0/2/8) |
+// +---------------+----------+---------+---------+---------+---------+
+//
+// Every multi-octet field is sent in little-endian order.
+mod field {
+    use wire::field::*;
+
+    pub const FRAME_CONTROL: Field = 0..2;
+    pub const SEQUENCE:      usize = 2;
+    pub const ADDRESSING:    Rest  = 3..;
+}
+
+// Bits of the frame control field.
+mod fc {
+    pub const TYPE_MASK:       u16 = 0b111;
+    pub const SECURITY:        u16 = 1 << 3;
+    pub const FRAME_PENDING:   u16 = 1 << 4;
+    pub const ACK_REQUEST:     u16 = 1 << 5;
+    pub const PAN_ID_COMPRESS: u16 = 1 << 6;
+    pub const DST_MODE_SHIFT:  u16 = 10;
+    pub const VERSION_SHIFT:   u16 = 12;
+    pub const SRC_MODE_SHIFT:  u16 = 14;
+}
+
+/// The frame version of IEEE 802.15.4-2006, which frames are sent with.
+const FRAME_VERSION_2006: u8 = 1;
+
+impl<T: AsRef<[u8]>> Frame<T> {
+    /// Imbue a raw octet buffer with IEEE 802.15.4 frame structure.
+    pub fn new(buffer: T) -> Frame<T> {
+        Frame { buffer }
+    }
+
+    /// Shorthand for a combination of [new] and [check_len].
+    ///
+    /// [new]: #method.new
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<Frame<T>> {
+        let frame = Self::new(buffer);
+        frame.check_len()?;
+        Ok(frame)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error::Truncated)` if the buffer is too short.
+    pub fn check_len(&self) -> Result<()> {
+        let len = self.buffer.as_ref().len();
+        if len < field::ADDRESSING.start || len < self.header_len() {
+            Err(Error::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Consume the frame, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    fn frame_control(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        LittleEndian::read_u16(&data[field::FRAME_CONTROL])
+    }
+
+    /// Return the frame type field.
+    #[inline]
+    pub fn frame_type(&self) -> FrameType {
+        FrameType::from((self.frame_control() & fc::TYPE_MASK) as u8)
+    }
+
+    /// Return the security enabled flag.
+    #[inline]
+    pub fn security_enabled(&self) -> bool {
+        self.frame_control() & fc::SECURITY != 0
+    }
+
+    /// Return the frame pending flag.
+    #[inline]
+    pub fn frame_pending(&self) -> bool {
+        self.frame_control() & fc::FRAME_PENDING != 0
+    }
+
+    /// Return the acknowledgement request flag.
+    #[inline]
+    pub fn ack_request(&self) -> bool {
+        self.frame_control() & fc::ACK_REQUEST != 0
+    }
+
+    /// Return the PAN ID compression flag, i.e. whether the source PAN identifier
+    /// is elided because it is the destination one.
+    #[inline]
+    pub fn pan_id_compression(&self) -> bool {
+        self.frame_control() & fc::PAN_ID_COMPRESS != 0
+    }
+
+    /// Return the destination addressing mode field.
+    #[inline]
+    pub fn dst_addressing_mode(&self) -> AddressingMode {
+        AddressingMode::from((self.frame_control() >> fc::DST_MODE_SHIFT) as u8 & 0b11)
+    }
+
+    /// Return the frame version field.
+    #[inline]
+    pub fn frame_version(&self) -> u8 {
+        (self.frame_control() >> fc::VERSION_SHIFT) as u8 & 0b11
+    }
+
+    /// Return the source addressing mode field.
+    #[inline]
+    pub fn src_addressing_mode(&self) -> AddressingMode {
+        AddressingMode::from((self.frame_control() >> fc::SRC_MODE_SHIFT) as u8 & 0b11)
+    }
+
+    /// Return the sequence number field.
+    #[inline]
+    pub fn sequence_number(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[field::SEQUENCE]
+    }
+
+    /// Query whether the source PAN identifier field is present.
+    fn has_src_pan_id(&self) -> bool {
+        self.src_addressing_mode() != AddressingMode::Absent &&
+            !(self.pan_id_compression() &&
+              self.dst_addressing_mode() != AddressingMode::Absent)
+    }
+
+    fn dst_pan_id_len(&self) -> usize {
+        if self.dst_addressing_mode() != AddressingMode::Absent { 2 } else { 0 }
+    }
+
+    fn src_pan_id_start(&self) -> usize {
+        field::ADDRESSING.start + self.dst_pan_id_len() + self.dst_addressing_mode().len()
+    }
+
+    fn src_addr_start(&self) -> usize {
+        self.src_pan_id_start() + if self.has_src_pan_id() { 2 } else { 0 }
+    }
+
+    /// Return the length of the MAC header, which depends on the addressing modes.
+    pub fn header_len(&self) -> usize {
+        self.src_addr_start() + self.src_addressing_mode().len()
+    }
+
+    /// Return the destination PAN identifier field, if present.
+    #[inline]
+    pub fn dst_pan_id(&self) -> Option<Pan> {
+        if self.dst_pan_id_len() == 0 { return None }
+        let data = self.buffer.as_ref();
+        Some(Pan(LittleEndian::read_u16(&data[field::ADDRESSING.start..])))
+    }
+
+    /// Return the source PAN identifier field, if present.
+    #[inline]
+    pub fn src_pan_id(&self) -> Option<Pan> {
+        if !self.has_src_pan_id() { return None }
+        let data = self.buffer.as_ref();
+        Some(Pan(LittleEndian::read_u16(&data[self.src_pan_id_start()..])))
+    }
+
+    fn read_addr(&self, start: usize, mode: AddressingMode) -> Option<Address> {
+        let data = self.buffer.as_ref();
+        let len = mode.len();
+        if len == 0 { return None }
+        let mut bytes = [0; 8];
+        for (byte, wire) in bytes[..len].iter_mut().zip(data[start..start + len].iter().rev()) {
+            *byte = *wire
+        }
+        Some(Address::from_bytes(&bytes[..len]))
+    }
+
+    /// Return the destination address field, if present.
+    #[inline]
+    pub fn dst_addr(&self) -> Option<Address> {
+        self.read_addr(field::ADDRESSING.start + self.dst_pan_id_len(),
+                       self.dst_addressing_mode())
+    }
+
+    /// Return the source address field, if present.
+    #[inline]
+    pub fn src_addr(&self) -> Option<Address> {
+        self.read_addr(self.src_addr_start(), self.src_addressing_mode())
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> Frame<&'a T> {
+    /// Return a pointer to the payload, following the MAC header.
+    #[inline]
+    pub fn payload(&self) -> &'a [u8] {
+        let data = self.buffer.as_ref();
+        &data[self.header_len()..]
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Frame<T> {
+    fn set_frame_control(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        LittleEndian::write_u16(&mut data[field::FRAME_CONTROL], value)
+    }
+
+    fn set_frame_control_bits(&mut self, mask: u16, value: u16) {
+        let raw = self.frame_control() & !mask;
+        self.set_frame_control(raw | (value & mask))
+    }
+
+    fn set_flag(&mut self, flag: u16, value: bool) {
+        self.set_frame_control_bits(flag, if value { flag } else { 0 })
+    }
+
+    /// Set the frame type field.
+    #[inline]
+    pub fn set_frame_type(&mut self, value: FrameType) {
+        self.set_frame_control_bits(fc::TYPE_MASK, u8::from(value) as u16)
+    }
+
+    /// Set the security enabled flag.
+    #[inline]
+    pub fn set_security_enabled(&mut self, value: bool) {
+        self.set_flag(fc::SECURITY, value)
+    }
+
+    /// Set the frame pending flag.
+    #[inline]
+    pub fn set_frame_pending(&mut self, value: bool) {
+        self.set_flag(fc::FRAME_PENDING, value)
+    }
+
+    /// Set the acknowledgement request flag.
+    #[inline]
+    pub fn set_ack_request(&mut self, value: bool) {
+        self.set_flag(fc::ACK_REQUEST, value)
+    }
+
+    /// Set the PAN ID compression flag.
+    #[inline]
+    pub fn set_pan_id_compression(&mut self, value: bool) {
+        self.set_flag(fc::PAN_ID_COMPRESS, value)
+    }
+
+    /// Set the destination addressing mode field.
+    #[inline]
+    pub fn set_dst_addressing_mode(&mut self, value: AddressingMode) {
+        self.set_frame_control_bits(0b11 << fc::DST_MODE_SHIFT,
+                                    (u8::from(value) as u16) << fc::DST_MODE_SHIFT)
+    }
+
+    /// Set the frame version field.
+    #[inline]
+    pub fn set_frame_version(&mut self, value: u8) {
+        self.set_frame_control_bits(0b11 << fc::VERSION_SHIFT,
+                                    (value as u16) << fc::VERSION_SHIFT)
+    }
+
+    /// Set the source addressing mode field.
+    #[inline]
+    pub fn set_src_addressing_mode(&mut self, value: AddressingMode) {
+        self.set_frame_control_bits(0b11 << fc::SRC_MODE_SHIFT,
+                                    (u8::from(value) as u16) << fc::SRC_MODE_SHIFT)
+    }
+
+    /// Set the sequence number field.
+    #[inline]
+    pub fn set_sequence_number(&mut self, value: u8) {
+        let data = self.buffer.as_mut();
+        data[field::SEQUENCE] = value
+    }
+
+    /// Set the destination PAN identifier field.
+    ///
+    /// The destination addressing mode must be set beforehand.
+    #[inline]
+    pub fn set_dst_pan_id(&mut self, value: Pan) {
+        let data = self.buffer.as_mut();
+        LittleEndian::write_u16(&mut data[field::ADDRESSING.start..], value.0)
+    }
+
+    /// Set the source PAN identifier field.
+    ///
+    /// The addressing modes and the PAN ID compression flag must be set beforehand.
+    #[inline]
+    pub fn set_src_pan_id(&mut self, value: Pan) {
+        let start = self.src_pan_id_start();
+        let data = self.buffer.as_mut();
+        LittleEndian::write_u16(&mut data[start..], value.0)
+    }
+
+    fn write_addr(&mut self, start: usize, value: Address) {
+        let data = self.buffer.as_mut();
+        let bytes = value.as_bytes();
+        for (wire, byte) in data[start..start + bytes.len()].iter_mut().zip(bytes.iter().rev()) {
+            *wire = *byte
+        }
+    }
+
+    /// Set the destination address field.
+    ///
+    /// The destination addressing mode must be set beforehand, and match the address.
+    #[inline]
+    pub fn set_dst_addr(&mut self, value: Address) {
+        let start = field::ADDRESSING.start + self.dst_pan_id_len();
+        self.write_addr(start, value)
+    }
+
+    /// Set the source address field.
+    ///
+    /// The addressing modes and the PAN ID compression flag must be set beforehand,
+    /// and the source addressing mode must match the address.
+    #[inline]
+    pub fn set_src_addr(&mut self, value: Address) {
+        let start = self.src_addr_start();
+        self.write_addr(start, value)
+    }
+
+    /// Return a mutable pointer to the payload, following the MAC header.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let header_len = self.header_len();
+        let data = self.buffer.as_mut();
+        &mut data[header_len..]
+    }
+}
+
+impl<T: AsRef<[u8]>> fmt::Display for Frame<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "IEEE802.15.4 type={} seq={}", self.frame_type(), self.sequence_number())?;
+        if let Some(pan_id) = self.dst_pan_id() {
+            write!(f, " dst_pan={}", pan_id)?;
+        }
+        if let Some(addr) = self.dst_addr() {
+            write!(f, " dst={}", addr)?;
+        }
+        if let Some(pan_id) = self.src_pan_id() {
+            write!(f, " src_pan={}", pan_id)?;
+        }
+        if let Some(addr) = self.src_addr() {
+            write!(f, " src={}", addr)?;
+        }
+        if self.security_enabled() {
+            write!(f, " secured")?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: AsRef<[u8]>> PrettyPrint for Frame<T> {
+    fn pretty_print(buffer: &AsRef<[u8]>, f: &mut fmt::Formatter,
+                    indent: &mut PrettyIndent) -> fmt::Result {
+        match Frame::new_checked(buffer) {
+            Err(err)  => write!(f, "{}({})", indent, err),
+            Ok(frame) => write!(f, "{}{}", indent, frame)
+        }
+    }
+}
+
+/// A high-level representation of an IEEE 802.15.4 MAC header.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Repr {
+    pub frame_type:         FrameType,
+    pub frame_pending:      bool,
+    pub ack_request:        bool,
+    pub sequence_number:    u8,
+    /// Whether the source PAN identifier is elided, because it is the destination one.
+    pub pan_id_compression: bool,
+    pub dst_pan_id:         Option<Pan>,
+    pub dst_addr:           Option<Address>,
+    /// The source PAN identifier; if it is elided by PAN ID compression, it is
+    /// the destination one when parsed, and ignored when emitted.
+    pub src_pan_id:         Option<Pan>,
+    pub src_addr:           Option<Address>,
+}
+
+impl Repr {
+    /// Parse an IEEE 802.15.4 MAC header and return a high-level representation.
+    ///
+    /// Frames with security enabled, which this implementation cannot authenticate,
+    /// are rejected with `Err(Error::Unrecognized)`.
+    pub fn parse<T: AsRef<[u8]> + ?Sized>(frame: &Frame<&T>) -> Result<Repr> {
+        frame.check_len()?;
+        if let AddressingMode::Unknown(_) = frame.dst_addressing_mode() {
+            return Err(Error::Malformed)
+        }
+        if let AddressingMode::Unknown(_) = frame.src_addressing_mode() {
+            return Err(Error::Malformed)
+        }
+        if frame.security_enabled() { return Err(Error::Unrecognized) }
+
+        let src_pan_id = match frame.src_pan_id() {
+            None if frame.src_addressing_mode() != AddressingMode::Absent =>
+                frame.dst_pan_id(),
+            src_pan_id => src_pan_id
+        };
+        Ok(Repr {
+            frame_type:         frame.frame_type(),
+            frame_pending:      frame.frame_pending(),
+            ack_request:        frame.ack_request(),
+            sequence_number:    frame.sequence_number(),
+            pan_id_compression: frame.pan_id_compression(),
+            dst_pan_id:         frame.dst_pan_id(),
+            dst_addr:           frame.dst_addr(),
+            src_pan_id:         src_pan_id,
+            src_addr:           frame.src_addr(),
+        })
+    }
+
+    fn dst_addressing_mode(&self) -> AddressingMode {
+        self.dst_addr.map_or(AddressingMode::Absent, |addr| addr.mode())
+    }
+
+    fn src_addressing_mode(&self) -> AddressingMode {
+        self.src_addr.map_or(AddressingMode::Absent, |addr| addr.mode())
+    }
+
+    /// Return the length of a header that will be emitted from this high-level representation.
+    pub fn buffer_len(&self) -> usize {
+        let mut len = field::ADDRESSING.start;
+        if self.dst_addr.is_some() {
+            len += 2 + self.dst_addressing_mode().len();
+        }
+        if self.src_addr.is_some() {
+            if !(self.pan_id_compression && self.dst_addr.is_some()) { len += 2 }
+            len += self.src_addressing_mode().len();
+        }
+        len
+    }
+
+    /// Emit a high-level representation into an IEEE 802.15.4 MAC header.
+    pub fn emit<T: AsRef<[u8]> + AsMut<[u8]>>(&self, frame: &mut Frame<T>) {
+        frame.set_frame_control(0);
+        frame.set_frame_type(self.frame_type);
+        frame.set_frame_pending(self.frame_pending);
+        frame.set_ack_request(self.ack_request);
+        frame.set_pan_id_compression(self.pan_id_compression);
+        frame.set_dst_addressing_mode(self.dst_addressing_mode());
+        frame.set_frame_version(FRAME_VERSION_2006);
+        frame.set_src_addressing_mode(self.src_addressing_mode());
+        frame.set_sequence_number(self.sequence_number);
+        if let Some(dst_addr) = self.dst_addr {
+            frame.set_dst_pan_id(self.dst_pan_id.unwrap_or(Pan::BROADCAST));
+            frame.set_dst_addr(dst_addr);
+        }
+        if let Some(src_addr) = self.src_addr {
+            if frame.has_src_pan_id() {
+                frame.set_src_pan_id(self.src_pan_id.unwrap_or(Pan::BROADCAST));
+            }
+            frame.set_src_addr(src_addr);
+        }
+    }
+}
+
+impl fmt::Display for Repr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "IEEE802.15.4 type={} seq={}", self.frame_type, self.sequence_number)?;
+        if let Some(addr) = self.dst_addr {
+            write!(f, " dst={}", addr)?;
+        }
+        if let Some(addr) = self.src_addr {
+            write!(f, " src={}", addr)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // A data frame from PAN 0xabcd, from the extended address 00:12:4b:00:14:b5:d9:c7
+    // to the short address 0x0001, with an acknowledgement requested and PAN ID compression.
+    static FRAME_BYTES: [u8; 17] =
+        [0x61, 0xc8, 0x2a,
+         0xcd, 0xab,
+         0x01, 0x00,
+         0xc7, 0xd9, 0xb5, 0x14, 0x00, 0x4b, 0x12, 0x00,
+         0xaa, 0xbb];
+
+    static PAYLOAD_BYTES: [u8; 2] = [0xaa, 0xbb];
+
+    const SRC_ADDR: Address = Address::Extended([0x00, 0x12, 0x4b, 0x00, 0x14, 0xb5, 0xd9, 0xc7]);
+    const DST_ADDR: Address = Address::Short([0x00, 0x01]);
+
+    fn frame_repr() -> Repr {
+        Repr {
+            frame_type:         FrameType::Data,
+            frame_pending:      false,
+            ack_request:        true,
+            sequence_number:    42,
+            pan_id_compression: true,
+            dst_pan_id:         Some(Pan(0xabcd)),
+            dst_addr:           Some(DST_ADDR),
+            src_pan_id:         Some(Pan(0xabcd)),
+            src_addr:           Some(SRC_ADDR),
+        }
+    }
+
+    #[test]
+    fn test_deconstruct() {
+        let frame = Frame::new(&FRAME_BYTES[..]);
+        assert_eq!(frame.frame_type(), FrameType::Data);
+        assert_eq!(frame.security_enabled(), false);
+        assert_eq!(frame.frame_pending(), false);
+        assert_eq!(frame.ack_request(), true);
+        assert_eq!(frame.pan_id_compression(), true);
+        assert_eq!(frame.dst_addressing_mode(), AddressingMode::Short);
+        assert_eq!(frame.frame_version(), 0);
+        assert_eq!(frame.src_addressing_mode(), AddressingMode::Extended);
+        assert_eq!(frame.sequence_number(), 42);
+        assert_eq!(frame.dst_pan_id(), Some(Pan(0xabcd)));
+        assert_eq!(frame.dst_addr(), Some(DST_ADDR));
+        assert_eq!(frame.src_pan_id(), None);
+        assert_eq!(frame.src_addr(), Some(SRC_ADDR));
+        assert_eq!(frame.header_len(), 15);
+        assert_eq!(frame.payload(), &PAYLOAD_BYTES[..]);
+    }
+
+    #[test]
+    fn test_check_len() {
+        assert_eq!(Frame::new(&FRAME_BYTES[..2]).check_len(), Err(Error::Truncated));
+        assert_eq!(Frame::new(&FRAME_BYTES[..14]).check_len(), Err(Error::Truncated));
+        assert_eq!(Frame::new(&FRAME_BYTES[..15]).check_len(), Ok(()));
+    }
+
+    #[test]
+    fn test_parse() {
+        let frame = Frame::new(&FRAME_BYTES[..]);
+        assert_eq!(Repr::parse(&frame), Ok(frame_repr()));
+    }
+
+    #[test]
+    fn test_parse_secured() {
+        let mut bytes = FRAME_BYTES;
+        bytes[0] |= 0x08;
+        assert_eq!(Repr::parse(&Frame::new(&bytes[..])), Err(Error::Unrecognized));
+    }
+
+    #[test]
+    fn test_emit() {
+        let repr = frame_repr();
+        let mut bytes = vec![0xa5; repr.buffer_len() + PAYLOAD_BYTES.len()];
+        {
+            let mut frame = Frame::new(&mut bytes);
+            repr.emit(&mut frame);
+            frame.payload_mut().copy_from_slice(&PAYLOAD_BYTES[..]);
+        }
+        // Frames are sent with the 2006 frame version.
+        let mut expected = FRAME_BYTES;
+        expected[1] |= 0x10;
+        assert_eq!(&bytes[..], &expected[..]);
+    }
+
+    #[test]
+    fn test_emit_uncompressed_pan_id() {
+        let repr = Repr {
+            pan_id_compression: false,
+            src_pan_id:         Some(Pan(0x1234)),
+            dst_addr:           Some(Address::BROADCAST),
+            ..frame_repr()
+        };
+        let mut bytes = vec![0; repr.buffer_len()];
+        repr.emit(&mut Frame::new(&mut bytes));
+        assert_eq!(bytes.len(), 17);
+        assert_eq!(Repr::parse(&Frame::new(&bytes[..])), Ok(repr));
+    }
+
+    #[test]
+    fn test_interface_id() {
+        assert_eq!(SRC_ADDR.as_interface_id(),
+                   [0x02, 0x12, 0x4b, 0x00, 0x14, 0xb5, 0xd9, 0xc7]);
+        assert_eq!(Address::from_interface_id(&SRC_ADDR.as_interface_id()), SRC_ADDR);
+        assert_eq!(DST_ADDR.as_interface_id(),
+                   [0x00, 0x00, 0x00, 0xff, 0xfe, 0x00, 0x00, 0x01]);
+        assert_eq!(Address::from_interface_id(&DST_ADDR.as_interface_id()), DST_ADDR);
+    }
+}
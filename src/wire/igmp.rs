@@ -0,0 +1,432 @@
+use core::fmt;
+use byteorder::{ByteOrder, NetworkEndian};
+
+use {Error, Result};
+#[cfg(feature = "arbitrary")]
+use arbitrary::{self, Arbitrary, Unstructured};
+use phy::ChecksumCapabilities;
+use super::ip::checksum;
+use super::Ipv4Address;
+
+enum_with_unknown! {
+    /// Internet Group Management Protocol message type.
+    pub doc enum Message(u8) {
+        /// Membership query
+        MembershipQuery    = 0x11,
+        /// Version 1 membership report
+        MembershipReportV1 = 0x12,
+        /// Version 2 membership report
+        MembershipReportV2 = 0x16,
+        /// Leave group
+        LeaveGroup         = 0x17,
+        /// Version 3 membership report
+        MembershipReportV3 = 0x22
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Message::MembershipQuery    => write!(f, "membership query"),
+            &Message::MembershipReportV1 => write!(f, "version 1 membership report"),
+            &Message::MembershipReportV2 => write!(f, "version 2 membership report"),
+            &Message::LeaveGroup         => write!(f, "leave group"),
+            &Message::MembershipReportV3 => write!(f, "version 3 membership report"),
+            &Message::Unknown(id)        => write!(f, "{}", id)
+        }
+    }
+}
+
+/// The version of the Internet Group Management Protocol a message belongs to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Version {
+    /// IGMPv1, see RFC 1112.
+    Version1,
+    /// IGMPv2, see RFC 2236.
+    Version2,
+}
+
+/// A read/write wrapper around an Internet Group Management Protocol packet buffer.
+#[derive(Debug, PartialEq)]
+pub struct Packet<T: AsRef<[u8]>> {
+    buffer: T
+}
+
+mod field {
+    use wire::field::*;
+
+    pub const TYPE:          usize = 0;
+    pub const MAX_RESP_CODE: usize = 1;
+    pub const CHECKSUM:      Field = 2..4;
+    pub const GROUP_ADDRESS: Field = 4..8;
+}
+
+impl<T: AsRef<[u8]>> Packet<T> {
+    /// Imbue a raw octet buffer with IGMP packet structure.
+    pub fn new(buffer: T) -> Packet<T> {
+        Packet { buffer }
+    }
+
+    /// Shorthand for a combination of [new] and [check_len].
+    ///
+    /// [new]: #method.new
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<Packet<T>> {
+        let packet = Self::new(buffer);
+        packet.check_len()?;
+        Ok(packet)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error::Truncated)` if the buffer is too short.
+    pub fn check_len(&self) -> Result<()> {
+        let len = self.buffer.as_ref().len();
+        if len < field::GROUP_ADDRESS.end {
+            Err(Error::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Consume the packet, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Return the message type field.
+    #[inline]
+    pub fn msg_type(&self) -> Message {
+        let data = self.buffer.as_ref();
+        Message::from(data[field::TYPE])
+    }
+
+    /// Return the maximum response code field, in tenths of a second.
+    #[inline]
+    pub fn max_resp_code(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[field::MAX_RESP_CODE]
+    }
+
+    /// Return the checksum field.
+    #[inline]
+    pub fn checksum(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::CHECKSUM])
+    }
+
+    /// Return the group address field.
+    #[inline]
+    pub fn group_addr(&self) -> Ipv4Address {
+        let data = self.buffer.as_ref();
+        Ipv4Address::from_bytes(&data[field::GROUP_ADDRESS])
+    }
+
+    /// Validate the checksum.
+    ///
+    /// # Fuzzing
+    /// This function always returns `true` when fuzzing.
+    pub fn verify_checksum(&self) -> bool {
+        if cfg!(fuzzing) { return true }
+
+        let data = self.buffer.as_ref();
+        checksum::data(data) == !0
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
+    /// Set the message type field.
+    #[inline]
+    pub fn set_msg_type(&mut self, value: Message) {
+        let data = self.buffer.as_mut();
+        data[field::TYPE] = value.into()
+    }
+
+    /// Set the maximum response code field.
+    #[inline]
+    pub fn set_max_resp_code(&mut self, value: u8) {
+        let data = self.buffer.as_mut();
+        data[field::MAX_RESP_CODE] = value
+    }
+
+    /// Set the checksum field.
+    #[inline]
+    pub fn set_checksum(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::CHECKSUM], value)
+    }
+
+    /// Set the group address field.
+    #[inline]
+    pub fn set_group_addr(&mut self, value: Ipv4Address) {
+        let data = self.buffer.as_mut();
+        data[field::GROUP_ADDRESS].copy_from_slice(value.as_bytes())
+    }
+
+    /// Compute and fill in the checksum.
+    pub fn fill_checksum(&mut self) {
+        self.set_checksum(0);
+        let checksum = {
+            let data = self.buffer.as_ref();
+            !checksum::data(data)
+        };
+        self.set_checksum(checksum)
+    }
+}
+
+/// A high-level representation of an Internet Group Management Protocol packet.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Repr {
+    /// A query for the members of a group, or of every group if the group address
+    /// is unspecified.
+    MembershipQuery {
+        /// The time the members may delay their reports by, in milliseconds.
+        max_resp_time: u64,
+        group_addr:    Ipv4Address,
+        version:       Version
+    },
+    MembershipReport {
+        group_addr: Ipv4Address,
+        version:    Version
+    },
+    LeaveGroup {
+        group_addr: Ipv4Address
+    }
+}
+
+impl Repr {
+    /// Parse an Internet Group Management Protocol packet and return
+    /// a high-level representation.
+    ///
+    /// IGMPv3 queries are parsed as IGMPv2 queries, which is how hosts that only
+    /// implement IGMPv2 treat them; IGMPv3 reports are not recognized.
+    pub fn parse<T>(packet: &Packet<&T>, checksum_caps: &ChecksumCapabilities) -> Result<Repr>
+                where T: AsRef<[u8]> + ?Sized {
+        packet.check_len()?;
+
+        // Valid checksum is expected.
+        if checksum_caps.igmp.rx() && !packet.verify_checksum() { return Err(Error::Checksum) }
+
+        match packet.msg_type() {
+            Message::MembershipQuery => {
+                // Queries from IGMPv1 routers have no maximum response time;
+                // it is ten seconds. See RFC 2236 § 4.
+                let (max_resp_time, version) = match packet.max_resp_code() {
+                    0 => (10_000, Version::Version1),
+                    code => (max_resp_time(code), Version::Version2)
+                };
+                Ok(Repr::MembershipQuery {
+                    max_resp_time, version,
+                    group_addr: packet.group_addr()
+                })
+            }
+            Message::MembershipReportV1 =>
+                Ok(Repr::MembershipReport {
+                    group_addr: packet.group_addr(),
+                    version:    Version::Version1
+                }),
+            Message::MembershipReportV2 =>
+                Ok(Repr::MembershipReport {
+                    group_addr: packet.group_addr(),
+                    version:    Version::Version2
+                }),
+            Message::LeaveGroup =>
+                Ok(Repr::LeaveGroup { group_addr: packet.group_addr() }),
+            _ => Err(Error::Unrecognized)
+        }
+    }
+
+    /// Return the length of a packet that will be emitted from this high-level representation.
+    pub fn buffer_len(&self) -> usize {
+        field::GROUP_ADDRESS.end
+    }
+
+    /// Emit a high-level representation into an Internet Group Management Protocol packet.
+    pub fn emit<T>(&self, packet: &mut Packet<&mut T>, checksum_caps: &ChecksumCapabilities)
+            where T: AsRef<[u8]> + AsMut<[u8]> + ?Sized {
+        match self {
+            &Repr::MembershipQuery { max_resp_time, group_addr, version } => {
+                packet.set_msg_type(Message::MembershipQuery);
+                match version {
+                    Version::Version1 => packet.set_max_resp_code(0),
+                    Version::Version2 => {
+                        let code = (max_resp_time / 100).max(1).min(0xff);
+                        packet.set_max_resp_code(code as u8)
+                    }
+                }
+                packet.set_group_addr(group_addr)
+            }
+            &Repr::MembershipReport { group_addr, version } => {
+                match version {
+                    Version::Version1 => packet.set_msg_type(Message::MembershipReportV1),
+                    Version::Version2 => packet.set_msg_type(Message::MembershipReportV2)
+                }
+                packet.set_max_resp_code(0);
+                packet.set_group_addr(group_addr)
+            }
+            &Repr::LeaveGroup { group_addr } => {
+                packet.set_msg_type(Message::LeaveGroup);
+                packet.set_max_resp_code(0);
+                packet.set_group_addr(group_addr)
+            }
+        }
+
+        if checksum_caps.igmp.tx() {
+            packet.fill_checksum()
+        } else {
+            // make sure we get a consistently zeroed checksum,
+            // since implementations might rely on it
+            packet.set_checksum(0);
+        }
+    }
+}
+
+/// Decode a maximum response code into milliseconds. Codes of 128 and above are
+/// only sent by IGMPv3 routers, and encode a larger time in floating point.
+/// See RFC 3376 § 4.1.1.
+fn max_resp_time(code: u8) -> u64 {
+    let tenths = if code < 128 {
+        code as u64
+    } else {
+        let mantissa = (code & 0x0f) as u64;
+        let exponent = ((code >> 4) & 0x07) as u64;
+        (mantissa | 0x10) << (exponent + 3)
+    };
+    tenths * 100
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Repr {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Repr> {
+        let version = if u.arbitrary()? { Version::Version1 } else { Version::Version2 };
+        match u.int_in_range(0..=2)? {
+            0 => {
+                let max_resp_time = match version {
+                    Version::Version1 => 10_000,
+                    Version::Version2 => u.int_in_range(1..=127)? * 100
+                };
+                Ok(Repr::MembershipQuery { max_resp_time, version, group_addr: u.arbitrary()? })
+            }
+            1 => Ok(Repr::MembershipReport { version, group_addr: u.arbitrary()? }),
+            _ => Ok(Repr::LeaveGroup { group_addr: u.arbitrary()? })
+        }
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> fmt::Display for Packet<&'a T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match Repr::parse(self, &ChecksumCapabilities::default()) {
+            Ok(repr) => write!(f, "{}", repr),
+            Err(err) => write!(f, "IGMP ({}) type={}", err, self.msg_type())
+        }
+    }
+}
+
+impl fmt::Display for Repr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Repr::MembershipQuery { max_resp_time, group_addr, version } =>
+                write!(f, "IGMP membership query group={} max_resp_time={}ms version={:?}",
+                       group_addr, max_resp_time, version),
+            &Repr::MembershipReport { group_addr, version } =>
+                write!(f, "IGMP membership report group={} version={:?}",
+                       group_addr, version),
+            &Repr::LeaveGroup { group_addr } =>
+                write!(f, "IGMP leave group group={}", group_addr)
+        }
+    }
+}
+
+use super::pretty_print::{PrettyPrint, PrettyIndent};
+
+impl<T: AsRef<[u8]>> PrettyPrint for Packet<T> {
+    fn pretty_print(buffer: &AsRef<[u8]>, f: &mut fmt::Formatter,
+                    indent: &mut PrettyIndent) -> fmt::Result {
+        match Packet::new_checked(buffer) {
+            Err(err)   => write!(f, "{}({})", indent, err),
+            Ok(packet) => write!(f, "{}{}", indent, packet)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static LEAVE_PACKET_BYTES: [u8; 8] =
+        [0x17, 0x00, 0x02, 0x69,
+         0xe0, 0x00, 0x06, 0x96];
+
+    static REPORT_PACKET_BYTES: [u8; 8] =
+        [0x16, 0x00, 0x08, 0xda,
+         0xe1, 0x00, 0x00, 0x25];
+
+    static QUERY_PACKET_BYTES: [u8; 8] =
+        [0x11, 0x64, 0xee, 0x9b,
+         0x00, 0x00, 0x00, 0x00];
+
+    #[test]
+    fn test_leave_group_deconstruct() {
+        let packet = Packet::new(&LEAVE_PACKET_BYTES[..]);
+        assert_eq!(packet.msg_type(), Message::LeaveGroup);
+        assert_eq!(packet.max_resp_code(), 0);
+        assert_eq!(packet.checksum(), 0x0269);
+        assert_eq!(packet.group_addr(), Ipv4Address::new(224, 0, 6, 150));
+        assert_eq!(packet.verify_checksum(), true);
+    }
+
+    #[test]
+    fn test_report_construct() {
+        let mut bytes = vec![0xa5; 8];
+        let mut packet = Packet::new(&mut bytes);
+        packet.set_msg_type(Message::MembershipReportV2);
+        packet.set_max_resp_code(0);
+        packet.set_group_addr(Ipv4Address::new(225, 0, 0, 37));
+        packet.fill_checksum();
+        assert_eq!(&packet.into_inner()[..], &REPORT_PACKET_BYTES[..]);
+    }
+
+    #[test]
+    fn test_query_parse() {
+        let packet = Packet::new(&QUERY_PACKET_BYTES[..]);
+        let repr = Repr::parse(&packet, &ChecksumCapabilities::default()).unwrap();
+        assert_eq!(repr, Repr::MembershipQuery {
+            max_resp_time: 10_000,
+            group_addr:    Ipv4Address::UNSPECIFIED,
+            version:       Version::Version2
+        });
+    }
+
+    #[test]
+    fn test_v1_query_parse() {
+        let mut bytes = QUERY_PACKET_BYTES;
+        {
+            let mut packet = Packet::new(&mut bytes[..]);
+            packet.set_max_resp_code(0);
+            packet.fill_checksum();
+        }
+        let packet = Packet::new(&bytes[..]);
+        match Repr::parse(&packet, &ChecksumCapabilities::default()) {
+            Ok(Repr::MembershipQuery { max_resp_time: 10_000, version: Version::Version1, .. }) => (),
+            repr => panic!("unexpected {:?}", repr)
+        }
+    }
+
+    #[test]
+    fn test_report_emit() {
+        let repr = Repr::MembershipReport {
+            group_addr: Ipv4Address::new(225, 0, 0, 37),
+            version:    Version::Version2
+        };
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        let mut packet = Packet::new(&mut bytes);
+        repr.emit(&mut packet, &ChecksumCapabilities::default());
+        assert_eq!(&packet.into_inner()[..], &REPORT_PACKET_BYTES[..]);
+    }
+
+    #[test]
+    fn test_max_resp_time() {
+        assert_eq!(max_resp_time(100), 10_000);
+        assert_eq!(max_resp_time(0x80), 12_800);
+        assert_eq!(max_resp_time(0xff), 3_174_400);
+    }
+}
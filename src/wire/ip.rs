@@ -2,6 +2,8 @@ use core::fmt;
 use core::convert::From;
 
 use {Error, Result};
+#[cfg(feature = "arbitrary")]
+use arbitrary::{self, Arbitrary, Unstructured};
 use phy::ChecksumCapabilities;
 #[cfg(feature = "proto-ipv4")]
 use super::{Ipv4Address, Ipv4Packet, Ipv4Repr, Ipv4Cidr};
@@ -54,6 +56,7 @@ enum_with_unknown! {
     pub enum Protocol(u8) {
         HopByHop  = 0x00,
         Icmp      = 0x01,
+        Igmp      = 0x02,
         Tcp       = 0x06,
         Udp       = 0x11,
         Ipv6Route = 0x2b,
@@ -69,6 +72,7 @@ impl fmt::Display for Protocol {
         match self {
             &Protocol::HopByHop    => write!(f, "Hop-by-Hop"),
             &Protocol::Icmp        => write!(f, "ICMP"),
+            &Protocol::Igmp        => write!(f, "IGMP"),
             &Protocol::Tcp         => write!(f, "TCP"),
             &Protocol::Udp         => write!(f, "UDP"),
             &Protocol::Ipv6Route   => write!(f, "IPv6-Route"),
@@ -123,6 +127,18 @@ impl Address {
         }
     }
 
+    /// Query whether the address is a multicast address.
+    pub fn is_multicast(&self) -> bool {
+        match self {
+            &Address::Unspecified     => false,
+            #[cfg(feature = "proto-ipv4")]
+            &Address::Ipv4(addr)      => addr.is_multicast(),
+            #[cfg(feature = "proto-ipv6")]
+            &Address::Ipv6(addr)      => addr.is_multicast(),
+            &Address::__Nonexhaustive => unreachable!()
+        }
+    }
+
     /// Query whether the address is the broadcast address.
     pub fn is_broadcast(&self) -> bool {
         match self {
@@ -135,6 +151,18 @@ impl Address {
         }
     }
 
+    /// Query whether the address is a loopback address.
+    pub fn is_loopback(&self) -> bool {
+        match self {
+            &Address::Unspecified     => false,
+            #[cfg(feature = "proto-ipv4")]
+            &Address::Ipv4(addr)      => addr.is_loopback(),
+            #[cfg(feature = "proto-ipv6")]
+            &Address::Ipv6(addr)      => addr.is_loopback(),
+            &Address::__Nonexhaustive => unreachable!()
+        }
+    }
+
     /// Query whether the address falls into the "unspecified" range.
     pub fn is_unspecified(&self) -> bool {
         match self {
@@ -147,6 +175,18 @@ impl Address {
         }
     }
 
+    /// Query whether the address falls into the "link-local" range.
+    pub fn is_link_local(&self) -> bool {
+        match self {
+            &Address::Unspecified     => false,
+            #[cfg(feature = "proto-ipv4")]
+            &Address::Ipv4(addr)      => addr.is_link_local(),
+            #[cfg(feature = "proto-ipv6")]
+            &Address::Ipv6(addr)      => addr.is_link_local(),
+            &Address::__Nonexhaustive => unreachable!()
+        }
+    }
+
     /// Return an unspecified address that has the same IP version as `self`.
     pub fn to_unspecified(&self) -> Address {
         match self {
@@ -193,6 +233,19 @@ impl fmt::Display for Address {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Address {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Address> {
+        match u.int_in_range(0..=2)? {
+            #[cfg(feature = "proto-ipv4")]
+            1 => Ok(Address::Ipv4(u.arbitrary()?)),
+            #[cfg(feature = "proto-ipv6")]
+            2 => Ok(Address::Ipv6(u.arbitrary()?)),
+            _ => Ok(Address::Unspecified)
+        }
+    }
+}
+
 /// A specification of a CIDR block, containing an address and a variable-length
 /// subnet masking prefix length.
 #[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
@@ -326,8 +379,14 @@ impl Endpoint {
 }
 
 impl fmt::Display for Endpoint {
+    /// Format the endpoint as its address and port separated by a colon, with an IPv6
+    /// address in brackets, so that the port cannot be taken for a part of it.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}:{}", self.addr, self.port)
+        match self.addr {
+            #[cfg(feature = "proto-ipv6")]
+            Address::Ipv6(addr) => write!(f, "[{}]:{}", addr, self.port),
+            _ => write!(f, "{}:{}", self.addr, self.port)
+        }
     }
 }
 
@@ -343,6 +402,13 @@ impl<T: Into<Address>> From<(T, u16)> for Endpoint {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Endpoint {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Endpoint> {
+        Ok(Endpoint { addr: u.arbitrary()?, port: u.arbitrary()? })
+    }
+}
+
 /// An IP packet representation.
 ///
 /// This enum abstracts the various versions of IP packets. It either contains a concrete
@@ -355,7 +421,12 @@ pub enum Repr {
         dst_addr:    Address,
         protocol:    Protocol,
         payload_len: usize,
-        hop_limit:   u8
+        hop_limit:   u8,
+        ecn:         u8,
+        dscp:        u8,
+        /// The flow label of an IPv6 packet, or 0 to let the interface assign one.
+        /// It is ignored for IPv4.
+        flow_label:  u32
     },
     #[cfg(feature = "proto-ipv4")]
     Ipv4(Ipv4Repr),
@@ -379,6 +450,28 @@ impl From<Ipv6Repr> for Repr {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Repr {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Repr> {
+        match u.int_in_range(0..=2)? {
+            #[cfg(feature = "proto-ipv4")]
+            1 => Ok(Repr::Ipv4(u.arbitrary()?)),
+            #[cfg(feature = "proto-ipv6")]
+            2 => Ok(Repr::Ipv6(u.arbitrary()?)),
+            _ => Ok(Repr::Unspecified {
+                src_addr:    u.arbitrary()?,
+                dst_addr:    u.arbitrary()?,
+                protocol:    u.arbitrary()?,
+                payload_len: u.int_in_range(0..=0xffff)?,
+                hop_limit:   u.arbitrary()?,
+                ecn:         u.int_in_range(0..=0x03)?,
+                dscp:        u.int_in_range(0..=0x3f)?,
+                flow_label:  u.int_in_range(0..=0xfffff)?
+            })
+        }
+    }
+}
+
 impl Repr {
     /// Return the protocol version.
     pub fn version(&self) -> Version {
@@ -467,6 +560,72 @@ impl Repr {
         }
     }
 
+    /// Return the Explicit Congestion Notification codepoint.
+    pub fn ecn(&self) -> u8 {
+        match self {
+            &Repr::Unspecified { ecn, .. }    => ecn,
+            #[cfg(feature = "proto-ipv4")]
+            &Repr::Ipv4(Ipv4Repr { ecn, .. }) => ecn,
+            #[cfg(feature = "proto-ipv6")]
+            &Repr::Ipv6(Ipv6Repr { ecn, .. }) => ecn,
+            &Repr::__Nonexhaustive => unreachable!()
+        }
+    }
+
+    /// Set the Explicit Congestion Notification codepoint.
+    pub fn set_ecn(&mut self, value: u8) {
+        match self {
+            &mut Repr::Unspecified { ref mut ecn, .. } =>
+                *ecn = value,
+            #[cfg(feature = "proto-ipv4")]
+            &mut Repr::Ipv4(Ipv4Repr { ref mut ecn, .. }) =>
+                *ecn = value,
+            #[cfg(feature = "proto-ipv6")]
+            &mut Repr::Ipv6(Ipv6Repr { ref mut ecn, .. }) =>
+                *ecn = value,
+            &mut Repr::__Nonexhaustive => unreachable!()
+        }
+    }
+
+    /// Return the Differentiated Services Code Point.
+    pub fn dscp(&self) -> u8 {
+        match self {
+            &Repr::Unspecified { dscp, .. }    => dscp,
+            #[cfg(feature = "proto-ipv4")]
+            &Repr::Ipv4(Ipv4Repr { dscp, .. }) => dscp,
+            #[cfg(feature = "proto-ipv6")]
+            &Repr::Ipv6(Ipv6Repr { dscp, .. }) => dscp,
+            &Repr::__Nonexhaustive => unreachable!()
+        }
+    }
+
+    /// Return the IPv6 flow label, which is always 0 for IPv4.
+    pub fn flow_label(&self) -> u32 {
+        match self {
+            &Repr::Unspecified { flow_label, .. } => flow_label,
+            #[cfg(feature = "proto-ipv4")]
+            &Repr::Ipv4(_) => 0,
+            #[cfg(feature = "proto-ipv6")]
+            &Repr::Ipv6(Ipv6Repr { flow_label, .. }) => flow_label,
+            &Repr::__Nonexhaustive => unreachable!()
+        }
+    }
+
+    /// Set the Differentiated Services Code Point.
+    pub fn set_dscp(&mut self, value: u8) {
+        match self {
+            &mut Repr::Unspecified { ref mut dscp, .. } =>
+                *dscp = value,
+            #[cfg(feature = "proto-ipv4")]
+            &mut Repr::Ipv4(Ipv4Repr { ref mut dscp, .. }) =>
+                *dscp = value,
+            #[cfg(feature = "proto-ipv6")]
+            &mut Repr::Ipv6(Ipv6Repr { ref mut dscp, .. }) =>
+                *dscp = value,
+            &mut Repr::__Nonexhaustive => unreachable!()
+        }
+    }
+
     /// Convert an unspecified representation into a concrete one, or return
     /// `Err(Error::Unaddressable)` if not possible.
     ///
@@ -498,12 +657,12 @@ impl Repr {
             &Repr::Unspecified {
                 src_addr: src_addr @ Address::Unspecified,
                 dst_addr: Address::Ipv4(dst_addr),
-                protocol, payload_len, hop_limit
+                protocol, payload_len, hop_limit, ecn, dscp, ..
             } |
             &Repr::Unspecified {
                 src_addr: src_addr @ Address::Ipv4(_),
                 dst_addr: Address::Ipv4(dst_addr),
-                protocol, payload_len, hop_limit
+                protocol, payload_len, hop_limit, ecn, dscp, ..
             } if src_addr.is_unspecified() => {
                 let mut src_addr = if let Address::Ipv4(src_ipv4_addr) = src_addr {
                     Some(src_ipv4_addr)
@@ -518,7 +677,7 @@ impl Repr {
                 }
                 Ok(Repr::Ipv4(Ipv4Repr {
                     src_addr:    src_addr.ok_or(Error::Unaddressable)?,
-                    dst_addr, protocol, payload_len, hop_limit
+                    dst_addr, protocol, payload_len, hop_limit, ecn, dscp
                 }))
             }
 
@@ -526,12 +685,12 @@ impl Repr {
             &Repr::Unspecified {
                 src_addr: src_addr @ Address::Unspecified,
                 dst_addr: Address::Ipv6(dst_addr),
-                protocol, payload_len, hop_limit
+                protocol, payload_len, hop_limit, ecn, dscp, flow_label
             } |
             &Repr::Unspecified {
                 src_addr: src_addr @ Address::Ipv6(_),
                 dst_addr: Address::Ipv6(dst_addr),
-                protocol, payload_len, hop_limit
+                protocol, payload_len, hop_limit, ecn, dscp, flow_label
             } if src_addr.is_unspecified() => {
                 let mut src_addr = if let Address::Ipv6(src_ipv6_addr) = src_addr {
                     Some(src_ipv6_addr)
@@ -547,7 +706,7 @@ impl Repr {
                 Ok(Repr::Ipv6(Ipv6Repr {
                     src_addr:    src_addr.ok_or(Error::Unaddressable)?,
                     next_header: protocol,
-                    dst_addr, payload_len, hop_limit
+                    dst_addr, payload_len, hop_limit, ecn, dscp, flow_label
                 }))
             }
 
@@ -555,13 +714,13 @@ impl Repr {
             &Repr::Unspecified {
                 src_addr: Address::Ipv4(src_addr),
                 dst_addr: Address::Ipv4(dst_addr),
-                protocol, payload_len, hop_limit
+                protocol, payload_len, hop_limit, ecn, dscp, ..
             } => {
                 Ok(Repr::Ipv4(Ipv4Repr {
                     src_addr:    src_addr,
                     dst_addr:    dst_addr,
                     protocol:    protocol,
-                    payload_len: payload_len, hop_limit
+                    payload_len: payload_len, hop_limit, ecn, dscp
                 }))
             }
 
@@ -569,14 +728,17 @@ impl Repr {
             &Repr::Unspecified {
                 src_addr: Address::Ipv6(src_addr),
                 dst_addr: Address::Ipv6(dst_addr),
-                protocol, payload_len, hop_limit
+                protocol, payload_len, hop_limit, ecn, dscp, flow_label
             } => {
                 Ok(Repr::Ipv6(Ipv6Repr {
                     src_addr:    src_addr,
                     dst_addr:    dst_addr,
                     next_header: protocol,
                     payload_len: payload_len,
-                    hop_limit:   hop_limit
+                    hop_limit:   hop_limit,
+                    flow_label:  flow_label,
+                    ecn:         ecn,
+                    dscp:        dscp
                 }))
             }
 
@@ -730,6 +892,18 @@ pub mod checksum {
         }
     }
 
+    /// Update a checksum field, i.e. the complement of an RFC 1071 compliant checksum,
+    /// after a 16-bit word it covers changed, as described in RFC 1624 § 3.
+    pub fn update(checksum: u16, old_word: u16, new_word: u16) -> u16 {
+        !combine(&[!checksum, !old_word, new_word])
+    }
+
+    /// Update a checksum field after data it covers changed; the data must be
+    /// aligned to 16-bit words.
+    pub fn update_data(checksum: u16, old_data: &[u8], new_data: &[u8]) -> u16 {
+        !combine(&[!checksum, !data(old_data), data(new_data)])
+    }
+
     // We use this in pretty printer implementations.
     pub(crate) fn format_checksum(f: &mut fmt::Formatter, correct: bool) -> fmt::Result {
         if !correct {
@@ -748,6 +922,8 @@ pub fn pretty_print_ip_payload<T: Into<Repr>>(f: &mut fmt::Formatter, indent: &m
     use wire::Icmpv4Packet;
     #[cfg(feature = "proto-ipv4")]
     use super::pretty_print::PrettyPrint;
+    #[cfg(feature = "proto-igmp")]
+    use wire::IgmpPacket;
     use wire::{TcpPacket, TcpRepr, UdpPacket, UdpRepr};
     use wire::ip::checksum::format_checksum;
 
@@ -759,6 +935,11 @@ pub fn pretty_print_ip_payload<T: Into<Repr>>(f: &mut fmt::Formatter, indent: &m
             indent.increase(f)?;
             Icmpv4Packet::<&[u8]>::pretty_print(&payload.as_ref(), f, indent)
         }
+        #[cfg(feature = "proto-igmp")]
+        Protocol::Igmp => {
+            indent.increase(f)?;
+            IgmpPacket::<&[u8]>::pretty_print(&payload.as_ref(), f, indent)
+        }
         Protocol::Udp => {
             indent.increase(f)?;
             match UdpPacket::<&[u8]>::new_checked(payload.as_ref()) {
@@ -829,7 +1010,23 @@ pub(crate) mod test {
     #[cfg(all(feature = "proto-ipv4", not(feature = "proto-ipv6")))]
     pub(crate) const MOCK_UNSPECIFIED: IpAddress = IpAddress::Ipv4(Ipv4Address::UNSPECIFIED);
 
+    /// Return a deterministic set of inputs of varying length for `Arbitrary` impls,
+    /// so that round-trip tests are reproducible without a fuzzer.
+    #[cfg(feature = "arbitrary")]
+    pub(crate) fn arbitrary_inputs() -> Vec<Vec<u8>> {
+        let mut state = 0x2545f491u32;
+        (0..256).map(|length| {
+            (0..length).map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            }).collect()
+        }).collect()
+    }
 
+    #[cfg(feature = "arbitrary")]
+    use std::vec::Vec;
     use super::*;
     use wire::{IpAddress, IpProtocol,IpCidr};
     #[cfg(feature = "proto-ipv4")]
@@ -849,6 +1046,9 @@ pub(crate) mod test {
                 dst_addr:  IpAddress::Ipv4(ip_addr_b),
                 protocol:  proto,
                 hop_limit: 0x2a,
+                ecn: 0,
+                dscp: 0,
+                flow_label: 0,
                 payload_len,
             }.lower(&[]),
             Ok(Repr::Ipv4(Ipv4Repr{
@@ -856,6 +1056,8 @@ pub(crate) mod test {
                 dst_addr:  ip_addr_b,
                 protocol:  proto,
                 hop_limit: 0x2a,
+                ecn: 0,
+                dscp: 0,
                 payload_len
             }))
         );
@@ -866,6 +1068,9 @@ pub(crate) mod test {
                 dst_addr:  IpAddress::Ipv4(ip_addr_b),
                 protocol:  proto,
                 hop_limit: 64,
+                ecn: 0,
+                dscp: 0,
+                flow_label: 0,
                 payload_len
             }.lower(&[]),
             Err(Error::Unaddressable)
@@ -877,6 +1082,9 @@ pub(crate) mod test {
                 dst_addr:  IpAddress::Ipv4(ip_addr_b),
                 protocol:  proto,
                 hop_limit: 64,
+                ecn: 0,
+                dscp: 0,
+                flow_label: 0,
                 payload_len
             }.lower(&[IpCidr::new(IpAddress::Ipv4(ip_addr_a), 24)]),
             Ok(Repr::Ipv4(Ipv4Repr{
@@ -884,6 +1092,8 @@ pub(crate) mod test {
                 dst_addr:  ip_addr_b,
                 protocol:  proto,
                 hop_limit: 64,
+                ecn: 0,
+                dscp: 0,
                 payload_len
             }))
         );
@@ -894,6 +1104,9 @@ pub(crate) mod test {
                 dst_addr:  IpAddress::Ipv4(ip_addr_b),
                 protocol:  proto,
                 hop_limit: 64,
+                ecn: 0,
+                dscp: 0,
+                flow_label: 0,
                 payload_len
             }.lower(&[IpCidr::new(IpAddress::Ipv4(ip_addr_a), 24)]),
             Ok(Repr::Ipv4(Ipv4Repr{
@@ -901,6 +1114,8 @@ pub(crate) mod test {
                 dst_addr:  ip_addr_b,
                 protocol:  proto,
                 hop_limit: 64,
+                ecn: 0,
+                dscp: 0,
                 payload_len
             }))
         );
@@ -911,6 +1126,9 @@ pub(crate) mod test {
                 dst_addr:  IpAddress::Ipv4(ip_addr_b),
                 protocol:  proto,
                 hop_limit: 64,
+                ecn: 0,
+                dscp: 0,
+                flow_label: 0,
                 payload_len
             }.lower(&[]),
             Ok(Repr::Ipv4(Ipv4Repr{
@@ -918,6 +1136,8 @@ pub(crate) mod test {
                 dst_addr:  ip_addr_b,
                 protocol:  proto,
                 hop_limit: 64,
+                ecn: 0,
+                dscp: 0,
                 payload_len
             }))
         );
@@ -928,6 +1148,8 @@ pub(crate) mod test {
                 dst_addr:  ip_addr_b,
                 protocol:  proto,
                 hop_limit: 255,
+                ecn: 0,
+                dscp: 0,
                 payload_len
             }).lower(&[]),
             Ok(Repr::Ipv4(Ipv4Repr{
@@ -935,6 +1157,8 @@ pub(crate) mod test {
                 dst_addr:  ip_addr_b,
                 protocol:  proto,
                 hop_limit: 255,
+                ecn: 0,
+                dscp: 0,
                 payload_len
             }))
         );
@@ -945,6 +1169,8 @@ pub(crate) mod test {
                 dst_addr:  ip_addr_b,
                 protocol:  proto,
                 hop_limit: 255,
+                ecn: 0,
+                dscp: 0,
                 payload_len
             }).lower(&[]),
             Err(Error::Unaddressable)
@@ -956,6 +1182,8 @@ pub(crate) mod test {
                 dst_addr:  ip_addr_b,
                 protocol:  proto,
                 hop_limit: 64,
+                ecn: 0,
+                dscp: 0,
                 payload_len
             }).lower(&[IpCidr::new(IpAddress::Ipv4(ip_addr_a), 24)]),
             Ok(Repr::Ipv4(Ipv4Repr{
@@ -963,6 +1191,8 @@ pub(crate) mod test {
                 dst_addr:  ip_addr_b,
                 protocol:  proto,
                 hop_limit: 64,
+                ecn: 0,
+                dscp: 0,
                 payload_len
             }))
         );
@@ -972,4 +1202,15 @@ pub(crate) mod test {
     fn endpoint_unspecified() {
         assert!(!Endpoint::UNSPECIFIED.is_specified());
     }
+
+    #[test]
+    fn checksum_update() {
+        let old_data = [0x12, 0x34, 0xab, 0xcd, 0x00, 0x01];
+        let new_data = [0x12, 0x34, 0xff, 0x00, 0x00, 0x01];
+        let old_checksum = !checksum::data(&old_data[..]);
+        let new_checksum = !checksum::data(&new_data[..]);
+        assert_eq!(checksum::update(old_checksum, 0xabcd, 0xff00), new_checksum);
+        assert_eq!(checksum::update_data(old_checksum, &old_data[2..4], &new_data[2..4]),
+                   new_checksum);
+    }
 }
@@ -2,6 +2,8 @@ use core::fmt;
 use byteorder::{ByteOrder, NetworkEndian};
 
 use {Error, Result};
+#[cfg(feature = "arbitrary")]
+use arbitrary::{self, Arbitrary, Unstructured};
 use phy::ChecksumCapabilities;
 use super::ip::{checksum, pretty_print_ip_payload};
 
@@ -32,6 +34,12 @@ impl Address {
     /// The broadcast address.
     pub const BROADCAST:   Address = Address([0xff; 4]);
 
+    /// The all-systems multicast group, which every host joins.
+    pub const MULTICAST_ALL_SYSTEMS: Address = Address([224, 0, 0, 1]);
+
+    /// The all-routers multicast group.
+    pub const MULTICAST_ALL_ROUTERS: Address = Address([224, 0, 0, 2]);
+
     /// Construct an IPv4 address from parts.
     pub fn new(a0: u8, a1: u8, a2: u8, a3: u8) -> Address {
         Address([a0, a1, a2, a3])
@@ -92,6 +100,13 @@ impl fmt::Display for Address {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Address {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Address> {
+        Ok(Address(u.arbitrary()?))
+    }
+}
+
 /// A specification of an IPv4 CIDR block, containing an address and a variable-length
 /// subnet masking prefix length.
 #[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
@@ -120,6 +135,31 @@ impl Cidr {
         self.prefix_len
     }
 
+    /// Return the network mask of this IPv4 CIDR block.
+    pub fn netmask(&self) -> Address {
+        // left shift by 32 is not legal
+        if self.prefix_len == 0 { return Address([0; 4]) }
+
+        let mut bytes = [0; 4];
+        NetworkEndian::write_u32(&mut bytes, !0 << (32 - self.prefix_len));
+        Address(bytes)
+    }
+
+    /// Return the directed broadcast address of this IPv4 CIDR block, or `None` if
+    /// the prefix length is 31 or 32, since such a subnetwork has no broadcast address
+    /// (see [RFC 3021]).
+    ///
+    /// [RFC 3021]: https://tools.ietf.org/html/rfc3021
+    pub fn broadcast(&self) -> Option<Address> {
+        if self.prefix_len >= 31 { return None }
+
+        let network = NetworkEndian::read_u32(self.address.as_bytes()) &
+                      NetworkEndian::read_u32(self.netmask().as_bytes());
+        let mut bytes = [0; 4];
+        NetworkEndian::write_u32(&mut bytes, network | (!0 >> self.prefix_len));
+        Some(Address(bytes))
+    }
+
     /// Query whether the subnetwork described by this IPv4 CIDR block contains
     /// the given address.
     pub fn contains_addr(&self, addr: &Address) -> bool {
@@ -207,6 +247,21 @@ impl<T: AsRef<[u8]>> Packet<T> {
         }
     }
 
+    /// Ensure that no accessor method will panic if called, except for [payload],
+    /// without requiring the whole datagram to be present, as it is not when
+    /// it is quoted by an ICMP error message.
+    /// Returns `Err(Error::Truncated)` if the buffer is too short for the header.
+    ///
+    /// [payload]: #method.payload
+    pub fn check_header_len(&self) -> Result<()> {
+        let len = self.buffer.as_ref().len();
+        if len < field::DST_ADDR.end || len < self.header_len() as usize {
+            Err(Error::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+
     /// Consume the packet, returning the underlying buffer.
     pub fn into_inner(self) -> T {
         self.buffer
@@ -470,32 +525,53 @@ pub struct Repr {
     pub dst_addr:    Address,
     pub protocol:    Protocol,
     pub payload_len: usize,
-    pub hop_limit:   u8
+    pub hop_limit:   u8,
+    /// The 2-bit Explicit Congestion Notification field.
+    pub ecn:         u8,
+    /// The 6-bit Differentiated Services Code Point field.
+    pub dscp:        u8
 }
 
 impl Repr {
     /// Parse an Internet Protocol version 4 packet and return a high-level representation.
+    ///
+    /// Returns `Err(Error::Fragmented)` if the packet is a fragment of a larger datagram;
+    /// see [parse_fragment](#method.parse_fragment).
     pub fn parse<T: AsRef<[u8]> + ?Sized>(packet: &Packet<&T>,
                                           checksum_caps: &ChecksumCapabilities) -> Result<Repr> {
+        let repr = Repr::parse_fragment(packet, checksum_caps)?;
+        if packet.more_frags() || packet.frag_offset() != 0 { return Err(Error::Fragmented) }
+        Ok(repr)
+    }
+
+    /// Parse an Internet Protocol version 4 packet that may be a fragment of a larger
+    /// datagram, and return a high-level representation of its header, with the payload
+    /// length being the length of the fragment.
+    pub fn parse_fragment<T: AsRef<[u8]> + ?Sized>(packet: &Packet<&T>,
+                                                   checksum_caps: &ChecksumCapabilities) ->
+                                                  Result<Repr> {
+        // The packet must include the entire header and payload of the fragment.
+        packet.check_len()?;
         // Version 4 is expected.
         if packet.version() != 4 { return Err(Error::Malformed) }
+        // The header must be at least as long as its fixed part, and fit in the packet.
+        if packet.header_len() < field::DST_ADDR.end as u8 ||
+           packet.header_len() as u16 > packet.total_len() { return Err(Error::Malformed) }
         // Valid checksum is expected.
         if checksum_caps.ipv4.rx() && !packet.verify_checksum() { return Err(Error::Checksum) }
-        // We do not support fragmentation.
-        if packet.more_frags() || packet.frag_offset() != 0 { return Err(Error::Fragmented) }
-        // Since the packet is not fragmented, it must include the entire payload.
         let payload_len = packet.total_len() as usize - packet.header_len() as usize;
-        if packet.payload().len() < payload_len  { return Err(Error::Truncated) }
 
         // All DSCP values are acceptable, since they are of no concern to receiving endpoint.
         // All ECN values are acceptable, since ECN requires opt-in from both endpoints.
-        // All TTL values are acceptable, since we do not perform routing.
+        // All TTL values are acceptable; the interface checks them when forwarding.
         Ok(Repr {
             src_addr:    packet.src_addr(),
             dst_addr:    packet.dst_addr(),
             protocol:    packet.protocol(),
             payload_len: payload_len,
-            hop_limit:   packet.hop_limit()
+            hop_limit:   packet.hop_limit(),
+            ecn:         packet.ecn(),
+            dscp:        packet.dscp()
         })
     }
 
@@ -509,8 +585,8 @@ impl Repr {
     pub fn emit<T: AsRef<[u8]> + AsMut<[u8]>>(&self, packet: &mut Packet<T>, checksum_caps: &ChecksumCapabilities) {
         packet.set_version(4);
         packet.set_header_len(field::DST_ADDR.end as u8);
-        packet.set_dscp(0);
-        packet.set_ecn(0);
+        packet.set_dscp(self.dscp);
+        packet.set_ecn(self.ecn);
         let total_len = packet.header_len() as u16 + self.payload_len as u16;
         packet.set_total_len(total_len);
         packet.set_ident(0);
@@ -533,6 +609,22 @@ impl Repr {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Repr {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Repr> {
+        Ok(Repr {
+            src_addr:    u.arbitrary()?,
+            dst_addr:    u.arbitrary()?,
+            protocol:    u.arbitrary()?,
+            // The total length field covers the header as well.
+            payload_len: u.int_in_range(0..=0xffff - field::DST_ADDR.end)?,
+            hop_limit:   u.arbitrary()?,
+            ecn:         u.int_in_range(0..=0x03)?,
+            dscp:        u.int_in_range(0..=0x3f)?
+        })
+    }
+}
+
 impl<'a, T: AsRef<[u8]> + ?Sized> fmt::Display for Packet<&'a T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match Repr::parse(self, &ChecksumCapabilities::ignored()) {
@@ -708,7 +800,9 @@ mod test {
             dst_addr:    Address([0x21, 0x22, 0x23, 0x24]),
             protocol:    Protocol::Icmp,
             payload_len: 4,
-            hop_limit:   64
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0
         }
     }
 
@@ -730,6 +824,46 @@ mod test {
         assert_eq!(Repr::parse(&packet, &ChecksumCapabilities::default()), Err(Error::Malformed));
     }
 
+    #[test]
+    fn test_parse_fragment() {
+        let mut bytes = vec![0; 24];
+        bytes.copy_from_slice(&REPR_PACKET_BYTES[..]);
+        let mut packet = Packet::new(&mut bytes);
+        packet.clear_flags();
+        packet.set_more_frags(true);
+        packet.set_frag_offset(64);
+        packet.fill_checksum();
+        let packet = Packet::new(&*packet.into_inner());
+        assert_eq!(Repr::parse(&packet, &ChecksumCapabilities::default()),
+                   Err(Error::Fragmented));
+        assert_eq!(Repr::parse_fragment(&packet, &ChecksumCapabilities::default()),
+                   Ok(packet_repr()));
+    }
+
+    #[test]
+    fn test_parse_fragment_truncated() {
+        let packet = Packet::new(&REPR_PACKET_BYTES[..20]);
+        assert_eq!(Repr::parse_fragment(&packet, &ChecksumCapabilities::default()),
+                   Err(Error::Truncated));
+        let packet = Packet::new(&REPR_PACKET_BYTES[..12]);
+        assert_eq!(Repr::parse_fragment(&packet, &ChecksumCapabilities::default()),
+                   Err(Error::Truncated));
+    }
+
+    #[test]
+    fn test_parse_fragment_short_header_len() {
+        let mut bytes = vec![0; 24];
+        bytes.copy_from_slice(&REPR_PACKET_BYTES[..]);
+        let mut packet = Packet::new(&mut bytes);
+        packet.set_header_len(0);
+        packet.set_total_len(13);
+        let packet = Packet::new(&*packet.into_inner());
+        assert_eq!(Repr::parse_fragment(&packet, &ChecksumCapabilities::ignored()),
+                   Err(Error::Malformed));
+        assert_eq!(Repr::parse(&packet, &ChecksumCapabilities::ignored()),
+                   Err(Error::Malformed));
+    }
+
     #[test]
     fn test_parse_total_len_less_than_header_len() {
         let mut bytes = vec![0; 40];
@@ -747,6 +881,28 @@ mod test {
         assert_eq!(&packet.into_inner()[..], &REPR_PACKET_BYTES[..]);
     }
 
+    #[test]
+    fn test_repr_ecn() {
+        let repr = Repr { ecn: 0b10, ..packet_repr() };
+        let mut bytes = vec![0xa5; repr.buffer_len() + REPR_PAYLOAD_BYTES.len()];
+        repr.emit(&mut Packet::new(&mut bytes), &ChecksumCapabilities::default());
+        let packet = Packet::new(&bytes);
+        assert_eq!(packet.dscp(), 0);
+        assert_eq!(packet.ecn(), 0b10);
+        assert_eq!(Repr::parse(&packet, &ChecksumCapabilities::default()), Ok(repr));
+    }
+
+    #[test]
+    fn test_repr_dscp() {
+        let repr = Repr { dscp: 46, ecn: 0b01, ..packet_repr() };
+        let mut bytes = vec![0xa5; repr.buffer_len() + REPR_PAYLOAD_BYTES.len()];
+        repr.emit(&mut Packet::new(&mut bytes), &ChecksumCapabilities::default());
+        let packet = Packet::new(&bytes);
+        assert_eq!(packet.dscp(), 46);
+        assert_eq!(packet.ecn(), 0b01);
+        assert_eq!(Repr::parse(&packet, &ChecksumCapabilities::default()), Ok(repr));
+    }
+
     #[test]
     fn test_unspecified() {
         assert!(Address::UNSPECIFIED.is_unspecified());
@@ -815,4 +971,46 @@ mod test {
         let cidr_without_prefix = Cidr::new(cidr.address(), 0);
         assert!(cidr_without_prefix.contains_addr(&Address::new(127, 0, 0, 1)));
     }
+
+    #[test]
+    fn test_cidr_netmask() {
+        assert_eq!(Cidr::new(Address::new(192, 168, 1, 10), 24).netmask(),
+                   Address::new(255, 255, 255, 0));
+        assert_eq!(Cidr::new(Address::new(10, 0, 0, 1), 9).netmask(),
+                   Address::new(255, 128, 0, 0));
+        assert_eq!(Cidr::new(Address::new(10, 0, 0, 1), 32).netmask(),
+                   Address::BROADCAST);
+        assert_eq!(Cidr::new(Address::new(10, 0, 0, 1), 0).netmask(),
+                   Address::UNSPECIFIED);
+    }
+
+    #[test]
+    fn test_cidr_broadcast() {
+        assert_eq!(Cidr::new(Address::new(192, 168, 1, 10), 24).broadcast(),
+                   Some(Address::new(192, 168, 1, 255)));
+        assert_eq!(Cidr::new(Address::new(10, 0, 0, 1), 9).broadcast(),
+                   Some(Address::new(10, 127, 255, 255)));
+        assert_eq!(Cidr::new(Address::new(10, 0, 0, 1), 0).broadcast(),
+                   Some(Address::BROADCAST));
+        assert_eq!(Cidr::new(Address::new(10, 0, 0, 1), 31).broadcast(), None);
+        assert_eq!(Cidr::new(Address::new(10, 0, 0, 1), 32).broadcast(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "arbitrary")]
+    fn test_arbitrary_round_trip() {
+        use arbitrary::Unstructured;
+        use wire::ip::test::arbitrary_inputs;
+
+        for input in arbitrary_inputs() {
+            let repr: Repr = match Unstructured::new(&input).arbitrary() {
+                Ok(repr) => repr,
+                Err(_) => continue
+            };
+            let mut bytes = vec![0xa5; repr.buffer_len() + repr.payload_len];
+            repr.emit(&mut Packet::new(&mut bytes), &ChecksumCapabilities::default());
+            assert_eq!(Repr::parse(&Packet::new(&bytes), &ChecksumCapabilities::default()),
+                       Ok(repr));
+        }
+    }
 }
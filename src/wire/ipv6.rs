@@ -4,6 +4,8 @@ use core::fmt;
 use byteorder::{ByteOrder, NetworkEndian};
 
 use {Error, Result};
+#[cfg(feature = "arbitrary")]
+use arbitrary::{self, Arbitrary, Unstructured};
 pub use super::IpProtocol as Protocol;
 use super::ip::pretty_print_ip_payload;
 
@@ -12,6 +14,32 @@ use super::ip::pretty_print_ip_payload;
 /// [RFC 8200 § 5]: https://tools.ietf.org/html/rfc8200#section-5
 pub const MIN_MTU: usize = 1280;
 
+/// The scope of an IPv6 multicast address, as defined by [RFC 4291 § 2.7] and RFC 7346.
+///
+/// [RFC 4291 § 2.7]: https://tools.ietf.org/html/rfc4291#section-2.7
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MulticastScope {
+    /// The reserved scope 0; packets sent to such addresses are discarded.
+    Reserved,
+    /// Interface-local scope, which only spans a single interface of a node;
+    /// packets sent to such addresses are only looped back.
+    InterfaceLocal,
+    /// Link-local scope.
+    LinkLocal,
+    /// Realm-local scope.
+    RealmLocal,
+    /// Admin-local scope.
+    AdminLocal,
+    /// Site-local scope.
+    SiteLocal,
+    /// Organization-local scope.
+    OrganizationLocal,
+    /// Global scope, which the reserved scope 15 is treated as.
+    Global,
+    /// An unassigned scope, which administrators may configure.
+    Unassigned(u8),
+}
+
 /// A sixteen-octet IPv6 address.
 #[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
 pub struct Address(pub [u8; 16]);
@@ -36,6 +64,13 @@ impl Address {
         Address([0xff, 0x02, 0x00, 0x0, 0x00, 0x00, 0x00, 0x0,
                  0x00, 0x00, 0x00, 0x0, 0x00, 0x00, 0x00, 0x2]);
 
+    /// The link-local [all MLDv2-capable routers multicast address].
+    ///
+    /// [all MLDv2-capable routers multicast address]: https://tools.ietf.org/html/rfc3810#section-11
+    pub const LINK_LOCAL_ALL_MLDV2_ROUTERS: Address =
+        Address([0xff, 0x02, 0x00, 0x0, 0x00, 0x00, 0x00, 0x0,
+                 0x00, 0x00, 0x00, 0x0, 0x00, 0x00, 0x00, 0x16]);
+
     /// The [loopback address].
     ///
     /// [loopback address]: https://tools.ietf.org/html/rfc4291#section-2.5.3
@@ -113,6 +148,25 @@ impl Address {
         self.0[0] == 0xff
     }
 
+    /// Return the scope of the IPv6 address, if it is a [multicast address].
+    ///
+    /// [multicast address]: https://tools.ietf.org/html/rfc4291#section-2.7
+    pub fn multicast_scope(&self) -> Option<MulticastScope> {
+        if !self.is_multicast() { return None }
+
+        match self.0[1] & 0x0f {
+            0x0 => Some(MulticastScope::Reserved),
+            0x1 => Some(MulticastScope::InterfaceLocal),
+            0x2 => Some(MulticastScope::LinkLocal),
+            0x3 => Some(MulticastScope::RealmLocal),
+            0x4 => Some(MulticastScope::AdminLocal),
+            0x5 => Some(MulticastScope::SiteLocal),
+            0x8 => Some(MulticastScope::OrganizationLocal),
+            0xe | 0xf => Some(MulticastScope::Global),
+            scope => Some(MulticastScope::Unassigned(scope))
+        }
+    }
+
     /// Query whether the IPv6 address is the [unspecified address].
     ///
     /// [unspecified address]: https://tools.ietf.org/html/rfc4291#section-2.5.2
@@ -135,6 +189,14 @@ impl Address {
         *self == Self::LOOPBACK
     }
 
+    /// Return the [solicited-node multicast address] of the IPv6 address.
+    ///
+    /// [solicited-node multicast address]: https://tools.ietf.org/html/rfc4291#section-2.7.1
+    pub fn solicited_node(&self) -> Address {
+        Address([0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                 0x00, 0x00, 0x00, 0x01, 0xff, self.0[13], self.0[14], self.0[15]])
+    }
+
     /// Query whether the IPv6 address is an [IPv4 mapped IPv6 address].
     ///
     /// [IPv4 mapped IPv6 address]: https://tools.ietf.org/html/rfc4291#section-2.5.5.2
@@ -225,6 +287,13 @@ impl fmt::Display for Address {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Address {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Address> {
+        Ok(Address(u.arbitrary()?))
+    }
+}
+
 #[cfg(feature = "proto-ipv4")]
 /// Convert the given IPv4 address into a IPv4-mapped IPv6 address
 impl From<::wire::ipv4::Address> for Address {
@@ -266,11 +335,7 @@ impl Cidr {
     /// Query whether the subnetwork described by this IPv6 CIDR block contains
     /// the given address.
     pub fn contains_addr(&self, addr: &Address) -> bool {
-        // right shift by 128 is not legal
-        if self.prefix_len == 0 { return true }
-
-        let shift = 128 - self.prefix_len;
-        self.address.mask(shift) == addr.mask(shift)
+        self.address.mask(self.prefix_len) == addr.mask(self.prefix_len)
     }
 
     /// Query whether the subnetwork described by this IPV6 CIDR block contains
@@ -372,6 +437,22 @@ impl<T: AsRef<[u8]>> Packet<T> {
         }
     }
 
+    /// Ensure that no accessor method will panic if called, except for [payload],
+    /// without requiring the whole packet to be present, as it is not when
+    /// it is quoted by an ICMPv6 error message.
+    /// Returns `Err(Error::Truncated)` if the buffer is too short for the header.
+    ///
+    /// [payload]: #method.payload
+    #[inline]
+    pub fn check_header_len(&self) -> Result<()> {
+        let len = self.buffer.as_ref().len();
+        if len < field::DST_ADDR.end {
+            Err(Error::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+
     /// Consume the packet, returning the underlying buffer.
     #[inline]
     pub fn into_inner(self) -> T {
@@ -560,7 +641,17 @@ pub struct Repr {
     /// Length of the payload including the extension headers.
     pub payload_len: usize,
     /// The 8-bit hop limit field.
-    pub hop_limit:   u8
+    pub hop_limit:   u8,
+    /// The 20-bit flow label field.
+    ///
+    /// A value of zero indicates that the packet is not labeled.
+    pub flow_label:  u32,
+    /// The 2-bit Explicit Congestion Notification field, in the low bits of the
+    /// traffic class field.
+    pub ecn:         u8,
+    /// The 6-bit Differentiated Services Code Point, in the high bits of the
+    /// traffic class field.
+    pub dscp:        u8
 }
 
 impl Repr {
@@ -574,7 +665,10 @@ impl Repr {
             dst_addr:    packet.dst_addr(),
             next_header: packet.next_header(),
             payload_len: packet.payload_len() as usize,
-            hop_limit:   packet.hop_limit()
+            hop_limit:   packet.hop_limit(),
+            flow_label:  packet.flow_label(),
+            ecn:         packet.traffic_class() & 0x03,
+            dscp:        packet.traffic_class() >> 2
         })
     }
 
@@ -589,8 +683,8 @@ impl Repr {
         // Make no assumptions about the original state of the packet buffer.
         // Make sure to set every byte.
         packet.set_version(6);
-        packet.set_traffic_class(0);
-        packet.set_flow_label(0);
+        packet.set_traffic_class((self.dscp << 2) | (self.ecn & 0x03));
+        packet.set_flow_label(self.flow_label);
         packet.set_payload_len(self.payload_len as u16);
         packet.set_hop_limit(self.hop_limit);
         packet.set_next_header(self.next_header);
@@ -599,6 +693,22 @@ impl Repr {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Repr {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Repr> {
+        Ok(Repr {
+            src_addr:    u.arbitrary()?,
+            dst_addr:    u.arbitrary()?,
+            next_header: u.arbitrary()?,
+            payload_len: u.int_in_range(0..=0xffff)?,
+            hop_limit:   u.arbitrary()?,
+            flow_label:  u.int_in_range(0..=0xfffff)?,
+            ecn:         u.int_in_range(0..=0x03)?,
+            dscp:        u.int_in_range(0..=0x3f)?
+        })
+    }
+}
+
 impl fmt::Display for Repr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "IPv6 src={} dst={} nxt_hdr={} hop_limit={}",
@@ -633,7 +743,7 @@ impl<T: AsRef<[u8]>> PrettyPrint for Packet<T> {
 #[cfg(test)]
 mod test {
     use Error;
-    use super::{Address, Cidr};
+    use super::{Address, Cidr, MulticastScope};
     use super::{Packet, Protocol, Repr};
     use wire::pretty_print::{PrettyPrinter};
 
@@ -672,6 +782,29 @@ mod test {
         assert!(Address::LOOPBACK.is_loopback());
     }
 
+    #[test]
+    fn test_solicited_node() {
+        let addr = Address::new(0x2001, 0xdb8, 0, 0, 0x5054, 0x00ff, 0xfe12, 0x3456);
+        assert_eq!(addr.solicited_node(),
+                   Address::new(0xff02, 0, 0, 0, 0, 1, 0xff12, 0x3456));
+        assert!(addr.solicited_node().is_multicast());
+    }
+
+    #[test]
+    fn test_multicast_scope() {
+        assert_eq!(Address::LINK_LOCAL_ALL_NODES.multicast_scope(),
+                   Some(MulticastScope::LinkLocal));
+        assert_eq!(Address::new(0xff01, 0, 0, 0, 0, 0, 0, 1).multicast_scope(),
+                   Some(MulticastScope::InterfaceLocal));
+        assert_eq!(Address::new(0xff10, 0, 0, 0, 0, 0, 0, 1).multicast_scope(),
+                   Some(MulticastScope::Reserved));
+        assert_eq!(Address::new(0xff1f, 0, 0, 0, 0, 0, 0, 1).multicast_scope(),
+                   Some(MulticastScope::Global));
+        assert_eq!(Address::new(0xff36, 0, 0, 0, 0, 0, 0, 1).multicast_scope(),
+                   Some(MulticastScope::Unassigned(6)));
+        assert_eq!(LINK_LOCAL_ADDR.multicast_scope(), None);
+    }
+
     #[test]
     fn test_address_format() {
         assert_eq!("ff02::1",
@@ -843,6 +976,14 @@ mod test {
 
         let cidr_without_prefix = Cidr::new(LINK_LOCAL_ADDR, 0);
         assert!(cidr_without_prefix.contains_addr(&Address::LOOPBACK));
+
+        // Prefixes other than /64 are matched on their own length.
+        let host = Cidr::new(LINK_LOCAL_ADDR, 128);
+        assert!(host.contains_addr(&LINK_LOCAL_ADDR));
+        assert!(!host.contains_addr(&Address::from_bytes(&inside_subnet[0])));
+        let short = Cidr::new(Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0), 32);
+        assert!(short.contains_addr(&Address::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 1)));
+        assert!(!short.contains_addr(&Address::new(0x2001, 0xdb9, 0, 0, 0, 0, 0, 1)));
     }
 
     #[test]
@@ -883,7 +1024,10 @@ mod test {
             dst_addr:    Address::LINK_LOCAL_ALL_NODES,
             next_header: Protocol::Udp,
             payload_len: 12,
-            hop_limit:   64
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0,
+            flow_label:  0
         }
     }
 
@@ -1011,9 +1155,68 @@ mod test {
         assert_eq!(&packet.into_inner()[..], &REPR_PACKET_BYTES[..]);
     }
 
+    #[test]
+    fn test_repr_flow_label() {
+        let repr = Repr { flow_label: 0x54321, ..packet_repr() };
+        let mut bytes = vec![0xff; repr.buffer_len() + REPR_PAYLOAD_BYTES.len()];
+        {
+            let mut packet = Packet::new(&mut bytes);
+            repr.emit(&mut packet);
+            packet.payload_mut().copy_from_slice(&REPR_PAYLOAD_BYTES);
+        }
+        let packet = Packet::new(&bytes);
+        assert_eq!(packet.flow_label(), 0x54321);
+        assert_eq!(Repr::parse(&packet), Ok(repr));
+    }
+
+    #[test]
+    fn test_repr_ecn() {
+        let repr = Repr { ecn: 0b11, ..packet_repr() };
+        let mut bytes = vec![0xff; repr.buffer_len() + REPR_PAYLOAD_BYTES.len()];
+        {
+            let mut packet = Packet::new(&mut bytes);
+            repr.emit(&mut packet);
+            packet.payload_mut().copy_from_slice(&REPR_PAYLOAD_BYTES);
+        }
+        let packet = Packet::new(&bytes);
+        assert_eq!(packet.traffic_class(), 0b11);
+        assert_eq!(Repr::parse(&packet), Ok(repr));
+    }
+
+    #[test]
+    fn test_repr_dscp() {
+        let repr = Repr { dscp: 46, ecn: 0b10, ..packet_repr() };
+        let mut bytes = vec![0xff; repr.buffer_len() + REPR_PAYLOAD_BYTES.len()];
+        {
+            let mut packet = Packet::new(&mut bytes);
+            repr.emit(&mut packet);
+            packet.payload_mut().copy_from_slice(&REPR_PAYLOAD_BYTES);
+        }
+        let packet = Packet::new(&bytes);
+        assert_eq!(packet.traffic_class(), 0xba);
+        assert_eq!(Repr::parse(&packet), Ok(repr));
+    }
+
     #[test]
     fn test_pretty_print() {
         assert_eq!(format!("{}", PrettyPrinter::<Packet<&'static [u8]>>::new("\n", &&REPR_PACKET_BYTES[..])),
                    "\nIPv6 src=fe80::1 dst=ff02::1 nxt_hdr=UDP hop_limit=64\n \\ UDP src=1 dst=2 len=4");
     }
+
+    #[test]
+    #[cfg(feature = "arbitrary")]
+    fn test_arbitrary_round_trip() {
+        use arbitrary::Unstructured;
+        use wire::ip::test::arbitrary_inputs;
+
+        for input in arbitrary_inputs() {
+            let repr: Repr = match Unstructured::new(&input).arbitrary() {
+                Ok(repr) => repr,
+                Err(_) => continue
+            };
+            let mut bytes = vec![0xa5; repr.buffer_len() + repr.payload_len];
+            repr.emit(&mut Packet::new(&mut bytes));
+            assert_eq!(Repr::parse(&Packet::new(&bytes)), Ok(repr));
+        }
+    }
 }
@@ -0,0 +1,308 @@
+use core::fmt;
+use byteorder::{ByteOrder, NetworkEndian};
+
+use {Error, Result};
+use super::IpProtocol as Protocol;
+#[cfg(feature = "arbitrary")]
+use arbitrary::{self, Arbitrary, Unstructured};
+
+/// A read/write wrapper around an IPv6 Fragment Header.
+#[derive(Debug, PartialEq)]
+pub struct Header<T: AsRef<[u8]>> {
+    buffer: T
+}
+
+// Format of the Fragment Header
+//
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |  Next Header  |   Reserved    |      Fragment Offset    |Res|M|
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                         Identification                        |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// See https://tools.ietf.org/html/rfc8200#section-4.5 for details.
+mod field {
+    use wire::field::*;
+
+    // 8-bit identifier of the header immediately following this header.
+    pub const NXT_HDR:  usize = 0;
+    // 8-bit reserved field.
+    pub const RESERVED: usize = 1;
+    // 16-bit field containing the fragment offset, reserved and more fragments values.
+    pub const FR_OF_M:  Field = 2..4;
+    // 32-bit field identifying the fragmented packet.
+    pub const IDENT:    Field = 4..8;
+}
+
+impl<T: AsRef<[u8]>> Header<T> {
+    /// Create a raw octet buffer with an IPv6 Fragment Header structure.
+    pub fn new(buffer: T) -> Header<T> {
+        Header { buffer }
+    }
+
+    /// Shorthand for a combination of [new] and [check_len].
+    ///
+    /// [new]: #method.new
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<Header<T>> {
+        let header = Self::new(buffer);
+        header.check_len()?;
+        Ok(header)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error::Truncated)` if the buffer is too short.
+    pub fn check_len(&self) -> Result<()> {
+        let data = self.buffer.as_ref();
+        if data.len() < field::IDENT.end {
+            Err(Error::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Consume the header, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Return the next header field.
+    #[inline]
+    pub fn next_header(&self) -> Protocol {
+        let data = self.buffer.as_ref();
+        Protocol::from(data[field::NXT_HDR])
+    }
+
+    /// Return the fragment offset field, in octets.
+    #[inline]
+    pub fn frag_offset(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::FR_OF_M]) & !0x7
+    }
+
+    /// Return the more fragments flag.
+    #[inline]
+    pub fn more_frags(&self) -> bool {
+        let data = self.buffer.as_ref();
+        (data[field::FR_OF_M.start + 1] & 0x1) == 1
+    }
+
+    /// Return the fragment identification field.
+    #[inline]
+    pub fn ident(&self) -> u32 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u32(&data[field::IDENT])
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Header<T> {
+    /// Set the next header field.
+    #[inline]
+    pub fn set_next_header(&mut self, value: Protocol) {
+        let data = self.buffer.as_mut();
+        data[field::NXT_HDR] = value.into();
+    }
+
+    /// Clear both of the reserved fields.
+    #[inline]
+    pub fn clear_reserved(&mut self) {
+        let data = self.buffer.as_mut();
+        data[field::RESERVED] = 0;
+        // The low two bits of the offset word are the second reserved field.
+        data[field::FR_OF_M.start + 1] &= !0x6;
+    }
+
+    /// Set the fragment offset field, in octets.
+    ///
+    /// # Panics
+    /// This function panics if the offset is not a multiple of 8.
+    #[inline]
+    pub fn set_frag_offset(&mut self, value: u16) {
+        assert!(value & 0x7 == 0, "fragment offset is not a multiple of 8");
+        let data = self.buffer.as_mut();
+        let raw = NetworkEndian::read_u16(&data[field::FR_OF_M]);
+        NetworkEndian::write_u16(&mut data[field::FR_OF_M], (raw & 0x7) | value);
+    }
+
+    /// Set the more fragments flag.
+    #[inline]
+    pub fn set_more_frags(&mut self, value: bool) {
+        let data = self.buffer.as_mut();
+        let raw = data[field::FR_OF_M.start + 1] & !0x1;
+        data[field::FR_OF_M.start + 1] = raw | value as u8;
+    }
+
+    /// Set the fragment identification field.
+    #[inline]
+    pub fn set_ident(&mut self, value: u32) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u32(&mut data[field::IDENT], value)
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> Header<&'a T> {
+    /// Return the data following the fragment header.
+    #[inline]
+    pub fn payload(&self) -> &'a [u8] {
+        let data = self.buffer.as_ref();
+        &data[field::IDENT.end..]
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> fmt::Display for Header<&'a T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match Repr::parse(self) {
+            Ok(repr) => write!(f, "{}", repr),
+            Err(err) => write!(f, "IPv6 Fragment ({})", err)
+        }
+    }
+}
+
+/// A high-level representation of an IPv6 Fragment Header.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Repr {
+    /// The type of header immediately following the Fragment header.
+    pub next_header: Protocol,
+    /// The offset of the data following this header, relative to the start
+    /// of the fragmentable part of the original packet, in octets.
+    pub frag_offset: u16,
+    /// Whether there are more fragments following this one.
+    pub more_frags:  bool,
+    /// The identification for every packet that is fragmented.
+    pub ident:       u32,
+}
+
+impl Repr {
+    /// Parse an IPv6 Fragment Header and return a high-level representation.
+    pub fn parse<T>(header: &Header<&T>) -> Result<Repr> where T: AsRef<[u8]> + ?Sized {
+        header.check_len()?;
+        Ok(Repr {
+            next_header: header.next_header(),
+            frag_offset: header.frag_offset(),
+            more_frags:  header.more_frags(),
+            ident:       header.ident()
+        })
+    }
+
+    /// Return the length, in bytes, of a header that will be emitted from this high-level
+    /// representation.
+    pub fn buffer_len(&self) -> usize {
+        field::IDENT.end
+    }
+
+    /// Emit a high-level representation into an IPv6 Fragment Header.
+    pub fn emit<T: AsRef<[u8]> + AsMut<[u8]> + ?Sized>(&self, header: &mut Header<&mut T>) {
+        header.set_next_header(self.next_header);
+        header.clear_reserved();
+        header.set_frag_offset(self.frag_offset);
+        header.set_more_frags(self.more_frags);
+        header.set_ident(self.ident);
+    }
+
+    /// Query whether this is an atomic fragment, i.e. a packet with a Fragment header
+    /// that is not actually fragmented. See RFC 6946.
+    pub fn is_atomic(&self) -> bool {
+        self.frag_offset == 0 && !self.more_frags
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Repr {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Repr> {
+        Ok(Repr {
+            next_header: u.arbitrary()?,
+            frag_offset: u.int_in_range(0..=0x1fff)? << 3,
+            more_frags:  u.arbitrary()?,
+            ident:       u.arbitrary()?
+        })
+    }
+}
+
+impl fmt::Display for Repr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "IPv6 Fragment next_hdr={} offset={} more={} ident={}",
+               self.next_header, self.frag_offset, self.more_frags, self.ident)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // A fragment with offset 1448 and more fragments, followed by a single payload octet.
+    static BYTES_FRAGMENT: [u8; 9] = [0x11, 0x00, 0x05, 0xa9,
+                                      0x12, 0x34, 0x56, 0x78,
+                                      0xff];
+
+    fn fragment_repr() -> Repr {
+        Repr {
+            next_header: Protocol::Udp,
+            frag_offset: 1448,
+            more_frags:  true,
+            ident:       0x12345678
+        }
+    }
+
+    #[test]
+    fn test_check_len() {
+        assert_eq!(Header::new(&BYTES_FRAGMENT[..7]).check_len(), Err(Error::Truncated));
+        assert_eq!(Header::new(&BYTES_FRAGMENT[..8]).check_len(), Ok(()));
+    }
+
+    #[test]
+    fn test_deconstruct() {
+        let header = Header::new(&BYTES_FRAGMENT[..]);
+        assert_eq!(header.next_header(), Protocol::Udp);
+        assert_eq!(header.frag_offset(), 1448);
+        assert_eq!(header.more_frags(), true);
+        assert_eq!(header.ident(), 0x12345678);
+        assert_eq!(header.payload(), &[0xff]);
+    }
+
+    #[test]
+    fn test_construct() {
+        let mut bytes = [0xff; 9];
+        {
+            let mut header = Header::new(&mut bytes[..]);
+            header.set_next_header(Protocol::Udp);
+            header.clear_reserved();
+            header.set_frag_offset(1448);
+            header.set_more_frags(true);
+            header.set_ident(0x12345678);
+        }
+        assert_eq!(&bytes[..], &BYTES_FRAGMENT[..]);
+    }
+
+    #[test]
+    fn test_parse() {
+        let header = Header::new(&BYTES_FRAGMENT[..]);
+        let repr = Repr::parse(&header).unwrap();
+        assert_eq!(repr, fragment_repr());
+        assert!(!repr.is_atomic());
+    }
+
+    #[test]
+    fn test_emit() {
+        let repr = fragment_repr();
+        let mut bytes = [0xff; 9];
+        repr.emit(&mut Header::new(&mut bytes[..]));
+        assert_eq!(&bytes[..], &BYTES_FRAGMENT[..]);
+    }
+
+    #[test]
+    #[cfg(feature = "arbitrary")]
+    fn test_arbitrary_round_trip() {
+        use arbitrary::Unstructured;
+        use wire::ip::test::arbitrary_inputs;
+
+        for input in arbitrary_inputs() {
+            let repr: Repr = match Unstructured::new(&input).arbitrary() {
+                Ok(repr) => repr,
+                Err(_) => continue
+            };
+            let mut bytes = vec![0xa5; repr.buffer_len()];
+            repr.emit(&mut Header::new(&mut bytes[..]));
+            assert_eq!(Repr::parse(&Header::new(&bytes[..])), Ok(repr));
+        }
+    }
+}
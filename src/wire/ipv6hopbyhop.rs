@@ -0,0 +1,268 @@
+use core::fmt;
+
+use {Error, Result};
+use super::IpProtocol as Protocol;
+use super::Ipv6OptionIter;
+#[cfg(feature = "arbitrary")]
+use arbitrary::{self, Arbitrary, Unstructured};
+
+/// A read/write wrapper around an IPv6 Hop-by-Hop Options Header.
+#[derive(Debug, PartialEq)]
+pub struct Header<T: AsRef<[u8]>> {
+    buffer: T
+}
+
+// Format of the Hop-by-Hop Options Header
+//
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |  Next Header  |  Hdr Ext Len  |                               |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+                               +
+// |                                                               |
+// .                                                               .
+// .                            Options                            .
+// .                                                               .
+// |                                                               |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// See https://tools.ietf.org/html/rfc8200#section-4.3 for details.
+mod field {
+    #![allow(non_snake_case)]
+
+    use wire::field::*;
+
+    // 8-bit identifier of the header immediately following this header.
+    pub const NXT_HDR: usize = 0;
+    // 8-bit unsigned integer. Length of the header in 8-octet units,
+    // not including the first 8 octets.
+    pub const LENGTH:  usize = 1;
+    // Variable-length field. Options.
+    pub fn OPTIONS(length_field: u8) -> Field {
+        2..(length_field as usize + 1) * 8
+    }
+}
+
+impl<T: AsRef<[u8]>> Header<T> {
+    /// Create a raw octet buffer with an IPv6 Hop-by-Hop Options Header structure.
+    pub fn new(buffer: T) -> Header<T> {
+        Header { buffer }
+    }
+
+    /// Shorthand for a combination of [new] and [check_len].
+    ///
+    /// [new]: #method.new
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<Header<T>> {
+        let header = Self::new(buffer);
+        header.check_len()?;
+        Ok(header)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error::Truncated)` if the buffer is too short.
+    pub fn check_len(&self) -> Result<()> {
+        let data = self.buffer.as_ref();
+        if data.len() <= field::LENGTH ||
+                data.len() < field::OPTIONS(data[field::LENGTH]).end {
+            Err(Error::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Consume the header, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Return the next header field.
+    #[inline]
+    pub fn next_header(&self) -> Protocol {
+        let data = self.buffer.as_ref();
+        Protocol::from(data[field::NXT_HDR])
+    }
+
+    /// Return the header length field, in 8-octet units, not including the first 8 octets.
+    #[inline]
+    pub fn header_len(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[field::LENGTH]
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> Header<&'a T> {
+    /// Return the options of the header.
+    #[inline]
+    pub fn options(&self) -> &'a [u8] {
+        let data = self.buffer.as_ref();
+        &data[field::OPTIONS(data[field::LENGTH])]
+    }
+
+    /// Return the data following the header.
+    #[inline]
+    pub fn payload(&self) -> &'a [u8] {
+        let data = self.buffer.as_ref();
+        &data[field::OPTIONS(data[field::LENGTH]).end..]
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Header<T> {
+    /// Set the next header field.
+    #[inline]
+    pub fn set_next_header(&mut self, value: Protocol) {
+        let data = self.buffer.as_mut();
+        data[field::NXT_HDR] = value.into();
+    }
+
+    /// Set the header length field, in 8-octet units, not including the first 8 octets.
+    #[inline]
+    pub fn set_header_len(&mut self, value: u8) {
+        let data = self.buffer.as_mut();
+        data[field::LENGTH] = value;
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + AsMut<[u8]> + ?Sized> Header<&'a mut T> {
+    /// Return a mutable pointer to the options of the header.
+    #[inline]
+    pub fn options_mut(&mut self) -> &mut [u8] {
+        let data = self.buffer.as_mut();
+        let range = field::OPTIONS(data[field::LENGTH]);
+        &mut data[range]
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> fmt::Display for Header<&'a T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match Repr::parse(self) {
+            Ok(repr) => write!(f, "{}", repr),
+            Err(err) => write!(f, "IPv6 Hop-by-Hop Options ({})", err)
+        }
+    }
+}
+
+/// A high-level representation of an IPv6 Hop-by-Hop Options Header.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Repr<'a> {
+    /// The type of header immediately following the Hop-by-Hop Options header.
+    pub next_header: Protocol,
+    /// The options, including any padding; the header is a multiple of 8 octets long,
+    /// so there are 6 octets of options, plus a multiple of 8.
+    pub options:     &'a [u8],
+}
+
+impl<'a> Repr<'a> {
+    /// Parse an IPv6 Hop-by-Hop Options Header and return a high-level representation.
+    pub fn parse<T>(header: &Header<&'a T>) -> Result<Repr<'a>> where T: AsRef<[u8]> + ?Sized {
+        header.check_len()?;
+        Ok(Repr {
+            next_header: header.next_header(),
+            options:     header.options()
+        })
+    }
+
+    /// Return an iterator over the options.
+    pub fn iter_options(&self) -> Ipv6OptionIter<'a> {
+        Ipv6OptionIter::new(self.options)
+    }
+
+    /// Return the length, in bytes, of a header that will be emitted from this high-level
+    /// representation.
+    pub fn buffer_len(&self) -> usize {
+        field::OPTIONS(0).start + self.options.len()
+    }
+
+    /// Emit a high-level representation into an IPv6 Hop-by-Hop Options Header.
+    ///
+    /// # Panics
+    /// This function panics if the options do not make the header a multiple
+    /// of 8 octets long.
+    pub fn emit<T: AsRef<[u8]> + AsMut<[u8]> + ?Sized>(&self, header: &mut Header<&mut T>) {
+        assert!(self.buffer_len() % 8 == 0, "options are not padded to a multiple of 8 octets");
+        header.set_next_header(self.next_header);
+        header.set_header_len((self.buffer_len() / 8 - 1) as u8);
+        header.options_mut().copy_from_slice(self.options);
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Repr<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Repr<'a>> {
+        let length = u.int_in_range(0..=u.len().saturating_sub(6).min(0xff * 8) / 8)?;
+        Ok(Repr {
+            next_header: u.arbitrary()?,
+            options:     u.bytes(6 + length * 8)?
+        })
+    }
+}
+
+impl<'a> fmt::Display for Repr<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "IPv6 Hop-by-Hop Options next_hdr={} length={}",
+               self.next_header, self.buffer_len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wire::{Ipv6OptionRepr, Ipv6RouterAlert};
+
+    // A Router Alert for an MLD message, padded with PadN, followed by a single payload octet.
+    static BYTES_HOP_BY_HOP: [u8; 9] = [0x3a, 0x00, 0x05, 0x02,
+                                        0x00, 0x00, 0x01, 0x00,
+                                        0xff];
+
+    #[test]
+    fn test_check_len() {
+        assert_eq!(Header::new(&BYTES_HOP_BY_HOP[..1]).check_len(), Err(Error::Truncated));
+        assert_eq!(Header::new(&BYTES_HOP_BY_HOP[..7]).check_len(), Err(Error::Truncated));
+        assert_eq!(Header::new(&BYTES_HOP_BY_HOP[..8]).check_len(), Ok(()));
+    }
+
+    #[test]
+    fn test_deconstruct() {
+        let header = Header::new(&BYTES_HOP_BY_HOP[..]);
+        assert_eq!(header.next_header(), Protocol::Icmpv6);
+        assert_eq!(header.header_len(), 0);
+        assert_eq!(header.options(), &BYTES_HOP_BY_HOP[2..8]);
+        assert_eq!(header.payload(), &[0xff]);
+    }
+
+    #[test]
+    fn test_parse() {
+        let header = Header::new(&BYTES_HOP_BY_HOP[..]);
+        let repr = Repr::parse(&header).unwrap();
+        assert_eq!(repr.next_header, Protocol::Icmpv6);
+        let mut options = repr.iter_options();
+        assert_eq!(options.next(),
+                   Some(Ok(Ipv6OptionRepr::RouterAlert(
+                       Ipv6RouterAlert::MulticastListenerDiscovery))));
+        assert_eq!(options.next(), Some(Ok(Ipv6OptionRepr::PadN(0))));
+        assert_eq!(options.next(), None);
+    }
+
+    #[test]
+    fn test_emit() {
+        let repr = Repr { next_header: Protocol::Icmpv6, options: &BYTES_HOP_BY_HOP[2..8] };
+        let mut bytes = [0xff; 9];
+        repr.emit(&mut Header::new(&mut bytes[..]));
+        assert_eq!(&bytes[..], &BYTES_HOP_BY_HOP[..]);
+    }
+
+    #[test]
+    #[cfg(feature = "arbitrary")]
+    fn test_arbitrary_round_trip() {
+        use arbitrary::Unstructured;
+        use wire::ip::test::arbitrary_inputs;
+
+        for input in arbitrary_inputs() {
+            let repr: Repr = match Unstructured::new(&input).arbitrary() {
+                Ok(repr) => repr,
+                Err(_) => continue
+            };
+            let mut bytes = vec![0xa5; repr.buffer_len()];
+            repr.emit(&mut Header::new(&mut bytes[..]));
+            assert_eq!(Repr::parse(&Header::new(&bytes[..])), Ok(repr));
+        }
+    }
+}
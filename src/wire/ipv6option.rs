@@ -1,5 +1,8 @@
 use core::fmt;
+use byteorder::{ByteOrder, NetworkEndian};
 use {Error, Result};
+#[cfg(feature = "arbitrary")]
+use arbitrary::{self, Arbitrary, Unstructured};
 
 enum_with_unknown! {
     /// IPv6 Extension Header Option Type
@@ -7,7 +10,9 @@ enum_with_unknown! {
         /// 1 byte of padding
         Pad1 =  0,
         /// Multiple bytes of padding
-        PadN =  1
+        PadN =  1,
+        /// Router Alert
+        RouterAlert = 5
     }
 }
 
@@ -16,11 +21,25 @@ impl fmt::Display for Type {
         match self {
             &Type::Pad1        => write!(f, "Pad1"),
             &Type::PadN        => write!(f, "PadN"),
+            &Type::RouterAlert => write!(f, "Router Alert"),
             &Type::Unknown(id) => write!(f, "{}", id)
         }
     }
 }
 
+enum_with_unknown! {
+    /// The value of a Router Alert option, telling the routers what the packet
+    /// is of interest to.
+    pub doc enum RouterAlert(u16) {
+        /// Multicast Listener Discovery message
+        MulticastListenerDiscovery = 0,
+        /// RSVP message
+        Rsvp = 1,
+        /// Active Networks message
+        ActiveNetworks = 2
+    }
+}
+
 /// A read/write wrapper around an IPv6 Extension Header Option.
 #[derive(Debug, PartialEq)]
 pub struct Ipv6Option<T: AsRef<[u8]>> {
@@ -179,6 +198,7 @@ impl<'a, T: AsRef<[u8]> + ?Sized> fmt::Display for Ipv6Option<&'a T> {
 pub enum Repr<'a> {
     Pad1,
     PadN(u8),
+    RouterAlert(RouterAlert),
     Unknown {
         type_:  u8,
         length: u8,
@@ -191,12 +211,16 @@ pub enum Repr<'a> {
 
 impl<'a> Repr<'a> {
     /// Parse an IPv6 Extension Header Option and return a high-level representation.
-    pub fn parse<T>(opt: &'a Ipv6Option<&'a T>) -> Result<Repr<'a>> where T: AsRef<[u8]> + ?Sized {
+    pub fn parse<T>(opt: &Ipv6Option<&'a T>) -> Result<Repr<'a>> where T: AsRef<[u8]> + ?Sized {
         match opt.option_type() {
             Type::Pad1 =>
                 Ok(Repr::Pad1),
             Type::PadN =>
                 Ok(Repr::PadN(opt.data_len())),
+            Type::RouterAlert => {
+                if opt.data_len() != 2 { return Err(Error::Malformed) }
+                Ok(Repr::RouterAlert(RouterAlert::from(NetworkEndian::read_u16(opt.data()))))
+            }
             Type::Unknown(type_) => {
                 Ok(Repr::Unknown {
                     type_:  type_,
@@ -213,6 +237,8 @@ impl<'a> Repr<'a> {
             &Repr::Pad1 => 1,
             &Repr::PadN(length) =>
                 field::DATA(length).end,
+            &Repr::RouterAlert(_) =>
+                field::DATA(2).end,
             &Repr::Unknown{ length, .. } =>
                 field::DATA(length).end,
 
@@ -233,6 +259,11 @@ impl<'a> Repr<'a> {
                     *x = 0
                 }
             }
+            &Repr::RouterAlert(value) => {
+                opt.set_option_type(Type::RouterAlert);
+                opt.set_data_len(2);
+                NetworkEndian::write_u16(opt.data_mut(), value.into());
+            }
             &Repr::Unknown{ type_, length, data } => {
                 opt.set_option_type(Type::Unknown(type_));
                 opt.set_data_len(length);
@@ -244,6 +275,58 @@ impl<'a> Repr<'a> {
     }
 }
 
+/// An iterator over the options in the data of an IPv6 extension header.
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    data:      &'a [u8],
+    pos:       usize,
+    hit_error: bool,
+}
+
+impl<'a> Iter<'a> {
+    /// Create an iterator over the options in the given data.
+    pub fn new(data: &'a [u8]) -> Iter<'a> {
+        Iter { data, pos: 0, hit_error: false }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Result<Repr<'a>>;
+
+    fn next(&mut self) -> Option<Result<Repr<'a>>> {
+        if self.pos >= self.data.len() || self.hit_error { return None }
+
+        let opt = Ipv6Option::new(&self.data[self.pos..]);
+        let repr = opt.check_len().and_then(|()| Repr::parse(&opt));
+        match repr {
+            Ok(repr) => self.pos += repr.buffer_len(),
+            Err(_) => self.hit_error = true
+        }
+        Some(repr)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Repr<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Repr<'a>> {
+        match u.int_in_range(0..=3)? {
+            0 => Ok(Repr::Pad1),
+            1 => Ok(Repr::PadN(u.arbitrary()?)),
+            2 => Ok(Repr::RouterAlert(RouterAlert::from(u.arbitrary::<u16>()?))),
+            _ => {
+                // Types 0, 1 and 5 would be parsed as known options.
+                let type_ = match u.int_in_range(2..=0xff)? {
+                    5 => 0xff,
+                    type_ => type_
+                };
+                let length = u.int_in_range(0..=u.len().min(0xff))?;
+                let data = u.bytes(length)?;
+                Ok(Repr::Unknown { type_: type_, length: length as u8, data: data })
+            }
+        }
+    }
+}
+
 impl<'a> fmt::Display for Repr<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "IPv6 Option ")?;
@@ -252,6 +335,8 @@ impl<'a> fmt::Display for Repr<'a> {
                 write!(f, "{} ", Type::Pad1),
             &Repr::PadN(len) =>
                 write!(f, "{} length={} ", Type::PadN, len),
+            &Repr::RouterAlert(value) =>
+                write!(f, "{} value={} ", Type::RouterAlert, u16::from(value)),
             &Repr::Unknown{ type_, length, .. } =>
                 write!(f, "{} length={} ", Type::Unknown(type_), length),
 
@@ -371,4 +456,47 @@ mod test {
         repr.emit(&mut opt);
         assert_eq!(opt.into_inner(), &IPV6OPTION_BYTES_UNKNOWN);
     }
+
+    #[test]
+    fn test_router_alert() {
+        let bytes: [u8; 4] = [0x05, 0x02, 0x00, 0x00];
+        let opt = Ipv6Option::new(&bytes);
+        let repr = Repr::parse(&opt).unwrap();
+        assert_eq!(repr, Repr::RouterAlert(RouterAlert::MulticastListenerDiscovery));
+
+        let mut buf = [0xffu8; 4];
+        repr.emit(&mut Ipv6Option::new(&mut buf));
+        assert_eq!(buf, bytes);
+
+        let bytes: [u8; 3] = [0x05, 0x01, 0x00];
+        assert_eq!(Repr::parse(&Ipv6Option::new(&bytes)), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn test_iter() {
+        let bytes: [u8; 8] = [0x05, 0x02, 0x00, 0x00, 0x01, 0x00, 0x00, 0xff];
+        let mut iter = Iter::new(&bytes);
+        assert_eq!(iter.next(), Some(Ok(Repr::RouterAlert(RouterAlert::MulticastListenerDiscovery))));
+        assert_eq!(iter.next(), Some(Ok(Repr::PadN(0))));
+        assert_eq!(iter.next(), Some(Ok(Repr::Pad1)));
+        assert_eq!(iter.next(), Some(Err(Error::Truncated)));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "arbitrary")]
+    fn test_arbitrary_round_trip() {
+        use arbitrary::Unstructured;
+        use wire::ip::test::arbitrary_inputs;
+
+        for input in arbitrary_inputs() {
+            let repr: Repr = match Unstructured::new(&input).arbitrary() {
+                Ok(repr) => repr,
+                Err(_) => continue
+            };
+            let mut bytes = vec![0xa5; repr.buffer_len()];
+            repr.emit(&mut Ipv6Option::new(&mut bytes[..]));
+            assert_eq!(Repr::parse(&Ipv6Option::new(&bytes[..])), Ok(repr));
+        }
+    }
 }
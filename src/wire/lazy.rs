@@ -0,0 +1,407 @@
+use core::cell::Cell;
+
+use {Error, Result};
+use super::{EthernetFrame, EthernetProtocol, IpVersion, IpProtocol, IpAddress,
+            TcpPacket, UdpPacket};
+#[cfg(feature = "proto-ipv4")]
+use super::Ipv4Packet;
+#[cfg(feature = "proto-ipv6")]
+use super::{Ipv6Packet, Ipv6HopByHopHeader};
+
+/// The layer a buffer viewed by a [LazyPacket](struct.LazyPacket.html) starts with.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Layer {
+    /// An Ethernet frame, as received by a packet socket.
+    Ethernet,
+    /// An IPv4 or IPv6 packet, as received by a raw socket with the header included.
+    Ip
+}
+
+/// The location of the upper layer of an IP packet in the viewed buffer.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Upper {
+    protocol: IpProtocol,
+    start:    usize,
+    end:      usize
+}
+
+/// A read-only view of a received packet that parses its headers on demand.
+///
+/// The view borrows a single buffer, holding an Ethernet frame or an IP packet,
+/// and hands out the header of each layer, or the payload, as a slice of that buffer;
+/// nothing is copied. Each layer is only parsed when it, or a layer above it, is
+/// asked for, and the location of the upper layer of an IP packet is remembered,
+/// so a dissector may look at the headers of every layer without walking the lower
+/// ones again.
+///
+/// Every accessor returns `Err(Error::Truncated)` if the buffer is too short for
+/// the headers it describes, and `Err(Error::Unrecognized)` if the packet does not
+/// have the requested layer, e.g. when asking for the Ethernet header of an IP packet,
+/// or for the UDP header of a TCP segment.
+///
+/// The Hop-by-Hop Options header of an IPv6 packet is skipped; the upper layer
+/// of a packet with other extension headers is reported as the first of them,
+/// e.g. `IpProtocol::Ipv6Frag` for a fragment, since only the first fragment carries
+/// the header of the upper layer.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "proto-ipv4")]
+/// # fn dissect(frame: &[u8]) -> smoltcp::Result<()> {
+/// use smoltcp::wire::{LazyPacket, IpProtocol};
+///
+/// let packet = LazyPacket::new_ethernet(frame);
+/// if packet.ip_protocol()? == IpProtocol::Udp && packet.udp()?.dst_port() == 53 {
+///     let query = packet.payload()?;
+///     // ...
+/// #   let _ = query;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LazyPacket<'a> {
+    buffer: &'a [u8],
+    layer:  Layer,
+    upper:  Cell<Option<Upper>>
+}
+
+impl<'a> LazyPacket<'a> {
+    /// Create a view of the given buffer, which starts with the given layer.
+    pub fn new(buffer: &'a [u8], layer: Layer) -> LazyPacket<'a> {
+        LazyPacket { buffer: buffer, layer: layer, upper: Cell::new(None) }
+    }
+
+    /// Create a view of the given Ethernet frame.
+    pub fn new_ethernet(buffer: &'a [u8]) -> LazyPacket<'a> {
+        LazyPacket::new(buffer, Layer::Ethernet)
+    }
+
+    /// Create a view of the given IP packet.
+    pub fn new_ip(buffer: &'a [u8]) -> LazyPacket<'a> {
+        LazyPacket::new(buffer, Layer::Ip)
+    }
+
+    /// Return the viewed buffer.
+    pub fn buffer(&self) -> &'a [u8] {
+        self.buffer
+    }
+
+    /// Return the layer the viewed buffer starts with.
+    pub fn layer(&self) -> Layer {
+        self.layer
+    }
+
+    /// Return the Ethernet frame.
+    pub fn ethernet(&self) -> Result<EthernetFrame<&'a [u8]>> {
+        match self.layer {
+            Layer::Ethernet => EthernetFrame::new_checked(self.buffer),
+            Layer::Ip => Err(Error::Unrecognized)
+        }
+    }
+
+    /// Return the octets of the IP packet, which may be followed by padding.
+    fn ip_buffer(&self) -> Result<&'a [u8]> {
+        match self.layer {
+            Layer::Ethernet => {
+                let frame = self.ethernet()?;
+                match frame.ethertype() {
+                    #[cfg(feature = "proto-ipv4")]
+                    EthernetProtocol::Ipv4 => Ok(frame.payload()),
+                    #[cfg(feature = "proto-ipv6")]
+                    EthernetProtocol::Ipv6 => Ok(frame.payload()),
+                    _ => Err(Error::Unrecognized)
+                }
+            }
+            Layer::Ip => Ok(self.buffer)
+        }
+    }
+
+    /// Return the offset of the IP packet in the viewed buffer.
+    fn ip_offset(&self) -> usize {
+        match self.layer {
+            Layer::Ethernet => EthernetFrame::<&[u8]>::header_len(),
+            Layer::Ip => 0
+        }
+    }
+
+    /// Return the version of the IP packet.
+    pub fn ip_version(&self) -> Result<IpVersion> {
+        let buffer = self.ip_buffer()?;
+        if buffer.is_empty() { return Err(Error::Truncated) }
+        IpVersion::of_packet(buffer)
+    }
+
+    /// Return the IPv4 packet.
+    #[cfg(feature = "proto-ipv4")]
+    pub fn ipv4(&self) -> Result<Ipv4Packet<&'a [u8]>> {
+        match self.ip_version()? {
+            IpVersion::Ipv4 => Ipv4Packet::new_checked(self.ip_buffer()?),
+            _ => Err(Error::Unrecognized)
+        }
+    }
+
+    /// Return the IPv6 packet.
+    #[cfg(feature = "proto-ipv6")]
+    pub fn ipv6(&self) -> Result<Ipv6Packet<&'a [u8]>> {
+        match self.ip_version()? {
+            IpVersion::Ipv6 => Ipv6Packet::new_checked(self.ip_buffer()?),
+            _ => Err(Error::Unrecognized)
+        }
+    }
+
+    /// Return the source and destination addresses of the IP packet.
+    pub fn ip_addrs(&self) -> Result<(IpAddress, IpAddress)> {
+        match self.ip_version()? {
+            #[cfg(feature = "proto-ipv4")]
+            IpVersion::Ipv4 => {
+                let packet = self.ipv4()?;
+                Ok((packet.src_addr().into(), packet.dst_addr().into()))
+            }
+            #[cfg(feature = "proto-ipv6")]
+            IpVersion::Ipv6 => {
+                let packet = self.ipv6()?;
+                Ok((packet.src_addr().into(), packet.dst_addr().into()))
+            }
+            _ => Err(Error::Unrecognized)
+        }
+    }
+
+    /// Locate the upper layer of the IP packet, and remember where it is.
+    fn upper(&self) -> Result<Upper> {
+        if let Some(upper) = self.upper.get() { return Ok(upper) }
+
+        let offset = self.ip_offset();
+        let upper = match self.ip_version()? {
+            #[cfg(feature = "proto-ipv4")]
+            IpVersion::Ipv4 => {
+                let packet = self.ipv4()?;
+                let start = offset + packet.header_len() as usize;
+                Upper {
+                    protocol: packet.protocol(),
+                    start:    start,
+                    end:      start + packet.payload().len()
+                }
+            }
+            #[cfg(feature = "proto-ipv6")]
+            IpVersion::Ipv6 => {
+                let packet = self.ipv6()?;
+                let mut protocol = packet.next_header();
+                let mut start = offset + packet.header_len();
+                let end = start + packet.payload().len();
+                while protocol == IpProtocol::HopByHop {
+                    let header = Ipv6HopByHopHeader::new_checked(&self.buffer[start..end])?;
+                    protocol = header.next_header();
+                    start += header.header_len() as usize * 8 + 8;
+                }
+                Upper { protocol: protocol, start: start, end: end }
+            }
+            _ => return Err(Error::Unrecognized)
+        };
+        self.upper.set(Some(upper));
+        Ok(upper)
+    }
+
+    /// Return the protocol of the upper layer of the IP packet.
+    pub fn ip_protocol(&self) -> Result<IpProtocol> {
+        Ok(self.upper()?.protocol)
+    }
+
+    /// Return the payload of the IP packet, past its extension headers, if any.
+    pub fn ip_payload(&self) -> Result<&'a [u8]> {
+        let upper = self.upper()?;
+        Ok(&self.buffer[upper.start..upper.end])
+    }
+
+    /// Return the TCP segment.
+    pub fn tcp(&self) -> Result<TcpPacket<&'a [u8]>> {
+        match self.ip_protocol()? {
+            IpProtocol::Tcp => TcpPacket::new_checked(self.ip_payload()?),
+            _ => Err(Error::Unrecognized)
+        }
+    }
+
+    /// Return the UDP datagram.
+    pub fn udp(&self) -> Result<UdpPacket<&'a [u8]>> {
+        match self.ip_protocol()? {
+            IpProtocol::Udp => UdpPacket::new_checked(self.ip_payload()?),
+            _ => Err(Error::Unrecognized)
+        }
+    }
+
+    /// Return the payload of the TCP segment or UDP datagram, or the payload
+    /// of the IP packet if it carries another protocol.
+    pub fn payload(&self) -> Result<&'a [u8]> {
+        match self.ip_protocol()? {
+            IpProtocol::Tcp => Ok(self.tcp()?.payload()),
+            IpProtocol::Udp => Ok(self.udp()?.payload()),
+            _ => self.ip_payload()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use phy::ChecksumCapabilities;
+    use wire::{EthernetAddress, UdpRepr};
+    #[cfg(feature = "proto-ipv4")]
+    use wire::{Ipv4Address, Ipv4Repr, IpRepr, TcpRepr, TcpControl, TcpSeqNumber,
+               TCP_SACK_RANGE_COUNT};
+    #[cfg(feature = "proto-ipv6")]
+    use wire::{Ipv6Address, Ipv6Repr, Ipv6HopByHopRepr};
+    use super::*;
+
+    fn ethernet_frame(ethertype: EthernetProtocol, ip_packet: &[u8]) -> Vec<u8> {
+        // Short frames are padded, which the view has to see through.
+        let len = (EthernetFrame::<&[u8]>::buffer_len(ip_packet.len())).max(60);
+        let mut bytes = vec![0; len];
+        {
+            let mut frame = EthernetFrame::new(&mut bytes);
+            frame.set_dst_addr(EthernetAddress([0x02, 0, 0, 0, 0, 1]));
+            frame.set_src_addr(EthernetAddress([0x02, 0, 0, 0, 0, 2]));
+            frame.set_ethertype(ethertype);
+            frame.payload_mut()[..ip_packet.len()].copy_from_slice(ip_packet);
+        }
+        bytes
+    }
+
+    #[cfg(feature = "proto-ipv4")]
+    fn udp_packet(payload: &[u8]) -> Vec<u8> {
+        let src_addr = Ipv4Address::new(10, 0, 0, 2);
+        let dst_addr = Ipv4Address::new(10, 0, 0, 1);
+        let udp_repr = UdpRepr { src_port: 1234, dst_port: 53, payload: payload };
+        let ip_repr = IpRepr::Ipv4(Ipv4Repr {
+            src_addr:    src_addr,
+            dst_addr:    dst_addr,
+            protocol:    IpProtocol::Udp,
+            payload_len: udp_repr.buffer_len(),
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0
+        });
+        let mut bytes = vec![0; ip_repr.total_len()];
+        ip_repr.emit(&mut bytes[..], &ChecksumCapabilities::default());
+        udp_repr.emit(&mut UdpPacket::new(&mut bytes[ip_repr.buffer_len()..]),
+                      &src_addr.into(), &dst_addr.into(), &ChecksumCapabilities::default());
+        bytes
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_ethernet_udp() {
+        let frame = ethernet_frame(EthernetProtocol::Ipv4, &udp_packet(b"query"));
+        let packet = LazyPacket::new_ethernet(&frame);
+        assert_eq!(packet.ethernet().unwrap().ethertype(), EthernetProtocol::Ipv4);
+        assert_eq!(packet.ip_version(), Ok(IpVersion::Ipv4));
+        assert_eq!(packet.ip_addrs(), Ok((IpAddress::v4(10, 0, 0, 2),
+                                          IpAddress::v4(10, 0, 0, 1))));
+        assert_eq!(packet.ip_protocol(), Ok(IpProtocol::Udp));
+        assert_eq!(packet.udp().unwrap().dst_port(), 53);
+        assert_eq!(packet.tcp().err(), Some(Error::Unrecognized));
+        // The padding of the frame is not a part of the payload.
+        assert_eq!(packet.payload(), Ok(&b"query"[..]));
+        assert_eq!(packet.ip_payload().map(|payload| payload.len()), Ok(8 + 5));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_ip_tcp() {
+        let src_addr = Ipv4Address::new(10, 0, 0, 2);
+        let dst_addr = Ipv4Address::new(10, 0, 0, 1);
+        let tcp_repr = TcpRepr {
+            src_port:     49152,
+            dst_port:     80,
+            control:      TcpControl::Psh,
+            seq_number:   TcpSeqNumber(0x01234567),
+            ack_number:   Some(TcpSeqNumber(0x76543210)),
+            window_len:   1024,
+            max_seg_size: None,
+            sack_permitted: false,
+            sack_ranges:  [None; TCP_SACK_RANGE_COUNT],
+            timestamp:    None,
+            md5_signature: None,
+            ece:          false,
+            cwr:          false,
+            urgent_at:    None,
+            payload:      b"GET /"
+        };
+        let ip_repr = IpRepr::Ipv4(Ipv4Repr {
+            src_addr:    src_addr,
+            dst_addr:    dst_addr,
+            protocol:    IpProtocol::Tcp,
+            payload_len: tcp_repr.buffer_len(),
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0
+        });
+        let mut bytes = vec![0; ip_repr.total_len()];
+        ip_repr.emit(&mut bytes[..], &ChecksumCapabilities::default());
+        tcp_repr.emit(&mut TcpPacket::new(&mut bytes[ip_repr.buffer_len()..]),
+                      &src_addr.into(), &dst_addr.into(), &ChecksumCapabilities::default());
+
+        let packet = LazyPacket::new_ip(&bytes);
+        assert_eq!(packet.ethernet().err(), Some(Error::Unrecognized));
+        assert_eq!(packet.ip_protocol(), Ok(IpProtocol::Tcp));
+        assert_eq!(packet.udp().err(), Some(Error::Unrecognized));
+        assert_eq!(packet.payload(), Ok(&b"GET /"[..]));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv6")]
+    fn test_ipv6_extension_headers() {
+        let src_addr = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 2);
+        let dst_addr = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        let udp_repr = UdpRepr { src_port: 1234, dst_port: 53, payload: b"query" };
+        // A Hop-by-Hop Options header holding a PadN option.
+        let hbh_repr = Ipv6HopByHopRepr {
+            next_header: IpProtocol::Udp,
+            options:     &[0x01, 0x04, 0x00, 0x00, 0x00, 0x00]
+        };
+        let ipv6_repr = Ipv6Repr {
+            src_addr:    src_addr,
+            dst_addr:    dst_addr,
+            next_header: IpProtocol::HopByHop,
+            payload_len: hbh_repr.buffer_len() + udp_repr.buffer_len(),
+            hop_limit:   64,
+            flow_label:  0,
+            ecn:         0,
+            dscp:        0
+        };
+        let mut bytes = vec![0; ipv6_repr.buffer_len() + ipv6_repr.payload_len];
+        ipv6_repr.emit(&mut Ipv6Packet::new(&mut bytes));
+        {
+            let payload = &mut bytes[ipv6_repr.buffer_len()..];
+            hbh_repr.emit(&mut Ipv6HopByHopHeader::new(&mut payload[..8]));
+            udp_repr.emit(&mut UdpPacket::new(&mut payload[8..]),
+                          &src_addr.into(), &dst_addr.into(),
+                          &ChecksumCapabilities::default());
+        }
+
+        let frame = ethernet_frame(EthernetProtocol::Ipv6, &bytes);
+        let packet = LazyPacket::new_ethernet(&frame);
+        assert_eq!(packet.ip_addrs(), Ok((src_addr.into(), dst_addr.into())));
+        assert_eq!(packet.ipv6().unwrap().next_header(), IpProtocol::HopByHop);
+        assert_eq!(packet.ip_protocol(), Ok(IpProtocol::Udp));
+        assert_eq!(packet.payload(), Ok(&b"query"[..]));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let packet = LazyPacket::new_ethernet(&[0; 10]);
+        assert_eq!(packet.ethernet().err(), Some(Error::Truncated));
+        assert_eq!(packet.payload(), Err(Error::Truncated));
+
+        let packet = LazyPacket::new_ip(&[]);
+        assert_eq!(packet.ip_protocol(), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn test_unrecognized() {
+        let frame = ethernet_frame(EthernetProtocol::Arp, &[0; 28]);
+        let packet = LazyPacket::new_ethernet(&frame);
+        assert_eq!(packet.ip_version(), Err(Error::Unrecognized));
+        assert_eq!(packet.payload(), Err(Error::Unrecognized));
+    }
+}
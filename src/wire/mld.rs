@@ -0,0 +1,473 @@
+use core::fmt;
+use byteorder::{ByteOrder, NetworkEndian};
+
+use {Error, Result};
+use super::icmpv6::{field, Message, Packet};
+use super::Ipv6Address;
+
+enum_with_unknown! {
+    /// The type of a Multicast Address Record in an MLDv2 report.
+    pub doc enum RecordType(u8) {
+        /// The current filter mode of the group is include.
+        ModeIsInclude   = 0x01,
+        /// The current filter mode of the group is exclude.
+        ModeIsExclude   = 0x02,
+        /// The filter mode of the group changed to include.
+        ChangeToInclude = 0x03,
+        /// The filter mode of the group changed to exclude.
+        ChangeToExclude = 0x04,
+        /// Sources were added to the filter of the group.
+        AllowNewSources = 0x05,
+        /// Sources were removed from the filter of the group.
+        BlockOldSources = 0x06
+    }
+}
+
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &RecordType::ModeIsInclude   => write!(f, "mode is include"),
+            &RecordType::ModeIsExclude   => write!(f, "mode is exclude"),
+            &RecordType::ChangeToInclude => write!(f, "change to include"),
+            &RecordType::ChangeToExclude => write!(f, "change to exclude"),
+            &RecordType::AllowNewSources => write!(f, "allow new sources"),
+            &RecordType::BlockOldSources => write!(f, "block old sources"),
+            &RecordType::Unknown(id)     => write!(f, "{}", id)
+        }
+    }
+}
+
+/// The version of Multicast Listener Discovery a message belongs to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Version {
+    /// MLDv1, see RFC 2710.
+    Version1,
+    /// MLDv2, see RFC 3810.
+    Version2,
+}
+
+// Multicast Listener Query, Report and Done message fields. MLDv2 queries
+// carry further fields, and are at least 28 octets long.
+//
+//     0                   1                   2                   3
+//     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    |     Type      |     Code      |          Checksum             |
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    |    Maximum Response Code      |           Reserved            |
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    |                                                               |
+//    *                       Multicast Address                       *
+//    |                                                               |
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    | Resv  |S| QRV |     QQIC      |     Number of Sources (N)     |
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    |   Source Addresses ...
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-
+//
+// See https://tools.ietf.org/html/rfc3810#section-5.1 for details.
+mod query {
+    use wire::field::*;
+
+    pub const MAX_RESP_CODE: Field = 4..6;
+    pub const MCAST_ADDR:    Field = 8..24;
+    pub const SQRV:          usize = 24;
+    pub const QQIC:          usize = 25;
+    pub const NUM_SOURCES:   Field = 26..28;
+
+    pub const V2_END:        usize = 28;
+}
+
+// MLDv2 Report message fields, followed by the Multicast Address Records.
+//
+//     0                   1                   2                   3
+//     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    |  Type = 143   |    Reserved   |           Checksum            |
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    |           Reserved            |Nr of Mcast Address Records (M)|
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    |  Record Type  |  Aux Data Len |     Number of Sources (N)     |
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    |                                                               |
+//    *                       Multicast Address                       *
+//    |                                                               |
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    |   Source Addresses and Auxiliary Data ...
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-
+//
+// The record fields are relative to the end of the report header.
+// See https://tools.ietf.org/html/rfc3810#section-5.2 for details.
+mod report {
+    use wire::field::*;
+
+    pub const NUM_RECORDS:  Field = 6..8;
+
+    pub const RECORD_TYPE:  usize = 0;
+    pub const AUX_DATA_LEN: usize = 1;
+    pub const NUM_SOURCES:  Field = 2..4;
+    pub const MCAST_ADDR:   Field = 4..20;
+}
+
+/// Getters for the Multicast Listener Discovery message header.
+/// See [RFC 3810 § 5].
+///
+/// [RFC 3810 § 5]: https://tools.ietf.org/html/rfc3810#section-5
+impl<T: AsRef<[u8]>> Packet<T> {
+    /// Return the maximum response code field (for queries).
+    #[inline]
+    pub fn max_resp_code(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[query::MAX_RESP_CODE])
+    }
+
+    /// Return the multicast address field (for queries, MLDv1 reports and done messages).
+    #[inline]
+    pub fn mcast_addr(&self) -> Ipv6Address {
+        let data = self.buffer.as_ref();
+        Ipv6Address::from_bytes(&data[query::MCAST_ADDR])
+    }
+
+    /// Return the number of multicast address records field (for MLDv2 reports).
+    #[inline]
+    pub fn num_mcast_records(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[report::NUM_RECORDS])
+    }
+}
+
+/// Setters for the Multicast Listener Discovery message header.
+/// See [RFC 3810 § 5].
+///
+/// [RFC 3810 § 5]: https://tools.ietf.org/html/rfc3810#section-5
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
+    /// Set the maximum response code field, and clear the reserved field
+    /// that follows it (for queries, MLDv1 reports and done messages).
+    #[inline]
+    pub fn set_max_resp_code(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[query::MAX_RESP_CODE], value);
+        NetworkEndian::write_u16(&mut data[6..8], 0);
+    }
+
+    /// Set the multicast address field (for queries, MLDv1 reports and done messages).
+    #[inline]
+    pub fn set_mcast_addr(&mut self, value: Ipv6Address) {
+        let data = self.buffer.as_mut();
+        data[query::MCAST_ADDR].copy_from_slice(value.as_bytes());
+    }
+
+    /// Set the number of multicast address records field, and clear the reserved field
+    /// that precedes it (for MLDv2 reports).
+    #[inline]
+    pub fn set_num_mcast_records(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[4..6], 0);
+        NetworkEndian::write_u16(&mut data[report::NUM_RECORDS], value);
+    }
+}
+
+/// A high-level representation of a Multicast Listener Discovery message.
+///
+/// Source filtering is not represented: MLDv2 reports have a single address record
+/// without sources, and the sources of MLDv2 queries are ignored.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Repr {
+    /// A query for the listeners of a group, or of every group if the multicast
+    /// address is unspecified.
+    Query {
+        /// The time the listeners may delay their reports by, in milliseconds.
+        max_resp_delay: u64,
+        mcast_addr:     Ipv6Address,
+        version:        Version
+    },
+    /// An MLDv1 report.
+    Report {
+        mcast_addr: Ipv6Address
+    },
+    /// An MLDv1 done message.
+    Done {
+        mcast_addr: Ipv6Address
+    },
+    /// An MLDv2 report.
+    ReportV2 {
+        record_type: RecordType,
+        mcast_addr:  Ipv6Address
+    },
+    #[doc(hidden)]
+    __Nonexhaustive
+}
+
+impl Repr {
+    /// Parse a Multicast Listener Discovery packet and return a high-level representation.
+    ///
+    /// MLDv2 reports with several address records, or with sources, are not recognized.
+    /// The ICMPv6 checksum is not verified; use [Icmpv6Repr::parse] for that.
+    ///
+    /// [Icmpv6Repr::parse]: enum.Icmpv6Repr.html#method.parse
+    pub fn parse<T>(packet: &Packet<&T>) -> Result<Repr>
+            where T: AsRef<[u8]> + ?Sized {
+        let data = packet.buffer.as_ref();
+        match packet.msg_type() {
+            Message::MldQuery => {
+                // The version of a query is told by its length. See RFC 3810 § 8.1.
+                if data.len() >= query::V2_END {
+                    Ok(Repr::Query {
+                        max_resp_delay: max_resp_delay(packet.max_resp_code()),
+                        mcast_addr:     packet.mcast_addr(),
+                        version:        Version::Version2
+                    })
+                } else {
+                    Ok(Repr::Query {
+                        max_resp_delay: packet.max_resp_code() as u64,
+                        mcast_addr:     packet.mcast_addr(),
+                        version:        Version::Version1
+                    })
+                }
+            }
+            Message::MldReport =>
+                Ok(Repr::Report { mcast_addr: packet.mcast_addr() }),
+            Message::MldDone =>
+                Ok(Repr::Done { mcast_addr: packet.mcast_addr() }),
+            Message::MldReportV2 => {
+                let record = packet.payload();
+                if record.len() < report::MCAST_ADDR.end { return Err(Error::Truncated) }
+                if packet.num_mcast_records() != 1 || record[report::AUX_DATA_LEN] != 0 ||
+                        NetworkEndian::read_u16(&record[report::NUM_SOURCES]) != 0 {
+                    return Err(Error::Unrecognized)
+                }
+                Ok(Repr::ReportV2 {
+                    record_type: RecordType::from(record[report::RECORD_TYPE]),
+                    mcast_addr:  Ipv6Address::from_bytes(&record[report::MCAST_ADDR])
+                })
+            }
+            _ => Err(Error::Unrecognized)
+        }
+    }
+
+    /// Return the length of a packet that will be emitted from this high-level representation.
+    pub fn buffer_len(&self) -> usize {
+        match self {
+            &Repr::Query { version: Version::Version2, .. } => query::V2_END,
+            &Repr::Query { .. } | &Repr::Report { .. } | &Repr::Done { .. } => field::MLD_END,
+            &Repr::ReportV2 { .. } => field::MLD_REPORT_V2_END + report::MCAST_ADDR.end,
+            &Repr::__Nonexhaustive => unreachable!()
+        }
+    }
+
+    /// Emit a high-level representation into a Multicast Listener Discovery packet.
+    ///
+    /// The ICMPv6 checksum is not filled in; use [Icmpv6Repr::emit] for that.
+    ///
+    /// [Icmpv6Repr::emit]: enum.Icmpv6Repr.html#method.emit
+    pub fn emit<T>(&self, packet: &mut Packet<&mut T>)
+            where T: AsRef<[u8]> + AsMut<[u8]> + ?Sized {
+        match self {
+            &Repr::Query { max_resp_delay, mcast_addr, version } => {
+                packet.set_msg_type(Message::MldQuery);
+                packet.set_msg_code(0);
+                packet.set_mcast_addr(mcast_addr);
+                match version {
+                    Version::Version1 =>
+                        packet.set_max_resp_code(max_resp_delay.min(0xffff) as u16),
+                    Version::Version2 => {
+                        packet.set_max_resp_code(max_resp_code(max_resp_delay));
+                        let data = packet.buffer.as_mut();
+                        // The default Robustness Variable and Query Interval;
+                        // see RFC 3810 § 9.1 and § 9.2.
+                        data[query::SQRV] = 2;
+                        data[query::QQIC] = 125;
+                        NetworkEndian::write_u16(&mut data[query::NUM_SOURCES], 0);
+                    }
+                }
+            }
+            &Repr::Report { mcast_addr } => {
+                packet.set_msg_type(Message::MldReport);
+                packet.set_msg_code(0);
+                packet.set_max_resp_code(0);
+                packet.set_mcast_addr(mcast_addr);
+            }
+            &Repr::Done { mcast_addr } => {
+                packet.set_msg_type(Message::MldDone);
+                packet.set_msg_code(0);
+                packet.set_max_resp_code(0);
+                packet.set_mcast_addr(mcast_addr);
+            }
+            &Repr::ReportV2 { record_type, mcast_addr } => {
+                packet.set_msg_type(Message::MldReportV2);
+                packet.set_msg_code(0);
+                packet.set_num_mcast_records(1);
+                let record = packet.payload_mut();
+                record[report::RECORD_TYPE] = record_type.into();
+                record[report::AUX_DATA_LEN] = 0;
+                NetworkEndian::write_u16(&mut record[report::NUM_SOURCES], 0);
+                record[report::MCAST_ADDR].copy_from_slice(mcast_addr.as_bytes());
+            }
+            &Repr::__Nonexhaustive => unreachable!()
+        }
+    }
+}
+
+/// Decode an MLDv2 maximum response code into milliseconds. Codes of 32768 and above
+/// encode a larger time in floating point. See RFC 3810 § 5.1.3.
+fn max_resp_delay(code: u16) -> u64 {
+    if code < 0x8000 {
+        code as u64
+    } else {
+        let mantissa = (code & 0x0fff) as u64;
+        let exponent = ((code >> 12) & 0x07) as u64;
+        (mantissa | 0x1000) << (exponent + 3)
+    }
+}
+
+/// Encode a time in milliseconds into an MLDv2 maximum response code, rounding down.
+fn max_resp_code(delay: u64) -> u16 {
+    if delay < 0x8000 { return delay as u16 }
+
+    let mut exponent = 0;
+    while exponent < 7 && delay >> (exponent + 3) > 0x1fff {
+        exponent += 1
+    }
+    let mantissa = (delay >> (exponent + 3)).min(0x1fff) & 0x0fff;
+    0x8000 | ((exponent as u16) << 12) | mantissa as u16
+}
+
+impl fmt::Display for Repr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Repr::Query { max_resp_delay, mcast_addr, version } =>
+                write!(f, "MLD query group={} max_resp_delay={}ms version={:?}",
+                       mcast_addr, max_resp_delay, version),
+            &Repr::Report { mcast_addr } =>
+                write!(f, "MLD report group={}", mcast_addr),
+            &Repr::Done { mcast_addr } =>
+                write!(f, "MLD done group={}", mcast_addr),
+            &Repr::ReportV2 { record_type, mcast_addr } =>
+                write!(f, "MLDv2 report group={} record={}", mcast_addr, record_type),
+            &Repr::__Nonexhaustive => unreachable!()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use phy::ChecksumCapabilities;
+    use wire::Icmpv6Repr;
+    use super::*;
+
+    static QUERY_PACKET_BYTES: [u8; 28] =
+        [0x82, 0x00, 0x54, 0x72,
+         0x27, 0x10, 0x00, 0x00,
+         0x00, 0x00, 0x00, 0x00,
+         0x00, 0x00, 0x00, 0x00,
+         0x00, 0x00, 0x00, 0x00,
+         0x00, 0x00, 0x00, 0x00,
+         0x02, 0x7d, 0x00, 0x00];
+
+    static REPORT_V2_PACKET_BYTES: [u8; 28] =
+        [0x8f, 0x00, 0x3a, 0x91,
+         0x00, 0x00, 0x00, 0x01,
+         0x04, 0x00, 0x00, 0x00,
+         0xff, 0x02, 0x00, 0x00,
+         0x00, 0x00, 0x00, 0x00,
+         0x00, 0x00, 0x00, 0x01,
+         0xff, 0x12, 0x34, 0x56];
+
+    fn solicited_node() -> Ipv6Address {
+        Ipv6Address::new(0xff02, 0, 0, 0, 0, 1, 0xff12, 0x3456)
+    }
+
+    #[test]
+    fn test_query_deconstruct() {
+        let packet = Packet::new(&QUERY_PACKET_BYTES[..]);
+        assert_eq!(packet.msg_type(), Message::MldQuery);
+        assert_eq!(packet.max_resp_code(), 10_000);
+        assert_eq!(packet.mcast_addr(), Ipv6Address::UNSPECIFIED);
+        assert_eq!(packet.verify_checksum(), true);
+    }
+
+    #[test]
+    fn test_query_repr_parse() {
+        let packet = Packet::new(&QUERY_PACKET_BYTES[..]);
+        assert_eq!(Icmpv6Repr::parse(&packet, &ChecksumCapabilities::default()),
+                   Ok(Icmpv6Repr::Mld(Repr::Query {
+                       max_resp_delay: 10_000,
+                       mcast_addr:     Ipv6Address::UNSPECIFIED,
+                       version:        Version::Version2
+                   })));
+
+        // A query without the MLDv2 fields is an MLDv1 query.
+        let mut bytes = QUERY_PACKET_BYTES;
+        {
+            let mut packet = Packet::new(&mut bytes[..24]);
+            packet.fill_checksum();
+        }
+        let packet = Packet::new(&bytes[..24]);
+        match Icmpv6Repr::parse(&packet, &ChecksumCapabilities::default()) {
+            Ok(Icmpv6Repr::Mld(Repr::Query { version: Version::Version1, .. })) => (),
+            repr => panic!("unexpected {:?}", repr)
+        }
+    }
+
+    #[test]
+    fn test_query_emit() {
+        let repr = Icmpv6Repr::Mld(Repr::Query {
+            max_resp_delay: 10_000,
+            mcast_addr:     Ipv6Address::UNSPECIFIED,
+            version:        Version::Version2
+        });
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new(&mut bytes), &ChecksumCapabilities::default());
+        assert_eq!(&bytes[..], &QUERY_PACKET_BYTES[..]);
+    }
+
+    #[test]
+    fn test_report_v2_repr() {
+        let repr = Icmpv6Repr::Mld(Repr::ReportV2 {
+            record_type: RecordType::ChangeToExclude,
+            mcast_addr:  solicited_node()
+        });
+        let packet = Packet::new(&REPORT_V2_PACKET_BYTES[..]);
+        assert_eq!(Icmpv6Repr::parse(&packet, &ChecksumCapabilities::default()), Ok(repr));
+
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new(&mut bytes), &ChecksumCapabilities::default());
+        assert_eq!(&bytes[..], &REPORT_V2_PACKET_BYTES[..]);
+    }
+
+    #[test]
+    fn test_report_v2_sources() {
+        let mut bytes = vec![0; 44];
+        bytes[..28].copy_from_slice(&REPORT_V2_PACKET_BYTES[..]);
+        {
+            let mut packet = Packet::new(&mut bytes[..]);
+            NetworkEndian::write_u16(&mut packet.payload_mut()[report::NUM_SOURCES], 1);
+        }
+        let packet = Packet::new(&bytes[..]);
+        assert_eq!(Repr::parse(&packet), Err(Error::Unrecognized));
+    }
+
+    #[test]
+    fn test_v1_repr() {
+        for &repr in &[Repr::Report { mcast_addr: solicited_node() },
+                       Repr::Done { mcast_addr: solicited_node() }] {
+            let repr = Icmpv6Repr::Mld(repr);
+            let mut bytes = vec![0xa5; repr.buffer_len()];
+            repr.emit(&mut Packet::new(&mut bytes), &ChecksumCapabilities::default());
+            assert_eq!(bytes.len(), 24);
+            assert_eq!(Icmpv6Repr::parse(&Packet::new(&bytes), &ChecksumCapabilities::default()),
+                       Ok(repr));
+        }
+    }
+
+    #[test]
+    fn test_max_resp_delay() {
+        assert_eq!(max_resp_delay(10_000), 10_000);
+        assert_eq!(max_resp_delay(0x8000), 32_768);
+        assert_eq!(max_resp_delay(0xffff), 8_387_584);
+        assert_eq!(max_resp_code(10_000), 10_000);
+        assert_eq!(max_resp_code(32_768), 0x8000);
+        assert_eq!(max_resp_code(8_387_584), 0xffff);
+        assert_eq!(max_resp_delay(max_resp_code(100_001)), 100_000);
+    }
+}
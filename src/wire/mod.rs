@@ -87,18 +87,53 @@ mod ipv4;
 mod ipv6;
 #[cfg(feature = "proto-ipv6")]
 mod ipv6option;
+#[cfg(feature = "proto-ipv6")]
+mod ipv6fragment;
+#[cfg(feature = "proto-ipv6")]
+mod ipv6hopbyhop;
 #[cfg(feature = "proto-ipv4")]
 mod icmpv4;
 #[cfg(feature = "proto-ipv6")]
 mod icmpv6;
+#[cfg(feature = "proto-igmp")]
+mod igmp;
+#[cfg(feature = "proto-ipv6")]
+mod ndisc;
+#[cfg(feature = "proto-ipv6")]
+mod ndiscoption;
+#[cfg(feature = "proto-ipv6")]
+mod mld;
+#[cfg(feature = "proto-sixlowpan")]
+mod ieee802154;
+#[cfg(feature = "proto-sixlowpan")]
+mod sixlowpan;
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+mod icmp;
 mod udp;
 mod tcp;
+mod wol;
+mod eapol;
+mod ptp;
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+mod lazy;
+#[cfg(feature = "proto-dhcpv4")]
+pub(crate) mod dhcpv4;
+#[cfg(feature = "proto-dhcpv6")]
+pub(crate) mod dhcpv6;
+#[cfg(feature = "proto-dns")]
+pub(crate) mod dns;
+#[cfg(feature = "proto-sntp")]
+pub(crate) mod ntp;
+#[cfg(feature = "proto-tftp")]
+pub(crate) mod tftp;
 
 pub use self::pretty_print::PrettyPrinter;
 
 pub use self::ethernet::{EtherType as EthernetProtocol,
                          Address as EthernetAddress,
-                         Frame as EthernetFrame};
+                         Frame as EthernetFrame,
+                         FCS_LEN as ETHERNET_FCS_LEN,
+                         fcs as ethernet_fcs};
 
 #[cfg(feature = "proto-ipv4")]
 pub use self::arp::{Hardware as ArpHardware,
@@ -125,12 +160,23 @@ pub use self::ipv6::{Address as Ipv6Address,
                      Packet as Ipv6Packet,
                      Repr as Ipv6Repr,
                      Cidr as Ipv6Cidr,
+                     MulticastScope as Ipv6MulticastScope,
                      MIN_MTU as IPV6_MIN_MTU};
 
 #[cfg(feature = "proto-ipv6")]
 pub use self::ipv6option::{Ipv6Option,
                            Repr as Ipv6OptionRepr,
-                           Type as Ipv6OptionType};
+                           Type as Ipv6OptionType,
+                           RouterAlert as Ipv6RouterAlert,
+                           Iter as Ipv6OptionIter};
+
+#[cfg(feature = "proto-ipv6")]
+pub use self::ipv6fragment::{Header as Ipv6FragmentHeader,
+                             Repr as Ipv6FragmentRepr};
+
+#[cfg(feature = "proto-ipv6")]
+pub use self::ipv6hopbyhop::{Header as Ipv6HopByHopHeader,
+                             Repr as Ipv6HopByHopRepr};
 
 #[cfg(feature = "proto-ipv4")]
 pub use self::icmpv4::{Message as Icmpv4Message,
@@ -141,17 +187,159 @@ pub use self::icmpv4::{Message as Icmpv4Message,
                        Packet as Icmpv4Packet,
                        Repr as Icmpv4Repr};
 
+#[cfg(feature = "proto-igmp")]
+pub use self::igmp::{Message as IgmpMessage,
+                     Version as IgmpVersion,
+                     Packet as IgmpPacket,
+                     Repr as IgmpRepr};
+
 #[cfg(feature = "proto-ipv6")]
 pub use self::icmpv6::{Message as Icmpv6Message,
                        DstUnreachable as Icmpv6DstUnreachable,
+                       ParamProblem as Icmpv6ParamProblem,
                        Packet as Icmpv6Packet,
                        Repr as Icmpv6Repr};
 
+#[cfg(feature = "proto-ipv6")]
+pub use self::ndisc::{Repr as NdiscRepr,
+                      PREFIX_INFO_COUNT as NDISC_PREFIX_INFO_COUNT,
+                      ROUTE_INFO_COUNT as NDISC_ROUTE_INFO_COUNT};
+
+#[cfg(feature = "proto-ipv6")]
+pub use self::ndiscoption::{NdiscOption,
+                            Repr as NdiscOptionRepr,
+                            Type as NdiscOptionType,
+                            PrefixInfo as NdiscPrefixInfo,
+                            RouteInfo as NdiscRouteInfo,
+                            RoutePreference as NdiscRoutePreference};
+
+#[cfg(feature = "proto-ipv6")]
+pub use self::mld::{Repr as MldRepr,
+                    Version as MldVersion,
+                    RecordType as MldRecordType};
+
+#[cfg(feature = "proto-sixlowpan")]
+pub use self::ieee802154::{Frame as Ieee802154Frame,
+                           Repr as Ieee802154Repr,
+                           FrameType as Ieee802154FrameType,
+                           AddressingMode as Ieee802154AddressingMode,
+                           Address as Ieee802154Address,
+                           Pan as Ieee802154Pan};
+
+#[cfg(feature = "proto-sixlowpan")]
+pub use self::sixlowpan::{Dispatch as SixlowpanDispatch,
+                          FragmentHeader as SixlowpanFragmentHeader,
+                          FragmentRepr as SixlowpanFragmentRepr,
+                          IphcPacket as SixlowpanIphcPacket,
+                          IphcRepr as SixlowpanIphcRepr,
+                          UdpNhcPacket as SixlowpanUdpNhcPacket,
+                          UdpNhcRepr as SixlowpanUdpNhcRepr,
+                          is_udp_nhc as sixlowpan_is_udp_nhc,
+                          FIRST_FRAGMENT_HEADER_LEN as SIXLOWPAN_FIRST_FRAGMENT_HEADER_LEN,
+                          FRAGMENT_HEADER_LEN as SIXLOWPAN_FRAGMENT_HEADER_LEN};
+
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+pub use self::icmp::Repr as IcmpRepr;
+
 pub use self::udp::{Packet as UdpPacket,
                     Repr as UdpRepr};
 
+pub use self::wol::{Repr as WolRepr,
+                    Password as WolPassword,
+                    UDP_PORT as WOL_UDP_PORT};
+
+pub use self::eapol::{Frame as EapolFrame,
+                      Repr as EapolRepr,
+                      PacketType as EapolPacketType,
+                      EapPacket,
+                      EapRepr,
+                      EapCode,
+                      EapType,
+                      PAE_GROUP_ADDRESS as EAPOL_PAE_GROUP_ADDRESS,
+                      VERSION_2004 as EAPOL_VERSION_2004,
+                      HEADER_LEN as EAPOL_HEADER_LEN,
+                      EAP_HEADER_LEN};
+
+pub use self::ptp::{Packet as PtpPacket,
+                    Repr as PtpRepr,
+                    Message as PtpMessage,
+                    MessageType as PtpMessageType,
+                    Timestamp as PtpTimestamp,
+                    PortIdentity as PtpPortIdentity,
+                    EVENT_PORT as PTP_EVENT_PORT,
+                    GENERAL_PORT as PTP_GENERAL_PORT,
+                    MULTICAST_ADDR as PTP_MULTICAST_ADDR,
+                    VERSION as PTP_VERSION,
+                    FLAG_TWO_STEP as PTP_FLAG_TWO_STEP,
+                    HEADER_LEN as PTP_HEADER_LEN};
+#[cfg(feature = "proto-ipv4")]
+pub use self::ptp::IPV4_MULTICAST_ADDR as PTP_IPV4_MULTICAST_ADDR;
+#[cfg(feature = "proto-ipv6")]
+pub use self::ptp::IPV6_MULTICAST_ADDR as PTP_IPV6_MULTICAST_ADDR;
+
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+pub use self::lazy::{LazyPacket,
+                     Layer as LazyPacketLayer};
+
 pub use self::tcp::{SeqNumber as TcpSeqNumber,
                     Packet as TcpPacket,
                     TcpOption,
+                    SignedHeader as TcpSignedHeader,
+                    SACK_RANGE_COUNT as TCP_SACK_RANGE_COUNT,
                     Repr as TcpRepr,
                     Control as TcpControl};
+
+#[cfg(feature = "proto-dhcpv4")]
+pub use self::dhcpv4::{Packet as DhcpPacket,
+                       Repr as DhcpRepr,
+                       MessageType as DhcpMessageType,
+                       DhcpOption,
+                       SERVER_PORT as DHCP_SERVER_PORT,
+                       CLIENT_PORT as DHCP_CLIENT_PORT};
+
+#[cfg(feature = "proto-dhcpv6")]
+pub use self::dhcpv6::{Packet as Dhcpv6Packet,
+                       Repr as Dhcpv6Repr,
+                       MessageType as Dhcpv6MessageType,
+                       StatusCode as Dhcpv6StatusCode,
+                       IaNa as Dhcpv6IaNa,
+                       IaAddress as Dhcpv6IaAddress,
+                       SERVER_PORT as DHCPV6_SERVER_PORT,
+                       CLIENT_PORT as DHCPV6_CLIENT_PORT,
+                       ALL_RELAY_AGENTS_AND_SERVERS as DHCPV6_ALL_RELAY_AGENTS_AND_SERVERS};
+
+#[cfg(feature = "proto-dns")]
+pub use self::dns::{Packet as DnsPacket,
+                    Question as DnsQuestion,
+                    Record as DnsRecord,
+                    RecordData as DnsRecordData,
+                    Name as DnsName,
+                    Labels as DnsLabels,
+                    Type as DnsType,
+                    Opcode as DnsOpcode,
+                    Rcode as DnsRcode,
+                    SERVER_PORT as DNS_SERVER_PORT,
+                    MDNS_PORT,
+                    LLMNR_PORT};
+#[cfg(all(feature = "proto-dns", feature = "proto-ipv4"))]
+pub use self::dns::{MDNS_IPV4_GROUP, LLMNR_IPV4_GROUP};
+#[cfg(all(feature = "proto-dns", feature = "proto-ipv6"))]
+pub use self::dns::{MDNS_IPV6_GROUP, LLMNR_IPV6_GROUP};
+
+#[cfg(feature = "proto-sntp")]
+pub use self::ntp::{Packet as NtpPacket,
+                    Repr as NtpRepr,
+                    Timestamp as NtpTimestamp,
+                    LeapIndicator as NtpLeapIndicator,
+                    ProtocolMode as NtpMode,
+                    SERVER_PORT as NTP_SERVER_PORT};
+
+#[cfg(feature = "proto-tftp")]
+pub use self::tftp::{Packet as TftpPacket,
+                     Repr as TftpRepr,
+                     OpCode as TftpOpCode,
+                     ErrorCode as TftpErrorCode,
+                     Mode as TftpMode,
+                     Options as TftpOptions,
+                     SERVER_PORT as TFTP_SERVER_PORT,
+                     DEFAULT_BLOCK_SIZE as TFTP_DEFAULT_BLOCK_SIZE};
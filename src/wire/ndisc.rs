@@ -0,0 +1,655 @@
+use byteorder::{ByteOrder, NetworkEndian};
+
+use {Error, Result};
+use super::icmpv6::{field, Message, Packet};
+use super::ndiscoption::{Iter as NdiscOptionIter, NdiscOption,
+                         Repr as NdiscOptionRepr, PrefixInfo, RouteInfo};
+use super::{EthernetAddress, Ipv6Address};
+
+/// The maximum number of Prefix Information options in a Router Advertisement
+/// that are represented by [NdiscRepr](enum.NdiscRepr.html); the rest are ignored.
+pub const PREFIX_INFO_COUNT: usize = 4;
+
+/// The maximum number of Route Information options in a Router Advertisement
+/// that are represented by [NdiscRepr](enum.NdiscRepr.html); the rest are ignored.
+pub const ROUTE_INFO_COUNT: usize = 4;
+
+// Router Advertisement message fields.
+//
+//     0                   1                   2                   3
+//     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    |     Type      |     Code      |          Checksum             |
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    | Cur Hop Limit |M|O|  Reserved |       Router Lifetime         |
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    |                         Reachable Time                        |
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    |                          Retrans Timer                        |
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    |   Options ...
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-
+//
+// See https://tools.ietf.org/html/rfc4861#section-4.2 for details.
+mod ra {
+    use wire::field::*;
+
+    pub const CUR_HOP_LIMIT: usize = 4;
+    pub const ROUTER_FLAGS:  usize = 5;
+    pub const ROUTER_LT:     Field = 6..8;
+    pub const REACHABLE_TM:  Field = 8..12;
+    pub const RETRANS_TM:    Field = 12..16;
+
+    pub const FLAG_MANAGED:  u8 = 0x80;
+    pub const FLAG_OTHER:    u8 = 0x40;
+}
+
+// Neighbor Solicitation and Neighbor Advertisement message fields.
+//
+//     0                   1                   2                   3
+//     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    |     Type      |     Code      |          Checksum             |
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    |R|S|O|                     Reserved                            |
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    |                                                               |
+//    +                                                               +
+//    |                                                               |
+//    +                       Target Address                          +
+//    |                                                               |
+//    +                                                               +
+//    |                                                               |
+//    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//    |   Options ...
+//    +-+-+-+-+-+-+-+-+-+-+-+-
+//
+// The flags are only present in Neighbor Advertisements.
+// See https://tools.ietf.org/html/rfc4861#section-4.3 for details.
+mod neighbor {
+    use wire::field::*;
+
+    pub const NEIGH_FLAGS:    usize = 4;
+    pub const TARGET_ADDR:    Field = 8..24;
+
+    pub const FLAG_ROUTER:    u8 = 0x80;
+    pub const FLAG_SOLICITED: u8 = 0x40;
+    pub const FLAG_OVERRIDE:  u8 = 0x20;
+}
+
+/// Getters for the Router Advertisement message header.
+/// See [RFC 4861 § 4.2].
+///
+/// [RFC 4861 § 4.2]: https://tools.ietf.org/html/rfc4861#section-4.2
+impl<T: AsRef<[u8]>> Packet<T> {
+    /// Return the current hop limit field.
+    #[inline]
+    pub fn current_hop_limit(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[ra::CUR_HOP_LIMIT]
+    }
+
+    /// Return the managed address configuration flag.
+    #[inline]
+    pub fn managed_flag(&self) -> bool {
+        let data = self.buffer.as_ref();
+        data[ra::ROUTER_FLAGS] & ra::FLAG_MANAGED != 0
+    }
+
+    /// Return the other configuration flag.
+    #[inline]
+    pub fn other_flag(&self) -> bool {
+        let data = self.buffer.as_ref();
+        data[ra::ROUTER_FLAGS] & ra::FLAG_OTHER != 0
+    }
+
+    /// Return the router lifetime field, in seconds.
+    #[inline]
+    pub fn router_lifetime(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[ra::ROUTER_LT])
+    }
+
+    /// Return the reachable time field, in milliseconds.
+    #[inline]
+    pub fn reachable_time(&self) -> u32 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u32(&data[ra::REACHABLE_TM])
+    }
+
+    /// Return the retransmit time field, in milliseconds.
+    #[inline]
+    pub fn retrans_time(&self) -> u32 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u32(&data[ra::RETRANS_TM])
+    }
+}
+
+/// Setters for the Router Advertisement message header.
+/// See [RFC 4861 § 4.2].
+///
+/// [RFC 4861 § 4.2]: https://tools.ietf.org/html/rfc4861#section-4.2
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
+    /// Set the current hop limit field.
+    #[inline]
+    pub fn set_current_hop_limit(&mut self, value: u8) {
+        let data = self.buffer.as_mut();
+        data[ra::CUR_HOP_LIMIT] = value;
+    }
+
+    /// Set the managed address configuration and other configuration flags,
+    /// clearing the reserved bits.
+    #[inline]
+    pub fn set_router_flags(&mut self, managed: bool, other: bool) {
+        let mut flags = 0;
+        if managed { flags |= ra::FLAG_MANAGED }
+        if other   { flags |= ra::FLAG_OTHER }
+        self.buffer.as_mut()[ra::ROUTER_FLAGS] = flags;
+    }
+
+    /// Set the router lifetime field, in seconds.
+    #[inline]
+    pub fn set_router_lifetime(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[ra::ROUTER_LT], value);
+    }
+
+    /// Set the reachable time field, in milliseconds.
+    #[inline]
+    pub fn set_reachable_time(&mut self, value: u32) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u32(&mut data[ra::REACHABLE_TM], value);
+    }
+
+    /// Set the retransmit time field, in milliseconds.
+    #[inline]
+    pub fn set_retrans_time(&mut self, value: u32) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u32(&mut data[ra::RETRANS_TM], value);
+    }
+
+    /// Clear the reserved field (for router solicitation messages).
+    #[inline]
+    pub fn clear_reserved(&mut self) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u32(&mut data[field::UNUSED], 0);
+    }
+}
+
+/// Getters for the Neighbor Solicitation and Neighbor Advertisement message headers.
+/// See [RFC 4861 § 4.3].
+///
+/// [RFC 4861 § 4.3]: https://tools.ietf.org/html/rfc4861#section-4.3
+impl<T: AsRef<[u8]>> Packet<T> {
+    /// Return the router flag.
+    #[inline]
+    pub fn router_flag(&self) -> bool {
+        let data = self.buffer.as_ref();
+        data[neighbor::NEIGH_FLAGS] & neighbor::FLAG_ROUTER != 0
+    }
+
+    /// Return the solicited flag.
+    #[inline]
+    pub fn solicited_flag(&self) -> bool {
+        let data = self.buffer.as_ref();
+        data[neighbor::NEIGH_FLAGS] & neighbor::FLAG_SOLICITED != 0
+    }
+
+    /// Return the override flag.
+    #[inline]
+    pub fn override_flag(&self) -> bool {
+        let data = self.buffer.as_ref();
+        data[neighbor::NEIGH_FLAGS] & neighbor::FLAG_OVERRIDE != 0
+    }
+
+    /// Return the target address field.
+    #[inline]
+    pub fn target_addr(&self) -> Ipv6Address {
+        let data = self.buffer.as_ref();
+        Ipv6Address::from_bytes(&data[neighbor::TARGET_ADDR])
+    }
+}
+
+/// Setters for the Neighbor Solicitation and Neighbor Advertisement message headers.
+/// See [RFC 4861 § 4.3].
+///
+/// [RFC 4861 § 4.3]: https://tools.ietf.org/html/rfc4861#section-4.3
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
+    /// Set the router, solicited and override flags, clearing the reserved bits.
+    #[inline]
+    pub fn set_neighbor_flags(&mut self, router: bool, solicited: bool, override_cache: bool) {
+        let mut flags = 0;
+        if router         { flags |= neighbor::FLAG_ROUTER }
+        if solicited      { flags |= neighbor::FLAG_SOLICITED }
+        if override_cache { flags |= neighbor::FLAG_OVERRIDE }
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u32(&mut data[field::UNUSED], (flags as u32) << 24);
+    }
+
+    /// Set the target address field.
+    #[inline]
+    pub fn set_target_addr(&mut self, value: Ipv6Address) {
+        let data = self.buffer.as_mut();
+        data[neighbor::TARGET_ADDR].copy_from_slice(value.as_bytes());
+    }
+}
+
+/// A high-level representation of a Neighbor Discovery packet header.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Repr {
+    RouterSolicit {
+        lladdr: Option<EthernetAddress>
+    },
+    RouterAdvert {
+        hop_limit:       u8,
+        managed:         bool,
+        other:           bool,
+        /// The lifetime of the router as a default router, in seconds.
+        router_lifetime: u16,
+        /// The reachable time, in milliseconds; zero means unspecified.
+        reachable_time:  u32,
+        /// The retransmit timer, in milliseconds; zero means unspecified.
+        retrans_time:    u32,
+        lladdr:          Option<EthernetAddress>,
+        mtu:             Option<u32>,
+        prefix_info:     [Option<PrefixInfo>; PREFIX_INFO_COUNT],
+        /// The routes to specific destinations through the router; see RFC 4191.
+        route_info:      [Option<RouteInfo>; ROUTE_INFO_COUNT]
+    },
+    NeighborSolicit {
+        target_addr: Ipv6Address,
+        /// The link-layer address of the sender; never present when the source address
+        /// is unspecified, i.e. in Duplicate Address Detection probes.
+        lladdr:      Option<EthernetAddress>
+    },
+    NeighborAdvert {
+        router:         bool,
+        solicited:      bool,
+        override_cache: bool,
+        target_addr:    Ipv6Address,
+        /// The link-layer address of the target.
+        lladdr:         Option<EthernetAddress>
+    },
+    #[doc(hidden)]
+    __Nonexhaustive
+}
+
+impl Repr {
+    /// Parse a Neighbor Discovery packet and return a high-level representation.
+    ///
+    /// The ICMPv6 checksum is not verified; use [Icmpv6Repr::parse] for that.
+    ///
+    /// [Icmpv6Repr::parse]: enum.Icmpv6Repr.html#method.parse
+    pub fn parse<T>(packet: &Packet<&T>) -> Result<Repr>
+            where T: AsRef<[u8]> + ?Sized {
+        match packet.msg_type() {
+            Message::RouterSolicit => {
+                let mut lladdr = None;
+                for opt in NdiscOptionIter::new(packet.payload()) {
+                    match opt? {
+                        NdiscOptionRepr::SourceLinkLayerAddr(addr) => lladdr = Some(addr),
+                        _ => ()
+                    }
+                }
+                Ok(Repr::RouterSolicit { lladdr })
+            }
+            Message::RouterAdvert => {
+                let (mut lladdr, mut mtu) = (None, None);
+                let mut prefix_info = [None; PREFIX_INFO_COUNT];
+                let mut route_info = [None; ROUTE_INFO_COUNT];
+                for opt in NdiscOptionIter::new(packet.payload()) {
+                    match opt? {
+                        NdiscOptionRepr::SourceLinkLayerAddr(addr) => lladdr = Some(addr),
+                        NdiscOptionRepr::Mtu(val) => mtu = Some(val),
+                        NdiscOptionRepr::PrefixInformation(info) => {
+                            if let Some(slot) = prefix_info.iter_mut()
+                                                           .find(|slot| slot.is_none()) {
+                                *slot = Some(info)
+                            }
+                        }
+                        NdiscOptionRepr::RouteInformation(info) => {
+                            if let Some(slot) = route_info.iter_mut()
+                                                          .find(|slot| slot.is_none()) {
+                                *slot = Some(info)
+                            }
+                        }
+                        _ => ()
+                    }
+                }
+                Ok(Repr::RouterAdvert {
+                    hop_limit:       packet.current_hop_limit(),
+                    managed:         packet.managed_flag(),
+                    other:           packet.other_flag(),
+                    router_lifetime: packet.router_lifetime(),
+                    reachable_time:  packet.reachable_time(),
+                    retrans_time:    packet.retrans_time(),
+                    lladdr, mtu, prefix_info, route_info
+                })
+            }
+            Message::NeighborSolicit => {
+                let target_addr = packet.target_addr();
+                if target_addr.is_multicast() { return Err(Error::Malformed) }
+
+                let mut lladdr = None;
+                for opt in NdiscOptionIter::new(packet.payload()) {
+                    match opt? {
+                        NdiscOptionRepr::SourceLinkLayerAddr(addr) => lladdr = Some(addr),
+                        _ => ()
+                    }
+                }
+                Ok(Repr::NeighborSolicit { target_addr, lladdr })
+            }
+            Message::NeighborAdvert => {
+                let target_addr = packet.target_addr();
+                if target_addr.is_multicast() { return Err(Error::Malformed) }
+
+                let mut lladdr = None;
+                for opt in NdiscOptionIter::new(packet.payload()) {
+                    match opt? {
+                        NdiscOptionRepr::TargetLinkLayerAddr(addr) => lladdr = Some(addr),
+                        _ => ()
+                    }
+                }
+                Ok(Repr::NeighborAdvert {
+                    router:         packet.router_flag(),
+                    solicited:      packet.solicited_flag(),
+                    override_cache: packet.override_flag(),
+                    target_addr, lladdr
+                })
+            }
+            _ => Err(Error::Unrecognized)
+        }
+    }
+
+    /// Return the length of a packet that will be emitted from this high-level representation.
+    pub fn buffer_len(&self) -> usize {
+        match self {
+            &Repr::RouterSolicit { lladdr } => {
+                let mut len = field::ROUTER_SOLICIT_END;
+                if let Some(addr) = lladdr {
+                    len += NdiscOptionRepr::SourceLinkLayerAddr(addr).buffer_len();
+                }
+                len
+            }
+            &Repr::RouterAdvert { lladdr, mtu, prefix_info, route_info, .. } => {
+                let mut len = field::ROUTER_ADVERT_END;
+                if let Some(addr) = lladdr {
+                    len += NdiscOptionRepr::SourceLinkLayerAddr(addr).buffer_len();
+                }
+                if let Some(mtu) = mtu {
+                    len += NdiscOptionRepr::Mtu(mtu).buffer_len();
+                }
+                for info in prefix_info.iter().filter_map(|info| *info) {
+                    len += NdiscOptionRepr::PrefixInformation(info).buffer_len();
+                }
+                for info in route_info.iter().filter_map(|info| *info) {
+                    len += NdiscOptionRepr::RouteInformation(info).buffer_len();
+                }
+                len
+            }
+            &Repr::NeighborSolicit { lladdr, .. } => {
+                let mut len = field::NEIGHBOR_END;
+                if let Some(addr) = lladdr {
+                    len += NdiscOptionRepr::SourceLinkLayerAddr(addr).buffer_len();
+                }
+                len
+            }
+            &Repr::NeighborAdvert { lladdr, .. } => {
+                let mut len = field::NEIGHBOR_END;
+                if let Some(addr) = lladdr {
+                    len += NdiscOptionRepr::TargetLinkLayerAddr(addr).buffer_len();
+                }
+                len
+            }
+            &Repr::__Nonexhaustive => unreachable!()
+        }
+    }
+
+    /// Emit a high-level representation into a Neighbor Discovery packet.
+    ///
+    /// The ICMPv6 checksum is not filled in; use [Icmpv6Repr::emit] for that.
+    ///
+    /// [Icmpv6Repr::emit]: enum.Icmpv6Repr.html#method.emit
+    pub fn emit<T>(&self, packet: &mut Packet<&mut T>)
+            where T: AsRef<[u8]> + AsMut<[u8]> + ?Sized {
+        fn emit_option(buffer: &mut [u8], repr: NdiscOptionRepr) -> usize {
+            let len = repr.buffer_len();
+            repr.emit(&mut NdiscOption::new(&mut buffer[..len]));
+            len
+        }
+
+        match self {
+            &Repr::RouterSolicit { lladdr } => {
+                packet.set_msg_type(Message::RouterSolicit);
+                packet.set_msg_code(0);
+                packet.clear_reserved();
+                if let Some(addr) = lladdr {
+                    emit_option(packet.payload_mut(),
+                                NdiscOptionRepr::SourceLinkLayerAddr(addr));
+                }
+            }
+
+            &Repr::RouterAdvert { hop_limit, managed, other, router_lifetime,
+                                  reachable_time, retrans_time, lladdr, mtu,
+                                  prefix_info, route_info } => {
+                packet.set_msg_type(Message::RouterAdvert);
+                packet.set_msg_code(0);
+                packet.set_current_hop_limit(hop_limit);
+                packet.set_router_flags(managed, other);
+                packet.set_router_lifetime(router_lifetime);
+                packet.set_reachable_time(reachable_time);
+                packet.set_retrans_time(retrans_time);
+
+                let mut offset = 0;
+                if let Some(addr) = lladdr {
+                    offset += emit_option(&mut packet.payload_mut()[offset..],
+                                          NdiscOptionRepr::SourceLinkLayerAddr(addr));
+                }
+                if let Some(mtu) = mtu {
+                    offset += emit_option(&mut packet.payload_mut()[offset..],
+                                          NdiscOptionRepr::Mtu(mtu));
+                }
+                for info in prefix_info.iter().filter_map(|info| *info) {
+                    offset += emit_option(&mut packet.payload_mut()[offset..],
+                                          NdiscOptionRepr::PrefixInformation(info));
+                }
+                for info in route_info.iter().filter_map(|info| *info) {
+                    offset += emit_option(&mut packet.payload_mut()[offset..],
+                                          NdiscOptionRepr::RouteInformation(info));
+                }
+            }
+
+            &Repr::NeighborSolicit { target_addr, lladdr } => {
+                packet.set_msg_type(Message::NeighborSolicit);
+                packet.set_msg_code(0);
+                packet.clear_reserved();
+                packet.set_target_addr(target_addr);
+                if let Some(addr) = lladdr {
+                    emit_option(packet.payload_mut(),
+                                NdiscOptionRepr::SourceLinkLayerAddr(addr));
+                }
+            }
+
+            &Repr::NeighborAdvert { router, solicited, override_cache, target_addr, lladdr } => {
+                packet.set_msg_type(Message::NeighborAdvert);
+                packet.set_msg_code(0);
+                packet.set_neighbor_flags(router, solicited, override_cache);
+                packet.set_target_addr(target_addr);
+                if let Some(addr) = lladdr {
+                    emit_option(packet.payload_mut(),
+                                NdiscOptionRepr::TargetLinkLayerAddr(addr));
+                }
+            }
+
+            &Repr::__Nonexhaustive => unreachable!()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use phy::ChecksumCapabilities;
+    use wire::{Ipv6Address, Icmpv6Repr};
+    use super::*;
+
+    static ROUTER_ADVERT_BYTES: [u8; 56] =
+        [0x86, 0x00, 0x55, 0x07,
+         0x40, 0x80, 0x03, 0x84,
+         0x00, 0x00, 0x03, 0x84,
+         0x00, 0x00, 0x03, 0x84,
+         0x01, 0x01, 0x52, 0x54,
+         0x00, 0x00, 0x00, 0x01,
+         0x03, 0x04, 0x40, 0xc0,
+         0x00, 0x00, 0x0e, 0x10,
+         0x00, 0x00, 0x07, 0x08,
+         0x00, 0x00, 0x00, 0x00,
+         0x20, 0x01, 0x0d, 0xb8,
+         0x00, 0x00, 0x00, 0x00,
+         0x00, 0x00, 0x00, 0x00,
+         0x00, 0x00, 0x00, 0x00];
+
+    static ROUTER_SOLICIT_BYTES: [u8; 16] =
+        [0x85, 0x00, 0x27, 0xa8,
+         0x00, 0x00, 0x00, 0x00,
+         0x01, 0x01, 0x52, 0x54,
+         0x00, 0x00, 0x00, 0x02];
+
+    static NEIGHBOR_SOLICIT_BYTES: [u8; 32] =
+        [0x87, 0x00, 0xf7, 0xed,
+         0x00, 0x00, 0x00, 0x00,
+         0x20, 0x01, 0x0d, 0xb8,
+         0x00, 0x00, 0x00, 0x00,
+         0x00, 0x00, 0x00, 0x00,
+         0x00, 0x00, 0x00, 0x01,
+         0x01, 0x01, 0x52, 0x54,
+         0x00, 0x00, 0x00, 0x02];
+
+    static NEIGHBOR_ADVERT_BYTES: [u8; 32] =
+        [0x88, 0x00, 0x95, 0xee,
+         0x60, 0x00, 0x00, 0x00,
+         0x20, 0x01, 0x0d, 0xb8,
+         0x00, 0x00, 0x00, 0x00,
+         0x00, 0x00, 0x00, 0x00,
+         0x00, 0x00, 0x00, 0x01,
+         0x02, 0x01, 0x52, 0x54,
+         0x00, 0x00, 0x00, 0x01];
+
+    fn router_advert_repr() -> Repr {
+        Repr::RouterAdvert {
+            hop_limit:       64,
+            managed:         true,
+            other:           false,
+            router_lifetime: 900,
+            reachable_time:  900,
+            retrans_time:    900,
+            lladdr:          Some(EthernetAddress([0x52, 0x54, 0x00, 0x00, 0x00, 0x01])),
+            mtu:             None,
+            prefix_info:     [Some(PrefixInfo {
+                prefix_len:         64,
+                on_link:            true,
+                autonomous:         true,
+                valid_lifetime:     3600,
+                preferred_lifetime: 1800,
+                prefix:             Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0)
+            }), None, None, None],
+            route_info:      [None; ROUTE_INFO_COUNT]
+        }
+    }
+
+    #[test]
+    fn test_router_advert_deconstruct() {
+        let packet = Packet::new(&ROUTER_ADVERT_BYTES[..]);
+        assert_eq!(packet.msg_type(), Message::RouterAdvert);
+        assert_eq!(packet.msg_code(), 0);
+        assert_eq!(packet.current_hop_limit(), 64);
+        assert!(packet.managed_flag());
+        assert!(!packet.other_flag());
+        assert_eq!(packet.router_lifetime(), 900);
+        assert_eq!(packet.reachable_time(), 900);
+        assert_eq!(packet.retrans_time(), 900);
+        assert_eq!(packet.payload(), &ROUTER_ADVERT_BYTES[16..]);
+    }
+
+    #[test]
+    fn test_router_advert_repr_parse() {
+        let packet = Packet::new(&ROUTER_ADVERT_BYTES[..]);
+        assert_eq!(Icmpv6Repr::parse(&packet, &ChecksumCapabilities::default()),
+                   Ok(Icmpv6Repr::Ndisc(router_advert_repr())));
+    }
+
+    #[test]
+    fn test_router_advert_emit() {
+        let repr = Icmpv6Repr::Ndisc(router_advert_repr());
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new(&mut bytes), &ChecksumCapabilities::default());
+        assert_eq!(&bytes[..], &ROUTER_ADVERT_BYTES[..]);
+    }
+
+    #[test]
+    fn test_router_advert_malformed_option() {
+        let mut bytes = ROUTER_ADVERT_BYTES;
+        bytes[17] = 0;
+        assert_eq!(Repr::parse(&Packet::new(&bytes[..])), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn test_router_solicit_repr() {
+        let repr = Repr::RouterSolicit {
+            lladdr: Some(EthernetAddress([0x52, 0x54, 0x00, 0x00, 0x00, 0x02]))
+        };
+        let packet = Packet::new(&ROUTER_SOLICIT_BYTES[..]);
+        assert_eq!(Icmpv6Repr::parse(&packet, &ChecksumCapabilities::default()),
+                   Ok(Icmpv6Repr::Ndisc(repr)));
+
+        let repr = Icmpv6Repr::Ndisc(repr);
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new(&mut bytes), &ChecksumCapabilities::default());
+        assert_eq!(&bytes[..], &ROUTER_SOLICIT_BYTES[..]);
+    }
+
+    #[test]
+    fn test_neighbor_solicit_repr() {
+        let repr = Repr::NeighborSolicit {
+            target_addr: Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1),
+            lladdr:      Some(EthernetAddress([0x52, 0x54, 0x00, 0x00, 0x00, 0x02]))
+        };
+        let packet = Packet::new(&NEIGHBOR_SOLICIT_BYTES[..]);
+        assert_eq!(packet.target_addr(), Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        assert_eq!(Icmpv6Repr::parse(&packet, &ChecksumCapabilities::default()),
+                   Ok(Icmpv6Repr::Ndisc(repr)));
+
+        let repr = Icmpv6Repr::Ndisc(repr);
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new(&mut bytes), &ChecksumCapabilities::default());
+        assert_eq!(&bytes[..], &NEIGHBOR_SOLICIT_BYTES[..]);
+    }
+
+    #[test]
+    fn test_neighbor_advert_repr() {
+        let repr = Repr::NeighborAdvert {
+            router:         false,
+            solicited:      true,
+            override_cache: true,
+            target_addr:    Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1),
+            lladdr:         Some(EthernetAddress([0x52, 0x54, 0x00, 0x00, 0x00, 0x01]))
+        };
+        let packet = Packet::new(&NEIGHBOR_ADVERT_BYTES[..]);
+        assert!(!packet.router_flag());
+        assert!(packet.solicited_flag());
+        assert!(packet.override_flag());
+        assert_eq!(Icmpv6Repr::parse(&packet, &ChecksumCapabilities::default()),
+                   Ok(Icmpv6Repr::Ndisc(repr)));
+
+        let repr = Icmpv6Repr::Ndisc(repr);
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new(&mut bytes), &ChecksumCapabilities::default());
+        assert_eq!(&bytes[..], &NEIGHBOR_ADVERT_BYTES[..]);
+    }
+
+    #[test]
+    fn test_neighbor_multicast_target() {
+        let mut bytes = NEIGHBOR_SOLICIT_BYTES;
+        bytes[8] = 0xff;
+        assert_eq!(Repr::parse(&Packet::new(&bytes[..])), Err(Error::Malformed));
+    }
+}
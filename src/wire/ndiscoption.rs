@@ -0,0 +1,808 @@
+use core::fmt;
+use byteorder::{ByteOrder, NetworkEndian};
+
+use {Error, Result};
+use super::{EthernetAddress, Ipv6Address};
+
+enum_with_unknown! {
+    /// NDISC Option Type
+    pub doc enum Type(u8) {
+        /// Source Link-layer Address
+        SourceLinkLayerAddr = 0x1,
+        /// Target Link-layer Address
+        TargetLinkLayerAddr = 0x2,
+        /// Prefix Information
+        PrefixInformation   = 0x3,
+        /// Redirected Header
+        RedirectedHeader    = 0x4,
+        /// MTU
+        Mtu                 = 0x5,
+        /// Route Information
+        RouteInformation    = 0x18
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Type::SourceLinkLayerAddr => write!(f, "source link-layer address"),
+            &Type::TargetLinkLayerAddr => write!(f, "target link-layer address"),
+            &Type::PrefixInformation   => write!(f, "prefix information"),
+            &Type::RedirectedHeader    => write!(f, "redirected header"),
+            &Type::Mtu                 => write!(f, "mtu"),
+            &Type::RouteInformation    => write!(f, "route information"),
+            &Type::Unknown(id)         => write!(f, "{}", id)
+        }
+    }
+}
+
+/// A read/write wrapper around an [NDISC Option].
+///
+/// [NDISC Option]: https://tools.ietf.org/html/rfc4861#section-4.6
+#[derive(Debug, PartialEq)]
+pub struct NdiscOption<T: AsRef<[u8]>> {
+    buffer: T
+}
+
+// Format of an NDISC Option
+//
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |     Type      |    Length     |              ...              |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// ~                              ...                              ~
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// See https://tools.ietf.org/html/rfc4861#section-4.6 for details.
+mod field {
+    #![allow(non_snake_case)]
+
+    use wire::field::*;
+
+    // 8-bit identifier of the type of option.
+    pub const TYPE:          usize = 0;
+    // 8-bit unsigned integer. Length of the option, in units of 8 octets.
+    pub const LENGTH:        usize = 1;
+    // Minimum length of an option.
+    pub const MIN_OPT_LEN:   usize = 8;
+    // Variable-length field. Option-Type-specific data.
+    pub fn DATA(length: u8) -> Field {
+        2..length as usize * 8
+    }
+
+    // Source/Target Link-layer Option fields.
+    //
+    //     0                   1                   2                   3
+    //     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    //    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //    |     Type      |    Length     |    Link-Layer Address ...
+    //    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+    // Link-Layer Address
+    pub const LL_ADDR:       Field = 2..8;
+
+    // Prefix Information Option fields.
+    //
+    //     0                   1                   2                   3
+    //     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    //    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //    |     Type      |    Length     | Prefix Length |L|A| Reserved1 |
+    //    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //    |                         Valid Lifetime                        |
+    //    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //    |                       Preferred Lifetime                      |
+    //    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //    |                           Reserved2                           |
+    //    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //    |                                                               |
+    //    +                                                               +
+    //    |                                                               |
+    //    +                            Prefix                             +
+    //    |                                                               |
+    //    +                                                               +
+    //    |                                                               |
+    //    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+    // Prefix length.
+    pub const PREFIX_LEN:    usize = 2;
+    // Flags field of prefix header.
+    pub const FLAGS:         usize = 3;
+    // Valid lifetime.
+    pub const VALID_LT:      Field = 4..8;
+    // Preferred lifetime.
+    pub const PREF_LT:       Field = 8..12;
+    // Reserved bits
+    pub const PREF_RESERVED: Field = 12..16;
+    // Prefix
+    pub const PREFIX:        Field = 16..32;
+
+    // MTU Option fields
+    //
+    //     0                   1                   2                   3
+    //     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    //    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //    |     Type      |    Length     |           Reserved            |
+    //    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //    |                              MTU                              |
+    //    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+    //  MTU
+    pub const MTU:           Field = 4..8;
+
+    // Route Information Option fields (see RFC 4191 § 2.3)
+    //
+    //     0                   1                   2                   3
+    //     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    //    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //    |     Type      |    Length     | Prefix Length |Resvd|Prf|Resvd|
+    //    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //    |                        Route Lifetime                         |
+    //    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //    |                   Prefix (Variable Length)                    |
+    //    .                                                               .
+    //    .                                                               .
+    //    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+    // Route lifetime.
+    pub const ROUTE_LT:      Field = 4..8;
+    // Prefix, as long as the option allows.
+    pub fn ROUTE_PREFIX(length: u8) -> Field {
+        8..::core::cmp::min(length as usize * 8, 24)
+    }
+}
+
+/// The on-link flag of a Prefix Information option.
+const PREFIX_FLAG_ON_LINK:    u8 = 0x80;
+/// The autonomous address-configuration flag of a Prefix Information option.
+const PREFIX_FLAG_AUTONOMOUS: u8 = 0x40;
+/// The route preference bits of a Route Information option.
+const ROUTE_PREFERENCE_MASK:  u8 = 0x18;
+
+/// The preference of a route over the other routes to the same destinations,
+/// as advertised in a Route Information option.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RoutePreference {
+    Low,
+    Medium,
+    High,
+}
+
+/// Return the length, in octets, of a Route Information option with the given
+/// prefix length.
+fn route_info_len(prefix_len: u8) -> usize {
+    match prefix_len {
+        0       => 8,
+        1..=64  => 16,
+        _       => 24
+    }
+}
+
+impl<T: AsRef<[u8]>> NdiscOption<T> {
+    /// Create a raw octet buffer with an NDISC Option structure.
+    pub fn new(buffer: T) -> NdiscOption<T> {
+        NdiscOption { buffer }
+    }
+
+    /// Shorthand for a combination of [new] and [check_len].
+    ///
+    /// [new]: #method.new
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<NdiscOption<T>> {
+        let opt = Self::new(buffer);
+        opt.check_len()?;
+        Ok(opt)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error::Truncated)` if the buffer is too short,
+    /// and `Err(Error::Malformed)` if the length field is zero, or too small
+    /// for the option type.
+    ///
+    /// The result of this check is invalidated by calling [set_data_len].
+    ///
+    /// [set_data_len]: #method.set_data_len
+    pub fn check_len(&self) -> Result<()> {
+        let data = self.buffer.as_ref();
+        let len = data.len();
+
+        if len < field::MIN_OPT_LEN {
+            return Err(Error::Truncated)
+        }
+
+        let opt_len = data[field::LENGTH] as usize * 8;
+        if opt_len == 0 {
+            return Err(Error::Malformed)
+        }
+        if len < opt_len {
+            return Err(Error::Truncated)
+        }
+
+        let min_len = match self.option_type() {
+            Type::SourceLinkLayerAddr | Type::TargetLinkLayerAddr | Type::Mtu =>
+                field::MIN_OPT_LEN,
+            Type::PrefixInformation =>
+                field::PREFIX.end,
+            Type::RouteInformation => {
+                let prefix_len = data[field::PREFIX_LEN];
+                if prefix_len > 128 { return Err(Error::Malformed) }
+                route_info_len(prefix_len)
+            }
+            _ => 0
+        };
+        if opt_len < min_len {
+            return Err(Error::Malformed)
+        }
+        Ok(())
+    }
+
+    /// Consume the NDISC option, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Return the option type.
+    #[inline]
+    pub fn option_type(&self) -> Type {
+        let data = self.buffer.as_ref();
+        Type::from(data[field::TYPE])
+    }
+
+    /// Return the length of the option, in units of 8 octets.
+    #[inline]
+    pub fn data_len(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[field::LENGTH]
+    }
+
+    /// Return the link-layer address (for source/target link-layer address options).
+    #[inline]
+    pub fn link_layer_addr(&self) -> EthernetAddress {
+        let data = self.buffer.as_ref();
+        EthernetAddress::from_bytes(&data[field::LL_ADDR])
+    }
+
+    /// Return the MTU (for MTU options).
+    #[inline]
+    pub fn mtu(&self) -> u32 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u32(&data[field::MTU])
+    }
+
+    /// Return the prefix length (for prefix information and route information options).
+    #[inline]
+    pub fn prefix_len(&self) -> u8 {
+        self.buffer.as_ref()[field::PREFIX_LEN]
+    }
+
+    /// Return the on-link flag (for prefix information options).
+    #[inline]
+    pub fn on_link(&self) -> bool {
+        self.buffer.as_ref()[field::FLAGS] & PREFIX_FLAG_ON_LINK != 0
+    }
+
+    /// Return the autonomous address-configuration flag (for prefix information options).
+    #[inline]
+    pub fn autonomous(&self) -> bool {
+        self.buffer.as_ref()[field::FLAGS] & PREFIX_FLAG_AUTONOMOUS != 0
+    }
+
+    /// Return the valid lifetime of the prefix, in seconds (for prefix information options).
+    #[inline]
+    pub fn valid_lifetime(&self) -> u32 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u32(&data[field::VALID_LT])
+    }
+
+    /// Return the preferred lifetime of the prefix, in seconds
+    /// (for prefix information options).
+    #[inline]
+    pub fn preferred_lifetime(&self) -> u32 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u32(&data[field::PREF_LT])
+    }
+
+    /// Return the prefix (for prefix information options).
+    #[inline]
+    pub fn prefix(&self) -> Ipv6Address {
+        let data = self.buffer.as_ref();
+        Ipv6Address::from_bytes(&data[field::PREFIX])
+    }
+
+    /// Return the route preference, or `None` if it has the reserved value
+    /// (for route information options).
+    #[inline]
+    pub fn route_preference(&self) -> Option<RoutePreference> {
+        match self.buffer.as_ref()[field::FLAGS] & ROUTE_PREFERENCE_MASK {
+            0x08 => Some(RoutePreference::High),
+            0x00 => Some(RoutePreference::Medium),
+            0x18 => Some(RoutePreference::Low),
+            _    => None
+        }
+    }
+
+    /// Return the lifetime of the route, in seconds (for route information options).
+    #[inline]
+    pub fn route_lifetime(&self) -> u32 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u32(&data[field::ROUTE_LT])
+    }
+
+    /// Return the prefix, padded with zeroes if the option omits its trailing octets
+    /// (for route information options).
+    #[inline]
+    pub fn route_prefix(&self) -> Ipv6Address {
+        let data = self.buffer.as_ref();
+        let prefix = &data[field::ROUTE_PREFIX(data[field::LENGTH])];
+        let mut bytes = [0; 16];
+        bytes[..prefix.len()].copy_from_slice(prefix);
+        Ipv6Address(bytes)
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> NdiscOption<&'a T> {
+    /// Return the option data.
+    #[inline]
+    pub fn data(&self) -> &'a [u8] {
+        let len = self.data_len();
+        let data = self.buffer.as_ref();
+        &data[field::DATA(len)]
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> NdiscOption<T> {
+    /// Set the option type.
+    #[inline]
+    pub fn set_option_type(&mut self, value: Type) {
+        let data = self.buffer.as_mut();
+        data[field::TYPE] = value.into();
+    }
+
+    /// Set the length of the option, in units of 8 octets.
+    #[inline]
+    pub fn set_data_len(&mut self, value: u8) {
+        let data = self.buffer.as_mut();
+        data[field::LENGTH] = value;
+    }
+
+    /// Set the link-layer address (for source/target link-layer address options).
+    #[inline]
+    pub fn set_link_layer_addr(&mut self, addr: EthernetAddress) {
+        let data = self.buffer.as_mut();
+        data[field::LL_ADDR].copy_from_slice(addr.as_bytes())
+    }
+
+    /// Set the MTU (for MTU options), clearing the reserved field.
+    #[inline]
+    pub fn set_mtu(&mut self, value: u32) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[2..4], 0);
+        NetworkEndian::write_u32(&mut data[field::MTU], value);
+    }
+
+    /// Set the prefix length (for prefix information and route information options).
+    #[inline]
+    pub fn set_prefix_len(&mut self, value: u8) {
+        self.buffer.as_mut()[field::PREFIX_LEN] = value;
+    }
+
+    /// Set the on-link and autonomous address-configuration flags
+    /// (for prefix information options), clearing the reserved bits.
+    #[inline]
+    pub fn set_prefix_flags(&mut self, on_link: bool, autonomous: bool) {
+        let mut flags = 0;
+        if on_link    { flags |= PREFIX_FLAG_ON_LINK }
+        if autonomous { flags |= PREFIX_FLAG_AUTONOMOUS }
+        self.buffer.as_mut()[field::FLAGS] = flags;
+    }
+
+    /// Set the valid lifetime of the prefix, in seconds (for prefix information options).
+    #[inline]
+    pub fn set_valid_lifetime(&mut self, value: u32) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u32(&mut data[field::VALID_LT], value);
+    }
+
+    /// Set the preferred lifetime of the prefix, in seconds
+    /// (for prefix information options), clearing the reserved field.
+    #[inline]
+    pub fn set_preferred_lifetime(&mut self, value: u32) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u32(&mut data[field::PREF_LT], value);
+        NetworkEndian::write_u32(&mut data[field::PREF_RESERVED], 0);
+    }
+
+    /// Set the prefix (for prefix information options).
+    #[inline]
+    pub fn set_prefix(&mut self, addr: Ipv6Address) {
+        let data = self.buffer.as_mut();
+        data[field::PREFIX].copy_from_slice(addr.as_bytes());
+    }
+
+    /// Set the route preference (for route information options), clearing
+    /// the reserved bits.
+    #[inline]
+    pub fn set_route_preference(&mut self, value: RoutePreference) {
+        self.buffer.as_mut()[field::FLAGS] = match value {
+            RoutePreference::High   => 0x08,
+            RoutePreference::Medium => 0x00,
+            RoutePreference::Low    => 0x18
+        };
+    }
+
+    /// Set the lifetime of the route, in seconds (for route information options).
+    #[inline]
+    pub fn set_route_lifetime(&mut self, value: u32) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u32(&mut data[field::ROUTE_LT], value);
+    }
+
+    /// Set the prefix, truncated to the length of the option
+    /// (for route information options).
+    #[inline]
+    pub fn set_route_prefix(&mut self, addr: Ipv6Address) {
+        let data = self.buffer.as_mut();
+        let field = field::ROUTE_PREFIX(data[field::LENGTH]);
+        let len = field.len();
+        data[field].copy_from_slice(&addr.as_bytes()[..len]);
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + AsMut<[u8]> + ?Sized> NdiscOption<&'a mut T> {
+    /// Return a mutable pointer to the option data.
+    #[inline]
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        let len = self.data_len();
+        let data = self.buffer.as_mut();
+        &mut data[field::DATA(len)]
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> fmt::Display for NdiscOption<&'a T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match Repr::parse(self) {
+            Ok(repr) => write!(f, "{}", repr),
+            Err(err) => write!(f, "NDISC Option ({})", err)
+        }
+    }
+}
+
+/// The contents of a Prefix Information option.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PrefixInfo {
+    pub prefix_len:         u8,
+    /// Whether the prefix can be used for on-link determination.
+    pub on_link:            bool,
+    /// Whether the prefix can be used for stateless address autoconfiguration.
+    pub autonomous:         bool,
+    /// The valid lifetime, in seconds; `0xffffffff` means infinity.
+    pub valid_lifetime:     u32,
+    /// The preferred lifetime, in seconds; `0xffffffff` means infinity.
+    pub preferred_lifetime: u32,
+    pub prefix:             Ipv6Address
+}
+
+/// The contents of a Route Information option.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RouteInfo {
+    pub prefix_len:     u8,
+    pub preference:     RoutePreference,
+    /// The route lifetime, in seconds; `0xffffffff` means infinity.
+    pub route_lifetime: u32,
+    pub prefix:         Ipv6Address
+}
+
+/// A high-level representation of an NDISC Option.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Repr<'a> {
+    SourceLinkLayerAddr(EthernetAddress),
+    TargetLinkLayerAddr(EthernetAddress),
+    PrefixInformation(PrefixInfo),
+    Mtu(u32),
+    RouteInformation(RouteInfo),
+    Unknown {
+        type_:  u8,
+        length: u8,
+        data:   &'a [u8]
+    },
+
+    #[doc(hidden)]
+    __Nonexhaustive
+}
+
+impl<'a> Repr<'a> {
+    /// Parse an NDISC Option and return a high-level representation.
+    pub fn parse<T>(opt: &NdiscOption<&'a T>) -> Result<Repr<'a>>
+            where T: AsRef<[u8]> + ?Sized {
+        match opt.option_type() {
+            Type::SourceLinkLayerAddr =>
+                Ok(Repr::SourceLinkLayerAddr(opt.link_layer_addr())),
+            Type::TargetLinkLayerAddr =>
+                Ok(Repr::TargetLinkLayerAddr(opt.link_layer_addr())),
+            Type::PrefixInformation =>
+                Ok(Repr::PrefixInformation(PrefixInfo {
+                    prefix_len:         opt.prefix_len(),
+                    on_link:            opt.on_link(),
+                    autonomous:         opt.autonomous(),
+                    valid_lifetime:     opt.valid_lifetime(),
+                    preferred_lifetime: opt.preferred_lifetime(),
+                    prefix:             opt.prefix()
+                })),
+            Type::Mtu =>
+                Ok(Repr::Mtu(opt.mtu())),
+            Type::RouteInformation if opt.route_preference().is_some() =>
+                Ok(Repr::RouteInformation(RouteInfo {
+                    prefix_len:     opt.prefix_len(),
+                    preference:     opt.route_preference().unwrap(),
+                    route_lifetime: opt.route_lifetime(),
+                    prefix:         opt.route_prefix()
+                })),
+            // A route with the reserved preference must be ignored; see RFC 4191 § 2.3.
+            Type::RouteInformation | Type::RedirectedHeader | Type::Unknown(_) =>
+                Ok(Repr::Unknown {
+                    type_:  opt.option_type().into(),
+                    length: opt.data_len(),
+                    data:   opt.data()
+                })
+        }
+    }
+
+    /// Return the length of an option that will be emitted from this high-level
+    /// representation.
+    pub fn buffer_len(&self) -> usize {
+        match self {
+            &Repr::SourceLinkLayerAddr(_) | &Repr::TargetLinkLayerAddr(_) |
+            &Repr::Mtu(_) =>
+                field::MIN_OPT_LEN,
+            &Repr::PrefixInformation(_) =>
+                field::PREFIX.end,
+            &Repr::RouteInformation(info) =>
+                route_info_len(info.prefix_len),
+            &Repr::Unknown { length, .. } =>
+                length as usize * 8,
+
+            &Repr::__Nonexhaustive => unreachable!()
+        }
+    }
+
+    /// Emit a high-level representation into an NDISC Option.
+    pub fn emit<T>(&self, opt: &mut NdiscOption<&'a mut T>)
+            where T: AsRef<[u8]> + AsMut<[u8]> + ?Sized {
+        opt.set_data_len((self.buffer_len() / 8) as u8);
+        match self {
+            &Repr::SourceLinkLayerAddr(addr) => {
+                opt.set_option_type(Type::SourceLinkLayerAddr);
+                opt.set_link_layer_addr(addr);
+            }
+            &Repr::TargetLinkLayerAddr(addr) => {
+                opt.set_option_type(Type::TargetLinkLayerAddr);
+                opt.set_link_layer_addr(addr);
+            }
+            &Repr::PrefixInformation(info) => {
+                opt.set_option_type(Type::PrefixInformation);
+                opt.set_prefix_len(info.prefix_len);
+                opt.set_prefix_flags(info.on_link, info.autonomous);
+                opt.set_valid_lifetime(info.valid_lifetime);
+                opt.set_preferred_lifetime(info.preferred_lifetime);
+                opt.set_prefix(info.prefix);
+            }
+            &Repr::Mtu(mtu) => {
+                opt.set_option_type(Type::Mtu);
+                opt.set_mtu(mtu);
+            }
+            &Repr::RouteInformation(info) => {
+                opt.set_option_type(Type::RouteInformation);
+                opt.set_prefix_len(info.prefix_len);
+                opt.set_route_preference(info.preference);
+                opt.set_route_lifetime(info.route_lifetime);
+                opt.set_route_prefix(info.prefix);
+            }
+            &Repr::Unknown { type_, data, .. } => {
+                opt.set_option_type(Type::Unknown(type_));
+                opt.data_mut().copy_from_slice(data);
+            }
+
+            &Repr::__Nonexhaustive => unreachable!()
+        }
+    }
+}
+
+impl<'a> fmt::Display for Repr<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NDISC Option: ")?;
+        match self {
+            &Repr::SourceLinkLayerAddr(addr) =>
+                write!(f, "{} {}", Type::SourceLinkLayerAddr, addr),
+            &Repr::TargetLinkLayerAddr(addr) =>
+                write!(f, "{} {}", Type::TargetLinkLayerAddr, addr),
+            &Repr::PrefixInformation(info) =>
+                write!(f, "{} {}/{}", Type::PrefixInformation, info.prefix, info.prefix_len),
+            &Repr::Mtu(mtu) =>
+                write!(f, "{} {}", Type::Mtu, mtu),
+            &Repr::RouteInformation(info) =>
+                write!(f, "{} {}/{}", Type::RouteInformation, info.prefix, info.prefix_len),
+            &Repr::Unknown { type_, length, .. } =>
+                write!(f, "{} length={}", Type::Unknown(type_), length),
+
+            &Repr::__Nonexhaustive => unreachable!()
+        }
+    }
+}
+
+/// An iterator over the options in the body of an NDISC message.
+///
+/// The iterator stops at the first malformed or truncated option, returning
+/// the error.
+#[derive(Debug, Clone)]
+pub(crate) struct Iter<'a> {
+    buffer: &'a [u8]
+}
+
+impl<'a> Iter<'a> {
+    pub(crate) fn new(buffer: &'a [u8]) -> Iter<'a> {
+        Iter { buffer }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Result<Repr<'a>>;
+
+    fn next(&mut self) -> Option<Result<Repr<'a>>> {
+        if self.buffer.is_empty() { return None }
+
+        let result = NdiscOption::new_checked(self.buffer).and_then(|opt| {
+            let repr = Repr::parse(&opt)?;
+            Ok((repr, opt.data_len() as usize * 8))
+        });
+        match result {
+            Ok((repr, len)) => {
+                self.buffer = &self.buffer[len..];
+                Some(Ok(repr))
+            }
+            Err(err) => {
+                self.buffer = &[];
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static PREFIX_OPT_BYTES: [u8; 32] = [
+        0x03, 0x04, 0x40, 0xc0,
+        0x00, 0x00, 0x03, 0x84,
+        0x00, 0x00, 0x03, 0xe8,
+        0x00, 0x00, 0x00, 0x00,
+        0xfe, 0x80, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x01
+    ];
+
+    static ROUTE_OPT_BYTES: [u8; 16] = [
+        0x18, 0x02, 0x30, 0x08,
+        0x00, 0x00, 0x07, 0x08,
+        0x20, 0x01, 0x0d, 0xb8,
+        0x00, 0x01, 0x00, 0x00
+    ];
+
+    static LLADDR_OPT_BYTES: [u8; 8] = [
+        0x01, 0x01, 0x54, 0x52, 0x00, 0x12, 0x23, 0x34
+    ];
+
+    #[test]
+    fn test_check_len() {
+        assert_eq!(NdiscOption::new(&PREFIX_OPT_BYTES[..7]).check_len(),
+                   Err(Error::Truncated));
+        assert_eq!(NdiscOption::new(&PREFIX_OPT_BYTES[..31]).check_len(),
+                   Err(Error::Truncated));
+        assert_eq!(NdiscOption::new(&PREFIX_OPT_BYTES[..]).check_len(), Ok(()));
+
+        // An option with a zero length field would loop forever.
+        let bytes = [0x01, 0x00, 0, 0, 0, 0, 0, 0];
+        assert_eq!(NdiscOption::new(&bytes[..]).check_len(), Err(Error::Malformed));
+        // A prefix information option cannot be eight octets long.
+        let bytes = [0x03, 0x01, 0, 0, 0, 0, 0, 0];
+        assert_eq!(NdiscOption::new(&bytes[..]).check_len(), Err(Error::Malformed));
+        // A route information option has to be long enough for its prefix.
+        let bytes = [0x18, 0x01, 0x30, 0, 0, 0, 0, 0];
+        assert_eq!(NdiscOption::new(&bytes[..]).check_len(), Err(Error::Malformed));
+        let bytes = [0x18, 0x01, 0x00, 0, 0, 0, 0, 0];
+        assert_eq!(NdiscOption::new(&bytes[..]).check_len(), Ok(()));
+    }
+
+    #[test]
+    fn test_prefix_info_deconstruct() {
+        let opt = NdiscOption::new(&PREFIX_OPT_BYTES[..]);
+        assert_eq!(opt.option_type(), Type::PrefixInformation);
+        assert_eq!(opt.data_len(), 4);
+        assert_eq!(opt.prefix_len(), 64);
+        assert!(opt.on_link());
+        assert!(opt.autonomous());
+        assert_eq!(opt.valid_lifetime(), 900);
+        assert_eq!(opt.preferred_lifetime(), 1000);
+        assert_eq!(opt.prefix(), Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1));
+    }
+
+    #[test]
+    fn test_repr_parse() {
+        let opt = NdiscOption::new(&LLADDR_OPT_BYTES[..]);
+        assert_eq!(Repr::parse(&opt),
+                   Ok(Repr::SourceLinkLayerAddr(
+                       EthernetAddress([0x54, 0x52, 0x00, 0x12, 0x23, 0x34]))));
+
+        let opt = NdiscOption::new(&PREFIX_OPT_BYTES[..]);
+        assert_eq!(Repr::parse(&opt), Ok(Repr::PrefixInformation(PrefixInfo {
+            prefix_len:         64,
+            on_link:            true,
+            autonomous:         true,
+            valid_lifetime:     900,
+            preferred_lifetime: 1000,
+            prefix:             Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)
+        })));
+    }
+
+    #[test]
+    fn test_repr_emit() {
+        let repr = Repr::PrefixInformation(PrefixInfo {
+            prefix_len:         64,
+            on_link:            true,
+            autonomous:         true,
+            valid_lifetime:     900,
+            preferred_lifetime: 1000,
+            prefix:             Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)
+        });
+        let mut bytes = [0x5au8; 32];
+        assert_eq!(repr.buffer_len(), bytes.len());
+        repr.emit(&mut NdiscOption::new(&mut bytes[..]));
+        assert_eq!(&bytes[..], &PREFIX_OPT_BYTES[..]);
+
+        let repr = Repr::SourceLinkLayerAddr(
+            EthernetAddress([0x54, 0x52, 0x00, 0x12, 0x23, 0x34]));
+        let mut bytes = [0x5au8; 8];
+        repr.emit(&mut NdiscOption::new(&mut bytes[..]));
+        assert_eq!(&bytes[..], &LLADDR_OPT_BYTES[..]);
+    }
+
+    #[test]
+    fn test_route_info_repr() {
+        let repr = Repr::RouteInformation(RouteInfo {
+            prefix_len:     48,
+            preference:     RoutePreference::High,
+            route_lifetime: 1800,
+            prefix:         Ipv6Address::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 0)
+        });
+        let opt = NdiscOption::new(&ROUTE_OPT_BYTES[..]);
+        assert_eq!(Repr::parse(&opt), Ok(repr));
+
+        let mut bytes = [0x5au8; 16];
+        assert_eq!(repr.buffer_len(), bytes.len());
+        repr.emit(&mut NdiscOption::new(&mut bytes[..]));
+        assert_eq!(&bytes[..], &ROUTE_OPT_BYTES[..]);
+
+        // A route with the reserved preference is ignored.
+        let mut bytes = ROUTE_OPT_BYTES;
+        bytes[3] = 0x10;
+        assert!(match Repr::parse(&NdiscOption::new(&bytes[..])) {
+            Ok(Repr::Unknown { type_: 0x18, .. }) => true,
+            _ => false
+        });
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut bytes = [0u8; 40];
+        bytes[..8].copy_from_slice(&LLADDR_OPT_BYTES);
+        bytes[8..].copy_from_slice(&PREFIX_OPT_BYTES);
+        let mut iter = Iter::new(&bytes[..]);
+        assert!(match iter.next() { Some(Ok(Repr::SourceLinkLayerAddr(_))) => true, _ => false });
+        assert!(match iter.next() { Some(Ok(Repr::PrefixInformation(_))) => true, _ => false });
+        assert_eq!(iter.next(), None);
+
+        let mut iter = Iter::new(&bytes[..39]);
+        assert!(match iter.next() { Some(Ok(Repr::SourceLinkLayerAddr(_))) => true, _ => false });
+        assert_eq!(iter.next(), Some(Err(Error::Truncated)));
+        assert_eq!(iter.next(), None);
+    }
+}
@@ -0,0 +1,443 @@
+// See https://tools.ietf.org/html/rfc5905 for the NTPv4 specification,
+// and https://tools.ietf.org/html/rfc4330 for SNTP.
+
+use core::fmt;
+use byteorder::{ByteOrder, NetworkEndian};
+
+use {Error, Result};
+
+/// The port NTP servers listen on.
+pub const SERVER_PORT: u16 = 123;
+
+/// The number of seconds from the NTP epoch, 1900-01-01, to the Unix epoch, 1970-01-01.
+const UNIX_EPOCH_SECONDS: i64 = 2_208_988_800;
+
+enum_with_unknown! {
+    /// A warning of an impending leap second, or an alarm condition if the clock
+    /// of the server is not synchronized.
+    pub enum LeapIndicator(u8) {
+        NoWarning    = 0,
+        LastMinute61 = 1,
+        LastMinute59 = 2,
+        Alarm        = 3
+    }
+}
+
+enum_with_unknown! {
+    /// The mode of an NTP packet.
+    pub enum ProtocolMode(u8) {
+        SymmetricActive  = 1,
+        SymmetricPassive = 2,
+        Client           = 3,
+        Server           = 4,
+        Broadcast        = 5
+    }
+}
+
+/// An NTP timestamp, as the seconds since the NTP epoch and their fraction.
+///
+/// The seconds wrap around in 2036; timestamps are mapped to Unix time in the era
+/// that spans from 1968 to 2104.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Timestamp {
+    pub seconds:  u32,
+    pub fraction: u32,
+}
+
+impl Timestamp {
+    /// Create a timestamp from a number of milliseconds since the Unix epoch.
+    pub fn from_unix_millis(millis: i64) -> Timestamp {
+        let seconds = millis.div_euclid(1000) + UNIX_EPOCH_SECONDS;
+        let millis = millis.rem_euclid(1000) as u64;
+        Timestamp {
+            seconds:  seconds as u32,
+            fraction: ((millis << 32) / 1000) as u32,
+        }
+    }
+
+    /// Return the number of milliseconds since the Unix epoch.
+    pub fn to_unix_millis(&self) -> i64 {
+        // Timestamps with the most significant bit cleared are in the next era.
+        let mut seconds = self.seconds as i64 - UNIX_EPOCH_SECONDS;
+        if self.seconds & 0x8000_0000 == 0 { seconds += 1 << 32 }
+        seconds * 1000 + ((self.fraction as u64 * 1000 + (1 << 31)) >> 32) as i64
+    }
+
+    /// Query whether the timestamp is zero, i.e. unknown.
+    pub fn is_zero(&self) -> bool {
+        self.seconds == 0 && self.fraction == 0
+    }
+}
+
+mod field {
+    #![allow(non_snake_case)]
+
+    use wire::field::*;
+
+    pub const LI_VN_MODE: usize = 0;
+    pub const STRATUM: usize = 1;
+    pub const POLL: usize = 2;
+    pub const PRECISION: usize = 3;
+    pub const ROOT_DELAY: Field = 4..8;
+    pub const ROOT_DISPERSION: Field = 8..12;
+    pub const REFERENCE_ID: Field = 12..16;
+    pub const REFERENCE_TIMESTAMP: Field = 16..24;
+    pub const ORIGINATE_TIMESTAMP: Field = 24..32;
+    pub const RECEIVE_TIMESTAMP: Field = 32..40;
+    pub const TRANSMIT_TIMESTAMP: Field = 40..48;
+}
+
+/// The length of an NTP packet without extension fields.
+pub const HEADER_LEN: usize = field::TRANSMIT_TIMESTAMP.end;
+
+/// A read/write wrapper around an NTP packet buffer.
+#[derive(Debug, PartialEq)]
+pub struct Packet<T: AsRef<[u8]>> {
+    buffer: T
+}
+
+impl<T: AsRef<[u8]>> Packet<T> {
+    /// Imbue a raw octet buffer with NTP packet structure.
+    pub fn new(buffer: T) -> Packet<T> {
+        Packet { buffer }
+    }
+
+    /// Shorthand for a combination of [new] and [check_len].
+    ///
+    /// [new]: #method.new
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<Packet<T>> {
+        let packet = Self::new(buffer);
+        packet.check_len()?;
+        Ok(packet)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error::Truncated)` if the buffer is too short.
+    pub fn check_len(&self) -> Result<()> {
+        if self.buffer.as_ref().len() < HEADER_LEN {
+            Err(Error::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Consume the packet, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Return the leap indicator field.
+    #[inline]
+    pub fn leap_indicator(&self) -> LeapIndicator {
+        LeapIndicator::from(self.buffer.as_ref()[field::LI_VN_MODE] >> 6)
+    }
+
+    /// Return the version field.
+    #[inline]
+    pub fn version(&self) -> u8 {
+        (self.buffer.as_ref()[field::LI_VN_MODE] >> 3) & 0x7
+    }
+
+    /// Return the mode field.
+    #[inline]
+    pub fn mode(&self) -> ProtocolMode {
+        ProtocolMode::from(self.buffer.as_ref()[field::LI_VN_MODE] & 0x7)
+    }
+
+    /// Return the stratum field.
+    #[inline]
+    pub fn stratum(&self) -> u8 {
+        self.buffer.as_ref()[field::STRATUM]
+    }
+
+    /// Return the poll field, the log2 of the poll interval in seconds.
+    #[inline]
+    pub fn poll(&self) -> i8 {
+        self.buffer.as_ref()[field::POLL] as i8
+    }
+
+    /// Return the precision field, the log2 of the precision of the clock in seconds.
+    #[inline]
+    pub fn precision(&self) -> i8 {
+        self.buffer.as_ref()[field::PRECISION] as i8
+    }
+
+    /// Return the root delay field, in seconds as a 16.16 fixed-point number.
+    #[inline]
+    pub fn root_delay(&self) -> u32 {
+        NetworkEndian::read_u32(&self.buffer.as_ref()[field::ROOT_DELAY])
+    }
+
+    /// Return the root dispersion field, in seconds as a 16.16 fixed-point number.
+    #[inline]
+    pub fn root_dispersion(&self) -> u32 {
+        NetworkEndian::read_u32(&self.buffer.as_ref()[field::ROOT_DISPERSION])
+    }
+
+    /// Return the reference ID field. In a Kiss-o'-Death packet, it holds the kiss code.
+    #[inline]
+    pub fn reference_id(&self) -> [u8; 4] {
+        let mut id = [0; 4];
+        id.copy_from_slice(&self.buffer.as_ref()[field::REFERENCE_ID]);
+        id
+    }
+
+    fn timestamp(&self, field: ::wire::field::Field) -> Timestamp {
+        let data = &self.buffer.as_ref()[field];
+        Timestamp {
+            seconds:  NetworkEndian::read_u32(&data[0..4]),
+            fraction: NetworkEndian::read_u32(&data[4..8]),
+        }
+    }
+
+    /// Return the reference timestamp field.
+    #[inline]
+    pub fn reference_timestamp(&self) -> Timestamp {
+        self.timestamp(field::REFERENCE_TIMESTAMP)
+    }
+
+    /// Return the originate timestamp field.
+    #[inline]
+    pub fn originate_timestamp(&self) -> Timestamp {
+        self.timestamp(field::ORIGINATE_TIMESTAMP)
+    }
+
+    /// Return the receive timestamp field.
+    #[inline]
+    pub fn receive_timestamp(&self) -> Timestamp {
+        self.timestamp(field::RECEIVE_TIMESTAMP)
+    }
+
+    /// Return the transmit timestamp field.
+    #[inline]
+    pub fn transmit_timestamp(&self) -> Timestamp {
+        self.timestamp(field::TRANSMIT_TIMESTAMP)
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
+    /// Set the leap indicator, version and mode fields.
+    #[inline]
+    pub fn set_header(&mut self, leap_indicator: LeapIndicator, version: u8, mode: ProtocolMode) {
+        let leap_indicator: u8 = leap_indicator.into();
+        let mode: u8 = mode.into();
+        self.buffer.as_mut()[field::LI_VN_MODE] =
+            (leap_indicator << 6) | ((version & 0x7) << 3) | (mode & 0x7)
+    }
+
+    /// Set the stratum field.
+    #[inline]
+    pub fn set_stratum(&mut self, value: u8) {
+        self.buffer.as_mut()[field::STRATUM] = value
+    }
+
+    /// Set the poll field.
+    #[inline]
+    pub fn set_poll(&mut self, value: i8) {
+        self.buffer.as_mut()[field::POLL] = value as u8
+    }
+
+    /// Set the precision field.
+    #[inline]
+    pub fn set_precision(&mut self, value: i8) {
+        self.buffer.as_mut()[field::PRECISION] = value as u8
+    }
+
+    /// Set the root delay field.
+    #[inline]
+    pub fn set_root_delay(&mut self, value: u32) {
+        NetworkEndian::write_u32(&mut self.buffer.as_mut()[field::ROOT_DELAY], value)
+    }
+
+    /// Set the root dispersion field.
+    #[inline]
+    pub fn set_root_dispersion(&mut self, value: u32) {
+        NetworkEndian::write_u32(&mut self.buffer.as_mut()[field::ROOT_DISPERSION], value)
+    }
+
+    /// Set the reference ID field.
+    #[inline]
+    pub fn set_reference_id(&mut self, value: [u8; 4]) {
+        self.buffer.as_mut()[field::REFERENCE_ID].copy_from_slice(&value)
+    }
+
+    fn set_timestamp(&mut self, field: ::wire::field::Field, value: Timestamp) {
+        let data = &mut self.buffer.as_mut()[field];
+        NetworkEndian::write_u32(&mut data[0..4], value.seconds);
+        NetworkEndian::write_u32(&mut data[4..8], value.fraction);
+    }
+
+    /// Set the reference timestamp field.
+    #[inline]
+    pub fn set_reference_timestamp(&mut self, value: Timestamp) {
+        self.set_timestamp(field::REFERENCE_TIMESTAMP, value)
+    }
+
+    /// Set the originate timestamp field.
+    #[inline]
+    pub fn set_originate_timestamp(&mut self, value: Timestamp) {
+        self.set_timestamp(field::ORIGINATE_TIMESTAMP, value)
+    }
+
+    /// Set the receive timestamp field.
+    #[inline]
+    pub fn set_receive_timestamp(&mut self, value: Timestamp) {
+        self.set_timestamp(field::RECEIVE_TIMESTAMP, value)
+    }
+
+    /// Set the transmit timestamp field.
+    #[inline]
+    pub fn set_transmit_timestamp(&mut self, value: Timestamp) {
+        self.set_timestamp(field::TRANSMIT_TIMESTAMP, value)
+    }
+}
+
+/// A high-level representation of an NTP packet, without extension fields.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Repr {
+    pub leap_indicator:      LeapIndicator,
+    pub version:             u8,
+    pub mode:                ProtocolMode,
+    pub stratum:             u8,
+    pub poll:                i8,
+    pub precision:           i8,
+    pub root_delay:          u32,
+    pub root_dispersion:     u32,
+    pub reference_id:        [u8; 4],
+    pub reference_timestamp: Timestamp,
+    pub originate_timestamp: Timestamp,
+    pub receive_timestamp:   Timestamp,
+    pub transmit_timestamp:  Timestamp,
+}
+
+impl Repr {
+    /// Parse an NTP packet and return a high-level representation.
+    pub fn parse<T: AsRef<[u8]> + ?Sized>(packet: &Packet<&T>) -> Result<Repr> {
+        packet.check_len()?;
+        if packet.version() == 0 { return Err(Error::Malformed) }
+        Ok(Repr {
+            leap_indicator:      packet.leap_indicator(),
+            version:             packet.version(),
+            mode:                packet.mode(),
+            stratum:             packet.stratum(),
+            poll:                packet.poll(),
+            precision:           packet.precision(),
+            root_delay:          packet.root_delay(),
+            root_dispersion:     packet.root_dispersion(),
+            reference_id:        packet.reference_id(),
+            reference_timestamp: packet.reference_timestamp(),
+            originate_timestamp: packet.originate_timestamp(),
+            receive_timestamp:   packet.receive_timestamp(),
+            transmit_timestamp:  packet.transmit_timestamp(),
+        })
+    }
+
+    /// Return the length of a packet that will be emitted from this high-level representation.
+    pub fn buffer_len(&self) -> usize {
+        HEADER_LEN
+    }
+
+    /// Emit a high-level representation into an NTP packet.
+    pub fn emit<T: AsRef<[u8]> + AsMut<[u8]>>(&self, packet: &mut Packet<T>) {
+        packet.set_header(self.leap_indicator, self.version, self.mode);
+        packet.set_stratum(self.stratum);
+        packet.set_poll(self.poll);
+        packet.set_precision(self.precision);
+        packet.set_root_delay(self.root_delay);
+        packet.set_root_dispersion(self.root_dispersion);
+        packet.set_reference_id(self.reference_id);
+        packet.set_reference_timestamp(self.reference_timestamp);
+        packet.set_originate_timestamp(self.originate_timestamp);
+        packet.set_receive_timestamp(self.receive_timestamp);
+        packet.set_transmit_timestamp(self.transmit_timestamp);
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> fmt::Display for Packet<&'a T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match Repr::parse(self) {
+            Ok(repr) => write!(f, "{}", repr),
+            Err(err) => write!(f, "NTP ({})", err)
+        }
+    }
+}
+
+impl fmt::Display for Repr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NTPv{} {:?} stratum={} xmit={}.{:08x}",
+               self.version, self.mode, self.stratum,
+               self.transmit_timestamp.seconds, self.transmit_timestamp.fraction)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // A server response, sent at 2020-01-01 00:00:00.5 UTC.
+    static RESPONSE_BYTES: [u8; 48] = [
+        0x24, 0x02, 0x06, 0xe9, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x20,
+        0xc0, 0xa8, 0x01, 0x01,
+        0xe1, 0xb6, 0x5f, 0x80, 0x00, 0x00, 0x00, 0x00,
+        0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0,
+        0xe1, 0xb6, 0x5f, 0x80, 0x40, 0x00, 0x00, 0x00,
+        0xe1, 0xb6, 0x5f, 0x80, 0x80, 0x00, 0x00, 0x00,
+    ];
+
+    fn response_repr() -> Repr {
+        Repr {
+            leap_indicator:      LeapIndicator::NoWarning,
+            version:             4,
+            mode:                ProtocolMode::Server,
+            stratum:             2,
+            poll:                6,
+            precision:           -23,
+            root_delay:          0x10,
+            root_dispersion:     0x20,
+            reference_id:        [192, 168, 1, 1],
+            reference_timestamp: Timestamp { seconds: 0xe1b65f80, fraction: 0 },
+            originate_timestamp: Timestamp { seconds: 0x12345678, fraction: 0x9abcdef0 },
+            receive_timestamp:   Timestamp { seconds: 0xe1b65f80, fraction: 0x40000000 },
+            transmit_timestamp:  Timestamp { seconds: 0xe1b65f80, fraction: 0x80000000 },
+        }
+    }
+
+    #[test]
+    fn test_deconstruct() {
+        let packet = Packet::new_checked(&RESPONSE_BYTES[..]).unwrap();
+        assert_eq!(packet.leap_indicator(), LeapIndicator::NoWarning);
+        assert_eq!(packet.version(), 4);
+        assert_eq!(packet.mode(), ProtocolMode::Server);
+        assert_eq!(packet.stratum(), 2);
+        assert_eq!(packet.precision(), -23);
+        assert_eq!(packet.reference_id(), [192, 168, 1, 1]);
+        assert_eq!(packet.transmit_timestamp().to_unix_millis(), 1_577_836_800_500);
+    }
+
+    #[test]
+    fn test_parse() {
+        let packet = Packet::new(&RESPONSE_BYTES[..]);
+        assert_eq!(Repr::parse(&packet), Ok(response_repr()));
+        assert_eq!(Repr::parse(&Packet::new(&RESPONSE_BYTES[..47])), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn test_emit() {
+        let repr = response_repr();
+        let mut bytes = [0xa5; 48];
+        repr.emit(&mut Packet::new(&mut bytes[..]));
+        assert_eq!(&bytes[..], &RESPONSE_BYTES[..]);
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let timestamp = Timestamp::from_unix_millis(1_577_836_800_500);
+        assert_eq!(timestamp, Timestamp { seconds: 0xe1b65f80, fraction: 0x80000000 });
+        assert_eq!(Timestamp::from_unix_millis(-1).to_unix_millis(), -1);
+        // The seconds wrap around on 2036-02-07.
+        let after_wrap = Timestamp::from_unix_millis(2_085_978_496_000 + 1_000);
+        assert_eq!(after_wrap.seconds, 1);
+        assert_eq!(after_wrap.to_unix_millis(), 2_085_978_497_000);
+    }
+}
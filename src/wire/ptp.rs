@@ -0,0 +1,760 @@
+// Heads up! Before working on this file you should read IEEE 1588-2008 § 13,
+// which describes the PTP message formats, and annexes D and F, which describe
+// the transport of PTP messages over UDP and over Ethernet.
+
+use core::fmt;
+use byteorder::{ByteOrder, NetworkEndian};
+
+use {Error, Result};
+use super::EthernetAddress;
+#[cfg(feature = "proto-ipv4")]
+use super::Ipv4Address;
+#[cfg(feature = "proto-ipv6")]
+use super::Ipv6Address;
+
+/// The UDP port event messages, which are timestamped, are sent to.
+pub const EVENT_PORT: u16 = 319;
+
+/// The UDP port general messages, which are not timestamped, are sent to.
+pub const GENERAL_PORT: u16 = 320;
+
+/// The multicast address PTP messages are sent to over Ethernet.
+pub const MULTICAST_ADDR: EthernetAddress =
+    EthernetAddress([0x01, 0x1b, 0x19, 0x00, 0x00, 0x00]);
+
+/// The multicast address PTP messages are sent to over UDP and IPv4.
+#[cfg(feature = "proto-ipv4")]
+pub const IPV4_MULTICAST_ADDR: Ipv4Address = Ipv4Address([224, 0, 1, 129]);
+
+/// The multicast address PTP messages are sent to over UDP and IPv6, with global scope.
+#[cfg(feature = "proto-ipv6")]
+pub const IPV6_MULTICAST_ADDR: Ipv6Address =
+    Ipv6Address([0xff, 0x0e, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0x81]);
+
+/// The version of PTP described by IEEE 1588-2008.
+pub const VERSION: u8 = 2;
+
+/// The flag set in the messages of a clock that sends the time a `Sync` message left
+/// at in a `Follow_Up` message.
+pub const FLAG_TWO_STEP: u16 = 0x0200;
+
+enum_with_unknown! {
+    /// PTP message type.
+    pub enum MessageType(u8) {
+        Sync               = 0x0,
+        DelayReq           = 0x1,
+        PdelayReq          = 0x2,
+        PdelayResp         = 0x3,
+        FollowUp           = 0x8,
+        DelayResp          = 0x9,
+        PdelayRespFollowUp = 0xa,
+        Announce           = 0xb,
+        Signaling          = 0xc,
+        Management         = 0xd
+    }
+}
+
+impl MessageType {
+    /// Return whether messages of this type are event messages, which are timestamped
+    /// when they are sent and received, and sent to the [event port](constant.EVENT_PORT.html).
+    pub fn is_event(&self) -> bool {
+        match *self {
+            MessageType::Sync | MessageType::DelayReq |
+            MessageType::PdelayReq | MessageType::PdelayResp => true,
+            _ => false
+        }
+    }
+}
+
+impl fmt::Display for MessageType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &MessageType::Sync => write!(f, "Sync"),
+            &MessageType::DelayReq => write!(f, "Delay_Req"),
+            &MessageType::PdelayReq => write!(f, "Pdelay_Req"),
+            &MessageType::PdelayResp => write!(f, "Pdelay_Resp"),
+            &MessageType::FollowUp => write!(f, "Follow_Up"),
+            &MessageType::DelayResp => write!(f, "Delay_Resp"),
+            &MessageType::PdelayRespFollowUp => write!(f, "Pdelay_Resp_Follow_Up"),
+            &MessageType::Announce => write!(f, "Announce"),
+            &MessageType::Signaling => write!(f, "Signaling"),
+            &MessageType::Management => write!(f, "Management"),
+            &MessageType::Unknown(id) => write!(f, "0x{:x}", id)
+        }
+    }
+}
+
+/// A PTP timestamp, made of 48-bit seconds and nanoseconds.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
+pub struct Timestamp {
+    pub seconds:     u64,
+    pub nanoseconds: u32
+}
+
+impl Timestamp {
+    /// Create a timestamp from a number of nanoseconds, e.g. a hardware timestamp
+    /// read from a PTP hardware clock.
+    pub fn from_nanos(nanos: u64) -> Timestamp {
+        Timestamp {
+            seconds:     nanos / 1_000_000_000,
+            nanoseconds: (nanos % 1_000_000_000) as u32
+        }
+    }
+
+    /// Return the number of nanoseconds this timestamp stands for.
+    pub fn total_nanos(&self) -> u64 {
+        self.seconds * 1_000_000_000 + self.nanoseconds as u64
+    }
+
+    fn read(data: &[u8]) -> Timestamp {
+        Timestamp {
+            seconds:     NetworkEndian::read_uint(&data[..6], 6),
+            nanoseconds: NetworkEndian::read_u32(&data[6..10])
+        }
+    }
+
+    fn write(&self, data: &mut [u8]) {
+        NetworkEndian::write_uint(&mut data[..6], self.seconds & 0xffff_ffff_ffff, 6);
+        NetworkEndian::write_u32(&mut data[6..10], self.nanoseconds)
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:09}", self.seconds, self.nanoseconds)
+    }
+}
+
+/// The identity of a port of a PTP clock.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
+pub struct PortIdentity {
+    /// The identity of the clock, usually derived from its hardware address.
+    pub clock_identity: [u8; 8],
+    /// The number of the port, starting from 1.
+    pub port_number:    u16
+}
+
+impl PortIdentity {
+    /// Create the identity of the given port of the clock of an interface with
+    /// the given hardware address, as described by IEEE 1588-2008 § 7.5.2.2.2.
+    pub fn from_ethernet_addr(addr: EthernetAddress, port_number: u16) -> PortIdentity {
+        let a = addr.0;
+        PortIdentity {
+            clock_identity: [a[0], a[1], a[2], 0xff, 0xfe, a[3], a[4], a[5]],
+            port_number
+        }
+    }
+
+    fn read(data: &[u8]) -> PortIdentity {
+        let mut clock_identity = [0; 8];
+        clock_identity.copy_from_slice(&data[..8]);
+        PortIdentity { clock_identity, port_number: NetworkEndian::read_u16(&data[8..10]) }
+    }
+
+    fn write(&self, data: &mut [u8]) {
+        data[..8].copy_from_slice(&self.clock_identity);
+        NetworkEndian::write_u16(&mut data[8..10], self.port_number)
+    }
+}
+
+impl fmt::Display for PortIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let c = self.clock_identity;
+        write!(f, "{:02x}{:02x}{:02x}.{:02x}{:02x}.{:02x}{:02x}{:02x}-{}",
+               c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7], self.port_number)
+    }
+}
+
+/// A read/write wrapper around a PTP message buffer.
+///
+/// The buffer may extend past the length of the message, e.g. with the padding
+/// of a short Ethernet frame, which is ignored.
+#[derive(Debug, PartialEq)]
+pub struct Packet<T: AsRef<[u8]>> {
+    buffer: T
+}
+
+mod field {
+    use wire::field::*;
+
+    pub const TYPE:          usize = 0;
+    pub const VERSION:       usize = 1;
+    pub const LENGTH:        Field = 2..4;
+    pub const DOMAIN:        usize = 4;
+    pub const FLAGS:         Field = 6..8;
+    pub const CORRECTION:    Field = 8..16;
+    pub const SOURCE_PORT:   Field = 20..30;
+    pub const SEQUENCE_ID:   Field = 30..32;
+    pub const CONTROL:       usize = 32;
+    pub const LOG_INTERVAL:  usize = 33;
+    pub const TIMESTAMP:     Field = 34..44;
+    pub const REQUESTING_PORT: Field = 44..54;
+}
+
+/// The length of a PTP message header.
+pub const HEADER_LEN: usize = field::TIMESTAMP.start;
+
+/// The length of a `Sync`, `Delay_Req` or `Follow_Up` message.
+pub const SYNC_LEN: usize = field::TIMESTAMP.end;
+
+/// The length of a `Delay_Resp` message.
+pub const DELAY_RESP_LEN: usize = field::REQUESTING_PORT.end;
+
+impl<T: AsRef<[u8]>> Packet<T> {
+    /// Imbue a raw octet buffer with PTP message structure.
+    pub fn new(buffer: T) -> Packet<T> {
+        Packet { buffer }
+    }
+
+    /// Shorthand for a combination of [new] and [check_len].
+    ///
+    /// [new]: #method.new
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<Packet<T>> {
+        let packet = Self::new(buffer);
+        packet.check_len()?;
+        Ok(packet)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error::Truncated)` if the buffer is shorter than the header
+    /// or the message length, and `Err(Error::Malformed)` if the message length
+    /// is shorter than the header.
+    ///
+    /// The result of this check is invalidated by calling [set_message_length].
+    ///
+    /// [set_message_length]: #method.set_message_length
+    pub fn check_len(&self) -> Result<()> {
+        let len = self.buffer.as_ref().len();
+        if len < HEADER_LEN {
+            Err(Error::Truncated)
+        } else if (self.message_length() as usize) < HEADER_LEN {
+            Err(Error::Malformed)
+        } else if len < self.message_length() as usize {
+            Err(Error::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Consume the packet, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Return the transport specific field.
+    #[inline]
+    pub fn transport_specific(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[field::TYPE] >> 4
+    }
+
+    /// Return the message type field.
+    #[inline]
+    pub fn message_type(&self) -> MessageType {
+        let data = self.buffer.as_ref();
+        MessageType::from(data[field::TYPE] & 0x0f)
+    }
+
+    /// Return the PTP version field.
+    #[inline]
+    pub fn version(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[field::VERSION] & 0x0f
+    }
+
+    /// Return the message length field.
+    #[inline]
+    pub fn message_length(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::LENGTH])
+    }
+
+    /// Return the domain number field.
+    #[inline]
+    pub fn domain_number(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[field::DOMAIN]
+    }
+
+    /// Return the flag field.
+    #[inline]
+    pub fn flags(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::FLAGS])
+    }
+
+    /// Return the correction field, in nanoseconds multiplied by 2<sup>16</sup>.
+    #[inline]
+    pub fn correction(&self) -> i64 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_i64(&data[field::CORRECTION])
+    }
+
+    /// Return the source port identity field.
+    #[inline]
+    pub fn source_port_identity(&self) -> PortIdentity {
+        let data = self.buffer.as_ref();
+        PortIdentity::read(&data[field::SOURCE_PORT])
+    }
+
+    /// Return the sequence identifier field.
+    #[inline]
+    pub fn sequence_id(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::SEQUENCE_ID])
+    }
+
+    /// Return the control field, which PTPv1 hardware uses in place of the message type.
+    #[inline]
+    pub fn control(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[field::CONTROL]
+    }
+
+    /// Return the log message interval field.
+    #[inline]
+    pub fn log_message_interval(&self) -> i8 {
+        let data = self.buffer.as_ref();
+        data[field::LOG_INTERVAL] as i8
+    }
+
+    /// Return the timestamp that follows the header of a `Sync`, `Delay_Req`, `Follow_Up`
+    /// or `Delay_Resp` message.
+    ///
+    /// # Panics
+    /// This function may panic if the message is shorter than `SYNC_LEN`.
+    #[inline]
+    pub fn timestamp(&self) -> Timestamp {
+        let data = self.buffer.as_ref();
+        Timestamp::read(&data[field::TIMESTAMP])
+    }
+
+    /// Return the requesting port identity field of a `Delay_Resp` message.
+    ///
+    /// # Panics
+    /// This function may panic if the message is shorter than `DELAY_RESP_LEN`.
+    #[inline]
+    pub fn requesting_port_identity(&self) -> PortIdentity {
+        let data = self.buffer.as_ref();
+        PortIdentity::read(&data[field::REQUESTING_PORT])
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> Packet<&'a T> {
+    /// Return a pointer to the body of the message, i.e. what follows the header.
+    #[inline]
+    pub fn body(&self) -> &'a [u8] {
+        let length = self.message_length() as usize;
+        let data = self.buffer.as_ref();
+        &data[HEADER_LEN..length]
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
+    /// Set the transport specific and message type fields.
+    #[inline]
+    pub fn set_message_type(&mut self, transport_specific: u8, value: MessageType) {
+        let data = self.buffer.as_mut();
+        data[field::TYPE] = (transport_specific << 4) | (u8::from(value) & 0x0f)
+    }
+
+    /// Set the PTP version field, clearing the reserved bits.
+    #[inline]
+    pub fn set_version(&mut self, value: u8) {
+        let data = self.buffer.as_mut();
+        data[field::VERSION] = value & 0x0f
+    }
+
+    /// Set the message length field.
+    #[inline]
+    pub fn set_message_length(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::LENGTH], value)
+    }
+
+    /// Set the domain number field.
+    #[inline]
+    pub fn set_domain_number(&mut self, value: u8) {
+        let data = self.buffer.as_mut();
+        data[field::DOMAIN] = value
+    }
+
+    /// Set the flag field.
+    #[inline]
+    pub fn set_flags(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::FLAGS], value)
+    }
+
+    /// Set the correction field.
+    #[inline]
+    pub fn set_correction(&mut self, value: i64) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_i64(&mut data[field::CORRECTION], value)
+    }
+
+    /// Set the source port identity field.
+    #[inline]
+    pub fn set_source_port_identity(&mut self, value: PortIdentity) {
+        let data = self.buffer.as_mut();
+        value.write(&mut data[field::SOURCE_PORT])
+    }
+
+    /// Set the sequence identifier field.
+    #[inline]
+    pub fn set_sequence_id(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::SEQUENCE_ID], value)
+    }
+
+    /// Set the control field.
+    #[inline]
+    pub fn set_control(&mut self, value: u8) {
+        let data = self.buffer.as_mut();
+        data[field::CONTROL] = value
+    }
+
+    /// Set the log message interval field.
+    #[inline]
+    pub fn set_log_message_interval(&mut self, value: i8) {
+        let data = self.buffer.as_mut();
+        data[field::LOG_INTERVAL] = value as u8
+    }
+
+    /// Clear the reserved fields.
+    #[inline]
+    pub fn clear_reserved(&mut self) {
+        let data = self.buffer.as_mut();
+        data[field::DOMAIN + 1] = 0;
+        for byte in &mut data[field::CORRECTION.end..field::SOURCE_PORT.start] {
+            *byte = 0
+        }
+    }
+
+    /// Set the timestamp that follows the header.
+    #[inline]
+    pub fn set_timestamp(&mut self, value: Timestamp) {
+        let data = self.buffer.as_mut();
+        value.write(&mut data[field::TIMESTAMP])
+    }
+
+    /// Set the requesting port identity field.
+    #[inline]
+    pub fn set_requesting_port_identity(&mut self, value: PortIdentity) {
+        let data = self.buffer.as_mut();
+        value.write(&mut data[field::REQUESTING_PORT])
+    }
+}
+
+/// The body of a PTP event message, or of the general message that follows one.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Message {
+    /// A `Sync` message, with the time it was sent at, or an estimate of it
+    /// if the time follows in a `Follow_Up` message.
+    Sync {
+        origin_timestamp: Timestamp
+    },
+    /// A `Delay_Req` message, with an estimate of the time it was sent at.
+    DelayReq {
+        origin_timestamp: Timestamp
+    },
+    /// A `Follow_Up` message, with the time the `Sync` message with the same
+    /// sequence identifier was sent at.
+    FollowUp {
+        precise_origin_timestamp: Timestamp
+    },
+    /// A `Delay_Resp` message, with the time the `Delay_Req` message from the given port,
+    /// with the same sequence identifier, was received at.
+    DelayResp {
+        receive_timestamp:        Timestamp,
+        requesting_port_identity: PortIdentity
+    },
+}
+
+impl Message {
+    /// Return the type of the message.
+    pub fn message_type(&self) -> MessageType {
+        match self {
+            &Message::Sync { .. } => MessageType::Sync,
+            &Message::DelayReq { .. } => MessageType::DelayReq,
+            &Message::FollowUp { .. } => MessageType::FollowUp,
+            &Message::DelayResp { .. } => MessageType::DelayResp
+        }
+    }
+
+    // The value of the control field, kept for the sake of PTPv1 hardware.
+    fn control(&self) -> u8 {
+        match self {
+            &Message::Sync { .. } => 0,
+            &Message::DelayReq { .. } => 1,
+            &Message::FollowUp { .. } => 2,
+            &Message::DelayResp { .. } => 3
+        }
+    }
+}
+
+/// A high-level representation of a PTP message.
+///
+/// Only the messages of the delay request-response mechanism are represented;
+/// the other messages, e.g. `Announce`, can be handled with a [Packet].
+///
+/// [Packet]: struct.Packet.html
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Repr {
+    pub domain_number:        u8,
+    pub flags:                u16,
+    pub correction:           i64,
+    pub source_port_identity: PortIdentity,
+    pub sequence_id:          u16,
+    pub log_message_interval: i8,
+    pub message:              Message
+}
+
+impl Repr {
+    /// Parse a PTP message and return a high-level representation, or return
+    /// `Err(Error::Unrecognized)` if the version or the message type is not supported.
+    pub fn parse<T>(packet: &Packet<&T>) -> Result<Repr>
+            where T: AsRef<[u8]> + ?Sized {
+        packet.check_len()?;
+        if packet.version() != VERSION { return Err(Error::Unrecognized) }
+
+        let length = packet.message_length() as usize;
+        let message = match packet.message_type() {
+            MessageType::Sync | MessageType::DelayReq | MessageType::FollowUp
+                    if length < SYNC_LEN => return Err(Error::Malformed),
+            MessageType::DelayResp if length < DELAY_RESP_LEN => return Err(Error::Malformed),
+            MessageType::Sync => Message::Sync { origin_timestamp: packet.timestamp() },
+            MessageType::DelayReq => Message::DelayReq { origin_timestamp: packet.timestamp() },
+            MessageType::FollowUp =>
+                Message::FollowUp { precise_origin_timestamp: packet.timestamp() },
+            MessageType::DelayResp =>
+                Message::DelayResp {
+                    receive_timestamp:        packet.timestamp(),
+                    requesting_port_identity: packet.requesting_port_identity()
+                },
+            _ => return Err(Error::Unrecognized)
+        };
+
+        Ok(Repr {
+            domain_number:        packet.domain_number(),
+            flags:                packet.flags(),
+            correction:           packet.correction(),
+            source_port_identity: packet.source_port_identity(),
+            sequence_id:          packet.sequence_id(),
+            log_message_interval: packet.log_message_interval(),
+            message
+        })
+    }
+
+    /// Return the length of a message that will be emitted from this high-level representation.
+    pub fn buffer_len(&self) -> usize {
+        match self.message {
+            Message::DelayResp { .. } => DELAY_RESP_LEN,
+            _ => SYNC_LEN
+        }
+    }
+
+    /// Emit a high-level representation into a PTP message.
+    pub fn emit<T: AsRef<[u8]> + AsMut<[u8]>>(&self, packet: &mut Packet<T>) {
+        packet.set_message_type(0, self.message.message_type());
+        packet.set_version(VERSION);
+        packet.set_message_length(self.buffer_len() as u16);
+        packet.set_domain_number(self.domain_number);
+        packet.set_flags(self.flags);
+        packet.set_correction(self.correction);
+        packet.clear_reserved();
+        packet.set_source_port_identity(self.source_port_identity);
+        packet.set_sequence_id(self.sequence_id);
+        packet.set_control(self.message.control());
+        packet.set_log_message_interval(self.log_message_interval);
+        match self.message {
+            Message::Sync { origin_timestamp: timestamp } |
+            Message::DelayReq { origin_timestamp: timestamp } |
+            Message::FollowUp { precise_origin_timestamp: timestamp } =>
+                packet.set_timestamp(timestamp),
+            Message::DelayResp { receive_timestamp, requesting_port_identity } => {
+                packet.set_timestamp(receive_timestamp);
+                packet.set_requesting_port_identity(requesting_port_identity);
+            }
+        }
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> fmt::Display for Packet<&'a T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match Repr::parse(self) {
+            Ok(repr) => write!(f, "{}", repr),
+            Err(Error::Unrecognized) if self.check_len().is_ok() =>
+                write!(f, "PTPv{} {} src={} seq={}",
+                       self.version(), self.message_type(),
+                       self.source_port_identity(), self.sequence_id()),
+            Err(err) => write!(f, "PTP ({})", err)
+        }
+    }
+}
+
+impl fmt::Display for Repr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PTPv2 {} src={} seq={} domain={}",
+               self.message.message_type(), self.source_port_identity,
+               self.sequence_id, self.domain_number)?;
+        match self.message {
+            Message::Sync { origin_timestamp: timestamp } |
+            Message::DelayReq { origin_timestamp: timestamp } |
+            Message::FollowUp { precise_origin_timestamp: timestamp } =>
+                write!(f, " time={}", timestamp),
+            Message::DelayResp { receive_timestamp, requesting_port_identity } =>
+                write!(f, " time={} req={}", receive_timestamp, requesting_port_identity)
+        }
+    }
+}
+
+use super::pretty_print::{PrettyPrint, PrettyIndent};
+
+impl<T: AsRef<[u8]>> PrettyPrint for Packet<T> {
+    fn pretty_print(buffer: &AsRef<[u8]>, f: &mut fmt::Formatter,
+                    indent: &mut PrettyIndent) -> fmt::Result {
+        write!(f, "{}{}", indent, Packet::new(buffer.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static SYNC_BYTES: [u8; 44] =
+        [0x00, 0x02, 0x00, 0x2c, 0x00, 0x00, 0x02, 0x00,
+         0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+         0x00, 0x00, 0x00, 0x00,
+         0x02, 0x00, 0x00, 0xff, 0xfe, 0x00, 0x00, 0x01, 0x00, 0x01,
+         0x00, 0x2a, 0x00, 0x00,
+         0x00, 0x00, 0x5f, 0x5e, 0x10, 0x00, 0x07, 0x5b, 0xcd, 0x15];
+
+    static DELAY_RESP_BYTES: [u8; 54] =
+        [0x09, 0x02, 0x00, 0x36, 0x00, 0x00, 0x00, 0x00,
+         0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+         0x00, 0x00, 0x00, 0x00,
+         0x02, 0x00, 0x00, 0xff, 0xfe, 0x00, 0x00, 0x01, 0x00, 0x01,
+         0x00, 0x07, 0x03, 0x7f,
+         0x00, 0x00, 0x5f, 0x5e, 0x10, 0x00, 0x00, 0x00, 0x03, 0xe8,
+         0x02, 0x00, 0x00, 0xff, 0xfe, 0x00, 0x00, 0x02, 0x00, 0x01];
+
+    fn master() -> PortIdentity {
+        PortIdentity::from_ethernet_addr(EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]), 1)
+    }
+
+    fn slave() -> PortIdentity {
+        PortIdentity::from_ethernet_addr(EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x02]), 1)
+    }
+
+    fn sync_repr() -> Repr {
+        Repr {
+            domain_number:        0,
+            flags:                FLAG_TWO_STEP,
+            correction:           0x10000,
+            source_port_identity: master(),
+            sequence_id:          42,
+            log_message_interval: 0,
+            message: Message::Sync {
+                origin_timestamp: Timestamp { seconds: 1_600_000_000, nanoseconds: 123_456_789 }
+            }
+        }
+    }
+
+    fn delay_resp_repr() -> Repr {
+        Repr {
+            domain_number:        0,
+            flags:                0,
+            correction:           0,
+            source_port_identity: master(),
+            sequence_id:          7,
+            log_message_interval: 127,
+            message: Message::DelayResp {
+                receive_timestamp:        Timestamp::from_nanos(1_600_000_000_000_001_000),
+                requesting_port_identity: slave()
+            }
+        }
+    }
+
+    #[test]
+    fn test_deconstruct() {
+        let packet = Packet::new(&SYNC_BYTES[..]);
+        assert_eq!(packet.transport_specific(), 0);
+        assert_eq!(packet.message_type(), MessageType::Sync);
+        assert!(packet.message_type().is_event());
+        assert_eq!(packet.version(), 2);
+        assert_eq!(packet.message_length(), 44);
+        assert_eq!(packet.flags(), FLAG_TWO_STEP);
+        assert_eq!(packet.correction(), 0x10000);
+        assert_eq!(packet.source_port_identity(), master());
+        assert_eq!(packet.sequence_id(), 42);
+        assert_eq!(packet.control(), 0);
+        assert_eq!(packet.timestamp().total_nanos(), 1_600_000_000_123_456_789);
+    }
+
+    #[test]
+    fn test_parse() {
+        let packet = Packet::new(&SYNC_BYTES[..]);
+        assert_eq!(Repr::parse(&packet), Ok(sync_repr()));
+        let packet = Packet::new(&DELAY_RESP_BYTES[..]);
+        assert_eq!(Repr::parse(&packet), Ok(delay_resp_repr()));
+
+        // The padding of a short Ethernet frame is ignored.
+        let mut bytes = SYNC_BYTES.to_vec();
+        bytes.resize(46, 0);
+        let packet = Packet::new(&bytes[..]);
+        assert_eq!(Repr::parse(&packet), Ok(sync_repr()));
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        let packet = Packet::new(&SYNC_BYTES[..43]);
+        assert_eq!(Repr::parse(&packet), Err(Error::Truncated));
+
+        // A Delay_Resp message as long as a Sync message.
+        let mut bytes = DELAY_RESP_BYTES.to_vec();
+        bytes[3] = 0x2c;
+        let packet = Packet::new(&bytes[..]);
+        assert_eq!(Repr::parse(&packet), Err(Error::Malformed));
+
+        // A PTPv1 message.
+        let mut bytes = SYNC_BYTES.to_vec();
+        bytes[1] = 0x01;
+        let packet = Packet::new(&bytes[..]);
+        assert_eq!(Repr::parse(&packet), Err(Error::Unrecognized));
+
+        // An Announce message.
+        let mut bytes = SYNC_BYTES.to_vec();
+        bytes[0] = 0x0b;
+        let packet = Packet::new(&bytes[..]);
+        assert_eq!(Repr::parse(&packet), Err(Error::Unrecognized));
+    }
+
+    #[test]
+    fn test_emit() {
+        let repr = sync_repr();
+        assert_eq!(repr.buffer_len(), SYNC_BYTES.len());
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new(&mut bytes));
+        assert_eq!(&bytes[..], &SYNC_BYTES[..]);
+
+        let repr = delay_resp_repr();
+        assert_eq!(repr.buffer_len(), DELAY_RESP_BYTES.len());
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new(&mut bytes));
+        assert_eq!(&bytes[..], &DELAY_RESP_BYTES[..]);
+    }
+
+    #[test]
+    fn test_display() {
+        let packet = Packet::new(&SYNC_BYTES[..]);
+        assert_eq!(format!("{}", packet),
+                   "PTPv2 Sync src=020000.fffe.000001-1 seq=42 domain=0 \
+                    time=1600000000.123456789");
+        let mut bytes = SYNC_BYTES.to_vec();
+        bytes[0] = 0x0b;
+        let packet = Packet::new(&bytes[..]);
+        assert_eq!(format!("{}", packet), "PTPv2 Announce src=020000.fffe.000001-1 seq=42");
+    }
+}
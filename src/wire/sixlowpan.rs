@@ -0,0 +1,1056 @@
+// Heads up! Before working on this file you should read RFC 4944, which describes
+// the transmission of IPv6 packets over IEEE 802.15.4 networks, and RFC 6282, which
+// describes the compression of their headers.
+
+use core::fmt;
+use byteorder::{ByteOrder, NetworkEndian};
+
+use {Error, Result};
+use super::{IpProtocol, Ipv6Address, Ieee802154Address};
+
+/// The kind of a 6LoWPAN header, told apart by its dispatch value; see RFC 4944 § 5.1
+/// and RFC 6282 § 3.1.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Dispatch {
+    /// An uncompressed IPv6 header.
+    Ipv6,
+    /// An IPv6 header compressed with IPHC.
+    Iphc,
+    /// The header of the first fragment of a datagram.
+    FirstFragment,
+    /// The header of a subsequent fragment of a datagram.
+    Fragment,
+}
+
+impl Dispatch {
+    /// Return the kind of the 6LoWPAN header the given payload of a frame starts with.
+    ///
+    /// Returns `Err(Error::Unrecognized)` for the headers that are not supported,
+    /// i.e. mesh and broadcast headers, and for data that is not 6LoWPAN at all.
+    pub fn of_packet(data: &[u8]) -> Result<Dispatch> {
+        match data.first() {
+            None => Err(Error::Truncated),
+            Some(&0x41) => Ok(Dispatch::Ipv6),
+            Some(&byte) if byte & 0xe0 == 0x60 => Ok(Dispatch::Iphc),
+            Some(&byte) if byte & 0xf8 == 0xc0 => Ok(Dispatch::FirstFragment),
+            Some(&byte) if byte & 0xf8 == 0xe0 => Ok(Dispatch::Fragment),
+            Some(_) => Err(Error::Unrecognized)
+        }
+    }
+}
+
+/// A read/write wrapper around a 6LoWPAN fragmentation header.
+#[derive(Debug, PartialEq)]
+pub struct FragmentHeader<T: AsRef<[u8]>> {
+    buffer: T
+}
+
+/// A read/write wrapper around a 6LoWPAN IPHC header, i.e. a compressed IPv6 header.
+#[derive(Debug, PartialEq)]
+pub struct IphcPacket<T: AsRef<[u8]>> {
+    buffer: T
+}
+
+/// A read/write wrapper around a 6LoWPAN UDP header compressed with next header compression.
+#[derive(Debug, PartialEq)]
+pub struct UdpNhcPacket<T: AsRef<[u8]>> {
+    buffer: T
+}
+
+// Format of the fragmentation headers; the first fragment has no offset:
+//
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |1 1 x 0 0|    datagram_size    |         datagram_tag          |datagram_offset|
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// The IPHC header starts with two octets of encoding bits, which are followed
+// by the fields that are carried inline, in the order of RFC 6282 § 3.1.1:
+//
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |0|1|1| TF |N|HLIM|C|S|SAM|M|D|DAM|
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+mod field {
+    use wire::field::*;
+
+    pub const FRAG_SIZE:   Field = 0..2;
+    pub const FRAG_TAG:    Field = 2..4;
+    pub const FRAG_OFFSET: usize = 4;
+
+    pub const IPHC_TF_NH_HLIM:  usize = 0;
+    pub const IPHC_CID_SAM_DAM: usize = 1;
+    pub const IPHC_INLINE:      Rest  = 2..;
+
+    pub const UDP_NHC:  usize = 0;
+    pub const UDP_PORTS: Rest = 1..;
+}
+
+/// The length of the header of the first fragment of a datagram.
+pub const FIRST_FRAGMENT_HEADER_LEN: usize = field::FRAG_OFFSET;
+
+/// The length of the header of a subsequent fragment of a datagram.
+pub const FRAGMENT_HEADER_LEN: usize = field::FRAG_OFFSET + 1;
+
+impl<T: AsRef<[u8]>> FragmentHeader<T> {
+    /// Imbue a raw octet buffer with 6LoWPAN fragmentation header structure.
+    pub fn new(buffer: T) -> FragmentHeader<T> {
+        FragmentHeader { buffer }
+    }
+
+    /// Shorthand for a combination of [new] and [check_len].
+    ///
+    /// [new]: #method.new
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<FragmentHeader<T>> {
+        let header = Self::new(buffer);
+        header.check_len()?;
+        Ok(header)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error::Truncated)` if the buffer is too short.
+    pub fn check_len(&self) -> Result<()> {
+        let len = self.buffer.as_ref().len();
+        if len < FIRST_FRAGMENT_HEADER_LEN || len < self.header_len() {
+            Err(Error::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Consume the header, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Query whether this is the header of the first fragment of a datagram.
+    #[inline]
+    pub fn is_first(&self) -> bool {
+        let data = self.buffer.as_ref();
+        data[field::FRAG_SIZE.start] & 0x20 == 0
+    }
+
+    /// Return the length of the header.
+    pub fn header_len(&self) -> usize {
+        if self.is_first() { FIRST_FRAGMENT_HEADER_LEN } else { FRAGMENT_HEADER_LEN }
+    }
+
+    /// Return the datagram size field, i.e. the length of the reassembled IPv6 packet.
+    #[inline]
+    pub fn datagram_size(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::FRAG_SIZE]) & 0x7ff
+    }
+
+    /// Return the datagram tag field.
+    #[inline]
+    pub fn datagram_tag(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::FRAG_TAG])
+    }
+
+    /// Return the datagram offset field, in octets; it is 0 in the first fragment.
+    #[inline]
+    pub fn datagram_offset(&self) -> u16 {
+        if self.is_first() { return 0 }
+        let data = self.buffer.as_ref();
+        data[field::FRAG_OFFSET] as u16 * 8
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> FragmentHeader<&'a T> {
+    /// Return the data following the fragmentation header.
+    #[inline]
+    pub fn payload(&self) -> &'a [u8] {
+        let data = self.buffer.as_ref();
+        &data[self.header_len()..]
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> FragmentHeader<T> {
+    /// Set the dispatch value and the datagram size field.
+    #[inline]
+    pub fn set_first_and_size(&mut self, first: bool, value: u16) {
+        let data = self.buffer.as_mut();
+        let dispatch = if first { 0xc000 } else { 0xe000 };
+        NetworkEndian::write_u16(&mut data[field::FRAG_SIZE], dispatch | (value & 0x7ff))
+    }
+
+    /// Set the datagram tag field.
+    #[inline]
+    pub fn set_datagram_tag(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::FRAG_TAG], value)
+    }
+
+    /// Set the datagram offset field, in octets.
+    ///
+    /// # Panics
+    /// This function panics if this is the header of the first fragment, or if
+    /// the offset is not a multiple of 8.
+    #[inline]
+    pub fn set_datagram_offset(&mut self, value: u16) {
+        assert!(!self.is_first(), "the first fragment has no offset");
+        assert!(value % 8 == 0, "datagram offset is not a multiple of 8");
+        let data = self.buffer.as_mut();
+        data[field::FRAG_OFFSET] = (value / 8) as u8
+    }
+}
+
+/// A high-level representation of a 6LoWPAN fragmentation header.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FragmentRepr {
+    /// The length of the reassembled IPv6 packet, in octets.
+    pub datagram_size:   u16,
+    pub datagram_tag:    u16,
+    /// The offset of the fragment in the reassembled IPv6 packet, in octets;
+    /// the first fragment, and only it, has an offset of 0.
+    pub datagram_offset: u16,
+}
+
+impl FragmentRepr {
+    /// Parse a 6LoWPAN fragmentation header and return a high-level representation.
+    pub fn parse<T: AsRef<[u8]> + ?Sized>(header: &FragmentHeader<&T>) -> Result<FragmentRepr> {
+        header.check_len()?;
+        match Dispatch::of_packet(header.buffer.as_ref())? {
+            Dispatch::FirstFragment | Dispatch::Fragment => (),
+            _ => return Err(Error::Unrecognized)
+        }
+        if !header.is_first() && header.datagram_offset() == 0 {
+            return Err(Error::Malformed)
+        }
+        Ok(FragmentRepr {
+            datagram_size:   header.datagram_size(),
+            datagram_tag:    header.datagram_tag(),
+            datagram_offset: header.datagram_offset()
+        })
+    }
+
+    /// Return the length of a header that will be emitted from this high-level representation.
+    pub fn buffer_len(&self) -> usize {
+        if self.datagram_offset == 0 { FIRST_FRAGMENT_HEADER_LEN } else { FRAGMENT_HEADER_LEN }
+    }
+
+    /// Emit a high-level representation into a 6LoWPAN fragmentation header.
+    pub fn emit<T: AsRef<[u8]> + AsMut<[u8]>>(&self, header: &mut FragmentHeader<T>) {
+        header.set_first_and_size(self.datagram_offset == 0, self.datagram_size);
+        header.set_datagram_tag(self.datagram_tag);
+        if self.datagram_offset != 0 {
+            header.set_datagram_offset(self.datagram_offset);
+        }
+    }
+}
+
+impl fmt::Display for FragmentRepr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "6LoWPAN Fragment size={} tag={} offset={}",
+               self.datagram_size, self.datagram_tag, self.datagram_offset)
+    }
+}
+
+/// Return the length of an inline IPv6 address, given the address mode bits of
+/// an IPHC header, or `None` if the encoding is reserved.
+fn iphc_addr_len(multicast: bool, context: bool, mode: u8) -> Option<usize> {
+    match (multicast, context, mode) {
+        (false, false, _) => Some([16, 8, 2, 0][mode as usize]),
+        (false, true, 0b00) => Some(0),
+        (false, true, _) => Some([16, 8, 2, 0][mode as usize]),
+        (true, false, _) => Some([16, 6, 4, 1][mode as usize]),
+        (true, true, 0b00) => Some(6),
+        (true, true, _) => None
+    }
+}
+
+/// Return the address with the link-local prefix and the given interface identifier.
+fn link_local_addr(iid: &[u8]) -> Ipv6Address {
+    let mut bytes = [0; 16];
+    bytes[0] = 0xfe;
+    bytes[1] = 0x80;
+    bytes[8..].copy_from_slice(iid);
+    Ipv6Address(bytes)
+}
+
+impl<T: AsRef<[u8]>> IphcPacket<T> {
+    /// Imbue a raw octet buffer with IPHC header structure.
+    pub fn new(buffer: T) -> IphcPacket<T> {
+        IphcPacket { buffer }
+    }
+
+    /// Shorthand for a combination of [new] and [check_len].
+    ///
+    /// [new]: #method.new
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<IphcPacket<T>> {
+        let packet = Self::new(buffer);
+        packet.check_len()?;
+        Ok(packet)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error::Truncated)` if the buffer is too short, and
+    /// `Err(Error::Malformed)` if an address is encoded in a reserved mode.
+    pub fn check_len(&self) -> Result<()> {
+        let len = self.buffer.as_ref().len();
+        if len < field::IPHC_INLINE.start {
+            return Err(Error::Truncated)
+        }
+        if iphc_addr_len(false, self.sac(), self.sam()).is_none() ||
+                iphc_addr_len(self.multicast(), self.dac(), self.dam()).is_none() {
+            return Err(Error::Malformed)
+        }
+        if len < self.header_len() {
+            Err(Error::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Consume the packet, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Return the traffic class and flow label encoding bits.
+    #[inline]
+    pub fn tf(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        (data[field::IPHC_TF_NH_HLIM] >> 3) & 0b11
+    }
+
+    /// Return the next header encoding bit, i.e. whether the next header is compressed.
+    #[inline]
+    pub fn nh(&self) -> bool {
+        let data = self.buffer.as_ref();
+        data[field::IPHC_TF_NH_HLIM] & 0b100 != 0
+    }
+
+    /// Return the hop limit encoding bits.
+    #[inline]
+    pub fn hlim(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[field::IPHC_TF_NH_HLIM] & 0b11
+    }
+
+    /// Return the context identifier extension bit.
+    #[inline]
+    pub fn cid(&self) -> bool {
+        let data = self.buffer.as_ref();
+        data[field::IPHC_CID_SAM_DAM] & 0x80 != 0
+    }
+
+    /// Return the source address compression bit, i.e. whether it is context-based.
+    #[inline]
+    pub fn sac(&self) -> bool {
+        let data = self.buffer.as_ref();
+        data[field::IPHC_CID_SAM_DAM] & 0x40 != 0
+    }
+
+    /// Return the source address mode bits.
+    #[inline]
+    pub fn sam(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        (data[field::IPHC_CID_SAM_DAM] >> 4) & 0b11
+    }
+
+    /// Return the multicast compression bit.
+    #[inline]
+    pub fn multicast(&self) -> bool {
+        let data = self.buffer.as_ref();
+        data[field::IPHC_CID_SAM_DAM] & 0x08 != 0
+    }
+
+    /// Return the destination address compression bit, i.e. whether it is context-based.
+    #[inline]
+    pub fn dac(&self) -> bool {
+        let data = self.buffer.as_ref();
+        data[field::IPHC_CID_SAM_DAM] & 0x04 != 0
+    }
+
+    /// Return the destination address mode bits.
+    #[inline]
+    pub fn dam(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[field::IPHC_CID_SAM_DAM] & 0b11
+    }
+
+    fn tf_start(&self) -> usize {
+        field::IPHC_INLINE.start + if self.cid() { 1 } else { 0 }
+    }
+
+    fn next_header_start(&self) -> usize {
+        self.tf_start() + [4, 3, 1, 0][self.tf() as usize]
+    }
+
+    fn hop_limit_start(&self) -> usize {
+        self.next_header_start() + if self.nh() { 0 } else { 1 }
+    }
+
+    fn src_addr_start(&self) -> usize {
+        self.hop_limit_start() + if self.hlim() == 0b00 { 1 } else { 0 }
+    }
+
+    fn dst_addr_start(&self) -> usize {
+        self.src_addr_start() + iphc_addr_len(false, self.sac(), self.sam()).unwrap_or(0)
+    }
+
+    /// Return the length of the IPHC header, including the fields carried inline.
+    pub fn header_len(&self) -> usize {
+        self.dst_addr_start() +
+            iphc_addr_len(self.multicast(), self.dac(), self.dam()).unwrap_or(0)
+    }
+
+    /// Return the Explicit Congestion Notification field.
+    pub fn ecn(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        match self.tf() {
+            0b11 => 0,
+            _ => data[self.tf_start()] >> 6
+        }
+    }
+
+    /// Return the Differentiated Services Code Point field.
+    pub fn dscp(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        match self.tf() {
+            0b00 | 0b10 => data[self.tf_start()] & 0x3f,
+            _ => 0
+        }
+    }
+
+    /// Return the flow label field.
+    pub fn flow_label(&self) -> u32 {
+        let data = self.buffer.as_ref();
+        let start = self.tf_start();
+        match self.tf() {
+            0b00 => NetworkEndian::read_u32(&data[start..start + 4]) & 0xfffff,
+            0b01 => NetworkEndian::read_u24(&data[start..start + 3]) & 0xfffff,
+            _ => 0
+        }
+    }
+
+    /// Return the next header field, or `None` if the next header is compressed too.
+    pub fn next_header(&self) -> Option<IpProtocol> {
+        if self.nh() { return None }
+        let data = self.buffer.as_ref();
+        Some(IpProtocol::from(data[self.next_header_start()]))
+    }
+
+    /// Return the hop limit field.
+    pub fn hop_limit(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        match self.hlim() {
+            0b00 => data[self.hop_limit_start()],
+            0b01 => 1,
+            0b10 => 64,
+            _ => 255
+        }
+    }
+
+    fn unicast_addr(&self, start: usize, context: bool, mode: u8,
+                    ll_addr: Ieee802154Address) -> Result<Ipv6Address> {
+        let data = self.buffer.as_ref();
+        match (context, mode) {
+            (false, 0b00) => Ok(Ipv6Address::from_bytes(&data[start..start + 16])),
+            (false, 0b01) => Ok(link_local_addr(&data[start..start + 8])),
+            (false, 0b10) => Ok(link_local_addr(&[0x00, 0x00, 0x00, 0xff, 0xfe, 0x00,
+                                                  data[start], data[start + 1]])),
+            (false, _) => Ok(link_local_addr(&ll_addr.as_interface_id())),
+            (true, 0b00) => Ok(Ipv6Address::UNSPECIFIED),
+            // Contexts are not supported.
+            (true, _) => Err(Error::Unrecognized)
+        }
+    }
+
+    /// Return the source address, given the link-layer source address of the frame.
+    ///
+    /// Returns `Err(Error::Unrecognized)` if the address is compressed using a context.
+    pub fn src_addr(&self, ll_src_addr: Ieee802154Address) -> Result<Ipv6Address> {
+        self.unicast_addr(self.src_addr_start(), self.sac(), self.sam(), ll_src_addr)
+    }
+
+    /// Return the destination address, given the link-layer destination address
+    /// of the frame.
+    ///
+    /// Returns `Err(Error::Unrecognized)` if the address is compressed using a context.
+    pub fn dst_addr(&self, ll_dst_addr: Ieee802154Address) -> Result<Ipv6Address> {
+        let start = self.dst_addr_start();
+        if !self.multicast() {
+            return self.unicast_addr(start, self.dac(), self.dam(), ll_dst_addr)
+        }
+
+        let data = self.buffer.as_ref();
+        let mut bytes = [0; 16];
+        bytes[0] = 0xff;
+        match (self.dac(), self.dam()) {
+            (false, 0b00) => bytes.copy_from_slice(&data[start..start + 16]),
+            (false, 0b01) => {
+                bytes[1] = data[start];
+                bytes[11..].copy_from_slice(&data[start + 1..start + 6]);
+            }
+            (false, 0b10) => {
+                bytes[1] = data[start];
+                bytes[13..].copy_from_slice(&data[start + 1..start + 4]);
+            }
+            (false, _) => {
+                bytes[1] = 0x02;
+                bytes[15] = data[start];
+            }
+            // Contexts are not supported.
+            (true, _) => return Err(Error::Unrecognized)
+        }
+        Ok(Ipv6Address(bytes))
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> IphcPacket<&'a T> {
+    /// Return the data following the IPHC header.
+    #[inline]
+    pub fn payload(&self) -> &'a [u8] {
+        let data = self.buffer.as_ref();
+        &data[self.header_len()..]
+    }
+}
+
+/// A high-level representation of an IPHC header, i.e. of the IPv6 header
+/// it compresses.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct IphcRepr {
+    pub src_addr:    Ipv6Address,
+    pub dst_addr:    Ipv6Address,
+    /// The protocol of the next header, or `None` if it is compressed too, i.e. a next
+    /// header compression header follows.
+    pub next_header: Option<IpProtocol>,
+    pub hop_limit:   u8,
+    pub ecn:         u8,
+    pub dscp:        u8,
+    pub flow_label:  u32,
+}
+
+impl IphcRepr {
+    /// Parse an IPHC header and return a high-level representation, given the link-layer
+    /// addresses of the frame it was received in, from which addresses may be derived.
+    ///
+    /// Returns `Err(Error::Unrecognized)` if the header refers to a context, as contexts
+    /// are not supported.
+    pub fn parse<T: AsRef<[u8]> + ?Sized>(packet: &IphcPacket<&T>,
+                                          ll_src_addr: Ieee802154Address,
+                                          ll_dst_addr: Ieee802154Address) -> Result<IphcRepr> {
+        packet.check_len()?;
+        if Dispatch::of_packet(packet.buffer.as_ref())? != Dispatch::Iphc {
+            return Err(Error::Unrecognized)
+        }
+        if packet.cid() { return Err(Error::Unrecognized) }
+        Ok(IphcRepr {
+            src_addr:    packet.src_addr(ll_src_addr)?,
+            dst_addr:    packet.dst_addr(ll_dst_addr)?,
+            next_header: packet.next_header(),
+            hop_limit:   packet.hop_limit(),
+            ecn:         packet.ecn(),
+            dscp:        packet.dscp(),
+            flow_label:  packet.flow_label()
+        })
+    }
+
+    fn tf(&self) -> u8 {
+        match (self.dscp, self.flow_label) {
+            (0, 0) if self.ecn == 0 => 0b11,
+            (_, 0) => 0b10,
+            (0, _) => 0b01,
+            _ => 0b00
+        }
+    }
+
+    fn hlim(&self) -> u8 {
+        match self.hop_limit {
+            1 => 0b01,
+            64 => 0b10,
+            255 => 0b11,
+            _ => 0b00
+        }
+    }
+
+    /// Return the address mode bits and the inline octets of a unicast address, i.e.
+    /// the context bit, the mode, and the range of the address that is carried inline.
+    fn unicast_mode(addr: &Ipv6Address, ll_addr: Ieee802154Address) -> (bool, u8, usize) {
+        let bytes = addr.as_bytes();
+        if addr.is_unspecified() {
+            (true, 0b00, 16)
+        } else if bytes[..8] != [0xfe, 0x80, 0, 0, 0, 0, 0, 0] {
+            (false, 0b00, 0)
+        } else if bytes[8..] == ll_addr.as_interface_id() {
+            (false, 0b11, 16)
+        } else if bytes[8..14] == [0x00, 0x00, 0x00, 0xff, 0xfe, 0x00] {
+            (false, 0b10, 14)
+        } else {
+            (false, 0b01, 8)
+        }
+    }
+
+    /// Return the address mode bits of a multicast address, and its octets
+    /// that are carried inline.
+    fn multicast_mode(addr: &Ipv6Address, inline: &mut [u8; 16]) -> (u8, usize) {
+        let bytes = addr.as_bytes();
+        if bytes[1] == 0x02 && bytes[2..15].iter().all(|&b| b == 0) {
+            inline[0] = bytes[15];
+            (0b11, 1)
+        } else if bytes[2..13].iter().all(|&b| b == 0) {
+            inline[0] = bytes[1];
+            inline[1..4].copy_from_slice(&bytes[13..]);
+            (0b10, 4)
+        } else if bytes[2..11].iter().all(|&b| b == 0) {
+            inline[0] = bytes[1];
+            inline[1..6].copy_from_slice(&bytes[11..]);
+            (0b01, 6)
+        } else {
+            inline.copy_from_slice(bytes);
+            (0b00, 16)
+        }
+    }
+
+    /// Return the length of a header that will be emitted from this high-level
+    /// representation, given the link-layer addresses of the frame it is sent in.
+    pub fn buffer_len(&self, ll_src_addr: Ieee802154Address,
+                      ll_dst_addr: Ieee802154Address) -> usize {
+        let mut len = field::IPHC_INLINE.start;
+        len += [4, 3, 1, 0][self.tf() as usize];
+        if self.next_header.is_some() { len += 1 }
+        if self.hlim() == 0b00 { len += 1 }
+        len += 16 - Self::unicast_mode(&self.src_addr, ll_src_addr).2;
+        if self.dst_addr.is_multicast() {
+            len += Self::multicast_mode(&self.dst_addr, &mut [0; 16]).1;
+        } else {
+            len += 16 - Self::unicast_mode(&self.dst_addr, ll_dst_addr).2;
+        }
+        len
+    }
+
+    /// Emit a high-level representation into an IPHC header, compressing it as much
+    /// as possible without contexts, given the link-layer addresses of the frame
+    /// it is sent in.
+    pub fn emit<T: AsRef<[u8]> + AsMut<[u8]>>(&self, packet: &mut IphcPacket<T>,
+                                              ll_src_addr: Ieee802154Address,
+                                              ll_dst_addr: Ieee802154Address) {
+        let data = packet.buffer.as_mut();
+        let tf = self.tf();
+        let hlim = self.hlim();
+        let (sac, sam, src_elided) = Self::unicast_mode(&self.src_addr, ll_src_addr);
+        let mut dst_inline = [0; 16];
+        let (dac, dam, dst_inline_len) = if self.dst_addr.is_multicast() {
+            let (dam, len) = Self::multicast_mode(&self.dst_addr, &mut dst_inline);
+            (false, dam, len)
+        } else {
+            let (dac, dam, elided) = Self::unicast_mode(&self.dst_addr, ll_dst_addr);
+            dst_inline[..16 - elided].copy_from_slice(&self.dst_addr.as_bytes()[elided..]);
+            (dac, dam, 16 - elided)
+        };
+
+        data[field::IPHC_TF_NH_HLIM] = 0x60 | (tf << 3) |
+                                       if self.next_header.is_none() { 0b100 } else { 0 } |
+                                       hlim;
+        data[field::IPHC_CID_SAM_DAM] = if sac { 0x40 } else { 0 } | (sam << 4) |
+                                        if self.dst_addr.is_multicast() { 0x08 } else { 0 } |
+                                        if dac { 0x04 } else { 0 } | dam;
+
+        let mut offset = field::IPHC_INLINE.start;
+        match tf {
+            0b00 => {
+                NetworkEndian::write_u32(&mut data[offset..offset + 4], self.flow_label);
+                data[offset] = (self.ecn << 6) | self.dscp;
+                offset += 4;
+            }
+            0b01 => {
+                NetworkEndian::write_u24(&mut data[offset..offset + 3], self.flow_label);
+                data[offset] = (self.ecn << 6) | (data[offset] & 0x0f);
+                offset += 3;
+            }
+            0b10 => {
+                data[offset] = (self.ecn << 6) | self.dscp;
+                offset += 1;
+            }
+            _ => ()
+        }
+        if let Some(next_header) = self.next_header {
+            data[offset] = next_header.into();
+            offset += 1;
+        }
+        if hlim == 0b00 {
+            data[offset] = self.hop_limit;
+            offset += 1;
+        }
+        let src_inline = &self.src_addr.as_bytes()[src_elided..];
+        data[offset..offset + src_inline.len()].copy_from_slice(src_inline);
+        offset += src_inline.len();
+        data[offset..offset + dst_inline_len].copy_from_slice(&dst_inline[..dst_inline_len]);
+    }
+}
+
+impl fmt::Display for IphcRepr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "6LoWPAN IPHC src={} dst={} hlim={}",
+               self.src_addr, self.dst_addr, self.hop_limit)?;
+        match self.next_header {
+            Some(next_header) => write!(f, " nxt_hdr={}", next_header),
+            None => write!(f, " nxt_hdr=compressed")
+        }
+    }
+}
+
+/// Query whether a next header compression header is a compressed UDP header.
+pub fn is_udp_nhc(data: &[u8]) -> bool {
+    data.first().map_or(false, |&byte| byte & 0xf8 == 0xf0)
+}
+
+impl<T: AsRef<[u8]>> UdpNhcPacket<T> {
+    /// Imbue a raw octet buffer with compressed UDP header structure.
+    pub fn new(buffer: T) -> UdpNhcPacket<T> {
+        UdpNhcPacket { buffer }
+    }
+
+    /// Shorthand for a combination of [new] and [check_len].
+    ///
+    /// [new]: #method.new
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<UdpNhcPacket<T>> {
+        let packet = Self::new(buffer);
+        packet.check_len()?;
+        Ok(packet)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error::Truncated)` if the buffer is too short.
+    pub fn check_len(&self) -> Result<()> {
+        let len = self.buffer.as_ref().len();
+        if len < field::UDP_PORTS.start || len < self.header_len() {
+            Err(Error::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Consume the packet, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Return the checksum compression bit, i.e. whether the checksum is elided.
+    #[inline]
+    pub fn checksum_elided(&self) -> bool {
+        let data = self.buffer.as_ref();
+        data[field::UDP_NHC] & 0b100 != 0
+    }
+
+    /// Return the port compression bits.
+    #[inline]
+    pub fn ports(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[field::UDP_NHC] & 0b11
+    }
+
+    fn ports_len(&self) -> usize {
+        [4, 3, 3, 1][self.ports() as usize]
+    }
+
+    /// Return the length of the compressed UDP header.
+    pub fn header_len(&self) -> usize {
+        field::UDP_PORTS.start + self.ports_len() + if self.checksum_elided() { 0 } else { 2 }
+    }
+
+    /// Return the source port field.
+    pub fn src_port(&self) -> u16 {
+        let data = &self.buffer.as_ref()[field::UDP_PORTS];
+        match self.ports() {
+            0b00 | 0b01 => NetworkEndian::read_u16(data),
+            0b10 => 0xf000 | data[0] as u16,
+            _ => 0xf0b0 | (data[0] >> 4) as u16
+        }
+    }
+
+    /// Return the destination port field.
+    pub fn dst_port(&self) -> u16 {
+        let data = &self.buffer.as_ref()[field::UDP_PORTS];
+        match self.ports() {
+            0b00 => NetworkEndian::read_u16(&data[2..]),
+            0b01 => 0xf000 | data[2] as u16,
+            0b10 => NetworkEndian::read_u16(&data[1..]),
+            _ => 0xf0b0 | (data[0] & 0x0f) as u16
+        }
+    }
+
+    /// Return the checksum field, unless it is elided.
+    pub fn checksum(&self) -> Option<u16> {
+        if self.checksum_elided() { return None }
+        let data = &self.buffer.as_ref()[field::UDP_PORTS.start + self.ports_len()..];
+        Some(NetworkEndian::read_u16(data))
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> UdpNhcPacket<&'a T> {
+    /// Return the data following the compressed UDP header.
+    #[inline]
+    pub fn payload(&self) -> &'a [u8] {
+        let data = self.buffer.as_ref();
+        &data[self.header_len()..]
+    }
+}
+
+/// A high-level representation of a compressed UDP header.
+///
+/// The length of the datagram is always elided, and derived from the length of the
+/// IPv6 packet; the checksum is always carried inline.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct UdpNhcRepr {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub checksum: u16,
+}
+
+impl UdpNhcRepr {
+    /// Parse a compressed UDP header and return a high-level representation.
+    ///
+    /// Returns `Err(Error::Unrecognized)` if the checksum is elided, which is only
+    /// allowed with an upper layer that protects the datagram otherwise; see RFC 6282 § 4.3.2.
+    pub fn parse<T: AsRef<[u8]> + ?Sized>(packet: &UdpNhcPacket<&T>) -> Result<UdpNhcRepr> {
+        packet.check_len()?;
+        if !is_udp_nhc(packet.buffer.as_ref()) { return Err(Error::Unrecognized) }
+        match packet.checksum() {
+            Some(checksum) => Ok(UdpNhcRepr {
+                src_port: packet.src_port(),
+                dst_port: packet.dst_port(),
+                checksum: checksum
+            }),
+            None => Err(Error::Unrecognized)
+        }
+    }
+
+    fn ports(&self) -> u8 {
+        if self.src_port & 0xfff0 == 0xf0b0 && self.dst_port & 0xfff0 == 0xf0b0 {
+            0b11
+        } else if self.dst_port & 0xff00 == 0xf000 {
+            0b01
+        } else if self.src_port & 0xff00 == 0xf000 {
+            0b10
+        } else {
+            0b00
+        }
+    }
+
+    /// Return the length of a header that will be emitted from this high-level representation.
+    pub fn buffer_len(&self) -> usize {
+        field::UDP_PORTS.start + [4, 3, 3, 1][self.ports() as usize] + 2
+    }
+
+    /// Emit a high-level representation into a compressed UDP header.
+    pub fn emit<T: AsRef<[u8]> + AsMut<[u8]>>(&self, packet: &mut UdpNhcPacket<T>) {
+        let ports = self.ports();
+        let data = packet.buffer.as_mut();
+        data[field::UDP_NHC] = 0xf0 | ports;
+        let data = &mut data[field::UDP_PORTS];
+        let checksum_start = match ports {
+            0b00 => {
+                NetworkEndian::write_u16(&mut data[0..2], self.src_port);
+                NetworkEndian::write_u16(&mut data[2..4], self.dst_port);
+                4
+            }
+            0b01 => {
+                NetworkEndian::write_u16(&mut data[0..2], self.src_port);
+                data[2] = self.dst_port as u8;
+                3
+            }
+            0b10 => {
+                data[0] = self.src_port as u8;
+                NetworkEndian::write_u16(&mut data[1..3], self.dst_port);
+                3
+            }
+            _ => {
+                data[0] = ((self.src_port as u8 & 0x0f) << 4) | (self.dst_port as u8 & 0x0f);
+                1
+            }
+        };
+        NetworkEndian::write_u16(&mut data[checksum_start..checksum_start + 2], self.checksum)
+    }
+}
+
+impl fmt::Display for UdpNhcRepr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "6LoWPAN UDP src={} dst={}", self.src_port, self.dst_port)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LL_SRC_ADDR: Ieee802154Address =
+        Ieee802154Address::Extended([0x00, 0x12, 0x4b, 0x00, 0x14, 0xb5, 0xd9, 0xc7]);
+    const LL_DST_ADDR: Ieee802154Address = Ieee802154Address::Short([0x00, 0x01]);
+
+    // The header of the first fragment of a 300-octet datagram with tag 0x1234,
+    // and of a subsequent fragment at offset 96.
+    static FIRST_FRAGMENT_BYTES: [u8; 4] = [0xc1, 0x2c, 0x12, 0x34];
+    static FRAGMENT_BYTES: [u8; 5] = [0xe1, 0x2c, 0x12, 0x34, 0x0c];
+
+    #[test]
+    fn test_dispatch() {
+        assert_eq!(Dispatch::of_packet(&[]), Err(Error::Truncated));
+        assert_eq!(Dispatch::of_packet(&[0x41]), Ok(Dispatch::Ipv6));
+        assert_eq!(Dispatch::of_packet(&[0x7a]), Ok(Dispatch::Iphc));
+        assert_eq!(Dispatch::of_packet(&FIRST_FRAGMENT_BYTES), Ok(Dispatch::FirstFragment));
+        assert_eq!(Dispatch::of_packet(&FRAGMENT_BYTES), Ok(Dispatch::Fragment));
+        // A mesh header.
+        assert_eq!(Dispatch::of_packet(&[0x80]), Err(Error::Unrecognized));
+    }
+
+    #[test]
+    fn test_fragment_parse() {
+        let header = FragmentHeader::new(&FIRST_FRAGMENT_BYTES[..]);
+        assert_eq!(FragmentRepr::parse(&header),
+                   Ok(FragmentRepr { datagram_size: 300, datagram_tag: 0x1234,
+                                     datagram_offset: 0 }));
+        let header = FragmentHeader::new(&FRAGMENT_BYTES[..]);
+        assert_eq!(FragmentRepr::parse(&header),
+                   Ok(FragmentRepr { datagram_size: 300, datagram_tag: 0x1234,
+                                     datagram_offset: 96 }));
+        assert_eq!(FragmentHeader::new(&FRAGMENT_BYTES[..4]).check_len(),
+                   Err(Error::Truncated));
+    }
+
+    #[test]
+    fn test_fragment_emit() {
+        let repr = FragmentRepr { datagram_size: 300, datagram_tag: 0x1234,
+                                  datagram_offset: 96 };
+        let mut bytes = vec![0; repr.buffer_len()];
+        repr.emit(&mut FragmentHeader::new(&mut bytes));
+        assert_eq!(&bytes[..], &FRAGMENT_BYTES[..]);
+
+        let repr = FragmentRepr { datagram_offset: 0, ..repr };
+        let mut bytes = vec![0; repr.buffer_len()];
+        repr.emit(&mut FragmentHeader::new(&mut bytes));
+        assert_eq!(&bytes[..], &FIRST_FRAGMENT_BYTES[..]);
+    }
+
+    fn link_local_repr() -> IphcRepr {
+        IphcRepr {
+            src_addr:    link_local_addr(&LL_SRC_ADDR.as_interface_id()),
+            dst_addr:    link_local_addr(&LL_DST_ADDR.as_interface_id()),
+            next_header: None,
+            hop_limit:   64,
+            ecn:         0,
+            dscp:        0,
+            flow_label:  0
+        }
+    }
+
+    #[test]
+    fn test_iphc_link_local() {
+        // Both addresses are derived from the link-layer ones, and everything
+        // else is elided too.
+        let repr = link_local_repr();
+        assert_eq!(repr.buffer_len(LL_SRC_ADDR, LL_DST_ADDR), 2);
+        let mut bytes = vec![0; 2];
+        repr.emit(&mut IphcPacket::new(&mut bytes), LL_SRC_ADDR, LL_DST_ADDR);
+        assert_eq!(&bytes[..], &[0x7e, 0x33]);
+
+        let packet = IphcPacket::new_checked(&bytes[..]).unwrap();
+        assert_eq!(IphcRepr::parse(&packet, LL_SRC_ADDR, LL_DST_ADDR), Ok(repr));
+    }
+
+    #[test]
+    fn test_iphc_inline() {
+        let repr = IphcRepr {
+            src_addr:    Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1),
+            dst_addr:    Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0xff, 0xfe00, 0x1234),
+            next_header: Some(IpProtocol::Tcp),
+            hop_limit:   17,
+            ecn:         1,
+            dscp:        46,
+            flow_label:  0xabcde
+        };
+        static BYTES: [u8; 26] =
+            [0x60, 0x02,
+             0x6e, 0x0a, 0xbc, 0xde,
+             0x06,
+             0x11,
+             0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00,
+             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+             0x12, 0x34];
+        assert_eq!(repr.buffer_len(LL_SRC_ADDR, LL_DST_ADDR), BYTES.len());
+        let mut bytes = vec![0xa5; BYTES.len()];
+        repr.emit(&mut IphcPacket::new(&mut bytes), LL_SRC_ADDR, LL_DST_ADDR);
+        assert_eq!(&bytes[..], &BYTES[..]);
+
+        let packet = IphcPacket::new_checked(&BYTES[..]).unwrap();
+        assert_eq!(packet.header_len(), BYTES.len());
+        assert_eq!(IphcRepr::parse(&packet, LL_SRC_ADDR, LL_DST_ADDR), Ok(repr));
+        assert_eq!(IphcPacket::new(&BYTES[..25]).check_len(), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn test_iphc_flow_label_only() {
+        let repr = IphcRepr { ecn: 2, flow_label: 0x12345, ..link_local_repr() };
+        let mut bytes = vec![0; repr.buffer_len(LL_SRC_ADDR, LL_DST_ADDR)];
+        repr.emit(&mut IphcPacket::new(&mut bytes), LL_SRC_ADDR, LL_DST_ADDR);
+        assert_eq!(&bytes[..], &[0x6e, 0x33, 0x81, 0x23, 0x45]);
+        let packet = IphcPacket::new_checked(&bytes[..]).unwrap();
+        assert_eq!(IphcRepr::parse(&packet, LL_SRC_ADDR, LL_DST_ADDR), Ok(repr));
+    }
+
+    #[test]
+    fn test_iphc_multicast() {
+        for &(addr, len) in &[(Ipv6Address::LINK_LOCAL_ALL_NODES, 1),
+                              (Ipv6Address::new(0xff05, 0, 0, 0, 0, 0, 0x1, 0x3), 4),
+                              (Ipv6Address::new(0xff02, 0, 0, 0, 0, 1, 0xff00, 0x1), 6),
+                              (Ipv6Address::new(0xff02, 0, 0, 0, 1, 0, 0, 1), 16)] {
+            let repr = IphcRepr { dst_addr: addr, ..link_local_repr() };
+            assert_eq!(repr.buffer_len(LL_SRC_ADDR, Ieee802154Address::BROADCAST), 2 + len);
+            let mut bytes = vec![0; 2 + len];
+            repr.emit(&mut IphcPacket::new(&mut bytes), LL_SRC_ADDR,
+                      Ieee802154Address::BROADCAST);
+            let packet = IphcPacket::new_checked(&bytes[..]).unwrap();
+            assert_eq!(IphcRepr::parse(&packet, LL_SRC_ADDR, Ieee802154Address::BROADCAST),
+                       Ok(repr));
+        }
+    }
+
+    #[test]
+    fn test_iphc_unspecified_src() {
+        let repr = IphcRepr { src_addr: Ipv6Address::UNSPECIFIED, ..link_local_repr() };
+        let mut bytes = vec![0; repr.buffer_len(LL_SRC_ADDR, LL_DST_ADDR)];
+        repr.emit(&mut IphcPacket::new(&mut bytes), LL_SRC_ADDR, LL_DST_ADDR);
+        assert_eq!(&bytes[..], &[0x7e, 0x43]);
+        let packet = IphcPacket::new_checked(&bytes[..]).unwrap();
+        assert_eq!(IphcRepr::parse(&packet, LL_SRC_ADDR, LL_DST_ADDR), Ok(repr));
+    }
+
+    #[test]
+    fn test_iphc_context() {
+        // A source address compressed with context 0.
+        let packet = IphcPacket::new(&[0x7e, 0x73][..]);
+        assert_eq!(IphcRepr::parse(&packet, LL_SRC_ADDR, LL_DST_ADDR),
+                   Err(Error::Unrecognized));
+        // A reserved destination address mode.
+        let packet = IphcPacket::new(&[0x7e, 0x3d][..]);
+        assert_eq!(packet.check_len(), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn test_udp_nhc() {
+        for &(src_port, dst_port, bytes) in
+                &[(1234, 5678, &[0xf0, 0x04, 0xd2, 0x16, 0x2e, 0xbe, 0xef][..]),
+                  (1234, 0xf012, &[0xf1, 0x04, 0xd2, 0x12, 0xbe, 0xef][..]),
+                  (0xf012, 5678, &[0xf2, 0x12, 0x16, 0x2e, 0xbe, 0xef][..]),
+                  (0xf0b1, 0xf0b2, &[0xf3, 0x12, 0xbe, 0xef][..])] {
+            let repr = UdpNhcRepr { src_port, dst_port, checksum: 0xbeef };
+            assert_eq!(repr.buffer_len(), bytes.len());
+            let mut emitted = vec![0; bytes.len()];
+            repr.emit(&mut UdpNhcPacket::new(&mut emitted));
+            assert_eq!(&emitted[..], bytes);
+            assert_eq!(UdpNhcRepr::parse(&UdpNhcPacket::new(bytes)), Ok(repr));
+        }
+    }
+
+    #[test]
+    fn test_udp_nhc_checksum_elided() {
+        let packet = UdpNhcPacket::new(&[0xf7, 0x12][..]);
+        assert_eq!(packet.checksum(), None);
+        assert_eq!(UdpNhcRepr::parse(&packet), Err(Error::Unrecognized));
+    }
+}
@@ -2,6 +2,8 @@ use core::{i32, ops, cmp, fmt};
 use byteorder::{ByteOrder, NetworkEndian};
 
 use {Error, Result};
+#[cfg(feature = "arbitrary")]
+use arbitrary::{self, Arbitrary, Unstructured};
 use phy::ChecksumCapabilities;
 use super::{IpProtocol, IpAddress};
 use super::ip::checksum;
@@ -19,6 +21,13 @@ impl fmt::Display for SeqNumber {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for SeqNumber {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<SeqNumber> {
+        Ok(SeqNumber(u.arbitrary()?))
+    }
+}
+
 impl ops::Add<usize> for SeqNumber {
     type Output = SeqNumber;
 
@@ -103,6 +112,10 @@ mod field {
     pub const OPT_NOP: u8 = 0x01;
     pub const OPT_MSS: u8 = 0x02;
     pub const OPT_WS:  u8 = 0x03;
+    pub const OPT_SACKPERM: u8 = 0x04;
+    pub const OPT_SACKRNG:  u8 = 0x05;
+    pub const OPT_TSTAMP:   u8 = 0x08;
+    pub const OPT_MD5:      u8 = 0x13;
 }
 
 impl<T: AsRef<[u8]>> Packet<T> {
@@ -492,6 +505,27 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
         };
         self.set_checksum(checksum)
     }
+
+    /// Fill in the checksum of the pseudo-header only, for a device offloading the checksum
+    /// to compute the checksum of the rest of the packet.
+    ///
+    /// # Panics
+    /// This function panics unless `src_addr` and `dst_addr` belong to the same family,
+    /// and that family is IPv4 or IPv6.
+    pub fn fill_pseudo_header_checksum(&mut self, src_addr: &IpAddress, dst_addr: &IpAddress) {
+        let length = self.buffer.as_ref().len() as u32;
+        self.set_checksum(checksum::pseudo_header(src_addr, dst_addr, IpProtocol::Tcp, length))
+    }
+}
+
+impl<T: AsRef<[u8]>> Packet<T> {
+    /// Return the octets a TCP MD5 signature of the segment covers ahead of the payload.
+    ///
+    /// The buffer must hold the whole segment and nothing else.
+    pub fn signed_header(&self, src_addr: &IpAddress, dst_addr: &IpAddress) -> SignedHeader {
+        let data = self.buffer.as_ref();
+        SignedHeader::new(data, data.len(), src_addr, dst_addr)
+    }
 }
 
 impl<'a, T: AsRef<[u8]> + AsMut<[u8]> + ?Sized> Packet<&'a mut T> {
@@ -512,6 +546,57 @@ impl<'a, T: AsRef<[u8]> + AsMut<[u8]> + ?Sized> Packet<&'a mut T> {
     }
 }
 
+/// The octets a TCP MD5 signature covers ahead of the payload of a segment.
+///
+/// The signature described in [RFC 2385] is the MD5 digest of the pseudo-header,
+/// of the header without the options and with a zero checksum, of the payload,
+/// and of the key shared with the remote end, in this order. The pseudo-header
+/// of IPv6 is the one its checksum covers.
+///
+/// [RFC 2385]: https://tools.ietf.org/html/rfc2385
+#[derive(Debug, Clone, Copy)]
+pub struct SignedHeader {
+    buffer: [u8; 60],
+    len:    usize
+}
+
+impl SignedHeader {
+    fn new(header: &[u8], segment_len: usize,
+           src_addr: &IpAddress, dst_addr: &IpAddress) -> SignedHeader {
+        let mut buffer = [0; 60];
+        let len = match (src_addr, dst_addr) {
+            #[cfg(feature = "proto-ipv4")]
+            (&IpAddress::Ipv4(src_addr), &IpAddress::Ipv4(dst_addr)) => {
+                buffer[0..4].copy_from_slice(src_addr.as_bytes());
+                buffer[4..8].copy_from_slice(dst_addr.as_bytes());
+                buffer[9] = IpProtocol::Tcp.into();
+                NetworkEndian::write_u16(&mut buffer[10..12], segment_len as u16);
+                12
+            }
+            #[cfg(feature = "proto-ipv6")]
+            (&IpAddress::Ipv6(src_addr), &IpAddress::Ipv6(dst_addr)) => {
+                buffer[0..16].copy_from_slice(src_addr.as_bytes());
+                buffer[16..32].copy_from_slice(dst_addr.as_bytes());
+                NetworkEndian::write_u32(&mut buffer[32..36], segment_len as u32);
+                buffer[39] = IpProtocol::Tcp.into();
+                40
+            }
+            _ => panic!("Unexpected pseudo header addresses: {}, {}",
+                        src_addr, dst_addr)
+        };
+        buffer[len..len + field::URGENT.end].copy_from_slice(&header[..field::URGENT.end]);
+        for octet in buffer[len + field::CHECKSUM.start..len + field::CHECKSUM.end].iter_mut() {
+            *octet = 0
+        }
+        SignedHeader { buffer, len: len + field::URGENT.end }
+    }
+
+    /// Return the octets, the pseudo-header followed by the header.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
 /// A representation of a single TCP option.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum TcpOption<'a> {
@@ -519,9 +604,19 @@ pub enum TcpOption<'a> {
     NoOperation,
     MaxSegmentSize(u16),
     WindowScale(u8),
+    SackPermitted,
+    SackRange([Option<(u32, u32)>; SACK_RANGE_COUNT]),
+    TimeStamp { tsval: u32, tsecr: u32 },
+    Md5Signature([u8; 16]),
     Unknown { kind: u8, data: &'a [u8] }
 }
 
+/// The maximum number of SACK blocks in a SACK option that are represented.
+///
+/// Four blocks fit into the option space of a segment, but only three if it also carries
+/// a timestamp option, which is what most implementations send.
+pub const SACK_RANGE_COUNT: usize = 3;
+
 impl<'a> TcpOption<'a> {
     pub fn parse(buffer: &'a [u8]) -> Result<(&'a [u8], TcpOption<'a>)> {
         let (length, option);
@@ -549,6 +644,35 @@ impl<'a> TcpOption<'a> {
                         option = TcpOption::WindowScale(data[0]),
                     (field::OPT_WS, _) =>
                         return Err(Error::Malformed),
+                    (field::OPT_SACKPERM, 2) =>
+                        option = TcpOption::SackPermitted,
+                    (field::OPT_SACKPERM, _) =>
+                        return Err(Error::Malformed),
+                    (field::OPT_SACKRNG, _) if data.len() > 0 && data.len() % 8 == 0 => {
+                        // Any blocks past the ones we represent are ignored.
+                        let mut ranges = [None; SACK_RANGE_COUNT];
+                        for (range, block) in ranges.iter_mut().zip(data.chunks(8)) {
+                            *range = Some((NetworkEndian::read_u32(&block[0..4]),
+                                           NetworkEndian::read_u32(&block[4..8])));
+                        }
+                        option = TcpOption::SackRange(ranges)
+                    }
+                    (field::OPT_SACKRNG, _) =>
+                        return Err(Error::Malformed),
+                    (field::OPT_TSTAMP, 10) =>
+                        option = TcpOption::TimeStamp {
+                            tsval: NetworkEndian::read_u32(&data[0..4]),
+                            tsecr: NetworkEndian::read_u32(&data[4..8])
+                        },
+                    (field::OPT_TSTAMP, _) =>
+                        return Err(Error::Malformed),
+                    (field::OPT_MD5, 18) => {
+                        let mut digest = [0; 16];
+                        digest.copy_from_slice(data);
+                        option = TcpOption::Md5Signature(digest)
+                    }
+                    (field::OPT_MD5, _) =>
+                        return Err(Error::Malformed),
                     (_, _) =>
                         option = TcpOption::Unknown { kind: kind, data: data }
                 }
@@ -563,6 +687,11 @@ impl<'a> TcpOption<'a> {
             &TcpOption::NoOperation => 1,
             &TcpOption::MaxSegmentSize(_) => 4,
             &TcpOption::WindowScale(_) => 3,
+            &TcpOption::SackPermitted => 2,
+            &TcpOption::SackRange(ranges) =>
+                2 + 8 * ranges.iter().filter(|range| range.is_some()).count(),
+            &TcpOption::TimeStamp { .. } => 10,
+            &TcpOption::Md5Signature(_) => 18,
             &TcpOption::Unknown { data, .. } => 2 + data.len()
         }
     }
@@ -593,6 +722,26 @@ impl<'a> TcpOption<'a> {
                         buffer[0] = field::OPT_WS;
                         buffer[2] = value;
                     }
+                    &TcpOption::SackPermitted => {
+                        buffer[0] = field::OPT_SACKPERM;
+                    }
+                    &TcpOption::SackRange(ranges) => {
+                        buffer[0] = field::OPT_SACKRNG;
+                        let blocks = buffer[2..length].chunks_mut(8);
+                        for (block, &(left, right)) in blocks.zip(ranges.iter().flat_map(|r| r)) {
+                            NetworkEndian::write_u32(&mut block[0..4], left);
+                            NetworkEndian::write_u32(&mut block[4..8], right);
+                        }
+                    }
+                    &TcpOption::TimeStamp { tsval, tsecr } => {
+                        buffer[0] = field::OPT_TSTAMP;
+                        NetworkEndian::write_u32(&mut buffer[2..6], tsval);
+                        NetworkEndian::write_u32(&mut buffer[6..10], tsecr);
+                    }
+                    &TcpOption::Md5Signature(ref digest) => {
+                        buffer[0] = field::OPT_MD5;
+                        buffer[2..18].copy_from_slice(digest);
+                    }
                     &TcpOption::Unknown { kind, data: provided } => {
                         buffer[0] = kind;
                         buffer[2..].copy_from_slice(provided)
@@ -604,6 +753,42 @@ impl<'a> TcpOption<'a> {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for TcpOption<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<TcpOption<'a>> {
+        match u.int_in_range(0..=8)? {
+            0 => Ok(TcpOption::EndOfList),
+            1 => Ok(TcpOption::NoOperation),
+            2 => Ok(TcpOption::MaxSegmentSize(u.arbitrary()?)),
+            3 => Ok(TcpOption::WindowScale(u.arbitrary()?)),
+            4 => Ok(TcpOption::SackPermitted),
+            5 => Ok(TcpOption::SackRange(arbitrary_sack_ranges(u, 1, SACK_RANGE_COUNT)?)),
+            6 => Ok(TcpOption::TimeStamp { tsval: u.arbitrary()?, tsecr: u.arbitrary()? }),
+            7 => Ok(TcpOption::Md5Signature(u.arbitrary()?)),
+            _ => {
+                // Kinds below this one, and the MD5 signature, have a fixed meaning and length.
+                let mut kind = u.int_in_range(field::OPT_TSTAMP + 1..=0xfe)?;
+                if kind >= field::OPT_MD5 { kind += 1 }
+                let length = u.int_in_range(0..=cmp::min(u.len(), 0xff - 2))?;
+                Ok(TcpOption::Unknown { kind: kind, data: u.bytes(length)? })
+            }
+        }
+    }
+}
+
+/// Generate SACK blocks, at least `min_count` and at most `max_count` of them, placed
+/// at the front as the parser would place them.
+#[cfg(feature = "arbitrary")]
+fn arbitrary_sack_ranges<'a>(u: &mut Unstructured<'a>, min_count: usize, max_count: usize) ->
+                            arbitrary::Result<[Option<(u32, u32)>; SACK_RANGE_COUNT]> {
+    let mut ranges = [None; SACK_RANGE_COUNT];
+    let count = u.int_in_range(min_count..=max_count)?;
+    for range in ranges[..count].iter_mut() {
+        *range = Some(u.arbitrary()?);
+    }
+    Ok(ranges)
+}
+
 /// The possible control flags of a Transmission Control Protocol packet.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Control {
@@ -632,6 +817,13 @@ impl Control {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Control {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Control> {
+        Ok(*u.choose(&[Control::None, Control::Psh, Control::Syn, Control::Fin, Control::Rst])?)
+    }
+}
+
 /// A high-level representation of a Transmission Control Protocol packet.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Repr<'a> {
@@ -642,6 +834,19 @@ pub struct Repr<'a> {
     pub ack_number:   Option<SeqNumber>,
     pub window_len:   u16,
     pub max_seg_size: Option<u16>,
+    pub sack_permitted: bool,
+    pub sack_ranges:  [Option<(u32, u32)>; SACK_RANGE_COUNT],
+    /// The timestamp value and the timestamp echo reply, if the segment carries them.
+    pub timestamp:    Option<(u32, u32)>,
+    /// The TCP MD5 signature (RFC 2385) of the segment, if it carries one.
+    pub md5_signature: Option<[u8; 16]>,
+    /// Whether the ECN-Echo flag is set.
+    pub ece:          bool,
+    /// Whether the Congestion Window Reduced flag is set.
+    pub cwr:          bool,
+    /// The urgent pointer, if the URG flag is set. It is the offset from the sequence number
+    /// of the segment to the octet following the urgent data.
+    pub urgent_at:    Option<u16>,
     pub payload:      &'a [u8]
 }
 
@@ -654,7 +859,7 @@ impl<'a> Repr<'a> {
         if packet.src_port() == 0 { return Err(Error::Malformed) }
         if packet.dst_port() == 0 { return Err(Error::Malformed) }
         // Valid checksum is expected.
-        if checksum_caps.tcpv4.rx() && !packet.verify_checksum(src_addr, dst_addr) {
+        if checksum_caps.tcp(src_addr).rx() && !packet.verify_checksum(src_addr, dst_addr) {
             return Err(Error::Checksum)
         }
 
@@ -673,11 +878,17 @@ impl<'a> Repr<'a> {
                 false => None
             };
         // The PSH flag is ignored.
-        // The URG flag and the urgent field is ignored. This behavior is standards-compliant,
-        // however, most deployed systems (e.g. Linux) are *not* standards-compliant, and would
-        // cut the byte at the urgent pointer from the stream.
+        let urgent_at =
+            match packet.urg() {
+                true  => Some(packet.urgent_at()),
+                false => None
+            };
 
         let mut max_seg_size = None;
+        let mut sack_permitted = false;
+        let mut sack_ranges = [None; SACK_RANGE_COUNT];
+        let mut timestamp = None;
+        let mut md5_signature = None;
         let mut options = packet.options();
         while options.len() > 0 {
             let (next_options, option) = TcpOption::parse(options)?;
@@ -686,6 +897,14 @@ impl<'a> Repr<'a> {
                 TcpOption::NoOperation => (),
                 TcpOption::MaxSegmentSize(value) =>
                     max_seg_size = Some(value),
+                TcpOption::SackPermitted =>
+                    sack_permitted = true,
+                TcpOption::SackRange(ranges) =>
+                    sack_ranges = ranges,
+                TcpOption::TimeStamp { tsval, tsecr } =>
+                    timestamp = Some((tsval, tsecr)),
+                TcpOption::Md5Signature(digest) =>
+                    md5_signature = Some(digest),
                 _ => ()
             }
             options = next_options;
@@ -699,6 +918,13 @@ impl<'a> Repr<'a> {
             ack_number:   ack_number,
             window_len:   packet.window_len(),
             max_seg_size: max_seg_size,
+            sack_permitted: sack_permitted,
+            sack_ranges:  sack_ranges,
+            timestamp:    timestamp,
+            md5_signature: md5_signature,
+            ece:          packet.ece(),
+            cwr:          packet.cwr(),
+            urgent_at:    urgent_at,
             payload:      packet.payload()
         })
     }
@@ -709,7 +935,20 @@ impl<'a> Repr<'a> {
         if self.max_seg_size.is_some() {
             length += 4
         }
-        length
+        if self.sack_permitted {
+            length += 2
+        }
+        if self.timestamp.is_some() {
+            length += 10
+        }
+        if self.sack_ranges[0].is_some() {
+            length += TcpOption::SackRange(self.sack_ranges).buffer_len()
+        }
+        if self.md5_signature.is_some() {
+            length += 18
+        }
+        // The options are padded to a multiple of four octets.
+        (length + 3) / 4 * 4
     }
 
     /// Return the length of a packet that will be emitted from this high-level representation.
@@ -721,6 +960,53 @@ impl<'a> Repr<'a> {
     pub fn emit<T>(&self, packet: &mut Packet<&mut T>, src_addr: &IpAddress, dst_addr: &IpAddress,
                    checksum_caps: &ChecksumCapabilities)
             where T: AsRef<[u8]> + AsMut<[u8]> + ?Sized {
+        self.emit_fields(packet);
+        packet.payload_mut().copy_from_slice(self.payload);
+
+        let checksum = checksum_caps.tcp(src_addr);
+        if checksum.tx() {
+            packet.fill_checksum(src_addr, dst_addr)
+        } else if checksum.partial() {
+            packet.fill_pseudo_header_checksum(src_addr, dst_addr)
+        } else {
+            // make sure we get a consistently zeroed checksum,
+            // since implementations might rely on it
+            packet.set_checksum(0);
+        }
+    }
+
+    /// Emit the header of a high-level representation into a buffer of `header_len()` octets,
+    /// for the payload to be sent from a separate buffer. The checksum covers the payload
+    /// all the same.
+    pub fn emit_header<T>(&self, packet: &mut Packet<&mut T>,
+                          src_addr: &IpAddress, dst_addr: &IpAddress,
+                          checksum_caps: &ChecksumCapabilities)
+            where T: AsRef<[u8]> + AsMut<[u8]> + ?Sized {
+        self.emit_fields(packet);
+
+        // The header is a multiple of 4 octets long, so that its checksum and the checksum
+        // of the payload can be combined.
+        let length = self.buffer_len() as u32;
+        let checksum = checksum_caps.tcp(src_addr);
+        if checksum.tx() {
+            packet.set_checksum(0);
+            let checksum = !checksum::combine(&[
+                checksum::pseudo_header(src_addr, dst_addr, IpProtocol::Tcp, length),
+                checksum::data(&packet.buffer.as_ref()[..self.header_len()]),
+                checksum::data(self.payload)
+            ]);
+            packet.set_checksum(checksum)
+        } else if checksum.partial() {
+            packet.set_checksum(checksum::pseudo_header(src_addr, dst_addr,
+                                                        IpProtocol::Tcp, length))
+        } else {
+            packet.set_checksum(0);
+        }
+    }
+
+    /// Emit the header fields of a high-level representation, other than the checksum.
+    fn emit_fields<T>(&self, packet: &mut Packet<&mut T>)
+            where T: AsRef<[u8]> + AsMut<[u8]> + ?Sized {
         packet.set_src_port(self.src_port);
         packet.set_dst_port(self.dst_port);
         packet.set_seq_number(self.seq_number);
@@ -736,25 +1022,42 @@ impl<'a> Repr<'a> {
             Control::Rst  => packet.set_rst(true)
         }
         packet.set_ack(self.ack_number.is_some());
+        packet.set_ece(self.ece);
+        packet.set_cwr(self.cwr);
+        packet.set_urg(self.urgent_at.is_some());
         {
             let mut options = packet.options_mut();
             if let Some(value) = self.max_seg_size {
                 let tmp = options; options = TcpOption::MaxSegmentSize(value).emit(tmp);
             }
-            if options.len() > 0 {
-                TcpOption::EndOfList.emit(options);
+            if self.sack_permitted {
+                let tmp = options; options = TcpOption::SackPermitted.emit(tmp);
+            }
+            if let Some((tsval, tsecr)) = self.timestamp {
+                let tmp = options; options = TcpOption::TimeStamp { tsval, tsecr }.emit(tmp);
+            }
+            if self.sack_ranges[0].is_some() {
+                let tmp = options; options = TcpOption::SackRange(self.sack_ranges).emit(tmp);
+            }
+            if let Some(digest) = self.md5_signature {
+                let tmp = options; options = TcpOption::Md5Signature(digest).emit(tmp);
+            }
+            while options.len() > 0 {
+                let tmp = options; options = TcpOption::EndOfList.emit(tmp);
             }
         }
-        packet.set_urgent_at(0);
-        packet.payload_mut().copy_from_slice(self.payload);
+        packet.set_urgent_at(self.urgent_at.unwrap_or(0));
+    }
 
-        if checksum_caps.tcpv4.tx() {
-            packet.fill_checksum(src_addr, dst_addr)
-        } else {
-            // make sure we get a consistently zeroed checksum,
-            // since implementations might rely on it
-            packet.set_checksum(0);
-        }
+    /// Return the octets a TCP MD5 signature of the segment covers ahead of the payload,
+    /// as the segment will be emitted from this high-level representation.
+    ///
+    /// # Panics
+    /// This function panics if the header is longer than 60 octets.
+    pub fn signed_header(&self, src_addr: &IpAddress, dst_addr: &IpAddress) -> SignedHeader {
+        let mut header = [0; 60];
+        self.emit_fields(&mut Packet::new(&mut header[..self.header_len()]));
+        SignedHeader::new(&header, self.buffer_len(), src_addr, dst_addr)
     }
 
     /// Return the length of the segment, in terms of sequence space.
@@ -772,6 +1075,40 @@ impl<'a> Repr<'a> {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Repr<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Repr<'a>> {
+        let mut repr = Repr {
+            // Source and destination ports must be present.
+            src_port:     u.int_in_range(1..=0xffff)?,
+            dst_port:     u.int_in_range(1..=0xffff)?,
+            control:      u.arbitrary()?,
+            seq_number:   u.arbitrary()?,
+            ack_number:   u.arbitrary()?,
+            window_len:   u.arbitrary()?,
+            max_seg_size: u.arbitrary()?,
+            sack_permitted: u.arbitrary()?,
+            sack_ranges:  [None; SACK_RANGE_COUNT],
+            timestamp:    u.arbitrary()?,
+            md5_signature: u.arbitrary()?,
+            ece:          u.arbitrary()?,
+            cwr:          u.arbitrary()?,
+            urgent_at:    u.arbitrary()?,
+            payload:      &[]
+        };
+        // Every option at once would not fit into the 40 octets of option space.
+        let mut options_len = 0;
+        if repr.max_seg_size.is_some()  { options_len += 4 }
+        if repr.sack_permitted          { options_len += 2 }
+        if repr.timestamp.is_some()     { options_len += 10 }
+        if repr.md5_signature.is_some() { options_len += 18 }
+        let max_sack_count = cmp::min((40 - options_len - 2) / 8, SACK_RANGE_COUNT);
+        repr.sack_ranges = arbitrary_sack_ranges(u, 0, max_sack_count)?;
+        repr.payload = u.arbitrary()?;
+        Ok(repr)
+    }
+}
+
 impl<'a, T: AsRef<[u8]> + ?Sized> fmt::Display for Packet<&'a T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         // Cannot use Repr::parse because we don't have the IP addresses.
@@ -808,6 +1145,16 @@ impl<'a, T: AsRef<[u8]> + ?Sized> fmt::Display for Packet<&'a T> {
                     write!(f, " mss={}", value)?,
                 TcpOption::WindowScale(value) =>
                     write!(f, " ws={}", value)?,
+                TcpOption::SackPermitted =>
+                    write!(f, " sACK")?,
+                TcpOption::SackRange(ranges) =>
+                    for &(left, right) in ranges.iter().flat_map(|r| r) {
+                        write!(f, " sack={}..{}", left, right)?
+                    },
+                TcpOption::TimeStamp { tsval, tsecr } =>
+                    write!(f, " tsval={} tsecr={}", tsval, tsecr)?,
+                TcpOption::Md5Signature(_) =>
+                    write!(f, " md5")?,
                 TcpOption::Unknown { kind, .. } =>
                     write!(f, " opt({})", kind)?,
             }
@@ -828,15 +1175,32 @@ impl<'a> fmt::Display for Repr<'a> {
             Control::Psh => write!(f, " psh")?,
             Control::None => ()
         }
+        if self.ece { write!(f, " ece")? }
+        if self.cwr { write!(f, " cwr")? }
         write!(f, " seq={}", self.seq_number)?;
         if let Some(ack_number) = self.ack_number {
             write!(f, " ack={}", ack_number)?;
         }
         write!(f, " win={}", self.window_len)?;
+        if let Some(urgent_at) = self.urgent_at {
+            write!(f, " urg={}", urgent_at)?;
+        }
         write!(f, " len={}", self.payload.len())?;
         if let Some(max_seg_size) = self.max_seg_size {
             write!(f, " mss={}", max_seg_size)?;
         }
+        if self.sack_permitted {
+            write!(f, " sACK")?;
+        }
+        for &(left, right) in self.sack_ranges.iter().flat_map(|r| r) {
+            write!(f, " sack={}..{}", left, right)?;
+        }
+        if let Some((tsval, tsecr)) = self.timestamp {
+            write!(f, " tsval={} tsecr={}", tsval, tsecr)?;
+        }
+        if self.md5_signature.is_some() {
+            write!(f, " md5")?;
+        }
         Ok(())
     }
 }
@@ -965,6 +1329,13 @@ mod test {
             window_len:   0x0123,
             control:      Control::Syn,
             max_seg_size: None,
+            sack_permitted: false,
+            sack_ranges:  [None; SACK_RANGE_COUNT],
+            timestamp:    None,
+            md5_signature: None,
+            ece:          false,
+            cwr:          false,
+            urgent_at:    None,
             payload:      &PAYLOAD_BYTES
         }
     }
@@ -987,10 +1358,144 @@ mod test {
         assert_eq!(&packet.into_inner()[..], &SYN_PACKET_BYTES[..]);
     }
 
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_emit_header() {
+        let repr = packet_repr();
+        let mut bytes = vec![0xa5; repr.header_len()];
+        repr.emit_header(&mut Packet::new(&mut bytes), &SRC_ADDR.into(), &DST_ADDR.into(),
+                         &ChecksumCapabilities::default());
+        bytes.extend_from_slice(repr.payload);
+        assert_eq!(&bytes[..], &SYN_PACKET_BYTES[..]);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_sack_options() {
+        let mut repr = packet_repr();
+        repr.max_seg_size = Some(1460);
+        repr.sack_permitted = true;
+        // MSS and SACK-permitted options are padded to 8 octets.
+        assert_eq!(repr.header_len(), 28);
+
+        repr.control = Control::None;
+        repr.ack_number = Some(SeqNumber(1));
+        repr.max_seg_size = None;
+        repr.sack_permitted = false;
+        repr.sack_ranges = [Some((500, 1500)), Some((2000, 3000)), None];
+        assert_eq!(repr.header_len(), 40);
+
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new(&mut bytes), &SRC_ADDR.into(), &DST_ADDR.into(),
+                  &ChecksumCapabilities::default());
+        assert_eq!(&bytes[20..40],
+                   &[0x05, 0x12,
+                     0x00, 0x00, 0x01, 0xf4, 0x00, 0x00, 0x05, 0xdc,
+                     0x00, 0x00, 0x07, 0xd0, 0x00, 0x00, 0x0b, 0xb8,
+                     0x00, 0x00][..]);
+        assert_eq!(Repr::parse(&Packet::new(&bytes[..]), &SRC_ADDR.into(), &DST_ADDR.into(),
+                               &ChecksumCapabilities::default()), Ok(repr));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_ecn_flags() {
+        let mut repr = packet_repr();
+        repr.ece = true;
+        repr.cwr = true;
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new(&mut bytes), &SRC_ADDR.into(), &DST_ADDR.into(),
+                  &ChecksumCapabilities::default());
+        {
+            let packet = Packet::new(&bytes[..]);
+            assert!(packet.syn() && packet.ece() && packet.cwr());
+            assert!(!packet.ns());
+        }
+        assert_eq!(Repr::parse(&Packet::new(&bytes[..]), &SRC_ADDR.into(), &DST_ADDR.into(),
+                               &ChecksumCapabilities::default()), Ok(repr));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_urgent() {
+        let mut repr = packet_repr();
+        repr.urgent_at = Some(0x0102);
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new(&mut bytes), &SRC_ADDR.into(), &DST_ADDR.into(),
+                  &ChecksumCapabilities::default());
+        {
+            let packet = Packet::new(&bytes[..]);
+            assert!(packet.urg());
+            assert_eq!(packet.urgent_at(), 0x0102);
+        }
+        assert_eq!(Repr::parse(&Packet::new(&bytes[..]), &SRC_ADDR.into(), &DST_ADDR.into(),
+                               &ChecksumCapabilities::default()), Ok(repr));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_timestamp_options() {
+        let mut repr = packet_repr();
+        repr.max_seg_size = Some(1460);
+        repr.sack_permitted = true;
+        repr.timestamp = Some((1000, 0));
+        // MSS, SACK-permitted and timestamp options are padded to 16 octets.
+        assert_eq!(repr.header_len(), 36);
+
+        repr.control = Control::None;
+        repr.ack_number = Some(SeqNumber(1));
+        repr.max_seg_size = None;
+        repr.sack_permitted = false;
+        repr.timestamp = Some((0x01020304, 0x05060708));
+        repr.sack_ranges = [Some((500, 1500)), None, None];
+        assert_eq!(repr.header_len(), 40);
+
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new(&mut bytes), &SRC_ADDR.into(), &DST_ADDR.into(),
+                  &ChecksumCapabilities::default());
+        assert_eq!(&bytes[20..40],
+                   &[0x08, 0x0a,
+                     0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08,
+                     0x05, 0x0a,
+                     0x00, 0x00, 0x01, 0xf4, 0x00, 0x00, 0x05, 0xdc][..]);
+        assert_eq!(Repr::parse(&Packet::new(&bytes[..]), &SRC_ADDR.into(), &DST_ADDR.into(),
+                               &ChecksumCapabilities::default()), Ok(repr));
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_md5_signature() {
+        let header = Packet::new(&PACKET_BYTES[..]).signed_header(&SRC_ADDR.into(),
+                                                                   &DST_ADDR.into());
+        assert_eq!(header.as_bytes(),
+                   &[192, 168, 1, 1,
+                     192, 168, 1, 2,
+                     0x00, 0x06, 0x00, 0x1c,
+                     0xbf, 0x00, 0x00, 0x50,
+                     0x01, 0x23, 0x45, 0x67,
+                     0x89, 0xab, 0xcd, 0xef,
+                     0x60, 0x35, 0x01, 0x23,
+                     0x00, 0x00, 0x02, 0x01][..]);
+
+        let mut repr = packet_repr();
+        repr.timestamp = Some((1000, 0));
+        repr.md5_signature = Some([0xa5; 16]);
+        // Timestamp and signature options take up 28 octets, a multiple of four.
+        assert_eq!(repr.header_len(), 48);
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new(&mut bytes), &SRC_ADDR.into(), &DST_ADDR.into(),
+                  &ChecksumCapabilities::default());
+        let packet = Packet::new(&bytes[..]);
+        assert_eq!(Repr::parse(&packet, &SRC_ADDR.into(), &DST_ADDR.into(),
+                               &ChecksumCapabilities::default()), Ok(repr));
+        assert_eq!(packet.signed_header(&SRC_ADDR.into(), &DST_ADDR.into()).as_bytes(),
+                   repr.signed_header(&SRC_ADDR.into(), &DST_ADDR.into()).as_bytes());
+    }
+
     macro_rules! assert_option_parses {
         ($opt:expr, $data:expr) => ({
             assert_eq!(TcpOption::parse($data), Ok((&[][..], $opt)));
-            let buffer = &mut [0; 20][..$opt.buffer_len()];
+            let buffer = &mut [0; 40][..$opt.buffer_len()];
             assert_eq!($opt.emit(buffer), &mut []);
             assert_eq!(&*buffer, $data);
         })
@@ -1006,6 +1511,24 @@ mod test {
                               &[0x02, 0x04, 0x05, 0xdc]);
         assert_option_parses!(TcpOption::WindowScale(12),
                               &[0x03, 0x03, 0x0c]);
+        assert_option_parses!(TcpOption::SackPermitted,
+                              &[0x04, 0x02]);
+        assert_option_parses!(TcpOption::SackRange([Some((500, 1500)), None, None]),
+                              &[0x05, 0x0a,
+                                0x00, 0x00, 0x01, 0xf4, 0x00, 0x00, 0x05, 0xdc]);
+        assert_option_parses!(TcpOption::SackRange([Some((875, 1225)), Some((1500, 2500)),
+                                                    Some((5000, 6000))]),
+                              &[0x05, 0x1a,
+                                0x00, 0x00, 0x03, 0x6b, 0x00, 0x00, 0x04, 0xc9,
+                                0x00, 0x00, 0x05, 0xdc, 0x00, 0x00, 0x09, 0xc4,
+                                0x00, 0x00, 0x13, 0x88, 0x00, 0x00, 0x17, 0x70]);
+        assert_option_parses!(TcpOption::TimeStamp { tsval: 0x01020304, tsecr: 0 },
+                              &[0x08, 0x0a,
+                                0x01, 0x02, 0x03, 0x04, 0x00, 0x00, 0x00, 0x00]);
+        assert_option_parses!(TcpOption::Md5Signature([0xa5; 16]),
+                              &[0x13, 0x12,
+                                0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5,
+                                0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5]);
         assert_option_parses!(TcpOption::Unknown { kind: 12, data: &[1, 2, 3][..] },
                               &[0x0c, 0x05, 0x01, 0x02, 0x03])
     }
@@ -1024,5 +1547,34 @@ mod test {
                    Err(Error::Malformed));
         assert_eq!(TcpOption::parse(&[0x3, 0x02]),
                    Err(Error::Malformed));
+        assert_eq!(TcpOption::parse(&[0x4, 0x03, 0x00]),
+                   Err(Error::Malformed));
+        assert_eq!(TcpOption::parse(&[0x5, 0x02]),
+                   Err(Error::Malformed));
+        assert_eq!(TcpOption::parse(&[0x5, 0x06, 0x00, 0x00, 0x00, 0x01]),
+                   Err(Error::Malformed));
+        assert_eq!(TcpOption::parse(&[0x8, 0x06, 0x00, 0x00, 0x00, 0x01]),
+                   Err(Error::Malformed));
+        assert_eq!(TcpOption::parse(&[0x13, 0x06, 0x00, 0x00, 0x00, 0x01]),
+                   Err(Error::Malformed));
+    }
+
+    #[test]
+    #[cfg(feature = "arbitrary")]
+    fn test_arbitrary_round_trip() {
+        use arbitrary::Unstructured;
+        use wire::ip::test::{arbitrary_inputs, MOCK_IP_ADDR_1, MOCK_IP_ADDR_2};
+
+        for input in arbitrary_inputs() {
+            let repr: Repr = match Unstructured::new(&input).arbitrary() {
+                Ok(repr) => repr,
+                Err(_) => continue
+            };
+            let mut bytes = vec![0xa5; repr.buffer_len()];
+            repr.emit(&mut Packet::new(&mut bytes), &MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2,
+                      &ChecksumCapabilities::default());
+            assert_eq!(Repr::parse(&Packet::new(&bytes), &MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2,
+                                   &ChecksumCapabilities::default()), Ok(repr));
+        }
     }
 }
@@ -0,0 +1,579 @@
+// See https://tools.ietf.org/html/rfc1350 for the TFTP specification, and
+// https://tools.ietf.org/html/rfc2347, https://tools.ietf.org/html/rfc2348 and
+// https://tools.ietf.org/html/rfc2349 for option negotiation.
+
+use core::{fmt, str};
+use byteorder::{ByteOrder, NetworkEndian};
+
+use {Error, Result};
+
+/// The port TFTP servers listen on.
+pub const SERVER_PORT: u16 = 69;
+
+/// The block size used unless another one is negotiated.
+pub const DEFAULT_BLOCK_SIZE: usize = 512;
+
+enum_with_unknown! {
+    /// The operation of a TFTP packet.
+    pub enum OpCode(u16) {
+        Read      = 1,
+        Write     = 2,
+        Data      = 3,
+        Ack       = 4,
+        Error     = 5,
+        OptionAck = 6
+    }
+}
+
+enum_with_unknown! {
+    /// The error code of a TFTP error packet.
+    pub enum ErrorCode(u16) {
+        Undefined         = 0,
+        FileNotFound      = 1,
+        AccessViolation   = 2,
+        DiskFull          = 3,
+        IllegalOperation  = 4,
+        UnknownTransferId = 5,
+        FileExists        = 6,
+        NoSuchUser        = 7,
+        OptionRefused     = 8
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ErrorCode::Undefined         => write!(f, "undefined"),
+            &ErrorCode::FileNotFound      => write!(f, "file not found"),
+            &ErrorCode::AccessViolation   => write!(f, "access violation"),
+            &ErrorCode::DiskFull          => write!(f, "disk full"),
+            &ErrorCode::IllegalOperation  => write!(f, "illegal operation"),
+            &ErrorCode::UnknownTransferId => write!(f, "unknown transfer ID"),
+            &ErrorCode::FileExists        => write!(f, "file exists"),
+            &ErrorCode::NoSuchUser        => write!(f, "no such user"),
+            &ErrorCode::OptionRefused     => write!(f, "option refused"),
+            &ErrorCode::Unknown(code)     => write!(f, "0x{:04x}", code)
+        }
+    }
+}
+
+/// The transfer mode of a TFTP request.
+///
+/// The obsolete `mail` mode is not supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    NetAscii,
+    Octet,
+}
+
+impl Mode {
+    fn parse(name: &str) -> Result<Mode> {
+        if name.eq_ignore_ascii_case("netascii") {
+            Ok(Mode::NetAscii)
+        } else if name.eq_ignore_ascii_case("octet") {
+            Ok(Mode::Octet)
+        } else {
+            Err(Error::Malformed)
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            &Mode::NetAscii => "netascii",
+            &Mode::Octet    => "octet"
+        }
+    }
+}
+
+/// The options of a transfer, negotiated as described in RFC 2347.
+///
+/// Options other than these are ignored when parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Options {
+    /// The number of octets in a data block, as described in RFC 2348.
+    pub block_size:    Option<u16>,
+    /// The retransmission timeout, in seconds, as described in RFC 2349.
+    pub timeout:       Option<u8>,
+    /// The size of the file, in octets, as described in RFC 2349.
+    pub transfer_size: Option<u32>,
+}
+
+const OPT_BLOCK_SIZE:    &str = "blksize";
+const OPT_TIMEOUT:       &str = "timeout";
+const OPT_TRANSFER_SIZE: &str = "tsize";
+
+impl Options {
+    /// Query whether no option is set.
+    pub fn is_empty(&self) -> bool {
+        self.block_size.is_none() && self.timeout.is_none() && self.transfer_size.is_none()
+    }
+
+    fn parse(mut strings: Strings) -> Result<Options> {
+        let mut options = Options::default();
+        while let Some(name) = strings.next()? {
+            let value = strings.next()?.ok_or(Error::Malformed)?;
+            if name.eq_ignore_ascii_case(OPT_BLOCK_SIZE) {
+                options.block_size = Some(value.parse().map_err(|_| Error::Malformed)?)
+            } else if name.eq_ignore_ascii_case(OPT_TIMEOUT) {
+                options.timeout = Some(value.parse().map_err(|_| Error::Malformed)?)
+            } else if name.eq_ignore_ascii_case(OPT_TRANSFER_SIZE) {
+                options.transfer_size = Some(value.parse().map_err(|_| Error::Malformed)?)
+            }
+        }
+        Ok(options)
+    }
+
+    fn buffer_len(&self) -> usize {
+        let mut len = 0;
+        if let Some(value) = self.block_size {
+            len += OPT_BLOCK_SIZE.len() + decimal_len(value as u32) + 2
+        }
+        if let Some(value) = self.timeout {
+            len += OPT_TIMEOUT.len() + decimal_len(value as u32) + 2
+        }
+        if let Some(value) = self.transfer_size {
+            len += OPT_TRANSFER_SIZE.len() + decimal_len(value) + 2
+        }
+        len
+    }
+
+    fn emit(&self, buffer: &mut [u8]) -> usize {
+        let mut len = 0;
+        if let Some(value) = self.block_size {
+            len += emit_string(OPT_BLOCK_SIZE, &mut buffer[len..]);
+            len += emit_decimal(value as u32, &mut buffer[len..]);
+        }
+        if let Some(value) = self.timeout {
+            len += emit_string(OPT_TIMEOUT, &mut buffer[len..]);
+            len += emit_decimal(value as u32, &mut buffer[len..]);
+        }
+        if let Some(value) = self.transfer_size {
+            len += emit_string(OPT_TRANSFER_SIZE, &mut buffer[len..]);
+            len += emit_decimal(value, &mut buffer[len..]);
+        }
+        len
+    }
+}
+
+/// An iterator over the NUL-terminated strings of a packet.
+struct Strings<'a> {
+    data: &'a [u8]
+}
+
+impl<'a> Strings<'a> {
+    fn next(&mut self) -> Result<Option<&'a str>> {
+        if self.data.is_empty() { return Ok(None) }
+        let end = self.data.iter().position(|&byte| byte == 0).ok_or(Error::Malformed)?;
+        let string = str::from_utf8(&self.data[..end]).map_err(|_| Error::Malformed)?;
+        self.data = &self.data[end + 1..];
+        Ok(Some(string))
+    }
+}
+
+fn decimal_len(mut value: u32) -> usize {
+    let mut len = 1;
+    while value >= 10 {
+        value /= 10;
+        len += 1;
+    }
+    len
+}
+
+/// Emit a number as a NUL-terminated decimal string, and return its length.
+fn emit_decimal(mut value: u32, buffer: &mut [u8]) -> usize {
+    let len = decimal_len(value);
+    for digit in buffer[..len].iter_mut().rev() {
+        *digit = b'0' + (value % 10) as u8;
+        value /= 10;
+    }
+    buffer[len] = 0;
+    len + 1
+}
+
+/// Emit a NUL-terminated string, and return its length.
+fn emit_string(string: &str, buffer: &mut [u8]) -> usize {
+    buffer[..string.len()].copy_from_slice(string.as_bytes());
+    buffer[string.len()] = 0;
+    string.len() + 1
+}
+
+mod field {
+    #![allow(non_snake_case)]
+
+    use wire::field::*;
+
+    pub const OPCODE: Field = 0..2;
+    pub const BLOCK_NUMBER: Field = 2..4;
+    pub const ERROR_CODE: Field = 2..4;
+    pub const STRINGS: Rest = 2..;
+    pub const DATA: Rest = 4..;
+}
+
+/// A read/write wrapper around a TFTP packet buffer.
+#[derive(Debug, PartialEq)]
+pub struct Packet<T: AsRef<[u8]>> {
+    buffer: T
+}
+
+impl<T: AsRef<[u8]>> Packet<T> {
+    /// Imbue a raw octet buffer with TFTP packet structure.
+    pub fn new(buffer: T) -> Packet<T> {
+        Packet { buffer }
+    }
+
+    /// Shorthand for a combination of [new] and [check_len].
+    ///
+    /// [new]: #method.new
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<Packet<T>> {
+        let packet = Self::new(buffer);
+        packet.check_len()?;
+        Ok(packet)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error::Truncated)` if the buffer is too short.
+    ///
+    /// The result of this check is invalidated by calling [set_opcode].
+    ///
+    /// [set_opcode]: #method.set_opcode
+    pub fn check_len(&self) -> Result<()> {
+        let len = self.buffer.as_ref().len();
+        if len < field::OPCODE.end {
+            return Err(Error::Truncated)
+        }
+        match self.opcode() {
+            OpCode::Data | OpCode::Ack | OpCode::Error if len < field::DATA.start =>
+                Err(Error::Truncated),
+            _ => Ok(())
+        }
+    }
+
+    /// Consume the packet, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Return the opcode field.
+    #[inline]
+    pub fn opcode(&self) -> OpCode {
+        OpCode::from(NetworkEndian::read_u16(&self.buffer.as_ref()[field::OPCODE]))
+    }
+
+    /// Return the block number field of a data or acknowledgement packet.
+    #[inline]
+    pub fn block_number(&self) -> u16 {
+        NetworkEndian::read_u16(&self.buffer.as_ref()[field::BLOCK_NUMBER])
+    }
+
+    /// Return the error code field of an error packet.
+    #[inline]
+    pub fn error_code(&self) -> ErrorCode {
+        ErrorCode::from(NetworkEndian::read_u16(&self.buffer.as_ref()[field::ERROR_CODE]))
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> Packet<&'a T> {
+    /// Return a pointer to the data of a data packet.
+    #[inline]
+    pub fn data(&self) -> &'a [u8] {
+        &self.buffer.as_ref()[field::DATA]
+    }
+
+    /// Return a pointer to the NUL-terminated strings of a request, error
+    /// or option acknowledgement packet. In an error packet, they are preceded
+    /// by the error code.
+    #[inline]
+    pub fn strings(&self) -> &'a [u8] {
+        &self.buffer.as_ref()[field::STRINGS]
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
+    /// Set the opcode field.
+    #[inline]
+    pub fn set_opcode(&mut self, value: OpCode) {
+        NetworkEndian::write_u16(&mut self.buffer.as_mut()[field::OPCODE], value.into())
+    }
+
+    /// Set the block number field.
+    #[inline]
+    pub fn set_block_number(&mut self, value: u16) {
+        NetworkEndian::write_u16(&mut self.buffer.as_mut()[field::BLOCK_NUMBER], value)
+    }
+
+    /// Set the error code field.
+    #[inline]
+    pub fn set_error_code(&mut self, value: ErrorCode) {
+        NetworkEndian::write_u16(&mut self.buffer.as_mut()[field::ERROR_CODE], value.into())
+    }
+
+    /// Return a mutable pointer to the data of a data packet.
+    #[inline]
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer.as_mut()[field::DATA]
+    }
+
+    /// Return a mutable pointer to the strings of a request, error
+    /// or option acknowledgement packet.
+    #[inline]
+    pub fn strings_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer.as_mut()[field::STRINGS]
+    }
+}
+
+/// A high-level representation of a TFTP packet.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Repr<'a> {
+    ReadRequest {
+        filename: &'a str,
+        mode:     Mode,
+        options:  Options,
+    },
+    WriteRequest {
+        filename: &'a str,
+        mode:     Mode,
+        options:  Options,
+    },
+    Data {
+        block_num: u16,
+        data:      &'a [u8],
+    },
+    Ack {
+        block_num: u16,
+    },
+    Error {
+        code:    ErrorCode,
+        message: &'a str,
+    },
+    OptionAck {
+        options: Options,
+    },
+}
+
+impl<'a> Repr<'a> {
+    /// Parse a TFTP packet and return a high-level representation.
+    pub fn parse<T>(packet: &Packet<&'a T>) -> Result<Repr<'a>>
+            where T: AsRef<[u8]> + ?Sized {
+        packet.check_len()?;
+        match packet.opcode() {
+            opcode @ OpCode::Read | opcode @ OpCode::Write => {
+                let mut strings = Strings { data: packet.strings() };
+                let filename = strings.next()?.ok_or(Error::Malformed)?;
+                let mode = Mode::parse(strings.next()?.ok_or(Error::Malformed)?)?;
+                let options = Options::parse(strings)?;
+                if opcode == OpCode::Read {
+                    Ok(Repr::ReadRequest { filename, mode, options })
+                } else {
+                    Ok(Repr::WriteRequest { filename, mode, options })
+                }
+            }
+            OpCode::Data => Ok(Repr::Data {
+                block_num: packet.block_number(),
+                data:      packet.data(),
+            }),
+            OpCode::Ack => Ok(Repr::Ack { block_num: packet.block_number() }),
+            OpCode::Error => {
+                let mut strings = Strings { data: packet.data() };
+                Ok(Repr::Error {
+                    code:    packet.error_code(),
+                    message: strings.next()?.unwrap_or(""),
+                })
+            }
+            OpCode::OptionAck => Ok(Repr::OptionAck {
+                options: Options::parse(Strings { data: packet.strings() })?
+            }),
+            OpCode::Unknown(_) => Err(Error::Unrecognized)
+        }
+    }
+
+    /// Return the length of a packet that will be emitted from this high-level representation.
+    pub fn buffer_len(&self) -> usize {
+        match self {
+            &Repr::ReadRequest { filename, mode, ref options } |
+            &Repr::WriteRequest { filename, mode, ref options } =>
+                field::STRINGS.start + filename.len() + mode.as_str().len() + 2 +
+                    options.buffer_len(),
+            &Repr::Data { data, .. } => field::DATA.start + data.len(),
+            &Repr::Ack { .. } => field::DATA.start,
+            &Repr::Error { message, .. } => field::DATA.start + message.len() + 1,
+            &Repr::OptionAck { ref options } => field::STRINGS.start + options.buffer_len(),
+        }
+    }
+
+    /// Emit a high-level representation into a TFTP packet.
+    pub fn emit<T>(&self, packet: &mut Packet<&mut T>)
+            where T: AsRef<[u8]> + AsMut<[u8]> + ?Sized {
+        match self {
+            &Repr::ReadRequest { filename, mode, ref options } |
+            &Repr::WriteRequest { filename, mode, ref options } => {
+                packet.set_opcode(match self {
+                    &Repr::ReadRequest { .. } => OpCode::Read,
+                    _ => OpCode::Write
+                });
+                let strings = packet.strings_mut();
+                let mut len = emit_string(filename, strings);
+                len += emit_string(mode.as_str(), &mut strings[len..]);
+                options.emit(&mut strings[len..]);
+            }
+            &Repr::Data { block_num, data } => {
+                packet.set_opcode(OpCode::Data);
+                packet.set_block_number(block_num);
+                packet.data_mut()[..data.len()].copy_from_slice(data);
+            }
+            &Repr::Ack { block_num } => {
+                packet.set_opcode(OpCode::Ack);
+                packet.set_block_number(block_num);
+            }
+            &Repr::Error { code, message } => {
+                packet.set_opcode(OpCode::Error);
+                packet.set_error_code(code);
+                emit_string(message, packet.data_mut());
+            }
+            &Repr::OptionAck { ref options } => {
+                packet.set_opcode(OpCode::OptionAck);
+                options.emit(packet.strings_mut());
+            }
+        }
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> fmt::Display for Packet<&'a T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match Repr::parse(self) {
+            Ok(repr) => write!(f, "{}", repr),
+            Err(err) => write!(f, "TFTP ({})", err)
+        }
+    }
+}
+
+impl<'a> fmt::Display for Repr<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Repr::ReadRequest { filename, mode, .. } =>
+                write!(f, "TFTP RRQ file={} mode={}", filename, mode.as_str()),
+            &Repr::WriteRequest { filename, mode, .. } =>
+                write!(f, "TFTP WRQ file={} mode={}", filename, mode.as_str()),
+            &Repr::Data { block_num, data } =>
+                write!(f, "TFTP DATA block={} len={}", block_num, data.len()),
+            &Repr::Ack { block_num } =>
+                write!(f, "TFTP ACK block={}", block_num),
+            &Repr::Error { code, message } =>
+                write!(f, "TFTP ERROR code={} msg={}", code, message),
+            &Repr::OptionAck { .. } =>
+                write!(f, "TFTP OACK"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static RRQ_BYTES: [u8; 34] = [
+        0x00, 0x01,
+        b'f', b'w', b'.', b'b', b'i', b'n', 0x00,
+        b'o', b'c', b't', b'e', b't', 0x00,
+        b'b', b'l', b'k', b's', b'i', b'z', b'e', 0x00, b'1', b'4', b'2', b'8', 0x00,
+        b't', b's', b'i', b'z', b'e', 0x00,
+    ];
+
+    static DATA_BYTES: [u8; 7] = [0x00, 0x03, 0x01, 0x02, 0xaa, 0xbb, 0xcc];
+
+    static ERROR_BYTES: [u8; 14] = [
+        0x00, 0x05, 0x00, 0x01,
+        b'n', b'o', b't', b' ', b'f', b'o', b'u', b'n', b'd', 0x00,
+    ];
+
+    #[test]
+    fn test_deconstruct() {
+        let packet = Packet::new_checked(&DATA_BYTES[..]).unwrap();
+        assert_eq!(packet.opcode(), OpCode::Data);
+        assert_eq!(packet.block_number(), 0x0102);
+        assert_eq!(packet.data(), &[0xaa, 0xbb, 0xcc]);
+
+        let packet = Packet::new_checked(&ERROR_BYTES[..]).unwrap();
+        assert_eq!(packet.opcode(), OpCode::Error);
+        assert_eq!(packet.error_code(), ErrorCode::FileNotFound);
+
+        assert_eq!(Packet::new_checked(&DATA_BYTES[..3]), Err(Error::Truncated));
+        assert_eq!(Packet::new_checked(&RRQ_BYTES[..1]), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn test_parse_request() {
+        // The tsize option is missing its value.
+        assert_eq!(Repr::parse(&Packet::new(&RRQ_BYTES[..])), Err(Error::Malformed));
+
+        let mut bytes = [0; 36];
+        bytes[..34].copy_from_slice(&RRQ_BYTES[..]);
+        bytes[34] = b'0';
+        let repr = Repr::parse(&Packet::new(&bytes[..])).unwrap();
+        assert_eq!(repr, Repr::ReadRequest {
+            filename: "fw.bin",
+            mode:     Mode::Octet,
+            options:  Options {
+                block_size:    Some(1428),
+                timeout:       None,
+                transfer_size: Some(0),
+            }
+        });
+        assert_eq!(repr.buffer_len(), 36);
+
+        let mut emitted = [0xa5; 36];
+        repr.emit(&mut Packet::new(&mut emitted[..]));
+        assert_eq!(&emitted[..], &bytes[..]);
+    }
+
+    #[test]
+    fn test_parse_case_insensitive() {
+        let bytes = b"\x00\x02boot\x00OCTET\x00BlkSize\x0016\x00unknown\x00x\x00";
+        assert_eq!(Repr::parse(&Packet::new(&bytes[..])), Ok(Repr::WriteRequest {
+            filename: "boot",
+            mode:     Mode::Octet,
+            options:  Options { block_size: Some(16), ..Options::default() }
+        }));
+        let bytes = b"\x00\x01boot\x00mail\x00";
+        assert_eq!(Repr::parse(&Packet::new(&bytes[..])), Err(Error::Malformed));
+        let bytes = b"\x00\x01boot\x00octet";
+        assert_eq!(Repr::parse(&Packet::new(&bytes[..])), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn test_data() {
+        let repr = Repr::parse(&Packet::new(&DATA_BYTES[..])).unwrap();
+        assert_eq!(repr, Repr::Data { block_num: 0x0102, data: &[0xaa, 0xbb, 0xcc] });
+
+        let mut bytes = [0; 7];
+        repr.emit(&mut Packet::new(&mut bytes[..]));
+        assert_eq!(bytes, DATA_BYTES);
+    }
+
+    #[test]
+    fn test_error() {
+        let repr = Repr::parse(&Packet::new(&ERROR_BYTES[..])).unwrap();
+        assert_eq!(repr, Repr::Error { code: ErrorCode::FileNotFound, message: "not found" });
+        assert_eq!(repr.buffer_len(), 14);
+
+        let mut bytes = [0; 14];
+        repr.emit(&mut Packet::new(&mut bytes[..]));
+        assert_eq!(bytes, ERROR_BYTES);
+    }
+
+    #[test]
+    fn test_option_ack() {
+        let repr = Repr::OptionAck {
+            options: Options {
+                block_size:    Some(1024),
+                timeout:       Some(3),
+                transfer_size: Some(4294967295),
+            }
+        };
+        let mut bytes = [0; 48];
+        let len = repr.buffer_len();
+        repr.emit(&mut Packet::new(&mut bytes[..len]));
+        assert_eq!(&bytes[..len],
+                   &b"\x00\x06blksize\x001024\x00timeout\x003\x00tsize\x004294967295\x00"[..]);
+        assert_eq!(Repr::parse(&Packet::new(&bytes[..len])), Ok(repr));
+    }
+}
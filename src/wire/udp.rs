@@ -2,6 +2,8 @@ use core::fmt;
 use byteorder::{ByteOrder, NetworkEndian};
 
 use {Error, Result};
+#[cfg(feature = "arbitrary")]
+use arbitrary::{self, Arbitrary, Unstructured};
 use phy::ChecksumCapabilities;
 use super::{IpProtocol, IpAddress};
 use super::ip::checksum;
@@ -180,6 +182,17 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
         // so no action is necessary on the remote end.
         self.set_checksum(if checksum == 0 { 0xffff } else { checksum })
     }
+
+    /// Fill in the checksum of the pseudo-header only, for a device offloading the checksum
+    /// to compute the checksum of the rest of the packet.
+    ///
+    /// # Panics
+    /// This function panics unless `src_addr` and `dst_addr` belong to the same family,
+    /// and that family is IPv4 or IPv6.
+    pub fn fill_pseudo_header_checksum(&mut self, src_addr: &IpAddress, dst_addr: &IpAddress) {
+        let length = self.len() as u32;
+        self.set_checksum(checksum::pseudo_header(src_addr, dst_addr, IpProtocol::Udp, length))
+    }
 }
 
 impl<'a, T: AsRef<[u8]> + AsMut<[u8]> + ?Sized> Packet<&'a mut T> {
@@ -208,7 +221,7 @@ impl<'a> Repr<'a> {
         // Destination port cannot be omitted (but source port can be).
         if packet.dst_port() == 0 { return Err(Error::Malformed) }
         // Valid checksum is expected...
-        if checksum_caps.udpv4.rx() && !packet.verify_checksum(src_addr, dst_addr) {
+        if checksum_caps.udp(src_addr).rx() && !packet.verify_checksum(src_addr, dst_addr) {
             match (src_addr, dst_addr) {
                 #[cfg(feature = "proto-ipv4")]
                 (&IpAddress::Ipv4(_), &IpAddress::Ipv4(_))
@@ -245,8 +258,11 @@ impl<'a> Repr<'a> {
         packet.set_len((field::CHECKSUM.end + self.payload.len()) as u16);
         packet.payload_mut().copy_from_slice(self.payload);
 
-        if checksum_caps.udpv4.tx() {
+        let checksum = checksum_caps.udp(src_addr);
+        if checksum.tx() {
             packet.fill_checksum(src_addr, dst_addr)
+        } else if checksum.partial() {
+            packet.fill_pseudo_header_checksum(src_addr, dst_addr)
         } else {
             // make sure we get a consistently zeroed checksum,
             // since implementations might rely on it
@@ -255,6 +271,21 @@ impl<'a> Repr<'a> {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Repr<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Repr<'a>> {
+        let src_port = u.arbitrary()?;
+        // Destination port cannot be omitted.
+        let dst_port = u.int_in_range(1..=0xffff)?;
+        let length = u.int_in_range(0..=u.len().min(0xffff - field::CHECKSUM.end))?;
+        Ok(Repr {
+            src_port: src_port,
+            dst_port: dst_port,
+            payload:  u.bytes(length)?
+        })
+    }
+}
+
 impl<'a, T: AsRef<[u8]> + ?Sized> fmt::Display for Packet<&'a T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         // Cannot use Repr::parse because we don't have the IP addresses.
@@ -286,6 +317,7 @@ impl<T: AsRef<[u8]>> PrettyPrint for Packet<T> {
 mod test {
     #[cfg(feature = "proto-ipv4")]
     use wire::Ipv4Address;
+    use phy::Checksum;
     use super::*;
 
     #[cfg(feature = "proto-ipv4")]
@@ -375,4 +407,64 @@ mod test {
         repr.emit(&mut packet, &SRC_ADDR.into(), &DST_ADDR.into(), &ChecksumCapabilities::default());
         assert_eq!(&packet.into_inner()[..], &PACKET_BYTES[..]);
     }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_emit_partial() {
+        let mut checksum_caps = ChecksumCapabilities::default();
+        checksum_caps.udpv4 = Checksum::Partial;
+
+        let repr = packet_repr();
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new(&mut bytes), &SRC_ADDR.into(), &DST_ADDR.into(),
+                  &checksum_caps);
+        assert_eq!(Packet::new(&bytes).checksum(),
+                   checksum::pseudo_header(&SRC_ADDR.into(), &DST_ADDR.into(),
+                                           IpProtocol::Udp, 12));
+        // The device completes the checksum by summing the whole packet.
+        let checksum = !checksum::data(&bytes);
+        NetworkEndian::write_u16(&mut bytes[field::CHECKSUM], checksum);
+        assert_eq!(&bytes[..], &PACKET_BYTES[..]);
+
+        // Received packets are not verified.
+        bytes[field::CHECKSUM.start] ^= 0xff;
+        assert_eq!(Repr::parse(&Packet::new(&bytes), &SRC_ADDR.into(), &DST_ADDR.into(),
+                               &checksum_caps), Ok(repr));
+    }
+
+    #[test]
+    #[cfg(all(feature = "proto-ipv4", feature = "proto-ipv6"))]
+    fn test_checksum_per_family() {
+        use wire::Ipv6Address;
+
+        let mut checksum_caps = ChecksumCapabilities::default();
+        checksum_caps.udpv6 = Checksum::None;
+
+        let mut bytes = PACKET_BYTES;
+        bytes[field::CHECKSUM.start] ^= 0xff;
+        assert_eq!(Repr::parse(&Packet::new(&bytes), &SRC_ADDR.into(), &DST_ADDR.into(),
+                               &checksum_caps), Err(Error::Checksum));
+        assert_eq!(Repr::parse(&Packet::new(&bytes), &Ipv6Address::LOOPBACK.into(),
+                               &Ipv6Address::LOOPBACK.into(), &checksum_caps),
+                   Ok(packet_repr()));
+    }
+
+    #[test]
+    #[cfg(feature = "arbitrary")]
+    fn test_arbitrary_round_trip() {
+        use arbitrary::Unstructured;
+        use wire::ip::test::{arbitrary_inputs, MOCK_IP_ADDR_1, MOCK_IP_ADDR_2};
+
+        for input in arbitrary_inputs() {
+            let repr: Repr = match Unstructured::new(&input).arbitrary() {
+                Ok(repr) => repr,
+                Err(_) => continue
+            };
+            let mut bytes = vec![0xa5; repr.buffer_len()];
+            repr.emit(&mut Packet::new(&mut bytes), &MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2,
+                      &ChecksumCapabilities::default());
+            assert_eq!(Repr::parse(&Packet::new(&bytes), &MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2,
+                                   &ChecksumCapabilities::default()), Ok(repr));
+        }
+    }
 }
@@ -0,0 +1,185 @@
+use {Error, Result};
+use super::EthernetAddress;
+
+/// The UDP port magic packets are conventionally sent to.
+pub const UDP_PORT: u16 = 9;
+
+/// The length of the synchronization stream that starts a magic packet.
+const SYNC_LEN: usize = 6;
+/// The number of times the target address is repeated in a magic packet.
+const REPEAT: usize = 16;
+/// The length of a magic packet without a password.
+const MAGIC_LEN: usize = SYNC_LEN + REPEAT * 6;
+
+/// A SecureOn password following the target address repetitions of a magic packet.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Password {
+    /// A four octet password, often written as an IPv4 address.
+    Short([u8; 4]),
+    /// A six octet password, often written as an Ethernet address.
+    Long([u8; 6]),
+}
+
+impl Password {
+    /// Return the password as a sequence of octets.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            &Password::Short(ref bytes) => &bytes[..],
+            &Password::Long(ref bytes) => &bytes[..],
+        }
+    }
+}
+
+/// A high-level representation of a Wake-on-LAN magic packet.
+///
+/// A magic packet consists of six `0xff` octets followed by sixteen repetitions of
+/// the hardware address of the host to wake, optionally followed by a password.
+/// It may be sent in an Ethernet frame with the EtherType `0x0842`, or as the payload
+/// of any other packet, such as a UDP datagram to [port 9](constant.WOL_UDP_PORT.html);
+/// network controllers recognize it anywhere in a frame.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Repr {
+    pub target:   EthernetAddress,
+    pub password: Option<Password>,
+}
+
+impl Repr {
+    /// Find a magic packet in the given data, e.g. a whole received frame.
+    ///
+    /// The password is only recognized if the magic packet ends the data with it.
+    /// Returns `Err(Error::Unrecognized)` if there is no magic packet in the data.
+    pub fn parse(data: &[u8]) -> Result<Repr> {
+        let mut start = 0;
+        while data.len() - start >= MAGIC_LEN {
+            let sync = data[start..start + SYNC_LEN].iter().all(|&b| b == 0xff);
+            if sync {
+                // A run of more than six 0xff octets may end in the first address repetition.
+                let mut addr = start + SYNC_LEN;
+                while addr + REPEAT * 6 <= data.len() {
+                    if let Some(repr) = Self::parse_at(data, addr) {
+                        return Ok(repr)
+                    }
+                    if data[addr] != 0xff { break }
+                    addr += 1;
+                }
+            }
+            start += 1;
+        }
+        Err(Error::Unrecognized)
+    }
+
+    fn parse_at(data: &[u8], start: usize) -> Option<Repr> {
+        let target = &data[start..start + 6];
+        let end = start + REPEAT * 6;
+        if !data[start..end].chunks(6).all(|chunk| chunk == target) {
+            return None
+        }
+
+        let password = match data.len() - end {
+            4 => {
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(&data[end..]);
+                Some(Password::Short(bytes))
+            }
+            6 => {
+                let mut bytes = [0; 6];
+                bytes.copy_from_slice(&data[end..]);
+                Some(Password::Long(bytes))
+            }
+            _ => None
+        };
+        Some(Repr { target: EthernetAddress::from_bytes(target), password })
+    }
+
+    /// Return the length of the magic packet that will be emitted from this
+    /// high-level representation.
+    pub fn buffer_len(&self) -> usize {
+        MAGIC_LEN + self.password.map(|password| password.as_bytes().len()).unwrap_or(0)
+    }
+
+    /// Emit a high-level representation into a buffer of exactly
+    /// [buffer_len](#method.buffer_len) octets.
+    pub fn emit(&self, buffer: &mut [u8]) {
+        for b in buffer[..SYNC_LEN].iter_mut() {
+            *b = 0xff
+        }
+        for chunk in buffer[SYNC_LEN..MAGIC_LEN].chunks_mut(6) {
+            chunk.copy_from_slice(self.target.as_bytes())
+        }
+        if let Some(password) = self.password {
+            buffer[MAGIC_LEN..].copy_from_slice(password.as_bytes())
+        }
+    }
+
+    /// Return whether this magic packet wakes the host with the given hardware address
+    /// and, if it has one, password.
+    pub fn wakes(&self, addr: EthernetAddress, password: Option<Password>) -> bool {
+        self.target == addr && (password.is_none() || self.password == password)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use super::*;
+
+    const TARGET: EthernetAddress = EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+
+    fn magic(repr: &Repr) -> Vec<u8> {
+        let mut buffer = vec![0; repr.buffer_len()];
+        repr.emit(&mut buffer);
+        buffer
+    }
+
+    #[test]
+    fn test_emit() {
+        let repr = Repr { target: TARGET, password: None };
+        let buffer = magic(&repr);
+        assert_eq!(buffer.len(), 102);
+        assert_eq!(&buffer[..6], &[0xff; 6]);
+        assert_eq!(&buffer[6..12], TARGET.as_bytes());
+        assert_eq!(&buffer[96..], TARGET.as_bytes());
+
+        let repr = Repr { target: TARGET, password: Some(Password::Short([1, 2, 3, 4])) };
+        let buffer = magic(&repr);
+        assert_eq!(buffer.len(), 106);
+        assert_eq!(&buffer[102..], &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_parse() {
+        let repr = Repr { target: TARGET, password: Some(Password::Long([1, 2, 3, 4, 5, 6])) };
+        assert_eq!(Repr::parse(&magic(&repr)), Ok(repr));
+
+        let mut frame = vec![0xff; 8];
+        frame.extend_from_slice(&[0x12; 20]);
+        frame.extend_from_slice(&magic(&Repr { target: TARGET, password: None }));
+        frame.extend_from_slice(&[0; 18]);
+        assert_eq!(Repr::parse(&frame), Ok(Repr { target: TARGET, password: None }));
+    }
+
+    #[test]
+    fn test_parse_broadcast_target() {
+        let repr = Repr { target: EthernetAddress::BROADCAST, password: None };
+        assert_eq!(Repr::parse(&magic(&repr)), Ok(repr));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let mut buffer = magic(&Repr { target: TARGET, password: None });
+        buffer[50] ^= 1;
+        assert_eq!(Repr::parse(&buffer), Err(Error::Unrecognized));
+        assert_eq!(Repr::parse(&buffer[..101]), Err(Error::Unrecognized));
+        assert_eq!(Repr::parse(&[]), Err(Error::Unrecognized));
+    }
+
+    #[test]
+    fn test_wakes() {
+        let password = Some(Password::Short([1, 2, 3, 4]));
+        let repr = Repr { target: TARGET, password };
+        assert!(repr.wakes(TARGET, None));
+        assert!(repr.wakes(TARGET, password));
+        assert!(!repr.wakes(TARGET, Some(Password::Short([0; 4]))));
+        assert!(!repr.wakes(EthernetAddress([0x02, 0, 0, 0, 0, 2]), None));
+    }
+}